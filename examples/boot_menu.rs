@@ -0,0 +1,100 @@
+// A boot menu: lists the Boot#### options and boots the one picked, by setting BootNext and resetting so the
+// firmware's own boot manager does the booting.
+//
+//     boot_menu.efi [list | <number>]
+//
+// Up and down choose, Enter boots and Esc leaves. `list` just lists them and a (hex) number boots that one without
+// asking. Under the runner, which stops QEMU at the reset:
+//
+//     EFI_RUNNER_ARGS="list" cargo run --example boot_menu --features qemu -Zbuild-std=core,alloc --target x86_64-unknown-uefi
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+#![feature(abi_efiapi)]
+
+#[macro_use] extern crate efi;
+#[macro_use] extern crate alloc;
+
+mod common;
+
+use efi::{
+    ffi::{self, console::{SCAN_UP, SCAN_DOWN, SCAN_ESC}},
+    init_env,
+    console::{self, Console},
+    variables::{boot_options::{self, LoadOption}, os_indications},
+};
+use alloc::string::String;
+
+const ENTER: u16 = 0x0d;
+
+#[no_mangle]
+pub extern "efiapi" fn efi_main(image_handle: ffi::EFI_HANDLE, sys_table: *const ffi::EFI_SYSTEM_TABLE) -> isize {
+    init_env(image_handle, sys_table);
+    common::finish(run())
+}
+
+fn run() -> Result<(), String> {
+    let mut options = boot_options::boot_options().map_err(|e| format!("reading the boot options: {}", e))?;
+    if options.is_empty() {
+        return Err(String::from("there are no boot options"));
+    }
+    let current = boot_options::boot_current().unwrap_or(None);
+    // In BootOrder's order, with the ones it leaves out at the end
+    let order = boot_options::boot_order().unwrap_or_default();
+    options.sort_by_key(|&(number, _)| order.iter().position(|n| *n == number).unwrap_or(usize::max_value()));
+
+    let args = common::args();
+    let chosen = match args.first().map(String::as_str) {
+        Some("list") => {
+            for &(number, ref option) in &options {
+                println!("{}", describe(number, option, current));
+            }
+            return Ok(());
+        }
+        Some(number) => u16::from_str_radix(number.trim_start_matches("Boot"), 16).map_err(|_| format!("{} isn't a boot option number", number))?,
+        None => match choose(&console::console(), &options, current)? {
+            Some(number) => number,
+            None => return Ok(()),
+        },
+    };
+
+    if !options.iter().any(|&(number, _)| number == chosen) {
+        return Err(format!("there's no Boot{:04X}", chosen));
+    }
+    boot_options::set_boot_next(chosen).map_err(|e| format!("setting BootNext: {}", e))?;
+    println!("Booting Boot{:04X}...", chosen);
+    os_indications::reboot()
+}
+
+fn describe(number: u16, option: &LoadOption, current: Option<u16>) -> String {
+    format!("Boot{:04X}{} {}{}{}", number, if current == Some(number) { "*" } else { " " }, option.description,
+        option.file_name().map(|f| format!(" ({})", f)).unwrap_or_default(), if option.is_active() { "" } else { " [inactive]" })
+}
+
+// Draws the menu and lets the user move through it. None if they pressed Esc
+fn choose(console: &Console, options: &[(u16, LoadOption)], current: Option<u16>) -> Result<Option<u16>, String> {
+    println!("Choose what to boot, then press Enter (Esc to leave):");
+    println!("");
+    let top = console.cursor_pos();
+    let mut selected = options.iter().position(|&(number, _)| Some(number) == current).unwrap_or(0);
+    loop {
+        let mut pos = top;
+        for (i, &(number, ref option)) in options.iter().enumerate() {
+            console.set_cursor_pos(pos).map_err(|e| format!("drawing the menu: {}", e))?;
+            print!("{} {}", if i == selected { ">" } else { " " }, describe(number, option, current));
+            pos.row += 1;
+        }
+
+        let key = console.read_key().map_err(|e| format!("reading the keyboard: {}", e))?;
+        match (key.ScanCode, key.UnicodeChar) {
+            (SCAN_UP, _) => selected = selected.checked_sub(1).unwrap_or(options.len() - 1),
+            (SCAN_DOWN, _) => selected = (selected + 1) % options.len(),
+            (SCAN_ESC, _) => return Ok(None),
+            (_, ENTER) => break,
+            _ => (),
+        }
+    }
+    println!("");
+    Ok(Some(options[selected].0))
+}
@@ -0,0 +1,73 @@
+// What the examples share: the handlers every UEFI application has to have, getting at arguments and finishing up.
+//
+// Each example takes its arguments from its load options, so at the shell they go after its name as usual. Booted by
+// efi-runner (see runner/) there are no load options and they come from EFI_RUNNER_ARGS instead. Build with the qemu
+// feature to run them that way, so they tell the runner whether they worked rather than going back to the firmware:
+//
+//     EFI_RUNNER_ARGS="..." cargo run --example <name> --features qemu -Zbuild-std=core,alloc --target x86_64-unknown-uefi
+
+#![allow(dead_code)] // Not every example uses everything
+
+use efi::{fs::ShellFs, image};
+use alloc::{string::String, vec::Vec};
+use core::{alloc::Layout, panic::PanicInfo};
+
+/// The example's arguments, without its own name
+pub fn args() -> Vec<String> {
+    let options = image::load_options().unwrap_or_default();
+    let mut args: Vec<String> = options.split_whitespace().map(String::from).collect();
+    // The shell passes the whole command line, name and all
+    if !args.is_empty() && ShellFs::new().is_ok() {
+        args.remove(0);
+    }
+    if args.is_empty() {
+        if let Some(runner_args) = runner_args() {
+            args = runner_args.split_whitespace().map(String::from).collect();
+        }
+    }
+    args
+}
+
+/// What efi_main returns. Says what went wrong if anything did
+pub fn finish(result: Result<(), String>) -> isize {
+    let success = match result {
+        Ok(()) => true,
+        Err(e) => {
+            println!("error: {}", e);
+            false
+        }
+    };
+    exit(success)
+}
+
+#[cfg(feature = "qemu")]
+fn runner_args() -> Option<String> {
+    efi::qemu::runner_args()
+}
+
+#[cfg(not(feature = "qemu"))]
+fn runner_args() -> Option<String> {
+    None
+}
+
+#[cfg(feature = "qemu")]
+fn exit(success: bool) -> isize {
+    efi::qemu::exit(success)
+}
+
+#[cfg(not(feature = "qemu"))]
+fn exit(success: bool) -> isize {
+    (if success { efi::ffi::EFI_SUCCESS } else { efi::ffi::EFI_ABORTED }) as isize
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    efi::backtrace::report_panic(info);
+    exit(false);
+    loop {}
+}
+
+#[alloc_error_handler]
+fn alloc_error(_: Layout) -> ! {
+    panic!("out of memory")
+}
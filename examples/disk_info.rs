@@ -0,0 +1,95 @@
+// Lists the disks the firmware knows about: size, device path and partitions, and for drives behind ATA or NVMe
+// pass-through the model, serial number and SMART health.
+//
+//     disk_info.efi
+//
+// Under QEMU the ESP the runner makes is one; give it more to look at with something like
+//
+//     EFI_RUNNER_QEMU_ARGS="-drive file=disk.img,if=none,id=nvm -device nvme,serial=deadbeef,drive=nvm" \
+//         cargo run --example disk_info --features qemu -Zbuild-std=core,alloc --target x86_64-unknown-uefi
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+#![feature(abi_efiapi)]
+
+#[macro_use] extern crate efi;
+#[macro_use] extern crate alloc;
+
+mod common;
+
+use efi::{
+    ffi::{self, media::EFI_BLOCK_IO_PROTOCOL_GUID},
+    init_env,
+    disk::{Drive, Interface},
+    fs::{BlockDevice, BlockIo},
+    handles,
+    partition,
+};
+use alloc::string::String;
+
+#[no_mangle]
+pub extern "efiapi" fn efi_main(image_handle: ffi::EFI_HANDLE, sys_table: *const ffi::EFI_SYSTEM_TABLE) -> isize {
+    init_env(image_handle, sys_table);
+    common::finish(run())
+}
+
+fn run() -> Result<(), String> {
+    let disks = handles::handles_with(&EFI_BLOCK_IO_PROTOCOL_GUID).map_err(|e| format!("listing block devices: {}", e))?;
+    let mut count = 0;
+    for info in disks {
+        let mut block_io = match BlockIo::new(info.handle) {
+            Ok(ref block_io) if block_io.is_partition() => continue, // Shown with their disk
+            Ok(block_io) => block_io,
+            Err(_) => continue, // No media
+        };
+        count += 1;
+
+        let size = block_io.block_size() as u64 * block_io.block_count();
+        println!("Disk {}: {} ({} blocks of {} bytes){}", count, human_size(size), block_io.block_count(), block_io.block_size(),
+            if block_io.is_read_only() { ", read only" } else { "" });
+        if let Some(path) = info.device_path() {
+            println!("  {}", path);
+        }
+
+        if let Ok(drive) = Drive::new(info.handle) {
+            match drive.health() {
+                Ok(health) => println!("  {}", health),
+                Err(e) => {
+                    let interface = match *drive.interface() {
+                        Interface::Ata(_) => "ATA",
+                        Interface::Nvme(_) => "NVMe",
+                    };
+                    if let Ok(identity) = drive.identity() {
+                        println!("  {} {} ({}), firmware {}", interface, identity.model, identity.serial, identity.firmware);
+                    }
+                    println!("  No SMART data: {}", e);
+                }
+            }
+        }
+
+        match partition::read_gpt(&mut block_io) {
+            Ok(partitions) => {
+                for (i, p) in partitions.iter().enumerate() {
+                    let (_, len) = p.byte_range(block_io.block_size());
+                    println!("  {}: {:<36} {:>10} {}", i + 1, p.name, human_size(len), p.type_guid);
+                }
+            }
+            Err(_) => println!("  No GPT"),
+        }
+        println!("");
+    }
+    println!("{} disks", count);
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 10 * 1024 && unit + 1 < UNITS.len() {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{} {}", size, UNITS[unit])
+}
@@ -0,0 +1,135 @@
+// A small shell for looking around the volume it was loaded from.
+//
+//     file_browser.efi [command; command...]
+//
+// Commands are `ls [dir]`, `cd <dir>`, `cat <file>`, `stat <path>` and `exit`. Given some as arguments it runs those
+// and exits, otherwise it reads them from the console. Under the runner the volume is its ESP:
+//
+//     EFI_RUNNER_ARGS="ls; cd EFI/BOOT; stat BOOTX64.EFI" \
+//         cargo run --example file_browser --features qemu -Zbuild-std=core,alloc --target x86_64-unknown-uefi
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+#![feature(abi_efiapi)]
+
+#[macro_use] extern crate efi;
+#[macro_use] extern crate alloc;
+
+mod common;
+
+use efi::{
+    ffi,
+    init_env,
+    fs::{FileSystem, SimpleFs},
+    io::BufRead,
+};
+use alloc::{string::String, vec::Vec};
+
+#[no_mangle]
+pub extern "efiapi" fn efi_main(image_handle: ffi::EFI_HANDLE, sys_table: *const ffi::EFI_SYSTEM_TABLE) -> isize {
+    init_env(image_handle, sys_table);
+    common::finish(run())
+}
+
+fn run() -> Result<(), String> {
+    let fs = SimpleFs::boot_volume().map_err(|e| format!("opening the boot volume: {}", e))?;
+    let mut browser = Browser { fs: &fs, cwd: Vec::new() };
+
+    let args = common::args();
+    if !args.is_empty() {
+        // Scripted: every command has to work
+        for command in args.join(" ").split(';') {
+            println!("/{}> {}", browser.cwd.join("/"), command.trim());
+            if !browser.run(command)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut lines = efi::stdin().lines();
+    loop {
+        print!("/{}> ", browser.cwd.join("/"));
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return Ok(()),
+        };
+        match browser.run(&line) {
+            Ok(true) => (),
+            Ok(false) => return Ok(()),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+struct Browser<'a> {
+    fs: &'a dyn FileSystem,
+    cwd: Vec<String>,
+}
+
+impl<'a> Browser<'a> {
+    // Runs one command. Returns false for exit
+    fn run(&mut self, command: &str) -> Result<bool, String> {
+        let mut words = command.split_whitespace();
+        let (name, arg) = match words.next() {
+            Some(name) => (name, words.next()),
+            None => return Ok(true),
+        };
+        match (name, arg) {
+            ("ls", dir) => {
+                let dir = self.resolve(dir.unwrap_or("."));
+                let mut entries = self.fs.read_dir(&dir).map_err(|e| format!("ls /{}: {}", dir, e))?;
+                entries.sort_by(|a, b| (!a.metadata.is_dir, &a.name).cmp(&(!b.metadata.is_dir, &b.name)));
+                for entry in entries {
+                    if entry.metadata.is_dir {
+                        println!("  {:>10}  {}/", "<DIR>", entry.name);
+                    } else {
+                        println!("  {:>10}  {}", entry.metadata.len, entry.name);
+                    }
+                }
+            }
+            ("cd", Some(dir)) => {
+                let path = self.resolve(dir);
+                let metadata = self.fs.metadata(&path).map_err(|e| format!("cd /{}: {}", path, e))?;
+                if !metadata.is_dir {
+                    return Err(format!("cd /{}: not a directory", path));
+                }
+                self.cwd = components(&path);
+            }
+            ("cat", Some(file)) => {
+                let path = self.resolve(file);
+                let data = self.fs.read(&path).map_err(|e| format!("cat /{}: {}", path, e))?;
+                println!("{}", String::from_utf8_lossy(&data));
+            }
+            ("stat", Some(path)) => {
+                let path = self.resolve(path);
+                let metadata = self.fs.metadata(&path).map_err(|e| format!("stat /{}: {}", path, e))?;
+                println!("  /{}: {}, {} bytes{}", path, if metadata.is_dir { "directory" } else { "file" }, metadata.len,
+                    if metadata.read_only { ", read only" } else { "" });
+            }
+            ("exit", _) => return Ok(false),
+            _ => return Err(format!("{}: try ls, cd, cat, stat or exit", command.trim())),
+        }
+        Ok(true)
+    }
+
+    // The path from the root, without a leading slash, of a path from the current directory
+    fn resolve(&self, path: &str) -> String {
+        let mut resolved = if path.starts_with('/') || path.starts_with('\\') { Vec::new() } else { self.cwd.clone() };
+        for component in components(path) {
+            match component.as_str() {
+                "." => (),
+                ".." => {
+                    resolved.pop();
+                }
+                _ => resolved.push(component),
+            }
+        }
+        resolved.join("/")
+    }
+}
+
+fn components(path: &str) -> Vec<String> {
+    path.split(|c| c == '/' || c == '\\').filter(|c| !c.is_empty()).map(String::from).collect()
+}
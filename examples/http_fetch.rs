@@ -0,0 +1,77 @@
+// GETs a URL and prints the response's status and headers, then either the body or, given a file to save it to, how
+// the download is going.
+//
+//     http_fetch.efi <url> [file]
+//
+// Plain http:// only, like efi::net::http. Under QEMU's user networking the host is 10.0.2.2, so with
+// `python3 -m http.server 8000` running there:
+//
+//     EFI_RUNNER_QEMU_ARGS="-nic user,model=virtio-net-pci" EFI_RUNNER_ARGS="http://10.0.2.2:8000/" \
+//         cargo run --example http_fetch --features qemu -Zbuild-std=core,alloc --target x86_64-unknown-uefi
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+#![feature(abi_efiapi)]
+
+#[macro_use] extern crate efi;
+#[macro_use] extern crate alloc;
+
+mod common;
+
+use efi::{
+    ffi,
+    init_env,
+    fs,
+    io::Read,
+    net::{http, Url},
+};
+use alloc::{string::String, vec::Vec};
+
+#[no_mangle]
+pub extern "efiapi" fn efi_main(image_handle: ffi::EFI_HANDLE, sys_table: *const ffi::EFI_SYSTEM_TABLE) -> isize {
+    init_env(image_handle, sys_table);
+    common::finish(run())
+}
+
+fn run() -> Result<(), String> {
+    let args = common::args();
+    let url = args.first().ok_or("usage: http_fetch <url> [file]")?;
+    let url: Url = url.parse().map_err(|e| format!("{}: {}", url, e))?;
+
+    println!("GET {}", url);
+    let mut response = http::get(&url).map_err(|e| format!("fetching {}: {}", url, e))?;
+    println!("{} {}", response.status(), response.reason());
+    for &(ref name, ref value) in response.headers() {
+        println!("{}: {}", name, value);
+    }
+    println!("");
+
+    let len = response.content_length();
+    let mut body = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = response.read(&mut buf).map_err(|e| format!("reading the body: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..n]);
+        if args.len() > 1 {
+            match len {
+                Some(len) if len > 0 => print!("\r{} of {} bytes ({}%)", body.len(), len, body.len() as u64 * 100 / len),
+                _ => print!("\r{} bytes", body.len()),
+            }
+        }
+    }
+
+    match args.get(1) {
+        Some(path) => {
+            println!("");
+            let fs = fs::default_fs().map_err(|e| format!("opening the file system: {}", e))?;
+            fs.write(path, &body).map_err(|e| format!("writing {}: {}", path, e))?;
+            println!("Saved {} bytes to {}", body.len(), path);
+        }
+        None => println!("{}", String::from_utf8_lossy(&body)),
+    }
+    Ok(())
+}
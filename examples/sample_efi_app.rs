@@ -1,6 +1,7 @@
 #![no_std]
 #![no_main]
 #![feature(alloc_error_handler)]
+#![feature(abi_efiapi)]
 
 #[macro_use] extern crate efi;
 #[macro_use] extern crate alloc;
@@ -19,7 +20,7 @@ use core::{panic::PanicInfo, alloc::Layout};
 
 // EFI entry point. This function is the one that the UEFI platform calls when this image is loaded.
 #[no_mangle]
-pub extern "win64" fn efi_main(image_handle: ffi::EFI_HANDLE,
+pub extern "efiapi" fn efi_main(image_handle: ffi::EFI_HANDLE,
                                 sys_table : *const ffi::EFI_SYSTEM_TABLE) -> isize {
 
     init_env(image_handle, sys_table);
@@ -0,0 +1,66 @@
+// Connects to a TCP server, sends it a line and prints what comes back until the server closes the connection.
+//
+//     tcp_client.efi <host>:<port> [message...]
+//
+// Names with both IPv6 and IPv4 addresses are raced, so the connection is over whichever family answers first.
+//
+// The firmware configures the NIC itself, with DHCP unless it's been set up otherwise. Under QEMU's user networking
+// the host is 10.0.2.2, so with `nc -l 8000` running there:
+//
+//     EFI_RUNNER_QEMU_ARGS="-nic user,model=virtio-net-pci" EFI_RUNNER_ARGS="10.0.2.2:8000 hello" \
+//         cargo run --example tcp_client --features qemu -Zbuild-std=core,alloc --target x86_64-unknown-uefi
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+#![feature(abi_efiapi)]
+
+#[macro_use] extern crate efi;
+#[macro_use] extern crate alloc;
+
+mod common;
+
+use efi::{
+    ffi,
+    init_env,
+    io::{Read, Write},
+    net::TcpStream,
+};
+use alloc::string::String;
+use core::time::Duration;
+
+#[no_mangle]
+pub extern "efiapi" fn efi_main(image_handle: ffi::EFI_HANDLE, sys_table: *const ffi::EFI_SYSTEM_TABLE) -> isize {
+    init_env(image_handle, sys_table);
+    common::finish(run())
+}
+
+fn run() -> Result<(), String> {
+    let args = common::args();
+    let addr = args.first().ok_or("usage: tcp_client <host>:<port> [message...]")?;
+    let message = if args.len() > 1 { args[1..].join(" ") } else { String::from("hello from UEFI") };
+
+    println!("Connecting to {}...", addr);
+    let mut stream = TcpStream::builder()
+        .happy_eyeballs(Duration::from_millis(250))
+        .connect(addr.as_str()).map_err(|e| format!("connecting to {}: {}", addr, e))?;
+    if let Ok(local) = stream.local_addr() {
+        println!("Connected from {}", local);
+    }
+
+    stream.write_all(message.as_bytes()).and_then(|_| stream.write_all(b"\r\n")).map_err(|e| format!("sending: {}", e))?;
+
+    let mut received = 0;
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = stream.read(&mut buf).map_err(|e| format!("receiving: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        print!("{}", String::from_utf8_lossy(&buf[..n]));
+        received += n;
+    }
+    println!("");
+    println!("{} bytes received", received);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+// Procedural macros re-exported by the efi crate. Use them through efi (e.g. #[efi::test]) rather than directly.
+// Parsing is done by hand on the raw token stream to keep this crate free of dependencies.
+
+extern crate proc_macro;
+
+use proc_macro::{TokenStream, TokenTree};
+
+/// Marks a function as a test to be run inside the firmware by `efi::testing::runner`.
+/// The function takes no arguments and returns either `()` or a `Result`. `#[efi::test(ignore)]` skips it.
+/// The test binary must enable `custom_test_frameworks` and set `efi::testing::runner` as its `test_runner`.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let ignore = match attr.to_string().trim() {
+        "" => false,
+        "ignore" => true,
+        other => return compile_error(&format!("unknown #[efi::test] option `{}`", other)),
+    };
+
+    let name = match fn_name(&item) {
+        Some(name) => name,
+        None => return compile_error("#[efi::test] can only be applied to functions"),
+    };
+
+    // Like #[test] the function and its descriptor only exist in test builds
+    let descriptor = format!(
+        "#[cfg(test)]
+        #[test_case]
+        #[allow(non_upper_case_globals)]
+        static __efi_test_{name}: ::efi::testing::Test = ::efi::testing::Test {{
+            name: concat!(module_path!(), \"::\", stringify!({name})),
+            ignore: {ignore},
+            run: || ::efi::testing::TestResult::passed({name}()),
+        }};",
+        name = name,
+        ignore = ignore);
+
+    let mut out: TokenStream = "#[cfg(test)]".parse().unwrap();
+    out.extend(item);
+    out.extend(descriptor.parse::<TokenStream>().unwrap());
+    out
+}
+
+// The name of the function is the identifier right after the `fn` keyword
+fn fn_name(item: &TokenStream) -> Option<String> {
+    let mut tokens = item.clone().into_iter();
+    while let Some(token) = tokens.next() {
+        if let TokenTree::Ident(ref ident) = token {
+            if ident.to_string() == "fn" {
+                return match tokens.next() {
+                    Some(TokenTree::Ident(name)) => Some(name.to_string()),
+                    _ => None,
+                };
+            }
+        }
+    }
+
+    None
+}
+
+fn compile_error(msg: &str) -> TokenStream {
+    format!("compile_error!({:?});", msg).parse().unwrap()
+}
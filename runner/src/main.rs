@@ -0,0 +1,239 @@
+// Cargo runner that boots a UEFI image under QEMU and OVMF and exits with whether it passed.
+// Install it with `cargo install --path runner` and set it as the runner for the UEFI targets in .cargo/config.toml:
+//
+//     [target.x86_64-unknown-uefi]
+//     runner = "efi-runner"
+//
+// after which `cargo run` and `cargo test` (with the in-firmware harness of the efi crate's `testing` feature) boot
+// the image. It's copied to EFI/BOOT/BOOT<arch>.EFI of a scratch directory which QEMU serves as a FAT drive, so the
+// firmware boots it straight away. The serial port goes to our stdout.
+//
+// Pass/fail comes from, in order:
+// - the isa-debug-exit device (x86 only), which the efi crate's qemu feature writes to (see efi::qemu::exit)
+// - the last "test result:" line printed by the test harness
+// - QEMU's own exit status
+//
+// Environment:
+// - EFI_RUNNER_FIRMWARE: the OVMF/AAVMF image to use instead of looking in the usual places
+// - EFI_RUNNER_QEMU: the QEMU binary to use instead of qemu-system-<arch>
+// - EFI_RUNNER_QEMU_ARGS: more arguments for QEMU, split on whitespace (e.g. "-nic user,model=virtio")
+// - EFI_RUNNER_ARGS: arguments for the image, which gets them from fw_cfg (see efi::qemu::runner_args). Images booted
+//   from EFI/BOOT have no load options to pass them in
+// Arguments after the image are passed to QEMU as well.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+// What efi::qemu writes to isa-debug-exit, after QEMU turns them into (code << 1) | 1
+const DEBUG_EXIT_SUCCESS: i32 = (0x10 << 1) | 1;
+const DEBUG_EXIT_FAILURE: i32 = (0x11 << 1) | 1;
+
+// The serial port is the only output, with no network unless asked for
+const QEMU_ARGS: &[&str] = &["-m", "256M", "-nodefaults", "-display", "none", "-no-reboot", "-serial", "stdio", "-net", "none"];
+const DEBUG_EXIT_ARGS: &[&str] = &["-device", "isa-debug-exit,iobase=0xf4,iosize=0x04"];
+
+// Where efi::qemu::runner_args looks for EFI_RUNNER_ARGS
+const ARGS_FILE: &str = "opt/efi-runner/args";
+
+struct Arch {
+    machine: u16, // PE machine type
+    qemu: &'static str,
+    boot_file: &'static str,
+    qemu_args: &'static [&'static str],
+    firmware: &'static [&'static str], // Where distributions put it
+    debug_exit: bool,
+}
+
+const ARCHES: &[Arch] = &[
+    Arch {
+        machine: 0x8664,
+        qemu: "qemu-system-x86_64",
+        boot_file: "BOOTX64.EFI",
+        qemu_args: &["-machine", "q35"],
+        firmware: &[
+            "/usr/share/OVMF/OVMF_CODE.fd",
+            "/usr/share/ovmf/OVMF.fd",
+            "/usr/share/edk2/ovmf/OVMF_CODE.fd",
+            "/usr/share/edk2-ovmf/x64/OVMF_CODE.fd",
+            "/usr/share/qemu/ovmf-x86_64.bin",
+        ],
+        debug_exit: true,
+    },
+    Arch {
+        machine: 0x014c,
+        qemu: "qemu-system-i386",
+        boot_file: "BOOTIA32.EFI",
+        qemu_args: &["-machine", "q35"],
+        firmware: &[
+            "/usr/share/OVMF/OVMF32_CODE_4M.fd",
+            "/usr/share/edk2/ovmf-ia32/OVMF_CODE.fd",
+            "/usr/share/edk2-ovmf/ia32/OVMF_CODE.fd",
+        ],
+        debug_exit: true,
+    },
+    Arch {
+        machine: 0xaa64,
+        qemu: "qemu-system-aarch64",
+        boot_file: "BOOTAA64.EFI",
+        qemu_args: &["-machine", "virt", "-cpu", "cortex-a57"],
+        firmware: &[
+            "/usr/share/AAVMF/AAVMF_CODE.fd",
+            "/usr/share/qemu-efi-aarch64/QEMU_EFI.fd",
+            "/usr/share/edk2/aarch64/QEMU_EFI.fd",
+            "/usr/share/edk2-armvirt/aarch64/QEMU_EFI.fd",
+        ],
+        debug_exit: false,
+    },
+    Arch {
+        machine: 0x5064,
+        qemu: "qemu-system-riscv64",
+        boot_file: "BOOTRISCV64.EFI",
+        qemu_args: &["-machine", "virt"],
+        firmware: &[
+            "/usr/share/qemu-efi-riscv64/RISCV_VIRT_CODE.fd",
+            "/usr/share/edk2/riscv/RISCV_VIRT_CODE.fd",
+        ],
+        debug_exit: false,
+    },
+];
+
+fn main() {
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("efi-runner: {}", message);
+            process::exit(2);
+        }
+    }
+}
+
+fn run() -> Result<i32, String> {
+    let mut args = env::args_os().skip(1);
+    let image = PathBuf::from(args.next().ok_or("usage: efi-runner <image.efi> [qemu args...]")?);
+    let image_bytes = fs::read(&image).map_err(|e| format!("can't read {}: {}", image.display(), e))?;
+    let machine = pe_machine(&image_bytes).ok_or_else(|| format!("{} isn't a PE image", image.display()))?;
+    let arch = ARCHES.iter().find(|a| a.machine == machine).ok_or_else(|| format!("unsupported machine type {:#x}", machine))?;
+
+    // Next to the image so that it's cleaned up with the rest of target/
+    let esp = image.with_extension("esp");
+    let boot_dir = esp.join("EFI").join("BOOT");
+    fs::create_dir_all(&boot_dir).map_err(|e| format!("can't create {}: {}", boot_dir.display(), e))?;
+    fs::write(boot_dir.join(arch.boot_file), &image_bytes).map_err(|e| format!("can't copy the image: {}", e))?;
+
+    let firmware = match env::var_os("EFI_RUNNER_FIRMWARE") {
+        Some(firmware) => PathBuf::from(firmware),
+        None => arch.firmware.iter().map(PathBuf::from).find(|p| p.exists())
+            .ok_or_else(|| format!("no firmware found for {}, set EFI_RUNNER_FIRMWARE", arch.qemu))?,
+    };
+
+    let qemu = env::var("EFI_RUNNER_QEMU").unwrap_or_else(|_| arch.qemu.to_string());
+    let mut command = Command::new(&qemu);
+    command.args(arch.qemu_args)
+        .args(QEMU_ARGS)
+        .arg("-drive").arg(flag("if=pflash,format=raw,readonly=on,file=", &firmware))
+        .arg("-drive").arg(flag("format=raw,file=fat:rw:", &esp));
+    if arch.debug_exit {
+        command.args(DEBUG_EXIT_ARGS);
+    }
+    if let Ok(image_args) = env::var("EFI_RUNNER_ARGS") {
+        command.arg("-fw_cfg").arg(format!("name={},string={}", ARGS_FILE, escape(&image_args)));
+    }
+    if let Ok(extra) = env::var("EFI_RUNNER_QEMU_ARGS") {
+        command.args(extra.split_whitespace());
+    }
+    command.args(args).stdout(Stdio::piped());
+
+    let mut child = command.spawn().map_err(|e| format!("can't start {}: {}", qemu, e))?;
+    let test_result = echo(child.stdout.take().unwrap());
+    let status = child.wait().map_err(|e| format!("waiting for {}: {}", qemu, e))?;
+
+    Ok(match (status.code(), test_result) {
+        (Some(DEBUG_EXIT_SUCCESS), _) => 0,
+        (Some(DEBUG_EXIT_FAILURE), _) => 1,
+        (_, Some(passed)) => if passed { 0 } else { 1 },
+        (Some(code), None) => code,
+        (None, None) => 1, // Killed
+    })
+}
+
+// Copies the guest's serial output to ours. Returns whether the tests passed if the harness said
+fn echo<R: io::Read>(output: R) -> Option<bool> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut result = None;
+    let mut reader = BufReader::new(output);
+    let mut line = Vec::new();
+    // Lines are read as bytes since firmware output isn't always UTF-8 (e.g. the escape codes of its setup screens)
+    while let Ok(n) = reader.read_until(b'\n', &mut line) {
+        if n == 0 {
+            break;
+        }
+        let _ = stdout.write_all(&line);
+        let _ = stdout.flush();
+        if let Some(passed) = test_result(&String::from_utf8_lossy(&line)) {
+            result = Some(passed);
+        }
+        line.clear();
+    }
+    result
+}
+
+// Found anywhere in the line since the console may have put escape codes before it
+fn test_result(line: &str) -> Option<bool> {
+    const RESULT: &str = "test result: ";
+    let line = &line[line.find(RESULT)? + RESULT.len()..];
+    if line.starts_with("ok.") {
+        Some(true)
+    } else if line.starts_with("FAILED.") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// QEMU options are comma separated, so commas in paths and strings have to be doubled
+fn flag(prefix: &str, path: &Path) -> String {
+    format!("{}{}", prefix, escape(&path.display().to_string()))
+}
+
+fn escape(value: &str) -> String {
+    value.replace(',', ",,")
+}
+
+fn pe_machine(image: &[u8]) -> Option<u16> {
+    if image.get(..2)? != b"MZ" {
+        return None;
+    }
+    let offset = image.get(0x3c..0x40)?;
+    let pe = u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+    if image.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    Some(u16::from_le_bytes([*image.get(pe + 4)?, *image.get(pe + 5)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_results() {
+        let mut image = vec![0u8; 0x90];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c] = 0x80;
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x84..0x86].copy_from_slice(&0xaa64u16.to_le_bytes());
+        assert_eq!(pe_machine(&image), Some(0xaa64));
+        assert_eq!(pe_machine(b"ELF"), None);
+
+        let output = "running 2 tests\r\ntest a ... ok\r\n\r\ntest result: FAILED. 1 passed; 1 failed; 0 ignored\r\n";
+        assert_eq!(echo(output.as_bytes()), Some(false));
+        assert_eq!(test_result("\x1b[0mtest result: ok. 2 passed; 0 failed; 0 ignored"), Some(true));
+        assert_eq!(echo("Welcome to UEFI\n".as_bytes()), None);
+
+        assert_eq!(flag("file=", Path::new("/tmp/a,b.esp")), "file=/tmp/a,,b.esp");
+    }
+}
@@ -0,0 +1,206 @@
+// ACPI tables.
+//
+// Firmware puts the RSDP in the configuration table. From there the XSDT (or the RSDT on ACPI 1.0 systems) lists the
+// physical addresses of all the other tables, which are identity mapped while boot services are up. Tables are handed
+// out as byte slices, checksums verified, for the modules that understand particular ones to parse.
+
+use byteorder::{ByteOrder, LittleEndian};
+use ffi::EFI_GUID;
+use {system_table, Result, EfiErrorKind};
+use alloc::vec::Vec;
+
+pub const EFI_ACPI_20_TABLE_GUID: EFI_GUID = EFI_GUID(0x8868E871, 0xE4F1, 0x11D3, [0xBC, 0x22, 0x00, 0x80, 0xC7, 0x3C, 0x88, 0x81]);
+pub const ACPI_TABLE_GUID: EFI_GUID = EFI_GUID(0xEB9D2D30, 0x2D88, 0x11D3, [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
+
+/// The header every system description table starts with
+pub const SDT_HEADER_SIZE: usize = 36;
+
+const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
+const RSDP_V1_SIZE: usize = 20;
+const RSDP_V2_SIZE: usize = 36;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: [u8; 4],
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    pub fn parse(table: &[u8]) -> Option<Self> {
+        if table.len() < SDT_HEADER_SIZE {
+            return None;
+        }
+        let mut header = SdtHeader {
+            signature: [0; 4],
+            length: LittleEndian::read_u32(&table[4..]),
+            revision: table[8],
+            oem_id: [0; 6],
+            oem_table_id: [0; 8],
+            oem_revision: LittleEndian::read_u32(&table[24..]),
+            creator_id: [0; 4],
+            creator_revision: LittleEndian::read_u32(&table[32..]),
+        };
+        header.signature.copy_from_slice(&table[..4]);
+        header.oem_id.copy_from_slice(&table[10..16]);
+        header.oem_table_id.copy_from_slice(&table[16..24]);
+        header.creator_id.copy_from_slice(&table[28..32]);
+        Some(header)
+    }
+}
+
+/// Whether the bytes add up to zero, as every ACPI structure's do
+pub fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// A table at a physical address, as long as its header says it is
+///
+/// # Safety
+/// `address` must point to a readable ACPI table
+pub unsafe fn table_at(address: usize) -> &'static [u8] {
+    let header = core::slice::from_raw_parts(address as *const u8, SDT_HEADER_SIZE);
+    let length = LittleEndian::read_u32(&header[4..]) as usize;
+    core::slice::from_raw_parts(address as *const u8, length.max(SDT_HEADER_SIZE))
+}
+
+/// Every table the XSDT or RSDT lists, in order. Empty if the firmware didn't install any ACPI tables. Tables whose
+/// checksums are wrong are left out
+pub fn tables() -> Result<Vec<&'static [u8]>> {
+    let (root, entry_size) = match root_table()? {
+        Some(root) => root,
+        None => return Ok(Vec::new()),
+    };
+
+    let entries = &root[SDT_HEADER_SIZE..];
+    let tables = entries.chunks_exact(entry_size).filter_map(|entry| {
+        let address = if entry_size == 8 { LittleEndian::read_u64(entry) } else { LittleEndian::read_u32(entry) as u64 };
+        if address == 0 {
+            return None;
+        }
+        let table = unsafe { table_at(address as usize) };
+        if checksum_ok(table) { Some(table) } else { None }
+    }).collect();
+    Ok(tables)
+}
+
+/// The first table with the given signature e.g. `b"FACP"`
+pub fn find_table(signature: &[u8; 4]) -> Result<Option<&'static [u8]>> {
+    Ok(tables()?.into_iter().find(|table| table[..4] == signature[..]))
+}
+
+/// The RSDP: 20 bytes for ACPI 1.0, 36 from 2.0 on. None if the firmware didn't install one
+pub fn rsdp() -> Result<Option<&'static [u8]>> {
+    let st = system_table();
+    let config_tables = unsafe { core::slice::from_raw_parts(st.ConfigurationTable, st.NumberOfTableEntries) };
+    let rsdp = config_tables.iter().find(|t| t.VendorGuid == EFI_ACPI_20_TABLE_GUID)
+        .or_else(|| config_tables.iter().find(|t| t.VendorGuid == ACPI_TABLE_GUID));
+    let rsdp = match rsdp {
+        Some(table) => table.VendorTable as *const u8,
+        None => return Ok(None),
+    };
+
+    let v1 = unsafe { core::slice::from_raw_parts(rsdp, RSDP_V1_SIZE) };
+    if &v1[..8] != RSDP_SIGNATURE || !checksum_ok(v1) {
+        return Err(EfiErrorKind::CrcError.into());
+    }
+    if v1[15] < 2 {
+        return Ok(Some(v1));
+    }
+    let v2 = unsafe { core::slice::from_raw_parts(rsdp, RSDP_V2_SIZE) };
+    if !checksum_ok(v2) {
+        return Err(EfiErrorKind::CrcError.into());
+    }
+    Ok(Some(v2))
+}
+
+// The XSDT, or the RSDT if there's no XSDT, along with the size of its entries
+fn root_table() -> Result<Option<(&'static [u8], usize)>> {
+    let rsdp = match rsdp()? {
+        Some(rsdp) => rsdp,
+        None => return Ok(None),
+    };
+    let (address, entry_size) = match rsdp.get(24..32).map(LittleEndian::read_u64) {
+        None | Some(0) => (LittleEndian::read_u32(&rsdp[16..]) as u64, 4),
+        Some(xsdt) => (xsdt, 8),
+    };
+    if address == 0 {
+        return Ok(None);
+    }
+
+    let root = unsafe { table_at(address as usize) };
+    let signature: &[u8] = if entry_size == 8 { b"XSDT" } else { b"RSDT" };
+    if &root[..4] != signature || !checksum_ok(root) {
+        return Err(EfiErrorKind::CrcError.into());
+    }
+    Ok(Some((root, entry_size)))
+}
+
+// Builds tables for tests: a header with the given signature and body, checksum filled in
+#[cfg(test)]
+pub(crate) fn build_table(signature: &[u8; 4], revision: u8, body: &[u8]) -> Vec<u8> {
+    let mut table = vec![0u8; SDT_HEADER_SIZE];
+    table[..4].copy_from_slice(signature);
+    LittleEndian::write_u32(&mut table[4..], (SDT_HEADER_SIZE + body.len()) as u32);
+    table[8] = revision;
+    table[10..16].copy_from_slice(b"EFIRS ");
+    table[16..24].copy_from_slice(b"TESTTABL");
+    table.extend_from_slice(body);
+    fix_checksum(&mut table, 9);
+    table
+}
+
+#[cfg(test)]
+fn fix_checksum(bytes: &mut [u8], at: usize) {
+    bytes[at] = 0;
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes[at] = 0u8.wrapping_sub(sum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::VOID;
+    use testing::mock;
+    use alloc::boxed::Box;
+
+    #[test]
+    fn finds_tables_through_xsdt() {
+        mock::install();
+        let apic: &'static [u8] = Box::leak(build_table(b"APIC", 4, &[1, 2, 3, 4]).into_boxed_slice());
+        let mut broken = build_table(b"HPET", 1, &[5; 8]);
+        broken[40] ^= 1;
+        let broken: &'static [u8] = Box::leak(broken.into_boxed_slice());
+
+        let mut entries = Vec::new();
+        for table in &[apic, broken] {
+            let mut entry = [0; 8];
+            LittleEndian::write_u64(&mut entry, table.as_ptr() as u64);
+            entries.extend_from_slice(&entry);
+        }
+        let xsdt: &'static [u8] = Box::leak(build_table(b"XSDT", 1, &entries).into_boxed_slice());
+
+        let mut rsdp = vec![0u8; RSDP_V2_SIZE];
+        rsdp[..8].copy_from_slice(RSDP_SIGNATURE);
+        rsdp[15] = 2;
+        LittleEndian::write_u32(&mut rsdp[20..], RSDP_V2_SIZE as u32);
+        LittleEndian::write_u64(&mut rsdp[24..], xsdt.as_ptr() as u64);
+        fix_checksum(&mut rsdp[..RSDP_V1_SIZE], 8);
+        fix_checksum(&mut rsdp, 32);
+        let rsdp: &'static [u8] = Box::leak(rsdp.into_boxed_slice());
+        let bs = system_table().BootServices;
+        unsafe { ((*bs).InstallConfigurationTable)(&EFI_ACPI_20_TABLE_GUID, rsdp.as_ptr() as *const VOID) };
+
+        assert_eq!(tables().unwrap(), [apic]);
+        let table = find_table(b"APIC").unwrap().unwrap();
+        let header = SdtHeader::parse(table).unwrap();
+        assert_eq!((&header.signature, header.length, header.revision, &header.oem_id), (b"APIC", 40, 4, b"EFIRS "));
+        assert_eq!(&table[SDT_HEADER_SIZE..], [1, 2, 3, 4]);
+        assert_eq!(find_table(b"HPET").unwrap(), None);
+    }
+}
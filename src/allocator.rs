@@ -1,5 +1,6 @@
 
 use system_table;
+use services::boot_services_exited;
 use ffi::{EFI_SUCCESS, VOID, boot_services::EFI_MEMORY_TYPE};
 use core::{
     ptr,
@@ -26,7 +27,8 @@ unsafe impl GlobalAlloc for EfiAllocator {
             // UEFI always allocates to 8-byte aligntment. So we're fine if align() says 8 or less.
             // If align() asks for something greater than 8 then we can handle that by rounding up here 
             // and by doing the converse calculation in dealloc() below. This is yet to be implemented.
-            layout.align() > 8 {
+            layout.align() > 8 ||
+            boot_services_exited() { // AllocatePool is gone along with the rest of boot services
             return ptr::null_mut();
         }
 
@@ -40,6 +42,10 @@ unsafe impl GlobalAlloc for EfiAllocator {
 
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
         // TODO: As mentioned above, stop ignoring layout::align() here
+        if boot_services_exited() {
+            return; // Can't free anymore. All memory belongs to the OS now anyway
+        }
+
         let status = ((*system_table().BootServices).FreePool)(ptr as *const VOID);
 
         if status != EFI_SUCCESS {
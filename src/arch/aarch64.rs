@@ -0,0 +1,80 @@
+// AArch64. UEFI runs us at EL1 or EL2 with the MMU on, identity mapped and caches enabled
+
+const DAIF_I: u64 = 1 << 7;
+
+/// Masks IRQs and FIQs.
+/// Unsafe because firmware timers and events stop working until interrupts are unmasked again
+pub unsafe fn disable_interrupts() {
+    asm!("msr daifset, #3", options(nomem, nostack));
+}
+
+pub unsafe fn enable_interrupts() {
+    asm!("msr daifclr, #3", options(nomem, nostack));
+}
+
+pub fn interrupts_enabled() -> bool {
+    let daif: u64;
+    unsafe { asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack, preserves_flags)) };
+    daif & DAIF_I == 0
+}
+
+/// Waits for the next interrupt
+pub fn halt() {
+    unsafe { asm!("wfi", options(nomem, nostack)) };
+}
+
+/// The caller's frame pointer (x29). Only meaningful if built with frame pointers (-C force-frame-pointers=yes)
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mov {}, x29", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+    fp
+}
+
+/// The frame pointer and return address (saved x29 and x30) in the frame record at `fp`.
+/// Unsafe because fp has to point at a frame record
+pub unsafe fn caller_frame(fp: usize) -> (usize, usize) {
+    let record = fp as *const usize;
+    (*record, *record.add(1))
+}
+
+/// Makes code written to [addr, addr + len) safe to execute by cleaning it to the point of
+/// coherency and invalidating the instruction cache. Must be done before jumping to a freshly copied image.
+pub fn sync_for_execution(addr: usize, len: usize) {
+    let line_size = dcache_line_size();
+    let mut line = addr & !(line_size - 1);
+    unsafe {
+        while line < addr + len {
+            asm!("dc civac, {}", in(reg) line, options(nostack, preserves_flags));
+            line += line_size;
+        }
+        asm!("dsb sy", "ic ialluis", "dsb sy", "isb", options(nostack, preserves_flags));
+    }
+}
+
+/// Smallest data cache line size in bytes as reported by CTR_EL0
+pub fn dcache_line_size() -> usize {
+    let ctr: u64;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack, preserves_flags)) };
+    4 << ((ctr >> 16) & 0xF) // DminLine is log2 of the number of 4-byte words
+}
+
+/// The exception level we're running at (1 or 2 under UEFI)
+pub fn current_el() -> u8 {
+    let el: u64;
+    unsafe { asm!("mrs {}, CurrentEL", out(reg) el, options(nomem, nostack, preserves_flags)) };
+    ((el >> 2) & 0x3) as u8
+}
+
+/// System control register of the current exception level
+pub fn read_sctlr() -> u64 {
+    let value: u64;
+    unsafe {
+        if current_el() == 2 {
+            asm!("mrs {}, sctlr_el2", out(reg) value, options(nomem, nostack, preserves_flags));
+        } else {
+            asm!("mrs {}, sctlr_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+    }
+    value
+}
@@ -0,0 +1,108 @@
+// Memory mapped I/O. Device registers have to be accessed with volatile reads and writes of exactly their width, or
+// the compiler is free to merge, split, reorder or drop them. UEFI identity maps MMIO ranges (those the firmware
+// knows about are in the memory map as EfiMemoryMappedIO), so physical addresses can be used as they are.
+
+use core::cell::UnsafeCell;
+
+/// Reads the register at `addr`.
+/// Unsafe because addr has to be a suitably aligned register, and reading some registers has side effects
+pub unsafe fn read<T: Copy>(addr: usize) -> T {
+    (addr as *const T).read_volatile()
+}
+
+/// Writes the register at `addr`.
+/// Unsafe because addr has to be a suitably aligned register, and writing it can make the device do anything
+pub unsafe fn write<T: Copy>(addr: usize, value: T) {
+    (addr as *mut T).write_volatile(value)
+}
+
+/// A read-write register, for use in `#[repr(C)]` structs laid out like a device's register block:
+///
+/// ```ignore
+/// #[repr(C)]
+/// struct Regs { control: Mmio<u32>, status: ReadOnly<u32> }
+/// let regs = unsafe { &*(base as *const Regs) };
+/// regs.control.modify(|c| c | 1);
+/// ```
+#[repr(transparent)]
+pub struct Mmio<T: Copy> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Copy> Mmio<T> {
+    /// The register at `addr`. Unsafe for the same reasons as read() and write()
+    pub unsafe fn at<'a>(addr: usize) -> &'a Self {
+        &*(addr as *const Self)
+    }
+
+    pub fn read(&self) -> T {
+        unsafe { self.value.get().read_volatile() }
+    }
+
+    pub fn write(&self, value: T) {
+        unsafe { self.value.get().write_volatile(value) }
+    }
+
+    /// Reads the register, then writes back what `f` makes of it
+    pub fn modify<F: FnOnce(T) -> T>(&self, f: F) {
+        self.write(f(self.read()))
+    }
+}
+
+/// A register that can only be read, e.g. a status register
+#[repr(transparent)]
+pub struct ReadOnly<T: Copy> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Copy> ReadOnly<T> {
+    pub unsafe fn at<'a>(addr: usize) -> &'a Self {
+        &*(addr as *const Self)
+    }
+
+    pub fn read(&self) -> T {
+        unsafe { self.value.get().read_volatile() }
+    }
+}
+
+/// A register that can only be written, e.g. a doorbell
+#[repr(transparent)]
+pub struct WriteOnly<T: Copy> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Copy> WriteOnly<T> {
+    pub unsafe fn at<'a>(addr: usize) -> &'a Self {
+        &*(addr as *const Self)
+    }
+
+    pub fn write(&self, value: T) {
+        unsafe { self.value.get().write_volatile(value) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Regs {
+        control: Mmio<u32>,
+        status: ReadOnly<u16>,
+        doorbell: WriteOnly<u16>,
+    }
+
+    #[test]
+    fn accesses_registers() {
+        let mut block = [0x11u32, 0x0000_0022];
+        let regs = unsafe { &*(block.as_mut_ptr() as *const Regs) };
+        regs.control.modify(|c| c | 0x100);
+        regs.doorbell.write(0xabcd);
+        assert_eq!(regs.control.read(), 0x111);
+        assert_eq!(regs.status.read(), if cfg!(target_endian = "little") { 0x22 } else { 0 });
+        let addr = block.as_ptr() as usize;
+        assert_eq!(unsafe { read::<u32>(addr + 4) }, if cfg!(target_endian = "little") { 0xabcd_0022 } else { 0x0022_abcd });
+        unsafe { write::<u32>(addr, 7) };
+        assert_eq!(block[0], 7);
+    }
+}
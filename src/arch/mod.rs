@@ -0,0 +1,32 @@
+// Architecture specific bits such as interrupt control, cache maintenance and control register access.
+// Each supported architecture has its own module. The functions common to all of them are re-exported here
+// so that code like the Linux loader can stay architecture neutral. MMIO accessors work the same everywhere and
+// are in mmio; port I/O only exists on x86.
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+pub mod x86;
+#[cfg(target_arch = "aarch64")]
+pub mod aarch64;
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+pub mod mmio;
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+use self::x86 as imp;
+#[cfg(target_arch = "aarch64")]
+use self::aarch64 as imp;
+#[cfg(target_arch = "riscv64")]
+use self::riscv64 as imp;
+
+pub use self::imp::{disable_interrupts, enable_interrupts, interrupts_enabled, halt, sync_for_execution, frame_pointer, caller_frame};
+
+/// Runs `f` with interrupts disabled, restoring the previous interrupt state afterwards
+pub fn without_interrupts<T, F: FnOnce() -> T>(f: F) -> T {
+    let enabled = interrupts_enabled();
+    unsafe { disable_interrupts() };
+    let ret = f();
+    if enabled {
+        unsafe { enable_interrupts() };
+    }
+    ret
+}
@@ -0,0 +1,57 @@
+// RISC-V 64. UEFI runs us in S-mode (or M-mode on some firmware, which we don't support) with paging off or identity mapped
+
+const SSTATUS_SIE: u64 = 1 << 1;
+
+/// Disables supervisor interrupts.
+/// Unsafe because firmware timers and events stop working until interrupts are enabled again
+pub unsafe fn disable_interrupts() {
+    asm!("csrci sstatus, 2", options(nomem, nostack));
+}
+
+pub unsafe fn enable_interrupts() {
+    asm!("csrsi sstatus, 2", options(nomem, nostack));
+}
+
+pub fn interrupts_enabled() -> bool {
+    read_sstatus() & SSTATUS_SIE != 0
+}
+
+/// Waits for the next interrupt
+pub fn halt() {
+    unsafe { asm!("wfi", options(nomem, nostack)) };
+}
+
+/// The caller's frame pointer (s0). Only meaningful if built with frame pointers (-C force-frame-pointers=yes)
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mv {}, s0", out(reg) fp, options(nomem, nostack)) };
+    fp
+}
+
+/// The frame pointer and return address saved in the frame at `fp`. Unlike on other architectures fp points just
+/// past the frame, so they're below it: the return address at fp - 8 and the saved frame pointer at fp - 16.
+/// Unsafe because fp has to point at a frame
+pub unsafe fn caller_frame(fp: usize) -> (usize, usize) {
+    let frame = fp as *const usize;
+    (*frame.sub(2), *frame.sub(1))
+}
+
+/// Makes code written to memory safe to execute. fence.i only covers the current hart which is fine
+/// since boot code runs on a single hart
+pub fn sync_for_execution(_addr: usize, _len: usize) {
+    unsafe { asm!("fence.i", options(nostack)) };
+}
+
+pub fn read_sstatus() -> u64 {
+    let value: u64;
+    unsafe { asm!("csrr {}, sstatus", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+/// Supervisor address translation and protection register (paging mode and root page table)
+pub fn read_satp() -> u64 {
+    let value: u64;
+    unsafe { asm!("csrr {}, satp", out(reg) value, options(nomem, nostack)) };
+    value
+}
@@ -0,0 +1,489 @@
+// x86 and x86_64. UEFI runs us in flat protected mode (ia32) or long mode (x64) with paging identity mapped
+
+const RFLAGS_IF: usize = 1 << 9;
+
+/// Disables maskable interrupts.
+/// Unsafe because firmware timers and events stop working until interrupts are enabled again
+pub unsafe fn disable_interrupts() {
+    asm!("cli", options(nomem, nostack));
+}
+
+pub unsafe fn enable_interrupts() {
+    asm!("sti", options(nomem, nostack));
+}
+
+pub fn interrupts_enabled() -> bool {
+    read_flags() & RFLAGS_IF != 0
+}
+
+/// Halts the CPU until the next interrupt
+pub fn halt() {
+    unsafe { asm!("hlt", options(nomem, nostack)) };
+}
+
+/// Makes code written to [addr, addr + len) safe to execute.
+/// Nothing to do here since x86 keeps instruction and data caches coherent
+pub fn sync_for_execution(_addr: usize, _len: usize) {}
+
+#[cfg(target_arch = "x86_64")]
+pub fn read_flags() -> usize {
+    let flags: usize;
+    unsafe { asm!("pushfq", "pop {}", out(reg) flags, options(preserves_flags)) };
+    flags
+}
+
+#[cfg(target_arch = "x86")]
+pub fn read_flags() -> usize {
+    let flags: usize;
+    unsafe { asm!("pushfd", "pop {}", out(reg) flags, options(preserves_flags)) };
+    flags
+}
+
+/// The caller's frame pointer. Only meaningful if built with frame pointers (-C force-frame-pointers=yes)
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+    fp
+}
+
+#[cfg(target_arch = "x86")]
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mov {}, ebp", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+    fp
+}
+
+/// The frame pointer and return address saved in the frame at `fp`: the saved frame pointer is at fp, the return
+/// address just above it.
+/// Unsafe because fp has to point at a frame
+pub unsafe fn caller_frame(fp: usize) -> (usize, usize) {
+    let frame = fp as *const usize;
+    (*frame, *frame.add(1))
+}
+
+pub fn read_cr0() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// Physical address of the top level page table plus flags
+pub fn read_cr3() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+pub fn read_cr4() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// Unsafe because clearing the wrong bits (e.g. PG or PE) will bring the machine down
+pub unsafe fn write_cr0(value: usize) {
+    asm!("mov cr0, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Unsafe because switching to a page table that doesn't map the running code will bring the machine down
+pub unsafe fn write_cr3(value: usize) {
+    asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+pub unsafe fn write_cr4(value: usize) {
+    asm!("mov cr4, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Whether enter_protected_mode() can be used. The code doing the switch has to be below 4GiB to keep running in 32
+/// bit mode, which it is unless the firmware loaded us above
+pub fn can_enter_protected_mode() -> bool {
+    (enter_protected_mode as *const () as u64) < 0xffff_0000
+}
+
+/// Jumps to `entry` in 32 bit protected mode with paging and interrupts off, flat 4GiB segments and EBX = `ebx`: what
+/// PVH and Multiboot's i386 entry expect. On x86_64 that means dropping out of long mode through a 32 bit code segment
+/// of our own.
+/// Unsafe because boot services have to have exited (firmware can't run without paging) and `entry` has to be code
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn enter_protected_mode(entry: u32, ebx: u32) -> ! {
+    // Null, then 32 bit code and data at 0x08 and 0x10
+    static GDT: [u64; 3] = [0, 0x00cf_9a00_0000_ffff, 0x00cf_9200_0000_ffff];
+    let mut gdtr = [0u8; 10];
+    gdtr[..2].copy_from_slice(&(core::mem::size_of_val(&GDT) as u16 - 1).to_le_bytes());
+    gdtr[2..].copy_from_slice(&(GDT.as_ptr() as u64).to_le_bytes());
+    // Compatibility mode first, then turning paging off leaves long mode and LME can be cleared. RBX can't be an
+    // operand, so EBX is set last from ESI
+    asm!(
+        "cli",
+        "lgdt [{gdtr}]",
+        "push 0x08",
+        "lea rax, [rip + 2f]",
+        "push rax",
+        "retfq",
+        ".code32",
+        "2:",
+        "mov eax, 0x10",
+        "mov ds, ax",
+        "mov es, ax",
+        "mov fs, ax",
+        "mov gs, ax",
+        "mov ss, ax",
+        "mov eax, cr0",
+        "and eax, 0x7fffffff",
+        "mov cr0, eax",
+        "mov ecx, 0xc0000080",
+        "rdmsr",
+        "and eax, 0xfffffeff",
+        "wrmsr",
+        "xor eax, eax",
+        "mov cr4, eax",
+        "mov ebx, esi",
+        "jmp edi",
+        ".code64",
+        gdtr = in(reg) gdtr.as_ptr(),
+        in("esi") ebx,
+        in("edi") entry,
+        options(noreturn)
+    )
+}
+
+#[cfg(target_arch = "x86")]
+pub unsafe fn enter_protected_mode(entry: u32, ebx: u32) -> ! {
+    // Firmware's segments are already flat
+    asm!(
+        "cli",
+        "mov eax, cr0",
+        "and eax, 0x7fffffff",
+        "mov cr0, eax",
+        "xor eax, eax",
+        "mov cr4, eax",
+        "mov ebx, ecx",
+        "jmp edx",
+        in("ecx") ebx,
+        in("edx") entry,
+        options(noreturn)
+    )
+}
+
+// Port I/O. Unsafe because what a port does is up to the device behind it, which could be anything from a debug
+// console to the keyboard controller's reset line
+
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    asm!("in al, dx", in("dx") port, out("al") value, options(nomem, nostack, preserves_flags));
+    value
+}
+
+pub unsafe fn inw(port: u16) -> u16 {
+    let value: u16;
+    asm!("in ax, dx", in("dx") port, out("ax") value, options(nomem, nostack, preserves_flags));
+    value
+}
+
+pub unsafe fn inl(port: u16) -> u32 {
+    let value: u32;
+    asm!("in eax, dx", in("dx") port, out("eax") value, options(nomem, nostack, preserves_flags));
+    value
+}
+
+pub unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+pub unsafe fn outw(port: u16, value: u16) {
+    asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+}
+
+// Not nomem since it's what starts DMA on some devices (e.g. QEMU's fw_cfg), which reads and writes our memory
+pub unsafe fn outl(port: u16, value: u32) {
+    asm!("out dx, eax", in("dx") port, in("eax") value, options(nostack, preserves_flags));
+}
+
+/// A value that can be read from and written to an I/O port
+pub trait PortValue: Copy {
+    unsafe fn read_port(port: u16) -> Self;
+    unsafe fn write_port(port: u16, value: Self);
+}
+
+impl PortValue for u8 {
+    unsafe fn read_port(port: u16) -> Self { inb(port) }
+    unsafe fn write_port(port: u16, value: Self) { outb(port, value) }
+}
+
+impl PortValue for u16 {
+    unsafe fn read_port(port: u16) -> Self { inw(port) }
+    unsafe fn write_port(port: u16, value: Self) { outw(port, value) }
+}
+
+impl PortValue for u32 {
+    unsafe fn read_port(port: u16) -> Self { inl(port) }
+    unsafe fn write_port(port: u16, value: Self) { outl(port, value) }
+}
+
+/// An I/O port of a given width, e.g. `Port::<u8>::new(0x3f8)` for COM1's data register
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Port<T: PortValue> {
+    port: u16,
+    _width: core::marker::PhantomData<T>,
+}
+
+impl<T: PortValue> Port<T> {
+    /// Unsafe because reads and writes through it are then safe, so the port has to be one where they're harmless
+    pub unsafe fn new(port: u16) -> Self {
+        Port { port, _width: core::marker::PhantomData }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn read(&self) -> T {
+        unsafe { T::read_port(self.port) }
+    }
+
+    pub fn write(&self, value: T) {
+        unsafe { T::write_port(self.port, value) }
+    }
+}
+
+/// The registers CPUID returns
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+/// Runs CPUID for the given leaf and subleaf. Leaves above max_leaf() (or max_extended_leaf() for 0x8000_0000 and up)
+/// return whatever the processor feels like, typically the highest basic leaf's values
+#[allow(unused_unsafe)] // The intrinsic stopped being unsafe in later compilers
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    #[cfg(target_arch = "x86_64")]
+    let r = unsafe { core::arch::x86_64::__cpuid_count(leaf, subleaf) };
+    #[cfg(target_arch = "x86")]
+    let r = unsafe { core::arch::x86::__cpuid_count(leaf, subleaf) };
+    CpuidResult { eax: r.eax, ebx: r.ebx, ecx: r.ecx, edx: r.edx }
+}
+
+pub fn max_leaf() -> u32 {
+    cpuid(0, 0).eax
+}
+
+pub fn max_extended_leaf() -> u32 {
+    cpuid(0x8000_0000, 0).eax
+}
+
+/// The vendor string, e.g. "GenuineIntel" or "AuthenticAMD"
+pub fn vendor() -> [u8; 12] {
+    let r = cpuid(0, 0);
+    let mut vendor = [0u8; 12];
+    vendor[..4].copy_from_slice(&r.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&r.edx.to_le_bytes());
+    vendor[8..].copy_from_slice(&r.ecx.to_le_bytes());
+    vendor
+}
+
+/// The marketing name, e.g. "Intel(R) Core(TM) i7-8650U CPU @ 1.90GHz", if the processor has one
+pub fn brand() -> Option<[u8; 48]> {
+    if max_extended_leaf() < 0x8000_0004 {
+        return None;
+    }
+    let mut brand = [0u8; 48];
+    for (i, chunk) in brand.chunks_mut(16).enumerate() {
+        let r = cpuid(0x8000_0002 + i as u32, 0);
+        for (j, reg) in [r.eax, r.ebx, r.ecx, r.edx].iter().enumerate() {
+            chunk[j * 4..][..4].copy_from_slice(&reg.to_le_bytes());
+        }
+    }
+    Some(brand)
+}
+
+/// Family, model and stepping from leaf 1, with the extended fields folded in the way Intel and AMD both document
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+impl Signature {
+    pub fn from_eax(eax: u32) -> Self {
+        let base_family = (eax >> 8) & 0xf;
+        let base_model = (eax >> 4) & 0xf;
+        let family = if base_family == 0xf { base_family + ((eax >> 20) & 0xff) } else { base_family };
+        let model = if base_family == 0x6 || base_family == 0xf { base_model | ((eax >> 12) & 0xf0) } else { base_model };
+        Signature { family, model, stepping: eax & 0xf }
+    }
+
+    pub fn get() -> Self {
+        Self::from_eax(cpuid(1, 0).eax)
+    }
+}
+
+/// A feature flag: the leaf, subleaf and register (0-3 for eax-edx) it's in and its bit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub name: &'static str,
+    pub leaf: u32,
+    pub subleaf: u32,
+    pub register: u8,
+    pub bit: u8,
+}
+
+const fn feature(name: &'static str, leaf: u32, register: u8, bit: u8) -> Feature {
+    Feature { name, leaf, subleaf: 0, register, bit }
+}
+
+/// The flags worth knowing about before boot, named as in /proc/cpuinfo
+pub const FEATURES: &[Feature] = &[
+    feature("fpu", 1, 3, 0),
+    feature("tsc", 1, 3, 4),
+    feature("msr", 1, 3, 5),
+    feature("pae", 1, 3, 6),
+    feature("apic", 1, 3, 9),
+    feature("mtrr", 1, 3, 12),
+    feature("pge", 1, 3, 13),
+    feature("pat", 1, 3, 16),
+    feature("clflush", 1, 3, 19),
+    feature("sse", 1, 3, 25),
+    feature("sse2", 1, 3, 26),
+    feature("ht", 1, 3, 28),
+    feature("sse3", 1, 2, 0),
+    feature("pclmulqdq", 1, 2, 1),
+    feature("vmx", 1, 2, 5),
+    feature("smx", 1, 2, 6),
+    feature("ssse3", 1, 2, 9),
+    feature("sse4_1", 1, 2, 19),
+    feature("sse4_2", 1, 2, 20),
+    feature("x2apic", 1, 2, 21),
+    feature("aes", 1, 2, 25),
+    feature("xsave", 1, 2, 26),
+    feature("avx", 1, 2, 28),
+    feature("rdrand", 1, 2, 30),
+    feature("hypervisor", 1, 2, 31),
+    feature("fsgsbase", 7, 1, 0),
+    feature("sgx", 7, 1, 2),
+    feature("avx2", 7, 1, 5),
+    feature("smep", 7, 1, 7),
+    feature("avx512f", 7, 1, 16),
+    feature("rdseed", 7, 1, 18),
+    feature("smap", 7, 1, 20),
+    feature("sha_ni", 7, 1, 29),
+    feature("umip", 7, 2, 2),
+    feature("la57", 7, 2, 16),
+    feature("svm", 0x8000_0001, 2, 2),
+    feature("nx", 0x8000_0001, 3, 20),
+    feature("pdpe1gb", 0x8000_0001, 3, 26),
+    feature("rdtscp", 0x8000_0001, 3, 27),
+    feature("lm", 0x8000_0001, 3, 29),
+];
+
+impl Feature {
+    pub fn is_supported(&self) -> bool {
+        let max = if self.leaf >= 0x8000_0000 { max_extended_leaf() } else { max_leaf() };
+        if self.leaf > max {
+            return false;
+        }
+        let r = cpuid(self.leaf, self.subleaf);
+        let register = [r.eax, r.ebx, r.ecx, r.edx][self.register as usize];
+        register & (1 << self.bit) != 0
+    }
+
+    pub fn by_name(name: &str) -> Option<Feature> {
+        FEATURES.iter().find(|f| f.name == name).cloned()
+    }
+}
+
+/// The supported features out of FEATURES
+pub fn features() -> impl Iterator<Item = Feature> {
+    FEATURES.iter().cloned().filter(Feature::is_supported)
+}
+
+/// How the processors are arranged, as far as the running one can tell
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Topology {
+    pub apic_id: u32, // The running processor's (x2APIC ID if there's leaf 0xB)
+    pub threads_per_core: u32,
+    pub logical_per_package: u32,
+}
+
+impl Topology {
+    pub fn get() -> Self {
+        let leaf1 = cpuid(1, 0);
+        let mut topology = Topology {
+            apic_id: leaf1.ebx >> 24,
+            threads_per_core: 1,
+            logical_per_package: (leaf1.ebx >> 16) & 0xff,
+        };
+        // Leaf 0xB lists the levels (SMT, then core) with how many logical processors each has
+        if max_leaf() >= 0xb && cpuid(0xb, 0).ebx != 0 {
+            for level in 0.. {
+                let r = cpuid(0xb, level);
+                let level_type = (r.ecx >> 8) & 0xff;
+                if level_type == 0 {
+                    break;
+                }
+                topology.apic_id = r.edx;
+                match level_type {
+                    1 => topology.threads_per_core = r.ebx & 0xffff,
+                    2 => topology.logical_per_package = r.ebx & 0xffff,
+                    _ => {}
+                }
+            }
+        }
+        topology
+    }
+}
+
+pub const IA32_APIC_BASE: u32 = 0x1b;
+pub const IA32_FEATURE_CONTROL: u32 = 0x3a;
+pub const IA32_BIOS_SIGN_ID: u32 = 0x8b; // Microcode revision in the high half on Intel, the low half on AMD
+pub const IA32_MTRRCAP: u32 = 0xfe;
+pub const IA32_PAT: u32 = 0x277;
+pub const IA32_EFER: u32 = 0xc000_0080;
+
+/// Reads a model specific register.
+/// Unsafe because reading one the processor doesn't have raises #GP, which firmware doesn't recover from
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    ((high as u64) << 32) | low as u64
+}
+
+/// Unsafe for the same reason as rdmsr(), and because MSRs control everything from caching to power management
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack, preserves_flags));
+}
+
+/// The revision of the loaded microcode update, or None if the processor's vendor isn't one we know how to ask
+pub fn microcode_revision() -> Option<u32> {
+    match &vendor() {
+        b"GenuineIntel" => unsafe {
+            // Intel only fills it in after the MSR is cleared and CPUID leaf 1 runs
+            wrmsr(IA32_BIOS_SIGN_ID, 0);
+            cpuid(1, 0);
+            Some((rdmsr(IA32_BIOS_SIGN_ID) >> 32) as u32)
+        },
+        b"AuthenticAMD" | b"HygonGenuine" => Some(unsafe { rdmsr(IA32_BIOS_SIGN_ID) } as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_cpuid() {
+        // A Kaby Lake and a Zen 2
+        assert_eq!(Signature::from_eax(0x0008_06ea), Signature { family: 6, model: 0x8e, stepping: 0xa });
+        assert_eq!(Signature::from_eax(0x0087_0f10), Signature { family: 0x17, model: 0x71, stepping: 0 });
+        #[cfg(target_arch = "x86_64")]
+        assert!(Feature::by_name("sse2").unwrap().is_supported() && Feature::by_name("lm").unwrap().is_supported());
+        assert!(max_leaf() >= 1);
+    }
+}
@@ -0,0 +1,372 @@
+// cpio in the "newc" format, which is what Linux unpacks initramfs images from.
+//
+// The kernel unpacks every archive in the initrd one after the other, later files replacing earlier ones, so a loader
+// can add files to an existing initramfs (configuration, microcode and the like) by appending an archive of its own
+// with CpioWriter and passing both to Linux::initrd().
+
+use fs::{FileSystem, components, from_io_error};
+use io::{self, Read, Write};
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+use core::str;
+
+const MAGIC: &[u8] = b"070701";
+const MAGIC_CRC: &[u8] = b"070702"; // Same but with a checksum of the data
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+// The file type bits of the mode
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The path within the archive, without a leading `/`
+    pub name: String,
+    pub ino: u32,
+    /// File type and permissions
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    /// Length of the data, which for a symlink is its target
+    pub len: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+}
+
+impl Entry {
+    /// An entry of the given type and permissions owned by root
+    pub fn new(name: &str, mode: u32, len: u32) -> Self {
+        Self {
+            name: normalize(name),
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            nlink: if mode & S_IFMT == S_IFDIR { 2 } else { 1 },
+            mtime: 0,
+            len,
+            dev_major: 0,
+            dev_minor: 0,
+            rdev_major: 0,
+            rdev_minor: 0,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+fn normalize(name: &str) -> String {
+    components(name).collect::<Vec<_>>().join("/")
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn padding(pos: u64) -> usize {
+    ((4 - pos % 4) % 4) as usize
+}
+
+/// Reads entries one at a time. After next_entry() the reader reads that entry's data
+pub struct CpioReader<R> {
+    inner: R,
+    pos: u64, // From the start of the stream, which is what the padding is relative to
+    left: u64, // Of the current entry's data
+    checksum: Option<(u32, u32)>, // Expected and so far, for the 070702 format
+    done: bool,
+}
+
+impl<R: Read> CpioReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, pos: 0, left: 0, checksum: None, done: false }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// The next entry, skipping whatever's left of the current one. None after the last archive's trailer. Archives
+    /// that follow one another, with or without zeros in between as in an initrd, are read as one
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        if self.done {
+            return Ok(None);
+        }
+        io::copy(self, &mut io::sink())?;
+        self.skip(padding(self.pos))?;
+
+        loop {
+            // Between archives. The first non-zero byte starts the next one
+            let mut header = [0u8; HEADER_SIZE];
+            loop {
+                match self.inner.read(&mut header[..1]) {
+                    Ok(0) if self.pos > 0 => {
+                        self.done = true;
+                        return Ok(None);
+                    },
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(_) if header[0] == 0 => self.pos += 1,
+                    Ok(_) => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e),
+                }
+            }
+            self.inner.read_exact(&mut header[1..])?;
+            self.pos += HEADER_SIZE as u64;
+
+            let magic = &header[..6];
+            if magic != MAGIC && magic != MAGIC_CRC {
+                return Err(invalid_data("not a newc cpio archive"));
+            }
+            let mut fields = [0u32; 13];
+            for (i, field) in fields.iter_mut().enumerate() {
+                let hex = str::from_utf8(&header[6 + i * 8..14 + i * 8]).map_err(|_| invalid_data("invalid cpio header"))?;
+                *field = u32::from_str_radix(hex, 16).map_err(|_| invalid_data("invalid cpio header"))?;
+            }
+
+            let name_len = fields[11] as usize;
+            if name_len == 0 {
+                return Err(invalid_data("invalid cpio header"));
+            }
+            let mut name = vec![0u8; name_len];
+            self.inner.read_exact(&mut name)?;
+            self.pos += name_len as u64;
+            self.skip(padding(self.pos))?;
+            name.pop(); // The terminating nul
+            let name = String::from_utf8(name).map_err(|_| invalid_data("cpio entry name isn't UTF-8"))?;
+
+            if name == TRAILER {
+                // Maybe another archive follows
+                continue;
+            }
+
+            self.left = fields[6] as u64;
+            self.checksum = if magic == MAGIC_CRC { Some((fields[12], 0)) } else { None };
+            return Ok(Some(Entry {
+                name: normalize(&name),
+                ino: fields[0],
+                mode: fields[1],
+                uid: fields[2],
+                gid: fields[3],
+                nlink: fields[4],
+                mtime: fields[5],
+                len: fields[6],
+                dev_major: fields[7],
+                dev_minor: fields[8],
+                rdev_major: fields[9],
+                rdev_minor: fields[10],
+            }));
+        }
+    }
+
+    fn skip(&mut self, len: usize) -> io::Result<()> {
+        self.inner.read_exact(&mut [0u8; 4][..len])?;
+        self.pos += len as u64;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for CpioReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.left.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.pos += n as u64;
+        self.left -= n as u64;
+
+        if let Some((expected, ref mut sum)) = self.checksum {
+            *sum = buf[..n].iter().fold(*sum, |sum, &b| sum.wrapping_add(b as u32));
+            if self.left == 0 && *sum != expected {
+                return Err(invalid_data("cpio checksum mismatch"));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Writes an archive. finish() must be called to write the trailer that ends it
+pub struct CpioWriter<W: Write> {
+    inner: W,
+    pos: u64,
+    next_ino: u32,
+}
+
+impl<W: Write> CpioWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, pos: 0, next_ino: 1 }
+    }
+
+    /// Writes an entry's header then its data. The inode number is filled in if it's 0
+    pub fn append<R: Read>(&mut self, entry: &Entry, data: &mut R) -> io::Result<()> {
+        let mut entry = entry.clone();
+        if entry.ino == 0 {
+            entry.ino = self.next_ino;
+            self.next_ino += 1;
+        }
+        self.header(&entry)?;
+
+        let copied = io::copy(&mut data.take(entry.len as u64), &mut self.inner)?;
+        if copied != entry.len as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.pos += copied;
+        self.pad()
+    }
+
+    pub fn add_file(&mut self, name: &str, permissions: u32, data: &[u8]) -> io::Result<()> {
+        if data.len() > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too big for cpio"));
+        }
+        self.append(&Entry::new(name, S_IFREG | permissions, data.len() as u32), &mut &data[..])
+    }
+
+    pub fn add_dir(&mut self, name: &str, permissions: u32) -> io::Result<()> {
+        self.append(&Entry::new(name, S_IFDIR | permissions, 0), &mut io::empty())
+    }
+
+    pub fn add_symlink(&mut self, name: &str, target: &str) -> io::Result<()> {
+        self.append(&Entry::new(name, S_IFLNK | 0o777, target.len() as u32), &mut target.as_bytes())
+    }
+
+    /// Adds a directory of a file system and everything in it as `prefix` in the archive, with the directories as
+    /// 755 and the files as 644 since FAT and friends don't have permissions
+    pub fn add_tree(&mut self, fs: &dyn FileSystem, path: &str, prefix: &str) -> Result<()> {
+        if !prefix.is_empty() {
+            self.add_dir(prefix, 0o755).map_err(from_io_error)?;
+        }
+        for entry in fs.read_dir(path)? {
+            let src = format!("{}/{}", path, entry.name);
+            let dst = format!("{}/{}", prefix, entry.name);
+            if entry.metadata.is_dir {
+                self.add_tree(fs, &src, &dst)?;
+                continue;
+            }
+
+            if entry.metadata.len > u32::MAX as u64 {
+                return Err(EfiErrorKind::BadBufferSize.into());
+            }
+            let mut file = fs.open(&src)?;
+            self.append(&Entry::new(&dst, S_IFREG | 0o644, entry.metadata.len as u32), &mut file).map_err(from_io_error)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the trailer, padding the archive to a multiple of 512 bytes like cpio does
+    pub fn finish(mut self) -> io::Result<W> {
+        self.header(&Entry::new(TRAILER, 0, 0))?;
+        while self.pos % 512 != 0 {
+            self.inner.write_all(&[0])?;
+            self.pos += 1;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn header(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_SIZE + entry.name.len() + 4);
+        header.extend_from_slice(MAGIC);
+        let fields = [
+            entry.ino, entry.mode, entry.uid, entry.gid, entry.nlink, entry.mtime, entry.len,
+            entry.dev_major, entry.dev_minor, entry.rdev_major, entry.rdev_minor, entry.name.len() as u32 + 1, 0,
+        ];
+        for field in fields.iter() {
+            header.extend_from_slice(format!("{:08X}", field).as_bytes());
+        }
+        header.extend_from_slice(entry.name.as_bytes());
+        header.push(0);
+        self.inner.write_all(&header)?;
+        self.pos += header.len() as u64;
+        self.pad()
+    }
+
+    fn pad(&mut self) -> io::Result<()> {
+        let len = padding(self.pos);
+        self.inner.write_all(&[0u8; 4][..len])?;
+        self.pos += len as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{MemDisk, format_fat32};
+    use testing::mock;
+
+    fn entries(archive: &[u8]) -> Vec<(Entry, Vec<u8>)> {
+        let mut reader = CpioReader::new(archive);
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry().unwrap() {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            entries.push((entry, data));
+        }
+        entries
+    }
+
+    #[test]
+    fn reads_back_concatenated_archives() {
+        let mut writer = CpioWriter::new(Vec::new());
+        writer.add_dir("/etc", 0o755).unwrap();
+        writer.add_file("/etc/hostname", 0o644, b"efi\n").unwrap();
+        writer.add_symlink("init", "/bin/sh").unwrap();
+        let mut archive = writer.finish().unwrap();
+        assert_eq!(archive.len() % 512, 0);
+
+        // Another archive after a bit of padding, like Initrd puts between parts
+        archive.extend_from_slice(&[0u8; 4]);
+        let mut writer = CpioWriter::new(Vec::new());
+        writer.add_file("kernel/x86/microcode/GenuineIntel.bin", 0o644, &[0xAB; 1000]).unwrap();
+        archive.extend(writer.finish().unwrap());
+
+        let entries = entries(&archive);
+        let names = entries.iter().map(|&(ref e, _)| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["etc", "etc/hostname", "init", "kernel/x86/microcode/GenuineIntel.bin"]);
+        assert!(entries[0].0.is_dir());
+        assert_eq!((entries[1].0.mode, &entries[1].1[..]), (S_IFREG | 0o644, &b"efi\n"[..]));
+        assert!(entries[2].0.is_symlink());
+        assert_eq!(entries[2].1, b"/bin/sh");
+        assert_eq!(entries[3].1, vec![0xAB; 1000]);
+
+        let mut truncated = CpioReader::new(&archive[..200]);
+        assert!(truncated.next_entry().is_ok());
+        assert!(truncated.next_entry().is_err());
+    }
+
+    #[test]
+    fn archives_file_system_tree() {
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "TREE").unwrap();
+        fs.create_dir("conf").unwrap();
+        fs.write("conf/a.txt", b"alpha").unwrap();
+        fs.write("top.txt", b"top").unwrap();
+
+        let mut writer = CpioWriter::new(Vec::new());
+        writer.add_tree(&fs, "", "extra").unwrap();
+        let mut entries = entries(&writer.finish().unwrap());
+        entries.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        let found = entries.iter().map(|&(ref e, ref data)| (e.name.as_str(), &data[..])).collect::<Vec<_>>();
+        assert_eq!(found, [("extra", &b""[..]), ("extra/conf", b""), ("extra/conf/a.txt", b"alpha"), ("extra/top.txt", b"top")]);
+    }
+}
@@ -0,0 +1,81 @@
+// Archive formats.
+//
+// Readers and writers work over any Read or Write so archives can come straight off a file system, the network or a
+// decompressor, and helpers move whole directory trees between an archive and a FileSystem.
+
+pub mod cpio;
+pub mod tar;
+pub mod zip;
+
+pub use self::cpio::{CpioReader, CpioWriter};
+pub use self::tar::TarReader;
+pub use self::zip::ZipReader;
+
+use fs::{FileSystem, components, from_io_error};
+use io::{Read, Write};
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+
+const EXTRACT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How far an extraction has got. Given to the progress callback as each file starts and after every chunk written
+#[derive(Debug, Copy, Clone)]
+pub struct Progress<'a> {
+    /// The file being written, relative to the archive
+    pub name: &'a str,
+    /// Entries extracted before this one
+    pub entries: usize,
+    /// Bytes written over all the entries
+    pub bytes: u64,
+}
+
+// Where an entry goes under `dest`. Names that would climb out of it are refused rather than trusted
+fn destination(dest: &str, name: &str) -> Result<String> {
+    let mut parts = components(dest).collect::<Vec<_>>();
+    for part in components(name) {
+        match part {
+            "." => {},
+            ".." => return Err(EfiErrorKind::AccessDenied.into()),
+            part => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+// Creates a directory and any parents it's missing
+fn create_dirs(fs: &dyn FileSystem, path: &str) -> Result<()> {
+    let mut dir = String::new();
+    for part in components(path) {
+        if !dir.is_empty() {
+            dir.push('/');
+        }
+        dir.push_str(part);
+        match fs.metadata(&dir) {
+            Ok(metadata) if metadata.is_dir => {},
+            Ok(_) => return Err(EfiErrorKind::AccessDenied.into()),
+            Err(_) => fs.create_dir(&dir)?,
+        }
+    }
+    Ok(())
+}
+
+// Writes an entry's data to `path`, creating its parent directories
+fn extract_file<R: Read, F: FnMut(&Progress)>(fs: &dyn FileSystem, path: &str, name: &str, data: &mut R, entries: usize, bytes: &mut u64, progress: &mut F) -> Result<()> {
+    let mut parents = components(path).collect::<Vec<_>>();
+    parents.pop();
+    create_dirs(fs, &parents.join("/"))?;
+
+    let mut file = fs.create(path)?;
+    let mut buf = vec![0u8; EXTRACT_CHUNK_SIZE];
+    progress(&Progress { name, entries, bytes: *bytes });
+    loop {
+        let n = data.read(&mut buf).map_err(from_io_error)?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n]).map_err(from_io_error)?;
+        *bytes += n as u64;
+        progress(&Progress { name, entries, bytes: *bytes });
+    }
+    file.flush().map_err(from_io_error)
+}
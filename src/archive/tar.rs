@@ -0,0 +1,295 @@
+// tar, as written by GNU tar or anything POSIX (ustar and pax). GNU long names and the path, linkpath and size pax
+// records are understood and other extensions skipped over.
+
+use super::{Progress, destination, create_dirs, extract_file};
+use fs::{FileSystem, from_io_error};
+use io::{self, Read};
+use Result;
+use alloc::{string::String, vec::Vec};
+use core::str;
+
+const BLOCK_SIZE: u64 = 512;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    HardLink,
+    /// Devices, FIFOs and whatever else, by their type flag
+    Other(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub entry_type: EntryType,
+    pub mode: u32,
+    pub mtime: u64,
+    /// Length of the data
+    pub len: u64,
+    /// The target of a link
+    pub link_name: String,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// A numeric header field: octal digits padded with spaces or nuls, or for big values GNU's base-256 with the top bit
+// of the first byte set
+fn number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..].iter().fold((field[0] & 0x7F) as u64, |n, &b| n << 8 | b as u64));
+    }
+    let digits = str::from_utf8(field).map_err(|_| invalid_data("invalid tar header"))?;
+    let digits = digits.trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data("invalid tar header"))
+}
+
+// A nul terminated string field
+fn string(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+/// Reads entries one at a time. After next_entry() the reader reads that entry's data
+pub struct TarReader<R> {
+    inner: R,
+    left: u64, // Of the current entry's data
+    padding: u64, // After it
+    done: bool,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, left: 0, padding: 0, done: false }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// The next entry, skipping whatever's left of the current one. None at the end of the archive
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        let mut long_name = None;
+        let mut long_link = None;
+        let mut pax_size = None;
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            self.skip_rest()?;
+
+            let mut header = [0u8; BLOCK_SIZE as usize];
+            if !self.block(&mut header)? || header.iter().all(|&b| b == 0) {
+                // Two zero blocks end the archive but one is enough to know
+                self.done = true;
+                return Ok(None);
+            }
+
+            // The checksum is of the header with the checksum itself taken as spaces. Some old tars summed signed bytes
+            let expected = number(&header[148..156])?;
+            let unsigned = header.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 }).sum::<u64>();
+            let signed = header.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { b' ' as i64 } else { b as i8 as i64 }).sum::<i64>();
+            if expected != unsigned && expected as i64 != signed {
+                return Err(invalid_data("tar header checksum mismatch"));
+            }
+
+            let len = pax_size.take().map_or_else(|| number(&header[124..136]), Ok)?;
+            self.left = len;
+            self.padding = (BLOCK_SIZE - len % BLOCK_SIZE) % BLOCK_SIZE;
+
+            let type_flag = header[156];
+            match type_flag {
+                b'L' => {
+                    long_name = Some(string(&self.data()?));
+                    continue;
+                },
+                b'K' => {
+                    long_link = Some(string(&self.data()?));
+                    continue;
+                },
+                b'x' => {
+                    for (key, value) in pax_records(&self.data()?)? {
+                        match key.as_str() {
+                            "path" => long_name = Some(value),
+                            "linkpath" => long_link = Some(value),
+                            "size" => pax_size = Some(value.parse().map_err(|_| invalid_data("invalid pax size"))?),
+                            _ => {},
+                        }
+                    }
+                    continue;
+                },
+                b'g' => continue, // Global pax records, none of which matter here
+                _ => {},
+            }
+
+            let mut name = string(&header[0..100]);
+            if &header[257..262] == b"ustar" && header[345] != 0 {
+                name = format!("{}/{}", string(&header[345..500]), name);
+            }
+            let name = long_name.take().unwrap_or(name);
+            let entry_type = match type_flag {
+                b'0' | b'\0' | b'7' if name.ends_with('/') => EntryType::Dir, // Pre-POSIX directories
+                b'0' | b'\0' | b'7' => EntryType::File,
+                b'1' => EntryType::HardLink,
+                b'2' => EntryType::Symlink,
+                b'5' => EntryType::Dir,
+                other => EntryType::Other(other),
+            };
+            return Ok(Some(Entry {
+                name,
+                entry_type,
+                mode: number(&header[100..108])? as u32,
+                mtime: number(&header[136..148])?,
+                len,
+                link_name: long_link.take().unwrap_or_else(|| string(&header[157..257])),
+            }));
+        }
+    }
+
+    // Reads a block. False at the end of the stream, which the last zero block or two are often missing from
+    fn block(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut len = 0;
+        while len < buf.len() {
+            match self.inner.read(&mut buf[len..]) {
+                Ok(0) if len == 0 => return Ok(false),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    // The current entry's data, for the entries that describe the next one
+    fn data(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn skip_rest(&mut self) -> io::Result<()> {
+        io::copy(self, &mut io::sink())?;
+        io::copy(&mut (&mut self.inner).take(self.padding), &mut io::sink())?;
+        self.padding = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.left.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+// "<length> <key>=<value>\n" records, the length counting the whole record
+fn pax_records(data: &[u8]) -> io::Result<Vec<(String, String)>> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() && rest[0] != 0 {
+        let space = rest.iter().position(|&b| b == b' ').ok_or_else(|| invalid_data("invalid pax record"))?;
+        let len = str::from_utf8(&rest[..space]).ok().and_then(|l| l.parse::<usize>().ok())
+            .filter(|&len| len > space + 1 && len <= rest.len())
+            .ok_or_else(|| invalid_data("invalid pax record"))?;
+        let record = &rest[space + 1..len - 1]; // Without the newline
+        let equals = record.iter().position(|&b| b == b'=').ok_or_else(|| invalid_data("invalid pax record"))?;
+        records.push((String::from_utf8_lossy(&record[..equals]).into_owned(), String::from_utf8_lossy(&record[equals + 1..]).into_owned()));
+        rest = &rest[len..];
+    }
+    Ok(records)
+}
+
+/// Extracts an archive into the directory `dest` of a file system, creating directories as needed. Links, devices
+/// and the like are skipped since FAT can't hold them. Returns how many files and directories were extracted
+pub fn extract<R: Read, F: FnMut(&Progress)>(reader: R, fs: &dyn FileSystem, dest: &str, mut progress: F) -> Result<usize> {
+    let mut tar = TarReader::new(reader);
+    let mut entries = 0;
+    let mut bytes = 0;
+    while let Some(entry) = tar.next_entry().map_err(from_io_error)? {
+        let path = destination(dest, &entry.name)?;
+        match entry.entry_type {
+            EntryType::Dir => create_dirs(fs, &path)?,
+            EntryType::File => extract_file(fs, &path, &entry.name, &mut tar, entries, &mut bytes, &mut progress)?,
+            _ => continue,
+        }
+        entries += 1;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompress::GzDecoder;
+    use fs::{MemDisk, format_fat32};
+    use testing::mock;
+
+    const LONG_DIR: &str = "boot/a-directory-with-a-name-long-enough-that-the-path-no-longer-fits-in-the-hundred-bytes-of-a-tar-header";
+
+    // GNU tar of boot/, holding a file under LONG_DIR, big.txt (3000 xs), cmdline.txt and link -> cmdline.txt
+    const BOOT_TGZ: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xed, 0x9a, 0x41, 0x6e, 0x83, 0x30, 0x10, 0x45, 0xbd, 0xee, 0x29, 0xb8, 0xc0, 0x04,
+        0x1b, 0xb0, 0x59, 0x55, 0xca, 0x01, 0xb2, 0xeb, 0x09, 0x0c, 0x4c, 0x82, 0x55, 0x62, 0x47, 0xc6, 0x51, 0xc3, 0xed, 0xeb, 0x46, 0xea, 0x86, 0xb4,
+        0x89, 0x22, 0xd5, 0x51, 0x5b, 0xe6, 0x2d, 0x6c, 0xa4, 0x19, 0xb1, 0xf0, 0xf8, 0x7f, 0x69, 0x06, 0x1a, 0xe7, 0x42, 0xce, 0xd2, 0xc2, 0x23, 0xb5,
+        0x94, 0xe7, 0x3d, 0x32, 0xdf, 0x2f, 0x9f, 0x05, 0xaf, 0x14, 0x67, 0x99, 0x64, 0x0f, 0xe0, 0x38, 0x06, 0xed, 0xb3, 0x8c, 0xf9, 0x78, 0x10, 0xd7,
+        0xf2, 0x6e, 0xc5, 0xff, 0x28, 0xab, 0x7c, 0x95, 0xaf, 0x37, 0xce, 0xee, 0x36, 0xc6, 0xbe, 0x26, 0xac, 0xbf, 0xaa, 0xaa, 0x6f, 0xeb, 0x2f, 0x64,
+        0x35, 0xab, 0xbf, 0x50, 0xbc, 0x64, 0xd9, 0x86, 0xea, 0x9f, 0x9c, 0xe6, 0x43, 0xff, 0x1a, 0x3a, 0xe3, 0xb1, 0x0d, 0xce, 0x4f, 0xf0, 0x66, 0x42,
+        0x0f, 0x1a, 0xac, 0xde, 0x23, 0x0c, 0xf1, 0x5e, 0x00, 0x5a, 0x77, 0xdc, 0xf5, 0x10, 0x7a, 0x1d, 0xe2, 0x82, 0x70, 0xd0, 0x31, 0xc1, 0xba, 0x73,
+        0x10, 0x3d, 0x6c, 0x4d, 0x18, 0xc1, 0xd8, 0x73, 0xa8, 0x3f, 0xda, 0xce, 0x63, 0x07, 0xcd, 0x14, 0x70, 0x04, 0xb7, 0x8d, 0xef, 0x89, 0x87, 0x0b,
+        0x3d, 0xea, 0x0e, 0x7d, 0xce, 0x88, 0x65, 0xd6, 0xff, 0x6e, 0xff, 0x2f, 0x8b, 0x98, 0x4f, 0xfe, 0xbf, 0x18, 0xff, 0x57, 0x5f, 0xf8, 0x7f, 0x45,
+        0xfe, 0xff, 0xcf, 0xfc, 0x7f, 0x6b, 0x06, 0x5c, 0x85, 0x53, 0x20, 0xd3, 0x5d, 0xa0, 0xff, 0x5f, 0xd3, 0x3f, 0xe7, 0xf2, 0xd2, 0xff, 0x0b, 0x96,
+        0x71, 0xd2, 0x7f, 0x72, 0x3a, 0xc4, 0xc3, 0x13, 0xc9, 0x60, 0xd9, 0xfa, 0x6f, 0xcc, 0x2e, 0xa5, 0x31, 0xdf, 0xd0, 0xbf, 0x54, 0xf5, 0xbc, 0xff,
+        0x17, 0x35, 0xe9, 0xff, 0x31, 0x9c, 0x08, 0x82, 0x20, 0x08, 0x82, 0x20, 0x08, 0x82, 0xf8, 0xf5, 0xfc, 0x68, 0xff, 0xd7, 0xee, 0xbb, 0xc1, 0xd8,
+        0x64, 0xc3, 0xb9, 0x9b, 0xf3, 0x1f, 0xa1, 0x66, 0xfd, 0x5f, 0xa1, 0x4a, 0x41, 0xfd, 0xdf, 0x23, 0x68, 0x9d, 0x1d, 0xdd, 0x80, 0xcf, 0x21, 0x4c,
+        0x2f, 0x9c, 0x06, 0x41, 0xcb, 0x9c, 0xff, 0x0c, 0xe9, 0xbe, 0xfd, 0x7c, 0xea, 0xbf, 0xae, 0xeb, 0x3b, 0xfe, 0xff, 0x28, 0xa5, 0x88, 0xfa, 0x2f,
+        0x12, 0xfb, 0x12, 0xe9, 0x9f, 0x20, 0x88, 0x25, 0xf3, 0x0e, 0x67, 0xab, 0xd0, 0x5a, 0x00, 0x28, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn reads_gnu_long_names_and_links() {
+        let mut tar = TarReader::new(GzDecoder::new(BOOT_TGZ));
+        let mut entries = Vec::new();
+        while let Some(entry) = tar.next_entry().unwrap() {
+            entries.push(entry);
+        }
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["boot/", &format!("{}/", LONG_DIR), &format!("{}/file.txt", LONG_DIR), "boot/big.txt", "boot/cmdline.txt", "boot/link"]);
+        assert_eq!(entries[1].entry_type, EntryType::Dir);
+        assert_eq!((entries[3].entry_type, entries[3].len, entries[3].mode), (EntryType::File, 3000, 0o644));
+        assert_eq!((entries[5].entry_type, entries[5].link_name.as_str()), (EntryType::Symlink, "cmdline.txt"));
+    }
+
+    #[test]
+    fn extracts_onto_file_system() {
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "TAR").unwrap();
+        let mut calls = 0;
+        let mut last = 0;
+        let extracted = extract(GzDecoder::new(BOOT_TGZ), &fs, "/restore", |p| {
+            calls += 1;
+            last = p.bytes;
+        }).unwrap();
+
+        assert_eq!(extracted, 5);
+        assert_eq!(last, 3000 + 14 + 5);
+        assert!(calls >= 6);
+        assert_eq!(fs.read("restore/boot/cmdline.txt").unwrap(), b"console=ttyS0\n");
+        assert_eq!(fs.read(&format!("restore/{}/file.txt", LONG_DIR)).unwrap(), b"deep\n");
+        assert!(!fs.exists("restore/boot/link"));
+    }
+}
@@ -0,0 +1,304 @@
+// ZIP, read front to back from the local headers rather than from the central directory at the end, so that it can
+// be streamed. Stored and deflated entries are supported, zip64 included, as are deflated entries that were streamed
+// when the archive was written and so have their sizes and CRC in a data descriptor after the data. A stored entry
+// like that can't be read this way since nothing but the central directory says where it ends.
+
+use super::{Progress, destination, create_dirs, extract_file};
+use decompress::{Input, Inflate};
+use fs::{FileSystem, from_io_error};
+use io::{self, Read};
+use utils::crc32_update;
+use Result;
+use alloc::string::String;
+use byteorder::{ByteOrder, LittleEndian};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4B50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4B50;
+const LOCAL_HEADER_SIZE: usize = 30;
+
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+pub const METHOD_STORED: u16 = 0;
+pub const METHOD_DEFLATED: u16 = 8;
+
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    /// How the data is compressed, METHOD_STORED or METHOD_DEFLATED
+    pub method: u16,
+    /// The decompressed length, unless the entry was streamed in which case it isn't known until the end
+    pub len: Option<u64>,
+    pub compressed_len: Option<u64>,
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// What's being read of the current entry
+struct Current {
+    method: u16,
+    left: u64, // Compressed bytes, for stored entries
+    expected: Option<(u32, u64)>, // CRC and length, unless they're in a data descriptor
+    zip64: bool, // Whether a data descriptor's lengths are 64 bits
+    crc: u32,
+    len: u64,
+    done: bool,
+}
+
+/// Reads entries one at a time. After next_entry() the reader reads that entry's decompressed data. Entries are
+/// checked against their CRC as the last of their data is read
+pub struct ZipReader<R> {
+    input: Input<R>,
+    inflate: Inflate,
+    current: Option<Current>,
+    done: bool,
+}
+
+impl<R: Read> ZipReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { input: Input::new(reader), inflate: Inflate::new(), current: None, done: false }
+    }
+
+    /// The next entry, skipping whatever's left of the current one. None once the central directory is reached
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        if self.done {
+            return Ok(None);
+        }
+        io::copy(self, &mut io::sink())?;
+        self.current = None;
+
+        let mut header = [0u8; LOCAL_HEADER_SIZE];
+        header[0] = match self.input.try_byte()? {
+            Some(b) => b,
+            None => {
+                self.done = true;
+                return Ok(None);
+            },
+        };
+        self.input.read_exact(&mut header[1..4])?;
+        if LittleEndian::read_u32(&header[0..4]) != LOCAL_HEADER_SIGNATURE {
+            // The central directory, or whatever else comes after the entries
+            self.done = true;
+            return Ok(None);
+        }
+        self.input.read_exact(&mut header[4..])?;
+
+        let flags = LittleEndian::read_u16(&header[6..8]);
+        let method = LittleEndian::read_u16(&header[8..10]);
+        let crc = LittleEndian::read_u32(&header[14..18]);
+        let mut compressed_len = LittleEndian::read_u32(&header[18..22]) as u64;
+        let mut len = LittleEndian::read_u32(&header[22..26]) as u64;
+        let mut name = vec![0u8; LittleEndian::read_u16(&header[26..28]) as usize];
+        let mut extra = vec![0u8; LittleEndian::read_u16(&header[28..30]) as usize];
+        self.input.read_exact(&mut name)?;
+        self.input.read_exact(&mut extra)?;
+        let name = String::from_utf8_lossy(&name).into_owned();
+
+        // Lengths that don't fit in 32 bits are in the zip64 extra field, which has just the ones that didn't
+        let mut zip64 = false;
+        let mut fields = &extra[..];
+        while fields.len() >= 4 {
+            let id = LittleEndian::read_u16(&fields[0..2]);
+            let size = (LittleEndian::read_u16(&fields[2..4]) as usize).min(fields.len() - 4);
+            let mut data = &fields[4..4 + size];
+            if id == ZIP64_EXTRA_ID {
+                zip64 = true;
+                for value in [&mut len, &mut compressed_len].iter_mut() {
+                    if **value == u32::MAX as u64 && data.len() >= 8 {
+                        **value = LittleEndian::read_u64(&data[..8]);
+                        data = &data[8..];
+                    }
+                }
+            }
+            fields = &fields[4 + size..];
+        }
+
+        if flags & FLAG_ENCRYPTED != 0 {
+            return Err(unsupported("encrypted zip entries aren't supported"));
+        }
+        let descriptor = flags & FLAG_DATA_DESCRIPTOR != 0;
+        match method {
+            METHOD_STORED if descriptor => return Err(unsupported("can't stream a stored zip entry with a data descriptor")),
+            METHOD_STORED => {},
+            METHOD_DEFLATED => self.inflate.reset(),
+            _ => return Err(unsupported("unsupported zip compression method")),
+        }
+
+        self.current = Some(Current {
+            method,
+            left: compressed_len,
+            expected: if descriptor { None } else { Some((crc, len)) },
+            zip64,
+            crc: 0,
+            len: 0,
+            done: false,
+        });
+        Ok(Some(Entry {
+            name,
+            method,
+            len: if descriptor { None } else { Some(len) },
+            compressed_len: if descriptor { None } else { Some(compressed_len) },
+        }))
+    }
+
+    // Checks what was read against the header or the data descriptor
+    fn finish(&mut self) -> io::Result<()> {
+        let current = self.current.as_mut().unwrap();
+        current.done = true;
+        let (crc, len) = match current.expected {
+            Some(expected) => expected,
+            None => {
+                // The descriptor's signature is optional
+                let mut word = [0u8; 4];
+                self.input.read_exact(&mut word)?;
+                if LittleEndian::read_u32(&word) == DATA_DESCRIPTOR_SIGNATURE {
+                    self.input.read_exact(&mut word)?;
+                }
+                let crc = LittleEndian::read_u32(&word);
+                let mut lengths = [0u8; 16];
+                let lengths = if current.zip64 { &mut lengths[..] } else { &mut lengths[..8] };
+                self.input.read_exact(lengths)?;
+                let len = if current.zip64 { LittleEndian::read_u64(&lengths[8..]) } else { LittleEndian::read_u32(&lengths[4..]) as u64 };
+                (crc, len)
+            },
+        };
+        if crc != current.crc || len != current.len {
+            return Err(invalid_data("zip CRC or length mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ZipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (method, left) = match self.current {
+            Some(ref current) if !current.done && !buf.is_empty() => (current.method, current.left),
+            _ => return Ok(0),
+        };
+
+        let n = if method == METHOD_STORED {
+            let len = buf.len().min(left.min(usize::MAX as u64) as usize);
+            let n = if len == 0 { 0 } else { self.input.read(&mut buf[..len])? };
+            if n == 0 && len > 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            n
+        } else {
+            self.inflate.read(&mut self.input, buf)?
+        };
+
+        let current = self.current.as_mut().unwrap();
+        current.crc = crc32_update(current.crc, &buf[..n]);
+        current.len += n as u64;
+        if method == METHOD_STORED {
+            current.left -= n as u64;
+        }
+        if n == 0 || (method == METHOD_STORED && current.left == 0) {
+            self.finish()?;
+        }
+        Ok(n)
+    }
+}
+
+/// Extracts an archive into the directory `dest` of a file system, creating directories as needed. Returns how many
+/// files and directories were extracted
+pub fn extract<R: Read, F: FnMut(&Progress)>(reader: R, fs: &dyn FileSystem, dest: &str, mut progress: F) -> Result<usize> {
+    let mut zip = ZipReader::new(reader);
+    let mut entries = 0;
+    let mut bytes = 0;
+    while let Some(entry) = zip.next_entry().map_err(from_io_error)? {
+        let path = destination(dest, &entry.name)?;
+        if entry.is_dir() {
+            create_dirs(fs, &path)?;
+        } else {
+            extract_file(fs, &path, &entry.name, &mut zip, entries, &mut bytes, &mut progress)?;
+        }
+        entries += 1;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{MemDisk, format_fat32};
+    use testing::mock;
+    use alloc::vec::Vec;
+
+    // boot/cmdline.txt (stored), boot/big.txt (3000 xs, deflated) and boot/efi/, by Info-ZIP's zip
+    const BOOT_ZIP: &[u8] = &[
+        0x50, 0x4b, 0x03, 0x04, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcf, 0x23, 0x4e, 0x5d, 0x70, 0xb9, 0x9c, 0xbe, 0x0e, 0x00, 0x00, 0x00, 0x0e, 0x00,
+        0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x62, 0x6f, 0x6f, 0x74, 0x2f, 0x63, 0x6d, 0x64, 0x6c, 0x69, 0x6e, 0x65, 0x2e, 0x74, 0x78, 0x74, 0x63, 0x6f,
+        0x6e, 0x73, 0x6f, 0x6c, 0x65, 0x3d, 0x74, 0x74, 0x79, 0x53, 0x30, 0x0a, 0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0xcf, 0x23,
+        0x4e, 0x5d, 0xe7, 0x17, 0xee, 0x98, 0x14, 0x00, 0x00, 0x00, 0xb8, 0x0b, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x62, 0x6f, 0x6f, 0x74, 0x2f, 0x62,
+        0x69, 0x67, 0x2e, 0x74, 0x78, 0x74, 0xed, 0xc1, 0x31, 0x01, 0x00, 0x00, 0x00, 0xc2, 0xa0, 0xda, 0x8b, 0x6f, 0x0d, 0x0f, 0xa0, 0x00, 0x00, 0x80,
+        0x77, 0x03, 0x50, 0x4b, 0x03, 0x04, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0xd4, 0x23, 0x4e, 0x5d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x62, 0x6f, 0x6f, 0x74, 0x2f, 0x65, 0x66, 0x69, 0x2f, 0x50, 0x4b, 0x01, 0x02, 0x1e, 0x03, 0x0a,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0xcf, 0x23, 0x4e, 0x5d, 0x70, 0xb9, 0x9c, 0xbe, 0x0e, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x00, 0x00, 0x62, 0x6f, 0x6f, 0x74, 0x2f, 0x63, 0x6d, 0x64, 0x6c,
+        0x69, 0x6e, 0x65, 0x2e, 0x74, 0x78, 0x74, 0x50, 0x4b, 0x01, 0x02, 0x1e, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0xcf, 0x23, 0x4e, 0x5d, 0xe7,
+        0x17, 0xee, 0x98, 0x14, 0x00, 0x00, 0x00, 0xb8, 0x0b, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xa4,
+        0x81, 0x3c, 0x00, 0x00, 0x00, 0x62, 0x6f, 0x6f, 0x74, 0x2f, 0x62, 0x69, 0x67, 0x2e, 0x74, 0x78, 0x74, 0x50, 0x4b, 0x01, 0x02, 0x1e, 0x03, 0x0a,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0xd4, 0x23, 0x4e, 0x5d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0xed, 0x41, 0x7a, 0x00, 0x00, 0x00, 0x62, 0x6f, 0x6f, 0x74, 0x2f, 0x65, 0x66, 0x69, 0x2f,
+        0x50, 0x4b, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x03, 0x00, 0xaf, 0x00, 0x00, 0x00, 0xa1, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // "streamed streamed streamed streamed\n" piped through zip -9 - -, which has to use zip64 and a data descriptor
+    const STREAMED_ZIP: &[u8] = &[
+        0x50, 0x4b, 0x03, 0x04, 0x2d, 0x00, 0x08, 0x00, 0x08, 0x00, 0x17, 0x24, 0x4e, 0x5d, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0x01, 0x00, 0x14, 0x00, 0x2d, 0x01, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x2b, 0x2e, 0x29, 0x4a, 0x4d, 0xcc, 0x4d, 0x4d, 0x51, 0x28, 0xc6, 0xc9, 0xe0, 0x02, 0x00, 0x50, 0x4b, 0x07, 0x08, 0xc4, 0xa1,
+        0x99, 0x89, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x50, 0x4b, 0x01, 0x02, 0x1e, 0x03,
+        0x2d, 0x00, 0x08, 0x00, 0x08, 0x00, 0x17, 0x24, 0x4e, 0x5d, 0xc4, 0xa1, 0x99, 0x89, 0x0f, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x80, 0x11, 0x00, 0x00, 0x00, 0x00, 0x2d, 0x50, 0x4b, 0x05, 0x06, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x01, 0x00, 0x2f, 0x00, 0x00, 0x00, 0x5a, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn reads_streamed_entries() {
+        let mut zip = ZipReader::new(STREAMED_ZIP);
+        let entry = zip.next_entry().unwrap().unwrap();
+        assert_eq!((entry.name.as_str(), entry.method, entry.len), ("-", METHOD_DEFLATED, None));
+        let mut data = Vec::new();
+        zip.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"streamed streamed streamed streamed\n");
+        assert!(zip.next_entry().unwrap().is_none());
+
+        let mut corrupt = BOOT_ZIP.to_vec();
+        let cmdline = corrupt.windows(7).position(|w| w == b"console").unwrap();
+        corrupt[cmdline] ^= 1;
+        let mut zip = ZipReader::new(&corrupt[..]);
+        zip.next_entry().unwrap();
+        assert_eq!(zip.read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn extracts_onto_file_system() {
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "ZIP").unwrap();
+        let mut files = Vec::new();
+        let extracted = extract(BOOT_ZIP, &fs, "", |p| files.push((String::from(p.name), p.bytes))).unwrap();
+
+        assert_eq!(extracted, 3);
+        files.dedup_by(|a, b| a.0 == b.0);
+        assert_eq!(files, [(String::from("boot/cmdline.txt"), 0), (String::from("boot/big.txt"), 14)]);
+        assert_eq!(fs.read("boot/cmdline.txt").unwrap(), b"console=ttyS0\n");
+        assert_eq!(fs.read("boot/big.txt").unwrap(), vec![b'x'; 3000]);
+        assert!(fs.metadata("boot/efi").unwrap().is_dir);
+    }
+}
@@ -0,0 +1,126 @@
+// A bump allocator for lots of small allocations that all go away together, e.g. the nodes of a parsed config file
+// or device paths being taken apart. AllocatePool is a call into firmware and a walk of its free lists every time,
+// and thousands of small frees leave the pool fragmented for the OS loader that comes after us. An Arena takes one
+// run of pages up front, hands out pieces of it by bumping an offset and gives it all back at once.
+//
+// What's allocated in an arena is never dropped, only forgotten when the arena is reset or goes, so it's for plain
+// data: bytes, strings, Copy types. Anything holding a Vec or a Box would leak it.
+
+use pages::{Pages, PAGE_SIZE};
+use {Result, EfiErrorKind};
+use core::{alloc::Layout, cell::Cell, mem, ptr, slice, str};
+
+/// A run of pages handed out front to back and freed all at once
+#[derive(Debug)]
+pub struct Arena {
+    pages: Pages,
+    used: Cell<usize>,
+}
+
+impl Arena {
+    /// An arena of at least `size` bytes, rounded up to whole pages
+    pub fn new(size: usize) -> Result<Self> {
+        Ok(Arena { pages: Pages::allocate(Pages::count_for(size.max(PAGE_SIZE)))?, used: Cell::new(0) })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Bytes handed out so far, alignment padding included
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.used()
+    }
+
+    /// Uninitialised memory for `layout`. Fails with OutOfResources once the arena is full
+    pub fn alloc_layout(&self, layout: Layout) -> Result<*mut u8> {
+        let base = self.pages.addr() as usize;
+        let start = base + self.used.get();
+        let aligned = start.checked_add(layout.align() - 1).ok_or(EfiErrorKind::OutOfResources)? & !(layout.align() - 1);
+        let end = aligned.checked_add(layout.size()).ok_or(EfiErrorKind::OutOfResources)?;
+        if end > base + self.capacity() {
+            return Err(EfiErrorKind::OutOfResources.into());
+        }
+        self.used.set(end - base);
+        Ok(aligned as *mut u8)
+    }
+
+    /// Moves `value` into the arena. It won't be dropped
+    #[allow(clippy::mut_from_ref)] // Each call hands out memory no other has
+    pub fn alloc<T>(&self, value: T) -> Result<&mut T> {
+        let p = self.alloc_layout(Layout::new::<T>())? as *mut T;
+        unsafe {
+            ptr::write(p, value);
+            Ok(&mut *p)
+        }
+    }
+
+    /// Zeroed bytes
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_bytes(&self, len: usize) -> Result<&mut [u8]> {
+        let p = self.alloc_layout(Layout::from_size_align(len, 1).map_err(|_| EfiErrorKind::InvalidParameter)?)?;
+        unsafe {
+            ptr::write_bytes(p, 0, len);
+            Ok(slice::from_raw_parts_mut(p, len))
+        }
+    }
+
+    /// A copy of `values`
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Copy>(&self, values: &[T]) -> Result<&mut [T]> {
+        let layout = Layout::from_size_align(mem::size_of::<T>() * values.len(), mem::align_of::<T>()).map_err(|_| EfiErrorKind::InvalidParameter)?;
+        let p = self.alloc_layout(layout)? as *mut T;
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), p, values.len());
+            Ok(slice::from_raw_parts_mut(p, values.len()))
+        }
+    }
+
+    /// A copy of `s`
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_str(&self, s: &str) -> Result<&mut str> {
+        let bytes = self.alloc_slice(s.as_bytes())?;
+        Ok(unsafe { str::from_utf8_unchecked_mut(bytes) })
+    }
+
+    /// Forgets everything allocated so far. Taking &mut makes sure none of it is still borrowed
+    pub fn reset(&mut self) {
+        self.used.set(0);
+    }
+
+    /// Runs `f` with the arena and resets it afterwards, for a parsing phase whose allocations don't outlive it
+    pub fn scope<R, F: FnOnce(&Arena) -> R>(&mut self, f: F) -> R {
+        let result = f(self);
+        self.reset();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::mock;
+
+    #[test]
+    fn bumps_and_resets() {
+        mock::install();
+        let mut arena = Arena::new(1).unwrap();
+        assert_eq!(arena.capacity(), PAGE_SIZE);
+
+        let len = arena.scope(|arena| {
+            let name = arena.alloc_str("Boot0001").unwrap();
+            arena.alloc(1u8).unwrap();
+            let word = arena.alloc(0x1234_5678u32).unwrap();
+            assert_eq!(&*word as *const u32 as usize % mem::align_of::<u32>(), 0);
+            assert_eq!((&*name, *word), ("Boot0001", 0x1234_5678));
+            assert_eq!(arena.used(), 8 + 1 + 3 + 4);
+            assert_eq!(arena.alloc_bytes(PAGE_SIZE).unwrap_err().kind(), EfiErrorKind::OutOfResources);
+            arena.alloc_slice(&[1u16, 2, 3]).unwrap().len()
+        });
+        assert_eq!((len, arena.used()), (3, 0));
+    }
+}
@@ -0,0 +1,295 @@
+// Backtraces for panics and CPU exceptions.
+// Walks the chain of saved frame pointers, so the image has to be built with -C force-frame-pointers=yes. Without
+// them the walk stops early or wanders off into nonsense, though never further than MAX_FRAMES.
+// Addresses are printed as offsets from our image base (from our EFI_LOADED_IMAGE_PROTOCOL) so that they can be
+// looked up against the build even when firmware loaded us somewhere different each time. If the application has
+// registered a symbol map with set_symbol_map(), e.g. one generated at build time and embedded with include_str!(),
+// they're printed as the function they're in as well.
+// Exceptions are only caught on x64, using the firmware's EFI_DEBUG_SUPPORT_PROTOCOL (see catch_exceptions())
+
+use ffi::{
+    loaded_image::{EFI_LOADED_IMAGE_PROTOCOL, EFI_LOADED_IMAGE_PROTOCOL_GUID},
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+};
+#[cfg(target_arch = "x86_64")]
+use ffi::debug_support::{
+    EFI_DEBUG_SUPPORT_PROTOCOL,
+    EFI_DEBUG_SUPPORT_PROTOCOL_GUID,
+    EFI_EXCEPTION_TYPE,
+    EFI_SYSTEM_CONTEXT,
+    EFI_SYSTEM_CONTEXT_X64,
+    IsaX64,
+    EXCEPT_X64_DIVIDE_ERROR,
+    EXCEPT_X64_INVALID_OPCODE,
+    EXCEPT_X64_DOUBLE_FAULT,
+    EXCEPT_X64_STACK_FAULT,
+    EXCEPT_X64_GP_FAULT,
+    EXCEPT_X64_PAGE_FAULT,
+    EXCEPT_X64_ALIGNMENT_CHECK,
+    EXCEPT_X64_SIMD,
+};
+use status_code::{self, StatusCode, StatusCodeWriter, Severity, ErrorCode};
+use {Result, EfiErrorKind, system_table, image_handle};
+use arch;
+use io::Write;
+use core::{fmt, mem, ptr, panic::PanicInfo};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+const MAX_FRAMES: usize = 64;
+const MAX_FRAME_SIZE: usize = 1024 * 1024; // A bigger step between frames means we've lost the chain
+
+/// Offsets into our image and the function at each, sorted
+pub struct SymbolMap {
+    symbols: Vec<(usize, String)>,
+}
+
+impl SymbolMap {
+    /// Parses lines of `<hex offset> <name>`, offsets being from the image base. nm-style lines with a type letter
+    /// between the two (`<hex offset> T <name>`) are fine too. Blank lines and those starting with # are skipped
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut symbols = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(2, char::is_whitespace);
+            let offset = fields.next().unwrap_or("");
+            let offset = usize::from_str_radix(offset.trim_start_matches("0x"), 16).map_err(|_| EfiErrorKind::VolumeCorrupted)?;
+            let mut name = fields.next().ok_or(EfiErrorKind::VolumeCorrupted)?.trim_start();
+            let mut rest = name.splitn(2, char::is_whitespace);
+            if let (Some(kind), Some(after)) = (rest.next(), rest.next()) {
+                if kind.len() == 1 {
+                    name = after.trim_start();
+                }
+            }
+            symbols.push((offset, String::from(name)));
+        }
+
+        symbols.sort_by_key(|&(offset, _)| offset);
+        Ok(SymbolMap { symbols })
+    }
+
+    /// The symbol `offset` is in and how far into it
+    pub fn lookup(&self, offset: usize) -> Option<(&str, usize)> {
+        let i = match self.symbols.binary_search_by_key(&offset, |&(start, _)| start) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (start, ref name) = self.symbols[i];
+        Some((name, offset - start))
+    }
+}
+
+static mut SYMBOL_MAP: *mut SymbolMap = ptr::null_mut(); // Boxed
+
+/// Has backtraces name the function each address is in
+pub fn set_symbol_map(map: SymbolMap) {
+    let old = unsafe { SYMBOL_MAP };
+    unsafe { SYMBOL_MAP = Box::into_raw(Box::new(map)) };
+    if !old.is_null() {
+        unsafe { drop(Box::from_raw(old)) };
+    }
+}
+
+pub struct Backtrace {
+    frames: Vec<usize>, // Return addresses, innermost first
+    image: Option<(usize, usize)>, // Base and size
+}
+
+impl Backtrace {
+    /// The backtrace of the caller
+    #[inline(never)]
+    pub fn capture() -> Self {
+        unsafe { Self::from_frame_pointer(arch::frame_pointer()) }
+    }
+
+    /// The backtrace from the frame at `fp`, e.g. a frame pointer saved when an exception was taken.
+    /// Unsafe because fp has to be a frame pointer (or zero)
+    pub unsafe fn from_frame_pointer(fp: usize) -> Self {
+        Backtrace { frames: walk(fp), image: image_range() }
+    }
+
+    /// Return addresses, innermost first
+    pub fn frames(&self) -> &[usize] {
+        &self.frames
+    }
+
+    /// Offset of the address from our image base, if it's in our image
+    pub fn image_offset(&self, address: usize) -> Option<usize> {
+        match self.image {
+            Some((base, size)) if address >= base && address - base < size => Some(address - base),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "backtrace:")?;
+        let symbols = unsafe { SYMBOL_MAP };
+        for (i, &address) in self.frames.iter().enumerate() {
+            write!(f, "{:>4}: {:#018x}", i, address)?;
+            if let Some(offset) = self.image_offset(address) {
+                write!(f, " image+{:#x}", offset)?;
+                // Return addresses are just past the call, which may be the first byte of the next function
+                let symbol = if symbols.is_null() { None } else { unsafe { (*symbols).lookup(offset.saturating_sub(1)) } };
+                if let Some((name, into)) = symbol {
+                    write!(f, " {}+{:#x}", name, into + 1)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// For a #[panic_handler]. Reports the panic with a backtrace on the console and as status codes
+#[inline(never)]
+pub fn report_panic(info: &PanicInfo) {
+    report(&format!("panicked: {}", info), &Backtrace::capture());
+}
+
+/// Has CPU exceptions (page faults, general protection faults, invalid opcodes and so on) reported with a backtrace
+/// like panics are rather than however the firmware deals with them, which is often a register dump or a silent hang.
+/// The machine is left halted afterwards.
+/// Fails with NotFound if the firmware doesn't have EFI_DEBUG_SUPPORT_PROTOCOL and AlreadyStarted if something else,
+/// such as a debugger agent, already handles some of the exceptions
+#[cfg(target_arch = "x86_64")]
+pub fn catch_exceptions() -> Result<()> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_DEBUG_SUPPORT_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_DEBUG_SUPPORT_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+    }
+
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+
+    if unsafe { (*protocol).Isa } != IsaX64 {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+
+    for &exception in EXCEPTIONS {
+        unsafe {
+            ret_on_err!(((*protocol).RegisterExceptionCallback)(protocol, 0, Some(on_exception), exception));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "x86_64")]
+const EXCEPTIONS: &[EFI_EXCEPTION_TYPE] = &[
+    EXCEPT_X64_DIVIDE_ERROR,
+    EXCEPT_X64_INVALID_OPCODE,
+    EXCEPT_X64_DOUBLE_FAULT,
+    EXCEPT_X64_STACK_FAULT,
+    EXCEPT_X64_GP_FAULT,
+    EXCEPT_X64_PAGE_FAULT,
+    EXCEPT_X64_ALIGNMENT_CHECK,
+    EXCEPT_X64_SIMD,
+];
+
+#[cfg(target_arch = "x86_64")]
+extern "efiapi" fn on_exception(exception: EFI_EXCEPTION_TYPE, context: EFI_SYSTEM_CONTEXT) {
+    let context = unsafe { &*(context as *const EFI_SYSTEM_CONTEXT_X64) };
+    let name = match exception {
+        EXCEPT_X64_DIVIDE_ERROR => "divide error",
+        EXCEPT_X64_INVALID_OPCODE => "invalid opcode",
+        EXCEPT_X64_DOUBLE_FAULT => "double fault",
+        EXCEPT_X64_STACK_FAULT => "stack fault",
+        EXCEPT_X64_GP_FAULT => "general protection fault",
+        EXCEPT_X64_PAGE_FAULT => "page fault",
+        EXCEPT_X64_ALIGNMENT_CHECK => "alignment check",
+        EXCEPT_X64_SIMD => "SIMD floating point exception",
+        _ => "exception",
+    };
+    let mut message = format!("{} (error code {:#x}) at {:#x}", name, context.ExceptionData, context.Rip);
+    if exception == EXCEPT_X64_PAGE_FAULT {
+        message.push_str(&format!(" accessing {:#x}", context.Cr2));
+    }
+
+    let mut backtrace = unsafe { Backtrace::from_frame_pointer(context.Rbp as usize) };
+    backtrace.frames.insert(0, context.Rip as usize);
+    report(&message, &backtrace);
+
+    // Returning would just run the faulting instruction again
+    loop {
+        arch::halt();
+    }
+}
+
+// Console output may not be seen by anyone in the field, so it's sent as status codes too
+fn report(message: &str, backtrace: &Backtrace) {
+    println!("\n{}\n{}", message, backtrace);
+    let _ = status_code::report_with_message(StatusCode::Error(Severity::Unrecovered, ErrorCode::NonSpecific), message);
+    let _ = write!(StatusCodeWriter::new(), "{}", backtrace);
+}
+
+fn walk(mut fp: usize) -> Vec<usize> {
+    let mut frames = Vec::new();
+    while fp != 0 && fp & (mem::align_of::<usize>() - 1) == 0 && frames.len() < MAX_FRAMES {
+        let (next, return_address) = unsafe { arch::caller_frame(fp) };
+        if return_address == 0 {
+            break;
+        }
+        frames.push(return_address);
+
+        // Stacks grow down so callers' frames are always higher up
+        if next <= fp || next - fp > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = next;
+    }
+    frames
+}
+
+fn image_range() -> Option<(usize, usize)> {
+    let bs = system_table().BootServices;
+    let loaded_image: *const EFI_LOADED_IMAGE_PROTOCOL = ptr::null();
+    let status = unsafe { ((*bs).OpenProtocol)(image_handle(), &EFI_LOADED_IMAGE_PROTOCOL_GUID, mem::transmute(&loaded_image), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL) };
+    if !::ffi::IsSuccess(status) || loaded_image.is_null() {
+        return None;
+    }
+    unsafe { Some(((*loaded_image).ImageBase as usize, (*loaded_image).ImageSize as usize)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn walks_frames() {
+        // Three frames, each a saved frame pointer followed by a return address (the layout on the host)
+        let mut stack = Box::new([0usize; 6]);
+        let base = stack.as_ptr() as usize;
+        let word = mem::size_of::<usize>();
+        stack[0] = base + 2 * word;
+        stack[1] = 0x1010;
+        stack[2] = base + 4 * word;
+        stack[3] = 0x1104;
+        stack[4] = 0;
+        stack[5] = 0x5000; // Outside the image
+        let backtrace = Backtrace { frames: walk(base), image: Some((0x1000, 0x1000)) };
+        assert_eq!(backtrace.frames(), [0x1010, 0x1104, 0x5000]);
+
+        set_symbol_map(SymbolMap::parse("# offset name\n0x100 T efi_main\n0 start\n").unwrap());
+        let text = backtrace.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "   0: 0x0000000000001010 image+0x10 start+0x10");
+        assert_eq!(lines[2], "   1: 0x0000000000001104 image+0x104 efi_main+0x4");
+        assert_eq!(lines[3], "   2: 0x0000000000005000");
+    }
+
+    #[test]
+    fn parses_symbol_maps() {
+        let map = SymbolMap::parse("2000 t <T as core::fmt::Debug>::fmt\n1000 efi::net::Tcp4Stream::write\n").unwrap();
+        assert_eq!(map.lookup(0xfff), None);
+        assert_eq!(map.lookup(0x1000), Some(("efi::net::Tcp4Stream::write", 0)));
+        assert_eq!(map.lookup(0x2345), Some(("<T as core::fmt::Debug>::fmt", 0x345)));
+        assert!(SymbolMap::parse("xyz efi_main").is_err());
+    }
+}
@@ -0,0 +1,337 @@
+// Bluetooth controllers via EFI_BLUETOOTH_CONFIG_PROTOCOL (BR/EDR) and EFI_BLUETOOTH_LE_CONFIG_PROTOCOL.
+// Only reading for now: which controllers there are, what they're called and which devices they've been paired with.
+// Dual-mode controllers have both protocols on their handle.
+
+use ffi::{
+    bluetooth::{
+        EFI_BLUETOOTH_CONFIG_PROTOCOL,
+        EFI_BLUETOOTH_CONFIG_PROTOCOL_GUID,
+        EFI_BLUETOOTH_LE_CONFIG_PROTOCOL,
+        EFI_BLUETOOTH_LE_CONFIG_PROTOCOL_GUID,
+        EFI_BLUETOOTH_CONFIG_DATA_TYPE,
+        BLUETOOTH_ADDRESS,
+        BLUETOOTH_LE_ADDRESS,
+        BLUETOOTH_LE_ADDRESS_RANDOM,
+    },
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_BUFFER_TOO_SMALL,
+    EFI_GUID,
+    EFI_HANDLE,
+    EFI_STATUS,
+    UINTN,
+    VOID,
+};
+use boot_services::locate_handles;
+use {Result, EfiError, EfiErrorKind, system_table, image_handle, to_res};
+use core::{fmt, mem, ptr, str::FromStr};
+use alloc::{string::String, vec::Vec};
+
+/// A device address. Held in the order it goes over the air, least significant byte first, but written the usual way
+/// round e.g. `00:1A:7D:DA:71:13`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BluetoothAddress(pub [u8; 6]);
+
+impl fmt::Display for BluetoothAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", b[5], b[4], b[3], b[2], b[1], b[0])
+    }
+}
+
+impl FromStr for BluetoothAddress {
+    type Err = EfiError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut address = [0; 6];
+        let mut parts = s.split(':');
+        for byte in address.iter_mut().rev() {
+            let part = parts.next().filter(|p| p.len() == 2).ok_or_else(|| EfiError::from(EfiErrorKind::InvalidParameter))?;
+            *byte = u8::from_str_radix(part, 16).map_err(|_| EfiError::from(EfiErrorKind::InvalidParameter))?;
+        }
+        if parts.next().is_some() {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(BluetoothAddress(address))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// Classic Bluetooth
+    BrEdr,
+    /// Low Energy, with either a public address or a random one
+    Le { random: bool },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PairedDevice {
+    pub address: BluetoothAddress,
+    pub transport: Transport,
+}
+
+pub struct Controller {
+    handle: EFI_HANDLE,
+    br_edr: *const EFI_BLUETOOTH_CONFIG_PROTOCOL,
+    le: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL,
+}
+
+impl Controller {
+    /// Every controller the firmware's Bluetooth stack drives
+    pub fn all() -> Result<Vec<Self>> {
+        let mut handles = locate_handles(&EFI_BLUETOOTH_CONFIG_PROTOCOL_GUID)?;
+        for handle in locate_handles(&EFI_BLUETOOTH_LE_CONFIG_PROTOCOL_GUID)? {
+            if !handles.contains(&handle) {
+                handles.push(handle);
+            }
+        }
+
+        Ok(handles.into_iter().map(|handle| Controller {
+            handle,
+            br_edr: open_protocol(handle, &EFI_BLUETOOTH_CONFIG_PROTOCOL_GUID),
+            le: open_protocol(handle, &EFI_BLUETOOTH_LE_CONFIG_PROTOCOL_GUID),
+        }).collect())
+    }
+
+    /// The controller's handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    pub fn supports_br_edr(&self) -> bool {
+        !self.br_edr.is_null()
+    }
+
+    pub fn supports_le(&self) -> bool {
+        !self.le.is_null()
+    }
+
+    /// The name the controller shows other devices
+    pub fn name(&self) -> Result<String> {
+        let name = self.get_data(EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeDeviceName)?;
+        Ok(c_string(&name))
+    }
+
+    /// The controller's public address
+    pub fn address(&self) -> Result<BluetoothAddress> {
+        let data = self.get_data(EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeBDADDR)?;
+        if data.len() < 6 {
+            return Err(EfiErrorKind::ProtocolError.into());
+        }
+        let mut address = [0; 6];
+        address.copy_from_slice(&data[..6]);
+        Ok(BluetoothAddress(address))
+    }
+
+    /// The 24-bit class of device the controller advertises over BR/EDR. Fails with Unsupported for LE-only
+    /// controllers
+    pub fn class_of_device(&self) -> Result<u32> {
+        if self.br_edr.is_null() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        let data = br_edr_data(self.br_edr, EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeClassOfDevice)?;
+        Ok(data.iter().take(3).enumerate().fold(0, |class, (i, &b)| class | (b as u32) << (8 * i)))
+    }
+
+    /// The devices the controller keeps pairings for, BR/EDR ones first
+    pub fn paired_devices(&self) -> Result<Vec<PairedDevice>> {
+        let mut devices = Vec::new();
+        if !self.br_edr.is_null() {
+            let data = optional(br_edr_data(self.br_edr, EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeControllerStoredPairedDeviceList))?;
+            devices.extend(data.chunks_exact(mem::size_of::<BLUETOOTH_ADDRESS>()).map(|entry| {
+                let mut address = [0; 6];
+                address.copy_from_slice(entry);
+                PairedDevice { address: BluetoothAddress(address), transport: Transport::BrEdr }
+            }));
+        }
+        if !self.le.is_null() {
+            let data = optional(le_data(self.le, EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeControllerStoredPairedDeviceList))?;
+            devices.extend(data.chunks_exact(mem::size_of::<BLUETOOTH_LE_ADDRESS>()).map(|entry| {
+                let mut address = [0; 6];
+                address.copy_from_slice(&entry[..6]);
+                PairedDevice { address: BluetoothAddress(address), transport: Transport::Le { random: entry[6] == BLUETOOTH_LE_ADDRESS_RANDOM } }
+            }));
+        }
+        Ok(devices)
+    }
+
+    /// The name of a remote device, as the controller last heard it
+    pub fn device_name(&self, device: &PairedDevice) -> Result<String> {
+        let data = match device.transport {
+            Transport::BrEdr if !self.br_edr.is_null() => {
+                let address = BLUETOOTH_ADDRESS { Address: device.address.0 };
+                read_data(|size, data| unsafe {
+                    ((*self.br_edr).GetRemoteData)(self.br_edr, EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeDeviceName, &address, size, data)
+                })?
+            },
+            Transport::Le { random } if !self.le.is_null() => {
+                let address = BLUETOOTH_LE_ADDRESS { Address: device.address.0, Type: random as u8 };
+                read_data(|size, data| unsafe {
+                    ((*self.le).GetRemoteData)(self.le, EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeDeviceName, &address, size, data)
+                })?
+            },
+            _ => return Err(EfiErrorKind::Unsupported.into()),
+        };
+        Ok(c_string(&data))
+    }
+
+    // Data about the controller itself, from whichever protocol there is
+    fn get_data(&self, data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE) -> Result<Vec<u8>> {
+        if !self.br_edr.is_null() {
+            br_edr_data(self.br_edr, data_type)
+        } else {
+            le_data(self.le, data_type)
+        }
+    }
+}
+
+fn br_edr_data(protocol: *const EFI_BLUETOOTH_CONFIG_PROTOCOL, data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE) -> Result<Vec<u8>> {
+    read_data(|size, data| unsafe { ((*protocol).GetData)(protocol, data_type, size, data) })
+}
+
+fn le_data(protocol: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL, data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE) -> Result<Vec<u8>> {
+    read_data(|size, data| unsafe { ((*protocol).GetData)(protocol, data_type, size, data) })
+}
+
+// Asks how big the data is, then gets it
+fn read_data<F: Fn(*mut UINTN, *mut VOID) -> EFI_STATUS>(get: F) -> Result<Vec<u8>> {
+    let mut size: UINTN = 0;
+    let status = get(&mut size, ptr::null_mut());
+    if status != EFI_BUFFER_TOO_SMALL {
+        to_res((), status)?;
+        return Ok(Vec::new());
+    }
+    let mut data = vec![0u8; size];
+    to_res((), get(&mut size, data.as_mut_ptr() as *mut VOID))?;
+    data.truncate(size);
+    Ok(data)
+}
+
+// Nothing stored is reported as NotFound by some stacks
+fn optional(data: Result<Vec<u8>>) -> Result<Vec<u8>> {
+    match data {
+        Err(e) if e.kind() == EfiErrorKind::NotFound => Ok(Vec::new()),
+        data => data,
+    }
+}
+
+// A null-terminated UTF-8 name
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> *const T {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    let status = unsafe { traced!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL)) };
+    if ::ffi::IsSuccess(status) { protocol } else { ptr::null() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_NOT_FOUND, EFI_SUCCESS};
+    use testing::mock;
+    use alloc::{boxed::Box, string::ToString};
+
+    // Answers GetData from a canned value for each type
+    fn answer(data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE, size: *mut UINTN, data: *mut VOID, le: bool) -> EFI_STATUS {
+        let value: &[u8] = match (data_type, le) {
+            (EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeDeviceName, _) => b"laptop\0",
+            (EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeBDADDR, _) => &[0x13, 0x71, 0xda, 0x7d, 0x1a, 0x00],
+            (EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeClassOfDevice, false) => &[0x0c, 0x01, 0x1a],
+            (EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeControllerStoredPairedDeviceList, false) => &[1, 2, 3, 4, 5, 6],
+            (EFI_BLUETOOTH_CONFIG_DATA_TYPE::EfiBluetoothConfigDataTypeControllerStoredPairedDeviceList, true) => &[0xaa; 7],
+            _ => return EFI_NOT_FOUND,
+        };
+        unsafe {
+            if *size < value.len() {
+                *size = value.len();
+                return EFI_BUFFER_TOO_SMALL;
+            }
+            ptr::copy_nonoverlapping(value.as_ptr(), data as *mut u8, value.len());
+            *size = value.len();
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn init(_this: *const EFI_BLUETOOTH_CONFIG_PROTOCOL) -> EFI_STATUS {
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_data(_this: *const EFI_BLUETOOTH_CONFIG_PROTOCOL, data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE, size: *mut UINTN, data: *mut VOID) -> EFI_STATUS {
+        answer(data_type, size, data, false)
+    }
+
+    extern "efiapi" fn get_remote_data(_this: *const EFI_BLUETOOTH_CONFIG_PROTOCOL, data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE, address: *const BLUETOOTH_ADDRESS, size: *mut UINTN, data: *mut VOID) -> EFI_STATUS {
+        if unsafe { (*address).Address } != [1, 2, 3, 4, 5, 6] {
+            return EFI_NOT_FOUND;
+        }
+        answer(data_type, size, data, false)
+    }
+
+    extern "efiapi" fn le_init(_this: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL) -> EFI_STATUS {
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn le_get_data(_this: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL, data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE, size: *mut UINTN, data: *mut VOID) -> EFI_STATUS {
+        answer(data_type, size, data, true)
+    }
+
+    extern "efiapi" fn le_get_remote_data(_this: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL, _data_type: EFI_BLUETOOTH_CONFIG_DATA_TYPE, _address: *const BLUETOOTH_LE_ADDRESS, _size: *mut UINTN, _data: *mut VOID) -> EFI_STATUS {
+        EFI_NOT_FOUND
+    }
+
+    #[test]
+    fn enumerates_controllers_and_pairings() {
+        mock::install();
+        let br_edr: &'static EFI_BLUETOOTH_CONFIG_PROTOCOL = Box::leak(Box::new(EFI_BLUETOOTH_CONFIG_PROTOCOL {
+            Init: init,
+            Scan: ptr::null(),
+            Connect: ptr::null(),
+            Disconnect: ptr::null(),
+            GetData: get_data,
+            SetData: ptr::null(),
+            GetRemoteData: get_remote_data,
+            RegisterPinCallback: ptr::null(),
+            RegisterGetLinkKeyCallback: ptr::null(),
+            RegisterSetLinkKeyCallback: ptr::null(),
+            RegisterLinkConnectCompleteCallback: ptr::null(),
+        }));
+        let le: &'static EFI_BLUETOOTH_LE_CONFIG_PROTOCOL = Box::leak(Box::new(EFI_BLUETOOTH_LE_CONFIG_PROTOCOL {
+            Init: le_init,
+            Scan: ptr::null(),
+            Connect: ptr::null(),
+            Disconnect: ptr::null(),
+            GetData: le_get_data,
+            GetRemoteData: le_get_remote_data,
+            RegisterSmpAuthCallback: ptr::null(),
+            SendSmpAuthData: ptr::null(),
+            RegisterSmpGetDataCallback: ptr::null(),
+            RegisterSmpSetDataCallback: ptr::null(),
+            RegisterLinkConnectCompleteCallback: ptr::null(),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        let bs = system_table().BootServices;
+        unsafe {
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_BLUETOOTH_CONFIG_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, br_edr as *const _ as *const VOID);
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_BLUETOOTH_LE_CONFIG_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, le as *const _ as *const VOID);
+        }
+
+        let controllers = Controller::all().unwrap();
+        let controller = controllers.iter().find(|c| c.handle() == handle).unwrap();
+        assert!(controller.supports_br_edr() && controller.supports_le());
+        assert_eq!(controller.name().unwrap(), "laptop");
+        assert_eq!(controller.address().unwrap().to_string(), "00:1A:7D:DA:71:13");
+        assert_eq!(controller.class_of_device().unwrap(), 0x1a010c);
+
+        let paired = controller.paired_devices().unwrap();
+        assert_eq!(paired, [
+            PairedDevice { address: "06:05:04:03:02:01".parse().unwrap(), transport: Transport::BrEdr },
+            PairedDevice { address: BluetoothAddress([0xaa; 6]), transport: Transport::Le { random: false } },
+        ]);
+        assert_eq!(controller.device_name(&paired[0]).unwrap(), "laptop");
+        assert_eq!(controller.device_name(&paired[1]).unwrap_err().kind(), EfiErrorKind::NotFound);
+        assert!("00:1A:7D:DA:71".parse::<BluetoothAddress>().is_err() && "00:1A:7D:DA:71:13:00".parse::<BluetoothAddress>().is_err());
+    }
+}
@@ -1,22 +1,32 @@
 use ffi::{
-    boot_services::EFI_LOCATE_SEARCH_TYPE,
+    boot_services::{EFI_LOCATE_SEARCH_TYPE, EFI_OPEN_PROTOCOL_GET_PROTOCOL},
+    device_path::{EFI_DEVICE_PATH_PROTOCOL, EFI_DEVICE_PATH_PROTOCOL_GUID},
     EFI_HANDLE,
     EFI_GUID,
     EFI_NOT_FOUND,
     VOID,
     UINTN,
 };
-use ::{Result, system_table, boxed::EfiBox};
-use core::{ptr};
+use ::{Result, EfiErrorKind, system_table, image_handle, boxed::EfiBox};
+use core::{mem, ptr};
 use alloc::vec::Vec;
 
 // TODO: this guy should return an iterator to avoid allocations
 pub (crate) fn locate_handles(protocol_guid: &EFI_GUID) -> Result<Vec<EFI_HANDLE>> {
+    locate(EFI_LOCATE_SEARCH_TYPE::ByProtocol, protocol_guid)
+}
+
+/// Every handle in the handle database
+pub (crate) fn all_handles() -> Result<Vec<EFI_HANDLE>> {
+    locate(EFI_LOCATE_SEARCH_TYPE::AllHandles, ptr::null())
+}
+
+fn locate(search_type: EFI_LOCATE_SEARCH_TYPE, protocol_guid: *const EFI_GUID) -> Result<Vec<EFI_HANDLE>> {
     let bs = (*system_table()).BootServices;
     let mut handle_buf: *const EFI_HANDLE = ptr::null();
     let mut no_of_handles: UINTN = 0;
     unsafe {
-        let status = ((*bs).LocateHandleBuffer)(EFI_LOCATE_SEARCH_TYPE::ByProtocol, protocol_guid, ptr::null() as *const VOID, &mut no_of_handles, &mut handle_buf);
+        let status = traced!(((*bs).LocateHandleBuffer)(search_type, protocol_guid, ptr::null() as *const VOID, &mut no_of_handles, &mut handle_buf));
         if status == EFI_NOT_FOUND {
             return Ok(Vec::new()); // returning empty
         }
@@ -33,3 +43,43 @@ pub (crate) fn locate_handles(protocol_guid: &EFI_GUID) -> Result<Vec<EFI_HANDLE
         Ok(handles)
     }
 }
+
+/// The GUIDs of the protocols installed on the handle
+pub (crate) fn protocols_per_handle(handle: EFI_HANDLE) -> Result<Vec<EFI_GUID>> {
+    let bs = system_table().BootServices;
+    let mut buf: *const *const EFI_GUID = ptr::null();
+    let mut count: UINTN = 0;
+    unsafe {
+        ret_on_err!(((*bs).ProtocolsPerHandle)(handle, &mut buf, &mut count));
+        if buf.is_null() {
+            return Ok(Vec::new());
+        }
+        let buf_box = EfiBox::from_raw(buf as *mut *const EFI_GUID); // The GUIDs themselves belong to the firmware
+        Ok((0..count).map(|i| **buf_box.as_raw().add(i)).collect())
+    }
+}
+
+/// The handle's device path
+pub (crate) fn device_path(handle: EFI_HANDLE) -> Result<*const EFI_DEVICE_PATH_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let path: *const EFI_DEVICE_PATH_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).OpenProtocol)(handle, &EFI_DEVICE_PATH_PROTOCOL_GUID, mem::transmute(&path), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL));
+    }
+    if path.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+    Ok(path)
+}
+
+/// The nearest handle on the way to `path` with the protocol, e.g. the controller a disk is on, and the rest of the
+/// path from there
+pub (crate) fn locate_device_path(protocol_guid: &EFI_GUID, path: *const EFI_DEVICE_PATH_PROTOCOL) -> Result<(EFI_HANDLE, *const EFI_DEVICE_PATH_PROTOCOL)> {
+    let bs = system_table().BootServices;
+    let mut rest = path;
+    let mut handle: EFI_HANDLE = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateDevicePath)(protocol_guid, &mut rest, &mut handle));
+    }
+    Ok((handle, rest))
+}
@@ -0,0 +1,381 @@
+// Boot Loader Specification type #1 entries (https://uapi-group.org/specifications/specs/boot_loader_specification/)
+// and the loader.conf that systemd-boot reads alongside them. Type #2 entries, unified kernel images, are in uki.rs
+// and listed here with the rest.
+//
+// Entries can be on the ESP and on an extended boot loader partition (XBOOTLDR) with more room; discover() reads
+// both, from every GPT disk, into one menu. Only GPT partition types say which is which, so MBR disks are left out.
+//
+// Each /loader/entries/*.conf is one entry of `key value` lines. Entries for another architecture are left out and the
+// rest are ordered the way the specification asks: those with a sort-key first, then newest version first.
+
+use super::{uki, BootEntry, BootImage, BootMenu, compare_versions};
+use core::{cmp::Ordering, time::Duration};
+use ffi::media::EFI_BLOCK_IO_PROTOCOL_GUID;
+use boot_services::locate_handles;
+use fs::{mount, BlockDevice, BlockIo, DiskIo, FileSystem, Region};
+use image::ExitData;
+use partition::{read_gpt, Partition, EFI_PART_TYPE_EFI_SYSTEM_PART_GUID, LINUX_EXTENDED_BOOT_GUID};
+use {Result, EfiErrorKind};
+use alloc::{boxed::Box, string::{String, ToString}, vec::Vec};
+
+pub const LOADER_CONF: &str = "loader/loader.conf";
+pub const ENTRIES_DIR: &str = "loader/entries";
+
+// The names the specification gives architectures, which are the ones in the removable media boot file names
+#[cfg(target_arch = "x86_64")]
+const ARCHITECTURE: &str = "x64";
+#[cfg(target_arch = "x86")]
+const ARCHITECTURE: &str = "ia32";
+#[cfg(target_arch = "aarch64")]
+const ARCHITECTURE: &str = "aa64";
+#[cfg(target_arch = "arm")]
+const ARCHITECTURE: &str = "arm";
+#[cfg(target_arch = "riscv64")]
+const ARCHITECTURE: &str = "riscv64";
+
+/// What loader.conf says about the menu. Settings other boot loaders don't share with us are ignored
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoaderConf {
+    /// Pattern matched against entry ids, with `*` and `?` wildcards
+    pub default: Option<String>,
+    /// None if the menu should wait for the user (`menu-force`)
+    pub timeout: Option<Duration>,
+}
+
+impl LoaderConf {
+    pub fn parse(text: &str) -> Self {
+        let mut conf = LoaderConf { default: None, timeout: Some(Duration::from_secs(0)) };
+        for (key, value) in pairs(text) {
+            match key {
+                "default" if !value.starts_with('@') => conf.default = Some(value.to_string()), // @saved etc. come from variables
+                "timeout" if value == "menu-force" => conf.timeout = None,
+                "timeout" => if let Ok(secs) = value.parse() {
+                    conf.timeout = Some(Duration::from_secs(secs));
+                },
+                _ => {},
+            }
+        }
+        conf
+    }
+}
+
+// The parts of an entry that order it but don't otherwise matter
+#[derive(Default)]
+struct SortKey {
+    sort_key: Option<String>,
+    machine_id: String,
+}
+
+/// Parses one entry file. `id` is the file's name. Fails with LoadError if the entry has nothing to boot and with
+/// Unsupported if it's for another architecture
+pub fn parse_entry(id: &str, text: &str) -> Result<BootEntry> {
+    parse(id, text).map(|(entry, _)| entry)
+}
+
+fn parse(id: &str, text: &str) -> Result<(BootEntry, SortKey)> {
+    let mut title = None;
+    let mut version = None;
+    let mut linux = None;
+    let mut efi = None;
+    let mut initrds = Vec::new();
+    let mut devicetree = None;
+    let mut overlays = Vec::new();
+    let mut options = Vec::new();
+    let mut sort = SortKey::default();
+
+    for (key, value) in pairs(text) {
+        match key {
+            "title" => title = Some(value.to_string()),
+            "version" => version = Some(value.to_string()),
+            "machine-id" => sort.machine_id = value.to_string(),
+            "sort-key" => sort.sort_key = Some(value.to_string()),
+            "linux" => linux = Some(value.to_string()),
+            "efi" => efi = Some(value.to_string()),
+            "initrd" => initrds.extend(value.split_whitespace().map(String::from)),
+            "devicetree" => devicetree = Some(value.to_string()),
+            "devicetree-overlay" => overlays.extend(value.split_whitespace().map(String::from)),
+            "options" => options.push(value),
+            "architecture" if !value.eq_ignore_ascii_case(ARCHITECTURE) => return Err(EfiErrorKind::Unsupported.into()),
+            _ => {},
+        }
+    }
+
+    let image = match (linux, efi) {
+        (Some(kernel), _) => BootImage::Linux { kernel, initrds, devicetree, overlays },
+        (None, Some(path)) => BootImage::Efi(path),
+        (None, None) => return Err(EfiErrorKind::LoadError.into()),
+    };
+    let title = title.or_else(|| version.clone()).unwrap_or_else(|| id.to_string());
+    let entry = BootEntry { id: id.to_string(), title, version, image, options: options.join(" ") };
+    Ok((entry, sort))
+}
+
+/// Reads loader.conf, if there is one, and all the entries of both types. Entry files that can't be booted from here
+/// are skipped
+pub fn load(fs: &dyn FileSystem) -> Result<BootMenu> {
+    let conf = read_conf(fs)?.unwrap_or_else(|| LoaderConf::parse(""));
+    let mut entries = entries(fs)?;
+    entries.sort_by(order);
+    Ok(menu(&conf, entries.into_iter().map(|(entry, _)| entry).collect()))
+}
+
+/// What a BLS partition is for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VolumeKind {
+    Esp,
+    /// The extended boot loader partition
+    Xbootldr,
+}
+
+/// A partition entries are read from
+pub struct Volume {
+    pub kind: VolumeKind,
+    pub partition: Partition,
+    pub fs: Box<dyn FileSystem>,
+}
+
+/// Every ESP and XBOOTLDR partition on every GPT disk. Those whose file system can't be read are left out
+pub fn volumes() -> Result<Vec<Volume>> {
+    let mut volumes = Vec::new();
+    for handle in locate_handles(&EFI_BLOCK_IO_PROTOCOL_GUID)? {
+        let mut block_io = match BlockIo::new(handle) {
+            Ok(block_io) if !block_io.is_partition() => block_io,
+            _ => continue,
+        };
+        let partitions = match read_gpt(&mut block_io) {
+            Ok(partitions) => partitions,
+            Err(_) => continue, // Not GPT
+        };
+        for partition in partitions {
+            let kind = match partition.type_guid {
+                EFI_PART_TYPE_EFI_SYSTEM_PART_GUID => VolumeKind::Esp,
+                LINUX_EXTENDED_BOOT_GUID => VolumeKind::Xbootldr,
+                _ => continue,
+            };
+            let (start, len) = partition.byte_range(block_io.block_size());
+            if let Ok(fs) = DiskIo::new(handle).and_then(|disk| Region::new(disk, start, len)).and_then(mount) {
+                volumes.push(Volume { kind, partition, fs });
+            }
+        }
+    }
+    Ok(volumes)
+}
+
+/// The entries on several volumes in one menu
+pub struct Discovered {
+    pub volumes: Vec<Volume>,
+    pub menu: BootMenu,
+    // Index into volumes for each entry
+    entry_volumes: Vec<usize>,
+}
+
+impl Discovered {
+    /// Reads the entries on all of `volumes` and orders them together. loader.conf comes from the first ESP that has
+    /// one, as the specification says it's only looked for there
+    pub fn new(volumes: Vec<Volume>) -> Result<Self> {
+        let mut conf = None;
+        let mut all = Vec::new();
+        for (i, volume) in volumes.iter().enumerate() {
+            if conf.is_none() && volume.kind == VolumeKind::Esp {
+                conf = read_conf(&*volume.fs)?;
+            }
+            all.extend(entries(&*volume.fs)?.into_iter().map(|entry| (entry, i)));
+        }
+        all.sort_by(|(a, _), (b, _)| order(a, b));
+        let entry_volumes = all.iter().map(|&(_, i)| i).collect();
+        let menu = menu(&conf.unwrap_or_else(|| LoaderConf::parse("")), all.into_iter().map(|((entry, _), _)| entry).collect());
+        Ok(Discovered { volumes, menu, entry_volumes })
+    }
+
+    /// The volume an entry's files are on
+    pub fn volume(&self, entry: usize) -> Option<&Volume> {
+        self.volumes.get(*self.entry_volumes.get(entry)?)
+    }
+
+    /// Boots the menu's `entry`th entry from its volume
+    pub fn boot(&self, entry: usize) -> Result<ExitData> {
+        let volume = self.volume(entry).ok_or(EfiErrorKind::InvalidParameter)?;
+        self.menu.entries[entry].boot(&*volume.fs)
+    }
+}
+
+/// The entries on every ESP and XBOOTLDR partition
+pub fn discover() -> Result<Discovered> {
+    Discovered::new(volumes()?)
+}
+
+fn read_conf(fs: &dyn FileSystem) -> Result<Option<LoaderConf>> {
+    match fs.read(LOADER_CONF) {
+        Ok(data) => Ok(Some(LoaderConf::parse(&String::from_utf8_lossy(&data)))),
+        Err(e) if e.kind() == EfiErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn entries(fs: &dyn FileSystem) -> Result<Vec<(BootEntry, SortKey)>> {
+    let mut entries = Vec::new();
+    for file in files(fs, ENTRIES_DIR, ".conf")? {
+        let data = fs.read(&format!("{}/{}", ENTRIES_DIR, file))?;
+        if let Ok(entry) = parse(&file, &String::from_utf8_lossy(&data)) {
+            entries.push(entry);
+        }
+    }
+    // Type #2 entries, which sort like type #1 ones with the image's id as their sort-key
+    for file in files(fs, uki::DIR, ".efi")? {
+        let path = format!("/{}/{}", uki::DIR, file);
+        if let Ok(info) = uki::Info::read(&mut *fs.open(&path)?) {
+            let sort_key = info.os_release.get("IMAGE_ID").or_else(|| info.os_release.get("ID")).map(String::from);
+            entries.push((info.entry(&path), SortKey { sort_key, machine_id: String::new() }));
+        }
+    }
+    Ok(entries)
+}
+
+fn menu(conf: &LoaderConf, entries: Vec<BootEntry>) -> BootMenu {
+    let default = match conf.default {
+        Some(ref pattern) => entries.iter().position(|e| matches(pattern, &e.id) || matches(pattern, e.id.trim_end_matches(".conf"))),
+        None => None,
+    };
+    let default = default.or(if entries.is_empty() { None } else { Some(0) });
+    BootMenu { entries, default, timeout: conf.timeout }
+}
+
+// The names of the files in `dir` ending in `extension`, none if there's no such directory
+fn files(fs: &dyn FileSystem, dir: &str, extension: &str) -> Result<Vec<String>> {
+    if !fs.exists(dir) {
+        return Ok(Vec::new());
+    }
+    Ok(fs.read_dir(dir)?.into_iter()
+        .filter(|file| !file.metadata.is_dir && file.name.to_ascii_lowercase().ends_with(extension))
+        .map(|file| file.name)
+        .collect())
+}
+
+fn order(&(ref a, ref a_sort): &(BootEntry, SortKey), &(ref b, ref b_sort): &(BootEntry, SortKey)) -> Ordering {
+    match (&a_sort.sort_key, &b_sort.sort_key) {
+        (Some(a_key), Some(b_key)) => a_key.cmp(b_key)
+            .then_with(|| a_sort.machine_id.cmp(&b_sort.machine_id))
+            .then_with(|| compare_versions(b.version.as_ref().map_or("", |v| v), a.version.as_ref().map_or("", |v| v)))
+            .then_with(|| compare_versions(&b.id, &a.id)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => compare_versions(&b.id, &a.id),
+    }
+}
+
+// The `key value` lines of a file, skipping blanks and comments
+fn pairs(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.lines().filter_map(|line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut parts = line.splitn(2, |c: char| c.is_whitespace());
+        Some((parts.next()?, parts.next().unwrap_or("").trim()))
+    })
+}
+
+// Glob matching with `*` for any run of characters and `?` for any one
+fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None; // Where the last * was and how much of the text it had taken
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match backtrack {
+                Some((star, taken)) => {
+                    p = star + 1;
+                    t = taken + 1;
+                    backtrack = Some((star, taken + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{MemDisk, format_fat32};
+    use testing::mock;
+
+    #[test]
+    fn parses_entries() {
+        let entry = parse_entry("arch.conf", "# Arch\ntitle   Arch Linux\nlinux /vmlinuz-linux\ninitrd /intel-ucode.img\ninitrd  /initramfs-linux.img\noptions root=/dev/sda2\noptions rw quiet\n").unwrap();
+        assert_eq!(entry.title, "Arch Linux");
+        assert_eq!(entry.options, "root=/dev/sda2 rw quiet");
+        assert_eq!(entry.image, BootImage::Linux {
+            kernel: "/vmlinuz-linux".into(),
+            initrds: vec!["/intel-ucode.img".into(), "/initramfs-linux.img".into()],
+            devicetree: None,
+            overlays: Vec::new(),
+        });
+
+        assert_eq!(parse_entry("uki.conf", "efi /EFI/Linux/uki.efi\nversion 6.1").unwrap().title, "6.1");
+        assert_eq!(parse_entry("empty.conf", "title Nothing").unwrap_err().kind(), EfiErrorKind::LoadError);
+        assert_eq!(parse_entry("other.conf", "linux /vmlinuz\narchitecture not-ours").unwrap_err().kind(), EfiErrorKind::Unsupported);
+        assert!(matches("fedora-*", "fedora-6.10.conf") && matches("*-6.?.conf", "fedora-6.9.conf") && !matches("*-6.?.conf", "fedora-6.10.conf"));
+    }
+
+    #[test]
+    fn loads_and_orders_menu() {
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "ESP").unwrap();
+        fs.create_dir("loader").unwrap();
+        fs.create_dir("loader/entries").unwrap();
+        fs.write("loader/loader.conf", b"timeout 3\ndefault fedora-6.9*\nconsole-mode max\n").unwrap();
+        fs.write("loader/entries/fedora-6.9.conf", b"title Fedora\nversion 6.9\nlinux /6.9/linux\n").unwrap();
+        fs.write("loader/entries/fedora-6.10.conf", b"title Fedora\nversion 6.10\nlinux /6.10/linux\n").unwrap();
+        fs.write("loader/entries/rescue.conf", b"title Rescue\nlinux /rescue\n").unwrap();
+        fs.write("loader/entries/windows.conf", b"title Windows\nsort-key windows\nefi /EFI/Microsoft/Boot/bootmgfw.efi\n").unwrap();
+        fs.write("loader/entries/broken.conf", b"title Broken\n").unwrap();
+
+        let menu = load(&fs).unwrap();
+        let ids = menu.entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["windows.conf", "rescue.conf", "fedora-6.10.conf", "fedora-6.9.conf"]);
+        assert_eq!(menu.default_entry().unwrap().version.as_ref().unwrap(), "6.9");
+        assert_eq!(menu.timeout, Some(Duration::from_secs(3)));
+        assert_eq!(super::super::load(&fs).unwrap(), menu);
+    }
+
+    #[test]
+    fn merges_esp_and_xbootldr() {
+        mock::install();
+        let partition = |type_guid| Partition { type_guid, unique_guid: type_guid, first_lba: 2048, last_lba: 4095, attributes: 0, name: String::new() };
+        let xbootldr = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "BOOT").unwrap();
+        xbootldr.create_dir("loader").unwrap();
+        xbootldr.create_dir("loader/entries").unwrap();
+        xbootldr.write("loader/loader.conf", b"default rescue*\n").unwrap(); // Only the ESP's counts
+        xbootldr.write("loader/entries/linux-6.2.conf", b"version 6.2\nlinux /6.2/linux\n").unwrap();
+        let esp = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "ESP").unwrap();
+        esp.create_dir("loader").unwrap();
+        esp.create_dir("loader/entries").unwrap();
+        esp.write("loader/loader.conf", b"default linux-6.1.conf\n").unwrap();
+        esp.write("loader/entries/linux-6.1.conf", b"version 6.1\nlinux /6.1/linux\n").unwrap();
+        esp.write("loader/entries/rescue.conf", b"linux /rescue\n").unwrap();
+        let empty = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "EMPTY").unwrap();
+
+        let discovered = Discovered::new(vec![
+            Volume { kind: VolumeKind::Xbootldr, partition: partition(LINUX_EXTENDED_BOOT_GUID), fs: Box::new(xbootldr) },
+            Volume { kind: VolumeKind::Esp, partition: partition(EFI_PART_TYPE_EFI_SYSTEM_PART_GUID), fs: Box::new(empty) },
+            Volume { kind: VolumeKind::Esp, partition: partition(EFI_PART_TYPE_EFI_SYSTEM_PART_GUID), fs: Box::new(esp) },
+        ]).unwrap();
+        let ids = discovered.menu.entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["rescue.conf", "linux-6.2.conf", "linux-6.1.conf"]);
+        assert_eq!(discovered.menu.default, Some(2));
+        let kinds = (0..3).map(|i| discovered.volume(i).unwrap().kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [VolumeKind::Esp, VolumeKind::Xbootldr, VolumeKind::Esp]);
+        assert!(discovered.volume(3).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+// Boot loader configuration files.
+//
+// Reads the menus other boot loaders leave on the ESP so that the same entries can be offered and booted: Boot Loader
+// Specification entries along with systemd-boot's loader.conf (bls.rs), the unified kernel images it also lists
+// (uki.rs) and syslinux/extlinux configs (syslinux.rs). They give a BootMenu of BootEntry values, which know how to
+// load and start what they describe. Windows keeps its menu in a BCD store only its own boot manager reads, so
+// windows.rs just finds that and chain-loads it.
+
+pub mod bls;
+pub mod syslinux;
+pub mod uki;
+pub mod windows;
+
+use core::{cmp::Ordering, time::Duration};
+use fs::FileSystem;
+use fdt::Fdt;
+use image::{self, ExitData};
+use linux::{CmdlineBuilder, Linux};
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+
+/// What an entry boots. Paths are on the file system the configuration came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum BootImage {
+    Linux {
+        kernel: String,
+        /// Concatenated in this order
+        initrds: Vec<String>,
+        /// Installed in the configuration table in place of the firmware's own
+        devicetree: Option<String>,
+        /// Applied to the device tree, which is then required
+        overlays: Vec<String>,
+    },
+    /// Any other EFI application, e.g. a unified kernel image or another boot loader
+    Efi(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootEntry {
+    /// Identifies the entry within its menu: the file name for BLS, the label for syslinux
+    pub id: String,
+    pub title: String,
+    pub version: Option<String>,
+    pub image: BootImage,
+    /// The kernel command line or the EFI application's load options
+    pub options: String,
+}
+
+impl BootEntry {
+    /// Loads the entry's files from `fs` and boots it. Returns only if booting failed or the image exited back to us
+    pub fn boot(&self, fs: &dyn FileSystem) -> Result<ExitData> {
+        match self.image {
+            BootImage::Linux { ref kernel, ref initrds, ref devicetree, ref overlays } => {
+                let mut linux = Linux::new(fs.read(kernel)?)?;
+                for initrd in initrds {
+                    linux.initrd(fs.read(initrd)?);
+                }
+                linux.cmdline_from(&CmdlineBuilder::parse(&self.options)?)?;
+
+                match *devicetree {
+                    Some(ref path) => {
+                        let mut fdt = Fdt::parse(&fs.read(path)?)?;
+                        for overlay in overlays {
+                            fdt.apply_overlay(Fdt::parse(&fs.read(overlay)?)?)?;
+                        }
+                        fdt.install()?;
+                    },
+                    None if !overlays.is_empty() => return Err(EfiErrorKind::InvalidParameter.into()), // Nothing to apply them to
+                    None => {},
+                }
+                linux.boot()
+            },
+            BootImage::Efi(ref path) => {
+                let mut loaded_image = image::load_image_from_buffer(&fs.read(path)?)?;
+                if !self.options.is_empty() {
+                    loaded_image.set_load_options(&self.options)?;
+                }
+                image::start_image(&loaded_image)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootMenu {
+    /// In the order they should be shown
+    pub entries: Vec<BootEntry>,
+    /// Index into `entries` of the entry to boot if the user doesn't choose
+    pub default: Option<usize>,
+    /// How long to show the menu before booting the default. None waits for the user
+    pub timeout: Option<Duration>,
+}
+
+impl BootMenu {
+    pub fn default_entry(&self) -> Option<&BootEntry> {
+        self.entries.get(self.default?)
+    }
+
+    pub fn entry(&self, id: &str) -> Option<&BootEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+}
+
+/// Finds and reads whichever boot loader configuration `fs` has, preferring BLS entries over syslinux configs.
+/// Fails with NotFound if there is none
+pub fn load(fs: &dyn FileSystem) -> Result<BootMenu> {
+    if fs.exists(bls::ENTRIES_DIR) || fs.exists(uki::DIR) {
+        let menu = bls::load(fs)?;
+        if !menu.entries.is_empty() {
+            return Ok(menu);
+        }
+    }
+
+    match syslinux::CONFIG_PATHS.iter().find(|path| fs.exists(path)) {
+        Some(path) => syslinux::load(fs, path),
+        None => Err(EfiErrorKind::NotFound.into()),
+    }
+}
+
+// Compares versions the way people expect, with runs of digits compared as numbers: 5.10 is after 5.9
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (na, nb) = (digits(a), digits(b));
+                let (da, db) = (trim_zeroes(&a[..na]), trim_zeroes(&b[..nb]));
+                let order = da.len().cmp(&db.len()).then(da.cmp(db));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = &a[na..];
+                b = &b[nb..];
+            },
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            },
+        }
+    }
+}
+
+fn trim_zeroes(digits: &[u8]) -> &[u8] {
+    let zeroes = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeroes..]
+}
@@ -0,0 +1,265 @@
+// syslinux and extlinux configuration files (https://wiki.syslinux.org/wiki/index.php?title=Config).
+//
+// Only what describes entries is read: LABELs with their kernel, initrd, device tree and command line, MENU LABEL and
+// MENU DEFAULT, DEFAULT, TIMEOUT and INCLUDE. Anything for the menu's looks is ignored, as are entries that run
+// COM32 modules or chain to the local disk since those only make sense under syslinux.
+
+use super::{BootEntry, BootImage, BootMenu};
+use core::time::Duration;
+use fs::{FileSystem, components};
+use {Result, EfiErrorKind};
+use alloc::{string::{String, ToString}, vec::Vec};
+
+/// Where syslinux and extlinux look for their configuration, in the order they look
+pub const CONFIG_PATHS: &[&str] = &[
+    "boot/syslinux/syslinux.cfg",
+    "syslinux/syslinux.cfg",
+    "syslinux.cfg",
+    "EFI/syslinux/syslinux.cfg",
+    "boot/extlinux/extlinux.conf",
+    "extlinux/extlinux.conf",
+    "extlinux.conf",
+];
+
+// INCLUDEs nested deeper than this are assumed to be a loop
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Kernels with these extensions are syslinux modules or boot sectors rather than anything we can boot
+const SYSLINUX_ONLY: &[&str] = &[".c32", ".com", ".cbt", ".bs", ".bss", ".0"];
+
+#[derive(Default)]
+struct Label {
+    name: String,
+    title: Option<String>,
+    kernel: Option<String>,
+    initrds: Vec<String>,
+    devicetree: Option<String>,
+    overlays: Vec<String>,
+    append: Option<String>,
+    bootable: bool,
+}
+
+struct Parser<'a> {
+    fs: &'a dyn FileSystem,
+    dir: String, // Relative paths are relative to the directory of the top level file
+    labels: Vec<Label>,
+    default: Option<String>,
+    menu_default: Option<String>,
+    timeout: Option<Duration>,
+    append: Option<String>, // Given before any LABEL, for labels without their own
+}
+
+/// Reads the configuration at `path` along with anything it includes
+pub fn load(fs: &dyn FileSystem, path: &str) -> Result<BootMenu> {
+    let mut dir = components(path).collect::<Vec<_>>();
+    dir.pop();
+    let mut parser = Parser { fs, dir: dir.join("/"), labels: Vec::new(), default: None, menu_default: None, timeout: None, append: None };
+    parser.file(path, 0)?;
+    Ok(parser.finish())
+}
+
+impl<'a> Parser<'a> {
+    fn file(&mut self, path: &str, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let data = self.fs.read(path)?;
+        let text = String::from_utf8_lossy(&data);
+
+        let mut in_text = false;
+        for line in text.lines() {
+            let line = line.trim();
+            let (keyword, argument) = split(line);
+            let keyword = keyword.to_ascii_lowercase();
+            if in_text {
+                in_text = keyword != "endtext";
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match keyword.as_str() {
+                "label" => self.labels.push(Label { name: argument.to_string(), bootable: true, ..Label::default() }),
+                "default" | "ontimeout" => if self.default.is_none() || keyword == "default" {
+                    self.default = Some(argument.to_string());
+                },
+                "timeout" => self.timeout = argument.parse::<u64>().ok().filter(|&tenths| tenths > 0).map(|tenths| Duration::from_millis(tenths * 100)),
+                "include" => self.include(argument, depth)?,
+                "text" => in_text = true,
+                "menu" => {
+                    let (keyword, argument) = split(argument);
+                    match keyword.to_ascii_lowercase().as_str() {
+                        "include" => self.include(split(argument).0, depth)?,
+                        "label" => if let Some(label) = self.labels.last_mut() {
+                            label.title = Some(argument.replace('^', ""));
+                        },
+                        "default" => if let Some(label) = self.labels.last() {
+                            self.menu_default = Some(label.name.clone());
+                        },
+                        _ => {},
+                    }
+                },
+                "append" if self.labels.is_empty() => self.append = Some(argument.to_string()),
+                _ => {
+                    let dir = self.dir.clone();
+                    if let Some(label) = self.labels.last_mut() {
+                        label.directive(&keyword, argument, &dir);
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, path: &str, depth: usize) -> Result<()> {
+        let path = resolve(&self.dir, path);
+        self.file(&path, depth + 1)
+    }
+
+    fn finish(self) -> BootMenu {
+        let append = self.append;
+        let dir = self.dir;
+        let entries = self.labels.into_iter().filter_map(|label| {
+            if !label.bootable {
+                return None;
+            }
+            let kernel = label.kernel?;
+            let options = label.append.or_else(|| append.clone()).unwrap_or_default();
+            let (image, options) = if kernel.to_ascii_lowercase().ends_with(".efi") {
+                (BootImage::Efi(kernel), options)
+            } else {
+                let (initrds, options) = split_initrds(&options, label.initrds, &dir);
+                (BootImage::Linux { kernel, initrds, devicetree: label.devicetree, overlays: label.overlays }, options)
+            };
+            let id = label.name;
+            let title = label.title.unwrap_or_else(|| id.clone());
+            Some(BootEntry { id, title, version: None, image, options })
+        }).collect::<Vec<_>>();
+
+        // MENU DEFAULT wins over DEFAULT, which might also be a command line rather than a label
+        let default = self.menu_default.as_ref().or(self.default.as_ref())
+            .and_then(|name| entries.iter().position(|e| e.id == *name))
+            .or(if entries.is_empty() { None } else { Some(0) });
+        BootMenu { entries, default, timeout: self.timeout }
+    }
+}
+
+impl Label {
+    fn directive(&mut self, keyword: &str, argument: &str, dir: &str) {
+        match keyword {
+            "kernel" | "linux" => {
+                let (path, rest) = split(argument);
+                if SYSLINUX_ONLY.iter().any(|ext| path.to_ascii_lowercase().ends_with(ext)) {
+                    self.bootable = false;
+                    return;
+                }
+                self.kernel = Some(resolve(dir, path));
+                // Anything after the file name is a command line like APPEND's
+                if !rest.is_empty() && self.append.is_none() {
+                    self.append = Some(rest.to_string());
+                }
+            },
+            "initrd" => self.initrds.extend(argument.split(',').map(str::trim).filter(|p| !p.is_empty()).map(|p| resolve(dir, p))),
+            "append" => self.append = Some(if argument == "-" { String::new() } else { argument.to_string() }),
+            "fdt" | "devicetree" => self.devicetree = Some(resolve(dir, argument)),
+            "fdtoverlays" => self.overlays.extend(argument.split_whitespace().map(|p| resolve(dir, p))),
+            "localboot" | "com32" | "config" | "boot" | "bss" | "pxe" | "fdimage" | "comboot" => self.bootable = false,
+            _ => {},
+        }
+    }
+}
+
+// Pulls initrd=a,b out of a command line, since the initrds are loaded for the kernel rather than by it
+fn split_initrds(options: &str, mut initrds: Vec<String>, dir: &str) -> (Vec<String>, String) {
+    let mut rest = Vec::new();
+    for option in options.split_whitespace() {
+        match option.strip_prefix("initrd=") {
+            Some(paths) => initrds.extend(paths.split(',').filter(|p| !p.is_empty()).map(|p| resolve(dir, p))),
+            None => rest.push(option),
+        }
+    }
+    (initrds, rest.join(" "))
+}
+
+// Paths starting with a separator are from the root of the file system, the others from the configuration's directory
+fn resolve(dir: &str, path: &str) -> String {
+    if path.starts_with('/') || path.starts_with('\\') || dir.is_empty() {
+        components(path).collect::<Vec<_>>().join("/")
+    } else {
+        components(dir).chain(components(path)).collect::<Vec<_>>().join("/")
+    }
+}
+
+// The first word of a line and the rest of it
+fn split(line: &str) -> (&str, &str) {
+    let mut parts = line.splitn(2, |c: char| c.is_whitespace());
+    (parts.next().unwrap_or(""), parts.next().unwrap_or("").trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{MemDisk, format_fat32};
+    use testing::mock;
+
+    const CONFIG: &str = "\
+UI menu.c32
+PROMPT 0
+TIMEOUT 50
+DEFAULT linux
+APPEND console=ttyS0
+
+MENU TITLE Boot menu
+INCLUDE extra.cfg
+
+LABEL linux
+  MENU LABEL ^Linux
+  KERNEL vmlinuz
+  INITRD initrd.img,/shared/ucode.img
+  APPEND root=/dev/sda1 ro
+  TEXT HELP
+    KERNEL not-a-directive
+  ENDTEXT
+
+LABEL hdt
+  MENU LABEL Hardware info
+  COM32 hdt.c32
+
+LABEL shell
+  KERNEL /EFI/tools/shell.efi
+";
+
+    #[test]
+    fn loads_labels_and_includes() {
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "SYSLINUX").unwrap();
+        fs.create_dir("syslinux").unwrap();
+        fs.write("syslinux/syslinux.cfg", CONFIG.as_bytes()).unwrap();
+        fs.write("syslinux/extra.cfg", b"label rescue\n menu default\n linux rescue/vmlinuz initrd=rescue/initrd.img single\n fdt rescue/board.dtb\n").unwrap();
+
+        let menu = load(&fs, "syslinux/syslinux.cfg").unwrap();
+        let ids = menu.entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["rescue", "linux", "shell"]);
+        assert_eq!(menu.default_entry().unwrap().id, "rescue");
+        assert_eq!(menu.timeout, Some(Duration::from_secs(5)));
+
+        let linux = menu.entry("linux").unwrap();
+        assert_eq!((linux.title.as_str(), linux.options.as_str()), ("Linux", "root=/dev/sda1 ro"));
+        assert_eq!(linux.image, BootImage::Linux {
+            kernel: "syslinux/vmlinuz".into(),
+            initrds: vec!["syslinux/initrd.img".into(), "shared/ucode.img".into()],
+            devicetree: None,
+            overlays: Vec::new(),
+        });
+        assert_eq!(menu.entries[0].options, "single");
+        assert_eq!(menu.entries[0].image, BootImage::Linux {
+            kernel: "syslinux/rescue/vmlinuz".into(),
+            initrds: vec!["syslinux/rescue/initrd.img".into()],
+            devicetree: Some("syslinux/rescue/board.dtb".into()),
+            overlays: Vec::new(),
+        });
+        assert_eq!((menu.entries[2].image.clone(), menu.entries[2].options.as_str()), (BootImage::Efi("EFI/tools/shell.efi".into()), "console=ttyS0"));
+        assert_eq!(super::super::load(&fs).unwrap(), menu);
+    }
+}
@@ -0,0 +1,290 @@
+// Unified kernel images: one signed EFI binary (systemd's stub) carrying the kernel, initrd, command line and an
+// os-release in PE sections named .linux, .initrd, .cmdline and .osrel, with .uname, .dtb and .ucode when the builder
+// put them in. These are the Boot Loader Specification's type #2 entries, found in /EFI/Linux.
+//
+// A UKI can be booted two ways. Chain loading starts the stub, which checks nothing more and boots what's inside it,
+// so under Secure Boot the firmware verifies the whole image including the command line. Extracting takes the
+// sections out and boots them ourselves through linux::Linux, which works where the stub doesn't but bypasses the
+// image's signature: only the kernel itself is checked, if the firmware checks it at all.
+
+use super::{BootEntry, BootImage};
+use fs::{File, from_io_error};
+use fdt::Fdt;
+use image::{self, ExitData};
+use io::SeekFrom;
+use linux::{CmdlineBuilder, Linux};
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::{string::{String, ToString}, vec::Vec};
+
+/// Where type #2 entries live on the ESP
+pub const DIR: &str = "EFI/Linux";
+
+const PE_OFFSET: usize = 0x3c;
+// The PE signature and COFF file header
+const COFF_SIZE: usize = 24;
+const SECTION_SIZE: usize = 40;
+// The text sections read for a menu are small; anything bigger isn't what we think it is
+const MAX_TEXT_SECTION: usize = 64 * 1024;
+
+/// A section's place in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// How to boot a UKI
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// Start the image and let its stub boot the kernel
+    ChainLoad,
+    /// Boot the kernel, initrd and command line ourselves
+    Extract,
+}
+
+/// The os-release of the system the image boots: KEY=value lines, values optionally quoted
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OsRelease {
+    pub values: Vec<(String, String)>,
+}
+
+impl OsRelease {
+    pub fn parse(text: &str) -> Self {
+        let values = text.lines().filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            let unquoted = match value.chars().next() {
+                Some(q) if (q == '"' || q == '\'') && value.len() >= 2 && value.ends_with(q) => &value[1..value.len() - 1],
+                _ => value,
+            };
+            Some((key.to_string(), unquoted.replace("\\\"", "\"").replace("\\\\", "\\")))
+        }).collect();
+        OsRelease { values }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+/// What a menu shows about a UKI, read without loading the rest of it
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Info {
+    pub os_release: OsRelease,
+    pub cmdline: Option<String>,
+    /// The kernel's release, as `uname -r` would print it
+    pub uname: Option<String>,
+}
+
+impl Info {
+    /// Reads the headers and text sections of a UKI file. Fails with LoadError if it isn't one
+    pub fn read(file: &mut dyn File) -> Result<Self> {
+        let len = file.len() as usize;
+        let mut read = |offset: usize, size: usize| -> Result<Vec<u8>> {
+            if offset.checked_add(size).map_or(true, |end| end > len) {
+                return Err(EfiErrorKind::LoadError.into());
+            }
+            let mut buf = vec![0; size];
+            file.seek(SeekFrom::Start(offset as u64)).map_err(from_io_error)?;
+            file.read_exact(&mut buf).map_err(from_io_error)?;
+            Ok(buf)
+        };
+        let sections = read_sections(&mut read)?;
+        let mut text = |name: &str| -> Result<Option<String>> {
+            match sections.iter().find(|s| s.name == name) {
+                Some(s) if s.size <= MAX_TEXT_SECTION => Ok(Some(section_text(&read(s.offset, s.size)?))),
+                _ => Ok(None),
+            }
+        };
+        let os_release = text(".osrel")?.map(|t| OsRelease::parse(&t)).unwrap_or_default();
+        Ok(Info { os_release, cmdline: text(".cmdline")?, uname: text(".uname")? })
+    }
+
+    /// What systemd-boot calls it: PRETTY_NAME, else NAME, else the image or OS id
+    pub fn title(&self) -> Option<&str> {
+        let r = &self.os_release;
+        r.get("PRETTY_NAME").or_else(|| r.get("NAME")).or_else(|| r.get("IMAGE_ID")).or_else(|| r.get("ID"))
+    }
+
+    /// The kernel release, else the image or OS version
+    pub fn version(&self) -> Option<&str> {
+        let r = &self.os_release;
+        self.uname.as_deref().or_else(|| r.get("IMAGE_VERSION")).or_else(|| r.get("VERSION_ID"))
+    }
+
+    /// A menu entry that chain loads the image at `path`, with the file's name as the id
+    pub fn entry(&self, path: &str) -> BootEntry {
+        let id = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+        BootEntry {
+            id: id.to_string(),
+            title: self.title().unwrap_or(id).to_string(),
+            version: self.version().map(String::from),
+            image: BootImage::Efi(path.to_string()),
+            options: String::new(),
+        }
+    }
+}
+
+/// A whole UKI in memory
+#[derive(Debug, Clone)]
+pub struct Uki {
+    data: Vec<u8>,
+    sections: Vec<Section>,
+}
+
+impl Uki {
+    /// Fails with LoadError if it isn't a PE image with a .linux section
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        let sections = read_sections(&mut |offset, size| match data.get(offset..offset.saturating_add(size)) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(EfiErrorKind::LoadError.into()),
+        })?;
+        if sections.iter().any(|s| s.offset.checked_add(s.size).map_or(true, |end| end > data.len())) {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        Ok(Uki { data, sections })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn section(&self, name: &str) -> Option<&[u8]> {
+        self.sections.iter().find(|s| s.name == name).map(|s| &self.data[s.offset..s.offset + s.size])
+    }
+
+    pub fn info(&self) -> Info {
+        let text = |name: &str| self.section(name).map(section_text);
+        Info {
+            os_release: text(".osrel").map(|t| OsRelease::parse(&t)).unwrap_or_default(),
+            cmdline: text(".cmdline"),
+            uname: text(".uname"),
+        }
+    }
+
+    /// Boots the image. Returns only if booting failed or the kernel exited back to us
+    pub fn boot(&self, method: Method) -> Result<ExitData> {
+        match method {
+            Method::ChainLoad => image::start_image(&image::load_image_from_buffer(&self.data)?),
+            Method::Extract => {
+                let kernel = self.section(".linux").ok_or(EfiErrorKind::LoadError)?;
+                let mut linux = Linux::new(kernel.to_vec())?;
+                // Microcode has to come first for the kernel to find it early
+                for name in &[".ucode", ".initrd"] {
+                    if let Some(initrd) = self.section(name) {
+                        linux.initrd(initrd.to_vec());
+                    }
+                }
+                if let Some(cmdline) = self.info().cmdline {
+                    linux.cmdline_from(&CmdlineBuilder::parse(&cmdline)?)?;
+                }
+                if let Some(dtb) = self.section(".dtb") {
+                    Fdt::parse(dtb)?.install()?;
+                }
+                linux.boot()
+            },
+        }
+    }
+}
+
+// The section table, through `read` which gets `size` bytes at `offset`. Only images with a kernel in them count
+fn read_sections<F: FnMut(usize, usize) -> Result<Vec<u8>>>(read: &mut F) -> Result<Vec<Section>> {
+    let dos = read(0, PE_OFFSET + 4)?;
+    if &dos[..2] != b"MZ" {
+        return Err(EfiErrorKind::LoadError.into());
+    }
+    let pe = LittleEndian::read_u32(&dos[PE_OFFSET..]) as usize;
+    let coff = read(pe, COFF_SIZE)?;
+    if &coff[..4] != b"PE\0\0" {
+        return Err(EfiErrorKind::LoadError.into());
+    }
+    let count = LittleEndian::read_u16(&coff[6..]) as usize;
+    let optional_header_size = LittleEndian::read_u16(&coff[20..]) as usize;
+    let table = read(pe + COFF_SIZE + optional_header_size, count * SECTION_SIZE)?;
+    let sections = table.chunks(SECTION_SIZE).map(|s| {
+        let name = &s[..8];
+        let virtual_size = LittleEndian::read_u32(&s[8..]) as usize;
+        let raw_size = LittleEndian::read_u32(&s[16..]) as usize;
+        Section {
+            name: String::from_utf8_lossy(&name[..name.iter().position(|&b| b == 0).unwrap_or(8)]).into_owned(),
+            offset: LittleEndian::read_u32(&s[20..]) as usize,
+            // Raw data is padded to the file alignment; the virtual size is what's really there
+            size: if virtual_size == 0 { raw_size } else { virtual_size.min(raw_size) },
+        }
+    }).collect::<Vec<_>>();
+    if !sections.iter().any(|s| s.name == ".linux") {
+        return Err(EfiErrorKind::LoadError.into());
+    }
+    Ok(sections)
+}
+
+fn section_text(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{FileSystem, MemDisk, format_fat32};
+    use testing::mock;
+
+    // A PE image with just a section table, which is all that's looked at
+    fn build(sections: &[(&str, &[u8])]) -> Vec<u8> {
+        let pe = 0x80;
+        let table = pe + COFF_SIZE;
+        let mut data = vec![0u8; table + sections.len() * SECTION_SIZE];
+        data[..2].copy_from_slice(b"MZ");
+        LittleEndian::write_u32(&mut data[PE_OFFSET..], pe as u32);
+        data[pe..pe + 4].copy_from_slice(b"PE\0\0");
+        LittleEndian::write_u16(&mut data[pe + 6..], sections.len() as u16);
+        for (i, &(name, contents)) in sections.iter().enumerate() {
+            let offset = data.len();
+            let header = table + i * SECTION_SIZE;
+            data[header..header + name.len()].copy_from_slice(name.as_bytes());
+            LittleEndian::write_u32(&mut data[header + 8..], contents.len() as u32);
+            LittleEndian::write_u32(&mut data[header + 16..], (contents.len() as u32 + 0x1ff) & !0x1ff);
+            LittleEndian::write_u32(&mut data[header + 20..], offset as u32);
+            data.extend_from_slice(contents);
+            data.resize(offset + ((contents.len() + 0x1ff) & !0x1ff), 0);
+        }
+        data
+    }
+
+    #[test]
+    fn reads_sections_and_metadata() {
+        let osrel = b"NAME=Fedora\nPRETTY_NAME=\"Fedora Linux 40 (Workstation)\"\nVERSION_ID=40\n";
+        let data = build(&[(".osrel", osrel), (".cmdline", b"root=/dev/sda2 quiet\n\0"), (".uname", b"6.9.4-200.fc40.x86_64"), (".linux", b"kernel"), (".initrd", b"initrd")]);
+        let uki = Uki::parse(data.clone()).unwrap();
+        assert_eq!(uki.section(".linux"), Some(&b"kernel"[..]));
+        assert_eq!(uki.sections().iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), [".osrel", ".cmdline", ".uname", ".linux", ".initrd"]);
+        let info = uki.info();
+        assert_eq!(info.cmdline.as_ref().unwrap(), "root=/dev/sda2 quiet");
+        assert_eq!(info.title(), Some("Fedora Linux 40 (Workstation)"));
+        assert_eq!(info.version(), Some("6.9.4-200.fc40.x86_64"));
+        assert_eq!(Uki::parse(build(&[(".text", b"code")])).unwrap_err().kind(), EfiErrorKind::LoadError);
+        assert!(Uki::parse(data[..0x90].to_vec()).is_err());
+
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "ESP").unwrap();
+        fs.create_dir("EFI").unwrap();
+        fs.create_dir(DIR).unwrap();
+        fs.write("EFI/Linux/fedora.efi", &data).unwrap();
+        assert_eq!(Info::read(&mut *fs.open("EFI/Linux/fedora.efi").unwrap()).unwrap(), info);
+        let entry = info.entry("/EFI/Linux/fedora.efi");
+        assert_eq!((&entry.id[..], &entry.title[..]), ("fedora.efi", "Fedora Linux 40 (Workstation)"));
+        assert_eq!(entry.image, BootImage::Efi("/EFI/Linux/fedora.efi".into()));
+        assert_eq!(super::super::load(&fs).unwrap().entries, [entry]);
+    }
+}
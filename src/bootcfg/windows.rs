@@ -0,0 +1,84 @@
+// Chain-loading Windows Boot Manager. It finds its BCD store and the rest of Windows through the device it was
+// loaded from, so it's loaded by device path rather than from a buffer. It's given the optional data of the firmware's
+// own Boot#### for it, which is what it would have had had the firmware booted it; failing that, whatever load
+// options we were started with.
+
+use boot_services::{locate_handles, device_path};
+use device_path::{DevicePath, create_file_path_node, append_path};
+use ffi::{media::EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID, EFI_HANDLE};
+use fs::{FileSystem, SimpleFs};
+use image::{self, ExitData};
+use variables::boot_options;
+use {Result, EfiErrorKind};
+use alloc::vec::Vec;
+
+/// Where Windows puts its boot manager on the ESP
+pub const BOOT_MANAGER_PATH: &str = "\\EFI\\Microsoft\\Boot\\bootmgfw.efi";
+
+/// Windows Boot Manager on the volume it was found on
+pub struct BootManager {
+    handle: EFI_HANDLE,
+    path: DevicePath,
+    load_options: Vec<u8>,
+}
+
+impl BootManager {
+    /// Looks for the boot manager on the volumes the firmware has file systems for, in handle order. Fails with
+    /// NotFound if there isn't one
+    pub fn find() -> Result<Self> {
+        for handle in locate_handles(&EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID)? {
+            if SimpleFs::new(handle).map(|fs| fs.exists(BOOT_MANAGER_PATH)).unwrap_or(false) {
+                let volume = DevicePath::from_ptr(device_path(handle)?)?;
+                let path = append_path(&volume, &create_file_path_node(BOOT_MANAGER_PATH)?.into_path())?;
+                return Ok(Self { handle, path, load_options: load_options()? });
+            }
+        }
+        Err(EfiErrorKind::NotFound.into())
+    }
+
+    /// The volume it's on
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// The volume's device path with the boot manager's file path on the end
+    pub fn device_path(&self) -> &DevicePath {
+        &self.path
+    }
+
+    pub fn load_options(&self) -> &[u8] {
+        &self.load_options
+    }
+
+    /// Replaces the load options it's given
+    pub fn set_load_options(&mut self, options: Vec<u8>) -> &mut Self {
+        self.load_options = options;
+        self
+    }
+
+    /// Loads and starts it. Returns only if that failed or it exited back to us
+    pub fn boot(&self) -> Result<ExitData> {
+        let mut path = self.path.try_clone()?;
+        let mut loaded_image = image::load_image_from_path(&mut path)?;
+        if !self.load_options.is_empty() {
+            loaded_image.set_raw_load_options(&self.load_options)?;
+        }
+        image::start_image(&loaded_image)
+    }
+}
+
+/// Finds Windows Boot Manager and chain-loads it
+pub fn boot() -> Result<ExitData> {
+    BootManager::find()?.boot()
+}
+
+// The optional data of the first boot option for the boot manager, or else our own load options. Options give the
+// path with or without the leading backslash
+fn load_options() -> Result<Vec<u8>> {
+    let is_boot_manager = |name: &str| name.trim_start_matches('\\').eq_ignore_ascii_case(&BOOT_MANAGER_PATH[1..]);
+    let options = boot_options::boot_options().unwrap_or_default();
+    match options.into_iter().find(|(_, option)| option.file_name().map_or(false, |name| is_boot_manager(&name))) {
+        Some((_, option)) => Ok(option.optional_data),
+        None => image::raw_load_options(),
+    }
+}
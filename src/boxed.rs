@@ -16,7 +16,7 @@ impl<T> EfiBox<T> {
     #[inline]
     pub unsafe fn allocate(size: usize) -> Result<Self> {
         let mut ptr = ptr::null() as *const VOID;
-        let status = ((*system_table().BootServices).AllocatePool)(EFI_MEMORY_TYPE::EfiLoaderData, size, &mut ptr);
+        let status = traced!(((*system_table().BootServices).AllocatePool)(EFI_MEMORY_TYPE::EfiLoaderData, size, &mut ptr));
         match status {
             EFI_SUCCESS => {
                 let unique = Unique::new_unchecked(ptr as *mut T);
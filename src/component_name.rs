@@ -0,0 +1,203 @@
+// Human readable names for drivers and the controllers they manage, via EFI_COMPONENT_NAME2_PROTOCOL or, for older
+// drivers, EFI_COMPONENT_NAME_PROTOCOL. Names are in English if the driver has it and otherwise in the first
+// language it lists.
+
+use boot_services::{locate_handles, all_handles};
+use ffi::{
+    component_name::{EFI_COMPONENT_NAME2_PROTOCOL, EFI_COMPONENT_NAME2_PROTOCOL_GUID, EFI_COMPONENT_NAME_PROTOCOL_GUID},
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    EFI_HANDLE,
+    EFI_GUID,
+    CHAR8,
+    CHAR16,
+};
+use utils::as_slice;
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::{string::String, vec::Vec};
+use core::{mem, ptr, slice};
+
+/// The names a driver gives itself and its controllers
+pub struct ComponentName {
+    driver: EFI_HANDLE,
+    protocol: *const EFI_COMPONENT_NAME2_PROTOCOL,
+    language: Vec<u8>, // Nul terminated
+}
+
+impl ComponentName {
+    /// The names of the driver on the image handle. Fails with Unsupported if it doesn't name itself
+    pub fn new(driver: EFI_HANDLE) -> Result<Self> {
+        if let Ok(protocol) = open_protocol(driver, &EFI_COMPONENT_NAME2_PROTOCOL_GUID) {
+            return Self::with_protocol(driver, protocol, false);
+        }
+        Self::with_protocol(driver, open_protocol(driver, &EFI_COMPONENT_NAME_PROTOCOL_GUID)?, true)
+    }
+
+    fn with_protocol(driver: EFI_HANDLE, protocol: *const EFI_COMPONENT_NAME2_PROTOCOL, iso639: bool) -> Result<Self> {
+        let supported = unsafe { (*protocol).SupportedLanguages };
+        if supported.is_null() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        let supported = unsafe { ascii_slice(supported) };
+        let mut language = select_language(supported, iso639).ok_or(EfiErrorKind::Unsupported)?;
+        language.push(0);
+        Ok(Self { driver, protocol, language })
+    }
+
+    /// Every driver that names itself
+    pub fn all() -> Result<Vec<Self>> {
+        let mut drivers = locate_handles(&EFI_COMPONENT_NAME2_PROTOCOL_GUID)?;
+        for driver in locate_handles(&EFI_COMPONENT_NAME_PROTOCOL_GUID)? {
+            if !drivers.contains(&driver) {
+                drivers.push(driver);
+            }
+        }
+        Ok(drivers.into_iter().filter_map(|d| Self::new(d).ok()).collect())
+    }
+
+    pub fn driver(&self) -> EFI_HANDLE {
+        self.driver
+    }
+
+    /// The language the names are in, e.g. "en-US", or "eng" for older drivers
+    pub fn language(&self) -> &str {
+        core::str::from_utf8(&self.language[..self.language.len() - 1]).unwrap_or("")
+    }
+
+    pub fn driver_name(&self) -> Option<String> {
+        let mut name: *const CHAR16 = ptr::null();
+        let status = unsafe { traced!(((*self.protocol).GetDriverName)(self.protocol, self.language.as_ptr() as *const CHAR8, &mut name)) };
+        to_string(status, name)
+    }
+
+    /// The name of a controller the driver manages or, with `child`, of a child it made for it. None if it doesn't
+    /// manage the controller
+    pub fn controller_name(&self, controller: EFI_HANDLE, child: Option<EFI_HANDLE>) -> Option<String> {
+        let mut name: *const CHAR16 = ptr::null();
+        let status = unsafe { traced!(((*self.protocol).GetControllerName)(self.protocol, controller, child.unwrap_or(ptr::null()), self.language.as_ptr() as *const CHAR8, &mut name)) };
+        to_string(status, name)
+    }
+}
+
+/// The name of the driver on the image handle, if it names itself
+pub fn driver_name(driver: EFI_HANDLE) -> Option<String> {
+    ComponentName::new(driver).ok()?.driver_name()
+}
+
+/// The name of the controller, from whichever driver manages it, e.g. "Intel(R) PRO/1000 MT Network Connection".
+/// Children a bus driver made are also tried as children of each handle, since they're named by their parent's
+/// driver
+pub fn controller_name(controller: EFI_HANDLE) -> Option<String> {
+    let drivers = ComponentName::all().ok()?;
+    if let Some(name) = drivers.iter().filter_map(|d| d.controller_name(controller, None)).next() {
+        return Some(name);
+    }
+    let parents = all_handles().ok()?;
+    drivers.iter().filter_map(|d| parents.iter().filter_map(|&p| d.controller_name(p, Some(controller))).next()).next()
+}
+
+// English if it's supported, otherwise the first supported language. Version 2 lists RFC 4646 languages separated
+// by semicolons, version 1 packs ISO 639-2 ones three letters each
+fn select_language(supported: &[u8], iso639: bool) -> Option<Vec<u8>> {
+    let languages: Vec<&[u8]> = if iso639 {
+        supported.chunks(3).filter(|l| l.len() == 3).collect()
+    } else {
+        supported.split(|&c| c == b';').filter(|l| !l.is_empty()).collect()
+    };
+    let english = |l: &[u8]| if iso639 { l == b"eng" } else { l == b"en" || l.starts_with(b"en-") };
+    languages.iter().find(|l| **l == b"en-US")
+        .or_else(|| languages.iter().find(|l| english(l)))
+        .or_else(|| languages.first())
+        .map(|l| l.to_vec())
+}
+
+unsafe fn ascii_slice<'a>(s: *const CHAR8) -> &'a [u8] {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+    slice::from_raw_parts(s as *const u8, len)
+}
+
+fn to_string(status: ::ffi::EFI_STATUS, name: *const CHAR16) -> Option<String> {
+    if ::to_res((), status).is_err() || name.is_null() {
+        return None;
+    }
+    Some(String::from_utf16_lossy(unsafe { as_slice(name) }))
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> Result<*const T> {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL));
+    }
+    if protocol.is_null() {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    Ok(protocol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_STATUS, EFI_SUCCESS, EFI_UNSUPPORTED, VOID};
+    use testing::mock;
+    use utils::to_ucs2;
+    use alloc::boxed::Box;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static CONTROLLER: AtomicUsize = AtomicUsize::new(0);
+
+    fn leak_name(name: &str) -> *const CHAR16 {
+        Box::leak(to_ucs2(name).into_boxed_slice()).as_ptr()
+    }
+
+    // Only has French names
+    extern "efiapi" fn get_driver_name(_this: *const EFI_COMPONENT_NAME2_PROTOCOL, language: *const CHAR8, name: *mut *const CHAR16) -> EFI_STATUS {
+        if unsafe { ascii_slice(language) } != b"fr" {
+            return EFI_UNSUPPORTED;
+        }
+        unsafe { *name = leak_name("Pilote réseau") };
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_controller_name(_this: *const EFI_COMPONENT_NAME2_PROTOCOL, controller: EFI_HANDLE, child: EFI_HANDLE, _language: *const CHAR8, name: *mut *const CHAR16) -> EFI_STATUS {
+        if controller as usize != CONTROLLER.load(Ordering::SeqCst) {
+            return EFI_UNSUPPORTED;
+        }
+        unsafe { *name = leak_name(if child.is_null() { "Carte réseau" } else { "Port" }) };
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn names_drivers_and_controllers() {
+        assert_eq!(select_language(b"fr;en-GB;en-US", false), Some(b"en-US".to_vec()));
+        assert_eq!(select_language(b"fr;en", false), Some(b"en".to_vec()));
+        assert_eq!(select_language(b"fr;de", false), Some(b"fr".to_vec()));
+        assert_eq!(select_language(b"fraeng", true), Some(b"eng".to_vec()));
+        assert_eq!(select_language(b"", false), None);
+
+        mock::install();
+        let protocol = Box::leak(Box::new(EFI_COMPONENT_NAME2_PROTOCOL {
+            GetDriverName: get_driver_name,
+            GetControllerName: get_controller_name,
+            SupportedLanguages: b"fr\0".as_ptr() as *const CHAR8,
+        }));
+        let (mut driver, mut controller) = (ptr::null(), ptr::null());
+        unsafe {
+            let bs = system_table().BootServices;
+            ((*bs).InstallProtocolInterface)(&mut driver, &EFI_COMPONENT_NAME2_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, protocol as *const _ as *const VOID);
+            // Only handles with protocols are in the handle database
+            let controller_guid = EFI_GUID(0x3b1f5a40, 0x8c2d, 0x4e6a, [0xb1, 0x07, 0x5d, 0x9e, 0x62, 0x14, 0xaf, 0x33]);
+            ((*bs).InstallProtocolInterface)(&mut controller, &controller_guid, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, ptr::null());
+        }
+        CONTROLLER.store(controller as usize, Ordering::SeqCst);
+
+        let names = ComponentName::new(driver).unwrap();
+        assert_eq!(names.language(), "fr");
+        assert_eq!(driver_name(driver).as_deref(), Some("Pilote réseau"));
+        assert_eq!(controller_name(controller).as_deref(), Some("Carte réseau"));
+        assert_eq!(names.controller_name(controller, Some(driver)).as_deref(), Some("Port"));
+        assert_eq!(names.controller_name(driver, None), None);
+    }
+}
@@ -0,0 +1,306 @@
+// Checks that the firmware does what the spec says, for the parts of it this crate leans on.
+//
+// Firmware differs in ways that only show up on the machine: variable stores that accept a write they can't hold,
+// timers that run at half speed, network stacks that aren't loaded until something asks, GOP drivers whose Blt gets
+// the colors wrong. run() goes through a set of checks and reports what each found, to compare platforms with or to
+// attach to a bug report. Each check can also be run on its own.
+//
+// The checks are careful to leave things as they were: test variables are deleted again, and parts of the screen drawn
+// on are put back. A check that can't be done on this firmware, e.g. timing without a timestamp protocol, is skipped
+// rather than failed.
+
+use boot_services::locate_handles;
+use events::{Timer, TimerSchedule, TimerState, EventTpl, Wait};
+use ffi::{
+    runtime_services::{EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS},
+    tcp4::EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID,
+    EFI_GUID,
+};
+use firmware::{self, Revision};
+use graphics::GraphicsOutput;
+use json::{ToJson, Value};
+use net::{UdpSocket, SocketAddr, SocketAddrV4, Ipv4Addr};
+use services::RuntimeServices;
+use time::{self, Timestamp};
+use {system_table, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+use core::{fmt, time::Duration};
+
+const VENDOR: EFI_GUID = EFI_GUID(0x2f0c5e3a, 0x7d41, 0x4b9e, [0x91, 0x6a, 0x0c, 0x58, 0xe2, 0x4d, 0x13, 0xb7]);
+const TEST_VARIABLE: &str = "ConformanceTest";
+
+// Timers are only as good as the firmware's tick, which is usually 10ms
+const TIMER_INTERVAL: Duration = Duration::from_millis(100);
+const TIMER_SLACK: Duration = Duration::from_millis(20);
+
+const LOOPBACK_PORT: u16 = 40123;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Skip, // The check couldn't be done here
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Outcome::Pass => "PASS",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "SKIP",
+        })
+    }
+}
+
+/// What one check found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, outcome: Outcome, detail: String) -> Self {
+        Check { name, outcome, detail }
+    }
+}
+
+/// Every check, and which firmware they were run on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub vendor: String,
+    pub vendor_revision: u32,
+    pub revision: Revision,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Whether nothing failed. Skipped checks don't count against it
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.outcome != Outcome::Fail)
+    }
+
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.checks.iter().filter(|c| c.outcome == outcome).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} revision {:#x}, UEFI {}", self.vendor, self.vendor_revision, self.revision)?;
+        for check in &self.checks {
+            writeln!(f, "{} {}: {}", check.outcome, check.name, check.detail)?;
+        }
+        write!(f, "{} passed, {} failed, {} skipped", self.count(Outcome::Pass), self.count(Outcome::Fail), self.count(Outcome::Skip))
+    }
+}
+
+impl ToJson for Report {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("vendor", &self.vendor);
+        value.set("vendor_revision", &self.vendor_revision);
+        value.set("uefi", &format!("{}", self.revision));
+        value.set("checks", &Value::Array(self.checks.iter().map(|c| {
+            let mut check = Value::Object(Vec::new());
+            check.set("name", c.name);
+            check.set("outcome", &format!("{}", c.outcome));
+            check.set("detail", &c.detail);
+            check
+        }).collect()));
+        value
+    }
+}
+
+/// Runs every check
+pub fn run() -> Report {
+    Report {
+        vendor: firmware::vendor(),
+        vendor_revision: firmware::vendor_revision(),
+        revision: firmware::revision(),
+        checks: vec![variable_write_limits(), timer_accuracy(), stall_accuracy(), tcp4_service_binding(), udp4_loopback(), gop_blt()],
+    }
+}
+
+/// QueryVariableInfo gives sensible sizes, a small non-volatile variable reads back as written, and one bigger than
+/// the maximum variable size is refused rather than accepted or truncated
+pub fn variable_write_limits() -> Check {
+    const NAME: &str = "variable_write_limits";
+    let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+    let attributes = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS;
+    let storage = match rs.query_variable_info(attributes) {
+        Ok(storage) => storage,
+        Err(e) if e.kind() == EfiErrorKind::Unsupported => return Check::new(NAME, Outcome::Skip, "no QueryVariableInfo".into()),
+        Err(e) => return Check::new(NAME, Outcome::Fail, format!("QueryVariableInfo failed: {}", e)),
+    };
+    let sizes = format!("storage {} bytes, {} left, variables up to {} bytes", storage.maximum, storage.remaining, storage.max_variable_size);
+    if storage.remaining > storage.maximum || storage.max_variable_size > storage.maximum || storage.max_variable_size == 0 {
+        return Check::new(NAME, Outcome::Fail, format!("inconsistent sizes: {}", sizes));
+    }
+
+    let data: Vec<u8> = (0..64).collect();
+    if let Err(e) = rs.set_variable(TEST_VARIABLE, &VENDOR, attributes, &data) {
+        return Check::new(NAME, Outcome::Fail, format!("writing 64 bytes failed: {}", e));
+    }
+    let read = rs.get_variable(TEST_VARIABLE, &VENDOR);
+    let _ = rs.set_variable(TEST_VARIABLE, &VENDOR, attributes, &[]);
+    match read {
+        Ok((ref read, a)) if *read == data && a == attributes => (),
+        Ok(_) => return Check::new(NAME, Outcome::Fail, "a variable read back differently from how it was written".into()),
+        Err(e) => return Check::new(NAME, Outcome::Fail, format!("reading back failed: {}", e)),
+    }
+
+    // Not worth the memory on firmware that claims variables can be huge
+    if storage.max_variable_size > 1024 * 1024 {
+        return Check::new(NAME, Outcome::Pass, format!("{}, too big to try exceeding", sizes));
+    }
+    let too_big = vec![0xa5u8; storage.max_variable_size as usize + 1];
+    match rs.set_variable(TEST_VARIABLE, &VENDOR, attributes, &too_big) {
+        Ok(()) => {
+            let _ = rs.set_variable(TEST_VARIABLE, &VENDOR, attributes, &[]);
+            Check::new(NAME, Outcome::Fail, format!("{}, but a bigger one was accepted", sizes))
+        }
+        Err(e) => Check::new(NAME, Outcome::Pass, format!("{}, a bigger one refused with {:?}", sizes, e.kind())),
+    }
+}
+
+/// A relative timer goes off when it should, by the timestamp counter
+pub fn timer_accuracy() -> Check {
+    const NAME: &str = "timer_accuracy";
+    let start = match Timestamp::now() {
+        Ok(start) => start,
+        Err(_) => return Check::new(NAME, Outcome::Skip, "no timestamp protocol to measure with".into()),
+    };
+    let waited = Timer::create(TIMER_INTERVAL, TimerSchedule::Relative, TimerState::Active, EventTpl::Callback).and_then(|timer| timer.wait());
+    if let Err(e) = waited {
+        return Check::new(NAME, Outcome::Fail, format!("waiting for a timer failed: {}", e));
+    }
+    within(NAME, "a timer", TIMER_INTERVAL, start.elapsed())
+}
+
+/// Stall() waits as long as it's asked to, by the timestamp counter
+pub fn stall_accuracy() -> Check {
+    const NAME: &str = "stall_accuracy";
+    let start = match Timestamp::now() {
+        Ok(start) => start,
+        Err(_) => return Check::new(NAME, Outcome::Skip, "no timestamp protocol to measure with".into()),
+    };
+    if let Err(e) = time::sleep(TIMER_INTERVAL) {
+        return Check::new(NAME, Outcome::Fail, format!("Stall failed: {}", e));
+    }
+    within(NAME, "Stall", TIMER_INTERVAL, start.elapsed())
+}
+
+fn within(name: &'static str, what: &str, expected: Duration, elapsed: ::Result<Duration>) -> Check {
+    let elapsed = match elapsed {
+        Ok(elapsed) => elapsed,
+        Err(e) => return Check::new(name, Outcome::Fail, format!("reading the timestamp failed: {}", e)),
+    };
+    let detail = format!("{} of {}ms took {}ms", what, expected.as_millis(), elapsed.as_millis());
+    let outcome = if elapsed + TIMER_SLACK >= expected && elapsed <= expected + TIMER_SLACK { Outcome::Pass } else { Outcome::Fail };
+    Check::new(name, outcome, detail)
+}
+
+/// There's a TCP4 service binding, i.e. a NIC with the network stack loaded on it. Fails rather than skips since
+/// without one nothing in net works
+pub fn tcp4_service_binding() -> Check {
+    const NAME: &str = "tcp4_service_binding";
+    match locate_handles(&EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID) {
+        Ok(ref handles) if !handles.is_empty() => Check::new(NAME, Outcome::Pass, format!("{} NIC(s)", handles.len())),
+        Ok(_) => Check::new(NAME, Outcome::Fail, "none installed, is the network stack loaded and connected?".into()),
+        Err(e) => Check::new(NAME, Outcome::Fail, format!("looking for one failed: {}", e)),
+    }
+}
+
+/// A datagram sent to our own address comes back. Not every stack delivers to itself, so one that doesn't arrive is
+/// a skip; errors sending are failures
+pub fn udp4_loopback() -> Check {
+    const NAME: &str = "udp4_loopback";
+    let mut socket = match UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::unspecified(), LOOPBACK_PORT)) {
+        Ok(socket) => socket,
+        Err(e) => return Check::new(NAME, Outcome::Skip, format!("can't bind a UDP4 socket: {}", e)),
+    };
+    let local = match socket.local_addr() {
+        Ok(SocketAddr::V4(local)) if !local.ip().is_unspecified() => local,
+        _ => return Check::new(NAME, Outcome::Skip, "no station address".into()),
+    };
+    if let Err(e) = socket.set_read_timeout(Some(Duration::from_secs(1))) {
+        return Check::new(NAME, Outcome::Fail, format!("setting a read timeout failed: {}", e));
+    }
+    let sent = b"efi conformance loopback";
+    if let Err(e) = socket.send_to(sent, local) {
+        return Check::new(NAME, Outcome::Fail, format!("sending to {} failed: {}", local, e));
+    }
+    let mut buf = [0u8; 64];
+    match socket.recv(&mut buf) {
+        Ok(n) if buf[..n] == sent[..] => Check::new(NAME, Outcome::Pass, format!("through {}", local)),
+        Ok(n) => Check::new(NAME, Outcome::Fail, format!("got {} bytes back that weren't what was sent", n)),
+        Err(e) => Check::new(NAME, Outcome::Skip, format!("nothing came back ({:?}), the stack may not deliver to itself", e.kind())),
+    }
+}
+
+/// Blt fills, draws, copies and reads back pixels unchanged, on the first display. Only a corner of the screen is
+/// used, and it's put back afterwards
+pub fn gop_blt() -> Check {
+    const NAME: &str = "gop_blt";
+    const SIZE: u32 = 8;
+    let mut outputs = GraphicsOutput::all().unwrap_or_default();
+    if outputs.is_empty() {
+        return Check::new(NAME, Outcome::Skip, "no graphics output".into());
+    }
+    let gop = outputs.swap_remove(0);
+    let resolution = gop.current_mode().resolution;
+    if resolution.width < 2 * SIZE || resolution.height < SIZE {
+        return Check::new(NAME, Outcome::Skip, format!("the screen is only {}", resolution));
+    }
+    let saved = match gop.read(0, 0, 2 * SIZE, SIZE) {
+        Ok(saved) => saved,
+        Err(e) => return Check::new(NAME, Outcome::Fail, format!("reading the screen failed: {}", e)),
+    };
+    let result = blt_round_trips(&gop, SIZE);
+    let restored = gop.write(0, 0, 2 * SIZE, SIZE, &saved);
+    match (result, restored) {
+        (Ok(()), Ok(())) => Check::new(NAME, Outcome::Pass, format!("at {}", resolution)),
+        (Err(detail), _) => Check::new(NAME, Outcome::Fail, detail),
+        (Ok(()), Err(e)) => Check::new(NAME, Outcome::Fail, format!("putting the screen back failed: {}", e)),
+    }
+}
+
+fn blt_round_trips(gop: &GraphicsOutput, size: u32) -> core::result::Result<(), String> {
+    let failed = |what: &str, e: ::EfiError| format!("{} failed: {}", what, e);
+    let color = (0x12, 0x34, 0x56);
+    gop.fill(0, 0, size, size, color).map_err(|e| failed("fill", e))?;
+    if gop.read(0, 0, size, size).map_err(|e| failed("reading back", e))?.iter().any(|&p| p != color) {
+        return Err("a fill read back as other colors".into());
+    }
+
+    // Every pixel different, so swapped channels or rows show up
+    let pattern: Vec<(u8, u8, u8)> = (0..size * size).map(|i| ((i * 4) as u8, (255 - i * 4) as u8, (i * 7) as u8)).collect();
+    gop.write(0, 0, size, size, &pattern).map_err(|e| failed("drawing", e))?;
+    if gop.read(0, 0, size, size).map_err(|e| failed("reading back", e))? != pattern {
+        return Err("a drawing read back differently".into());
+    }
+    gop.copy((0, 0), (size, 0), size, size).map_err(|e| failed("copying", e))?;
+    if gop.read(size, 0, size, size).map_err(|e| failed("reading back", e))? != pattern {
+        return Err("a copy read back differently".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::mock;
+
+    #[test]
+    fn reports_on_the_mock() {
+        mock::install();
+        let report = run();
+        let outcome = |name| report.checks.iter().find(|c| c.name == name).unwrap().outcome;
+        assert_eq!(outcome("variable_write_limits"), Outcome::Pass, "{}", report);
+        assert_eq!(outcome("gop_blt"), Outcome::Skip);
+        assert_eq!(report.count(Outcome::Pass) + report.count(Outcome::Fail) + report.count(Outcome::Skip), 6);
+        assert!(::json::to_string(&report).contains("\"name\":\"variable_write_limits\",\"outcome\":\"PASS\""));
+    }
+}
@@ -34,6 +34,8 @@ use ffi::{
         EFI_BACKGROUND_LIGHTGRAY,
     }, 
     IsSuccess, 
+    EFI_EVENT,
+    EFI_SUCCESS,
     UINTN,
     TRUE,
     FALSE,
@@ -41,7 +43,12 @@ use ffi::{
 use core::{cmp, mem::transmute};
 use io::{self, Write, Cursor, BufRead, BufReader, LineWriter};
 use ::Result;
+use EfiErrorKind;
 use system_table;
+use keyboard;
+use scrollback;
+use mux;
+use graphics::GraphicsOutput;
 use TextInputProcolPtr;
 use alloc::{vec::Vec, string::String, str, fmt};
 
@@ -136,6 +143,7 @@ impl Console {
         unsafe {
             ret_on_err!(((*(*self).output).SetCursorPosition)(self.output, pos.col as usize, pos.row as usize));
         }
+        mux::set_cursor_pos(pos.row, pos.col);
 
         Ok(())
     }
@@ -160,6 +168,7 @@ impl Console {
         unsafe {
             ret_on_err!(((*(*self).output).ClearScreen)(self.output));
         }
+        mux::clear_screen();
 
         Ok(())
     }
@@ -179,6 +188,73 @@ impl Console {
         Ok(())
     }
 
+    /// Columns and rows of a text mode. Fails with Unsupported for modes below max_supported_mode() that the current
+    /// graphics mode is too small for
+    pub fn query_mode(&self, mode_number: u32) -> Result<(usize, usize)> {
+        let mut cols: UINTN = 0;
+        let mut rows: UINTN = 0;
+        unsafe {
+            ret_on_err!(((*self.output).QueryMode)(self.output, mode_number as usize, &mut cols, &mut rows));
+        }
+
+        Ok((cols, rows))
+    }
+
+    /// Columns and rows of the current text mode
+    pub fn size(&self) -> Result<(usize, usize)> {
+        let mode_number = unsafe { (*(*self.output).Mode).Mode };
+        self.query_mode(mode_number as u32)
+    }
+
+    /// Waits for a key press and returns it without echoing it. Keys that don't type anything, like the arrows and
+    /// PgUp/PgDn, have a UnicodeChar of 0 and one of the SCAN_* codes
+    pub fn read_key(&self) -> Result<EFI_INPUT_KEY> {
+        let event = match self.input {
+            TextInputProcolPtr::Input(input) => unsafe { (*input).WaitForKey },
+            TextInputProcolPtr::InputEx(input_ex) => unsafe { (*input_ex).WaitForKeyEx },
+        };
+        if let Some(key) = self.wait_for_key(event)? {
+            return Ok(key);
+        }
+
+        match self.input {
+            TextInputProcolPtr::Input(input) => {
+                let mut key = EFI_INPUT_KEY::default();
+                unsafe {
+                    ret_on_err!(((*input).ReadKeyStroke)(input, &mut key));
+                }
+                Ok(key)
+            }
+            TextInputProcolPtr::InputEx(input_ex) => {
+                let mut key_data = EFI_KEY_DATA::default();
+                unsafe {
+                    ret_on_err!(((*input_ex).ReadKeyStrokeEx)(input_ex, &mut key_data));
+                }
+                Ok(key_data.Key)
+            }
+        }
+    }
+
+    /// Switches to the text mode with the most characters and returns its columns and rows
+    pub fn set_best_mode(&mut self) -> Result<(usize, usize)> {
+        let mut best = None;
+        for mode_number in 0..self.max_supported_mode() {
+            if let Ok((cols, rows)) = self.query_mode(mode_number) {
+                if best.map_or(true, |(_, c, r)| cols * rows > c * r) {
+                    best = Some((mode_number, cols, rows));
+                }
+            }
+        }
+
+        match best {
+            Some((mode_number, cols, rows)) => {
+                self.set_mode(mode_number)?;
+                Ok((cols, rows))
+            }
+            None => Err(EfiErrorKind::Unsupported.into()),
+        }
+    }
+
     pub fn fore_color(&mut self) -> ForeColor {
         let attribute = unsafe { (*(*(*self).output).Mode).Attribute } as UINTN; // TODO: Cast should be safe on patforms with 32 and 64 ptr widths. Do we need to worry about other platforms?
         let fore_color_num = attribute & 0b1111; // Bits 0..3 are fore color, 4..6 are back color
@@ -226,6 +302,13 @@ impl Console {
     }
 
     fn write_to_efi(&self, buf: &[u16]) -> Result<()> {
+        if mux::any_enabled() {
+            let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+            mux::write(&String::from_utf16_lossy(&buf[..len]));
+            if !mux::local_enabled() {
+                return Ok(());
+            }
+        }
         unsafe {
             let (ptr, _) = to_ptr(buf);
             ret_on_err!(((*(*self).output).OutputString)(self.output, ptr));
@@ -233,6 +316,27 @@ impl Console {
         }
     }
 
+    // Waits for the local keyboard's `event`, returning None once it's signalled, or for a key from one of the mux's
+    // sinks if any are on
+    fn wait_for_key(&self, event: EFI_EVENT) -> Result<Option<EFI_INPUT_KEY>> {
+        let bs = system_table().BootServices;
+        while mux::any_enabled() {
+            if mux::local_enabled() && unsafe { traced!(((*bs).CheckEvent)(event)) } == EFI_SUCCESS {
+                return Ok(None);
+            }
+            if let Some(key) = mux::poll_key() {
+                return Ok(Some(key));
+            }
+        }
+
+        let mut evt_index: UINTN = 0;
+        let mut evt_list = [event; 1];
+        unsafe {
+            ret_on_err!(((*bs).WaitForEvent)(evt_list.len(), evt_list.as_mut_ptr(), &mut evt_index));
+        }
+        Ok(None)
+    }
+
     fn read_from_efi(&self, buf: &mut [u16]) -> Result<usize> {
         match self.input {
             TextInputProcolPtr::Input(input) => self.read_from_efi_input(buf, input),
@@ -244,21 +348,18 @@ impl Console {
     fn read_from_efi_input_ex(&self, buf: &mut [u16], input_ex: *mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL) -> Result<usize> {
         let mut bytes_read = 0;
 
-        let mut evt_index: UINTN = 0;
         let mut key_data = EFI_KEY_DATA::default();
-        let mut evt_list = unsafe { [(*input_ex).WaitForKeyEx; 1] };
+        let event = unsafe { (*input_ex).WaitForKeyEx };
 
         while bytes_read < buf.len() {
-            // TODO: For some reason we can't use ret_on_err here. Why?
-            let status = unsafe { ((*system_table().BootServices).WaitForEvent)(evt_list.len(), evt_list.as_mut_ptr(), &mut evt_index) };
-            if !IsSuccess(status) {
-                return Err(status.into()); // TODO: Can we send some error text too with such errors
-            }
-
-            // TODO: For some reason we can't use ret_on_err here. Why?
-            let status = unsafe { ((*input_ex).ReadKeyStrokeEx)(input_ex, &mut key_data) };
-            if !IsSuccess(status) {
-                return Err(status.into()); // TODO: Can we send some error text too with such errors
+            if let Some(key) = self.wait_for_key(event)? {
+                key_data = EFI_KEY_DATA { Key: key, ..EFI_KEY_DATA::default() };
+            } else {
+                // TODO: For some reason we can't use ret_on_err here. Why?
+                let status = unsafe { traced!(((*input_ex).ReadKeyStrokeEx)(input_ex, &mut key_data)) };
+                if !IsSuccess(status) {
+                    return Err(status.into()); // TODO: Can we send some error text too with such errors
+                }
             }
 
             fn is_ctr_z(key_data: &EFI_KEY_DATA) -> bool {
@@ -280,6 +381,7 @@ impl Console {
                         }
                     },
                     c => {
+                        let c = keyboard::remap(c);
                         if is_ctr_z(&key_data) {
                             break;
                         } else {
@@ -308,21 +410,18 @@ impl Console {
     fn read_from_efi_input(&self, buf: &mut [u16], input: *mut EFI_SIMPLE_TEXT_INPUT_PROTOCOL) -> Result<usize> {
         let mut bytes_read = 0;
 
-        let mut evt_index: UINTN = 0;
         let mut key_data = EFI_INPUT_KEY::default();
-        let mut evt_list = unsafe { [(*input).WaitForKey; 1] };
+        let event = unsafe { (*input).WaitForKey };
 
         while bytes_read < buf.len() {
-            // TODO: For some reason we can't use ret_on_err here. Why?
-            let status = unsafe { ((*system_table().BootServices).WaitForEvent)(evt_list.len(), evt_list.as_mut_ptr(), &mut evt_index) };
-            if !IsSuccess(status) {
-                return Err(status.into()); // TODO: Can we send some error text too with such errors
-            }
-
-            // TODO: For some reason we can't use ret_on_err here. Why?
-            let status = unsafe { ((*input).ReadKeyStroke)(input, &mut key_data) };
-            if !IsSuccess(status) {
-                return Err(status.into()); // TODO: Can we send some error text too with such errors
+            if let Some(key) = self.wait_for_key(event)? {
+                key_data = key;
+            } else {
+                // TODO: For some reason we can't use ret_on_err here. Why?
+                let status = unsafe { traced!(((*input).ReadKeyStroke)(input, &mut key_data)) };
+                if !IsSuccess(status) {
+                    return Err(status.into()); // TODO: Can we send some error text too with such errors
+                }
             }
 
             if key_data.UnicodeChar != 0 { // != 0 means it's a printable unicode char
@@ -338,6 +437,7 @@ impl Console {
                         }
                     },
                     c => {
+                        let c = keyboard::remap(c);
                         buf[bytes_read] = c;
                         bytes_read += 1;
 
@@ -395,6 +495,8 @@ impl io::Write for Console {
 
         utf16_buf.push(0); // Appending the null terminator
 
+        scrollback::record(utf8_buf);
+
         self.write_to_efi(&utf16_buf)
             .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to write to EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL"))?; // TODO: Don't swallaow EFI status like this. Error handling in this whole crate needs fixing
 
@@ -477,6 +579,15 @@ pub fn console() -> Console {
         .expect("failed to create system table").console()
 }
 
+/// Puts every display in its best graphics mode (see GraphicsOutput::set_best_mode) and then the console in the text
+/// mode with the most characters. The graphics mode goes first since it decides which text modes there are
+pub fn set_best_mode() -> Result<(usize, usize)> {
+    for output in GraphicsOutput::all()? {
+        output.set_best_mode(&[])?;
+    }
+    console().set_best_mode()
+}
+
 // TODO: Remove this uncessary SystemTable::new() business
 // Do we need this SystemTable type?
 pub fn stdin() -> StdIn {
@@ -498,9 +609,9 @@ macro_rules! print {
     ($($arg:tt)*) => ($crate::console::print_args(format_args!($($arg)*)));
 }
 
-// TODO: Call to stdout() creates a new StdOut obj everytime. Remove this extravagance.
+// Goes through firmware() so that output can be captured when running against a fake
 pub fn print_args(args: fmt::Arguments) {
-    return stdout().write_fmt(args).expect("Failed to write to stdout")
+    return ::firmware::firmware().write_console(args).expect("Failed to write to stdout")
 }
 
 
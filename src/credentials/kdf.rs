@@ -0,0 +1,427 @@
+// Turning passwords into keys. PBKDF2 is what LUKS1, sedutil and most older formats use; Argon2 is what LUKS2 and
+// anything new does, since it needs memory as well as time and so is much harder to guess passwords against on GPUs.
+//
+// Argon2 is RFC 9106, version 0x13, in all three variants, with lanes filled one after another since there are no
+// threads to fill them at once: the result is the same, it just takes as long as the lanes put together.
+
+use {Result, EfiErrorKind};
+use hash::{Hash, Hmac, Sha256};
+use super::zeroize;
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian, BigEndian};
+
+/// PBKDF2 (RFC 8018) with HMAC over any of the hashes, filling `out`
+pub fn pbkdf2<H: Hash>(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let keyed = Hmac::<H>::new(password);
+    for (i, chunk) in out.chunks_mut(H::LEN).enumerate() {
+        let mut index = [0; 4];
+        BigEndian::write_u32(&mut index, i as u32 + 1);
+        let mut hmac = keyed.clone();
+        hmac.update(salt);
+        hmac.update(&index);
+        let mut u = hmac.finish();
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut hmac = keyed.clone();
+            hmac.update(u.as_ref());
+            u = hmac.finish();
+            for (t, u) in t.as_mut().iter_mut().zip(u.as_ref().iter()) {
+                *t ^= u;
+            }
+        }
+        chunk.copy_from_slice(&t.as_ref()[..chunk.len()]);
+        zeroize(u.as_mut());
+        zeroize(t.as_mut());
+    }
+}
+
+/// PBKDF2 with HMAC-SHA-256
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    pbkdf2::<Sha256>(password, salt, iterations, out)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Variant {
+    /// Data dependent memory access: strongest against GPUs, but leaks through side channels
+    D,
+    /// Data independent memory access
+    I,
+    /// I for the first half of the first pass, then D. What RFC 9106 recommends
+    Id,
+}
+
+/// Argon2 parameters
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Argon2 {
+    pub variant: Variant,
+    /// In KiB. Rounded down to a multiple of four times the lanes, and at least eight times the lanes
+    pub memory: u32,
+    pub iterations: u32,
+    pub lanes: u32,
+}
+
+const VERSION: u32 = 0x13;
+const BLOCK_WORDS: usize = 128; // A block is 1 KiB
+const SLICES: usize = 4;
+
+type Block = [u64; BLOCK_WORDS];
+
+impl Argon2 {
+    pub fn new(variant: Variant, memory: u32, iterations: u32, lanes: u32) -> Self {
+        Argon2 { variant, memory, iterations, lanes }
+    }
+
+    /// Fills `out` with the key derived from `password` and `salt`. InvalidParameter if the parameters are out of range
+    pub fn derive(&self, password: &[u8], salt: &[u8], out: &mut [u8]) -> Result<()> {
+        self.derive_keyed(password, salt, &[], &[], out)
+    }
+
+    /// The same with a secret key and associated data, which hardly anything uses
+    pub fn derive_keyed(&self, password: &[u8], salt: &[u8], secret: &[u8], data: &[u8], out: &mut [u8]) -> Result<()> {
+        let lanes = self.lanes as usize;
+        if lanes == 0 || lanes > 0xFF_FFFF || self.iterations == 0 || self.memory < 8 * self.lanes || out.len() < 4 || salt.len() < 8 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let segment = self.memory as usize / (SLICES * lanes);
+        let lane_len = segment * SLICES;
+        let mut memory: Vec<Block> = vec![[0; BLOCK_WORDS]; lane_len * lanes];
+
+        let mut h0 = Blake2b::new(64);
+        for n in &[self.lanes, out.len() as u32, self.memory, self.iterations, VERSION, self.variant as u32] {
+            h0.update(&n.to_le_bytes());
+        }
+        for input in &[password, salt, secret, data] {
+            h0.update(&(input.len() as u32).to_le_bytes());
+            h0.update(input);
+        }
+        let mut seed = [0u8; 72];
+        seed[..64].copy_from_slice(&h0.finish());
+
+        let mut bytes = [0u8; 1024];
+        for lane in 0..lanes {
+            for column in 0..2 {
+                LittleEndian::write_u32(&mut seed[64..], column as u32);
+                LittleEndian::write_u32(&mut seed[68..], lane as u32);
+                long_hash(&seed, &mut bytes);
+                LittleEndian::read_u64_into(&bytes, &mut memory[lane * lane_len + column]);
+            }
+        }
+
+        let fill = Fill { argon2: self, lanes, segment, lane_len, blocks: lane_len * lanes };
+        for pass in 0..self.iterations as usize {
+            for slice in 0..SLICES {
+                for lane in 0..lanes {
+                    fill.segment(&mut memory, pass, slice, lane);
+                }
+            }
+        }
+
+        let mut last = memory[lane_len - 1];
+        for lane in 1..lanes {
+            xor(&mut last, &memory[lane * lane_len + lane_len - 1]);
+        }
+        LittleEndian::write_u64_into(&last, &mut bytes);
+        long_hash(&bytes, out);
+
+        zeroize(&mut seed);
+        zeroize(&mut bytes);
+        for block in &mut memory {
+            zeroize_block(block);
+        }
+        zeroize_block(&mut last);
+        Ok(())
+    }
+}
+
+struct Fill<'a> {
+    argon2: &'a Argon2,
+    lanes: usize,
+    segment: usize,
+    lane_len: usize,
+    blocks: usize,
+}
+
+impl<'a> Fill<'a> {
+    fn segment(&self, memory: &mut [Block], pass: usize, slice: usize, lane: usize) {
+        let independent = self.argon2.variant == Variant::I || (self.argon2.variant == Variant::Id && pass == 0 && slice < SLICES / 2);
+        let mut input = [0u64; BLOCK_WORDS];
+        let mut addresses = [0u64; BLOCK_WORDS];
+        if independent {
+            let values = [pass, lane, slice, self.blocks, self.argon2.iterations as usize, self.argon2.variant as usize];
+            for (word, value) in input.iter_mut().zip(values.iter()) {
+                *word = *value as u64;
+            }
+        }
+        let start = if pass == 0 && slice == 0 {
+            if independent {
+                next_addresses(&mut input, &mut addresses);
+            }
+            2 // The first two blocks of each lane come from the seed
+        } else {
+            0
+        };
+
+        for index in start..self.segment {
+            let current = lane * self.lane_len + slice * self.segment + index;
+            let previous = if current % self.lane_len == 0 { current + self.lane_len - 1 } else { current - 1 };
+            let random = if independent {
+                if index % BLOCK_WORDS == 0 {
+                    next_addresses(&mut input, &mut addresses);
+                }
+                addresses[index % BLOCK_WORDS]
+            } else {
+                memory[previous][0]
+            };
+            let ref_lane = if pass == 0 && slice == 0 { lane } else { (random >> 32) as usize % self.lanes };
+            let ref_index = self.reference(pass, slice, index, random as u32, ref_lane == lane);
+            let reference = memory[ref_lane * self.lane_len + ref_index];
+            let previous = memory[previous];
+            compress(&previous, &reference, &mut memory[current], pass > 0);
+        }
+    }
+
+    // Which block in the reference lane to mix in, out of those already filled and not in the segment being filled
+    // in another lane
+    fn reference(&self, pass: usize, slice: usize, index: usize, random: u32, same_lane: bool) -> usize {
+        let area = (if pass == 0 {
+            if slice == 0 {
+                index - 1
+            } else if same_lane {
+                slice * self.segment + index - 1
+            } else {
+                slice * self.segment - if index == 0 { 1 } else { 0 }
+            }
+        } else if same_lane {
+            self.lane_len - self.segment + index - 1
+        } else {
+            self.lane_len - self.segment - if index == 0 { 1 } else { 0 }
+        }) as u64;
+        let x = (random as u64 * random as u64) >> 32;
+        let relative = area - 1 - ((area * x) >> 32);
+        let start = if pass != 0 && slice != SLICES - 1 { (slice + 1) * self.segment } else { 0 };
+        (start + relative as usize) % self.lane_len
+    }
+}
+
+fn next_addresses(input: &mut Block, addresses: &mut Block) {
+    input[6] += 1;
+    let zero = [0u64; BLOCK_WORDS];
+    let mut once = [0u64; BLOCK_WORDS];
+    compress(&zero, input, &mut once, false);
+    compress(&zero, &once, addresses, false);
+}
+
+// G: the BLAKE2b round run over the rows and then the columns of the XOR of two blocks, XORed back in. With
+// `accumulate` the result is XORed into what `out` had, as passes after the first do
+fn compress(x: &Block, y: &Block, out: &mut Block, accumulate: bool) {
+    let mut r = *x;
+    xor(&mut r, y);
+    let mut z = r;
+    if accumulate {
+        xor(&mut z, out);
+    }
+    for row in 0..8 {
+        let mut v = [0usize; 16];
+        for (i, v) in v.iter_mut().enumerate() {
+            *v = 16 * row + i;
+        }
+        permute(&mut r, &v);
+    }
+    for column in 0..8 {
+        let mut v = [0usize; 16];
+        for (i, v) in v.iter_mut().enumerate() {
+            *v = 2 * column + (i % 2) + 16 * (i / 2);
+        }
+        permute(&mut r, &v);
+    }
+    xor(&mut z, &r);
+    *out = z;
+}
+
+fn permute(b: &mut Block, v: &[usize; 16]) {
+    for &(a, b_, c, d) in &[(0, 4, 8, 12), (1, 5, 9, 13), (2, 6, 10, 14), (3, 7, 11, 15),
+        (0, 5, 10, 15), (1, 6, 11, 12), (2, 7, 8, 13), (3, 4, 9, 14)] {
+        let (a, b_, c, d) = (v[a], v[b_], v[c], v[d]);
+        let mix = |x: u64, y: u64| x.wrapping_add(y).wrapping_add(2u64.wrapping_mul(x & 0xFFFF_FFFF).wrapping_mul(y & 0xFFFF_FFFF));
+        b[a] = mix(b[a], b[b_]);
+        b[d] = (b[d] ^ b[a]).rotate_right(32);
+        b[c] = mix(b[c], b[d]);
+        b[b_] = (b[b_] ^ b[c]).rotate_right(24);
+        b[a] = mix(b[a], b[b_]);
+        b[d] = (b[d] ^ b[a]).rotate_right(16);
+        b[c] = mix(b[c], b[d]);
+        b[b_] = (b[b_] ^ b[c]).rotate_right(63);
+    }
+}
+
+fn xor(a: &mut Block, b: &Block) {
+    for (a, b) in a.iter_mut().zip(b.iter()) {
+        *a ^= b;
+    }
+}
+
+fn zeroize_block(block: &mut Block) {
+    for word in block.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+}
+
+// H': BLAKE2b stretched to any length, by chaining 64 byte hashes and keeping the first half of each
+fn long_hash(input: &[u8], out: &mut [u8]) {
+    let len = (out.len() as u32).to_le_bytes();
+    if out.len() <= 64 {
+        let mut hash = Blake2b::new(out.len());
+        hash.update(&len);
+        hash.update(input);
+        out.copy_from_slice(&hash.finish()[..out.len()]);
+        return;
+    }
+    let mut hash = Blake2b::new(64);
+    hash.update(&len);
+    hash.update(input);
+    let mut v = hash.finish();
+    let mut written = 0;
+    loop {
+        out[written..written + 32].copy_from_slice(&v[..32]);
+        written += 32;
+        let rest = out.len() - written;
+        let mut hash = Blake2b::new(core::cmp::min(rest, 64));
+        hash.update(&v);
+        v = hash.finish();
+        if rest <= 64 {
+            out[written..].copy_from_slice(&v[..rest]);
+            break;
+        }
+    }
+    zeroize(&mut v);
+}
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6A09_E667_F3BC_C908, 0xBB67_AE85_84CA_A73B, 0x3C6E_F372_FE94_F82B, 0xA54F_F53A_5F1D_36F1,
+    0x510E_527F_ADE6_82D1, 0x9B05_688C_2B3E_6C1F, 0x1F83_D9AB_FB41_BD6B, 0x5BE0_CD19_137E_2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+// BLAKE2b (RFC 7693) without a key, which is all Argon2 needs
+struct Blake2b {
+    state: [u64; 8],
+    block: [u8; 128],
+    block_len: usize,
+    len: u64,
+    out_len: usize,
+}
+
+impl Blake2b {
+    fn new(out_len: usize) -> Self {
+        let mut state = BLAKE2B_IV;
+        state[0] ^= 0x0101_0000 ^ out_len as u64;
+        Blake2b { state, block: [0; 128], block_len: 0, len: 0, out_len }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block is compressed differently, so a full one waits until there's more after it
+            if self.block_len == self.block.len() {
+                self.len += self.block.len() as u64;
+                let block = self.block;
+                self.compress(&block, false);
+                self.block_len = 0;
+            }
+            let n = core::cmp::min(data.len(), self.block.len() - self.block_len);
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+        }
+    }
+
+    fn finish(mut self) -> [u8; 64] {
+        self.len += self.block_len as u64;
+        for b in &mut self.block[self.block_len..] {
+            *b = 0;
+        }
+        let block = self.block;
+        self.compress(&block, true);
+        let mut out = [0u8; 64];
+        LittleEndian::write_u64_into(&self.state, &mut out);
+        for b in &mut out[self.out_len..] {
+            *b = 0;
+        }
+        out
+    }
+
+    fn compress(&mut self, block: &[u8; 128], last: bool) {
+        let mut m = [0u64; 16];
+        LittleEndian::read_u64_into(block, &mut m);
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.state);
+        v[8..].copy_from_slice(&BLAKE2B_IV);
+        v[12] ^= self.len;
+        if last {
+            v[14] = !v[14];
+        }
+        for round in 0..12 {
+            let s = &BLAKE2B_SIGMA[round % 10];
+            for (i, &(a, b, c, d)) in [(0, 4, 8, 12), (1, 5, 9, 13), (2, 6, 10, 14), (3, 7, 11, 15),
+                (0, 5, 10, 15), (1, 6, 11, 12), (2, 7, 8, 13), (3, 4, 9, 14)].iter().enumerate() {
+                v[a] = v[a].wrapping_add(v[b]).wrapping_add(m[s[2 * i]]);
+                v[d] = (v[d] ^ v[a]).rotate_right(32);
+                v[c] = v[c].wrapping_add(v[d]);
+                v[b] = (v[b] ^ v[c]).rotate_right(24);
+                v[a] = v[a].wrapping_add(v[b]).wrapping_add(m[s[2 * i + 1]]);
+                v[d] = (v[d] ^ v[a]).rotate_right(16);
+                v[c] = v[c].wrapping_add(v[d]);
+                v[b] = (v[b] ^ v[c]).rotate_right(63);
+            }
+        }
+        for i in 0..8 {
+            self.state[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::{from_hex, to_hex};
+
+    #[test]
+    fn derives_keys() {
+        // From RFC 7914
+        let mut key = [0u8; 64];
+        pbkdf2_sha256(b"passwd", b"salt", 1, &mut key);
+        assert_eq!(key[..], from_hex(concat!("55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc",
+            "49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783")).unwrap()[..]);
+        let mut key = [0u8; 20];
+        pbkdf2_sha256(b"password", b"salt", 4096, &mut key);
+        assert_eq!(to_hex(&key), "c5e478d59288c841aa530db6845c4c8d962893a0");
+
+        let mut hash = Blake2b::new(64);
+        hash.update(b"abc");
+        assert_eq!(to_hex(&hash.finish()), concat!("ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1",
+            "7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"));
+
+        // From RFC 9106
+        let argon2 = |variant| {
+            let mut tag = [0u8; 32];
+            Argon2::new(variant, 32, 3, 4).derive_keyed(&[1; 32], &[2; 16], &[3; 8], &[4; 12], &mut tag).unwrap();
+            to_hex(&tag)
+        };
+        assert_eq!(argon2(Variant::D), "512b391b6f1162975371d30919734294f868e3be3984f3c1a13a4db9fabe4acb");
+        assert_eq!(argon2(Variant::I), "c814d9d1dc7f37aa13f0d77f2494bda1c8de6b016dd388d29952a4c4672b6ce8");
+        assert_eq!(argon2(Variant::Id), "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659");
+
+        assert_eq!(Argon2::new(Variant::Id, 16, 1, 4).derive(b"password", b"somesalt", &mut key).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
@@ -0,0 +1,284 @@
+// Asking for passwords and PINs and turning them into keys, for unlocking disks and guarding setup menus.
+//
+// What's typed is kept in a Secret, which never reallocates, so no copies of it are left behind in freed memory, and
+// is zeroed when it's dropped. Prompt reads one from the console, echoing a mask or nothing at all, and on tablets
+// without a keyboard can put an on-screen one up on the display:
+//
+//     let password = Prompt::new("Passphrase: ").on_screen_keyboard(true).read()?;
+//     let mut key = [0u8; 32];
+//     kdf::pbkdf2_sha256(password.as_bytes(), &salt, 100_000, &mut key);
+//
+// Esc gives up, with Aborted.
+
+pub mod kdf;
+pub mod osk;
+
+pub use self::osk::OnScreenKeyboard;
+
+use {Result, EfiErrorKind, system_table};
+use ffi::console::{EFI_INPUT_KEY, SCAN_ESC};
+use keyboard;
+use alloc::{string::String, vec::Vec};
+use core::{char, fmt, ptr, str, sync::atomic::{compiler_fence, Ordering}};
+
+/// Longest secret a Prompt takes unless told otherwise, in bytes
+pub const DEFAULT_MAX_LEN: usize = 256;
+
+const CHAR_BACKSPACE: u16 = 0x08;
+const CHAR_LINEFEED: u16 = 0x0A;
+const CHAR_CARRIAGE_RETURN: u16 = 0x0D;
+
+/// Overwrites `buf` with zeros in a way the compiler can't leave out for being dead stores
+pub fn zeroize(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Bytes that are zeroed when dropped and kept out of Debug output. Its capacity is fixed when it's made
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    /// An empty secret with room for `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Secret(Vec::with_capacity(capacity))
+    }
+
+    /// `len` zeros, to be filled in, e.g. with a derived key
+    pub fn zeroed(len: usize) -> Self {
+        Secret(vec![0; len])
+    }
+
+    /// A copy of `bytes`, which the caller still has to zero
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut secret = Self::with_capacity(bytes.len());
+        secret.0.extend_from_slice(bytes);
+        secret
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+
+    /// None if it isn't UTF-8, which it always is if it was typed
+    pub fn as_str(&self) -> Option<&str> {
+        str::from_utf8(&self.0).ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds a character. BufferTooSmall, leaving it as it was, if there isn't room
+    pub fn push(&mut self, c: char) -> Result<()> {
+        let mut buf = [0u8; 4];
+        let encoded = c.encode_utf8(&mut buf).as_bytes();
+        if self.0.len() + encoded.len() > self.0.capacity() {
+            zeroize(&mut buf);
+            return Err(EfiErrorKind::BufferTooSmall.into());
+        }
+        self.0.extend_from_slice(encoded);
+        zeroize(&mut buf);
+        Ok(())
+    }
+
+    /// Removes the last character, zeroing its bytes. Not for secrets that aren't UTF-8
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str()?.chars().next_back()?;
+        let len = self.0.len() - c.len_utf8();
+        zeroize(&mut self.0[len..]);
+        self.0.truncate(len);
+        Some(c)
+    }
+
+    pub fn clear(&mut self) {
+        zeroize(&mut self.0);
+        self.0.clear();
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secret({} bytes)", self.0.len())
+    }
+}
+
+/// What a key press does to a secret being typed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Enter,
+    Escape,
+}
+
+/// What to show for each character typed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Echo {
+    Mask(char),
+    /// Nothing, so not even the length shows
+    Hidden,
+}
+
+/// Asks for a secret on the console
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    message: String,
+    echo: Echo,
+    max_len: usize,
+    keyboard: bool,
+}
+
+impl Prompt {
+    pub fn new(message: &str) -> Self {
+        Prompt { message: message.into(), echo: Echo::Mask('*'), max_len: DEFAULT_MAX_LEN, keyboard: false }
+    }
+
+    pub fn echo(&mut self, echo: Echo) -> &mut Self {
+        self.echo = echo;
+        self
+    }
+
+    /// In bytes. What's typed past it is ignored
+    pub fn max_len(&mut self, max_len: usize) -> &mut Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Shows an on-screen keyboard while reading if there's a touch screen, see OnScreenKeyboard. A real keyboard
+    /// works either way
+    pub fn on_screen_keyboard(&mut self, show: bool) -> &mut Self {
+        self.keyboard = show;
+        self
+    }
+
+    /// Prints the message and reads until Enter. Aborted if Esc is pressed
+    pub fn read(&self) -> Result<Secret> {
+        print!("{}", self.message);
+        let mut keyboard = if self.keyboard { OnScreenKeyboard::open().ok() } else { None };
+        if let Some(ref mut keyboard) = keyboard {
+            keyboard.show()?;
+        }
+        let result = self.read_keys(
+            || match keyboard {
+                Some(ref mut keyboard) => keyboard.read_key(),
+                None => read_console_key(),
+            },
+            |echo| print!("{}", echo));
+        if let Some(ref mut keyboard) = keyboard {
+            keyboard.hide()?;
+        }
+        println!("");
+        result
+    }
+
+    fn read_keys<K: FnMut() -> Result<Key>, E: FnMut(&str)>(&self, mut next: K, mut echo: E) -> Result<Secret> {
+        let mut secret = Secret::with_capacity(self.max_len);
+        loop {
+            match next()? {
+                Key::Char(c) => {
+                    if secret.push(c).is_ok() {
+                        if let Echo::Mask(mask) = self.echo {
+                            echo(mask.encode_utf8(&mut [0; 4]));
+                        }
+                    }
+                },
+                Key::Backspace => {
+                    if secret.pop().is_some() && self.echo != Echo::Hidden {
+                        echo("\u{8} \u{8}");
+                    }
+                },
+                Key::Enter => return Ok(secret),
+                Key::Escape => return Err(EfiErrorKind::Aborted.into()),
+            }
+        }
+    }
+}
+
+/// Prompts for a secret with the defaults: masked, on the console only
+pub fn read_password(message: &str) -> Result<Secret> {
+    Prompt::new(message).read()
+}
+
+// The next key on the console that means something to a prompt
+fn read_console_key() -> Result<Key> {
+    let console = ::console::console();
+    loop {
+        if let Some(key) = console_key(&console.read_key()?) {
+            return Ok(key);
+        }
+    }
+}
+
+// Printable characters go through the keyboard layout, if one's been set, the same as the rest of console input
+pub(crate) fn console_key(key: &EFI_INPUT_KEY) -> Option<Key> {
+    match key.UnicodeChar {
+        _ if key.ScanCode == SCAN_ESC => Some(Key::Escape),
+        CHAR_CARRIAGE_RETURN | CHAR_LINEFEED => Some(Key::Enter),
+        CHAR_BACKSPACE => Some(Key::Backspace),
+        c if c >= 0x20 && c != 0x7F => char::from_u32(keyboard::remap(c) as u32).map(Key::Char),
+        _ => None,
+    }
+}
+
+// The console's input event, for waiting on it alongside others
+pub(crate) fn console_event() -> ::ffi::EFI_EVENT {
+    unsafe { (*system_table().ConIn).WaitForKey }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(prompt: &Prompt, keys: &[Key]) -> (Result<Secret>, String) {
+        let mut keys = keys.iter();
+        let mut echoed = String::new();
+        let result = prompt.read_keys(|| Ok(*keys.next().unwrap()), |s| echoed.push_str(s));
+        (result, echoed)
+    }
+
+    #[test]
+    fn reads_secrets() {
+        let keys = [Key::Char('p'), Key::Char('å'), Key::Char('x'), Key::Backspace, Key::Char('s'), Key::Enter];
+        let (secret, echoed) = type_keys(&Prompt::new("Password: "), &keys);
+        let secret = secret.unwrap();
+        assert_eq!(secret.as_str(), Some("pås"));
+        assert_eq!(secret.len(), 4);
+        assert_eq!(echoed, "***\u{8} \u{8}*");
+        assert_eq!(format!("{:?}", secret), "Secret(4 bytes)");
+
+        // Nothing's echoed when hidden, and what doesn't fit is dropped
+        let (secret, echoed) = type_keys(Prompt::new("PIN: ").echo(Echo::Hidden).max_len(2), &[Key::Char('1'), Key::Char('2'), Key::Char('3'), Key::Enter]);
+        assert_eq!(secret.unwrap().as_bytes(), b"12");
+        assert_eq!(echoed, "");
+        let (secret, _) = type_keys(&Prompt::new("PIN: "), &[Key::Char('1'), Key::Escape]);
+        assert_eq!(secret.unwrap_err().kind(), EfiErrorKind::Aborted);
+
+        let mut secret = Secret::with_capacity(3);
+        secret.push('a').unwrap();
+        assert_eq!(secret.push('€').unwrap_err().kind(), EfiErrorKind::BufferTooSmall);
+        assert_eq!(secret.pop(), Some('a'));
+        assert_eq!(secret.pop(), None);
+
+        let enter = EFI_INPUT_KEY { ScanCode: 0, UnicodeChar: CHAR_CARRIAGE_RETURN };
+        assert_eq!(console_key(&enter), Some(Key::Enter));
+        assert_eq!(console_key(&EFI_INPUT_KEY { ScanCode: SCAN_ESC, UnicodeChar: 0 }), Some(Key::Escape));
+        assert_eq!(console_key(&EFI_INPUT_KEY { ScanCode: 0, UnicodeChar: 'k' as u16 }), Some(Key::Char('k')));
+        assert_eq!(console_key(&EFI_INPUT_KEY { ScanCode: 1, UnicodeChar: 0 }), None);
+    }
+}
@@ -0,0 +1,361 @@
+// An on-screen keyboard for tablets and kiosks that have a touch screen and nothing to type on. It's drawn across the
+// bottom of the display with GOP, under whatever the console shows, and reads taps through
+// EFI_ABSOLUTE_POINTER_PROTOCOL. Letters, digits and the symbols over them with Shift, which lasts for one key.
+//
+// Labels come from a 5x7 font of our own since there's no telling whether firmware has one to lend through HII.
+
+use {Result, EfiErrorKind, system_table};
+use ffi::{
+    console::{EFI_ABSOLUTE_POINTER_PROTOCOL, EFI_ABSOLUTE_POINTER_PROTOCOL_GUID, EFI_ABSOLUTE_POINTER_STATE, EFI_ABSP_TouchActive},
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    EFI_HANDLE,
+    EFI_SUCCESS,
+    UINTN,
+};
+use boot_services::locate_handles;
+use graphics::GraphicsOutput;
+use super::{Key, console_key, console_event};
+use alloc::vec::Vec;
+use core::{cmp, mem, ptr};
+
+const ROWS: [(&str, &str); 4] = [
+    ("1234567890", "!@#$%^&*()"),
+    ("qwertyuiop", "QWERTYUIOP"),
+    ("asdfghjkl", "ASDFGHJKL"),
+    ("zxcvbnm", "ZXCVBNM"),
+];
+
+const BACKGROUND: (u8, u8, u8) = (0x20, 0x20, 0x20);
+const CAP: (u8, u8, u8) = (0x50, 0x50, 0x50);
+const ACTIVE: (u8, u8, u8) = (0x30, 0x60, 0xA0);
+const LABEL: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+const GAP: u32 = 2;
+
+/// What a key on the keyboard does
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Cap {
+    Char(char, char), // Without and with Shift
+    Shift,
+    Backspace,
+    Space,
+    Enter,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Button {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    cap: Cap,
+}
+
+impl Button {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+pub struct OnScreenKeyboard {
+    gop: GraphicsOutput,
+    pointer: *const EFI_ABSOLUTE_POINTER_PROTOCOL,
+    area: (u32, u32, u32, u32), // x, y, width and height
+    buttons: Vec<Button>,
+    saved: Option<Vec<(u8, u8, u8)>>,
+    shifted: bool,
+    touching: bool,
+}
+
+impl OnScreenKeyboard {
+    /// For the console's display and touch screen, or the first of each if the console doesn't say. NotFound if
+    /// there isn't a touch screen
+    pub fn open() -> Result<Self> {
+        let console_out = system_table().ConsoleOutHandle;
+        let mut outputs = GraphicsOutput::all()?;
+        let index = outputs.iter().position(|gop| gop.handle() == console_out).unwrap_or(0);
+        if outputs.is_empty() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        let gop = outputs.swap_remove(index);
+        let pointer = open_pointer()?;
+        let resolution = gop.current_mode().resolution;
+        let (area, buttons) = layout(resolution.width, resolution.height);
+        Ok(OnScreenKeyboard { gop, pointer, area, buttons, saved: None, shifted: false, touching: false })
+    }
+
+    /// Draws the keyboard, keeping what was under it for hide()
+    pub fn show(&mut self) -> Result<()> {
+        let (x, y, width, height) = self.area;
+        if self.saved.is_none() {
+            self.saved = Some(self.gop.read(x, y, width, height)?);
+        }
+        self.gop.fill(x, y, width, height, BACKGROUND)?;
+        self.draw_buttons()
+    }
+
+    /// Puts back what the keyboard covered
+    pub fn hide(&mut self) -> Result<()> {
+        let (x, y, width, height) = self.area;
+        match self.saved.take() {
+            Some(pixels) => self.gop.write(x, y, width, height, &pixels),
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for a key to be tapped or typed on a real keyboard
+    pub fn read_key(&mut self) -> Result<Key> {
+        let bs = system_table().BootServices;
+        loop {
+            let mut events = [console_event(), unsafe { (*self.pointer).WaitForInput }];
+            let mut index: UINTN = 0;
+            unsafe {
+                ret_on_err!(((*bs).WaitForEvent)(events.len(), events.as_mut_ptr(), &mut index));
+            }
+            if index == 0 {
+                if let Some(key) = console_key(&::console::console().read_key()?) {
+                    return Ok(key);
+                }
+                continue;
+            }
+
+            let mut state = EFI_ABSOLUTE_POINTER_STATE::default();
+            if unsafe { ((*self.pointer).GetState)(self.pointer, &mut state) } != EFI_SUCCESS {
+                continue;
+            }
+            let touching = state.ActiveButtons & EFI_ABSP_TouchActive != 0;
+            let pressed = touching && !self.touching;
+            self.touching = touching;
+            if !pressed {
+                continue;
+            }
+            let (x, y) = self.to_screen(&state);
+            let cap = match self.buttons.iter().find(|b| b.contains(x, y)) {
+                Some(button) => button.cap,
+                None => continue,
+            };
+            if let Some(key) = self.press(cap)? {
+                return Ok(key);
+            }
+        }
+    }
+
+    fn press(&mut self, cap: Cap) -> Result<Option<Key>> {
+        let key = match cap {
+            Cap::Char(normal, shifted) => Key::Char(if self.shifted { shifted } else { normal }),
+            Cap::Shift => {
+                self.shifted = !self.shifted;
+                self.draw_buttons()?;
+                return Ok(None);
+            },
+            Cap::Backspace => Key::Backspace,
+            Cap::Space => Key::Char(' '),
+            Cap::Enter => Key::Enter,
+        };
+        if self.shifted {
+            self.shifted = false;
+            self.draw_buttons()?;
+        }
+        Ok(Some(key))
+    }
+
+    fn draw_buttons(&self) -> Result<()> {
+        for button in &self.buttons {
+            self.gop.write(button.x, button.y, button.width, button.height, &render(button, self.shifted))?;
+        }
+        Ok(())
+    }
+
+    // The pointer's range scaled to the display
+    fn to_screen(&self, state: &EFI_ABSOLUTE_POINTER_STATE) -> (u32, u32) {
+        let mode = unsafe { &*(*self.pointer).Mode };
+        let resolution = self.gop.current_mode().resolution;
+        let scale = |value: u64, min: u64, max: u64, size: u32| {
+            if max <= min {
+                return cmp::min(value, size as u64 - 1) as u32; // No range given, so hope it's in pixels
+            }
+            let offset = cmp::min(value.saturating_sub(min), max - min);
+            (offset as u128 * (size as u128 - 1) / (max - min) as u128) as u32
+        };
+        (scale(state.CurrentX, mode.AbsoluteMinX, mode.AbsoluteMaxX, resolution.width),
+         scale(state.CurrentY, mode.AbsoluteMinY, mode.AbsoluteMaxY, resolution.height))
+    }
+}
+
+fn open_pointer() -> Result<*const EFI_ABSOLUTE_POINTER_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let console_in = system_table().ConsoleInHandle;
+    let mut handles: Vec<EFI_HANDLE> = locate_handles(&EFI_ABSOLUTE_POINTER_PROTOCOL_GUID)?;
+    if let Some(index) = handles.iter().position(|&h| h == console_in) {
+        handles.swap(0, index);
+    }
+    for handle in handles {
+        let protocol: *const EFI_ABSOLUTE_POINTER_PROTOCOL = ptr::null();
+        let status = unsafe {
+            traced!(((*bs).OpenProtocol)(handle, &EFI_ABSOLUTE_POINTER_PROTOCOL_GUID, mem::transmute(&protocol), ::image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL))
+        };
+        if ::ffi::IsSuccess(status) && !protocol.is_null() {
+            return Ok(protocol);
+        }
+    }
+    Err(EfiErrorKind::NotFound.into())
+}
+
+// Ten keys across at most, five rows at most two fifths of the way up the screen
+fn layout(width: u32, height: u32) -> ((u32, u32, u32, u32), Vec<Button>) {
+    let unit = cmp::max(cmp::min(width / 10, height * 2 / 5 / 5), 8);
+    let (left, top) = ((width.saturating_sub(unit * 10)) / 2, height.saturating_sub(unit * 5));
+    let mut buttons = Vec::new();
+    let mut add = |x: u32, row: u32, keys: u32, cap: Cap| {
+        // In half keys from the left
+        buttons.push(Button { x: left + x * unit / 2, y: top + row * unit, width: keys * unit / 2, height: unit, cap });
+    };
+    for (row, &(normal, shifted)) in ROWS.iter().enumerate() {
+        let indent = match row { 2 => 1, 3 => 3, _ => 0 };
+        for (i, (n, s)) in normal.chars().zip(shifted.chars()).enumerate() {
+            add(indent + 2 * i as u32, row as u32, 2, Cap::Char(n, s));
+        }
+    }
+    add(0, 3, 3, Cap::Shift);
+    add(17, 3, 3, Cap::Backspace);
+    add(4, 4, 12, Cap::Space);
+    add(16, 4, 4, Cap::Enter);
+    ((left, top, unit * 10, unit * 5), buttons)
+}
+
+// A button's pixels, a gap around the key and its label in the middle
+fn render(button: &Button, shifted: bool) -> Vec<(u8, u8, u8)> {
+    let (width, height) = (button.width, button.height);
+    let mut pixels = vec![BACKGROUND; (width * height) as usize];
+    let background = if button.cap == Cap::Shift && shifted { ACTIVE } else { CAP };
+    for y in GAP..height.saturating_sub(GAP) {
+        for x in GAP..width.saturating_sub(GAP) {
+            pixels[(y * width + x) as usize] = background;
+        }
+    }
+
+    let label: &[char] = match button.cap {
+        Cap::Char(normal, shifted_char) => &[if shifted { shifted_char } else { normal }],
+        Cap::Shift => &['↑'],
+        Cap::Backspace => &['←'],
+        Cap::Space => &[],
+        Cap::Enter => &['O', 'K'],
+    };
+    let scale = cmp::max(height / 14, 1);
+    let label_width = label.len() as u32 * 6 * scale - scale * if label.is_empty() { 0 } else { 1 };
+    let (left, top) = (width.saturating_sub(label_width) / 2, height.saturating_sub(7 * scale) / 2);
+    for (i, c) in label.iter().enumerate() {
+        let glyph = glyph(c.to_ascii_uppercase());
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0x10 >> column) == 0 {
+                    continue;
+                }
+                let (x, y) = (left + (i as u32 * 6 + column) * scale, top + row as u32 * scale);
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        if x + dx < width && y + dy < height {
+                            pixels[((y + dy) * width + x + dx) as usize] = LABEL;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    pixels
+}
+
+fn glyph(c: char) -> [u8; 7] {
+    FONT.iter().find(|&&(f, _)| f == c).map(|&(_, g)| g).unwrap_or([0; 7])
+}
+
+// Rows top down, the leftmost pixel in bit 4
+const FONT: [(char, [u8; 7]); 48] = [
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('@', [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('$', [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('^', [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('&', [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101]),
+    ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('↑', [0b00100, 0b01110, 0b10101, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('←', [0b00000, 0b00100, 0b01000, 0b11111, 0b01000, 0b00100, 0b00000]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_and_draws_keys() {
+        let (area, buttons) = layout(1000, 1250);
+        assert_eq!(area, (0, 750, 1000, 500));
+        assert_eq!(buttons.len(), 10 + 10 + 9 + 7 + 4);
+        let at = |x, y| buttons.iter().find(|b| b.contains(x, y)).map(|b| b.cap);
+        assert_eq!(at(50, 800), Some(Cap::Char('1', '!')));
+        assert_eq!(at(50, 900), Some(Cap::Char('q', 'Q')));
+        assert_eq!(at(100, 1000), Some(Cap::Char('a', 'A')));
+        assert_eq!(at(20, 1100), Some(Cap::Shift));
+        assert_eq!(at(980, 1100), Some(Cap::Backspace));
+        assert_eq!(at(500, 1200), Some(Cap::Space));
+        assert_eq!(at(900, 1200), Some(Cap::Enter));
+        assert_eq!(at(10, 1000), None);
+        assert_eq!(at(500, 100), None);
+        // All on screen and none overlapping
+        for (i, a) in buttons.iter().enumerate() {
+            assert!(a.x + a.width <= 1000 && a.y + a.height <= 1250);
+            assert!(!buttons[i + 1..].iter().any(|b| b.contains(a.x, a.y)));
+        }
+        // On a smaller screen the keys shrink to fit two fifths of the height
+        assert_eq!(layout(1000, 800).0, (180, 480, 640, 320));
+
+        // The 1 is drawn white in the middle of a grey key, seven pixels of font to a scale of 7
+        let one = buttons[0];
+        let pixels = render(&one, false);
+        assert_eq!(pixels.len(), 100 * 100);
+        assert_eq!(pixels[0], BACKGROUND);
+        assert_eq!(pixels[5 * 100 + 5], CAP);
+        let (left, top) = ((100 - 5 * 7) / 2, (100 - 7 * 7) / 2);
+        assert_eq!(pixels[(top * 100 + left + 2 * 7) as usize], LABEL);
+        assert_eq!(pixels[(top * 100 + left) as usize], CAP);
+        assert_eq!(render(&buttons[36], true)[5 * 150 + 5], ACTIVE);
+    }
+}
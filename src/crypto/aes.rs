@@ -0,0 +1,331 @@
+// AES (FIPS 197), and the block modes disks are encrypted with: CBC, and XTS (IEEE 1619). GCM, which is for
+// messages, is in gcm.
+//
+// Rounds are table driven. The tables leave AES open to cache timing attacks, which need another program running
+// alongside to pull off, and before the OS is up there isn't one.
+
+use super::xor;
+use {Result, EfiErrorKind};
+use byteorder::{BigEndian, ByteOrder};
+
+pub const BLOCK_LEN: usize = 16;
+
+const MAX_ROUND_KEYS: usize = 60; // 4 words a round, 14 rounds and the initial key for AES-256
+
+static SBOX: [u8; 256] = sbox();
+static INV_SBOX: [u8; 256] = inv_sbox();
+static TE: [u32; 256] = te();
+static TD: [u32; 256] = td();
+
+const fn xtime(x: u8) -> u8 {
+    (x << 1) ^ if x & 0x80 != 0 { 0x1B } else { 0 }
+}
+
+const fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+// Walks the multiplicative group with p a power of 3 and q its inverse, a power of 3's inverse, so every byte's
+// inverse comes out without a division
+const fn sbox() -> [u8; 256] {
+    let mut sbox = [0u8; 256];
+    let (mut p, mut q) = (1u8, 1u8);
+    loop {
+        p ^= xtime(p);
+        q ^= q << 1;
+        q ^= q << 2;
+        q ^= q << 4;
+        if q & 0x80 != 0 {
+            q ^= 0x09;
+        }
+        sbox[p as usize] = q ^ q.rotate_left(1) ^ q.rotate_left(2) ^ q.rotate_left(3) ^ q.rotate_left(4) ^ 0x63;
+        if p == 1 {
+            break;
+        }
+    }
+    sbox[0] = 0x63;
+    sbox
+}
+
+const fn inv_sbox() -> [u8; 256] {
+    let sbox = sbox();
+    let mut inv = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[sbox[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+}
+
+// SubBytes and MixColumns for a byte in the first row. The other rows are the same rotated
+const fn te() -> [u32; 256] {
+    let sbox = sbox();
+    let mut te = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let s = sbox[i];
+        te[i] = (mul(s, 2) as u32) << 24 | (s as u32) << 16 | (s as u32) << 8 | mul(s, 3) as u32;
+        i += 1;
+    }
+    te
+}
+
+const fn td() -> [u32; 256] {
+    let inv = inv_sbox();
+    let mut td = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let s = inv[i];
+        td[i] = (mul(s, 14) as u32) << 24 | (mul(s, 9) as u32) << 16 | (mul(s, 13) as u32) << 8 | mul(s, 11) as u32;
+        i += 1;
+    }
+    td
+}
+
+fn sub_word(w: u32) -> u32 {
+    (SBOX[(w >> 24) as usize] as u32) << 24
+        | (SBOX[(w >> 16 & 0xFF) as usize] as u32) << 16
+        | (SBOX[(w >> 8 & 0xFF) as usize] as u32) << 8
+        | SBOX[(w & 0xFF) as usize] as u32
+}
+
+fn round(table: &[u32; 256], a: u32, b: u32, c: u32, d: u32) -> u32 {
+    table[(a >> 24) as usize]
+        ^ table[(b >> 16 & 0xFF) as usize].rotate_right(8)
+        ^ table[(c >> 8 & 0xFF) as usize].rotate_right(16)
+        ^ table[(d & 0xFF) as usize].rotate_right(24)
+}
+
+fn last_round(sbox: &[u8; 256], a: u32, b: u32, c: u32, d: u32) -> u32 {
+    (sbox[(a >> 24) as usize] as u32) << 24
+        | (sbox[(b >> 16 & 0xFF) as usize] as u32) << 16
+        | (sbox[(c >> 8 & 0xFF) as usize] as u32) << 8
+        | sbox[(d & 0xFF) as usize] as u32
+}
+
+/// An expanded AES-128, AES-192 or AES-256 key, zeroed when dropped
+pub struct Aes {
+    rounds: usize,
+    encrypt: [u32; MAX_ROUND_KEYS],
+    // For the equivalent inverse cipher: in reverse, with InvMixColumns applied to all but the first and last
+    decrypt: [u32; MAX_ROUND_KEYS],
+}
+
+impl Aes {
+    /// InvalidParameter unless the key is 16, 24 or 32 bytes
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let nk = match key.len() {
+            16 | 24 | 32 => key.len() / 4,
+            _ => return Err(EfiErrorKind::InvalidParameter.into()),
+        };
+        let rounds = nk + 6;
+        let words = 4 * (rounds + 1);
+
+        let mut encrypt = [0u32; MAX_ROUND_KEYS];
+        for (i, word) in key.chunks(4).enumerate() {
+            encrypt[i] = BigEndian::read_u32(word);
+        }
+        let mut rcon = 1u8;
+        for i in nk..words {
+            let mut temp = encrypt[i - 1];
+            if i % nk == 0 {
+                temp = sub_word(temp.rotate_left(8)) ^ (rcon as u32) << 24;
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                temp = sub_word(temp);
+            }
+            encrypt[i] = encrypt[i - nk] ^ temp;
+        }
+
+        let mut decrypt = [0u32; MAX_ROUND_KEYS];
+        for r in 0..=rounds {
+            for c in 0..4 {
+                let w = encrypt[4 * (rounds - r) + c];
+                decrypt[4 * r + c] = if r == 0 || r == rounds {
+                    w
+                } else {
+                    round(&TD, sub_word(w), sub_word(w), sub_word(w), sub_word(w))
+                };
+            }
+        }
+
+        Ok(Aes { rounds, encrypt, decrypt })
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        self.crypt(block, &self.encrypt, &TE, &SBOX, [1, 2, 3])
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        self.crypt(block, &self.decrypt, &TD, &INV_SBOX, [3, 2, 1])
+    }
+
+    // The rows are shifted by taking the column `shift` ahead for each row, which going back is the one behind
+    fn crypt(&self, block: &mut [u8], keys: &[u32; MAX_ROUND_KEYS], table: &[u32; 256], sbox: &[u8; 256], shift: [usize; 3]) {
+        let mut s = [0u32; 4];
+        for c in 0..4 {
+            s[c] = BigEndian::read_u32(&block[4 * c..]) ^ keys[c];
+        }
+        for r in 1..=self.rounds {
+            let mut t = [0u32; 4];
+            for c in 0..4 {
+                let (a, b, cc, d) = (s[c], s[(c + shift[0]) % 4], s[(c + shift[1]) % 4], s[(c + shift[2]) % 4]);
+                t[c] = keys[4 * r + c] ^ if r < self.rounds { round(table, a, b, cc, d) } else { last_round(sbox, a, b, cc, d) };
+            }
+            s = t;
+        }
+        for c in 0..4 {
+            BigEndian::write_u32(&mut block[4 * c..], s[c]);
+        }
+    }
+}
+
+impl Drop for Aes {
+    fn drop(&mut self) {
+        for w in self.encrypt.iter_mut().chain(self.decrypt.iter_mut()) {
+            unsafe { ::core::ptr::write_volatile(w, 0) };
+        }
+    }
+}
+
+impl Aes {
+    /// CBC encryption of a whole number of blocks, chained on from `iv`
+    pub fn cbc_encrypt(&self, iv: &[u8; BLOCK_LEN], buf: &mut [u8]) {
+        let mut previous = *iv;
+        for block in buf.chunks_mut(BLOCK_LEN) {
+            xor(block, &previous);
+            self.encrypt_block(block);
+            previous.copy_from_slice(block);
+        }
+    }
+
+    pub fn cbc_decrypt(&self, iv: &[u8; BLOCK_LEN], buf: &mut [u8]) {
+        let mut previous = *iv;
+        for block in buf.chunks_mut(BLOCK_LEN) {
+            let mut ciphertext = [0u8; BLOCK_LEN];
+            ciphertext.copy_from_slice(block);
+            self.decrypt_block(block);
+            xor(block, &previous);
+            previous = ciphertext;
+        }
+    }
+}
+
+/// XTS-AES (IEEE 1619), for disk sectors: each is encrypted under a tweak, usually its number, so identical sectors
+/// don't look it. Only whole blocks, since sectors always are
+pub struct Xts {
+    data: Aes,
+    tweak: Aes,
+}
+
+impl Xts {
+    /// The data key and the tweak key back to back: 32 bytes for XTS-AES-128, 64 for XTS-AES-256. InvalidParameter
+    /// for anything else
+    pub fn new(key: &[u8]) -> Result<Self> {
+        if key.len() % 2 != 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let (data, tweak) = key.split_at(key.len() / 2);
+        Ok(Xts { data: Aes::new(data)?, tweak: Aes::new(tweak)? })
+    }
+
+    pub fn encrypt(&self, tweak: &[u8; BLOCK_LEN], buf: &mut [u8]) {
+        self.crypt(tweak, buf, Aes::encrypt_block)
+    }
+
+    pub fn decrypt(&self, tweak: &[u8; BLOCK_LEN], buf: &mut [u8]) {
+        self.crypt(tweak, buf, Aes::decrypt_block)
+    }
+
+    fn crypt(&self, tweak: &[u8; BLOCK_LEN], buf: &mut [u8], block_op: fn(&Aes, &mut [u8])) {
+        let mut t = *tweak;
+        self.tweak.encrypt_block(&mut t);
+        for block in buf.chunks_mut(BLOCK_LEN) {
+            xor(block, &t);
+            block_op(&self.data, block);
+            xor(block, &t);
+            mul_alpha(&mut t);
+        }
+    }
+}
+
+// The next XTS tweak: multiplied by x in GF(2^128), little endian
+fn mul_alpha(tweak: &mut [u8; BLOCK_LEN]) {
+    let mut carry = 0;
+    for b in tweak.iter_mut() {
+        let next = *b >> 7;
+        *b = *b << 1 | carry;
+        carry = next;
+    }
+    if carry != 0 {
+        tweak[0] ^= 0x87;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::from_hex;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn encrypts_blocks() {
+        // FIPS 197 appendix C
+        let plaintext = from_hex("00112233445566778899aabbccddeeff").unwrap();
+        for &(key, ciphertext) in &[
+            ("000102030405060708090a0b0c0d0e0f", "69c4e0d86a7b0430d8cdb78070b4c55a"),
+            ("000102030405060708090a0b0c0d0e0f1011121314151617", "dda97ca4864cdfe06eaf70a0ec0d7191"),
+            ("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "8ea2b7ca516745bfeafc49904b496089"),
+        ] {
+            let aes = Aes::new(&from_hex(key).unwrap()).unwrap();
+            let mut block = plaintext.clone();
+            aes.encrypt_block(&mut block);
+            assert_eq!(block, from_hex(ciphertext).unwrap());
+            aes.decrypt_block(&mut block);
+            assert_eq!(block, plaintext);
+        }
+        assert!(Aes::new(&[0; 20]).is_err());
+
+        // SP 800-38A F.2.1
+        let aes = Aes::new(&from_hex("2b7e151628aed2a6abf7158809cf4f3c").unwrap()).unwrap();
+        let iv = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        let mut blocks = from_hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51").unwrap();
+        aes.cbc_encrypt(&iv, &mut blocks);
+        assert_eq!(blocks, from_hex("7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2").unwrap());
+        aes.cbc_decrypt(&iv, &mut blocks);
+        assert_eq!(blocks, from_hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51").unwrap());
+
+        // IEEE 1619 vectors 1 and 4, at tweak 0, and 10, which has 256 bit keys and tweak 0xff
+        let mut sector = [0u8; 32];
+        Xts::new(&[0; 32]).unwrap().encrypt(&[0; BLOCK_LEN], &mut sector);
+        assert_eq!(sector[..], from_hex("917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e").unwrap()[..]);
+
+        let mut four = from_hex("27182818284590452353602874713526").unwrap();
+        four.extend(from_hex("31415926535897932384626433832795").unwrap());
+        let mut sector: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        let xts = Xts::new(&four).unwrap();
+        xts.encrypt(&[0; BLOCK_LEN], &mut sector);
+        assert_eq!(sector[..16], from_hex("27a7479befa1d476489f308cd4cfa6e2").unwrap()[..]);
+        assert_eq!(sector[496..], from_hex("0a282df920147beabe421ee5319d0568").unwrap()[..]);
+        xts.decrypt(&[0; BLOCK_LEN], &mut sector);
+        assert!(sector.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        let mut ten = from_hex("2718281828459045235360287471352662497757247093699959574966967627").unwrap();
+        ten.extend(from_hex("3141592653589793238462643383279502884197169399375105820974944592").unwrap());
+        let mut sector: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        let mut tweak = [0; BLOCK_LEN];
+        tweak[0] = 0xff;
+        Xts::new(&ten).unwrap().encrypt(&tweak, &mut sector);
+        assert_eq!(sector[..16], from_hex("1c3b3a102f770386e4836c99e370cf9b").unwrap()[..]);
+        assert!(Xts::new(&[0; 40]).is_err());
+    }
+}
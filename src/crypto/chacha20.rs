@@ -0,0 +1,322 @@
+// ChaCha20 and Poly1305 (RFC 8439), and the AEAD made of the two. The alternative to AES-GCM that's as fast in
+// software as AES is with hardware, and has nothing in it that takes time depending on the key.
+
+use super::{constant_time_eq, xor};
+use {Result, EfiErrorKind};
+use byteorder::{ByteOrder, LittleEndian};
+use core::ptr;
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+const BLOCK_LEN: usize = 64;
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574]; // "expand 32-byte k"
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// The ChaCha20 stream cipher. Encrypting and decrypting are the same: XORing with the keystream
+pub struct ChaCha20 {
+    state: [u32; 16],
+    keystream: [u8; BLOCK_LEN],
+    // How much of the keystream block has been used
+    used: usize,
+}
+
+impl ChaCha20 {
+    /// Starts at block `counter`, which AEADs start at 1, keeping 0 for their MAC key
+    pub fn new(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32) -> Self {
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&CONSTANTS);
+        LittleEndian::read_u32_into(key, &mut state[4..12]);
+        state[12] = counter;
+        LittleEndian::read_u32_into(nonce, &mut state[13..]);
+        ChaCha20 { state, keystream: [0; BLOCK_LEN], used: BLOCK_LEN }
+    }
+
+    /// XORs the next bytes of keystream into `buf`
+    pub fn apply(&mut self, buf: &mut [u8]) {
+        let mut done = 0;
+        while done < buf.len() {
+            if self.used == BLOCK_LEN {
+                self.next_block();
+            }
+            let take = (BLOCK_LEN - self.used).min(buf.len() - done);
+            xor(&mut buf[done..done + take], &self.keystream[self.used..]);
+            self.used += take;
+            done += take;
+        }
+    }
+
+    fn next_block(&mut self) {
+        let mut s = self.state;
+        for _ in 0..10 {
+            quarter_round(&mut s, 0, 4, 8, 12);
+            quarter_round(&mut s, 1, 5, 9, 13);
+            quarter_round(&mut s, 2, 6, 10, 14);
+            quarter_round(&mut s, 3, 7, 11, 15);
+            quarter_round(&mut s, 0, 5, 10, 15);
+            quarter_round(&mut s, 1, 6, 11, 12);
+            quarter_round(&mut s, 2, 7, 8, 13);
+            quarter_round(&mut s, 3, 4, 9, 14);
+        }
+        for (s, initial) in s.iter_mut().zip(self.state.iter()) {
+            *s = s.wrapping_add(*initial);
+        }
+        LittleEndian::write_u32_into(&s, &mut self.keystream);
+        self.state[12] = self.state[12].wrapping_add(1);
+        self.used = 0;
+    }
+}
+
+impl Drop for ChaCha20 {
+    fn drop(&mut self) {
+        for w in self.state.iter_mut() {
+            unsafe { ptr::write_volatile(w, 0) };
+        }
+        ::credentials::zeroize(&mut self.keystream);
+    }
+}
+
+const MASK_26: u32 = 0x3FF_FFFF;
+
+/// The Poly1305 one-time authenticator. A key must only ever be used for one message
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    block: [u8; TAG_LEN],
+    block_len: usize,
+}
+
+impl Poly1305 {
+    pub fn new(key: &[u8; KEY_LEN]) -> Self {
+        // r is clamped, and kept in 26 bit limbs so products fit in 64 bits
+        let r = [
+            LittleEndian::read_u32(&key[0..]) & 0x3FF_FFFF,
+            LittleEndian::read_u32(&key[3..]) >> 2 & 0x3FF_FF03,
+            LittleEndian::read_u32(&key[6..]) >> 4 & 0x3FF_C0FF,
+            LittleEndian::read_u32(&key[9..]) >> 6 & 0x3F0_3FFF,
+            LittleEndian::read_u32(&key[12..]) >> 8 & 0x00F_FFFF,
+        ];
+        let mut pad = [0u32; 4];
+        LittleEndian::read_u32_into(&key[16..], &mut pad);
+        Poly1305 { r, h: [0; 5], pad, block: [0; TAG_LEN], block_len: 0 }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.block_len > 0 {
+            let take = data.len().min(TAG_LEN - self.block_len);
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < TAG_LEN {
+                return;
+            }
+            let block = self.block;
+            self.add_block(&block, 1 << 24);
+            self.block_len = 0;
+        }
+        let mut blocks = data.chunks_exact(TAG_LEN);
+        for block in &mut blocks {
+            self.add_block(block, 1 << 24);
+        }
+        let rest = blocks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
+
+    // h = (h + block) * r, mod 2^130 - 5. `high` is the bit above the block, which a short last block has in it
+    fn add_block(&mut self, block: &[u8], high: u32) {
+        let h = &mut self.h;
+        h[0] += LittleEndian::read_u32(&block[0..]) & MASK_26;
+        h[1] += LittleEndian::read_u32(&block[3..]) >> 2 & MASK_26;
+        h[2] += LittleEndian::read_u32(&block[6..]) >> 4 & MASK_26;
+        h[3] += LittleEndian::read_u32(&block[9..]) >> 6 & MASK_26;
+        h[4] += LittleEndian::read_u32(&block[12..]) >> 8 | high;
+
+        // What's multiplied past 2^130 comes back round times 5
+        let r = self.r;
+        let (r0, r1, r2, r3, r4) = (r[0] as u64, r[1] as u64, r[2] as u64, r[3] as u64, r[4] as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let (h0, h1, h2, h3, h4) = (h[0] as u64, h[1] as u64, h[2] as u64, h[3] as u64, h[4] as u64);
+        let d = [
+            h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1,
+            h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2,
+            h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3,
+            h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4,
+            h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0,
+        ];
+
+        let mut carry = 0;
+        for (h, d) in h.iter_mut().zip(d.iter()) {
+            let d = d + carry;
+            *h = d as u32 & MASK_26;
+            carry = d >> 26;
+        }
+        h[0] += carry as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK_26;
+    }
+
+    pub fn finish(mut self) -> [u8; TAG_LEN] {
+        if self.block_len > 0 {
+            let mut block = [0u8; TAG_LEN];
+            block[..self.block_len].copy_from_slice(&self.block[..self.block_len]);
+            block[self.block_len] = 1;
+            self.add_block(&block, 0);
+        }
+
+        // Carries all the way through, then takes h - p if that isn't negative
+        let h = &mut self.h;
+        for i in 1..5 {
+            h[i] += h[i - 1] >> 26;
+            h[i - 1] &= MASK_26;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= MASK_26;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK_26;
+
+        let mut g = [0u32; 5];
+        let mut carry = 5;
+        for i in 0..5 {
+            g[i] = h[i].wrapping_add(carry);
+            carry = g[i] >> 26;
+            g[i] &= MASK_26;
+        }
+        g[4] = g[4].wrapping_add(carry << 26).wrapping_sub(1 << 26);
+        let use_g = (g[4] >> 31).wrapping_sub(1);
+        for (h, g) in h.iter_mut().zip(g.iter()) {
+            *h = *h & !use_g | *g & use_g;
+        }
+
+        let words = [
+            h[0] | h[1] << 26,
+            h[1] >> 6 | h[2] << 20,
+            h[2] >> 12 | h[3] << 14,
+            h[3] >> 18 | h[4] << 8,
+        ];
+        let mut tag = [0u8; TAG_LEN];
+        let mut carry = 0u64;
+        for (i, (&w, &p)) in words.iter().zip(self.pad.iter()).enumerate() {
+            let sum = w as u64 + p as u64 + carry;
+            LittleEndian::write_u32(&mut tag[4 * i..], sum as u32);
+            carry = sum >> 32;
+        }
+        tag
+    }
+}
+
+/// ChaCha20-Poly1305, the AEAD
+pub struct ChaCha20Poly1305 {
+    key: [u8; KEY_LEN],
+}
+
+impl ChaCha20Poly1305 {
+    pub fn new(key: &[u8; KEY_LEN]) -> Self {
+        ChaCha20Poly1305 { key: *key }
+    }
+
+    /// Encrypts `buf` in place, giving the tag that authenticates it and `aad`. A nonce must never be used twice
+    /// with the same key
+    pub fn encrypt(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], buf: &mut [u8]) -> [u8; TAG_LEN] {
+        let (mut cipher, poly) = self.start(nonce);
+        cipher.apply(buf);
+        tag(poly, aad, buf)
+    }
+
+    /// Decrypts `buf` in place if `tag` is right for it and `aad`. SecurityViolation, leaving it as it was, if not
+    pub fn decrypt(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], buf: &mut [u8], tag_given: &[u8; TAG_LEN]) -> Result<()> {
+        let (mut cipher, poly) = self.start(nonce);
+        if !constant_time_eq(&tag(poly, aad, buf), tag_given) {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        cipher.apply(buf);
+        Ok(())
+    }
+
+    // The cipher from block 1, and the authenticator keyed with block 0
+    fn start(&self, nonce: &[u8; NONCE_LEN]) -> (ChaCha20, Poly1305) {
+        let mut cipher = ChaCha20::new(&self.key, nonce, 0);
+        let mut poly_key = [0u8; KEY_LEN];
+        cipher.apply(&mut poly_key);
+        let poly = Poly1305::new(&poly_key);
+        ::credentials::zeroize(&mut poly_key);
+        cipher.used = BLOCK_LEN;
+        (cipher, poly)
+    }
+}
+
+impl Drop for ChaCha20Poly1305 {
+    fn drop(&mut self) {
+        ::credentials::zeroize(&mut self.key);
+    }
+}
+
+// The AAD and the ciphertext, each padded to 16 bytes, then their lengths
+fn tag(mut poly: Poly1305, aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+    let zeros = [0u8; TAG_LEN];
+    for data in &[aad, ciphertext] {
+        poly.update(data);
+        poly.update(&zeros[..(TAG_LEN - data.len() % TAG_LEN) % TAG_LEN]);
+    }
+    let mut lengths = [0u8; 16];
+    LittleEndian::write_u64(&mut lengths, aad.len() as u64);
+    LittleEndian::write_u64(&mut lengths[8..], ciphertext.len() as u64);
+    poly.update(&lengths);
+    poly.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::{from_hex, to_hex};
+
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+    fn array<A: AsMut<[u8]> + Default>(hex: &str) -> A {
+        let mut a = A::default();
+        a.as_mut().copy_from_slice(&from_hex(hex).unwrap());
+        a
+    }
+
+    #[test]
+    fn encrypts_and_authenticates() {
+        // RFC 8439 2.4.2, in pieces that don't line up with blocks
+        let key: [u8; KEY_LEN] = array("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let mut cipher = ChaCha20::new(&key, &array("000000000000004a00000000"), 1);
+        let mut buf = SUNSCREEN.to_vec();
+        for piece in buf.chunks_mut(50) {
+            cipher.apply(piece);
+        }
+        assert_eq!(to_hex(&buf), "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0bf91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d807ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab77937365af90bbf74a35be6b40b8eedf2785e42874d");
+
+        // 2.5.2
+        let mut poly = Poly1305::new(&array("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b"));
+        poly.update(b"Cryptographic Forum");
+        poly.update(b" Research Group");
+        assert_eq!(to_hex(&poly.finish()), "a8061dc1305136c6c22b8baf0c0127a9");
+
+        // 2.8.2
+        let aead = ChaCha20Poly1305::new(&array("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f"));
+        let nonce = array("070000004041424344454647");
+        let aad = from_hex("50515253c0c1c2c3c4c5c6c7").unwrap();
+        let mut buf = SUNSCREEN.to_vec();
+        let tag = aead.encrypt(&nonce, &aad, &mut buf);
+        assert_eq!(to_hex(&tag), "1ae10b594f09e26a7e902ecbd0600691");
+        assert_eq!(to_hex(&buf[..16]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(aead.decrypt(&nonce, &aad[1..], &mut buf, &tag).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        aead.decrypt(&nonce, &aad, &mut buf, &tag).unwrap();
+        assert_eq!(&buf[..], SUNSCREEN);
+    }
+}
@@ -0,0 +1,49 @@
+// Ciphers in software, for when there's no firmware protocol to do it: AES for disks and messages, ChaCha20-Poly1305
+// for messages on machines without AES instructions. The hashes and HMAC are in hash, and are here too so one import
+// does for both.
+//
+// None of it takes time that depends on the key or the data, apart from AES's tables, which see aes.
+
+pub mod aes;
+pub mod gcm;
+pub mod chacha20;
+
+pub use self::aes::{Aes, Xts};
+pub use self::gcm::AesGcm;
+pub use self::chacha20::{ChaCha20, Poly1305, ChaCha20Poly1305};
+pub use hash::{Hash, Hmac, HmacSha256, Sha1, Sha224, Sha256, Sha384, Sha512};
+
+use core::ptr;
+
+/// Whether `a` and `b` are the same, taking as long whichever byte they first differ at. What MACs and password
+/// digests must be compared with, so how long it takes doesn't say how much of a guess was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (a, b) in a.iter().zip(b.iter()) {
+        diff |= a ^ b;
+    }
+    // So the compiler can't stop at the first difference
+    unsafe { ptr::read_volatile(&diff) == 0 }
+}
+
+pub(crate) fn xor(block: &mut [u8], with: &[u8]) {
+    for (b, w) in block.iter_mut().zip(with.iter()) {
+        *b ^= w;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_in_constant_time() {
+        assert!(constant_time_eq(b"tag", b"tag"));
+        assert!(constant_time_eq(&[], &[]));
+        assert!(!constant_time_eq(b"tag", b"taG"));
+        assert!(!constant_time_eq(b"tag", b"tags"));
+    }
+}
@@ -0,0 +1,148 @@
+// gzip (RFC 1952). Concatenated members decompress to their concatenated contents, as with gunzip
+
+use super::{Input, invalid_data, unexpected_eof, GZIP_MAGIC};
+use super::inflate::Inflate;
+use io::{self, Read};
+use utils::crc32_update;
+
+const CM_DEFLATE: u8 = 8;
+
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+const FRESERVED: u8 = 0xE0;
+
+enum State {
+    Header,
+    Body,
+    Done,
+}
+
+/// Decompresses a gzip stream as it's read
+pub struct GzDecoder<R> {
+    input: Input<R>,
+    inflate: Inflate,
+    state: State,
+    members: usize,
+    crc: u32,
+    size: u32, // Modulo 2^32 like the trailer's
+}
+
+impl<R: Read> GzDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self { input: Input::new(reader), inflate: Inflate::new(), state: State::Header, members: 0, crc: 0, size: 0 }
+    }
+
+    // Reads a member header. False if there are no more members
+    fn header(&mut self) -> io::Result<bool> {
+        // Anything other than another member after the first is taken to be padding, which gunzip ignores too
+        let first = match self.input.try_byte()? {
+            Some(b) => b,
+            None if self.members > 0 => return Ok(false),
+            None => return Err(unexpected_eof()),
+        };
+        if first != GZIP_MAGIC[0] || self.input.try_byte()? != Some(GZIP_MAGIC[1]) {
+            return if self.members > 0 { Ok(false) } else { Err(invalid_data("not a gzip stream")) };
+        }
+
+        let mut header = [0u8; 8]; // CM, FLG, MTIME, XFL and OS
+        self.input.read_exact(&mut header)?;
+        let flags = header[1];
+        if header[0] != CM_DEFLATE || flags & FRESERVED != 0 {
+            return Err(invalid_data("unsupported gzip compression method or flags"));
+        }
+
+        if flags & FEXTRA != 0 {
+            let len = self.input.byte()? as u16 | (self.input.byte()? as u16) << 8;
+            for _ in 0..len {
+                self.input.byte()?;
+            }
+        }
+        for &flag in [FNAME, FCOMMENT].iter() {
+            if flags & flag != 0 {
+                while self.input.byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            // TODO: check the header CRC
+            self.input.read_exact(&mut [0u8; 2])?;
+        }
+
+        self.members += 1;
+        self.crc = 0;
+        self.size = 0;
+        self.inflate.reset();
+        Ok(true)
+    }
+
+    fn trailer(&mut self) -> io::Result<()> {
+        let mut trailer = [0u8; 8];
+        self.input.read_exact(&mut trailer)?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != self.crc || size != self.size {
+            return Err(invalid_data("gzip CRC or length mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.state {
+                State::Header => self.state = if self.header()? { State::Body } else { State::Done },
+                State::Body => {
+                    let n = self.inflate.read(&mut self.input, buf)?;
+                    if n > 0 {
+                        self.crc = crc32_update(self.crc, &buf[..n]);
+                        self.size = self.size.wrapping_add(n as u32);
+                        return Ok(n);
+                    }
+                    self.trailer()?;
+                    self.state = State::Header;
+                },
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    // "Hello, world! " 20 times then "Goodbye.", as written by gzip with the name hello.txt
+    const HELLO: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2e, 0x74, 0x78, 0x74, 0x00, 0xf3, 0x48, 0xcd, 0xc9,
+        0xc9, 0xd7, 0x51, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x54, 0xf0, 0x18, 0xe5, 0x41, 0x79, 0xee, 0xf9, 0xf9, 0x29, 0x49, 0x95, 0xa9, 0x7a, 0x00,
+        0xb2, 0xd9, 0x1b, 0x0d, 0x20, 0x01, 0x00, 0x00,
+    ];
+
+    fn hello() -> Vec<u8> {
+        let mut text = b"Hello, world! ".repeat(20);
+        text.extend_from_slice(b"Goodbye.");
+        text
+    }
+
+    #[test]
+    fn decompresses_concatenated_members() {
+        let mut out = Vec::new();
+        GzDecoder::new(HELLO.chain(HELLO)).read_to_end(&mut out).unwrap();
+        assert_eq!(out, [hello(), hello()].concat());
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let mut corrupt = HELLO.to_vec();
+        corrupt[48] ^= 1; // In the CRC
+        assert_eq!(GzDecoder::new(&corrupt[..]).read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(GzDecoder::new(&HELLO[..40]).read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
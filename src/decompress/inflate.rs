@@ -0,0 +1,261 @@
+// DEFLATE (RFC 1951) decoding
+
+use super::{Input, Window, invalid_data, unexpected_eof};
+use io::{self, Read};
+use alloc::{boxed::Box, vec::Vec};
+
+const MAX_BITS: u32 = 15;
+const MAX_DISTANCE: usize = 32 * 1024;
+const MAX_MATCH: usize = 258;
+
+// Codes up to this long are decoded with one table lookup, longer ones a bit at a time
+const FAST_BITS: u32 = 9;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// The order code length code lengths come in, in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const END_OF_BLOCK: u16 = 256;
+
+// A canonical Huffman code
+struct Huffman {
+    counts: [u16; MAX_BITS as usize + 1], // Number of codes of each length
+    symbols: Vec<u16>, // Ordered by code
+    fast: Vec<u16>, // Indexed by the next FAST_BITS bits: symbol << 4 | length, 0 if the code is longer
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; MAX_BITS as usize + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // More codes of some length than there are bit patterns for is an error. Fewer (an incomplete code) is
+        // allowed, e.g. a distance code with a single distance
+        let mut left: i32 = 1;
+        for &count in counts[1..].iter() {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS as usize + 2];
+        for len in 1..=MAX_BITS as usize {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_BITS as usize + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        // Codes are packed starting from their first bit which is the most significant one, so the table is indexed
+        // by the code reversed
+        let mut fast = vec![0u16; 1 << FAST_BITS];
+        let mut code = 0u32;
+        let mut index = 0;
+        for len in 1..=FAST_BITS {
+            for _ in 0..counts[len as usize] {
+                let reversed = code.reverse_bits() >> (32 - len);
+                let mut fill = reversed;
+                while fill < 1 << FAST_BITS {
+                    fast[fill as usize] = symbols[index] << 4 | len as u16;
+                    fill += 1 << len;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+
+        Ok(Self { counts, symbols, fast })
+    }
+
+    fn decode<R: Read>(&self, input: &mut Input<R>) -> io::Result<u16> {
+        let (bits, available) = input.peek_bits(MAX_BITS)?;
+        let entry = self.fast[(bits & ((1 << FAST_BITS) - 1)) as usize];
+        if entry != 0 {
+            let len = (entry & 0xF) as u32;
+            if len > available {
+                return Err(unexpected_eof());
+            }
+            input.consume_bits(len);
+            return Ok(entry >> 4);
+        }
+
+        // The long way: walk the code lengths a bit at a time
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= ((bits >> (len - 1)) & 1) as i32;
+            let count = self.counts[len as usize] as i32;
+            if code - first < count {
+                if len > available {
+                    return Err(unexpected_eof());
+                }
+                input.consume_bits(len);
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+}
+
+enum State {
+    BlockHeader,
+    Stored(u16), // Bytes left
+    Compressed(Box<(Huffman, Huffman)>), // Literal/length and distance codes
+    Done,
+}
+
+/// A raw DEFLATE stream
+pub(crate) struct Inflate {
+    window: Window,
+    state: State,
+    last_block: bool,
+}
+
+impl Inflate {
+    pub(crate) fn new() -> Self {
+        Self { window: Window::new(MAX_DISTANCE, MAX_DISTANCE), state: State::BlockHeader, last_block: false }
+    }
+
+    // Starts over for another stream
+    pub(crate) fn reset(&mut self) {
+        self.window.reset();
+        self.state = State::BlockHeader;
+        self.last_block = false;
+    }
+
+    // Decompresses into `out`. 0 means the stream has ended (or that `out` is empty), after which the input is left
+    // at the byte following the stream
+    pub(crate) fn read<R: Read>(&mut self, input: &mut Input<R>, out: &mut [u8]) -> io::Result<usize> {
+        while self.window.unread() < out.len() && self.window.room() >= MAX_MATCH {
+            match self.state {
+                State::BlockHeader if self.last_block => {
+                    input.align();
+                    self.state = State::Done;
+                },
+                State::BlockHeader => self.block_header(input)?,
+                State::Stored(0) => self.state = State::BlockHeader,
+                State::Stored(ref mut left) => {
+                    let b = input.byte()?;
+                    *left -= 1;
+                    self.window.push(b);
+                },
+                State::Compressed(ref codes) => {
+                    let (ref literal, ref distance) = **codes;
+                    let symbol = literal.decode(input)?;
+                    if symbol < END_OF_BLOCK {
+                        self.window.push(symbol as u8);
+                    } else if symbol == END_OF_BLOCK {
+                        self.state = State::BlockHeader;
+                    } else {
+                        let i = (symbol - END_OF_BLOCK - 1) as usize;
+                        if i >= LENGTH_BASE.len() {
+                            return Err(invalid_data("invalid match length"));
+                        }
+                        let len = LENGTH_BASE[i] as usize + input.bits(LENGTH_EXTRA[i] as u32)? as usize;
+
+                        let i = distance.decode(input)? as usize;
+                        if i >= DISTANCE_BASE.len() {
+                            return Err(invalid_data("invalid match distance"));
+                        }
+                        let dist = DISTANCE_BASE[i] as usize + input.bits(DISTANCE_EXTRA[i] as u32)? as usize;
+                        self.window.copy(dist, len)?;
+                    }
+                },
+                State::Done => break,
+            }
+        }
+
+        Ok(self.window.read(out))
+    }
+
+    fn block_header<R: Read>(&mut self, input: &mut Input<R>) -> io::Result<()> {
+        self.last_block = input.bits(1)? == 1;
+        self.state = match input.bits(2)? {
+            0 => {
+                input.align();
+                let len = input.bits(16)?;
+                if input.bits(16)? != !len & 0xFFFF {
+                    return Err(invalid_data("corrupt stored block length"));
+                }
+                State::Stored(len as u16)
+            },
+            1 => State::Compressed(Box::new(fixed_codes()?)),
+            2 => State::Compressed(Box::new(dynamic_codes(input)?)),
+            _ => return Err(invalid_data("invalid block type")),
+        };
+        Ok(())
+    }
+}
+
+// TODO: build these once rather than for every fixed block
+fn fixed_codes() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes<R: Read>(input: &mut Input<R>) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid_data("too many codes in dynamic block"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &i in CODE_LENGTH_ORDER[..code_length_count].iter() {
+        code_lengths[i] = input.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // The literal/length and distance code lengths come as one run-length coded sequence
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_length_code.decode(input)?;
+        let (len, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 if i == 0 => return Err(invalid_data("repeated code length with no previous length")),
+            16 => (lengths[i - 1], 3 + input.bits(2)? as usize),
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid_data("code lengths overrun"));
+        }
+        for l in lengths[i..i + repeat].iter_mut() {
+            *l = len;
+        }
+        i += repeat;
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(invalid_data("no end of block code"));
+    }
+    let (literal, distance) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literal)?, Huffman::new(distance)?))
+}
@@ -0,0 +1,483 @@
+// LZMA2 decoding, LZMA wrapped in chunks that can each reset the dictionary, the probabilities or switch to storing
+// data uncompressed. Follows the decoder in the xz-embedded project.
+//
+// Raw LZMA, as in .lzma files and EDK2's LZMA compressed sections, is decoded as if it were one endless LZMA2 chunk
+
+use super::{Input, Window, invalid_data};
+use io::{self, Read};
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian};
+
+const MIN_DICT_SIZE: usize = 4096;
+const MIN_MATCH: usize = 2;
+const MAX_MATCH: usize = 273;
+
+const STATES: usize = 12;
+const LIT_STATES: usize = 7; // States below this follow a literal
+const POS_STATES_MAX: usize = 1 << 4;
+const DIST_STATES: usize = 4;
+const DIST_SLOTS: usize = 64;
+const DIST_MODEL_START: u32 = 4;
+const DIST_MODEL_END: u32 = 14;
+const FULL_DISTANCES: usize = 128;
+const ALIGN_BITS: u32 = 4;
+const LITERAL_CODER_SIZE: usize = 0x300;
+
+const PROB_INIT: u16 = 1 << 10;
+const BIT_MODEL_TOTAL_BITS: u32 = 11;
+const MOVE_BITS: u32 = 5;
+const RC_TOP: u32 = 1 << 24;
+
+// Room in the window for output the caller hasn't read yet, on top of the dictionary
+const OUTPUT_MARGIN: usize = 64 * 1024;
+
+struct RangeDecoder {
+    range: u32,
+    code: u32,
+}
+
+impl RangeDecoder {
+    fn new<R: Read>(input: &mut Input<R>) -> io::Result<Self> {
+        if input.byte()? != 0 {
+            return Err(invalid_data("corrupt LZMA range coder"));
+        }
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code << 8 | input.byte()? as u32;
+        }
+        Ok(Self { range: !0, code })
+    }
+
+    fn normalize<R: Read>(&mut self, input: &mut Input<R>) -> io::Result<()> {
+        if self.range < RC_TOP {
+            self.range <<= 8;
+            self.code = self.code << 8 | input.byte()? as u32;
+        }
+        Ok(())
+    }
+
+    fn bit<R: Read>(&mut self, input: &mut Input<R>, prob: &mut u16) -> io::Result<u32> {
+        self.normalize(input)?;
+        let bound = (self.range >> BIT_MODEL_TOTAL_BITS) * *prob as u32;
+        if self.code < bound {
+            self.range = bound;
+            *prob += ((1 << BIT_MODEL_TOTAL_BITS) - *prob) >> MOVE_BITS;
+            Ok(0)
+        } else {
+            self.range -= bound;
+            self.code -= bound;
+            *prob -= *prob >> MOVE_BITS;
+            Ok(1)
+        }
+    }
+
+    fn bittree<R: Read>(&mut self, input: &mut Input<R>, probs: &mut [u16], bits: u32) -> io::Result<u32> {
+        let mut symbol = 1;
+        for _ in 0..bits {
+            symbol = symbol << 1 | self.bit(input, &mut probs[symbol as usize])?;
+        }
+        Ok(symbol - (1 << bits))
+    }
+
+    // Least significant bit first. Unlike bittree(), `probs` starts at the probability for the first bit
+    fn bittree_reverse<R: Read>(&mut self, input: &mut Input<R>, probs: &mut [u16], bits: u32) -> io::Result<u32> {
+        let mut symbol = 1;
+        let mut value = 0;
+        for i in 0..bits {
+            let bit = self.bit(input, &mut probs[symbol as usize - 1])?;
+            symbol = symbol << 1 | bit;
+            value |= bit << i;
+        }
+        Ok(value)
+    }
+
+    fn direct<R: Read>(&mut self, input: &mut Input<R>, bits: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for _ in 0..bits {
+            self.normalize(input)?;
+            self.range >>= 1;
+            let bit = if self.code >= self.range {
+                self.code -= self.range;
+                1
+            } else {
+                0
+            };
+            value = value << 1 | bit;
+        }
+        Ok(value)
+    }
+}
+
+struct LengthDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 8]; POS_STATES_MAX],
+    mid: [[u16; 8]; POS_STATES_MAX],
+    high: [u16; 256],
+}
+
+impl LengthDecoder {
+    fn new() -> Self {
+        Self { choice: PROB_INIT, choice2: PROB_INIT, low: [[PROB_INIT; 8]; POS_STATES_MAX], mid: [[PROB_INIT; 8]; POS_STATES_MAX], high: [PROB_INIT; 256] }
+    }
+
+    // The match length minus MIN_MATCH
+    fn decode<R: Read>(&mut self, rc: &mut RangeDecoder, input: &mut Input<R>, pos_state: usize) -> io::Result<usize> {
+        Ok(if rc.bit(input, &mut self.choice)? == 0 {
+            rc.bittree(input, &mut self.low[pos_state], 3)? as usize
+        } else if rc.bit(input, &mut self.choice2)? == 0 {
+            8 + rc.bittree(input, &mut self.mid[pos_state], 3)? as usize
+        } else {
+            16 + rc.bittree(input, &mut self.high, 8)? as usize
+        })
+    }
+}
+
+// Everything a state reset resets
+struct Probabilities {
+    is_match: [[u16; POS_STATES_MAX]; STATES],
+    is_rep: [u16; STATES],
+    is_rep0: [u16; STATES],
+    is_rep1: [u16; STATES],
+    is_rep2: [u16; STATES],
+    is_rep0_long: [[u16; POS_STATES_MAX]; STATES],
+    dist_slot: [[u16; DIST_SLOTS]; DIST_STATES],
+    dist_special: [u16; FULL_DISTANCES - DIST_MODEL_END as usize],
+    dist_align: [u16; 1 << ALIGN_BITS],
+    len: LengthDecoder,
+    rep_len: LengthDecoder,
+    literal: Vec<u16>,
+}
+
+impl Probabilities {
+    fn new(lc: u32, lp: u32) -> Self {
+        Self {
+            is_match: [[PROB_INIT; POS_STATES_MAX]; STATES],
+            is_rep: [PROB_INIT; STATES],
+            is_rep0: [PROB_INIT; STATES],
+            is_rep1: [PROB_INIT; STATES],
+            is_rep2: [PROB_INIT; STATES],
+            is_rep0_long: [[PROB_INIT; POS_STATES_MAX]; STATES],
+            dist_slot: [[PROB_INIT; DIST_SLOTS]; DIST_STATES],
+            dist_special: [PROB_INIT; FULL_DISTANCES - DIST_MODEL_END as usize],
+            dist_align: [PROB_INIT; 1 << ALIGN_BITS],
+            len: LengthDecoder::new(),
+            rep_len: LengthDecoder::new(),
+            literal: vec![PROB_INIT; LITERAL_CODER_SIZE << (lc + lp)],
+        }
+    }
+}
+
+enum Chunk {
+    Start, // Expecting a control byte
+    Lzma { unpacked: usize, packed_end: Option<u64>, rc: RangeDecoder }, // Unpacked bytes left, and where the chunk ends in the input
+    Uncompressed(usize),
+    Done,
+}
+
+/// An LZMA2 stream
+pub(super) struct Lzma2 {
+    window: Window,
+    chunk: Chunk,
+    need_dict_reset: bool,
+    need_props: bool,
+    lc: u32,
+    lp: u32,
+    pb: u32,
+    probs: Option<Probabilities>,
+    state: usize,
+    reps: [usize; 4],
+    pos: u64, // Since the last dictionary reset
+    raw: bool, // Raw LZMA rather than LZMA2
+}
+
+impl Lzma2 {
+    /// A stream with the given dictionary size, from the LZMA2 filter properties
+    pub(super) fn new(dict_size: u32) -> Self {
+        // A dictionary bigger than the address space could never fill up anyway
+        let dict_size = (dict_size as usize).max(MIN_DICT_SIZE).min(usize::MAX / 2);
+        Self {
+            window: Window::new(dict_size, OUTPUT_MARGIN),
+            chunk: Chunk::Start,
+            need_dict_reset: true,
+            need_props: true,
+            lc: 0,
+            lp: 0,
+            pb: 0,
+            probs: None,
+            state: 0,
+            reps: [0; 4],
+            pos: 0,
+            raw: false,
+        }
+    }
+
+    /// Raw LZMA with the given properties and decompressed size, None meaning it ends with an end marker
+    pub(super) fn raw<R: Read>(input: &mut Input<R>, props: u8, dict_size: u32, unpacked: Option<u64>) -> io::Result<Self> {
+        let mut lzma = Self::new(dict_size);
+        lzma.set_props(props)?;
+        lzma.probs = Some(Probabilities::new(lzma.lc, lzma.lp));
+        lzma.need_dict_reset = false;
+        lzma.raw = true;
+        let unpacked = unpacked.map_or(usize::MAX, |n| n.min(usize::MAX as u64) as usize);
+        lzma.chunk = Chunk::Lzma { unpacked, packed_end: None, rc: RangeDecoder::new(input)? };
+        Ok(lzma)
+    }
+
+    // The properties byte shared with raw LZMA
+    fn set_props(&mut self, props: u8) -> io::Result<()> {
+        if props >= 9 * 5 * 5 {
+            return Err(invalid_data("invalid LZMA properties"));
+        }
+        self.pb = props as u32 / 45;
+        self.lp = props as u32 % 45 / 9;
+        self.lc = props as u32 % 9;
+        self.need_props = false;
+        Ok(())
+    }
+
+    /// The dictionary size encoded in the one byte of LZMA2 filter properties
+    pub(super) fn dict_size(props: u8) -> io::Result<u32> {
+        match props {
+            0..=39 => Ok((2 | (props as u32 & 1)) << (props / 2 + 11)),
+            40 => Ok(!0),
+            _ => Err(invalid_data("invalid LZMA2 dictionary size")),
+        }
+    }
+
+    // Decompresses into `out`. 0 means the stream has ended, leaving the input at the byte following it
+    pub(super) fn read<R: Read>(&mut self, input: &mut Input<R>, out: &mut [u8]) -> io::Result<usize> {
+        while self.window.unread() < out.len() && self.window.room() >= MAX_MATCH {
+            match self.chunk {
+                Chunk::Start => self.chunk_header(input)?,
+                Chunk::Uncompressed(0) => self.chunk = Chunk::Start,
+                Chunk::Uncompressed(ref mut left) => {
+                    *left -= 1;
+                    let b = input.byte()?;
+                    self.window.push(b);
+                    self.pos += 1;
+                },
+                Chunk::Lzma { unpacked: 0, packed_end: None, .. } => self.chunk = Chunk::Done,
+                Chunk::Lzma { unpacked: 0, packed_end: Some(packed_end), ref mut rc } => {
+                    rc.normalize(input)?;
+                    if rc.code != 0 || input.position() != packed_end {
+                        return Err(invalid_data("LZMA2 chunk size mismatch"));
+                    }
+                    self.chunk = Chunk::Start;
+                },
+                Chunk::Lzma { .. } => self.symbol(input)?,
+                Chunk::Done => break,
+            }
+        }
+
+        Ok(self.window.read(out))
+    }
+
+    fn chunk_header<R: Read>(&mut self, input: &mut Input<R>) -> io::Result<()> {
+        let control = input.byte()?;
+        if control == 0x00 {
+            self.chunk = Chunk::Done;
+            return Ok(());
+        }
+
+        // 0x01 and 0xE0 and above reset the dictionary, which the first chunk must
+        if control == 0x01 || control >= 0xE0 {
+            self.window.reset();
+            self.pos = 0;
+            self.need_dict_reset = false;
+        } else if self.need_dict_reset {
+            return Err(invalid_data("LZMA2 stream doesn't start with a dictionary reset"));
+        }
+
+        if control < 0x80 {
+            if control > 0x02 {
+                return Err(invalid_data("invalid LZMA2 control byte"));
+            }
+            let size = (input.byte()? as usize) << 8 | input.byte()? as usize;
+            self.chunk = Chunk::Uncompressed(size + 1);
+            return Ok(());
+        }
+
+        let unpacked = ((control as usize & 0x1F) << 16 | (input.byte()? as usize) << 8 | input.byte()? as usize) + 1;
+        let packed = ((input.byte()? as u64) << 8 | input.byte()? as u64) + 1;
+        let reset = (control >> 5) & 3; // 1 resets the state, 2 also sets new properties, 3 also resets the dictionary
+        if reset >= 2 {
+            self.set_props(input.byte()?)?;
+            if self.lc + self.lp > 4 {
+                return Err(invalid_data("invalid LZMA2 properties"));
+            }
+        } else if self.need_props {
+            return Err(invalid_data("LZMA2 chunk without properties"));
+        }
+
+        if reset >= 1 {
+            self.probs = Some(Probabilities::new(self.lc, self.lp));
+            self.state = 0;
+            self.reps = [0; 4];
+        }
+
+        // The range coder starts over in every chunk. Its 5 bytes count towards the packed size
+        let packed_end = Some(input.position() + packed);
+        let rc = RangeDecoder::new(input)?;
+        self.chunk = Chunk::Lzma { unpacked, packed_end, rc };
+        Ok(())
+    }
+
+    // Decodes one literal or match
+    fn symbol<R: Read>(&mut self, input: &mut Input<R>) -> io::Result<()> {
+        let (unpacked, rc) = match self.chunk {
+            Chunk::Lzma { ref mut unpacked, ref mut rc, .. } => (unpacked, rc),
+            _ => unreachable!(),
+        };
+        let probs = self.probs.as_mut().ok_or_else(|| invalid_data("LZMA2 chunk without properties"))?;
+        let window = &mut self.window;
+        let state = self.state;
+        let pos_state = (self.pos & ((1 << self.pb) - 1)) as usize;
+
+        if rc.bit(input, &mut probs.is_match[state][pos_state])? == 0 {
+            let prev = if window.filled() == 0 { 0 } else { window.get(1) as usize };
+            let lit_state = ((self.pos as usize & ((1 << self.lp) - 1)) << self.lc) + (prev >> (8 - self.lc));
+            let coder = &mut probs.literal[lit_state * LITERAL_CODER_SIZE..(lit_state + 1) * LITERAL_CODER_SIZE];
+
+            let mut symbol: usize = 1;
+            if state >= LIT_STATES {
+                // After a match the literal is coded relative to the byte the match would have continued with
+                if self.reps[0] >= window.filled() {
+                    return Err(invalid_data("match distance out of range"));
+                }
+                let mut match_byte = window.get(self.reps[0] + 1) as usize;
+                while symbol < 0x100 {
+                    let match_bit = (match_byte >> 7) & 1;
+                    match_byte <<= 1;
+                    let bit = rc.bit(input, &mut coder[0x100 + (match_bit << 8) + symbol])? as usize;
+                    symbol = symbol << 1 | bit;
+                    if match_bit != bit {
+                        break;
+                    }
+                }
+            }
+            while symbol < 0x100 {
+                symbol = symbol << 1 | rc.bit(input, &mut coder[symbol])? as usize;
+            }
+
+            if *unpacked == 0 {
+                return Err(invalid_data("LZMA2 chunk overrun"));
+            }
+            window.push(symbol as u8);
+            *unpacked -= 1;
+            self.pos += 1;
+            self.state = match state {
+                0..=3 => 0,
+                4..=9 => state - 3,
+                _ => state - 6,
+            };
+            return Ok(());
+        }
+
+        let len;
+        if rc.bit(input, &mut probs.is_rep[state])? == 0 {
+            // A match with a new distance
+            len = probs.len.decode(rc, input, pos_state)?;
+            self.state = if state < LIT_STATES { 7 } else { 10 };
+            self.reps = [0, self.reps[0], self.reps[1], self.reps[2]];
+
+            let dist_state = len.min(DIST_STATES - 1);
+            let slot = rc.bittree(input, &mut probs.dist_slot[dist_state], 6)?;
+            self.reps[0] = if slot < DIST_MODEL_START {
+                slot as usize
+            } else {
+                let direct = (slot >> 1) - 1;
+                let mut dist = (2 | (slot & 1)) << direct;
+                if slot < DIST_MODEL_END {
+                    dist += rc.bittree_reverse(input, &mut probs.dist_special[(dist - slot) as usize..], direct)?;
+                } else {
+                    dist += rc.direct(input, direct - ALIGN_BITS)? << ALIGN_BITS;
+                    dist += rc.bittree_reverse(input, &mut probs.dist_align[1..], ALIGN_BITS)?;
+                }
+                dist as usize
+            };
+            // The end of payload marker isn't allowed in LZMA2
+            if self.reps[0] == !0u32 as usize {
+                if !self.raw {
+                    return Err(invalid_data("end marker in LZMA2 chunk"));
+                }
+                self.chunk = Chunk::Done;
+                return Ok(());
+            }
+        } else if rc.bit(input, &mut probs.is_rep0[state])? == 0 {
+            if rc.bit(input, &mut probs.is_rep0_long[state][pos_state])? == 0 {
+                // A single byte from the last distance
+                if self.reps[0] >= window.filled() || *unpacked == 0 {
+                    return Err(invalid_data("LZMA2 chunk overrun"));
+                }
+                let b = window.get(self.reps[0] + 1);
+                window.push(b);
+                *unpacked -= 1;
+                self.pos += 1;
+                self.state = if state < LIT_STATES { 9 } else { 11 };
+                return Ok(());
+            }
+            len = probs.rep_len.decode(rc, input, pos_state)?;
+            self.state = if state < LIT_STATES { 8 } else { 11 };
+        } else {
+            // One of the other previous distances, which moves to the front
+            let dist;
+            if rc.bit(input, &mut probs.is_rep1[state])? == 0 {
+                dist = self.reps[1];
+            } else if rc.bit(input, &mut probs.is_rep2[state])? == 0 {
+                dist = self.reps[2];
+                self.reps[2] = self.reps[1];
+            } else {
+                dist = self.reps[3];
+                self.reps[3] = self.reps[2];
+                self.reps[2] = self.reps[1];
+            }
+            self.reps[1] = self.reps[0];
+            self.reps[0] = dist;
+            len = probs.rep_len.decode(rc, input, pos_state)?;
+            self.state = if state < LIT_STATES { 8 } else { 11 };
+        }
+
+        let len = len + MIN_MATCH;
+        if len > *unpacked {
+            return Err(invalid_data("LZMA2 chunk overrun"));
+        }
+        window.copy(self.reps[0] + 1, len)?;
+        *unpacked -= len;
+        self.pos += len as u64;
+        Ok(())
+    }
+}
+
+/// Decompresses raw LZMA as it's read: the "LZMA alone" format of .lzma files and EDK2's LZMA sections. That's a
+/// properties byte, the dictionary size and the decompressed size (all ones if it ends with an end marker instead)
+/// followed by the data
+pub struct LzmaDecoder<R> {
+    input: Input<R>,
+    lzma: Option<Lzma2>,
+}
+
+impl<R: Read> LzmaDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self { input: Input::new(reader), lzma: None }
+    }
+}
+
+impl<R: Read> Read for LzmaDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.lzma.is_none() {
+            let mut header = [0u8; 13];
+            self.input.read_exact(&mut header)?;
+            let unpacked = match LittleEndian::read_u64(&header[5..13]) {
+                core::u64::MAX => None,
+                n => Some(n),
+            };
+            self.lzma = Some(Lzma2::raw(&mut self.input, header[0], LittleEndian::read_u32(&header[1..5]), unpacked)?);
+        }
+        self.lzma.as_mut().unwrap().read(&mut self.input, buf)
+    }
+}
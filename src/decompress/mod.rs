@@ -0,0 +1,256 @@
+// Decompression.
+//
+// The decoders wrap any Read and are themselves Read, decompressing as the caller reads. Memory use is bounded by the
+// format's window (32KiB for gzip, the dictionary size for xz) regardless of how big the payload is, so a compressed
+// kernel or initrd can be decompressed straight off the network into wherever it needs to go.
+//
+// The UEFI/Tiano format is the exception: its header gives the decompressed size up front and it's only ever used
+// for firmware sized payloads, so it works on whole buffers like EFI_DECOMPRESS_PROTOCOL does.
+
+mod inflate;
+mod lzma;
+pub mod gzip;
+pub mod xz;
+pub mod tiano;
+
+pub use self::gzip::GzDecoder;
+pub use self::xz::XzDecoder;
+pub use self::lzma::LzmaDecoder;
+pub(crate) use self::inflate::Inflate;
+
+use io::{self, Read, Cursor};
+use alloc::{boxed::Box, vec::Vec};
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const XZ_MAGIC: &[u8] = &[0xFD, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Wraps `reader` in whichever decoder its first few bytes call for, or passes it through as is if it doesn't look
+/// compressed
+pub fn decoder<'a, R: Read + 'a>(mut reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut magic = [0u8; 6];
+    let mut len = 0;
+    while len < magic.len() {
+        match reader.read(&mut magic[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Put back what we peeked at
+    let magic = &magic[..len];
+    let reader = Cursor::new(magic.to_vec()).chain(reader);
+    Ok(if magic.starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(reader))
+    } else if magic.starts_with(XZ_MAGIC) {
+        Box::new(XzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "compressed stream ended early")
+}
+
+// The compressed input. Buffers the underlying reader and hands out either whole bytes or, for DEFLATE, bits least
+// significant first. Also used by the archive readers so that raw DEFLATE data can be read in between other things
+pub(crate) struct Input<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    consumed: u64, // Bytes taken from buf so far, including those sitting in `bits`
+    bits: u64,
+    bit_count: u32,
+}
+
+impl<R: Read> Input<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, buf: vec![0u8; 16 * 1024].into_boxed_slice(), pos: 0, len: 0, consumed: 0, bits: 0, bit_count: 0 }
+    }
+
+    // The next byte of the underlying reader, ignoring `bits`. None at the end of the stream
+    fn next_raw(&mut self) -> io::Result<Option<u8>> {
+        while self.pos == self.len {
+            match self.inner.read(&mut self.buf) {
+                Ok(0) => return Ok(None),
+                Ok(n) => {
+                    self.pos = 0;
+                    self.len = n;
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+
+        let b = self.buf[self.pos];
+        self.pos += 1;
+        self.consumed += 1;
+        Ok(Some(b))
+    }
+
+    // The next byte, or None at the end of the stream. Bits must be byte aligned
+    pub(crate) fn try_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.bit_count >= 8 {
+            return Ok(Some(self.bits(8)? as u8));
+        }
+        self.next_raw()
+    }
+
+    pub(crate) fn byte(&mut self) -> io::Result<u8> {
+        self.try_byte()?.ok_or_else(unexpected_eof)
+    }
+
+    pub(crate) fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        for b in buf.iter_mut() {
+            *b = self.byte()?;
+        }
+        Ok(())
+    }
+
+    // Some bytes, 0 at the end of the stream. Bits must be byte aligned
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.bit_count >= 8 || self.pos == self.len {
+            return Ok(match self.try_byte()? {
+                Some(b) => {
+                    buf[0] = b;
+                    1
+                },
+                None => 0,
+            });
+        }
+
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        self.consumed += n as u64;
+        Ok(n)
+    }
+
+    // Bytes consumed so far, not counting any still in the bit buffer. Only meaningful when byte aligned
+    fn position(&self) -> u64 {
+        self.consumed - (self.bit_count / 8) as u64
+    }
+
+    // Up to `count` bits without consuming them, padded with zeros past the end of the stream. Also returns how many
+    // of them are real
+    fn peek_bits(&mut self, count: u32) -> io::Result<(u32, u32)> {
+        while self.bit_count < count {
+            match self.next_raw()? {
+                Some(b) => {
+                    self.bits |= (b as u64) << self.bit_count;
+                    self.bit_count += 8;
+                },
+                None => break,
+            }
+        }
+        Ok((self.bits as u32 & mask(count), self.bit_count.min(count)))
+    }
+
+    fn consume_bits(&mut self, count: u32) {
+        self.bits >>= count;
+        self.bit_count -= count;
+    }
+
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        let (bits, available) = self.peek_bits(count)?;
+        if available < count {
+            return Err(unexpected_eof());
+        }
+        self.consume_bits(count);
+        Ok(bits)
+    }
+
+    // Skips to the next byte boundary
+    fn align(&mut self) {
+        let partial = self.bit_count % 8;
+        self.consume_bits(partial);
+    }
+}
+
+fn mask(count: u32) -> u32 {
+    if count >= 32 { !0 } else { (1 << count) - 1 }
+}
+
+// The decompressed output: a ring buffer holding both the history that matches copy from and the output the caller
+// hasn't read yet. Decoders decode into it while there's room, then the caller's buffer is filled from it
+struct Window {
+    buf: Vec<u8>, // Grows up to `size` so small payloads don't pay for a big dictionary
+    size: usize,
+    pos: usize, // Where the next byte goes
+    filled: usize, // How far back matches may reach, i.e. bytes since the last reset up to the window size
+    unread: usize,
+}
+
+impl Window {
+    // A window that can be matched up to `history` bytes back and can hold `margin` bytes of unread output on top
+    fn new(history: usize, margin: usize) -> Self {
+        Self { buf: Vec::new(), size: history + margin, pos: 0, filled: 0, unread: 0 }
+    }
+
+    // Forgets the history, as at the start of a stream
+    fn reset(&mut self) {
+        self.filled = 0;
+    }
+
+    // Up to how many more bytes can be decoded before the caller has to read some
+    fn room(&self) -> usize {
+        self.size - self.unread
+    }
+
+    fn unread(&self) -> usize {
+        self.unread
+    }
+
+    fn filled(&self) -> usize {
+        self.filled
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.buf.len() < self.size {
+            self.buf.push(b);
+        } else {
+            self.buf[self.pos] = b;
+        }
+        self.pos = (self.pos + 1) % self.size;
+        self.filled = (self.filled + 1).min(self.size);
+        self.unread += 1;
+    }
+
+    // The byte `distance` bytes back, 1 being the last one written. The distance must be valid
+    fn get(&self, distance: usize) -> u8 {
+        self.buf[(self.pos + self.size - distance) % self.size]
+    }
+
+    // Copies `len` bytes starting `distance` bytes back. The copy may overlap what it writes, repeating the bytes
+    fn copy(&mut self, distance: usize, len: usize) -> io::Result<()> {
+        if distance == 0 || distance > self.filled {
+            return Err(invalid_data("match distance out of range"));
+        }
+        for _ in 0..len {
+            let b = self.get(distance);
+            self.push(b);
+        }
+        Ok(())
+    }
+
+    // Moves as much unread output as fits into `out`
+    fn read(&mut self, out: &mut [u8]) -> usize {
+        let len = self.unread.min(out.len());
+        let start = (self.pos + self.size - self.unread) % self.size;
+        for (i, b) in out[..len].iter_mut().enumerate() {
+            *b = self.buf[(start + i) % self.size];
+        }
+        self.unread -= len;
+        len
+    }
+}
@@ -0,0 +1,316 @@
+// The compression format of the UEFI spec ("Compression Algorithm Specification") and the Tiano variant of it that
+// EDK tools produce. Both are LZ77 with blocks of Huffman coded literals, match lengths and match positions, much like
+// LHA's -lh5-. Tiano just allows matches further back and so needs one more bit for the number of position codes.
+//
+// The data starts with its compressed and decompressed sizes (32 bits each, little endian) and bits are read most
+// significant first.
+
+use ffi::{
+    decompress::{EFI_DECOMPRESS_PROTOCOL, EFI_DECOMPRESS_PROTOCOL_GUID},
+    UINT32,
+    VOID,
+};
+use {Result, EfiErrorKind, system_table};
+use alloc::vec::Vec;
+use core::{mem, ptr};
+use byteorder::{ByteOrder, LittleEndian};
+
+const HEADER_SIZE: usize = 8;
+
+const MAX_CODE_LEN: u8 = 16;
+const THRESHOLD: usize = 3; // The shortest match
+const NC: usize = 0xFF + 256 + 2 - THRESHOLD; // Literals and match lengths
+const CBIT: u32 = 9;
+const NT: usize = MAX_CODE_LEN as usize + 3; // Code length codes
+const TBIT: u32 = 5;
+const MAXNP: usize = 31; // Position codes
+
+// Don't trust the header with more than this much up front
+const MAX_PREALLOCATION: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Version {
+    /// The UEFI spec's format, which EFI_DECOMPRESS_PROTOCOL decompresses
+    Efi,
+    /// The EDK Tiano format
+    Tiano,
+}
+
+impl Version {
+    // Bits used for the number of position codes
+    fn pbit(self) -> u32 {
+        match self {
+            Version::Efi => 4,
+            Version::Tiano => 5,
+        }
+    }
+}
+
+fn corrupt() -> ::EfiError {
+    EfiErrorKind::InvalidParameter.into()
+}
+
+/// The size `src` decompresses to
+pub fn decompressed_size(src: &[u8]) -> Result<usize> {
+    if src.len() < HEADER_SIZE {
+        return Err(corrupt());
+    }
+    Ok(LittleEndian::read_u32(&src[4..8]) as usize)
+}
+
+/// Decompresses data in either format. Corrupt data fails with InvalidParameter like EFI_DECOMPRESS_PROTOCOL
+pub fn decompress(src: &[u8], version: Version) -> Result<Vec<u8>> {
+    let size = decompressed_size(src)?;
+    let compressed_size = LittleEndian::read_u32(&src[0..4]) as usize;
+    if src.len() - HEADER_SIZE < compressed_size {
+        return Err(corrupt());
+    }
+
+    let mut bits = Bits { data: &src[HEADER_SIZE..HEADER_SIZE + compressed_size], pos: 0 };
+    let mut out = Vec::with_capacity(size.min(MAX_PREALLOCATION));
+    let mut block_left = 0;
+    let mut codes = None;
+    while out.len() < size {
+        if block_left == 0 {
+            // A block size of 0 is really 65536, wrapping around like it does in the reference decoder
+            block_left = match bits.get(16)? {
+                0 => 0x10000,
+                n => n,
+            };
+            let lengths_code = read_pt_code(&mut bits, NT, TBIT, Some(3))?;
+            let c = read_c_code(&mut bits, &lengths_code)?;
+            let p = read_pt_code(&mut bits, MAXNP, version.pbit(), None)?;
+            codes = Some((c, p));
+        }
+        block_left -= 1;
+
+        let (ref c, ref p) = *codes.as_ref().unwrap();
+        let symbol = c.decode(&mut bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+
+        // A match. Its length comes from the symbol and then comes its distance back; 0 meaning the last byte
+        let len = symbol - 256 + THRESHOLD;
+        let distance = match p.decode(&mut bits)? as u32 {
+            code @ 0..=1 => code,
+            code => (1 << (code - 1)) + bits.get(code - 1)?,
+        } as usize + 1;
+        if distance > out.len() {
+            return Err(corrupt());
+        }
+        for _ in 0..len.min(size - out.len()) {
+            let b = out[out.len() - distance];
+            out.push(b);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Decompresses with the firmware's EFI_DECOMPRESS_PROTOCOL, which only handles Version::Efi
+pub fn firmware_decompress(src: &[u8]) -> Result<Vec<u8>> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_DECOMPRESS_PROTOCOL = ptr::null();
+    let mut size: UINT32 = 0;
+    let mut scratch_size: UINT32 = 0;
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_DECOMPRESS_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+        if protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        ret_on_err!(((*protocol).GetInfo)(protocol, src.as_ptr() as *const VOID, src.len() as UINT32, &mut size, &mut scratch_size));
+    }
+
+    let mut out = vec![0u8; size as usize];
+    let mut scratch = vec![0u8; scratch_size as usize];
+    unsafe {
+        ret_on_err!(((*protocol).Decompress)(protocol, src.as_ptr() as *const VOID, src.len() as UINT32, out.as_mut_ptr() as *mut VOID, size, scratch.as_mut_ptr() as *mut VOID, scratch_size));
+    }
+    Ok(out)
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize, // In bits
+}
+
+impl<'a> Bits<'a> {
+    fn bit(&mut self) -> Result<u32> {
+        let byte = *self.data.get(self.pos / 8).ok_or_else(corrupt)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit as u32)
+    }
+
+    fn get(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = value << 1 | self.bit()?;
+        }
+        Ok(value)
+    }
+}
+
+// A canonical Huffman code, or a single symbol that takes no bits at all
+enum Code {
+    Huffman { counts: [u16; MAX_CODE_LEN as usize + 1], symbols: Vec<u16> },
+    Single(u16),
+}
+
+impl Code {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0u16; MAX_CODE_LEN as usize + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for &count in counts[1..].iter() {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(corrupt());
+            }
+        }
+
+        let mut symbols = Vec::new();
+        for len in 1..=MAX_CODE_LEN {
+            symbols.extend((0..lengths.len()).filter(|&symbol| lengths[symbol] == len).map(|symbol| symbol as u16));
+        }
+        Ok(Code::Huffman { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let (counts, symbols) = match *self {
+            Code::Huffman { ref counts, ref symbols } => (counts, symbols),
+            Code::Single(symbol) => return Ok(symbol),
+        };
+
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_CODE_LEN as usize {
+            code |= bits.bit()? as i32;
+            let count = counts[len] as i32;
+            if code - first < count {
+                return Ok(symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupt())
+    }
+}
+
+// The code length code or the position code. Lengths below 7 take 3 bits, longer ones are 7 followed by a 1 for each
+// extra and then a 0. In the code length code the third length is followed by a 2 bit count of zero lengths
+fn read_pt_code(bits: &mut Bits, count: usize, count_bits: u32, zeros_after: Option<usize>) -> Result<Code> {
+    let n = bits.get(count_bits)? as usize;
+    if n == 0 {
+        let symbol = bits.get(count_bits)?;
+        return if (symbol as usize) < count { Ok(Code::Single(symbol as u16)) } else { Err(corrupt()) };
+    }
+    if n > count {
+        return Err(corrupt());
+    }
+
+    let mut lengths = vec![0u8; count];
+    let mut i = 0;
+    while i < n {
+        let mut len = bits.get(3)? as u8;
+        if len == 7 {
+            while bits.bit()? == 1 {
+                len += 1;
+                if len > MAX_CODE_LEN {
+                    return Err(corrupt());
+                }
+            }
+        }
+        lengths[i] = len;
+        i += 1;
+
+        if Some(i) == zeros_after {
+            i += bits.get(2)? as usize;
+            if i > count {
+                return Err(corrupt());
+            }
+        }
+    }
+    Code::new(&lengths)
+}
+
+// The literal and match length code, whose lengths are coded with the code length code. Symbols 0 to 2 are runs of
+// zero lengths, the rest are the length plus 2
+fn read_c_code(bits: &mut Bits, lengths_code: &Code) -> Result<Code> {
+    let n = bits.get(CBIT)? as usize;
+    if n == 0 {
+        let symbol = bits.get(CBIT)?;
+        return if (symbol as usize) < NC { Ok(Code::Single(symbol as u16)) } else { Err(corrupt()) };
+    }
+    if n > NC {
+        return Err(corrupt());
+    }
+
+    let mut lengths = vec![0u8; NC];
+    let mut i = 0;
+    while i < n {
+        let zeros = match lengths_code.decode(bits)? {
+            0 => 1,
+            1 => bits.get(4)? as usize + 3,
+            2 => bits.get(CBIT)? as usize + 20,
+            len => {
+                lengths[i] = (len - 2) as u8;
+                i += 1;
+                continue;
+            },
+        };
+        i += zeros;
+        if i > NC {
+            return Err(corrupt());
+        }
+    }
+    Code::new(&lengths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog. The lazy dog sleeps. The quick fox runs. The end.";
+
+    const FOX_EFI: &[u8] = &[
+        0x44, 0x00, 0x00, 0x00, 0x5e, 0x00, 0x00, 0x00, 0x00, 0x3f, 0x4e, 0xb2, 0xcd, 0x18, 0x37, 0x03, 0x3f, 0xf5, 0x5c, 0x11, 0x7a, 0x45, 0x80, 0x96,
+        0x9b, 0x30, 0x38, 0xe2, 0xbe, 0x94, 0x00, 0x03, 0x29, 0xd9, 0x91, 0x0d, 0xdb, 0x3b, 0xf5, 0x17, 0x21, 0xf5, 0x11, 0x81, 0xf6, 0x34, 0x6d, 0xa4,
+        0xa0, 0xf9, 0x24, 0x1c, 0x7c, 0xe8, 0x5b, 0xef, 0x81, 0xe7, 0x8b, 0x84, 0xe6, 0x57, 0x50, 0xb0, 0x22, 0x92, 0xd0, 0x94, 0xe0, 0xce, 0xda, 0x34,
+        0x7f, 0x19, 0x45, 0xee,
+    ];
+
+    const FOX_TIANO: &[u8] = &[
+        0x45, 0x00, 0x00, 0x00, 0x5e, 0x00, 0x00, 0x00, 0x00, 0x3f, 0x4e, 0xb2, 0xcd, 0x18, 0x37, 0x03, 0x3f, 0xf5, 0x5c, 0x11, 0x7a, 0x45, 0x80, 0x96,
+        0x9b, 0x30, 0x38, 0xe2, 0xbe, 0x92, 0x00, 0x01, 0x94, 0xec, 0xc8, 0x86, 0xed, 0x9d, 0xfa, 0x8b, 0x90, 0xfa, 0x88, 0xc0, 0xfb, 0x1a, 0x36, 0xd2,
+        0x50, 0x7c, 0x92, 0x0e, 0x3e, 0x74, 0x2d, 0xf7, 0xc0, 0xf3, 0xc5, 0xc2, 0x73, 0x2b, 0xa8, 0x58, 0x11, 0x49, 0x68, 0x4a, 0x70, 0x67, 0x6d, 0x1a,
+        0x3f, 0x8c, 0xa2, 0xf7, 0x00,
+    ];
+
+    #[test]
+    fn decompresses_both_versions() {
+        assert_eq!(decompressed_size(FOX_EFI).unwrap(), FOX.len());
+        assert_eq!(decompress(FOX_EFI, Version::Efi).unwrap(), FOX);
+        assert_eq!(decompress(FOX_TIANO, Version::Tiano).unwrap(), FOX);
+
+        // Ten As, with every code being a single symbol
+        let single = [0x07, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x04, 0x10, 0x00];
+        assert_eq!(decompress(&single, Version::Tiano).unwrap(), b"AAAAAAAAAA");
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let mut truncated = FOX_EFI[..40].to_vec();
+        LittleEndian::write_u32(&mut truncated[0..4], 32);
+        assert_eq!(decompress(&truncated, Version::Efi).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        assert_eq!(decompress(&FOX_EFI[..40], Version::Efi).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
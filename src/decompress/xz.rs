@@ -0,0 +1,386 @@
+// The .xz container format (https://tukaani.org/xz/xz-file-format.txt). Only the LZMA2 filter is supported, not the
+// branch converters (BCJ) or delta filters that can be chained in front of it
+//
+// TODO: support the x86 BCJ filter, which xz uses for executables when asked to
+
+use super::{Input, invalid_data, unexpected_eof, XZ_MAGIC};
+use super::lzma::Lzma2;
+use io::{self, Read};
+use utils::crc32_update;
+use alloc::vec::Vec;
+
+const FOOTER_MAGIC: &[u8; 2] = b"YZ";
+const FILTER_LZMA2: u64 = 0x21;
+
+const CHECK_NONE: u8 = 0x00;
+const CHECK_CRC32: u8 = 0x01;
+const CHECK_CRC64: u8 = 0x04;
+const CHECK_MAX: u8 = 0x0F;
+
+// Sizes of the check types by ID, including ones reserved for the future so they can at least be skipped
+const CHECK_SIZES: [usize; CHECK_MAX as usize + 1] = [0, 4, 4, 4, 8, 8, 8, 16, 16, 16, 32, 32, 32, 64, 64, 64];
+
+// CRC-64 with the ECMA-182 polynomial, reflected
+const CRC64_TABLE: [u64; 256] = crc64_table();
+
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xC96C_5795_D787_0F42 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc64_update(crc: u64, data: &[u8]) -> u64 {
+    !data.iter().fold(!crc, |crc, &b| CRC64_TABLE[((crc ^ b as u64) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+// The integrity check of a block's uncompressed data
+enum Check {
+    None,
+    Crc32(u32),
+    Crc64(u64),
+    Unverified(usize), // TODO: verify SHA-256
+}
+
+impl Check {
+    fn new(id: u8) -> Self {
+        match id {
+            CHECK_NONE => Check::None,
+            CHECK_CRC32 => Check::Crc32(0),
+            CHECK_CRC64 => Check::Crc64(0),
+            _ => Check::Unverified(CHECK_SIZES[id as usize]),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match *self {
+            Check::Crc32(ref mut crc) => *crc = crc32_update(*crc, data),
+            Check::Crc64(ref mut crc) => *crc = crc64_update(*crc, data),
+            Check::None | Check::Unverified(_) => {},
+        }
+    }
+
+    fn size(&self) -> usize {
+        match *self {
+            Check::None => 0,
+            Check::Crc32(_) => 4,
+            Check::Crc64(_) => 8,
+            Check::Unverified(size) => size,
+        }
+    }
+
+    fn matches(&self, stored: &[u8]) -> bool {
+        match *self {
+            Check::Crc32(crc) => stored == &crc.to_le_bytes()[..],
+            Check::Crc64(crc) => stored == &crc.to_le_bytes()[..],
+            Check::None | Check::Unverified(_) => true,
+        }
+    }
+}
+
+// Multibyte integers: 7 bits at a time, least significant first, with the top bit set on all but the last byte
+fn varint<F: FnMut() -> io::Result<u8>>(mut next: F) -> io::Result<u64> {
+    let mut value = 0;
+    for i in 0..9 {
+        let b = next()?;
+        value |= ((b & 0x7F) as u64) << (i * 7);
+        if b & 0x80 == 0 {
+            if b == 0 && i > 0 {
+                return Err(invalid_data("overlong xz integer"));
+            }
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("xz integer too long"))
+}
+
+// Reads the index, keeping track of its size and CRC
+struct IndexReader<'a, R> {
+    input: &'a mut Input<R>,
+    crc: u32,
+    size: u64,
+}
+
+impl<'a, R: Read> IndexReader<'a, R> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let b = self.input.byte()?;
+        self.crc = crc32_update(self.crc, &[b]);
+        self.size += 1;
+        Ok(b)
+    }
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+#[derive(PartialEq)]
+enum State {
+    StreamHeader,
+    BlockHeader, // Or the index, after the last block
+    Data,
+    Done,
+}
+
+// What we know about the current block
+struct Block {
+    header_size: u64,
+    data_start: u64, // Position in the input
+    compressed_size: Option<u64>,
+    uncompressed_size: Option<u64>,
+    uncompressed: u64, // So far
+    check: Check,
+}
+
+/// Decompresses an xz stream as it's read
+pub struct XzDecoder<R> {
+    input: Input<R>,
+    state: State,
+    flags: [u8; 2],
+    lzma2: Option<Lzma2>,
+    block: Block,
+    records: Vec<(u64, u64)>, // Unpadded and uncompressed size of each block in the stream, to check the index against
+}
+
+impl<R: Read> XzDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            input: Input::new(reader),
+            state: State::StreamHeader,
+            flags: [0; 2],
+            lzma2: None,
+            block: Block { header_size: 0, data_start: 0, compressed_size: None, uncompressed_size: None, uncompressed: 0, check: Check::None },
+            records: Vec::new(),
+        }
+    }
+
+    // The rest of a stream header whose first byte has been read
+    fn stream_header(&mut self, first: u8) -> io::Result<()> {
+        let mut header = [0u8; 12];
+        header[0] = first;
+        self.input.read_exact(&mut header[1..])?;
+        if &header[..6] != XZ_MAGIC {
+            return Err(invalid_data("not an xz stream"));
+        }
+        if crc32_update(0, &header[6..8]) != le32(&header[8..12]) {
+            return Err(invalid_data("corrupt xz stream header"));
+        }
+        if header[6] != 0 || header[7] > CHECK_MAX {
+            return Err(io::Error::new(io::ErrorKind::Other, "unsupported xz stream flags"));
+        }
+
+        self.flags = [header[6], header[7]];
+        self.records.clear();
+        Ok(())
+    }
+
+    // A block header whose first byte, its size, has been read
+    fn block_header(&mut self, size: u8) -> io::Result<()> {
+        let header_size = (size as usize + 1) * 4;
+        let mut header = vec![0u8; header_size];
+        header[0] = size;
+        self.input.read_exact(&mut header[1..])?;
+        let (header, crc) = header.split_at(header_size - 4);
+        if crc32_update(0, header) != le32(crc) {
+            return Err(invalid_data("corrupt xz block header"));
+        }
+
+        let flags = header[1];
+        if flags & 0x3C != 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "unsupported xz block flags"));
+        }
+        let mut fields = header[2..].iter().cloned();
+        let mut next = || fields.next().ok_or_else(|| invalid_data("truncated xz block header"));
+        let compressed_size = if flags & 0x40 != 0 { Some(varint(&mut next)?) } else { None };
+        let uncompressed_size = if flags & 0x80 != 0 { Some(varint(&mut next)?) } else { None };
+
+        let filter_count = (flags & 0x03) + 1;
+        let id = varint(&mut next)?;
+        let props_size = varint(&mut next)?;
+        if filter_count != 1 || id != FILTER_LZMA2 || props_size != 1 {
+            return Err(io::Error::new(io::ErrorKind::Other, "unsupported xz filter"));
+        }
+        let dict_size = Lzma2::dict_size(next()?)?;
+        while let Ok(b) = next() {
+            if b != 0 {
+                return Err(invalid_data("corrupt xz block header padding"));
+            }
+        }
+
+        self.lzma2 = Some(Lzma2::new(dict_size));
+        self.block = Block {
+            header_size: header_size as u64,
+            data_start: self.input.position(),
+            compressed_size,
+            uncompressed_size,
+            uncompressed: 0,
+            check: Check::new(self.flags[1]),
+        };
+        Ok(())
+    }
+
+    fn finish_block(&mut self) -> io::Result<()> {
+        let compressed = self.input.position() - self.block.data_start;
+        if self.block.compressed_size.map_or(false, |size| size != compressed)
+            || self.block.uncompressed_size.map_or(false, |size| size != self.block.uncompressed) {
+            return Err(invalid_data("xz block size mismatch"));
+        }
+
+        for _ in compressed..(compressed + 3) / 4 * 4 {
+            if self.input.byte()? != 0 {
+                return Err(invalid_data("corrupt xz block padding"));
+            }
+        }
+
+        let mut check = vec![0u8; self.block.check.size()];
+        self.input.read_exact(&mut check)?;
+        if !self.block.check.matches(&check) {
+            return Err(invalid_data("xz check mismatch"));
+        }
+
+        let unpadded = self.block.header_size + compressed + check.len() as u64;
+        self.records.push((unpadded, self.block.uncompressed));
+        self.lzma2 = None;
+        Ok(())
+    }
+
+    // The index, whose indicator byte has been read, then the stream footer
+    fn index_and_footer(&mut self) -> io::Result<()> {
+        let mut index = IndexReader { input: &mut self.input, crc: crc32_update(0, &[0]), size: 1 };
+
+        if varint(|| index.byte())? != self.records.len() as u64 {
+            return Err(invalid_data("xz index doesn't match the blocks"));
+        }
+        for &(unpadded, uncompressed) in self.records.iter() {
+            if varint(|| index.byte())? != unpadded || varint(|| index.byte())? != uncompressed {
+                return Err(invalid_data("xz index doesn't match the blocks"));
+            }
+        }
+        while index.size % 4 != 0 {
+            if index.byte()? != 0 {
+                return Err(invalid_data("corrupt xz index padding"));
+            }
+        }
+
+        let (crc, size) = (index.crc, index.size);
+        let input = &mut self.input;
+        let mut stored = [0u8; 4];
+        input.read_exact(&mut stored)?;
+        if crc != le32(&stored) {
+            return Err(invalid_data("corrupt xz index"));
+        }
+
+        let mut footer = [0u8; 12];
+        input.read_exact(&mut footer)?;
+        if crc32_update(0, &footer[4..10]) != le32(&footer[0..4])
+            || (le32(&footer[4..8]) as u64 + 1) * 4 != size + 4
+            || footer[8..10] != self.flags
+            || &footer[10..12] != FOOTER_MAGIC {
+            return Err(invalid_data("corrupt xz stream footer"));
+        }
+        Ok(())
+    }
+
+    // Skips stream padding and starts the next stream, if there is one
+    fn next_stream(&mut self) -> io::Result<bool> {
+        loop {
+            match self.input.try_byte()? {
+                None => return Ok(false),
+                Some(0) => {
+                    // Padding comes in multiples of four zero bytes
+                    let mut padding = [0u8; 3];
+                    self.input.read_exact(&mut padding)?;
+                    if padding != [0; 3] {
+                        return Err(invalid_data("corrupt xz stream padding"));
+                    }
+                },
+                Some(b) => {
+                    self.stream_header(b)?;
+                    return Ok(true);
+                },
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for XzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.state {
+                State::StreamHeader => {
+                    let first = self.input.try_byte()?.ok_or_else(unexpected_eof)?;
+                    self.stream_header(first)?;
+                    self.state = State::BlockHeader;
+                },
+                State::BlockHeader => match self.input.byte()? {
+                    0 => {
+                        self.index_and_footer()?;
+                        self.state = if self.next_stream()? { State::BlockHeader } else { State::Done };
+                    },
+                    size => {
+                        self.block_header(size)?;
+                        self.state = State::Data;
+                    },
+                },
+                State::Data => {
+                    let n = match self.lzma2 {
+                        Some(ref mut lzma2) => lzma2.read(&mut self.input, buf)?,
+                        None => 0,
+                    };
+                    if n > 0 {
+                        self.block.check.update(&buf[..n]);
+                        self.block.uncompressed += n as u64;
+                        return Ok(n);
+                    }
+                    self.finish_block()?;
+                    self.state = State::BlockHeader;
+                },
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompress::decoder;
+
+    // "Hello, world! " 20 times then "Goodbye.", as written by xz with a CRC-64 check
+    const HELLO: &[u8] = &[
+        0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00, 0x04, 0xe6, 0xd6, 0xb4, 0x46, 0x02, 0x00, 0x21, 0x01, 0x16, 0x00, 0x00, 0x00, 0x74, 0x2f, 0xe5, 0xa3,
+        0xe0, 0x01, 0x1f, 0x00, 0x1d, 0x5d, 0x00, 0x24, 0x19, 0x49, 0x98, 0x6f, 0x16, 0x02, 0x8c, 0xe8, 0xe6, 0x5b, 0xb1, 0x47, 0xc5, 0x36, 0xac, 0xb6,
+        0x78, 0x3d, 0x20, 0x8e, 0xa7, 0xd3, 0xec, 0x95, 0x40, 0x47, 0x39, 0xbd, 0x00, 0x00, 0x00, 0x00, 0x14, 0x12, 0x7e, 0x51, 0x76, 0xd6, 0x77, 0x66,
+        0x00, 0x01, 0x39, 0xa0, 0x02, 0x00, 0x00, 0x00, 0xa5, 0xd5, 0x0e, 0x3a, 0xb1, 0xc4, 0x67, 0xfb, 0x02, 0x00, 0x00, 0x00, 0x00, 0x04, 0x59, 0x5a,
+    ];
+
+    #[test]
+    fn decompresses_padded_streams() {
+        let mut text = b"Hello, world! ".repeat(20);
+        text.extend_from_slice(b"Goodbye.");
+
+        let mut input = HELLO.to_vec();
+        input.extend_from_slice(&[0; 8]);
+        input.extend_from_slice(HELLO);
+        let mut out = Vec::new();
+        decoder(&input[..]).unwrap().read_to_end(&mut out).unwrap();
+        assert_eq!(out, [&text[..], &text[..]].concat());
+
+        let mut corrupt = HELLO.to_vec();
+        corrupt[64] ^= 1; // In the CRC-64
+        assert_eq!(XzDecoder::new(&corrupt[..]).read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,5 +1,4 @@
 use ffi::{
-    IsSuccess,
     CHAR16,
     FALSE,
     device_path::{
@@ -14,9 +13,8 @@ use ffi::{
     UINT16,
 };
 
-use {EfiError, EfiErrorKind, Result, utils::as_slice};
-use core::{mem, ptr, fmt, slice};
-use system_table;
+use {EfiError, EfiErrorKind, Result, utils::as_slice, protocol_cache};
+use core::{ptr, fmt, slice};
 use alloc::{string::String, boxed::Box, vec::Vec};
 
 // TODO: the whole concept of wrapping device path pointers like
@@ -131,23 +129,9 @@ impl fmt::Display for DevicePath {
 }
 
 fn to_string(path: *const EFI_DEVICE_PATH_PROTOCOL, is_single_node: bool) -> Result<String> {
-    let bs = (*system_table()).BootServices;
-
-    let protocol: *mut EFI_DEVICE_PATH_TO_TEXT_PROTOCOL   = ptr::null_mut();
-    unsafe {
-        // TODO: Are we supposed to call CloseProtocol on a protocol pointer obtained via LocateProtocol?
-        // UEFI documentation seems to suggest it's not required but doesn't the firmeware need to know we're
-        // no longer using the pointer and hence if needed it can clean it up? Check this.
-        let status = ((*bs).LocateProtocol)(&EFI_DEVICE_PATH_TO_TEXT_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol));
-
-        if !IsSuccess(status) {
-            return Err(EfiErrorKind::DeviceError.into());
-        }
-
-        if protocol.is_null() { // If above call returned null protocol that means no such protocol is associated with the handle (which is odd)
-            return Err(EfiErrorKind::DeviceError.into()); // TODO: Need proper error here
-        }
-    }
+    // Error mapping kept from when this was looked up here: callers have always seen DeviceError
+    let protocol = protocol_cache::locate::<EFI_DEVICE_PATH_TO_TEXT_PROTOCOL>(&EFI_DEVICE_PATH_TO_TEXT_PROTOCOL_GUID)
+        .map_err(|_| EfiError::from(EfiErrorKind::DeviceError))?;
 
     let text_ptr = unsafe { if is_single_node {
         ((*protocol).ConvertDeviceNodeToText)(path, FALSE, FALSE)
@@ -175,22 +159,8 @@ fn to_string(path: *const EFI_DEVICE_PATH_PROTOCOL, is_single_node: bool) -> Res
 // }
 
 fn path_utils() -> Result<*mut EFI_DEVICE_PATH_UTILITIES_PROTOCOL> {
-    // TODO: Don't "locate" this protocol every time. Do it once and keep a global pointer.
-    let bs = (*system_table()).BootServices;
-
-    let utils: *mut EFI_DEVICE_PATH_UTILITIES_PROTOCOL   = ptr::null_mut();
-    unsafe {
-        // TODO: Are we supposed to call CloseProtocol on a protocol pointer obtained via LocateProtocol?
-        // UEFI documentation seems to suggest it's not required but doesn't the firmeware need to know we're
-        // no longer using the pointer and hence if needed it can clean it up? Check this.
-        ret_on_err!(((*bs).LocateProtocol)(&EFI_DEVICE_PATH_UTILITIES_PROTOCOL_GUID, ptr::null(), mem::transmute(&utils)));
-
-        if utils.is_null() { // If above call returned null protocol that means no such protocol is associated with the handle (which is odd)
-            return Err(EfiErrorKind::LoadError.into()); // TODO: Need proper error here
-        }
-    }
-
-    Ok(utils)
+    let utils = protocol_cache::locate::<EFI_DEVICE_PATH_UTILITIES_PROTOCOL>(&EFI_DEVICE_PATH_UTILITIES_PROTOCOL_GUID)?;
+    Ok(utils as *mut EFI_DEVICE_PATH_UTILITIES_PROTOCOL)
 }
 
 pub fn create_file_path_node<P: AsRef<str>>(relative_file_path: P) -> Result<DeviceNode> { // TODO: return value should be strongly typed as FileDeviceNode 
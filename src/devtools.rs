@@ -0,0 +1,221 @@
+// Primitives for poking at firmware: hex dumps, reading physical memory without wandering off the memory map, and
+// searching for byte patterns and GUIDs in buffers or in memory itself.
+
+use memory::MemoryMap;
+use memdump::{self, read_physical};
+use ffi::EFI_GUID;
+use io::{self, Write};
+use {Result, EfiErrorKind};
+use byteorder::{ByteOrder, LittleEndian};
+use alloc::vec::Vec;
+use core::{cmp, fmt};
+
+const BYTES_PER_LINE: usize = 16;
+
+// How much memory is read at a time when searching it
+const SEARCH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Formats as the classic hex dump: the address, sixteen bytes in hex split into two groups of eight, then the same
+/// bytes as ASCII with anything unprintable as a dot. One line per sixteen bytes, each ending in a newline
+pub struct HexDump<'a> {
+    address: u64,
+    data: &'a [u8],
+}
+
+impl<'a> HexDump<'a> {
+    /// The first byte of `data` is shown at `address`
+    pub fn new(address: u64, data: &'a [u8]) -> Self {
+        Self { address, data }
+    }
+}
+
+impl<'a> fmt::Display for HexDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, line) in self.data.chunks(BYTES_PER_LINE).enumerate() {
+            write!(f, "{:016x} ", self.address + (i * BYTES_PER_LINE) as u64)?;
+            for j in 0..BYTES_PER_LINE {
+                if j == BYTES_PER_LINE / 2 {
+                    f.write_str(" ")?;
+                }
+                match line.get(j) {
+                    Some(b) => write!(f, " {:02x}", b)?,
+                    None => f.write_str("   ")?,
+                }
+            }
+            f.write_str("  |")?;
+            for &b in line {
+                write!(f, "{}", if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })?;
+            }
+            f.write_str("|\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a hex dump of `data`, shown as being at `address`
+pub fn hexdump<W: Write + ?Sized>(out: &mut W, address: u64, data: &[u8]) -> io::Result<()> {
+    write!(out, "{}", HexDump::new(address, data))
+}
+
+/// Where `needle` first occurs in `haystack`. An empty needle is found at the start
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Everywhere `needle` occurs in `haystack`, overlapping occurrences included
+pub fn find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    haystack.windows(needle.len()).enumerate().filter(|(_, w)| *w == needle).map(|(i, _)| i).collect()
+}
+
+/// Everywhere the GUID occurs in `haystack` in its in-memory form
+pub fn find_guid(haystack: &[u8], guid: &EFI_GUID) -> Vec<usize> {
+    find_all(haystack, &guid_bytes(guid))
+}
+
+/// The GUID as it's laid out in memory, which is what firmware images and tables contain
+pub fn guid_bytes(guid: &EFI_GUID) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    LittleEndian::write_u32(&mut bytes, guid.0);
+    LittleEndian::write_u16(&mut bytes[4..], guid.1);
+    LittleEndian::write_u16(&mut bytes[6..], guid.2);
+    bytes[8..].copy_from_slice(&guid.3);
+    bytes
+}
+
+/// Physical memory as far as the memory map says there is any. Reads of anything else, MMIO included, fail rather
+/// than touching it
+pub struct PhysicalMemory {
+    ranges: Vec<(u64, u64)>, // Start and end, sorted and with neighbours merged
+}
+
+impl PhysicalMemory {
+    /// The memory in the current memory map
+    pub fn new() -> Result<Self> {
+        Ok(Self::from_map(&MemoryMap::get()?))
+    }
+
+    pub fn from_map(map: &MemoryMap) -> Self {
+        Self::from_ranges(&memdump::ranges(map, memdump::is_memory))
+    }
+
+    /// Memory made up of the given ranges only
+    pub fn from_ranges(ranges: &[memdump::Range]) -> Self {
+        let mut sorted: Vec<_> = ranges.iter().map(|r| (r.start, r.start + r.length)).collect();
+        sorted.sort();
+        let mut merged: Vec<(u64, u64)> = Vec::new();
+        for (start, end) in sorted {
+            match merged.last_mut() {
+                Some(last) if last.1 >= start => last.1 = cmp::max(last.1, end),
+                _ => merged.push((start, end)),
+            }
+        }
+        Self { ranges: merged }
+    }
+
+    /// The start and end of each run of memory, in order
+    pub fn ranges(&self) -> &[(u64, u64)] {
+        &self.ranges
+    }
+
+    /// Whether all `len` bytes from `address` on are memory
+    pub fn contains(&self, address: u64, len: usize) -> bool {
+        let end = match address.checked_add(len as u64) {
+            Some(end) => end,
+            None => return false,
+        };
+        self.ranges.iter().any(|&(start, stop)| start <= address && end <= stop)
+    }
+
+    /// Fills `buf` from `address`. Fails with InvalidParameter if any of it isn't memory
+    pub fn read(&self, address: u64, buf: &mut [u8]) -> Result<()> {
+        if !self.contains(address, buf.len()) {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        unsafe { read_physical(address, buf) };
+        Ok(())
+    }
+
+    pub fn read_vec(&self, address: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.read(address, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Writes a hex dump of `len` bytes from `address`
+    pub fn hexdump<W: Write + ?Sized>(&self, out: &mut W, address: u64, len: usize) -> Result<()> {
+        let data = self.read_vec(address, len)?;
+        hexdump(out, address, &data).map_err(|_| EfiErrorKind::DeviceError.into())
+    }
+
+    /// The addresses of every occurrence of `needle` in memory. Occurrences straddling two runs of memory aren't
+    /// found
+    pub fn search(&self, needle: &[u8]) -> Vec<u64> {
+        let mut found = Vec::new();
+        if needle.is_empty() {
+            return found;
+        }
+        let mut buf = vec![0u8; SEARCH_CHUNK_SIZE + needle.len() - 1];
+        for &(start, end) in &self.ranges {
+            let mut address = start;
+            while address < end {
+                let len = cmp::min(buf.len() as u64, end - address) as usize;
+                unsafe { read_physical(address, &mut buf[..len]) };
+                found.extend(find_all(&buf[..len], needle).into_iter().map(|i| address + i as u64));
+                // Step back so matches across the chunk boundary are seen, but not twice
+                if address + len as u64 == end {
+                    break;
+                }
+                address += (len + 1 - needle.len()) as u64;
+            }
+        }
+        found
+    }
+
+    /// The addresses of every occurrence of the GUID in memory
+    pub fn search_guid(&self, guid: &EFI_GUID) -> Vec<u64> {
+        self.search(&guid_bytes(guid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::boot_services::EFI_MEMORY_TYPE;
+    use alloc::string::String;
+
+    #[test]
+    fn dumps_and_searches() {
+        let mut out = Vec::new();
+        hexdump(&mut out, 0x1000, b"Hello, world!\n\x00\xffABC").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "0000000000001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+             0000000000001010  41 42 43                                          |ABC|\n");
+
+        assert_eq!((find(b"abcabc", b"ca"), find(b"abc", b"d"), find_all(b"aaaa", b"aa")), (Some(2), None, vec![0, 1, 2]));
+        let guid = EFI_GUID(0x8be4df61, 0x93ca, 0x11d2, [0xaa, 0x0d, 0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c]);
+        let mut haystack = vec![0u8; 40];
+        haystack[20..36].copy_from_slice(&guid_bytes(&guid));
+        assert_eq!(&haystack[20..24], &[0x61, 0xdf, 0xe4, 0x8b]);
+        assert_eq!(find_guid(&haystack, &guid), vec![20]);
+
+        // A needle straddling the search chunks is found once, and nothing outside the ranges is read
+        let mut memory = vec![0u8; SEARCH_CHUNK_SIZE * 2];
+        memory[SEARCH_CHUNK_SIZE - 2..SEARCH_CHUNK_SIZE + 2].copy_from_slice(b"EFI!");
+        let base = memory.as_ptr() as u64;
+        let ram = EFI_MEMORY_TYPE::EfiConventionalMemory as u32;
+        let physical = PhysicalMemory::from_ranges(&[
+            memdump::Range { start: base + 0x100, length: memory.len() as u64 / 2, memory_type: ram },
+            memdump::Range { start: base, length: 0x200, memory_type: ram },
+        ]);
+        assert_eq!(physical.ranges(), &[(base, base + 0x100 + memory.len() as u64 / 2)]);
+        assert_eq!(physical.search(b"EFI!"), vec![base + SEARCH_CHUNK_SIZE as u64 - 2]);
+        assert_eq!(physical.read_vec(base + SEARCH_CHUNK_SIZE as u64, 2).unwrap(), b"I!");
+        assert_eq!(physical.read_vec(base + memory.len() as u64 - 4, 4).err().unwrap().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
@@ -0,0 +1,378 @@
+// ATA commands through EFI_ATA_PASS_THRU_PROTOCOL, which AHCI and IDE controller drivers install on the controller.
+// Devices on it are addressed by port and port multiplier port (0xFFFF when there's no multiplier).
+
+use ffi::{
+    ata_pass_thru::*,
+    device_path::EFI_DEVICE_PATH_PROTOCOL,
+    EFI_HANDLE,
+    VOID,
+};
+use fs::disk::Bounce;
+use {Result, EfiErrorKind};
+use alloc::string::String;
+use core::{ptr, time::Duration};
+
+pub const IDENTIFY_DEVICE: u8 = 0xec;
+pub const SECURITY_SET_PASSWORD: u8 = 0xf1;
+pub const SECURITY_ERASE_PREPARE: u8 = 0xf3;
+pub const SECURITY_ERASE_UNIT: u8 = 0xf4;
+pub const SECURITY_DISABLE_PASSWORD: u8 = 0xf6;
+pub const SANITIZE_DEVICE: u8 = 0xb4;
+pub const SMART: u8 = 0xb0;
+
+// SMART features. The command only goes through with 0xC24F in LBA 23:8
+pub const SMART_READ_DATA: u16 = 0x00d0;
+pub const SMART_RETURN_STATUS: u16 = 0x00da;
+const SMART_SIGNATURE: u64 = 0xc2_4f00;
+const SMART_THRESHOLD_EXCEEDED: u64 = 0x2c_f400;
+
+// SANITIZE DEVICE features, each with a signature in the LBA so it can't be sent by accident
+pub const SANITIZE_STATUS_EXT: u16 = 0x0000;
+pub const CRYPTO_SCRAMBLE_EXT: u16 = 0x0011;
+pub const BLOCK_ERASE_EXT: u16 = 0x0012;
+pub const OVERWRITE_EXT: u16 = 0x0014;
+const CRYPTO_SCRAMBLE_SIGNATURE: u64 = 0x4372_7970; // "Cryp"
+const BLOCK_ERASE_SIGNATURE: u64 = 0x426b_4572; // "BkEr"
+
+const STATUS_ERR: u8 = 0x01;
+const DEVICE_LBA: u8 = 0x40;
+const SECTOR_SIZE: usize = 512;
+
+/// The registers of a command
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Command {
+    pub command: u8,
+    pub features: u16,
+    pub count: u16,
+    pub lba: u64, // 48 bits
+    pub device: u8,
+}
+
+/// The registers the device answered with
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Status {
+    pub status: u8,
+    pub error: u8,
+    pub count: u16,
+    pub lba: u64,
+}
+
+/// Which way data goes, if any
+pub enum Data<'a> {
+    None,
+    In(&'a mut [u8]),
+    Out(&'a [u8]),
+}
+
+/// The parts of IDENTIFY DEVICE we use
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Identify {
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+    pub sectors: u64,
+    pub smart_supported: bool,
+    pub smart_enabled: bool,
+    pub security: SecurityState,
+    pub sanitize: SanitizeSupport,
+    /// How long SECURITY ERASE UNIT takes, if the device says
+    pub erase_time: Option<Duration>,
+    pub enhanced_erase_time: Option<Duration>,
+}
+
+/// Word 128, the security feature set's state
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SecurityState {
+    pub supported: bool,
+    /// A user password is set
+    pub enabled: bool,
+    pub locked: bool,
+    /// Security commands are refused until the next power cycle. Firmware usually does this on purpose
+    pub frozen: bool,
+    pub enhanced_erase: bool,
+}
+
+/// Word 59, which SANITIZE DEVICE operations there are
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SanitizeSupport {
+    pub supported: bool,
+    pub crypto_scramble: bool,
+    pub overwrite: bool,
+    pub block_erase: bool,
+}
+
+impl Identify {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < SECTOR_SIZE {
+            return None;
+        }
+        let word = |n: usize| u16::from(data[n * 2]) | u16::from(data[n * 2 + 1]) << 8;
+        let lba48 = word(83) & (1 << 10) != 0;
+        let sectors = if lba48 {
+            (0..4).fold(0u64, |n, i| n | (word(100 + i) as u64) << (16 * i))
+        } else {
+            word(60) as u64 | (word(61) as u64) << 16
+        };
+        let security = word(128);
+        let sanitize = word(59);
+        let minutes = |w: u16| match w & 0xff {
+            0 => None,
+            n => Some(Duration::from_secs(n as u64 * 2 * 60)), // 255 means longer than 508 minutes, the best we can say
+        };
+        Some(Identify {
+            model: string(&data[54..94]),
+            serial: string(&data[20..40]),
+            firmware: string(&data[46..54]),
+            sectors,
+            smart_supported: word(82) & 0x01 != 0,
+            smart_enabled: word(85) & 0x01 != 0,
+            security: SecurityState {
+                supported: security & 0x01 != 0,
+                enabled: security & 0x02 != 0,
+                locked: security & 0x04 != 0,
+                frozen: security & 0x08 != 0,
+                enhanced_erase: security & 0x20 != 0,
+            },
+            sanitize: SanitizeSupport {
+                supported: sanitize & (1 << 12) != 0,
+                crypto_scramble: sanitize & (1 << 13) != 0,
+                overwrite: sanitize & (1 << 14) != 0,
+                block_erase: sanitize & (1 << 15) != 0,
+            },
+            erase_time: minutes(word(89)),
+            enhanced_erase_time: minutes(word(90)),
+        })
+    }
+}
+
+// Strings have the two characters of each word swapped, and are padded with spaces
+fn string(words: &[u8]) -> String {
+    let mut s = String::with_capacity(words.len());
+    for pair in words.chunks(2) {
+        s.push(pair[1] as char);
+        s.push(pair[0] as char);
+    }
+    s.trim_matches(|c| c == ' ' || c == '\0').into()
+}
+
+/// A device on an ATA controller
+pub struct AtaDevice {
+    protocol: *const EFI_ATA_PASS_THRU_PROTOCOL,
+    port: u16,
+    port_multiplier_port: u16,
+}
+
+impl AtaDevice {
+    pub fn new(controller: EFI_HANDLE, port: u16, port_multiplier_port: u16) -> Result<Self> {
+        let protocol = super::open_protocol::<EFI_ATA_PASS_THRU_PROTOCOL>(controller, &EFI_ATA_PASS_THRU_PROTOCOL_GUID)?;
+        Ok(AtaDevice { protocol, port, port_multiplier_port })
+    }
+
+    // The device a SATA device path node on the controller is for
+    pub(super) fn from_path(controller: EFI_HANDLE, node: *const EFI_DEVICE_PATH_PROTOCOL) -> Result<Self> {
+        let protocol = super::open_protocol::<EFI_ATA_PASS_THRU_PROTOCOL>(controller, &EFI_ATA_PASS_THRU_PROTOCOL_GUID)?;
+        let (mut port, mut port_multiplier_port) = (0u16, 0u16);
+        unsafe {
+            ret_on_err!(((*protocol).GetDevice)(protocol, node, &mut port, &mut port_multiplier_port));
+        }
+        Ok(AtaDevice { protocol, port, port_multiplier_port })
+    }
+
+    pub fn port(&self) -> (u16, u16) {
+        (self.port, self.port_multiplier_port)
+    }
+
+    /// Runs a command. `timeout` of zero waits for as long as it takes. Fails with DeviceError if the device set ERR,
+    /// the registers are still filled in
+    pub fn execute(&self, command: &Command, data: Data, timeout: Duration) -> Result<Status> {
+        let extended = command.lba >> 28 != 0 || command.count >> 8 != 0 || command.features >> 8 != 0 || command.command == SANITIZE_DEVICE;
+        let acb = EFI_ATA_COMMAND_BLOCK {
+            AtaCommand: command.command,
+            AtaFeatures: command.features as u8,
+            AtaFeaturesExp: (command.features >> 8) as u8,
+            AtaSectorCount: command.count as u8,
+            AtaSectorCountExp: (command.count >> 8) as u8,
+            AtaSectorNumber: command.lba as u8,
+            AtaCylinderLow: (command.lba >> 8) as u8,
+            AtaCylinderHigh: (command.lba >> 16) as u8,
+            AtaSectorNumberExp: (command.lba >> 24) as u8,
+            AtaCylinderLowExp: (command.lba >> 32) as u8,
+            AtaCylinderHighExp: (command.lba >> 40) as u8,
+            AtaDeviceHead: command.device | if extended { DEVICE_LBA } else { (command.lba >> 24) as u8 & 0x0f },
+            ..Default::default()
+        };
+        let mut asb = EFI_ATA_STATUS_BLOCK::default();
+        let mut packet = EFI_ATA_PASS_THRU_COMMAND_PACKET {
+            Asb: &mut asb,
+            Acb: &acb,
+            Timeout: (timeout.as_nanos() / 100) as u64,
+            InDataBuffer: ptr::null_mut(),
+            OutDataBuffer: ptr::null(),
+            InTransferLength: 0,
+            OutTransferLength: 0,
+            Protocol: EFI_ATA_PASS_THRU_PROTOCOL_ATA_NON_DATA,
+            Length: EFI_ATA_PASS_THRU_LENGTH_NO_DATA_TRANSFER,
+        };
+
+        let align = unsafe { (*(*self.protocol).Mode).IoAlign as usize }.max(1);
+        let mut bounce = match data {
+            Data::None => None,
+            Data::In(ref buf) => Some(Bounce::new(buf.len(), align)?),
+            Data::Out(buf) => {
+                let mut bounce = Bounce::new(buf.len(), align)?;
+                bounce.as_mut_slice().copy_from_slice(buf);
+                Some(bounce)
+            }
+        };
+        if let Some(ref mut bounce) = bounce {
+            let buf = bounce.as_mut_slice();
+            packet.Length = EFI_ATA_PASS_THRU_LENGTH_BYTES | EFI_ATA_PASS_THRU_LENGTH_SECTOR_COUNT;
+            if let Data::Out(_) = data {
+                packet.OutDataBuffer = buf.as_ptr() as *const VOID;
+                packet.OutTransferLength = buf.len() as u32;
+                packet.Protocol = EFI_ATA_PASS_THRU_PROTOCOL_PIO_DATA_OUT;
+            } else {
+                packet.InDataBuffer = buf.as_mut_ptr() as *mut VOID;
+                packet.InTransferLength = buf.len() as u32;
+                packet.Protocol = EFI_ATA_PASS_THRU_PROTOCOL_PIO_DATA_IN;
+            }
+        }
+
+        let status = unsafe { traced!(((*self.protocol).PassThru)(self.protocol, self.port, self.port_multiplier_port, &mut packet, ptr::null())) };
+        if let (Data::In(buf), Some(ref mut bounce)) = (data, bounce) {
+            buf.copy_from_slice(bounce.as_mut_slice());
+        }
+        let registers = Status {
+            status: asb.AtaStatus,
+            error: asb.AtaError,
+            count: asb.AtaSectorCount as u16 | (asb.AtaSectorCountExp as u16) << 8,
+            lba: asb.AtaSectorNumber as u64 | (asb.AtaCylinderLow as u64) << 8 | (asb.AtaCylinderHigh as u64) << 16
+                | (asb.AtaSectorNumberExp as u64) << 24 | (asb.AtaCylinderLowExp as u64) << 32 | (asb.AtaCylinderHighExp as u64) << 40,
+        };
+        ret_on_err!(status);
+        if registers.status & STATUS_ERR != 0 {
+            return Err(EfiErrorKind::DeviceError.into());
+        }
+        Ok(registers)
+    }
+
+    pub fn identify(&self) -> Result<Identify> {
+        let mut data = [0u8; SECTOR_SIZE];
+        let command = Command { command: IDENTIFY_DEVICE, count: 1, ..Default::default() };
+        self.execute(&command, Data::In(&mut data), Duration::from_secs(5))?;
+        Identify::parse(&data).ok_or_else(|| EfiErrorKind::DeviceError.into())
+    }
+
+    /// Sets the user password, which enables security. Needed before SECURITY ERASE UNIT
+    pub fn security_set_password(&self, password: &[u8]) -> Result<()> {
+        let block = password_block(0, password)?;
+        let command = Command { command: SECURITY_SET_PASSWORD, count: 1, ..Default::default() };
+        self.execute(&command, Data::Out(&block), Duration::from_secs(5)).map(|_| ())
+    }
+
+    pub fn security_disable_password(&self, password: &[u8]) -> Result<()> {
+        let block = password_block(0, password)?;
+        let command = Command { command: SECURITY_DISABLE_PASSWORD, count: 1, ..Default::default() };
+        self.execute(&command, Data::Out(&block), Duration::from_secs(5)).map(|_| ())
+    }
+
+    /// Erases the whole device with the user password, which the device then forgets. Blocks until it's done, which
+    /// can be hours
+    pub fn security_erase_unit(&self, password: &[u8], enhanced: bool) -> Result<()> {
+        let block = password_block(if enhanced { 0x02 } else { 0 }, password)?;
+        self.execute(&Command { command: SECURITY_ERASE_PREPARE, ..Default::default() }, Data::None, Duration::from_secs(5))?;
+        let command = Command { command: SECURITY_ERASE_UNIT, count: 1, ..Default::default() };
+        self.execute(&command, Data::Out(&block), Duration::default()).map(|_| ())
+    }
+
+    /// The 512 byte SMART data, with the vendor's attribute table at the start
+    pub fn smart_read_data(&self) -> Result<[u8; SECTOR_SIZE]> {
+        let mut data = [0u8; SECTOR_SIZE];
+        let command = Command { command: SMART, features: SMART_READ_DATA, count: 1, lba: SMART_SIGNATURE, ..Default::default() };
+        self.execute(&command, Data::In(&mut data), Duration::from_secs(5))?;
+        Ok(data)
+    }
+
+    /// Whether the device thinks it's failing, i.e. some attribute has reached its threshold
+    pub fn smart_threshold_exceeded(&self) -> Result<bool> {
+        let command = Command { command: SMART, features: SMART_RETURN_STATUS, lba: SMART_SIGNATURE, ..Default::default() };
+        let status = self.execute(&command, Data::None, Duration::from_secs(5))?;
+        Ok(status.lba & 0xff_ff00 == SMART_THRESHOLD_EXCEEDED)
+    }
+
+    /// Starts a sanitize operation, which carries on in the background. See sanitize_status()
+    pub fn sanitize(&self, feature: u16) -> Result<()> {
+        let lba = match feature {
+            CRYPTO_SCRAMBLE_EXT => CRYPTO_SCRAMBLE_SIGNATURE,
+            BLOCK_ERASE_EXT => BLOCK_ERASE_SIGNATURE,
+            _ => return Err(EfiErrorKind::InvalidParameter.into()),
+        };
+        let command = Command { command: SANITIZE_DEVICE, features: feature, lba, ..Default::default() };
+        self.execute(&command, Data::None, Duration::from_secs(5)).map(|_| ())
+    }
+
+    pub fn sanitize_status(&self) -> Result<SanitizeStatus> {
+        let command = Command { command: SANITIZE_DEVICE, features: SANITIZE_STATUS_EXT, ..Default::default() };
+        let status = self.execute(&command, Data::None, Duration::from_secs(5))?;
+        Ok(SanitizeStatus {
+            completed: status.count & (1 << 15) != 0,
+            in_progress: status.count & (1 << 14) != 0,
+            progress: (status.lba & 0xffff) as u16,
+        })
+    }
+}
+
+/// What SANITIZE STATUS EXT says
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SanitizeStatus {
+    /// The last sanitize finished without errors
+    pub completed: bool,
+    pub in_progress: bool,
+    /// Out of 65536
+    pub progress: u16,
+}
+
+// The data of the security commands: a control word followed by the 32 byte password
+fn password_block(control: u16, password: &[u8]) -> Result<[u8; SECTOR_SIZE]> {
+    if password.len() > 32 {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+    let mut block = [0u8; SECTOR_SIZE];
+    block[0] = control as u8;
+    block[1] = (control >> 8) as u8;
+    block[2..2 + password.len()].copy_from_slice(password);
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_identify() {
+        let mut data = [0u8; SECTOR_SIZE];
+        let mut set_word = |n: usize, value: u16| {
+            data[n * 2] = value as u8;
+            data[n * 2 + 1] = (value >> 8) as u8;
+        };
+        set_word(59, 1 << 12 | 1 << 13);
+        set_word(83, 1 << 10);
+        set_word(100, 0x5000);
+        set_word(101, 0x0d1c);
+        set_word(128, 0x0029);
+        set_word(89, 3);
+        for (i, pair) in b"QEMU HARDDISK   ".chunks(2).enumerate() {
+            set_word(27 + i, (pair[0] as u16) << 8 | pair[1] as u16);
+        }
+        let identify = Identify::parse(&data).unwrap();
+        assert_eq!(identify.model, "QEMU HARDDISK");
+        assert_eq!(identify.sectors, 0x0d1c_5000);
+        assert!(identify.security.supported && identify.security.frozen && identify.security.enhanced_erase && !identify.security.enabled);
+        assert!(identify.sanitize.supported && identify.sanitize.crypto_scramble && !identify.sanitize.block_erase);
+        assert_eq!(identify.erase_time, Some(Duration::from_secs(360)));
+        assert_eq!(identify.enhanced_erase_time, None);
+
+        assert_eq!(&password_block(0x02, b"pw").unwrap()[..4], b"\x02\x00pw");
+        assert!(password_block(0, &[0; 33]).is_err());
+    }
+}
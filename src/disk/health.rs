@@ -0,0 +1,285 @@
+// Drive health from SMART: ATA's attribute table and NVMe's health log, in one shape so a pre-boot check can say
+// "this disk is dying" without caring which kind it is. ATA attributes are vendor defined beyond a handful everyone
+// agrees on, which are the ones we pull out; the rest are kept raw.
+
+use super::{Drive, Identity, Interface};
+use byteorder::{ByteOrder, LittleEndian};
+use json::{ToJson, Value};
+use Result;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+const ATTRIBUTE_COUNT: usize = 30;
+const ATTRIBUTE_SIZE: usize = 12;
+
+const REALLOCATED_SECTORS: u8 = 5;
+const POWER_ON_HOURS: u8 = 9;
+const POWER_CYCLES: u8 = 12;
+const AIRFLOW_TEMPERATURE: u8 = 190;
+const TEMPERATURE: u8 = 194;
+const PENDING_SECTORS: u8 = 197;
+const UNCORRECTABLE_SECTORS: u8 = 198;
+
+// NVMe data units are thousands of 512 byte blocks
+const NVME_DATA_UNIT: u64 = 512 * 1000;
+
+/// An entry in an ATA drive's SMART attribute table
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    pub id: u8,
+    /// Normalised, higher is better. What it's compared with is in the thresholds, which we don't read
+    pub current: u8,
+    pub worst: u8,
+    /// 48 bits, meaning up to the vendor
+    pub raw: u64,
+}
+
+/// The health of one drive. Whatever the drive doesn't report is None
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    pub identity: Identity,
+    /// "ATA" or "NVMe"
+    pub interface: &'static str,
+    /// False if the drive says it's failing: an ATA attribute past its threshold or an NVMe critical warning
+    pub passed: bool,
+    /// Celsius
+    pub temperature: Option<i16>,
+    pub reallocated_sectors: Option<u64>,
+    pub pending_sectors: Option<u64>,
+    pub uncorrectable_sectors: Option<u64>,
+    /// NVMe's estimate of how much of its life is used, which can go past 100
+    pub percentage_used: Option<u8>,
+    pub available_spare: Option<u8>,
+    pub critical_warning: Option<u8>,
+    pub media_errors: Option<u64>,
+    pub bytes_written: Option<u64>,
+    pub power_on_hours: Option<u64>,
+    pub power_cycles: Option<u64>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Health {
+    fn new(identity: Identity, interface: &'static str, passed: bool) -> Self {
+        Health {
+            identity,
+            interface,
+            passed,
+            temperature: None,
+            reallocated_sectors: None,
+            pending_sectors: None,
+            uncorrectable_sectors: None,
+            percentage_used: None,
+            available_spare: None,
+            critical_warning: None,
+            media_errors: None,
+            bytes_written: None,
+            power_on_hours: None,
+            power_cycles: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// From SMART READ DATA and SMART RETURN STATUS
+    pub fn from_ata(identity: Identity, data: &[u8], threshold_exceeded: bool) -> Self {
+        let mut health = Health::new(identity, "ATA", !threshold_exceeded);
+        let attributes = parse_attributes(data);
+        let raw = |id: u8| attributes.iter().find(|a| a.id == id).map(|a| a.raw);
+        // Temperatures keep min and max in the higher bytes
+        health.temperature = raw(TEMPERATURE).or_else(|| raw(AIRFLOW_TEMPERATURE)).map(|t| t as u8 as i16);
+        health.reallocated_sectors = raw(REALLOCATED_SECTORS);
+        health.pending_sectors = raw(PENDING_SECTORS);
+        health.uncorrectable_sectors = raw(UNCORRECTABLE_SECTORS);
+        health.power_on_hours = raw(POWER_ON_HOURS).map(|h| h & 0xffff_ffff); // Some drives put milliseconds on top
+        health.power_cycles = raw(POWER_CYCLES);
+        health.attributes = attributes;
+        health
+    }
+
+    /// From the SMART / health information log page
+    pub fn from_nvme(identity: Identity, log: &[u8]) -> Self {
+        let critical_warning = log[0];
+        let mut health = Health::new(identity, "NVMe", critical_warning == 0);
+        // 128 bit counters, but the top half being set would be something
+        let counter = |offset: usize| LittleEndian::read_u64(&log[offset..]);
+        health.critical_warning = Some(critical_warning);
+        health.temperature = match LittleEndian::read_u16(&log[1..]) {
+            0 => None,
+            kelvin => Some(kelvin as i16 - 273),
+        };
+        health.available_spare = Some(log[3]);
+        health.percentage_used = Some(log[5]);
+        health.bytes_written = Some(counter(48).saturating_mul(NVME_DATA_UNIT));
+        health.power_cycles = Some(counter(112));
+        health.power_on_hours = Some(counter(128));
+        health.media_errors = Some(counter(160));
+        health
+    }
+}
+
+fn parse_attributes(data: &[u8]) -> Vec<Attribute> {
+    let table = match data.get(2..2 + ATTRIBUTE_COUNT * ATTRIBUTE_SIZE) {
+        Some(table) => table,
+        None => return Vec::new(),
+    };
+    table.chunks(ATTRIBUTE_SIZE).filter(|a| a[0] != 0).map(|a| Attribute {
+        id: a[0],
+        current: a[3],
+        worst: a[4],
+        raw: a[5..11].iter().rev().fold(0, |n, b| n << 8 | *b as u64),
+    }).collect()
+}
+
+impl Drive {
+    /// Reads the drive's SMART data. Fails with Unsupported for ATA drives with SMART turned off
+    pub fn health(&self) -> Result<Health> {
+        let identity = self.identity()?;
+        match self.interface {
+            Interface::Ata(ref device) => {
+                let identify = device.identify()?;
+                if !identify.smart_supported || !identify.smart_enabled {
+                    return Err(::EfiErrorKind::Unsupported.into());
+                }
+                let data = device.smart_read_data()?;
+                Ok(Health::from_ata(identity, &data, device.smart_threshold_exceeded()?))
+            }
+            Interface::Nvme(ref namespace) => Ok(Health::from_nvme(identity, &namespace.smart_log()?)),
+        }
+    }
+}
+
+/// Every drive's health
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HealthReport {
+    pub disks: Vec<Health>,
+}
+
+impl HealthReport {
+    pub fn all_passed(&self) -> bool {
+        self.disks.iter().all(|d| d.passed)
+    }
+}
+
+/// The health of every drive we can get SMART data from. Drives we can't are left out
+pub fn health_report() -> HealthReport {
+    let drives = Drive::all().unwrap_or_default();
+    HealthReport { disks: drives.iter().filter_map(|d| d.health().ok()).collect() }
+}
+
+impl Display for Health {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {} ({}): {}", self.interface, self.identity.model, self.identity.serial, if self.passed { "PASSED" } else { "FAILING" })?;
+        if let Some(t) = self.temperature {
+            write!(f, ", {} C", t)?;
+        }
+        if let Some(used) = self.percentage_used {
+            write!(f, ", {}% used", used)?;
+        }
+        for &(name, count) in &[("reallocated", self.reallocated_sectors), ("pending", self.pending_sectors), ("uncorrectable", self.uncorrectable_sectors), ("media errors", self.media_errors)] {
+            match count {
+                Some(n) if n != 0 => write!(f, ", {} {}", n, name)?,
+                _ => {}
+            }
+        }
+        if let Some(hours) = self.power_on_hours {
+            write!(f, ", {} hours", hours)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for disk in &self.disks {
+            writeln!(f, "{}", disk)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for Attribute {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("id", &self.id);
+        value.set("current", &self.current);
+        value.set("worst", &self.worst);
+        value.set("raw", &self.raw);
+        value
+    }
+}
+
+impl ToJson for Health {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("interface", self.interface);
+        value.set("model", &self.identity.model);
+        value.set("serial", &self.identity.serial);
+        value.set("firmware", &self.identity.firmware);
+        value.set("passed", &self.passed);
+        value.set("temperature", &self.temperature);
+        value.set("reallocated_sectors", &self.reallocated_sectors);
+        value.set("pending_sectors", &self.pending_sectors);
+        value.set("uncorrectable_sectors", &self.uncorrectable_sectors);
+        value.set("percentage_used", &self.percentage_used);
+        value.set("available_spare", &self.available_spare);
+        value.set("critical_warning", &self.critical_warning);
+        value.set("media_errors", &self.media_errors);
+        value.set("bytes_written", &self.bytes_written);
+        value.set("power_on_hours", &self.power_on_hours);
+        value.set("power_cycles", &self.power_cycles);
+        value.set("attributes", &self.attributes);
+        value
+    }
+}
+
+impl ToJson for HealthReport {
+    fn to_json(&self) -> Value {
+        self.disks.to_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn normalises_ata_and_nvme() {
+        let mut data = [0u8; 512];
+        let mut attribute = |slot: usize, id: u8, current: u8, raw: &[u8]| {
+            let a = &mut data[2 + slot * ATTRIBUTE_SIZE..][..ATTRIBUTE_SIZE];
+            a[0] = id;
+            a[3] = current;
+            a[4] = current;
+            a[5..5 + raw.len()].copy_from_slice(raw);
+        };
+        attribute(0, REALLOCATED_SECTORS, 100, &[8]);
+        attribute(1, POWER_ON_HOURS, 95, &[0x10, 0x27]);
+        attribute(2, TEMPERATURE, 64, &[36, 0, 20, 0, 45, 0]);
+        let identity = Identity { model: "WDC WD10EZEX".into(), serial: "WD-1".into(), firmware: "01.01A01".into() };
+        let ata = Health::from_ata(identity.clone(), &data, false);
+        assert_eq!(ata.attributes.len(), 3);
+        assert_eq!(ata.temperature, Some(36));
+        assert_eq!(ata.reallocated_sectors, Some(8));
+        assert_eq!(ata.power_on_hours, Some(10_000));
+        assert_eq!(ata.pending_sectors, None);
+        assert_eq!(ata.to_string(), "ATA WDC WD10EZEX (WD-1): PASSED, 36 C, 8 reallocated, 10000 hours");
+        assert!(!Health::from_ata(identity.clone(), &data, true).passed);
+
+        let mut log = [0u8; 512];
+        log[1..3].copy_from_slice(&311u16.to_le_bytes());
+        log[3] = 100;
+        log[5] = 3;
+        log[48] = 2;
+        log[128] = 42;
+        let nvme = Health::from_nvme(identity.clone(), &log);
+        assert!(nvme.passed);
+        assert_eq!(nvme.temperature, Some(38));
+        assert_eq!(nvme.bytes_written, Some(1_024_000));
+        assert_eq!(nvme.media_errors, Some(0));
+        assert_eq!(nvme.to_string(), "NVMe WDC WD10EZEX (WD-1): PASSED, 38 C, 3% used, 42 hours");
+        log[0] = 0x04;
+        let report = HealthReport { disks: vec![ata, Health::from_nvme(identity, &log)] };
+        assert!(!report.all_passed());
+        assert!(::json::to_string(&report).contains(r#""critical_warning":4"#));
+    }
+}
@@ -0,0 +1,133 @@
+// Whole drives and the commands that go straight to them, past the block layer: identify data, SMART health,
+// erasing and sanitizing. Firmware's ATA and NVMe pass-through protocols sit on the controller, so a drive is found
+// from its block device's path: the controller is the nearest handle on the path with the protocol, and the rest of
+// the path is the node that says which device or namespace on it.
+
+pub mod ata;
+pub mod nvme;
+mod health;
+mod sanitize;
+
+pub use self::ata::AtaDevice;
+pub use self::nvme::NvmeNamespace;
+pub use self::health::{health_report, Attribute, Health, HealthReport};
+pub use self::sanitize::{sanitize, methods, Method, Options, Progress};
+
+use ffi::{
+    ata_pass_thru::EFI_ATA_PASS_THRU_PROTOCOL_GUID,
+    nvme_pass_thru::EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID,
+    media::EFI_BLOCK_IO_PROTOCOL_GUID,
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_HANDLE,
+    EFI_GUID,
+};
+use boot_services::{device_path, locate_device_path, locate_handles};
+use fs::BlockIo;
+use component_name;
+use opal::Opal;
+use storage_security::{StorageSecurity, PROTOCOL_TCG};
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::{string::String, vec::Vec};
+use core::{mem, ptr};
+
+/// How the drive is reached
+pub enum Interface {
+    Ata(AtaDevice),
+    Nvme(NvmeNamespace),
+}
+
+/// Model, serial number and firmware revision, as the drive reports them
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Identity {
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+}
+
+/// A drive behind ATA or NVMe pass-through
+pub struct Drive {
+    handle: EFI_HANDLE,
+    interface: Interface,
+}
+
+impl Drive {
+    /// Every whole-disk block device we can reach with pass-through commands
+    pub fn all() -> Result<Vec<Self>> {
+        let mut drives = Vec::new();
+        for handle in locate_handles(&EFI_BLOCK_IO_PROTOCOL_GUID)? {
+            match BlockIo::new(handle) {
+                Ok(ref block_io) if !block_io.is_partition() => {}
+                _ => continue,
+            }
+            if let Ok(drive) = Self::new(handle) {
+                drives.push(drive);
+            }
+        }
+        Ok(drives)
+    }
+
+    /// The drive behind a whole-disk block device handle. Fails with Unsupported if it isn't on an ATA or NVMe
+    /// controller with pass-through
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let path = device_path(handle)?;
+        if let Ok((controller, node)) = locate_device_path(&EFI_ATA_PASS_THRU_PROTOCOL_GUID, path) {
+            if let Ok(device) = AtaDevice::from_path(controller, node) {
+                return Ok(Drive { handle, interface: Interface::Ata(device) });
+            }
+        }
+        if let Ok((controller, node)) = locate_device_path(&EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID, path) {
+            if let Ok(namespace) = NvmeNamespace::from_path(controller, node) {
+                return Ok(Drive { handle, interface: Interface::Nvme(namespace) });
+            }
+        }
+        Err(EfiErrorKind::Unsupported.into())
+    }
+
+    /// The block device handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// What the drive's controller driver calls it, e.g. "QEMU HARDDISK". Identity has the model the drive reports
+    pub fn name(&self) -> Option<String> {
+        component_name::controller_name(self.handle)
+    }
+
+    pub fn interface(&self) -> &Interface {
+        &self.interface
+    }
+
+    pub fn identity(&self) -> Result<Identity> {
+        match self.interface {
+            Interface::Ata(ref device) => {
+                let identify = device.identify()?;
+                Ok(Identity { model: identify.model, serial: identify.serial, firmware: identify.firmware })
+            }
+            Interface::Nvme(ref namespace) => {
+                let controller = namespace.identify_controller()?;
+                Ok(Identity { model: controller.model, serial: controller.serial, firmware: controller.firmware })
+            }
+        }
+    }
+
+    /// The drive as a self-encrypting drive, if it is one
+    pub fn opal(&self) -> Option<Opal> {
+        let device = StorageSecurity::new(self.handle).ok()?;
+        if !device.supported_protocols().ok()?.contains(&PROTOCOL_TCG) {
+            return None;
+        }
+        Opal::new(device).ok()
+    }
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> Result<*const T> {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+    }
+    if protocol.is_null() {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    Ok(protocol)
+}
@@ -0,0 +1,269 @@
+// NVMe admin commands through EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL, which the NVMe driver installs on the controller.
+// Namespaces are what become block devices; most admin commands are for the controller and take namespace 0.
+
+use ffi::{
+    nvme_pass_thru::*,
+    device_path::EFI_DEVICE_PATH_PROTOCOL,
+    EFI_HANDLE,
+    VOID,
+};
+use fs::disk::Bounce;
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::string::String;
+use core::{ptr, time::Duration};
+
+pub const GET_LOG_PAGE: u8 = 0x02;
+pub const IDENTIFY: u8 = 0x06;
+pub const FORMAT_NVM: u8 = 0x80;
+pub const SANITIZE: u8 = 0x84;
+
+pub const LOG_SMART: u8 = 0x02;
+pub const LOG_SANITIZE_STATUS: u8 = 0x81;
+
+pub const ALL_NAMESPACES: u32 = 0xffff_ffff;
+
+const IDENTIFY_NAMESPACE: u32 = 0x00;
+const IDENTIFY_CONTROLLER: u32 = 0x01;
+const IDENTIFY_SIZE: usize = 4096;
+
+/// Format NVM's secure erase settings
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SecureErase {
+    None = 0,
+    UserData = 1,
+    Cryptographic = 2,
+}
+
+/// Sanitize's actions
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SanitizeAction {
+    ExitFailureMode = 1,
+    BlockErase = 2,
+    Overwrite = 3,
+    CryptoErase = 4,
+}
+
+/// The parts of the identify controller data we use
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Controller {
+    pub serial: String,
+    pub model: String,
+    pub firmware: String,
+    /// OACS bit 1
+    pub format_supported: bool,
+    /// FNA bit 2, Format NVM can do a cryptographic erase
+    pub crypto_format_supported: bool,
+    /// FNA bit 0, Format NVM applies to every namespace rather than the one given
+    pub format_all_namespaces: bool,
+    pub sanitize_crypto_erase: bool,
+    pub sanitize_block_erase: bool,
+    pub sanitize_overwrite: bool,
+}
+
+impl Controller {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < IDENTIFY_SIZE {
+            return None;
+        }
+        let string = |range: &[u8]| String::from_utf8_lossy(range).trim().into();
+        let oacs = LittleEndian::read_u16(&data[256..]);
+        let fna = data[524];
+        let sanicap = LittleEndian::read_u32(&data[328..]);
+        Some(Controller {
+            serial: string(&data[4..24]),
+            model: string(&data[24..64]),
+            firmware: string(&data[64..72]),
+            format_supported: oacs & 0x02 != 0,
+            crypto_format_supported: fna & 0x04 != 0,
+            format_all_namespaces: fna & 0x01 != 0,
+            sanitize_crypto_erase: sanicap & 0x01 != 0,
+            sanitize_block_erase: sanicap & 0x02 != 0,
+            sanitize_overwrite: sanicap & 0x04 != 0,
+        })
+    }
+}
+
+/// The sanitize status log page
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SanitizeStatus {
+    /// Out of 65536, while one is in progress
+    pub progress: u16,
+    /// SSTAT bits 2:0
+    pub state: u8,
+    /// Estimated seconds for each kind, 0xFFFFFFFF if the controller doesn't know
+    pub overwrite_time: u32,
+    pub block_erase_time: u32,
+    pub crypto_erase_time: u32,
+}
+
+impl SanitizeStatus {
+    pub const NEVER: u8 = 0;
+    pub const COMPLETED: u8 = 1;
+    pub const IN_PROGRESS: u8 = 2;
+    pub const FAILED: u8 = 3;
+    pub const COMPLETED_NO_DEALLOCATE: u8 = 4;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 20 {
+            return None;
+        }
+        Some(SanitizeStatus {
+            progress: LittleEndian::read_u16(data),
+            state: data[2] & 0x07,
+            overwrite_time: LittleEndian::read_u32(&data[8..]),
+            block_erase_time: LittleEndian::read_u32(&data[12..]),
+            crypto_erase_time: LittleEndian::read_u32(&data[16..]),
+        })
+    }
+}
+
+/// A namespace on an NVMe controller
+pub struct NvmeNamespace {
+    protocol: *const EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL,
+    nsid: u32,
+}
+
+impl NvmeNamespace {
+    pub fn new(controller: EFI_HANDLE, nsid: u32) -> Result<Self> {
+        let protocol = super::open_protocol::<EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL>(controller, &EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID)?;
+        Ok(NvmeNamespace { protocol, nsid })
+    }
+
+    // The namespace an NVMe device path node on the controller is for
+    pub(super) fn from_path(controller: EFI_HANDLE, node: *const EFI_DEVICE_PATH_PROTOCOL) -> Result<Self> {
+        let protocol = super::open_protocol::<EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL>(controller, &EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID)?;
+        let mut nsid = 0u32;
+        unsafe {
+            ret_on_err!(((*protocol).GetNamespace)(protocol, node, &mut nsid));
+        }
+        Ok(NvmeNamespace { protocol, nsid })
+    }
+
+    pub fn nsid(&self) -> u32 {
+        self.nsid
+    }
+
+    /// Runs an admin command, with `data` going whichever way the command says. Returns completion dword 0.
+    /// A zero timeout waits for as long as it takes. Fails with DeviceError if the status isn't success
+    pub fn admin(&self, opcode: u8, nsid: u32, cdw: [u32; 6], data: Option<&mut [u8]>, timeout: Duration) -> Result<u32> {
+        let command = EFI_NVM_EXPRESS_COMMAND {
+            Cdw0: opcode as u32,
+            Flags: CDW10_VALID | CDW11_VALID | CDW12_VALID | CDW13_VALID | CDW14_VALID | CDW15_VALID,
+            Nsid: nsid,
+            Cdw10: cdw[0],
+            Cdw11: cdw[1],
+            Cdw12: cdw[2],
+            Cdw13: cdw[3],
+            Cdw14: cdw[4],
+            Cdw15: cdw[5],
+            ..Default::default()
+        };
+        let mut completion = EFI_NVM_EXPRESS_COMPLETION::default();
+        let mut packet = EFI_NVM_EXPRESS_PASS_THRU_COMMAND_PACKET {
+            CommandTimeout: (timeout.as_nanos() / 100) as u64,
+            TransferBuffer: ptr::null_mut(),
+            TransferLength: 0,
+            MetadataBuffer: ptr::null_mut(),
+            MetadataLength: 0,
+            QueueType: NVME_ADMIN_QUEUE,
+            NvmeCmd: &command,
+            NvmeCompletion: &mut completion,
+        };
+        let align = unsafe { (*(*self.protocol).Mode).IoAlign as usize }.max(1);
+        let mut bounce = match data {
+            Some(ref buf) => Some(Bounce::new(buf.len(), align)?),
+            None => None,
+        };
+        if let Some(ref mut bounce) = bounce {
+            let buf = bounce.as_mut_slice();
+            packet.TransferBuffer = buf.as_mut_ptr() as *mut VOID;
+            packet.TransferLength = buf.len() as u32;
+        }
+
+        let status = unsafe { traced!(((*self.protocol).PassThru)(self.protocol, nsid, &mut packet, ptr::null())) };
+        if let (Some(buf), Some(ref mut bounce)) = (data, bounce) {
+            buf.copy_from_slice(bounce.as_mut_slice());
+        }
+        ret_on_err!(status);
+        if completion.DW3 >> 17 != 0 {
+            return Err(EfiErrorKind::DeviceError.into());
+        }
+        Ok(completion.DW0)
+    }
+
+    pub fn identify_controller(&self) -> Result<Controller> {
+        let mut data = vec![0u8; IDENTIFY_SIZE];
+        self.admin(IDENTIFY, 0, [IDENTIFY_CONTROLLER, 0, 0, 0, 0, 0], Some(&mut data), Duration::from_secs(5))?;
+        Controller::parse(&data).ok_or_else(|| EfiErrorKind::DeviceError.into())
+    }
+
+    /// The LBA format the namespace uses now, from FLBAS
+    pub fn lba_format(&self) -> Result<u8> {
+        let mut data = vec![0u8; IDENTIFY_SIZE];
+        self.admin(IDENTIFY, self.nsid, [IDENTIFY_NAMESPACE, 0, 0, 0, 0, 0], Some(&mut data), Duration::from_secs(5))?;
+        Ok(data[26] & 0x0f)
+    }
+
+    /// Reads a log page into `buf`, whose length must be a multiple of 4
+    pub fn log_page(&self, nsid: u32, id: u8, buf: &mut [u8]) -> Result<()> {
+        let dwords = (buf.len() / 4) as u32;
+        if dwords == 0 || buf.len() & 3 != 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let cdw10 = (dwords - 1) << 16 | id as u32;
+        self.admin(GET_LOG_PAGE, nsid, [cdw10, 0, 0, 0, 0, 0], Some(buf), Duration::from_secs(5)).map(|_| ())
+    }
+
+    /// The SMART / health information log for the whole controller
+    pub fn smart_log(&self) -> Result<[u8; 512]> {
+        let mut data = [0u8; 512];
+        self.log_page(ALL_NAMESPACES, LOG_SMART, &mut data)?;
+        Ok(data)
+    }
+
+    /// Formats the namespace with its current LBA format. Blocks until it's done
+    pub fn format(&self, erase: SecureErase) -> Result<()> {
+        let cdw10 = self.lba_format()? as u32 | (erase as u32) << 9;
+        self.admin(FORMAT_NVM, self.nsid, [cdw10, 0, 0, 0, 0, 0], None, Duration::default()).map(|_| ())
+    }
+
+    /// Starts sanitizing the whole controller, which carries on in the background. See sanitize_status()
+    pub fn sanitize(&self, action: SanitizeAction) -> Result<()> {
+        self.admin(SANITIZE, 0, [action as u32, 0, 0, 0, 0, 0], None, Duration::from_secs(5)).map(|_| ())
+    }
+
+    pub fn sanitize_status(&self) -> Result<SanitizeStatus> {
+        let mut data = [0u8; 512];
+        self.log_page(0, LOG_SANITIZE_STATUS, &mut data)?;
+        SanitizeStatus::parse(&data).ok_or_else(|| EfiErrorKind::DeviceError.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_identify_and_sanitize_status() {
+        let mut data = vec![0u8; IDENTIFY_SIZE];
+        data[4..24].copy_from_slice(b"S4EVNX0N123456      ");
+        data[24..64].copy_from_slice(b"Samsung SSD 970 EVO Plus 1TB            ");
+        data[64..72].copy_from_slice(b"2B2QEXM7");
+        data[256] = 0x17;
+        data[328] = 0x03;
+        data[524] = 0x04;
+        let controller = Controller::parse(&data).unwrap();
+        assert_eq!(controller.model, "Samsung SSD 970 EVO Plus 1TB");
+        assert_eq!(controller.serial, "S4EVNX0N123456");
+        assert!(controller.format_supported && controller.crypto_format_supported && !controller.format_all_namespaces);
+        assert!(controller.sanitize_crypto_erase && controller.sanitize_block_erase && !controller.sanitize_overwrite);
+        assert!(Controller::parse(&data[..100]).is_none());
+
+        let log = [0x00, 0x80, 0x02, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 120, 0, 0, 0, 5, 0, 0, 0];
+        let status = SanitizeStatus::parse(&log).unwrap();
+        assert_eq!(status.progress, 0x8000);
+        assert_eq!(status.state, SanitizeStatus::IN_PROGRESS);
+        assert_eq!(status.block_erase_time, 120);
+    }
+}
@@ -0,0 +1,228 @@
+// Erasing everything on a drive with the drive's own commands rather than by writing over it from here, which is
+// faster and also gets the spare and remapped blocks that writes can't reach.
+//
+// Drives offer some of: NVMe Sanitize and ATA SANITIZE DEVICE (which cover the whole device, carry on in the
+// background and survive a reset), NVMe Format NVM with secure erase, a TCG Opal revert (which throws the keys
+// away), and the old ATA SECURITY ERASE UNIT. sanitize() takes the best one it can, or the one asked for, and reports
+// progress while it waits. Firmware often freezes ATA security before booting us, which rules out SECURITY ERASE
+// until a power cycle.
+
+use super::{Drive, Interface};
+use super::ata::{self, AtaDevice};
+use super::nvme::{self, NvmeNamespace, SanitizeAction, SecureErase};
+use firmware::firmware;
+use opal::{uid::Uid, Opal};
+use {Result, EfiErrorKind};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// The user password we set for SECURITY ERASE UNIT, which the drive forgets once it's done
+const ERASE_PASSWORD: &[u8] = b"sanitize";
+
+/// Ways of erasing a drive, best first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Method {
+    NvmeSanitizeCryptoErase,
+    NvmeSanitizeBlockErase,
+    AtaSanitizeCryptoScramble,
+    AtaSanitizeBlockErase,
+    NvmeFormatCryptoErase,
+    /// Needs Options::opal_authority
+    OpalRevert,
+    NvmeFormatUserDataErase,
+    AtaEnhancedSecurityErase,
+    AtaSecurityErase,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Options<'a> {
+    /// The method to use rather than the best supported one
+    pub method: Option<Method>,
+    /// SID or PSID and its password, to allow OpalRevert
+    pub opal_authority: Option<(Uid, &'a [u8])>,
+}
+
+/// How far along it is, passed to sanitize()'s callback about once a second
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub method: Method,
+    pub elapsed: Duration,
+    /// How long the drive says it takes, if it does
+    pub estimate: Option<Duration>,
+    /// None for methods that block until they're done
+    pub percent: Option<u8>,
+}
+
+/// What the drive supports, best first
+pub fn methods(drive: &Drive) -> Result<Vec<Method>> {
+    let mut methods = Vec::new();
+    match drive.interface {
+        Interface::Ata(ref device) => {
+            let identify = device.identify()?;
+            if identify.sanitize.crypto_scramble {
+                methods.push(Method::AtaSanitizeCryptoScramble);
+            }
+            if identify.sanitize.block_erase {
+                methods.push(Method::AtaSanitizeBlockErase);
+            }
+            let security = identify.security;
+            if security.supported && !security.frozen && !security.locked && !security.enabled {
+                if security.enhanced_erase {
+                    methods.push(Method::AtaEnhancedSecurityErase);
+                }
+                methods.push(Method::AtaSecurityErase);
+            }
+        }
+        Interface::Nvme(ref namespace) => {
+            let controller = namespace.identify_controller()?;
+            if controller.sanitize_crypto_erase {
+                methods.push(Method::NvmeSanitizeCryptoErase);
+            }
+            if controller.sanitize_block_erase {
+                methods.push(Method::NvmeSanitizeBlockErase);
+            }
+            if controller.format_supported {
+                if controller.crypto_format_supported {
+                    methods.push(Method::NvmeFormatCryptoErase);
+                }
+                methods.push(Method::NvmeFormatUserDataErase);
+            }
+        }
+    }
+    if drive.opal().is_some() {
+        methods.push(Method::OpalRevert);
+    }
+    methods.sort();
+    Ok(methods)
+}
+
+/// Erases the whole drive, everything on it and every namespace, calling `progress` while it goes. Returns the
+/// method used. Fails with Unsupported if there's no method to use, or the one asked for isn't supported
+pub fn sanitize<F: FnMut(&Progress)>(drive: &Drive, options: &Options, mut progress: F) -> Result<Method> {
+    let method = choose(&methods(drive)?, options)?;
+    let mut report = Progress { method, elapsed: Duration::default(), estimate: None, percent: None };
+    match drive.interface {
+        Interface::Ata(ref device) => match method {
+            Method::AtaSanitizeCryptoScramble => ata_sanitize(device, ata::CRYPTO_SCRAMBLE_EXT, &mut report, &mut progress)?,
+            Method::AtaSanitizeBlockErase => ata_sanitize(device, ata::BLOCK_ERASE_EXT, &mut report, &mut progress)?,
+            Method::AtaEnhancedSecurityErase => security_erase(device, true, &mut report, &mut progress)?,
+            Method::AtaSecurityErase => security_erase(device, false, &mut report, &mut progress)?,
+            _ => revert(drive, options, &mut report, &mut progress)?,
+        },
+        Interface::Nvme(ref namespace) => match method {
+            Method::NvmeSanitizeCryptoErase => nvme_sanitize(namespace, SanitizeAction::CryptoErase, &mut report, &mut progress)?,
+            Method::NvmeSanitizeBlockErase => nvme_sanitize(namespace, SanitizeAction::BlockErase, &mut report, &mut progress)?,
+            Method::NvmeFormatCryptoErase => {
+                progress(&report);
+                namespace.format(SecureErase::Cryptographic)?;
+            }
+            Method::NvmeFormatUserDataErase => {
+                progress(&report);
+                namespace.format(SecureErase::UserData)?;
+            }
+            _ => revert(drive, options, &mut report, &mut progress)?,
+        },
+    }
+    Ok(method)
+}
+
+fn choose(available: &[Method], options: &Options) -> Result<Method> {
+    let usable = |m: &&Method| **m != Method::OpalRevert || options.opal_authority.is_some();
+    let method = match options.method {
+        Some(method) => available.iter().filter(usable).find(|&&m| m == method),
+        None => available.iter().find(usable),
+    };
+    method.cloned().ok_or_else(|| EfiErrorKind::Unsupported.into())
+}
+
+fn percent(progress: u16) -> u8 {
+    (progress as u32 * 100 / 0x10000) as u8
+}
+
+fn wait(report: &mut Progress) -> Result<()> {
+    firmware().stall(POLL_INTERVAL)?;
+    report.elapsed += POLL_INTERVAL;
+    Ok(())
+}
+
+fn ata_sanitize<F: FnMut(&Progress)>(device: &AtaDevice, feature: u16, report: &mut Progress, progress: &mut F) -> Result<()> {
+    device.sanitize(feature)?;
+    loop {
+        // An unsuccessful sanitize shows up as ERR on the status command, and execute() turns that into DeviceError
+        let status = device.sanitize_status()?;
+        if !status.in_progress {
+            return if status.completed { Ok(()) } else { Err(EfiErrorKind::DeviceError.into()) };
+        }
+        report.percent = Some(percent(status.progress));
+        progress(report);
+        wait(report)?;
+    }
+}
+
+fn security_erase<F: FnMut(&Progress)>(device: &AtaDevice, enhanced: bool, report: &mut Progress, progress: &mut F) -> Result<()> {
+    let identify = device.identify()?;
+    report.estimate = if enhanced { identify.enhanced_erase_time } else { identify.erase_time };
+    progress(report);
+    device.security_set_password(ERASE_PASSWORD)?;
+    let result = device.security_erase_unit(ERASE_PASSWORD, enhanced);
+    if result.is_err() {
+        // Don't leave the drive with a password nobody knows about
+        let _ = device.security_disable_password(ERASE_PASSWORD);
+    }
+    result
+}
+
+fn nvme_sanitize<F: FnMut(&Progress)>(namespace: &NvmeNamespace, action: SanitizeAction, report: &mut Progress, progress: &mut F) -> Result<()> {
+    namespace.sanitize(action)?;
+    loop {
+        let status = namespace.sanitize_status()?;
+        let seconds = match action {
+            SanitizeAction::CryptoErase => status.crypto_erase_time,
+            SanitizeAction::BlockErase => status.block_erase_time,
+            _ => status.overwrite_time,
+        };
+        report.estimate = if seconds == u32::MAX { None } else { Some(Duration::from_secs(seconds as u64)) };
+        match status.state {
+            nvme::SanitizeStatus::IN_PROGRESS => {}
+            nvme::SanitizeStatus::COMPLETED | nvme::SanitizeStatus::COMPLETED_NO_DEALLOCATE => return Ok(()),
+            _ => return Err(EfiErrorKind::DeviceError.into()),
+        }
+        report.percent = Some(percent(status.progress));
+        progress(report);
+        wait(report)?;
+    }
+}
+
+fn revert<F: FnMut(&Progress)>(drive: &Drive, options: &Options, report: &mut Progress, progress: &mut F) -> Result<()> {
+    let (authority, password) = options.opal_authority.ok_or(EfiErrorKind::InvalidParameter)?;
+    let opal: Opal = drive.opal().ok_or(EfiErrorKind::Unsupported)?;
+    progress(report);
+    opal.revert(authority, password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opal::uid;
+
+    #[test]
+    fn chooses_the_best_usable_method() {
+        let available = [Method::NvmeSanitizeBlockErase, Method::OpalRevert, Method::NvmeFormatUserDataErase];
+        assert_eq!(choose(&available, &Options::default()).unwrap(), Method::NvmeSanitizeBlockErase);
+        let options = Options { method: Some(Method::OpalRevert), opal_authority: None };
+        assert_eq!(choose(&available, &options).unwrap_err().kind(), EfiErrorKind::Unsupported);
+        let options = Options { method: Some(Method::OpalRevert), opal_authority: Some((uid::PSID, b"psid")) };
+        assert_eq!(choose(&available, &options).unwrap(), Method::OpalRevert);
+        assert_eq!(choose(&[Method::OpalRevert], &Options::default()).unwrap_err().kind(), EfiErrorKind::Unsupported);
+        let options = Options { method: Some(Method::AtaSecurityErase), opal_authority: None };
+        assert!(choose(&available, &options).is_err());
+
+        let mut sorted = [Method::AtaSecurityErase, Method::OpalRevert, Method::NvmeSanitizeCryptoErase];
+        sorted.sort();
+        assert_eq!(sorted, [Method::NvmeSanitizeCryptoErase, Method::OpalRevert, Method::AtaSecurityErase]);
+        assert_eq!(percent(0x8000), 50);
+        assert_eq!(percent(0xffff), 99);
+    }
+}
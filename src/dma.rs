@@ -0,0 +1,121 @@
+// Buffers for devices to read and write directly.
+//
+// Block I/O, the pass-thru protocols and bus-mastering PCI devices want buffers aligned to what the controller asks
+// for (IoAlign), and 32-bit-only controllers want them below 4GiB too. A Vec has neither: it's aligned to its element
+// type and comes from wherever the pool is. Handing one over works until it happens to land badly, then fails with
+// InvalidParameter or, worse, the controller DMAs somewhere else. A DmaBuffer comes from pages and is aligned and
+// placed as asked for, and derefs to a slice so it goes wherever a &[u8]/&mut [u8] does. Block I/O uses it as is
+// rather than bouncing through a copy.
+
+use pages::{Pages, PageAlloc, MemoryType, PAGE_SIZE, MAX_ADDRESS_32BIT};
+use {Result, EfiErrorKind};
+use core::{ops::{Deref, DerefMut}, slice};
+
+/// Page-backed memory with a guaranteed alignment, and optionally below an address
+#[derive(Debug)]
+pub struct DmaBuffer {
+    pages: Pages,
+    offset: usize,
+    len: usize,
+}
+
+impl DmaBuffer {
+    /// `len` bytes, page aligned
+    pub fn new(len: usize) -> Result<Self> {
+        Self::allocate(len, PAGE_SIZE, None)
+    }
+
+    /// `len` bytes aligned to `align`, a power of two. 0 and 1 mean no alignment, as IoAlign has it
+    pub fn aligned(len: usize, align: usize) -> Result<Self> {
+        Self::allocate(len, align, None)
+    }
+
+    /// The same but below 4GiB, for controllers that can only address 32 bits
+    pub fn below_4g(len: usize, align: usize) -> Result<Self> {
+        Self::allocate(len, align, Some(MAX_ADDRESS_32BIT))
+    }
+
+    /// `len` bytes aligned to `align` and, if there's a `max_addr`, with the last byte at or below it
+    pub fn allocate(len: usize, align: usize, max_addr: Option<u64>) -> Result<Self> {
+        let align = align.max(1);
+        if !align.is_power_of_two() {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        // Pages are page aligned already, anything more takes room to slide along
+        let slack = align.saturating_sub(PAGE_SIZE);
+        let mut alloc = PageAlloc::builder();
+        alloc.memory_type(MemoryType::BootServicesData);
+        if let Some(max_addr) = max_addr {
+            alloc.below(max_addr);
+        }
+        let pages = alloc.allocate_bytes(len.max(1) + slack)?;
+        let offset = (align - (pages.addr() as usize & (align - 1))) & (align - 1);
+        Ok(DmaBuffer { pages, offset, len })
+    }
+
+    /// Physical address of the first byte, which is where a device sees it unless there's an IOMMU in the way
+    pub fn addr(&self) -> u64 {
+        self.pages.addr() + self.offset as u64
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether it's aligned to `align`, for checking against something other than what it was allocated for
+    pub fn is_aligned(&self, align: usize) -> bool {
+        align <= 1 || self.addr() & (align as u64 - 1) == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.addr() as *const u8
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.addr() as *mut u8
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl DerefMut for DmaBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::mock;
+
+    #[test]
+    fn aligns_past_a_page() {
+        mock::install();
+        let mut buf = DmaBuffer::aligned(100, 64 * 1024).unwrap();
+        assert!(buf.is_aligned(64 * 1024));
+        assert_eq!(buf.len(), 100);
+        buf[99] = 0xaa;
+        assert_eq!(buf.as_slice()[99], 0xaa);
+        assert!(DmaBuffer::new(0).unwrap().is_empty());
+        assert_eq!(DmaBuffer::aligned(16, 24).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
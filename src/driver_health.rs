@@ -0,0 +1,301 @@
+// Driver health, via EFI_DRIVER_HEALTH_PROTOCOL: which devices a driver says need repairing or configuring before
+// they'll work, with the messages it gives for them, and asking it to repair them. Boot managers are meant to do
+// this before booting but plenty don't.
+//
+// Messages are HII strings, looked up in English if the driver has it and otherwise in the first language it has.
+
+use boot_services::{locate_handles, all_handles};
+use ffi::{
+    driver_health::*,
+    hii::{EFI_HII_STRING_PROTOCOL, EFI_HII_STRING_PROTOCOL_GUID, EFI_HII_HANDLE, EFI_STRING_ID},
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    EFI_HANDLE,
+    EFI_BUFFER_TOO_SMALL,
+    EFI_UNSUPPORTED,
+    CHAR8,
+    CHAR16,
+    UINTN,
+};
+use boxed::EfiBox;
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::{string::String, vec::Vec};
+use core::{mem, ptr};
+
+const PREFERRED_LANGUAGE: &[u8] = b"en-US\0";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    RepairRequired,
+    ConfigurationRequired,
+    Failed,
+    ReconnectRequired,
+    RebootRequired,
+    /// A status from a later version of the spec
+    Other(u32),
+}
+
+impl HealthStatus {
+    fn from_raw(status: EFI_DRIVER_HEALTH_STATUS) -> Self {
+        match status {
+            EFI_DRIVER_HEALTH_STATUS_HEALTHY => HealthStatus::Healthy,
+            EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED => HealthStatus::RepairRequired,
+            EFI_DRIVER_HEALTH_STATUS_CONFIGURATION_REQUIRED => HealthStatus::ConfigurationRequired,
+            EFI_DRIVER_HEALTH_STATUS_FAILED => HealthStatus::Failed,
+            EFI_DRIVER_HEALTH_STATUS_RECONNECT_REQUIRED => HealthStatus::ReconnectRequired,
+            EFI_DRIVER_HEALTH_STATUS_REBOOT_REQUIRED => HealthStatus::RebootRequired,
+            other => HealthStatus::Other(other),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        *self == HealthStatus::Healthy
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Driver specific. Zero if there isn't one
+    pub code: u64,
+    /// None if the string couldn't be found in the HII database
+    pub text: Option<String>,
+}
+
+/// What a driver said about a controller it manages, or about itself and everything it manages together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub driver: EFI_HANDLE,
+    pub controller: Option<EFI_HANDLE>,
+    pub child: Option<EFI_HANDLE>,
+    pub status: HealthStatus,
+    pub messages: Vec<Message>,
+    /// The HII form to show to configure it, for ConfigurationRequired
+    pub form: Option<EFI_HII_HANDLE>,
+}
+
+/// A driver that reports its health
+pub struct DriverHealth {
+    driver: EFI_HANDLE,
+    protocol: *const EFI_DRIVER_HEALTH_PROTOCOL,
+}
+
+impl DriverHealth {
+    /// The health protocol of the driver image on the handle. Fails with Unsupported if it hasn't got one
+    pub fn new(driver: EFI_HANDLE) -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_DRIVER_HEALTH_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).OpenProtocol)(driver, &EFI_DRIVER_HEALTH_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL));
+        }
+        if protocol.is_null() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        Ok(Self { driver, protocol })
+    }
+
+    /// Every driver that reports its health
+    pub fn all() -> Result<Vec<Self>> {
+        Ok(locate_handles(&EFI_DRIVER_HEALTH_PROTOCOL_GUID)?.into_iter().filter_map(|h| Self::new(h).ok()).collect())
+    }
+
+    pub fn driver(&self) -> EFI_HANDLE {
+        self.driver
+    }
+
+    /// The health of the driver and everything it manages taken together
+    pub fn status(&self) -> Result<Report> {
+        self.get(None, None)
+    }
+
+    /// The health of a controller the driver manages or, with `child`, of a child it made for it. Fails with
+    /// Unsupported if it doesn't manage the controller
+    pub fn controller_status(&self, controller: EFI_HANDLE, child: Option<EFI_HANDLE>) -> Result<Report> {
+        self.get(Some(controller), child)
+    }
+
+    /// Asks the driver to repair the controller, the status of which should then be checked again
+    pub fn repair(&self, controller: EFI_HANDLE, child: Option<EFI_HANDLE>) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).Repair)(self.protocol, controller, child.unwrap_or(ptr::null()), None));
+        }
+        Ok(())
+    }
+
+    /// A report for each controller the driver manages. Each handle is tried as a controller since the ones the
+    /// driver doesn't manage fail
+    pub fn controller_reports(&self) -> Result<Vec<Report>> {
+        let mut reports = Vec::new();
+        for handle in all_handles()? {
+            match self.controller_status(handle, None) {
+                Ok(report) => reports.push(report),
+                Err(e) if e.kind() == EfiErrorKind::Unsupported || e.kind() == EfiErrorKind::InvalidParameter => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(reports)
+    }
+
+    fn get(&self, controller: Option<EFI_HANDLE>, child: Option<EFI_HANDLE>) -> Result<Report> {
+        let mut status: EFI_DRIVER_HEALTH_STATUS = EFI_DRIVER_HEALTH_STATUS_HEALTHY;
+        let mut list: *const EFI_DRIVER_HEALTH_HII_MESSAGE = ptr::null();
+        let mut form: EFI_HII_HANDLE = ptr::null();
+        unsafe {
+            ret_on_err!(((*self.protocol).GetHealthStatus)(self.protocol, controller.unwrap_or(ptr::null()), child.unwrap_or(ptr::null()), &mut status, &mut list, &mut form));
+        }
+
+        let mut messages = Vec::new();
+        if !list.is_null() {
+            let list = unsafe { EfiBox::from_raw(list as *mut EFI_DRIVER_HEALTH_HII_MESSAGE) };
+            let mut entry = list.as_raw() as *const EFI_DRIVER_HEALTH_HII_MESSAGE;
+            unsafe {
+                while !(*entry).HiiHandle.is_null() {
+                    messages.push(Message { code: (*entry).MessageCode, text: hii_string((*entry).HiiHandle, (*entry).StringId) });
+                    entry = entry.add(1);
+                }
+            }
+        }
+
+        Ok(Report {
+            driver: self.driver,
+            controller,
+            child,
+            status: HealthStatus::from_raw(status),
+            messages,
+            form: if form.is_null() { None } else { Some(form) },
+        })
+    }
+}
+
+/// Every driver's overall report and, for the ones that aren't healthy, a report for each controller they manage
+pub fn reports() -> Result<Vec<Report>> {
+    let mut reports = Vec::new();
+    for driver in DriverHealth::all()? {
+        let overall = driver.status()?;
+        let healthy = overall.status.is_healthy();
+        reports.push(overall);
+        if !healthy {
+            reports.extend(driver.controller_reports()?);
+        }
+    }
+    Ok(reports)
+}
+
+/// Repairs every controller that needs it then reports on them all again
+pub fn repair_all() -> Result<Vec<Report>> {
+    for report in reports()? {
+        if let (HealthStatus::RepairRequired, Some(controller)) = (report.status, report.controller) {
+            DriverHealth::new(report.driver)?.repair(controller, report.child)?;
+        }
+    }
+    reports()
+}
+
+// A string from the HII database in English, or else the first language the package list has it in
+fn hii_string(package_list: EFI_HII_HANDLE, id: EFI_STRING_ID) -> Option<String> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_HII_STRING_PROTOCOL = ptr::null();
+    unsafe {
+        let status = traced!(((*bs).LocateProtocol)(&EFI_HII_STRING_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+        if ::to_res((), status).is_err() || protocol.is_null() {
+            return None;
+        }
+    }
+
+    get_string(protocol, PREFERRED_LANGUAGE, package_list, id).or_else(|| {
+        let mut size: UINTN = 0;
+        let status = unsafe { traced!(((*protocol).GetLanguages)(protocol, package_list, ptr::null_mut(), &mut size)) };
+        if status != EFI_BUFFER_TOO_SMALL {
+            return None;
+        }
+        let mut languages = vec![0u8; size];
+        let status = unsafe { traced!(((*protocol).GetLanguages)(protocol, package_list, languages.as_mut_ptr() as *mut CHAR8, &mut size)) };
+        ::to_res((), status).ok()?;
+        let mut first: Vec<u8> = languages.iter().cloned().take_while(|&c| c != b';' && c != 0).collect();
+        first.push(0);
+        get_string(protocol, &first, package_list, id)
+    })
+}
+
+fn get_string(protocol: *const EFI_HII_STRING_PROTOCOL, language: &[u8], package_list: EFI_HII_HANDLE, id: EFI_STRING_ID) -> Option<String> {
+    let mut buf: Vec<CHAR16> = Vec::new();
+    let mut size: UINTN = 0;
+    loop {
+        let status = unsafe { traced!(((*protocol).GetString)(protocol, language.as_ptr() as *const CHAR8, package_list, id, buf.as_mut_ptr(), &mut size, ptr::null_mut())) };
+        if status == EFI_BUFFER_TOO_SMALL {
+            buf.resize((size + 1) / 2, 0);
+            continue;
+        }
+        if status == EFI_UNSUPPORTED {
+            return None;
+        }
+        ::to_res((), status).ok()?;
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        return Some(String::from_utf16_lossy(&buf[..len]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::{EFI_INTERFACE_TYPE, EFI_MEMORY_TYPE}, EFI_GUID, EFI_STATUS, EFI_SUCCESS, VOID};
+    use testing::mock;
+    use alloc::boxed::Box;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    static CONTROLLER: AtomicUsize = AtomicUsize::new(0);
+    static REPAIRED: AtomicBool = AtomicBool::new(false);
+
+    // Has one controller, broken until it's repaired, and says why with a message it has no HII string for
+    extern "efiapi" fn get_health_status(_this: *const EFI_DRIVER_HEALTH_PROTOCOL, controller: EFI_HANDLE, _child: EFI_HANDLE, status: *mut EFI_DRIVER_HEALTH_STATUS, messages: *mut *const EFI_DRIVER_HEALTH_HII_MESSAGE, _form: *mut EFI_HII_HANDLE) -> EFI_STATUS {
+        if !controller.is_null() && controller as usize != CONTROLLER.load(Ordering::SeqCst) {
+            return EFI_UNSUPPORTED;
+        }
+        unsafe {
+            if REPAIRED.load(Ordering::SeqCst) {
+                *status = EFI_DRIVER_HEALTH_STATUS_HEALTHY;
+                return EFI_SUCCESS;
+            }
+            *status = EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED;
+            let mut list: *const VOID = ptr::null();
+            ((*system_table().BootServices).AllocatePool)(EFI_MEMORY_TYPE::EfiBootServicesData, 2 * mem::size_of::<EFI_DRIVER_HEALTH_HII_MESSAGE>(), &mut list);
+            let list = list as *mut EFI_DRIVER_HEALTH_HII_MESSAGE;
+            *list = EFI_DRIVER_HEALTH_HII_MESSAGE { HiiHandle: 1 as EFI_HII_HANDLE, StringId: 7, MessageCode: 0x42 };
+            *list.add(1) = EFI_DRIVER_HEALTH_HII_MESSAGE { HiiHandle: ptr::null(), StringId: 0, MessageCode: 0 };
+            *messages = list;
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn repair(_this: *const EFI_DRIVER_HEALTH_PROTOCOL, controller: EFI_HANDLE, _child: EFI_HANDLE, _notify: Option<EFI_DRIVER_HEALTH_REPAIR_NOTIFY>) -> EFI_STATUS {
+        if controller as usize != CONTROLLER.load(Ordering::SeqCst) {
+            return EFI_UNSUPPORTED;
+        }
+        REPAIRED.store(true, Ordering::SeqCst);
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn reports_and_repairs() {
+        mock::install();
+        let protocol = Box::leak(Box::new(EFI_DRIVER_HEALTH_PROTOCOL { GetHealthStatus: get_health_status, Repair: repair }));
+        let (mut controller, mut driver) = (ptr::null(), ptr::null());
+        unsafe {
+            let bs = system_table().BootServices;
+            ((*bs).InstallProtocolInterface)(&mut driver, &EFI_DRIVER_HEALTH_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, protocol as *const _ as *const VOID);
+            // Only handles with protocols are in the handle database
+            let controller_guid = EFI_GUID(0x6d1b1e6c, 0x45e3, 0x4c8f, [0x9a, 0x47, 0x3f, 0x5e, 0x21, 0x8d, 0x0b, 0x74]);
+            ((*bs).InstallProtocolInterface)(&mut controller, &controller_guid, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, ptr::null());
+        }
+        CONTROLLER.store(controller as usize, Ordering::SeqCst);
+
+        let reports: Vec<_> = reports().unwrap().into_iter().filter(|r| r.driver == driver).collect();
+        assert_eq!(reports.len(), 2);
+        assert_eq!((reports[0].controller, reports[0].status), (None, HealthStatus::RepairRequired));
+        assert_eq!((reports[1].controller, reports[1].status), (Some(controller), HealthStatus::RepairRequired));
+        assert_eq!(reports[1].messages, vec![Message { code: 0x42, text: None }]);
+
+        let after: Vec<_> = repair_all().unwrap().into_iter().filter(|r| r.driver == driver).collect();
+        assert_eq!(after.len(), 1);
+        assert!(after[0].status.is_healthy() && after[0].messages.is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+// Which driver gets a controller. ConnectController offers it to the drivers the platform's override protocol names,
+// then the ones the bus names (a PCI card's option ROM, say), then the rest by binding version. These show the first
+// two, and bind() puts a driver of our choosing ahead of all of them.
+
+use ffi::{
+    driver_override::*,
+    device_path::EFI_DEVICE_PATH_PROTOCOL,
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    EFI_HANDLE,
+    EFI_NOT_FOUND,
+};
+use device_path::DevicePath;
+use handles::{connect_controller_with, disconnect_controller};
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::vec::Vec;
+use core::{mem, ptr};
+
+/// The platform's say in which drivers manage which controllers
+pub struct PlatformDriverOverride {
+    protocol: *const EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL,
+}
+
+impl PlatformDriverOverride {
+    /// Fails with NotFound if the platform hasn't got one, which most haven't
+    pub fn new() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).LocateProtocol)(&EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+        }
+        if protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Ok(Self { protocol })
+    }
+
+    /// The drivers the platform wants for the controller, most wanted first
+    pub fn drivers(&self, controller: EFI_HANDLE) -> Result<Vec<EFI_HANDLE>> {
+        walk(|driver| unsafe { ((*self.protocol).GetDriver)(self.protocol, controller, driver) })
+    }
+
+    /// The images of drivers the platform wants for the controller that haven't been loaded yet
+    pub fn driver_paths(&self, controller: EFI_HANDLE) -> Result<Vec<DevicePath>> {
+        let mut paths = Vec::new();
+        let mut path: *const EFI_DEVICE_PATH_PROTOCOL = ptr::null();
+        loop {
+            let status = unsafe { traced!(((*self.protocol).GetDriverPath)(self.protocol, controller, &mut path)) };
+            if status == EFI_NOT_FOUND {
+                return Ok(paths);
+            }
+            ret_on_err!(status);
+            paths.push(DevicePath::from_ptr(path)?);
+        }
+    }
+
+    /// Tells the platform that the image at one of the paths from driver_paths() has been loaded as `driver`
+    pub fn driver_loaded(&self, controller: EFI_HANDLE, path: &DevicePath, driver: EFI_HANDLE) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).DriverLoaded)(self.protocol, controller, path.as_ptr(), driver));
+        }
+        Ok(())
+    }
+}
+
+/// The drivers the bus wants for the controller, most wanted first. Empty if the bus doesn't say
+pub fn bus_specific_drivers(controller: EFI_HANDLE) -> Result<Vec<EFI_HANDLE>> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL = ptr::null();
+    let status = unsafe { traced!(((*bs).OpenProtocol)(controller, &EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL)) };
+    match ::to_res((), status) {
+        Err(ref e) if e.kind() == EfiErrorKind::Unsupported => return Ok(Vec::new()),
+        res => res?,
+    }
+    if protocol.is_null() {
+        return Ok(Vec::new());
+    }
+    walk(|driver| unsafe { ((*protocol).GetDriver)(protocol, driver) })
+}
+
+/// The overriding drivers for the controller in the order ConnectController tries them: the platform's then the
+/// bus's. Drivers that aren't named by either come after these
+pub fn override_drivers(controller: EFI_HANDLE) -> Result<Vec<EFI_HANDLE>> {
+    let mut drivers = match PlatformDriverOverride::new() {
+        Ok(platform) => platform.drivers(controller)?,
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    for driver in bus_specific_drivers(controller)? {
+        if !drivers.contains(&driver) {
+            drivers.push(driver);
+        }
+    }
+    Ok(drivers)
+}
+
+/// Disconnects whatever manages the controller and connects it again with `driver` offered it first. false if
+/// nothing wanted it
+pub fn bind(controller: EFI_HANDLE, driver: EFI_HANDLE) -> Result<bool> {
+    disconnect_controller(controller)?;
+    connect_controller_with(controller, &[driver], None, true)
+}
+
+// Calls GetDriver until it runs out
+fn walk<F: Fn(*mut EFI_HANDLE) -> ::ffi::EFI_STATUS>(get_driver: F) -> Result<Vec<EFI_HANDLE>> {
+    let mut drivers = Vec::new();
+    let mut driver: EFI_HANDLE = ptr::null();
+    loop {
+        let status = get_driver(&mut driver);
+        if status == EFI_NOT_FOUND {
+            return Ok(drivers);
+        }
+        ret_on_err!(status);
+        drivers.push(driver);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_GUID, EFI_STATUS, EFI_SUCCESS, EFI_INVALID_PARAMETER, VOID};
+    use testing::mock;
+    use alloc::boxed::Box;
+
+    const DRIVERS: [usize; 2] = [0x1000, 0x2000];
+
+    // Names the same two drivers for every controller
+    extern "efiapi" fn get_driver(_this: *const EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL, driver: *mut EFI_HANDLE) -> EFI_STATUS {
+        let next = match unsafe { *driver } as usize {
+            0 => DRIVERS[0],
+            d if d == DRIVERS[0] => DRIVERS[1],
+            d if d == DRIVERS[1] => return EFI_NOT_FOUND,
+            _ => return EFI_INVALID_PARAMETER,
+        };
+        unsafe { *driver = next as EFI_HANDLE };
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn lists_override_drivers() {
+        mock::install();
+        let protocol = Box::leak(Box::new(EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL { GetDriver: get_driver }));
+        let (mut controller, mut other) = (ptr::null(), ptr::null());
+        unsafe {
+            let bs = system_table().BootServices;
+            ((*bs).InstallProtocolInterface)(&mut controller, &EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, protocol as *const _ as *const VOID);
+            let vendor_guid = EFI_GUID(0x0e5c1f8a, 0x2b7d, 0x4c39, [0x8f, 0x61, 0x94, 0x2a, 0xd3, 0x07, 0x5b, 0xc8]);
+            ((*bs).InstallProtocolInterface)(&mut other, &vendor_guid, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, ptr::null());
+        }
+
+        let expected: Vec<EFI_HANDLE> = DRIVERS.iter().map(|&d| d as EFI_HANDLE).collect();
+        assert_eq!(bus_specific_drivers(controller).unwrap(), expected);
+        assert_eq!(override_drivers(controller).unwrap(), expected);
+        assert!(bus_specific_drivers(other).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+// Display identification data (EDID) via EFI_EDID_DISCOVERED_PROTOCOL and EFI_EDID_ACTIVE_PROTOCOL.
+// Both live on a display's GOP handle. Discovered is what the display itself sent; active is what the firmware went
+// with, which differs when the platform overrides a panel with a broken EDID. Only the 128 byte base block is parsed,
+// which has everything needed to pick a mode and work out the pixel density.
+
+use ffi::{
+    edid::{
+        EFI_EDID_ACTIVE_PROTOCOL,
+        EFI_EDID_ACTIVE_PROTOCOL_GUID,
+        EFI_EDID_DISCOVERED_PROTOCOL,
+        EFI_EDID_DISCOVERED_PROTOCOL_GUID,
+    },
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_GUID,
+    EFI_HANDLE,
+    UINT8,
+    UINT32,
+};
+use graphics::Resolution;
+use {Result, EfiErrorKind, system_table, image_handle};
+use core::{mem, ptr, slice, str};
+use alloc::{string::String, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian, BigEndian};
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const BLOCK_SIZE: usize = 128;
+const DESCRIPTORS: [usize; 4] = [54, 72, 90, 108];
+
+// Display descriptor tags
+const SERIAL_NUMBER_TAG: u8 = 0xff;
+const DISPLAY_NAME_TAG: u8 = 0xfc;
+
+/// One detailed timing
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Timing {
+    pub resolution: Resolution,
+    pub pixel_clock_khz: u32,
+    /// In mHz, e.g. 59940 for 59.94Hz
+    pub refresh_millihertz: u32,
+    pub interlaced: bool,
+    /// Size of the visible area in millimetres. Zero if the display didn't say
+    pub width_mm: u32,
+    pub height_mm: u32,
+}
+
+/// A parsed EDID base block
+#[derive(Debug, Clone)]
+pub struct Edid {
+    manufacturer: [u8; 3],
+    product_code: u16,
+    serial_number: u32,
+    week: u8,
+    year: u16,
+    version: (u8, u8),
+    width_cm: u8,
+    height_cm: u8,
+    timings: Vec<Timing>,
+    name: Option<String>,
+    serial_string: Option<String>,
+    raw: Vec<u8>,
+}
+
+impl Edid {
+    /// The EDID the firmware is using for the display with this GOP handle, if it has one
+    pub fn active(handle: EFI_HANDLE) -> Result<Option<Self>> {
+        let protocol = open_protocol::<EFI_EDID_ACTIVE_PROTOCOL>(handle, &EFI_EDID_ACTIVE_PROTOCOL_GUID);
+        if protocol.is_null() {
+            return Ok(None);
+        }
+        unsafe { from_raw((*protocol).Edid, (*protocol).SizeOfEdid) }
+    }
+
+    /// The EDID the display with this GOP handle sent, if it sent one
+    pub fn discovered(handle: EFI_HANDLE) -> Result<Option<Self>> {
+        let protocol = open_protocol::<EFI_EDID_DISCOVERED_PROTOCOL>(handle, &EFI_EDID_DISCOVERED_PROTOCOL_GUID);
+        if protocol.is_null() {
+            return Ok(None);
+        }
+        unsafe { from_raw((*protocol).Edid, (*protocol).SizeOfEdid) }
+    }
+
+    /// Parses the base block. Extension blocks that follow it are kept but not looked at
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < BLOCK_SIZE || buf[..8] != HEADER {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        if buf[..BLOCK_SIZE].iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(EfiErrorKind::CrcError.into());
+        }
+
+        // Three letters of five bits each, 1 being A
+        let id = BigEndian::read_u16(&buf[8..10]);
+        let letter = |shift: u16| b'A' - 1 + ((id >> shift) & 0x1f) as u8;
+        let manufacturer = [letter(10), letter(5), letter(0)];
+
+        let mut timings = Vec::new();
+        let mut name = None;
+        let mut serial_string = None;
+        for &offset in DESCRIPTORS.iter() {
+            let descriptor = &buf[offset..offset + 18];
+            if descriptor[0] != 0 || descriptor[1] != 0 {
+                timings.push(parse_timing(descriptor));
+            } else if descriptor[3] == DISPLAY_NAME_TAG {
+                name = Some(descriptor_string(descriptor));
+            } else if descriptor[3] == SERIAL_NUMBER_TAG {
+                serial_string = Some(descriptor_string(descriptor));
+            }
+        }
+
+        Ok(Edid {
+            manufacturer,
+            product_code: LittleEndian::read_u16(&buf[10..12]),
+            serial_number: LittleEndian::read_u32(&buf[12..16]),
+            week: buf[16],
+            year: 1990 + buf[17] as u16,
+            version: (buf[18], buf[19]),
+            width_cm: buf[21],
+            height_cm: buf[22],
+            timings,
+            name,
+            serial_string,
+            raw: buf.to_vec(),
+        })
+    }
+
+    /// The three letter PNP id of the manufacturer, e.g. `DEL` or `SAM`
+    pub fn manufacturer(&self) -> &str {
+        str::from_utf8(&self.manufacturer).unwrap_or("???")
+    }
+
+    pub fn product_code(&self) -> u16 {
+        self.product_code
+    }
+
+    /// Zero if the manufacturer didn't set it. Many put it in serial_string() instead
+    pub fn serial_number(&self) -> u32 {
+        self.serial_number
+    }
+
+    pub fn serial_string(&self) -> Option<&str> {
+        self.serial_string.as_deref()
+    }
+
+    /// The model name, e.g. `DELL U2720Q`
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Year of manufacture, or the model year if week() is 0xff
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn week(&self) -> u8 {
+        self.week
+    }
+
+    /// EDID version and revision, e.g. (1, 4)
+    pub fn version(&self) -> (u8, u8) {
+        self.version
+    }
+
+    /// The detailed timings, the native one first
+    pub fn timings(&self) -> &[Timing] {
+        &self.timings
+    }
+
+    pub fn native_timing(&self) -> Option<&Timing> {
+        self.timings.first()
+    }
+
+    pub fn native_resolution(&self) -> Option<Resolution> {
+        self.native_timing().map(|t| t.resolution)
+    }
+
+    /// Width and height of the screen in millimetres, from the native timing if it says and the coarser size in
+    /// centimetres otherwise. None for projectors and displays that don't say
+    pub fn physical_size_mm(&self) -> Option<(u32, u32)> {
+        match self.native_timing() {
+            Some(timing) if timing.width_mm != 0 && timing.height_mm != 0 => Some((timing.width_mm, timing.height_mm)),
+            _ if self.width_cm != 0 && self.height_cm != 0 => Some((self.width_cm as u32 * 10, self.height_cm as u32 * 10)),
+            _ => None,
+        }
+    }
+
+    /// Horizontal and vertical pixels per inch at the native resolution
+    pub fn dpi(&self) -> Option<(u32, u32)> {
+        let resolution = self.native_resolution()?;
+        let (width_mm, height_mm) = self.physical_size_mm()?;
+        Some((resolution.width * 254 / (width_mm * 10), resolution.height * 254 / (height_mm * 10)))
+    }
+
+    /// The EDID as the firmware had it, extension blocks and all
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+fn parse_timing(d: &[u8]) -> Timing {
+    let pixel_clock_khz = LittleEndian::read_u16(&d[0..2]) as u32 * 10;
+    let h_active = d[2] as u32 | (d[4] as u32 & 0xf0) << 4;
+    let h_blank = d[3] as u32 | (d[4] as u32 & 0x0f) << 8;
+    let v_active = d[5] as u32 | (d[7] as u32 & 0xf0) << 4;
+    let v_blank = d[6] as u32 | (d[7] as u32 & 0x0f) << 8;
+    let total = (h_active + h_blank) as u64 * (v_active + v_blank) as u64;
+    let refresh_millihertz = (pixel_clock_khz as u64 * 1_000_000).checked_div(total).unwrap_or(0) as u32;
+
+    Timing {
+        resolution: Resolution::new(h_active, v_active),
+        pixel_clock_khz,
+        refresh_millihertz,
+        interlaced: d[17] & 0x80 != 0,
+        width_mm: d[12] as u32 | (d[14] as u32 & 0xf0) << 4,
+        height_mm: d[13] as u32 | (d[14] as u32 & 0x0f) << 8,
+    }
+}
+
+// Up to 13 characters, ended by a line feed and padded with spaces
+fn descriptor_string(d: &[u8]) -> String {
+    let text = &d[5..18];
+    let end = text.iter().position(|&b| b == b'\n').unwrap_or(text.len());
+    String::from_utf8_lossy(&text[..end]).trim_end().into()
+}
+
+unsafe fn from_raw(edid: *const UINT8, size: UINT32) -> Result<Option<Edid>> {
+    if edid.is_null() || size == 0 {
+        return Ok(None);
+    }
+    Edid::parse(slice::from_raw_parts(edid, size as usize)).map(Some)
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> *const T {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        let status = traced!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+        if !::ffi::IsSuccess(status) {
+            return ptr::null();
+        }
+    }
+    protocol
+}
+
+#[cfg(test)]
+pub(crate) fn build_edid(native: Resolution, size_mm: (u32, u32), name: &str) -> Vec<u8> {
+    let mut edid = HEADER.to_vec();
+    edid.resize(BLOCK_SIZE, 0);
+    BigEndian::write_u16(&mut edid[8..10], (4 << 10) | (5 << 5) | 12); // DEL
+    LittleEndian::write_u16(&mut edid[10..12], 0xa0f1);
+    edid[16] = 20;
+    edid[17] = 30; // 2020
+    edid[18] = 1;
+    edid[19] = 4;
+    edid[21] = (size_mm.0 / 10) as u8;
+    edid[22] = (size_mm.1 / 10) as u8;
+
+    // 60Hz with 160 pixels and 62 lines of blanking, like CVT reduced blanking
+    let (h_blank, v_blank) = (160, 62);
+    let clock = (native.width + h_blank) * (native.height + v_blank) * 60 / 10_000;
+    let t = &mut edid[54..72];
+    LittleEndian::write_u16(&mut t[0..2], clock as u16);
+    t[2] = native.width as u8;
+    t[3] = h_blank as u8;
+    t[4] = ((native.width >> 4) & 0xf0) as u8 | (h_blank >> 8) as u8;
+    t[5] = native.height as u8;
+    t[6] = v_blank as u8;
+    t[7] = ((native.height >> 4) & 0xf0) as u8 | (v_blank >> 8) as u8;
+    t[12] = size_mm.0 as u8;
+    t[13] = size_mm.1 as u8;
+    t[14] = ((size_mm.0 >> 4) & 0xf0) as u8 | ((size_mm.1 >> 8) & 0x0f) as u8;
+
+    let d = &mut edid[72..90];
+    d[3] = DISPLAY_NAME_TAG;
+    for (i, b) in name.bytes().chain(Some(b'\n')).chain(core::iter::repeat(b' ')).take(13).enumerate() {
+        d[5 + i] = b;
+    }
+
+    let sum = edid.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    edid[127] = 0u8.wrapping_sub(sum);
+    edid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_monitor() {
+        let edid = Edid::parse(&build_edid(Resolution::new(3840, 2160), (597, 336), "DELL U2720Q")).unwrap();
+        assert_eq!(edid.manufacturer(), "DEL");
+        assert_eq!(edid.product_code(), 0xa0f1);
+        assert_eq!(edid.name(), Some("DELL U2720Q"));
+        assert_eq!(edid.serial_string(), None);
+        assert_eq!((edid.year(), edid.week(), edid.version()), (2020, 20, (1, 4)));
+
+        let timing = edid.native_timing().unwrap();
+        assert_eq!(timing.resolution, Resolution::new(3840, 2160));
+        assert_eq!(timing.refresh_millihertz, 60000);
+        assert_eq!(edid.physical_size_mm(), Some((597, 336)));
+        assert_eq!(edid.dpi(), Some((163, 163)));
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum() {
+        let mut edid = build_edid(Resolution::new(1920, 1080), (0, 0), "");
+        assert_eq!(Edid::parse(&edid).unwrap().physical_size_mm(), None);
+        edid[20] ^= 1;
+        assert_eq!(Edid::parse(&edid).unwrap_err().kind(), EfiErrorKind::CrcError);
+        assert_eq!(Edid::parse(&edid[..100]).unwrap_err().kind(), EfiErrorKind::VolumeCorrupted);
+    }
+}
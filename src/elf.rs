@@ -0,0 +1,255 @@
+// Just enough ELF to load kernels: the entry point, the PT_LOAD segments placed by physical address and the notes in
+// PT_NOTE segments. Only little endian images are supported, 32 or 64 bit.
+
+use {Result, EfiErrorKind};
+use byteorder::{ByteOrder, LittleEndian};
+use alloc::vec::Vec;
+
+const MAGIC: &[u8] = b"\x7fELF";
+const CLASS_32: u8 = 1;
+const CLASS_64: u8 = 2;
+const DATA_LSB: u8 = 1;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_NOTE: u32 = 4;
+
+/// A piece of the image and where it goes. Past its bytes in the file it's zeroed up to mem_size
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u64,
+    pub offset: usize,
+    pub file_size: usize,
+    pub mem_size: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Note<'a> {
+    /// The owner, e.g. "Xen" or "GNU", without its NUL
+    pub name: &'a [u8],
+    pub kind: u32,
+    pub desc: &'a [u8],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct ProgramHeader {
+    kind: u32,
+    offset: u64,
+    address: u64,
+    file_size: u64,
+    mem_size: u64,
+}
+
+pub struct Elf<'a> {
+    image: &'a [u8],
+    is_64bit: bool,
+    entry: u64,
+    headers: Vec<ProgramHeader>,
+}
+
+impl<'a> Elf<'a> {
+    /// Fails with LoadError if it isn't a little endian ELF image or a program header points outside it
+    pub fn parse(image: &'a [u8]) -> Result<Self> {
+        if image.len() < 64 || &image[..4] != MAGIC || image[5] != DATA_LSB {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let is_64bit = match image[4] {
+            CLASS_32 => false,
+            CLASS_64 => true,
+            _ => return Err(EfiErrorKind::LoadError.into()),
+        };
+        let (entry, phoff, phentsize, phnum) = if is_64bit {
+            (LittleEndian::read_u64(&image[24..]), LittleEndian::read_u64(&image[32..]) as usize, LittleEndian::read_u16(&image[54..]), LittleEndian::read_u16(&image[56..]))
+        } else {
+            (LittleEndian::read_u32(&image[24..]) as u64, LittleEndian::read_u32(&image[28..]) as usize, LittleEndian::read_u16(&image[42..]), LittleEndian::read_u16(&image[44..]))
+        };
+
+        let mut headers = Vec::new();
+        for i in 0..phnum as usize {
+            let start = phoff.checked_add(i * phentsize as usize).ok_or(EfiErrorKind::LoadError)?;
+            let end = start.checked_add(phentsize as usize).ok_or(EfiErrorKind::LoadError)?;
+            let ph = image.get(start..end).ok_or(EfiErrorKind::LoadError)?;
+            if ph.len() < if is_64bit { 56 } else { 32 } {
+                return Err(EfiErrorKind::LoadError.into());
+            }
+            let header = if is_64bit {
+                ProgramHeader {
+                    kind: LittleEndian::read_u32(ph),
+                    offset: LittleEndian::read_u64(&ph[8..]),
+                    address: LittleEndian::read_u64(&ph[24..]),
+                    file_size: LittleEndian::read_u64(&ph[32..]),
+                    mem_size: LittleEndian::read_u64(&ph[40..]),
+                }
+            } else {
+                ProgramHeader {
+                    kind: LittleEndian::read_u32(ph),
+                    offset: LittleEndian::read_u32(&ph[4..]) as u64,
+                    address: LittleEndian::read_u32(&ph[12..]) as u64,
+                    file_size: LittleEndian::read_u32(&ph[16..]) as u64,
+                    mem_size: LittleEndian::read_u32(&ph[20..]) as u64,
+                }
+            };
+            match header.offset.checked_add(header.file_size) {
+                Some(end) if end <= image.len() as u64 => {},
+                _ => return Err(EfiErrorKind::LoadError.into()),
+            }
+            headers.push(header);
+        }
+        Ok(Self { image, is_64bit, entry, headers })
+    }
+
+    pub fn is_64bit(&self) -> bool {
+        self.is_64bit
+    }
+
+    /// The entry point as a virtual address
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// The PT_LOAD segments. Fails with LoadError if there aren't any or one is bigger on file than in memory
+    pub fn segments(&self) -> Result<Vec<Segment>> {
+        let mut segments = Vec::new();
+        for ph in self.headers.iter().filter(|ph| ph.kind == PT_LOAD) {
+            if ph.file_size > ph.mem_size {
+                return Err(EfiErrorKind::LoadError.into());
+            }
+            segments.push(Segment { address: ph.address, offset: ph.offset as usize, file_size: ph.file_size as usize, mem_size: ph.mem_size as usize });
+        }
+        if segments.is_empty() {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        Ok(segments)
+    }
+
+    /// The notes in all PT_NOTE segments. A truncated note ends its segment's
+    pub fn notes(&self) -> Vec<Note<'a>> {
+        let mut notes = Vec::new();
+        for ph in self.headers.iter().filter(|ph| ph.kind == PT_NOTE) {
+            let mut data = &self.image[ph.offset as usize..(ph.offset + ph.file_size) as usize];
+            while data.len() >= 12 {
+                let (name_size, desc_size, kind) = (LittleEndian::read_u32(data) as usize, LittleEndian::read_u32(&data[4..]) as usize, LittleEndian::read_u32(&data[8..]));
+                let desc_start = 12 + ((name_size + 3) & !3);
+                let (name, desc) = match (data.get(12..12 + name_size), data.get(desc_start..desc_start + desc_size)) {
+                    (Some(name), Some(desc)) => (name, desc),
+                    _ => break,
+                };
+                let name = match name.split_last() {
+                    Some((0, name)) => name,
+                    _ => name,
+                };
+                notes.push(Note { name, kind, desc });
+                data = data.get(desc_start + ((desc_size + 3) & !3)..).unwrap_or(&[]);
+            }
+        }
+        notes
+    }
+
+    /// The description of the first note with this owner and type
+    pub fn note(&self, name: &[u8], kind: u32) -> Option<&'a [u8]> {
+        self.notes().into_iter().find(|n| n.name == name && n.kind == kind).map(|n| n.desc)
+    }
+}
+
+/// The page aligned start and the end of the memory the segments cover. LoadError if a segment runs past the top of
+/// the address space
+pub fn extent(segments: &[Segment]) -> Result<(u64, u64)> {
+    let start = segments.iter().map(|s| s.address).min().ok_or(EfiErrorKind::LoadError)? & !0xfff;
+    let mut end = 0;
+    for s in segments {
+        end = end.max(s.address.checked_add(s.mem_size as u64).ok_or(EfiErrorKind::LoadError)?);
+    }
+    Ok((start, end))
+}
+
+/// Copies the segments into `memory`, which is where `start` ends up, and zeroes everything around them. LoadError
+/// if a segment is outside the image or doesn't fit in `memory`
+pub fn copy_segments(image: &[u8], segments: &[Segment], start: u64, memory: &mut [u8]) -> Result<()> {
+    for b in memory.iter_mut() {
+        *b = 0;
+    }
+    for segment in segments {
+        let at = segment.address.checked_sub(start).ok_or(EfiErrorKind::LoadError)? as usize;
+        let data = segment.offset.checked_add(segment.file_size).and_then(|end| image.get(segment.offset..end));
+        let to = at.checked_add(segment.file_size).and_then(|end| memory.get_mut(at..end));
+        match (data, to) {
+            (Some(data), Some(to)) => to.copy_from_slice(data),
+            _ => return Err(EfiErrorKind::LoadError.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An image with the given program headers, each (type, offset, paddr, filesz, memsz)
+    fn build(is_64bit: bool, entry: u64, headers: &[(u32, u64, u64, u64, u64)]) -> Vec<u8> {
+        let mut image = vec![0u8; 0x200];
+        image[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', if is_64bit { CLASS_64 } else { CLASS_32 }, DATA_LSB, 1]);
+        let (phoff, phentsize) = if is_64bit { (64, 56) } else { (52, 32) };
+        if is_64bit {
+            LittleEndian::write_u64(&mut image[24..], entry);
+            LittleEndian::write_u64(&mut image[32..], phoff as u64);
+            LittleEndian::write_u16(&mut image[54..], phentsize as u16);
+            LittleEndian::write_u16(&mut image[56..], headers.len() as u16);
+        } else {
+            LittleEndian::write_u32(&mut image[24..], entry as u32);
+            LittleEndian::write_u32(&mut image[28..], phoff as u32);
+            LittleEndian::write_u16(&mut image[42..], phentsize as u16);
+            LittleEndian::write_u16(&mut image[44..], headers.len() as u16);
+        }
+        for (i, &(kind, offset, paddr, filesz, memsz)) in headers.iter().enumerate() {
+            let ph = &mut image[phoff + i * phentsize..];
+            LittleEndian::write_u32(ph, kind);
+            if is_64bit {
+                LittleEndian::write_u64(&mut ph[8..], offset);
+                LittleEndian::write_u64(&mut ph[24..], paddr);
+                LittleEndian::write_u64(&mut ph[32..], filesz);
+                LittleEndian::write_u64(&mut ph[40..], memsz);
+            } else {
+                LittleEndian::write_u32(&mut ph[4..], offset as u32);
+                LittleEndian::write_u32(&mut ph[12..], paddr as u32);
+                LittleEndian::write_u32(&mut ph[16..], filesz as u32);
+                LittleEndian::write_u32(&mut ph[20..], memsz as u32);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn reads_segments_and_notes() {
+        // A PT_LOAD at 1MiB with bss and a PT_NOTE that's only looked at for notes
+        let elf32 = build(false, 0x10_000c, &[(PT_LOAD, 0x100, 0x10_0000, 0x100, 0x3000), (PT_NOTE, 0x180, 0, 0, 0)]);
+        let elf = Elf::parse(&elf32).unwrap();
+        assert_eq!((elf.is_64bit(), elf.entry()), (false, 0x10_000c));
+        assert_eq!(elf.segments().unwrap(), vec![Segment { address: 0x10_0000, offset: 0x100, file_size: 0x100, mem_size: 0x3000 }]);
+        assert!(elf.notes().is_empty());
+
+        // Two notes, the first with a description that needs padding
+        let mut elf64 = build(true, 0xffff_ffff_8100_0000, &[(PT_NOTE, 0x100, 0, 0x2c, 0), (PT_LOAD, 0x180, 0x100_0000, 0x80, 0x80)]);
+        let notes: [u32; 11] = [4, 3, 1, 0x0055_4e47, 0x3412, 4, 8, 18, 0x006e_6558, 0x0100_0200, 0];
+        for (i, word) in notes.iter().enumerate() {
+            LittleEndian::write_u32(&mut elf64[0x100 + i * 4..], *word);
+        }
+        let elf = Elf::parse(&elf64).unwrap();
+        assert_eq!(elf.notes()[0], Note { name: b"GNU", kind: 1, desc: &[0x12, 0x34, 0] });
+        assert_eq!(elf.note(b"Xen", 18), Some(&[0, 2, 0, 1, 0, 0, 0, 0][..]));
+        assert_eq!(extent(&elf.segments().unwrap()).unwrap(), (0x100_0000, 0x100_0080));
+
+        elf64[4] = 3;
+        assert_eq!(Elf::parse(&elf64).err().unwrap().kind(), EfiErrorKind::LoadError);
+        assert_eq!(Elf::parse(&build(false, 0, &[(PT_LOAD, 0x1f0, 0, 0x20, 0x20)])).err().unwrap().kind(), EfiErrorKind::LoadError);
+
+        // Sizes that would wrap around rather than run off the end
+        assert_eq!(Elf::parse(&build(true, 0, &[(PT_NOTE, u64::MAX - 0xf, 0, 0x20, 0)])).err().unwrap().kind(), EfiErrorKind::LoadError);
+        let mut elf64 = build(true, 0, &[]);
+        LittleEndian::write_u64(&mut elf64[32..], u64::MAX);
+        LittleEndian::write_u16(&mut elf64[56..], 1);
+        assert_eq!(Elf::parse(&elf64).err().unwrap().kind(), EfiErrorKind::LoadError);
+        let high = [Segment { address: u64::MAX - 0xfff, offset: 0, file_size: 0, mem_size: 0x2000 }];
+        assert_eq!(extent(&high).unwrap_err().kind(), EfiErrorKind::LoadError);
+        let past = [Segment { address: 0x1000, offset: 0x100, file_size: usize::MAX, mem_size: usize::MAX }];
+        assert_eq!(copy_segments(&[0; 0x200], &past, 0x1000, &mut [0; 0x100]).unwrap_err().kind(), EfiErrorKind::LoadError);
+    }
+}
@@ -0,0 +1,66 @@
+// Saying what was being done when an error happened.
+//
+// A status on its own makes a poor error message: NotFound from deep inside loading a kernel doesn't say whether it
+// was the file, the partition or a protocol that wasn't there. Context attached on the way up reads outermost first,
+// e.g. "loading the kernel: opening \EFI\BOOT\BOOTX64.EFI: NotFound (...) - The item was not found", and leaves
+// kind() alone so code matching on it carries on working.
+//
+// With the core-error feature EfiError is also a core::error::Error, so it can go in the error chains of crates like
+// anyhow built for no_std.
+
+use {EfiError, Result};
+use alloc::string::{String, ToString};
+use core::fmt::Display;
+
+impl EfiError {
+    /// Adds what was being done when it happened
+    pub fn context<C: Display>(mut self, context: C) -> Self {
+        self.context.push(context.to_string());
+        self
+    }
+
+    /// The context added so far, outermost first
+    pub fn contexts(&self) -> impl Iterator<Item = &str> {
+        self.context.iter().rev().map(String::as_str)
+    }
+}
+
+/// Context for errors in a Result, added on the way out, e.g. `file.read_to_end(&mut buf).context("reading the config")?`
+pub trait ResultExt<T> {
+    fn context<C: Display>(self, context: C) -> Result<T>;
+
+    /// The same, for context that takes work to build, which only happens if there's an error
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T>;
+}
+
+impl<T, E: Into<EfiError>> ResultExt<T> for core::result::Result<T, E> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for EfiError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EfiErrorKind;
+    use alloc::vec::Vec;
+
+    fn open(path: &str) -> Result<()> {
+        Err(EfiErrorKind::NotFound).with_context(|| format!("opening {}", path))
+    }
+
+    #[test]
+    fn reads_outermost_first() {
+        let err = open("\\EFI\\BOOT\\BOOTX64.EFI").context("loading the kernel").unwrap_err();
+        assert_eq!(err.kind(), EfiErrorKind::NotFound);
+        assert_eq!(err.contexts().collect::<Vec<_>>(), ["loading the kernel", "opening \\EFI\\BOOT\\BOOTX64.EFI"]);
+        assert!(format!("{}", err).starts_with("loading the kernel: opening \\EFI\\BOOT\\BOOTX64.EFI: NotFound (0x"));
+    }
+}
@@ -58,7 +58,7 @@ pub enum EventTpl {
 
 
 // TODO: Disabled until we figured out a better design. Enable them back
-// extern "win64" fn common_notify_func<F: FnMut()>(_event: EFI_EVENT, context: *const VOID) -> EFI_STATUS {
+// extern "efiapi" fn common_notify_func<F: FnMut()>(_event: EFI_EVENT, context: *const VOID) -> EFI_STATUS {
 //     if !context.is_null() {
 //         let closure: *mut F = unsafe { mem::transmute(context) }; // Safe to make this transmute because we know this is the pointer to the closure
 //         unsafe { (*closure)(); }
@@ -276,7 +276,7 @@ impl Wait for Timer {
 
     fn is_signaled(&self) ->  Result<bool> {
         let bs = system_table().BootServices;
-        let status = unsafe { ((*bs).CheckEvent)(self.0) };
+        let status = unsafe { traced!(((*bs).CheckEvent)(self.0)) };
         match status {
             EFI_SUCCESS => Ok(true),
             EFI_NOT_READY=> Ok(false),
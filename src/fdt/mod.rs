@@ -0,0 +1,544 @@
+// Flattened device tree (FDT) support.
+//
+// On ARM and RISC-V the kernel gets its hardware description from a device tree passed via the EFI configuration table.
+// This module lets you load a DTB, patch it (e.g. /chosen) and apply overlays before installing it for the OS.
+// The blob is unflattened into a tree of nodes on parse and flattened back on install.
+// See the Devicetree Specification (devicetree.org) for the format.
+
+mod overlay;
+
+use {Result, EfiErrorKind, system_table};
+use io::Read;
+use ffi::{EFI_GUID, VOID};
+use pages::Pages;
+use byteorder::{ByteOrder, BigEndian};
+use alloc::{vec::Vec, string::String};
+use core::str;
+
+pub const EFI_DTB_TABLE_GUID: EFI_GUID = EFI_GUID(0xB1B621D5, 0xF19C, 0x41A5, [0x83, 0x0B, 0xD9, 0x15, 0x2C, 0x69, 0xAA, 0xE0]);
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_VERSION: u32 = 17;
+const FDT_LAST_COMP_VERSION: u32 = 16;
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+// Extra room left after the blob when installing so that whoever consumes it (e.g. the Linux EFI stub) can add properties in place
+const INSTALL_SLACK: usize = 0x1000;
+
+/// A device tree property. The value is raw big-endian bytes as in the blob
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl Property {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        if self.value.len() == 4 { Some(BigEndian::read_u32(&self.value)) } else { None }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        if self.value.len() == 8 { Some(BigEndian::read_u64(&self.value)) } else { None }
+    }
+
+    /// The value as a string without the null terminator
+    pub fn as_str(&self) -> Option<&str> {
+        match self.value.split_last() {
+            Some((&0, s)) => str::from_utf8(s).ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a list of null-terminated strings (e.g. "compatible")
+    pub fn as_str_list(&self) -> Vec<&str> {
+        let value = match self.value.split_last() {
+            Some((&0, v)) => v,
+            _ => return Vec::new(),
+        };
+        value.split(|b| *b == 0).filter_map(|s| str::from_utf8(s).ok()).collect()
+    }
+}
+
+/// A device tree node
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    name: String,
+    properties: Vec<Property>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self { name: name.into(), properties: Vec::new(), children: Vec::new() }
+    }
+
+    /// Full node name including the unit address e.g. "memory@80000000"
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn properties(&self) -> &[Property] {
+        &self.properties
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    /// Sets a property, replacing its value if the property already exists
+    pub fn set_property<S: Into<String>>(&mut self, name: S, value: Vec<u8>) {
+        let name = name.into();
+        match self.properties.iter_mut().find(|p| p.name == name) {
+            Some(p) => p.value = value,
+            None => self.properties.push(Property { name, value }),
+        }
+    }
+
+    pub fn set_property_u32<S: Into<String>>(&mut self, name: S, value: u32) {
+        let mut buf = vec![0; 4];
+        BigEndian::write_u32(&mut buf, value);
+        self.set_property(name, buf);
+    }
+
+    pub fn set_property_u64<S: Into<String>>(&mut self, name: S, value: u64) {
+        let mut buf = vec![0; 8];
+        BigEndian::write_u64(&mut buf, value);
+        self.set_property(name, buf);
+    }
+
+    pub fn set_property_str<S: Into<String>>(&mut self, name: S, value: &str) {
+        let mut buf = Vec::with_capacity(value.len() + 1);
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(0); // Null terminator
+        self.set_property(name, buf);
+    }
+
+    pub fn remove_property(&mut self, name: &str) -> Option<Property> {
+        let pos = self.properties.iter().position(|p| p.name == name)?;
+        Some(self.properties.remove(pos))
+    }
+
+    /// Finds a child by name. If `name` has no unit address then it matches the first child with that base name
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|c| node_name_matches(&c.name, name))
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        self.children.iter_mut().find(|c| node_name_matches(&c.name, name))
+    }
+
+    /// Returns the child with the given name, creating it if necessary
+    pub fn child_or_insert(&mut self, name: &str) -> &mut Node {
+        match self.children.iter().position(|c| node_name_matches(&c.name, name)) {
+            Some(pos) => &mut self.children[pos],
+            None => {
+                self.children.push(Node::new(name));
+                self.children.last_mut().expect("just pushed a child")
+            }
+        }
+    }
+
+    pub fn add_child(&mut self, child: Node) -> &mut Node {
+        self.children.push(child);
+        self.children.last_mut().expect("just pushed a child")
+    }
+
+    pub fn remove_child(&mut self, name: &str) -> Option<Node> {
+        let pos = self.children.iter().position(|c| node_name_matches(&c.name, name))?;
+        Some(self.children.remove(pos))
+    }
+
+    /// The node's phandle if it has one
+    pub fn phandle(&self) -> Option<u32> {
+        self.property("phandle").or_else(|| self.property("linux,phandle")).and_then(|p| p.as_u32())
+    }
+}
+
+fn node_name_matches(node_name: &str, name: &str) -> bool {
+    if node_name == name {
+        return true;
+    }
+
+    !name.contains('@') && node_name.split('@').next() == Some(name)
+}
+
+/// A parsed device tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fdt {
+    boot_cpuid_phys: u32,
+    reservations: Vec<(u64, u64)>, // (address, size) pairs from the memory reservation block
+    root: Node,
+}
+
+impl Fdt {
+    pub fn new() -> Self {
+        Self { boot_cpuid_phys: 0, reservations: Vec::new(), root: Node::new("") }
+    }
+
+    /// Reads a DTB in its entirety from the given reader (e.g. a file) and parses it
+    pub fn load<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).map_err(|_| ::EfiError::from(EfiErrorKind::DeviceError))?;
+        Self::parse(&buf)
+    }
+
+    pub fn parse(blob: &[u8]) -> Result<Self> {
+        if blob.len() < FDT_HEADER_SIZE || BigEndian::read_u32(blob) != FDT_MAGIC {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+
+        let header = |index: usize| BigEndian::read_u32(&blob[index * 4..]) as usize;
+        let total_size = header(1);
+        let off_dt_struct = header(2);
+        let off_dt_strings = header(3);
+        let off_mem_rsvmap = header(4);
+        let last_comp_version = header(6) as u32;
+        let boot_cpuid_phys = header(7) as u32;
+        let size_dt_strings = header(8);
+        let size_dt_struct = header(9);
+
+        if total_size > blob.len() || last_comp_version > FDT_VERSION {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let blob = &blob[..total_size];
+
+        let strings = slice_checked(blob, off_dt_strings, size_dt_strings)?;
+        let structure = slice_checked(blob, off_dt_struct, size_dt_struct)?;
+
+        // Memory reservation block is a list of (address, size) pairs terminated by an all zero entry
+        let mut reservations = Vec::new();
+        let mut offset = off_mem_rsvmap;
+        loop {
+            let entry = slice_checked(blob, offset, 16)?;
+            let (address, size) = (BigEndian::read_u64(entry), BigEndian::read_u64(&entry[8..]));
+            if address == 0 && size == 0 {
+                break;
+            }
+            reservations.push((address, size));
+            offset += 16;
+        }
+
+        let mut parser = StructParser { structure, strings, offset: 0 };
+        let root = loop {
+            match parser.token()? {
+                FDT_NOP => continue,
+                FDT_BEGIN_NODE => break parser.node()?,
+                _ => return Err(EfiErrorKind::LoadError.into()),
+            }
+        };
+
+        // Anything other than NOPs before FDT_END means the blob has more than one root
+        loop {
+            match parser.token()? {
+                FDT_NOP => continue,
+                FDT_END => break,
+                _ => return Err(EfiErrorKind::LoadError.into()),
+            }
+        }
+
+        Ok(Self { boot_cpuid_phys, reservations, root })
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    pub fn root_mut(&mut self) -> &mut Node {
+        &mut self.root
+    }
+
+    pub fn reservations(&self) -> &[(u64, u64)] {
+        &self.reservations
+    }
+
+    /// Adds an entry to the memory reservation block
+    pub fn add_reservation(&mut self, address: u64, size: u64) {
+        self.reservations.push((address, size));
+    }
+
+    /// Looks up a node by its absolute path e.g. "/soc/serial@10000000"
+    pub fn node(&self, path: &str) -> Option<&Node> {
+        let mut node = &self.root;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node.child(component)?;
+        }
+        Some(node)
+    }
+
+    pub fn node_mut(&mut self, path: &str) -> Option<&mut Node> {
+        let mut node = &mut self.root;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node.child_mut(component)?;
+        }
+        Some(node)
+    }
+
+    /// Looks up a node by path creating any missing nodes along the way
+    pub fn node_or_insert(&mut self, path: &str) -> &mut Node {
+        let mut node = &mut self.root;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node.child_or_insert(component);
+        }
+        node
+    }
+
+    /// Returns the path of the node with the given phandle
+    pub fn find_phandle(&self, phandle: u32) -> Option<String> {
+        fn find(node: &Node, phandle: u32, path: &mut String) -> bool {
+            if node.phandle() == Some(phandle) {
+                return true;
+            }
+
+            for child in node.children.iter() {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&child.name);
+                if find(child, phandle, path) {
+                    return true;
+                }
+                path.truncate(len);
+            }
+            false
+        }
+
+        let mut path = String::new();
+        if find(&self.root, phandle, &mut path) {
+            if path.is_empty() {
+                path.push('/');
+            }
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// The highest phandle in use in the tree or 0 if there are none
+    pub fn max_phandle(&self) -> u32 {
+        fn max(node: &Node) -> u32 {
+            node.children.iter().map(max).fold(node.phandle().unwrap_or(0), |a, b| if a > b { a } else { b })
+        }
+        max(&self.root)
+    }
+
+    /// Sets the kernel command line in /chosen
+    pub fn set_bootargs(&mut self, bootargs: &str) {
+        self.node_or_insert("/chosen").set_property_str("bootargs", bootargs);
+    }
+
+    /// Tells the kernel where an initrd has been placed in memory. `end` is exclusive
+    pub fn set_initrd(&mut self, start: u64, end: u64) {
+        let chosen = self.node_or_insert("/chosen");
+        chosen.set_property_u64("linux,initrd-start", start);
+        chosen.set_property_u64("linux,initrd-end", end);
+    }
+
+    pub fn remove_initrd(&mut self) {
+        if let Some(chosen) = self.node_mut("/chosen") {
+            chosen.remove_property("linux,initrd-start");
+            chosen.remove_property("linux,initrd-end");
+        }
+    }
+
+    /// Flattens the tree back into a DTB blob
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut structure = Vec::new();
+        let mut strings = StringTable::default();
+        flatten_node(&self.root, &mut structure, &mut strings);
+        push_u32(&mut structure, FDT_END);
+
+        let off_mem_rsvmap = FDT_HEADER_SIZE;
+        let rsvmap_size = (self.reservations.len() + 1) * 16;
+        let off_dt_struct = off_mem_rsvmap + rsvmap_size;
+        let off_dt_strings = off_dt_struct + structure.len();
+        let total_size = off_dt_strings + strings.data.len();
+
+        let mut blob = Vec::with_capacity(total_size);
+        for value in [FDT_MAGIC, total_size as u32, off_dt_struct as u32, off_dt_strings as u32, off_mem_rsvmap as u32,
+            FDT_VERSION, FDT_LAST_COMP_VERSION, self.boot_cpuid_phys, strings.data.len() as u32, structure.len() as u32].iter() {
+            push_u32(&mut blob, *value);
+        }
+
+        for &(address, size) in self.reservations.iter().chain([(0, 0)].iter()) {
+            push_u64(&mut blob, address);
+            push_u64(&mut blob, size);
+        }
+
+        blob.extend_from_slice(&structure);
+        blob.extend_from_slice(&strings.data);
+        blob
+    }
+
+    /// Installs the device tree into the EFI configuration table where the OS loader expects to find it.
+    /// Replaces any device tree previously installed by firmware. The memory is deliberately leaked since it must outlive us.
+    pub fn install(&self) -> Result<()> {
+        let blob = self.to_bytes();
+        let mut pages = Pages::allocate(Pages::count_for(blob.len() + INSTALL_SLACK))?;
+        pages.as_mut_slice()[..blob.len()].copy_from_slice(&blob);
+
+        // The header's totalsize stays that of the blob. The slack is only there so that consumers can grow it in place
+        let bs = system_table().BootServices;
+        unsafe {
+            ret_on_err!(((*bs).InstallConfigurationTable)(&EFI_DTB_TABLE_GUID, pages.as_ptr() as *const VOID));
+        }
+
+        pages.leak();
+        Ok(())
+    }
+
+    /// Returns the device tree firmware installed in the configuration table, if any
+    pub fn from_config_table() -> Result<Option<Self>> {
+        let st = system_table();
+        let tables = unsafe { core::slice::from_raw_parts(st.ConfigurationTable, st.NumberOfTableEntries) };
+        let table = match tables.iter().find(|t| t.VendorGuid == EFI_DTB_TABLE_GUID) {
+            Some(table) => table,
+            None => return Ok(None),
+        };
+
+        // Read the header first to find out how big the blob is
+        let header = unsafe { core::slice::from_raw_parts(table.VendorTable as *const u8, FDT_HEADER_SIZE) };
+        if BigEndian::read_u32(header) != FDT_MAGIC {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let total_size = BigEndian::read_u32(&header[4..]) as usize;
+        let blob = unsafe { core::slice::from_raw_parts(table.VendorTable as *const u8, total_size) };
+        Self::parse(blob).map(Some)
+    }
+}
+
+fn slice_checked(buf: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    match offset.checked_add(len) {
+        Some(end) if end <= buf.len() => Ok(&buf[offset..end]),
+        _ => Err(EfiErrorKind::LoadError.into()),
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+struct StructParser<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> StructParser<'a> {
+    fn token(&mut self) -> Result<u32> {
+        let token = BigEndian::read_u32(slice_checked(self.structure, self.offset, 4)?);
+        self.offset += 4;
+        Ok(token)
+    }
+
+    // Reads a null-terminated string at self.offset and advances past its padding
+    fn name(&mut self) -> Result<String> {
+        let rest = slice_checked(self.structure, self.offset, self.structure.len().saturating_sub(self.offset))?;
+        let len = rest.iter().position(|b| *b == 0).ok_or_else(|| ::EfiError::from(EfiErrorKind::LoadError))?;
+        let name = str::from_utf8(&rest[..len]).map_err(|_| ::EfiError::from(EfiErrorKind::LoadError))?;
+        self.offset = align4(self.offset + len + 1);
+        Ok(String::from(name))
+    }
+
+    fn string_at(&self, offset: usize) -> Result<String> {
+        let rest = slice_checked(self.strings, offset, self.strings.len().saturating_sub(offset))?;
+        let len = rest.iter().position(|b| *b == 0).ok_or_else(|| ::EfiError::from(EfiErrorKind::LoadError))?;
+        let s = str::from_utf8(&rest[..len]).map_err(|_| ::EfiError::from(EfiErrorKind::LoadError))?;
+        Ok(String::from(s))
+    }
+
+    // Parses a node whose FDT_BEGIN_NODE token has already been consumed
+    fn node(&mut self) -> Result<Node> {
+        let mut node = Node::new(self.name()?);
+        loop {
+            match self.token()? {
+                FDT_PROP => {
+                    let header = slice_checked(self.structure, self.offset, 8)?;
+                    let (len, name_offset) = (BigEndian::read_u32(header) as usize, BigEndian::read_u32(&header[4..]) as usize);
+                    let value = slice_checked(self.structure, self.offset + 8, len)?.to_vec();
+                    self.offset = align4(self.offset + 8 + len);
+                    node.properties.push(Property { name: self.string_at(name_offset)?, value });
+                },
+                FDT_BEGIN_NODE => node.children.push(self.node()?),
+                FDT_END_NODE => return Ok(node),
+                FDT_NOP => {},
+                _ => return Err(EfiErrorKind::LoadError.into()),
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct StringTable {
+    data: Vec<u8>,
+    offsets: Vec<(String, u32)>,
+}
+
+impl StringTable {
+    fn offset_of(&mut self, s: &str) -> u32 {
+        if let Some(&(_, offset)) = self.offsets.iter().find(|&&(ref name, _)| name == s) {
+            return offset;
+        }
+
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(0);
+        self.offsets.push((String::from(s), offset));
+        offset
+    }
+}
+
+fn flatten_node(node: &Node, structure: &mut Vec<u8>, strings: &mut StringTable) {
+    push_u32(structure, FDT_BEGIN_NODE);
+    structure.extend_from_slice(node.name.as_bytes());
+    structure.push(0);
+    pad4(structure);
+
+    for prop in node.properties.iter() {
+        push_u32(structure, FDT_PROP);
+        push_u32(structure, prop.value.len() as u32);
+        push_u32(structure, strings.offset_of(&prop.name));
+        structure.extend_from_slice(&prop.value);
+        pad4(structure);
+    }
+
+    for child in node.children.iter() {
+        flatten_node(child, structure, strings);
+    }
+
+    push_u32(structure, FDT_END_NODE);
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    let mut bytes = [0; 4];
+    BigEndian::write_u32(&mut bytes, value);
+    buf.extend_from_slice(&bytes);
+}
+
+fn push_u64(buf: &mut Vec<u8>, value: u64) {
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, value);
+    buf.extend_from_slice(&bytes);
+}
+
+fn pad4(buf: &mut Vec<u8>) {
+    while buf.len() % 4 != 0 {
+        buf.push(0);
+    }
+}
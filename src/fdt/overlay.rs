@@ -0,0 +1,190 @@
+// Device tree overlay application, following the same rules as libfdt's fdt_overlay_apply():
+// 1. Overlay phandles are shifted past the base tree's highest phandle and references to them fixed up via __local_fixups__
+// 2. References to labels in the base tree are resolved via the base's __symbols__ and the overlay's __fixups__
+// 3. The __overlay__ node of each fragment is merged into the fragment's target node
+// 4. Overlay symbols are added into the base's __symbols__ with their paths rewritten to the merged location
+
+use {Result, EfiErrorKind};
+use super::{Fdt, Node, Property};
+use byteorder::{ByteOrder, BigEndian};
+use alloc::{vec::Vec, string::String};
+
+impl Fdt {
+    /// Applies an overlay (a .dtbo compiled with symbols) to this tree. The overlay is consumed in the process
+    pub fn apply_overlay(&mut self, mut overlay: Fdt) -> Result<()> {
+        let delta = self.max_phandle();
+        shift_phandles(&mut overlay.root, delta);
+        apply_local_fixups(&mut overlay, delta)?;
+        self.apply_external_fixups(&mut overlay)?;
+
+        let mut targets = Vec::new();
+        for fragment in overlay.root.children.iter().filter(|f| f.child("__overlay__").is_some()) {
+            let target = self.fragment_target(fragment)?;
+            let contents = fragment.child("__overlay__").expect("filtered on __overlay__ above");
+            merge(self.node_mut(&target).ok_or_else(|| ::EfiError::from(EfiErrorKind::NotFound))?, contents);
+            targets.push((String::from(fragment.name()), target));
+        }
+
+        self.merge_symbols(&overlay, &targets)
+    }
+
+    fn fragment_target(&self, fragment: &Node) -> Result<String> {
+        if let Some(phandle) = fragment.property("target").and_then(|p| p.as_u32()) {
+            return self.find_phandle(phandle).ok_or_else(|| EfiErrorKind::NotFound.into());
+        }
+
+        match fragment.property("target-path").and_then(|p| p.as_str()) {
+            Some(path) => Ok(self.resolve_path_or_alias(path)?),
+            None => Err(EfiErrorKind::InvalidParameter.into()),
+        }
+    }
+
+    // target-path may also be an alias (e.g. "serial0") or a label from __symbols__
+    fn resolve_path_or_alias(&self, path: &str) -> Result<String> {
+        if path.starts_with('/') {
+            return Ok(String::from(path));
+        }
+
+        ["/aliases", "/__symbols__"].iter()
+            .filter_map(|n| self.node(n))
+            .filter_map(|n| n.property(path))
+            .filter_map(|p| p.as_str())
+            .map(String::from)
+            .next()
+            .ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    // Each property in the overlay's __fixups__ is named after a label in the base tree and lists "path:property:offset"
+    // locations in the overlay where that label's phandle must be written
+    fn apply_external_fixups(&mut self, overlay: &mut Fdt) -> Result<()> {
+        let fixups = match overlay.node("/__fixups__") {
+            Some(fixups) => fixups.properties.clone(),
+            None => return Ok(()),
+        };
+
+        for fixup in fixups.iter() {
+            let target_path = self.resolve_path_or_alias(fixup.name())?;
+            let phandle = self.phandle_for(&target_path)?;
+
+            for location in fixup.as_str_list() {
+                let mut parts = location.rsplitn(3, ':');
+                let (offset, prop, path) = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(offset), Some(prop), Some(path)) => (offset, prop, path),
+                    _ => return Err(EfiErrorKind::InvalidParameter.into()),
+                };
+                let offset = offset.parse::<usize>().map_err(|_| ::EfiError::from(EfiErrorKind::InvalidParameter))?;
+                let node = overlay.node_mut(path).ok_or_else(|| ::EfiError::from(EfiErrorKind::NotFound))?;
+                write_u32_at(node, prop, offset, |_| phandle)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the phandle of the node at the given path, assigning a fresh one if it doesn't have one yet
+    fn phandle_for(&mut self, path: &str) -> Result<u32> {
+        if let Some(phandle) = self.node(path).and_then(|n| n.phandle()) {
+            return Ok(phandle);
+        }
+
+        let phandle = self.max_phandle() + 1;
+        self.node_mut(path).ok_or_else(|| ::EfiError::from(EfiErrorKind::NotFound))?.set_property_u32("phandle", phandle);
+        Ok(phandle)
+    }
+
+    fn merge_symbols(&mut self, overlay: &Fdt, targets: &[(String, String)]) -> Result<()> {
+        let symbols = match overlay.node("/__symbols__") {
+            Some(symbols) => symbols,
+            None => return Ok(()),
+        };
+
+        let mut merged = Vec::new();
+        for symbol in symbols.properties.iter() {
+            let path = symbol.as_str().ok_or_else(|| ::EfiError::from(EfiErrorKind::InvalidParameter))?;
+
+            // Symbols inside fragments look like /fragment@0/__overlay__/foo and must be rebased onto the fragment's target
+            let rebased = targets.iter().filter_map(|&(ref fragment, ref target)| {
+                let prefix = format!("/{}/__overlay__", fragment);
+                if path.starts_with(&prefix) && (path.len() == prefix.len() || path[prefix.len()..].starts_with('/')) {
+                    let rest = &path[prefix.len()..];
+                    Some(if target == "/" && !rest.is_empty() { String::from(rest) } else { format!("{}{}", target, rest) })
+                } else {
+                    None
+                }
+            }).next();
+
+            if let Some(rebased) = rebased {
+                merged.push((String::from(symbol.name()), rebased));
+            }
+        }
+
+        let base_symbols = self.node_or_insert("/__symbols__");
+        for (name, path) in merged {
+            base_symbols.set_property_str(name, &path);
+        }
+        Ok(())
+    }
+}
+
+fn shift_phandles(node: &mut Node, delta: u32) {
+    for prop in node.properties.iter_mut().filter(|p| p.name == "phandle" || p.name == "linux,phandle") {
+        if let Some(phandle) = prop.as_u32() {
+            BigEndian::write_u32(&mut prop.value, phandle + delta);
+        }
+    }
+
+    for child in node.children.iter_mut() {
+        shift_phandles(child, delta);
+    }
+}
+
+// __local_fixups__ mirrors the overlay's structure. Each property in it lists offsets within the same-named
+// property of the corresponding overlay node where a phandle to another overlay node lives
+fn apply_local_fixups(overlay: &mut Fdt, delta: u32) -> Result<()> {
+    let local_fixups = match overlay.root.remove_child("__local_fixups__") {
+        Some(local_fixups) => local_fixups,
+        None => return Ok(()),
+    };
+
+    fn walk(fixups: &Node, node: &mut Node, delta: u32) -> Result<()> {
+        for fixup in fixups.properties.iter() {
+            for offset in fixup.value.chunks(4).filter(|c| c.len() == 4).map(BigEndian::read_u32) {
+                write_u32_at(node, fixup.name(), offset as usize, |v| v + delta)?;
+            }
+        }
+
+        for child_fixups in fixups.children.iter() {
+            let child = node.child_mut(child_fixups.name()).ok_or_else(|| ::EfiError::from(EfiErrorKind::NotFound))?;
+            walk(child_fixups, child, delta)?;
+        }
+        Ok(())
+    }
+
+    walk(&local_fixups, &mut overlay.root, delta)
+}
+
+fn write_u32_at<F: Fn(u32) -> u32>(node: &mut Node, prop: &str, offset: usize, f: F) -> Result<()> {
+    let prop = node.properties.iter_mut().find(|p| p.name == prop).ok_or_else(|| ::EfiError::from(EfiErrorKind::NotFound))?;
+    if offset + 4 > prop.value.len() {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+
+    let value = f(BigEndian::read_u32(&prop.value[offset..]));
+    BigEndian::write_u32(&mut prop.value[offset..], value);
+    Ok(())
+}
+
+// Properties in `contents` overwrite those of `target`. Child nodes are merged recursively
+fn merge(target: &mut Node, contents: &Node) {
+    for prop in contents.properties.iter() {
+        let Property { ref name, ref value } = *prop;
+        target.set_property(name.clone(), value.clone());
+    }
+
+    for child in contents.children.iter() {
+        match target.children.iter().position(|c| c.name == child.name) {
+            Some(pos) => merge(&mut target.children[pos], child),
+            None => { target.add_child(child.clone()); },
+        }
+    }
+}
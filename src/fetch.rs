@@ -0,0 +1,440 @@
+// Fetching files by URL.
+//
+// fetch() opens whatever a URL names as a Read, whether it's on a file system (file://), a TFTP server (tftp://, over
+// the PXE base code protocol) or a web server (http://), so loaders can take kernel and initrd locations from their
+// configuration without caring where they live. Fetcher adds retries, progress reporting and further schemes.
+//
+// Retries only cover opening, since a half read stream can't be rewound for the caller. fetch_to_vec() reads the whole
+// thing itself so it starts transfers that fail part way over too.
+//
+// fetch_verified() only hands over what matches what the caller expected, so nothing tampered with or cut short
+// gets as far as being booted:
+//
+//     let expected = Expected::new().with_len(entry.size).with_sha256_hex(&entry.sha256)?;
+//     let kernel = fetch::fetch_verified(&entry.kernel, &expected)?;
+
+use fs::{FileSystem, SimpleFs};
+use io::{self, Cursor, Read};
+use net::{IpAddr, Url, dns, http, pxebc::PxeBaseCodeProtocol};
+use utils::NullTerminatedAsciiStr;
+use hash::{self, SHA256_LEN};
+use security::{authenticode, pkcs7, RsaPublicKey, TrustStore};
+use {Result, EfiError, EfiErrorKind};
+pub use retry::RetryPolicy;
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// Opens URLs of one scheme
+pub trait Backend {
+    /// Starts a transfer, giving its data and length if known up front
+    fn open<'a>(&'a self, url: &Url) -> Result<(Box<dyn Read + 'a>, Option<u64>)>;
+}
+
+impl<'b, B: Backend + ?Sized> Backend for &'b B {
+    fn open<'a>(&'a self, url: &Url) -> Result<(Box<dyn Read + 'a>, Option<u64>)> {
+        (**self).open(url)
+    }
+}
+
+/// How far a transfer has got. Given to the progress callback as it starts and after every read
+#[derive(Debug, Copy, Clone)]
+pub struct Progress<'a> {
+    pub url: &'a Url,
+    pub bytes: u64,
+    /// The total if the source said what it is
+    pub len: Option<u64>,
+}
+
+/// Data being fetched
+pub struct Resource<'a> {
+    url: Url,
+    len: Option<u64>,
+    bytes: u64,
+    reader: Box<dyn Read + 'a>,
+    progress: Option<&'a mut dyn FnMut(&Progress)>,
+}
+
+impl<'a> Resource<'a> {
+    fn new(url: Url, reader: Box<dyn Read + 'a>, len: Option<u64>, progress: Option<&'a mut dyn FnMut(&Progress)>) -> Self {
+        let mut resource = Resource { url, len, bytes: 0, reader, progress };
+        resource.report();
+        resource
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The length of the data, for sources that say up front
+    pub fn size(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// Bytes read so far
+    pub fn position(&self) -> u64 {
+        self.bytes
+    }
+
+    fn report(&mut self) {
+        let progress = Progress { url: &self.url, bytes: self.bytes, len: self.len };
+        if let Some(ref mut f) = self.progress {
+            f(&progress);
+        }
+    }
+}
+
+impl<'a> Read for Resource<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.bytes += n as u64;
+        if n > 0 {
+            self.report();
+        }
+        Ok(n)
+    }
+}
+
+/// What a download has to be to be handed over by fetch_verified(). Anything left unset isn't checked
+#[derive(Debug, Clone, Default)]
+pub struct Expected {
+    len: Option<u64>,
+    sha256: Option<[u8; SHA256_LEN]>,
+    signature: Option<Signature>,
+}
+
+impl Expected {
+    pub fn new() -> Self {
+        Expected::default()
+    }
+
+    /// Exactly this many bytes. Sources that say up front they have something else aren't read at all
+    pub fn with_len(self, len: u64) -> Self {
+        Expected { len: Some(len), ..self }
+    }
+
+    pub fn with_sha256(self, digest: [u8; SHA256_LEN]) -> Self {
+        Expected { sha256: Some(digest), ..self }
+    }
+
+    /// The SHA-256 written in hex, as config files have it. InvalidParameter if it isn't 64 hex digits
+    pub fn with_sha256_hex(self, hex: &str) -> Result<Self> {
+        let mut digest = [0u8; SHA256_LEN];
+        match hash::from_hex(hex.trim()) {
+            Some(ref bytes) if bytes.len() == SHA256_LEN => digest.copy_from_slice(bytes),
+            _ => return Err(EfiErrorKind::InvalidParameter.into()),
+        }
+        Ok(self.with_sha256(digest))
+    }
+
+    pub fn with_signature(self, signature: Signature) -> Self {
+        Expected { signature: Some(signature), ..self }
+    }
+
+    /// SecurityViolation unless `data` is everything expected. A signature that can't be checked at all gives whatever
+    /// error checking it did, e.g. Unsupported for an algorithm we don't have
+    pub fn check(&self, data: &[u8]) -> Result<()> {
+        if self.len.is_some() && self.len != Some(data.len() as u64) {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        if self.sha256.is_none() && self.signature.is_none() {
+            return Ok(());
+        }
+        let digest = hash::sha256(data);
+        if self.sha256.is_some() && self.sha256 != Some(digest) {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        match self.signature {
+            Some(Signature::Rsa(ref key, ref signature)) => key.verify_sha256(&digest, signature),
+            Some(Signature::Pkcs7(ref signature, ref trust)) => pkcs7::verify_pkcs7(signature, Some(data), trust).map(|_| ()),
+            Some(Signature::Authenticode(ref trust)) => authenticode::verify(data, trust),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A signature over a download, kept apart from it
+#[derive(Debug, Clone)]
+pub enum Signature {
+    /// The key's PKCS #1 v1.5 signature over the SHA-256 of the data, as from `openssl dgst -sha256 -sign`
+    Rsa(RsaPublicKey, Vec<u8>),
+    /// Detached PKCS #7 signed data, as from `openssl cms -sign -binary -outform DER`, by someone the store trusts
+    Pkcs7(Vec<u8>, TrustStore),
+    /// The download is a PE image with an Authenticode signature chaining to the store, or a digest it lists
+    Authenticode(TrustStore),
+}
+
+/// Fetches URLs with its own choice of retries, progress callback, file system and schemes
+pub struct Fetcher<'a> {
+    fs: Option<&'a dyn FileSystem>,
+    retry: RetryPolicy,
+    progress: Option<Box<dyn FnMut(&Progress) + 'a>>,
+    backends: Vec<(String, Box<dyn Backend + 'a>)>,
+}
+
+impl<'a> Fetcher<'a> {
+    /// Handles file://, tftp:// and http:// with the default retry policy and no progress callback
+    pub fn new() -> Self {
+        Fetcher { fs: None, retry: RetryPolicy::default(), progress: None, backends: Vec::new() }
+    }
+
+    /// The file system file:// URLs are on. Without one they're on the volume this image was loaded from
+    pub fn file_system(&mut self, fs: &'a dyn FileSystem) -> &mut Self {
+        self.fs = Some(fs);
+        self
+    }
+
+    pub fn retry(&mut self, retry: RetryPolicy) -> &mut Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn progress<F: FnMut(&Progress) + 'a>(&mut self, progress: F) -> &mut Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Handles URLs with the given scheme, in place of the built in backend if there is one
+    pub fn backend<B: Backend + 'a>(&mut self, scheme: &str, backend: B) -> &mut Self {
+        let scheme = scheme.to_ascii_lowercase();
+        self.backends.retain(|&(ref s, _)| *s != scheme);
+        self.backends.push((scheme, Box::new(backend)));
+        self
+    }
+
+    /// Opens `url` for reading
+    pub fn fetch<'b>(&'b mut self, url: &str) -> Result<Resource<'b>> {
+        let url = parse(url)?;
+        let Fetcher { fs, ref retry, ref mut progress, ref backends } = *self;
+        let (reader, len) = retry.run(|| open(fs, backends, &url, retry))?;
+        Ok(Resource::new(url, reader, len, callback(progress)))
+    }
+
+    /// Reads all of `url`, starting over if the transfer fails part way with an error worth retrying
+    pub fn fetch_to_vec(&mut self, url: &str) -> Result<Vec<u8>> {
+        self.read_all(url, None)
+    }
+
+    /// Reads all of `url` like fetch_to_vec(), then checks it's what was expected. SecurityViolation if it isn't, in
+    /// which case the data is thrown away
+    pub fn fetch_verified(&mut self, url: &str, expected: &Expected) -> Result<Vec<u8>> {
+        let data = self.read_all(url, expected.len)?;
+        expected.check(&data)?;
+        Ok(data)
+    }
+
+    // Reading no more than one byte past `expected_len`, which is enough to tell it's too long
+    fn read_all(&mut self, url: &str, expected_len: Option<u64>) -> Result<Vec<u8>> {
+        let url = parse(url)?;
+        let Fetcher { fs, ref retry, ref mut progress, ref backends } = *self;
+        retry.run(|| {
+            let (reader, len) = open(fs, backends, &url, retry)?;
+            if let (Some(len), Some(expected_len)) = (len, expected_len) {
+                if len != expected_len {
+                    return Err(EfiErrorKind::SecurityViolation.into());
+                }
+            }
+            let mut resource = Resource::new(url.clone(), reader, len, callback(progress));
+            let mut data = Vec::with_capacity(len.or(expected_len).unwrap_or(0) as usize);
+            match expected_len {
+                Some(expected_len) => resource.by_ref().take(expected_len + 1).read_to_end(&mut data),
+                None => resource.read_to_end(&mut data),
+            }.map_err(http::network_error)?;
+            match len {
+                Some(len) if len != data.len() as u64 => Err(EfiErrorKind::ProtocolError.into()),
+                _ => Ok(data),
+            }
+        })
+    }
+}
+
+/// Opens `url` with the default Fetcher
+pub fn fetch(url: &str) -> Result<Resource<'static>> {
+    let url = parse(url)?;
+    let retry = RetryPolicy::default();
+    let (reader, len) = retry.run(|| open(None, &[], &url, &retry))?;
+    Ok(Resource::new(url, reader, len, None))
+}
+
+/// Reads all of `url` with the default Fetcher
+pub fn fetch_to_vec(url: &str) -> Result<Vec<u8>> {
+    Fetcher::new().fetch_to_vec(url)
+}
+
+/// Reads all of `url` with the default Fetcher and checks it's what was expected
+pub fn fetch_verified(url: &str, expected: &Expected) -> Result<Vec<u8>> {
+    Fetcher::new().fetch_verified(url, expected)
+}
+
+fn callback<'a, 'f: 'a>(progress: &'a mut Option<Box<dyn FnMut(&Progress) + 'f>>) -> Option<&'a mut dyn FnMut(&Progress)> {
+    match *progress {
+        Some(ref mut f) => Some(&mut **f),
+        None => None,
+    }
+}
+
+fn parse(url: &str) -> Result<Url> {
+    url.parse().map_err(|_| EfiErrorKind::InvalidParameter.into())
+}
+
+// The retrying happens out here, so backends only get the policy's time limit
+fn open<'a>(fs: Option<&'a dyn FileSystem>, backends: &'a [(String, Box<dyn Backend + 'a>)], url: &Url, retry: &RetryPolicy) -> Result<(Box<dyn Read + 'a>, Option<u64>)> {
+    if let Some(&(_, ref backend)) = backends.iter().find(|&&(ref s, _)| s == url.scheme()) {
+        return backend.open(url);
+    }
+    match url.scheme() {
+        "file" => open_file(fs, url),
+        "tftp" => open_tftp(url),
+        "http" => {
+            let response = http::get_with(url, &retry.once())?;
+            let len = response.content_length();
+            Ok((Box::new(response), len))
+        },
+        _ => Err(EfiErrorKind::Unsupported.into()),
+    }
+}
+
+fn open_file<'a>(fs: Option<&'a dyn FileSystem>, url: &Url) -> Result<(Box<dyn Read + 'a>, Option<u64>)> {
+    if !url.host().is_empty() && !url.host().eq_ignore_ascii_case("localhost") {
+        return Err(EfiErrorKind::Unsupported.into()); // Files on other machines
+    }
+    let path = url.decoded_path().map_err(|_| EfiError::from(EfiErrorKind::InvalidParameter))?;
+    match fs {
+        Some(fs) => {
+            let file = fs.open(&path)?;
+            let len = file.len();
+            Ok((Box::new(file), Some(len)))
+        },
+        None => {
+            // The volume would have to outlive the file borrowing it, so read the lot up front
+            let data = SimpleFs::boot_volume()?.read(&path)?;
+            let len = data.len() as u64;
+            Ok((Box::new(Cursor::new(data)), Some(len)))
+        },
+    }
+}
+
+fn open_tftp(url: &Url) -> Result<(Box<dyn Read + 'static>, Option<u64>)> {
+    if url.port().map_or(false, |port| port != 69) {
+        return Err(EfiErrorKind::Unsupported.into()); // The PXE base code only talks to the standard port
+    }
+    let server = match url.ip() {
+        Some(ip) => ip,
+        None => *dns::lookup_host(url.host())?.iter().find(|ip| match **ip { IpAddr::V4(_) => true, _ => false })
+            .ok_or_else(|| EfiError::from(EfiErrorKind::NotFound))?,
+    };
+
+    let pxe = PxeBaseCodeProtocol::get_any()?.ok_or_else(|| EfiError::from(EfiErrorKind::Unsupported))?;
+    if pxe.cached_dhcp_config()?.is_none() {
+        pxe.run_dhcp()?;
+    }
+
+    // TFTP servers take paths relative to their root
+    let path = url.decoded_path().map_err(|_| EfiError::from(EfiErrorKind::InvalidParameter))?;
+    let mut filename = path.trim_start_matches('/').as_bytes().to_vec();
+    filename.push(0);
+    let data = pxe.mtftp_get_file(&server, &NullTerminatedAsciiStr::new(&filename)?)?;
+    let len = data.len() as u64;
+    Ok((Box::new(Cursor::new(data)), Some(len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use fs::{MemDisk, format_fat32};
+    use testing::mock;
+    use security::testdata;
+
+    // Fails its first few opens with a timeout, then serves a fixed string
+    struct Flaky {
+        failures: Cell<u32>,
+        opens: Cell<u32>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Self {
+            Flaky { failures: Cell::new(failures), opens: Cell::new(0) }
+        }
+    }
+
+    impl Backend for Flaky {
+        fn open<'a>(&'a self, url: &Url) -> Result<(Box<dyn Read + 'a>, Option<u64>)> {
+            self.opens.set(self.opens.get() + 1);
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(EfiErrorKind::Timeout.into());
+            }
+            match url.path() {
+                "/missing" => Err(EfiErrorKind::NotFound.into()),
+                _ => Ok((Box::new(&b"flaky data"[..]), Some(10))),
+            }
+        }
+    }
+
+    #[test]
+    fn fetches_files_with_progress() {
+        mock::install();
+        let fs = format_fat32(MemDisk::zeroed(40 * 1024 * 1024), "FETCH").unwrap();
+        fs.create_dir("boot").unwrap();
+        fs.write("boot/initrd image", &vec![7; 5000]).unwrap();
+
+        let mut reports = Vec::new();
+        {
+            let mut fetcher = Fetcher::new();
+            fetcher.file_system(&fs).progress(|p: &Progress| reports.push((p.bytes, p.len)));
+            let mut data = Vec::new();
+            let mut resource = fetcher.fetch("file:///boot/initrd%20image").unwrap();
+            assert_eq!(resource.size(), Some(5000));
+            resource.read_to_end(&mut data).unwrap();
+            drop(resource);
+            assert_eq!(data, vec![7; 5000]);
+
+            assert_eq!(fetcher.fetch("file:///boot/nothing").err().unwrap().kind(), EfiErrorKind::NotFound);
+            assert_eq!(fetcher.fetch("gopher://host/").err().unwrap().kind(), EfiErrorKind::Unsupported);
+            assert_eq!(fetcher.fetch("not a url").err().unwrap().kind(), EfiErrorKind::InvalidParameter);
+        }
+        assert_eq!(reports.first(), Some(&(0, Some(5000))));
+        assert_eq!(reports.last(), Some(&(5000, Some(5000))));
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        mock::install();
+        let (twice, always, missing) = (Flaky::new(2), Flaky::new(3), Flaky::new(0));
+        let mut fetcher = Fetcher::new();
+        fetcher.backend("twice", &twice).backend("always", &always).backend("missing", &missing);
+
+        assert_eq!(fetcher.fetch_to_vec("twice://server/file").unwrap(), b"flaky data");
+        assert_eq!(fetcher.fetch_to_vec("always://server/file").unwrap_err().kind(), EfiErrorKind::Timeout);
+        assert_eq!(fetcher.fetch_to_vec("missing://server/missing").unwrap_err().kind(), EfiErrorKind::NotFound);
+        assert_eq!((twice.opens.get(), always.opens.get(), missing.opens.get()), (3, 3, 1));
+
+        fetcher.retry(RetryPolicy::never());
+        assert_eq!(fetcher.fetch("twice://server/file").unwrap().size(), Some(10));
+    }
+
+    #[test]
+    fn verifies_downloads() {
+        mock::install();
+        let source = Flaky::new(0);
+        let mut fetcher = Fetcher::new();
+        fetcher.backend("flaky", &source);
+
+        let sha256 = "d7fc85eb2dd79a2e27dd33f4462d9ee4ad9d4750c0f427cccb1e48e46a8195d9";
+        let expected = Expected::new().with_len(10).with_sha256_hex(sha256).unwrap();
+        assert!(expected.check(b"flaky data").is_ok());
+        assert_eq!(fetcher.fetch_verified("flaky://server/file", &expected).unwrap(), b"flaky data");
+
+        let wrong_len = Expected::new().with_len(9);
+        assert_eq!(fetcher.fetch_verified("flaky://server/file", &wrong_len).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert_eq!(source.opens.get(), 2);
+        let wrong_digest = Expected::new().with_sha256(hash::sha256(b"other data"));
+        assert_eq!(fetcher.fetch_verified("flaky://server/file", &wrong_digest).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert!(Expected::new().with_sha256_hex("abcd").is_err());
+
+        let trust = TrustStore::from_pem(testdata::ROOT_PEM).unwrap();
+        let signed = Expected::new().with_signature(Signature::Pkcs7(hash::from_hex(testdata::DETACHED_SIGNATURE).unwrap(), trust.clone()));
+        assert!(signed.check(b"kernel image").is_ok());
+        assert_eq!(signed.check(b"flaky data").unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert!(Expected::new().with_signature(Signature::Authenticode(trust)).check(b"flaky data").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// Typed blocks of information a NIC driver can give beyond what the simple network protocol has
+
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    EFI_MAC_ADDRESS,
+    BOOLEAN,
+    UINT8,
+    UINTN,
+    VOID,
+};
+
+pub const EFI_ADAPTER_INFORMATION_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xE5DD1403, 0xD622, 0xC24E, [0x84, 0x88, 0xC7, 0x1B, 0x17, 0xF5, 0xE8, 0x02]);
+
+pub const EFI_ADAPTER_INFO_MEDIA_STATE_GUID: EFI_GUID = EFI_GUID(0xD7C74207, 0xA831, 0x4A26, [0xB1, 0xF5, 0xD1, 0x93, 0x06, 0x5C, 0xE8, 0xB6]);
+pub const EFI_ADAPTER_INFO_NETWORK_BOOT_GUID: EFI_GUID = EFI_GUID(0x1FBD2960, 0x4130, 0x41E5, [0x94, 0xAC, 0xD2, 0xCF, 0x03, 0x7F, 0xB3, 0x7C]);
+pub const EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID: EFI_GUID = EFI_GUID(0x114DA5EF, 0x2CF1, 0x4E12, [0x9B, 0xBB, 0xC4, 0x70, 0xB5, 0x52, 0x05, 0xD9]);
+pub const EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID: EFI_GUID = EFI_GUID(0x4BD56BE3, 0x4975, 0x4D8A, [0xA0, 0xAD, 0xC4, 0x91, 0x20, 0x4B, 0x5D, 0x4D]);
+pub const EFI_ADAPTER_INFO_MEDIA_TYPE_GUID: EFI_GUID = EFI_GUID(0x8484472F, 0x71EC, 0x411A, [0xB3, 0x9C, 0x62, 0xCD, 0x94, 0xD9, 0x91, 0x6E]);
+
+#[repr(C)]
+pub struct EFI_ADAPTER_INFORMATION_PROTOCOL {
+    pub GetInformation: EFI_ADAPTER_INFO_GET_INFO,
+    pub SetInformation: EFI_ADAPTER_INFO_SET_INFO,
+    pub GetSupportedTypes: EFI_ADAPTER_INFO_GET_SUPPORTED_TYPES,
+}
+
+debug_as_table!(EFI_ADAPTER_INFORMATION_PROTOCOL);
+
+/// The block is allocated from pool and the caller frees it
+pub type EFI_ADAPTER_INFO_GET_INFO = extern "efiapi" fn(
+    This: *const EFI_ADAPTER_INFORMATION_PROTOCOL,
+    InformationType: *const EFI_GUID,
+    InformationBlock: *mut *mut VOID,
+    InformationBlockSize: *mut UINTN
+) -> EFI_STATUS;
+
+pub type EFI_ADAPTER_INFO_SET_INFO = extern "efiapi" fn(
+    This: *const EFI_ADAPTER_INFORMATION_PROTOCOL,
+    InformationType: *const EFI_GUID,
+    InformationBlock: *const VOID,
+    InformationBlockSize: UINTN
+) -> EFI_STATUS;
+
+/// The buffer is allocated from pool and the caller frees it
+pub type EFI_ADAPTER_INFO_GET_SUPPORTED_TYPES = extern "efiapi" fn(
+    This: *const EFI_ADAPTER_INFORMATION_PROTOCOL,
+    InfoTypesBuffer: *mut *mut EFI_GUID,
+    InfoTypesBufferCount: *mut UINTN
+) -> EFI_STATUS;
+
+/// EFI_SUCCESS if there's link, EFI_NO_MEDIA if there isn't and EFI_NOT_READY while it's being negotiated
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_ADAPTER_INFO_MEDIA_STATE {
+    pub MediaState: EFI_STATUS,
+}
+
+/// The spelling of the capability fields is the spec's
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_ADAPTER_INFO_NETWORK_BOOT {
+    pub iScsiIpv4BootCapablity: BOOLEAN,
+    pub iScsiIpv6BootCapablity: BOOLEAN,
+    pub FCoeBootCapablity: BOOLEAN,
+    pub OffloadCapability: BOOLEAN,
+    pub iScsiMpioCapability: BOOLEAN,
+    pub iScsiIpv4Boot: BOOLEAN,
+    pub iScsiIpv6Boot: BOOLEAN,
+    pub FCoeBoot: BOOLEAN,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_ADAPTER_INFO_SAN_MAC_ADDRESS {
+    pub SanMacAddress: EFI_MAC_ADDRESS,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT {
+    pub Ipv6Support: BOOLEAN,
+}
+
+/// MediaType is an IANA ifType
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_ADAPTER_INFO_MEDIA_TYPE {
+    pub MediaType: UINT8,
+}
@@ -0,0 +1,145 @@
+use ffi::{
+    base::{
+        EFI_GUID,
+        EFI_STATUS,
+        EFI_EVENT,
+        UINT8,
+        UINT16,
+        UINT32,
+        UINT64,
+        VOID,
+        NOT_DEFINED,
+    },
+    device_path::EFI_DEVICE_PATH_PROTOCOL,
+};
+
+pub const EFI_ATA_PASS_THRU_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x1d3de7f0, 0x0807, 0x424f, [0xaa, 0x69, 0x11, 0xa5, 0x4e, 0x19, 0xa4, 0x6f]);
+
+pub const EFI_ATA_PASS_THRU_ATTRIBUTES_PHYSICAL: UINT32 = 0x0001;
+pub const EFI_ATA_PASS_THRU_ATTRIBUTES_LOGICAL: UINT32 = 0x0002;
+pub const EFI_ATA_PASS_THRU_ATTRIBUTES_NONBLOCKIO: UINT32 = 0x0004;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_ATA_PASS_THRU_MODE {
+    pub Attributes: UINT32,
+    pub IoAlign: UINT32,
+}
+
+#[repr(C)]
+pub struct EFI_ATA_PASS_THRU_PROTOCOL {
+    pub Mode: *const EFI_ATA_PASS_THRU_MODE,
+    pub PassThru: EFI_ATA_PASS_THRU_PASSTHRU,
+    pub GetNextPort: EFI_ATA_PASS_THRU_GET_NEXT_PORT,
+    pub GetNextDevice: EFI_ATA_PASS_THRU_GET_NEXT_DEVICE,
+    pub BuildDevicePath: EFI_ATA_PASS_THRU_BUILD_DEVICE_PATH,
+    pub GetDevice: EFI_ATA_PASS_THRU_GET_DEVICE,
+    pub ResetPort: EFI_ATA_PASS_THRU_RESET_PORT,
+    pub ResetDevice: EFI_ATA_PASS_THRU_RESET_DEVICE,
+}
+
+debug_as_table!(EFI_ATA_PASS_THRU_PROTOCOL);
+
+pub type EFI_ATA_PASS_THRU_PASSTHRU = extern "efiapi" fn(
+    This: *const EFI_ATA_PASS_THRU_PROTOCOL,
+    Port: UINT16,
+    PortMultiplierPort: UINT16,
+    Packet: *mut EFI_ATA_PASS_THRU_COMMAND_PACKET,
+    Event: EFI_EVENT
+) -> EFI_STATUS;
+
+// Start from 0xFFFF and get EFI_NOT_FOUND after the last one
+pub type EFI_ATA_PASS_THRU_GET_NEXT_PORT = extern "efiapi" fn(
+    This: *const EFI_ATA_PASS_THRU_PROTOCOL,
+    Port: *mut UINT16
+) -> EFI_STATUS;
+
+pub type EFI_ATA_PASS_THRU_GET_NEXT_DEVICE = extern "efiapi" fn(
+    This: *const EFI_ATA_PASS_THRU_PROTOCOL,
+    Port: UINT16,
+    PortMultiplierPort: *mut UINT16
+) -> EFI_STATUS;
+
+pub type EFI_ATA_PASS_THRU_BUILD_DEVICE_PATH = *const NOT_DEFINED;
+
+pub type EFI_ATA_PASS_THRU_GET_DEVICE = extern "efiapi" fn(
+    This: *const EFI_ATA_PASS_THRU_PROTOCOL,
+    DevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
+    Port: *mut UINT16,
+    PortMultiplierPort: *mut UINT16
+) -> EFI_STATUS;
+
+pub type EFI_ATA_PASS_THRU_RESET_PORT = *const NOT_DEFINED;
+pub type EFI_ATA_PASS_THRU_RESET_DEVICE = *const NOT_DEFINED;
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_ATA_COMMAND_BLOCK {
+    pub Reserved1: [UINT8; 2],
+    pub AtaCommand: UINT8,
+    pub AtaFeatures: UINT8,
+    pub AtaSectorNumber: UINT8,
+    pub AtaCylinderLow: UINT8,
+    pub AtaCylinderHigh: UINT8,
+    pub AtaDeviceHead: UINT8,
+    pub AtaSectorNumberExp: UINT8,
+    pub AtaCylinderLowExp: UINT8,
+    pub AtaCylinderHighExp: UINT8,
+    pub AtaFeaturesExp: UINT8,
+    pub AtaSectorCount: UINT8,
+    pub AtaSectorCountExp: UINT8,
+    pub Reserved2: [UINT8; 6],
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_ATA_STATUS_BLOCK {
+    pub Reserved1: [UINT8; 2],
+    pub AtaStatus: UINT8,
+    pub AtaError: UINT8,
+    pub AtaSectorNumber: UINT8,
+    pub AtaCylinderLow: UINT8,
+    pub AtaCylinderHigh: UINT8,
+    pub AtaDeviceHead: UINT8,
+    pub AtaSectorNumberExp: UINT8,
+    pub AtaCylinderLowExp: UINT8,
+    pub AtaCylinderHighExp: UINT8,
+    pub Reserved2: UINT8,
+    pub AtaSectorCount: UINT8,
+    pub AtaSectorCountExp: UINT8,
+    pub Reserved3: [UINT8; 6],
+}
+
+pub type EFI_ATA_PASS_THRU_CMD_PROTOCOL = UINT8;
+
+pub const EFI_ATA_PASS_THRU_PROTOCOL_ATA_HARDWARE_RESET: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x00;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_ATA_SOFTWARE_RESET: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x01;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_ATA_NON_DATA: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x02;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_PIO_DATA_IN: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x04;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_PIO_DATA_OUT: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x05;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_DMA: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x06;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_UDMA_DATA_IN: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x0a;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_UDMA_DATA_OUT: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0x0b;
+pub const EFI_ATA_PASS_THRU_PROTOCOL_RETURN_RESPONSE: EFI_ATA_PASS_THRU_CMD_PROTOCOL = 0xff;
+
+pub type EFI_ATA_PASS_THRU_LENGTH = UINT8;
+
+pub const EFI_ATA_PASS_THRU_LENGTH_BYTES: EFI_ATA_PASS_THRU_LENGTH = 0x80;
+pub const EFI_ATA_PASS_THRU_LENGTH_NO_DATA_TRANSFER: EFI_ATA_PASS_THRU_LENGTH = 0x00;
+pub const EFI_ATA_PASS_THRU_LENGTH_FEATURES: EFI_ATA_PASS_THRU_LENGTH = 0x10;
+pub const EFI_ATA_PASS_THRU_LENGTH_SECTOR_COUNT: EFI_ATA_PASS_THRU_LENGTH = 0x20;
+pub const EFI_ATA_PASS_THRU_LENGTH_TPSIU: EFI_ATA_PASS_THRU_LENGTH = 0x30;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_ATA_PASS_THRU_COMMAND_PACKET {
+    pub Asb: *mut EFI_ATA_STATUS_BLOCK,
+    pub Acb: *const EFI_ATA_COMMAND_BLOCK,
+    pub Timeout: UINT64, // 100ns units, 0 waits forever
+    pub InDataBuffer: *mut VOID,
+    pub OutDataBuffer: *const VOID,
+    pub InTransferLength: UINT32,
+    pub OutTransferLength: UINT32,
+    pub Protocol: EFI_ATA_PASS_THRU_CMD_PROTOCOL,
+    pub Length: EFI_ATA_PASS_THRU_LENGTH,
+}
@@ -73,7 +73,7 @@ impl EFI_TIME_CAPABILITIES  {
 }
 
 /// 4-byte buffer. An IPv4 internet protocol address.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct EFI_IPv4_ADDRESS {
   pub Addr: [UINT8; 4],
@@ -85,8 +85,16 @@ impl EFI_IPv4_ADDRESS {
     }
 }
 
+// Dotted, as everything else shows them
+impl fmt::Debug for EFI_IPv4_ADDRESS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let a = &self.Addr;
+        write!(f, "{}.{}.{}.{}", a[0], a[1], a[2], a[3])
+    }
+}
+
 /// 16-byte buffer. An IPv6 internet protocol address.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct EFI_IPv6_ADDRESS {
   pub Addr: [UINT8; 16],
@@ -98,8 +106,21 @@ impl EFI_IPv6_ADDRESS {
     }
 }
 
+// Eight groups of hex without the zero compression, which is plenty for debugging
+impl fmt::Debug for EFI_IPv6_ADDRESS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, group) in self.Addr.chunks(2).enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:x}", (group[0] as u16) << 8 | group[1] as u16)?;
+        }
+        Ok(())
+    }
+}
+
 /// 32-byte buffer containing a network Media Access Control address.
-#[derive(PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct EFI_MAC_ADDRESS {
   pub Addr: [UINT8; 32],
@@ -117,12 +138,17 @@ impl Default for EFI_MAC_ADDRESS {
     }
 }
 
-// Had to implement by hand 'cause Debug derive not allowed for unions
+// Colon separated hex, with the padding after an Ethernet address's six bytes left off
 impl fmt::Debug for EFI_MAC_ADDRESS {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("EFI_MAC_ADDRESS ")
-            .field("Addr", &format_args!("{:x?}", self.Addr))
-            .finish()
+        let len = if self.Addr[6..].iter().all(|&b| b == 0) { 6 } else { self.Addr.len() };
+        for (i, b) in self.Addr[..len].iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
     }
 }
 
@@ -143,12 +169,17 @@ impl EFI_IP_ADDRESS {
     }
 }
 
-// Had to implement by hand 'cause Debug derive not allowed for unions
+// Had to implement by hand 'cause Debug derive not allowed for unions. Which of the two it is depends on the
+// protocol, so it's shown as IPv4 when the rest is zeroes
 impl fmt::Debug for EFI_IP_ADDRESS {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("EFI_IP_ADDRESS")
-            .field("Addr", &format_args!("{:?}", unsafe { self.Addr }))
-            .finish()
+        unsafe {
+            if self.Addr[1..] == [0, 0, 0] {
+                write!(f, "{:?}", self.v4)
+            } else {
+                write!(f, "{:?}", self.v6)
+            }
+        }
     }
 }
 
@@ -157,11 +188,25 @@ pub type EFI_PHYSICAL_ADDRESS = UINT64;
 #[derive(Debug)]
 #[repr(C)]
 pub struct EFI_TABLE_HEADER {
-    Signature : UINT64,
-    Revision : UINT32,
-    HeaderSize : UINT32,
-    CRC32 : UINT32,
-    Reserved : UINT32
+    pub Signature : UINT64,
+    pub Revision : UINT32,
+    pub HeaderSize : UINT32,
+    pub CRC32 : UINT32,
+    pub Reserved : UINT32
+}
+
+// Debug for tables of functions: efiapi function pointers don't implement it, and a table's functions say nothing
+// printed anyway, so it shows as its name and where it is
+macro_rules! debug_as_table {
+    ($($table:ident),+) => {
+        $(
+            impl ::core::fmt::Debug for $table {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                    write!(f, "{} @ {:p}", stringify!($table), self)
+                }
+            }
+        )+
+    };
 }
 
 macro_rules! with_high_bit_set {
@@ -253,6 +298,7 @@ pub type UINT8 = u8;
 pub type CHAR8 = i8;
 pub type INT8 = i8;
 pub type UINTN = usize;
+pub type INTN = isize;
 
 pub const TRUE: BOOLEAN = 1;
 pub const FALSE: BOOLEAN = 0;
@@ -262,10 +308,59 @@ pub enum VOID { }
 pub type EFI_HANDLE = *const VOID;
 pub type EFI_EVENT = *const VOID;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct EFI_GUID(pub UINT32, pub UINT16, pub UINT16, pub [UINT8; 8]);
 
+impl EFI_GUID {
+    /// Parses the registry format, e.g. 8be4df61-93ca-11d2-aa0d-00e098032b8c, in either case
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.as_bytes();
+        if s.len() != 36 || s[8] != b'-' || s[13] != b'-' || s[18] != b'-' || s[23] != b'-' {
+            return None;
+        }
+        let hex = |range: &[u8]| -> Option<u64> {
+            range.iter().try_fold(0u64, |n, &c| Some(n << 4 | (c as char).to_digit(16)? as u64))
+        };
+        let mut tail = [0u8; 8];
+        tail[0] = hex(&s[19..21])? as u8;
+        tail[1] = hex(&s[21..23])? as u8;
+        for (i, b) in tail[2..].iter_mut().enumerate() {
+            *b = hex(&s[24 + i * 2..26 + i * 2])? as u8;
+        }
+        Some(EFI_GUID(hex(&s[..8])? as u32, hex(&s[9..13])? as u16, hex(&s[14..18])? as u16, tail))
+    }
+
+    /// As GUIDs are laid out in memory and in variables and tables: the first three fields little endian
+    pub fn from_le_bytes(b: &[u8; 16]) -> Self {
+        let mut tail = [0u8; 8];
+        tail.copy_from_slice(&b[8..]);
+        EFI_GUID(u32::from_le_bytes([b[0], b[1], b[2], b[3]]), u16::from_le_bytes([b[4], b[5]]), u16::from_le_bytes([b[6], b[7]]), tail)
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 16] {
+        let mut b = [0u8; 16];
+        b[..4].copy_from_slice(&self.0.to_le_bytes());
+        b[4..6].copy_from_slice(&self.1.to_le_bytes());
+        b[6..8].copy_from_slice(&self.2.to_le_bytes());
+        b[8..].copy_from_slice(&self.3);
+        b
+    }
+}
+
+impl fmt::Display for EFI_GUID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let t = &self.3;
+        write!(f, "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}", self.0, self.1, self.2, t[0], t[1], t[2], t[3], t[4], t[5], t[6], t[7])
+    }
+}
+
+impl fmt::Debug for EFI_GUID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 pub type EFI_STATUS = UINTN;
 
 pub enum NOT_DEFINED  {}
@@ -0,0 +1,142 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINTN,
+    VOID,
+    NOT_DEFINED,
+};
+
+pub const EFI_BLUETOOTH_CONFIG_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x62960cf3, 0x40ff, 0x4263, [0xa7, 0x7c, 0xdf, 0xde, 0xbd, 0x19, 0x1b, 0x4b]);
+pub const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x8f76da58, 0x1f99, 0x4275, [0xa4, 0xec, 0x47, 0x56, 0x51, 0x5b, 0x1c, 0xe8]);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct BLUETOOTH_ADDRESS {
+    pub Address: [UINT8; 6],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct BLUETOOTH_LE_ADDRESS {
+    pub Address: [UINT8; 6],
+    pub Type: UINT8,
+}
+
+// BLUETOOTH_LE_ADDRESS types
+pub const BLUETOOTH_LE_ADDRESS_PUBLIC: UINT8 = 0x00;
+pub const BLUETOOTH_LE_ADDRESS_RANDOM: UINT8 = 0x01;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_BLUETOOTH_CONFIG_DATA_TYPE {
+    EfiBluetoothConfigDataTypeDeviceName,
+    EfiBluetoothConfigDataTypeClassOfDevice,
+    EfiBluetoothConfigDataTypeRemoteDeviceState,
+    EfiBluetoothConfigDataTypeSdpInfo,
+    EfiBluetoothConfigDataTypeBDADDR,
+    EfiBluetoothConfigDataTypeDiscoverable,
+    EfiBluetoothConfigDataTypeControllerStoredPairedDeviceList,
+    EfiBluetoothConfigDataTypeAvailableDeviceList,
+    EfiBluetoothConfigDataTypeRandomAddress,
+    EfiBluetoothConfigDataTypeRSSI,
+    EfiBluetoothConfigDataTypeAdvertisementData,
+    EfiBluetoothConfigDataTypeIoCapability,
+    EfiBluetoothConfigDataTypeOOBDataFlag,
+    EfiBluetoothConfigDataTypeKeyType,
+    EfiBluetoothConfigDataTypeEncKeySize,
+    EfiBluetoothConfigDataTypeMax,
+}
+
+#[repr(C)]
+pub struct EFI_BLUETOOTH_CONFIG_PROTOCOL {
+    pub Init: EFI_BLUETOOTH_CONFIG_INIT,
+    pub Scan: EFI_BLUETOOTH_CONFIG_SCAN,
+    pub Connect: EFI_BLUETOOTH_CONFIG_CONNECT,
+    pub Disconnect: EFI_BLUETOOTH_CONFIG_DISCONNECT,
+    pub GetData: EFI_BLUETOOTH_CONFIG_GET_DATA,
+    pub SetData: EFI_BLUETOOTH_CONFIG_SET_DATA,
+    pub GetRemoteData: EFI_BLUETOOTH_CONFIG_GET_REMOTE_DATA,
+    pub RegisterPinCallback: EFI_BLUETOOTH_CONFIG_REGISTER_PIN_CALLBACK,
+    pub RegisterGetLinkKeyCallback: EFI_BLUETOOTH_CONFIG_REGISTER_GET_LINK_KEY_CALLBACK,
+    pub RegisterSetLinkKeyCallback: EFI_BLUETOOTH_CONFIG_REGISTER_SET_LINK_KEY_CALLBACK,
+    pub RegisterLinkConnectCompleteCallback: EFI_BLUETOOTH_CONFIG_REGISTER_CONNECT_COMPLETE_CALLBACK,
+}
+
+debug_as_table!(EFI_BLUETOOTH_CONFIG_PROTOCOL);
+
+pub type EFI_BLUETOOTH_CONFIG_INIT = extern "efiapi" fn(
+    This: *const EFI_BLUETOOTH_CONFIG_PROTOCOL
+) -> EFI_STATUS;
+
+pub type EFI_BLUETOOTH_CONFIG_SCAN = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_CONFIG_CONNECT = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_CONFIG_DISCONNECT = *const NOT_DEFINED;
+
+pub type EFI_BLUETOOTH_CONFIG_GET_DATA = extern "efiapi" fn(
+    This: *const EFI_BLUETOOTH_CONFIG_PROTOCOL,
+    DataType: EFI_BLUETOOTH_CONFIG_DATA_TYPE,
+    DataSize: *mut UINTN,
+    Data: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_BLUETOOTH_CONFIG_SET_DATA = *const NOT_DEFINED;
+
+pub type EFI_BLUETOOTH_CONFIG_GET_REMOTE_DATA = extern "efiapi" fn(
+    This: *const EFI_BLUETOOTH_CONFIG_PROTOCOL,
+    DataType: EFI_BLUETOOTH_CONFIG_DATA_TYPE,
+    BDAddr: *const BLUETOOTH_ADDRESS,
+    DataSize: *mut UINTN,
+    Data: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_BLUETOOTH_CONFIG_REGISTER_PIN_CALLBACK = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_CONFIG_REGISTER_GET_LINK_KEY_CALLBACK = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_CONFIG_REGISTER_SET_LINK_KEY_CALLBACK = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_CONFIG_REGISTER_CONNECT_COMPLETE_CALLBACK = *const NOT_DEFINED;
+
+#[repr(C)]
+pub struct EFI_BLUETOOTH_LE_CONFIG_PROTOCOL {
+    pub Init: EFI_BLUETOOTH_LE_CONFIG_INIT,
+    pub Scan: EFI_BLUETOOTH_LE_CONFIG_SCAN,
+    pub Connect: EFI_BLUETOOTH_LE_CONFIG_CONNECT,
+    pub Disconnect: EFI_BLUETOOTH_LE_CONFIG_DISCONNECT,
+    pub GetData: EFI_BLUETOOTH_LE_CONFIG_GET_DATA,
+    pub GetRemoteData: EFI_BLUETOOTH_LE_CONFIG_GET_REMOTE_DATA,
+    pub RegisterSmpAuthCallback: EFI_BLUETOOTH_LE_REGISTER_SMP_AUTH_CALLBACK,
+    pub SendSmpAuthData: EFI_BLUETOOTH_LE_SEND_SMP_AUTH_DATA,
+    pub RegisterSmpGetDataCallback: EFI_BLUETOOTH_LE_CONFIG_REGISTER_SMP_GET_DATA_CALLBACK,
+    pub RegisterSmpSetDataCallback: EFI_BLUETOOTH_LE_CONFIG_REGISTER_SMP_SET_DATA_CALLBACK,
+    pub RegisterLinkConnectCompleteCallback: EFI_BLUETOOTH_LE_CONFIG_REGISTER_CONNECT_COMPLETE_CALLBACK,
+}
+
+debug_as_table!(EFI_BLUETOOTH_LE_CONFIG_PROTOCOL);
+
+pub type EFI_BLUETOOTH_LE_CONFIG_INIT = extern "efiapi" fn(
+    This: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL
+) -> EFI_STATUS;
+
+pub type EFI_BLUETOOTH_LE_CONFIG_SCAN = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_LE_CONFIG_CONNECT = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_LE_CONFIG_DISCONNECT = *const NOT_DEFINED;
+
+pub type EFI_BLUETOOTH_LE_CONFIG_GET_DATA = extern "efiapi" fn(
+    This: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL,
+    DataType: EFI_BLUETOOTH_CONFIG_DATA_TYPE,
+    DataSize: *mut UINTN,
+    Data: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_BLUETOOTH_LE_CONFIG_GET_REMOTE_DATA = extern "efiapi" fn(
+    This: *const EFI_BLUETOOTH_LE_CONFIG_PROTOCOL,
+    DataType: EFI_BLUETOOTH_CONFIG_DATA_TYPE,
+    BDAddr: *const BLUETOOTH_LE_ADDRESS,
+    DataSize: *mut UINTN,
+    Data: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_BLUETOOTH_LE_REGISTER_SMP_AUTH_CALLBACK = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_LE_SEND_SMP_AUTH_DATA = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_LE_CONFIG_REGISTER_SMP_GET_DATA_CALLBACK = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_LE_CONFIG_REGISTER_SMP_SET_DATA_CALLBACK = *const NOT_DEFINED;
+pub type EFI_BLUETOOTH_LE_CONFIG_REGISTER_CONNECT_COMPLETE_CALLBACK = *const NOT_DEFINED;
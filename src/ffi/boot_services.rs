@@ -7,7 +7,7 @@ use ffi::{
 // use guid;
 // use table;
 
-pub const EFI_BOOT_SERVICES_SIGNATURE: UINTN = 0x56524553544f4f42;
+pub const EFI_BOOT_SERVICES_SIGNATURE: UINT64 = 0x56524553544f4f42;
 pub const EFI_BOOT_SERVICES_REVISION: UINTN = EFI_SPECIFICATION_VERSION;
 
 #[repr(C)]
@@ -70,27 +70,19 @@ pub struct EFI_BOOT_SERVICES {
   pub CreateEventEx: EFI_CREATE_EVENT_EX,
 }
 
+debug_as_table!(EFI_BOOT_SERVICES);
+
 // The below are methods currently not defined
 pub type EFI_RAISE_TPL = *const NOT_DEFINED;
 pub type EFI_RESTORE_TPL = *const NOT_DEFINED;
-pub type EFI_ALLOCATE_PAGES = *const NOT_DEFINED;
-pub type EFI_FREE_PAGES = *const NOT_DEFINED;
-pub type EFI_GET_MEMORY_MAP = *const NOT_DEFINED;
 
 pub type EFI_REINSTALL_PROTOCOL_INTERFACE = *const NOT_DEFINED;
 pub type EFI_HANDLE_PROTOCOL = *const NOT_DEFINED;
-pub type EFI_REGISTER_PROTOCOL_NOTIFY = *const NOT_DEFINED;
 pub type EFI_LOCATE_HANDLE = *const NOT_DEFINED;
-pub type EFI_LOCATE_DEVICE_PATH = *const NOT_DEFINED;
-pub type EFI_INSTALL_CONFIGURATION_TABLE = *const NOT_DEFINED;
 pub type EFI_EXIT = *const NOT_DEFINED;
 pub type EFI_IMAGE_UNLOAD = *const NOT_DEFINED;
-pub type EFI_EXIT_BOOT_SERVICES = *const NOT_DEFINED;
 pub type EFI_SET_WATCHDOG_TIMER = *const NOT_DEFINED;
-pub type EFI_CONNECT_CONTROLLER = *const NOT_DEFINED;
-pub type EFI_DISCONNECT_CONTROLLER = *const NOT_DEFINED;
 pub type EFI_OPEN_PROTOCOL_INFORMATION = *const NOT_DEFINED;
-pub type EFI_PROTOCOLS_PER_HANDLE = *const NOT_DEFINED;
 pub type EFI_INSTALL_MULTIPLE_PROTOCOL_INTERFACES = *const NOT_DEFINED;
 pub type EFI_UNINSTALL_MULTIPLE_PROTOCOL_INTERFACES = *const NOT_DEFINED;
 pub type EFI_CALCULATE_CRC32 = *const NOT_DEFINED;
@@ -98,13 +90,72 @@ pub type EFI_COPY_MEM = *const NOT_DEFINED;
 pub type EFI_SET_MEM = *const NOT_DEFINED;
 pub type EFI_CREATE_EVENT_EX = *const NOT_DEFINED;
 
-pub type EFI_ALLOCATE_POOL = extern "win64" fn(
+/// Signals Event whenever an interface of Protocol is installed or reinstalled, not when one is uninstalled
+pub type EFI_REGISTER_PROTOCOL_NOTIFY = extern "efiapi" fn(
+    Protocol: *const EFI_GUID,
+    Event: EFI_EVENT,
+    Registration: *mut *const VOID
+) -> EFI_STATUS;
+
+// DriverImageHandle is a null terminated list of drivers to try first, or null for the usual driver selection
+pub type EFI_CONNECT_CONTROLLER = extern "efiapi" fn(
+    ControllerHandle: EFI_HANDLE,
+    DriverImageHandle: *const EFI_HANDLE,
+    RemainingDevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
+    Recursive: BOOLEAN
+) -> EFI_STATUS;
+
+// A null DriverImageHandle disconnects every driver and a null ChildHandle destroys every child
+pub type EFI_DISCONNECT_CONTROLLER = extern "efiapi" fn(
+    ControllerHandle: EFI_HANDLE,
+    DriverImageHandle: EFI_HANDLE,
+    ChildHandle: EFI_HANDLE
+) -> EFI_STATUS;
+
+// Finds the handle with the protocol whose device path is the longest prefix of DevicePath, and moves DevicePath past it
+pub type EFI_LOCATE_DEVICE_PATH = extern "efiapi" fn(
+    Protocol: *const EFI_GUID,
+    DevicePath: *mut *const EFI_DEVICE_PATH_PROTOCOL,
+    Device: *mut EFI_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_INSTALL_CONFIGURATION_TABLE = extern "efiapi" fn(
+    Guid: *const EFI_GUID,
+    Table: *const VOID
+) -> EFI_STATUS;
+
+pub type EFI_GET_MEMORY_MAP = extern "efiapi" fn(
+    MemoryMapSize: *mut UINTN,
+    MemoryMap: *mut EFI_MEMORY_DESCRIPTOR,
+    MapKey: *mut UINTN,
+    DescriptorSize: *mut UINTN,
+    DescriptorVersion: *mut UINT32
+) -> EFI_STATUS;
+
+pub type EFI_EXIT_BOOT_SERVICES = extern "efiapi" fn(
+    ImageHandle: EFI_HANDLE,
+    MapKey: UINTN
+) -> EFI_STATUS;
+
+pub type EFI_ALLOCATE_PAGES = extern "efiapi" fn(
+    Type: EFI_ALLOCATE_TYPE,
+    MemoryType: EFI_MEMORY_TYPE,
+    Pages: UINTN,
+    Memory: *mut EFI_PHYSICAL_ADDRESS
+) -> EFI_STATUS;
+
+pub type EFI_FREE_PAGES = extern "efiapi" fn(
+    Memory: EFI_PHYSICAL_ADDRESS,
+    Pages: UINTN
+) -> EFI_STATUS;
+
+pub type EFI_ALLOCATE_POOL = extern "efiapi" fn(
     PoolType: EFI_MEMORY_TYPE,
     Size: UINTN,
     Buffer: *mut *const VOID
 ) -> EFI_STATUS;
 
-pub type EFI_FREE_POOL = extern "win64" fn(
+pub type EFI_FREE_POOL = extern "efiapi" fn(
     Buffer: *const VOID
 ) -> EFI_STATUS;
 
@@ -122,7 +173,7 @@ pub const EVT_NOTIFY_SIGNAL: UINT32 = 0x00000200;
 pub const EVT_SIGNAL_EXIT_BOOT_SERVICES: UINT32 = 0x00000201;
 pub const EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE: UINT32 = 0x60000202;
 
-pub type EFI_CREATE_EVENT = extern "win64" fn(
+pub type EFI_CREATE_EVENT = extern "efiapi" fn(
     Type: UINT32,
     NotifyTpl: EFI_TPL,
     NotifyFunction: Option<EFI_EVENT_NOTIFY>,
@@ -130,21 +181,21 @@ pub type EFI_CREATE_EVENT = extern "win64" fn(
     Event: *mut EFI_EVENT 
 ) -> EFI_STATUS;
 
-pub type EFI_CLOSE_EVENT = extern "win64" fn(
+pub type EFI_CLOSE_EVENT = extern "efiapi" fn(
     Event: EFI_EVENT 
 ) -> EFI_STATUS;
 
-pub type EFI_SIGNAL_EVENT = extern "win64" fn(
+pub type EFI_SIGNAL_EVENT = extern "efiapi" fn(
     Event: EFI_EVENT
 ) -> EFI_STATUS;
 
-pub type EFI_WAIT_FOR_EVENT = extern "win64" fn(
+pub type EFI_WAIT_FOR_EVENT = extern "efiapi" fn(
     NumberOfEvents: UINTN,
     Event: *const EFI_EVENT,
     Index: *mut UINTN
 ) -> EFI_STATUS;
 
-pub type EFI_CHECK_EVENT = extern "win64" fn(
+pub type EFI_CHECK_EVENT = extern "efiapi" fn(
     Event: EFI_EVENT
 ) -> EFI_STATUS;
 
@@ -156,7 +207,7 @@ pub enum EFI_TIMER_DELAY {
     TimerRelative
 }
 
-pub type EFI_SET_TIMER = extern "win64" fn(
+pub type EFI_SET_TIMER = extern "efiapi" fn(
     Event: EFI_EVENT,
     Type: EFI_TIMER_DELAY,
     TriggerTime: UINT64
@@ -168,20 +219,20 @@ pub enum EFI_INTERFACE_TYPE {
     EFI_NATIVE_INTERFACE = 0
 }
 
-pub type EFI_INSTALL_PROTOCOL_INTERFACE = extern "win64" fn(
+pub type EFI_INSTALL_PROTOCOL_INTERFACE = extern "efiapi" fn(
     Handle: *mut EFI_HANDLE,
     Protocol: *const EFI_GUID,
     InterfaceType: EFI_INTERFACE_TYPE,
     Interface: *const VOID
 ) -> EFI_STATUS;
 
-pub type EFI_UNINSTALL_PROTOCOL_INTERFACE  = extern "win64" fn(
+pub type EFI_UNINSTALL_PROTOCOL_INTERFACE  = extern "efiapi" fn(
     Handle: EFI_HANDLE,
     Protocol: *const EFI_GUID,
     Interface: *const VOID
 ) -> EFI_STATUS;
 
-pub type EFI_EVENT_NOTIFY = extern "win64" fn(
+pub type EFI_EVENT_NOTIFY = extern "efiapi" fn(
     Event: EFI_EVENT,
     Context: *const VOID
 ) -> EFI_STATUS;
@@ -193,7 +244,7 @@ pub const EFI_OPEN_PROTOCOL_BY_CHILD_CONTROLLER: UINT32 = 0x00000008;
 pub const EFI_OPEN_PROTOCOL_BY_DRIVER: UINT32 = 0x00000010;
 pub const EFI_OPEN_PROTOCOL_EXCLUSIVE: UINT32 = 0x00000020;
 
-pub type EFI_OPEN_PROTOCOL =  extern "win64" fn(
+pub type EFI_OPEN_PROTOCOL =  extern "efiapi" fn(
     Handle: EFI_HANDLE,
     Protocol: *const EFI_GUID,
     Interface: *mut *const VOID,
@@ -202,7 +253,7 @@ pub type EFI_OPEN_PROTOCOL =  extern "win64" fn(
     Attributes: UINT32
 ) -> EFI_STATUS;
 
-pub type EFI_CLOSE_PROTOCOL = extern "win64" fn(
+pub type EFI_CLOSE_PROTOCOL = extern "efiapi" fn(
   Handle: EFI_HANDLE,
   Protocol: *const EFI_GUID,
   AgentHandle: EFI_HANDLE,
@@ -221,7 +272,14 @@ pub enum EFI_LOCATE_SEARCH_TYPE {
   ByProtocol
 }
 
-pub type EFI_LOCATE_HANDLE_BUFFER = extern "win64" fn(
+/// The buffer is allocated from pool and the caller frees it, but not the GUIDs it points at
+pub type EFI_PROTOCOLS_PER_HANDLE = extern "efiapi" fn(
+    Handle: EFI_HANDLE,
+    ProtocolBuffer: *mut *const *const EFI_GUID,
+    ProtocolBufferCount: *mut UINTN
+) -> EFI_STATUS;
+
+pub type EFI_LOCATE_HANDLE_BUFFER = extern "efiapi" fn(
     SearchType: EFI_LOCATE_SEARCH_TYPE,
     Protocol: *const EFI_GUID,
     SearchKey: *const VOID,
@@ -229,13 +287,13 @@ pub type EFI_LOCATE_HANDLE_BUFFER = extern "win64" fn(
     Buffer: *mut *const EFI_HANDLE
 ) -> EFI_STATUS;
  
-pub type EFI_LOCATE_PROTOCOL = extern "win64" fn(
+pub type EFI_LOCATE_PROTOCOL = extern "efiapi" fn(
     Protocol: *const EFI_GUID,
     Registration: *const VOID,
     Interface: *mut *const VOID
 ) -> EFI_STATUS;
 
-pub type EFI_IMAGE_LOAD = extern "win64" fn(
+pub type EFI_IMAGE_LOAD = extern "efiapi" fn(
     BootPolicy: BOOLEAN,
     ParentImageHandle: EFI_HANDLE,
     DevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
@@ -244,17 +302,17 @@ pub type EFI_IMAGE_LOAD = extern "win64" fn(
     ImageHandle: *mut EFI_HANDLE
 ) -> EFI_STATUS;
 
-pub type EFI_IMAGE_START = extern "win64" fn(
+pub type EFI_IMAGE_START = extern "efiapi" fn(
     ImageHandle: EFI_HANDLE,
     ExitDataSize: *mut UINTN,
     ExitData: *mut *const CHAR16
 ) -> EFI_STATUS;
 
-pub type EFI_STALL = extern "win64" fn(
+pub type EFI_STALL = extern "efiapi" fn(
     Microseconds: UINTN
 ) -> EFI_STATUS;
 
-pub type EFI_GET_NEXT_MONOTONIC_COUNT = extern "win64" fn(
+pub type EFI_GET_NEXT_MONOTONIC_COUNT = extern "efiapi" fn(
     Count: *mut UINT64  
 ) -> EFI_STATUS;
 
@@ -288,3 +346,33 @@ pub enum EFI_MEMORY_TYPE {
 } 
 
 pub type EFI_PHYSICAL_ADDRESS = UINT64;
+
+pub type EFI_VIRTUAL_ADDRESS = UINT64;
+
+pub const EFI_MEMORY_DESCRIPTOR_VERSION: UINT32 = 1;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_MEMORY_DESCRIPTOR {
+    pub Type: UINT32,
+    pub PhysicalStart: EFI_PHYSICAL_ADDRESS,
+    pub VirtualStart: EFI_VIRTUAL_ADDRESS,
+    pub NumberOfPages: UINT64,
+    pub Attribute: UINT64,
+}
+
+// Memory attribute bits of EFI_MEMORY_DESCRIPTOR.Attribute
+pub const EFI_MEMORY_UC: UINT64 = 0x0000000000000001;
+pub const EFI_MEMORY_WC: UINT64 = 0x0000000000000002;
+pub const EFI_MEMORY_WT: UINT64 = 0x0000000000000004;
+pub const EFI_MEMORY_WB: UINT64 = 0x0000000000000008;
+pub const EFI_MEMORY_UCE: UINT64 = 0x0000000000000010;
+pub const EFI_MEMORY_WP: UINT64 = 0x0000000000001000;
+pub const EFI_MEMORY_RP: UINT64 = 0x0000000000002000;
+pub const EFI_MEMORY_XP: UINT64 = 0x0000000000004000;
+pub const EFI_MEMORY_NV: UINT64 = 0x0000000000008000;
+pub const EFI_MEMORY_MORE_RELIABLE: UINT64 = 0x0000000000010000;
+pub const EFI_MEMORY_RO: UINT64 = 0x0000000000020000;
+pub const EFI_MEMORY_SP: UINT64 = 0x0000000000040000;
+pub const EFI_MEMORY_CPU_CRYPTO: UINT64 = 0x0000000000080000;
+pub const EFI_MEMORY_RUNTIME: UINT64 = 0x8000000000000000;
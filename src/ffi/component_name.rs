@@ -0,0 +1,38 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_HANDLE,
+    EFI_STATUS,
+    CHAR8,
+    CHAR16,
+};
+
+pub const EFI_COMPONENT_NAME2_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x6a7a5cff, 0xe8d9, 0x4f70, [0xba, 0xda, 0x75, 0xab, 0x30, 0x25, 0xce, 0x14]);
+
+/// The same but with ISO 639-2 languages ("eng") rather than RFC 4646 ones ("en-US"). Older drivers only have this
+pub const EFI_COMPONENT_NAME_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x107a772c, 0xd5e1, 0x11d4, [0x9a, 0x46, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+
+/// Installed on a driver's image handle. EFI_COMPONENT_NAME_PROTOCOL has the same layout
+#[repr(C)]
+pub struct EFI_COMPONENT_NAME2_PROTOCOL {
+    pub GetDriverName: EFI_COMPONENT_NAME2_GET_DRIVER_NAME,
+    pub GetControllerName: EFI_COMPONENT_NAME2_GET_CONTROLLER_NAME,
+    /// The languages the names are in, separated by semicolons for version 2 and packed three letters each for 1
+    pub SupportedLanguages: *const CHAR8,
+}
+
+debug_as_table!(EFI_COMPONENT_NAME2_PROTOCOL);
+
+pub type EFI_COMPONENT_NAME2_GET_DRIVER_NAME = extern "efiapi" fn(
+    This: *const EFI_COMPONENT_NAME2_PROTOCOL,
+    Language: *const CHAR8,
+    DriverName: *mut *const CHAR16
+) -> EFI_STATUS;
+
+/// ChildHandle is null for the name of the controller itself rather than one of the children the driver made
+pub type EFI_COMPONENT_NAME2_GET_CONTROLLER_NAME = extern "efiapi" fn(
+    This: *const EFI_COMPONENT_NAME2_PROTOCOL,
+    ControllerHandle: EFI_HANDLE,
+    ChildHandle: EFI_HANDLE,
+    Language: *const CHAR8,
+    ControllerName: *mut *const CHAR16
+) -> EFI_STATUS;
@@ -98,49 +98,49 @@ pub const EFI_BACKGROUND_LIGHTGRAY: UINTN = EFI_BACKGROUND_BLUE | EFI_BACKGROUND
 // non-local displays, such as serial or LAN consoles.
 pub const EFI_WIDE_ATTRIBUTE: UINTN = 0x80;
 
-pub type EFI_TEXT_RESET = extern "win64" fn(
+pub type EFI_TEXT_RESET = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     ExtendedVerification: BOOLEAN
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_STRING = extern "win64" fn(
+pub type EFI_TEXT_STRING = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     String: *const CHAR16
  ) -> EFI_STATUS;
 
-pub type EFI_TEXT_TEST_STRING = extern "win64" fn(
+pub type EFI_TEXT_TEST_STRING = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     String: *const CHAR16
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_QUERY_MODE = extern "win64" fn(
+pub type EFI_TEXT_QUERY_MODE = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     ModeNumber: UINTN,
     Columns: *const UINTN,
     Rows: *const UINTN
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_SET_MODE = extern "win64" fn(
+pub type EFI_TEXT_SET_MODE = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     ModeNumber: UINTN
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_SET_ATTRIBUTE = extern "win64" fn(
+pub type EFI_TEXT_SET_ATTRIBUTE = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     Attribute: UINTN
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_CLEAR_SCREEN = extern "win64" fn(
+pub type EFI_TEXT_CLEAR_SCREEN = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_SET_CURSOR_POSITION = extern "win64" fn(
+pub type EFI_TEXT_SET_CURSOR_POSITION = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     Column: UINTN,
     Row: UINTN
 ) -> EFI_STATUS;
 
-pub type EFI_TEXT_ENABLE_CURSOR = extern "win64" fn(
+pub type EFI_TEXT_ENABLE_CURSOR = extern "efiapi" fn(
     This: *const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL,
     Visible: BOOLEAN
 ) -> EFI_STATUS;
@@ -183,16 +183,18 @@ pub struct EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL {
   pub Mode: *const EFI_SIMPLE_TEXT_OUTPUT_MODE,
 }
 
+debug_as_table!(EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL);
+
 
 // INPUT PROTOCOL
 pub const EFI_SIMPLE_TEXT_INPUT_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x387477c1, 0x69c7, 0x11d2, [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
 
-pub type EFI_INPUT_RESET = extern "win64" fn(
+pub type EFI_INPUT_RESET = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_PROTOCOL,
     ExtendedVerification: BOOLEAN
 ) -> EFI_STATUS;
 
-pub type EFI_INPUT_READ_KEY = extern "win64" fn(
+pub type EFI_INPUT_READ_KEY = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_PROTOCOL,
     Key: *mut EFI_INPUT_KEY
 ) -> EFI_STATUS;
@@ -213,6 +215,32 @@ impl Default for EFI_INPUT_KEY  {
     }
 }
 
+// EFI_INPUT_KEY ScanCodes
+pub const SCAN_NULL: UINT16 = 0x0000;
+pub const SCAN_UP: UINT16 = 0x0001;
+pub const SCAN_DOWN: UINT16 = 0x0002;
+pub const SCAN_RIGHT: UINT16 = 0x0003;
+pub const SCAN_LEFT: UINT16 = 0x0004;
+pub const SCAN_HOME: UINT16 = 0x0005;
+pub const SCAN_END: UINT16 = 0x0006;
+pub const SCAN_INSERT: UINT16 = 0x0007;
+pub const SCAN_DELETE: UINT16 = 0x0008;
+pub const SCAN_PAGE_UP: UINT16 = 0x0009;
+pub const SCAN_PAGE_DOWN: UINT16 = 0x000A;
+pub const SCAN_F1: UINT16 = 0x000B;
+pub const SCAN_F2: UINT16 = 0x000C;
+pub const SCAN_F3: UINT16 = 0x000D;
+pub const SCAN_F4: UINT16 = 0x000E;
+pub const SCAN_F5: UINT16 = 0x000F;
+pub const SCAN_F6: UINT16 = 0x0010;
+pub const SCAN_F7: UINT16 = 0x0011;
+pub const SCAN_F8: UINT16 = 0x0012;
+pub const SCAN_F9: UINT16 = 0x0013;
+pub const SCAN_F10: UINT16 = 0x0014;
+pub const SCAN_F11: UINT16 = 0x0015;
+pub const SCAN_F12: UINT16 = 0x0016;
+pub const SCAN_ESC: UINT16 = 0x0017;
+
 #[repr(C)]
 pub struct EFI_SIMPLE_TEXT_INPUT_PROTOCOL {
     pub Reset: EFI_INPUT_RESET,
@@ -220,6 +248,8 @@ pub struct EFI_SIMPLE_TEXT_INPUT_PROTOCOL {
     pub WaitForKey: EFI_EVENT,
 }
 
+debug_as_table!(EFI_SIMPLE_TEXT_INPUT_PROTOCOL);
+
 pub const EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xdd9e7534, 0x7762, 0x4698, [0x8c, 0x14, 0xf5, 0x85, 0x17, 0xa6, 0x25, 0xaa]);
 
 #[repr(C)]
@@ -232,37 +262,40 @@ pub struct EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL{
     pub UnregisterKeyNotify: EFI_UNREGISTER_KEYSTROKE_NOTIFY,
 }
 
-pub type EFI_INPUT_RESET_EX = extern "win64" fn(
+debug_as_table!(EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL);
+
+pub type EFI_INPUT_RESET_EX = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL,
     ExtendedVerification: BOOLEAN
 ) -> EFI_STATUS;
 
-pub type EFI_INPUT_READ_KEY_EX = extern "win64" fn(
+pub type EFI_INPUT_READ_KEY_EX = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL,
     KeyData: *mut EFI_KEY_DATA
 ) -> EFI_STATUS;
 
-pub type EFI_SET_STATE = extern "win64" fn(
+pub type EFI_SET_STATE = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL,
     KeyToggleState: *const EFI_KEY_TOGGLE_STATE 
 ) -> EFI_STATUS;
 
-pub type EFI_REGISTER_KEYSTROKE_NOTIFY = extern "win64" fn(
+pub type EFI_REGISTER_KEYSTROKE_NOTIFY = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL,
     KeyData: *const EFI_KEY_DATA,
     KeyNotificationFunction: EFI_KEY_NOTIFY_FUNCTION,
     NotifyHandle: *const *mut VOID
 ) -> EFI_STATUS;
 
-pub type EFI_KEY_NOTIFY_FUNCTION = extern "win64" fn(
+pub type EFI_KEY_NOTIFY_FUNCTION = extern "efiapi" fn(
     KeyData: *const EFI_KEY_DATA
 ) -> EFI_STATUS;
 
-pub type EFI_UNREGISTER_KEYSTROKE_NOTIFY = extern "win64" fn(
+pub type EFI_UNREGISTER_KEYSTROKE_NOTIFY = extern "efiapi" fn(
     This: *mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL,
     NotificationHandle: *const VOID
 );
 
+#[derive(Debug)]
 #[repr(C)]
 pub struct EFI_KEY_DATA {
     pub Key: EFI_INPUT_KEY,
@@ -298,6 +331,7 @@ pub const EFI_SCROLL_LOCK_ACTIVE: EFI_KEY_TOGGLE_STATE = 0x01;
 pub const EFI_NUM_LOCK_ACTIVE: EFI_KEY_TOGGLE_STATE = 0x02;
 pub const EFI_CAPS_LOCK_ACTIVE: EFI_KEY_TOGGLE_STATE = 0x04;
 
+#[derive(Debug)]
 pub struct EFI_KEY_STATE {
     pub KeyShiftState: UINT32,
     pub KeyToggleState: EFI_KEY_TOGGLE_STATE,
@@ -310,4 +344,58 @@ impl Default for EFI_KEY_STATE  {
             KeyToggleState: 0x00
         }
     }
-}
\ No newline at end of file
+}
+// ABSOLUTE POINTER PROTOCOL, for touch screens and tablets
+pub const EFI_ABSOLUTE_POINTER_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x8d59d32b, 0xc655, 0x4ae9, [0x9b, 0x15, 0xf2, 0x59, 0x04, 0x99, 0x2a, 0x43]);
+
+#[repr(C)]
+pub struct EFI_ABSOLUTE_POINTER_PROTOCOL {
+    pub Reset: EFI_ABSOLUTE_POINTER_RESET,
+    pub GetState: EFI_ABSOLUTE_POINTER_GET_STATE,
+    pub WaitForInput: EFI_EVENT,
+    pub Mode: *const EFI_ABSOLUTE_POINTER_MODE,
+}
+
+debug_as_table!(EFI_ABSOLUTE_POINTER_PROTOCOL);
+
+pub type EFI_ABSOLUTE_POINTER_RESET = extern "efiapi" fn(
+    This: *const EFI_ABSOLUTE_POINTER_PROTOCOL,
+    ExtendedVerification: BOOLEAN
+) -> EFI_STATUS;
+
+// EFI_NOT_READY if nothing's changed since last time
+pub type EFI_ABSOLUTE_POINTER_GET_STATE = extern "efiapi" fn(
+    This: *const EFI_ABSOLUTE_POINTER_PROTOCOL,
+    State: *mut EFI_ABSOLUTE_POINTER_STATE
+) -> EFI_STATUS;
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_ABSOLUTE_POINTER_MODE {
+    pub AbsoluteMinX: UINT64,
+    pub AbsoluteMinY: UINT64,
+    pub AbsoluteMinZ: UINT64,
+    pub AbsoluteMaxX: UINT64,
+    pub AbsoluteMaxY: UINT64,
+    pub AbsoluteMaxZ: UINT64,
+    pub Attributes: UINT32,
+}
+
+#[allow(non_upper_case_globals)]
+pub const EFI_ABSP_SupportsAltActive: UINT32 = 0x00000001;
+#[allow(non_upper_case_globals)]
+pub const EFI_ABSP_SupportsPressureAsZ: UINT32 = 0x00000002;
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_ABSOLUTE_POINTER_STATE {
+    pub CurrentX: UINT64,
+    pub CurrentY: UINT64,
+    pub CurrentZ: UINT64,
+    pub ActiveButtons: UINT32,
+}
+
+#[allow(non_upper_case_globals)]
+pub const EFI_ABSP_TouchActive: UINT32 = 0x00000001;
+#[allow(non_upper_case_globals)]
+pub const EFI_ABS_AltActive: UINT32 = 0x00000002;
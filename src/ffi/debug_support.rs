@@ -0,0 +1,137 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    INTN,
+    UINTN,
+    UINT8,
+    UINT32,
+    UINT64,
+    VOID,
+    NOT_DEFINED,
+};
+
+pub const EFI_DEBUG_SUPPORT_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x2755590c, 0x6f3c, 0x42fa, [0x9e, 0xa4, 0xa3, 0xba, 0x54, 0x3c, 0xda, 0x25]);
+
+#[repr(C)]
+pub struct EFI_DEBUG_SUPPORT_PROTOCOL {
+    pub Isa: EFI_INSTRUCTION_SET_ARCHITECTURE,
+    pub GetMaximumProcessorIndex: EFI_GET_MAXIMUM_PROCESSOR_INDEX,
+    pub RegisterPeriodicCallback: EFI_REGISTER_PERIODIC_CALLBACK,
+    pub RegisterExceptionCallback: EFI_REGISTER_EXCEPTION_CALLBACK,
+    pub InvalidateInstructionCache: EFI_INVALIDATE_INSTRUCTION_CACHE,
+}
+
+debug_as_table!(EFI_DEBUG_SUPPORT_PROTOCOL);
+
+pub type EFI_INSTRUCTION_SET_ARCHITECTURE = UINT32;
+#[allow(non_upper_case_globals)]
+pub const IsaIa32: EFI_INSTRUCTION_SET_ARCHITECTURE = 0x014c;
+#[allow(non_upper_case_globals)]
+pub const IsaX64: EFI_INSTRUCTION_SET_ARCHITECTURE = 0x8664;
+#[allow(non_upper_case_globals)]
+pub const IsaIpf: EFI_INSTRUCTION_SET_ARCHITECTURE = 0x0200;
+#[allow(non_upper_case_globals)]
+pub const IsaEbc: EFI_INSTRUCTION_SET_ARCHITECTURE = 0x0EBC;
+#[allow(non_upper_case_globals)]
+pub const IsaArm: EFI_INSTRUCTION_SET_ARCHITECTURE = 0x01c2;
+#[allow(non_upper_case_globals)]
+pub const IsaAArch64: EFI_INSTRUCTION_SET_ARCHITECTURE = 0xAA64;
+#[allow(non_upper_case_globals)]
+pub const IsaRiscV64: EFI_INSTRUCTION_SET_ARCHITECTURE = 0x5064;
+
+pub type EFI_GET_MAXIMUM_PROCESSOR_INDEX = *const NOT_DEFINED;
+
+pub type EFI_REGISTER_PERIODIC_CALLBACK = *const NOT_DEFINED;
+
+/// A null ExceptionCallback unregisters the one registered for ExceptionType
+pub type EFI_REGISTER_EXCEPTION_CALLBACK = extern "efiapi" fn(
+    This: *const EFI_DEBUG_SUPPORT_PROTOCOL,
+    ProcessorIndex: UINTN,
+    ExceptionCallback: Option<EFI_EXCEPTION_CALLBACK>,
+    ExceptionType: EFI_EXCEPTION_TYPE
+) -> EFI_STATUS;
+
+pub type EFI_INVALIDATE_INSTRUCTION_CACHE = *const NOT_DEFINED;
+
+pub type EFI_EXCEPTION_CALLBACK = extern "efiapi" fn(
+    ExceptionType: EFI_EXCEPTION_TYPE,
+    SystemContext: EFI_SYSTEM_CONTEXT
+);
+
+pub type EFI_EXCEPTION_TYPE = INTN;
+
+// X64 exception types
+pub const EXCEPT_X64_DIVIDE_ERROR: EFI_EXCEPTION_TYPE = 0;
+pub const EXCEPT_X64_DEBUG: EFI_EXCEPTION_TYPE = 1;
+pub const EXCEPT_X64_NMI: EFI_EXCEPTION_TYPE = 2;
+pub const EXCEPT_X64_BREAKPOINT: EFI_EXCEPTION_TYPE = 3;
+pub const EXCEPT_X64_OVERFLOW: EFI_EXCEPTION_TYPE = 4;
+pub const EXCEPT_X64_BOUND: EFI_EXCEPTION_TYPE = 5;
+pub const EXCEPT_X64_INVALID_OPCODE: EFI_EXCEPTION_TYPE = 6;
+pub const EXCEPT_X64_DOUBLE_FAULT: EFI_EXCEPTION_TYPE = 8;
+pub const EXCEPT_X64_INVALID_TSS: EFI_EXCEPTION_TYPE = 10;
+pub const EXCEPT_X64_SEG_NOT_PRESENT: EFI_EXCEPTION_TYPE = 11;
+pub const EXCEPT_X64_STACK_FAULT: EFI_EXCEPTION_TYPE = 12;
+pub const EXCEPT_X64_GP_FAULT: EFI_EXCEPTION_TYPE = 13;
+pub const EXCEPT_X64_PAGE_FAULT: EFI_EXCEPTION_TYPE = 14;
+pub const EXCEPT_X64_FP_ERROR: EFI_EXCEPTION_TYPE = 16;
+pub const EXCEPT_X64_ALIGNMENT_CHECK: EFI_EXCEPTION_TYPE = 17;
+pub const EXCEPT_X64_MACHINE_CHECK: EFI_EXCEPTION_TYPE = 18;
+pub const EXCEPT_X64_SIMD: EFI_EXCEPTION_TYPE = 19;
+
+/// In the spec a union of pointers to each ISA's context. Points to the EFI_SYSTEM_CONTEXT_<ISA> of the Isa in the
+/// protocol
+pub type EFI_SYSTEM_CONTEXT = *mut VOID;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_FX_SAVE_STATE_X64 {
+    pub State: [UINT8; 512],
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_SYSTEM_CONTEXT_X64 {
+    pub ExceptionData: UINT64,
+    pub FxSaveState: EFI_FX_SAVE_STATE_X64,
+    pub Dr0: UINT64,
+    pub Dr1: UINT64,
+    pub Dr2: UINT64,
+    pub Dr3: UINT64,
+    pub Dr6: UINT64,
+    pub Dr7: UINT64,
+    pub Cr0: UINT64,
+    pub Cr1: UINT64,
+    pub Cr2: UINT64,
+    pub Cr3: UINT64,
+    pub Cr4: UINT64,
+    pub Cr8: UINT64,
+    pub Rflags: UINT64,
+    pub Ldtr: UINT64,
+    pub Tr: UINT64,
+    pub Gdtr: [UINT64; 2],
+    pub Idtr: [UINT64; 2],
+    pub Rip: UINT64,
+    pub Gs: UINT64,
+    pub Fs: UINT64,
+    pub Es: UINT64,
+    pub Ds: UINT64,
+    pub Cs: UINT64,
+    pub Ss: UINT64,
+    pub Rdi: UINT64,
+    pub Rsi: UINT64,
+    pub Rbp: UINT64,
+    pub Rsp: UINT64,
+    pub Rbx: UINT64,
+    pub Rdx: UINT64,
+    pub Rcx: UINT64,
+    pub Rax: UINT64,
+    pub R8: UINT64,
+    pub R9: UINT64,
+    pub R10: UINT64,
+    pub R11: UINT64,
+    pub R12: UINT64,
+    pub R13: UINT64,
+    pub R14: UINT64,
+    pub R15: UINT64,
+}
@@ -0,0 +1,34 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT32,
+    VOID,
+};
+
+pub const EFI_DECOMPRESS_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xd8117cfe, 0x94a6, 0x11d4, [0x9a, 0x3a, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+
+#[repr(C)]
+pub struct EFI_DECOMPRESS_PROTOCOL {
+    pub GetInfo: EFI_DECOMPRESS_GET_INFO,
+    pub Decompress: EFI_DECOMPRESS_DECOMPRESS,
+}
+
+debug_as_table!(EFI_DECOMPRESS_PROTOCOL);
+
+pub type EFI_DECOMPRESS_GET_INFO = extern "efiapi" fn(
+    This: *const EFI_DECOMPRESS_PROTOCOL,
+    Source: *const VOID,
+    SourceSize: UINT32,
+    DestinationSize: *mut UINT32,
+    ScratchSize: *mut UINT32
+) -> EFI_STATUS;
+
+pub type EFI_DECOMPRESS_DECOMPRESS = extern "efiapi" fn(
+    This: *const EFI_DECOMPRESS_PROTOCOL,
+    Source: *const VOID,
+    SourceSize: UINT32,
+    Destination: *mut VOID,
+    DestinationSize: UINT32,
+    Scratch: *mut VOID,
+    ScratchSize: UINT32
+) -> EFI_STATUS;
@@ -29,35 +29,37 @@ pub struct EFI_DEVICE_PATH_UTILITIES_PROTOCOL {
     pub CreateDeviceNode: EFI_DEVICE_PATH_UTILS_CREATE_NODE,
 }
 
+debug_as_table!(EFI_DEVICE_PATH_UTILITIES_PROTOCOL);
+
 pub type EFI_DEVICE_PATH_UTILS_GET_DEVICE_PATH_SIZE = *const NOT_DEFINED;
 pub type EFI_DEVICE_PATH_UTILS_APPEND_INSTANCE = *const NOT_DEFINED;
 pub type EFI_DEVICE_PATH_UTILS_GET_NEXT_INSTANCE = *const NOT_DEFINED;
 pub type EFI_DEVICE_PATH_UTILS_IS_MULTI_INSTANCE = *const NOT_DEFINED;
 
-pub type EFI_DEVICE_PATH_UTILS_APPEND_PATH = extern "win64" fn(
+pub type EFI_DEVICE_PATH_UTILS_APPEND_PATH = extern "efiapi" fn(
     Src1: *const EFI_DEVICE_PATH_PROTOCOL,
     Src2: *const EFI_DEVICE_PATH_PROTOCOL
 ) -> *const EFI_DEVICE_PATH_PROTOCOL;
 
-pub type EFI_DEVICE_PATH_UTILS_APPEND_NODE = extern "win64" fn(
+pub type EFI_DEVICE_PATH_UTILS_APPEND_NODE = extern "efiapi" fn(
     DevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
     DevicePath: *const EFI_DEVICE_PATH_PROTOCOL
 ) -> *const EFI_DEVICE_PATH_PROTOCOL;
 
-pub type EFI_DEVICE_PATH_UTILS_CREATE_NODE = extern "win64" fn(
+pub type EFI_DEVICE_PATH_UTILS_CREATE_NODE = extern "efiapi" fn(
     NodeType: UINT8,
     NodeSubType: UINT8,
     NodeLength: UINT16
 ) -> *const EFI_DEVICE_PATH_PROTOCOL;
 
-pub type EFI_DEVICE_PATH_UTILS_DUP_DEVICE_PATH = extern "win64" fn(
+pub type EFI_DEVICE_PATH_UTILS_DUP_DEVICE_PATH = extern "efiapi" fn(
     DevicePath: *const EFI_DEVICE_PATH_PROTOCOL
 ) -> *const EFI_DEVICE_PATH_PROTOCOL;
 
 pub const EFI_DEVICE_PATH_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x09576e91, 0x6d3f, 0x11d2, [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
 
 // Almost all the structs here are unaligned (repr(packed)) because UEFI spec wants it that way
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct EFI_DEVICE_PATH_PROTOCOL {
     pub Type: UINT8,
@@ -70,6 +72,7 @@ pub const HARDWARE_DEVICE_PATH: UINT8 = 0x01;
 
 pub const HW_PCI_DP: UINT8 = 0x01;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct PCI_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -80,6 +83,7 @@ pub struct PCI_DEVICE_PATH {
 
 pub const HW_PCCARD_DP: UINT8 = 0x02;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct PCCARD_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -89,6 +93,7 @@ pub struct PCCARD_DEVICE_PATH {
 
 pub const HW_MEMMAP_DP: UINT8 = 0x03;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct MEMMAP_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -99,6 +104,7 @@ pub struct MEMMAP_DEVICE_PATH {
 
 pub const HW_VENDOR_DP: UINT8 = 0x04;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct VENDOR_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -108,6 +114,7 @@ pub struct VENDOR_DEVICE_PATH {
 
 pub const HW_CONTROLLER_DP: UINT8 = 0x05;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct CONTROLLER_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -118,6 +125,7 @@ pub struct CONTROLLER_DEVICE_PATH {
 pub const ACPI_DEVICE_PATH: UINT8 = 0x02;
 pub const ACPI_DP: UINT8  = 0x01;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct ACPI_HID_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -128,6 +136,7 @@ pub struct ACPI_HID_DEVICE_PATH {
 
 pub const ACPI_EXTENDED_DP: UINT8 = 0x02;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct ACPI_EXTENDED_HID_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -143,6 +152,7 @@ pub const MESSAGING_DEVICE_PATH: UINT8 = 0x03;
 
 pub const MSG_ATAPI_DP: UINT8 = 0x01;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct  ATAPI_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -162,6 +172,7 @@ pub struct  ATAPI_DEVICE_PATH {
 
 pub const MSG_SCSI_DP: UINT8 = 0x02;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct SCSI_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -177,6 +188,7 @@ pub struct SCSI_DEVICE_PATH {
 
 pub const MSG_FIBRECHANNEL_DP: UINT8 = 0x03;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct FIBRECHANNEL_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -196,6 +208,7 @@ pub struct FIBRECHANNEL_DEVICE_PATH {
 
 pub const MSG_FIBRECHANNELEX_DP: UINT8 = 0x15;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct FIBRECHANNELEX_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -215,6 +228,7 @@ pub struct FIBRECHANNELEX_DEVICE_PATH {
 
 pub const MSG_1394_DP: UINT8 = 0x04;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct F1394_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -230,6 +244,7 @@ pub struct F1394_DEVICE_PATH {
 
 pub const MSG_USB_DP: UINT8 = 0x05;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct USB_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -245,6 +260,7 @@ pub struct USB_DEVICE_PATH {
 
 pub const MSG_USB_CLASS_DP: UINT8 = 0x0f;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct USB_CLASS_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -280,6 +296,7 @@ pub const MSG_USB_WWID_DP: UINT8 = 0x10;
 ///
 /// This device path describes a USB device using its serial number.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct USB_WWID_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -309,6 +326,7 @@ pub struct USB_WWID_DEVICE_PATH {
 ///
 pub const MSG_DEVICE_LOGICAL_UNIT_DP: UINT8 = 0x11;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct DEVICE_LOGICAL_UNIT_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -323,6 +341,7 @@ pub struct DEVICE_LOGICAL_UNIT_DEVICE_PATH {
 ///
 pub const MSG_SATA_DP: UINT8 = 0x12;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct SATA_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -353,6 +372,7 @@ pub const SATA_HBA_DIRECT_CONNECT_FLAG: UINTN = 0x8000;
 ///
 pub const MSG_I2O_DP: UINT8 = 0x06;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct I2O_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -367,6 +387,7 @@ pub struct I2O_DEVICE_PATH {
 ///
 pub const MSG_MAC_ADDR_DP: UINT8 = 0x0b;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct MAC_ADDR_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -386,6 +407,7 @@ pub struct MAC_ADDR_DEVICE_PATH {
 #[allow(non_upper_case_globals)]
 pub const MSG_IPv4_DP: UINT8  = 0x0c;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct IPv4_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -430,6 +452,7 @@ pub struct IPv4_DEVICE_PATH {
 #[allow(non_upper_case_globals)]
 pub const MSG_IPv6_DP: UINT8 = 0x0d;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct IPv6_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -476,6 +499,7 @@ pub struct IPv6_DEVICE_PATH {
 ///
 pub const MSG_INFINIBAND_DP: UINT8 = 0x09;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct INFINIBAND_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -519,6 +543,7 @@ pub const INFINIBAND_RESOURCE_FLAG_NETWORK_PROTOCOL: UINTN = 0x10;
 ///
 pub const MSG_UART_DP: UINT8 = 0x0e;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct UART_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -572,6 +597,7 @@ pub type VENDOR_DEFINED_DEVICE_PATH = VENDOR_DEVICE_PATH;
 /// UART Flow Control Messaging Device Path
 ///
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct UART_FLOW_CONTROL_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -596,6 +622,7 @@ pub const UART_FLOW_CONTROL_XON_XOFF: UINTN = 0x00000010;
 /// Serial Attached SCSI (SAS) Device Path.
 ///
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct SAS_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -630,6 +657,7 @@ pub struct SAS_DEVICE_PATH {
 ///
 pub const MSG_SASEX_DP: UINT8 = 0x16;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct SASEX_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -656,6 +684,7 @@ pub struct SASEX_DEVICE_PATH {
 ///
 pub const MSG_NVME_NAMESPACE_DP: UINT8 = 0x17;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct NVME_NAMESPACE_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -668,6 +697,7 @@ pub struct NVME_NAMESPACE_DEVICE_PATH {
 ///
 pub const MSG_ISCSI_DP: UINT8 = 0x13;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct ISCSI_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -709,6 +739,7 @@ pub const ISCSI_LOGIN_OPTION_CHAP_UNI: UINTN = 0x2000;
 ///
 pub const MSG_VLAN_DP: UINT8 = 0x14;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct VLAN_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -731,6 +762,7 @@ pub const MEDIA_HARDDRIVE_DP: UINT8 = 0x01;
 ///
 /// The Hard Drive Media Device Path is used to represent a partition on a hard drive.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct HARDDRIVE_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -787,6 +819,7 @@ pub const MEDIA_CDROM_DP: UINT8 = 0x02;
 ///
 /// The CD-ROM Media Device Path is used to define a system partition that exists on a CD-ROM.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct CDROM_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -814,6 +847,7 @@ pub const MEDIA_VENDOR_DP: UINT8 = 0x03;  //< Media vendor device path subtype.;
 ///
 pub const MEDIA_FILEPATH_DP: UINT8 = 0x04;
 
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct FILEPATH_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -835,6 +869,7 @@ pub const MEDIA_PROTOCOL_DP: UINT8 = 0x05;
 /// used in a device path at the location of the path specified. 
 /// Many protocols are inherent to the style of device path.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct MEDIA_PROTOCOL_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -852,6 +887,7 @@ pub const MEDIA_PIWG_FW_FILE_DP: UINT8 = 0x06;
 ///
 /// This device path is used by systems implementing the UEFI PI Specification 1.0 to describe a firmware file.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct MEDIA_FW_VOL_FILEPATH_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -869,6 +905,7 @@ pub const MEDIA_PIWG_FW_VOL_DP: UINT8 = 0x07;
 ///
 /// This device path is used by systems implementing the UEFI PI Specification 1.0 to describe a firmware volume.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct MEDIA_FW_VOL_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -886,6 +923,7 @@ pub const MEDIA_RELATIVE_OFFSET_RANGE_DP: UINT8 = 0x08;
 ///
 /// Used to describe the offset range of media relative.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct MEDIA_RELATIVE_OFFSET_RANGE_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -907,6 +945,7 @@ pub const BBS_BBS_DP: UINT8 = 0x01;
 ///
 /// This Device Path is used to describe the booting of non-EFI-aware operating systems.
 ///
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct BBS_BBS_DEVICE_PATH {
   pub Header: EFI_DEVICE_PATH_PROTOCOL,
@@ -1001,13 +1040,15 @@ pub struct EFI_DEVICE_PATH_TO_TEXT_PROTOCOL {
     pub ConvertDevicePathToText: EFI_DEVICE_PATH_TO_TEXT_PATH,
 }
 
-pub type EFI_DEVICE_PATH_TO_TEXT_NODE = extern "win64" fn(
+debug_as_table!(EFI_DEVICE_PATH_TO_TEXT_PROTOCOL);
+
+pub type EFI_DEVICE_PATH_TO_TEXT_NODE = extern "efiapi" fn(
     DeviceNode: *const EFI_DEVICE_PATH_PROTOCOL,
     DisplayOnly: BOOLEAN,
     AllowShortcuts: BOOLEAN
 ) -> *const CHAR16;
 
-pub type EFI_DEVICE_PATH_TO_TEXT_PATH = extern "win64" fn(
+pub type EFI_DEVICE_PATH_TO_TEXT_PATH = extern "efiapi" fn(
     DevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
     DisplayOnly: BOOLEAN,
     AllowShortcuts: BOOLEAN
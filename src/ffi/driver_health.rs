@@ -0,0 +1,63 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_HANDLE,
+    EFI_STATUS,
+    UINT32,
+    UINT64,
+    UINTN,
+};
+use ffi::hii::{EFI_HII_HANDLE, EFI_STRING_ID};
+
+pub const EFI_DRIVER_HEALTH_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x2a534210, 0x9280, 0x41d8, [0xae, 0x79, 0xca, 0xda, 0x01, 0xa2, 0xb1, 0x27]);
+
+/// Installed on a driver's image handle
+#[repr(C)]
+pub struct EFI_DRIVER_HEALTH_PROTOCOL {
+    pub GetHealthStatus: EFI_DRIVER_HEALTH_GET_HEALTH_STATUS,
+    pub Repair: EFI_DRIVER_HEALTH_REPAIR,
+}
+
+debug_as_table!(EFI_DRIVER_HEALTH_PROTOCOL);
+
+/// A null ControllerHandle gets the health of the driver and everything it manages together. MessageList is
+/// allocated from pool and the caller frees it
+pub type EFI_DRIVER_HEALTH_GET_HEALTH_STATUS = extern "efiapi" fn(
+    This: *const EFI_DRIVER_HEALTH_PROTOCOL,
+    ControllerHandle: EFI_HANDLE,
+    ChildHandle: EFI_HANDLE,
+    HealthStatus: *mut EFI_DRIVER_HEALTH_STATUS,
+    MessageList: *mut *const EFI_DRIVER_HEALTH_HII_MESSAGE,
+    FormHiiHandle: *mut EFI_HII_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_DRIVER_HEALTH_REPAIR = extern "efiapi" fn(
+    This: *const EFI_DRIVER_HEALTH_PROTOCOL,
+    ControllerHandle: EFI_HANDLE,
+    ChildHandle: EFI_HANDLE,
+    ProgressNotification: Option<EFI_DRIVER_HEALTH_REPAIR_NOTIFY>
+) -> EFI_STATUS;
+
+pub type EFI_DRIVER_HEALTH_REPAIR_NOTIFY = extern "efiapi" fn(
+    Value: UINTN,
+    Limit: UINTN
+) -> EFI_STATUS;
+
+// EfiDriverHealthStatusHealthy and so on
+pub type EFI_DRIVER_HEALTH_STATUS = UINT32;
+
+pub const EFI_DRIVER_HEALTH_STATUS_HEALTHY: EFI_DRIVER_HEALTH_STATUS = 0;
+pub const EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED: EFI_DRIVER_HEALTH_STATUS = 1;
+pub const EFI_DRIVER_HEALTH_STATUS_CONFIGURATION_REQUIRED: EFI_DRIVER_HEALTH_STATUS = 2;
+pub const EFI_DRIVER_HEALTH_STATUS_FAILED: EFI_DRIVER_HEALTH_STATUS = 3;
+pub const EFI_DRIVER_HEALTH_STATUS_RECONNECT_REQUIRED: EFI_DRIVER_HEALTH_STATUS = 4;
+pub const EFI_DRIVER_HEALTH_STATUS_REBOOT_REQUIRED: EFI_DRIVER_HEALTH_STATUS = 5;
+
+/// The list ends with an entry whose HiiHandle is null
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_DRIVER_HEALTH_HII_MESSAGE {
+    pub HiiHandle: EFI_HII_HANDLE,
+    pub StringId: EFI_STRING_ID,
+    /// Driver specific. Zero if there's no code
+    pub MessageCode: UINT64,
+}
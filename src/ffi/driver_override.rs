@@ -0,0 +1,58 @@
+// The protocols ConnectController asks, in this order, which drivers should get first go at a controller
+
+use ffi::base::{
+    EFI_GUID,
+    EFI_HANDLE,
+    EFI_STATUS,
+};
+use super::device_path::EFI_DEVICE_PATH_PROTOCOL;
+
+pub const EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x6b30c738, 0xa391, 0x11d4, [0x9a, 0x3b, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+pub const EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x3bc1b285, 0x8a15, 0x4a82, [0xaa, 0xbf, 0x4d, 0x7d, 0x13, 0xfb, 0x32, 0x65]);
+
+/// At most one, installed by the platform
+#[repr(C)]
+pub struct EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL {
+    pub GetDriver: EFI_PLATFORM_DRIVER_OVERRIDE_GET_DRIVER,
+    pub GetDriverPath: EFI_PLATFORM_DRIVER_OVERRIDE_GET_DRIVER_PATH,
+    pub DriverLoaded: EFI_PLATFORM_DRIVER_OVERRIDE_DRIVER_LOADED,
+}
+
+debug_as_table!(EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL);
+
+/// Walks the drivers for the controller in order: pass null for the first and the previous one for the next.
+/// EFI_NOT_FOUND after the last
+pub type EFI_PLATFORM_DRIVER_OVERRIDE_GET_DRIVER = extern "efiapi" fn(
+    This: *const EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL,
+    ControllerHandle: EFI_HANDLE,
+    DriverImageHandle: *mut EFI_HANDLE
+) -> EFI_STATUS;
+
+/// The same but for drivers that haven't been loaded yet, by the path to their image
+pub type EFI_PLATFORM_DRIVER_OVERRIDE_GET_DRIVER_PATH = extern "efiapi" fn(
+    This: *const EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL,
+    ControllerHandle: EFI_HANDLE,
+    DriverImagePath: *mut *const EFI_DEVICE_PATH_PROTOCOL
+) -> EFI_STATUS;
+
+/// Tells the platform the image at a path GetDriverPath gave has been loaded, so GetDriver returns it
+pub type EFI_PLATFORM_DRIVER_OVERRIDE_DRIVER_LOADED = extern "efiapi" fn(
+    This: *const EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL,
+    ControllerHandle: EFI_HANDLE,
+    DriverImagePath: *const EFI_DEVICE_PATH_PROTOCOL,
+    DriverImageHandle: EFI_HANDLE
+) -> EFI_STATUS;
+
+/// Installed on a controller by the bus driver that made it, e.g. for a PCI option ROM's driver
+#[repr(C)]
+pub struct EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL {
+    pub GetDriver: EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_GET_DRIVER,
+}
+
+debug_as_table!(EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL);
+
+/// Walks the drivers in order as with the platform's GetDriver
+pub type EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_GET_DRIVER = extern "efiapi" fn(
+    This: *const EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL,
+    DriverImageHandle: *mut EFI_HANDLE
+) -> EFI_STATUS;
@@ -0,0 +1,71 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINTN,
+    VOID,
+};
+
+pub const EFI_EAP_CONFIGURATION_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xe5b58dbb, 0x7688, 0x44b4, [0x97, 0xbf, 0x5f, 0x1d, 0x4b, 0x7c, 0xc8, 0xdb]);
+
+pub type EFI_EAP_TYPE = UINT8;
+
+// EAP method numbers as assigned by IANA, plus 0 for settings that aren't specific to one method
+pub const EFI_EAP_TYPE_ATTRIBUTE: EFI_EAP_TYPE = 0;
+pub const EFI_EAP_TYPE_IDENTITY: EFI_EAP_TYPE = 1;
+pub const EFI_EAP_TYPE_NOTIFICATION: EFI_EAP_TYPE = 2;
+pub const EFI_EAP_TYPE_NAK: EFI_EAP_TYPE = 3;
+pub const EFI_EAP_TYPE_MD5CHALLENGE: EFI_EAP_TYPE = 4;
+pub const EFI_EAP_TYPE_OTP: EFI_EAP_TYPE = 5;
+pub const EFI_EAP_TYPE_GTC: EFI_EAP_TYPE = 6;
+pub const EFI_EAP_TYPE_EAPTLS: EFI_EAP_TYPE = 13;
+pub const EFI_EAP_TYPE_EAPSIM: EFI_EAP_TYPE = 18;
+pub const EFI_EAP_TYPE_TTLS: EFI_EAP_TYPE = 21;
+pub const EFI_EAP_TYPE_PEAP: EFI_EAP_TYPE = 25;
+pub const EFI_EAP_TYPE_MSCHAPV2: EFI_EAP_TYPE = 26;
+pub const EFI_EAP_TYPE_EAP_EXTENSION: EFI_EAP_TYPE = 33;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_EAP_CONFIG_DATA_TYPE {
+    // EFI_EAP_TYPE_ATTRIBUTE
+    EfiEapConfigEapAuthMethod,
+    EfiEapConfigEapSupportedAuthMethod,
+    // EFI_EAP_TYPE_IDENTITY
+    EfiEapConfigIdentityString,
+    // EFI_EAP_TYPE_EAPTLS
+    EfiEapConfigEapTlsCACert,
+    EfiEapConfigEapTlsClientCert,
+    EfiEapConfigEapTlsClientPrivateKeyFile,
+    EfiEapConfigEapTlsClientPrivateKeyFilePassword,
+    EfiEapConfigEapTlsCipherSuite,
+    EfiEapConfigEapTlsSupportedCipherSuite,
+    // EFI_EAP_TYPE_MSCHAPV2
+    EfiEapConfigEapMSChapV2Password,
+    // EFI_EAP_TYPE_PEAP and EFI_EAP_TYPE_TTLS
+    EfiEapConfigEap2ndAuthMethod,
+}
+
+#[repr(C)]
+pub struct EFI_EAP_CONFIGURATION_PROTOCOL {
+    pub SetData: EFI_EAP_CONFIGURATION_SET_DATA,
+    pub GetData: EFI_EAP_CONFIGURATION_GET_DATA,
+}
+
+debug_as_table!(EFI_EAP_CONFIGURATION_PROTOCOL);
+
+pub type EFI_EAP_CONFIGURATION_SET_DATA = extern "efiapi" fn(
+    This: *const EFI_EAP_CONFIGURATION_PROTOCOL,
+    EapType: EFI_EAP_TYPE,
+    DataType: EFI_EAP_CONFIG_DATA_TYPE,
+    Data: *const VOID,
+    DataSize: UINTN
+) -> EFI_STATUS;
+
+pub type EFI_EAP_CONFIGURATION_GET_DATA = extern "efiapi" fn(
+    This: *const EFI_EAP_CONFIGURATION_PROTOCOL,
+    EapType: EFI_EAP_TYPE,
+    DataType: EFI_EAP_CONFIG_DATA_TYPE,
+    Data: *mut VOID,
+    DataSize: *mut UINTN
+) -> EFI_STATUS;
@@ -0,0 +1,26 @@
+use ffi::base::{
+    EFI_GUID,
+    UINT8,
+    UINT32,
+};
+
+pub const EFI_EDID_DISCOVERED_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x1c0c34f6, 0xd380, 0x41fa, [0xa0, 0x49, 0x8a, 0xd0, 0x6c, 0x1a, 0x66, 0xaa]);
+pub const EFI_EDID_ACTIVE_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xbd8c1056, 0x9f36, 0x44ec, [0x92, 0xa8, 0xa6, 0x33, 0x7f, 0x81, 0x79, 0x86]);
+
+/// What the display reported
+#[repr(C)]
+pub struct EFI_EDID_DISCOVERED_PROTOCOL {
+    pub SizeOfEdid: UINT32,
+    pub Edid: *const UINT8,
+}
+
+debug_as_table!(EFI_EDID_DISCOVERED_PROTOCOL);
+
+/// What the firmware went with, after any platform override
+#[repr(C)]
+pub struct EFI_EDID_ACTIVE_PROTOCOL {
+    pub SizeOfEdid: UINT32,
+    pub Edid: *const UINT8,
+}
+
+debug_as_table!(EFI_EDID_ACTIVE_PROTOCOL);
@@ -0,0 +1,146 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    CHAR8,
+    CHAR16,
+    UINT8,
+    UINT16,
+    UINT32,
+    UINTN,
+    VOID,
+    NOT_DEFINED,
+};
+
+pub const EFI_HII_DATABASE_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xef9fc172, 0xa1b2, 0x4693, [0xb3, 0x27, 0x6d, 0x32, 0xfc, 0x41, 0x60, 0x42]);
+
+#[repr(C)]
+pub struct EFI_HII_DATABASE_PROTOCOL {
+    pub NewPackageList: EFI_HII_DATABASE_NEW_PACK,
+    pub RemovePackageList: EFI_HII_DATABASE_REMOVE_PACK,
+    pub UpdatePackageList: EFI_HII_DATABASE_UPDATE_PACK,
+    pub ListPackageLists: EFI_HII_DATABASE_LIST_PACKS,
+    pub ExportPackageLists: EFI_HII_DATABASE_EXPORT_PACKS,
+    pub RegisterPackageNotify: EFI_HII_DATABASE_REGISTER_NOTIFY,
+    pub UnregisterPackageNotify: EFI_HII_DATABASE_UNREGISTER_NOTIFY,
+    pub FindKeyboardLayouts: EFI_HII_FIND_KEYBOARD_LAYOUTS,
+    pub GetKeyboardLayout: EFI_HII_GET_KEYBOARD_LAYOUT,
+    pub SetKeyboardLayout: EFI_HII_SET_KEYBOARD_LAYOUT,
+    pub GetPackageListHandle: EFI_HII_DATABASE_GET_PACK_HANDLE,
+}
+
+debug_as_table!(EFI_HII_DATABASE_PROTOCOL);
+
+pub type EFI_HII_DATABASE_NEW_PACK = *const NOT_DEFINED;
+
+pub type EFI_HII_DATABASE_REMOVE_PACK = *const NOT_DEFINED;
+
+pub type EFI_HII_DATABASE_UPDATE_PACK = *const NOT_DEFINED;
+
+pub type EFI_HII_DATABASE_LIST_PACKS = *const NOT_DEFINED;
+
+pub type EFI_HII_DATABASE_EXPORT_PACKS = *const NOT_DEFINED;
+
+pub type EFI_HII_DATABASE_REGISTER_NOTIFY = *const NOT_DEFINED;
+
+pub type EFI_HII_DATABASE_UNREGISTER_NOTIFY = *const NOT_DEFINED;
+
+/// KeyGuidBufferLength is in bytes
+pub type EFI_HII_FIND_KEYBOARD_LAYOUTS = extern "efiapi" fn(
+    This: *const EFI_HII_DATABASE_PROTOCOL,
+    KeyGuidBufferLength: *mut UINT16,
+    KeyGuidBuffer: *mut EFI_GUID
+) -> EFI_STATUS;
+
+/// A null KeyGuid gets the current layout
+pub type EFI_HII_GET_KEYBOARD_LAYOUT = extern "efiapi" fn(
+    This: *const EFI_HII_DATABASE_PROTOCOL,
+    KeyGuid: *const EFI_GUID,
+    KeyboardLayoutLength: *mut UINT16,
+    KeyboardLayout: *mut EFI_HII_KEYBOARD_LAYOUT
+) -> EFI_STATUS;
+
+pub type EFI_HII_SET_KEYBOARD_LAYOUT = extern "efiapi" fn(
+    This: *const EFI_HII_DATABASE_PROTOCOL,
+    KeyGuid: *const EFI_GUID
+) -> EFI_STATUS;
+
+pub type EFI_HII_DATABASE_GET_PACK_HANDLE = *const NOT_DEFINED;
+
+/// Packed, so it's followed directly by DescriptorCount EFI_KEY_DESCRIPTORs. The EFI_DESCRIPTION_STRING_BUNDLE is at
+/// LayoutDescriptorStringOffset from the start of the layout
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct EFI_HII_KEYBOARD_LAYOUT {
+    pub LayoutLength: UINT16,
+    pub Guid: EFI_GUID,
+    pub LayoutDescriptorStringOffset: UINT32,
+    pub DescriptorCount: UINT8,
+}
+
+/// Physical key position, per ISO/IEC 9995-1 (e.g. EfiKeyC1 is the key where US keyboards have A)
+pub type EFI_KEY = UINT32;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_KEY_DESCRIPTOR {
+    pub Key: EFI_KEY,
+    pub Unicode: CHAR16,
+    pub ShiftedUnicode: CHAR16,
+    pub AltGrUnicode: CHAR16,
+    pub ShiftedAltGrUnicode: CHAR16,
+    pub Modifier: UINT16,
+    pub AffectedAttribute: UINT16,
+}
+
+// EFI_KEY_DESCRIPTOR Modifiers, those that matter to us. The rest mark keys like Ctrl, Home or F1
+pub const EFI_NULL_MODIFIER: UINT16 = 0x0000;
+pub const EFI_ALT_GR_MODIFIER: UINT16 = 0x0005;
+pub const EFI_LEFT_SHIFT_MODIFIER: UINT16 = 0x000C;
+pub const EFI_RIGHT_SHIFT_MODIFIER: UINT16 = 0x000D;
+pub const EFI_CAPS_LOCK_MODIFIER: UINT16 = 0x000E;
+pub const EFI_NS_KEY_MODIFIER: UINT16 = 0x0014;
+pub const EFI_NS_KEY_DEPENDENCY_MODIFIER: UINT16 = 0x0015;
+
+// EFI_KEY_DESCRIPTOR AffectedAttributes
+pub const EFI_AFFECTED_BY_STANDARD_SHIFT: UINT16 = 0x0001;
+pub const EFI_AFFECTED_BY_CAPS_LOCK: UINT16 = 0x0002;
+pub const EFI_AFFECTED_BY_NUM_LOCK: UINT16 = 0x0004;
+
+/// A package list in the HII database
+pub type EFI_HII_HANDLE = *const VOID;
+
+/// A string in a package list's string packages
+pub type EFI_STRING_ID = UINT16;
+
+pub const EFI_HII_STRING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x0fd96974, 0x23aa, 0x4cdc, [0xb9, 0xcb, 0x98, 0xd1, 0x77, 0x50, 0x32, 0x2a]);
+
+#[repr(C)]
+pub struct EFI_HII_STRING_PROTOCOL {
+    pub NewString: *const NOT_DEFINED,
+    pub GetString: EFI_HII_GET_STRING,
+    pub SetString: *const NOT_DEFINED,
+    pub GetLanguages: EFI_HII_GET_LANGUAGES,
+    pub GetSecondaryLanguages: *const NOT_DEFINED,
+}
+
+debug_as_table!(EFI_HII_STRING_PROTOCOL);
+
+/// StringSize is in bytes and includes the terminator. Fails with EFI_INVALID_LANGUAGE if the package list hasn't
+/// got the language
+pub type EFI_HII_GET_STRING = extern "efiapi" fn(
+    This: *const EFI_HII_STRING_PROTOCOL,
+    Language: *const CHAR8,
+    PackageList: EFI_HII_HANDLE,
+    StringId: EFI_STRING_ID,
+    String: *mut CHAR16,
+    StringSize: *mut UINTN,
+    StringFontInfo: *mut *const VOID
+) -> EFI_STATUS;
+
+/// The languages are separated by semicolons and null terminated. LanguagesSize is in bytes
+pub type EFI_HII_GET_LANGUAGES = extern "efiapi" fn(
+    This: *const EFI_HII_STRING_PROTOCOL,
+    PackageList: EFI_HII_HANDLE,
+    Languages: *mut CHAR8,
+    LanguagesSize: *mut UINTN
+) -> EFI_STATUS;
@@ -0,0 +1,5 @@
+use ffi::base::EFI_GUID;
+
+pub const EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xbdc8e6af, 0xd9bc, 0x4379, [0xa7, 0x2a, 0xe0, 0xc4, 0xe7, 0x5d, 0xae, 0x1c]);
+
+pub const EFI_HTTP_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x7a59b29b, 0x910b, 0x4171, [0x82, 0x42, 0xa8, 0x5a, 0x0d, 0xf2, 0x5b, 0x5b]);
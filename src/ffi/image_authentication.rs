@@ -0,0 +1,45 @@
+use ffi::base::{EFI_GUID, UINT16, UINT32};
+
+// The variables holding the Secure Boot databases, all under EFI_IMAGE_SECURITY_DATABASE_GUID
+pub const EFI_IMAGE_SECURITY_DATABASE_GUID: EFI_GUID = EFI_GUID(0xd719b2cb, 0x3d3a, 0x4596, [0xa3, 0xbc, 0xda, 0xd0, 0x0e, 0x67, 0x65, 0x6f]);
+pub const EFI_IMAGE_SECURITY_DATABASE: &str = "db";
+pub const EFI_IMAGE_SECURITY_DATABASE1: &str = "dbx";
+pub const EFI_IMAGE_SECURITY_DATABASE2: &str = "dbt";
+
+// Signature types in an EFI_SIGNATURE_LIST
+pub const EFI_CERT_SHA256_GUID: EFI_GUID = EFI_GUID(0xc1c41626, 0x504c, 0x4092, [0xac, 0xa9, 0x41, 0xf9, 0x36, 0x93, 0x43, 0x28]);
+pub const EFI_CERT_RSA2048_GUID: EFI_GUID = EFI_GUID(0x3c5766e8, 0x269c, 0x4e34, [0xaa, 0x14, 0xed, 0x77, 0x6e, 0x85, 0xb3, 0xb6]);
+pub const EFI_CERT_X509_GUID: EFI_GUID = EFI_GUID(0xa5c059a1, 0x94e4, 0x4aa7, [0x87, 0xb5, 0xab, 0x15, 0x5c, 0x2b, 0xf0, 0x72]);
+pub const EFI_CERT_X509_SHA256_GUID: EFI_GUID = EFI_GUID(0x3bd2a492, 0x96c0, 0x4079, [0xb4, 0x20, 0xfc, 0xf9, 0x8e, 0xf1, 0x03, 0xed]);
+pub const EFI_CERT_TYPE_PKCS7_GUID: EFI_GUID = EFI_GUID(0x4aafd29d, 0x68df, 0x49ee, [0x8a, 0xa9, 0x34, 0x7d, 0x37, 0x56, 0x65, 0xa7]);
+
+// Followed by SignatureHeaderSize bytes of header, then SignatureSize byte EFI_SIGNATURE_DATAs up to SignatureListSize
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_SIGNATURE_LIST {
+    pub SignatureType: EFI_GUID,
+    pub SignatureListSize: UINT32,
+    pub SignatureHeaderSize: UINT32,
+    pub SignatureSize: UINT32,
+}
+
+// Followed by the signature itself
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_SIGNATURE_DATA {
+    pub SignatureOwner: EFI_GUID,
+}
+
+// A PE image's certificate table is a list of these, each starting on an 8 byte boundary
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct WIN_CERTIFICATE {
+    pub dwLength: UINT32,
+    pub wRevision: UINT16,
+    pub wCertificateType: UINT16,
+}
+
+pub const WIN_CERT_REVISION_2_0: UINT16 = 0x0200;
+pub const WIN_CERT_TYPE_PKCS_SIGNED_DATA: UINT16 = 0x0002;
+pub const WIN_CERT_TYPE_EFI_PKCS115: UINT16 = 0x0EF0;
+pub const WIN_CERT_TYPE_EFI_GUID: UINT16 = 0x0EF1;
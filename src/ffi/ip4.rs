@@ -20,7 +20,7 @@ use ffi::{
     },
 };
 
-use core::ptr;
+use core::{fmt, ptr};
 
 pub const EFI_IP4_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xc51711e7, 0xb4bf, 0x404a, [0xbf, 0xb8, 0x0a, 0x04, 0x8e, 0xf1, 0xff, 0xe4]);
 pub const EFI_IP4_PROTOCOL_GUID : EFI_GUID = EFI_GUID(0x41d94cd2, 0x35b6, 0x455a, [0x82, 0x58, 0xd4, 0xe5, 0x13, 0x34, 0xaa, 0xdd]);
@@ -37,25 +37,27 @@ pub struct EFI_IP4_PROTOCOL {
     pub Poll: EFI_IP4_POLL,
 }
 
-pub type EFI_IP4_GET_MODE_DATA = extern "win64" fn(
+debug_as_table!(EFI_IP4_PROTOCOL);
+
+pub type EFI_IP4_GET_MODE_DATA = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     Ip4ModeData: *mut EFI_IP4_MODE_DATA,
     MnpConfigData: *mut EFI_MANAGED_NETWORK_CONFIG_DATA,
     SnpModeData: *mut EFI_SIMPLE_NETWORK_MODE
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_CONFIGURE = extern "win64" fn(
+pub type EFI_IP4_CONFIGURE = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     IpConfigData: *const EFI_IP4_CONFIG_DATA
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_GROUPS = extern "win64" fn(
+pub type EFI_IP4_GROUPS = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     JoinFlag: BOOLEAN,
     GroupAddress: *const EFI_IPv4_ADDRESS
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_ROUTES = extern "win64" fn(
+pub type EFI_IP4_ROUTES = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     DeleteRoute: BOOLEAN,
     SubnetAddress: *const EFI_IPv4_ADDRESS,
@@ -63,22 +65,22 @@ pub type EFI_IP4_ROUTES = extern "win64" fn(
     GatewayAddres: *const EFI_IPv4_ADDRESS
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_TRANSMIT = extern "win64" fn(
+pub type EFI_IP4_TRANSMIT = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     Token: *const EFI_IP4_COMPLETION_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_RECEIVE = extern "win64" fn(
+pub type EFI_IP4_RECEIVE = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     Token: *const EFI_IP4_COMPLETION_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_CANCEL = extern "win64" fn(
+pub type EFI_IP4_CANCEL = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL,
     Token: *const EFI_IP4_COMPLETION_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_POLL = extern "win64" fn(
+pub type EFI_IP4_POLL = extern "efiapi" fn(
     This: *const EFI_IP4_PROTOCOL
 ) -> EFI_STATUS;
 
@@ -120,6 +122,7 @@ impl Default for EFI_IP4_MODE_DATA {
     }
 }
 
+#[derive(Debug)]
 #[repr(C)]
 pub struct EFI_IP4_COMPLETION_TOKEN {
     pub Event: EFI_EVENT,
@@ -143,6 +146,14 @@ pub union PacketUnion {
     pub TxData: *const EFI_IP4_TRANSMIT_DATA,
 }
 
+// Had to implement by hand 'cause Debug derive not allowed for unions. Both are pointers and which it is depends on
+// the token, so it's just the address
+impl fmt::Debug for PacketUnion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", unsafe { self.RxData })
+    }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct EFI_IP4_RECEIVE_DATA {
@@ -160,6 +171,7 @@ pub struct EFI_IP4_RECEIVE_DATA {
  // See these issues: https://github.com/rust-lang/rust/issues/46043, https://github.com/rust-lang/rust/issues/27060
  // When creating this struct, better to perhaps write directly to a raw buffer and then transmute into it
  // instead of assigning to individual fields. This is a protocol header anyway. So it should be kinda idiomatic to do raw writes like this.
+#[derive(Debug, Copy, Clone)]
 #[repr(packed)]
 pub struct EFI_IP4_HEADER {
     pub HeaderLengthAndVersion: UINT8, // This is actually two bit fields called "HeaderLength" and "Version" with 4 bits each. Rust doesn't support bitfields (yet)
@@ -285,17 +297,19 @@ pub struct EFI_IP4_CONFIG_PROTOCOL {
     pub GetData: EFI_IP4_CONFIG_GET_DATA,
 }
 
-pub type EFI_IP4_CONFIG_START = extern "win64" fn(
+debug_as_table!(EFI_IP4_CONFIG_PROTOCOL);
+
+pub type EFI_IP4_CONFIG_START = extern "efiapi" fn(
     This: *const EFI_IP4_CONFIG_PROTOCOL,
     DoneEvent: EFI_EVENT,
     ReconfigEvent: EFI_EVENT
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_CONFIG_STOP = extern "win64" fn(
+pub type EFI_IP4_CONFIG_STOP = extern "efiapi" fn(
     This: *const EFI_IP4_CONFIG_PROTOCOL
 ) -> EFI_STATUS;
 
-pub type EFI_IP4_CONFIG_GET_DATA = extern "win64" fn(
+pub type EFI_IP4_CONFIG_GET_DATA = extern "efiapi" fn(
     This: *const EFI_IP4_CONFIG_PROTOCOL,
     IpConfigDataSize: *mut UINTN,
     IpConfigData: *mut EFI_IP4_IPCONFIG_DATA,
@@ -0,0 +1,28 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINTN,
+    VOID,
+};
+
+pub const EFI_ISCSI_INITIATOR_NAME_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x59324945, 0xec44, 0x4c0d, [0xb1, 0xcd, 0x9d, 0xb1, 0x39, 0xdf, 0x07, 0x0c]);
+
+#[repr(C)]
+pub struct EFI_ISCSI_INITIATOR_NAME_PROTOCOL {
+    pub Get: EFI_ISCSI_INITIATOR_NAME_GET,
+    pub Set: EFI_ISCSI_INITIATOR_NAME_SET,
+}
+
+debug_as_table!(EFI_ISCSI_INITIATOR_NAME_PROTOCOL);
+
+pub type EFI_ISCSI_INITIATOR_NAME_GET = extern "efiapi" fn(
+    This: *const EFI_ISCSI_INITIATOR_NAME_PROTOCOL,
+    BufferSize: *mut UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_ISCSI_INITIATOR_NAME_SET = extern "efiapi" fn(
+    This: *const EFI_ISCSI_INITIATOR_NAME_PROTOCOL,
+    BufferSize: *mut UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
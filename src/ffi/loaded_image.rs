@@ -11,6 +11,7 @@ use ffi::{
     device_path::EFI_DEVICE_PATH_PROTOCOL,
     EFI_SYSTEM_TABLE
 };
+use core::fmt;
 
 pub const EFI_LOADED_IMAGE_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x5B1B31A1, 0x9562, 0x11d2, [0x8E, 0x3F, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
 
@@ -34,6 +35,25 @@ pub struct EFI_LOADED_IMAGE_PROTOCOL {
     pub Unload: EFI_IMAGE_UNLOAD
 }
 
-pub type EFI_IMAGE_UNLOAD = extern "win64" fn(
+// By hand for the sake of Unload, a function pointer without Debug
+impl fmt::Debug for EFI_LOADED_IMAGE_PROTOCOL {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EFI_LOADED_IMAGE_PROTOCOL")
+            .field("Revision", &self.Revision)
+            .field("ParentHandle", &self.ParentHandle)
+            .field("SystemTable", &self.SystemTable)
+            .field("DeviceHandle", &self.DeviceHandle)
+            .field("FilePath", &self.FilePath)
+            .field("LoadOptionsSize", &self.LoadOptionsSize)
+            .field("LoadOptions", &self.LoadOptions)
+            .field("ImageBase", &self.ImageBase)
+            .field("ImageSize", &self.ImageSize)
+            .field("ImageCodeType", &self.ImageCodeType)
+            .field("ImageDataType", &self.ImageDataType)
+            .finish()
+    }
+}
+
+pub type EFI_IMAGE_UNLOAD = extern "efiapi" fn(
     Handle: EFI_HANDLE
 ) -> EFI_STATUS;
\ No newline at end of file
@@ -21,7 +21,9 @@ pub struct EFI_LOAD_FILE_PROTOCOL {
     pub LoadFile: EFI_LOAD_FILE
 }
 
-pub type EFI_LOAD_FILE = extern "win64" fn(
+debug_as_table!(EFI_LOAD_FILE_PROTOCOL);
+
+pub type EFI_LOAD_FILE = extern "efiapi" fn(
     This: *const EFI_LOAD_FILE_PROTOCOL, 
     FilePath: *const EFI_DEVICE_PATH_PROTOCOL,
     BootPolicy: BOOLEAN,
@@ -29,6 +31,11 @@ pub type EFI_LOAD_FILE = extern "win64" fn(
     BufferPtr: *mut VOID
 ) -> EFI_STATUS;
 
+pub const EFI_LOAD_FILE2_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x4006C0C1, 0xFCB3, 0x403E, [0x99, 0x6D, 0x4A, 0x6C, 0x87, 0x24, 0xE0, 0x6D]);
+
+// LoadFile2 has exactly the same layout as LoadFile. The only difference is that BootPolicy must always be FALSE
+pub type EFI_LOAD_FILE2_PROTOCOL = EFI_LOAD_FILE_PROTOCOL;
+
 pub const EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID: EFI_GUID  = EFI_GUID(0x0964E5B22, 0x6459, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
 
 pub const EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_REVISION: UINT64 = 0x00010000;
@@ -40,7 +47,9 @@ pub struct EFI_SIMPLE_FILE_SYSTEM_PROTOCOL {
     pub OpenVolume: EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_OPEN_VOLUME,
 }
 
-pub type EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_OPEN_VOLUME = extern "win64" fn(
+debug_as_table!(EFI_SIMPLE_FILE_SYSTEM_PROTOCOL);
+
+pub type EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_OPEN_VOLUME = extern "efiapi" fn(
     This: *const EFI_SIMPLE_FILE_SYSTEM_PROTOCOL,
     Root: *mut *const EFI_FILE_PROTOCOL
 ) -> EFI_STATUS;
@@ -69,7 +78,9 @@ pub struct EFI_FILE_PROTOCOL {
     pub FlushEx: EFI_FILE_FLUSH_EX,
 }
 
-pub type EFI_FILE_OPEN = extern "win64" fn(
+debug_as_table!(EFI_FILE_PROTOCOL);
+
+pub type EFI_FILE_OPEN = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     NewHandle: *mut *const EFI_FILE_PROTOCOL,
     FileName: *const CHAR16,
@@ -77,55 +88,55 @@ pub type EFI_FILE_OPEN = extern "win64" fn(
     Attribute: UINT64
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_CLOSE = extern "win64" fn(
+pub type EFI_FILE_CLOSE = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_DELETE = extern "win64" fn(
+pub type EFI_FILE_DELETE = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_READ = extern "win64" fn(
+pub type EFI_FILE_READ = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     BufferSize: *mut UINTN,
     Buffer: *mut VOID
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_WRITE = extern "win64" fn(
+pub type EFI_FILE_WRITE = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     BufferSize: *mut UINTN,
     Buffer: *const VOID
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_GET_POSITION = extern "win64" fn(
+pub type EFI_FILE_GET_POSITION = extern "efiapi" fn(
     This: *const EFI_FILE_PROTOCOL,
     Position: *mut UINT64
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_SET_POSITION = extern "win64" fn(
+pub type EFI_FILE_SET_POSITION = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     Position: UINT64
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_GET_INFO = extern "win64" fn(
+pub type EFI_FILE_GET_INFO = extern "efiapi" fn(
     This: *const EFI_FILE_PROTOCOL,
     InformationType: *const EFI_GUID,
     BufferSize: *mut UINTN,
     Buffer: *mut VOID
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_SET_INFO = extern "win64" fn(
+pub type EFI_FILE_SET_INFO = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     InformationType: *const EFI_GUID,
     BufferSize: UINTN,
     Buffer: *const VOID
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_FLUSH = extern "win64" fn(
+pub type EFI_FILE_FLUSH = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_OPEN_EX =  extern "win64" fn(
+pub type EFI_FILE_OPEN_EX =  extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     NewHandle: *mut *const EFI_FILE_PROTOCOL,
     FileName: *const CHAR16,
@@ -134,17 +145,17 @@ pub type EFI_FILE_OPEN_EX =  extern "win64" fn(
     Token: *mut EFI_FILE_IO_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_READ_EX = extern "win64" fn(
+pub type EFI_FILE_READ_EX = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     Token: *mut EFI_FILE_IO_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_WRITE_EX = extern "win64" fn(
+pub type EFI_FILE_WRITE_EX = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     Token: *mut EFI_FILE_IO_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_FILE_FLUSH_EX = extern "win64" fn(
+pub type EFI_FILE_FLUSH_EX = extern "efiapi" fn(
     This: *mut EFI_FILE_PROTOCOL,
     Token: *mut EFI_FILE_IO_TOKEN
 ) -> EFI_STATUS;
@@ -223,4 +234,96 @@ pub struct EFI_FILE_SYSTEM_INFO {
     pub FreeSpace: UINT64,
     pub BlockSize: UINT32,
     pub VolumeLabel: [CHAR16; 1], // Dynamically sized, null-terminated embedded string
-}
\ No newline at end of file
+}
+
+pub const EFI_BLOCK_IO_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x964E5B21, 0x6459, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+
+pub const EFI_BLOCK_IO_PROTOCOL_REVISION2: UINT64 = 0x00020001;
+pub const EFI_BLOCK_IO_PROTOCOL_REVISION3: UINT64 = 0x0002001F;
+
+#[repr(C)]
+pub struct EFI_BLOCK_IO_PROTOCOL {
+    pub Revision: UINT64,
+    pub Media: *const EFI_BLOCK_IO_MEDIA,
+    pub Reset: EFI_BLOCK_RESET,
+    pub ReadBlocks: EFI_BLOCK_READ,
+    pub WriteBlocks: EFI_BLOCK_WRITE,
+    pub FlushBlocks: EFI_BLOCK_FLUSH,
+}
+
+debug_as_table!(EFI_BLOCK_IO_PROTOCOL);
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_BLOCK_IO_MEDIA {
+    pub MediaId: UINT32,
+    pub RemovableMedia: BOOLEAN,
+    pub MediaPresent: BOOLEAN,
+    pub LogicalPartition: BOOLEAN,
+    pub ReadOnly: BOOLEAN,
+    pub WriteCaching: BOOLEAN,
+    pub BlockSize: UINT32,
+    pub IoAlign: UINT32,
+    pub LastBlock: EFI_LBA,
+    // Revision 2 and later
+    pub LowestAlignedLba: EFI_LBA,
+    pub LogicalBlocksPerPhysicalBlock: UINT32,
+    // Revision 3 and later
+    pub OptimalTransferLengthGranularity: UINT32,
+}
+
+pub type EFI_LBA = UINT64;
+
+pub type EFI_BLOCK_RESET = extern "efiapi" fn(
+    This: *const EFI_BLOCK_IO_PROTOCOL,
+    ExtendedVerification: BOOLEAN
+) -> EFI_STATUS;
+
+pub type EFI_BLOCK_READ = extern "efiapi" fn(
+    This: *const EFI_BLOCK_IO_PROTOCOL,
+    MediaId: UINT32,
+    LBA: EFI_LBA,
+    BufferSize: UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_BLOCK_WRITE = extern "efiapi" fn(
+    This: *const EFI_BLOCK_IO_PROTOCOL,
+    MediaId: UINT32,
+    LBA: EFI_LBA,
+    BufferSize: UINTN,
+    Buffer: *const VOID
+) -> EFI_STATUS;
+
+pub type EFI_BLOCK_FLUSH = extern "efiapi" fn(
+    This: *const EFI_BLOCK_IO_PROTOCOL
+) -> EFI_STATUS;
+
+pub const EFI_DISK_IO_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xCE345171, 0xBA0B, 0x11D2, [0x8E, 0x4F, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+
+pub const EFI_DISK_IO_PROTOCOL_REVISION: UINT64 = 0x00010000;
+
+#[repr(C)]
+pub struct EFI_DISK_IO_PROTOCOL {
+    pub Revision: UINT64,
+    pub ReadDisk: EFI_DISK_READ,
+    pub WriteDisk: EFI_DISK_WRITE,
+}
+
+debug_as_table!(EFI_DISK_IO_PROTOCOL);
+
+pub type EFI_DISK_READ = extern "efiapi" fn(
+    This: *const EFI_DISK_IO_PROTOCOL,
+    MediaId: UINT32,
+    Offset: UINT64,
+    BufferSize: UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_DISK_WRITE = extern "efiapi" fn(
+    This: *const EFI_DISK_IO_PROTOCOL,
+    MediaId: UINT32,
+    Offset: UINT64,
+    BufferSize: UINTN,
+    Buffer: *const VOID
+) -> EFI_STATUS;
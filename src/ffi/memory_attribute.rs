@@ -0,0 +1,104 @@
+// Page protections on memory we've allocated (UEFI 2.10), and the PI DXE services that did the job before it
+
+use ffi::base::{
+    EFI_GUID,
+    EFI_HANDLE,
+    EFI_PHYSICAL_ADDRESS,
+    EFI_STATUS,
+    EFI_TABLE_HEADER,
+    UINT64,
+    VOID,
+};
+
+pub const EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xf4560cf6, 0x40ec, 0x4b4a, [0xa1, 0x92, 0xbf, 0x1d, 0x57, 0xd0, 0xb1, 0x89]);
+
+#[repr(C)]
+pub struct EFI_MEMORY_ATTRIBUTE_PROTOCOL {
+    pub GetMemoryAttributes: EFI_GET_MEMORY_ATTRIBUTES,
+    pub SetMemoryAttributes: EFI_SET_MEMORY_ATTRIBUTES,
+    pub ClearMemoryAttributes: EFI_CLEAR_MEMORY_ATTRIBUTES,
+}
+
+debug_as_table!(EFI_MEMORY_ATTRIBUTE_PROTOCOL);
+
+/// Only EFI_MEMORY_RP, EFI_MEMORY_RO and EFI_MEMORY_XP are reported. EFI_NO_MAPPING if they aren't the same across
+/// the whole range
+pub type EFI_GET_MEMORY_ATTRIBUTES = extern "efiapi" fn(
+    This: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL,
+    BaseAddress: EFI_PHYSICAL_ADDRESS,
+    Length: UINT64,
+    Attributes: *mut UINT64
+) -> EFI_STATUS;
+
+/// Adds to what's set already. EFI_INVALID_PARAMETER for anything but RP, RO and XP, or none of them
+pub type EFI_SET_MEMORY_ATTRIBUTES = extern "efiapi" fn(
+    This: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL,
+    BaseAddress: EFI_PHYSICAL_ADDRESS,
+    Length: UINT64,
+    Attributes: UINT64
+) -> EFI_STATUS;
+
+pub type EFI_CLEAR_MEMORY_ATTRIBUTES = extern "efiapi" fn(
+    This: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL,
+    BaseAddress: EFI_PHYSICAL_ADDRESS,
+    Length: UINT64,
+    Attributes: UINT64
+) -> EFI_STATUS;
+
+/// The configuration table holding the DXE services
+pub const DXE_SERVICES_TABLE_GUID: EFI_GUID = EFI_GUID(0x05ad34ba, 0x6f02, 0x4214, [0x95, 0x2e, 0x4d, 0xa0, 0x39, 0x8e, 0x2b, 0xb9]);
+
+pub const DXE_SERVICES_SIGNATURE: UINT64 = 0x565245535f455844;
+
+/// Only as far as the memory space services. The I/O space and dispatcher services follow but we've no use for them
+#[repr(C)]
+pub struct EFI_DXE_SERVICES {
+    pub Hdr: EFI_TABLE_HEADER,
+    pub AddMemorySpace: *const VOID,
+    pub AllocateMemorySpace: *const VOID,
+    pub FreeMemorySpace: *const VOID,
+    pub RemoveMemorySpace: *const VOID,
+    pub GetMemorySpaceDescriptor: EFI_GET_MEMORY_SPACE_DESCRIPTOR,
+    pub SetMemorySpaceAttributes: EFI_SET_MEMORY_SPACE_ATTRIBUTES,
+    pub GetMemorySpaceMap: *const VOID,
+}
+
+debug_as_table!(EFI_DXE_SERVICES);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_GCD_MEMORY_TYPE {
+    EfiGcdMemoryTypeNonExistent,
+    EfiGcdMemoryTypeReserved,
+    EfiGcdMemoryTypeSystemMemory,
+    EfiGcdMemoryTypeMemoryMappedIo,
+    EfiGcdMemoryTypePersistent,
+    EfiGcdMemoryTypeMoreReliable,
+    EfiGcdMemoryTypeUnaccepted,
+    EfiGcdMemoryTypeMaximum,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_GCD_MEMORY_SPACE_DESCRIPTOR {
+    pub BaseAddress: EFI_PHYSICAL_ADDRESS,
+    pub Length: UINT64,
+    pub Capabilities: UINT64,
+    pub Attributes: UINT64,
+    pub GcdMemoryType: EFI_GCD_MEMORY_TYPE,
+    pub ImageHandle: EFI_HANDLE,
+    pub DeviceHandle: EFI_HANDLE,
+}
+
+/// The descriptor of the region `BaseAddress` is in
+pub type EFI_GET_MEMORY_SPACE_DESCRIPTOR = extern "efiapi" fn(
+    BaseAddress: EFI_PHYSICAL_ADDRESS,
+    Descriptor: *mut EFI_GCD_MEMORY_SPACE_DESCRIPTOR
+) -> EFI_STATUS;
+
+/// Replaces all the attributes, cacheability included, not just the protection ones
+pub type EFI_SET_MEMORY_SPACE_ATTRIBUTES = extern "efiapi" fn(
+    BaseAddress: EFI_PHYSICAL_ADDRESS,
+    Length: UINT64,
+    Attributes: UINT64
+) -> EFI_STATUS;
@@ -13,16 +13,51 @@ pub mod managed_network;
 pub mod ip4;
 pub mod udp4;
 pub mod tcp4;
+pub mod tcp6;
 pub mod console;
 pub mod boot_services;
 pub mod runtime_services;
+pub mod ram_disk;
+pub mod decompress;
+pub mod iscsi;
+pub mod vlan;
+pub mod wifi;
+pub mod supplicant;
+pub mod bluetooth;
+pub mod eap;
+pub mod user_manager;
+pub mod hii;
+pub mod graphics;
+pub mod edid;
+pub mod status_code;
+pub mod debug_support;
+pub mod serial_io;
+pub mod pci_io;
+pub mod timestamp;
+pub mod variable_policy;
+pub mod storage_security;
+pub mod ata_pass_thru;
+pub mod nvme_pass_thru;
+pub mod shell;
+pub mod component_name;
+pub mod driver_health;
+pub mod driver_override;
+pub mod adapter_info;
+pub mod memory_attribute;
+pub mod rng;
+pub mod http;
+pub mod tls;
+pub mod tcg2;
+pub mod image_authentication;
+pub mod shim;
+pub mod pkcs7_verify;
 
 pub use self::base::*;
 use ffi::boot_services::EFI_BOOT_SERVICES;
 use ffi::runtime_services::EFI_RUNTIME_SERVICES;
 use ffi::console::{EFI_SIMPLE_TEXT_INPUT_PROTOCOL, EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL};
 
-pub const EFI_SYSTEM_TABLE_SIGNATURE: UINTN = 0x5453595320494249;
+pub const EFI_SYSTEM_TABLE_SIGNATURE: UINT64 = 0x5453595320494249;
 pub const EFI_2_31_SYSTEM_TABLE_REVISION: UINTN = (2<<16) | (31);
 pub const EFI_2_30_SYSTEM_TABLE_REVISION: UINTN = (2<<16) | (30);
 pub const EFI_2_20_SYSTEM_TABLE_REVISION: UINTN = (2<<16) | (20);
@@ -33,6 +68,7 @@ pub const EFI_1_02_SYSTEM_TABLE_REVISION: UINTN = (1<<16) | (02);
 pub const EFI_SYSTEM_TABLE_REVISION: UINTN = EFI_2_31_SYSTEM_TABLE_REVISION;
 pub const EFI_SPECIFICATION_VERSION: UINTN = EFI_SYSTEM_TABLE_REVISION;
 
+#[derive(Debug)]
 #[repr(C)]
 pub struct EFI_SYSTEM_TABLE {
     pub Hdr : EFI_TABLE_HEADER,
@@ -65,12 +101,14 @@ pub struct EFI_SERVICE_BINDING_PROTOCOL {
     pub DestroyChild: EFI_SERVICE_BINDING_DESTROY_CHILD,
 }
 
-pub type EFI_SERVICE_BINDING_CREATE_CHILD = extern "win64" fn(
+debug_as_table!(EFI_SERVICE_BINDING_PROTOCOL);
+
+pub type EFI_SERVICE_BINDING_CREATE_CHILD = extern "efiapi" fn(
     This: *const EFI_SERVICE_BINDING_PROTOCOL,
     ChildHandle: *mut EFI_HANDLE
 ) -> EFI_STATUS;
 
-pub type EFI_SERVICE_BINDING_DESTROY_CHILD = extern "win64" fn(
+pub type EFI_SERVICE_BINDING_DESTROY_CHILD = extern "efiapi" fn(
     This: *const EFI_SERVICE_BINDING_PROTOCOL,
     ChildHandle: *mut EFI_HANDLE
 ) -> EFI_STATUS;
\ No newline at end of file
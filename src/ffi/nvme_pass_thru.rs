@@ -0,0 +1,111 @@
+use ffi::{
+    base::{
+        EFI_GUID,
+        EFI_STATUS,
+        EFI_EVENT,
+        UINT8,
+        UINT32,
+        UINT64,
+        VOID,
+        NOT_DEFINED,
+    },
+    device_path::EFI_DEVICE_PATH_PROTOCOL,
+};
+
+pub const EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x52c78312, 0x8edc, 0x4233, [0x98, 0xf2, 0x1a, 0x1a, 0xa5, 0xe3, 0x88, 0xa5]);
+
+pub const EFI_NVM_EXPRESS_PASS_THRU_ATTRIBUTES_PHYSICAL: UINT32 = 0x0001;
+pub const EFI_NVM_EXPRESS_PASS_THRU_ATTRIBUTES_LOGICAL: UINT32 = 0x0002;
+pub const EFI_NVM_EXPRESS_PASS_THRU_ATTRIBUTES_NONBLOCKIO: UINT32 = 0x0004;
+pub const EFI_NVM_EXPRESS_PASS_THRU_ATTRIBUTES_CMD_SET_NVM: UINT32 = 0x0008;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_NVM_EXPRESS_PASS_THRU_MODE {
+    pub Attributes: UINT32,
+    pub IoAlign: UINT32,
+    pub NvmeVersion: UINT32,
+}
+
+#[repr(C)]
+pub struct EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL {
+    pub Mode: *const EFI_NVM_EXPRESS_PASS_THRU_MODE,
+    pub PassThru: EFI_NVM_EXPRESS_PASS_THRU_PASSTHRU,
+    pub GetNextNamespace: EFI_NVM_EXPRESS_PASS_THRU_GET_NEXT_NAMESPACE,
+    pub BuildDevicePath: EFI_NVM_EXPRESS_PASS_THRU_BUILD_DEVICE_PATH,
+    pub GetNamespace: EFI_NVM_EXPRESS_PASS_THRU_GET_NAMESPACE,
+}
+
+debug_as_table!(EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL);
+
+pub type EFI_NVM_EXPRESS_PASS_THRU_PASSTHRU = extern "efiapi" fn(
+    This: *const EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL,
+    NamespaceId: UINT32, // 0 for admin commands that aren't about a namespace
+    Packet: *mut EFI_NVM_EXPRESS_PASS_THRU_COMMAND_PACKET,
+    Event: EFI_EVENT
+) -> EFI_STATUS;
+
+// Start from 0xFFFFFFFF and get EFI_NOT_FOUND after the last one
+pub type EFI_NVM_EXPRESS_PASS_THRU_GET_NEXT_NAMESPACE = extern "efiapi" fn(
+    This: *const EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL,
+    NamespaceId: *mut UINT32
+) -> EFI_STATUS;
+
+pub type EFI_NVM_EXPRESS_PASS_THRU_BUILD_DEVICE_PATH = *const NOT_DEFINED;
+
+pub type EFI_NVM_EXPRESS_PASS_THRU_GET_NAMESPACE = extern "efiapi" fn(
+    This: *const EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL,
+    DevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
+    NamespaceId: *mut UINT32
+) -> EFI_STATUS;
+
+pub const NVME_ADMIN_QUEUE: UINT8 = 0x00;
+pub const NVME_IO_QUEUE: UINT8 = 0x01;
+
+// Which of the command dwords EFI_NVM_EXPRESS_COMMAND::Flags says to use
+pub const CDW2_VALID: UINT8 = 0x01;
+pub const CDW3_VALID: UINT8 = 0x02;
+pub const CDW10_VALID: UINT8 = 0x04;
+pub const CDW11_VALID: UINT8 = 0x08;
+pub const CDW12_VALID: UINT8 = 0x10;
+pub const CDW13_VALID: UINT8 = 0x20;
+pub const CDW14_VALID: UINT8 = 0x40;
+pub const CDW15_VALID: UINT8 = 0x80;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_NVM_EXPRESS_PASS_THRU_COMMAND_PACKET {
+    pub CommandTimeout: UINT64, // 100ns units, 0 waits forever
+    pub TransferBuffer: *mut VOID,
+    pub TransferLength: UINT32,
+    pub MetadataBuffer: *mut VOID,
+    pub MetadataLength: UINT32,
+    pub QueueType: UINT8,
+    pub NvmeCmd: *const EFI_NVM_EXPRESS_COMMAND,
+    pub NvmeCompletion: *mut EFI_NVM_EXPRESS_COMPLETION,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_NVM_EXPRESS_COMMAND {
+    pub Cdw0: UINT32, // Opcode in bits 7:0, fused operation in 9:8
+    pub Flags: UINT8,
+    pub Nsid: UINT32,
+    pub Cdw2: UINT32,
+    pub Cdw3: UINT32,
+    pub Cdw10: UINT32,
+    pub Cdw11: UINT32,
+    pub Cdw12: UINT32,
+    pub Cdw13: UINT32,
+    pub Cdw14: UINT32,
+    pub Cdw15: UINT32,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_NVM_EXPRESS_COMPLETION {
+    pub DW0: UINT32,
+    pub DW1: UINT32,
+    pub DW2: UINT32,
+    pub DW3: UINT32, // Status field in bits 31:17
+}
@@ -0,0 +1,129 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_PHYSICAL_ADDRESS,
+    EFI_STATUS,
+    UINTN,
+    UINT32,
+    UINT64,
+    VOID,
+    NOT_DEFINED,
+};
+
+pub const EFI_PCI_IO_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x4cf5b200, 0x68b8, 0x4ca5, [0x9e, 0xec, 0xb2, 0x3e, 0x3f, 0x50, 0x02, 0x9a]);
+
+#[repr(C)]
+pub struct EFI_PCI_IO_PROTOCOL {
+    pub PollMem: EFI_PCI_IO_PROTOCOL_POLL_IO_MEM,
+    pub PollIo: EFI_PCI_IO_PROTOCOL_POLL_IO_MEM,
+    pub Mem: EFI_PCI_IO_PROTOCOL_ACCESS,
+    pub Io: EFI_PCI_IO_PROTOCOL_ACCESS,
+    pub Pci: EFI_PCI_IO_PROTOCOL_CONFIG_ACCESS,
+    pub CopyMem: EFI_PCI_IO_PROTOCOL_COPY_MEM,
+    pub Map: EFI_PCI_IO_PROTOCOL_MAP,
+    pub Unmap: EFI_PCI_IO_PROTOCOL_UNMAP,
+    pub AllocateBuffer: EFI_PCI_IO_PROTOCOL_ALLOCATE_BUFFER,
+    pub FreeBuffer: EFI_PCI_IO_PROTOCOL_FREE_BUFFER,
+    pub Flush: EFI_PCI_IO_PROTOCOL_FLUSH,
+    pub GetLocation: EFI_PCI_IO_PROTOCOL_GET_LOCATION,
+    pub Attributes: EFI_PCI_IO_PROTOCOL_ATTRIBUTES,
+    pub GetBarAttributes: EFI_PCI_IO_PROTOCOL_GET_BAR_ATTRIBUTES,
+    pub SetBarAttributes: EFI_PCI_IO_PROTOCOL_SET_BAR_ATTRIBUTES,
+    pub RomSize: UINT64,
+    pub RomImage: *const VOID,
+}
+
+debug_as_table!(EFI_PCI_IO_PROTOCOL);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_PCI_IO_PROTOCOL_WIDTH {
+    EfiPciIoWidthUint8,
+    EfiPciIoWidthUint16,
+    EfiPciIoWidthUint32,
+    EfiPciIoWidthUint64,
+    EfiPciIoWidthFifoUint8,
+    EfiPciIoWidthFifoUint16,
+    EfiPciIoWidthFifoUint32,
+    EfiPciIoWidthFifoUint64,
+    EfiPciIoWidthFillUint8,
+    EfiPciIoWidthFillUint16,
+    EfiPciIoWidthFillUint32,
+    EfiPciIoWidthFillUint64,
+}
+
+pub type EFI_PCI_IO_PROTOCOL_POLL_IO_MEM = *const NOT_DEFINED;
+
+#[repr(C)]
+pub struct EFI_PCI_IO_PROTOCOL_ACCESS {
+    pub Read: *const NOT_DEFINED,
+    pub Write: *const NOT_DEFINED,
+}
+
+debug_as_table!(EFI_PCI_IO_PROTOCOL_ACCESS);
+
+#[repr(C)]
+pub struct EFI_PCI_IO_PROTOCOL_CONFIG_ACCESS {
+    pub Read: EFI_PCI_IO_PROTOCOL_CONFIG,
+    pub Write: EFI_PCI_IO_PROTOCOL_CONFIG,
+}
+
+debug_as_table!(EFI_PCI_IO_PROTOCOL_CONFIG_ACCESS);
+
+/// Reads or writes Count items of Width at Offset in the function's configuration space
+pub type EFI_PCI_IO_PROTOCOL_CONFIG = extern "efiapi" fn(
+    This: *const EFI_PCI_IO_PROTOCOL,
+    Width: EFI_PCI_IO_PROTOCOL_WIDTH,
+    Offset: UINT32,
+    Count: UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_PCI_IO_PROTOCOL_COPY_MEM = *const NOT_DEFINED;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_PCI_IO_PROTOCOL_OPERATION {
+    /// The device reads from system memory
+    EfiPciIoOperationBusMasterRead,
+    /// The device writes to system memory
+    EfiPciIoOperationBusMasterWrite,
+    /// Both at once, only for memory from AllocateBuffer
+    EfiPciIoOperationBusMasterCommonBuffer,
+    EfiPciIoOperationMaximum,
+}
+
+/// Makes host memory visible to the device. NumberOfBytes comes back as how much of it was, which can be less.
+/// The mapping may be a bounce buffer, so it's only in step with the host memory once it's been unmapped
+pub type EFI_PCI_IO_PROTOCOL_MAP = extern "efiapi" fn(
+    This: *const EFI_PCI_IO_PROTOCOL,
+    Operation: EFI_PCI_IO_PROTOCOL_OPERATION,
+    HostAddress: *mut VOID,
+    NumberOfBytes: *mut UINTN,
+    DeviceAddress: *mut EFI_PHYSICAL_ADDRESS,
+    Mapping: *mut *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_PCI_IO_PROTOCOL_UNMAP = extern "efiapi" fn(
+    This: *const EFI_PCI_IO_PROTOCOL,
+    Mapping: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_PCI_IO_PROTOCOL_ALLOCATE_BUFFER = *const NOT_DEFINED;
+
+pub type EFI_PCI_IO_PROTOCOL_FREE_BUFFER = *const NOT_DEFINED;
+
+pub type EFI_PCI_IO_PROTOCOL_FLUSH = *const NOT_DEFINED;
+
+pub type EFI_PCI_IO_PROTOCOL_GET_LOCATION = extern "efiapi" fn(
+    This: *const EFI_PCI_IO_PROTOCOL,
+    SegmentNumber: *mut UINTN,
+    BusNumber: *mut UINTN,
+    DeviceNumber: *mut UINTN,
+    FunctionNumber: *mut UINTN
+) -> EFI_STATUS;
+
+pub type EFI_PCI_IO_PROTOCOL_ATTRIBUTES = *const NOT_DEFINED;
+
+pub type EFI_PCI_IO_PROTOCOL_GET_BAR_ATTRIBUTES = *const NOT_DEFINED;
+
+pub type EFI_PCI_IO_PROTOCOL_SET_BAR_ATTRIBUTES = *const NOT_DEFINED;
@@ -39,6 +39,8 @@ pub struct EFI_PXE_BASE_CODE_PROTOCOL {
     pub Mode: *const EFI_PXE_BASE_CODE_MODE,
 }
 
+debug_as_table!(EFI_PXE_BASE_CODE_PROTOCOL);
+
 pub const EFI_PXE_BASE_CODE_MAX_ARP_ENTRIES: UINTN = 8;
 pub const EFI_PXE_BASE_CODE_MAX_ROUTE_ENTRIES: UINTN = 8;
 
@@ -287,15 +289,15 @@ pub const DEFAULT_TTL: UINT32 = 16;
 pub const DEFAULT_ToS: UINT32 = 0;
 
 
-pub type EFI_PXE_BASE_CODE_START = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_START = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL,
     UseIpv6: BOOLEAN) -> EFI_STATUS;
-pub type EFI_PXE_BASE_CODE_STOP = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_STOP = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL) -> EFI_STATUS;
-pub type EFI_PXE_BASE_CODE_DHCP = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_DHCP = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL,
     SortOffers: BOOLEAN) -> EFI_STATUS;
-pub type EFI_PXE_BASE_CODE_DISCOVER = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_DISCOVER = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL,
     Type: UINT16,
     Layer: *const UINT16,
@@ -332,7 +334,7 @@ pub const EFI_PXE_BASE_CODE_BOOT_LAYER_MASK: UINT16 = 0x7FFF;
 pub const EFI_PXE_BASE_CODE_BOOT_LAYER_INITIAL: UINT16 = 0x0000;
 
 
-pub type EFI_PXE_BASE_CODE_MTFTP = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_MTFTP = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL,
     Operation: EFI_PXE_BASE_CODE_TFTP_OPCODE, 
     BufferPtr: *const VOID,
@@ -394,7 +396,7 @@ pub struct EFI_PXE_BASE_CODE_MTFTP_INFO {
 }
 
 
-pub type EFI_PXE_BASE_CODE_UDP_WRITE = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_UDP_WRITE = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     OpFlags: UINT16, 
     DestIp: *const EFI_IP_ADDRESS,
@@ -408,7 +410,7 @@ pub type EFI_PXE_BASE_CODE_UDP_WRITE = extern "win64" fn(
     BufferPt: *const VOID 
 ) -> EFI_STATUS;
 
-pub type EFI_PXE_BASE_CODE_UDP_READ = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_UDP_READ = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     OpFlags: UINT16, 
     DestIp: *const EFI_IP_ADDRESS,
@@ -422,19 +424,19 @@ pub type EFI_PXE_BASE_CODE_UDP_READ = extern "win64" fn(
 ) -> EFI_STATUS;
 
 
-pub type EFI_PXE_BASE_CODE_SET_IP_FILTER = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_SET_IP_FILTER = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     NewFilter: *const EFI_PXE_BASE_CODE_IP_FILTER
 ) -> EFI_STATUS;
 
-pub type EFI_PXE_BASE_CODE_ARP = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_ARP = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     IpAddr: *const EFI_IP_ADDRESS, 
     MacAddr: *const EFI_MAC_ADDRESS
 ) -> EFI_STATUS;
 
 
-pub type EFI_PXE_BASE_CODE_SET_PARAMETERS = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_SET_PARAMETERS = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     NewAutoArp: *const BOOLEAN, 
     NewSendGUID: *const BOOLEAN,
@@ -444,14 +446,14 @@ pub type EFI_PXE_BASE_CODE_SET_PARAMETERS = extern "win64" fn(
 ) -> EFI_STATUS; 
 
 
-pub type EFI_PXE_BASE_CODE_SET_STATION_IP = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_SET_STATION_IP = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     NewStationIp: *const EFI_IP_ADDRESS,
     NewSubnetMask: *const EFI_IP_ADDRESS 
 ) -> EFI_STATUS;
 
 
-pub type EFI_PXE_BASE_CODE_SET_PACKETS = extern "win64" fn(
+pub type EFI_PXE_BASE_CODE_SET_PACKETS = extern "efiapi" fn(
     This: *const EFI_PXE_BASE_CODE_PROTOCOL, 
     NewDhcpDiscoverValid: *const BOOLEAN,
     NewDhcpAckReceived: *const BOOLEAN,
@@ -0,0 +1,35 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT64,
+};
+
+use super::device_path::EFI_DEVICE_PATH_PROTOCOL;
+
+pub const EFI_RAM_DISK_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xab38a0df, 0x6873, 0x44a9, [0x87, 0xe6, 0xd4, 0xeb, 0x56, 0x14, 0x84, 0x49]);
+
+// RAM disk types
+pub const EFI_VIRTUAL_DISK_GUID: EFI_GUID = EFI_GUID(0x77AB535A, 0x45FC, 0x624B, [0x55, 0x60, 0xF7, 0xB2, 0x81, 0xD1, 0xF9, 0x6E]);
+pub const EFI_VIRTUAL_CD_GUID: EFI_GUID = EFI_GUID(0x3D5ABD30, 0x4175, 0x87CE, [0x6D, 0x64, 0xD2, 0xAD, 0xE5, 0x23, 0xC4, 0xBB]);
+pub const EFI_PERSISTENT_VIRTUAL_DISK_GUID: EFI_GUID = EFI_GUID(0x5CEA02C9, 0x4D07, 0x69D3, [0x26, 0x9F, 0x44, 0x96, 0xFB, 0xE0, 0x96, 0xF9]);
+pub const EFI_PERSISTENT_VIRTUAL_CD_GUID: EFI_GUID = EFI_GUID(0x08018188, 0x42CD, 0xBB48, [0x10, 0x0F, 0x53, 0x87, 0xD5, 0x3D, 0xED, 0x3D]);
+
+#[repr(C)]
+pub struct EFI_RAM_DISK_PROTOCOL {
+    pub Register: EFI_RAM_DISK_REGISTER_RAMDISK,
+    pub Unregister: EFI_RAM_DISK_UNREGISTER_RAMDISK,
+}
+
+debug_as_table!(EFI_RAM_DISK_PROTOCOL);
+
+pub type EFI_RAM_DISK_REGISTER_RAMDISK = extern "efiapi" fn(
+    RamDiskBase: UINT64,
+    RamDiskSize: UINT64,
+    RamDiskType: *const EFI_GUID,
+    ParentDevicePath: *const EFI_DEVICE_PATH_PROTOCOL,
+    DevicePath: *mut *const EFI_DEVICE_PATH_PROTOCOL
+) -> EFI_STATUS;
+
+pub type EFI_RAM_DISK_UNREGISTER_RAMDISK = extern "efiapi" fn(
+    DevicePath: *const EFI_DEVICE_PATH_PROTOCOL
+) -> EFI_STATUS;
@@ -0,0 +1,31 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINTN,
+};
+
+pub const EFI_RNG_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x3152bca5, 0xeade, 0x433d, [0x86, 0x2e, 0xc0, 0x1c, 0xdc, 0x29, 0x1f, 0x44]);
+
+pub const EFI_RNG_ALGORITHM_RAW: EFI_GUID = EFI_GUID(0xe43176d7, 0xb6e8, 0x4827, [0xb7, 0x84, 0x7f, 0xfd, 0xc4, 0xb6, 0x85, 0x61]);
+
+#[repr(C)]
+pub struct EFI_RNG_PROTOCOL {
+    pub GetInfo: EFI_RNG_GET_INFO,
+    pub GetRNG: EFI_RNG_GET_RNG,
+}
+
+debug_as_table!(EFI_RNG_PROTOCOL);
+
+pub type EFI_RNG_GET_INFO = extern "efiapi" fn(
+    This: *const EFI_RNG_PROTOCOL,
+    RNGAlgorithmListSize: *mut UINTN,
+    RNGAlgorithmList: *mut EFI_GUID,
+) -> EFI_STATUS;
+
+pub type EFI_RNG_GET_RNG = extern "efiapi" fn(
+    This: *const EFI_RNG_PROTOCOL,
+    RNGAlgorithm: *const EFI_GUID, // Null for the default
+    RNGValueLength: UINTN,
+    RNGValue: *mut UINT8,
+) -> EFI_STATUS;
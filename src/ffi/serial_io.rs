@@ -0,0 +1,88 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINTN,
+    UINT8,
+    UINT32,
+    UINT64,
+    VOID,
+    NOT_DEFINED,
+};
+
+pub const EFI_SERIAL_IO_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xbb25cf6f, 0xf1d4, 0x11d2, [0x9a, 0x0c, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0xfd]);
+
+#[repr(C)]
+pub struct EFI_SERIAL_IO_PROTOCOL {
+    pub Revision: UINT32,
+    pub Reset: EFI_SERIAL_RESET,
+    pub SetAttributes: EFI_SERIAL_SET_ATTRIBUTES,
+    pub SetControl: EFI_SERIAL_SET_CONTROL_BITS,
+    pub GetControl: EFI_SERIAL_GET_CONTROL_BITS,
+    pub Write: EFI_SERIAL_WRITE,
+    pub Read: EFI_SERIAL_READ,
+    pub Mode: *const SERIAL_IO_MODE,
+}
+
+debug_as_table!(EFI_SERIAL_IO_PROTOCOL);
+
+pub type EFI_SERIAL_RESET = *const NOT_DEFINED;
+
+/// Zero for any of them means the device's default
+pub type EFI_SERIAL_SET_ATTRIBUTES = extern "efiapi" fn(
+    This: *const EFI_SERIAL_IO_PROTOCOL,
+    BaudRate: UINT64,
+    ReceiveFifoDepth: UINT32,
+    Timeout: UINT32, // In microseconds
+    Parity: EFI_PARITY_TYPE,
+    DataBits: UINT8,
+    StopBits: EFI_STOP_BITS_TYPE
+) -> EFI_STATUS;
+
+pub type EFI_SERIAL_SET_CONTROL_BITS = *const NOT_DEFINED;
+
+pub type EFI_SERIAL_GET_CONTROL_BITS = *const NOT_DEFINED;
+
+pub type EFI_SERIAL_WRITE = extern "efiapi" fn(
+    This: *const EFI_SERIAL_IO_PROTOCOL,
+    BufferSize: *mut UINTN,
+    Buffer: *const VOID
+) -> EFI_STATUS;
+
+/// Returns EFI_TIMEOUT with BufferSize set to what was read if the buffer didn't fill up within the timeout
+pub type EFI_SERIAL_READ = extern "efiapi" fn(
+    This: *const EFI_SERIAL_IO_PROTOCOL,
+    BufferSize: *mut UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct SERIAL_IO_MODE {
+    pub ControlMask: UINT32,
+    pub Timeout: UINT32,
+    pub BaudRate: UINT64,
+    pub ReceiveFifoDepth: UINT32,
+    pub DataBits: UINT32,
+    pub Parity: UINT32,
+    pub StopBits: UINT32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_PARITY_TYPE {
+    DefaultParity,
+    NoParity,
+    EvenParity,
+    OddParity,
+    MarkParity,
+    SpaceParity,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_STOP_BITS_TYPE {
+    DefaultStopBits,
+    OneStopBit,
+    OneFiveStopBits,
+    TwoStopBits,
+}
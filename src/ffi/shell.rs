@@ -0,0 +1,143 @@
+// The UEFI Shell's protocol (from the UEFI Shell specification, not the UEFI one). Only the file functions and the
+// current directory are defined; the rest are placeholders to keep the layout
+
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    EFI_EVENT,
+    CHAR16,
+    UINT32,
+    UINT64,
+    UINTN,
+    VOID,
+    NOT_DEFINED,
+};
+use ffi::media::EFI_FILE_INFO;
+
+pub const EFI_SHELL_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x6302d008, 0x7f9b, 0x4f30, [0x87, 0xac, 0x60, 0xc9, 0xfe, 0xf5, 0xda, 0x4e]);
+
+/// Opaque to callers, although the shell's own is an EFI_FILE_PROTOCOL
+pub type SHELL_FILE_HANDLE = *mut VOID;
+
+#[repr(C)]
+pub struct EFI_SHELL_PROTOCOL {
+    pub Execute: *const NOT_DEFINED,
+    pub GetEnv: *const NOT_DEFINED,
+    pub SetEnv: *const NOT_DEFINED,
+    pub GetAlias: *const NOT_DEFINED,
+    pub SetAlias: *const NOT_DEFINED,
+    pub GetHelpText: *const NOT_DEFINED,
+    pub GetDevicePathFromMap: *const NOT_DEFINED,
+    pub GetMapFromDevicePath: *const NOT_DEFINED,
+    pub GetDevicePathFromFilePath: *const NOT_DEFINED,
+    pub GetFilePathFromDevicePath: *const NOT_DEFINED,
+    pub SetMap: *const NOT_DEFINED,
+    pub GetCurDir: EFI_SHELL_GET_CUR_DIR,
+    pub SetCurDir: *const NOT_DEFINED,
+    pub OpenFileList: *const NOT_DEFINED,
+    pub FreeFileList: *const NOT_DEFINED,
+    pub RemoveDupInFileList: *const NOT_DEFINED,
+    pub BatchIsActive: *const NOT_DEFINED,
+    pub IsRootShell: *const NOT_DEFINED,
+    pub EnablePageBreak: *const NOT_DEFINED,
+    pub DisablePageBreak: *const NOT_DEFINED,
+    pub GetPageBreak: *const NOT_DEFINED,
+    pub GetDeviceName: *const NOT_DEFINED,
+    pub GetFileInfo: EFI_SHELL_GET_FILE_INFO,
+    pub SetFileInfo: EFI_SHELL_SET_FILE_INFO,
+    pub OpenFileByName: EFI_SHELL_OPEN_FILE_BY_NAME,
+    pub CloseFile: EFI_SHELL_CLOSE_FILE,
+    pub CreateFile: EFI_SHELL_CREATE_FILE,
+    pub ReadFile: EFI_SHELL_READ_FILE,
+    pub WriteFile: EFI_SHELL_WRITE_FILE,
+    pub DeleteFile: EFI_SHELL_DELETE_FILE,
+    pub DeleteFileByName: EFI_SHELL_DELETE_FILE_BY_NAME,
+    pub GetFilePosition: EFI_SHELL_GET_FILE_POSITION,
+    pub SetFilePosition: EFI_SHELL_SET_FILE_POSITION,
+    pub FlushFile: EFI_SHELL_FLUSH_FILE,
+    pub FindFiles: *const NOT_DEFINED,
+    pub FindFilesInDir: *const NOT_DEFINED,
+    pub GetFileSize: EFI_SHELL_GET_FILE_SIZE,
+    pub OpenRoot: *const NOT_DEFINED,
+    pub OpenRootByHandle: *const NOT_DEFINED,
+    pub ExecutionBreak: EFI_EVENT,
+    pub MajorVersion: UINT32,
+    pub MinorVersion: UINT32,
+}
+
+debug_as_table!(EFI_SHELL_PROTOCOL);
+
+/// The current directory of the mapping, or of the current mapping if it's null. Null if there isn't one
+pub type EFI_SHELL_GET_CUR_DIR = extern "efiapi" fn(
+    FileSystemMapping: *const CHAR16
+) -> *const CHAR16;
+
+/// The info is allocated from pool and the caller frees it
+pub type EFI_SHELL_GET_FILE_INFO = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE
+) -> *mut EFI_FILE_INFO;
+
+pub type EFI_SHELL_SET_FILE_INFO = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE,
+    FileInfo: *const EFI_FILE_INFO
+) -> EFI_STATUS;
+
+/// FileName is a shell path: mapped (fs0:\dir\file), absolute on the current mapping or relative to the current
+/// directory
+pub type EFI_SHELL_OPEN_FILE_BY_NAME = extern "efiapi" fn(
+    FileName: *const CHAR16,
+    FileHandle: *mut SHELL_FILE_HANDLE,
+    OpenMode: UINT64
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_CLOSE_FILE = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE
+) -> EFI_STATUS;
+
+/// Creates the file or directory, or opens it if it already exists
+pub type EFI_SHELL_CREATE_FILE = extern "efiapi" fn(
+    FileName: *const CHAR16,
+    FileAttribs: UINT64,
+    FileHandle: *mut SHELL_FILE_HANDLE
+) -> EFI_STATUS;
+
+/// Reading a directory gives an EFI_FILE_INFO per entry, as with EFI_FILE_PROTOCOL
+pub type EFI_SHELL_READ_FILE = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE,
+    ReadSize: *mut UINTN,
+    Buffer: *mut VOID
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_WRITE_FILE = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE,
+    BufferSize: *mut UINTN,
+    Buffer: *const VOID
+) -> EFI_STATUS;
+
+/// Closes the handle too
+pub type EFI_SHELL_DELETE_FILE = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_DELETE_FILE_BY_NAME = extern "efiapi" fn(
+    FileName: *const CHAR16
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_GET_FILE_POSITION = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE,
+    Position: *mut UINT64
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_SET_FILE_POSITION = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE,
+    Position: UINT64
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_FLUSH_FILE = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_SHELL_GET_FILE_SIZE = extern "efiapi" fn(
+    FileHandle: SHELL_FILE_HANDLE,
+    Size: *mut UINT64
+) -> EFI_STATUS;
@@ -0,0 +1,48 @@
+use ffi::base::{EFI_GUID, EFI_STATUS, UINT32, VOID};
+
+// Both the protocol shim installs for the loaders it starts and the vendor of its variables
+pub const SHIM_LOCK_GUID: EFI_GUID = EFI_GUID(0x605dab50, 0xe046, 0x4300, [0xab, 0xb6, 0x3d, 0xd8, 0x10, 0xdd, 0x8b, 0x23]);
+
+// Keys shim trusts and denies besides db and dbx. The RT copies are what shim leaves readable for the loader and
+// the OS; when one gets too big for a variable it carries on in MokListRT1, MokListRT2 and so on
+pub const MOK_LIST: &str = "MokList";
+pub const MOK_LIST_RT: &str = "MokListRT";
+pub const MOK_LIST_X: &str = "MokListX";
+pub const MOK_LIST_X_RT: &str = "MokListXRT";
+// A single byte, 1 if the owner has turned shim's verification off
+pub const MOK_SB_STATE_RT: &str = "MokSBStateRT";
+
+// Requests for MokManager: EFI_SIGNATURE_LISTs to enroll or delete, each with the SHA-256 of the lists followed by
+// the password in UCS-2, which the owner has to type again to confirm
+pub const MOK_NEW: &str = "MokNew";
+pub const MOK_AUTH: &str = "MokAuth";
+pub const MOK_DEL: &str = "MokDel";
+pub const MOK_DEL_AUTH: &str = "MokDelAuth";
+pub const MOK_X_NEW: &str = "MokXNew";
+pub const MOK_X_AUTH: &str = "MokXAuth";
+pub const MOK_X_DEL: &str = "MokXDel";
+pub const MOK_X_DEL_AUTH: &str = "MokXDelAuth";
+
+pub const SHIM_PASSWORD_MAX: usize = 256;
+
+#[repr(C)]
+pub struct SHIM_LOCK {
+    pub Verify: SHIM_LOCK_VERIFY,
+    pub Hash: *const VOID, // Takes shim's own PE loader context, which we've no use for
+    pub Context: *const VOID,
+}
+
+debug_as_table!(SHIM_LOCK);
+
+// Shim is built with gnu-efi, so its protocol uses the native calling convention rather than the EFI one
+#[cfg(target_arch = "x86_64")]
+pub type SHIM_LOCK_VERIFY = extern "sysv64" fn(
+    buffer: *const VOID,
+    size: UINT32
+) -> EFI_STATUS;
+
+#[cfg(not(target_arch = "x86_64"))]
+pub type SHIM_LOCK_VERIFY = extern "C" fn(
+    buffer: *const VOID,
+    size: UINT32
+) -> EFI_STATUS;
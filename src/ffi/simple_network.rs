@@ -37,6 +37,8 @@ pub struct EFI_SIMPLE_NETWORK_PROTOCOL {
     pub Mode: *mut EFI_SIMPLE_NETWORK_MODE,
 }
 
+debug_as_table!(EFI_SIMPLE_NETWORK_PROTOCOL);
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct EFI_SIMPLE_NETWORK_MODE {
@@ -101,7 +103,7 @@ pub type EFI_SIMPLE_NETWORK_RECEIVE = *const NOT_DEFINED;
 pub type EFI_SIMPLE_NETWORK_TRANSMIT = *const NOT_DEFINED;
 
 
-pub type EFI_SIMPLE_NETWORK_GET_STATUS = extern "win64" fn(
+pub type EFI_SIMPLE_NETWORK_GET_STATUS = extern "efiapi" fn(
     This: *const EFI_SIMPLE_NETWORK_PROTOCOL,
     InterruptStatus: *mut UINT32,
     TxBuf: *mut *const VOID
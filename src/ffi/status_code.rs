@@ -0,0 +1,124 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    CHAR8,
+    UINT16,
+    UINT32,
+};
+
+// From the PI spec. UEFI 2.x dropped ReportStatusCode from the runtime services table, so this is the only way to it
+pub const EFI_STATUS_CODE_RUNTIME_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xd2b2b828, 0x0826, 0x48a7, [0xb3, 0xdf, 0x98, 0x3c, 0x00, 0x60, 0x24, 0xf0]);
+
+#[repr(C)]
+pub struct EFI_STATUS_CODE_PROTOCOL {
+    pub ReportStatusCode: EFI_REPORT_STATUS_CODE,
+}
+
+debug_as_table!(EFI_STATUS_CODE_PROTOCOL);
+
+pub type EFI_REPORT_STATUS_CODE = extern "efiapi" fn(
+    Type: EFI_STATUS_CODE_TYPE,
+    Value: EFI_STATUS_CODE_VALUE,
+    Instance: UINT32,
+    CallerId: *const EFI_GUID,
+    Data: *const EFI_STATUS_CODE_DATA
+) -> EFI_STATUS;
+
+/// The low byte is the code type, the high byte the severity (for error codes)
+pub type EFI_STATUS_CODE_TYPE = UINT32;
+
+/// Class in bits 24-31, subclass in 16-23, operation in 0-15
+pub type EFI_STATUS_CODE_VALUE = UINT32;
+
+// Code types
+pub const EFI_STATUS_CODE_TYPE_MASK: EFI_STATUS_CODE_TYPE = 0x000000FF;
+pub const EFI_STATUS_CODE_SEVERITY_MASK: EFI_STATUS_CODE_TYPE = 0xFF000000;
+pub const EFI_PROGRESS_CODE: EFI_STATUS_CODE_TYPE = 0x00000001;
+pub const EFI_ERROR_CODE: EFI_STATUS_CODE_TYPE = 0x00000002;
+pub const EFI_DEBUG_CODE: EFI_STATUS_CODE_TYPE = 0x00000003;
+
+// Error code severities
+pub const EFI_ERROR_MINOR: EFI_STATUS_CODE_TYPE = 0x40000000;
+pub const EFI_ERROR_MAJOR: EFI_STATUS_CODE_TYPE = 0x80000000;
+pub const EFI_ERROR_UNRECOVERED: EFI_STATUS_CODE_TYPE = 0x90000000;
+pub const EFI_ERROR_UNCONTAINED: EFI_STATUS_CODE_TYPE = 0xa0000000;
+
+// Masks and ranges of values
+pub const EFI_STATUS_CODE_CLASS_MASK: EFI_STATUS_CODE_VALUE = 0xFF000000;
+pub const EFI_STATUS_CODE_SUBCLASS_MASK: EFI_STATUS_CODE_VALUE = 0x00FF0000;
+pub const EFI_STATUS_CODE_OPERATION_MASK: EFI_STATUS_CODE_VALUE = 0x0000FFFF;
+pub const EFI_SUBCLASS_SPECIFIC: EFI_STATUS_CODE_VALUE = 0x1000;
+pub const EFI_OEM_SPECIFIC: EFI_STATUS_CODE_VALUE = 0x8000;
+
+// Classes
+pub const EFI_COMPUTING_UNIT: EFI_STATUS_CODE_VALUE = 0x00000000;
+pub const EFI_PERIPHERAL: EFI_STATUS_CODE_VALUE = 0x01000000;
+pub const EFI_IO_BUS: EFI_STATUS_CODE_VALUE = 0x02000000;
+pub const EFI_SOFTWARE: EFI_STATUS_CODE_VALUE = 0x03000000;
+
+// Software subclasses, those an application may be
+pub const EFI_SOFTWARE_UNSPECIFIED: EFI_STATUS_CODE_VALUE = EFI_SOFTWARE | 0x00000000;
+pub const EFI_SOFTWARE_EFI_APPLICATION: EFI_STATUS_CODE_VALUE = EFI_SOFTWARE | 0x00100000;
+pub const EFI_SOFTWARE_EFI_OS_LOADER: EFI_STATUS_CODE_VALUE = EFI_SOFTWARE | 0x00110000;
+
+// Progress code operations common to all software subclasses
+pub const EFI_SW_PC_INIT: EFI_STATUS_CODE_VALUE = 0x00000000;
+pub const EFI_SW_PC_LOAD: EFI_STATUS_CODE_VALUE = 0x00000001;
+pub const EFI_SW_PC_INIT_BEGIN: EFI_STATUS_CODE_VALUE = 0x00000002;
+pub const EFI_SW_PC_INIT_END: EFI_STATUS_CODE_VALUE = 0x00000003;
+pub const EFI_SW_PC_AUTHENTICATE_BEGIN: EFI_STATUS_CODE_VALUE = 0x00000004;
+pub const EFI_SW_PC_AUTHENTICATE_END: EFI_STATUS_CODE_VALUE = 0x00000005;
+pub const EFI_SW_PC_INPUT_WAIT: EFI_STATUS_CODE_VALUE = 0x00000006;
+pub const EFI_SW_PC_USER_SETUP: EFI_STATUS_CODE_VALUE = 0x00000007;
+
+// Error code operations common to all software subclasses
+pub const EFI_SW_EC_NON_SPECIFIC: EFI_STATUS_CODE_VALUE = 0x00000000;
+pub const EFI_SW_EC_LOAD_ERROR: EFI_STATUS_CODE_VALUE = 0x00000001;
+pub const EFI_SW_EC_INVALID_PARAMETER: EFI_STATUS_CODE_VALUE = 0x00000002;
+pub const EFI_SW_EC_UNSUPPORTED: EFI_STATUS_CODE_VALUE = 0x00000003;
+pub const EFI_SW_EC_INVALID_BUFFER: EFI_STATUS_CODE_VALUE = 0x00000004;
+pub const EFI_SW_EC_OUT_OF_RESOURCES: EFI_STATUS_CODE_VALUE = 0x00000005;
+pub const EFI_SW_EC_ABORTED: EFI_STATUS_CODE_VALUE = 0x00000006;
+pub const EFI_SW_EC_ILLEGAL_SOFTWARE_STATE: EFI_STATUS_CODE_VALUE = 0x00000007;
+pub const EFI_SW_EC_ILLEGAL_HARDWARE_STATE: EFI_STATUS_CODE_VALUE = 0x00000008;
+pub const EFI_SW_EC_START_ERROR: EFI_STATUS_CODE_VALUE = 0x00000009;
+pub const EFI_SW_EC_BAD_DATE_TIME: EFI_STATUS_CODE_VALUE = 0x0000000A;
+pub const EFI_SW_EC_CFG_INVALID: EFI_STATUS_CODE_VALUE = 0x0000000B;
+pub const EFI_SW_EC_CFG_CLR_REQUEST: EFI_STATUS_CODE_VALUE = 0x0000000C;
+pub const EFI_SW_EC_CFG_DEFAULT: EFI_STATUS_CODE_VALUE = 0x0000000D;
+pub const EFI_SW_EC_PWD_INVALID: EFI_STATUS_CODE_VALUE = 0x0000000E;
+pub const EFI_SW_EC_PWD_CLR_REQUEST: EFI_STATUS_CODE_VALUE = 0x0000000F;
+pub const EFI_SW_EC_PWD_CLEARED: EFI_STATUS_CODE_VALUE = 0x00000010;
+pub const EFI_SW_EC_EVENT_LOG_FULL: EFI_STATUS_CODE_VALUE = 0x00000011;
+
+// Debug code operations
+pub const EFI_DC_UNSPECIFIED: EFI_STATUS_CODE_VALUE = 0x00000000;
+
+/// Header of the extended data that may go with a code. Type says what follows the header
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_STATUS_CODE_DATA {
+    pub HeaderSize: UINT16,
+    pub Size: UINT16, // Of what follows the header
+    pub Type: EFI_GUID,
+}
+
+pub const EFI_STATUS_CODE_DATA_TYPE_STRING_GUID: EFI_GUID = EFI_GUID(0x92d11080, 0x496f, 0x4d95, [0xbe, 0x7e, 0x03, 0x74, 0x88, 0x38, 0x2b, 0x0a]);
+
+pub type EFI_STRING_TYPE = UINT32;
+#[allow(non_upper_case_globals)]
+pub const EfiStringAscii: EFI_STRING_TYPE = 0;
+#[allow(non_upper_case_globals)]
+pub const EfiStringUnicode: EFI_STRING_TYPE = 1;
+#[allow(non_upper_case_globals)]
+pub const EfiStringToken: EFI_STRING_TYPE = 2;
+
+/// In the spec String is a union of an ASCII pointer, a UCS-2 pointer and an HII token, picked by StringType. We only
+/// ever send null-terminated ASCII
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_STATUS_CODE_STRING_DATA {
+    pub DataHeader: EFI_STATUS_CODE_DATA,
+    pub StringType: EFI_STRING_TYPE,
+    pub String: *const CHAR8,
+}
@@ -0,0 +1,42 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINTN,
+    UINT8,
+    UINT16,
+    UINT32,
+    UINT64,
+    VOID,
+};
+
+pub const EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xC88B0B6D, 0x0DFC, 0x49A7, [0x9C, 0xB4, 0x49, 0x07, 0x4B, 0x4C, 0x3A, 0x78]);
+
+#[repr(C)]
+pub struct EFI_STORAGE_SECURITY_COMMAND_PROTOCOL {
+    pub ReceiveData: EFI_STORAGE_SECURITY_RECEIVE_DATA,
+    pub SendData: EFI_STORAGE_SECURITY_SEND_DATA,
+}
+
+debug_as_table!(EFI_STORAGE_SECURITY_COMMAND_PROTOCOL);
+
+// Timeouts are in 100ns units, 0 meaning wait forever
+pub type EFI_STORAGE_SECURITY_RECEIVE_DATA = extern "efiapi" fn(
+    This: *const EFI_STORAGE_SECURITY_COMMAND_PROTOCOL,
+    MediaId: UINT32,
+    Timeout: UINT64,
+    SecurityProtocolId: UINT8,
+    SecurityProtocolSpecificData: UINT16, // Big-endian, as it goes in the command
+    PayloadBufferSize: UINTN,
+    PayloadBuffer: *mut VOID,
+    PayloadTransferSize: *mut UINTN
+) -> EFI_STATUS;
+
+pub type EFI_STORAGE_SECURITY_SEND_DATA = extern "efiapi" fn(
+    This: *const EFI_STORAGE_SECURITY_COMMAND_PROTOCOL,
+    MediaId: UINT32,
+    Timeout: UINT64,
+    SecurityProtocolId: UINT8,
+    SecurityProtocolSpecificData: UINT16,
+    PayloadBufferSize: UINTN,
+    PayloadBuffer: *const VOID
+) -> EFI_STATUS;
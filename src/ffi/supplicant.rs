@@ -0,0 +1,97 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINT32,
+    UINTN,
+    VOID,
+};
+
+pub const EFI_SUPPLICANT_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x45bcd98e, 0x59ad, 0x4174, [0x95, 0x46, 0x34, 0x4a, 0x07, 0x48, 0x58, 0x98]);
+pub const EFI_SUPPLICANT_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x54fcc43e, 0xaa89, 0x4333, [0x9a, 0x85, 0xcd, 0xea, 0x24, 0x05, 0x1e, 0x9e]);
+
+#[repr(C)]
+pub struct EFI_SUPPLICANT_PROTOCOL {
+    pub BuildResponsePacket: EFI_SUPPLICANT_BUILD_RESPONSE_PACKET,
+    pub ProcessPacket: EFI_SUPPLICANT_PROCESS_PACKET,
+    pub SetData: EFI_SUPPLICANT_SET_DATA,
+    pub GetData: EFI_SUPPLICANT_GET_DATA,
+}
+
+debug_as_table!(EFI_SUPPLICANT_PROTOCOL);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_SUPPLICANT_DATA_TYPE {
+    EfiSupplicant80211AKMSuite,
+    EfiSupplicant80211GroupDataCipherSuite,
+    EfiSupplicant80211PairwiseCipherSuite,
+    EfiSupplicant80211PskPassword,
+    EfiSupplicant80211TargetSSIDName,
+    EfiSupplicant80211StationMac,
+    EfiSupplicant80211TargetSSIDMac,
+    EfiSupplicant80211PTK,
+    EfiSupplicant80211GTK,
+    EfiSupplicantState,
+    EfiSupplicant80211LinkState,
+    EfiSupplicantKeyRefresh,
+    EfiSupplicant80211SupportedAKMSuites,
+    EfiSupplicant80211SupportedSoftwareCipherSuites,
+    EfiSupplicant80211SupportedHardwareCipherSuites,
+    EfiSupplicant80211IGTK,
+    EfiSupplicant80211PMK,
+    EfiSupplicantDataTypeMaximum,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum EFI_SUPPLICANT_CRYPT_MODE {
+    EfiSupplicantEncrypt,
+    EfiSupplicantDecrypt,
+}
+
+// EFI_80211_LINK_STATE
+#[allow(non_upper_case_globals)]
+pub const Ieee80211UnauthenticatedUnassociated: UINT32 = 1;
+#[allow(non_upper_case_globals)]
+pub const Ieee80211AuthenticatedUnassociated: UINT32 = 2;
+#[allow(non_upper_case_globals)]
+pub const Ieee80211PendingRSNAuthentication: UINT32 = 3;
+#[allow(non_upper_case_globals)]
+pub const Ieee80211AuthenticatedAssociated: UINT32 = 4;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_SUPPLICANT_FRAGMENT_DATA {
+    pub FragmentLength: UINT32,
+    pub FragmentBuffer: *mut VOID,
+}
+
+pub type EFI_SUPPLICANT_BUILD_RESPONSE_PACKET = extern "efiapi" fn(
+    This: *const EFI_SUPPLICANT_PROTOCOL,
+    RequestBuffer: *const UINT8,
+    RequestBufferSize: UINTN,
+    Buffer: *mut UINT8,
+    BufferSize: *mut UINTN
+) -> EFI_STATUS;
+
+pub type EFI_SUPPLICANT_PROCESS_PACKET = extern "efiapi" fn(
+    This: *const EFI_SUPPLICANT_PROTOCOL,
+    FragmentTable: *mut *mut EFI_SUPPLICANT_FRAGMENT_DATA,
+    FragmentCount: *mut UINT32,
+    EncryptMode: EFI_SUPPLICANT_CRYPT_MODE
+) -> EFI_STATUS;
+
+pub type EFI_SUPPLICANT_SET_DATA = extern "efiapi" fn(
+    This: *const EFI_SUPPLICANT_PROTOCOL,
+    DataType: EFI_SUPPLICANT_DATA_TYPE,
+    Data: *const VOID,
+    DataSize: UINTN
+) -> EFI_STATUS;
+
+pub type EFI_SUPPLICANT_GET_DATA = extern "efiapi" fn(
+    This: *const EFI_SUPPLICANT_PROTOCOL,
+    DataType: EFI_SUPPLICANT_DATA_TYPE,
+    Data: *mut UINT8,
+    DataSize: *mut UINTN
+) -> EFI_STATUS;
@@ -0,0 +1,149 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    EFI_PHYSICAL_ADDRESS,
+    BOOLEAN,
+    UINT8,
+    UINT16,
+    UINT32,
+    UINT64,
+};
+
+pub const EFI_TCG2_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x607f766c, 0x7455, 0x42be, [0x93, 0x0b, 0xe4, 0xd7, 0x6d, 0xb2, 0x72, 0x0f]);
+
+#[repr(C)]
+pub struct EFI_TCG2_PROTOCOL {
+    pub GetCapability: EFI_TCG2_GET_CAPABILITY,
+    pub GetEventLog: EFI_TCG2_GET_EVENT_LOG,
+    pub HashLogExtendEvent: EFI_TCG2_HASH_LOG_EXTEND_EVENT,
+    pub SubmitCommand: EFI_TCG2_SUBMIT_COMMAND,
+    pub GetActivePcrBanks: EFI_TCG2_GET_ACTIVE_PCR_BANKS,
+    pub SetActivePcrBanks: EFI_TCG2_SET_ACTIVE_PCR_BANKS,
+    pub GetResultOfSetActivePcrBanks: EFI_TCG2_GET_RESULT_OF_SET_ACTIVE_PCR_BANKS,
+}
+
+debug_as_table!(EFI_TCG2_PROTOCOL);
+
+pub type EFI_TCG2_GET_CAPABILITY = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    ProtocolCapability: *mut EFI_TCG2_BOOT_SERVICE_CAPABILITY // Size set to how much of it we know about
+) -> EFI_STATUS;
+
+pub type EFI_TCG2_GET_EVENT_LOG = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    EventLogFormat: EFI_TCG2_EVENT_LOG_FORMAT,
+    EventLogLocation: *mut EFI_PHYSICAL_ADDRESS,
+    EventLogLastEntry: *mut EFI_PHYSICAL_ADDRESS,
+    EventLogTruncated: *mut BOOLEAN
+) -> EFI_STATUS;
+
+pub type EFI_TCG2_HASH_LOG_EXTEND_EVENT = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    Flags: UINT64,
+    DataToHash: EFI_PHYSICAL_ADDRESS,
+    DataToHashLen: UINT64,
+    EfiTcgEvent: *const EFI_TCG2_EVENT
+) -> EFI_STATUS;
+
+pub type EFI_TCG2_SUBMIT_COMMAND = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    InputParameterBlockSize: UINT32,
+    InputParameterBlock: *const UINT8,
+    OutputParameterBlockSize: UINT32,
+    OutputParameterBlock: *mut UINT8
+) -> EFI_STATUS;
+
+pub type EFI_TCG2_GET_ACTIVE_PCR_BANKS = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    ActivePcrBanks: *mut UINT32
+) -> EFI_STATUS;
+
+pub type EFI_TCG2_SET_ACTIVE_PCR_BANKS = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    ActivePcrBanks: UINT32
+) -> EFI_STATUS;
+
+pub type EFI_TCG2_GET_RESULT_OF_SET_ACTIVE_PCR_BANKS = extern "efiapi" fn(
+    This: *const EFI_TCG2_PROTOCOL,
+    OperationPresent: *mut UINT32,
+    Response: *mut UINT32
+) -> EFI_STATUS;
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct EFI_TCG2_VERSION {
+    pub Major: UINT8,
+    pub Minor: UINT8,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C, packed)]
+pub struct EFI_TCG2_BOOT_SERVICE_CAPABILITY {
+    pub Size: UINT8,
+    pub StructureVersion: EFI_TCG2_VERSION,
+    pub ProtocolVersion: EFI_TCG2_VERSION,
+    pub HashAlgorithmBitmap: EFI_TCG2_EVENT_ALGORITHM_BITMAP,
+    pub SupportedEventLogs: EFI_TCG2_EVENT_LOG_BITMAP,
+    pub TPMPresentFlag: BOOLEAN,
+    pub MaxCommandSize: UINT16,
+    pub MaxResponseSize: UINT16,
+    pub ManufacturerID: UINT32,
+    pub NumberOfPCRBanks: UINT32,
+    pub ActivePcrBanks: EFI_TCG2_EVENT_ALGORITHM_BITMAP,
+}
+
+pub type EFI_TCG2_EVENT_LOG_BITMAP = UINT32;
+pub type EFI_TCG2_EVENT_LOG_FORMAT = UINT32;
+pub type EFI_TCG2_EVENT_ALGORITHM_BITMAP = UINT32;
+
+pub const EFI_TCG2_EVENT_LOG_FORMAT_TCG_1_2: EFI_TCG2_EVENT_LOG_FORMAT = 0x0000_0001;
+pub const EFI_TCG2_EVENT_LOG_FORMAT_TCG_2: EFI_TCG2_EVENT_LOG_FORMAT = 0x0000_0002;
+
+pub const EFI_TCG2_BOOT_HASH_ALG_SHA1: EFI_TCG2_EVENT_ALGORITHM_BITMAP = 0x0000_0001;
+pub const EFI_TCG2_BOOT_HASH_ALG_SHA256: EFI_TCG2_EVENT_ALGORITHM_BITMAP = 0x0000_0002;
+pub const EFI_TCG2_BOOT_HASH_ALG_SHA384: EFI_TCG2_EVENT_ALGORITHM_BITMAP = 0x0000_0004;
+pub const EFI_TCG2_BOOT_HASH_ALG_SHA512: EFI_TCG2_EVENT_ALGORITHM_BITMAP = 0x0000_0008;
+pub const EFI_TCG2_BOOT_HASH_ALG_SM3_256: EFI_TCG2_EVENT_ALGORITHM_BITMAP = 0x0000_0010;
+
+// Flags for HashLogExtendEvent
+pub const EFI_TCG2_EXTEND_ONLY: UINT64 = 0x0000_0000_0000_0001;
+pub const PE_COFF_IMAGE: UINT64 = 0x0000_0000_0000_0010;
+
+pub const EFI_TCG2_EVENT_HEADER_VERSION: UINT16 = 1;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct EFI_TCG2_EVENT_HEADER {
+    pub HeaderSize: UINT32,
+    pub HeaderVersion: UINT16,
+    pub PCRIndex: TCG_PCRINDEX,
+    pub EventType: TCG_EVENTTYPE,
+}
+
+/// Followed by the event data, which is logged but not hashed. Size covers all of it
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct EFI_TCG2_EVENT {
+    pub Size: UINT32,
+    pub Header: EFI_TCG2_EVENT_HEADER,
+    // UINT8 Event[]
+}
+
+pub type TCG_PCRINDEX = UINT32;
+pub type TCG_EVENTTYPE = UINT32;
+
+// Event types from the TCG PC Client Platform Firmware Profile
+pub const EV_POST_CODE: TCG_EVENTTYPE = 0x0000_0001;
+pub const EV_NO_ACTION: TCG_EVENTTYPE = 0x0000_0003;
+pub const EV_SEPARATOR: TCG_EVENTTYPE = 0x0000_0004;
+pub const EV_ACTION: TCG_EVENTTYPE = 0x0000_0005;
+pub const EV_EVENT_TAG: TCG_EVENTTYPE = 0x0000_0006;
+pub const EV_IPL: TCG_EVENTTYPE = 0x0000_000D;
+pub const EV_COMPACT_HASH: TCG_EVENTTYPE = 0x0000_000C;
+pub const EV_EFI_EVENT_BASE: TCG_EVENTTYPE = 0x8000_0000;
+pub const EV_EFI_VARIABLE_DRIVER_CONFIG: TCG_EVENTTYPE = EV_EFI_EVENT_BASE + 0x1;
+pub const EV_EFI_VARIABLE_BOOT: TCG_EVENTTYPE = EV_EFI_EVENT_BASE + 0x2;
+pub const EV_EFI_BOOT_SERVICES_APPLICATION: TCG_EVENTTYPE = EV_EFI_EVENT_BASE + 0x3;
+pub const EV_EFI_BOOT_SERVICES_DRIVER: TCG_EVENTTYPE = EV_EFI_EVENT_BASE + 0x4;
+pub const EV_EFI_ACTION: TCG_EVENTTYPE = EV_EFI_EVENT_BASE + 0x7;
+pub const EV_EFI_VARIABLE_AUTHORITY: TCG_EVENTTYPE = EV_EFI_EVENT_BASE + 0xE0;
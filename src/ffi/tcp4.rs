@@ -20,7 +20,7 @@ use ffi::{
     ip4::EFI_IP4_MODE_DATA,
 };
 
-use core::{mem, ptr};
+use core::{fmt, mem, ptr};
 
 pub const EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x00720665, 0x67EB, 0x4a99, [0xBA, 0xF7, 0xD3, 0xC3, 0x3A, 0x1C, 0x7C, 0xC9]);
 
@@ -40,8 +40,10 @@ pub struct EFI_TCP4_PROTOCOL {
     pub Poll: EFI_TCP4_POLL,
 }
 
+debug_as_table!(EFI_TCP4_PROTOCOL);
 
-pub type EFI_TCP4_GET_MODE_DATA = extern "win64" fn(
+
+pub type EFI_TCP4_GET_MODE_DATA = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     Tcp4State: *mut EFI_TCP4_CONNECTION_STATE,
     Tcp4ConfigData: *mut EFI_TCP4_CONFIG_DATA,
@@ -96,6 +98,30 @@ pub struct EFI_TCP4_OPTION {
     pub EnablePathMtuDiscovery: BOOLEAN,
 }
 
+// The driver's usual settings. Zeroes aren't "don't care" for all of these: they'd turn Nagle, timestamps and window
+// scaling off
+impl Default for EFI_TCP4_OPTION {
+    fn default() -> Self {
+        Self {
+            ReceiveBufferSize: 0x10000,
+            SendBufferSize: 0x10000,
+            MaxSynBackLog: 5,
+            ConnectionTimeout: 75,
+            DataRetries: 12,
+            FinTimeout: 60,
+            TimeWaitTimeout: 60,
+            KeepAliveProbes: 0,
+            KeepAliveTime: 7200,
+            KeepAliveInterval: 75,
+            EnableNagle: TRUE,
+            EnableTimeStamp: TRUE,
+            EnableWindowScaling: TRUE,
+            EnableSelectiveAck: FALSE,
+            EnablePathMtuDiscovery: FALSE,
+        }
+    }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct EFI_TCP4_CONFIG_DATA {
@@ -132,13 +158,13 @@ pub enum EFI_TCP4_CONNECTION_STATE{
     Tcp4StateLastAck = 10
 }
 
-pub type EFI_TCP4_CONFIGURE = extern "win64" fn(
+pub type EFI_TCP4_CONFIGURE = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     TcpConfigData: *const EFI_TCP4_CONFIG_DATA,
 ) -> EFI_STATUS;
 
 
-pub type EFI_TCP4_ROUTES = extern "win64" fn(
+pub type EFI_TCP4_ROUTES = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     DeleteRoute: BOOLEAN,
     SubnetAddress: *const EFI_IPv4_ADDRESS,
@@ -146,7 +172,7 @@ pub type EFI_TCP4_ROUTES = extern "win64" fn(
     GatewayAddress: *const EFI_IPv4_ADDRESS
 ) -> EFI_STATUS;
 
-pub type EFI_TCP4_CONNECT = extern "win64" fn(
+pub type EFI_TCP4_CONNECT = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     ConnectionToken: *mut EFI_TCP4_CONNECTION_TOKEN
 ) -> EFI_STATUS;
@@ -180,7 +206,7 @@ impl Default for EFI_TCP4_CONNECTION_TOKEN {
     }
 }
 
-pub type EFI_TCP4_ACCEPT = extern "win64" fn(
+pub type EFI_TCP4_ACCEPT = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     ListenToken: *const EFI_TCP4_LISTEN_TOKEN
 ) -> EFI_STATUS;
@@ -213,7 +239,7 @@ pub struct EFI_TCP4_TRANSMIT_DATA {
     pub FragmentTable: [EFI_TCP4_FRAGMENT_DATA; 1], 
 }
 
-pub type EFI_TCP4_TRANSMIT = extern "win64" fn(
+pub type EFI_TCP4_TRANSMIT = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     Token: *const EFI_TCP4_IO_TOKEN
 ) -> EFI_STATUS;
@@ -224,6 +250,15 @@ pub union PacketUnion {
     pub TxData: *const EFI_TCP4_TRANSMIT_DATA,
 }
 
+// Had to implement by hand 'cause Debug derive not allowed for unions. Both are pointers and which it is depends on
+// the token, so it's just the address
+impl fmt::Debug for PacketUnion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", unsafe { self.RxData })
+    }
+}
+
+#[derive(Debug)]
 #[repr(C)]
 pub struct EFI_TCP4_IO_TOKEN {
     pub CompletionToken: EFI_TCP4_COMPLETION_TOKEN,
@@ -243,6 +278,7 @@ pub const EFI_CONNECTION_FIN: UINTN = with_high_bit_set!(104);
 pub const EFI_CONNECTION_RESET: UINTN = with_high_bit_set!(105);
 pub const EFI_CONNECTION_REFUSED: UINTN =  with_high_bit_set!(106);
 
+#[derive(Debug)]
 #[repr(C)]
 pub struct EFI_TCP4_RECEIVE_DATA {
     pub UrgentFlag: BOOLEAN,
@@ -260,12 +296,12 @@ pub struct EFI_TCP4_FRAGMENT_DATA {
     pub FragmentBuffer: *const VOID,
 }
 
-pub type EFI_TCP4_RECEIVE = extern "win64" fn(
+pub type EFI_TCP4_RECEIVE = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     Token: *const EFI_TCP4_IO_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_TCP4_CLOSE = extern "win64" fn(
+pub type EFI_TCP4_CLOSE = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     CloseToken: *const EFI_TCP4_CLOSE_TOKEN
 ) -> EFI_STATUS;
@@ -286,11 +322,11 @@ impl Default for EFI_TCP4_CLOSE_TOKEN {
     }
 }
 
-pub type EFI_TCP4_CANCEL = extern "win64" fn(
+pub type EFI_TCP4_CANCEL = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL,
     Token: *const EFI_TCP4_COMPLETION_TOKEN
 ) -> EFI_STATUS;
 
-pub type EFI_TCP4_POLL = extern "win64" fn(
+pub type EFI_TCP4_POLL = extern "efiapi" fn(
     This: *const EFI_TCP4_PROTOCOL
 ) -> EFI_STATUS;
\ No newline at end of file
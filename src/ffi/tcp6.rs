@@ -0,0 +1,138 @@
+use ffi::{
+    base::{
+        EFI_IPv6_ADDRESS,
+        EFI_STATUS,
+        EFI_GUID,
+        UINT8,
+        UINT16,
+        BOOLEAN,
+        TRUE,
+    },
+    managed_network::EFI_MANAGED_NETWORK_CONFIG_DATA,
+    simple_network::EFI_SIMPLE_NETWORK_MODE,
+    tcp4,
+    VOID,
+};
+
+use core::ptr;
+
+pub const EFI_TCP6_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0xec20eb79, 0x6c1a, 0x4664, [0x9a, 0x0d, 0xd2, 0xe4, 0xcc, 0x16, 0xd6, 0x64]);
+
+pub const EFI_TCP6_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x46e44855, 0xbd60, 0x4ab7, [0xab, 0x0d, 0xa6, 0x79, 0xb9, 0x44, 0x7d, 0x77]);
+
+// The tokens, options and packets are laid out the same as TCP4's, and only the addresses differ
+pub type EFI_TCP6_OPTION = tcp4::EFI_TCP4_OPTION;
+pub type EFI_TCP6_CONNECTION_STATE = tcp4::EFI_TCP4_CONNECTION_STATE;
+pub type EFI_TCP6_COMPLETION_TOKEN = tcp4::EFI_TCP4_COMPLETION_TOKEN;
+pub type EFI_TCP6_CONNECTION_TOKEN = tcp4::EFI_TCP4_CONNECTION_TOKEN;
+pub type EFI_TCP6_LISTEN_TOKEN = tcp4::EFI_TCP4_LISTEN_TOKEN;
+pub type EFI_TCP6_IO_TOKEN = tcp4::EFI_TCP4_IO_TOKEN;
+pub type EFI_TCP6_CLOSE_TOKEN = tcp4::EFI_TCP4_CLOSE_TOKEN;
+pub type EFI_TCP6_RECEIVE_DATA = tcp4::EFI_TCP4_RECEIVE_DATA;
+pub type EFI_TCP6_TRANSMIT_DATA = tcp4::EFI_TCP4_TRANSMIT_DATA;
+pub type EFI_TCP6_FRAGMENT_DATA = tcp4::EFI_TCP4_FRAGMENT_DATA;
+
+#[repr(C)]
+pub struct EFI_TCP6_PROTOCOL {
+    pub GetModeData: EFI_TCP6_GET_MODE_DATA,
+    pub Configure: EFI_TCP6_CONFIGURE,
+    pub Connect: EFI_TCP6_CONNECT,
+    pub Accept: EFI_TCP6_ACCEPT,
+    pub Transmit: EFI_TCP6_TRANSMIT,
+    pub Receive: EFI_TCP6_RECEIVE,
+    pub Close: EFI_TCP6_CLOSE,
+    pub Cancel: EFI_TCP6_CANCEL,
+    pub Poll: EFI_TCP6_POLL,
+}
+
+debug_as_table!(EFI_TCP6_PROTOCOL);
+
+pub type EFI_TCP6_GET_MODE_DATA = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    Tcp6State: *mut EFI_TCP6_CONNECTION_STATE,
+    Tcp6ConfigData: *mut EFI_TCP6_CONFIG_DATA,
+    Ip6ModeData: *mut VOID, // EFI_IP6_MODE_DATA, which we don't have
+    MnpConfigData: *mut EFI_MANAGED_NETWORK_CONFIG_DATA,
+    SnpModeData: *mut EFI_SIMPLE_NETWORK_MODE
+) -> EFI_STATUS;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_TCP6_ACCESS_POINT {
+    pub StationAddress: EFI_IPv6_ADDRESS,
+    pub StationPort: UINT16,
+    pub RemoteAddress: EFI_IPv6_ADDRESS,
+    pub RemotePort: UINT16,
+    pub ActiveFlag: BOOLEAN,
+}
+
+impl Default for EFI_TCP6_ACCESS_POINT {
+    fn default() -> Self {
+        Self {
+            StationAddress: EFI_IPv6_ADDRESS::zero(),
+            StationPort: 0,
+            RemoteAddress: EFI_IPv6_ADDRESS::zero(),
+            RemotePort: 0,
+            ActiveFlag: TRUE,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_TCP6_CONFIG_DATA {
+    pub TrafficClass: UINT8,
+    pub HopLimit: UINT8,
+    pub AccessPoint: EFI_TCP6_ACCESS_POINT,
+    pub ControlOption: *const EFI_TCP6_OPTION,
+}
+
+impl Default for EFI_TCP6_CONFIG_DATA {
+    fn default() -> Self {
+        Self {
+            TrafficClass: 0,
+            HopLimit: 0,
+            AccessPoint: EFI_TCP6_ACCESS_POINT::default(),
+            ControlOption: ptr::null() as *const EFI_TCP6_OPTION,
+        }
+    }
+}
+
+pub type EFI_TCP6_CONFIGURE = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    Tcp6ConfigData: *const EFI_TCP6_CONFIG_DATA,
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_CONNECT = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    ConnectionToken: *mut EFI_TCP6_CONNECTION_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_ACCEPT = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    ListenToken: *const EFI_TCP6_LISTEN_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_TRANSMIT = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    Token: *const EFI_TCP6_IO_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_RECEIVE = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    Token: *const EFI_TCP6_IO_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_CLOSE = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    CloseToken: *const EFI_TCP6_CLOSE_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_CANCEL = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL,
+    Token: *const EFI_TCP6_COMPLETION_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_TCP6_POLL = extern "efiapi" fn(
+    This: *const EFI_TCP6_PROTOCOL
+) -> EFI_STATUS;
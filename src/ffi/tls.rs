@@ -0,0 +1,5 @@
+use ffi::base::EFI_GUID;
+
+pub const EFI_TLS_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x952cb795, 0xff36, 0x48cf, [0xa2, 0x49, 0x4d, 0xf4, 0x86, 0xd6, 0xab, 0x8d]);
+
+pub const EFI_TLS_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x00ca959f, 0x6cfa, 0x4db1, [0x95, 0xbc, 0xe4, 0x6c, 0x47, 0x51, 0x43, 0x90]);
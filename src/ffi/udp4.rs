@@ -19,7 +19,7 @@ use ffi::{
     simple_network::EFI_SIMPLE_NETWORK_MODE,
     ip4::EFI_IP4_MODE_DATA,
 };
-use core::{mem, ptr};
+use core::{fmt, mem, ptr};
 
 pub const EFI_UDP4_SERVICE_BINDING_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x83f01464, 0x99bd, 0x45e5, [0xb3, 0x83, 0xaf, 0x63, 0x05, 0xd8, 0xe9, 0xe6]);
 
@@ -37,7 +37,9 @@ pub struct EFI_UDP4_PROTOCOL {
     pub Poll: EFI_UDP4_POLL,
 }
 
-pub type EFI_UDP4_GET_MODE_DATA = extern "win64" fn(
+debug_as_table!(EFI_UDP4_PROTOCOL);
+
+pub type EFI_UDP4_GET_MODE_DATA = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     Udp4ConfigData: *mut EFI_UDP4_CONFIG_DATA,
     Ip4ModeData: *mut EFI_IP4_MODE_DATA,
@@ -46,7 +48,7 @@ pub type EFI_UDP4_GET_MODE_DATA = extern "win64" fn(
 ) -> EFI_STATUS;
 
 #[repr(C)]
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct EFI_UDP4_CONFIG_DATA {
     //Receiving Filters
     pub AcceptBroadcast: BOOLEAN,
@@ -90,18 +92,18 @@ impl Default for EFI_UDP4_CONFIG_DATA  {
     }
 }
 
-pub type EFI_UDP4_CONFIGURE = extern "win64" fn(
+pub type EFI_UDP4_CONFIGURE = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     UdpConfigData: *const EFI_UDP4_CONFIG_DATA,
 ) -> EFI_STATUS;
 
-pub type EFI_UDP4_GROUPS = extern "win64" fn(
+pub type EFI_UDP4_GROUPS = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     JoinFlag: BOOLEAN,
     MulticastAddress: *const EFI_IPv4_ADDRESS,
 ) -> EFI_STATUS;
 
-pub type EFI_UDP4_ROUTES = extern "win64" fn(
+pub type EFI_UDP4_ROUTES = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     DeleteRoute: BOOLEAN,
     SubnetAddress: *const EFI_IPv4_ADDRESS,
@@ -109,11 +111,12 @@ pub type EFI_UDP4_ROUTES = extern "win64" fn(
     GatewayAddress: *const EFI_IPv4_ADDRESS
 ) -> EFI_STATUS;
 
-pub type EFI_UDP4_TRANSMIT = extern "win64" fn(
+pub type EFI_UDP4_TRANSMIT = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     Token: *const EFI_UDP4_COMPLETION_TOKEN,
 ) -> EFI_STATUS;
 
+#[derive(Debug)]
 #[repr(C)]
 pub struct EFI_UDP4_COMPLETION_TOKEN {
     pub Event: EFI_EVENT,
@@ -137,6 +140,14 @@ pub union PacketUnion {
     pub TxData: *const EFI_UDP4_TRANSMIT_DATA,
 }
 
+// Had to implement by hand 'cause Debug derive not allowed for unions. Both are pointers and which it is depends on
+// the token, so it's just the address
+impl fmt::Debug for PacketUnion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", unsafe { self.RxData })
+    }
+}
+
 pub const EFI_NETWORK_UNREACHABLE: UINTN = with_high_bit_set!(100);
 pub const EFI_HOST_UNREACHABLE: UINTN = with_high_bit_set!(101) ;
 pub const EFI_PROTOCOL_UNREACHABLE: UINTN = with_high_bit_set!(102);
@@ -179,16 +190,16 @@ pub struct EFI_UDP4_TRANSMIT_DATA {
     pub FragmentTable: [EFI_UDP4_FRAGMENT_DATA; 1],
 }
 
-pub type EFI_UDP4_RECEIVE = extern "win64" fn(
+pub type EFI_UDP4_RECEIVE = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     Token: *const EFI_UDP4_COMPLETION_TOKEN,
 ) -> EFI_STATUS;
 
-pub type EFI_UDP4_CANCEL = extern "win64" fn(
+pub type EFI_UDP4_CANCEL = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
     Token: *const EFI_UDP4_COMPLETION_TOKEN,
 ) -> EFI_STATUS;
 
-pub type EFI_UDP4_POLL = extern "win64" fn(
+pub type EFI_UDP4_POLL = extern "efiapi" fn(
     This: *const EFI_UDP4_PROTOCOL,
 ) -> EFI_STATUS;
\ No newline at end of file
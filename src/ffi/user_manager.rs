@@ -0,0 +1,132 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINT16,
+    UINT32,
+    UINTN,
+    VOID,
+    NOT_DEFINED,
+};
+
+pub const EFI_USER_MANAGER_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x6fd5b00c, 0xd426, 0x4283, [0x98, 0x87, 0x6c, 0xf5, 0xcf, 0x1c, 0xb1, 0xfe]);
+
+pub type EFI_USER_PROFILE_HANDLE = *const VOID;
+pub type EFI_USER_INFO_HANDLE = *const VOID;
+
+pub type EFI_USER_INFO_ATTRIBS = UINT16;
+
+// EFI_USER_INFO_ATTRIBS
+pub const EFI_USER_INFO_STORAGE_VOLATILE: EFI_USER_INFO_ATTRIBS = 0x0000;
+pub const EFI_USER_INFO_STORAGE_CREDENTIAL_NV: EFI_USER_INFO_ATTRIBS = 0x0001;
+pub const EFI_USER_INFO_STORAGE_PLATFORM_NV: EFI_USER_INFO_ATTRIBS = 0x0002;
+pub const EFI_USER_INFO_STORAGE: EFI_USER_INFO_ATTRIBS = 0x000F;
+pub const EFI_USER_INFO_PUBLIC: EFI_USER_INFO_ATTRIBS = 0x0010;
+pub const EFI_USER_INFO_PRIVATE: EFI_USER_INFO_ATTRIBS = 0x0020;
+pub const EFI_USER_INFO_PROTECTED: EFI_USER_INFO_ATTRIBS = 0x0030;
+pub const EFI_USER_INFO_EXCLUSIVE: EFI_USER_INFO_ATTRIBS = 0x0080;
+
+/// Header of a user information record. InfoSize includes the header, the record's data follows it
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_USER_INFO {
+    pub Credential: EFI_GUID,
+    pub InfoType: UINT8,
+    pub Reserved1: UINT8,
+    pub InfoAttribs: EFI_USER_INFO_ATTRIBS,
+    pub InfoSize: UINT32,
+}
+
+// EFI_USER_INFO InfoTypes
+pub const EFI_USER_INFO_EMPTY_RECORD: UINT8 = 0x00;
+pub const EFI_USER_INFO_NAME_RECORD: UINT8 = 0x01;
+pub const EFI_USER_INFO_CREATE_DATE_RECORD: UINT8 = 0x02;
+pub const EFI_USER_INFO_USAGE_DATE_RECORD: UINT8 = 0x03;
+pub const EFI_USER_INFO_USAGE_COUNT_RECORD: UINT8 = 0x04;
+pub const EFI_USER_INFO_IDENTIFIER_RECORD: UINT8 = 0x05;
+pub const EFI_USER_INFO_CREDENTIAL_TYPE_RECORD: UINT8 = 0x06;
+pub const EFI_USER_INFO_CREDENTIAL_TYPE_NAME_RECORD: UINT8 = 0x07;
+pub const EFI_USER_INFO_CREDENTIAL_PROVIDER_RECORD: UINT8 = 0x08;
+pub const EFI_USER_INFO_CREDENTIAL_PROVIDER_NAME_RECORD: UINT8 = 0x09;
+pub const EFI_USER_INFO_PKCS11_RECORD: UINT8 = 0x0A;
+pub const EFI_USER_INFO_CBEFF_RECORD: UINT8 = 0x0B;
+pub const EFI_USER_INFO_FAR_RECORD: UINT8 = 0x0C;
+pub const EFI_USER_INFO_RETRY_RECORD: UINT8 = 0x0D;
+pub const EFI_USER_INFO_ACCESS_POLICY_RECORD: UINT8 = 0x0E;
+pub const EFI_USER_INFO_IDENTITY_POLICY_RECORD: UINT8 = 0x0F;
+pub const EFI_USER_INFO_GUID_RECORD: UINT8 = 0xFF;
+
+/// One term of an identity policy record. Length includes the header, the term's data follows it
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_USER_INFO_IDENTITY_POLICY {
+    pub Type: UINT32,
+    pub Length: UINT32,
+}
+
+// EFI_USER_INFO_IDENTITY_POLICY Types
+pub const EFI_USER_INFO_IDENTITY_FALSE: UINT32 = 0x00;
+pub const EFI_USER_INFO_IDENTITY_TRUE: UINT32 = 0x01;
+pub const EFI_USER_INFO_IDENTITY_CREDENTIAL_TYPE: UINT32 = 0x02;
+pub const EFI_USER_INFO_IDENTITY_CREDENTIAL_PROVIDER: UINT32 = 0x03;
+pub const EFI_USER_INFO_IDENTITY_NOT: UINT32 = 0x10;
+pub const EFI_USER_INFO_IDENTITY_AND: UINT32 = 0x11;
+pub const EFI_USER_INFO_IDENTITY_OR: UINT32 = 0x12;
+
+#[repr(C)]
+pub struct EFI_USER_MANAGER_PROTOCOL {
+    pub Create: EFI_USER_PROFILE_CREATE,
+    pub Delete: EFI_USER_PROFILE_DELETE,
+    pub GetNext: EFI_USER_PROFILE_GET_NEXT,
+    pub Current: EFI_USER_PROFILE_CURRENT,
+    pub Identify: EFI_USER_PROFILE_IDENTIFY,
+    pub Find: EFI_USER_PROFILE_FIND,
+    pub Notify: EFI_USER_PROFILE_NOTIFY,
+    pub GetInfo: EFI_USER_PROFILE_GET_INFO,
+    pub SetInfo: EFI_USER_PROFILE_SET_INFO,
+    pub DeleteInfo: EFI_USER_PROFILE_DELETE_INFO,
+    pub GetNextInfo: EFI_USER_PROFILE_GET_NEXT_INFO,
+}
+
+debug_as_table!(EFI_USER_MANAGER_PROTOCOL);
+
+pub type EFI_USER_PROFILE_CREATE = *const NOT_DEFINED;
+
+pub type EFI_USER_PROFILE_DELETE = *const NOT_DEFINED;
+
+pub type EFI_USER_PROFILE_GET_NEXT = extern "efiapi" fn(
+    This: *const EFI_USER_MANAGER_PROTOCOL,
+    User: *mut EFI_USER_PROFILE_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_USER_PROFILE_CURRENT = extern "efiapi" fn(
+    This: *const EFI_USER_MANAGER_PROTOCOL,
+    CurrentUser: *mut EFI_USER_PROFILE_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_USER_PROFILE_IDENTIFY = extern "efiapi" fn(
+    This: *const EFI_USER_MANAGER_PROTOCOL,
+    User: *mut EFI_USER_PROFILE_HANDLE
+) -> EFI_STATUS;
+
+pub type EFI_USER_PROFILE_FIND = *const NOT_DEFINED;
+
+pub type EFI_USER_PROFILE_NOTIFY = *const NOT_DEFINED;
+
+pub type EFI_USER_PROFILE_GET_INFO = extern "efiapi" fn(
+    This: *const EFI_USER_MANAGER_PROTOCOL,
+    User: EFI_USER_PROFILE_HANDLE,
+    UserInfo: EFI_USER_INFO_HANDLE,
+    Info: *mut EFI_USER_INFO,
+    InfoSize: *mut UINTN
+) -> EFI_STATUS;
+
+pub type EFI_USER_PROFILE_SET_INFO = *const NOT_DEFINED;
+
+pub type EFI_USER_PROFILE_DELETE_INFO = *const NOT_DEFINED;
+
+pub type EFI_USER_PROFILE_GET_NEXT_INFO = extern "efiapi" fn(
+    This: *const EFI_USER_MANAGER_PROTOCOL,
+    User: EFI_USER_PROFILE_HANDLE,
+    UserInfo: *mut EFI_USER_INFO_HANDLE
+) -> EFI_STATUS;
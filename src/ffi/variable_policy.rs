@@ -0,0 +1,93 @@
+// EDK2's variable policy protocol (MdeModulePkg/Include/Protocol/VariablePolicy.h), through which drivers restrict
+// what can be written to variables and when they become read-only.
+use ffi::base::{
+    BOOLEAN,
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINT16,
+    UINT32,
+    UINT64,
+    NOT_DEFINED,
+};
+
+pub const EDKII_VARIABLE_POLICY_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x81D1675C, 0x86F6, 0x48DF, [0xBD, 0x95, 0x9A, 0x6E, 0x4F, 0x09, 0x25, 0xC3]);
+
+pub const EDKII_VARIABLE_POLICY_PROTOCOL_REVISION: UINT64 = 0x0000_0000_0001_0000;
+/// From this revision on the protocol has GetVariablePolicyInfo and GetLockOnVariableStateVariablePolicyInfo
+pub const EDKII_VARIABLE_POLICY_PROTOCOL_REVISION_2: UINT64 = 0x0000_0000_0001_0001;
+
+#[repr(C)]
+pub struct EDKII_VARIABLE_POLICY_PROTOCOL {
+    pub Revision: UINT64,
+    pub DisableVariablePolicy: DISABLE_VARIABLE_POLICY,
+    pub IsVariablePolicyEnabled: IS_VARIABLE_POLICY_ENABLED,
+    pub RegisterVariablePolicy: REGISTER_VARIABLE_POLICY,
+    pub DumpVariablePolicy: DUMP_VARIABLE_POLICY,
+    pub LockVariablePolicy: LOCK_VARIABLE_POLICY,
+    pub GetVariablePolicyInfo: GET_VARIABLE_POLICY_INFO,
+    pub GetLockOnVariableStateVariablePolicyInfo: GET_LOCK_ON_VARIABLE_STATE_VARIABLE_POLICY_INFO,
+}
+
+debug_as_table!(EDKII_VARIABLE_POLICY_PROTOCOL);
+
+pub type DISABLE_VARIABLE_POLICY = *const NOT_DEFINED;
+
+pub type IS_VARIABLE_POLICY_ENABLED = extern "efiapi" fn(
+    State: *mut BOOLEAN
+) -> EFI_STATUS;
+
+pub type REGISTER_VARIABLE_POLICY = *const NOT_DEFINED;
+
+/// Copies every registered policy, one VARIABLE_POLICY_ENTRY after another, into Policy. With a null Policy (or one
+/// that's too small) it returns EFI_BUFFER_TOO_SMALL and the size needed
+pub type DUMP_VARIABLE_POLICY = extern "efiapi" fn(
+    Policy: *mut UINT8,
+    Size: *mut UINT32
+) -> EFI_STATUS;
+
+pub type LOCK_VARIABLE_POLICY = *const NOT_DEFINED;
+
+pub type GET_VARIABLE_POLICY_INFO = *const NOT_DEFINED;
+
+pub type GET_LOCK_ON_VARIABLE_STATE_VARIABLE_POLICY_INFO = *const NOT_DEFINED;
+
+pub const VARIABLE_POLICY_ENTRY_REVISION: UINT32 = 0x0001_0000;
+
+/// Followed by the lock policy (for VARIABLE_POLICY_TYPE_LOCK_ON_VAR_STATE) and then the null-terminated name the
+/// policy is for, which may contain '#' standing for any hex digit. With no name (OffsetToName == Size) it covers the
+/// whole namespace
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct VARIABLE_POLICY_ENTRY {
+    pub Version: UINT32,
+    pub Size: UINT16,
+    pub OffsetToName: UINT16,
+    pub Namespace: EFI_GUID,
+    pub MinSize: UINT32,
+    pub MaxSize: UINT32,
+    pub AttributesMustHave: UINT32,
+    pub AttributesCantHave: UINT32,
+    pub LockPolicyType: UINT8,
+    pub Reserved: [UINT8; 3],
+}
+
+pub const VARIABLE_POLICY_NO_MIN_SIZE: UINT32 = 0;
+pub const VARIABLE_POLICY_NO_MAX_SIZE: UINT32 = UINT32::max_value();
+pub const VARIABLE_POLICY_NO_MUST_ATTR: UINT32 = 0;
+pub const VARIABLE_POLICY_NO_CANT_ATTR: UINT32 = 0;
+
+pub const VARIABLE_POLICY_TYPE_NO_LOCK: UINT8 = 0;
+pub const VARIABLE_POLICY_TYPE_LOCK_NOW: UINT8 = 1;
+pub const VARIABLE_POLICY_TYPE_LOCK_ON_CREATE: UINT8 = 2;
+pub const VARIABLE_POLICY_TYPE_LOCK_ON_VAR_STATE: UINT8 = 3;
+
+/// The variable is locked once the variable Name in Namespace holds the single byte Value
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct VARIABLE_LOCK_ON_VAR_STATE_POLICY {
+    pub Namespace: EFI_GUID,
+    pub Value: UINT8,
+    pub Reserved: UINT8,
+    // CHAR16 Name[]
+}
@@ -0,0 +1,42 @@
+use ffi::base::{
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINT16,
+};
+
+pub const EFI_VLAN_CONFIG_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x9e23d768, 0xd2f3, 0x4366, [0x9f, 0xc3, 0x3a, 0x7a, 0xba, 0x86, 0x43, 0x74]);
+
+#[repr(C)]
+pub struct EFI_VLAN_CONFIG_PROTOCOL {
+    pub Set: EFI_VLAN_CONFIG_SET,
+    pub Find: EFI_VLAN_CONFIG_FIND,
+    pub Remove: EFI_VLAN_CONFIG_REMOVE,
+}
+
+debug_as_table!(EFI_VLAN_CONFIG_PROTOCOL);
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_VLAN_FIND_DATA {
+    pub VlanId: UINT16,
+    pub Priority: UINT8,
+}
+
+pub type EFI_VLAN_CONFIG_SET = extern "efiapi" fn(
+    This: *const EFI_VLAN_CONFIG_PROTOCOL,
+    VlanId: UINT16,
+    Priority: UINT8
+) -> EFI_STATUS;
+
+pub type EFI_VLAN_CONFIG_FIND = extern "efiapi" fn(
+    This: *const EFI_VLAN_CONFIG_PROTOCOL,
+    VlanId: *const UINT16,
+    NumberOfVlan: *mut UINT16,
+    Entries: *mut *mut EFI_VLAN_FIND_DATA
+) -> EFI_STATUS;
+
+pub type EFI_VLAN_CONFIG_REMOVE = extern "efiapi" fn(
+    This: *const EFI_VLAN_CONFIG_PROTOCOL,
+    VlanId: UINT16
+) -> EFI_STATUS;
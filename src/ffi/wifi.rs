@@ -0,0 +1,149 @@
+use ffi::base::{
+    EFI_EVENT,
+    EFI_GUID,
+    EFI_STATUS,
+    UINT8,
+    UINT16,
+    UINT32,
+};
+
+pub const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID: EFI_GUID = EFI_GUID(0x1b0fb9bf, 0x699d, 0x4fdd, [0xa7, 0xc3, 0x25, 0x46, 0x68, 0x1b, 0xf6, 0x3b]);
+
+#[repr(C)]
+pub struct EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL {
+    pub GetNetworks: EFI_WIRELESS_MAC_CONNECTION_II_GET_NETWORKS,
+    pub ConnectNetwork: EFI_WIRELESS_MAC_CONNECTION_II_CONNECT_NETWORK,
+    pub DisconnectNetwork: EFI_WIRELESS_MAC_CONNECTION_II_DISCONNECT_NETWORK,
+}
+
+debug_as_table!(EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL);
+
+pub type EFI_WIRELESS_MAC_CONNECTION_II_GET_NETWORKS = extern "efiapi" fn(
+    This: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL,
+    Token: *mut EFI_80211_GET_NETWORKS_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_WIRELESS_MAC_CONNECTION_II_CONNECT_NETWORK = extern "efiapi" fn(
+    This: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL,
+    Token: *mut EFI_80211_CONNECT_NETWORK_TOKEN
+) -> EFI_STATUS;
+
+pub type EFI_WIRELESS_MAC_CONNECTION_II_DISCONNECT_NETWORK = extern "efiapi" fn(
+    This: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL,
+    Token: *mut EFI_80211_DISCONNECT_NETWORK_TOKEN
+) -> EFI_STATUS;
+
+pub const EFI_MAX_SSID_LEN: usize = 32;
+
+// EFI_80211_BSS_TYPE
+#[allow(non_upper_case_globals)]
+pub const IeeeInfrastructureBSS: UINT32 = 0;
+#[allow(non_upper_case_globals)]
+pub const IeeeIndependentBSS: UINT32 = 1;
+#[allow(non_upper_case_globals)]
+pub const IeeeMeshBSS: UINT32 = 2;
+#[allow(non_upper_case_globals)]
+pub const IeeeAnyBss: UINT32 = 3;
+
+// EFI_80211_CONNECT_NETWORK_RESULT_CODE
+#[allow(non_upper_case_globals)]
+pub const ConnectSuccess: UINT32 = 0;
+#[allow(non_upper_case_globals)]
+pub const ConnectRefused: UINT32 = 1;
+#[allow(non_upper_case_globals)]
+pub const ConnectFailed: UINT32 = 2;
+#[allow(non_upper_case_globals)]
+pub const ConnectFailureTimeout: UINT32 = 3;
+#[allow(non_upper_case_globals)]
+pub const ConnectFailedReasonUnspecified: UINT32 = 4;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_80211_SSID {
+    pub SSIdLen: UINT8,
+    pub SSId: [UINT8; EFI_MAX_SSID_LEN],
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct EFI_80211_SUITE_SELECTOR {
+    pub Oui: [UINT8; 3],
+    pub SuiteType: UINT8,
+}
+
+// The lists in this and EFI_80211_CIPHER_SUITE_SELECTOR run on past the one element declared
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_AKM_SUITE_SELECTOR {
+    pub AKMSuiteCount: UINT16,
+    pub AKMSuiteList: [EFI_80211_SUITE_SELECTOR; 1],
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_CIPHER_SUITE_SELECTOR {
+    pub CipherSuiteCount: UINT16,
+    pub CipherSuiteList: [EFI_80211_SUITE_SELECTOR; 1],
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_NETWORK {
+    pub BSSType: UINT32,
+    pub SSId: EFI_80211_SSID,
+    pub AKMSuite: *mut EFI_80211_AKM_SUITE_SELECTOR,
+    pub CipherSuite: *mut EFI_80211_CIPHER_SUITE_SELECTOR,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_NETWORK_DESCRIPTION {
+    pub Network: EFI_80211_NETWORK,
+    pub NetworkQuality: UINT8,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_GET_NETWORKS_DATA {
+    pub NumOfSSID: UINT32,
+    pub SSIDList: [EFI_80211_SSID; 1],
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_GET_NETWORKS_RESULT {
+    pub NumOfNetworkDesc: UINT8,
+    pub NetworkDesc: [EFI_80211_NETWORK_DESCRIPTION; 1],
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_GET_NETWORKS_TOKEN {
+    pub Event: EFI_EVENT,
+    pub Status: EFI_STATUS,
+    pub Data: *mut EFI_80211_GET_NETWORKS_DATA,
+    pub Result: *mut EFI_80211_GET_NETWORKS_RESULT,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_CONNECT_NETWORK_DATA {
+    pub Network: *mut EFI_80211_NETWORK,
+    pub FailureTimeout: UINT32,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_CONNECT_NETWORK_TOKEN {
+    pub Event: EFI_EVENT,
+    pub Status: EFI_STATUS,
+    pub Data: *mut EFI_80211_CONNECT_NETWORK_DATA,
+    pub ResultCode: UINT32,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EFI_80211_DISCONNECT_NETWORK_TOKEN {
+    pub Event: EFI_EVENT,
+    pub Status: EFI_STATUS,
+}
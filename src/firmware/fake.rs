@@ -0,0 +1,318 @@
+// An in-memory stand-in for firmware: variables in a fake NVRAM, files on a RAM disk, a loopback TCP network,
+// a console that records what's written to it and a clock that only moves when someone stalls.
+// Everything is single threaded and nothing ever blocks so tests using it are deterministic.
+
+use super::{Firmware, Connection, set_firmware};
+use ffi::EFI_GUID;
+use io::{self, Read, Write};
+use net::{SocketAddr, SocketAddrV4, Ipv4Addr};
+use {Result, EfiErrorKind};
+use alloc::{boxed::Box, collections::{BTreeMap, VecDeque}, rc::Rc, string::{String, ToString}, vec::Vec};
+use core::{cell::{Cell, RefCell}, fmt::{self, Write as FmtWrite}, time::Duration};
+
+// Ephemeral ports handed out to the client end of loopback connections
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+struct Variable {
+    name: String,
+    vendor: EFI_GUID,
+    attributes: u32,
+    data: Vec<u8>,
+}
+
+type Backlog = Rc<RefCell<VecDeque<FakeConnection>>>;
+
+pub struct FakeFirmware {
+    variables: RefCell<Vec<Variable>>,
+    files: RefCell<BTreeMap<String, Vec<u8>>>,
+    listeners: RefCell<Vec<(SocketAddr, Backlog)>>,
+    next_port: Cell<u16>,
+    console: RefCell<String>,
+    elapsed: Cell<Duration>,
+}
+
+impl FakeFirmware {
+    pub fn new() -> Self {
+        Self {
+            variables: RefCell::new(Vec::new()),
+            files: RefCell::new(BTreeMap::new()),
+            listeners: RefCell::new(Vec::new()),
+            next_port: Cell::new(FIRST_EPHEMERAL_PORT),
+            console: RefCell::new(String::new()),
+            elapsed: Cell::new(Duration::from_secs(0)),
+        }
+    }
+
+    /// Makes this the firmware used by everything going through firmware(). Lives for the rest of the program
+    pub fn install(self) -> &'static FakeFirmware {
+        let fake: &'static FakeFirmware = Box::leak(Box::new(self));
+        set_firmware(fake);
+        fake
+    }
+
+    /// Puts a file on the RAM disk
+    pub fn add_file(&self, path: &str, data: &[u8]) {
+        self.files.borrow_mut().insert(normalize(path), data.to_vec());
+    }
+
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.borrow().get(&normalize(path)).cloned()
+    }
+
+    /// Paths of all the files on the RAM disk
+    pub fn files(&self) -> Vec<String> {
+        self.files.borrow().keys().cloned().collect()
+    }
+
+    /// A variable's attributes and data
+    pub fn variable(&self, name: &str, vendor: &EFI_GUID) -> Option<(u32, Vec<u8>)> {
+        self.variables.borrow().iter()
+            .find(|v| v.name == name && v.vendor == *vendor)
+            .map(|v| (v.attributes, v.data.clone()))
+    }
+
+    /// Starts accepting loopback connections to addr
+    pub fn listen(&self, addr: SocketAddr) -> Result<FakeListener> {
+        let mut listeners = self.listeners.borrow_mut();
+        listeners.retain(|&(_, ref backlog)| Rc::strong_count(backlog) > 1); // Forget the ones whose FakeListener is gone
+        if listeners.iter().any(|&(a, _)| a == addr) {
+            return Err(EfiErrorKind::AccessDenied.into());
+        }
+
+        let backlog = Rc::new(RefCell::new(VecDeque::new()));
+        listeners.push((addr, backlog.clone()));
+        Ok(FakeListener { addr, backlog })
+    }
+
+    /// Everything written to the console so far
+    pub fn console_output(&self) -> String {
+        self.console.borrow().clone()
+    }
+
+    /// Total time spent stalling
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+}
+
+impl Default for FakeFirmware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Firmware for FakeFirmware {
+    fn get_variable(&self, name: &str, vendor: &EFI_GUID) -> Result<Vec<u8>> {
+        self.variable(name, vendor).map(|(_, data)| data).ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    fn set_variable(&self, name: &str, vendor: &EFI_GUID, attributes: u32, data: &[u8]) -> Result<()> {
+        let mut variables = self.variables.borrow_mut();
+        let existing = variables.iter().position(|v| v.name == name && v.vendor == *vendor);
+        match existing {
+            Some(index) if data.is_empty() => { variables.remove(index); }
+            Some(index) => {
+                variables[index].attributes = attributes;
+                variables[index].data = data.to_vec();
+            }
+            None if data.is_empty() => return Err(EfiErrorKind::NotFound.into()),
+            None => variables.push(Variable { name: name.to_string(), vendor: *vendor, attributes, data: data.to_vec() }),
+        }
+        Ok(())
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.file(path).ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.add_file(path, data);
+        Ok(())
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Result<Box<dyn Connection>> {
+        let backlog = self.listeners.borrow().iter()
+            .find(|&&(a, ref backlog)| a == addr && Rc::strong_count(backlog) > 1)
+            .map(|&(_, ref backlog)| backlog.clone())
+            .ok_or_else(|| ::EfiError::from(EfiErrorKind::ConnectionRefused))?;
+
+        let port = self.next_port.get();
+        self.next_port.set(port.checked_add(1).unwrap_or(FIRST_EPHEMERAL_PORT));
+        let local_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+
+        let (client, server) = FakeConnection::pair(local_addr, addr);
+        backlog.borrow_mut().push_back(server);
+        Ok(Box::new(client))
+    }
+
+    fn write_console(&self, args: fmt::Arguments) -> Result<()> {
+        self.console.borrow_mut().write_fmt(args).map_err(|_| EfiErrorKind::DeviceError.into())
+    }
+
+    fn stall(&self, dur: Duration) -> Result<()> {
+        self.elapsed.set(self.elapsed.get() + dur);
+        Ok(())
+    }
+}
+
+// Paths are compared with forward slashes and no leading separator
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/").trim_start_matches('/').to_string()
+}
+
+/// The listening end of the fake network
+pub struct FakeListener {
+    addr: SocketAddr,
+    backlog: Backlog,
+}
+
+impl FakeListener {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The server end of the oldest connection not yet accepted, if any
+    pub fn accept(&self) -> Option<FakeConnection> {
+        self.backlog.borrow_mut().pop_front()
+    }
+}
+
+type Pipe = Rc<RefCell<VecDeque<u8>>>;
+
+/// One end of a loopback connection. Reads never block: they fail with WouldBlock when there is nothing to read
+/// yet and return 0 once the other end has been dropped
+pub struct FakeConnection {
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    rx: Pipe,
+    tx: Pipe,
+}
+
+impl FakeConnection {
+    fn pair(client_addr: SocketAddr, server_addr: SocketAddr) -> (Self, Self) {
+        let to_server: Pipe = Rc::new(RefCell::new(VecDeque::new()));
+        let to_client: Pipe = Rc::new(RefCell::new(VecDeque::new()));
+        let client = Self { local_addr: client_addr, peer_addr: server_addr, rx: to_client.clone(), tx: to_server.clone() };
+        let server = Self { local_addr: server_addr, peer_addr: client_addr, rx: to_server, tx: to_client };
+        (client, server)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Each pipe is shared by exactly two ends so if we hold the only reference the peer is gone
+    fn peer_closed(pipe: &Pipe) -> bool {
+        Rc::strong_count(pipe) == 1
+    }
+}
+
+impl Read for FakeConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut rx = self.rx.borrow_mut();
+        if rx.is_empty() {
+            return if Self::peer_closed(&self.rx) { Ok(0) } else { Err(io::Error::new(io::ErrorKind::WouldBlock, "no data from peer yet")) };
+        }
+
+        let mut read = 0;
+        while read < buf.len() {
+            match rx.pop_front() {
+                Some(b) => buf[read] = b,
+                None => break,
+            }
+            read += 1;
+        }
+        Ok(read)
+    }
+}
+
+impl Write for FakeConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if Self::peer_closed(&self.tx) {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "peer closed the connection"));
+        }
+
+        self.tx.borrow_mut().extend(buf.iter().cloned());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for FakeConnection {
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VENDOR: EFI_GUID = EFI_GUID(0x5c1b7a3e, 0x21d4, 0x4f0b, [0x8a, 0x6e, 0x93, 0x10, 0x4d, 0xb2, 0x7c, 0x05]);
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), port))
+    }
+
+    #[test]
+    fn variables_round_trip_and_delete() {
+        let fw = FakeFirmware::new();
+        fw.set_variable("BootNext", &VENDOR, 7, &[1, 0]).unwrap();
+        assert_eq!(fw.get_variable("BootNext", &VENDOR).unwrap(), vec![1, 0]);
+        assert_eq!(fw.variable("BootNext", &VENDOR), Some((7, vec![1, 0])));
+
+        fw.set_variable("BootNext", &VENDOR, 7, &[]).unwrap();
+        assert!(fw.get_variable("BootNext", &VENDOR).is_err());
+    }
+
+    #[test]
+    fn files_ignore_separator_style() {
+        let fw = FakeFirmware::new();
+        fw.write_file("\\EFI\\BOOT\\grub.cfg", b"set timeout=5").unwrap();
+        assert_eq!(fw.read_file("/EFI/BOOT/grub.cfg").unwrap(), b"set timeout=5".to_vec());
+        assert!(fw.read_file("EFI/BOOT/missing").is_err());
+    }
+
+    #[test]
+    fn loopback_connection_carries_data_both_ways() {
+        let fw = FakeFirmware::new();
+        let listener = fw.listen(addr(80)).unwrap();
+        let mut client = fw.connect(addr(80)).unwrap();
+        let mut server = listener.accept().unwrap();
+        assert_eq!(server.peer_addr().unwrap().port(), FIRST_EPHEMERAL_PORT);
+
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(server.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"ping");
+        assert_eq!(server.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        server.write_all(b"pong").unwrap();
+        drop(server);
+        assert_eq!(client.read(&mut buf).unwrap(), 4);
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn connecting_without_a_listener_is_refused() {
+        let fw = FakeFirmware::new();
+        assert_eq!(fw.connect(addr(22)).err().unwrap().kind(), EfiErrorKind::ConnectionRefused);
+
+        let listener = fw.listen(addr(22)).unwrap();
+        drop(listener);
+        assert!(fw.connect(addr(22)).is_err());
+    }
+
+    #[test]
+    fn console_and_clock_are_recorded() {
+        let fw = FakeFirmware::new();
+        fw.write_console(format_args!("hello {}", 42)).unwrap();
+        fw.stall(Duration::from_millis(5)).unwrap();
+        fw.stall(Duration::from_millis(7)).unwrap();
+        assert_eq!(fw.console_output(), "hello 42");
+        assert_eq!(fw.elapsed(), Duration::from_millis(12));
+    }
+}
@@ -0,0 +1,201 @@
+// What the firmware is and what it can do.
+//
+// Optional protocols are optional in practice too: plenty of shipping firmware has no HTTP boot, no TLS, no TPM or no
+// RNG, and finding out by getting NotFound halfway through a download or a measurement makes for a bad error. features()
+// looks for them all up front so an application can pick another way or say plainly what's missing.
+
+use boot_services::locate_handles;
+use ffi::{
+    ata_pass_thru::EFI_ATA_PASS_THRU_PROTOCOL_GUID,
+    graphics::EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID,
+    http::EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID,
+    memory_attribute::EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID,
+    nvme_pass_thru::EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID,
+    pxebc::EFI_PXE_BASE_CODE_PROTOCOL_GUID,
+    rng::EFI_RNG_PROTOCOL_GUID,
+    shell::EFI_SHELL_PROTOCOL_GUID,
+    tcg2::EFI_TCG2_PROTOCOL_GUID,
+    tcp4::EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID,
+    timestamp::EFI_TIMESTAMP_PROTOCOL_GUID,
+    tls::EFI_TLS_SERVICE_BINDING_PROTOCOL_GUID,
+    EFI_GUID,
+};
+use system_table;
+use alloc::string::String;
+use core::{fmt, ops::BitOr};
+
+/// A UEFI specification revision as the system table has it. Minor versions are written the way the spec does, so
+/// 2.3.1 is minor 31 and 2.7 is minor 70
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Revision {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Revision {
+    pub const V2_0: Revision = Revision::new(2, 0);
+    pub const V2_3_1: Revision = Revision::new(2, 31);
+    pub const V2_5: Revision = Revision::new(2, 50);
+    pub const V2_7: Revision = Revision::new(2, 70);
+    pub const V2_8: Revision = Revision::new(2, 80);
+    pub const V2_9: Revision = Revision::new(2, 90);
+    pub const V2_10: Revision = Revision::new(2, 100);
+
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Revision { major, minor }
+    }
+
+    /// From a table header's Revision field
+    pub fn from_raw(raw: u32) -> Self {
+        Revision { major: (raw >> 16) as u16, minor: raw as u16 }
+    }
+}
+
+impl fmt::Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor / 10)?;
+        let patch = self.minor % 10;
+        if patch != 0 {
+            write!(f, ".{}", patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// The UEFI revision the firmware implements
+pub fn revision() -> Revision {
+    Revision::from_raw(system_table().Hdr.Revision)
+}
+
+/// Who made the firmware, e.g. "EDK II" or "American Megatrends"
+pub fn vendor() -> String {
+    let st = system_table();
+    if st.FirmwareVendor.is_null() {
+        String::new()
+    } else {
+        String::from_utf16_lossy(unsafe { ::utils::as_slice(st.FirmwareVendor) })
+    }
+}
+
+/// The firmware's own revision, whose meaning is up to the vendor
+pub fn vendor_revision() -> u32 {
+    system_table().FirmwareRevision
+}
+
+/// Optional protocols the firmware has
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Hash)]
+pub struct Features(pub u64);
+
+impl Features {
+    pub const TCP4: Features = Features(1 << 0);
+    pub const HTTP: Features = Features(1 << 1);
+    pub const TLS: Features = Features(1 << 2);
+    pub const PXE: Features = Features(1 << 3);
+    pub const RNG: Features = Features(1 << 4);
+    pub const TCG2: Features = Features(1 << 5);
+    pub const NVME_PASS_THRU: Features = Features(1 << 6);
+    pub const ATA_PASS_THRU: Features = Features(1 << 7);
+    pub const GRAPHICS_OUTPUT: Features = Features(1 << 8);
+    pub const MEMORY_ATTRIBUTE: Features = Features(1 << 9);
+    pub const TIMESTAMP: Features = Features(1 << 10);
+    pub const SHELL: Features = Features(1 << 11);
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Features) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Features) {
+        self.0 &= !other.0;
+    }
+
+    /// Which of `wanted` are missing
+    pub fn missing(&self, wanted: Features) -> Features {
+        Features(wanted.0 & !self.0)
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    fn bitor(self, other: Features) -> Features {
+        Features(self.0 | other.0)
+    }
+}
+
+// Each feature and the protocol that has to be installed somewhere for it. For network protocols that's the service
+// binding, which shows up once per NIC with the driver loaded
+const PROBES: &[(Features, &str, EFI_GUID)] = &[
+    (Features::TCP4, "TCP4", EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID),
+    (Features::HTTP, "HTTP", EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID),
+    (Features::TLS, "TLS", EFI_TLS_SERVICE_BINDING_PROTOCOL_GUID),
+    (Features::PXE, "PXE", EFI_PXE_BASE_CODE_PROTOCOL_GUID),
+    (Features::RNG, "RNG", EFI_RNG_PROTOCOL_GUID),
+    (Features::TCG2, "TCG2", EFI_TCG2_PROTOCOL_GUID),
+    (Features::NVME_PASS_THRU, "NVME_PASS_THRU", EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID),
+    (Features::ATA_PASS_THRU, "ATA_PASS_THRU", EFI_ATA_PASS_THRU_PROTOCOL_GUID),
+    (Features::GRAPHICS_OUTPUT, "GRAPHICS_OUTPUT", EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID),
+    (Features::MEMORY_ATTRIBUTE, "MEMORY_ATTRIBUTE", EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID),
+    (Features::TIMESTAMP, "TIMESTAMP", EFI_TIMESTAMP_PROTOCOL_GUID),
+    (Features::SHELL, "SHELL", EFI_SHELL_PROTOCOL_GUID),
+];
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for &(feature, name, _) in PROBES {
+            if self.contains(feature) {
+                write!(f, "{}{}", if first { "" } else { " | " }, name)?;
+                first = false;
+            }
+        }
+        if first {
+            write!(f, "(none)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks for each of the optional protocols. Drivers can still be loaded or connected afterwards, so it's worth asking
+/// again after doing that
+pub fn features() -> Features {
+    let mut features = Features::default();
+    for &(feature, _, ref guid) in PROBES {
+        match locate_handles(guid) {
+            Ok(ref handles) if !handles.is_empty() => features.insert(feature),
+            _ => (),
+        }
+    }
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_HANDLE, VOID};
+    use testing::mock;
+    use core::ptr;
+
+    #[test]
+    fn probes_for_protocols() {
+        mock::install();
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_TCG2_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, 0x1000 as *const VOID);
+        }
+        let found = features();
+        assert!(found.contains(Features::TCG2));
+        assert_eq!(Features::TCG2.missing(Features::TCG2 | Features::HTTP), Features::HTTP);
+        assert_eq!(format!("{}", Features::RNG | Features::TLS), "TLS | RNG");
+
+        assert_eq!(format!("{}", Revision::from_raw((2 << 16) | 31)), "2.3.1");
+        assert!(Revision::V2_10 > Revision::V2_9 && format!("{}", Revision::V2_10) == "2.10");
+    }
+}
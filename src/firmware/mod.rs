@@ -0,0 +1,68 @@
+// A seam between the crate and the firmware.
+//
+// Code that needs variables, files on the boot volume, TCP connections or the console can go through firmware()
+// instead of calling FFI pointers directly. By default that is the real firmware. Swap in a FakeFirmware with
+// set_firmware() and the same code runs deterministically on the host or in a VM with no network or disks.
+//
+// revision(), vendor() and features() say which firmware it is and which optional protocols it has.
+
+mod uefi;
+pub mod fake;
+mod features;
+
+pub use self::uefi::Uefi;
+pub use self::fake::{FakeFirmware, FakeListener, FakeConnection};
+pub use self::features::{Revision, Features, revision, vendor, vendor_revision, features};
+
+use ffi::EFI_GUID;
+use io::{Read, Write};
+use net::SocketAddr;
+use Result;
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt, time::Duration};
+
+/// The services the rest of the crate needs from firmware
+pub trait Firmware {
+    /// Reads a variable's data
+    fn get_variable(&self, name: &str, vendor: &EFI_GUID) -> Result<Vec<u8>>;
+
+    /// Writes a variable. Empty data deletes it
+    fn set_variable(&self, name: &str, vendor: &EFI_GUID, attributes: u32, data: &[u8]) -> Result<()>;
+
+    /// Reads a whole file from the volume we were loaded from or, under the shell, wherever the shell path leads.
+    /// Paths are separated by `/` or `\`
+    fn read_file(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Creates or replaces a file on the volume we were loaded from or, under the shell, wherever the shell path leads
+    fn write_file(&self, path: &str, data: &[u8]) -> Result<()>;
+
+    /// Opens a TCP connection
+    fn connect(&self, addr: SocketAddr) -> Result<Box<dyn Connection>>;
+
+    fn write_console(&self, args: fmt::Arguments) -> Result<()>;
+
+    fn stall(&self, dur: Duration) -> Result<()>;
+}
+
+/// A connected stream as returned by Firmware::connect()
+pub trait Connection: Read + Write {
+    fn peer_addr(&self) -> Result<SocketAddr>;
+}
+
+static UEFI: Uefi = Uefi;
+static mut FIRMWARE: Option<&'static dyn Firmware> = None;
+
+/// The firmware currently in use. The real one unless set_firmware() was called
+pub fn firmware() -> &'static dyn Firmware {
+    unsafe { FIRMWARE.unwrap_or(&UEFI) }
+}
+
+/// Routes everything that goes through firmware() to the given backend from now on
+pub fn set_firmware(firmware: &'static dyn Firmware) {
+    unsafe { FIRMWARE = Some(firmware) };
+}
+
+/// Goes back to using the real firmware
+pub fn reset_firmware() {
+    unsafe { FIRMWARE = None };
+}
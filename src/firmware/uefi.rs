@@ -0,0 +1,54 @@
+// The real firmware backend
+
+use super::{Firmware, Connection};
+use ffi::EFI_GUID;
+use fs;
+use io::Write;
+use net::{SocketAddr, TcpStream};
+use services::{BootServices, RuntimeServices};
+use {Result, EfiErrorKind, system_table, stdout};
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt, time::Duration};
+
+/// Firmware as in the actual UEFI implementation we're running on
+pub struct Uefi;
+
+impl Firmware for Uefi {
+    fn get_variable(&self, name: &str, vendor: &EFI_GUID) -> Result<Vec<u8>> {
+        runtime_services().get_variable(name, vendor).map(|(data, _)| data)
+    }
+
+    fn set_variable(&self, name: &str, vendor: &EFI_GUID, attributes: u32, data: &[u8]) -> Result<()> {
+        runtime_services().set_variable(name, vendor, attributes, data)
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        fs::default_fs()?.read(path)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        fs::default_fs()?.write(path, data)
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Result<Box<dyn Connection>> {
+        Ok(Box::new(TcpStream::connect(addr)?))
+    }
+
+    fn write_console(&self, args: fmt::Arguments) -> Result<()> {
+        stdout().write_fmt(args).map_err(|_| EfiErrorKind::DeviceError.into())
+    }
+
+    fn stall(&self, dur: Duration) -> Result<()> {
+        BootServices::new(unsafe { &*system_table().BootServices }).stall(dur)
+    }
+}
+
+impl Connection for TcpStream {
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+}
+
+fn runtime_services() -> RuntimeServices<'static> {
+    RuntimeServices::new(unsafe { &*system_table().RuntimeServices })
+}
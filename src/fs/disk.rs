@@ -0,0 +1,367 @@
+// The storage file systems sit on. Disk is byte addressed like EFI_DISK_IO_PROTOCOL, BlockDevice is block addressed
+// like EFI_BLOCK_IO_PROTOCOL. BlockDisk turns the latter into the former.
+
+use ffi::{
+    media::{EFI_BLOCK_IO_PROTOCOL, EFI_BLOCK_IO_PROTOCOL_GUID, EFI_DISK_IO_PROTOCOL, EFI_DISK_IO_PROTOCOL_GUID},
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_HANDLE,
+    VOID,
+};
+use dma::DmaBuffer;
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::{alloc::{alloc_zeroed, dealloc, Layout}, vec::Vec};
+use core::{mem, ptr, slice};
+
+/// Byte addressed storage
+pub trait Disk {
+    /// Size in bytes
+    fn size(&self) -> u64;
+
+    /// Fills `buf` with the bytes starting at `offset`
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()>;
+
+    fn write_at(&mut self, _offset: u64, _buf: &[u8]) -> Result<()> {
+        Err(EfiErrorKind::WriteProtected.into())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Block addressed storage
+pub trait BlockDevice {
+    /// Size of a block in bytes
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    /// Reads whole blocks starting at `lba`. `buf` must be a multiple of the block size
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()>;
+
+    fn write_blocks(&mut self, _lba: u64, _buf: &[u8]) -> Result<()> {
+        Err(EfiErrorKind::WriteProtected.into())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn check_range(offset: u64, len: usize, size: u64) -> Result<()> {
+    match offset.checked_add(len as u64) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(EfiErrorKind::EndOfMedia.into()),
+    }
+}
+
+/// Byte access to a block device. Partial blocks are read, modified and written back
+pub struct BlockDisk<B: BlockDevice> {
+    device: B,
+}
+
+impl<B: BlockDevice> BlockDisk<B> {
+    pub fn new(device: B) -> Self {
+        Self { device }
+    }
+
+    pub fn into_inner(self) -> B {
+        self.device
+    }
+
+    // Runs f over each block touched by the range with the block's number, the range within the block and the
+    // range within the caller's buffer
+    fn for_each_block<F: FnMut(&mut B, u64, usize, usize, usize) -> Result<()>>(&mut self, offset: u64, len: usize, mut f: F) -> Result<()> {
+        let block_size = self.device.block_size() as u64;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let lba = pos / block_size;
+            let in_block = (pos % block_size) as usize;
+            let count = ((block_size as usize) - in_block).min(len - done);
+            f(&mut self.device, lba, in_block, done, count)?;
+            done += count;
+        }
+        Ok(())
+    }
+}
+
+impl<B: BlockDevice> Disk for BlockDisk<B> {
+    fn size(&self) -> u64 {
+        self.device.block_size() as u64 * self.device.block_count()
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_range(offset, buf.len(), self.size())?;
+        let block_size = self.device.block_size();
+        let mut block = vec![0u8; block_size];
+        self.for_each_block(offset, buf.len(), |device, lba, in_block, done, count| {
+            if count == block_size {
+                return device.read_blocks(lba, &mut buf[done..done + count]);
+            }
+            device.read_blocks(lba, &mut block)?;
+            buf[done..done + count].copy_from_slice(&block[in_block..in_block + count]);
+            Ok(())
+        })
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        check_range(offset, buf.len(), self.size())?;
+        let block_size = self.device.block_size();
+        let mut block = vec![0u8; block_size];
+        self.for_each_block(offset, buf.len(), |device, lba, in_block, done, count| {
+            if count == block_size {
+                return device.write_blocks(lba, &buf[done..done + count]);
+            }
+            device.read_blocks(lba, &mut block)?;
+            block[in_block..in_block + count].copy_from_slice(&buf[done..done + count]);
+            device.write_blocks(lba, &block)
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.device.flush()
+    }
+}
+
+/// A disk image held in memory
+pub struct MemDisk(Vec<u8>);
+
+impl MemDisk {
+    pub fn new(data: Vec<u8>) -> Self {
+        MemDisk(data)
+    }
+
+    /// A zeroed disk of the given size
+    pub fn zeroed(size: usize) -> Self {
+        MemDisk(vec![0; size])
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Disk for MemDisk {
+    fn size(&self) -> u64 {
+        self.0.len() as u64
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_range(offset, buf.len(), self.size())?;
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        check_range(offset, buf.len(), self.size())?;
+        let offset = offset as usize;
+        self.0[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// A byte range of another disk, e.g. a partition
+pub struct Region<D: Disk> {
+    disk: D,
+    start: u64,
+    len: u64,
+}
+
+impl<D: Disk> Region<D> {
+    pub fn new(disk: D, start: u64, len: u64) -> Result<Self> {
+        check_range(start, 0, disk.size())?;
+        check_range(start + len, 0, disk.size())?;
+        Ok(Self { disk, start, len })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+}
+
+impl<D: Disk> Disk for Region<D> {
+    fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_range(offset, buf.len(), self.len)?;
+        self.disk.read_at(self.start + offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        check_range(offset, buf.len(), self.len)?;
+        self.disk.write_at(self.start + offset, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.disk.flush()
+    }
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &::ffi::EFI_GUID) -> Result<*const T> {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+    }
+    Ok(protocol)
+}
+
+/// A firmware EFI_BLOCK_IO_PROTOCOL device
+pub struct BlockIo {
+    protocol: *const EFI_BLOCK_IO_PROTOCOL,
+}
+
+impl BlockIo {
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let protocol = open_protocol::<EFI_BLOCK_IO_PROTOCOL>(handle, &EFI_BLOCK_IO_PROTOCOL_GUID)?;
+        if unsafe { (*(*protocol).Media).MediaPresent } == 0 {
+            return Err(EfiErrorKind::NoMedia.into());
+        }
+        Ok(Self { protocol })
+    }
+
+    pub fn media_id(&self) -> u32 {
+        unsafe { (*(*self.protocol).Media).MediaId }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        unsafe { (*(*self.protocol).Media).ReadOnly != 0 }
+    }
+
+    /// Whether this is a partition rather than a whole device
+    pub fn is_partition(&self) -> bool {
+        unsafe { (*(*self.protocol).Media).LogicalPartition != 0 }
+    }
+
+    fn io_align(&self) -> usize {
+        unsafe { (*(*self.protocol).Media).IoAlign as usize }
+    }
+
+    /// A buffer of `len` bytes with the alignment the device asks for, so reads and writes don't need a bounce buffer
+    pub fn buffer(&self, len: usize) -> Result<DmaBuffer> {
+        DmaBuffer::aligned(len, self.io_align())
+    }
+}
+
+// A heap buffer with the alignment BlockIo asks for, used when the caller's buffer doesn't have it
+pub(crate) struct Bounce {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Bounce {
+    pub(crate) fn new(len: usize, align: usize) -> Result<Self> {
+        let layout = Layout::from_size_align(len, align).map_err(|_| ::EfiError::from(EfiErrorKind::InvalidParameter))?;
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(EfiErrorKind::OutOfResources.into());
+        }
+        Ok(Self { ptr, layout })
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+}
+
+impl Drop for Bounce {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
+
+impl BlockDevice for BlockIo {
+    fn block_size(&self) -> usize {
+        unsafe { (*(*self.protocol).Media).BlockSize as usize }
+    }
+
+    fn block_count(&self) -> u64 {
+        unsafe { (*(*self.protocol).Media).LastBlock + 1 }
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let align = self.io_align();
+        if align > 1 && buf.as_ptr() as usize % align != 0 {
+            let mut bounce = Bounce::new(buf.len(), align)?;
+            self.read_blocks(lba, bounce.as_mut_slice())?;
+            buf.copy_from_slice(bounce.as_mut_slice());
+            return Ok(());
+        }
+
+        unsafe {
+            ret_on_err!(((*self.protocol).ReadBlocks)(self.protocol, self.media_id(), lba, buf.len(), buf.as_mut_ptr() as *mut VOID));
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        let align = self.io_align();
+        if align > 1 && buf.as_ptr() as usize % align != 0 {
+            let mut bounce = Bounce::new(buf.len(), align)?;
+            bounce.as_mut_slice().copy_from_slice(buf);
+            return self.write_blocks(lba, bounce.as_mut_slice());
+        }
+
+        unsafe {
+            ret_on_err!(((*self.protocol).WriteBlocks)(self.protocol, self.media_id(), lba, buf.len(), buf.as_ptr() as *const VOID));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).FlushBlocks)(self.protocol));
+        }
+        Ok(())
+    }
+}
+
+/// A firmware EFI_DISK_IO_PROTOCOL disk. Firmware binds DiskIo to every BlockIo device, including each partition
+pub struct DiskIo {
+    protocol: *const EFI_DISK_IO_PROTOCOL,
+    block_io: BlockIo,
+}
+
+impl DiskIo {
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let block_io = BlockIo::new(handle)?; // For the media id and size
+        let protocol = open_protocol::<EFI_DISK_IO_PROTOCOL>(handle, &EFI_DISK_IO_PROTOCOL_GUID)?;
+        Ok(Self { protocol, block_io })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.block_io.is_read_only()
+    }
+}
+
+impl Disk for DiskIo {
+    fn size(&self) -> u64 {
+        self.block_io.block_size() as u64 * self.block_io.block_count()
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).ReadDisk)(self.protocol, self.block_io.media_id(), offset, buf.len(), buf.as_mut_ptr() as *mut VOID));
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).WriteDisk)(self.protocol, self.block_io.media_id(), offset, buf.len(), buf.as_ptr() as *const VOID));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.block_io.flush()
+    }
+}
@@ -0,0 +1,315 @@
+// FAT directory entries. Each is 32 bytes: either an 8.3 short entry or one piece of a long file name (LFN) that
+// precedes the short entry it belongs to, last piece first
+
+use byteorder::{ByteOrder, LittleEndian};
+use alloc::{string::String, vec::Vec};
+
+pub(crate) const ENTRY_SIZE: usize = 32;
+
+pub(crate) const ATTR_READ_ONLY: u8 = 0x01;
+pub(crate) const ATTR_HIDDEN: u8 = 0x02;
+pub(crate) const ATTR_SYSTEM: u8 = 0x04;
+pub(crate) const ATTR_VOLUME_ID: u8 = 0x08;
+pub(crate) const ATTR_DIRECTORY: u8 = 0x10;
+pub(crate) const ATTR_ARCHIVE: u8 = 0x20;
+pub(crate) const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+pub(crate) const END_OF_DIR: u8 = 0x00;
+pub(crate) const DELETED: u8 = 0xE5;
+const KANJI_E5: u8 = 0x05; // A name really starting with 0xE5 is stored as 0x05
+
+pub(crate) const LFN_LAST: u8 = 0x40;
+pub(crate) const LFN_SEQUENCE_MASK: u8 = 0x1F;
+pub(crate) const LFN_CHARS_PER_ENTRY: usize = 13;
+
+// Where the 13 UCS-2 characters of an LFN entry live
+pub(crate) const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+pub(crate) const LFN_CHECKSUM: usize = 13;
+
+// Windows NT keeps all lower case 8.3 names as short entries with these flags instead of adding an LFN
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+/// A parsed directory entry along with where it lives on disk
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub name: String,
+    pub short_name: [u8; 11],
+    pub attr: u8,
+    pub first_cluster: u32,
+    pub size: u32,
+    // Disk offsets of this entry's LFN slots followed by its short entry
+    pub slots: Vec<u64>,
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// Disk offset of the short entry
+    pub fn offset(&self) -> u64 {
+        *self.slots.last().expect("every entry has a short entry")
+    }
+
+    /// Whether `name` refers to this entry. FAT names are case insensitive and files can always be reached by
+    /// their short name too
+    pub fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || short_name_to_string(&self.short_name, 0).eq_ignore_ascii_case(name)
+    }
+}
+
+pub(crate) fn checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &c| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c))
+}
+
+// "README  TXT" -> "README.TXT", honouring the NT lower case flags
+fn short_name_to_string(short_name: &[u8; 11], nt_flags: u8) -> String {
+    let mut base = short_name[..8].to_vec();
+    if base[0] == KANJI_E5 {
+        base[0] = DELETED;
+    }
+    let base = String::from_utf8_lossy(&base).trim_end_matches(' ').into();
+    let ext: String = String::from_utf8_lossy(&short_name[8..]).trim_end_matches(' ').into();
+
+    let base = if nt_flags & NT_LOWER_BASE != 0 { ascii_lowercase(base) } else { base };
+    let ext = if nt_flags & NT_LOWER_EXT != 0 { ascii_lowercase(ext) } else { ext };
+
+    if ext.is_empty() { base } else { format!("{}.{}", base, ext) }
+}
+
+fn ascii_lowercase(mut s: String) -> String {
+    s.make_ascii_lowercase();
+    s
+}
+
+// Pieces of a long name collected while walking towards its short entry
+struct PendingLfn {
+    chars: Vec<u16>,
+    checksum: u8,
+    next_sequence: u8,
+    slots: Vec<u64>,
+}
+
+/// Parses the raw slots of a directory, given as (disk offset, contents), into its entries. `.`, `..`, deleted
+/// entries and the volume label are left out
+pub(crate) fn parse_entries(slots: &[(u64, [u8; ENTRY_SIZE])]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut lfn: Option<PendingLfn> = None;
+
+    for &(offset, ref slot) in slots {
+        match slot[0] {
+            END_OF_DIR => break,
+            DELETED => {
+                lfn = None;
+                continue;
+            }
+            _ => {}
+        }
+
+        let attr = slot[11];
+        if attr & ATTR_LONG_NAME == ATTR_LONG_NAME {
+            let sequence = slot[0] & LFN_SEQUENCE_MASK;
+            if slot[0] & LFN_LAST != 0 {
+                lfn = Some(PendingLfn {
+                    chars: vec![0xFFFF; sequence as usize * LFN_CHARS_PER_ENTRY],
+                    checksum: slot[LFN_CHECKSUM],
+                    next_sequence: sequence,
+                    slots: Vec::new(),
+                });
+            }
+
+            // Pieces must count down to 1 with the same checksum or the name is an orphan left by some other OS
+            lfn = match lfn.take() {
+                Some(mut pending) if pending.next_sequence == sequence && sequence > 0 && pending.checksum == slot[LFN_CHECKSUM] => {
+                    let start = (sequence as usize - 1) * LFN_CHARS_PER_ENTRY;
+                    for (i, &at) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                        pending.chars[start + i] = LittleEndian::read_u16(&slot[at..at + 2]);
+                    }
+                    pending.next_sequence -= 1;
+                    pending.slots.push(offset);
+                    Some(pending)
+                }
+                _ => None,
+            };
+            continue;
+        }
+
+        if attr & ATTR_VOLUME_ID != 0 {
+            lfn = None;
+            continue;
+        }
+
+        let mut short_name = [0u8; 11];
+        short_name.copy_from_slice(&slot[..11]);
+        if &short_name == b".          " || &short_name == b"..         " {
+            lfn = None;
+            continue;
+        }
+
+        let mut entry_slots = Vec::new();
+        let name = match lfn.take() {
+            Some(pending) if pending.next_sequence == 0 && pending.checksum == checksum(&short_name) => {
+                entry_slots = pending.slots;
+                let len = pending.chars.iter().position(|&c| c == 0 || c == 0xFFFF).unwrap_or(pending.chars.len());
+                String::from_utf16_lossy(&pending.chars[..len])
+            }
+            _ => short_name_to_string(&short_name, slot[12]),
+        };
+        entry_slots.push(offset);
+
+        entries.push(Entry {
+            name,
+            short_name,
+            attr,
+            first_cluster: (LittleEndian::read_u16(&slot[20..22]) as u32) << 16 | LittleEndian::read_u16(&slot[26..28]) as u32,
+            size: LittleEndian::read_u32(&slot[28..32]),
+            slots: entry_slots,
+        });
+    }
+
+    entries
+}
+
+// Characters allowed in long names are anything but these and control characters
+const INVALID_LONG_NAME_CHARS: &str = "\"*/:<>?\\|";
+// Short names additionally can't have these, nor lower case
+const INVALID_SHORT_NAME_CHARS: &str = "+,.;=[] ";
+
+const MAX_LONG_NAME_LEN: usize = 255;
+
+/// Whether a name can be stored in a directory at all
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "." && name != ".."
+        && name.encode_utf16().count() <= MAX_LONG_NAME_LEN
+        && !name.chars().any(|c| (c as u32) < 0x20 || INVALID_LONG_NAME_CHARS.contains(c))
+        && !name.ends_with('.') && !name.ends_with(' ')
+}
+
+fn short_char(c: char) -> Option<u8> {
+    if c.is_ascii() && !c.is_ascii_control() && !INVALID_LONG_NAME_CHARS.contains(c) && !INVALID_SHORT_NAME_CHARS.contains(c) {
+        Some(c.to_ascii_uppercase() as u8)
+    } else {
+        None
+    }
+}
+
+// The name as an 8.3 short name if it already is a valid upper case one, e.g. "BOOTX64.EFI"
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = match name.rfind('.') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+
+    let mut short_name = [b' '; 11];
+    for (i, c) in base.chars().enumerate() {
+        short_name[i] = short_char(c).filter(|&b| b as char == c)?;
+    }
+    for (i, c) in ext.chars().enumerate() {
+        short_name[8 + i] = short_char(c).filter(|&b| b as char == c)?;
+    }
+    if short_name[0] == DELETED {
+        short_name[0] = KANJI_E5;
+    }
+    Some(short_name)
+}
+
+/// The short name for a new entry and whether it needs an LFN to go with it. Names that aren't already 8.3 get a
+/// "BASENA~N.EXT" alias like Windows makes, with N picked so as not to clash with `taken`
+pub(crate) fn short_name_for(name: &str, taken: &[[u8; 11]]) -> Option<([u8; 11], bool)> {
+    if let Some(short_name) = exact_short_name(name) {
+        if !taken.contains(&short_name) {
+            return Some((short_name, false));
+        }
+    }
+
+    // "readme.txt" can still be README.TXT as long as the LFN keeps the case
+    if let Some(short_name) = exact_short_name(&name.to_ascii_uppercase()) {
+        if !taken.contains(&short_name) {
+            return Some((short_name, true));
+        }
+    }
+
+    let name = name.trim_start_matches('.');
+    let (base, ext) = match name.rfind('.') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => (name, ""),
+    };
+    let squash = |s: &str| s.chars().filter(|&c| c != ' ' && c != '.').map(|c| short_char(c).unwrap_or(b'_')).collect::<Vec<_>>();
+    let mut base = squash(base);
+    let ext = squash(ext);
+    if base.is_empty() {
+        base.push(b'_');
+    }
+
+    for n in 1..1_000_000u32 {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+
+        let mut short_name = [b' '; 11];
+        short_name[..keep].copy_from_slice(&base[..keep]);
+        short_name[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        for (i, &b) in ext.iter().take(3).enumerate() {
+            short_name[8 + i] = b;
+        }
+        if short_name[0] == DELETED {
+            short_name[0] = KANJI_E5;
+        }
+
+        if !taken.contains(&short_name) {
+            return Some((short_name, true));
+        }
+    }
+
+    None
+}
+
+/// The slots for a new entry: its LFN pieces, if it needs any, in the order they go on disk followed by the
+/// short entry
+pub(crate) fn encode_entry(name: &str, short_name: &[u8; 11], needs_lfn: bool, attr: u8, first_cluster: u32, size: u32) -> Vec<[u8; ENTRY_SIZE]> {
+    let mut slots = Vec::new();
+
+    if needs_lfn {
+        let mut chars = name.encode_utf16().collect::<Vec<_>>();
+        if chars.len() % LFN_CHARS_PER_ENTRY != 0 {
+            chars.push(0); // Only terminated if it doesn't exactly fill the last piece
+        }
+        let pieces = (chars.len() + LFN_CHARS_PER_ENTRY - 1) / LFN_CHARS_PER_ENTRY;
+        chars.resize(pieces * LFN_CHARS_PER_ENTRY, 0xFFFF);
+
+        let sum = checksum(short_name);
+        for sequence in (1..=pieces).rev() {
+            let mut slot = [0u8; ENTRY_SIZE];
+            slot[0] = sequence as u8 | if sequence == pieces { LFN_LAST } else { 0 };
+            slot[11] = ATTR_LONG_NAME;
+            slot[LFN_CHECKSUM] = sum;
+            for (i, &at) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                LittleEndian::write_u16(&mut slot[at..at + 2], chars[(sequence - 1) * LFN_CHARS_PER_ENTRY + i]);
+            }
+            slots.push(slot);
+        }
+    }
+
+    slots.push(short_entry(short_name, attr, first_cluster, size));
+    slots
+}
+
+/// A bare short entry
+// TODO: set the creation and modification times once there's a clock that works without boot services too
+pub(crate) fn short_entry(short_name: &[u8; 11], attr: u8, first_cluster: u32, size: u32) -> [u8; ENTRY_SIZE] {
+    let mut slot = [0u8; ENTRY_SIZE];
+    slot[..11].copy_from_slice(short_name);
+    slot[11] = attr;
+    set_cluster_and_size(&mut slot, first_cluster, size);
+    slot
+}
+
+pub(crate) fn set_cluster_and_size(slot: &mut [u8; ENTRY_SIZE], first_cluster: u32, size: u32) {
+    LittleEndian::write_u16(&mut slot[20..22], (first_cluster >> 16) as u16);
+    LittleEndian::write_u16(&mut slot[26..28], first_cluster as u16);
+    LittleEndian::write_u32(&mut slot[28..32], size);
+}
@@ -0,0 +1,132 @@
+// Creating new FAT32 file systems, laid out the way Windows' format does it (see the cluster size table and FAT size
+// calculation in Microsoft's FAT specification)
+
+use super::{Fat, FatType, BOOT_SIGNATURE, FIRST_CLUSTER, FSINFO_LEAD_SIGNATURE, FSINFO_STRUCT_SIGNATURE, FSINFO_TRAIL_SIGNATURE, FSINFO_FREE_COUNT, FSINFO_NEXT_FREE};
+use super::dir::{ATTR_VOLUME_ID, ENTRY_SIZE, short_entry};
+use fs::Disk;
+use partition::new_guid;
+use {Result, EfiErrorKind};
+use byteorder::{ByteOrder, LittleEndian};
+
+const BYTES_PER_SECTOR: u32 = 512;
+const RESERVED_SECTORS: u32 = 32;
+const FAT_COUNT: u32 = 2;
+const FSINFO_SECTOR: u32 = 1;
+const BACKUP_BOOT_SECTOR: u32 = 6;
+const MEDIA_FIXED: u8 = 0xF8;
+
+// The largest value the FAT32 total sector count can hold
+const MAX_SECTORS: u64 = 0xFFFF_FFFF;
+
+const INVALID_LABEL_CHARS: &str = "\"*+,./:;<=>?[\\]|";
+
+/// Formats the disk with a new, empty FAT32 file system and mounts it. `label` is the volume label, at most 11
+/// characters. Disks smaller than around 33MiB are too small for FAT32 and fail with InvalidParameter
+pub fn format_fat32<D: Disk>(mut disk: D, label: &str) -> Result<Fat<D>> {
+    let label = volume_label(label)?;
+    let total_sectors = (disk.size() / BYTES_PER_SECTOR as u64).min(MAX_SECTORS) as u32;
+    let sectors_per_cluster = sectors_per_cluster(total_sectors);
+
+    // Slightly overestimates the FAT size, never underestimates it
+    let per_fat_sector = (256 * sectors_per_cluster + FAT_COUNT) / 2;
+    let fat_size = ((total_sectors.saturating_sub(RESERVED_SECTORS)) as u64 + per_fat_sector as u64 - 1) / per_fat_sector as u64;
+    let fat_size = fat_size as u32;
+    let first_data_sector = (RESERVED_SECTORS + FAT_COUNT * fat_size) as u64;
+    let cluster_count = ((total_sectors as u64).saturating_sub(first_data_sector) / sectors_per_cluster as u64) as u32;
+    if FatType::from_cluster_count(cluster_count) != FatType::Fat32 {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+
+    let sector = |n: u32| n as u64 * BYTES_PER_SECTOR as u64;
+    let zero = vec![0u8; 64 * 1024];
+    let zero_range = |disk: &mut D, mut offset: u64, end: u64| -> Result<()> {
+        while offset < end {
+            let len = (end - offset).min(zero.len() as u64) as usize;
+            disk.write_at(offset, &zero[..len])?;
+            offset += len as u64;
+        }
+        Ok(())
+    };
+
+    // Clear the reserved sectors, the FATs and the root directory's cluster
+    let root_cluster_end = first_data_sector + sectors_per_cluster as u64;
+    zero_range(&mut disk, 0, root_cluster_end * BYTES_PER_SECTOR as u64)?;
+
+    let mut bpb = [0u8; BYTES_PER_SECTOR as usize];
+    bpb[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]); // jmp to the (empty) boot code then nop
+    bpb[3..11].copy_from_slice(b"MSWIN4.1");
+    LittleEndian::write_u16(&mut bpb[11..13], BYTES_PER_SECTOR as u16);
+    bpb[13] = sectors_per_cluster as u8;
+    LittleEndian::write_u16(&mut bpb[14..16], RESERVED_SECTORS as u16);
+    bpb[16] = FAT_COUNT as u8;
+    bpb[21] = MEDIA_FIXED;
+    LittleEndian::write_u16(&mut bpb[24..26], 63); // Sectors per track and heads for the sake of INT 13h
+    LittleEndian::write_u16(&mut bpb[26..28], 255);
+    LittleEndian::write_u32(&mut bpb[32..36], total_sectors);
+    LittleEndian::write_u32(&mut bpb[36..40], fat_size);
+    LittleEndian::write_u32(&mut bpb[44..48], FIRST_CLUSTER);
+    LittleEndian::write_u16(&mut bpb[48..50], FSINFO_SECTOR as u16);
+    LittleEndian::write_u16(&mut bpb[50..52], BACKUP_BOOT_SECTOR as u16);
+    bpb[64] = 0x80; // Drive number
+    bpb[66] = 0x29; // Extended boot signature: the volume ID, label and type follow
+    LittleEndian::write_u32(&mut bpb[67..71], new_guid()?.0);
+    bpb[71..82].copy_from_slice(&label);
+    bpb[82..90].copy_from_slice(b"FAT32   ");
+    LittleEndian::write_u16(&mut bpb[510..512], BOOT_SIGNATURE);
+
+    // The root directory already has the first cluster
+    let mut fsinfo = [0u8; BYTES_PER_SECTOR as usize];
+    LittleEndian::write_u32(&mut fsinfo[0..4], FSINFO_LEAD_SIGNATURE);
+    LittleEndian::write_u32(&mut fsinfo[484..488], FSINFO_STRUCT_SIGNATURE);
+    LittleEndian::write_u32(&mut fsinfo[FSINFO_FREE_COUNT..FSINFO_FREE_COUNT + 4], cluster_count - 1);
+    LittleEndian::write_u32(&mut fsinfo[FSINFO_NEXT_FREE..FSINFO_NEXT_FREE + 4], FIRST_CLUSTER + 1);
+    LittleEndian::write_u32(&mut fsinfo[508..512], FSINFO_TRAIL_SIGNATURE);
+
+    for &at in [0, BACKUP_BOOT_SECTOR].iter() {
+        disk.write_at(sector(at), &bpb)?;
+        disk.write_at(sector(at + FSINFO_SECTOR), &fsinfo)?;
+    }
+
+    // The first two entries hold the media type and "clean" flags. The third ends the root directory's chain
+    let mut fat = [0u8; 12];
+    LittleEndian::write_u32(&mut fat[0..4], 0x0FFF_FF00 | MEDIA_FIXED as u32);
+    LittleEndian::write_u32(&mut fat[4..8], 0x0FFF_FFFF);
+    LittleEndian::write_u32(&mut fat[8..12], FatType::Fat32.end_of_chain_marker());
+    for copy in 0..FAT_COUNT {
+        disk.write_at(sector(RESERVED_SECTORS + copy * fat_size), &fat)?;
+    }
+
+    if &label != b"NO NAME    " {
+        disk.write_at(first_data_sector * BYTES_PER_SECTOR as u64, &short_entry(&label, ATTR_VOLUME_ID, 0, 0)[..ENTRY_SIZE])?;
+    }
+    disk.flush()?;
+
+    Fat::new(disk)
+}
+
+// Cluster sizes by volume size from Microsoft's table for 512 byte sectors
+fn sectors_per_cluster(total_sectors: u32) -> u32 {
+    match total_sectors {
+        0..=532_480 => 1, // Up to 260MiB
+        532_481..=16_777_216 => 8, // Up to 8GiB
+        16_777_217..=33_554_432 => 16, // Up to 16GiB
+        33_554_433..=67_108_864 => 32, // Up to 32GiB
+        _ => 64,
+    }
+}
+
+// Labels are stored like short names: uppercase and padded with spaces
+fn volume_label(label: &str) -> Result<[u8; 11]> {
+    if label.is_empty() {
+        return Ok(*b"NO NAME    ");
+    }
+    if label.len() > 11 || label.chars().any(|c| !c.is_ascii() || c < ' ' || INVALID_LABEL_CHARS.contains(c)) {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+
+    let mut name = [b' '; 11];
+    for (b, c) in name.iter_mut().zip(label.bytes()) {
+        *b = c.to_ascii_uppercase();
+    }
+    Ok(name)
+}
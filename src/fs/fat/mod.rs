@@ -0,0 +1,937 @@
+// FAT12/16/32.
+//
+// The FAT type is decided purely by the number of clusters, as the spec says, never by the label in the boot
+// sector. On FAT12/16 the root directory is a fixed region between the FATs and the data area which we refer to as
+// cluster 0, the same way `..` entries do. On FAT32 it is an ordinary cluster chain.
+//
+// Writes go straight to the disk: every FAT copy is updated as clusters are allocated and a file's directory entry
+// is rewritten whenever its size changes, so there is nothing to lose if the caller never closes anything.
+
+mod dir;
+mod format;
+
+pub use self::format::format_fat32;
+
+use self::dir::{Entry, ENTRY_SIZE, ATTR_READ_ONLY, ATTR_DIRECTORY, ATTR_ARCHIVE, DELETED, END_OF_DIR};
+use self::dir::{parse_entries, is_valid_name, short_name_for, encode_entry, short_entry, set_cluster_and_size};
+use super::{FileSystem, File, Dir, DirEntry, Metadata, Disk, components, split_parent, to_io_error, seek_position};
+use io::{self, Read, Write, Seek, SeekFrom};
+use {Result, EfiError, EfiErrorKind};
+use alloc::{boxed::Box, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
+use core::cell::{Cell, RefCell};
+
+const BOOT_SIGNATURE: u16 = 0xAA55;
+
+// Cluster numbers 0 and 1 are reserved so the first data cluster is 2
+const FIRST_CLUSTER: u32 = 2;
+const FREE_CLUSTER: u32 = 0;
+
+// FAT32 keeps a hint of the free cluster count and where to look for the next free cluster in the FSInfo sector
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// Directories can't have more entries than this
+const MAX_DIR_ENTRIES: usize = 65536;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    fn from_cluster_count(count: u32) -> Self {
+        if count < 4085 {
+            FatType::Fat12
+        } else if count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    // FAT entries at or above this mark the end of a chain
+    fn end_of_chain(self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFF8,
+            FatType::Fat16 => 0xFFF8,
+            FatType::Fat32 => 0x0FFF_FFF8,
+        }
+    }
+
+    // What we write to end a chain
+    fn end_of_chain_marker(self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+}
+
+/// A FAT file system
+pub struct Fat<D: Disk> {
+    disk: RefCell<D>,
+    fat_type: FatType,
+    bytes_per_sector: u32,
+    sectors_per_cluster: u32,
+    reserved_sectors: u32,
+    fat_count: u32,
+    fat_size: u32, // In sectors
+    root_entries: u32, // FAT12/16 only
+    first_data_sector: u32,
+    cluster_count: u32,
+    root_cluster: u32, // FAT32 only
+    fsinfo_sector: Option<u32>, // FAT32 only, and only if the sector is valid
+    free_count: Cell<u32>, // FSINFO_UNKNOWN if we don't know
+    next_free: Cell<u32>,
+    fat_cache: RefCell<Option<(u64, Vec<u8>)>>, // The last sector of the first FAT we read, by offset into the FAT
+}
+
+impl<D: Disk> Fat<D> {
+    /// Whether the disk looks like it has a FAT file system on it
+    pub fn probe(disk: &mut D) -> bool {
+        let mut boot_sector = [0u8; 512];
+        if disk.read_at(0, &mut boot_sector).is_err() {
+            return false;
+        }
+
+        let bytes_per_sector = LittleEndian::read_u16(&boot_sector[11..13]);
+        let sectors_per_cluster = boot_sector[13];
+        (boot_sector[0] == 0xEB || boot_sector[0] == 0xE9)
+            && LittleEndian::read_u16(&boot_sector[510..512]) == BOOT_SIGNATURE
+            && bytes_per_sector.is_power_of_two() && bytes_per_sector >= 512 && bytes_per_sector <= 4096
+            && sectors_per_cluster.is_power_of_two()
+            && boot_sector[16] != 0
+    }
+
+    pub fn new(mut disk: D) -> Result<Self> {
+        let mut bpb = [0u8; 512];
+        disk.read_at(0, &mut bpb)?;
+
+        let bytes_per_sector = LittleEndian::read_u16(&bpb[11..13]) as u32;
+        let sectors_per_cluster = bpb[13] as u32;
+        let reserved_sectors = LittleEndian::read_u16(&bpb[14..16]) as u32;
+        let fat_count = bpb[16] as u32;
+        let root_entries = LittleEndian::read_u16(&bpb[17..19]) as u32;
+        let total_sectors = match LittleEndian::read_u16(&bpb[19..21]) {
+            0 => LittleEndian::read_u32(&bpb[32..36]),
+            total => total as u32,
+        };
+        let fat_size = match LittleEndian::read_u16(&bpb[22..24]) {
+            0 => LittleEndian::read_u32(&bpb[36..40]),
+            size => size as u32,
+        };
+
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 || fat_count == 0 || fat_size == 0 {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+
+        let root_dir_sectors = (root_entries * ENTRY_SIZE as u32 + bytes_per_sector - 1) / bytes_per_sector;
+        let first_data_sector = reserved_sectors + fat_count * fat_size + root_dir_sectors;
+        let data_sectors = total_sectors.checked_sub(first_data_sector).ok_or_else(|| EfiError::from(EfiErrorKind::VolumeCorrupted))?;
+        let cluster_count = data_sectors / sectors_per_cluster;
+        let fat_type = FatType::from_cluster_count(cluster_count);
+
+        let mut root_cluster = 0;
+        let mut fsinfo_sector = None;
+        let mut free_count = FSINFO_UNKNOWN;
+        let mut next_free = FIRST_CLUSTER;
+        if fat_type == FatType::Fat32 {
+            root_cluster = LittleEndian::read_u32(&bpb[44..48]);
+
+            let sector = LittleEndian::read_u16(&bpb[48..50]) as u32;
+            if sector != 0 && sector < reserved_sectors {
+                let mut fsinfo = [0u8; 512];
+                disk.read_at(sector as u64 * bytes_per_sector as u64, &mut fsinfo)?;
+                if LittleEndian::read_u32(&fsinfo[0..4]) == FSINFO_LEAD_SIGNATURE
+                    && LittleEndian::read_u32(&fsinfo[484..488]) == FSINFO_STRUCT_SIGNATURE
+                    && LittleEndian::read_u32(&fsinfo[508..512]) == FSINFO_TRAIL_SIGNATURE {
+                    fsinfo_sector = Some(sector);
+
+                    // Both are only hints and may be garbage
+                    let count = LittleEndian::read_u32(&fsinfo[FSINFO_FREE_COUNT..FSINFO_FREE_COUNT + 4]);
+                    if count <= cluster_count {
+                        free_count = count;
+                    }
+                    let next = LittleEndian::read_u32(&fsinfo[FSINFO_NEXT_FREE..FSINFO_NEXT_FREE + 4]);
+                    if next >= FIRST_CLUSTER && next < cluster_count + FIRST_CLUSTER {
+                        next_free = next;
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            disk: RefCell::new(disk),
+            fat_type,
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            fat_size,
+            root_entries,
+            first_data_sector,
+            cluster_count,
+            root_cluster,
+            fsinfo_sector,
+            free_count: Cell::new(free_count),
+            next_free: Cell::new(next_free),
+            fat_cache: RefCell::new(None),
+        })
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk.into_inner()
+    }
+
+    fn cluster_size(&self) -> u64 {
+        self.bytes_per_sector as u64 * self.sectors_per_cluster as u64
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        let sector = self.first_data_sector as u64 + (cluster - FIRST_CLUSTER) as u64 * self.sectors_per_cluster as u64;
+        sector * self.bytes_per_sector as u64
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= FIRST_CLUSTER && cluster < self.cluster_count + FIRST_CLUSTER
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        self.disk.borrow_mut().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<()> {
+        self.disk.borrow_mut().write_at(offset, buf)
+    }
+
+    fn fat_start(&self, copy: u32) -> u64 {
+        (self.reserved_sectors + copy * self.fat_size) as u64 * self.bytes_per_sector as u64
+    }
+
+    // Reads bytes of the first FAT. Walking a chain reads the same sector over and over so keep the last one around
+    fn read_fat(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let sector_size = self.bytes_per_sector as u64;
+        let sector = offset / sector_size * sector_size;
+        if offset + buf.len() as u64 > sector + sector_size {
+            // A FAT12 entry straddling two sectors
+            return self.read_at(self.fat_start(0) + offset, buf);
+        }
+
+        let mut cache = self.fat_cache.borrow_mut();
+        let cached = match *cache {
+            Some((cached_sector, _)) => cached_sector == sector,
+            None => false,
+        };
+        if !cached {
+            let mut data = vec![0u8; sector_size as usize];
+            self.read_at(self.fat_start(0) + sector, &mut data)?;
+            *cache = Some((sector, data));
+        }
+
+        let data = &cache.as_ref().expect("just filled").1;
+        let start = (offset - sector) as usize;
+        buf.copy_from_slice(&data[start..start + buf.len()]);
+        Ok(())
+    }
+
+    // Writes bytes to every copy of the FAT
+    fn write_fat(&self, offset: u64, buf: &[u8]) -> Result<()> {
+        for copy in 0..self.fat_count {
+            self.write_at(self.fat_start(copy) + offset, buf)?;
+        }
+
+        let mut cache = self.fat_cache.borrow_mut();
+        let sector_size = self.bytes_per_sector as u64;
+        let patched = match *cache {
+            Some((sector, ref mut data)) if offset >= sector && offset + buf.len() as u64 <= sector + sector_size => {
+                let start = (offset - sector) as usize;
+                data[start..start + buf.len()].copy_from_slice(buf);
+                true
+            }
+            Some((sector, _)) => offset + buf.len() as u64 <= sector || offset >= sector + sector_size, // Untouched
+            None => true,
+        };
+        if !patched {
+            *cache = None;
+        }
+        Ok(())
+    }
+
+    // The entry for a cluster in the first FAT
+    fn fat_entry(&self, cluster: u32) -> Result<u32> {
+        match self.fat_type {
+            FatType::Fat12 => {
+                // 12 bit entries are packed two to three bytes
+                let mut buf = [0u8; 2];
+                self.read_fat((cluster + cluster / 2) as u64, &mut buf)?;
+                let value = LittleEndian::read_u16(&buf) as u32;
+                Ok(if cluster & 1 == 0 { value & 0xFFF } else { value >> 4 })
+            }
+            FatType::Fat16 => {
+                let mut buf = [0u8; 2];
+                self.read_fat(cluster as u64 * 2, &mut buf)?;
+                Ok(LittleEndian::read_u16(&buf) as u32)
+            }
+            FatType::Fat32 => {
+                let mut buf = [0u8; 4];
+                self.read_fat(cluster as u64 * 4, &mut buf)?;
+                Ok(LittleEndian::read_u32(&buf) & 0x0FFF_FFFF) // The top 4 bits are reserved
+            }
+        }
+    }
+
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<()> {
+        match self.fat_type {
+            FatType::Fat12 => {
+                let offset = (cluster + cluster / 2) as u64;
+                let mut buf = [0u8; 2];
+                self.read_fat(offset, &mut buf)?;
+                let current = LittleEndian::read_u16(&buf);
+                let value = value as u16 & 0xFFF;
+                let packed = if cluster & 1 == 0 { (current & 0xF000) | value } else { (current & 0x000F) | (value << 4) };
+                LittleEndian::write_u16(&mut buf, packed);
+                self.write_fat(offset, &buf)
+            }
+            FatType::Fat16 => {
+                let mut buf = [0u8; 2];
+                LittleEndian::write_u16(&mut buf, value as u16);
+                self.write_fat(cluster as u64 * 2, &buf)
+            }
+            FatType::Fat32 => {
+                // Leave the reserved top 4 bits as they were
+                let mut buf = [0u8; 4];
+                self.read_fat(cluster as u64 * 4, &mut buf)?;
+                let value = (LittleEndian::read_u32(&buf) & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                LittleEndian::write_u32(&mut buf, value);
+                self.write_fat(cluster as u64 * 4, &buf)
+            }
+        }
+    }
+
+    // All the clusters of the chain starting at `first`
+    fn chain(&self, first: u32) -> Result<Vec<u32>> {
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+        loop {
+            // A chain longer than the volume has clusters must loop
+            if !self.is_valid_cluster(cluster) || clusters.len() as u32 >= self.cluster_count {
+                return Err(EfiErrorKind::VolumeCorrupted.into());
+            }
+            clusters.push(cluster);
+
+            let next = self.fat_entry(cluster)?;
+            if next >= self.fat_type.end_of_chain() {
+                return Ok(clusters);
+            }
+            cluster = next;
+        }
+    }
+
+    // Finds a free cluster and marks it as the end of a chain
+    fn allocate_cluster(&self) -> Result<u32> {
+        let start = self.next_free.get() - FIRST_CLUSTER;
+        for i in 0..self.cluster_count {
+            let cluster = FIRST_CLUSTER + (start + i) % self.cluster_count;
+            if self.fat_entry(cluster)? == FREE_CLUSTER {
+                self.set_fat_entry(cluster, self.fat_type.end_of_chain_marker())?;
+                self.next_free.set(if cluster + 1 < self.cluster_count + FIRST_CLUSTER { cluster + 1 } else { FIRST_CLUSTER });
+                if self.free_count.get() != FSINFO_UNKNOWN {
+                    self.free_count.set(self.free_count.get().saturating_sub(1));
+                }
+                return Ok(cluster);
+            }
+        }
+
+        Err(EfiErrorKind::VolumeFull.into())
+    }
+
+    fn free_cluster(&self, cluster: u32) -> Result<()> {
+        self.set_fat_entry(cluster, FREE_CLUSTER)?;
+        if self.free_count.get() != FSINFO_UNKNOWN {
+            self.free_count.set(self.free_count.get() + 1);
+        }
+        Ok(())
+    }
+
+    // Grows or shrinks a chain to `count` clusters. New clusters aren't zeroed
+    fn resize_chain(&self, clusters: &mut Vec<u32>, count: usize) -> Result<()> {
+        let result = if count < clusters.len() { self.shrink_chain(clusters, count) } else { self.grow_chain(clusters, count) };
+        self.write_fsinfo()?;
+        result
+    }
+
+    fn grow_chain(&self, clusters: &mut Vec<u32>, count: usize) -> Result<()> {
+        let original = clusters.len();
+        while clusters.len() < count {
+            let cluster = match self.allocate_cluster() {
+                Ok(cluster) => cluster,
+                Err(e) => {
+                    // Give back what we got so the volume doesn't leak clusters
+                    self.shrink_chain(clusters, original)?;
+                    return Err(e);
+                }
+            };
+            if let Some(&last) = clusters.last() {
+                self.set_fat_entry(last, cluster)?;
+            }
+            clusters.push(cluster);
+        }
+        Ok(())
+    }
+
+    fn shrink_chain(&self, clusters: &mut Vec<u32>, count: usize) -> Result<()> {
+        if count > 0 {
+            self.set_fat_entry(clusters[count - 1], self.fat_type.end_of_chain_marker())?;
+        }
+        for &cluster in &clusters[count..] {
+            self.free_cluster(cluster)?;
+        }
+        clusters.truncate(count);
+        Ok(())
+    }
+
+    fn write_fsinfo(&self) -> Result<()> {
+        let sector = match self.fsinfo_sector {
+            Some(sector) => sector,
+            None => return Ok(()),
+        };
+
+        let mut buf = [0u8; 8];
+        LittleEndian::write_u32(&mut buf[0..4], self.free_count.get());
+        LittleEndian::write_u32(&mut buf[4..8], self.next_free.get());
+        self.write_at(sector as u64 * self.bytes_per_sector as u64 + FSINFO_FREE_COUNT as u64, &buf)
+    }
+
+    fn zero_cluster(&self, cluster: u32) -> Result<()> {
+        self.write_at(self.cluster_offset(cluster), &vec![0u8; self.cluster_size() as usize])
+    }
+
+    fn root(&self) -> u32 {
+        if self.fat_type == FatType::Fat32 { self.root_cluster } else { 0 }
+    }
+
+    // The raw 32 byte slots of a directory along with their disk offsets
+    fn dir_slots(&self, dir: u32) -> Result<Vec<(u64, [u8; ENTRY_SIZE])>> {
+        let regions = if dir == 0 {
+            let root_start = self.fat_start(self.fat_count);
+            vec![(root_start, self.root_entries as usize * ENTRY_SIZE)]
+        } else {
+            let cluster_size = self.cluster_size() as usize;
+            self.chain(dir)?.into_iter().map(|cluster| (self.cluster_offset(cluster), cluster_size)).collect()
+        };
+
+        let mut slots = Vec::new();
+        for (start, len) in regions {
+            let mut data = vec![0u8; len];
+            self.read_at(start, &mut data)?;
+            for (i, chunk) in data.chunks(ENTRY_SIZE).enumerate() {
+                let mut slot = [0u8; ENTRY_SIZE];
+                slot.copy_from_slice(chunk);
+                slots.push((start + (i * ENTRY_SIZE) as u64, slot));
+            }
+        }
+        Ok(slots)
+    }
+
+    fn read_dir_entries(&self, dir: u32) -> Result<Vec<Entry>> {
+        Ok(parse_entries(&self.dir_slots(dir)?))
+    }
+
+    // The entry at a path. None for the root directory, which has no entry of its own
+    fn lookup(&self, path: &str) -> Result<Option<Entry>> {
+        let mut current: Option<Entry> = None;
+        for name in components(path) {
+            let dir = match current {
+                None => self.root(),
+                Some(ref entry) if entry.is_dir() => entry.first_cluster,
+                Some(_) => return Err(EfiErrorKind::NotFound.into()),
+            };
+
+            let entry = self.read_dir_entries(dir)?
+                .into_iter()
+                .find(|e| e.matches(name))
+                .ok_or_else(|| EfiError::from(EfiErrorKind::NotFound))?;
+            current = Some(entry);
+        }
+        Ok(current)
+    }
+
+    // The first cluster of the directory at a path
+    fn dir_cluster(&self, path: &str) -> Result<u32> {
+        match self.lookup(path)? {
+            None => Ok(self.root()),
+            Some(ref entry) if entry.is_dir() => Ok(entry.first_cluster),
+            Some(_) => Err(EfiErrorKind::NotFound.into()),
+        }
+    }
+
+    // Adds an entry, with an LFN if the name needs one, growing the directory if there isn't room
+    fn add_entry(&self, dir: u32, name: &str, attr: u8, first_cluster: u32, size: u32) -> Result<Entry> {
+        if !is_valid_name(name) {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+
+        let mut slots = self.dir_slots(dir)?;
+        let taken = parse_entries(&slots).into_iter().map(|e| e.short_name).collect::<Vec<_>>();
+        let (short_name, needs_lfn) = short_name_for(name, &taken).ok_or_else(|| EfiError::from(EfiErrorKind::OutOfResources))?;
+        let encoded = encode_entry(name, &short_name, needs_lfn, attr, first_cluster, size);
+
+        loop {
+            // The slots of an entry must be consecutive. Everything after the end marker is free too
+            let mut run = 0;
+            let mut found = None;
+            for (i, &(_, ref slot)) in slots.iter().enumerate() {
+                run = if slot[0] == END_OF_DIR || slot[0] == DELETED { run + 1 } else { 0 };
+                if run == encoded.len() {
+                    found = Some(i + 1 - run);
+                    break;
+                }
+            }
+
+            if let Some(start) = found {
+                let offsets = slots[start..start + encoded.len()].iter().map(|&(offset, _)| offset).collect::<Vec<_>>();
+                for (offset, slot) in offsets.iter().zip(encoded.iter()) {
+                    self.write_at(*offset, slot)?;
+                }
+                return Ok(Entry { name: name.into(), short_name, attr, first_cluster, size, slots: offsets });
+            }
+
+            // The FAT12/16 root directory can't grow
+            if dir == 0 || slots.len() >= MAX_DIR_ENTRIES {
+                return Err(EfiErrorKind::VolumeFull.into());
+            }
+
+            let mut clusters = self.chain(dir)?;
+            let count = clusters.len() + 1;
+            self.resize_chain(&mut clusters, count)?;
+            let cluster = *clusters.last().expect("just grew");
+            self.zero_cluster(cluster)?;
+
+            let start = self.cluster_offset(cluster);
+            for i in 0..self.cluster_size() / ENTRY_SIZE as u64 {
+                slots.push((start + i * ENTRY_SIZE as u64, [0u8; ENTRY_SIZE]));
+            }
+        }
+    }
+
+    // Writes an entry's first cluster and size back to its short entry
+    fn update_entry(&self, entry: &Entry) -> Result<()> {
+        let mut slot = [0u8; ENTRY_SIZE];
+        self.read_at(entry.offset(), &mut slot)?;
+        set_cluster_and_size(&mut slot, entry.first_cluster, entry.size);
+        if !entry.is_dir() {
+            slot[11] |= ATTR_ARCHIVE;
+        }
+        self.write_at(entry.offset(), &slot)
+    }
+
+    fn remove_entry(&self, entry: &Entry) -> Result<()> {
+        for &offset in &entry.slots {
+            self.write_at(offset, &[DELETED])?;
+        }
+        Ok(())
+    }
+
+    fn file<'a>(&'a self, entry: Entry) -> FatFile<'a, D> {
+        FatFile { fs: self, entry, pos: 0, clusters: None }
+    }
+}
+
+fn metadata_of(entry: &Entry) -> Metadata {
+    Metadata { is_dir: entry.is_dir(), len: entry.size as u64, read_only: entry.attr & ATTR_READ_ONLY != 0 }
+}
+
+impl<D: Disk> FileSystem for Fat<D> {
+    fn open<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        match self.lookup(path)? {
+            Some(entry) if !entry.is_dir() => Ok(Box::new(self.file(entry))),
+            _ => Err(EfiErrorKind::InvalidParameter.into()),
+        }
+    }
+
+    fn open_dir<'a>(&'a self, path: &str) -> Result<Box<dyn Dir + 'a>> {
+        let dir = match self.lookup(path)? {
+            None => self.root(),
+            Some(ref entry) if entry.is_dir() => entry.first_cluster,
+            Some(_) => return Err(EfiErrorKind::InvalidParameter.into()),
+        };
+        Ok(Box::new(FatDir { entries: self.read_dir_entries(dir)?.into_iter() }))
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(match self.lookup(path)? {
+            Some(entry) => metadata_of(&entry),
+            None => Metadata { is_dir: true, len: 0, read_only: false },
+        })
+    }
+
+    fn create<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        let (parent, name) = split_parent(path).ok_or_else(|| EfiError::from(EfiErrorKind::InvalidParameter))?;
+        let dir = self.dir_cluster(&parent)?;
+
+        match self.read_dir_entries(dir)?.into_iter().find(|e| e.matches(name)) {
+            Some(ref entry) if entry.is_dir() => Err(EfiErrorKind::InvalidParameter.into()),
+            Some(entry) => {
+                let mut file = self.file(entry);
+                file.set_len(0)?;
+                Ok(Box::new(file))
+            }
+            None => Ok(Box::new(self.file(self.add_entry(dir, name, ATTR_ARCHIVE, 0, 0)?))),
+        }
+    }
+
+    fn create_dir(&self, path: &str) -> Result<()> {
+        let (parent, name) = split_parent(path).ok_or_else(|| EfiError::from(EfiErrorKind::InvalidParameter))?;
+        let dir = self.dir_cluster(&parent)?;
+        if self.read_dir_entries(dir)?.iter().any(|e| e.matches(name)) {
+            return Err(EfiErrorKind::AccessDenied.into());
+        }
+
+        let mut clusters = Vec::new();
+        self.resize_chain(&mut clusters, 1)?;
+        let cluster = clusters[0];
+
+        // `..` points at cluster 0 when the parent is the root, even on FAT32
+        let parent_cluster = if dir == self.root() { 0 } else { dir };
+        let dot = short_entry(b".          ", ATTR_DIRECTORY, cluster, 0);
+        let dot_dot = short_entry(b"..         ", ATTR_DIRECTORY, parent_cluster, 0);
+        let result = self.zero_cluster(cluster)
+            .and_then(|_| self.write_at(self.cluster_offset(cluster), &dot))
+            .and_then(|_| self.write_at(self.cluster_offset(cluster) + ENTRY_SIZE as u64, &dot_dot))
+            .and_then(|_| self.add_entry(dir, name, ATTR_DIRECTORY, cluster, 0));
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.resize_chain(&mut clusters, 0)?;
+                Err(e)
+            }
+        }
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        let entry = self.lookup(path)?.ok_or_else(|| EfiError::from(EfiErrorKind::AccessDenied))?; // The root
+        if entry.attr & ATTR_READ_ONLY != 0 {
+            return Err(EfiErrorKind::AccessDenied.into());
+        }
+        if entry.is_dir() && !self.read_dir_entries(entry.first_cluster)?.is_empty() {
+            return Err(EfiErrorKind::AccessDenied.into());
+        }
+
+        self.remove_entry(&entry)?;
+        if entry.first_cluster != 0 {
+            let mut clusters = self.chain(entry.first_cluster)?;
+            self.resize_chain(&mut clusters, 0)?;
+        }
+        Ok(())
+    }
+}
+
+// Files on FAT are at most 4GiB - 1 since the size field is 32 bits
+const MAX_FILE_SIZE: u64 = 0xFFFF_FFFF;
+
+struct FatFile<'a, D: Disk> {
+    fs: &'a Fat<D>,
+    entry: Entry,
+    pos: u64,
+    clusters: Option<Vec<u32>>, // The chain, looked up on first use
+}
+
+impl<'a, D: Disk> FatFile<'a, D> {
+    fn clusters(&mut self) -> Result<&mut Vec<u32>> {
+        if self.clusters.is_none() {
+            // Empty files have no clusters at all
+            let first = self.entry.first_cluster;
+            self.clusters = Some(if first == 0 { Vec::new() } else { self.fs.chain(first)? });
+        }
+        Ok(self.clusters.as_mut().unwrap())
+    }
+
+    // The disk offset of a position in the file and how many bytes from there are in the same cluster
+    fn locate(&mut self, pos: u64) -> Result<(u64, u64)> {
+        let fs = self.fs;
+        let cluster_size = fs.cluster_size();
+        let cluster = *self.clusters()?.get((pos / cluster_size) as usize).ok_or_else(|| EfiError::from(EfiErrorKind::VolumeCorrupted))?;
+        Ok((fs.cluster_offset(cluster) + pos % cluster_size, cluster_size - pos % cluster_size))
+    }
+
+    // Resizes the file. When growing, the bytes between the old end and `zero_until` are zeroed so that whatever
+    // was on the disk before doesn't show up in the file. Callers about to write the rest don't need it zeroed
+    fn resize(&mut self, len: u64, zero_until: u64) -> Result<()> {
+        if len > MAX_FILE_SIZE {
+            return Err(EfiErrorKind::VolumeFull.into());
+        }
+
+        let fs = self.fs;
+        let old_len = self.len();
+        let cluster_size = fs.cluster_size();
+        let count = ((len + cluster_size - 1) / cluster_size) as usize;
+        fs.resize_chain(self.clusters()?, count)?;
+
+        self.entry.first_cluster = self.clusters()?.first().cloned().unwrap_or(0);
+        self.entry.size = len as u32;
+        fs.update_entry(&self.entry)?;
+
+        let mut pos = old_len;
+        let end = zero_until.min(len);
+        while pos < end {
+            let (offset, available) = self.locate(pos)?;
+            let count = available.min(end - pos);
+            fs.write_at(offset, &vec![0u8; count as usize])?;
+            pos += count;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, D: Disk> Read for FatFile<'a, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+
+        // One cluster at a time since consecutive clusters needn't be next to each other on disk
+        let (offset, available) = self.locate(self.pos).map_err(to_io_error)?;
+        let count = (buf.len() as u64).min(available).min(self.len() - self.pos) as usize;
+        self.fs.read_at(offset, &mut buf[..count]).map_err(to_io_error)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<'a, D: Disk> Write for FatFile<'a, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.entry.attr & ATTR_READ_ONLY != 0 {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+
+        let end = self.pos + buf.len() as u64;
+        if end > self.len() {
+            let pos = self.pos;
+            self.resize(end, pos).map_err(to_io_error)?;
+        }
+
+        let mut written = 0;
+        while written < buf.len() {
+            let (offset, available) = self.locate(self.pos).map_err(to_io_error)?;
+            let count = (available as usize).min(buf.len() - written);
+            self.fs.write_at(offset, &buf[written..written + count]).map_err(to_io_error)?;
+            written += count;
+            self.pos += count as u64;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.fs.disk.borrow_mut().flush().map_err(to_io_error)
+    }
+}
+
+impl<'a, D: Disk> Seek for FatFile<'a, D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(pos, self.pos, self.len())?;
+        Ok(self.pos)
+    }
+}
+
+impl<'a, D: Disk> File for FatFile<'a, D> {
+    fn len(&self) -> u64 {
+        self.entry.size as u64
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        if self.entry.attr & ATTR_READ_ONLY != 0 {
+            return Err(EfiErrorKind::AccessDenied.into());
+        }
+        self.resize(len, len)
+    }
+}
+
+struct FatDir {
+    entries: ::alloc::vec::IntoIter<Entry>,
+}
+
+impl Dir for FatDir {
+    fn next_entry(&mut self) -> Result<Option<DirEntry>> {
+        Ok(self.entries.next().map(|e| DirEntry { metadata: metadata_of(&e), name: e.name }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::dir::{checksum, ATTR_LONG_NAME, LFN_LAST, LFN_CHAR_OFFSETS, LFN_CHECKSUM};
+    use fs::MemDisk;
+
+    const SECTOR: usize = 512;
+
+    fn lfn_entry(sequence: u8, name: &str, short_name: &[u8; 11]) -> [u8; ENTRY_SIZE] {
+        let mut chars = name.encode_utf16().collect::<Vec<_>>();
+        chars.push(0);
+        chars.resize(((chars.len() + 12) / 13) * 13, 0xFFFF);
+
+        let mut e = [0u8; ENTRY_SIZE];
+        e[0] = sequence | if sequence as usize * 13 >= chars.len() { LFN_LAST } else { 0 };
+        e[11] = ATTR_LONG_NAME;
+        e[LFN_CHECKSUM] = checksum(short_name);
+        for (i, &at) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            LittleEndian::write_u16(&mut e[at..], chars[(sequence as usize - 1) * 13 + i]);
+        }
+        e
+    }
+
+    // FAT12, one sector clusters, 64 sectors: boot sector, two one sector FATs, a one sector root directory then data.
+    // "Hello World.txt" is 600 bytes over clusters 2 and 3, EFI/ is cluster 4 and holds BOOTX64.EFI in cluster 5
+    fn image() -> MemDisk {
+        let mut disk = vec![0u8; 64 * SECTOR];
+        {
+            let bpb = &mut disk[..SECTOR];
+            bpb[0] = 0xEB;
+            LittleEndian::write_u16(&mut bpb[11..], SECTOR as u16);
+            bpb[13] = 1;
+            LittleEndian::write_u16(&mut bpb[14..], 1);
+            bpb[16] = 2;
+            LittleEndian::write_u16(&mut bpb[17..], 16);
+            LittleEndian::write_u16(&mut bpb[19..], 64);
+            LittleEndian::write_u16(&mut bpb[22..], 1);
+            LittleEndian::write_u16(&mut bpb[510..], BOOT_SIGNATURE);
+        }
+
+        let fat = [0xFF8, 0xFFF, 3, 0xFFF, 0xFFF, 0xFFF];
+        for copy in 0..2 {
+            for (cluster, &value) in fat.iter().enumerate() {
+                let at = (1 + copy) * SECTOR + cluster + cluster / 2;
+                let current = LittleEndian::read_u16(&disk[at..]);
+                let packed = if cluster & 1 == 0 { (current & 0xF000) | value } else { (current & 0x000F) | (value << 4) };
+                LittleEndian::write_u16(&mut disk[at..], packed);
+            }
+        }
+
+        let short_name = b"HELLOW~1TXT";
+        let root = [
+            short_entry(b"TESTVOL    ", 0x08, 0, 0),
+            lfn_entry(2, "Hello World.txt", short_name),
+            lfn_entry(1, "Hello World.txt", short_name),
+            short_entry(short_name, 0, 2, 600),
+            short_entry(b"EFI        ", ATTR_DIRECTORY, 4, 0),
+        ];
+        for (i, e) in root.iter().enumerate() {
+            disk[3 * SECTOR + i * ENTRY_SIZE..][..ENTRY_SIZE].copy_from_slice(e);
+        }
+
+        let efi = [
+            short_entry(b".          ", ATTR_DIRECTORY, 4, 0),
+            short_entry(b"..         ", ATTR_DIRECTORY, 0, 0),
+            short_entry(b"BOOTX64 EFI", 0, 5, 4),
+        ];
+        for (i, e) in efi.iter().enumerate() {
+            disk[6 * SECTOR + i * ENTRY_SIZE..][..ENTRY_SIZE].copy_from_slice(e);
+        }
+
+        for (i, b) in disk[4 * SECTOR..4 * SECTOR + 600].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        disk[7 * SECTOR..7 * SECTOR + 4].copy_from_slice(b"MZ\x90\x00");
+        MemDisk::new(disk)
+    }
+
+    #[test]
+    fn reads_fat12_image() {
+        let mut disk = image();
+        assert!(Fat::probe(&mut disk));
+
+        let fs = Fat::new(disk).unwrap();
+        assert_eq!(fs.fat_type(), FatType::Fat12);
+
+        let names = fs.read_dir("/").unwrap().into_iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Hello World.txt", "EFI"]);
+
+        let data = fs.read("hello world.txt").unwrap();
+        assert_eq!(data.len(), 600);
+        assert!(data.iter().enumerate().all(|(i, &b)| b == i as u8));
+        assert_eq!(fs.read("HELLOW~1.TXT").unwrap(), data);
+
+        assert_eq!(fs.read("\\EFI\\BOOTX64.EFI").unwrap(), b"MZ\x90\x00".to_vec());
+        assert_eq!(fs.read_dir("efi").unwrap().len(), 1);
+        assert_eq!(fs.open("efi/missing.efi").err().unwrap().kind(), EfiErrorKind::NotFound);
+    }
+
+    #[test]
+    fn seeks_across_clusters() {
+        let fs = Fat::new(image()).unwrap();
+        let mut file = fs.open("Hello World.txt").unwrap();
+        file.seek(SeekFrom::Start(510)).unwrap();
+        let mut buf = [0u8; 4];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [254, 255, 0, 1]);
+    }
+
+    #[test]
+    fn written_files_survive_a_remount() {
+        let fs = Fat::new(image()).unwrap();
+        let data = (0..1500).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        fs.create_dir("EFI/Boot Loader").unwrap();
+        fs.write("EFI/Boot Loader/config file.conf", &data).unwrap();
+        fs.write("efi/bootx64.efi", b"MZ").unwrap();
+
+        let fs = Fat::new(fs.into_inner()).unwrap();
+        assert_eq!(fs.read("efi/boot loader/CONFIG FILE.CONF").unwrap(), data);
+        assert_eq!(fs.read("EFI/BOOTX64.EFI").unwrap(), b"MZ".to_vec());
+        assert_eq!(fs.read("EFI/BOOTLO~1/CONFIG~1.CON").unwrap(), data);
+        assert_eq!(fs.read_dir("EFI").unwrap().iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["BOOTX64.EFI", "Boot Loader"]);
+    }
+
+    #[test]
+    fn truncating_and_removing_frees_clusters() {
+        let fs = Fat::new(image()).unwrap();
+        {
+            let mut file = fs.create("big.bin").unwrap();
+            file.write_all(&[0xAA; 2000]).unwrap();
+            file.set_len(100).unwrap();
+            file.seek(SeekFrom::Start(300)).unwrap();
+            file.write_all(b"end").unwrap();
+        }
+
+        let data = fs.read("BIG.BIN").unwrap();
+        assert_eq!(data.len(), 303);
+        assert!(data[..100].iter().all(|&b| b == 0xAA));
+        assert!(data[100..300].iter().all(|&b| b == 0)); // Not the old contents of the clusters
+        assert_eq!(&data[300..], b"end");
+
+        assert_eq!(fs.remove("efi").unwrap_err().kind(), EfiErrorKind::AccessDenied); // Not empty
+        fs.remove("efi/bootx64.efi").unwrap();
+        fs.remove("efi").unwrap();
+        fs.remove("big.bin").unwrap();
+        assert_eq!(fs.read_dir("").unwrap().into_iter().map(|e| e.name).collect::<Vec<_>>(), vec!["Hello World.txt"]);
+
+        // Everything but the 2 clusters of "Hello World.txt" is free again so a file filling the rest fits
+        let free = (fs.cluster_count - 2) as usize * SECTOR;
+        fs.write("fill", &vec![1u8; free]).unwrap();
+        assert_eq!(fs.write("more", &[1]).unwrap_err().kind(), EfiErrorKind::VolumeFull);
+    }
+
+    #[test]
+    fn short_names_are_generated_like_windows() {
+        assert_eq!(short_name_for("BOOTX64.EFI", &[]), Some((*b"BOOTX64 EFI", false)));
+        assert_eq!(short_name_for("readme.txt", &[]), Some((*b"README  TXT", true)));
+        assert_eq!(short_name_for("Hello World.txt", &[]), Some((*b"HELLOW~1TXT", true)));
+        assert_eq!(short_name_for("Hello World.txt", &[*b"HELLOW~1TXT"]), Some((*b"HELLOW~2TXT", true)));
+        assert_eq!(short_name_for(".bashrc", &[]), Some((*b"BASHRC~1   ", true)));
+        assert!(!is_valid_name("a:b"));
+    }
+}
@@ -0,0 +1,362 @@
+// ISO9660 (CD/DVD images), read only.
+//
+// Names come from the Joliet supplementary descriptor when there is one, otherwise from Rock Ridge NM entries,
+// otherwise from the plain ISO9660 identifiers with their ";1" version suffix stripped. Lookups ignore ASCII case
+// since plain ISO9660 names are upper case and nobody types them that way.
+
+use super::{FileSystem, File, Dir, DirEntry, Metadata, Disk, components, to_io_error, seek_position};
+use io::{self, Read, Write, Seek, SeekFrom};
+use {Result, EfiErrorKind};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use core::cell::RefCell;
+
+const SECTOR_SIZE: u64 = 2048;
+const FIRST_DESCRIPTOR_SECTOR: u64 = 16;
+const STANDARD_IDENTIFIER: &[u8] = b"CD001";
+
+const DESCRIPTOR_PRIMARY: u8 = 1;
+const DESCRIPTOR_SUPPLEMENTARY: u8 = 2;
+const DESCRIPTOR_TERMINATOR: u8 = 255;
+
+// Escape sequences marking a supplementary descriptor as Joliet (UCS-2 levels 1 to 3)
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+const FLAG_DIRECTORY: u8 = 0x02;
+
+// Directory record layout
+const RECORD_EXTENT: usize = 2;
+const RECORD_DATA_LEN: usize = 10;
+const RECORD_FLAGS: usize = 25;
+const RECORD_NAME_LEN: usize = 32;
+const RECORD_NAME: usize = 33;
+
+#[derive(Debug, Clone)]
+struct Record {
+    name: String,
+    extent: u32,
+    len: u32,
+    is_dir: bool,
+}
+
+impl Record {
+    fn metadata(&self) -> Metadata {
+        Metadata { is_dir: self.is_dir, len: self.len as u64, read_only: true }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Names {
+    Plain,
+    Joliet,
+}
+
+/// An ISO9660 file system
+pub struct Iso9660<D: Disk> {
+    disk: RefCell<D>,
+    block_size: u64,
+    root: Record,
+    names: Names,
+}
+
+impl<D: Disk> Iso9660<D> {
+    /// Whether the disk looks like it has an ISO9660 file system on it
+    pub fn probe(disk: &mut D) -> bool {
+        let mut id = [0u8; 5];
+        disk.read_at(FIRST_DESCRIPTOR_SECTOR * SECTOR_SIZE + 1, &mut id).is_ok() && id == STANDARD_IDENTIFIER
+    }
+
+    pub fn new(mut disk: D) -> Result<Self> {
+        let mut primary = None;
+        let mut joliet = None;
+
+        let mut descriptor = [0u8; SECTOR_SIZE as usize];
+        let mut sector = FIRST_DESCRIPTOR_SECTOR;
+        loop {
+            disk.read_at(sector * SECTOR_SIZE, &mut descriptor)?;
+            if &descriptor[1..6] != STANDARD_IDENTIFIER {
+                return Err(EfiErrorKind::VolumeCorrupted.into());
+            }
+
+            match descriptor[0] {
+                DESCRIPTOR_PRIMARY if primary.is_none() => primary = Some(descriptor),
+                DESCRIPTOR_SUPPLEMENTARY if JOLIET_ESCAPES.iter().any(|e| &descriptor[88..91] == *e) => joliet = Some(descriptor),
+                DESCRIPTOR_TERMINATOR => break,
+                _ => {}
+            }
+            sector += 1;
+        }
+
+        let (descriptor, names) = match (joliet, primary) {
+            (Some(joliet), _) => (joliet, Names::Joliet),
+            (None, Some(primary)) => (primary, Names::Plain),
+            (None, None) => return Err(EfiErrorKind::VolumeCorrupted.into()),
+        };
+
+        let block_size = LittleEndian::read_u16(&descriptor[128..130]) as u64;
+        if block_size == 0 {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        let root = parse_record(&descriptor[156..190], names).ok_or_else(|| ::EfiError::from(EfiErrorKind::VolumeCorrupted))?;
+
+        Ok(Self { disk: RefCell::new(disk), block_size, root, names })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk.into_inner()
+    }
+
+    fn read_extent(&self, extent: u32, offset: u64, buf: &mut [u8]) -> Result<()> {
+        self.disk.borrow_mut().read_at(extent as u64 * self.block_size + offset, buf)
+    }
+
+    fn read_dir_records(&self, dir: &Record) -> Result<Vec<Record>> {
+        let mut data = vec![0u8; dir.len as usize];
+        self.read_extent(dir.extent, 0, &mut data)?;
+
+        // Records never straddle a block. The rest of a block is zero filled when the next record wouldn't fit
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let len = data[pos] as usize;
+            if len == 0 {
+                pos = (pos / self.block_size as usize + 1) * self.block_size as usize;
+                continue;
+            }
+            if pos + len > data.len() {
+                return Err(EfiErrorKind::VolumeCorrupted.into());
+            }
+
+            if let Some(record) = parse_record(&data[pos..pos + len], self.names) {
+                if record.name != "." && record.name != ".." {
+                    records.push(record);
+                }
+            }
+            pos += len;
+        }
+
+        Ok(records)
+    }
+
+    fn lookup(&self, path: &str) -> Result<Record> {
+        let mut current = self.root.clone();
+        for name in components(path) {
+            if !current.is_dir {
+                return Err(EfiErrorKind::NotFound.into());
+            }
+            current = self.read_dir_records(&current)?
+                .into_iter()
+                .find(|r| r.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| ::EfiError::from(EfiErrorKind::NotFound))?;
+        }
+        Ok(current)
+    }
+}
+
+// Parses one directory record. None if it is too short to be one
+fn parse_record(r: &[u8], names: Names) -> Option<Record> {
+    if r.len() < RECORD_NAME || r.len() < RECORD_NAME + r[RECORD_NAME_LEN] as usize {
+        return None;
+    }
+
+    let flags = r[RECORD_FLAGS];
+    let name_len = r[RECORD_NAME_LEN] as usize;
+    let raw_name = &r[RECORD_NAME..RECORD_NAME + name_len];
+
+    // The name is padded to an even length and the system use area (where Rock Ridge lives) follows
+    let system_use_start = RECORD_NAME + name_len + (1 - name_len % 2);
+    let system_use = if system_use_start < r.len() { &r[system_use_start..] } else { &[] };
+
+    let name = match raw_name {
+        [0] => String::from("."),
+        [1] => String::from(".."),
+        _ if names == Names::Joliet => {
+            let chars = raw_name.chunks(2).filter(|c| c.len() == 2).map(BigEndian::read_u16).collect::<Vec<_>>();
+            strip_version(&String::from_utf16_lossy(&chars))
+        }
+        _ => match rock_ridge_name(system_use) {
+            Some(name) => name,
+            None => strip_version(&String::from_utf8_lossy(raw_name)),
+        },
+    };
+
+    // TODO: files bigger than 4GiB are split over several records with the multi-extent flag (0x80) set.
+    // We only read the first extent for now
+    Some(Record {
+        name,
+        extent: LittleEndian::read_u32(&r[RECORD_EXTENT..RECORD_EXTENT + 4]),
+        len: LittleEndian::read_u32(&r[RECORD_DATA_LEN..RECORD_DATA_LEN + 4]),
+        is_dir: flags & FLAG_DIRECTORY != 0,
+    })
+}
+
+// "NAME.EXT;1" -> "NAME.EXT", and "NAME." -> "NAME" for files without an extension
+fn strip_version(name: &str) -> String {
+    let name = match name.rfind(';') {
+        Some(index) => &name[..index],
+        None => name,
+    };
+    name.trim_end_matches('.').into()
+}
+
+// The alternate name from Rock Ridge NM entries in a record's system use area, if it has any
+fn rock_ridge_name(mut system_use: &[u8]) -> Option<String> {
+    const NM_CONTINUE: u8 = 0x01;
+
+    let mut name: Option<Vec<u8>> = None;
+    while system_use.len() >= 4 {
+        let len = system_use[2] as usize;
+        if len < 4 || len > system_use.len() {
+            break;
+        }
+
+        let (entry, rest) = system_use.split_at(len);
+        if &entry[..2] == b"NM" && len >= 5 {
+            let flags = entry[4];
+            name.get_or_insert_with(Vec::new).extend_from_slice(&entry[5..]);
+            if flags & NM_CONTINUE == 0 {
+                break;
+            }
+        }
+        system_use = rest;
+    }
+
+    name.map(|n| String::from_utf8_lossy(&n).into())
+}
+
+impl<D: Disk> FileSystem for Iso9660<D> {
+    fn open<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        let record = self.lookup(path)?;
+        if record.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(Box::new(IsoFile { fs: self, extent: record.extent, len: record.len as u64, pos: 0 }))
+    }
+
+    fn open_dir<'a>(&'a self, path: &str) -> Result<Box<dyn Dir + 'a>> {
+        let record = self.lookup(path)?;
+        if !record.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(Box::new(IsoDir { records: self.read_dir_records(&record)?.into_iter() }))
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(self.lookup(path)?.metadata())
+    }
+}
+
+struct IsoFile<'a, D: Disk> {
+    fs: &'a Iso9660<D>,
+    extent: u32,
+    len: u64,
+    pos: u64,
+}
+
+impl<'a, D: Disk> Read for IsoFile<'a, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = (self.len.saturating_sub(self.pos) as usize).min(buf.len());
+        self.fs.read_extent(self.extent, self.pos, &mut buf[..count]).map_err(to_io_error)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<'a, D: Disk> Write for IsoFile<'a, D> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::PermissionDenied.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, D: Disk> Seek for IsoFile<'a, D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(pos, self.pos, self.len)?;
+        Ok(self.pos)
+    }
+}
+
+impl<'a, D: Disk> File for IsoFile<'a, D> {
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+struct IsoDir {
+    records: ::alloc::vec::IntoIter<Record>,
+}
+
+impl Dir for IsoDir {
+    fn next_entry(&mut self) -> Result<Option<DirEntry>> {
+        Ok(self.records.next().map(|r| DirEntry { metadata: r.metadata(), name: r.name }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::MemDisk;
+
+    fn record(name: &[u8], extent: u32, len: u32, flags: u8) -> Vec<u8> {
+        let mut r = vec![0u8; RECORD_NAME + name.len() + (1 - name.len() % 2)];
+        r[0] = r.len() as u8;
+        LittleEndian::write_u32(&mut r[RECORD_EXTENT..], extent);
+        LittleEndian::write_u32(&mut r[RECORD_DATA_LEN..], len);
+        r[RECORD_FLAGS] = flags;
+        r[RECORD_NAME_LEN] = name.len() as u8;
+        r[RECORD_NAME..RECORD_NAME + name.len()].copy_from_slice(name);
+        r
+    }
+
+    // Root directory at sector 20 holding BOOT/ (sector 21) which holds GRUB.CFG;1 (sector 22)
+    fn image() -> MemDisk {
+        let mut disk = vec![0u8; 23 * SECTOR_SIZE as usize];
+        let sector = |n: usize| n * SECTOR_SIZE as usize;
+
+        let pvd = &mut disk[sector(16)..sector(17)];
+        pvd[0] = DESCRIPTOR_PRIMARY;
+        pvd[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+        LittleEndian::write_u16(&mut pvd[128..], SECTOR_SIZE as u16);
+        let root = record(&[0], 20, SECTOR_SIZE as u32, FLAG_DIRECTORY);
+        pvd[156..156 + root.len()].copy_from_slice(&root);
+
+        disk[sector(17)] = DESCRIPTOR_TERMINATOR;
+        disk[sector(17) + 1..sector(17) + 6].copy_from_slice(STANDARD_IDENTIFIER);
+
+        let mut root_dir = record(&[0], 20, SECTOR_SIZE as u32, FLAG_DIRECTORY);
+        root_dir.extend(record(&[1], 20, SECTOR_SIZE as u32, FLAG_DIRECTORY));
+        root_dir.extend(record(b"BOOT", 21, SECTOR_SIZE as u32, FLAG_DIRECTORY));
+        disk[sector(20)..sector(20) + root_dir.len()].copy_from_slice(&root_dir);
+
+        let boot_dir = record(b"GRUB.CFG;1", 22, 13, 0);
+        disk[sector(21)..sector(21) + boot_dir.len()].copy_from_slice(&boot_dir);
+
+        disk[sector(22)..sector(22) + 13].copy_from_slice(b"set timeout=5");
+        MemDisk::new(disk)
+    }
+
+    #[test]
+    fn reads_files_through_directories() {
+        let mut disk = image();
+        assert!(Iso9660::probe(&mut disk));
+
+        let fs = Iso9660::new(disk).unwrap();
+        assert_eq!(fs.read("/boot/grub.cfg").unwrap(), b"set timeout=5".to_vec());
+        assert_eq!(fs.metadata("BOOT").unwrap(), Metadata { is_dir: true, len: SECTOR_SIZE, read_only: true });
+        assert_eq!(fs.read_dir("\\BOOT").unwrap().iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["GRUB.CFG"]);
+        assert_eq!(fs.open("boot/missing").err().unwrap().kind(), EfiErrorKind::NotFound);
+        assert_eq!(fs.write("boot/grub.cfg", b"").unwrap_err().kind(), EfiErrorKind::WriteProtected);
+    }
+
+    #[test]
+    fn prefers_rock_ridge_names() {
+        let mut r = record(b"VMLINUZ.;1", 30, 0, 0);
+        r.extend_from_slice(b"NM\x0c\x01\x00vmlinuz");
+        r[0] = r.len() as u8;
+        assert_eq!(parse_record(&r, Names::Plain).unwrap().name, "vmlinuz");
+        assert_eq!(parse_record(&r[..RECORD_NAME + 10], Names::Plain).unwrap().name, "VMLINUZ");
+    }
+}
@@ -0,0 +1,196 @@
+// File systems.
+//
+// Everything implements the FileSystem trait whether the firmware provides the file system (SimpleFs, over
+// EFI_SIMPLE_FILE_SYSTEM_PROTOCOL) or the crate does (Iso9660 and Fat, which parse the disk themselves for media
+// the firmware didn't bind a file system to). open_volume() picks whichever is available for a handle. Under the UEFI
+// Shell there's ShellFs too, which goes through the shell and so reaches every file system it has mapped.
+//
+// Paths are separated by `/` or `\` and are always relative to the root of the file system, except on ShellFs where
+// they're shell paths.
+
+pub mod disk;
+pub mod iso9660;
+pub mod fat;
+mod simple;
+mod shell;
+
+pub use self::disk::{Disk, BlockDevice, BlockDisk, BlockIo, DiskIo, MemDisk, Region};
+pub use self::iso9660::Iso9660;
+pub use self::fat::{Fat, format_fat32};
+pub use self::simple::SimpleFs;
+pub use self::shell::ShellFs;
+
+use ffi::EFI_HANDLE;
+use io::{self, Read, Write, Seek};
+use {Result, EfiError, EfiErrorKind};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// A file system
+pub trait FileSystem {
+    /// Opens an existing file
+    fn open<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>>;
+
+    /// Opens an existing directory to list its entries
+    fn open_dir<'a>(&'a self, path: &str) -> Result<Box<dyn Dir + 'a>>;
+
+    fn metadata(&self, path: &str) -> Result<Metadata>;
+
+    /// Creates a new empty file or truncates an existing one
+    fn create<'a>(&'a self, _path: &str) -> Result<Box<dyn File + 'a>> {
+        Err(EfiErrorKind::WriteProtected.into())
+    }
+
+    fn create_dir(&self, _path: &str) -> Result<()> {
+        Err(EfiErrorKind::WriteProtected.into())
+    }
+
+    /// Removes a file or an empty directory
+    fn remove(&self, _path: &str) -> Result<()> {
+        Err(EfiErrorKind::WriteProtected.into())
+    }
+
+    /// Reads a whole file
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let mut data = Vec::with_capacity(file.len() as usize);
+        file.read_to_end(&mut data).map_err(from_io_error)?;
+        Ok(data)
+    }
+
+    /// Creates or replaces a file with the given contents
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut file = self.create(path)?;
+        file.write_all(data).map_err(from_io_error)?;
+        file.flush().map_err(from_io_error)
+    }
+
+    /// All the entries of a directory
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let mut dir = self.open_dir(path)?;
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.metadata(path).is_ok()
+    }
+}
+
+/// An open file. Read only file systems fail writes with PermissionDenied
+pub trait File: Read + Write + Seek {
+    /// Length of the file in bytes
+    fn len(&self) -> u64;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Truncates or extends (with zeroes) the file
+    fn set_len(&mut self, _len: u64) -> Result<()> {
+        Err(EfiErrorKind::WriteProtected.into())
+    }
+}
+
+/// An open directory
+pub trait Dir {
+    /// The next entry in the directory. `.` and `..` are skipped
+    fn next_entry(&mut self) -> Result<Option<DirEntry>>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Metadata {
+    pub is_dir: bool,
+    pub len: u64,
+    pub read_only: bool,
+}
+
+/// The file system on the given handle. Uses the firmware's own driver if it bound one, otherwise looks at the
+/// disk itself for an ISO9660 or FAT file system
+pub fn open_volume(handle: EFI_HANDLE) -> Result<Box<dyn FileSystem>> {
+    match SimpleFs::new(handle) {
+        Ok(fs) => return Ok(Box::new(fs)),
+        Err(e) if e.kind() != EfiErrorKind::Unsupported => return Err(e),
+        Err(_) => {} // No file system driver bound to the handle
+    }
+
+    mount(DiskIo::new(handle)?)
+}
+
+/// Under the shell, the shell's view of the file systems, so that mapped paths like fs0:\efi and paths relative to
+/// its current directory work as they do at its prompt. Otherwise the volume the running image was loaded from
+pub fn default_fs() -> Result<Box<dyn FileSystem>> {
+    match ShellFs::new() {
+        Ok(fs) => Ok(Box::new(fs)),
+        Err(_) => Ok(Box::new(SimpleFs::boot_volume()?)),
+    }
+}
+
+/// Identifies the file system on a disk and mounts it
+pub fn mount<D: Disk + 'static>(mut disk: D) -> Result<Box<dyn FileSystem>> {
+    if Iso9660::probe(&mut disk) {
+        return Ok(Box::new(Iso9660::new(disk)?));
+    }
+
+    if Fat::probe(&mut disk) {
+        return Ok(Box::new(Fat::new(disk)?));
+    }
+
+    Err(EfiErrorKind::Unsupported.into())
+}
+
+// Splits a path into its components, ignoring empty ones so that leading, trailing and doubled separators don't matter
+pub(crate) fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split(|c| c == '/' || c == '\\').filter(|c| !c.is_empty())
+}
+
+// Splits a path into the parent directory and the last component
+pub(crate) fn split_parent(path: &str) -> Option<(String, &str)> {
+    let mut parts = components(path).collect::<Vec<_>>();
+    let name = parts.pop()?;
+    Some((parts.join("/"), name))
+}
+
+pub(crate) fn to_io_error(e: EfiError) -> io::Error {
+    match e.kind() {
+        EfiErrorKind::NotFound => io::ErrorKind::NotFound.into(),
+        EfiErrorKind::WriteProtected | EfiErrorKind::AccessDenied => io::ErrorKind::PermissionDenied.into(),
+        EfiErrorKind::VolumeCorrupted => io::ErrorKind::InvalidData.into(),
+        EfiErrorKind::VolumeFull => io::ErrorKind::WriteZero.into(),
+        EfiErrorKind::InvalidParameter => io::ErrorKind::InvalidInput.into(),
+        _ => io::ErrorKind::Other.into(),
+    }
+}
+
+pub(crate) fn from_io_error(e: io::Error) -> EfiError {
+    match e.kind() {
+        io::ErrorKind::NotFound => EfiErrorKind::NotFound.into(),
+        io::ErrorKind::PermissionDenied => EfiErrorKind::WriteProtected.into(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => EfiErrorKind::VolumeCorrupted.into(),
+        io::ErrorKind::InvalidInput => EfiErrorKind::InvalidParameter.into(),
+        io::ErrorKind::WriteZero => EfiErrorKind::VolumeFull.into(), // Out of space, as far as write_all() could tell
+        _ => EfiErrorKind::DeviceError.into(),
+    }
+}
+
+// The Seek maths shared by all the file implementations
+pub(crate) fn seek_position(pos: io::SeekFrom, current: u64, len: u64) -> io::Result<u64> {
+    let new = match pos {
+        io::SeekFrom::Start(offset) => offset as i64,
+        io::SeekFrom::End(offset) => len as i64 + offset,
+        io::SeekFrom::Current(offset) => current as i64 + offset,
+    };
+
+    if new < 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"));
+    }
+    Ok(new as u64)
+}
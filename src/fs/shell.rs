@@ -0,0 +1,257 @@
+// Files through the UEFI Shell when we're running under it, via EFI_SHELL_PROTOCOL. The shell resolves the paths, so
+// they're shell paths: mapped (fs0:\efi\boot), absolute on the current mapping (\efi) or relative to the current
+// directory (boot\x64.efi), and any of them can reach any file system the shell has mapped.
+
+use super::{FileSystem, File, Dir, DirEntry, Metadata, to_io_error, seek_position, simple::parse_file_info};
+use ffi::{
+    shell::{EFI_SHELL_PROTOCOL, EFI_SHELL_PROTOCOL_GUID, SHELL_FILE_HANDLE},
+    media::{EFI_FILE_INFO, EFI_FILE_MODE_READ, EFI_FILE_MODE_WRITE, EFI_FILE_DIRECTORY},
+    EFI_BUFFER_TOO_SMALL,
+    UINTN,
+    UINT64,
+    VOID,
+};
+use io::{self, Read, Write, Seek, SeekFrom};
+use utils::as_slice;
+use ucs2;
+use {Result, EfiErrorKind, system_table};
+use alloc::{boxed::Box, string::String};
+use core::{mem, ptr};
+
+/// Every file system the shell has mapped, through the shell
+pub struct ShellFs {
+    shell: *const EFI_SHELL_PROTOCOL,
+}
+
+impl ShellFs {
+    /// The shell we're running under. Fails with NotFound if we aren't
+    pub fn new() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let shell: *const EFI_SHELL_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).LocateProtocol)(&EFI_SHELL_PROTOCOL_GUID, ptr::null(), mem::transmute(&shell)));
+        }
+        Ok(Self { shell })
+    }
+
+    /// The shell's current directory, e.g. fs0:\efi\boot. None if there's no current mapping
+    pub fn current_dir(&self) -> Option<String> {
+        let dir = unsafe { ((*self.shell).GetCurDir)(ptr::null()) };
+        if dir.is_null() {
+            return None;
+        }
+        Some(String::from_utf16_lossy(unsafe { as_slice(dir) }))
+    }
+
+    fn open_handle(&self, path: &str, mode: UINT64) -> Result<Handle> {
+        let path = ucs2::cached(&shell_path(path));
+        let mut handle: SHELL_FILE_HANDLE = ptr::null_mut();
+        unsafe {
+            ret_on_err!(((*self.shell).OpenFileByName)(path.as_ptr(), &mut handle, mode));
+        }
+        Ok(Handle { shell: self.shell, handle })
+    }
+
+    // Opens the file or directory, creating it if it doesn't exist
+    fn create_handle(&self, path: &str, attributes: UINT64) -> Result<Handle> {
+        let path = ucs2::cached(&shell_path(path));
+        let mut handle: SHELL_FILE_HANDLE = ptr::null_mut();
+        unsafe {
+            ret_on_err!(((*self.shell).CreateFile)(path.as_ptr(), attributes, &mut handle));
+        }
+        Ok(Handle { shell: self.shell, handle })
+    }
+}
+
+impl FileSystem for ShellFs {
+    fn open<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        // Fall back to read only for write protected media and read only files
+        let handle = match self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE) {
+            Err(e) if e.kind() == EfiErrorKind::WriteProtected || e.kind() == EfiErrorKind::AccessDenied => self.open_handle(path, EFI_FILE_MODE_READ)?,
+            handle => handle?,
+        };
+
+        if handle.info()?.0.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(Box::new(ShellFile(handle)))
+    }
+
+    fn open_dir<'a>(&'a self, path: &str) -> Result<Box<dyn Dir + 'a>> {
+        let handle = self.open_handle(path, EFI_FILE_MODE_READ)?;
+        if !handle.info()?.0.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(Box::new(ShellDir(handle)))
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(self.open_handle(path, EFI_FILE_MODE_READ)?.info()?.0)
+    }
+
+    fn create<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        // CreateFile opens existing files as they are so truncate them
+        let mut file = ShellFile(self.create_handle(path, 0)?);
+        if file.0.info()?.0.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        file.set_len(0)?;
+        Ok(Box::new(file))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<()> {
+        self.create_handle(path, EFI_FILE_DIRECTORY)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE)?.delete()
+    }
+}
+
+// The shell takes its own separator only. An empty path is the current directory
+fn shell_path(path: &str) -> String {
+    if path.is_empty() {
+        return String::from(".");
+    }
+    path.replace('/', "\\")
+}
+
+// An open SHELL_FILE_HANDLE. Closed on drop
+struct Handle {
+    shell: *const EFI_SHELL_PROTOCOL,
+    handle: SHELL_FILE_HANDLE,
+}
+
+impl Handle {
+    fn info(&self) -> Result<(Metadata, String)> {
+        let info = self.raw_info()?;
+        let parsed = unsafe { parse_file_info(info) };
+        free_pool(info);
+        Ok(parsed)
+    }
+
+    // The shell's EFI_FILE_INFO, which must be freed
+    fn raw_info(&self) -> Result<*mut EFI_FILE_INFO> {
+        let info = unsafe { ((*self.shell).GetFileInfo)(self.handle) };
+        if info.is_null() {
+            return Err(EfiErrorKind::DeviceError.into());
+        }
+        Ok(info)
+    }
+
+    fn delete(self) -> Result<()> {
+        // Delete closes the file too so we must not close it again on drop
+        let (shell, handle) = (self.shell, self.handle);
+        mem::forget(self);
+        unsafe {
+            ret_on_err!(((*shell).DeleteFile)(handle));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { ((*self.shell).CloseFile)(self.handle) };
+    }
+}
+
+fn free_pool(info: *mut EFI_FILE_INFO) {
+    unsafe { ((*system_table().BootServices).FreePool)(info as *const VOID) }; // Nothing to be done if it fails
+}
+
+struct ShellFile(Handle);
+
+impl Read for ShellFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut size: UINTN = buf.len();
+        let status = unsafe { traced!(((*self.0.shell).ReadFile)(self.0.handle, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+        ::to_res(size, status).map_err(to_io_error)
+    }
+}
+
+impl Write for ShellFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut size: UINTN = buf.len();
+        let status = unsafe { traced!(((*self.0.shell).WriteFile)(self.0.handle, &mut size, buf.as_ptr() as *const VOID)) };
+        ::to_res(size, status).map_err(to_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let status = unsafe { traced!(((*self.0.shell).FlushFile)(self.0.handle)) };
+        ::to_res((), status).map_err(to_io_error)
+    }
+}
+
+impl Seek for ShellFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut current: UINT64 = 0;
+        let status = unsafe { traced!(((*self.0.shell).GetFilePosition)(self.0.handle, &mut current)) };
+        ::to_res((), status).map_err(to_io_error)?;
+
+        let new = seek_position(pos, current, self.len())?;
+        let status = unsafe { traced!(((*self.0.shell).SetFilePosition)(self.0.handle, new)) };
+        ::to_res(new, status).map_err(to_io_error)
+    }
+}
+
+impl File for ShellFile {
+    fn len(&self) -> u64 {
+        let mut size: UINT64 = 0;
+        let status = unsafe { traced!(((*self.0.shell).GetFileSize)(self.0.handle, &mut size)) };
+        ::to_res(size, status).unwrap_or(0)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        // Truncating goes through SetFileInfo with the FileSize changed
+        let info = self.0.raw_info()?;
+        let status = unsafe {
+            (*info).FileSize = len;
+            ((*self.0.shell).SetFileInfo)(self.0.handle, info)
+        };
+        free_pool(info);
+        ret_on_err!(status);
+        Ok(())
+    }
+}
+
+struct ShellDir(Handle);
+
+impl Dir for ShellDir {
+    fn next_entry(&mut self) -> Result<Option<DirEntry>> {
+        // Each read of a directory returns the EFI_FILE_INFO of the next entry. A size of zero means there are no more
+        let mut buf = vec![0u64; 64];
+        loop {
+            let mut size: UINTN = buf.len() * 8;
+            let status = unsafe { traced!(((*self.0.shell).ReadFile)(self.0.handle, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+            if status == EFI_BUFFER_TOO_SMALL {
+                buf.resize((size + 7) / 8, 0);
+                continue;
+            }
+            ret_on_err!(status);
+
+            if size == 0 {
+                return Ok(None);
+            }
+
+            let (metadata, name) = unsafe { parse_file_info(buf.as_ptr() as *const EFI_FILE_INFO) };
+            if name == "." || name == ".." {
+                continue;
+            }
+            return Ok(Some(DirEntry { name, metadata }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_shell_paths() {
+        assert_eq!(shell_path("fs0:/efi/boot/bootx64.efi"), "fs0:\\efi\\boot\\bootx64.efi");
+        assert_eq!(shell_path("..\\tools"), "..\\tools");
+        assert_eq!(shell_path(""), ".");
+    }
+}
@@ -0,0 +1,261 @@
+// File systems the firmware provides, via EFI_SIMPLE_FILE_SYSTEM_PROTOCOL and EFI_FILE_PROTOCOL
+
+use super::{FileSystem, File, Dir, DirEntry, Metadata, components, to_io_error, seek_position};
+use ffi::{
+    loaded_image::{EFI_LOADED_IMAGE_PROTOCOL, EFI_LOADED_IMAGE_PROTOCOL_GUID},
+    media::{
+        EFI_SIMPLE_FILE_SYSTEM_PROTOCOL,
+        EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID,
+        EFI_FILE_PROTOCOL,
+        EFI_FILE_INFO,
+        EFI_FILE_INFO_ID,
+        EFI_FILE_MODE_READ,
+        EFI_FILE_MODE_WRITE,
+        EFI_FILE_MODE_CREATE,
+        EFI_FILE_DIRECTORY,
+        EFI_FILE_READ_ONLY,
+    },
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_HANDLE,
+    EFI_BUFFER_TOO_SMALL,
+    UINTN,
+    UINT64,
+    VOID,
+};
+use io::{self, Read, Write, Seek, SeekFrom};
+use utils::as_slice;
+use ucs2;
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{mem, ptr};
+
+/// A file system the firmware has a driver for
+pub struct SimpleFs {
+    root: Handle,
+}
+
+impl SimpleFs {
+    /// The file system on the given handle. Fails with Unsupported if the firmware bound no file system to it
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let bs = system_table().BootServices;
+        let fs: *const EFI_SIMPLE_FILE_SYSTEM_PROTOCOL = ptr::null();
+        let mut root: *const EFI_FILE_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).OpenProtocol)(handle, &EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID, mem::transmute(&fs), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+            ret_on_err!(((*fs).OpenVolume)(fs, &mut root));
+        }
+
+        Ok(Self { root: Handle(root as *mut EFI_FILE_PROTOCOL) })
+    }
+
+    /// The volume the running image was loaded from
+    pub fn boot_volume() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let loaded_image: *const EFI_LOADED_IMAGE_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).OpenProtocol)(image_handle(), &EFI_LOADED_IMAGE_PROTOCOL_GUID, mem::transmute(&loaded_image), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+            Self::new((*loaded_image).DeviceHandle)
+        }
+    }
+
+    fn open_handle(&self, path: &str, mode: UINT64, attributes: UINT64) -> Result<Handle> {
+        // UEFI paths use backslashes. A lone backslash is the root itself
+        let path = format!("\\{}", components(path).collect::<Vec<_>>().join("\\"));
+        let path = ucs2::cached(&path);
+        let mut file: *const EFI_FILE_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*self.root.0).Open)(self.root.0, &mut file, path.as_ptr(), mode, attributes));
+        }
+        Ok(Handle(file as *mut EFI_FILE_PROTOCOL))
+    }
+}
+
+impl FileSystem for SimpleFs {
+    fn open<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        // Fall back to read only for write protected media and read only files
+        let handle = match self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE, 0) {
+            Err(e) if e.kind() == EfiErrorKind::WriteProtected || e.kind() == EfiErrorKind::AccessDenied => self.open_handle(path, EFI_FILE_MODE_READ, 0)?,
+            handle => handle?,
+        };
+
+        if handle.info()?.0.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(Box::new(SimpleFile(handle)))
+    }
+
+    fn open_dir<'a>(&'a self, path: &str) -> Result<Box<dyn Dir + 'a>> {
+        let handle = self.open_handle(path, EFI_FILE_MODE_READ, 0)?;
+        if !handle.info()?.0.is_dir {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(Box::new(SimpleDir(handle)))
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(self.open_handle(path, EFI_FILE_MODE_READ, 0)?.info()?.0)
+    }
+
+    fn create<'a>(&'a self, path: &str) -> Result<Box<dyn File + 'a>> {
+        // The file protocol opens existing files as they are so truncate them first
+        if let Ok(existing) = self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE, 0) {
+            let mut existing = SimpleFile(existing);
+            existing.set_len(0)?;
+            return Ok(Box::new(existing));
+        }
+
+        let handle = self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE | EFI_FILE_MODE_CREATE, 0)?;
+        Ok(Box::new(SimpleFile(handle)))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<()> {
+        self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE | EFI_FILE_MODE_CREATE, EFI_FILE_DIRECTORY)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        self.open_handle(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE, 0)?.delete()
+    }
+}
+
+// An open EFI_FILE_PROTOCOL. Closed on drop
+struct Handle(*mut EFI_FILE_PROTOCOL);
+
+impl Handle {
+    // The EFI_FILE_INFO of the file, parsed into its metadata and name
+    fn info(&self) -> Result<(Metadata, String)> {
+        let mut buf = Vec::<u64>::new(); // u64 for EFI_FILE_INFO's alignment
+        let mut size: UINTN = 0;
+        loop {
+            let status = unsafe { traced!(((*self.0).GetInfo)(self.0, &EFI_FILE_INFO_ID, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+            if status != EFI_BUFFER_TOO_SMALL {
+                ret_on_err!(status);
+                return Ok(unsafe { parse_file_info(buf.as_ptr() as *const EFI_FILE_INFO) });
+            }
+            buf.resize((size + 7) / 8, 0);
+        }
+    }
+
+    fn delete(self) -> Result<()> {
+        // Delete closes the file too so we must not close it again on drop
+        let file = self.0;
+        mem::forget(self);
+        unsafe {
+            ret_on_err!(((*file).Delete)(file));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { ((*self.0).Close)(self.0) };
+    }
+}
+
+pub(super) unsafe fn parse_file_info(info: *const EFI_FILE_INFO) -> (Metadata, String) {
+    let metadata = Metadata {
+        is_dir: (*info).Attribute & EFI_FILE_DIRECTORY != 0,
+        len: (*info).FileSize,
+        read_only: (*info).Attribute & EFI_FILE_READ_ONLY != 0,
+    };
+
+    let name = String::from_utf16_lossy(as_slice((*info).FileName.as_ptr()));
+
+    (metadata, name)
+}
+
+struct SimpleFile(Handle);
+
+impl Read for SimpleFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let file = (self.0).0;
+        let mut size: UINTN = buf.len();
+        let status = unsafe { traced!(((*file).Read)(file, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+        ::to_res(size, status).map_err(to_io_error)
+    }
+}
+
+impl Write for SimpleFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = (self.0).0;
+        let mut size: UINTN = buf.len();
+        let status = unsafe { traced!(((*file).Write)(file, &mut size, buf.as_ptr() as *const VOID)) };
+        ::to_res(size, status).map_err(to_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let file = (self.0).0;
+        let status = unsafe { traced!(((*file).Flush)(file)) };
+        ::to_res((), status).map_err(to_io_error)
+    }
+}
+
+impl Seek for SimpleFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let file = (self.0).0;
+        let mut current: UINT64 = 0;
+        let status = unsafe { traced!(((*file).GetPosition)(file, &mut current)) };
+        ::to_res((), status).map_err(to_io_error)?;
+
+        let new = seek_position(pos, current, self.len())?;
+        let status = unsafe { traced!(((*file).SetPosition)(file, new)) };
+        ::to_res(new, status).map_err(to_io_error)
+    }
+}
+
+impl File for SimpleFile {
+    fn len(&self) -> u64 {
+        self.0.info().map(|(metadata, _)| metadata.len).unwrap_or(0)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        // Truncating goes through SetInfo with the FileSize changed
+        let file = (self.0).0;
+        let mut buf = Vec::<u64>::new();
+        let mut size: UINTN = 0;
+        loop {
+            let status = unsafe { traced!(((*file).GetInfo)(file, &EFI_FILE_INFO_ID, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+            if status != EFI_BUFFER_TOO_SMALL {
+                ret_on_err!(status);
+                break;
+            }
+            buf.resize((size + 7) / 8, 0);
+        }
+
+        unsafe {
+            (*(buf.as_mut_ptr() as *mut EFI_FILE_INFO)).FileSize = len;
+            ret_on_err!(((*file).SetInfo)(file, &EFI_FILE_INFO_ID, size, buf.as_ptr() as *const VOID));
+        }
+        Ok(())
+    }
+}
+
+struct SimpleDir(Handle);
+
+impl Dir for SimpleDir {
+    fn next_entry(&mut self) -> Result<Option<DirEntry>> {
+        // Each read of a directory returns the EFI_FILE_INFO of the next entry. A size of zero means there are no more
+        let dir = (self.0).0;
+        let mut buf = vec![0u64; 64];
+        loop {
+            let mut size: UINTN = buf.len() * 8;
+            let status = unsafe { traced!(((*dir).Read)(dir, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+            if status == EFI_BUFFER_TOO_SMALL {
+                buf.resize((size + 7) / 8, 0);
+                continue;
+            }
+            ret_on_err!(status);
+
+            if size == 0 {
+                return Ok(None);
+            }
+
+            let (metadata, name) = unsafe { parse_file_info(buf.as_ptr() as *const EFI_FILE_INFO) };
+            if name == "." || name == ".." {
+                continue;
+            }
+            return Ok(Some(DirEntry { name, metadata }));
+        }
+    }
+}
@@ -0,0 +1,584 @@
+// Firmware volumes and the firmware file system (PI spec volume 3), i.e. what's in the platform's flash.
+//
+// A volume holds FFS files and most files hold sections. Sections can encapsulate further sections, compressed or
+// otherwise wrapped, and FirmwareFile::sections() unwraps all the ones it knows how to so that callers only see the
+// leaves: PE32 images, UI names, nested volume images and so on. Everything borrows from the bytes the volume was
+// parsed from except what had to be decompressed.
+
+use ffi::EFI_GUID;
+use decompress::{tiano, LzmaDecoder};
+use io::Read;
+use utils::crc32;
+use {Result, EfiErrorKind};
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::slice;
+use byteorder::{ByteOrder, LittleEndian};
+
+pub const EFI_FIRMWARE_FILE_SYSTEM2_GUID: EFI_GUID = EFI_GUID(0x8C8CE578, 0x8A3D, 0x4F1C, [0x99, 0x35, 0x89, 0x61, 0x85, 0xC3, 0x2D, 0xD3]);
+pub const EFI_FIRMWARE_FILE_SYSTEM3_GUID: EFI_GUID = EFI_GUID(0x5473C07A, 0x3DCB, 0x4DCA, [0xBD, 0x6F, 0x1E, 0x96, 0x89, 0xE7, 0x34, 0x9A]);
+
+// GUIDed section formats that can be unwrapped. The LZMA and Tiano ones aren't in the PI spec but are what EDK2 builds
+pub const EFI_CRC32_GUIDED_SECTION_EXTRACTION_GUID: EFI_GUID = EFI_GUID(0xFC1BCDB0, 0x7D31, 0x49AA, [0x93, 0x6A, 0xA4, 0x60, 0x0D, 0x9D, 0xD0, 0x83]);
+pub const LZMA_CUSTOM_DECOMPRESS_GUID: EFI_GUID = EFI_GUID(0xEE4E5898, 0x3914, 0x4259, [0x9D, 0x6E, 0xDC, 0x7B, 0xD7, 0x94, 0x03, 0xCF]);
+pub const TIANO_CUSTOM_DECOMPRESS_GUID: EFI_GUID = EFI_GUID(0xA31280AD, 0x481E, 0x41B6, [0x95, 0xE8, 0x12, 0x7F, 0x4C, 0x98, 0x47, 0x79]);
+
+/// Erased flash reads as ones rather than zeros, which inverts file states
+pub const EFI_FVB2_ERASE_POLARITY: u32 = 0x0000_0800;
+
+pub const EFI_FV_FILETYPE_RAW: u8 = 0x01;
+pub const EFI_FV_FILETYPE_FREEFORM: u8 = 0x02;
+pub const EFI_FV_FILETYPE_SECURITY_CORE: u8 = 0x03;
+pub const EFI_FV_FILETYPE_PEI_CORE: u8 = 0x04;
+pub const EFI_FV_FILETYPE_DXE_CORE: u8 = 0x05;
+pub const EFI_FV_FILETYPE_PEIM: u8 = 0x06;
+pub const EFI_FV_FILETYPE_DRIVER: u8 = 0x07;
+pub const EFI_FV_FILETYPE_COMBINED_PEIM_DRIVER: u8 = 0x08;
+pub const EFI_FV_FILETYPE_APPLICATION: u8 = 0x09;
+pub const EFI_FV_FILETYPE_MM: u8 = 0x0A;
+pub const EFI_FV_FILETYPE_FIRMWARE_VOLUME_IMAGE: u8 = 0x0B;
+pub const EFI_FV_FILETYPE_COMBINED_MM_DXE: u8 = 0x0C;
+pub const EFI_FV_FILETYPE_MM_CORE: u8 = 0x0D;
+pub const EFI_FV_FILETYPE_MM_STANDALONE: u8 = 0x0E;
+pub const EFI_FV_FILETYPE_MM_CORE_STANDALONE: u8 = 0x0F;
+pub const EFI_FV_FILETYPE_FFS_PAD: u8 = 0xF0;
+
+pub const EFI_SECTION_COMPRESSION: u8 = 0x01;
+pub const EFI_SECTION_GUID_DEFINED: u8 = 0x02;
+pub const EFI_SECTION_DISPOSABLE: u8 = 0x03;
+pub const EFI_SECTION_PE32: u8 = 0x10;
+pub const EFI_SECTION_PIC: u8 = 0x11;
+pub const EFI_SECTION_TE: u8 = 0x12;
+pub const EFI_SECTION_DXE_DEPEX: u8 = 0x13;
+pub const EFI_SECTION_VERSION: u8 = 0x14;
+pub const EFI_SECTION_USER_INTERFACE: u8 = 0x15;
+pub const EFI_SECTION_COMPATIBILITY16: u8 = 0x16;
+pub const EFI_SECTION_FIRMWARE_VOLUME_IMAGE: u8 = 0x17;
+pub const EFI_SECTION_FREEFORM_SUBTYPE_GUID: u8 = 0x18;
+pub const EFI_SECTION_RAW: u8 = 0x19;
+pub const EFI_SECTION_PEI_DEPEX: u8 = 0x1B;
+pub const EFI_SECTION_MM_DEPEX: u8 = 0x1C;
+
+const FV_SIGNATURE: &[u8] = b"_FVH";
+const FV_HEADER_SIZE: usize = 56; // Up to the block map
+const BLOCK_MAP_ENTRY_SIZE: usize = 8;
+
+const FFS_HEADER_SIZE: usize = 24;
+const FFS_HEADER2_SIZE: usize = 32;
+const FFS_ATTRIB_LARGE_FILE: u8 = 0x01;
+const FFS_ATTRIB_CHECKSUM: u8 = 0x40;
+const FFS_FIXED_CHECKSUM: u8 = 0xAA;
+const FFS_ALIGNMENT: usize = 8;
+
+const EFI_FILE_DATA_VALID: u8 = 0x04;
+const EFI_FILE_MARKED_FOR_UPDATE: u8 = 0x08;
+
+const SECTION_HEADER_SIZE: usize = 4;
+const SECTION_HEADER2_SIZE: usize = 8;
+const SECTION_ALIGNMENT: usize = 4;
+const EFI_NOT_COMPRESSED: u8 = 0;
+const EFI_STANDARD_COMPRESSION: u8 = 1;
+const EFI_GUIDED_SECTION_PROCESSING_REQUIRED: u16 = 0x01;
+
+// How deep encapsulation sections may nest before the data is taken to be malicious
+const MAX_NESTING: usize = 16;
+
+fn corrupt() -> ::EfiError {
+    EfiErrorKind::VolumeCorrupted.into()
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+fn read_guid(buf: &[u8]) -> EFI_GUID {
+    let mut tail = [0u8; 8];
+    tail.copy_from_slice(&buf[8..16]);
+    EFI_GUID(LittleEndian::read_u32(&buf[0..4]), LittleEndian::read_u16(&buf[4..6]), LittleEndian::read_u16(&buf[6..8]), tail)
+}
+
+fn sum8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+#[derive(Debug, Clone)]
+pub struct FirmwareVolume<'a> {
+    data: &'a [u8],
+    file_system: EFI_GUID,
+    attributes: u32,
+    name: Option<EFI_GUID>,
+    files_offset: usize,
+}
+
+impl<'a> FirmwareVolume<'a> {
+    /// Parses the volume at the start of `data`, which may carry on past the end of the volume. The volume could be
+    /// a flash dump read from a file or a section's data as well as the real thing
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        if data.len() < FV_HEADER_SIZE || &data[40..44] != FV_SIGNATURE {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+
+        let len = LittleEndian::read_u64(&data[32..40]);
+        let header_len = LittleEndian::read_u16(&data[48..50]) as usize;
+        if len > data.len() as u64 || header_len < FV_HEADER_SIZE + BLOCK_MAP_ENTRY_SIZE || header_len as u64 > len {
+            return Err(corrupt());
+        }
+        let data = &data[..len as usize];
+        let checksum = data[..header_len].chunks(2).fold(0u16, |sum, w| sum.wrapping_add(LittleEndian::read_u16(w)));
+        if checksum != 0 {
+            return Err(EfiErrorKind::CrcError.into());
+        }
+
+        // The extended header, if there is one, names the volume and comes before the files
+        let mut name = None;
+        let mut files_offset = header_len;
+        let ext_offset = LittleEndian::read_u16(&data[52..54]) as usize;
+        if ext_offset != 0 {
+            if ext_offset + 20 > data.len() {
+                return Err(corrupt());
+            }
+            name = Some(read_guid(&data[ext_offset..]));
+            files_offset = ext_offset + LittleEndian::read_u32(&data[ext_offset + 16..ext_offset + 20]) as usize;
+        }
+
+        Ok(Self {
+            data,
+            file_system: read_guid(&data[16..32]),
+            attributes: LittleEndian::read_u32(&data[44..48]),
+            name,
+            files_offset: align(files_offset, FFS_ALIGNMENT),
+        })
+    }
+
+    /// Parses the volume mapped at `address`, such as one in memory mapped flash
+    pub unsafe fn from_address(address: usize) -> Result<FirmwareVolume<'static>> {
+        let header = slice::from_raw_parts(address as *const u8, FV_HEADER_SIZE);
+        if &header[40..44] != FV_SIGNATURE {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        let len = LittleEndian::read_u64(&header[32..40]) as usize;
+        FirmwareVolume::new(slice::from_raw_parts(address as *const u8, len))
+    }
+
+    /// The whole volume, header included
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The format of the volume's contents. Only EFI_FIRMWARE_FILE_SYSTEM2_GUID and EFI_FIRMWARE_FILE_SYSTEM3_GUID
+    /// volumes have files
+    pub fn file_system(&self) -> EFI_GUID {
+        self.file_system
+    }
+
+    /// EFI_FVB2_* attributes
+    pub fn attributes(&self) -> u32 {
+        self.attributes
+    }
+
+    /// The volume's name from its extended header
+    pub fn name(&self) -> Option<EFI_GUID> {
+        self.name
+    }
+
+    /// The files in the volume, skipping deleted ones. Stops after the first error
+    pub fn files(&self) -> Files<'a> {
+        let supported = self.file_system == EFI_FIRMWARE_FILE_SYSTEM2_GUID || self.file_system == EFI_FIRMWARE_FILE_SYSTEM3_GUID;
+        Files {
+            data: self.data,
+            offset: if supported { self.files_offset } else { self.data.len() },
+            erase_byte: if self.attributes & EFI_FVB2_ERASE_POLARITY != 0 { 0xFF } else { 0x00 },
+            error: if supported { None } else { Some(EfiErrorKind::Unsupported.into()) },
+        }
+    }
+
+    pub fn find_file(&self, name: &EFI_GUID) -> Result<Option<FirmwareFile<'a>>> {
+        for file in self.files() {
+            let file = file?;
+            if file.name == *name {
+                return Ok(Some(file));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Every volume in a flash image, found by looking for volume headers at each 8 byte boundary
+pub fn find_volumes(image: &[u8]) -> Vec<FirmwareVolume<'_>> {
+    let mut volumes = Vec::new();
+    let mut offset = 0;
+    while offset + FV_HEADER_SIZE <= image.len() {
+        match FirmwareVolume::new(&image[offset..]) {
+            Ok(volume) => {
+                offset += align(volume.size(), FFS_ALIGNMENT);
+                volumes.push(volume);
+            },
+            Err(_) => offset += FFS_ALIGNMENT,
+        }
+    }
+    volumes
+}
+
+pub struct Files<'a> {
+    data: &'a [u8],
+    offset: usize,
+    erase_byte: u8,
+    error: Option<::EfiError>,
+}
+
+impl<'a> Files<'a> {
+    fn next_file(&mut self) -> Result<Option<FirmwareFile<'a>>> {
+        loop {
+            // Files end where the free space starts
+            let start = self.offset;
+            if start + FFS_HEADER_SIZE > self.data.len()
+                || self.data[start..start + FFS_HEADER_SIZE].iter().all(|&b| b == self.erase_byte) {
+                return Ok(None);
+            }
+
+            let header = &self.data[start..start + FFS_HEADER_SIZE];
+            let attributes = header[19];
+            let (header_len, size) = if attributes & FFS_ATTRIB_LARGE_FILE != 0 {
+                if start + FFS_HEADER2_SIZE > self.data.len() {
+                    return Err(corrupt());
+                }
+                (FFS_HEADER2_SIZE, LittleEndian::read_u64(&self.data[start + 24..start + 32]))
+            } else {
+                (FFS_HEADER_SIZE, LittleEndian::read_u24(&header[20..23]) as u64)
+            };
+            if size < header_len as u64 || size > (self.data.len() - start) as u64 {
+                return Err(corrupt());
+            }
+            let end = start + size as usize;
+            self.offset = align(end, FFS_ALIGNMENT);
+
+            // The most significant state bit set says which state the file's got to. Anything short of valid data is
+            // a file that was never finished, and anything past being marked for update is a file that's been deleted
+            let mut state = header[23];
+            if self.erase_byte != 0 {
+                state = !state;
+            }
+            let state = if state == 0 { 0 } else { 0x80 >> state.leading_zeros() };
+            if state != EFI_FILE_DATA_VALID && state != EFI_FILE_MARKED_FOR_UPDATE {
+                continue;
+            }
+
+            // The header checksum covers the whole header bar the state and the file checksum
+            let header = &self.data[start..start + header_len];
+            if sum8(header).wrapping_sub(header[17]).wrapping_sub(header[23]) != 0 {
+                return Err(EfiErrorKind::CrcError.into());
+            }
+            let data = &self.data[start + header_len..end];
+            let file_checksum = header[17];
+            let valid = if attributes & FFS_ATTRIB_CHECKSUM != 0 {
+                sum8(data).wrapping_add(file_checksum) == 0
+            } else {
+                file_checksum == FFS_FIXED_CHECKSUM
+            };
+            if !valid {
+                return Err(EfiErrorKind::CrcError.into());
+            }
+
+            return Ok(Some(FirmwareFile { name: read_guid(header), file_type: header[18], attributes, data }));
+        }
+    }
+}
+
+impl<'a> Iterator for Files<'a> {
+    type Item = Result<FirmwareFile<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            self.offset = self.data.len();
+            return Some(Err(e));
+        }
+        match self.next_file() {
+            Ok(file) => file.map(Ok),
+            Err(e) => {
+                self.offset = self.data.len();
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FirmwareFile<'a> {
+    pub name: EFI_GUID,
+    /// One of the EFI_FV_FILETYPE_* constants
+    pub file_type: u8,
+    pub attributes: u8,
+    data: &'a [u8],
+}
+
+impl<'a> FirmwareFile<'a> {
+    /// The file's contents, after its header
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The file's sections with the encapsulation sections unwrapped. Raw and pad files don't have any
+    pub fn sections(&self) -> Result<Vec<Section<'a>>> {
+        let mut sections = Vec::new();
+        if self.file_type != EFI_FV_FILETYPE_RAW && self.file_type != EFI_FV_FILETYPE_FFS_PAD {
+            parse_sections(self.data, 0, &mut sections)?;
+        }
+        Ok(sections)
+    }
+
+    /// The name in the file's UI section, which is what tools show rather than the GUID
+    pub fn user_interface(&self) -> Result<Option<String>> {
+        Ok(self.sections()?.iter().filter_map(Section::user_interface).next())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Section<'a> {
+    /// One of the EFI_SECTION_* constants
+    pub section_type: u8,
+    /// The subtype of a freeform subtype GUID section, or the format of a GUID defined section that couldn't be
+    /// unwrapped
+    pub guid: Option<EFI_GUID>,
+    /// The contents after the section's header. A firmware volume image section's can be parsed with
+    /// FirmwareVolume::new()
+    pub data: Cow<'a, [u8]>,
+}
+
+impl<'a> Section<'a> {
+    /// The string in a UI section
+    pub fn user_interface(&self) -> Option<String> {
+        if self.section_type != EFI_SECTION_USER_INTERFACE {
+            return None;
+        }
+        let units = self.data.chunks_exact(2).map(LittleEndian::read_u16).take_while(|&c| c != 0);
+        Some(core::char::decode_utf16(units).map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER)).collect())
+    }
+
+    fn into_owned<'b>(self) -> Section<'b> {
+        Section { section_type: self.section_type, guid: self.guid, data: Cow::Owned(self.data.into_owned()) }
+    }
+}
+
+fn parse_sections<'a>(data: &'a [u8], depth: usize, out: &mut Vec<Section<'a>>) -> Result<()> {
+    if depth > MAX_NESTING {
+        return Err(corrupt());
+    }
+
+    let mut offset = 0;
+    while offset + SECTION_HEADER_SIZE <= data.len() {
+        let mut size = LittleEndian::read_u24(&data[offset..offset + 3]) as usize;
+        let section_type = data[offset + 3];
+        let mut header_len = SECTION_HEADER_SIZE;
+        if size == 0xFF_FFFF {
+            if offset + SECTION_HEADER2_SIZE > data.len() {
+                return Err(corrupt());
+            }
+            size = LittleEndian::read_u32(&data[offset + 4..offset + 8]) as usize;
+            header_len = SECTION_HEADER2_SIZE;
+        }
+        if size < header_len || size > data.len() - offset {
+            return Err(corrupt());
+        }
+        let section = &data[offset..offset + size];
+        offset = align(offset + size, SECTION_ALIGNMENT);
+
+        let body = &section[header_len..];
+        match section_type {
+            EFI_SECTION_COMPRESSION => {
+                if body.len() < 5 {
+                    return Err(corrupt());
+                }
+                let len = LittleEndian::read_u32(&body[0..4]) as usize;
+                let compressed = &body[5..];
+                match body[4] {
+                    EFI_NOT_COMPRESSED => parse_sections(compressed, depth + 1, out)?,
+                    EFI_STANDARD_COMPRESSION => {
+                        // Some older firmware uses the Tiano format here despite what the spec says
+                        let inner = tiano::decompress(compressed, tiano::Version::Efi)
+                            .or_else(|_| tiano::decompress(compressed, tiano::Version::Tiano))?;
+                        if inner.len() != len {
+                            return Err(corrupt());
+                        }
+                        parse_owned(inner, depth, out)?;
+                    },
+                    _ => return Err(EfiErrorKind::Unsupported.into()),
+                }
+            },
+            EFI_SECTION_GUID_DEFINED => {
+                if body.len() < 20 {
+                    return Err(corrupt());
+                }
+                let guid = read_guid(body);
+                let data_offset = LittleEndian::read_u16(&body[16..18]) as usize;
+                let attributes = LittleEndian::read_u16(&body[18..20]);
+                if data_offset < header_len + 20 || data_offset > section.len() {
+                    return Err(corrupt());
+                }
+                let inner = &section[data_offset..];
+
+                if guid == LZMA_CUSTOM_DECOMPRESS_GUID {
+                    let mut decompressed = Vec::new();
+                    LzmaDecoder::new(inner).read_to_end(&mut decompressed).map_err(|_| corrupt())?;
+                    parse_owned(decompressed, depth, out)?;
+                } else if guid == TIANO_CUSTOM_DECOMPRESS_GUID {
+                    parse_owned(tiano::decompress(inner, tiano::Version::Tiano)?, depth, out)?;
+                } else if guid == EFI_CRC32_GUIDED_SECTION_EXTRACTION_GUID {
+                    if body.len() < 24 {
+                        return Err(corrupt());
+                    }
+                    if crc32(inner) != LittleEndian::read_u32(&body[20..24]) {
+                        return Err(EfiErrorKind::CrcError.into());
+                    }
+                    parse_sections(inner, depth + 1, out)?;
+                } else if attributes & EFI_GUIDED_SECTION_PROCESSING_REQUIRED == 0 {
+                    // The sections are usable as they are even if we don't know what the GUID's about
+                    parse_sections(inner, depth + 1, out)?;
+                } else {
+                    out.push(Section { section_type, guid: Some(guid), data: Cow::Borrowed(inner) });
+                }
+            },
+            EFI_SECTION_DISPOSABLE => parse_sections(body, depth + 1, out)?,
+            EFI_SECTION_FREEFORM_SUBTYPE_GUID => {
+                if body.len() < 16 {
+                    return Err(corrupt());
+                }
+                out.push(Section { section_type, guid: Some(read_guid(body)), data: Cow::Borrowed(&body[16..]) });
+            },
+            _ => out.push(Section { section_type, guid: None, data: Cow::Borrowed(body) }),
+        }
+    }
+    Ok(())
+}
+
+// Sections in data that had to be decompressed, and so can't be borrowed from the volume
+fn parse_owned<'a>(data: Vec<u8>, depth: usize, out: &mut Vec<Section<'a>>) -> Result<()> {
+    let mut inner = Vec::new();
+    parse_sections(&data, depth + 1, &mut inner)?;
+    out.extend(inner.into_iter().map(Section::into_owned));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRIVER_GUID: EFI_GUID = EFI_GUID(0x12345678, 0x9ABC, 0xDEF0, [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    const APP_GUID: EFI_GUID = EFI_GUID(0x0FEDCBA9, 0x8765, 0x4321, [0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE]);
+
+    // A raw section holding "Hello from an LZMA section", as written by xz --format=lzma
+    const HELLO_LZMA: &[u8] = &[
+        0x5d, 0x00, 0x00, 0x04, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x0e, 0xff, 0xfc, 0x02, 0xef, 0x38, 0xb8, 0x52, 0xe0, 0x60,
+        0xf3, 0x7c, 0xd4, 0xd7, 0x56, 0x7f, 0x9b, 0x84, 0x08, 0x7b, 0xcf, 0xec, 0x41, 0xe3, 0xc9, 0xf5, 0xa7, 0x29, 0x0d, 0x7c, 0x72, 0x39, 0xbc, 0x7f,
+        0x0e, 0x52, 0xdb, 0xff, 0xff, 0x0d, 0x14, 0x00, 0x00,
+    ];
+
+    fn guid_bytes(guid: &EFI_GUID) -> Vec<u8> {
+        let mut buf = vec![0u8; 16];
+        LittleEndian::write_u32(&mut buf[0..4], guid.0);
+        LittleEndian::write_u16(&mut buf[4..6], guid.1);
+        LittleEndian::write_u16(&mut buf[6..8], guid.2);
+        buf[8..16].copy_from_slice(&guid.3);
+        buf
+    }
+
+    fn section(section_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut s = vec![0u8; 4];
+        LittleEndian::write_u24(&mut s[0..3], (body.len() + 4) as u32);
+        s[3] = section_type;
+        s.extend_from_slice(body);
+        while s.len() % 4 != 0 {
+            s.push(0);
+        }
+        s
+    }
+
+    fn ui(name: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        for c in name.encode_utf16().chain(Some(0)) {
+            body.extend_from_slice(&c.to_le_bytes());
+        }
+        section(EFI_SECTION_USER_INTERFACE, &body)
+    }
+
+    fn file(name: &EFI_GUID, file_type: u8, data: &[u8]) -> Vec<u8> {
+        let mut f = guid_bytes(name);
+        f.extend_from_slice(&[0, 0, file_type, FFS_ATTRIB_CHECKSUM, 0, 0, 0, 0]);
+        LittleEndian::write_u24(&mut f[20..23], (data.len() + FFS_HEADER_SIZE) as u32);
+        f[16] = 0u8.wrapping_sub(sum8(&f));
+        f[17] = 0u8.wrapping_sub(sum8(data));
+        f[23] = !(0x07); // Header and data valid, inverted for erase polarity 1
+        f.extend_from_slice(data);
+        f
+    }
+
+    // A volume with erase polarity 1 holding the given files
+    fn volume(files: &[Vec<u8>]) -> Vec<u8> {
+        let mut fv = vec![0u8; 72];
+        fv[16..32].copy_from_slice(&guid_bytes(&EFI_FIRMWARE_FILE_SYSTEM2_GUID));
+        fv[40..44].copy_from_slice(FV_SIGNATURE);
+        LittleEndian::write_u32(&mut fv[44..48], EFI_FVB2_ERASE_POLARITY);
+        LittleEndian::write_u16(&mut fv[48..50], 72);
+        fv[55] = 2;
+        LittleEndian::write_u32(&mut fv[56..60], 1); // One block, then the block map's terminator
+        for f in files {
+            fv.extend_from_slice(f);
+            while fv.len() % 8 != 0 {
+                fv.push(0xFF);
+            }
+        }
+        fv.resize(fv.len() + 64, 0xFF);
+        let len = fv.len() as u64;
+        LittleEndian::write_u64(&mut fv[32..40], len);
+        LittleEndian::write_u32(&mut fv[60..64], len as u32);
+        let checksum = fv[..72].chunks(2).fold(0u16, |sum, w| sum.wrapping_add(LittleEndian::read_u16(w)));
+        LittleEndian::write_u16(&mut fv[50..52], 0u16.wrapping_sub(checksum));
+        fv
+    }
+
+    fn image() -> Vec<u8> {
+        // The driver's sections are LZMA compressed and the application is just a UI section and a PE32 one
+        let mut guided = guid_bytes(&LZMA_CUSTOM_DECOMPRESS_GUID);
+        guided.extend_from_slice(&[24, 0, EFI_GUIDED_SECTION_PROCESSING_REQUIRED as u8, 0]);
+        guided.extend_from_slice(HELLO_LZMA);
+        let driver = [section(EFI_SECTION_GUID_DEFINED, &guided), ui("Driver")].concat();
+        let app = [ui("App"), section(EFI_SECTION_PE32, b"MZ")].concat();
+
+        let mut image = vec![0xFF; 40];
+        image.extend(volume(&[file(&DRIVER_GUID, EFI_FV_FILETYPE_DRIVER, &driver), file(&APP_GUID, EFI_FV_FILETYPE_APPLICATION, &app)]));
+        image
+    }
+
+    #[test]
+    fn parses_files_and_sections() {
+        let image = image();
+        let volumes = find_volumes(&image);
+        assert_eq!(volumes.len(), 1);
+        let files = volumes[0].files().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(files.len(), 2);
+
+        let sections = files[0].sections().unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!((sections[0].section_type, &sections[0].data[..26]), (EFI_SECTION_RAW, &b"Hello from an LZMA section"[..]));
+        assert_eq!(files[0].user_interface().unwrap().unwrap(), "Driver");
+
+        let app = volumes[0].find_file(&APP_GUID).unwrap().unwrap();
+        assert_eq!(app.file_type, EFI_FV_FILETYPE_APPLICATION);
+        assert_eq!(app.user_interface().unwrap().unwrap(), "App");
+        let sections = app.sections().unwrap();
+        assert_eq!((sections[1].section_type, &sections[1].data[..]), (EFI_SECTION_PE32, &b"MZ"[..]));
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut image = image();
+        let pe32 = image.windows(2).position(|w| w == b"MZ").unwrap();
+        image[pe32 + 1] ^= 1;
+        let volume = FirmwareVolume::new(&image[40..]).unwrap();
+        let results = volume.files().collect::<Vec<_>>();
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().kind(), EfiErrorKind::CrcError);
+    }
+}
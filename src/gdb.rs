@@ -0,0 +1,519 @@
+// A GDB remote serial protocol stub, x64 only.
+// start() takes over a serial port, hooks the breakpoint and single step exceptions through the firmware's
+// EFI_DEBUG_SUPPORT_PROTOCOL and then breaks in, waiting for GDB to attach:
+//
+//     (gdb) target remote /dev/ttyS0
+//
+// Under QEMU give the guest a second serial port for it (-serial stdio -serial tcp::1234,server,nowait) and use
+// `target remote :1234`. GDB needs our symbols at the address firmware loaded us to, so load them with
+// add-symbol-file and the image base (printed in backtraces, see the backtrace module).
+// From then on every int3 stops in the stub, whether one of GDB's breakpoints or a call to breakpoint(). Only the
+// basics are there: the general purpose registers, memory, software breakpoints, continue and single step. Reading
+// memory that isn't mapped takes the machine down, as does stopping somewhere the serial port can't be used from
+// (e.g. in its own driver).
+
+use ffi::debug_support::{
+    EFI_DEBUG_SUPPORT_PROTOCOL,
+    EFI_DEBUG_SUPPORT_PROTOCOL_GUID,
+    EFI_EXCEPTION_TYPE,
+    EFI_SYSTEM_CONTEXT,
+    EFI_SYSTEM_CONTEXT_X64,
+    IsaX64,
+    EXCEPT_X64_DEBUG,
+    EXCEPT_X64_BREAKPOINT,
+};
+use serial::SerialPort;
+use arch::x86::{read_cr0, write_cr0};
+use io::{Read, Write};
+use {Result, EfiErrorKind, system_table};
+use core::{mem, ptr, str};
+use alloc::{boxed::Box, vec::Vec};
+
+const INT3: u8 = 0xCC;
+const SIGTRAP: u8 = 5;
+const RFLAGS_TF: u32 = 1 << 8; // Trap after every instruction
+const CR0_WP: usize = 1 << 16; // Write protect, which firmware may use to keep code read only
+
+/// What the stub is given control of the target through
+pub(crate) struct Stub<P: Read + Write> {
+    port: P,
+    breakpoints: Vec<(u64, u8)>, // Address and the byte the int3 replaced
+    running: bool, // Whether GDB is waiting to hear that we've stopped
+    unprotect_code: bool, // Whether CR0.WP has to be cleared to put breakpoints in (not when testing on the host)
+}
+
+/// How to carry on after a stop
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Resume {
+    Continue,
+    Step,
+    Detach,
+}
+
+/// What the 'g' packet carries, in its order
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Registers {
+    pub gprs: [u64; 16], // rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8-r15
+    pub rip: u64,
+    pub eflags: u32,
+    pub segments: [u32; 6], // cs, ss, ds, es, fs, gs
+}
+
+impl Registers {
+    fn from_context(context: &EFI_SYSTEM_CONTEXT_X64) -> Self {
+        let c = context;
+        Registers {
+            gprs: [c.Rax, c.Rbx, c.Rcx, c.Rdx, c.Rsi, c.Rdi, c.Rbp, c.Rsp, c.R8, c.R9, c.R10, c.R11, c.R12, c.R13, c.R14, c.R15],
+            rip: c.Rip,
+            eflags: c.Rflags as u32,
+            segments: [c.Cs as u32, c.Ss as u32, c.Ds as u32, c.Es as u32, c.Fs as u32, c.Gs as u32],
+        }
+    }
+
+    // Segment registers are left alone. Changing them is never what anyone debugging an application wants
+    fn write_to(&self, context: &mut EFI_SYSTEM_CONTEXT_X64) {
+        let c = context;
+        let g = self.gprs;
+        c.Rax = g[0]; c.Rbx = g[1]; c.Rcx = g[2]; c.Rdx = g[3]; c.Rsi = g[4]; c.Rdi = g[5]; c.Rbp = g[6]; c.Rsp = g[7];
+        c.R8 = g[8]; c.R9 = g[9]; c.R10 = g[10]; c.R11 = g[11]; c.R12 = g[12]; c.R13 = g[13]; c.R14 = g[14]; c.R15 = g[15];
+        c.Rip = self.rip;
+        c.Rflags = (c.Rflags & !0xFFFF_FFFF) | self.eflags as u64;
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &gpr in self.gprs.iter().chain(Some(&self.rip)) {
+            push_hex(&mut out, &gpr.to_le_bytes());
+        }
+        for &reg in Some(&self.eflags).into_iter().chain(self.segments.iter()) {
+            push_hex(&mut out, &reg.to_le_bytes());
+        }
+        out
+    }
+
+    fn decode(&mut self, hex: &[u8]) -> Option<()> {
+        let bytes = from_hex(hex)?;
+        if bytes.len() < 17 * 8 + 7 * 4 {
+            return None;
+        }
+        for n in 0..24 {
+            self.set(n, &bytes[offset(n)..offset(n + 1)])?;
+        }
+        Some(())
+    }
+
+    // Register n as little endian bytes
+    fn get(&self, n: usize) -> Option<Vec<u8>> {
+        match n {
+            0..=15 => Some(self.gprs[n].to_le_bytes().to_vec()),
+            16 => Some(self.rip.to_le_bytes().to_vec()),
+            17 => Some(self.eflags.to_le_bytes().to_vec()),
+            18..=23 => Some(self.segments[n - 18].to_le_bytes().to_vec()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, n: usize, bytes: &[u8]) -> Option<()> {
+        let mut value = [0u8; 8];
+        if bytes.len() != offset(n + 1) - offset(n) {
+            return None;
+        }
+        value[..bytes.len()].copy_from_slice(bytes);
+        let value = u64::from_le_bytes(value);
+        match n {
+            0..=15 => self.gprs[n] = value,
+            16 => self.rip = value,
+            17 => self.eflags = value as u32,
+            18..=23 => self.segments[n - 18] = value as u32,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+// Where register n starts in the 'g' packet's bytes
+fn offset(n: usize) -> usize {
+    if n <= 17 { n * 8 } else { 17 * 8 + (n - 17) * 4 }
+}
+
+impl<P: Read + Write> Stub<P> {
+    pub(crate) fn new(port: P, unprotect_code: bool) -> Self {
+        Stub { port, breakpoints: Vec::new(), running: false, unprotect_code }
+    }
+
+    fn has_breakpoint(&self, address: u64) -> bool {
+        self.breakpoints.iter().any(|&(a, _)| a == address)
+    }
+
+    /// The target has stopped. Talks to GDB until it says to carry on
+    pub(crate) fn stopped(&mut self, regs: &mut Registers, signal: u8) -> Resume {
+        if self.running {
+            self.send(format!("S{:02x}", signal).as_bytes());
+            self.running = false;
+        }
+
+        loop {
+            let packet = self.receive();
+            if let Some(resume) = self.handle(&packet, regs, signal) {
+                match resume {
+                    Resume::Continue => regs.eflags &= !RFLAGS_TF,
+                    Resume::Step => regs.eflags |= RFLAGS_TF,
+                    Resume::Detach => {
+                        regs.eflags &= !RFLAGS_TF;
+                        self.remove_breakpoints();
+                    }
+                }
+                self.running = resume != Resume::Detach;
+                return resume;
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &[u8], regs: &mut Registers, signal: u8) -> Option<Resume> {
+        let (command, args) = match packet.split_first() {
+            Some((&command, args)) => (command, args),
+            None => {
+                self.send(b"");
+                return None;
+            }
+        };
+
+        let reply = match command {
+            b'?' => format!("S{:02x}", signal).into_bytes(),
+            b'g' => regs.encode(),
+            b'G' => ok_or_error(regs.decode(args)),
+            b'p' => match parse_hex(args).and_then(|n| regs.get(n as usize)) {
+                Some(value) => hex(&value),
+                None => b"E01".to_vec(),
+            },
+            b'P' => ok_or_error(split(args, b'=').and_then(|(n, value)| regs.set(parse_hex(n)? as usize, &from_hex(value)?))),
+            b'm' => match split(args, b',').and_then(|(address, len)| Some((parse_hex(address)?, parse_hex(len)?))) {
+                Some((address, len)) => hex(&self.read_memory(address, len as usize)),
+                None => b"E01".to_vec(),
+            },
+            b'M' => {
+                let write = split(args, b':').and_then(|(range, data)| Some((split(range, b',')?.0, from_hex(data)?)));
+                ok_or_error(write.and_then(|(address, data)| {
+                    self.write_memory(parse_hex(address)?, &data);
+                    Some(())
+                }))
+            }
+            b'c' | b's' => {
+                if let Some(address) = parse_hex(args) {
+                    regs.rip = address;
+                }
+                return Some(if command == b'c' { Resume::Continue } else { Resume::Step });
+            }
+            b'Z' | b'z' if args.starts_with(b"0,") => {
+                let address = split(&args[2..], b',').and_then(|(address, _kind)| parse_hex(address));
+                ok_or_error(address.map(|address| {
+                    if command == b'Z' { self.insert_breakpoint(address) } else { self.remove_breakpoint(address) }
+                }))
+            }
+            b'D' => {
+                self.send(b"OK");
+                return Some(Resume::Detach);
+            }
+            b'k' => return Some(Resume::Detach),
+            b'H' => b"OK".to_vec(),
+            b'q' if args.starts_with(b"Supported") => b"PacketSize=400".to_vec(),
+            b'q' if args == b"Attached" => b"1".to_vec(),
+            _ => Vec::new(), // We don't do it
+        };
+        self.send(&reply);
+        None
+    }
+
+    fn insert_breakpoint(&mut self, address: u64) {
+        if !self.has_breakpoint(address) {
+            let original = self.read_memory(address, 1)[0];
+            self.write_memory(address, &[INT3]);
+            self.breakpoints.push((address, original));
+        }
+    }
+
+    fn remove_breakpoint(&mut self, address: u64) {
+        if let Some(i) = self.breakpoints.iter().position(|&(a, _)| a == address) {
+            let (_, original) = self.breakpoints.remove(i);
+            self.write_memory(address, &[original]);
+        }
+    }
+
+    fn remove_breakpoints(&mut self) {
+        while let Some(&(address, _)) = self.breakpoints.first() {
+            self.remove_breakpoint(address);
+        }
+    }
+
+    fn read_memory(&self, address: u64, len: usize) -> Vec<u8> {
+        (0..len).map(|i| unsafe { ptr::read_volatile((address as usize + i) as *const u8) }).collect()
+    }
+
+    fn write_memory(&mut self, address: u64, data: &[u8]) {
+        let cr0 = if self.unprotect_code { read_cr0() } else { 0 };
+        unsafe {
+            if cr0 & CR0_WP != 0 {
+                write_cr0(cr0 & !CR0_WP);
+            }
+            for (i, &b) in data.iter().enumerate() {
+                ptr::write_volatile((address as usize + i) as *mut u8, b);
+            }
+            if cr0 & CR0_WP != 0 {
+                write_cr0(cr0);
+            }
+        }
+    }
+
+    // The next packet with a good checksum. Acks and interrupts (Ctrl-C) outside of packets mean nothing while we're
+    // stopped
+    fn receive(&mut self) -> Vec<u8> {
+        loop {
+            while self.read_byte() != b'$' {}
+            let mut packet = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.read_byte() {
+                    b'#' => break,
+                    b => {
+                        sum = sum.wrapping_add(b);
+                        packet.push(b);
+                    }
+                }
+            }
+
+            let checksum = [self.read_byte(), self.read_byte()];
+            if parse_hex(&checksum) == Some(sum as u64) {
+                let _ = self.port.write_all(b"+");
+                return packet;
+            }
+            let _ = self.port.write_all(b"-");
+        }
+    }
+
+    // Sends until GDB acks it
+    fn send(&mut self, data: &[u8]) {
+        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(data);
+        packet.push(b'#');
+        push_hex(&mut packet, &[sum]);
+        loop {
+            let _ = self.port.write_all(&packet);
+            loop {
+                match self.read_byte() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut b = [0];
+        loop {
+            if let Ok(1) = self.port.read(&mut b) {
+                return b[0];
+            }
+        }
+    }
+}
+
+static mut STUB: *mut Stub<SerialPort> = ptr::null_mut(); // Boxed
+
+/// Starts debugging over `port` and waits for GDB to attach. Fails with NotFound if the firmware doesn't have
+/// EFI_DEBUG_SUPPORT_PROTOCOL and AlreadyStarted if something else is handling breakpoints (e.g. a debugger agent, or
+/// the backtrace module after catch_exceptions(), which doesn't handle them but has to be called after this)
+pub fn start(port: SerialPort) -> Result<()> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_DEBUG_SUPPORT_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_DEBUG_SUPPORT_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+    }
+
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+
+    if unsafe { (*protocol).Isa } != IsaX64 {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+
+    if !unsafe { STUB }.is_null() {
+        return Err(EfiErrorKind::AlreadyStarted.into());
+    }
+    unsafe { STUB = Box::into_raw(Box::new(Stub::new(port, true))) };
+
+    for &exception in &[EXCEPT_X64_BREAKPOINT, EXCEPT_X64_DEBUG] {
+        unsafe {
+            ret_on_err!(((*protocol).RegisterExceptionCallback)(protocol, 0, Some(on_exception), exception));
+        }
+    }
+
+    breakpoint();
+    Ok(())
+}
+
+/// Stops in the debugger. Does nothing much if start() hasn't been called, depending on how the firmware deals with an
+/// int3 nobody handles
+#[inline(always)]
+pub fn breakpoint() {
+    unsafe { asm!("int3") };
+}
+
+extern "efiapi" fn on_exception(exception: EFI_EXCEPTION_TYPE, context: EFI_SYSTEM_CONTEXT) {
+    let stub = unsafe { STUB };
+    if stub.is_null() {
+        return;
+    }
+
+    let context = unsafe { &mut *(context as *mut EFI_SYSTEM_CONTEXT_X64) };
+    let mut regs = Registers::from_context(context);
+    let stub = unsafe { &mut *stub };
+    // The int3 has been run, but GDB expects to be stopped at its breakpoint so it can put back what was there
+    if exception == EXCEPT_X64_BREAKPOINT && stub.has_breakpoint(regs.rip.wrapping_sub(1)) {
+        regs.rip -= 1;
+    }
+    stub.stopped(&mut regs, SIGTRAP);
+    regs.write_to(context);
+}
+
+fn split(s: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let i = s.iter().position(|&b| b == separator)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    u64::from_str_radix(str::from_utf8(s).ok()?, 16).ok()
+}
+
+fn from_hex(s: &[u8]) -> Option<Vec<u8>> {
+    if s.len() & 1 != 0 {
+        return None;
+    }
+    s.chunks(2).map(|pair| parse_hex(pair).map(|b| b as u8)).collect()
+}
+
+fn hex(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() * 2);
+    push_hex(&mut out, bytes);
+    out
+}
+
+fn push_hex(out: &mut Vec<u8>, bytes: &[u8]) {
+    const DIGITS: &[u8] = b"0123456789abcdef";
+    for &b in bytes {
+        out.push(DIGITS[(b >> 4) as usize]);
+        out.push(DIGITS[(b & 0xF) as usize]);
+    }
+}
+
+fn ok_or_error(result: Option<()>) -> Vec<u8> {
+    match result {
+        Some(()) => b"OK".to_vec(),
+        None => b"E01".to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io;
+    use alloc::{collections::VecDeque, string::String};
+
+    struct FakePort {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            buf[0] = self.input.pop_front().expect("the stub wanted more from GDB");
+            Ok(1)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn to_string(packet: &[u8]) -> String {
+        String::from_utf8_lossy(packet).into_owned()
+    }
+
+    fn packet(data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        format!("${}#{:02x}", data, sum)
+    }
+
+    fn stub(gdb: &[String]) -> Stub<FakePort> {
+        let input = gdb.concat().into_bytes().into_iter().collect();
+        Stub::new(FakePort { input, output: Vec::new() }, false)
+    }
+
+    #[test]
+    fn reads_and_writes_memory_and_breakpoints() {
+        let mut memory = Box::new([0x55u8, 0x48, 0x89, 0xe5]);
+        let address = memory.as_mut_ptr() as u64;
+        let mut stub = stub(&[
+            packet(&format!("m{:x},4", address)), "+".into(),
+            packet(&format!("Z0,{:x},1", address + 1)), "+".into(),
+            packet(&format!("M{:x},1:90", address + 3)), "+".into(),
+            "$c#00".into(), // Bad checksum, so GDB has to send it again
+            packet("c"),
+        ]);
+
+        let mut regs = Registers::default();
+        assert_eq!(stub.stopped(&mut regs, SIGTRAP), Resume::Continue);
+        assert_eq!(*memory, [0x55, INT3, 0x89, 0x90]);
+        let expected = ["+", &packet("554889e5"), "+", &packet("OK"), "+", &packet("OK"), "-", "+"].concat();
+        assert_eq!(to_string(&stub.port.output), expected);
+
+        // The next stop is reported, then GDB takes its breakpoint out and steps over it
+        stub.port.output.clear();
+        stub.port.input.extend(["+", &packet(&format!("z0,{:x},1", address + 1)), "+", &packet("s")].concat().bytes());
+        assert_eq!(stub.stopped(&mut regs, SIGTRAP), Resume::Step);
+        assert_eq!(memory[1], 0x48);
+        assert_eq!(regs.eflags & RFLAGS_TF, RFLAGS_TF);
+        assert!(to_string(&stub.port.output).starts_with(&packet("S05")));
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let mut regs = Registers::default();
+        regs.gprs[0] = 0x1122334455667788;
+        regs.rip = 0x401000;
+        regs.eflags = 0x246;
+        regs.segments[0] = 0x38;
+        let encoded = to_string(&regs.encode());
+        assert_eq!(&encoded[..16], "8877665544332211");
+        assert_eq!(encoded.len(), 17 * 16 + 7 * 8);
+
+        let mut changed = encoded.clone();
+        changed.replace_range(16 * 16..17 * 16, "0020400000000000"); // rip
+        let mut stub = stub(&[
+            packet(&format!("G{}", changed)), "+".into(),
+            packet("p10"), "+".into(),
+            packet("P0=0100000000000000"), "+".into(),
+            packet("qXfer:features:read:target.xml:0,ffb"), "+".into(),
+            packet("D"), "+".into(),
+        ]);
+        assert_eq!(stub.stopped(&mut regs, SIGTRAP), Resume::Detach);
+        assert_eq!(regs.rip, 0x402000);
+        assert_eq!(regs.gprs[0], 1);
+        assert_eq!(regs.eflags, 0x246);
+        let expected = ["+", &packet("OK"), "+", &packet("0020400000000000"), "+", &packet("OK"), "+", &packet(""), "+", &packet("OK")].concat();
+        assert_eq!(to_string(&stub.port.output), expected);
+    }
+}
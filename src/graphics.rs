@@ -0,0 +1,447 @@
+// Displays via EFI_GRAPHICS_OUTPUT_PROTOCOL (GOP).
+// Every display the firmware drives has its own GOP handle; the console splitter adds one more that mirrors onto all
+// of them. Firmware tends to leave displays in whatever mode was quickest to set up, often 800x600 or 1024x768 on a
+// 4K panel, so set_best_mode() looks up the panel's native resolution in its EDID and switches to that.
+// screenshot() saves the screen as a BMP, which is handy for bug reports about boot menus and for checking them in
+// automated tests.
+
+use ffi::{
+    graphics::{
+        EFI_GRAPHICS_OUTPUT_PROTOCOL,
+        EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID,
+        EFI_GRAPHICS_OUTPUT_MODE_INFORMATION,
+        PIXEL_RED_GREEN_BLUE_RESERVED_8BIT_PER_COLOR,
+        PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR,
+        PIXEL_BIT_MASK,
+        EFI_GRAPHICS_OUTPUT_BLT_PIXEL,
+        EFI_GRAPHICS_OUTPUT_BLT_OPERATION,
+    },
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_GUID,
+    EFI_HANDLE,
+};
+use boxed::EfiBox;
+use edid::Edid;
+use boot_services::locate_handles;
+use {Result, EfiErrorKind, system_table, image_handle};
+use core::{fmt, mem, ptr};
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    pub fn new(width: u32, height: u32) -> Self {
+        Resolution { width, height }
+    }
+
+    fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// How pixels are laid out in the frame buffer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 32 bits a pixel: red, green, blue, reserved
+    Rgb,
+    /// 32 bits a pixel: blue, green, red, reserved
+    Bgr,
+    /// The red, green and blue masks of each 32 bit pixel
+    BitMask { red: u32, green: u32, blue: u32 },
+    /// No frame buffer. Drawing has to go through Blt
+    BltOnly,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mode {
+    pub number: u32,
+    pub resolution: Resolution,
+    pub pixel_format: PixelFormat,
+    /// Pixels per line in the frame buffer, which can be more than the width
+    pub stride: u32,
+}
+
+impl Mode {
+    fn from_info(number: u32, info: &EFI_GRAPHICS_OUTPUT_MODE_INFORMATION) -> Self {
+        let pixel_format = if info.PixelFormat == PIXEL_RED_GREEN_BLUE_RESERVED_8BIT_PER_COLOR {
+            PixelFormat::Rgb
+        } else if info.PixelFormat == PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR {
+            PixelFormat::Bgr
+        } else if info.PixelFormat == PIXEL_BIT_MASK {
+            let masks = &info.PixelInformation;
+            PixelFormat::BitMask { red: masks.RedMask, green: masks.GreenMask, blue: masks.BlueMask }
+        } else {
+            PixelFormat::BltOnly
+        };
+
+        Mode {
+            number,
+            resolution: Resolution::new(info.HorizontalResolution, info.VerticalResolution),
+            pixel_format,
+            stride: info.PixelsPerScanLine,
+        }
+    }
+}
+
+pub struct GraphicsOutput {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_GRAPHICS_OUTPUT_PROTOCOL,
+}
+
+impl GraphicsOutput {
+    /// One for every display, plus the console splitter's if there is one
+    pub fn all() -> Result<Vec<Self>> {
+        let mut outputs = Vec::new();
+        for handle in locate_handles(&EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID)? {
+            let protocol = open_protocol::<EFI_GRAPHICS_OUTPUT_PROTOCOL>(handle, &EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID);
+            if !protocol.is_null() {
+                outputs.push(GraphicsOutput { handle, protocol });
+            }
+        }
+        Ok(outputs)
+    }
+
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// The modes the display supports
+    pub fn modes(&self) -> Result<Vec<Mode>> {
+        let max_mode = unsafe { (*(*self.protocol).Mode).MaxMode };
+        let mut modes = Vec::new();
+        for number in 0..max_mode {
+            let mut size = 0;
+            let mut info = ptr::null_mut();
+            unsafe {
+                ret_on_err!(((*self.protocol).QueryMode)(self.protocol, number, &mut size, &mut info));
+                let info = EfiBox::from_raw(info); // The firmware allocated it, so we free it
+                modes.push(Mode::from_info(number, &*info.as_raw()));
+            }
+        }
+        Ok(modes)
+    }
+
+    pub fn current_mode(&self) -> Mode {
+        unsafe {
+            let mode = &*(*self.protocol).Mode;
+            Mode::from_info(mode.Mode, &*mode.Info)
+        }
+    }
+
+    /// The current mode's frame buffer as its physical address and size. None if the mode is BltOnly
+    pub fn frame_buffer(&self) -> Option<(u64, usize)> {
+        if self.current_mode().pixel_format == PixelFormat::BltOnly {
+            return None;
+        }
+        let mode = unsafe { &*(*self.protocol).Mode };
+        Some((mode.FrameBufferBase, mode.FrameBufferSize))
+    }
+
+    /// Switches modes. The screen is cleared even if it's already in this one
+    pub fn set_mode(&self, number: u32) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).SetMode)(self.protocol, number));
+        }
+        Ok(())
+    }
+
+    /// The EDID in use for the display. None for the console splitter and most virtual machines' displays
+    pub fn edid(&self) -> Result<Option<Edid>> {
+        Edid::active(self.handle)
+    }
+
+    /// The display's native resolution, according to its EDID
+    pub fn native_resolution(&self) -> Option<Resolution> {
+        self.edid().ok().and_then(|edid| edid?.native_resolution())
+    }
+
+    /// Switches to the native resolution if the display supports it, or else to the first of `preferred` that it does,
+    /// or else to its highest resolution. Leaves the display alone if it's already in that mode
+    pub fn set_best_mode(&self, preferred: &[Resolution]) -> Result<Mode> {
+        let modes = self.modes()?;
+        let best = match best_mode(&modes, self.native_resolution(), preferred) {
+            Some(best) => *best,
+            None => return Err(EfiErrorKind::Unsupported.into()),
+        };
+        if best.number != self.current_mode().number {
+            self.set_mode(best.number)?;
+        }
+        Ok(best)
+    }
+
+    /// Copies everything on the screen. Goes through Blt so it works whatever the pixel format, even with no frame
+    /// buffer
+    pub fn capture(&self) -> Result<Screenshot> {
+        let resolution = self.current_mode().resolution;
+        let (width, height) = (resolution.width as usize, resolution.height as usize);
+        let mut pixels = vec![EFI_GRAPHICS_OUTPUT_BLT_PIXEL::default(); width * height];
+        unsafe {
+            ret_on_err!(((*self.protocol).Blt)(self.protocol, pixels.as_mut_ptr(), EFI_GRAPHICS_OUTPUT_BLT_OPERATION::EfiBltVideoToBltBuffer, 0, 0, 0, 0, width, height, 0));
+        }
+        Ok(Screenshot { resolution, pixels })
+    }
+
+    /// Fills a rectangle with one color, given as red, green and blue
+    pub fn fill(&self, x: u32, y: u32, width: u32, height: u32, color: (u8, u8, u8)) -> Result<()> {
+        let mut pixel = to_blt_pixel(color);
+        unsafe {
+            ret_on_err!(((*self.protocol).Blt)(self.protocol, &mut pixel, EFI_GRAPHICS_OUTPUT_BLT_OPERATION::EfiBltVideoFill, 0, 0, x as usize, y as usize, width as usize, height as usize, 0));
+        }
+        Ok(())
+    }
+
+    /// A rectangle's pixels as red, green and blue, top row first
+    pub fn read(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Vec<(u8, u8, u8)>> {
+        let mut pixels = vec![EFI_GRAPHICS_OUTPUT_BLT_PIXEL::default(); width as usize * height as usize];
+        unsafe {
+            ret_on_err!(((*self.protocol).Blt)(self.protocol, pixels.as_mut_ptr(), EFI_GRAPHICS_OUTPUT_BLT_OPERATION::EfiBltVideoToBltBuffer, x as usize, y as usize, 0, 0, width as usize, height as usize, 0));
+        }
+        Ok(pixels.iter().map(|p| (p.Red, p.Green, p.Blue)).collect())
+    }
+
+    /// Draws a rectangle of pixels, top row first. InvalidParameter if there aren't width * height of them
+    pub fn write(&self, x: u32, y: u32, width: u32, height: u32, pixels: &[(u8, u8, u8)]) -> Result<()> {
+        if pixels.len() != width as usize * height as usize {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let mut pixels = pixels.iter().map(|&p| to_blt_pixel(p)).collect::<Vec<_>>();
+        unsafe {
+            ret_on_err!(((*self.protocol).Blt)(self.protocol, pixels.as_mut_ptr(), EFI_GRAPHICS_OUTPUT_BLT_OPERATION::EfiBltBufferToVideo, 0, 0, x as usize, y as usize, width as usize, height as usize, 0));
+        }
+        Ok(())
+    }
+
+    /// Copies a rectangle from one place on the screen to another
+    pub fn copy(&self, from: (u32, u32), to: (u32, u32), width: u32, height: u32) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).Blt)(self.protocol, ptr::null_mut(), EFI_GRAPHICS_OUTPUT_BLT_OPERATION::EfiBltVideoToVideo, from.0 as usize, from.1 as usize, to.0 as usize, to.1 as usize, width as usize, height as usize, 0));
+        }
+        Ok(())
+    }
+}
+
+fn to_blt_pixel((red, green, blue): (u8, u8, u8)) -> EFI_GRAPHICS_OUTPUT_BLT_PIXEL {
+    EFI_GRAPHICS_OUTPUT_BLT_PIXEL { Red: red, Green: green, Blue: blue, Reserved: 0 }
+}
+
+/// What was on a screen
+pub struct Screenshot {
+    resolution: Resolution,
+    pixels: Vec<EFI_GRAPHICS_OUTPUT_BLT_PIXEL>, // Top row first
+}
+
+impl Screenshot {
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Red, green and blue
+    pub fn pixel(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let p = self.pixels[(y * self.resolution.width + x) as usize];
+        (p.Red, p.Green, p.Blue)
+    }
+
+    /// As a 24 bit uncompressed BMP file
+    pub fn to_bmp(&self) -> Vec<u8> {
+        const HEADERS_SIZE: usize = 14 + 40;
+        let (width, height) = (self.resolution.width as usize, self.resolution.height as usize);
+        let row_size = (width * 3 + 3) & !3; // Rows are padded to 4 bytes
+        let image_size = row_size * height;
+
+        let mut bmp = vec![0; HEADERS_SIZE + image_size];
+        // BITMAPFILEHEADER
+        bmp[0..2].copy_from_slice(b"BM");
+        LittleEndian::write_u32(&mut bmp[2..6], (HEADERS_SIZE + image_size) as u32);
+        LittleEndian::write_u32(&mut bmp[10..14], HEADERS_SIZE as u32);
+        // BITMAPINFOHEADER. A positive height means the bottom row comes first
+        LittleEndian::write_u32(&mut bmp[14..18], 40);
+        LittleEndian::write_i32(&mut bmp[18..22], width as i32);
+        LittleEndian::write_i32(&mut bmp[22..26], height as i32);
+        LittleEndian::write_u16(&mut bmp[26..28], 1);
+        LittleEndian::write_u16(&mut bmp[28..30], 24);
+        LittleEndian::write_u32(&mut bmp[34..38], image_size as u32);
+        LittleEndian::write_u32(&mut bmp[38..42], 2835); // 72 DPI, in pixels per metre
+        LittleEndian::write_u32(&mut bmp[42..46], 2835);
+
+        for (y, row) in self.pixels.chunks(width.max(1)).enumerate() {
+            let start = HEADERS_SIZE + (height - 1 - y) * row_size;
+            for (x, p) in row.iter().enumerate() {
+                bmp[start + x * 3..start + x * 3 + 3].copy_from_slice(&[p.Blue, p.Green, p.Red]);
+            }
+        }
+        bmp
+    }
+}
+
+/// Saves what's on the console's screen as a BMP file on the volume we were loaded from
+pub fn screenshot(path: &str) -> Result<()> {
+    let output = console_output()?;
+    ::firmware::firmware().write_file(path, &output.capture()?.to_bmp())
+}
+
+// The GOP the console draws on: the console splitter's if there is one, else the first display
+fn console_output() -> Result<GraphicsOutput> {
+    let handle = system_table().ConsoleOutHandle;
+    let protocol = open_protocol::<EFI_GRAPHICS_OUTPUT_PROTOCOL>(handle, &EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID);
+    if !protocol.is_null() {
+        return Ok(GraphicsOutput { handle, protocol });
+    }
+    GraphicsOutput::all()?.into_iter().next().ok_or_else(|| EfiErrorKind::NotFound.into())
+}
+
+fn best_mode<'a>(modes: &'a [Mode], native: Option<Resolution>, preferred: &[Resolution]) -> Option<&'a Mode> {
+    let with = |resolution: Resolution| modes.iter().find(|m| m.resolution == resolution);
+    native.and_then(with)
+        .or_else(|| preferred.iter().filter_map(|r| with(*r)).next())
+        .or_else(|| modes.iter().rev().max_by_key(|m| m.resolution.pixels())) // Rev so that ties go to the lower mode number
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> *const T {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        let status = traced!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+        if !::ffi::IsSuccess(status) {
+            return ptr::null();
+        }
+    }
+    protocol
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{
+        boot_services::EFI_INTERFACE_TYPE,
+        graphics::{EFI_GRAPHICS_OUTPUT_PROTOCOL_MODE, EFI_PIXEL_BITMASK},
+        edid::{EFI_EDID_ACTIVE_PROTOCOL, EFI_EDID_ACTIVE_PROTOCOL_GUID},
+        EFI_STATUS,
+        EFI_SUCCESS,
+        EFI_UNSUPPORTED,
+        UINT32,
+        UINTN,
+        VOID,
+    };
+    use edid::build_edid;
+    use testing::mock;
+    use alloc::boxed::Box;
+
+    const RESOLUTIONS: [(u32, u32); 4] = [(800, 600), (1024, 768), (2560, 1440), (1920, 1080)];
+
+    #[repr(C)]
+    struct FakeGop {
+        protocol: EFI_GRAPHICS_OUTPUT_PROTOCOL,
+        mode: EFI_GRAPHICS_OUTPUT_PROTOCOL_MODE,
+        info: EFI_GRAPHICS_OUTPUT_MODE_INFORMATION,
+    }
+
+    fn info(number: u32) -> EFI_GRAPHICS_OUTPUT_MODE_INFORMATION {
+        let (width, height) = RESOLUTIONS[number as usize];
+        EFI_GRAPHICS_OUTPUT_MODE_INFORMATION {
+            Version: 0,
+            HorizontalResolution: width,
+            VerticalResolution: height,
+            PixelFormat: PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR,
+            PixelInformation: EFI_PIXEL_BITMASK { RedMask: 0, GreenMask: 0, BlueMask: 0, ReservedMask: 0 },
+            PixelsPerScanLine: width,
+        }
+    }
+
+    extern "efiapi" fn query_mode(_this: *const EFI_GRAPHICS_OUTPUT_PROTOCOL, number: UINT32, size: *mut UINTN, out: *mut *mut EFI_GRAPHICS_OUTPUT_MODE_INFORMATION) -> EFI_STATUS {
+        if number as usize >= RESOLUTIONS.len() {
+            return EFI_UNSUPPORTED;
+        }
+        unsafe {
+            let buf = EfiBox::<EFI_GRAPHICS_OUTPUT_MODE_INFORMATION>::allocate(mem::size_of::<EFI_GRAPHICS_OUTPUT_MODE_INFORMATION>()).unwrap().into_raw();
+            *buf = info(number);
+            *out = buf;
+            *size = mem::size_of::<EFI_GRAPHICS_OUTPUT_MODE_INFORMATION>();
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn set_mode(this: *const EFI_GRAPHICS_OUTPUT_PROTOCOL, number: UINT32) -> EFI_STATUS {
+        let gop = this as *mut FakeGop;
+        unsafe {
+            (*gop).mode.Mode = number;
+            (*gop).info = info(number);
+        }
+        EFI_SUCCESS
+    }
+
+    // Fills the buffer with a gradient: red goes up along x, green along y
+    extern "efiapi" fn blt(_this: *const EFI_GRAPHICS_OUTPUT_PROTOCOL, buffer: *mut EFI_GRAPHICS_OUTPUT_BLT_PIXEL, operation: EFI_GRAPHICS_OUTPUT_BLT_OPERATION, _sx: UINTN, _sy: UINTN, _dx: UINTN, _dy: UINTN, width: UINTN, height: UINTN, _delta: UINTN) -> EFI_STATUS {
+        if operation != EFI_GRAPHICS_OUTPUT_BLT_OPERATION::EfiBltVideoToBltBuffer {
+            return EFI_UNSUPPORTED;
+        }
+        for y in 0..height {
+            for x in 0..width {
+                unsafe { *buffer.add(y * width + x) = EFI_GRAPHICS_OUTPUT_BLT_PIXEL { Blue: 0xff, Green: y as u8, Red: x as u8, Reserved: 0 } };
+            }
+        }
+        EFI_SUCCESS
+    }
+
+    fn install(native: Option<(u32, u32)>) -> GraphicsOutput {
+        let gop: &'static mut FakeGop = Box::leak(Box::new(FakeGop {
+            protocol: EFI_GRAPHICS_OUTPUT_PROTOCOL { QueryMode: query_mode, SetMode: set_mode, Blt: blt, Mode: ptr::null() },
+            mode: EFI_GRAPHICS_OUTPUT_PROTOCOL_MODE { MaxMode: RESOLUTIONS.len() as u32, Mode: 0, Info: ptr::null(), SizeOfInfo: 0, FrameBufferBase: 0, FrameBufferSize: 0 },
+            info: info(0),
+        }));
+        gop.mode.Info = &gop.info;
+        gop.protocol.Mode = &gop.mode;
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, gop as *const _ as *const VOID);
+            if let Some((width, height)) = native {
+                let edid = Box::leak(build_edid(Resolution::new(width, height), (0, 0), "").into_boxed_slice());
+                let active = Box::leak(Box::new(EFI_EDID_ACTIVE_PROTOCOL { SizeOfEdid: edid.len() as u32, Edid: edid.as_ptr() }));
+                ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_EDID_ACTIVE_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, active as *const _ as *const VOID);
+            }
+        }
+        GraphicsOutput::all().unwrap().into_iter().find(|g| g.handle() == handle).unwrap()
+    }
+
+    #[test]
+    fn picks_the_native_resolution() {
+        mock::install();
+        let panel = install(Some((1920, 1080)));
+        assert_eq!(panel.native_resolution(), Some(Resolution::new(1920, 1080)));
+        assert_eq!(panel.modes().unwrap().len(), 4);
+        assert_eq!(panel.current_mode().resolution, Resolution::new(800, 600));
+        assert_eq!(panel.set_best_mode(&[Resolution::new(1024, 768)]).unwrap().number, 3);
+        assert_eq!(panel.current_mode(), Mode { number: 3, resolution: Resolution::new(1920, 1080), pixel_format: PixelFormat::Bgr, stride: 1920 });
+
+        let virtual_display = install(None);
+        assert_eq!(virtual_display.native_resolution(), None);
+        assert_eq!(virtual_display.set_best_mode(&[Resolution::new(1280, 1024), Resolution::new(1024, 768)]).unwrap().number, 1);
+        assert_eq!(virtual_display.set_best_mode(&[]).unwrap().resolution, Resolution::new(2560, 1440));
+    }
+
+    #[test]
+    fn captures_the_screen_as_a_bmp() {
+        mock::install();
+        let display = install(None);
+        let screenshot = display.capture().unwrap();
+        assert_eq!(screenshot.resolution(), Resolution::new(800, 600));
+        assert_eq!(screenshot.pixel(10, 20), (10, 20, 0xff));
+
+        let bmp = screenshot.to_bmp();
+        assert_eq!(&bmp[0..2], b"BM");
+        assert_eq!(bmp.len(), 54 + 800 * 3 * 600);
+        assert_eq!(LittleEndian::read_u32(&bmp[2..6]) as usize, bmp.len());
+        assert_eq!(LittleEndian::read_i32(&bmp[22..26]), 600);
+        assert_eq!(&bmp[54..60], [0xff, 87, 0, 0xff, 87, 1]); // The bottom row comes first, in blue, green, red order
+    }
+}
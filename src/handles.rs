@@ -0,0 +1,280 @@
+// The handle database: every handle along with the protocols installed on it, more or less what the shell's dh shows.
+// For finding out what the firmware actually has and why looking a protocol up came up empty.
+//
+// Also connecting drivers to controllers, which firmware otherwise only does at boot (and often only for the boot
+// device at that). Anything that makes a device after that - a RAM disk, a NIC brought up, a VLAN - needs connecting
+// before drivers bind to it and e.g. a file system shows up, as the shell's connect does.
+
+use boot_services::{all_handles, protocols_per_handle, device_path};
+use device_path::DevicePath;
+use component_name;
+use ffi::{
+    component_name::{EFI_COMPONENT_NAME2_PROTOCOL_GUID, EFI_COMPONENT_NAME_PROTOCOL_GUID},
+    driver_override::{EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL_GUID, EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL_GUID},
+    console::{EFI_SIMPLE_TEXT_INPUT_PROTOCOL_GUID, EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL_GUID, EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID},
+    device_path::{EFI_DEVICE_PATH_PROTOCOL_GUID, EFI_DEVICE_PATH_TO_TEXT_PROTOCOL_GUID, EFI_DEVICE_PATH_UTILITIES_PROTOCOL_GUID},
+    graphics::EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID,
+    loaded_image::EFI_LOADED_IMAGE_PROTOCOL_GUID,
+    media::{EFI_BLOCK_IO_PROTOCOL_GUID, EFI_DISK_IO_PROTOCOL_GUID, EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID, EFI_LOAD_FILE_PROTOCOL_GUID, EFI_LOAD_FILE2_PROTOCOL_GUID},
+    pci_io::EFI_PCI_IO_PROTOCOL_GUID,
+    simple_network::EFI_SIMPLE_NETWORK_PROTOCOL_GUID,
+    pxebc::EFI_PXE_BASE_CODE_PROTOCOL_GUID,
+    ip4::{EFI_IP4_SERVICE_BINDING_PROTOCOL_GUID, EFI_IP4_CONFIG_PROTOCOL_GUID},
+    tcp4::{EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID, EFI_TCP4_PROTOCOL_GUID},
+    udp4::{EFI_UDP4_SERVICE_BINDING_PROTOCOL_GUID, EFI_UDP4_PROTOCOL_GUID},
+    serial_io::EFI_SERIAL_IO_PROTOCOL_GUID,
+    ata_pass_thru::EFI_ATA_PASS_THRU_PROTOCOL_GUID,
+    nvme_pass_thru::EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID,
+    storage_security::EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID,
+    edid::{EFI_EDID_DISCOVERED_PROTOCOL_GUID, EFI_EDID_ACTIVE_PROTOCOL_GUID},
+    hii::EFI_HII_DATABASE_PROTOCOL_GUID,
+    ram_disk::EFI_RAM_DISK_PROTOCOL_GUID,
+    shell::EFI_SHELL_PROTOCOL_GUID,
+    EFI_HANDLE,
+    EFI_GUID,
+    EFI_NOT_FOUND,
+    TRUE,
+    FALSE,
+};
+use {Result, system_table};
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::{fmt, ptr};
+
+// Short names for the protocols the crate knows, as dh would show them
+// Connecting can make handles that have drivers of their own, so connect_all() goes round again while there are new
+// ones, up to this many times
+const MAX_CONNECT_PASSES: usize = 8;
+
+const PROTOCOL_NAMES: &[(EFI_GUID, &str)] = &[
+    (EFI_LOADED_IMAGE_PROTOCOL_GUID, "LoadedImage"),
+    (EFI_DEVICE_PATH_PROTOCOL_GUID, "DevicePath"),
+    (EFI_DEVICE_PATH_TO_TEXT_PROTOCOL_GUID, "DevicePathToText"),
+    (EFI_DEVICE_PATH_UTILITIES_PROTOCOL_GUID, "DevicePathUtilities"),
+    (EFI_SIMPLE_TEXT_INPUT_PROTOCOL_GUID, "SimpleTextIn"),
+    (EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL_GUID, "SimpleTextInEx"),
+    (EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID, "SimpleTextOut"),
+    (EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID, "GraphicsOutput"),
+    (EFI_EDID_DISCOVERED_PROTOCOL_GUID, "EdidDiscovered"),
+    (EFI_EDID_ACTIVE_PROTOCOL_GUID, "EdidActive"),
+    (EFI_BLOCK_IO_PROTOCOL_GUID, "BlockIo"),
+    (EFI_DISK_IO_PROTOCOL_GUID, "DiskIo"),
+    (EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID, "SimpleFileSystem"),
+    (EFI_LOAD_FILE_PROTOCOL_GUID, "LoadFile"),
+    (EFI_LOAD_FILE2_PROTOCOL_GUID, "LoadFile2"),
+    (EFI_RAM_DISK_PROTOCOL_GUID, "RamDisk"),
+    (EFI_ATA_PASS_THRU_PROTOCOL_GUID, "AtaPassThru"),
+    (EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID, "NvmExpressPassThru"),
+    (EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID, "StorageSecurityCommand"),
+    (EFI_PCI_IO_PROTOCOL_GUID, "PciIo"),
+    (EFI_SERIAL_IO_PROTOCOL_GUID, "SerialIo"),
+    (EFI_SIMPLE_NETWORK_PROTOCOL_GUID, "SimpleNetwork"),
+    (EFI_PXE_BASE_CODE_PROTOCOL_GUID, "PxeBaseCode"),
+    (EFI_IP4_SERVICE_BINDING_PROTOCOL_GUID, "Ip4ServiceBinding"),
+    (EFI_IP4_CONFIG_PROTOCOL_GUID, "Ip4Config"),
+    (EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID, "Tcp4ServiceBinding"),
+    (EFI_TCP4_PROTOCOL_GUID, "Tcp4"),
+    (EFI_UDP4_SERVICE_BINDING_PROTOCOL_GUID, "Udp4ServiceBinding"),
+    (EFI_UDP4_PROTOCOL_GUID, "Udp4"),
+    (EFI_HII_DATABASE_PROTOCOL_GUID, "HiiDatabase"),
+    (EFI_COMPONENT_NAME2_PROTOCOL_GUID, "ComponentName2"),
+    (EFI_COMPONENT_NAME_PROTOCOL_GUID, "ComponentName"),
+    (EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL_GUID, "PlatformDriverOverride"),
+    (EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL_GUID, "BusSpecificDriverOverride"),
+    (EFI_SHELL_PROTOCOL_GUID, "Shell"),
+];
+
+/// A handle and the protocols on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandleInfo {
+    pub handle: EFI_HANDLE,
+    pub protocols: Vec<EFI_GUID>,
+}
+
+impl HandleInfo {
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        Ok(Self { handle, protocols: protocols_per_handle(handle)? })
+    }
+
+    pub fn supports(&self, protocol: &EFI_GUID) -> bool {
+        self.protocols.contains(protocol)
+    }
+
+    /// The handle's device path as text. None if it has none or the firmware can't turn it into text
+    pub fn device_path(&self) -> Option<String> {
+        if !self.supports(&EFI_DEVICE_PATH_PROTOCOL_GUID) {
+            return None;
+        }
+        let path = DevicePath::from_ptr(device_path(self.handle).ok()?).ok()?;
+        let mut text = String::new();
+        fmt::write(&mut text, format_args!("{}", path)).ok()?;
+        Some(text)
+    }
+
+    /// The name of the driver on the handle, if it is one and names itself
+    pub fn driver_name(&self) -> Option<String> {
+        if !self.supports(&EFI_COMPONENT_NAME2_PROTOCOL_GUID) && !self.supports(&EFI_COMPONENT_NAME_PROTOCOL_GUID) {
+            return None;
+        }
+        component_name::driver_name(self.handle)
+    }
+
+    /// The name the driver managing the controller on the handle gives it
+    pub fn controller_name(&self) -> Option<String> {
+        component_name::controller_name(self.handle)
+    }
+
+    /// Something to call the handle by: the driver's name for drivers, the controller's for controllers
+    pub fn name(&self) -> Option<String> {
+        self.driver_name().or_else(|| self.controller_name())
+    }
+}
+
+impl fmt::Display for HandleInfo {
+    /// The handle then its protocols by name, or by GUID for ones the crate doesn't know
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", self.handle)?;
+        for guid in &self.protocols {
+            match protocol_name(guid) {
+                Some(name) => write!(f, " {}", name)?,
+                None => write!(f, " {}", guid)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every handle in the handle database, in the firmware's order. Any that go away while they're being listed are
+/// left out
+pub fn handles() -> Result<Vec<HandleInfo>> {
+    Ok(all_handles()?.into_iter().filter_map(|handle| HandleInfo::new(handle).ok()).collect())
+}
+
+/// Connects drivers to the controller, and with `recursive` to the children they make and so on down. Succeeds with
+/// false if no driver wanted it
+pub fn connect_controller(controller: EFI_HANDLE, recursive: bool) -> Result<bool> {
+    connect_controller_with(controller, &[], None, recursive)
+}
+
+/// Connects the given drivers to the controller in preference to the rest. `remaining_path` asks bus drivers for
+/// just the child at the end of it rather than all of them
+pub fn connect_controller_with(controller: EFI_HANDLE, drivers: &[EFI_HANDLE], remaining_path: Option<&DevicePath>, recursive: bool) -> Result<bool> {
+    let bs = system_table().BootServices;
+    let mut drivers = drivers.to_vec();
+    let drivers = if drivers.is_empty() {
+        ptr::null()
+    } else {
+        drivers.push(ptr::null()); // The list is null terminated
+        drivers.as_ptr()
+    };
+    let remaining_path = remaining_path.map_or(ptr::null(), |path| path.as_ptr());
+    let status = unsafe { traced!(((*bs).ConnectController)(controller, drivers, remaining_path, if recursive { TRUE } else { FALSE })) };
+    if status == EFI_NOT_FOUND {
+        return Ok(false);
+    }
+    ret_on_err!(status);
+    Ok(true)
+}
+
+/// Disconnects every driver from the controller, destroying any children they made
+pub fn disconnect_controller(controller: EFI_HANDLE) -> Result<()> {
+    disconnect_controller_with(controller, None, None)
+}
+
+/// Disconnects just the one driver, or with `child` just destroys that child
+pub fn disconnect_controller_with(controller: EFI_HANDLE, driver: Option<EFI_HANDLE>, child: Option<EFI_HANDLE>) -> Result<()> {
+    let bs = system_table().BootServices;
+    unsafe {
+        ret_on_err!(((*bs).DisconnectController)(controller, driver.unwrap_or(ptr::null()), child.unwrap_or(ptr::null())));
+    }
+    Ok(())
+}
+
+/// Disconnects the controller then connects it again recursively, so it picks up a driver loaded after it was
+/// first connected
+pub fn reconnect_controller(controller: EFI_HANDLE) -> Result<bool> {
+    disconnect_controller(controller)?;
+    connect_controller(controller, true)
+}
+
+/// Connects every driver to every controller it'll bind to, like connect -r in the shell. Returns how many handles
+/// there are afterwards
+pub fn connect_all() -> Result<usize> {
+    let mut handles = all_handles()?;
+    for _ in 0..MAX_CONNECT_PASSES {
+        for &handle in &handles {
+            // Handles can disappear from under us as drivers rearrange things, and a failed one shouldn't stop the rest
+            let _ = connect_controller(handle, true);
+        }
+        let after = all_handles()?;
+        if after.len() <= handles.len() {
+            return Ok(after.len());
+        }
+        handles = after;
+    }
+    Ok(handles.len())
+}
+
+/// The handles with the protocol, for when one you expected isn't there
+pub fn handles_with(protocol: &EFI_GUID) -> Result<Vec<HandleInfo>> {
+    Ok(handles()?.into_iter().filter(|h| h.supports(protocol)).collect())
+}
+
+/// The short name of a protocol the crate knows about
+pub fn protocol_name(protocol: &EFI_GUID) -> Option<&'static str> {
+    PROTOCOL_NAMES.iter().find(|(guid, _)| guid == protocol).map(|&(_, name)| name)
+}
+
+/// The protocol's name if the crate knows it, otherwise its GUID
+pub fn describe_protocol(protocol: &EFI_GUID) -> String {
+    protocol_name(protocol).map_or_else(|| protocol.to_string(), String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EfiErrorKind;
+    use ffi::{component_name::EFI_COMPONENT_NAME2_PROTOCOL, boot_services::EFI_INTERFACE_TYPE, EFI_STATUS, EFI_SUCCESS, EFI_UNSUPPORTED, CHAR8, CHAR16, VOID};
+    use testing::mock;
+    use utils::to_ucs2;
+    use alloc::boxed::Box;
+
+    const VENDOR_GUID: EFI_GUID = EFI_GUID(0x1234_5678, 0x9abc, 0xdef0, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    extern "efiapi" fn get_driver_name(_this: *const EFI_COMPONENT_NAME2_PROTOCOL, language: *const CHAR8, name: *mut *const CHAR16) -> EFI_STATUS {
+        if unsafe { *language } != b'e' as CHAR8 {
+            return EFI_UNSUPPORTED;
+        }
+        unsafe { *name = Box::leak(to_ucs2("Fake Driver").into_boxed_slice()).as_ptr() };
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_controller_name(_this: *const EFI_COMPONENT_NAME2_PROTOCOL, _controller: EFI_HANDLE, _child: EFI_HANDLE, _language: *const CHAR8, _name: *mut *const CHAR16) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    #[test]
+    fn lists_protocols_per_handle() {
+        mock::install();
+        let name = Box::leak(Box::new(EFI_COMPONENT_NAME2_PROTOCOL { GetDriverName: get_driver_name, GetControllerName: get_controller_name, SupportedLanguages: b"en\0".as_ptr() as *const CHAR8 }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            let bs = system_table().BootServices;
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_COMPONENT_NAME2_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, name as *const _ as *const VOID);
+            ((*bs).InstallProtocolInterface)(&mut handle, &VENDOR_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, ptr::null());
+        }
+
+        let info = handles().unwrap().into_iter().find(|h| h.handle == handle).unwrap();
+        assert_eq!(info.protocols, vec![EFI_COMPONENT_NAME2_PROTOCOL_GUID, VENDOR_GUID]);
+        assert!(handles_with(&VENDOR_GUID).unwrap().contains(&info));
+        assert_eq!(info.driver_name().as_deref(), Some("Fake Driver"));
+        assert_eq!(info.device_path(), None);
+        assert_eq!(info.to_string(), format!("{:p} ComponentName2 12345678-9abc-def0-0102-030405060708", handle));
+        assert_eq!(describe_protocol(&EFI_BLOCK_IO_PROTOCOL_GUID), "BlockIo");
+
+        // There are no drivers to connect, which isn't a failure, but a handle that doesn't exist is
+        assert!(!connect_controller(handle, true).unwrap());
+        assert!(!reconnect_controller(handle).unwrap());
+        assert!(connect_all().unwrap() >= 1);
+        assert_eq!(disconnect_controller(0xdead_0000 as EFI_HANDLE).err().unwrap().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
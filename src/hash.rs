@@ -0,0 +1,548 @@
+// Hashing, in software so it works on the firmware without EFI_HASH2_PROTOCOL, which is most of it.
+// SHA-256 for checking downloads against a digest given in a config file and for what signatures sign, the rest of
+// SHA-2 and SHA-1 for formats that ask for them, and HMAC over any of them for deriving keys. The hashers are
+// io::Write as well, so a stream can be hashed without being kept:
+//
+//     let mut hasher = Sha256::new();
+//     io::copy(&mut fetch::fetch(url)?, &mut hasher)?;
+//     let expected = hash::from_hex(&entry.sha256).ok_or(EfiErrorKind::InvalidParameter)?;
+//     if hasher.finish()[..] != expected[..] { .. }
+//
+// Code that works with whichever hash it's told to, like Hmac and PBKDF2, takes a Hash.
+
+use io;
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+use byteorder::{BigEndian, ByteOrder};
+
+pub const SHA1_LEN: usize = 20;
+pub const SHA224_LEN: usize = 28;
+pub const SHA256_LEN: usize = 32;
+pub const SHA384_LEN: usize = 48;
+pub const SHA512_LEN: usize = 64;
+
+// The longest block of any hash here, SHA-512's
+const MAX_BLOCK_LEN: usize = 128;
+
+const SHA256_INIT: [u32; 8] = [
+    0x6A09_E667, 0xBB67_AE85, 0x3C6E_F372, 0xA54F_F53A, 0x510E_527F, 0x9B05_688C, 0x1F83_D9AB, 0x5BE0_CD19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428A_2F98, 0x7137_4491, 0xB5C0_FBCF, 0xE9B5_DBA5, 0x3956_C25B, 0x59F1_11F1, 0x923F_82A4, 0xAB1C_5ED5,
+    0xD807_AA98, 0x1283_5B01, 0x2431_85BE, 0x550C_7DC3, 0x72BE_5D74, 0x80DE_B1FE, 0x9BDC_06A7, 0xC19B_F174,
+    0xE49B_69C1, 0xEFBE_4786, 0x0FC1_9DC6, 0x240C_A1CC, 0x2DE9_2C6F, 0x4A74_84AA, 0x5CB0_A9DC, 0x76F9_88DA,
+    0x983E_5152, 0xA831_C66D, 0xB003_27C8, 0xBF59_7FC7, 0xC6E0_0BF3, 0xD5A7_9147, 0x06CA_6351, 0x1429_2967,
+    0x27B7_0A85, 0x2E1B_2138, 0x4D2C_6DFC, 0x5338_0D13, 0x650A_7354, 0x766A_0ABB, 0x81C2_C92E, 0x9272_2C85,
+    0xA2BF_E8A1, 0xA81A_664B, 0xC24B_8B70, 0xC76C_51A3, 0xD192_E819, 0xD699_0624, 0xF40E_3585, 0x106A_A070,
+    0x19A4_C116, 0x1E37_6C08, 0x2748_774C, 0x34B0_BCB5, 0x391C_0CB3, 0x4ED8_AA4A, 0x5B9C_CA4F, 0x682E_6FF3,
+    0x748F_82EE, 0x78A5_636F, 0x84C8_7814, 0x8CC7_0208, 0x90BE_FFFA, 0xA450_6CEB, 0xBEF9_A3F7, 0xC671_78F2,
+];
+
+
+const SHA224_INIT: [u32; 8] = [
+    0xC105_9ED8, 0x367C_D507, 0x3070_DD17, 0xF70E_5939, 0xFFC0_0B31, 0x6858_1511, 0x64F9_8FA7, 0xBEFA_4FA4,
+];
+
+const SHA384_INIT: [u64; 8] = [
+    0xCBBB_9D5D_C105_9ED8, 0x629A_292A_367C_D507, 0x9159_015A_3070_DD17, 0x152F_ECD8_F70E_5939,
+    0x6733_2667_FFC0_0B31, 0x8EB4_4A87_6858_1511, 0xDB0C_2E0D_64F9_8FA7, 0x47B5_481D_BEFA_4FA4,
+];
+
+const SHA512_INIT: [u64; 8] = [
+    0x6A09_E667_F3BC_C908, 0xBB67_AE85_84CA_A73B, 0x3C6E_F372_FE94_F82B, 0xA54F_F53A_5F1D_36F1,
+    0x510E_527F_ADE6_82D1, 0x9B05_688C_2B3E_6C1F, 0x1F83_D9AB_FB41_BD6B, 0x5BE0_CD19_137E_2179,
+];
+
+const SHA512_K: [u64; 80] = [
+    0x428A_2F98_D728_AE22, 0x7137_4491_23EF_65CD, 0xB5C0_FBCF_EC4D_3B2F, 0xE9B5_DBA5_8189_DBBC,
+    0x3956_C25B_F348_B538, 0x59F1_11F1_B605_D019, 0x923F_82A4_AF19_4F9B, 0xAB1C_5ED5_DA6D_8118,
+    0xD807_AA98_A303_0242, 0x1283_5B01_4570_6FBE, 0x2431_85BE_4EE4_B28C, 0x550C_7DC3_D5FF_B4E2,
+    0x72BE_5D74_F27B_896F, 0x80DE_B1FE_3B16_96B1, 0x9BDC_06A7_25C7_1235, 0xC19B_F174_CF69_2694,
+    0xE49B_69C1_9EF1_4AD2, 0xEFBE_4786_384F_25E3, 0x0FC1_9DC6_8B8C_D5B5, 0x240C_A1CC_77AC_9C65,
+    0x2DE9_2C6F_592B_0275, 0x4A74_84AA_6EA6_E483, 0x5CB0_A9DC_BD41_FBD4, 0x76F9_88DA_8311_53B5,
+    0x983E_5152_EE66_DFAB, 0xA831_C66D_2DB4_3210, 0xB003_27C8_98FB_213F, 0xBF59_7FC7_BEEF_0EE4,
+    0xC6E0_0BF3_3DA8_8FC2, 0xD5A7_9147_930A_A725, 0x06CA_6351_E003_826F, 0x1429_2967_0A0E_6E70,
+    0x27B7_0A85_46D2_2FFC, 0x2E1B_2138_5C26_C926, 0x4D2C_6DFC_5AC4_2AED, 0x5338_0D13_9D95_B3DF,
+    0x650A_7354_8BAF_63DE, 0x766A_0ABB_3C77_B2A8, 0x81C2_C92E_47ED_AEE6, 0x9272_2C85_1482_353B,
+    0xA2BF_E8A1_4CF1_0364, 0xA81A_664B_BC42_3001, 0xC24B_8B70_D0F8_9791, 0xC76C_51A3_0654_BE30,
+    0xD192_E819_D6EF_5218, 0xD699_0624_5565_A910, 0xF40E_3585_5771_202A, 0x106A_A070_32BB_D1B8,
+    0x19A4_C116_B8D2_D0C8, 0x1E37_6C08_5141_AB53, 0x2748_774C_DF8E_EB99, 0x34B0_BCB5_E19B_48A8,
+    0x391C_0CB3_C5C9_5A63, 0x4ED8_AA4A_E341_8ACB, 0x5B9C_CA4F_7763_E373, 0x682E_6FF3_D6B2_B8A3,
+    0x748F_82EE_5DEF_B2FC, 0x78A5_636F_4317_2F60, 0x84C8_7814_A1F0_AB72, 0x8CC7_0208_1A64_39EC,
+    0x90BE_FFFA_2363_1E28, 0xA450_6CEB_DE82_BDE9, 0xBEF9_A3F7_B2C6_7915, 0xC671_78F2_E372_532B,
+    0xCA27_3ECE_EA26_619C, 0xD186_B8C7_21C0_C207, 0xEADA_7DD6_CDE0_EB1E, 0xF57D_4F7F_EE6E_D178,
+    0x06F0_67AA_7217_6FBA, 0x0A63_7DC5_A2C8_98A6, 0x113F_9804_BEF9_0DAE, 0x1B71_0B35_131C_471B,
+    0x28DB_77F5_2304_7D84, 0x32CA_AB7B_40C7_2493, 0x3C9E_BE0A_15C9_BEBC, 0x431D_67C4_9C10_0D4C,
+    0x4CC5_D4BE_CB3E_42B6, 0x597F_299C_FC65_7E2A, 0x5FCB_6FAB_3AD6_FAEC, 0x6C44_198C_4A47_5817,
+];
+
+const SHA1_INIT: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+/// A hash function, for code that can use any of them
+pub trait Hash: Clone {
+    /// The size of the digest, in bytes
+    const LEN: usize;
+    /// The size of the blocks it compresses, in bytes
+    const BLOCK_LEN: usize;
+    type Digest: AsRef<[u8]> + AsMut<[u8]> + Copy;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finish(self) -> Self::Digest;
+
+    fn digest(data: &[u8]) -> Self::Digest {
+        let mut hasher = Self::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+}
+
+// Adds data to a partly filled block, compressing each block as it's filled
+fn absorb<F: FnMut(&[u8])>(block: &mut [u8], block_len: &mut usize, mut data: &[u8], mut compress: F) {
+    let size = block.len();
+    if *block_len > 0 {
+        let take = data.len().min(size - *block_len);
+        block[*block_len..*block_len + take].copy_from_slice(&data[..take]);
+        *block_len += take;
+        data = &data[take..];
+        if *block_len < size {
+            return;
+        }
+        compress(block);
+        *block_len = 0;
+    }
+    let mut blocks = data.chunks_exact(size);
+    for full in &mut blocks {
+        compress(full);
+    }
+    let rest = blocks.remainder();
+    block[..rest.len()].copy_from_slice(rest);
+    *block_len = rest.len();
+}
+
+// What finishes a message of `len` bytes: 0x80, zeros, then the length in bits in the last `len_bytes` of a block
+fn padding(len: u64, block_size: usize, len_bytes: usize, padding: &mut [u8; 2 * MAX_BLOCK_LEN]) -> &[u8] {
+    let used = (len % block_size as u64) as usize;
+    let zeros = if used < block_size - len_bytes { block_size - len_bytes - used } else { 2 * block_size - len_bytes - used };
+    padding[0] = 0x80;
+    BigEndian::write_u64(&mut padding[zeros + len_bytes - 8..], len.wrapping_mul(8));
+    &padding[..zeros + len_bytes]
+}
+
+macro_rules! hasher {
+    ($name:ident, $len:expr, $block:expr) => {
+        impl Default for $name {
+            fn default() -> Self {
+                $name::new()
+            }
+        }
+
+        impl io::Write for $name {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.update(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Hash for $name {
+            const LEN: usize = $len;
+            const BLOCK_LEN: usize = $block;
+            type Digest = [u8; $len];
+
+            fn new() -> Self {
+                $name::new()
+            }
+
+            fn update(&mut self, data: &[u8]) {
+                $name::update(self, data)
+            }
+
+            fn finish(self) -> [u8; $len] {
+                $name::finish(self)
+            }
+        }
+    }
+}
+
+/// SHA-256 of data given a piece at a time
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    len: u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self::with_state(SHA256_INIT)
+    }
+
+    fn with_state(state: [u32; 8]) -> Self {
+        Sha256 { state, block: [0; 64], block_len: 0, len: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        let state = &mut self.state;
+        absorb(&mut self.block, &mut self.block_len, data, |block| compress256(state, block));
+    }
+
+    /// The digest of everything given
+    pub fn finish(self) -> [u8; SHA256_LEN] {
+        let mut digest = [0u8; SHA256_LEN];
+        BigEndian::write_u32_into(&self.finish_state(), &mut digest);
+        digest
+    }
+
+    fn finish_state(mut self) -> [u32; 8] {
+        let mut buf = [0u8; 2 * MAX_BLOCK_LEN];
+        let len = self.len;
+        self.update(padding(len, 64, 8, &mut buf));
+        self.state
+    }
+}
+
+fn compress256(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    BigEndian::read_u32_into(block, &mut w[..16]);
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let mut v = *state;
+    for (&k, &w) in SHA256_K.iter().zip(w.iter()) {
+        let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+        let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+        let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(k).wrapping_add(w);
+        let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+        let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+        let t2 = s0.wrapping_add(maj);
+        v = [t1.wrapping_add(t2), v[0], v[1], v[2], v[3].wrapping_add(t1), v[4], v[5], v[6]];
+    }
+    for (s, v) in state.iter_mut().zip(v.iter()) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+hasher!(Sha256, SHA256_LEN, 64);
+
+pub fn sha256(data: &[u8]) -> [u8; SHA256_LEN] {
+    Sha256::digest(data)
+}
+
+/// SHA-224: SHA-256 started from elsewhere and cut short
+#[derive(Clone)]
+pub struct Sha224(Sha256);
+
+impl Sha224 {
+    pub fn new() -> Self {
+        Sha224(Sha256::with_state(SHA224_INIT))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    pub fn finish(self) -> [u8; SHA224_LEN] {
+        let mut digest = [0u8; SHA224_LEN];
+        BigEndian::write_u32_into(&self.0.finish_state()[..7], &mut digest);
+        digest
+    }
+}
+
+hasher!(Sha224, SHA224_LEN, 64);
+
+/// SHA-512, which is faster than SHA-256 on 64 bit machines
+#[derive(Clone)]
+pub struct Sha512 {
+    state: [u64; 8],
+    block: [u8; 128],
+    block_len: usize,
+    len: u64,
+}
+
+impl Sha512 {
+    pub fn new() -> Self {
+        Self::with_state(SHA512_INIT)
+    }
+
+    fn with_state(state: [u64; 8]) -> Self {
+        Sha512 { state, block: [0; 128], block_len: 0, len: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        let state = &mut self.state;
+        absorb(&mut self.block, &mut self.block_len, data, |block| compress512(state, block));
+    }
+
+    pub fn finish(self) -> [u8; SHA512_LEN] {
+        let mut digest = [0u8; SHA512_LEN];
+        BigEndian::write_u64_into(&self.finish_state(), &mut digest);
+        digest
+    }
+
+    // The length goes in 16 bytes, of which we only ever need the last 8
+    fn finish_state(mut self) -> [u64; 8] {
+        let mut buf = [0u8; 2 * MAX_BLOCK_LEN];
+        let len = self.len;
+        self.update(padding(len, 128, 16, &mut buf));
+        self.state
+    }
+}
+
+fn compress512(state: &mut [u64; 8], block: &[u8]) {
+    let mut w = [0u64; 80];
+    BigEndian::read_u64_into(block, &mut w[..16]);
+    for i in 16..80 {
+        let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+        let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let mut v = *state;
+    for (&k, &w) in SHA512_K.iter().zip(w.iter()) {
+        let s1 = v[4].rotate_right(14) ^ v[4].rotate_right(18) ^ v[4].rotate_right(41);
+        let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+        let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(k).wrapping_add(w);
+        let s0 = v[0].rotate_right(28) ^ v[0].rotate_right(34) ^ v[0].rotate_right(39);
+        let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+        let t2 = s0.wrapping_add(maj);
+        v = [t1.wrapping_add(t2), v[0], v[1], v[2], v[3].wrapping_add(t1), v[4], v[5], v[6]];
+    }
+    for (s, v) in state.iter_mut().zip(v.iter()) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+hasher!(Sha512, SHA512_LEN, 128);
+
+pub fn sha512(data: &[u8]) -> [u8; SHA512_LEN] {
+    Sha512::digest(data)
+}
+
+/// SHA-384: SHA-512 started from elsewhere and cut short
+#[derive(Clone)]
+pub struct Sha384(Sha512);
+
+impl Sha384 {
+    pub fn new() -> Self {
+        Sha384(Sha512::with_state(SHA384_INIT))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    pub fn finish(self) -> [u8; SHA384_LEN] {
+        let mut digest = [0u8; SHA384_LEN];
+        BigEndian::write_u64_into(&self.0.finish_state()[..6], &mut digest);
+        digest
+    }
+}
+
+hasher!(Sha384, SHA384_LEN, 128);
+
+pub fn sha384(data: &[u8]) -> [u8; SHA384_LEN] {
+    Sha384::digest(data)
+}
+
+/// SHA-1 (FIPS 180-4). Broken for signatures; only for formats that still ask for it, like the WebSocket handshake
+/// and LUKS volumes made by old cryptsetups
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    block: [u8; 64],
+    block_len: usize,
+    len: u64,
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Sha1 { state: SHA1_INIT, block: [0; 64], block_len: 0, len: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        let state = &mut self.state;
+        absorb(&mut self.block, &mut self.block_len, data, |block| compress1(state, block));
+    }
+
+    pub fn finish(mut self) -> [u8; SHA1_LEN] {
+        let mut buf = [0u8; 2 * MAX_BLOCK_LEN];
+        let len = self.len;
+        self.update(padding(len, 64, 8, &mut buf));
+        let mut digest = [0u8; SHA1_LEN];
+        BigEndian::write_u32_into(&self.state, &mut digest);
+        digest
+    }
+}
+
+fn compress1(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = [0u32; 80];
+    BigEndian::read_u32_into(block, &mut w[..16]);
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, &w) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+            20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+            _ => (b ^ c ^ d, 0xCA62_C1D6),
+        };
+        let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = t;
+    }
+    for (s, v) in state.iter_mut().zip(&[a, b, c, d, e]) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+hasher!(Sha1, SHA1_LEN, 64);
+
+pub fn sha1(data: &[u8]) -> [u8; SHA1_LEN] {
+    Sha1::digest(data)
+}
+
+/// HMAC (RFC 2104) over any hash, of data given a piece at a time. Clones carry on from the same key, which is how
+/// PBKDF2 saves rehashing it every iteration
+#[derive(Clone)]
+pub struct Hmac<H: Hash> {
+    inner: H,
+    outer: H,
+}
+
+pub type HmacSha256 = Hmac<Sha256>;
+
+impl<H: Hash> Hmac<H> {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; MAX_BLOCK_LEN];
+        let block = &mut block[..H::BLOCK_LEN];
+        if key.len() > block.len() {
+            let digest = H::digest(key);
+            block[..digest.as_ref().len()].copy_from_slice(digest.as_ref());
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let (mut inner, mut outer) = (H::new(), H::new());
+        for b in block.iter_mut() {
+            *b ^= 0x36;
+        }
+        inner.update(block);
+        for b in block.iter_mut() {
+            *b ^= 0x36 ^ 0x5c;
+        }
+        outer.update(block);
+        Hmac { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finish(self) -> H::Digest {
+        let mut outer = self.outer;
+        outer.update(self.inner.finish().as_ref());
+        outer.finish()
+    }
+}
+
+pub fn hmac<H: Hash>(key: &[u8], data: &[u8]) -> H::Digest {
+    let mut hmac = Hmac::<H>::new(key);
+    hmac.update(data);
+    hmac.finish()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; SHA256_LEN] {
+    hmac::<Sha256>(key, data)
+}
+
+/// Lower case hex, the way digests are usually written down
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(hex, "{:02x}", b);
+    }
+    hex
+}
+
+/// Hex in either case. None if it's not hex or has an odd number of digits
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    hex.as_bytes().chunks(2).map(|pair| {
+        let digit = |c: u8| (c as char).to_digit(16);
+        Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_sha256() {
+        // From FIPS 180-2
+        assert_eq!(to_hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(to_hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        let two_blocks = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(to_hex(&sha256(two_blocks)), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+
+        // The same however it's split up
+        let data = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut hasher = Sha256::new();
+        for piece in data.chunks(37) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish(), sha256(&data));
+
+        // From RFC 4231, the second with a key longer than a block
+        assert_eq!(to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(to_hex(&hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+
+        assert_eq!(from_hex("00ff7A"), Some(vec![0x00, 0xFF, 0x7A]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn hashes_sha1_and_the_rest_of_sha2() {
+        // From FIPS 180-2 and RFC 4231
+        assert_eq!(to_hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(to_hex(&Sha224::digest(b"abc")), "23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7");
+        assert_eq!(to_hex(&sha384(b"abc")), "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7");
+        assert_eq!(to_hex(&sha512(b"abc")),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f");
+        let two_blocks = b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+        assert_eq!(to_hex(&sha512(two_blocks)),
+            "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909");
+
+        let data = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut hasher = Sha512::new();
+        for piece in data.chunks(101) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish()[..], sha512(&data)[..]);
+
+        assert_eq!(to_hex(&hmac::<Sha512>(b"Jefe", b"what do ya want for nothing?")),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737");
+        assert_eq!(to_hex(&hmac::<Sha384>(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "4ece084485813e9088d2c63a041bc5b44f9ef1012a2b588f3cd11f05033ac4c60c2ef6ab4030fe8296248df163f44952");
+    }
+}
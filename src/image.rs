@@ -11,14 +11,17 @@ use ffi::{
     EFI_DEVICE_ERROR,
     boot_services::{EFI_INTERFACE_TYPE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL},
     UINTN,
+    UINT32,
     CHAR16,
     BOOLEAN,
     VOID,
+    TRUE,
     FALSE,
 };
 use device_path::{DevicePath, create_file_path_node, append_path};
+use security::{lockdown, measure::{self, Component}};
 use core::{self, ptr, mem, slice, cmp};
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 
 
 // TODO: we should create a virtualfs (filesystem) and put all our images there.
@@ -33,21 +36,51 @@ pub trait Len { // TODO: Move this a more general module like 'io' or something.
 
 // TODO: this whole shit about wrapping raw paths into DevicePath type is unsafe. Address this unsafety
 pub fn load_image_from_path(path: &mut DevicePath) -> Result<LoadedImage> {
-    let bs = (*system_table()).BootServices;
+    load_image_with_boot_policy(path, false)
+}
+
+/// Loads the image at the path with the firmware's boot policy if `boot_policy` is set, i.e. as the boot manager
+/// would for a boot option. That's what network boot needs: the image is fetched through the NIC's LoadFile
+/// protocol, which only boots, rather than LoadFile2, and a path to just a device gets its removable media default
+/// (\EFI\BOOT\BOOTX64.EFI and so on)
+pub fn load_image_with_boot_policy(path: &mut DevicePath, boot_policy: bool) -> Result<LoadedImage> {
+    let bs = system_table().BootServices;
     let current_image_handle = image_handle();
     let path = path.as_ptr();
 
     let loaded_img_handle = unsafe {
         let mut loaded_img_handle: EFI_HANDLE = ptr::null_mut();
-        ret_on_err!(((*bs).LoadImage)(FALSE, current_image_handle, path, ptr::null(), 0, &mut loaded_img_handle)); // TODO: should we pass true or false to first arg? What difference does it make? Should we expose it out to the caller?
+        ret_on_err!(((*bs).LoadImage)(if boot_policy { TRUE } else { FALSE }, current_image_handle, path, ptr::null(), 0, &mut loaded_img_handle));
+        loaded_img_handle
+    };
+
+    Ok(LoadedImage::new(loaded_img_handle))
+}
+
+/// Loads an image that is already present in memory in its entirety. It's checked against the deny list first if
+/// lockdown is on, see security::lockdown, and measured if there's a TPM, see security::measure
+pub fn load_image_from_buffer(buf: &[u8]) -> Result<LoadedImage> {
+    lockdown::check(buf, "image")?;
+    measure::measure(Component::Image, buf, "image")?;
+    load_unmeasured(buf)
+}
+
+// For callers that have checked and measured the image as something more specific, like a kernel
+pub(crate) fn load_unmeasured(buf: &[u8]) -> Result<LoadedImage> {
+    let bs = (*system_table()).BootServices;
+    let current_image_handle = image_handle();
+
+    let loaded_img_handle = unsafe {
+        let mut loaded_img_handle: EFI_HANDLE = ptr::null_mut();
+        ret_on_err!(((*bs).LoadImage)(FALSE, current_image_handle, ptr::null(), buf.as_ptr() as *const VOID, buf.len(), &mut loaded_img_handle));
         loaded_img_handle
     };
 
-    Ok(LoadedImage(loaded_img_handle))
+    Ok(LoadedImage::new(loaded_img_handle))
 }
 
 //TODO: Provide a way for the user to specify load options as well
-/// Loads image read from the given reader
+/// Loads image read from the given reader, measuring it as it's read if there's a TPM
 pub fn load_image<R: Read + Len>(reader: &mut R) -> Result<LoadedImage> {
     let loader = Loader::new(reader);
     let bs = (*system_table()).BootServices;
@@ -107,7 +140,7 @@ pub fn start_image(image: &LoadedImage ) -> Result<ExitData> {
     unsafe {
         let mut exit_data_size: UINTN = 0;
         let mut exit_data_ptr = ptr::null_mut() as *const CHAR16;
-        ret_on_err!(((*bs).StartImage)(image.0, &mut exit_data_size, &mut exit_data_ptr));
+        ret_on_err!(((*bs).StartImage)(image.handle, &mut exit_data_size, &mut exit_data_ptr));
         Ok(ExitData::from_raw_parts(exit_data_ptr, exit_data_size)) // TODO: Will exit_data_ptr ever be null? Test this by starting an image that doesn't call Exit()
     }
 }
@@ -125,7 +158,7 @@ impl<'a, R: 'a + Read + Len> Loader<'a, R> {
     }
 }
 
-extern "win64" fn load_file_callback<'a, R: 'a + Read + Len>(
+extern "efiapi" fn load_file_callback<'a, R: 'a + Read + Len>(
     this: *const EFI_LOAD_FILE_PROTOCOL, 
     file_path: *const EFI_DEVICE_PATH_PROTOCOL,
     _boot_policy: BOOLEAN,
@@ -184,6 +217,10 @@ extern "win64" fn load_file_callback<'a, R: 'a + Read + Len>(
     let mut buf = unsafe { slice::from_raw_parts_mut(buffer_ptr as *mut u8, *buffer_size) };
     match io::fill_buf(&mut loader.reader, &mut buf) {
         Ok(bytes_read) => {
+            let data = &buf[..bytes_read];
+            if let Err(e) = lockdown::check(data, "image").and_then(|_| measure::measure(Component::Image, data, "image")) {
+                return e.into();
+            }
             unsafe { *buffer_size = bytes_read };
             EFI_SUCCESS
         },
@@ -192,8 +229,84 @@ extern "win64" fn load_file_callback<'a, R: 'a + Read + Len>(
 }
 
 
+/// The load options the running image was started with, as text. Empty if there are none or they aren't UCS-2
+pub fn load_options() -> Result<String> {
+    let loaded_image = LoadedImage::new(image_handle()).loaded_image_protocol()?;
+    let (options, size) = unsafe { ((*loaded_image).LoadOptions as *const u16, (*loaded_image).LoadOptionsSize as usize) };
+    if options.is_null() || size & 1 != 0 {
+        return Ok(String::new());
+    }
+    let options = unsafe { slice::from_raw_parts(options, size / 2) };
+    let len = options.iter().position(|c| *c == 0).unwrap_or(options.len());
+    Ok(String::from_utf16(&options[..len]).unwrap_or_default())
+}
+
+/// The load options the running image was started with, as they are. Empty if there are none
+pub fn raw_load_options() -> Result<Vec<u8>> {
+    let loaded_image = LoadedImage::new(image_handle()).loaded_image_protocol()?;
+    let (options, size) = unsafe { ((*loaded_image).LoadOptions as *const u8, (*loaded_image).LoadOptionsSize as usize) };
+    if options.is_null() {
+        return Ok(Vec::new());
+    }
+    Ok(unsafe { slice::from_raw_parts(options, size) }.to_vec())
+}
+
 #[derive(Debug)]
-pub struct LoadedImage(EFI_HANDLE);
+pub struct LoadedImage {
+    handle: EFI_HANDLE,
+    load_options: Option<Vec<u16>>, // Kept here because the firmware only stores a pointer to it. Must outlive the running image.
+}
+
+impl LoadedImage {
+    fn new(handle: EFI_HANDLE) -> Self {
+        Self { handle, load_options: None }
+    }
+
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// Sets the load options (i.e. the command line) the image will see when started.
+    /// The options are passed to the image as a null-terminated UCS-2 string.
+    pub fn set_load_options(&mut self, options: &str) -> Result<()> {
+        let mut utf16_buf = options.encode_utf16().collect::<Vec<_>>();
+        utf16_buf.push(0); //Adding null terminator
+        let size = utf16_buf.len() * 2; // * 2 because u16 is 2 bytes
+        self.install_load_options(utf16_buf, size)
+    }
+
+    /// Sets the load options to bytes the image is given as they are, e.g. a boot option's optional data
+    pub fn set_raw_load_options(&mut self, options: &[u8]) -> Result<()> {
+        let buf = options.chunks(2).map(|c| u16::from_le_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect::<Vec<_>>();
+        self.install_load_options(buf, options.len())
+    }
+
+    // Points the image at the first `size` bytes of `buf`
+    fn install_load_options(&mut self, buf: Vec<u16>, size: usize) -> Result<()> {
+        let loaded_image = self.loaded_image_protocol()?;
+        unsafe {
+            (*loaded_image).LoadOptions = buf.as_ptr() as *const VOID;
+            (*loaded_image).LoadOptionsSize = size as UINT32;
+        }
+
+        self.load_options = Some(buf); // Moving a Vec doesn't move its heap buffer so the pointer above stays valid
+        Ok(())
+    }
+
+    fn loaded_image_protocol(&self) -> Result<*mut EFI_LOADED_IMAGE_PROTOCOL> {
+        let bs = (*system_table()).BootServices;
+        let loaded_image: *mut EFI_LOADED_IMAGE_PROTOCOL = ptr::null_mut();
+        unsafe {
+            ret_on_err!(((*bs).OpenProtocol)(self.handle, &EFI_LOADED_IMAGE_PROTOCOL_GUID, mem::transmute(&loaded_image), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+        }
+
+        if loaded_image.is_null() {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+
+        Ok(loaded_image)
+    }
+}
 
 /// The data returned by a running image when it exits.
 /// Contains a UCS-2 string part followed by an optional binary data.
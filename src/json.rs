@@ -0,0 +1,699 @@
+// JSON (RFC 8259), for configuration files and machine readable reports.
+//
+// parse() gives a Value tree. Types implementing FromJson can be read straight out of one and ToJson types turned
+// into one, which the standard types do already; a config struct implements them in a few lines with Value::field().
+// Objects keep their keys in document order so that what's written back out reads the way it was put together.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{fmt::{self, Display, Formatter, Write}, ops::Index, result, str};
+use {EfiError, EfiErrorKind};
+
+// Arrays and objects nested deeper than this are refused rather than recursed into
+const MAX_DEPTH: usize = 128;
+
+static NULL: Value = Value::Null;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// A number without a fraction or exponent that fits in an i64
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Not valid JSON. Lines and columns count from 1, columns in characters
+    Syntax { line: usize, column: usize, message: &'static str },
+    /// Valid JSON but not what was expected of it. The path says where, e.g. `entries[2].title`
+    Type { path: String, expected: &'static str },
+}
+
+impl Error {
+    fn expected(expected: &'static str) -> Self {
+        Error::Type { path: String::new(), expected }
+    }
+
+    // Prefixes the path of a type error with the key or index it happened under
+    fn under(self, key: &str) -> Self {
+        match self {
+            Error::Type { path, expected } => {
+                let separator = if path.is_empty() || path.starts_with('[') { "" } else { "." };
+                Error::Type { path: format!("{}{}{}", key, separator, path), expected }
+            },
+            e => e,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Syntax { line, column, message } => write!(f, "{} at line {} column {}", message, line, column),
+            Error::Type { ref path, expected } if path.is_empty() => write!(f, "expected {}", expected),
+            Error::Type { ref path, expected } => write!(f, "expected {} at {}", expected, path),
+        }
+    }
+}
+
+impl From<Error> for EfiError {
+    fn from(_: Error) -> Self {
+        EfiErrorKind::InvalidParameter.into()
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Integer(n) => Some(n as f64),
+            Value::Float(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match *self {
+            Value::Object(ref members) => Some(members),
+            _ => None,
+        }
+    }
+
+    /// An object's member. The last one wins if the key appears more than once
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_object()?.iter().rev().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v)
+    }
+
+    /// Reads an object's member as a T. A missing member reads as null, so Option<T> fields are optional
+    pub fn field<T: FromJson>(&self, key: &str) -> Result<T> {
+        if self.as_object().is_none() {
+            return Err(Error::expected("an object"));
+        }
+        T::from_json(self.get(key).unwrap_or(&NULL)).map_err(|e| e.under(key))
+    }
+
+    /// Adds or replaces an object's member. Does nothing to anything that isn't an object
+    pub fn set<T: ToJson + ?Sized>(&mut self, key: &str, value: &T) {
+        if let Value::Object(ref mut members) = *self {
+            let value = value.to_json();
+            match members.iter_mut().find(|&&mut (ref k, _)| k == key) {
+                Some(member) => member.1 = value,
+                None => members.push((String::from(key), value)),
+            }
+        }
+    }
+
+    /// Indented two spaces a level, for people to read
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        write_value(&mut out, self, Some(0)).unwrap();
+        out
+    }
+}
+
+/// Missing members and out of range indices give Null like they do in JavaScript
+impl<'a> Index<&'a str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        self.as_array().and_then(|values| values.get(index)).unwrap_or(&NULL)
+    }
+}
+
+/// Compact JSON, all on one line
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_value(f, self, None)
+    }
+}
+
+fn write_value<W: Write>(out: &mut W, value: &Value, indent: Option<usize>) -> fmt::Result {
+    let newline = |out: &mut W, level: usize| -> fmt::Result {
+        out.write_char('\n')?;
+        for _ in 0..level {
+            out.write_str("  ")?;
+        }
+        Ok(())
+    };
+
+    match *value {
+        Value::Null => out.write_str("null"),
+        Value::Bool(b) => write!(out, "{}", b),
+        Value::Integer(n) => write!(out, "{}", n),
+        Value::Float(n) if !n.is_finite() => out.write_str("null"), // JSON has no NaN or infinity
+        Value::Float(n) => write!(out, "{:?}", n),
+        Value::String(ref s) => write_string(out, s),
+        Value::Array(ref values) if values.is_empty() => out.write_str("[]"),
+        Value::Object(ref members) if members.is_empty() => out.write_str("{}"),
+        Value::Array(ref values) => {
+            out.write_char('[')?;
+            for (i, v) in values.iter().enumerate() {
+                if i > 0 {
+                    out.write_char(',')?;
+                }
+                if let Some(level) = indent {
+                    newline(out, level + 1)?;
+                }
+                write_value(out, v, indent.map(|level| level + 1))?;
+            }
+            if let Some(level) = indent {
+                newline(out, level)?;
+            }
+            out.write_char(']')
+        },
+        Value::Object(ref members) => {
+            out.write_char('{')?;
+            for (i, &(ref k, ref v)) in members.iter().enumerate() {
+                if i > 0 {
+                    out.write_char(',')?;
+                }
+                if let Some(level) = indent {
+                    newline(out, level + 1)?;
+                }
+                write_string(out, k)?;
+                out.write_str(if indent.is_some() { ": " } else { ":" })?;
+                write_value(out, v, indent.map(|level| level + 1))?;
+            }
+            if let Some(level) = indent {
+                newline(out, level)?;
+            }
+            out.write_char('}')
+        },
+    }
+}
+
+fn write_string<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Parses a whole document
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser { text: text.as_bytes(), pos: 0, depth: 0 };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.text.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+/// Parses a document as a T
+pub fn from_str<T: FromJson>(text: &str) -> Result<T> {
+    T::from_json(&parse(text)?)
+}
+
+/// Compact JSON for a T
+pub fn to_string<T: ToJson + ?Sized>(value: &T) -> String {
+    format!("{}", value.to_json())
+}
+
+/// Indented JSON for a T
+pub fn to_string_pretty<T: ToJson + ?Sized>(value: &T) -> String {
+    value.to_json().to_pretty_string()
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> Error {
+        let before = &self.text[..self.pos.min(self.text.len())];
+        let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let column = String::from_utf8_lossy(&before[line_start..]).chars().count() + 1;
+        Error::Syntax { line: before.iter().filter(|&&b| b == b'\n').count() + 1, column, message }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str, value: Value) -> Result<Value> {
+        if !self.text[self.pos..].starts_with(literal.as_bytes()) {
+            return Err(self.error("invalid literal"));
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.expect("null", Value::Null),
+            Some(b't') => self.expect("true", Value::Bool(true)),
+            Some(b'f') => self.expect("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'[') => self.nested(|parser| {
+                let mut values = Vec::new();
+                parser.items(b']', |parser| {
+                    values.push(parser.value()?);
+                    Ok(())
+                })?;
+                Ok(Value::Array(values))
+            }),
+            Some(b'{') => self.nested(|parser| {
+                let mut members = Vec::new();
+                parser.items(b'}', |parser| {
+                    if parser.peek() != Some(b'"') {
+                        return Err(parser.error("expected a string key"));
+                    }
+                    let key = parser.string()?;
+                    parser.skip_whitespace();
+                    if parser.peek() != Some(b':') {
+                        return Err(parser.error("expected ':'"));
+                    }
+                    parser.pos += 1;
+                    parser.skip_whitespace();
+                    members.push((key, parser.value()?));
+                    Ok(())
+                })?;
+                Ok(Value::Object(members))
+            }),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    // An array or object, limiting how deep they go
+    fn nested<F: FnOnce(&mut Self) -> Result<Value>>(&mut self, body: F) -> Result<Value> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        self.pos += 1;
+        let value = body(self);
+        self.depth -= 1;
+        value
+    }
+
+    // Comma separated items up to the closing bracket, which is consumed
+    fn items<F: FnMut(&mut Self) -> Result<()>>(&mut self, close: u8, mut item: F) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            self.skip_whitespace();
+            item(self)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(());
+                },
+                Some(_) => return Err(self.error(if close == b']' { "expected ',' or ']'" } else { "expected ',' or '}'" })),
+                None => return Err(self.error("unexpected end of input")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1; // The opening quote
+        let mut s = String::new();
+        loop {
+            // Copy everything up to the next quote, escape or control character as is
+            let start = self.pos;
+            while let Some(b) = self.peek() {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // The input is a &str and we only stop at ASCII so this is still valid UTF-8
+            s.push_str(str::from_utf8(&self.text[start..self.pos]).unwrap());
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                },
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = self.peek().ok_or_else(|| self.error("unexpected end of input"))?;
+                    self.pos += 1;
+                    s.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error("invalid escape"));
+                        },
+                    });
+                },
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // After the \u. Characters outside the BMP come as a surrogate pair of escapes
+    fn unicode_escape(&mut self) -> Result<char> {
+        let first = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&first) {
+            if !self.text[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let second = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&second) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+        } else {
+            first
+        };
+        core::char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error("invalid escape"))?;
+        let value = str::from_utf8(digits).ok().and_then(|d| u32::from_str_radix(d, 16).ok()).ok_or_else(|| self.error("invalid escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let start = parser.pos;
+            while let Some(b'0'..=b'9') = parser.peek() {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return Err(self.error("invalid number"));
+        }
+        let mut integer = true;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            integer = false;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+            integer = false;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+
+        let text = str::from_utf8(&self.text[start..self.pos]).unwrap();
+        if integer {
+            if let Ok(n) = text.parse() {
+                return Ok(Value::Integer(n));
+            }
+        }
+        text.parse().map(Value::Float).map_err(|_| self.error("invalid number"))
+    }
+}
+
+/// Types that can be read out of a Value
+pub trait FromJson: Sized {
+    fn from_json(value: &Value) -> Result<Self>;
+}
+
+/// Types that can be turned into a Value
+pub trait ToJson {
+    fn to_json(&self) -> Value;
+}
+
+impl FromJson for Value {
+    fn from_json(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl ToJson for Value {
+    fn to_json(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(value: &Value) -> Result<Self> {
+        value.as_bool().ok_or_else(|| Error::expected("a boolean"))
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+macro_rules! integer_json {
+    ($($t:ty),*) => {
+        $(
+            impl FromJson for $t {
+                fn from_json(value: &Value) -> Result<Self> {
+                    value.as_i64().and_then(|n| if n as $t as i64 == n { Some(n as $t) } else { None })
+                        .ok_or_else(|| Error::expected(concat!("an integer that fits in ", stringify!($t))))
+                }
+            }
+
+            impl ToJson for $t {
+                fn to_json(&self) -> Value {
+                    // Only u64s and usizes past i64::MAX don't fit, and those are better off approximate than wrapped
+                    if *self as i64 as $t == *self && (*self as i64 >= 0) == (*self >= 0 as $t) {
+                        Value::Integer(*self as i64)
+                    } else {
+                        Value::Float(*self as f64)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+integer_json!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl FromJson for f64 {
+    fn from_json(value: &Value) -> Result<Self> {
+        value.as_f64().ok_or_else(|| Error::expected("a number"))
+    }
+}
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl FromJson for String {
+    fn from_json(value: &Value) -> Result<Self> {
+        value.as_str().map(String::from).ok_or_else(|| Error::expected("a string"))
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> Value {
+        Value::String(String::from(self))
+    }
+}
+
+/// Null reads as None
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(value: &Value) -> Result<Self> {
+        if value.is_null() { Ok(None) } else { T::from_json(value).map(Some) }
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Value {
+        self.as_ref().map_or(Value::Null, ToJson::to_json)
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(value: &Value) -> Result<Self> {
+        let values = value.as_array().ok_or_else(|| Error::expected("an array"))?;
+        values.iter().enumerate().map(|(i, v)| T::from_json(v).map_err(|e| e.under(&format!("[{}]", i)))).collect()
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Value {
+        Value::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Value {
+        self[..].to_json()
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json(value: &Value) -> Result<Self> {
+        T::from_json(value).map(Box::new)
+    }
+}
+
+impl<'a, T: ToJson + ?Sized> ToJson for &'a T {
+    fn to_json(&self) -> Value {
+        (**self).to_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Entry {
+        title: String,
+        kernel: String,
+        timeout: Option<u32>,
+        args: Vec<String>,
+    }
+
+    impl FromJson for Entry {
+        fn from_json(value: &Value) -> Result<Self> {
+            Ok(Entry { title: value.field("title")?, kernel: value.field("kernel")?, timeout: value.field("timeout")?, args: value.field("args")? })
+        }
+    }
+
+    impl ToJson for Entry {
+        fn to_json(&self) -> Value {
+            let mut value = Value::Object(Vec::new());
+            value.set("title", &self.title);
+            value.set("kernel", &self.kernel);
+            value.set("timeout", &self.timeout);
+            value.set("args", &self.args);
+            value
+        }
+    }
+
+    const MENU: &str = r#"{
+        "default": 0,
+        "entries": [
+            { "title": "Linux é 🐧", "kernel": "\\vmlinuz", "timeout": 5, "args": ["quiet", "console=ttyS0"] },
+            { "title": "Rescue", "kernel": "\\rescue", "args": [] }
+        ],
+        "scale": -1.5e3
+    }"#;
+
+    #[test]
+    fn reads_typed_config() {
+        let menu = parse(MENU).unwrap();
+        assert_eq!(menu["default"].as_i64(), Some(0));
+        assert_eq!(menu["scale"].as_f64(), Some(-1500.0));
+        assert!(menu["missing"][3].is_null());
+
+        let entries: Vec<Entry> = menu.field("entries").unwrap();
+        assert_eq!(entries[0].title, "Linux \u{e9} \u{1F427}");
+        assert_eq!(entries[0].args, ["quiet", "console=ttyS0"]);
+        assert_eq!(entries[1], Entry { title: "Rescue".into(), kernel: "\\rescue".into(), timeout: None, args: Vec::new() });
+
+        // Written out and read back
+        assert_eq!(from_str::<Vec<Entry>>(&to_string(&entries)).unwrap(), entries);
+        assert_eq!(from_str::<Vec<Entry>>(&to_string_pretty(&entries)).unwrap(), entries);
+        assert_eq!(to_string(&entries[1]), r#"{"title":"Rescue","kernel":"\\rescue","timeout":null,"args":[]}"#);
+        assert_eq!(Value::from_json(&Value::Array(vec![Value::Float(1.0)])).unwrap().to_pretty_string(), "[\n  1.0\n]");
+    }
+
+    #[test]
+    fn reports_where_errors_are() {
+        assert_eq!(parse("{\n  \"a\": [1, 2,]\n}"), Err(Error::Syntax { line: 2, column: 14, message: "unexpected character" }));
+        assert_eq!(parse("\"\u{e9}\\x\""), Err(Error::Syntax { line: 1, column: 4, message: "invalid escape" }));
+        assert_eq!(parse("[1] 2").unwrap_err(), Error::Syntax { line: 1, column: 5, message: "trailing characters" });
+        assert!(parse(&"[".repeat(MAX_DEPTH + 1)).is_err());
+
+        let menu = parse(&MENU.replace("\"timeout\": 5", "\"timeout\": \"5\"")).unwrap();
+        let error = menu.field::<Vec<Entry>>("entries").unwrap_err();
+        assert_eq!(format!("{}", error), "expected an integer that fits in u32 at entries[0].timeout");
+    }
+}
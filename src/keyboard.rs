@@ -0,0 +1,450 @@
+// Keyboard layouts from the HII database.
+// A layout says which character each physical key makes, with and without Shift and AltGr. Firmware keyboard drivers
+// are supposed to translate keys with the current layout, but plenty only ever do US English. For those, give the
+// console a KeyMap from the US layout to the one the user actually has and it fixes up what they type, which matters
+// most for passwords since nobody can see what's being typed.
+
+use ffi::{
+    hii::{
+        EFI_HII_DATABASE_PROTOCOL,
+        EFI_HII_DATABASE_PROTOCOL_GUID,
+        EFI_HII_KEYBOARD_LAYOUT,
+        EFI_KEY_DESCRIPTOR,
+        EFI_NULL_MODIFIER,
+        EFI_AFFECTED_BY_CAPS_LOCK,
+    },
+    EFI_BUFFER_TOO_SMALL,
+    EFI_GUID,
+};
+pub use ffi::hii::EFI_KEY;
+use {Result, EfiErrorKind, system_table, to_res};
+use core::{char, mem, ptr};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Which of a key's characters to use
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Normal,
+    Shifted,
+    AltGr,
+    ShiftedAltGr,
+}
+
+const LEVELS: [Level; 4] = [Level::Normal, Level::Shifted, Level::AltGr, Level::ShiftedAltGr];
+
+/// The characters one key makes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Key {
+    /// Where it is on the keyboard
+    pub position: EFI_KEY,
+    pub normal: Option<char>,
+    pub shifted: Option<char>,
+    pub alt_gr: Option<char>,
+    pub shifted_alt_gr: Option<char>,
+    /// Whether Caps Lock acts like Shift for it, as it does for letters
+    pub caps_lock: bool,
+}
+
+impl Key {
+    pub fn char_at(&self, level: Level) -> Option<char> {
+        match level {
+            Level::Normal => self.normal,
+            Level::Shifted => self.shifted,
+            Level::AltGr => self.alt_gr,
+            Level::ShiftedAltGr => self.shifted_alt_gr,
+        }
+    }
+}
+
+pub struct KeyboardLayout {
+    guid: EFI_GUID,
+    keys: Vec<Key>,
+    descriptions: Vec<(String, String)>,
+}
+
+impl KeyboardLayout {
+    /// Parses an EFI_HII_KEYBOARD_LAYOUT
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let header_size = mem::size_of::<EFI_HII_KEYBOARD_LAYOUT>();
+        let descriptor_size = mem::size_of::<EFI_KEY_DESCRIPTOR>();
+        if buf.len() < header_size {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        let len = LittleEndian::read_u16(&buf[0..2]) as usize;
+        let strings = LittleEndian::read_u32(&buf[18..22]) as usize;
+        let count = buf[22] as usize;
+        if len > buf.len() || header_size + count * descriptor_size > len || strings > len {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        let buf = &buf[..len];
+
+        let mut tail = [0; 8];
+        tail.copy_from_slice(&buf[10..18]);
+        let guid = EFI_GUID(LittleEndian::read_u32(&buf[2..6]), LittleEndian::read_u16(&buf[6..8]), LittleEndian::read_u16(&buf[8..10]), tail);
+
+        // Keys with modifiers are Shift, Ctrl, dead keys and the like rather than ones that type something
+        let keys = buf[header_size..header_size + count * descriptor_size].chunks(descriptor_size)
+            .filter(|d| LittleEndian::read_u16(&d[12..14]) == EFI_NULL_MODIFIER)
+            .map(|d| Key {
+                position: LittleEndian::read_u32(&d[0..4]),
+                normal: to_char(LittleEndian::read_u16(&d[4..6])),
+                shifted: to_char(LittleEndian::read_u16(&d[6..8])),
+                alt_gr: to_char(LittleEndian::read_u16(&d[8..10])),
+                shifted_alt_gr: to_char(LittleEndian::read_u16(&d[10..12])),
+                caps_lock: LittleEndian::read_u16(&d[14..16]) & EFI_AFFECTED_BY_CAPS_LOCK != 0,
+            })
+            .collect();
+
+        Ok(KeyboardLayout { guid, keys, descriptions: parse_descriptions(&buf[strings..])? })
+    }
+
+    pub fn guid(&self) -> EFI_GUID {
+        self.guid
+    }
+
+    /// The keys that type characters
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// The layout's name in a language such as `en-US`. Falls back on one in the same language for another region
+    pub fn description(&self, language: &str) -> Option<&str> {
+        let primary = |l: &str| l.split('-').next().unwrap_or("").to_ascii_lowercase();
+        self.descriptions.iter().find(|(l, _)| l.eq_ignore_ascii_case(language))
+            .or_else(|| self.descriptions.iter().find(|(l, _)| primary(l) == primary(language)))
+            .map(|(_, d)| d.as_str())
+    }
+
+    /// The key and level that type `c`
+    pub fn find(&self, c: char) -> Option<(&Key, Level)> {
+        for level in LEVELS.iter() {
+            if let Some(key) = self.keys.iter().find(|k| k.char_at(*level) == Some(c)) {
+                return Some((key, *level));
+            }
+        }
+        None
+    }
+
+    pub fn key(&self, position: EFI_KEY) -> Option<&Key> {
+        self.keys.iter().find(|k| k.position == position)
+    }
+}
+
+/// Translates characters typed with one layout to what the same keys make in another
+pub struct KeyMap(Vec<(char, char)>);
+
+impl KeyMap {
+    pub fn new(from: &KeyboardLayout, to: &KeyboardLayout) -> Self {
+        let mut map = Vec::new();
+        for key in from.keys() {
+            for level in LEVELS.iter() {
+                let pair = (key.char_at(*level), to.key(key.position).and_then(|k| k.char_at(*level)));
+                if let (Some(from), Some(to)) = pair {
+                    if from != to && !map.iter().any(|&(f, _)| f == from) {
+                        map.push((from, to));
+                    }
+                }
+            }
+        }
+        KeyMap(map)
+    }
+
+    /// Characters that aren't on both layouts come through as they are
+    pub fn translate(&self, c: char) -> char {
+        self.0.iter().find(|&&(from, _)| from == c).map_or(c, |&(_, to)| to)
+    }
+}
+
+static mut INPUT_MAP: *mut KeyMap = ptr::null_mut(); // Boxed
+
+/// Has console input go through `map` from now on, or stops translating it if `None`
+pub fn remap_input(map: Option<KeyMap>) {
+    let new = map.map_or(ptr::null_mut(), |map| Box::into_raw(Box::new(map)));
+    unsafe {
+        let old = INPUT_MAP;
+        INPUT_MAP = new;
+        if !old.is_null() {
+            drop(Box::from_raw(old));
+        }
+    }
+}
+
+// Used by the console on every character typed
+pub(crate) fn remap(c: u16) -> u16 {
+    let map = unsafe { INPUT_MAP };
+    if map.is_null() {
+        return c;
+    }
+    match to_char(c) {
+        Some(ch) => {
+            let mut buf = [0; 2];
+            let translated = unsafe { (*map).translate(ch) }.encode_utf16(&mut buf);
+            if translated.len() == 1 { translated[0] } else { c } // UCS-2 can't hold the rest
+        }
+        None => c,
+    }
+}
+
+/// The GUIDs of every layout the firmware knows
+pub fn layouts() -> Result<Vec<EFI_GUID>> {
+    let protocol = hii_database()?;
+    let mut len = 0;
+    let status = unsafe { traced!(((*protocol).FindKeyboardLayouts)(protocol, &mut len, ptr::null_mut())) };
+    if status != EFI_BUFFER_TOO_SMALL {
+        to_res((), status)?;
+        return Ok(Vec::new());
+    }
+
+    let mut guids = vec![EFI_GUID(0, 0, 0, [0; 8]); len as usize / mem::size_of::<EFI_GUID>()];
+    unsafe {
+        ret_on_err!(((*protocol).FindKeyboardLayouts)(protocol, &mut len, guids.as_mut_ptr()));
+    }
+    guids.truncate(len as usize / mem::size_of::<EFI_GUID>());
+    Ok(guids)
+}
+
+/// The layout the firmware translates keys with
+pub fn current_layout() -> Result<KeyboardLayout> {
+    get_layout(ptr::null())
+}
+
+pub fn layout(guid: &EFI_GUID) -> Result<KeyboardLayout> {
+    get_layout(guid)
+}
+
+/// Switches the firmware to another layout. Fails with NotFound if it doesn't have one with this GUID
+pub fn set_layout(guid: &EFI_GUID) -> Result<()> {
+    let protocol = hii_database()?;
+    unsafe {
+        ret_on_err!(((*protocol).SetKeyboardLayout)(protocol, guid));
+    }
+    Ok(())
+}
+
+fn get_layout(guid: *const EFI_GUID) -> Result<KeyboardLayout> {
+    let protocol = hii_database()?;
+    let mut len = 0;
+    let status = unsafe { traced!(((*protocol).GetKeyboardLayout)(protocol, guid, &mut len, ptr::null_mut())) };
+    if status != EFI_BUFFER_TOO_SMALL {
+        to_res((), status)?;
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    unsafe {
+        ret_on_err!(((*protocol).GetKeyboardLayout)(protocol, guid, &mut len, buf.as_mut_ptr() as *mut EFI_HII_KEYBOARD_LAYOUT));
+    }
+    KeyboardLayout::parse(&buf)
+}
+
+fn hii_database() -> Result<*const EFI_HII_DATABASE_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_HII_DATABASE_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_HII_DATABASE_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+    }
+
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+
+    Ok(protocol)
+}
+
+fn to_char(c: u16) -> Option<char> {
+    if c == 0 { None } else { char::from_u32(c as u32) }
+}
+
+// An EFI_DESCRIPTION_STRING_BUNDLE: a count, then that many descriptions each made up of a language code, a space and
+// a null-terminated name, all UCS-2
+fn parse_descriptions(buf: &[u8]) -> Result<Vec<(String, String)>> {
+    if buf.len() < 2 {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+    let count = LittleEndian::read_u16(&buf[0..2]);
+    let mut chars = buf[2..].chunks_exact(2).map(LittleEndian::read_u16);
+
+    let mut descriptions = Vec::new();
+    for _ in 0..count {
+        let language = chars.by_ref().take_while(|&c| c != ' ' as u16).collect::<Vec<_>>();
+        let description = chars.by_ref().take_while(|&c| c != 0).collect::<Vec<_>>();
+        if language.is_empty() {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        descriptions.push((String::from_utf16_lossy(&language), String::from_utf16_lossy(&description)));
+    }
+    Ok(descriptions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{
+        boot_services::EFI_INTERFACE_TYPE,
+        hii::{EFI_AFFECTED_BY_STANDARD_SHIFT, EFI_LEFT_SHIFT_MODIFIER},
+        EFI_HANDLE,
+        EFI_NOT_FOUND,
+        EFI_STATUS,
+        EFI_SUCCESS,
+        UINT16,
+        VOID,
+    };
+    use testing::mock;
+    use core::cell::Cell;
+
+    const US: EFI_GUID = EFI_GUID(0xc9dd9d5d, 0x1a3b, 0x4a2f, [0x9f, 0x4e, 0x6b, 0x87, 0x4c, 0x1d, 0x6f, 0x01]);
+    const DE: EFI_GUID = EFI_GUID(0xc9dd9d5d, 0x1a3b, 0x4a2f, [0x9f, 0x4e, 0x6b, 0x87, 0x4c, 0x1d, 0x6f, 0x02]);
+
+    // Key positions: EfiKeyB1, EfiKeyC1, EfiKeyD6 and EfiKeyE2
+    const B1: u32 = 16;
+    const C1: u32 = 32;
+    const D6: u32 = 54;
+    const E2: u32 = 70;
+
+    fn build_layout(guid: &EFI_GUID, keys: &[(u32, &str, u16)], description: &str) -> Vec<u8> {
+        let mut descriptors = Vec::new();
+        for &(position, chars, modifier) in keys {
+            let mut d = vec![0; 16];
+            LittleEndian::write_u32(&mut d[0..4], position);
+            for (i, c) in chars.encode_utf16().enumerate() {
+                LittleEndian::write_u16(&mut d[4 + i * 2..6 + i * 2], if c == '_' as u16 { 0 } else { c });
+            }
+            LittleEndian::write_u16(&mut d[12..14], modifier);
+            LittleEndian::write_u16(&mut d[14..16], EFI_AFFECTED_BY_STANDARD_SHIFT | EFI_AFFECTED_BY_CAPS_LOCK);
+            descriptors.extend(d);
+        }
+
+        let mut strings = vec![1, 0];
+        for c in description.encode_utf16().chain(Some(0)) {
+            strings.extend_from_slice(&c.to_le_bytes());
+        }
+
+        let mut buf = vec![0; 23];
+        LittleEndian::write_u16(&mut buf[0..2], (23 + descriptors.len() + strings.len()) as u16);
+        LittleEndian::write_u32(&mut buf[2..6], guid.0);
+        LittleEndian::write_u16(&mut buf[6..8], guid.1);
+        LittleEndian::write_u16(&mut buf[8..10], guid.2);
+        buf[10..18].copy_from_slice(&guid.3);
+        LittleEndian::write_u32(&mut buf[18..22], (23 + descriptors.len()) as u32);
+        buf[22] = keys.len() as u8;
+        buf.extend(descriptors);
+        buf.extend(strings);
+        buf
+    }
+
+    #[repr(C)]
+    struct FakeHii {
+        protocol: EFI_HII_DATABASE_PROTOCOL,
+        layouts: Vec<Vec<u8>>,
+        current: Cell<usize>,
+    }
+
+    fn hii<'a>(this: *const EFI_HII_DATABASE_PROTOCOL) -> &'a FakeHii {
+        unsafe { &*(this as *const FakeHii) }
+    }
+
+    fn index_of(hii: &FakeHii, guid: *const EFI_GUID) -> Option<usize> {
+        if guid.is_null() {
+            return Some(hii.current.get());
+        }
+        hii.layouts.iter().position(|l| KeyboardLayout::parse(l).unwrap().guid() == unsafe { *guid })
+    }
+
+    extern "efiapi" fn find_keyboard_layouts(this: *const EFI_HII_DATABASE_PROTOCOL, len: *mut UINT16, guids: *mut EFI_GUID) -> EFI_STATUS {
+        let layouts = &hii(this).layouts;
+        unsafe {
+            let needed = (layouts.len() * mem::size_of::<EFI_GUID>()) as u16;
+            if *len < needed {
+                *len = needed;
+                return EFI_BUFFER_TOO_SMALL;
+            }
+            for (i, layout) in layouts.iter().enumerate() {
+                *guids.add(i) = KeyboardLayout::parse(layout).unwrap().guid();
+            }
+            *len = needed;
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_keyboard_layout(this: *const EFI_HII_DATABASE_PROTOCOL, guid: *const EFI_GUID, len: *mut UINT16, layout: *mut EFI_HII_KEYBOARD_LAYOUT) -> EFI_STATUS {
+        let hii = hii(this);
+        let layout_buf = match index_of(hii, guid) {
+            Some(i) => &hii.layouts[i],
+            None => return EFI_NOT_FOUND,
+        };
+        unsafe {
+            if (*len as usize) < layout_buf.len() {
+                *len = layout_buf.len() as u16;
+                return EFI_BUFFER_TOO_SMALL;
+            }
+            ptr::copy_nonoverlapping(layout_buf.as_ptr(), layout as *mut u8, layout_buf.len());
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn set_keyboard_layout(this: *const EFI_HII_DATABASE_PROTOCOL, guid: *const EFI_GUID) -> EFI_STATUS {
+        let hii = hii(this);
+        match index_of(hii, guid) {
+            Some(i) => {
+                hii.current.set(i);
+                EFI_SUCCESS
+            }
+            None => EFI_NOT_FOUND,
+        }
+    }
+
+    #[test]
+    fn switches_layouts_and_remaps_input() {
+        mock::install();
+        let us = build_layout(&US, &[(B1, "zZ__", 0), (C1, "aA__", 0), (D6, "yY__", 0), (E2, "2@__", 0)], "en-US English");
+        let de = build_layout(&DE, &[(0, "____", EFI_LEFT_SHIFT_MODIFIER), (B1, "yY__", 0), (C1, "aA__", 0), (D6, "zZ__", 0), (E2, "2\"\u{b2}_", 0)], "de-DE Deutsch");
+        let fake: &'static FakeHii = Box::leak(Box::new(FakeHii {
+            protocol: EFI_HII_DATABASE_PROTOCOL {
+                NewPackageList: ptr::null(),
+                RemovePackageList: ptr::null(),
+                UpdatePackageList: ptr::null(),
+                ListPackageLists: ptr::null(),
+                ExportPackageLists: ptr::null(),
+                RegisterPackageNotify: ptr::null(),
+                UnregisterPackageNotify: ptr::null(),
+                FindKeyboardLayouts: find_keyboard_layouts,
+                GetKeyboardLayout: get_keyboard_layout,
+                SetKeyboardLayout: set_keyboard_layout,
+                GetPackageListHandle: ptr::null(),
+            },
+            layouts: vec![us, de],
+            current: Cell::new(0),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_HII_DATABASE_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, fake as *const _ as *const VOID);
+        }
+
+        assert_eq!(layouts().unwrap(), [US, DE]);
+        assert_eq!(current_layout().unwrap().guid(), US);
+        set_layout(&DE).unwrap();
+        let de = current_layout().unwrap();
+        assert_eq!(de.guid(), DE);
+        assert_eq!(de.description("de"), Some("Deutsch"));
+        assert_eq!(de.description("fr-FR"), None);
+        assert_eq!(de.keys().len(), 4); // Not the shift key
+        assert_eq!(de.find('"').map(|(k, level)| (k.position, level)), Some((E2, Level::Shifted)));
+        assert_eq!(set_layout(&EFI_GUID(0, 0, 0, [0; 8])).unwrap_err().kind(), EfiErrorKind::NotFound);
+
+        let map = KeyMap::new(&layout(&US).unwrap(), &de);
+        assert_eq!("Zyx@a2".chars().map(|c| map.translate(c)).collect::<String>(), "Yzx\"a2");
+
+        remap_input(Some(map));
+        assert_eq!(remap('y' as u16), 'z' as u16);
+        remap_input(None);
+        assert_eq!(remap('y' as u16), 'y' as u16);
+    }
+
+    #[test]
+    fn rejects_descriptors_past_the_end() {
+        let mut layout = build_layout(&US, &[(C1, "aA__", 0)], "en-US English");
+        assert_eq!(KeyboardLayout::parse(&layout).unwrap().description("en-GB"), Some("English"));
+        layout[22] = 20;
+        assert_eq!(KeyboardLayout::parse(&layout).err().unwrap().kind(), EfiErrorKind::VolumeCorrupted);
+    }
+}
@@ -6,6 +6,9 @@
 
 #![feature(str_internals)] // TODO: this looks very new and unstable. Can we get rid of it?
 #![feature(ptr_internals)]
+#![feature(abi_efiapi)] // Gives us the right calling convention for UEFI on each architecture (win64 on x86_64, C elsewhere)
+#![feature(asm)]
+#![cfg_attr(feature = "core-error", feature(error_in_core))] // core::error::Error, in nightlies from 2022 on
 
 // #![warn(missing_debug_implementations)]
 
@@ -14,6 +17,7 @@ extern crate failure;
 extern crate byteorder;
 extern crate rlibc;
 extern crate utf8_width;
+#[cfg(feature = "testing")] extern crate efi_macros;
 
 #[macro_use] mod utils;
 #[macro_use] pub mod console;
@@ -25,6 +29,73 @@ pub mod device_path;
 pub mod boxed;
 pub mod events;
 pub mod time;
+pub mod pages;
+pub mod dma;
+pub mod arena;
+pub mod ucs2;
+pub mod protocol_cache;
+#[cfg(feature = "trace-ffi")]
+pub mod trace;
+pub mod linux;
+pub mod multiboot2;
+pub mod elf;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+pub mod pvh;
+pub mod fdt;
+pub mod arch;
+pub mod memory;
+pub mod memory_protection;
+pub mod memdump;
+pub mod devtools;
+pub mod services;
+pub mod handles;
+pub mod component_name;
+pub mod driver_override;
+pub mod driver_health;
+pub mod firmware;
+pub mod ramdisk;
+pub mod fs;
+pub mod partition;
+pub mod decompress;
+pub mod fv;
+pub mod archive;
+pub mod json;
+pub mod bootcfg;
+pub mod retry;
+pub mod hash;
+pub mod crypto;
+pub mod security;
+pub mod credentials;
+pub mod luks;
+pub mod fetch;
+pub mod acpi;
+pub mod bluetooth;
+pub mod user;
+pub mod keyboard;
+pub mod graphics;
+pub mod edid;
+pub mod scrollback;
+pub mod mux;
+pub mod status_code;
+pub mod backtrace;
+pub mod serial;
+pub mod smbios;
+pub mod pci;
+pub mod storage_security;
+pub mod opal;
+pub mod disk;
+pub mod sysinfo;
+pub mod perf;
+pub mod variables;
+pub mod resilient_boot;
+pub mod conformance;
+pub mod error;
+#[cfg(target_arch = "x86_64")]
+pub mod gdb;
+#[cfg(feature = "qemu")]
+pub mod qemu;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod allocator;
 mod boot_services;
 
@@ -36,7 +107,7 @@ mod std {
     pub use core::fmt;
 }
 
-use core::{fmt::{Debug, Display, Formatter}, ptr, mem::transmute};
+use core::{fmt::{Debug, Display, Formatter}, ptr, mem::transmute, marker::PhantomData};
 use ffi::{
     tcp4,
     EFI_STATUS,
@@ -47,10 +118,15 @@ use ffi::{
 };
 
 use failure::{Context, Fail, Backtrace};
-#[cfg(allocator)]
+use alloc::{string::String, vec::Vec};
+#[cfg(all(feature = "allocator", not(test)))]
 use allocator::EfiAllocator;
 pub use console::{Console, stdin, stdout};
 pub use utils::NullTerminatedAsciiStr;
+pub use services::{Boot, Runtime, SystemTableView, BootServices, RuntimeServices, VariableStorage};
+use memory::MemoryMap;
+#[cfg(feature = "testing")]
+pub use efi_macros::test;
 
 static mut SYSTEM_TABLE: Option<*const EFI_SYSTEM_TABLE> = None;
 static mut IMAGE_HANDLE: Option<EFI_HANDLE> = None;
@@ -62,6 +138,8 @@ pub fn init_env(image_handle: EFI_HANDLE, system_table: *const EFI_SYSTEM_TABLE)
     }
 }
 
+// TODO: this global is a raw escape hatch kept around because most of the crate still uses it.
+// New code should take a SystemTable<Boot> so that boot services can't be reached after ExitBootServices.
 #[inline]
 pub fn system_table() -> &'static EFI_SYSTEM_TABLE {
     unsafe {
@@ -77,7 +155,8 @@ pub fn image_handle() -> EFI_HANDLE {
     }
 }
 
-#[cfg(allocator)]
+// Unit tests run on the host with std's allocator
+#[cfg(all(feature = "allocator", not(test)))]
 #[global_allocator]
 static ALLOCATOR: EfiAllocator = EfiAllocator;
 
@@ -85,7 +164,8 @@ static ALLOCATOR: EfiAllocator = EfiAllocator;
 // TODO: instead of calling them errors we should change the name to status and remove Fail etc. from them.
 // They'll then only be used in as the "causes" of actual errors which we will introduce
 pub struct EfiError {
-    inner: Context<EfiErrorKind>
+    inner: Context<EfiErrorKind>,
+    context: Vec<String>, // What was being done when it happened, innermost first. See the error module
 }
 
 impl EfiError {
@@ -96,13 +176,13 @@ impl EfiError {
 
 impl From<EfiErrorKind> for EfiError {
     fn from(kind: EfiErrorKind) -> EfiError {
-        EfiError { inner: Context::new(kind) }
+        EfiError { inner: Context::new(kind), context: Vec::new() }
     }
 }
 
 impl From<Context<EfiErrorKind>> for EfiError {
     fn from(inner: Context<EfiErrorKind>) -> EfiError {
-        EfiError { inner: inner }
+        EfiError { inner: inner, context: Vec::new() }
     }
 }
 
@@ -130,12 +210,18 @@ impl Fail for EfiError {
 
 impl Debug for EfiError {
     fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        for context in self.context.iter().rev() {
+            write!(f, "{}: ", context)?;
+        }
         write!(f, "{:?} (0x{:X})", self.kind() , self.kind() as usize)
     }
 }
 
 impl Display for EfiError {
     fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        for context in self.context.iter().rev() {
+            write!(f, "{}: ", context)?;
+        }
         write!(f, "{:?} (0x{:X}) - {}", self.kind() , self.kind() as usize, self.kind())
     }
 }
@@ -301,34 +387,67 @@ pub enum TextInputProcolPtr {
     InputEx(*mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL),
 }
 
-pub struct SystemTable { 
+/// The system table. `View` is either `Boot` (before ExitBootServices) or `Runtime` (after),
+/// which determines what services are reachable through it
+pub struct SystemTable<View: SystemTableView = Boot> {
     table_ptr: *const EFI_SYSTEM_TABLE,
-    con_in: TextInputProcolPtr,
+    con_in: Option<TextInputProcolPtr>, // Console goes away along with boot services
+    _view: PhantomData<View>,
 }
 
-impl SystemTable {
+impl SystemTable<Boot> {
     pub fn new(table_ptr: *const EFI_SYSTEM_TABLE) -> Result<Self> {
         //We first try to get the EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL and if it is not supported then
         //we fallback to EFI_SIMPLE_TEXT_INPUT_PROTOCOL. This is to workaround the behaviour in 
         //HP EliteBook 840 G2 Notebook PC where calling the OpenProtocol for the INPUT_EX return EFI_UNSUPPORTED
-        match get_simple_text_input_ex(table_ptr) {
-            Ok(con_in_ex) => Ok(Self { table_ptr, con_in: TextInputProcolPtr::InputEx(con_in_ex) }),
+        let con_in = match get_simple_text_input_ex(table_ptr) {
+            Ok(con_in_ex) => TextInputProcolPtr::InputEx(con_in_ex),
             Err(err) => { 
                 if err.kind() == EfiErrorKind::Unsupported {
-                    return Ok(Self { table_ptr, con_in: TextInputProcolPtr::Input(get_simple_text_input(table_ptr)?) }) 
+                    TextInputProcolPtr::Input(get_simple_text_input(table_ptr)?)
+                } else {
+                    return Err(err);
                 }
-                Err(err)
             }
-        }
+        };
+
+        Ok(Self { table_ptr, con_in: Some(con_in), _view: PhantomData })
     }
 
     // TODO: Split console into StdIn, StdOut and StdErr objects
     // TODO: return a reference to Console here. That will help enforce lifetimes
     pub fn console(self) -> Console {
         unsafe {
-            Console::new(self.con_in, (*self.table_ptr).ConOut)
+            Console::new(self.con_in.expect("boot view always has console input"), (*self.table_ptr).ConOut)
         }
     }
+
+    pub fn boot_services(&self) -> BootServices<'_> {
+        unsafe { BootServices::new(&*(*self.table_ptr).BootServices) }
+    }
+
+    /// Exits boot services. On success only runtime services remain available.
+    /// The returned memory map is the one that was current at the time of exit
+    pub fn exit_boot_services(self) -> Result<(SystemTable<Runtime>, MemoryMap)> {
+        let map = services::exit_boot_services(self.table_ptr)?;
+        Ok((SystemTable { table_ptr: self.table_ptr, con_in: None, _view: PhantomData }, map))
+    }
+}
+
+impl<View: SystemTableView> SystemTable<View> {
+    pub fn runtime_services(&self) -> RuntimeServices<'_> {
+        unsafe { RuntimeServices::new(&*(*self.table_ptr).RuntimeServices) }
+    }
+
+    /// The raw system table for calling into protocols this crate doesn't wrap.
+    /// Unsafe because nothing stops you from using boot services through it after they're gone
+    pub unsafe fn raw(&self) -> *const EFI_SYSTEM_TABLE {
+        self.table_ptr
+    }
+
+    pub fn firmware_revision(&self) -> u32 {
+        unsafe { (*self.table_ptr).FirmwareRevision }
+    }
 }
 
 fn get_simple_text_input_ex(table_ptr: *const EFI_SYSTEM_TABLE) -> Result<*mut EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL> {
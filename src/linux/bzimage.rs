@@ -0,0 +1,183 @@
+// Parsing of the x86 Linux boot protocol setup header.
+// See Documentation/x86/boot.rst in the Linux kernel tree for the meaning of these fields.
+
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::vec::Vec;
+
+// Offsets of setup header fields. They're the same in the bzImage file and in boot_params (the "zero page")
+pub const SETUP_SECTS: usize = 0x1F1;
+pub const BOOT_FLAG: usize = 0x1FE;
+pub const JUMP: usize = 0x200;
+pub const HEADER_MAGIC: usize = 0x202;
+pub const VERSION: usize = 0x206;
+pub const TYPE_OF_LOADER: usize = 0x210;
+pub const LOADFLAGS: usize = 0x211;
+pub const CODE32_START: usize = 0x214;
+pub const RAMDISK_IMAGE: usize = 0x218;
+pub const RAMDISK_SIZE: usize = 0x21C;
+pub const CMD_LINE_PTR: usize = 0x228;
+pub const INITRD_ADDR_MAX: usize = 0x22C;
+pub const KERNEL_ALIGNMENT: usize = 0x230;
+pub const RELOCATABLE_KERNEL: usize = 0x234;
+pub const XLOADFLAGS: usize = 0x236;
+pub const CMDLINE_SIZE: usize = 0x238;
+pub const PREF_ADDRESS: usize = 0x258;
+pub const INIT_SIZE: usize = 0x260;
+pub const HANDOVER_OFFSET: usize = 0x264;
+
+// Offsets of boot_params fields which are outside the setup header
+pub const EXT_RAMDISK_IMAGE: usize = 0x0C0;
+pub const EXT_RAMDISK_SIZE: usize = 0x0C4;
+pub const EXT_CMD_LINE_PTR: usize = 0x0C8;
+
+pub const BOOT_PARAMS_SIZE: usize = 0x1000;
+
+const BOOT_FLAG_MAGIC: u16 = 0xAA55;
+const HDRS_MAGIC: &[u8] = b"HdrS";
+const SECTOR_SIZE: usize = 512;
+const DEFAULT_SETUP_SECTS: usize = 4; // A setup_sects value of 0 means 4
+const DEFAULT_INITRD_ADDR_MAX: u32 = 0x37FF_FFFF; // Used by protocols older than 2.03
+
+pub const LOADER_TYPE_UNDEFINED: u8 = 0xFF;
+pub const XLF_KERNEL_64: u16 = 1 << 0;
+pub const XLF_CAN_BE_LOADED_ABOVE_4G: u16 = 1 << 1;
+pub const XLF_EFI_HANDOVER_32: u16 = 1 << 2;
+pub const XLF_EFI_HANDOVER_64: u16 = 1 << 3;
+
+/// A bzImage whose setup header has been validated
+pub struct BzImage {
+    data: Vec<u8>,
+}
+
+impl BzImage {
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if data.len() < HANDOVER_OFFSET + 4 {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+
+        if LittleEndian::read_u16(&data[BOOT_FLAG..]) != BOOT_FLAG_MAGIC || &data[HEADER_MAGIC..HEADER_MAGIC + 4] != HDRS_MAGIC {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+
+        let image = Self { data };
+        if image.setup_size() > image.data.len() || image.header_end() > image.data.len() {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+
+        Ok(image)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Boot protocol version as (major, minor), e.g. (2, 15)
+    pub fn protocol_version(&self) -> (u8, u8) {
+        let version = self.version();
+        ((version >> 8) as u8, version as u8)
+    }
+
+    fn version(&self) -> u16 {
+        LittleEndian::read_u16(&self.data[VERSION..])
+    }
+
+    /// Size of the real-mode setup code including the boot sector
+    pub fn setup_size(&self) -> usize {
+        let setup_sects = match self.data[SETUP_SECTS] as usize {
+            0 => DEFAULT_SETUP_SECTS,
+            n => n,
+        };
+        (setup_sects + 1) * SECTOR_SIZE
+    }
+
+    /// End offset of the setup header. The header is variable length and its size is given by the jump instruction at 0x200
+    pub fn header_end(&self) -> usize {
+        JUMP + 2 + self.data[JUMP + 1] as usize
+    }
+
+    /// The setup header bytes that must be copied into boot_params
+    pub fn setup_header(&self) -> &[u8] {
+        &self.data[SETUP_SECTS..self.header_end()]
+    }
+
+    /// The 32/64-bit protected mode kernel that follows the setup code
+    pub fn protected_mode_kernel(&self) -> &[u8] {
+        &self.data[self.setup_size()..]
+    }
+
+    pub fn is_relocatable(&self) -> bool {
+        self.version() >= 0x0205 && self.data[RELOCATABLE_KERNEL] != 0
+    }
+
+    pub fn kernel_alignment(&self) -> u32 {
+        if self.version() >= 0x0205 { LittleEndian::read_u32(&self.data[KERNEL_ALIGNMENT..]) } else { 0 }
+    }
+
+    /// Preferred load address of the kernel. Only meaningful for protocol 2.10 and above
+    pub fn pref_address(&self) -> Option<u64> {
+        if self.version() >= 0x020A { Some(LittleEndian::read_u64(&self.data[PREF_ADDRESS..])) } else { None }
+    }
+
+    /// Amount of linear contiguous memory the kernel needs before it is able to examine its memory map
+    pub fn init_size(&self) -> usize {
+        if self.version() >= 0x020A { LittleEndian::read_u32(&self.data[INIT_SIZE..]) as usize } else { 0 }
+    }
+
+    /// Highest address the initrd may occupy
+    pub fn initrd_addr_max(&self) -> u32 {
+        if self.version() >= 0x0203 { LittleEndian::read_u32(&self.data[INITRD_ADDR_MAX..]) } else { DEFAULT_INITRD_ADDR_MAX }
+    }
+
+    /// Maximum length of the command line (excluding the null terminator)
+    pub fn cmdline_size(&self) -> usize {
+        if self.version() >= 0x0206 { LittleEndian::read_u32(&self.data[CMDLINE_SIZE..]) as usize } else { 255 }
+    }
+
+    pub fn xloadflags(&self) -> u16 {
+        if self.version() >= 0x020C { LittleEndian::read_u16(&self.data[XLOADFLAGS..]) } else { 0 }
+    }
+
+    /// Offset of the EFI handover entry point from the start of the protected mode kernel (minus the 512 bytes of 64-bit entry skew)
+    pub fn handover_offset(&self) -> Option<u32> {
+        if self.version() < 0x020B {
+            return None;
+        }
+
+        match LittleEndian::read_u32(&self.data[HANDOVER_OFFSET..]) {
+            0 => None,
+            offset => Some(offset)
+        }
+    }
+
+    /// Whether the kernel can be entered via the EFI handover protocol from the architecture we're running on
+    pub fn supports_efi_handover(&self) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        const XLF_EFI_HANDOVER: u16 = XLF_EFI_HANDOVER_64;
+        #[cfg(target_arch = "x86")]
+        const XLF_EFI_HANDOVER: u16 = XLF_EFI_HANDOVER_32;
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+        const XLF_EFI_HANDOVER: u16 = 0; // No handover on non-x86. An x86 kernel can't run here anyway
+
+        // xloadflags was introduced after the handover protocol so older kernels with a handover offset are assumed to support it
+        XLF_EFI_HANDOVER != 0 && self.handover_offset().is_some() && (self.version() < 0x020C || self.xloadflags() & XLF_EFI_HANDOVER != 0)
+    }
+
+    /// Whether the kernel was built with CONFIG_EFI_STUB and is therefore also a valid PE image
+    pub fn has_efi_stub(&self) -> bool {
+        const PE_HEADER_OFFSET_OFFSET: usize = 0x3C;
+        if &self.data[..2] != b"MZ" {
+            return false;
+        }
+
+        let pe_offset = LittleEndian::read_u32(&self.data[PE_HEADER_OFFSET_OFFSET..]) as usize;
+        match pe_offset.checked_add(4) {
+            Some(end) => self.data.get(pe_offset..end) == Some(&b"PE\0\0"[..]),
+            None => false,
+        }
+    }
+}
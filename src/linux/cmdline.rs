@@ -0,0 +1,248 @@
+// Building kernel command lines out of pieces: the boot entry's options, a few parameters of our own and whatever the
+// user typed after our name in the shell.
+//
+// The kernel splits the command line at spaces outside double quotes and takes the first = in each word as the end of
+// the name. Quotes are how a value gets spaces in it; there's no escaping, so a value can't contain a quote. Words
+// after a lone -- aren't the kernel's and are passed on to init, so they're kept apart and never deduplicated.
+
+use image;
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+/// What the command line is held to unless told otherwise: x86's COMMAND_LINE_SIZE for a long time, less its NUL
+pub const DEFAULT_LIMIT: usize = 2047;
+
+// Parameters the kernel acts on every time they're given, so a second one adds rather than replaces
+const REPEATABLE: [&str; 3] = ["console", "initrd", "earlycon"];
+
+/// Kernel parameters in order, each name once unless it's one the kernel takes more than once (console= and the like)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CmdlineBuilder {
+    params: Vec<(String, Option<String>)>,
+    init_args: Vec<String>,
+    limit: usize,
+}
+
+impl Default for CmdlineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CmdlineBuilder {
+    pub fn new() -> Self {
+        Self { params: Vec::new(), init_args: Vec::new(), limit: DEFAULT_LIMIT }
+    }
+
+    /// Splits a command line the way the kernel does
+    pub fn parse(cmdline: &str) -> Result<Self> {
+        let mut builder = Self::new();
+        builder.append(cmdline)?;
+        Ok(builder)
+    }
+
+    /// The most bytes build() will make, not counting the NUL
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets name=value, replacing any earlier value
+    pub fn set(&mut self, name: &str, value: &str) -> Result<&mut Self> {
+        self.insert(name, Some(value), true)
+    }
+
+    /// Sets a parameter without a value, like `quiet`
+    pub fn flag(&mut self, name: &str) -> Result<&mut Self> {
+        self.insert(name, None, true)
+    }
+
+    /// Adds name=value after any the parameter already has, for those the kernel takes more than once
+    pub fn add(&mut self, name: &str, value: &str) -> Result<&mut Self> {
+        self.insert(name, Some(value), false)
+    }
+
+    pub fn remove(&mut self, name: &str) -> &mut Self {
+        self.params.retain(|(n, _)| n != name);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.params.iter().any(|(n, _)| n == name)
+    }
+
+    /// The parameter's last value. None if it isn't there or has no value
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params.iter().rev().find(|(n, _)| n == name).and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Adds an argument for init, after the --
+    pub fn init_arg(&mut self, arg: &str) -> Result<&mut Self> {
+        if arg.contains('"') {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        self.init_args.push(arg.into());
+        Ok(self)
+    }
+
+    /// Adds the parameters in a command line, e.g. from a configuration file. Later ones replace earlier ones of the
+    /// same name
+    pub fn append(&mut self, cmdline: &str) -> Result<&mut Self> {
+        let mut init = false;
+        for word in split(cmdline) {
+            if init {
+                self.init_args.push(word);
+            } else if word == "--" {
+                init = true;
+            } else {
+                self.append_word(&word)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Adds arguments that have already been split, one parameter each, e.g. from the shell. Quotes in them are the
+    /// shell's and already gone, so spaces in values are kept
+    pub fn append_args<S: AsRef<str>>(&mut self, args: &[S]) -> Result<&mut Self> {
+        let mut init = false;
+        for arg in args {
+            let arg = arg.as_ref();
+            if init {
+                self.init_arg(arg)?;
+            } else if arg == "--" {
+                init = true;
+            } else {
+                self.append_word(arg)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Adds what we were started with: the words after our own name when the shell started us, or all of our load
+    /// options otherwise. The shell puts the path it ran first, which is how it's told apart
+    pub fn append_load_options(&mut self) -> Result<&mut Self> {
+        let options = image::load_options()?;
+        let mut words = split(&options);
+        if words.first().map_or(false, |w| w.to_ascii_lowercase().ends_with(".efi")) {
+            words.remove(0);
+        }
+        self.append_args(&words)
+    }
+
+    /// The command line. Fails with BadBufferSize if it's longer than the limit
+    pub fn build(&self) -> Result<String> {
+        let mut cmdline = String::new();
+        for (name, value) in &self.params {
+            if !cmdline.is_empty() {
+                cmdline.push(' ');
+            }
+            cmdline.push_str(name);
+            if let Some(value) = value {
+                cmdline.push('=');
+                push_quoted(&mut cmdline, value);
+            }
+        }
+        if !self.init_args.is_empty() {
+            cmdline.push_str(if cmdline.is_empty() { "--" } else { " --" });
+            for arg in &self.init_args {
+                cmdline.push(' ');
+                push_quoted(&mut cmdline, arg);
+            }
+        }
+        if cmdline.len() > self.limit {
+            return Err(EfiErrorKind::BadBufferSize.into());
+        }
+        Ok(cmdline)
+    }
+
+    fn append_word(&mut self, word: &str) -> Result<&mut Self> {
+        match word.find('=') {
+            Some(i) => self.insert(&word[..i], Some(&word[i + 1..]), !REPEATABLE.contains(&&word[..i])),
+            None => self.insert(word, None, true),
+        }
+    }
+
+    fn insert(&mut self, name: &str, value: Option<&str>, replace: bool) -> Result<&mut Self> {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '=' || c == '"') || value.map_or(false, |v| v.contains('"')) {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let value = value.map(String::from);
+        if replace {
+            if let Some(i) = self.params.iter().position(|(n, _)| n == name) {
+                self.params[i].1 = value;
+                let mut j = i + 1;
+                while j < self.params.len() {
+                    if self.params[j].0 == name { self.params.remove(j); } else { j += 1; }
+                }
+                return Ok(self);
+            }
+        } else if self.params.iter().any(|(n, v)| n == name && *v == value) {
+            return Ok(self);
+        }
+        self.params.push((name.into(), value));
+        Ok(self)
+    }
+}
+
+// The kernel's next_arg(): spaces outside quotes end a word and the quotes themselves are dropped
+fn split(cmdline: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut any = false;
+    for c in cmdline.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                any = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if any {
+                    words.push(core::mem::take(&mut word));
+                    any = false;
+                }
+            }
+            c => {
+                word.push(c);
+                any = true;
+            }
+        }
+    }
+    if any {
+        words.push(word);
+    }
+    words
+}
+
+fn push_quoted(cmdline: &mut String, value: &str) {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        let _ = write!(cmdline, "\"{}\"", value);
+    } else {
+        cmdline.push_str(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_dedupes_and_limits() {
+        let mut builder = CmdlineBuilder::parse(r#"root=/dev/sda1 ro quiet console=tty0 "dyndbg=file foo.c +p" -- single"#).unwrap();
+        builder.append("root=UUID=1234 console=ttyS0,115200 console=tty0 splash").unwrap();
+        builder.append_args(&["acpi.debug_layer=0x2", "name=has space", "--", "emergency"]).unwrap();
+        builder.set("ro", "").unwrap().remove("quiet");
+        assert_eq!(builder.get("root"), Some("UUID=1234"));
+        assert_eq!(
+            builder.build().unwrap(),
+            r#"root=UUID=1234 ro="" console=tty0 dyndbg="file foo.c +p" console=ttyS0,115200 splash acpi.debug_layer=0x2 name="has space" -- single emergency"#
+        );
+        assert_eq!(CmdlineBuilder::parse(&builder.build().unwrap()).unwrap(), builder);
+
+        assert!(builder.set("bad name", "x").is_err());
+        assert!(builder.set("x", "say \"hi\"").is_err());
+        assert!(builder.limit(20).build().is_err());
+        assert_eq!(CmdlineBuilder::new().build().unwrap(), "");
+    }
+}
@@ -0,0 +1,122 @@
+// The x86 EFI handover protocol. We fill in boot_params ourselves and jump to the kernel's handover entry point
+// while boot services are still active. The kernel then calls ExitBootServices itself.
+
+use super::bzimage::*;
+use super::Initrd;
+use {Result, EfiErrorKind, system_table, image_handle};
+use pages::{Pages, PAGE_SIZE, MAX_ADDRESS_32BIT};
+use ffi::{EFI_HANDLE, EFI_SYSTEM_TABLE};
+use byteorder::{ByteOrder, LittleEndian};
+use core::{cmp, mem};
+
+// The 64-bit handover entry point is 512 bytes past the 32-bit one and uses the SysV calling convention.
+// The 32-bit one takes its arguments on the stack as per cdecl.
+#[cfg(target_arch = "x86_64")]
+type HandoverFn = extern "sysv64" fn(EFI_HANDLE, *const EFI_SYSTEM_TABLE, *mut u8);
+#[cfg(target_arch = "x86_64")]
+const HANDOVER_ENTRY_SKEW: u64 = 512;
+#[cfg(target_arch = "x86")]
+type HandoverFn = extern "C" fn(EFI_HANDLE, *const EFI_SYSTEM_TABLE, *mut u8);
+#[cfg(target_arch = "x86")]
+const HANDOVER_ENTRY_SKEW: u64 = 0;
+
+/// Boots the kernel via the handover protocol. Only returns if something went wrong
+pub fn boot(kernel: &BzImage, initrd: &Initrd, cmdline: &str) -> Result<()> {
+    let handover_offset = kernel.handover_offset().ok_or_else(|| ::EfiError::from(EfiErrorKind::Unsupported))?;
+
+    if cmdline.len() > kernel.cmdline_size() {
+        return Err(EfiErrorKind::BadBufferSize.into());
+    }
+
+    // Everything the kernel looks at during early boot must be 32-bit addressable
+    let mut boot_params = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(BOOT_PARAMS_SIZE))?;
+    let (kernel_pages, kernel_addr) = load_protected_mode_kernel(kernel)?;
+    let cmdline_pages = load_cmdline(cmdline)?;
+    let initrd_pages = load_initrd(kernel, initrd)?;
+
+    {
+        let params = boot_params.as_mut_slice();
+        for b in params.iter_mut() {
+            *b = 0;
+        }
+
+        let header = kernel.setup_header();
+        params[SETUP_SECTS..SETUP_SECTS + header.len()].copy_from_slice(header);
+
+        params[TYPE_OF_LOADER] = LOADER_TYPE_UNDEFINED;
+        LittleEndian::write_u32(&mut params[CODE32_START..], kernel_addr as u32);
+        LittleEndian::write_u32(&mut params[CMD_LINE_PTR..], cmdline_pages.addr() as u32);
+        LittleEndian::write_u32(&mut params[EXT_CMD_LINE_PTR..], (cmdline_pages.addr() >> 32) as u32);
+
+        if let Some((ref pages, len)) = initrd_pages {
+            LittleEndian::write_u32(&mut params[RAMDISK_IMAGE..], pages.addr() as u32);
+            LittleEndian::write_u32(&mut params[RAMDISK_SIZE..], len as u32);
+            LittleEndian::write_u32(&mut params[EXT_RAMDISK_IMAGE..], (pages.addr() >> 32) as u32);
+            LittleEndian::write_u32(&mut params[EXT_RAMDISK_SIZE..], (len as u64 >> 32) as u32);
+        }
+    }
+
+    // The kernel owns all of these from here on
+    let boot_params = boot_params.leak();
+    kernel_pages.leak();
+    cmdline_pages.leak();
+    if let Some((pages, _)) = initrd_pages {
+        pages.leak();
+    }
+
+    let entry_addr = kernel_addr + HANDOVER_ENTRY_SKEW + handover_offset as u64;
+    unsafe {
+        let handover: HandoverFn = mem::transmute(entry_addr as usize);
+        handover(image_handle(), system_table(), boot_params.as_mut_ptr());
+    }
+
+    // Kernel never returns from the handover entry point. If it did something went badly wrong.
+    Err(EfiErrorKind::LoadError.into())
+}
+
+fn load_protected_mode_kernel(kernel: &BzImage) -> Result<(Pages, u64)> {
+    let code = kernel.protected_mode_kernel();
+    let size = cmp::max(kernel.init_size(), code.len());
+
+    let (mut pages, offset) = if kernel.is_relocatable() {
+        // AllocatePages only guarantees page alignment so over-allocate and align within the allocation
+        let alignment = cmp::max(kernel.kernel_alignment() as usize, PAGE_SIZE);
+        if !alignment.is_power_of_two() {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let padded_size = size.checked_add(alignment).ok_or(EfiErrorKind::LoadError)?;
+        let pages = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(padded_size))?;
+        let aligned_addr = pages.addr().checked_add(alignment as u64 - 1).ok_or(EfiErrorKind::LoadError)? & !(alignment as u64 - 1);
+        let offset = (aligned_addr - pages.addr()) as usize;
+        (pages, offset)
+    } else {
+        let pref_address = kernel.pref_address().unwrap_or(0x100000); // Non-relocatable kernels older than 2.10 load at 1MiB
+        (Pages::allocate_at(pref_address, Pages::count_for(size))?, 0)
+    };
+
+    let addr = pages.addr() + offset as u64;
+    let end = offset.checked_add(code.len()).ok_or(EfiErrorKind::LoadError)?;
+    pages.as_mut_slice().get_mut(offset..end).ok_or(EfiErrorKind::LoadError)?.copy_from_slice(code);
+    Ok((pages, addr))
+}
+
+fn load_cmdline(cmdline: &str) -> Result<Pages> {
+    let mut pages = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(cmdline.len() + 1))?;
+    {
+        let buf = pages.as_mut_slice();
+        buf[..cmdline.len()].copy_from_slice(cmdline.as_bytes());
+        buf[cmdline.len()] = 0; // Null terminator
+    }
+    Ok(pages)
+}
+
+fn load_initrd(kernel: &BzImage, initrd: &Initrd) -> Result<Option<(Pages, usize)>> {
+    if initrd.is_empty() {
+        return Ok(None);
+    }
+
+    let len = initrd.len();
+    let mut pages = Pages::allocate_below(kernel.initrd_addr_max() as u64, Pages::count_for(len))?;
+    initrd.copy_to(&mut pages.as_mut_slice()[..len]);
+    Ok(Some((pages, len)))
+}
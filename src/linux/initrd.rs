@@ -0,0 +1,225 @@
+// Delivery of initrds to the kernel's EFI stub.
+//
+// Since Linux 5.8 the EFI stub looks for a handle carrying a vendor media device path with LINUX_EFI_INITRD_MEDIA_GUID
+// and calls LoadFile2 on it to fetch the initrd. This is how we hand over an initrd when booting via the EFI stub
+// without having to put it on a filesystem the kernel understands.
+
+use {Result, EfiErrorKind, system_table};
+use boot_services::locate_device_path;
+use io::Read;
+use ffi::{
+    media::{EFI_LOAD_FILE2_PROTOCOL, EFI_LOAD_FILE2_PROTOCOL_GUID},
+    device_path::{
+        EFI_DEVICE_PATH_PROTOCOL,
+        EFI_DEVICE_PATH_PROTOCOL_GUID,
+        VENDOR_DEVICE_PATH,
+        MEDIA_DEVICE_PATH,
+        MEDIA_VENDOR_DP,
+        END_DEVICE_PATH_TYPE,
+        END_ENTIRE_DEVICE_PATH_SUBTYPE,
+    },
+    boot_services::EFI_INTERFACE_TYPE,
+    EFI_GUID,
+    EFI_HANDLE,
+    EFI_STATUS,
+    EFI_SUCCESS,
+    EFI_BUFFER_TOO_SMALL,
+    EFI_INVALID_PARAMETER,
+    EFI_UNSUPPORTED,
+    EFI_NOT_FOUND,
+    UINTN,
+    BOOLEAN,
+    VOID,
+};
+use core::{mem, ptr, slice};
+use alloc::{vec::Vec, boxed::Box};
+
+pub const LINUX_EFI_INITRD_MEDIA_GUID: EFI_GUID = EFI_GUID(0x5568E427, 0x68FC, 0x4F3D, [0xAC, 0x74, 0xCA, 0x55, 0x52, 0x31, 0xCC, 0x68]);
+
+// Individual initrds are padded to this alignment when concatenated. The kernel's cpio unpacker expects each archive to start 4-byte aligned
+const INITRD_ALIGNMENT: usize = 4;
+
+/// One or more initrds which are presented to the kernel as a single concatenated initrd
+#[derive(Debug, Default, Clone)]
+pub struct Initrd {
+    parts: Vec<Vec<u8>>,
+}
+
+impl Initrd {
+    pub fn new() -> Self {
+        Self { parts: Vec::new() }
+    }
+
+    /// Appends an initrd that is already in memory
+    pub fn add(&mut self, initrd: Vec<u8>) -> &mut Self {
+        self.parts.push(initrd);
+        self
+    }
+
+    /// Appends an initrd by reading the given reader (e.g. a file on disk) to the end
+    pub fn add_from_reader<R: Read>(&mut self, reader: &mut R) -> Result<&mut Self> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).map_err(|_| ::EfiError::from(EfiErrorKind::DeviceError))?;
+        Ok(self.add(buf))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.iter().all(|p| p.is_empty())
+    }
+
+    // Each initrd as it was added
+    pub(crate) fn parts(&self) -> impl Iterator<Item=&[u8]> {
+        self.parts.iter().map(|p| p.as_slice())
+    }
+
+    /// Total length of the concatenated initrd including padding
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        for part in self.parts.iter().filter(|p| !p.is_empty()) {
+            len = align_up(len) + part.len();
+        }
+        len
+    }
+
+    /// Writes the concatenated initrd into `buf` which must be at least `len()` bytes long
+    pub fn copy_to(&self, buf: &mut [u8]) {
+        let mut offset = 0;
+        for part in self.parts.iter().filter(|p| !p.is_empty()) {
+            let start = align_up(offset);
+            for b in buf[offset..start].iter_mut() {
+                *b = 0;
+            }
+            buf[start..start + part.len()].copy_from_slice(part);
+            offset = start + part.len();
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![0; self.len()];
+        self.copy_to(&mut buf);
+        buf
+    }
+
+    /// Installs the initrd device path and LoadFile2 protocol so that the kernel's EFI stub can find it.
+    /// The protocols are uninstalled when the returned object is dropped. AccessDenied if there's an initrd installed
+    /// already, since the stub would only find one of them
+    pub fn install(&self) -> Result<InstalledInitrd<'_>> {
+        let loader = Box::new(InitrdLoader {
+            proto: EFI_LOAD_FILE2_PROTOCOL { LoadFile: load_file2_callback },
+            initrd: self,
+        });
+        let device_path = Box::new(InitrdDevicePath::new());
+        let bs = system_table().BootServices;
+
+        match locate_device_path(&EFI_LOAD_FILE2_PROTOCOL_GUID, &*device_path as *const InitrdDevicePath as *const EFI_DEVICE_PATH_PROTOCOL) {
+            Ok(_) => return Err(EfiErrorKind::AccessDenied.into()),
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+
+        let mut handle: EFI_HANDLE = ptr::null_mut();
+        unsafe {
+            ret_on_err!(((*bs).InstallProtocolInterface)(&mut handle, &EFI_DEVICE_PATH_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, &*device_path as *const InitrdDevicePath as *const VOID));
+            let status = traced!(((*bs).InstallProtocolInterface)(&mut handle, &EFI_LOAD_FILE2_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, &loader.proto as *const EFI_LOAD_FILE2_PROTOCOL as *const VOID));
+            if status != EFI_SUCCESS {
+                traced!(((*bs).UninstallProtocolInterface)(handle, &EFI_DEVICE_PATH_PROTOCOL_GUID, &*device_path as *const InitrdDevicePath as *const VOID));
+                return Err(status.into());
+            }
+        }
+
+        Ok(InstalledInitrd { handle, loader, device_path })
+    }
+}
+
+fn align_up(offset: usize) -> usize {
+    (offset + INITRD_ALIGNMENT - 1) & !(INITRD_ALIGNMENT - 1)
+}
+
+/// An initrd that is currently being served to the EFI stub via LoadFile2
+pub struct InstalledInitrd<'a> {
+    handle: EFI_HANDLE,
+    loader: Box<InitrdLoader<'a>>, // Boxed because the firmware holds pointers to these. They must not move while installed
+    device_path: Box<InitrdDevicePath>,
+}
+
+impl<'a> InstalledInitrd<'a> {
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    pub fn initrd(&self) -> &'a Initrd {
+        self.loader.initrd
+    }
+}
+
+impl<'a> Drop for InstalledInitrd<'a> {
+    fn drop(&mut self) {
+        let bs = system_table().BootServices;
+        unsafe { // Can't do anything if these fail
+            traced!(((*bs).UninstallProtocolInterface)(self.handle, &EFI_LOAD_FILE2_PROTOCOL_GUID, &self.loader.proto as *const EFI_LOAD_FILE2_PROTOCOL as *const VOID));
+            traced!(((*bs).UninstallProtocolInterface)(self.handle, &EFI_DEVICE_PATH_PROTOCOL_GUID, &*self.device_path as *const InitrdDevicePath as *const VOID));
+        }
+    }
+}
+
+#[repr(C)] // repr C needed so that we can safely cast the protocol pointer back to this struct in load_file2_callback below
+struct InitrdLoader<'a> {
+    proto: EFI_LOAD_FILE2_PROTOCOL,
+    initrd: &'a Initrd,
+}
+
+#[allow(dead_code)] // Fields are only ever read by the firmware
+#[repr(packed)]
+struct InitrdDevicePath {
+    vendor: VENDOR_DEVICE_PATH,
+    end: EFI_DEVICE_PATH_PROTOCOL,
+}
+
+impl InitrdDevicePath {
+    fn new() -> Self {
+        let vendor_len = mem::size_of::<VENDOR_DEVICE_PATH>() as u16;
+        let end_len = mem::size_of::<EFI_DEVICE_PATH_PROTOCOL>() as u16;
+        Self {
+            vendor: VENDOR_DEVICE_PATH {
+                Header: EFI_DEVICE_PATH_PROTOCOL { Type: MEDIA_DEVICE_PATH, SubType: MEDIA_VENDOR_DP, Length: [vendor_len as u8, (vendor_len >> 8) as u8] },
+                Guid: LINUX_EFI_INITRD_MEDIA_GUID,
+            },
+            end: EFI_DEVICE_PATH_PROTOCOL { Type: END_DEVICE_PATH_TYPE, SubType: END_ENTIRE_DEVICE_PATH_SUBTYPE, Length: [end_len as u8, (end_len >> 8) as u8] },
+        }
+    }
+}
+
+extern "efiapi" fn load_file2_callback(
+    this: *const EFI_LOAD_FILE2_PROTOCOL,
+    file_path: *const EFI_DEVICE_PATH_PROTOCOL,
+    boot_policy: BOOLEAN,
+    buffer_size: *mut UINTN,
+    buffer_ptr: *mut VOID
+) -> EFI_STATUS {
+    if this.is_null() || file_path.is_null() || buffer_size.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    // As per UEFI spec LoadFile2 doesn't support boot policy
+    if boot_policy != 0 {
+        return EFI_UNSUPPORTED;
+    }
+
+    let loader = unsafe { &*(this as *const InitrdLoader) }; // Should be safe to do this cast since InitrdLoader is marked repr C
+    let initrd_len = loader.initrd.len();
+    if initrd_len == 0 {
+        return EFI_NOT_FOUND;
+    }
+
+    // Same as with LoadFile, the caller first asks for the size with a null buffer and then calls again with a big enough buffer
+    if buffer_ptr.is_null() || unsafe { *buffer_size } < initrd_len {
+        unsafe { *buffer_size = initrd_len };
+        return EFI_BUFFER_TOO_SMALL;
+    }
+
+    let buf = unsafe { slice::from_raw_parts_mut(buffer_ptr as *mut u8, initrd_len) };
+    loader.initrd.copy_to(buf);
+    unsafe { *buffer_size = initrd_len };
+    EFI_SUCCESS
+}
+
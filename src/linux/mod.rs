@@ -0,0 +1,142 @@
+// Booting Linux kernels.
+//
+// Two entry methods are supported:
+// - EFI stub: the bzImage is also a PE image so we simply LoadImage/StartImage it passing the command line as load options.
+//   The initrd, if any, is served to the stub via the LoadFile2 initrd device path (see initrd.rs).
+// - EFI handover protocol: we build boot_params ourselves (command line, initrd etc.) and jump to the
+//   kernel's handover entry point. This is the only way to hand an in-memory initrd to kernels older than 5.8.
+
+pub mod bzimage;
+mod cmdline;
+mod initrd;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+mod handover;
+
+pub use self::bzimage::BzImage;
+pub use self::cmdline::{CmdlineBuilder, DEFAULT_LIMIT as DEFAULT_CMDLINE_LIMIT};
+pub use self::initrd::{Initrd, InstalledInitrd, LINUX_EFI_INITRD_MEDIA_GUID};
+
+use {Result, EfiErrorKind};
+use image::{self, ExitData};
+use security::{lockdown, measure::{self, Component}};
+use alloc::{vec::Vec, string::String};
+
+/// How control is transferred to the kernel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BootMethod {
+    /// Pick the handover protocol if an initrd is present (and the kernel supports it), otherwise the EFI stub
+    Auto,
+    EfiStub,
+    Handover,
+}
+
+/// A Linux kernel ready to be booted along with its initrd and command line
+pub struct Linux {
+    kernel: BzImage,
+    initrd: Initrd,
+    cmdline: String,
+    method: BootMethod,
+}
+
+impl Linux {
+    pub fn new(kernel: Vec<u8>) -> Result<Self> {
+        Ok(Self { kernel: BzImage::parse(kernel)?, initrd: Initrd::new(), cmdline: String::new(), method: BootMethod::Auto })
+    }
+
+    pub fn kernel(&self) -> &BzImage {
+        &self.kernel
+    }
+
+    /// Adds an initrd. If called more than once the initrds are concatenated in the order they were added
+    pub fn initrd(&mut self, initrd: Vec<u8>) -> &mut Self {
+        self.initrd.add(initrd);
+        self
+    }
+
+    pub fn cmdline<S: Into<String>>(&mut self, cmdline: S) -> &mut Self {
+        self.cmdline = cmdline.into();
+        self
+    }
+
+    /// Builds the command line, held to what the kernel takes if that's less than the builder's limit
+    pub fn cmdline_from(&mut self, builder: &CmdlineBuilder) -> Result<&mut Self> {
+        let cmdline = builder.build()?;
+        if cmdline.len() > self.kernel.cmdline_size() {
+            return Err(EfiErrorKind::BadBufferSize.into());
+        }
+        self.cmdline = cmdline;
+        Ok(self)
+    }
+
+    pub fn method(&mut self, method: BootMethod) -> &mut Self {
+        self.method = method;
+        self
+    }
+
+    /// Boots the kernel. Returns only if booting failed or, in case of the EFI stub, if the kernel exited back to us.
+    /// A kernel with an EFI stub is checked against the deny list first if lockdown is on, see security::lockdown,
+    /// whichever way it's booted. The kernel, each initrd and the command line are measured if there's a TPM, see
+    /// security::measure
+    pub fn boot(&self) -> Result<ExitData> {
+        let method = self.resolve_method()?;
+        if self.kernel.has_efi_stub() {
+            lockdown::check(self.kernel.as_bytes(), "kernel")?;
+        }
+        self.measure()?;
+        match method {
+            BootMethod::Handover => self.boot_handover().map(|_| unreachable!()),
+            _ => self.boot_efi_stub(),
+        }
+    }
+
+    fn measure(&self) -> Result<()> {
+        measure::measure(Component::Kernel, self.kernel.as_bytes(), "kernel")?;
+        for initrd in self.initrd.parts().filter(|p| !p.is_empty()) {
+            measure::measure(Component::Initrd, initrd, "initrd")?;
+        }
+        measure::measure(Component::Cmdline, self.cmdline.as_bytes(), &self.cmdline)
+    }
+
+    fn resolve_method(&self) -> Result<BootMethod> {
+        match self.method {
+            BootMethod::Auto => {
+                if !self.initrd.is_empty() && self.kernel.supports_efi_handover() {
+                    Ok(BootMethod::Handover)
+                } else if self.kernel.has_efi_stub() {
+                    Ok(BootMethod::EfiStub)
+                } else if self.kernel.supports_efi_handover() {
+                    Ok(BootMethod::Handover)
+                } else {
+                    Err(EfiErrorKind::Unsupported.into())
+                }
+            },
+            BootMethod::EfiStub if !self.kernel.has_efi_stub() => Err(EfiErrorKind::Unsupported.into()),
+            BootMethod::Handover if !self.kernel.supports_efi_handover() => Err(EfiErrorKind::Unsupported.into()),
+            m => Ok(m),
+        }
+    }
+
+    fn boot_efi_stub(&self) -> Result<ExitData> {
+        // TODO: stubs older than 5.8 don't know about LoadFile2 and will silently boot without the initrd.
+        // Can we detect that from the image somehow?
+        let _installed_initrd = if self.initrd.is_empty() {
+            None
+        } else {
+            Some(self.initrd.install()?) // Uninstalled when this goes out of scope i.e. after the kernel exits back to us
+        };
+
+        let mut loaded_image = image::load_unmeasured(self.kernel.as_bytes())?; // Checked and measured as the kernel already
+        loaded_image.set_load_options(&self.cmdline)?;
+        image::start_image(&loaded_image)
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    fn boot_handover(&self) -> Result<()> {
+        handover::boot(&self.kernel, &self.initrd, &self.cmdline)
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    fn boot_handover(&self) -> Result<()> {
+        Err(EfiErrorKind::Unsupported.into()) // The handover protocol only exists on x86
+    }
+}
@@ -0,0 +1,765 @@
+// LUKS encrypted volumes, so that an encrypted /boot can be read before the OS is up.
+//
+// Luks::open() reads the header: LUKS1's fixed one, or LUKS2's binary header and the JSON metadata after it, falling
+// back to the secondary copy if the primary's checksum is wrong. unlock() tries a passphrase against each key slot.
+// The slot's KDF turns the passphrase into the key the slot's area is encrypted with, the area decrypts to the master
+// key split into anti-forensic stripes, and the stripes merge back into a master key which has to match the digest.
+// volume() then gives the data as a BlockDevice that decrypts what's read and encrypts what's written:
+//
+//     let mut luks = Luks::open(partition)?;
+//     let passphrase = credentials::read_password("Passphrase for /boot: ")?;
+//     let key = luks.unlock(passphrase.as_bytes())?;
+//     let boot = Fat::new(BlockDisk::new(luks.volume(&key)?))?;
+//
+// Ciphers are limited to AES in XTS or CBC-ESSIV and hashes to SHA-1, SHA-256 and SHA-512, which covers what cryptsetup has made by
+// default since 1.7. LUKS2 volumes with integrity protection or a reencryption under way aren't supported.
+
+mod sector;
+
+use self::sector::SectorCipher;
+use credentials::{kdf::{self, Argon2}, Secret};
+use fs::{BlockDevice, Disk};
+use crypto::{constant_time_eq, Hash, Sha1, Sha256, Sha512};
+use hash::{sha256, SHA256_LEN};
+use json;
+use utils::from_base64;
+use {Result, EfiError, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+use byteorder::{BigEndian, ByteOrder};
+use core::{cmp::Reverse, str};
+
+/// What offsets and sizes in the header count in, and key slot areas are encrypted in
+pub const SECTOR_SIZE: u64 = 512;
+
+const MAGIC: &[u8; 6] = b"LUKS\xba\xbe";
+const SECONDARY_MAGIC: &[u8; 6] = b"SKUL\xba\xbe";
+
+const LUKS1_HEADER_LEN: usize = 592;
+const LUKS1_KEY_SLOTS: usize = 8;
+const LUKS1_KEY_SLOT_LEN: usize = 48;
+const LUKS1_KEY_ENABLED: u32 = 0x00AC_71F3;
+
+const LUKS2_BINARY_HEADER_LEN: usize = 4096;
+// The sizes a LUKS2 header (binary and JSON) can be. The secondary copy starts straight after the primary
+const LUKS2_HEADER_SIZES: [u64; 9] = [0x4000, 0x8000, 0x10000, 0x20000, 0x40000, 0x80000, 0x100000, 0x200000, 0x400000];
+
+// Master keys longer than this aren't for any cipher we know
+const MAX_KEY_LEN: usize = 512;
+// What cryptsetup writes. More only makes the key slot area bigger, up to whatever a hostile header likes
+const MAX_STRIPES: u32 = 4000;
+
+fn corrupted() -> EfiError {
+    EfiErrorKind::VolumeCorrupted.into()
+}
+
+/// How a key slot turns a passphrase into a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2 { hash: String, iterations: u32 },
+    Argon2(Argon2),
+}
+
+/// A key slot in use
+#[derive(Debug, Clone)]
+pub struct KeySlot {
+    pub id: u32,
+    pub kdf: Kdf,
+    pub salt: Vec<u8>,
+    /// Where the slot's encrypted, split master key is, in bytes from the start of the disk
+    pub area_offset: u64,
+    pub area_size: u64,
+    /// The cipher spec the area is encrypted with, e.g. aes-xts-plain64
+    pub area_cipher: String,
+    /// Length of the key the KDF makes for the area
+    pub area_key_size: usize,
+    /// Length of the master key
+    pub key_size: usize,
+    pub stripes: u32,
+    /// What the stripes are diffused with
+    pub af_hash: String,
+    /// LUKS2 slots at 0 are only tried when asked for by id, and those at 2 are tried first. LUKS1 ones are all 1
+    pub priority: u32,
+}
+
+// A check for the master key: PBKDF2 of it, for the key slots listed
+#[derive(Debug, Clone)]
+struct Digest {
+    hash: String,
+    iterations: u32,
+    salt: Vec<u8>,
+    digest: Vec<u8>,
+    key_slots: Vec<u32>,
+}
+
+impl Digest {
+    fn matches(&self, key: &[u8]) -> Result<bool> {
+        let mut derived = Secret::zeroed(self.digest.len());
+        pbkdf2(&self.hash, key, &self.salt, self.iterations, derived.as_bytes_mut())?;
+        Ok(constant_time_eq(derived.as_bytes(), &self.digest))
+    }
+}
+
+/// What's in a LUKS header
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// 1 or 2
+    pub version: u16,
+    pub uuid: String,
+    /// LUKS2 only, empty on LUKS1
+    pub label: String,
+    /// The spec the data is encrypted with, e.g. aes-xts-plain64
+    pub cipher: String,
+    /// Where the encrypted data starts, in bytes from the start of the disk
+    pub data_offset: u64,
+    /// In bytes. None if it runs to the end of the disk
+    pub data_size: Option<u64>,
+    /// What the data is encrypted in units of: 512 bytes, and on LUKS2 up to 4096
+    pub sector_size: usize,
+    /// Added to the sector number when making IVs, in 512 byte sectors
+    pub iv_tweak: u64,
+    pub key_slots: Vec<KeySlot>,
+    digests: Vec<Digest>,
+}
+
+/// A LUKS volume, locked
+pub struct Luks<D: Disk> {
+    disk: D,
+    header: Header,
+}
+
+impl<D: Disk> Luks<D> {
+    /// Whether the disk starts with a LUKS header
+    pub fn probe(disk: &mut D) -> bool {
+        let mut magic = [0u8; 6];
+        disk.read_at(0, &mut magic).is_ok() && magic == *MAGIC
+    }
+
+    /// Reads the header. VolumeCorrupted if there isn't a valid one, Unsupported if it asks for features we don't have
+    pub fn open(mut disk: D) -> Result<Self> {
+        let mut start = [0u8; 8];
+        disk.read_at(0, &mut start)?;
+        if start[..6] != MAGIC[..] {
+            return Err(corrupted());
+        }
+        let header = match BigEndian::read_u16(&start[6..]) {
+            1 => {
+                let mut header = [0u8; LUKS1_HEADER_LEN];
+                disk.read_at(0, &mut header)?;
+                parse_luks1(&header)?
+            },
+            2 => read_luks2(&mut disk)?,
+            _ => return Err(EfiErrorKind::Unsupported.into()),
+        };
+        Ok(Self { disk, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The master key, from the first key slot the passphrase opens. AccessDenied if it doesn't open any that we can
+    /// open at all, Unsupported if there aren't any of those
+    pub fn unlock(&mut self, passphrase: &[u8]) -> Result<Secret> {
+        let mut slots: Vec<KeySlot> = self.header.key_slots.iter().filter(|s| s.priority > 0).cloned().collect();
+        slots.sort_by_key(|s| Reverse(s.priority));
+        let mut error = EfiErrorKind::Unsupported;
+        for slot in slots {
+            match self.unlock_slot_with(&slot, passphrase) {
+                Ok(key) => return Ok(key),
+                Err(ref e) if e.kind() == EfiErrorKind::AccessDenied => error = EfiErrorKind::AccessDenied,
+                Err(ref e) if e.kind() == EfiErrorKind::Unsupported => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Err(error.into())
+    }
+
+    /// The master key from one key slot. NotFound if the slot isn't in use, AccessDenied if the passphrase is wrong
+    pub fn unlock_slot(&mut self, id: u32, passphrase: &[u8]) -> Result<Secret> {
+        let slot = self.header.key_slots.iter().find(|s| s.id == id).cloned().ok_or(EfiErrorKind::NotFound)?;
+        self.unlock_slot_with(&slot, passphrase)
+    }
+
+    fn unlock_slot_with(&mut self, slot: &KeySlot, passphrase: &[u8]) -> Result<Secret> {
+        // Everything that could be unsupported is found out before spending seconds in the KDF
+        SectorCipher::new(&slot.area_cipher, &vec![0; slot.area_key_size])?;
+        let diffuse = diffuser(&slot.af_hash)?;
+        let split_len = slot.key_size.checked_mul(slot.stripes as usize).ok_or_else(corrupted)?;
+        let area_len = round_up(split_len as u64, SECTOR_SIZE);
+        if area_len > slot.area_size {
+            return Err(corrupted());
+        }
+        match slot.area_offset.checked_add(slot.area_size) {
+            Some(end) if end <= self.disk.size() => {},
+            _ => return Err(corrupted()),
+        }
+
+        let mut area_key = Secret::zeroed(slot.area_key_size);
+        match slot.kdf {
+            Kdf::Pbkdf2 { ref hash, iterations } => pbkdf2(hash, passphrase, &slot.salt, iterations, area_key.as_bytes_mut())?,
+            Kdf::Argon2(ref argon2) => argon2.derive(passphrase, &slot.salt, area_key.as_bytes_mut())?,
+        }
+        let cipher = SectorCipher::new(&slot.area_cipher, area_key.as_bytes())?;
+
+        let mut split = Secret::zeroed(area_len as usize);
+        self.disk.read_at(slot.area_offset, split.as_bytes_mut())?;
+        for (sector, data) in split.as_bytes_mut().chunks_mut(SECTOR_SIZE as usize).enumerate() {
+            cipher.decrypt(sector as u64, data);
+        }
+        let key = af_merge(&split.as_bytes()[..split_len], slot.key_size, diffuse);
+
+        for digest in self.header.digests.iter().filter(|d| d.key_slots.contains(&slot.id)) {
+            if digest.matches(key.as_bytes())? {
+                return Ok(key);
+            }
+        }
+        Err(EfiErrorKind::AccessDenied.into())
+    }
+
+    /// The decrypted data, given the master key from unlock(). InvalidParameter if the key is the wrong size for the
+    /// cipher; a key that's the right size but wrong isn't noticed and reads back as garbage
+    pub fn volume(self, key: &Secret) -> Result<Volume<D>> {
+        let header = self.header;
+        let cipher = SectorCipher::new(&header.cipher, key.as_bytes())?;
+        let disk_size = self.disk.size();
+        let size = match header.data_size {
+            Some(size) => size,
+            None => disk_size.checked_sub(header.data_offset).ok_or_else(corrupted)?,
+        };
+        match header.data_offset.checked_add(size) {
+            Some(end) if end <= disk_size => {},
+            _ => return Err(corrupted()),
+        }
+        let sector_size = header.sector_size;
+        // LUKS2 counts IVs in its own sector size
+        let iv_shift = if header.version == 2 { (sector_size as u64 / SECTOR_SIZE).trailing_zeros() } else { 0 };
+        Ok(Volume {
+            disk: self.disk,
+            cipher,
+            offset: header.data_offset,
+            sector_size,
+            sectors: size / sector_size as u64,
+            iv_tweak: header.iv_tweak,
+            iv_shift,
+        })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+}
+
+/// The data in an unlocked LUKS volume
+pub struct Volume<D: Disk> {
+    disk: D,
+    cipher: SectorCipher,
+    offset: u64,
+    sector_size: usize,
+    sectors: u64,
+    iv_tweak: u64,
+    iv_shift: u32,
+}
+
+impl<D: Disk> Volume<D> {
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+
+    fn check(&self, lba: u64, len: usize) -> Result<()> {
+        if len % self.sector_size != 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        match lba.checked_add((len / self.sector_size) as u64) {
+            Some(end) if end <= self.sectors => Ok(()),
+            _ => Err(EfiErrorKind::EndOfMedia.into()),
+        }
+    }
+
+    // The sectors with their numbers for the IV
+    fn sectors<'a>(&self, lba: u64, buf: &'a mut [u8]) -> impl Iterator<Item = (u64, &'a mut [u8])> {
+        let (per_sector, tweak, shift) = (self.sector_size as u64 / SECTOR_SIZE, self.iv_tweak, self.iv_shift);
+        buf.chunks_mut(self.sector_size).enumerate().map(move |(i, data)| (((lba + i as u64) * per_sector + tweak) >> shift, data))
+    }
+}
+
+impl<D: Disk> BlockDevice for Volume<D> {
+    fn block_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        self.check(lba, buf.len())?;
+        self.disk.read_at(self.offset + lba * self.sector_size as u64, buf)?;
+        for (sector, data) in self.sectors(lba, buf) {
+            self.cipher.decrypt(sector, data);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        self.check(lba, buf.len())?;
+        let mut encrypted = buf.to_vec();
+        for (sector, data) in self.sectors(lba, &mut encrypted) {
+            self.cipher.encrypt(sector, data);
+        }
+        self.disk.write_at(self.offset + lba * self.sector_size as u64, &encrypted)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.disk.flush()
+    }
+}
+
+fn round_up(n: u64, to: u64) -> u64 {
+    match n % to {
+        0 => n,
+        rest => n + to - rest,
+    }
+}
+
+fn pbkdf2(hash: &str, password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) -> Result<()> {
+    match hash {
+        "sha1" => kdf::pbkdf2::<Sha1>(password, salt, iterations, out),
+        "sha256" => kdf::pbkdf2::<Sha256>(password, salt, iterations, out),
+        "sha512" => kdf::pbkdf2::<Sha512>(password, salt, iterations, out),
+        _ => return Err(EfiErrorKind::Unsupported.into()),
+    }
+    Ok(())
+}
+
+fn diffuser(hash: &str) -> Result<fn(&mut [u8])> {
+    match hash {
+        "sha1" => Ok(diffuse::<Sha1>),
+        "sha256" => Ok(diffuse::<Sha256>),
+        "sha512" => Ok(diffuse::<Sha512>),
+        _ => Err(EfiErrorKind::Unsupported.into()),
+    }
+}
+
+// Each hash sized piece of the buffer is replaced with the hash of its index and itself
+fn diffuse<H: Hash>(buf: &mut [u8]) {
+    for (i, piece) in buf.chunks_mut(H::LEN).enumerate() {
+        let mut index = [0u8; 4];
+        BigEndian::write_u32(&mut index, i as u32);
+        let mut hasher = H::new();
+        hasher.update(&index);
+        hasher.update(piece);
+        let mut hash = hasher.finish();
+        piece.copy_from_slice(&hash.as_ref()[..piece.len()]);
+        ::credentials::zeroize(hash.as_mut());
+    }
+}
+
+// The key is the last stripe XORed with all the others, each diffused into the next. Losing any part of any
+// stripe loses the key, which is the point: an old copy of a slot can't be left behind in a remapped sector
+fn af_merge(split: &[u8], key_size: usize, diffuse: fn(&mut [u8])) -> Secret {
+    let mut key = Secret::zeroed(key_size);
+    let stripes = split.len() / key_size;
+    for (i, stripe) in split.chunks(key_size).enumerate() {
+        for (k, s) in key.as_bytes_mut().iter_mut().zip(stripe) {
+            *k ^= s;
+        }
+        if i + 1 < stripes {
+            diffuse(key.as_bytes_mut());
+        }
+    }
+    key
+}
+
+// A NUL padded string
+fn field(bytes: &[u8]) -> Result<String> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    str::from_utf8(&bytes[..len]).map(String::from).map_err(|_| corrupted())
+}
+
+fn parse_luks1(header: &[u8]) -> Result<Header> {
+    let cipher = format!("{}-{}", field(&header[8..40])?, field(&header[40..72])?);
+    let hash = field(&header[72..104])?;
+    let key_size = BigEndian::read_u32(&header[108..]) as usize;
+    if key_size == 0 || key_size > MAX_KEY_LEN {
+        return Err(corrupted());
+    }
+
+    let mut key_slots = Vec::new();
+    for id in 0..LUKS1_KEY_SLOTS {
+        let slot = &header[208 + LUKS1_KEY_SLOT_LEN * id..][..LUKS1_KEY_SLOT_LEN];
+        if BigEndian::read_u32(slot) != LUKS1_KEY_ENABLED {
+            continue;
+        }
+        let stripes = BigEndian::read_u32(&slot[44..]);
+        if stripes == 0 || stripes > MAX_STRIPES {
+            return Err(corrupted());
+        }
+        let area_size = key_size.checked_mul(stripes as usize).ok_or_else(corrupted)?;
+        key_slots.push(KeySlot {
+            id: id as u32,
+            kdf: Kdf::Pbkdf2 { hash: hash.clone(), iterations: BigEndian::read_u32(&slot[4..]) },
+            salt: slot[8..40].to_vec(),
+            area_offset: BigEndian::read_u32(&slot[40..]) as u64 * SECTOR_SIZE,
+            area_size: round_up(area_size as u64, SECTOR_SIZE),
+            area_cipher: cipher.clone(),
+            area_key_size: key_size,
+            key_size,
+            stripes,
+            af_hash: hash.clone(),
+            priority: 1,
+        });
+    }
+
+    let digest = Digest {
+        hash,
+        iterations: BigEndian::read_u32(&header[164..]),
+        salt: header[132..164].to_vec(),
+        digest: header[112..132].to_vec(),
+        key_slots: (0..LUKS1_KEY_SLOTS as u32).collect(),
+    };
+
+    Ok(Header {
+        version: 1,
+        uuid: field(&header[168..208])?,
+        label: String::new(),
+        cipher,
+        data_offset: BigEndian::read_u32(&header[104..]) as u64 * SECTOR_SIZE,
+        data_size: None,
+        sector_size: SECTOR_SIZE as usize,
+        iv_tweak: 0,
+        key_slots,
+        digests: vec![digest],
+    })
+}
+
+// The LUKS2 header at `offset`, binary header and JSON, if its magic and checksum are right
+fn read_luks2_at<D: Disk>(disk: &mut D, offset: u64, magic: &[u8]) -> Result<Vec<u8>> {
+    let mut binary = [0u8; LUKS2_BINARY_HEADER_LEN];
+    disk.read_at(offset, &mut binary)?;
+    let size = BigEndian::read_u64(&binary[8..]);
+    if &binary[..6] != magic || BigEndian::read_u16(&binary[6..]) != 2 || BigEndian::read_u64(&binary[256..]) != offset
+        || !LUKS2_HEADER_SIZES.contains(&size) || field(&binary[72..104])? != "sha256" {
+        return Err(corrupted());
+    }
+
+    let mut header = vec![0u8; size as usize];
+    disk.read_at(offset, &mut header)?;
+    let mut checksum = [0u8; SHA256_LEN];
+    checksum.copy_from_slice(&header[448..448 + SHA256_LEN]);
+    for b in &mut header[448..512] {
+        *b = 0;
+    }
+    if sha256(&header) != checksum {
+        return Err(corrupted());
+    }
+    Ok(header)
+}
+
+fn read_luks2<D: Disk>(disk: &mut D) -> Result<Header> {
+    let header = match read_luks2_at(disk, 0, MAGIC) {
+        Ok(header) => header,
+        Err(_) => LUKS2_HEADER_SIZES.iter()
+            .filter_map(|&offset| read_luks2_at(disk, offset, SECONDARY_MAGIC).ok())
+            .next()
+            .ok_or_else(corrupted)?,
+    };
+    let text = &header[LUKS2_BINARY_HEADER_LEN..];
+    let len = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+    let metadata = str::from_utf8(&text[..len]).ok().and_then(|text| json::parse(text).ok()).ok_or_else(corrupted)?;
+    parse_luks2(&header, &metadata)
+}
+
+// LUKS2 writes sizes and offsets as strings, since JSON numbers can't be trusted with 64 bits, and everything else
+// as numbers
+fn number(object: &json::Value, key: &str) -> Result<u64> {
+    match object.get(key) {
+        Some(json::Value::String(s)) => s.parse().ok(),
+        Some(&json::Value::Integer(n)) if n >= 0 => Some(n as u64),
+        _ => None,
+    }.ok_or_else(corrupted)
+}
+
+fn number_u32(object: &json::Value, key: &str) -> Result<u32> {
+    let n = number(object, key)?;
+    if n > u32::MAX as u64 {
+        return Err(corrupted());
+    }
+    Ok(n as u32)
+}
+
+fn string<'a>(object: &'a json::Value, key: &str) -> Result<&'a str> {
+    object.get(key).and_then(json::Value::as_str).ok_or_else(corrupted)
+}
+
+fn base64(object: &json::Value, key: &str) -> Result<Vec<u8>> {
+    from_base64(string(object, key)?).ok_or_else(corrupted)
+}
+
+fn members(object: &json::Value, key: &str) -> Result<Vec<(u32, json::Value)>> {
+    let members = object.get(key).and_then(json::Value::as_object).ok_or_else(corrupted)?;
+    members.iter().map(|(id, value)| Ok((id.parse().map_err(|_| corrupted())?, value.clone()))).collect()
+}
+
+fn ids(object: &json::Value, key: &str) -> Result<Vec<u32>> {
+    let ids = object.get(key).and_then(json::Value::as_array).ok_or_else(corrupted)?;
+    ids.iter().map(|id| id.as_str().and_then(|id| id.parse().ok()).ok_or_else(corrupted)).collect()
+}
+
+fn parse_kdf(kdf: &json::Value) -> Result<Option<Kdf>> {
+    let variant = match string(kdf, "type")? {
+        "pbkdf2" => return Ok(Some(Kdf::Pbkdf2 { hash: string(kdf, "hash")?.into(), iterations: number_u32(kdf, "iterations")? })),
+        "argon2i" => kdf::Variant::I,
+        "argon2id" => kdf::Variant::Id,
+        _ => return Ok(None),
+    };
+    Ok(Some(Kdf::Argon2(Argon2::new(variant, number_u32(kdf, "memory")?, number_u32(kdf, "time")?, number_u32(kdf, "cpus")?))))
+}
+
+fn parse_luks2(binary: &[u8], metadata: &json::Value) -> Result<Header> {
+    let requirements = metadata.get("config").and_then(|c| c.get("requirements")).and_then(|r| r.get("mandatory"));
+    if !requirements.and_then(json::Value::as_array).unwrap_or(&[]).is_empty() {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+
+    // Slots of types we can't open (reencryption's, or ones from a newer cryptsetup) are left out
+    let mut key_slots = Vec::new();
+    for (id, slot) in members(metadata, "keyslots")? {
+        let (area, af) = (slot.get("area").ok_or_else(corrupted)?, slot.get("af").ok_or_else(corrupted)?);
+        let kdf = match parse_kdf(slot.get("kdf").ok_or_else(corrupted)?)? {
+            Some(kdf) if string(&slot, "type")? == "luks2" && string(area, "type")? == "raw" && string(af, "type")? == "luks1" => kdf,
+            _ => continue,
+        };
+        let (key_size, area_key_size, stripes) = (number(&slot, "key_size")? as usize, number(area, "key_size")? as usize, number_u32(af, "stripes")?);
+        if key_size == 0 || key_size > MAX_KEY_LEN || area_key_size > MAX_KEY_LEN || stripes == 0 || stripes > MAX_STRIPES {
+            return Err(corrupted());
+        }
+        key_slots.push(KeySlot {
+            id,
+            kdf,
+            salt: base64(slot.get("kdf").unwrap(), "salt")?,
+            area_offset: number(area, "offset")?,
+            area_size: number(area, "size")?,
+            area_cipher: string(area, "encryption")?.into(),
+            area_key_size,
+            key_size,
+            stripes,
+            af_hash: string(af, "hash")?.into(),
+            priority: if slot.get("priority").is_some() { number_u32(&slot, "priority")? } else { 1 },
+        });
+    }
+
+    let mut digests = Vec::new();
+    for (_, digest) in members(metadata, "digests")? {
+        if string(&digest, "type")? != "pbkdf2" {
+            continue;
+        }
+        digests.push(Digest {
+            hash: string(&digest, "hash")?.into(),
+            iterations: number_u32(&digest, "iterations")?,
+            salt: base64(&digest, "salt")?,
+            digest: base64(&digest, "digest")?,
+            key_slots: ids(&digest, "keyslots")?,
+        });
+    }
+
+    let mut segments = members(metadata, "segments")?;
+    segments.sort_by_key(|(id, _)| *id);
+    let segment = segments.into_iter().map(|(_, segment)| segment).next().ok_or_else(corrupted)?;
+    if string(&segment, "type")? != "crypt" || segment.get("integrity").filter(|i| !i.is_null()).is_some() {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    let sector_size = number(&segment, "sector_size")?;
+    if !(SECTOR_SIZE..=4096).contains(&sector_size) || !sector_size.is_power_of_two() {
+        return Err(corrupted());
+    }
+
+    Ok(Header {
+        version: 2,
+        uuid: field(&binary[168..208])?,
+        label: field(&binary[24..72])?,
+        cipher: string(&segment, "encryption")?.into(),
+        data_offset: number(&segment, "offset")?,
+        data_size: if string(&segment, "size").ok() == Some("dynamic") { None } else { Some(number(&segment, "size")?) },
+        sector_size: sector_size as usize,
+        iv_tweak: number(&segment, "iv_tweak")?,
+        key_slots,
+        digests,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{BlockDisk, MemDisk};
+    use utils::to_base64;
+
+    const KEY: [u8; 64] = [0x42; 64];
+    const PASSPHRASE: &[u8] = b"correct horse";
+
+    // The reverse of af_merge, with stripes that would be random
+    fn af_split(key: &[u8], stripes: usize) -> Vec<u8> {
+        let mut split = Vec::new();
+        let mut d = vec![0u8; key.len()];
+        for i in 0..stripes - 1 {
+            let stripe = vec![i as u8 + 1; key.len()];
+            for (d, s) in d.iter_mut().zip(&stripe) {
+                *d ^= s;
+            }
+            diffuse::<Sha256>(&mut d);
+            split.extend(stripe);
+        }
+        split.extend(d.iter().zip(key).map(|(d, k)| d ^ k));
+        split
+    }
+
+    // Writes a key slot area holding `KEY`, encrypted under `area_key`
+    fn write_area(disk: &mut MemDisk, offset: u64, area_key: &[u8], stripes: usize) {
+        let mut split = af_split(&KEY, stripes);
+        split.resize(round_up(split.len() as u64, SECTOR_SIZE) as usize, 0);
+        let cipher = SectorCipher::new("aes-xts-plain64", area_key).unwrap();
+        for (sector, data) in split.chunks_mut(SECTOR_SIZE as usize).enumerate() {
+            cipher.encrypt(sector as u64, data);
+        }
+        disk.write_at(offset, &split).unwrap();
+    }
+
+    fn luks1() -> MemDisk {
+        let mut disk = MemDisk::zeroed(16 * 512 + 4 * 512);
+        let mut header = [0u8; LUKS1_HEADER_LEN];
+        header[..6].copy_from_slice(MAGIC);
+        BigEndian::write_u16(&mut header[6..], 1);
+        header[8..11].copy_from_slice(b"aes");
+        header[40..51].copy_from_slice(b"xts-plain64");
+        header[72..78].copy_from_slice(b"sha256");
+        BigEndian::write_u32(&mut header[104..], 16);
+        BigEndian::write_u32(&mut header[108..], KEY.len() as u32);
+        let mut digest = [0u8; 20];
+        kdf::pbkdf2_sha256(&KEY, &[9; 32], 10, &mut digest);
+        header[112..132].copy_from_slice(&digest);
+        header[132..164].copy_from_slice(&[9; 32]);
+        BigEndian::write_u32(&mut header[164..], 10);
+        header[168..172].copy_from_slice(b"uuid");
+
+        let slot = &mut header[208 + LUKS1_KEY_SLOT_LEN * 3..];
+        BigEndian::write_u32(slot, LUKS1_KEY_ENABLED);
+        BigEndian::write_u32(&mut slot[4..], 5);
+        slot[8..40].copy_from_slice(&[3; 32]);
+        BigEndian::write_u32(&mut slot[40..], 8);
+        BigEndian::write_u32(&mut slot[44..], 8);
+        disk.write_at(0, &header).unwrap();
+
+        let mut area_key = [0u8; 64];
+        kdf::pbkdf2_sha256(PASSPHRASE, &[3; 32], 5, &mut area_key);
+        write_area(&mut disk, 8 * 512, &area_key, 8);
+        disk
+    }
+
+    fn luks2_header(metadata: &str, offset: u64, magic: &[u8]) -> Vec<u8> {
+        let mut header = vec![0u8; 0x4000];
+        header[..6].copy_from_slice(magic);
+        BigEndian::write_u16(&mut header[6..], 2);
+        BigEndian::write_u64(&mut header[8..], 0x4000);
+        header[24..28].copy_from_slice(b"boot");
+        header[72..78].copy_from_slice(b"sha256");
+        header[168..172].copy_from_slice(b"uuid");
+        BigEndian::write_u64(&mut header[256..], offset);
+        header[LUKS2_BINARY_HEADER_LEN..][..metadata.len()].copy_from_slice(metadata.as_bytes());
+        let checksum = sha256(&header);
+        header[448..480].copy_from_slice(&checksum);
+        header
+    }
+
+    fn luks2() -> MemDisk {
+        let mut digest = [0u8; 32];
+        kdf::pbkdf2_sha256(&KEY, &[9; 32], 10, &mut digest);
+        let metadata = format!(r#"{{
+            "keyslots": {{
+                "0": {{"type": "luks2", "key_size": 64, "af": {{"type": "luks1", "stripes": 4, "hash": "sha256"}},
+                    "area": {{"type": "raw", "offset": "32768", "size": "4096", "encryption": "aes-xts-plain64", "key_size": 64}},
+                    "kdf": {{"type": "argon2id", "time": 1, "memory": 32, "cpus": 1, "salt": "{}"}}}}
+            }},
+            "tokens": {{}},
+            "segments": {{
+                "0": {{"type": "crypt", "offset": "65536", "size": "dynamic", "iv_tweak": "0", "encryption": "aes-xts-plain64", "sector_size": 4096}}
+            }},
+            "digests": {{
+                "0": {{"type": "pbkdf2", "keyslots": ["0"], "segments": ["0"], "hash": "sha256", "iterations": 10, "salt": "{}", "digest": "{}"}}
+            }},
+            "config": {{"json_size": "12288", "keyslots_size": "32768"}}
+        }}"#, to_base64(&[3; 16]), to_base64(&[9; 32]), to_base64(&digest));
+
+        let mut disk = MemDisk::zeroed(0x10000 + 2 * 4096);
+        disk.write_at(0, &luks2_header(&metadata, 0, MAGIC)).unwrap();
+        disk.write_at(0x4000, &luks2_header(&metadata, 0x4000, SECONDARY_MAGIC)).unwrap();
+        let mut area_key = [0u8; 64];
+        Argon2::new(kdf::Variant::Id, 32, 1, 1).derive(PASSPHRASE, &[3; 16], &mut area_key).unwrap();
+        write_area(&mut disk, 0x8000, &area_key, 4);
+        disk
+    }
+
+    #[test]
+    fn unlocks_volumes() {
+        let mut disk = luks1();
+        assert!(Luks::probe(&mut disk));
+        let mut luks = Luks::open(disk).unwrap();
+        assert_eq!((luks.header().version, &luks.header().cipher[..], &luks.header().uuid[..]), (1, "aes-xts-plain64", "uuid"));
+        assert_eq!(luks.header().key_slots.iter().map(|s| s.id).collect::<Vec<_>>(), [3]);
+        assert_eq!(luks.unlock(b"wrong").unwrap_err().kind(), EfiErrorKind::AccessDenied);
+        assert_eq!(luks.unlock_slot(0, PASSPHRASE).unwrap_err().kind(), EfiErrorKind::NotFound);
+        let key = luks.unlock(PASSPHRASE).unwrap();
+        assert_eq!(key.as_bytes(), &KEY[..]);
+
+        // What's written through the volume is encrypted on the disk and reads back
+        let mut volume = luks.volume(&key).unwrap();
+        assert_eq!((volume.block_size(), volume.block_count()), (512, 4));
+        volume.write_blocks(1, &[0xAA; 1024]).unwrap();
+        let mut sectors = [0u8; 1024];
+        volume.read_blocks(1, &mut sectors).unwrap();
+        assert!(sectors.iter().all(|&b| b == 0xAA));
+        assert_eq!(volume.read_blocks(3, &mut sectors).unwrap_err().kind(), EfiErrorKind::EndOfMedia);
+        let mut disk = volume.into_inner();
+        let mut raw = [0u8; 512];
+        disk.read_at(17 * 512, &mut raw).unwrap();
+        assert!(raw.iter().any(|&b| b != 0xAA));
+        SectorCipher::new("aes-xts-plain64", &KEY).unwrap().decrypt(1, &mut raw);
+        assert!(raw.iter().all(|&b| b == 0xAA));
+
+        // LUKS2 opens from the secondary header when the primary is damaged, and uses its sector size
+        let mut disk = luks2();
+        disk.write_at(LUKS2_BINARY_HEADER_LEN as u64 + 10, b"!").unwrap();
+        let mut luks = Luks::open(disk).unwrap();
+        assert_eq!((luks.header().version, &luks.header().label[..], luks.header().data_size), (2, "boot", None));
+        match luks.header().key_slots[0].kdf {
+            Kdf::Argon2(ref argon2) => assert_eq!((argon2.variant, argon2.memory), (kdf::Variant::Id, 32)),
+            ref kdf => panic!("{:?}", kdf),
+        }
+        let key = luks.unlock(PASSPHRASE).unwrap();
+        let mut disk = BlockDisk::new(luks.volume(&key).unwrap());
+        assert_eq!(disk.size(), 2 * 4096);
+        disk.write_at(4090, b"across sectors").unwrap();
+        let mut text = [0u8; 14];
+        disk.read_at(4090, &mut text).unwrap();
+        assert_eq!(&text, b"across sectors");
+        let mut raw = [0u8; 4096];
+        let mut disk = disk.into_inner().into_inner();
+        disk.read_at(0x10000 + 4096, &mut raw).unwrap();
+        SectorCipher::new("aes-xts-plain64", &KEY).unwrap().decrypt(1, &mut raw);
+        assert_eq!(&raw[..8], b" sectors");
+
+        assert_eq!(Luks::open(MemDisk::zeroed(4096)).err().unwrap().kind(), EfiErrorKind::VolumeCorrupted);
+    }
+
+    #[test]
+    fn rejects_hostile_key_slots() {
+        let slot = 208 + LUKS1_KEY_SLOT_LEN * 3;
+        let mut disk = luks1();
+        disk.write_at(slot as u64 + 44, &[0xFF; 4]).unwrap(); // Stripes
+        assert_eq!(Luks::open(disk).err().unwrap().kind(), EfiErrorKind::VolumeCorrupted);
+
+        // The area has to be on the disk, which is found out before the KDF is run
+        let mut disk = luks1();
+        disk.write_at(slot as u64 + 40, &[0xFF; 4]).unwrap(); // Area offset
+        let mut luks = Luks::open(disk).unwrap();
+        assert_eq!(luks.unlock(PASSPHRASE).unwrap_err().kind(), EfiErrorKind::VolumeCorrupted);
+    }
+}
@@ -0,0 +1,122 @@
+// The sector ciphers dm-crypt has, as named in cipher specs like aes-xts-plain64: a block cipher, a mode and how a
+// sector's number turns into its IV.
+
+use crypto::aes::{Aes, Xts, BLOCK_LEN};
+use credentials::zeroize;
+use hash::sha256;
+use {Result, EfiErrorKind};
+use alloc::boxed::Box;
+use byteorder::{ByteOrder, LittleEndian};
+
+// How a sector's number becomes its IV
+enum Iv {
+    // The low 32 bits, little endian
+    Plain,
+    // All 64 bits, little endian
+    Plain64,
+    // Plain64 encrypted under the SHA-256 of the key, so it can't be predicted without the key
+    Essiv(Box<Aes>),
+}
+
+impl Iv {
+    fn for_sector(&self, sector: u64) -> [u8; BLOCK_LEN] {
+        let mut iv = [0u8; BLOCK_LEN];
+        match *self {
+            Iv::Plain => LittleEndian::write_u32(&mut iv, sector as u32),
+            Iv::Plain64 => LittleEndian::write_u64(&mut iv, sector),
+            Iv::Essiv(ref aes) => {
+                LittleEndian::write_u64(&mut iv, sector);
+                aes.encrypt_block(&mut iv);
+            },
+        }
+        iv
+    }
+}
+
+enum Mode {
+    Xts(Box<Xts>),
+    Cbc(Box<Aes>),
+}
+
+/// A dm-crypt sector cipher
+pub struct SectorCipher {
+    mode: Mode,
+    iv: Iv,
+}
+
+impl SectorCipher {
+    /// From a cryptsetup cipher spec, cipher-mode-iv. Unsupported for anything but AES in XTS or CBC with a plain,
+    /// plain64 or essiv:sha256 IV. InvalidParameter if the key is the wrong size for it
+    pub fn new(spec: &str, key: &[u8]) -> Result<Self> {
+        let mut parts = spec.splitn(3, '-');
+        let (cipher, mode, iv) = (parts.next(), parts.next(), parts.next().unwrap_or("plain"));
+        if cipher != Some("aes") {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        let mode = match mode {
+            Some("xts") => Mode::Xts(Box::new(Xts::new(key)?)),
+            Some("cbc") => Mode::Cbc(Box::new(Aes::new(key)?)),
+            _ => return Err(EfiErrorKind::Unsupported.into()),
+        };
+        let iv = match iv {
+            "plain" => Iv::Plain,
+            "plain64" => Iv::Plain64,
+            "essiv:sha256" => {
+                let mut salt = sha256(key);
+                let aes = Aes::new(&salt);
+                zeroize(&mut salt);
+                Iv::Essiv(Box::new(aes?))
+            },
+            _ => return Err(EfiErrorKind::Unsupported.into()),
+        };
+        Ok(SectorCipher { mode, iv })
+    }
+
+    /// Decrypts a sector, which has to be a multiple of the block size
+    pub fn decrypt(&self, sector: u64, buf: &mut [u8]) {
+        let iv = self.iv.for_sector(sector);
+        match self.mode {
+            Mode::Xts(ref xts) => xts.decrypt(&iv, buf),
+            Mode::Cbc(ref aes) => aes.cbc_decrypt(&iv, buf),
+        }
+    }
+
+    /// Encrypts a sector, which has to be a multiple of the block size
+    pub fn encrypt(&self, sector: u64, buf: &mut [u8]) {
+        let iv = self.iv.for_sector(sector);
+        match self.mode {
+            Mode::Xts(ref xts) => xts.encrypt(&iv, buf),
+            Mode::Cbc(ref aes) => aes.cbc_encrypt(&iv, buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypts_sectors() {
+        // plain64 is the sector number as the tweak
+        let (mut a, mut b) = ([0x5au8; 512], [0x5au8; 512]);
+        SectorCipher::new("aes-xts-plain64", &[1; 64]).unwrap().encrypt(0x1_0000_0002, &mut a);
+        let mut tweak = [0u8; BLOCK_LEN];
+        tweak[0] = 2;
+        tweak[4] = 1;
+        Xts::new(&[1; 64]).unwrap().encrypt(&tweak, &mut b);
+        assert_eq!(a[..], b[..]);
+
+        // With ESSIV each sector chains from an IV that depends on its number
+        let cbc = SectorCipher::new("aes-cbc-essiv:sha256", &[7; 32]).unwrap();
+        let (mut a, mut b) = ([0x5au8; 512], [0x5au8; 512]);
+        cbc.encrypt(1, &mut a);
+        cbc.encrypt(2, &mut b);
+        assert!(a[..] != b[..] && a[..16] != a[16..32]);
+        cbc.decrypt(1, &mut a);
+        assert!(a.iter().all(|&x| x == 0x5a));
+
+        assert_eq!(SectorCipher::new("serpent-xts-plain64", &[0; 64]).err().unwrap().kind(), EfiErrorKind::Unsupported);
+        assert_eq!(SectorCipher::new("aes-xts-benbi", &[0; 64]).err().unwrap().kind(), EfiErrorKind::Unsupported);
+        assert_eq!(SectorCipher::new("aes-xts-plain64", &[0; 40]).err().unwrap().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
@@ -0,0 +1,348 @@
+// Dumping physical memory for offline forensics. The ranges come from the memory map and are streamed to anything
+// that's io::Write - a file, a TCP connection, a serial port - in chunks that are each stored raw, elided if they're
+// all zeros or compressed as an LZ4 block (the raw block format, without the frame, so any LZ4 library can decode
+// them). Nothing is ever seeked back over, so the ranges go in a header up front and the offset of each chunk in an
+// index at the end, with a fixed size footer pointing at the index last of all:
+//
+//   header   magic "EFIMDUMP", version, compression, chunk size and range count, all u32 but the magic
+//   ranges   start u64, length u64, memory type u32, reserved u32; for each range
+//   chunks   encoding u32, stored size u32, stored bytes; for each chunk of each range, in order
+//   index    the u64 offset of each chunk
+//   footer   index offset u64, chunk count u64, magic "EFIMDIDX"
+//
+// Everything is little endian. This is live memory, and dumping it allocates, so the pages holding our own buffers
+// won't be what they were before we started.
+
+use memory::MemoryMap;
+use ffi::boot_services::{EFI_MEMORY_DESCRIPTOR, EFI_MEMORY_TYPE};
+use pages::PAGE_SIZE;
+use io::{self, Write};
+use byteorder::{ByteOrder, LittleEndian};
+use alloc::vec::Vec;
+use core::{cmp, ptr};
+
+pub const MAGIC: &[u8; 8] = b"EFIMDUMP";
+pub const INDEX_MAGIC: &[u8; 8] = b"EFIMDIDX";
+pub const VERSION: u32 = 1;
+
+pub const HEADER_SIZE: usize = 24;
+pub const RANGE_SIZE: usize = 24;
+pub const CHUNK_HEADER_SIZE: usize = 8;
+pub const FOOTER_SIZE: usize = 24;
+
+/// How a chunk is stored
+pub const CHUNK_RAW: u32 = 0;
+pub const CHUNK_ZERO: u32 = 1;
+pub const CHUNK_LZ4: u32 = 2;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 16 * PAGE_SIZE;
+
+// EfiPersistentMemory, from UEFI 2.5 on
+const EFI_PERSISTENT_MEMORY: u32 = 14;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    /// Every chunk that isn't all zeros is stored raw
+    None,
+    /// Chunks are compressed as LZ4 blocks unless that doesn't make them smaller
+    Lz4,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Range {
+    pub start: u64,
+    pub length: u64,
+    /// The EFI_MEMORY_TYPE it had in the memory map
+    pub memory_type: u32,
+}
+
+/// Whether the descriptor is for memory, as opposed to MMIO or something not there at all. These are the ranges
+/// worth dumping
+pub fn is_memory(descriptor: &EFI_MEMORY_DESCRIPTOR) -> bool {
+    use self::EFI_MEMORY_TYPE::*;
+    [EfiLoaderCode, EfiLoaderData, EfiBootServicesCode, EfiBootServicesData, EfiRuntimeServicesCode, EfiRuntimeServicesData,
+     EfiConventionalMemory, EfiACPIReclaimMemory, EfiACPIMemoryNVS, EfiPalCode].iter().any(|&t| descriptor.Type == t as u32)
+        || descriptor.Type == EFI_PERSISTENT_MEMORY
+}
+
+/// The ranges of the descriptors the filter picks, with neighbours of the same type merged
+pub fn ranges<F: FnMut(&EFI_MEMORY_DESCRIPTOR) -> bool>(map: &MemoryMap, mut filter: F) -> Vec<Range> {
+    let mut descriptors: Vec<_> = map.iter().filter(|d| filter(d)).collect();
+    descriptors.sort_by_key(|d| d.PhysicalStart);
+    let mut ranges: Vec<Range> = Vec::new();
+    for d in descriptors {
+        let length = d.NumberOfPages * PAGE_SIZE as u64;
+        match ranges.last_mut() {
+            Some(last) if last.start + last.length == d.PhysicalStart && last.memory_type == d.Type => last.length += length,
+            _ => ranges.push(Range { start: d.PhysicalStart, length, memory_type: d.Type }),
+        }
+    }
+    ranges
+}
+
+/// Writes a dump of the given ranges
+pub struct Dumper<W: Write> {
+    writer: W,
+    ranges: Vec<Range>,
+    compression: Compression,
+    chunk_size: usize,
+}
+
+impl<W: Write> Dumper<W> {
+    /// A dumper that compresses with LZ4 in chunks of DEFAULT_CHUNK_SIZE
+    pub fn new(writer: W, ranges: Vec<Range>) -> Self {
+        Self { writer, ranges, compression: Compression::Lz4, chunk_size: DEFAULT_CHUNK_SIZE }
+    }
+
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+
+    /// How much memory goes in each chunk. Smaller chunks compress worse but can be got at with less decompressing.
+    /// Panics if it's zero
+    pub fn chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Writes the whole dump, calling `progress` with the bytes of memory done and the total after each chunk.
+    /// Returns how many bytes were written
+    pub fn write<F: FnMut(u64, u64)>(&mut self, mut progress: F) -> io::Result<u64> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[..8].copy_from_slice(MAGIC);
+        LittleEndian::write_u32(&mut header[8..], VERSION);
+        LittleEndian::write_u32(&mut header[12..], match self.compression { Compression::None => 0, Compression::Lz4 => 1 });
+        LittleEndian::write_u32(&mut header[16..], self.chunk_size as u32);
+        LittleEndian::write_u32(&mut header[20..], self.ranges.len() as u32);
+        self.writer.write_all(&header)?;
+        for range in &self.ranges {
+            let mut entry = [0u8; RANGE_SIZE];
+            LittleEndian::write_u64(&mut entry, range.start);
+            LittleEndian::write_u64(&mut entry[8..], range.length);
+            LittleEndian::write_u32(&mut entry[16..], range.memory_type);
+            self.writer.write_all(&entry)?;
+        }
+        let mut written = (HEADER_SIZE + RANGE_SIZE * self.ranges.len()) as u64;
+
+        let total = self.ranges.iter().map(|r| r.length).sum();
+        let mut done = 0;
+        let mut index = Vec::new();
+        let mut chunk = vec![0u8; self.chunk_size];
+        let mut compressed = Vec::with_capacity(self.chunk_size);
+        for range in &self.ranges {
+            let mut address = range.start;
+            while address < range.start + range.length {
+                let len = cmp::min(self.chunk_size as u64, range.start + range.length - address) as usize;
+                unsafe { read_physical(address, &mut chunk[..len]) };
+                let data = &chunk[..len];
+
+                let is_zero = data.iter().all(|&b| b == 0);
+                compressed.clear();
+                if !is_zero && self.compression == Compression::Lz4 {
+                    lz4_compress(data, &mut compressed);
+                }
+                let (encoding, stored) = if is_zero {
+                    (CHUNK_ZERO, &[][..])
+                } else if !compressed.is_empty() && compressed.len() < len {
+                    (CHUNK_LZ4, &compressed[..])
+                } else {
+                    (CHUNK_RAW, data)
+                };
+                let mut chunk_header = [0u8; CHUNK_HEADER_SIZE];
+                LittleEndian::write_u32(&mut chunk_header, encoding);
+                LittleEndian::write_u32(&mut chunk_header[4..], stored.len() as u32);
+                self.writer.write_all(&chunk_header)?;
+                self.writer.write_all(stored)?;
+                index.push(written);
+                written += (CHUNK_HEADER_SIZE + stored.len()) as u64;
+
+                address += len as u64;
+                done += len as u64;
+                progress(done, total);
+            }
+        }
+
+        let index_offset = written;
+        for offset in &index {
+            let mut entry = [0u8; 8];
+            LittleEndian::write_u64(&mut entry, *offset);
+            self.writer.write_all(&entry)?;
+        }
+        let mut footer = [0u8; FOOTER_SIZE];
+        LittleEndian::write_u64(&mut footer, index_offset);
+        LittleEndian::write_u64(&mut footer[8..], index.len() as u64);
+        footer[16..].copy_from_slice(INDEX_MAGIC);
+        self.writer.write_all(&footer)?;
+        self.writer.flush()?;
+        Ok(written + (index.len() * 8 + FOOTER_SIZE) as u64)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+// Copies with volatile reads so that nothing is assumed about what's there - page zero included - a word at a time
+// where the address allows it
+pub(crate) unsafe fn read_physical(address: u64, buf: &mut [u8]) {
+    let mut done = 0;
+    if address & 7 == 0 {
+        let words = address as *const u64;
+        for (i, bytes) in buf.chunks_exact_mut(8).enumerate() {
+            bytes.copy_from_slice(&ptr::read_volatile(words.add(i)).to_ne_bytes());
+            done += 8;
+        }
+    }
+    let bytes = address as *const u8;
+    for (i, b) in buf.iter_mut().enumerate().skip(done) {
+        *b = ptr::read_volatile(bytes.add(i));
+    }
+}
+
+// LZ4 wants at least this much of a match, the last five bytes of a block as literals and no match starting in the
+// last twelve
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+const MATCH_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+
+// A greedy LZ4 block compressor with one candidate per hash. Nowhere near as thorough as the real thing but it does
+// well enough on memory, which is mostly zeros and repeats
+fn lz4_compress(input: &[u8], out: &mut Vec<u8>) {
+    let mut table = [0usize; 1 << HASH_BITS]; // Position + 1, so zero is empty
+    let mut anchor = 0;
+    let mut i = 0;
+    if input.len() > MATCH_LIMIT {
+        while i < input.len() - MATCH_LIMIT {
+            let sequence = LittleEndian::read_u32(&input[i..]);
+            let hash = (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+            let candidate = table[hash];
+            table[hash] = i + 1;
+            if candidate != 0 && i - (candidate - 1) <= 0xffff && LittleEndian::read_u32(&input[candidate - 1..]) == sequence {
+                let from = candidate - 1;
+                let mut len = MIN_MATCH;
+                while i + len < input.len() - LAST_LITERALS && input[from + len] == input[i + len] {
+                    len += 1;
+                }
+                lz4_sequence(out, &input[anchor..i], Some(((i - from) as u16, len)));
+                i += len;
+                anchor = i;
+            } else {
+                i += 1;
+            }
+        }
+    }
+    lz4_sequence(out, &input[anchor..], None);
+}
+
+fn lz4_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(u16, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((cmp::min(literals.len(), 15) << 4 | cmp::min(match_len, 15)) as u8);
+    if literals.len() >= 15 {
+        lz4_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&offset.to_le_bytes());
+        if match_len >= 15 {
+            lz4_length(out, match_len - 15);
+        }
+    }
+}
+
+fn lz4_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lz4_decompress(mut input: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        let length = |input: &mut &[u8], mut len: usize| {
+            if len == 15 {
+                loop {
+                    let b = input[0];
+                    *input = &input[1..];
+                    len += b as usize;
+                    if b != 255 { break len; }
+                }
+            } else {
+                len
+            }
+        };
+        loop {
+            let token = input[0];
+            input = &input[1..];
+            let literals = length(&mut input, (token >> 4) as usize);
+            out.extend_from_slice(&input[..literals]);
+            input = &input[literals..];
+            if input.is_empty() {
+                return out;
+            }
+            let offset = LittleEndian::read_u16(input) as usize;
+            input = &input[2..];
+            let len = length(&mut input, (token & 15) as usize) + MIN_MATCH;
+            for _ in 0..len {
+                let b = out[out.len() - offset];
+                out.push(b);
+            }
+        }
+    }
+
+    #[test]
+    fn dumps_and_indexes_chunks() {
+        // A zero page, a compressible page and a page of noise, then a short range that isn't a whole chunk
+        let mut memory = vec![0u64; 3 * PAGE_SIZE / 8 + 4];
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for (i, word) in memory.iter_mut().enumerate().skip(PAGE_SIZE / 8) {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            *word = if i < 2 * PAGE_SIZE / 8 { (i as u64 % 5) * 0x0101_0101 } else { seed };
+        }
+        let base = memory.as_ptr() as u64;
+        let ranges = vec![
+            Range { start: base, length: 3 * PAGE_SIZE as u64, memory_type: EFI_MEMORY_TYPE::EfiConventionalMemory as u32 },
+            Range { start: base + 3 * PAGE_SIZE as u64, length: 32, memory_type: EFI_MEMORY_TYPE::EfiLoaderData as u32 },
+        ];
+        let mut dumper = Dumper::new(Vec::new(), ranges.clone());
+        let mut reports = Vec::new();
+        let size = dumper.chunk_size(PAGE_SIZE).write(|done, total| reports.push((done, total))).unwrap();
+        let dump = dumper.into_inner();
+        assert_eq!(size, dump.len() as u64);
+        assert_eq!(reports.last(), Some(&(3 * PAGE_SIZE as u64 + 32, 3 * PAGE_SIZE as u64 + 32)));
+
+        assert_eq!(&dump[..8], MAGIC);
+        assert_eq!((LittleEndian::read_u32(&dump[16..]), LittleEndian::read_u32(&dump[20..])), (PAGE_SIZE as u32, 2));
+        assert_eq!(LittleEndian::read_u64(&dump[HEADER_SIZE + RANGE_SIZE..]), base + 3 * PAGE_SIZE as u64);
+
+        let footer = &dump[dump.len() - FOOTER_SIZE..];
+        assert_eq!(&footer[16..], INDEX_MAGIC);
+        let (index_offset, count) = (LittleEndian::read_u64(footer) as usize, LittleEndian::read_u64(&footer[8..]) as usize);
+        assert_eq!(count, 4);
+        let mut contents = Vec::new();
+        let mut encodings = Vec::new();
+        for i in 0..count {
+            let at = LittleEndian::read_u64(&dump[index_offset + i * 8..]) as usize;
+            let (encoding, size) = (LittleEndian::read_u32(&dump[at..]), LittleEndian::read_u32(&dump[at + 4..]) as usize);
+            let stored = &dump[at + CHUNK_HEADER_SIZE..at + CHUNK_HEADER_SIZE + size];
+            encodings.push(encoding);
+            match encoding {
+                CHUNK_ZERO => contents.extend(vec![0u8; PAGE_SIZE]),
+                CHUNK_LZ4 => contents.extend(lz4_decompress(stored)),
+                _ => contents.extend_from_slice(stored),
+            }
+        }
+        assert_eq!(encodings, vec![CHUNK_ZERO, CHUNK_LZ4, CHUNK_RAW, CHUNK_RAW]);
+        let original = unsafe { ::core::slice::from_raw_parts(base as *const u8, memory.len() * 8) };
+        assert_eq!(contents, original);
+    }
+}
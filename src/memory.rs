@@ -0,0 +1,131 @@
+// The UEFI memory map
+
+use ffi::{
+    boot_services::{EFI_MEMORY_DESCRIPTOR, EFI_MEMORY_TYPE},
+    EFI_BUFFER_TOO_SMALL,
+    UINTN,
+    UINT32,
+};
+use pages::{Pages, PAGE_SIZE};
+use {Result, system_table};
+use core::mem;
+
+// Extra descriptors to leave room for when allocating the map buffer.
+// Allocating the buffer itself can split an existing region and grow the map.
+const SLACK_DESCRIPTORS: usize = 8;
+
+/// A snapshot of the memory map along with the key needed to exit boot services
+pub struct MemoryMap {
+    buf: Pages,
+    len: usize, // Bytes of buf actually filled by the firmware
+    map_key: UINTN,
+    descriptor_size: usize,
+    descriptor_version: UINT32,
+}
+
+impl MemoryMap {
+    /// Gets the current memory map from firmware
+    pub fn get() -> Result<Self> {
+        let bs = system_table().BootServices;
+
+        // First find out how big a buffer we need
+        let mut size: UINTN = 0;
+        let mut map_key: UINTN = 0;
+        let mut descriptor_size: UINTN = 0;
+        let mut descriptor_version: UINT32 = 0;
+        unsafe {
+            let status = traced!(((*bs).GetMemoryMap)(&mut size, core::ptr::null_mut(), &mut map_key, &mut descriptor_size, &mut descriptor_version));
+            if status != EFI_BUFFER_TOO_SMALL {
+                ret_on_err!(status);
+            }
+        }
+
+        let descriptor_size = if descriptor_size == 0 { mem::size_of::<EFI_MEMORY_DESCRIPTOR>() } else { descriptor_size };
+        let buf = Pages::allocate(Pages::count_for(size + SLACK_DESCRIPTORS * descriptor_size))?;
+        let mut map = Self { buf, len: 0, map_key: 0, descriptor_size, descriptor_version };
+        map.refresh()?;
+        Ok(map)
+    }
+
+    /// Gets the memory map again into the same buffer. Does not allocate, which makes this safe
+    /// to call between a failed ExitBootServices and the retry.
+    pub fn refresh(&mut self) -> Result<()> {
+        let bs = system_table().BootServices;
+        let mut size = self.buf.len() as UINTN;
+        let mut descriptor_size: UINTN = 0;
+        unsafe {
+            ret_on_err!(((*bs).GetMemoryMap)(&mut size, self.buf.as_mut_ptr() as *mut EFI_MEMORY_DESCRIPTOR, &mut self.map_key, &mut descriptor_size, &mut self.descriptor_version));
+        }
+
+        self.len = size;
+        self.descriptor_size = descriptor_size;
+        Ok(())
+    }
+
+    /// Key identifying this snapshot. Required by ExitBootServices
+    pub fn key(&self) -> UINTN {
+        self.map_key
+    }
+
+    pub fn descriptor_size(&self) -> usize {
+        self.descriptor_size
+    }
+
+    pub fn descriptor_version(&self) -> u32 {
+        self.descriptor_version
+    }
+
+    /// Number of descriptors in the map
+    pub fn len(&self) -> usize {
+        self.len / self.descriptor_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The raw map as the firmware returned it
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf.as_slice()[..self.len]
+    }
+
+    pub fn iter(&self) -> MemoryMapIter<'_> {
+        MemoryMapIter { map: self, index: 0 }
+    }
+
+    /// Total number of bytes of memory of the given type
+    pub fn total_of_type(&self, memory_type: EFI_MEMORY_TYPE) -> u64 {
+        self.iter().filter(|d| d.Type == memory_type as u32).map(|d| d.NumberOfPages * PAGE_SIZE as u64).sum()
+    }
+}
+
+// Descriptors must be walked using descriptor_size rather than size_of::<EFI_MEMORY_DESCRIPTOR>()
+// because newer firmware is allowed to append fields to them
+#[derive(Clone)]
+pub struct MemoryMapIter<'a> {
+    map: &'a MemoryMap,
+    index: usize,
+}
+
+impl<'a> Iterator for MemoryMapIter<'a> {
+    type Item = &'a EFI_MEMORY_DESCRIPTOR;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.map.len() {
+            return None;
+        }
+
+        let offset = self.index * self.map.descriptor_size;
+        self.index += 1;
+        unsafe { Some(&*(self.map.buf.as_ptr().add(offset) as *const EFI_MEMORY_DESCRIPTOR)) } // Pages are page aligned and descriptor_size is a multiple of 8 so this is aligned
+    }
+}
+
+impl<'a> IntoIterator for &'a MemoryMap {
+    type Item = &'a EFI_MEMORY_DESCRIPTOR;
+    type IntoIter = MemoryMapIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
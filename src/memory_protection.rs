@@ -0,0 +1,341 @@
+// Page protections: making memory read-only or non-executable, so what a loader stages for the OS (kernels,
+// trampolines, page tables) is never writable and executable at once.
+//
+// Firmware from UEFI 2.10 on has the memory attribute protocol for this. Before that the only way was the PI DXE
+// services, found in the configuration table of EDK2 derived firmware, whose SetMemorySpaceAttributes replaces all of
+// a region's attributes and so has to be told the cacheability it already has. get() prefers the protocol and falls
+// back to the DXE services, failing with Unsupported if there's neither.
+//
+// Both work on whole pages of memory that's been allocated, and only until ExitBootServices.
+
+use ffi::{
+    boot_services::{EFI_MEMORY_RP, EFI_MEMORY_RO, EFI_MEMORY_XP},
+    memory_attribute::*,
+    EFI_GUID,
+    EFI_SUCCESS,
+    UINT64,
+};
+use pages::{Pages, PAGE_SIZE};
+use {Result, EfiErrorKind, system_table};
+use alloc::vec::Vec;
+use core::{fmt, mem, ops::BitOr, ptr, slice};
+
+/// A set of page protections
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Protection(pub u64);
+
+impl Protection {
+    /// Writes fault
+    pub const READ_ONLY: Self = Protection(EFI_MEMORY_RO);
+    /// Instruction fetches fault
+    pub const NO_EXECUTE: Self = Protection(EFI_MEMORY_XP);
+    /// Any access faults. For guard pages
+    pub const NO_ACCESS: Self = Protection(EFI_MEMORY_RP);
+    /// Every protection there is
+    pub const ALL: Self = Protection(EFI_MEMORY_RO | EFI_MEMORY_XP | EFI_MEMORY_RP);
+
+    /// Code: executable but not writable
+    pub const CODE: Self = Self::READ_ONLY;
+    /// Data: writable but not executable
+    pub const DATA: Self = Self::NO_EXECUTE;
+    /// Constants: neither writable nor executable
+    pub const READ_ONLY_DATA: Self = Protection(EFI_MEMORY_RO | EFI_MEMORY_XP);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for Protection {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Protection(self.0 | other.0)
+    }
+}
+
+/// "r", "w" and "x" as ls would have them, e.g. "r-x" for code
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.contains(Self::NO_ACCESS) {
+            return f.write_str("---");
+        }
+        write!(f, "r{}{}", if self.contains(Self::READ_ONLY) { "-" } else { "w" }, if self.contains(Self::NO_EXECUTE) { "-" } else { "x" })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Backend {
+    Protocol(*const EFI_MEMORY_ATTRIBUTE_PROTOCOL),
+    Dxe(*const EFI_DXE_SERVICES),
+}
+
+/// Gets and sets the protections on ranges of pages
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryProtection {
+    backend: Backend,
+}
+
+impl MemoryProtection {
+    pub fn get() -> Result<Self> {
+        if let Some(protocol) = locate_protocol() {
+            return Ok(MemoryProtection { backend: Backend::Protocol(protocol) });
+        }
+        match dxe_services() {
+            Some(dxe) => Ok(MemoryProtection { backend: Backend::Dxe(dxe) }),
+            None => Err(EfiErrorKind::Unsupported.into()),
+        }
+    }
+
+    /// Whether it's the memory attribute protocol doing the work rather than the DXE services
+    pub fn is_protocol(&self) -> bool {
+        match self.backend {
+            Backend::Protocol(_) => true,
+            Backend::Dxe(_) => false,
+        }
+    }
+
+    /// The protections on the range. Fails with NoMapping if they aren't the same all the way through it
+    pub fn protection(&self, base: u64, len: u64) -> Result<Protection> {
+        check_range(base, len)?;
+        match self.backend {
+            Backend::Protocol(protocol) => {
+                let mut attributes: UINT64 = 0;
+                unsafe {
+                    ret_on_err!(((*protocol).GetMemoryAttributes)(protocol, base, len, &mut attributes));
+                }
+                Ok(Protection(attributes & Protection::ALL.0))
+            },
+            Backend::Dxe(dxe) => {
+                let mut found = None;
+                for (_, _, attributes) in descriptors(dxe, base, len)? {
+                    let protection = Protection(attributes & Protection::ALL.0);
+                    match found {
+                        Some(p) if p != protection => return Err(EfiErrorKind::NoMapping.into()),
+                        _ => found = Some(protection),
+                    }
+                }
+                Ok(found.unwrap_or_default())
+            },
+        }
+    }
+
+    /// Adds protections to the range, leaving any it has already
+    pub fn add(&self, base: u64, len: u64, protection: Protection) -> Result<()> {
+        self.update(base, len, protection, Protection::default())
+    }
+
+    /// Takes protections off the range, leaving the rest
+    pub fn remove(&self, base: u64, len: u64, protection: Protection) -> Result<()> {
+        self.update(base, len, Protection::default(), protection)
+    }
+
+    /// Gives the range exactly these protections
+    pub fn set(&self, base: u64, len: u64, protection: Protection) -> Result<()> {
+        self.update(base, len, protection, Protection(Protection::ALL.0 & !protection.0))
+    }
+
+    /// Gives pages exactly these protections, e.g. Protection::CODE once a kernel has been copied into them
+    pub fn set_pages(&self, pages: &Pages, protection: Protection) -> Result<()> {
+        self.set(pages.addr(), pages.len() as u64, protection)
+    }
+
+    fn update(&self, base: u64, len: u64, add: Protection, remove: Protection) -> Result<()> {
+        check_range(base, len)?;
+        if (add | remove).0 & !Protection::ALL.0 != 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        match self.backend {
+            Backend::Protocol(protocol) => unsafe {
+                // Clearing first so that going from code to data there's no moment the pages are both
+                if !remove.is_empty() {
+                    ret_on_err!(((*protocol).ClearMemoryAttributes)(protocol, base, len, remove.0));
+                }
+                if !add.is_empty() {
+                    ret_on_err!(((*protocol).SetMemoryAttributes)(protocol, base, len, add.0));
+                }
+                Ok(())
+            },
+            Backend::Dxe(dxe) => {
+                for (start, size, attributes) in descriptors(dxe, base, len)? {
+                    let attributes = (attributes & !remove.0) | add.0;
+                    unsafe {
+                        ret_on_err!(((*dxe).SetMemorySpaceAttributes)(start, size, attributes));
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+fn check_range(base: u64, len: u64) -> Result<()> {
+    let offset = PAGE_SIZE as u64 - 1;
+    if base & offset != 0 || len & offset != 0 || len == 0 || base.checked_add(len).is_none() {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+    Ok(())
+}
+
+fn locate_protocol() -> Option<*const EFI_MEMORY_ATTRIBUTE_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL = ptr::null();
+    let status = unsafe { traced!(((*bs).LocateProtocol)(&EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol))) };
+    if status == EFI_SUCCESS && !protocol.is_null() { Some(protocol) } else { None }
+}
+
+fn dxe_services() -> Option<*const EFI_DXE_SERVICES> {
+    let st = system_table();
+    if st.ConfigurationTable.is_null() {
+        return None;
+    }
+    let tables = unsafe { slice::from_raw_parts(st.ConfigurationTable, st.NumberOfTableEntries) };
+    let guid: EFI_GUID = DXE_SERVICES_TABLE_GUID;
+    let dxe = tables.iter().find(|t| t.VendorGuid == guid)?.VendorTable as *const EFI_DXE_SERVICES;
+    if dxe.is_null() || unsafe { (*dxe).Hdr.Signature } != DXE_SERVICES_SIGNATURE {
+        return None;
+    }
+    Some(dxe)
+}
+
+// The pieces of the range in each GCD region it covers, with that region's attributes
+fn descriptors(dxe: *const EFI_DXE_SERVICES, base: u64, len: u64) -> Result<impl Iterator<Item=(u64, u64, u64)>> {
+    let end = base + len;
+    let mut pieces = Vec::new();
+    let mut addr = base;
+    while addr < end {
+        let mut descriptor: EFI_GCD_MEMORY_SPACE_DESCRIPTOR = unsafe { mem::zeroed() };
+        unsafe {
+            ret_on_err!(((*dxe).GetMemorySpaceDescriptor)(addr, &mut descriptor));
+        }
+        let region_end = descriptor.BaseAddress.saturating_add(descriptor.Length);
+        if descriptor.GcdMemoryType == EFI_GCD_MEMORY_TYPE::EfiGcdMemoryTypeNonExistent || region_end <= addr {
+            return Err(EfiErrorKind::NoMapping.into());
+        }
+        let piece_end = if region_end < end { region_end } else { end };
+        pieces.push((addr, piece_end - addr, descriptor.Attributes));
+        addr = piece_end;
+    }
+    Ok(pieces.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::{EFI_MEMORY_WB, EFI_INTERFACE_TYPE}, EFI_STATUS, EFI_NO_MAPPING, EFI_TABLE_HEADER, EFI_PHYSICAL_ADDRESS, EFI_HANDLE, VOID};
+    use testing::mock;
+    use alloc::boxed::Box;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    const BASE: u64 = 0x10_0000;
+
+    // The attributes of each page from BASE. The DXE services have no This to hang them off
+    static PAGES: [AtomicU64; 4] = [AtomicU64::new(EFI_MEMORY_WB), AtomicU64::new(EFI_MEMORY_WB), AtomicU64::new(EFI_MEMORY_WB), AtomicU64::new(EFI_MEMORY_WB)];
+
+    fn pages(base: u64, len: u64) -> &'static [AtomicU64] {
+        let page = |addr: u64| ((addr - BASE) / PAGE_SIZE as u64) as usize;
+        &PAGES[page(base)..page(base + len)]
+    }
+
+    extern "efiapi" fn get_attributes(_this: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL, base: EFI_PHYSICAL_ADDRESS, len: UINT64, attributes: *mut UINT64) -> EFI_STATUS {
+        let range = pages(base, len);
+        let first = range[0].load(Ordering::SeqCst);
+        if range.iter().any(|a| a.load(Ordering::SeqCst) != first) {
+            return EFI_NO_MAPPING;
+        }
+        unsafe { *attributes = first & Protection::ALL.0 };
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn set_attributes(_this: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL, base: EFI_PHYSICAL_ADDRESS, len: UINT64, attributes: UINT64) -> EFI_STATUS {
+        pages(base, len).iter().for_each(|a| { a.fetch_or(attributes, Ordering::SeqCst); });
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn clear_attributes(_this: *const EFI_MEMORY_ATTRIBUTE_PROTOCOL, base: EFI_PHYSICAL_ADDRESS, len: UINT64, attributes: UINT64) -> EFI_STATUS {
+        pages(base, len).iter().for_each(|a| { a.fetch_and(!attributes, Ordering::SeqCst); });
+        EFI_SUCCESS
+    }
+
+    // Each page is its own GCD region
+    extern "efiapi" fn get_descriptor(addr: EFI_PHYSICAL_ADDRESS, descriptor: *mut EFI_GCD_MEMORY_SPACE_DESCRIPTOR) -> EFI_STATUS {
+        let attributes = pages(addr, PAGE_SIZE as u64)[0].load(Ordering::SeqCst);
+        unsafe {
+            *descriptor = EFI_GCD_MEMORY_SPACE_DESCRIPTOR {
+                BaseAddress: addr & !(PAGE_SIZE as u64 - 1),
+                Length: PAGE_SIZE as u64,
+                Capabilities: 0,
+                Attributes: attributes,
+                GcdMemoryType: EFI_GCD_MEMORY_TYPE::EfiGcdMemoryTypeSystemMemory,
+                ImageHandle: ptr::null_mut(),
+                DeviceHandle: ptr::null_mut(),
+            };
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn set_space_attributes(base: EFI_PHYSICAL_ADDRESS, len: UINT64, attributes: UINT64) -> EFI_STATUS {
+        pages(base, len).iter().for_each(|a| a.store(attributes, Ordering::SeqCst));
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn sets_exact_protections_either_way() {
+        mock::install();
+        let page = PAGE_SIZE as u64;
+        assert_eq!(MemoryProtection::get().unwrap_err().kind(), EfiErrorKind::Unsupported);
+
+        // Only the DXE services, which have to keep the cacheability
+        let dxe: &'static EFI_DXE_SERVICES = Box::leak(Box::new(EFI_DXE_SERVICES {
+            Hdr: EFI_TABLE_HEADER { Signature: DXE_SERVICES_SIGNATURE, Revision: 0, HeaderSize: 0, CRC32: 0, Reserved: 0 },
+            AddMemorySpace: ptr::null(),
+            AllocateMemorySpace: ptr::null(),
+            FreeMemorySpace: ptr::null(),
+            RemoveMemorySpace: ptr::null(),
+            GetMemorySpaceDescriptor: get_descriptor,
+            SetMemorySpaceAttributes: set_space_attributes,
+            GetMemorySpaceMap: ptr::null(),
+        }));
+        let bs = system_table().BootServices;
+        unsafe { ((*bs).InstallConfigurationTable)(&DXE_SERVICES_TABLE_GUID, dxe as *const _ as *const _) };
+        let mp = MemoryProtection::get().unwrap();
+        assert!(!mp.is_protocol());
+        mp.set(BASE, 2 * page, Protection::CODE).unwrap();
+        mp.set(BASE + 2 * page, page, Protection::DATA).unwrap();
+        assert_eq!(mp.protection(BASE, 2 * page).unwrap(), Protection::CODE);
+        assert_eq!(mp.protection(BASE, 3 * page).unwrap_err().kind(), EfiErrorKind::NoMapping);
+        assert_eq!(PAGES[0].load(Ordering::SeqCst), EFI_MEMORY_WB | EFI_MEMORY_RO);
+        assert_eq!(mp.set(BASE + 1, page, Protection::CODE).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+
+        // The protocol when there is one
+        let protocol: &'static EFI_MEMORY_ATTRIBUTE_PROTOCOL = Box::leak(Box::new(EFI_MEMORY_ATTRIBUTE_PROTOCOL {
+            GetMemoryAttributes: get_attributes,
+            SetMemoryAttributes: set_attributes,
+            ClearMemoryAttributes: clear_attributes,
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, protocol as *const _ as *const VOID);
+        }
+        let mp = MemoryProtection::get().unwrap();
+        assert!(mp.is_protocol());
+        mp.set(BASE, 3 * page, Protection::READ_ONLY_DATA).unwrap();
+        assert_eq!(mp.protection(BASE, 3 * page).unwrap(), Protection::READ_ONLY_DATA);
+        mp.remove(BASE, page, Protection::READ_ONLY).unwrap();
+        assert_eq!(mp.protection(BASE, page).unwrap(), Protection::DATA);
+        assert_eq!(format!("{}", Protection::CODE), "r-x");
+        assert_eq!(format!("{}", Protection::DATA), "rw-");
+    }
+}
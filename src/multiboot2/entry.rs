@@ -0,0 +1,45 @@
+// Jumping to the kernel. Both entries want EAX = the magic and EBX = the boot information, and LLVM won't let us hand
+// it RBX directly on x86_64, so it goes through ECX. Neither returns.
+
+use super::BOOTLOADER_MAGIC;
+#[cfg(target_arch = "x86")]
+use super::BootInfo;
+#[cfg(target_arch = "x86")]
+use {Result, system_table};
+
+/// The EFI i386 or amd64 entry, with boot services still running
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn jump_efi(entry: u64, info: u64) -> ! {
+    asm!("mov ebx, ecx", "jmp rdx", in("eax") BOOTLOADER_MAGIC, in("rcx") info, in("rdx") entry, options(noreturn))
+}
+
+#[cfg(target_arch = "x86")]
+pub unsafe fn jump_efi(entry: u64, info: u64) -> ! {
+    asm!("mov ebx, ecx", "jmp edx", in("eax") BOOTLOADER_MAGIC, in("ecx") info as u32, in("edx") entry as u32, options(noreturn))
+}
+
+/// The i386 entry: exits boot services, adds the final memory map, turns paging off and jumps. Only returns if
+/// something went wrong
+#[cfg(target_arch = "x86")]
+pub fn boot_i386(mut info: BootInfo, entry: u64, info_addr: u64) -> Result<()> {
+    // Nothing can be allocated or printed from here on, so errors just go back to the caller
+    let map = ::services::exit_boot_services(system_table())?;
+    info.memory_map(map.iter(), true)?;
+    info.efi_memory_map(map.descriptor_size(), map.descriptor_version(), map.as_bytes())?;
+    info.finish()?;
+    unsafe {
+        asm!(
+            "cli",
+            "mov ebx, ecx",
+            "mov eax, cr0",
+            "and eax, 0x7fffffff",
+            "mov cr0, eax",
+            "mov eax, {magic}",
+            "jmp edx",
+            magic = const BOOTLOADER_MAGIC,
+            in("ecx") info_addr as u32,
+            in("edx") entry as u32,
+            options(noreturn)
+        )
+    }
+}
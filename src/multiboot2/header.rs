@@ -0,0 +1,185 @@
+// The Multiboot2 header: a magic number in the first 32KiB of the image, 8 byte aligned, followed by tags saying how
+// the kernel wants to be loaded and what it wants to be told. A tag without the optional flag has to be honoured or
+// the kernel mustn't be booted.
+
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::vec::Vec;
+
+pub const HEADER_MAGIC: u32 = 0xE852_50D6;
+
+pub const ARCHITECTURE_I386: u32 = 0;
+pub const ARCHITECTURE_MIPS32: u32 = 4;
+
+const SEARCH_LEN: usize = 32768;
+const HEADER_SIZE: usize = 16;
+
+const TAG_END: u16 = 0;
+const TAG_INFORMATION_REQUEST: u16 = 1;
+const TAG_ADDRESS: u16 = 2;
+const TAG_ENTRY_ADDRESS: u16 = 3;
+const TAG_CONSOLE_FLAGS: u16 = 4;
+const TAG_FRAMEBUFFER: u16 = 5;
+const TAG_MODULE_ALIGN: u16 = 6;
+const TAG_EFI_BOOT_SERVICES: u16 = 7;
+const TAG_ENTRY_ADDRESS_EFI32: u16 = 8;
+const TAG_ENTRY_ADDRESS_EFI64: u16 = 9;
+const TAG_RELOCATABLE: u16 = 10;
+
+const TAG_OPTIONAL: u16 = 1;
+
+/// Where an a.out kludge image goes, instead of its ELF headers saying
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Address {
+    /// Where the header itself is loaded, which says where the rest of the file goes
+    pub header_addr: u32,
+    pub load_addr: u32,
+    /// 0 for the rest of the file
+    pub load_end_addr: u32,
+    /// 0 for no bss
+    pub bss_end_addr: u32,
+}
+
+/// The video mode the kernel would like. Zeroes mean no preference
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub optional: bool,
+}
+
+/// What a relocatable kernel accepts in place of where it was linked
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Relocatable {
+    pub min_addr: u32,
+    pub max_addr: u32,
+    pub align: u32,
+    /// 0 for none, 1 for as low as possible, 2 for as high
+    pub preference: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Header {
+    /// Where in the image it is
+    pub offset: usize,
+    /// One of the ARCHITECTURE_* constants
+    pub architecture: u32,
+    /// Boot information tag types the kernel can't do without
+    pub required_info: Vec<u32>,
+    /// And those it would like
+    pub optional_info: Vec<u32>,
+    pub address: Option<Address>,
+    pub entry: Option<u32>,
+    pub efi32_entry: Option<u32>,
+    pub efi64_entry: Option<u32>,
+    pub console_flags: Option<u32>,
+    pub framebuffer: Option<Framebuffer>,
+    /// Modules have to be page aligned, which ours always are
+    pub module_align: bool,
+    /// The kernel can start with boot services still running
+    pub efi_boot_services: bool,
+    pub relocatable: Option<Relocatable>,
+}
+
+impl Header {
+    /// Finds the header in an image. Fails with LoadError if there isn't one and with Unsupported if it has a
+    /// required tag we don't know
+    pub fn find(image: &[u8]) -> Result<Self> {
+        let end = image.len().min(SEARCH_LEN);
+        let mut offset = 0;
+        while offset + HEADER_SIZE <= end {
+            let h = &image[offset..];
+            let (magic, architecture, length) = (LittleEndian::read_u32(h), LittleEndian::read_u32(&h[4..]), LittleEndian::read_u32(&h[8..]));
+            let checksum = LittleEndian::read_u32(&h[12..]);
+            if magic == HEADER_MAGIC && magic.wrapping_add(architecture).wrapping_add(length).wrapping_add(checksum) == 0 {
+                let tags = image.get(offset + HEADER_SIZE..offset + length as usize).ok_or(EfiErrorKind::LoadError)?;
+                return Self::parse_tags(offset, architecture, tags);
+            }
+            offset += 8;
+        }
+        Err(EfiErrorKind::LoadError.into())
+    }
+
+    fn parse_tags(offset: usize, architecture: u32, mut tags: &[u8]) -> Result<Self> {
+        let mut header = Header { offset, architecture, ..Header::default() };
+        while tags.len() >= 8 {
+            let (kind, flags, size) = (LittleEndian::read_u16(tags), LittleEndian::read_u16(&tags[2..]), LittleEndian::read_u32(&tags[4..]) as usize);
+            let body = tags.get(8..size).ok_or(EfiErrorKind::LoadError)?;
+            let optional = flags & TAG_OPTIONAL != 0;
+            let field = |i: usize| body.get(i * 4..i * 4 + 4).map(LittleEndian::read_u32).ok_or(EfiErrorKind::LoadError);
+            match kind {
+                TAG_END => break,
+                TAG_INFORMATION_REQUEST => {
+                    let requests = body.chunks_exact(4).map(LittleEndian::read_u32);
+                    if optional { header.optional_info.extend(requests) } else { header.required_info.extend(requests) }
+                },
+                TAG_ADDRESS => header.address = Some(Address { header_addr: field(0)?, load_addr: field(1)?, load_end_addr: field(2)?, bss_end_addr: field(3)? }),
+                TAG_ENTRY_ADDRESS => header.entry = Some(field(0)?),
+                TAG_ENTRY_ADDRESS_EFI32 => header.efi32_entry = Some(field(0)?),
+                TAG_ENTRY_ADDRESS_EFI64 => header.efi64_entry = Some(field(0)?),
+                TAG_CONSOLE_FLAGS => header.console_flags = Some(field(0)?),
+                TAG_FRAMEBUFFER => header.framebuffer = Some(Framebuffer { width: field(0)?, height: field(1)?, depth: field(2)?, optional }),
+                TAG_MODULE_ALIGN => header.module_align = true,
+                TAG_EFI_BOOT_SERVICES => header.efi_boot_services = true,
+                TAG_RELOCATABLE => header.relocatable = Some(Relocatable { min_addr: field(0)?, max_addr: field(1)?, align: field(2)?, preference: field(3)? }),
+                _ if optional => {},
+                _ => return Err(EfiErrorKind::Unsupported.into()),
+            }
+            tags = tags.get((size + 7) & !7..).unwrap_or(&[]);
+        }
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A header with the given tags, each (type, flags, fields), and the end tag
+    fn build(tags: &[(u16, u16, &[u32])]) -> Vec<u8> {
+        let mut header = vec![0u8; HEADER_SIZE];
+        for &(kind, flags, fields) in tags {
+            let start = header.len();
+            header.extend_from_slice(&kind.to_le_bytes());
+            header.extend_from_slice(&flags.to_le_bytes());
+            header.extend_from_slice(&(8 + fields.len() as u32 * 4).to_le_bytes());
+            for field in fields {
+                header.extend_from_slice(&field.to_le_bytes());
+            }
+            header.resize(start + ((header.len() - start + 7) & !7), 0);
+        }
+        header.extend_from_slice(&[0, 0, 0, 0, 8, 0, 0, 0]);
+        let length = header.len() as u32;
+        LittleEndian::write_u32(&mut header, HEADER_MAGIC);
+        LittleEndian::write_u32(&mut header[8..], length);
+        LittleEndian::write_u32(&mut header[12..], 0u32.wrapping_sub(HEADER_MAGIC).wrapping_sub(length));
+        header
+    }
+
+    #[test]
+    fn finds_header_and_tags() {
+        let mut image = vec![0u8; 24];
+        image.extend(build(&[
+            (TAG_INFORMATION_REQUEST, 0, &[6, 14]),
+            (TAG_INFORMATION_REQUEST, TAG_OPTIONAL, &[8]),
+            (TAG_ADDRESS, 0, &[0x10_0000, 0x10_0000, 0, 0x20_0000]),
+            (TAG_ENTRY_ADDRESS, 0, &[0x10_0040]),
+            (TAG_FRAMEBUFFER, TAG_OPTIONAL, &[1024, 768, 32]),
+            (TAG_EFI_BOOT_SERVICES, 0, &[]),
+            (42, TAG_OPTIONAL, &[1]),
+        ]));
+        let header = Header::find(&image).unwrap();
+        assert_eq!(header.offset, 24);
+        assert_eq!((header.required_info.as_slice(), header.optional_info.as_slice()), (&[6, 14][..], &[8][..]));
+        assert_eq!(header.address.unwrap().bss_end_addr, 0x20_0000);
+        assert_eq!(header.entry, Some(0x10_0040));
+        assert_eq!(header.framebuffer, Some(Framebuffer { width: 1024, height: 768, depth: 32, optional: true }));
+        assert!(header.efi_boot_services && !header.module_align && header.efi64_entry.is_none());
+
+        assert_eq!(Header::find(&build(&[(42, 0, &[])])).unwrap_err().kind(), EfiErrorKind::Unsupported);
+        let mut bad = build(&[]);
+        bad[12] ^= 1;
+        assert_eq!(Header::find(&bad).unwrap_err().kind(), EfiErrorKind::LoadError);
+    }
+}
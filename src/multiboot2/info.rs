@@ -0,0 +1,268 @@
+// The boot information structure the kernel is handed in EBX: a total size, then 8 byte aligned tags ending with an
+// end tag. It's written into a buffer given up front rather than a Vec since the memory map has to go in after
+// ExitBootServices, when nothing can be allocated.
+
+use ffi::boot_services::{EFI_MEMORY_DESCRIPTOR, EFI_MEMORY_TYPE};
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+
+pub const TAG_CMDLINE: u32 = 1;
+pub const TAG_BOOT_LOADER_NAME: u32 = 2;
+pub const TAG_MODULE: u32 = 3;
+pub const TAG_BASIC_MEMINFO: u32 = 4;
+pub const TAG_MMAP: u32 = 6;
+pub const TAG_FRAMEBUFFER: u32 = 8;
+pub const TAG_EFI32: u32 = 11;
+pub const TAG_EFI64: u32 = 12;
+pub const TAG_ACPI_OLD: u32 = 14;
+pub const TAG_ACPI_NEW: u32 = 15;
+pub const TAG_EFI_MMAP: u32 = 17;
+pub const TAG_EFI_BS: u32 = 18;
+pub const TAG_EFI32_IH: u32 = 19;
+pub const TAG_EFI64_IH: u32 = 20;
+pub const TAG_LOAD_BASE_ADDR: u32 = 21;
+
+// Memory map entry types
+pub const MEMORY_AVAILABLE: u32 = 1;
+pub const MEMORY_RESERVED: u32 = 2;
+pub const MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+pub const MEMORY_NVS: u32 = 4;
+pub const MEMORY_BADRAM: u32 = 5;
+
+/// Bytes each entry of our own memory map takes
+pub const MMAP_ENTRY_SIZE: usize = 24;
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+/// A direct colour frame buffer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub address: u64,
+    /// Bytes per line
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+    /// Position and size in bits of red, green and blue
+    pub red: (u8, u8),
+    pub green: (u8, u8),
+    pub blue: (u8, u8),
+}
+
+/// Boot information being written into a buffer
+pub struct BootInfo<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> BootInfo<'a> {
+    /// `buf` has to be 8 byte aligned where the kernel will see it
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        BootInfo { buf, len: 8 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 8
+    }
+
+    pub fn cmdline(&mut self, cmdline: &str) -> Result<&mut Self> {
+        self.string_tag(TAG_CMDLINE, &[], cmdline)
+    }
+
+    pub fn boot_loader_name(&mut self, name: &str) -> Result<&mut Self> {
+        self.string_tag(TAG_BOOT_LOADER_NAME, &[], name)
+    }
+
+    /// A module at [start, end) with its command line
+    pub fn module(&mut self, start: u32, end: u32, cmdline: &str) -> Result<&mut Self> {
+        let mut range = [0u8; 8];
+        LittleEndian::write_u32(&mut range, start);
+        LittleEndian::write_u32(&mut range[4..], end);
+        self.string_tag(TAG_MODULE, &range, cmdline)
+    }
+
+    /// Multiboot's own memory map and the basic lower and upper memory sizes worked out from it. Only conventional
+    /// memory is available unless `boot_services_exited`, when boot services and loader memory is too
+    pub fn memory_map<'m, I: Iterator<Item = &'m EFI_MEMORY_DESCRIPTOR> + Clone>(&mut self, map: I, boot_services_exited: bool) -> Result<&mut Self> {
+        // Lower memory runs up from 0 to at most 640KiB and upper memory up from 1MiB, each as far as available
+        // memory is contiguous. Sorting would need somewhere to put the map, so go over it until neither grows
+        let (mut lower, mut upper_end) = (0u64, 0x10_0000u64);
+        let mut grew = true;
+        while grew {
+            grew = false;
+            for d in map.clone().filter(|d| memory_type(d.Type, boot_services_exited) == MEMORY_AVAILABLE) {
+                let (start, end) = (d.PhysicalStart, d.PhysicalStart + d.NumberOfPages * 4096);
+                if start <= lower && end > lower && lower < 0xa_0000 {
+                    lower = end.min(0xa_0000);
+                    grew = true;
+                }
+                if start <= upper_end && end > upper_end {
+                    upper_end = end;
+                    grew = true;
+                }
+            }
+        }
+        let upper = upper_end - 0x10_0000;
+        let mut meminfo = [0u8; 8];
+        LittleEndian::write_u32(&mut meminfo, (lower / 1024) as u32);
+        LittleEndian::write_u32(&mut meminfo[4..], (upper / 1024).min(u32::MAX as u64) as u32);
+        self.tag(TAG_BASIC_MEMINFO, &[&meminfo])?;
+
+        let count = map.clone().count();
+        let body = self.open_tag(TAG_MMAP, 8 + count * MMAP_ENTRY_SIZE)?;
+        LittleEndian::write_u32(body, MMAP_ENTRY_SIZE as u32);
+        LittleEndian::write_u32(&mut body[4..], 0); // Entry version
+        for (entry, d) in body[8..].chunks_exact_mut(MMAP_ENTRY_SIZE).zip(map) {
+            LittleEndian::write_u64(entry, d.PhysicalStart);
+            LittleEndian::write_u64(&mut entry[8..], d.NumberOfPages * 4096);
+            LittleEndian::write_u32(&mut entry[16..], memory_type(d.Type, boot_services_exited));
+            LittleEndian::write_u32(&mut entry[20..], 0);
+        }
+        Ok(self)
+    }
+
+    /// The firmware's memory map as it is, descriptors `descriptor_size` apart
+    pub fn efi_memory_map(&mut self, descriptor_size: usize, descriptor_version: u32, map: &[u8]) -> Result<&mut Self> {
+        let mut header = [0u8; 8];
+        LittleEndian::write_u32(&mut header, descriptor_size as u32);
+        LittleEndian::write_u32(&mut header[4..], descriptor_version);
+        self.tag(TAG_EFI_MMAP, &[&header, map])
+    }
+
+    pub fn framebuffer(&mut self, fb: &Framebuffer) -> Result<&mut Self> {
+        let mut common = [0u8; 24];
+        LittleEndian::write_u64(&mut common, fb.address);
+        LittleEndian::write_u32(&mut common[8..], fb.pitch);
+        LittleEndian::write_u32(&mut common[12..], fb.width);
+        LittleEndian::write_u32(&mut common[16..], fb.height);
+        common[20] = fb.bpp;
+        common[21] = FRAMEBUFFER_TYPE_RGB;
+        let colours = [fb.red.0, fb.red.1, fb.green.0, fb.green.1, fb.blue.0, fb.blue.1];
+        self.tag(TAG_FRAMEBUFFER, &[&common, &colours])
+    }
+
+    /// The system table's address, as tag 12 or tag 11 for 32 bit firmware
+    pub fn efi_system_table(&mut self, address: u64, is_64bit: bool) -> Result<&mut Self> {
+        self.address_tag(if is_64bit { TAG_EFI64 } else { TAG_EFI32 }, address, is_64bit)
+    }
+
+    pub fn efi_image_handle(&mut self, address: u64, is_64bit: bool) -> Result<&mut Self> {
+        self.address_tag(if is_64bit { TAG_EFI64_IH } else { TAG_EFI32_IH }, address, is_64bit)
+    }
+
+    /// A copy of the RSDP, as tag 14 for an ACPI 1.0 one and 15 otherwise
+    pub fn rsdp(&mut self, rsdp: &[u8]) -> Result<&mut Self> {
+        self.tag(if rsdp.len() > 20 { TAG_ACPI_NEW } else { TAG_ACPI_OLD }, &[rsdp])
+    }
+
+    /// Says boot services are still running
+    pub fn boot_services_not_terminated(&mut self) -> Result<&mut Self> {
+        self.tag(TAG_EFI_BS, &[])
+    }
+
+    /// Where a relocated image was put
+    pub fn load_base_addr(&mut self, address: u32) -> Result<&mut Self> {
+        self.tag(TAG_LOAD_BASE_ADDR, &[&address.to_le_bytes()])
+    }
+
+    /// Adds the end tag and fills in the total size. Returns the structure's bytes
+    pub fn finish(mut self) -> Result<&'a [u8]> {
+        self.tag(0, &[])?;
+        let (buf, len) = (self.buf, self.len);
+        LittleEndian::write_u32(buf, len as u32);
+        LittleEndian::write_u32(&mut buf[4..], 0);
+        Ok(&buf[..len])
+    }
+
+    fn address_tag(&mut self, kind: u32, address: u64, is_64bit: bool) -> Result<&mut Self> {
+        let bytes = address.to_le_bytes();
+        self.tag(kind, &[if is_64bit { &bytes[..] } else { &bytes[..4] }])
+    }
+
+    fn string_tag(&mut self, kind: u32, prefix: &[u8], s: &str) -> Result<&mut Self> {
+        self.tag(kind, &[prefix, s.as_bytes(), &[0]])
+    }
+
+    fn tag(&mut self, kind: u32, parts: &[&[u8]]) -> Result<&mut Self> {
+        let size = parts.iter().map(|p| p.len()).sum();
+        let mut body = self.open_tag(kind, size)?;
+        for part in parts {
+            let (dest, rest) = body.split_at_mut(part.len());
+            dest.copy_from_slice(part);
+            body = rest;
+        }
+        Ok(self)
+    }
+
+    // Writes a tag header and reserves `size` bytes after it, returning them
+    fn open_tag(&mut self, kind: u32, size: usize) -> Result<&mut [u8]> {
+        let start = self.len;
+        let end = start + 8 + size;
+        if (end + 7) & !7 > self.buf.len() {
+            return Err(EfiErrorKind::BufferTooSmall.into());
+        }
+        LittleEndian::write_u32(&mut self.buf[start..], kind);
+        LittleEndian::write_u32(&mut self.buf[start + 4..], (8 + size) as u32);
+        for b in &mut self.buf[end..(end + 7) & !7] {
+            *b = 0;
+        }
+        self.len = (end + 7) & !7;
+        Ok(&mut self.buf[start + 8..end])
+    }
+}
+
+fn memory_type(efi_type: u32, boot_services_exited: bool) -> u32 {
+    use self::EFI_MEMORY_TYPE::*;
+    match efi_type {
+        t if t == EfiConventionalMemory as u32 => MEMORY_AVAILABLE,
+        t if boot_services_exited && [EfiBootServicesCode as u32, EfiBootServicesData as u32, EfiLoaderCode as u32, EfiLoaderData as u32].contains(&t) => MEMORY_AVAILABLE,
+        t if t == EfiACPIReclaimMemory as u32 => MEMORY_ACPI_RECLAIMABLE,
+        t if t == EfiACPIMemoryNVS as u32 => MEMORY_NVS,
+        t if t == EfiUnusableMemory as u32 => MEMORY_BADRAM,
+        _ => MEMORY_RESERVED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn descriptor(efi_type: EFI_MEMORY_TYPE, start: u64, pages: u64) -> EFI_MEMORY_DESCRIPTOR {
+        EFI_MEMORY_DESCRIPTOR { Type: efi_type as u32, PhysicalStart: start, VirtualStart: 0, NumberOfPages: pages, Attribute: 0 }
+    }
+
+    #[test]
+    fn writes_aligned_tags_and_meminfo() {
+        use self::EFI_MEMORY_TYPE::*;
+        // Upper memory only counts boot services memory once they've exited and stops at the ACPI tables
+        let map = [
+            descriptor(EfiConventionalMemory, 0x20_0000, 0x100),
+            descriptor(EfiConventionalMemory, 0, 0xa0),
+            descriptor(EfiBootServicesData, 0x10_0000, 0x100),
+            descriptor(EfiACPIReclaimMemory, 0x30_0000, 1),
+            descriptor(EfiConventionalMemory, 0x30_1000, 0x10),
+        ];
+        let mut buf = [0u8; 256];
+        let mut info = BootInfo::new(&mut buf);
+        info.cmdline("quiet").unwrap().memory_map(map.iter(), true).unwrap();
+        assert!(!info.is_empty());
+        let bytes = info.finish().unwrap().to_vec();
+
+        assert_eq!(LittleEndian::read_u32(&bytes), bytes.len() as u32);
+        assert_eq!(&bytes[8..16], &[1, 0, 0, 0, 14, 0, 0, 0]);
+        assert_eq!(&bytes[16..24], b"quiet\0\0\0");
+        assert_eq!(&bytes[24..40], &[4, 0, 0, 0, 16, 0, 0, 0, 0x80, 2, 0, 0, 0, 8, 0, 0]);
+        assert_eq!(LittleEndian::read_u32(&bytes[44..]), 16 + 5 * 24);
+        let types: Vec<u32> = bytes[56..56 + 5 * 24].chunks(24).map(|e| LittleEndian::read_u32(&e[16..])).collect();
+        assert_eq!(types, [MEMORY_AVAILABLE, MEMORY_AVAILABLE, MEMORY_AVAILABLE, MEMORY_ACPI_RECLAIMABLE, MEMORY_AVAILABLE]);
+        assert_eq!(&bytes[bytes.len() - 8..], &[0, 0, 0, 0, 8, 0, 0, 0]);
+
+        assert_eq!(memory_type(EfiBootServicesData as u32, false), MEMORY_RESERVED);
+        let mut small = [0u8; 16];
+        assert_eq!(BootInfo::new(&mut small).cmdline("quiet").err().unwrap().kind(), EfiErrorKind::BufferTooSmall);
+    }
+}
@@ -0,0 +1,345 @@
+// Booting Multiboot2 kernels (Xen, hypervisors, hobby kernels and the like).
+//
+// The image is loaded where its address tag or else its ELF program headers say, or somewhere else if it's
+// relocatable, modules are put below 4GiB, and the boot information is built from the firmware's memory map, the GOP
+// frame buffer and the RSDP. How control gets to the kernel depends on what it supports:
+// - EFI entry: the kernel has an EFI i386 or amd64 entry address and the tag saying it can start with boot services
+//   still running. We jump there with EAX = the magic and EBX = the boot information, in whichever mode the firmware
+//   is in. This is the only way on x86_64, where we're in long mode and the i386 entry wants protected mode.
+// - i386 entry: only on 32 bit firmware. We exit boot services, turn paging off (the firmware identity maps, so
+//   nothing moves) and jump to the normal entry address.
+// Anything else, including MIPS kernels, fails with Unsupported.
+
+pub mod header;
+pub mod info;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+mod entry;
+
+pub use self::header::Header;
+pub use self::info::BootInfo;
+
+use self::header::ARCHITECTURE_I386;
+use self::info::*;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+use self::entry::jump_efi;
+#[cfg(target_arch = "x86")]
+use self::entry::boot_i386;
+use {Result, EfiErrorKind, system_table, image_handle};
+use pages::{Pages, PAGE_SIZE, MAX_ADDRESS_32BIT};
+use memory::MemoryMap;
+use graphics::{GraphicsOutput, PixelFormat};
+use acpi;
+use security::measure::{self, Component};
+use elf::{self, Elf, Segment};
+use alloc::{vec::Vec, string::String};
+
+/// What the kernel finds in EAX
+pub const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+const BOOT_LOADER_NAME: &str = "efi";
+
+// Tags we can always give, should the kernel insist
+const PROVIDED: [u32; 12] = [
+    TAG_CMDLINE, TAG_BOOT_LOADER_NAME, TAG_MODULE, TAG_BASIC_MEMINFO, TAG_MMAP, TAG_EFI32, TAG_EFI64, TAG_EFI_MMAP,
+    TAG_EFI_BS, TAG_EFI32_IH, TAG_EFI64_IH, TAG_LOAD_BASE_ADDR,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Entry {
+    Efi(u64),
+    I386(u64),
+}
+
+struct Module {
+    data: Vec<u8>,
+    cmdline: String,
+}
+
+/// A Multiboot2 kernel ready to be booted along with its modules and command line
+pub struct Multiboot2 {
+    image: Vec<u8>,
+    header: Header,
+    cmdline: String,
+    modules: Vec<Module>,
+}
+
+impl Multiboot2 {
+    /// Fails with LoadError if the image has no Multiboot2 header and with Unsupported if the header asks for
+    /// something we can't do
+    pub fn new(image: Vec<u8>) -> Result<Self> {
+        let header = Header::find(&image)?;
+        if header.architecture != ARCHITECTURE_I386 {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        Ok(Self { image, header, cmdline: String::new(), modules: Vec::new() })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn cmdline<S: Into<String>>(&mut self, cmdline: S) -> &mut Self {
+        self.cmdline = cmdline.into();
+        self
+    }
+
+    /// Adds a module. The kernel sees them in the order they were added, each with its own command line
+    pub fn module<S: Into<String>>(&mut self, data: Vec<u8>, cmdline: S) -> &mut Self {
+        self.modules.push(Module { data, cmdline: cmdline.into() });
+        self
+    }
+
+    /// Boots the kernel, measuring it, its modules and the command lines first if there's a TPM. Only returns if
+    /// something went wrong
+    pub fn boot(&self) -> Result<()> {
+        let (default_entry, segments) = layout(&self.image, &self.header)?;
+        let entry = self.entry(default_entry)?;
+        let framebuffer = self.framebuffer();
+        let rsdp = acpi::rsdp().ok().flatten();
+        let wants = |tag: u32| self.header.required_info.contains(&tag);
+        let wants_console = self.header.console_flags.map_or(false, |flags| flags & 1 != 0);
+        let wants_framebuffer = wants(TAG_FRAMEBUFFER) || wants_console || self.header.framebuffer.map_or(false, |fb| !fb.optional);
+        if self.header.required_info.iter().any(|tag| !PROVIDED.contains(tag) && *tag != TAG_FRAMEBUFFER && *tag != TAG_ACPI_OLD && *tag != TAG_ACPI_NEW)
+            || (wants_framebuffer && framebuffer.is_none())
+            || ((wants(TAG_ACPI_OLD) || wants(TAG_ACPI_NEW)) && rsdp.is_none())
+        {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+
+        self.measure()?;
+
+        let (kernel_pages, delta) = self.load(&segments)?;
+        let entry = match entry {
+            Entry::Efi(address) => Entry::Efi(address.wrapping_add(delta as u64)),
+            Entry::I386(address) => Entry::I386(address.wrapping_add(delta as u64)),
+        };
+
+        let mut module_pages = Vec::new();
+        for module in &self.modules {
+            let mut pages = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(module.data.len().max(1)))?;
+            pages.as_mut_slice()[..module.data.len()].copy_from_slice(&module.data);
+            module_pages.push(pages);
+        }
+
+        // The memory maps go in last, so leave room for them to have grown by then
+        let map = MemoryMap::get()?;
+        let strings = self.cmdline.len() + self.modules.iter().map(|m| m.cmdline.len()).sum::<usize>();
+        let info_size = 2 * PAGE_SIZE + strings + 24 * self.modules.len() + (map.len() + 32) * (MMAP_ENTRY_SIZE + map.descriptor_size());
+        // Leaked straight away: the i386 entry finishes it after ExitBootServices, when pages can't be freed
+        let info_buf = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(info_size))?.leak();
+        let info_addr = info_buf.as_ptr() as u64;
+
+        let is_64bit = cfg!(target_pointer_width = "64");
+        let mut info = BootInfo::new(info_buf);
+        info.cmdline(&self.cmdline)?.boot_loader_name(BOOT_LOADER_NAME)?;
+        for (module, pages) in self.modules.iter().zip(&module_pages) {
+            info.module(pages.addr() as u32, (pages.addr() + module.data.len() as u64) as u32, &module.cmdline)?;
+        }
+        if let Some(ref fb) = framebuffer {
+            info.framebuffer(fb)?;
+        }
+        info.efi_system_table(system_table() as *const _ as u64, is_64bit)?;
+        info.efi_image_handle(image_handle() as u64, is_64bit)?;
+        if let Some(rsdp) = rsdp {
+            // Kernels that only look for the old tag still get the 1.0 part
+            if rsdp.len() > 20 {
+                info.rsdp(&rsdp[..20])?;
+            }
+            info.rsdp(rsdp)?;
+        }
+        if delta != 0 {
+            let base = segments.iter().map(|s| s.address).min().unwrap_or(0).wrapping_add(delta as u64);
+            info.load_base_addr(base as u32)?;
+        }
+
+        // The kernel owns all of these from here on
+        kernel_pages.leak();
+        for pages in module_pages {
+            pages.leak();
+        }
+
+        match entry {
+            Entry::Efi(address) => {
+                let map = MemoryMap::get()?;
+                info.memory_map(map.iter(), false)?;
+                info.efi_memory_map(map.descriptor_size(), map.descriptor_version(), map.as_bytes())?;
+                info.boot_services_not_terminated()?;
+                info.finish()?;
+                unsafe { jump_efi(address, info_addr) }
+            },
+            Entry::I386(address) => boot_i386(info, address, info_addr),
+        }
+    }
+
+    // The kernel, each module with its command line, and the kernel's command line
+    fn measure(&self) -> Result<()> {
+        measure::measure(Component::Kernel, &self.image, "kernel")?;
+        for module in &self.modules {
+            measure::measure(Component::Initrd, &module.data, "module")?;
+            if !module.cmdline.is_empty() {
+                measure::measure(Component::Cmdline, module.cmdline.as_bytes(), &module.cmdline)?;
+            }
+        }
+        measure::measure(Component::Cmdline, self.cmdline.as_bytes(), &self.cmdline)
+    }
+
+    // The entry we'll use: the EFI one for our architecture if the kernel has it and can start with boot services
+    // running, or else the i386 one where we can get to protected mode with paging off
+    fn entry(&self, default: Option<u64>) -> Result<Entry> {
+        let efi_entry = if cfg!(target_arch = "x86_64") {
+            self.header.efi64_entry
+        } else if cfg!(target_arch = "x86") {
+            self.header.efi32_entry
+        } else {
+            None
+        };
+        match efi_entry {
+            Some(address) if self.header.efi_boot_services => Ok(Entry::Efi(address as u64)),
+            _ if cfg!(target_arch = "x86") => {
+                let address = self.header.entry.map(|address| address as u64).or(default);
+                address.map(Entry::I386).ok_or_else(|| EfiErrorKind::LoadError.into())
+            },
+            _ => Err(EfiErrorKind::Unsupported.into()),
+        }
+    }
+
+    // Allocates where the image was linked to run, or else where the relocatable tag allows, and copies it there.
+    // Returns the pages and how far the image was moved
+    fn load(&self, segments: &[Segment]) -> Result<(Pages, i64)> {
+        let (start, end) = elf::extent(segments)?;
+        let count = Pages::count_for((end - start) as usize);
+
+        let (mut pages, base) = match (Pages::allocate_at(start, count), self.header.relocatable) {
+            (Ok(pages), _) => {
+                let addr = pages.addr();
+                (pages, addr)
+            },
+            (Err(_), Some(reloc)) => {
+                let align = (reloc.align as u64).max(PAGE_SIZE as u64);
+                let extra = Pages::count_for(align as usize) - 1;
+                let pages = Pages::allocate_below(reloc.max_addr as u64, count + extra)?;
+                let base = (pages.addr() + align - 1) & !(align - 1);
+                if base < reloc.min_addr as u64 || base + (end - start) > reloc.max_addr as u64 + 1 {
+                    return Err(EfiErrorKind::OutOfResources.into());
+                }
+                (pages, base)
+            },
+            (Err(e), None) => return Err(e),
+        };
+
+        let skip = (base - pages.addr()) as usize;
+        elf::copy_segments(&self.image, segments, start, &mut pages.as_mut_slice()[skip..])?;
+        Ok((pages, base as i64 - start as i64))
+    }
+
+    // The frame buffer of the first GOP that has one, switched to the kernel's preferred resolution if there's a mode
+    // for it
+    fn framebuffer(&self) -> Option<Framebuffer> {
+        let wanted = self.header.framebuffer.is_some() || self.header.required_info.contains(&TAG_FRAMEBUFFER)
+            || self.header.optional_info.contains(&TAG_FRAMEBUFFER);
+        if !wanted {
+            return None;
+        }
+        for gop in GraphicsOutput::all().unwrap_or_default() {
+            if let Some(preferred) = self.header.framebuffer {
+                let modes = gop.modes().unwrap_or_default();
+                let mode = modes.iter().find(|m| {
+                    m.pixel_format != PixelFormat::BltOnly
+                        && (preferred.width == 0 || m.resolution.width == preferred.width)
+                        && (preferred.height == 0 || m.resolution.height == preferred.height)
+                });
+                if let Some(mode) = mode {
+                    if mode.number != gop.current_mode().number {
+                        let _ = gop.set_mode(mode.number);
+                    }
+                }
+            }
+            let mode = gop.current_mode();
+            let (red, green, blue) = match mode.pixel_format {
+                PixelFormat::Rgb => ((0, 8), (8, 8), (16, 8)),
+                PixelFormat::Bgr => ((16, 8), (8, 8), (0, 8)),
+                PixelFormat::BitMask { red, green, blue } => (mask_field(red), mask_field(green), mask_field(blue)),
+                PixelFormat::BltOnly => continue,
+            };
+            if let Some((address, _)) = gop.frame_buffer() {
+                return Some(Framebuffer {
+                    address,
+                    pitch: mode.stride * 4,
+                    width: mode.resolution.width,
+                    height: mode.resolution.height,
+                    bpp: 32,
+                    red,
+                    green,
+                    blue,
+                });
+            }
+        }
+        None
+    }
+}
+
+// A colour's bit position and size from its mask
+fn mask_field(mask: u32) -> (u8, u8) {
+    if mask == 0 {
+        return (0, 0);
+    }
+    (mask.trailing_zeros() as u8, mask.count_ones() as u8)
+}
+
+// Where the image's pieces go and the entry address it has of its own, if any. The address tag wins over ELF headers
+fn layout(image: &[u8], header: &Header) -> Result<(Option<u64>, Vec<Segment>)> {
+    let address = match header.address {
+        Some(address) => address,
+        None => {
+            let elf = Elf::parse(image)?;
+            return Ok((Some(elf.entry()), elf.segments()?));
+        },
+    };
+    // The header is at header_addr once loaded, which places the rest of the file
+    let before = address.header_addr.checked_sub(address.load_addr).ok_or(EfiErrorKind::LoadError)? as usize;
+    let offset = header.offset.checked_sub(before).ok_or(EfiErrorKind::LoadError)?;
+    let file_size = if address.load_end_addr == 0 {
+        image.len() - offset
+    } else {
+        address.load_end_addr.checked_sub(address.load_addr).ok_or(EfiErrorKind::LoadError)? as usize
+    };
+    if offset + file_size > image.len() {
+        return Err(EfiErrorKind::LoadError.into());
+    }
+    let mem_size = match address.bss_end_addr {
+        0 => file_size,
+        bss_end => file_size.max(bss_end.saturating_sub(address.load_addr) as usize),
+    };
+    Ok((None, vec![Segment { address: address.load_addr as u64, offset, file_size, mem_size }]))
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+unsafe fn jump_efi(_entry: u64, _info: u64) -> ! {
+    unreachable!() // Multiboot2::entry() only picks EFI entries on x86
+}
+
+#[cfg(not(target_arch = "x86"))]
+fn boot_i386(_info: BootInfo, _entry: u64, _info_addr: u64) -> Result<()> {
+    Err(EfiErrorKind::Unsupported.into()) // Only 32 bit firmware runs in protected mode
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::header::Address;
+
+    #[test]
+    fn lays_out_address_tag_images() {
+        // An a.out kludge image whose header is 0x40 bytes into the file, loaded from the start of it
+        let header = Header {
+            offset: 0x40,
+            address: Some(Address { header_addr: 0x20_0040, load_addr: 0x20_0000, load_end_addr: 0, bss_end_addr: 0x20_4000 }),
+            ..Header::default()
+        };
+        let (entry, segments) = layout(&[0u8; 0x1000], &header).unwrap();
+        assert_eq!(entry, None);
+        assert_eq!(segments, vec![Segment { address: 0x20_0000, offset: 0, file_size: 0x1000, mem_size: 0x4000 }]);
+
+        assert_eq!((mask_field(0x00ff_0000), mask_field(0x7e0), mask_field(0)), ((16, 8), (5, 6), (0, 0)));
+    }
+}
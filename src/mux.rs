@@ -0,0 +1,304 @@
+// Fanning the console out to more than one place. Everything written to the console also goes to each sink added
+// here, and keys typed at any sink that takes input come back through the console alongside the local keyboard's, so
+// an operator at the screen and one on a serial line or TCP connection see the same thing and can both answer:
+//
+//     mux::add("serial", SerialConsole::new(SerialPort::first()?)?);
+//     mux::add("log", WriteSink(log_file));
+//     telnet::attach(RemoteConsole::accept(telnet::TELNET_PORT, Mode::Telnet, None)?);
+//     mux::set_enabled(mux::LOCAL, false)?; // Everything but the screen from here on
+//
+// Sinks are known by name, and adding one under a name already taken replaces it. Each can be turned off and on
+// again without losing it, including the local console, which is called LOCAL. The local console is used regardless
+// while no other sink is on, so the machine can't be left with no console at all. A sink that fails is taken out,
+// since that's usually a connection gone. Terminals (the remote console and SerialConsole) follow the local console's
+// clearing and cursor movement too, but not its colours.
+
+use ffi::console::EFI_INPUT_KEY;
+use io::{self, Read, Write};
+use net::telnet::{self, Decoder, Mode};
+use serial::SerialPort;
+use utils::TryLock;
+use {Result, EfiErrorKind};
+use alloc::{boxed::Box, collections::VecDeque, string::{String, ToString}, vec::Vec};
+use core::{mem, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+/// The name of the local console
+pub const LOCAL: &str = "local";
+
+/// Somewhere console output goes, and possibly keys come from
+pub trait Sink {
+    /// Text written to the console, with lines ended by CR LF
+    fn write(&mut self, text: &str) -> io::Result<()>;
+
+    /// A key typed at this end if there is one. It shouldn't wait more than a few milliseconds, since the other
+    /// sinks and the local keyboard aren't being looked at meanwhile
+    fn poll_key(&mut self) -> io::Result<Option<EFI_INPUT_KEY>> {
+        Ok(None)
+    }
+
+    /// For terminals, which can follow the local console's cursor. Rows and columns count from 0
+    fn set_cursor_pos(&mut self, _row: u32, _col: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn clear_screen(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An output only sink for anything that can be written to, e.g. a log file or an NvramLog
+pub struct WriteSink<W>(pub W);
+
+impl<W: Write> Sink for WriteSink<W> {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_all(text.as_bytes())
+    }
+}
+
+/// A terminal on a serial port. Keys come in as VT100/xterm sequences, like the remote console's in raw mode. Not for
+/// a port the firmware already has its console on, which would get everything twice
+pub struct SerialConsole {
+    port: SerialPort,
+    decoder: Decoder,
+    keys: VecDeque<EFI_INPUT_KEY>,
+}
+
+impl SerialConsole {
+    // Reads wait this long, in microseconds
+    const POLL_TIMEOUT: u32 = 1000;
+
+    pub fn new(mut port: SerialPort) -> Result<Self> {
+        port.set_timeout(Self::POLL_TIMEOUT)?;
+        Ok(SerialConsole { port, decoder: Decoder::new(Mode::Raw), keys: VecDeque::new() })
+    }
+
+    pub fn port(&self) -> &SerialPort {
+        &self.port
+    }
+}
+
+impl Sink for SerialConsole {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.port.write_all(&telnet::encode_output(text, Mode::Raw))
+    }
+
+    fn set_cursor_pos(&mut self, row: u32, col: u32) -> io::Result<()> {
+        write!(self.port, "\x1b[{};{}H", row + 1, col + 1)
+    }
+
+    fn clear_screen(&mut self) -> io::Result<()> {
+        self.port.write_all(b"\x1b[2J\x1b[H")
+    }
+
+    fn poll_key(&mut self) -> io::Result<Option<EFI_INPUT_KEY>> {
+        if self.keys.is_empty() {
+            let mut buf = [0u8; 64];
+            let len = self.port.read(&mut buf)?; // 0 if nothing came
+            self.decoder.feed(&buf[..len], &mut self.keys);
+        }
+        Ok(self.keys.pop_front())
+    }
+}
+
+struct Entry {
+    name: String,
+    sink: Box<dyn Sink>,
+    enabled: bool,
+}
+
+static SINKS: TryLock<Vec<Entry>> = TryLock::new(Vec::new());
+static LOCAL_DISABLED: AtomicBool = AtomicBool::new(false);
+// Where poll_key() starts looking, so that a sink with a lot to say doesn't starve the rest
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Adds a sink, turned on, in place of any other called `name`
+pub fn add<S: Sink + 'static>(name: &str, sink: S) {
+    let entry = Entry { name: name.to_string(), sink: Box::new(sink), enabled: true };
+    let _old = SINKS.try_with(|sinks| match sinks.iter().position(|e| e.name == name) {
+        Some(i) => Some(mem::replace(&mut sinks[i], entry)),
+        None => {
+            sinks.push(entry);
+            None
+        },
+    });
+}
+
+/// Takes the sink called `name` out, returning it
+pub fn remove(name: &str) -> Option<Box<dyn Sink>> {
+    SINKS.try_with(|sinks| sinks.iter().position(|e| e.name == name).map(|i| sinks.remove(i).sink)).and_then(|s| s)
+}
+
+pub fn contains(name: &str) -> bool {
+    name == LOCAL || names().iter().any(|n| n == name)
+}
+
+/// Every sink's name, starting with the local console's
+pub fn names() -> Vec<String> {
+    let mut names = vec![LOCAL.to_string()];
+    SINKS.try_with(|sinks| names.extend(sinks.iter().map(|e| e.name.clone())));
+    names
+}
+
+/// Turns a sink off or back on. NotFound if there isn't one called `name`
+pub fn set_enabled(name: &str, enabled: bool) -> Result<()> {
+    if name == LOCAL {
+        LOCAL_DISABLED.store(!enabled, Ordering::Relaxed);
+        return Ok(());
+    }
+    let found = SINKS.try_with(|sinks| match sinks.iter_mut().find(|e| e.name == name) {
+        Some(entry) => {
+            entry.enabled = enabled;
+            true
+        },
+        None => false,
+    });
+    if found == Some(true) { Ok(()) } else { Err(EfiErrorKind::NotFound.into()) }
+}
+
+pub fn is_enabled(name: &str) -> bool {
+    if name == LOCAL {
+        return local_enabled();
+    }
+    SINKS.try_with(|sinks| sinks.iter().any(|e| e.name == name && e.enabled)) == Some(true)
+}
+
+// Whether the console should use the screen and the local keyboard
+pub(crate) fn local_enabled() -> bool {
+    !LOCAL_DISABLED.load(Ordering::Relaxed) || !any_enabled()
+}
+
+// Whether the console needs to poll for keys rather than wait for the local keyboard
+pub(crate) fn any_enabled() -> bool {
+    SINKS.try_with(|sinks| sinks.iter().any(|e| e.enabled)) == Some(true)
+}
+
+// Used by the console on everything it writes. Anything written from inside a sink isn't, since the sinks are locked
+pub(crate) fn write(text: &str) {
+    each(|sink| sink.write(text));
+}
+
+pub(crate) fn set_cursor_pos(row: u32, col: u32) {
+    each(|sink| sink.set_cursor_pos(row, col));
+}
+
+pub(crate) fn clear_screen() {
+    each(|sink| sink.clear_screen());
+}
+
+// Does `f` to each sink that's on, taking out those it fails for
+fn each<F: FnMut(&mut dyn Sink) -> io::Result<()>>(mut f: F) {
+    let failed = SINKS.try_with(|sinks| {
+        let mut failed = Vec::new();
+        let mut i = 0;
+        while i < sinks.len() {
+            if sinks[i].enabled && f(&mut *sinks[i].sink).is_err() {
+                failed.push(sinks.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        failed
+    });
+    drop(failed); // After unlocking, in case closing one writes to the console
+}
+
+// Used by the console while waiting for a key. Each sink that's on is asked once, starting after the one that last
+// had a key
+pub(crate) fn poll_key() -> Option<EFI_INPUT_KEY> {
+    let mut failed = Vec::new();
+    let key = SINKS.try_with(|sinks| {
+        let mut key = None;
+        let mut failed_at = Vec::new();
+        let start = NEXT.load(Ordering::Relaxed);
+        for n in 0..sinks.len() {
+            let i = (start + n) % sinks.len();
+            if !sinks[i].enabled {
+                continue;
+            }
+            match sinks[i].sink.poll_key() {
+                Ok(Some(k)) => {
+                    NEXT.store(i + 1, Ordering::Relaxed);
+                    key = Some(k);
+                    break;
+                },
+                Ok(None) => (),
+                Err(_) => failed_at.push(i),
+            }
+        }
+        failed_at.sort_unstable();
+        for &i in failed_at.iter().rev() {
+            failed.push(sinks.remove(i));
+        }
+        key
+    });
+    drop(failed);
+    key.and_then(|k| k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::console::SCAN_NULL;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    struct Fake {
+        written: Rc<RefCell<String>>,
+        keys: Vec<u16>,
+        fail: bool,
+    }
+
+    impl Sink for Fake {
+        fn write(&mut self, text: &str) -> io::Result<()> {
+            if self.fail {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.written.borrow_mut().push_str(text);
+            Ok(())
+        }
+
+        fn poll_key(&mut self) -> io::Result<Option<EFI_INPUT_KEY>> {
+            Ok(self.keys.pop().map(|c| EFI_INPUT_KEY { ScanCode: SCAN_NULL, UnicodeChar: c }))
+        }
+    }
+
+    fn fake(keys: &str) -> (Fake, Rc<RefCell<String>>) {
+        let written = Rc::new(RefCell::new(String::new()));
+        (Fake { written: written.clone(), keys: keys.chars().rev().map(|c| c as u16).collect(), fail: false }, written)
+    }
+
+    #[test]
+    fn fans_out_and_merges_input() {
+        let (serial, serial_out) = fake("ab");
+        let (log, log_out) = fake("");
+        add("serial", serial);
+        add("log", log);
+        assert_eq!(names(), ["local", "serial", "log"]);
+        assert!(local_enabled());
+
+        write("one\r\n");
+        set_enabled("log", false).unwrap();
+        write("two\r\n");
+        assert_eq!(*serial_out.borrow(), "one\r\ntwo\r\n");
+        assert_eq!(*log_out.borrow(), "one\r\n");
+        assert!(set_enabled("missing", true).is_err());
+
+        // Input from whichever has some, taking turns
+        let (remote, _) = fake("x");
+        add("remote", remote);
+        let keys = (0..4).map(|_| poll_key().map(|k| k.UnicodeChar as u8 as char)).collect::<Vec<_>>();
+        assert_eq!(keys, [Some('a'), Some('x'), Some('b'), None]);
+
+        // The local console comes back if there's nothing else
+        set_enabled(LOCAL, false).unwrap();
+        assert!(!local_enabled());
+        let (broken, _) = fake("");
+        add("remote", Fake { fail: true, ..broken });
+        write("three\r\n");
+        assert!(!contains("remote"));
+        remove("serial");
+        assert!(local_enabled());
+        set_enabled(LOCAL, true).unwrap();
+        remove("log");
+    }
+}
@@ -0,0 +1,254 @@
+// What a NIC's driver says about it through EFI_ADAPTER_INFORMATION_PROTOCOL, for the things the simple network
+// protocol doesn't cover: whether there's link, the MAC address used for storage traffic, what it can boot from and
+// whether its UNDI does IPv6. Each is a block of its own type named by GUID, and drivers only have some of them.
+// There's no standard block for IPsec offload, so that isn't here.
+
+use ffi::{
+    adapter_info::*,
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    EFI_HANDLE,
+    EFI_GUID,
+    EFI_MAC_ADDRESS,
+    EFI_SUCCESS,
+    EFI_NO_MEDIA,
+    EFI_NOT_READY,
+    BOOLEAN,
+    TRUE,
+    UINT8,
+    VOID,
+};
+use boxed::EfiBox;
+use boot_services::locate_handles;
+use {Result, EfiErrorKind, system_table, image_handle};
+use core::{mem, ptr, slice};
+use alloc::vec::Vec;
+
+/// Whether there's a link partner
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MediaState {
+    Present,
+    NoMedia,
+    /// Still being negotiated
+    NotReady,
+}
+
+/// Which network boot methods the NIC can do, and which it's set up to do
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct NetworkBoot {
+    pub iscsi_ipv4_capable: bool,
+    pub iscsi_ipv6_capable: bool,
+    pub fcoe_capable: bool,
+    /// Can offload iSCSI or FCoE
+    pub offload_capable: bool,
+    pub iscsi_mpio_capable: bool,
+    pub iscsi_ipv4_boot: bool,
+    pub iscsi_ipv6_boot: bool,
+    pub fcoe_boot: bool,
+}
+
+/// A NIC's adapter information
+pub struct AdapterInfo {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_ADAPTER_INFORMATION_PROTOCOL,
+}
+
+impl AdapterInfo {
+    /// Fails with Unsupported if the NIC's driver doesn't have the protocol
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_ADAPTER_INFORMATION_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).OpenProtocol)(handle, &EFI_ADAPTER_INFORMATION_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL));
+        }
+        if protocol.is_null() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        Ok(AdapterInfo { handle, protocol })
+    }
+
+    /// One for every NIC that has it
+    pub fn all() -> Result<Vec<Self>> {
+        Ok(locate_handles(&EFI_ADAPTER_INFORMATION_PROTOCOL_GUID)?.into_iter().filter_map(|h| Self::new(h).ok()).collect())
+    }
+
+    /// The NIC's handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// The GUIDs of the blocks the driver has
+    pub fn supported_types(&self) -> Result<Vec<EFI_GUID>> {
+        let mut types: *mut EFI_GUID = ptr::null_mut();
+        let mut count = 0;
+        unsafe {
+            ret_on_err!(((*self.protocol).GetSupportedTypes)(self.protocol, &mut types, &mut count));
+            if types.is_null() {
+                return Ok(Vec::new());
+            }
+            let types = EfiBox::from_raw(types); // The driver allocates the list and we free it
+            Ok(slice::from_raw_parts(types.as_raw(), count).to_vec())
+        }
+    }
+
+    pub fn supports(&self, info_type: &EFI_GUID) -> bool {
+        self.supported_types().map_or(false, |types| types.contains(info_type))
+    }
+
+    /// The block of the given type as it is. Fails with Unsupported if the driver hasn't got one
+    pub fn get(&self, info_type: &EFI_GUID) -> Result<Vec<u8>> {
+        let mut block: *mut VOID = ptr::null_mut();
+        let mut size = 0;
+        unsafe {
+            ret_on_err!(((*self.protocol).GetInformation)(self.protocol, info_type, &mut block, &mut size));
+            if block.is_null() {
+                return Err(EfiErrorKind::Unsupported.into());
+            }
+            let block = EfiBox::from_raw(block as *mut u8);
+            Ok(slice::from_raw_parts(block.as_raw(), size).to_vec())
+        }
+    }
+
+    /// Replaces the block of the given type. Only some types can be set, e.g. the SAN MAC address
+    pub fn set(&self, info_type: &EFI_GUID, block: &[u8]) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).SetInformation)(self.protocol, info_type, block.as_ptr() as *const VOID, block.len()));
+        }
+        Ok(())
+    }
+
+    pub fn media_state(&self) -> Result<MediaState> {
+        let block: EFI_ADAPTER_INFO_MEDIA_STATE = self.get_as(&EFI_ADAPTER_INFO_MEDIA_STATE_GUID)?;
+        match block.MediaState {
+            EFI_SUCCESS => Ok(MediaState::Present),
+            EFI_NO_MEDIA => Ok(MediaState::NoMedia),
+            EFI_NOT_READY => Ok(MediaState::NotReady),
+            _ => Err(EfiErrorKind::ProtocolError.into()),
+        }
+    }
+
+    /// The MAC address the NIC uses for iSCSI and FCoE, when that's not its LAN address
+    pub fn san_mac_address(&self) -> Result<[u8; 6]> {
+        let block: EFI_ADAPTER_INFO_SAN_MAC_ADDRESS = self.get_as(&EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID)?;
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&block.SanMacAddress.Addr[..6]);
+        Ok(mac)
+    }
+
+    pub fn set_san_mac_address(&self, mac: [u8; 6]) -> Result<()> {
+        let mut block = EFI_ADAPTER_INFO_SAN_MAC_ADDRESS { SanMacAddress: EFI_MAC_ADDRESS::default() };
+        block.SanMacAddress.Addr[..6].copy_from_slice(&mac);
+        let bytes = unsafe { slice::from_raw_parts(&block as *const _ as *const u8, mem::size_of_val(&block)) };
+        self.set(&EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID, bytes)
+    }
+
+    pub fn network_boot(&self) -> Result<NetworkBoot> {
+        let block: EFI_ADAPTER_INFO_NETWORK_BOOT = self.get_as(&EFI_ADAPTER_INFO_NETWORK_BOOT_GUID)?;
+        let b = |v: BOOLEAN| v == TRUE;
+        Ok(NetworkBoot {
+            iscsi_ipv4_capable: b(block.iScsiIpv4BootCapablity),
+            iscsi_ipv6_capable: b(block.iScsiIpv6BootCapablity),
+            fcoe_capable: b(block.FCoeBootCapablity),
+            offload_capable: b(block.OffloadCapability),
+            iscsi_mpio_capable: b(block.iScsiMpioCapability),
+            iscsi_ipv4_boot: b(block.iScsiIpv4Boot),
+            iscsi_ipv6_boot: b(block.iScsiIpv6Boot),
+            fcoe_boot: b(block.FCoeBoot),
+        })
+    }
+
+    /// Whether the NIC's UNDI supports IPv6, and so whether PXE over IPv6 can work
+    pub fn undi_ipv6_support(&self) -> Result<bool> {
+        let block: EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT = self.get_as(&EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID)?;
+        Ok(block.Ipv6Support == TRUE)
+    }
+
+    /// The media the NIC is on, as the IANA interface type (6 for Ethernet, 71 for Wi-Fi)
+    pub fn media_type(&self) -> Result<UINT8> {
+        let block: EFI_ADAPTER_INFO_MEDIA_TYPE = self.get_as(&EFI_ADAPTER_INFO_MEDIA_TYPE_GUID)?;
+        Ok(block.MediaType)
+    }
+
+    // Blocks shorter than their type are from a confused driver
+    fn get_as<T: Copy>(&self, info_type: &EFI_GUID) -> Result<T> {
+        let block = self.get(info_type)?;
+        if block.len() < mem::size_of::<T>() {
+            return Err(EfiErrorKind::ProtocolError.into());
+        }
+        Ok(unsafe { ptr::read_unaligned(block.as_ptr() as *const T) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_STATUS, EFI_UNSUPPORTED, UINTN, FALSE};
+    use testing::mock;
+    use alloc::boxed::Box;
+
+    // Copies `block` into pool as the driver would
+    unsafe fn give<T>(block: &T, out: *mut *mut VOID, size: *mut UINTN) -> EFI_STATUS {
+        let buf = EfiBox::<T>::allocate(mem::size_of::<T>()).unwrap().into_raw();
+        ptr::copy_nonoverlapping(block as *const T, buf, 1);
+        *out = buf as *mut VOID;
+        *size = mem::size_of::<T>();
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_info(_this: *const EFI_ADAPTER_INFORMATION_PROTOCOL, info_type: *const EFI_GUID, block: *mut *mut VOID, size: *mut UINTN) -> EFI_STATUS {
+        unsafe {
+            match *info_type {
+                EFI_ADAPTER_INFO_MEDIA_STATE_GUID => give(&EFI_ADAPTER_INFO_MEDIA_STATE { MediaState: EFI_NO_MEDIA }, block, size),
+                EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID => give(&EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT { Ipv6Support: TRUE }, block, size),
+                EFI_ADAPTER_INFO_NETWORK_BOOT_GUID => give(&EFI_ADAPTER_INFO_NETWORK_BOOT {
+                    iScsiIpv4BootCapablity: TRUE,
+                    iScsiIpv6BootCapablity: FALSE,
+                    FCoeBootCapablity: TRUE,
+                    OffloadCapability: FALSE,
+                    iScsiMpioCapability: FALSE,
+                    iScsiIpv4Boot: TRUE,
+                    iScsiIpv6Boot: FALSE,
+                    FCoeBoot: FALSE,
+                }, block, size),
+                _ => EFI_UNSUPPORTED,
+            }
+        }
+    }
+
+    extern "efiapi" fn set_info(_this: *const EFI_ADAPTER_INFORMATION_PROTOCOL, _info_type: *const EFI_GUID, _block: *const VOID, _size: UINTN) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_supported_types(_this: *const EFI_ADAPTER_INFORMATION_PROTOCOL, types: *mut *mut EFI_GUID, count: *mut UINTN) -> EFI_STATUS {
+        let supported = [EFI_ADAPTER_INFO_MEDIA_STATE_GUID, EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID, EFI_ADAPTER_INFO_NETWORK_BOOT_GUID];
+        unsafe {
+            give(&supported, types as *mut *mut VOID, count);
+            *count = supported.len();
+        }
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn reads_typed_blocks() {
+        mock::install();
+        let protocol = Box::leak(Box::new(EFI_ADAPTER_INFORMATION_PROTOCOL {
+            GetInformation: get_info,
+            SetInformation: set_info,
+            GetSupportedTypes: get_supported_types,
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_ADAPTER_INFORMATION_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, protocol as *const _ as *const VOID);
+        }
+
+        let info = AdapterInfo::all().unwrap().into_iter().find(|i| i.handle() == handle).unwrap();
+        assert_eq!(info.supported_types().unwrap().len(), 3);
+        assert!(info.supports(&EFI_ADAPTER_INFO_NETWORK_BOOT_GUID));
+        assert!(!info.supports(&EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID));
+        assert_eq!(info.media_state().unwrap(), MediaState::NoMedia);
+        assert!(info.undi_ipv6_support().unwrap());
+        let boot = info.network_boot().unwrap();
+        assert!(boot.iscsi_ipv4_capable && boot.fcoe_capable && boot.iscsi_ipv4_boot && !boot.fcoe_boot);
+        assert_eq!(info.san_mac_address().unwrap_err().kind(), EfiErrorKind::Unsupported);
+        assert_eq!(info.set_san_mac_address([2, 0, 0, 0, 0, 1]).unwrap_err().kind(), EfiErrorKind::Unsupported);
+    }
+}
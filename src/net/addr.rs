@@ -2,7 +2,7 @@ use ffi::{EFI_IPv4_ADDRESS, EFI_IPv6_ADDRESS, EFI_IP_ADDRESS};
 use core::{mem, fmt, iter, slice, option, cmp::Ordering};
 use io;
 use alloc::{string::String, vec::{self, Vec}};
-use super::dns::lookup_host;
+use super::dns::{lookup_host, lookup_host_dual_stack};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Ipv4Addr(EFI_IPv4_ADDRESS);
@@ -637,6 +637,12 @@ impl From<EFI_IPv6_ADDRESS> for IpAddr {
     }
 }
 
+impl From<[u8; 4]> for IpAddr {
+    fn from(octets: [u8; 4]) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(octets))
+    }
+}
+
 impl From<[u8; 16]> for IpAddr {
     fn from(octets: [u8; 16]) -> IpAddr {
         IpAddr::V6(Ipv6Addr::from(octets))
@@ -804,6 +810,13 @@ pub trait ToSocketAddrs {
     /// Note that this function may block the current thread while resolution is
     /// performed.
     fn to_socket_addrs(&self) -> io::Result<Self::Iter>;
+
+    // What TcpStreamBuilder's happy eyeballs connect races: to_socket_addrs() but with DNS asked for IPv6 addresses
+    // as well, which only makes a difference for host names
+    #[doc(hidden)]
+    fn to_dual_stack_socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.to_socket_addrs().map(Iterator::collect)
+    }
 }
 
 impl ToSocketAddrs for SocketAddr {
@@ -854,57 +867,108 @@ impl ToSocketAddrs for (Ipv6Addr, u16) {
     }
 }
 
+impl ToSocketAddrs for ([u8; 4], u16) {
+    type Iter = option::IntoIter<SocketAddr>;
+    fn to_socket_addrs(&self) -> io::Result<option::IntoIter<SocketAddr>> {
+        let (ip, port) = *self;
+        (Ipv4Addr::from(ip), port).to_socket_addrs()
+    }
+}
+
+impl ToSocketAddrs for ([u8; 16], u16) {
+    type Iter = option::IntoIter<SocketAddr>;
+    fn to_socket_addrs(&self) -> io::Result<option::IntoIter<SocketAddr>> {
+        let (ip, port) = *self;
+        (Ipv6Addr::from(ip), port).to_socket_addrs()
+    }
+}
+
+impl ToSocketAddrs for ([u16; 8], u16) {
+    type Iter = option::IntoIter<SocketAddr>;
+    fn to_socket_addrs(&self) -> io::Result<option::IntoIter<SocketAddr>> {
+        let (ip, port) = *self;
+        (Ipv6Addr::from(ip), port).to_socket_addrs()
+    }
+}
+
+type Lookup = fn(&str) -> ::Result<Vec<IpAddr>>;
+
 #[allow(deprecated)]
-fn resolve_socket_addr(hostname: &str, port: u16) -> io::Result<vec::IntoIter<SocketAddr>> {
-    let ip_addrs = lookup_host(hostname).map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to resolve host name"))?;
+fn resolve_socket_addr(hostname: &str, port: u16, lookup: Lookup) -> io::Result<vec::IntoIter<SocketAddr>> {
+    let ip_addrs = lookup(hostname).map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to resolve host name"))?;
     let sock_addrs: Vec<_> = ip_addrs.into_iter().map(|ip| SocketAddr::new(ip, port)).collect();
     Ok(sock_addrs.into_iter())
 }
 
+fn host_socket_addrs(host: &str, port: u16, lookup: Lookup) -> io::Result<vec::IntoIter<SocketAddr>> {
+    // try to parse the host as a regular IP address first
+    if let Ok(addr) = host.parse::<Ipv4Addr>() {
+        let addr = SocketAddrV4::new(addr, port);
+        return Ok(vec![SocketAddr::V4(addr)].into_iter())
+    }
+    if let Ok(addr) = host.parse::<Ipv6Addr>() {
+        let addr = SocketAddrV6::new(addr, port);
+        return Ok(vec![SocketAddr::V6(addr)].into_iter())
+    }
+
+    resolve_socket_addr(host, port, lookup)
+}
+
 impl<'a> ToSocketAddrs for (&'a str, u16) {
     type Iter = vec::IntoIter<SocketAddr>;
     fn to_socket_addrs(&self) -> io::Result<vec::IntoIter<SocketAddr>> {
-        let (host, port) = *self;
+        host_socket_addrs(self.0, self.1, lookup_host)
+    }
 
-        // try to parse the host as a regular IP address first
-        if let Ok(addr) = host.parse::<Ipv4Addr>() {
-            let addr = SocketAddrV4::new(addr, port);
-            return Ok(vec![SocketAddr::V4(addr)].into_iter())
-        }
-        if let Ok(addr) = host.parse::<Ipv6Addr>() {
-            let addr = SocketAddrV6::new(addr, port);
-            return Ok(vec![SocketAddr::V6(addr)].into_iter())
-        }
+    fn to_dual_stack_socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        host_socket_addrs(self.0, self.1, lookup_host_dual_stack).map(Iterator::collect)
+    }
+}
 
-        resolve_socket_addr(host, port)
+impl ToSocketAddrs for (String, u16) {
+    type Iter = vec::IntoIter<SocketAddr>;
+    fn to_socket_addrs(&self) -> io::Result<vec::IntoIter<SocketAddr>> {
+        (&*self.0, self.1).to_socket_addrs()
+    }
+
+    fn to_dual_stack_socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        (&*self.0, self.1).to_dual_stack_socket_addrs()
     }
 }
 
 // accepts strings like 'localhost:12345'
+fn str_socket_addrs(s: &str, lookup: Lookup) -> io::Result<vec::IntoIter<SocketAddr>> {
+    // try to parse as a regular SocketAddr first
+    if let Some(addr) = s.parse().ok() {
+        return Ok(vec![addr].into_iter());
+    }
+
+    macro_rules! try_opt {
+        ($e:expr, $msg:expr) => (
+            match $e {
+                Some(r) => r,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  $msg)),
+            }
+        )
+    }
+
+    // split the string by ':' and convert the second part to u16
+    let mut parts_iter = s.rsplitn(2, ':');
+    let port_str = try_opt!(parts_iter.next(), "invalid socket address");
+    let host = try_opt!(parts_iter.next(), "invalid socket address");
+    let port: u16 = try_opt!(port_str.parse().ok(), "invalid port value");
+    resolve_socket_addr(host, port, lookup)
+}
+
 impl ToSocketAddrs for str {
     type Iter = vec::IntoIter<SocketAddr>;
     fn to_socket_addrs(&self) -> io::Result<vec::IntoIter<SocketAddr>> {
-        // try to parse as a regular SocketAddr first
-        if let Some(addr) = self.parse().ok() {
-            return Ok(vec![addr].into_iter());
-        }
-
-        macro_rules! try_opt {
-            ($e:expr, $msg:expr) => (
-                match $e {
-                    Some(r) => r,
-                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
-                                                      $msg)),
-                }
-            )
-        }
+        str_socket_addrs(self, lookup_host)
+    }
 
-        // split the string by ':' and convert the second part to u16
-        let mut parts_iter = self.rsplitn(2, ':');
-        let port_str = try_opt!(parts_iter.next(), "invalid socket address");
-        let host = try_opt!(parts_iter.next(), "invalid socket address");
-        let port: u16 = try_opt!(port_str.parse().ok(), "invalid port value");
-        resolve_socket_addr(host, port)
+    fn to_dual_stack_socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        str_socket_addrs(self, lookup_host_dual_stack).map(Iterator::collect)
     }
 }
 
@@ -921,6 +985,10 @@ impl<'a, T: ToSocketAddrs + ?Sized> ToSocketAddrs for &'a T {
     fn to_socket_addrs(&self) -> io::Result<T::Iter> {
         (**self).to_socket_addrs()
     }
+
+    fn to_dual_stack_socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        (**self).to_dual_stack_socket_addrs()
+    }
 }
 
 impl ToSocketAddrs for String {
@@ -928,6 +996,10 @@ impl ToSocketAddrs for String {
     fn to_socket_addrs(&self) -> io::Result<vec::IntoIter<SocketAddr>> {
         (&**self).to_socket_addrs()
     }
+
+    fn to_dual_stack_socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        (&**self).to_dual_stack_socket_addrs()
+    }
 }
 
 // Tests for this module
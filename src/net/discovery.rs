@@ -0,0 +1,285 @@
+// Finding services on the local link with multicast DNS and DNS-SD (RFC 6762 and 6763), e.g. an imaging server on a
+// provisioning network that has no DNS server and where nobody wants to type in addresses:
+//
+//     for service in discovery::browse("_http._tcp", Duration::from_secs(2))? {
+//         println!("{} at {}:{} path={:?}", service.name, service.host, service.port, service.txt_value("path"));
+//     }
+//
+// Queries are one-shot (RFC 6762 section 5.1): sent to the mDNS group from an ordinary port asking for unicast
+// replies, so there's no group to join and nothing answered to. Responders that only ever answer to the group aren't
+// heard. Names have to be ASCII, since that's all the DNS parser takes.
+
+use ::{Result, EfiErrorKind};
+use events::{Timer, TimerSchedule, TimerState, EventTpl, Wait};
+use super::{UdpSocket, Ipv4Addr, IpAddr, SocketAddr, SocketAddrV4};
+use super::dns::{Builder, Packet, QueryType, QueryClass, RData, rdata};
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::time::Duration;
+
+pub const MDNS_PORT: u16 = 5353;
+
+/// The IPv4 group mDNS queries go to
+pub fn mdns_group() -> Ipv4Addr {
+    Ipv4Addr::new(224, 0, 0, 251)
+}
+
+const DOMAIN: &str = "local";
+// How often to stop waiting for a reply to see whether the time's up
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Replies can be bigger than DNS's 512 bytes, up to what fits in a packet
+const MAX_REPLY_LEN: usize = 9000;
+
+/// An instance of a service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    /// The instance's full name, e.g. `Imaging Server._http._tcp.local`
+    pub name: String,
+    /// The host it's on, e.g. `server1.local`
+    pub host: String,
+    pub port: u16,
+    /// The host's addresses, or none if it didn't say
+    pub addrs: Vec<IpAddr>,
+    /// The TXT record's key/value pairs. A key without a value has an empty one
+    pub txt: Vec<(String, String)>,
+}
+
+impl Service {
+    /// The value for a key in the TXT record. Keys are case insensitive
+    pub fn txt_value(&self, key: &str) -> Option<&str> {
+        self.txt.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+    }
+
+    /// Where to connect to it
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.addrs.iter().map(|&ip| SocketAddr::new(ip, self.port)).collect()
+    }
+}
+
+/// The instances of `service_type` (e.g. `_http._tcp`, with or without `.local`) that answer within `wait`. Those
+/// that don't say where they are in their first answer are asked again, for up to another `wait`
+pub fn browse(service_type: &str, wait: Duration) -> Result<Vec<Service>> {
+    let service_type = fully_qualified(service_type);
+    let mut socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let group = SocketAddrV4::new(mdns_group(), MDNS_PORT);
+
+    let mut answers = Answers::default();
+    socket.send_to(&query(&[(&service_type, QueryType::PTR)])?, group)?;
+    listen(&mut socket, wait, &service_type, &mut answers)?;
+
+    let missing = answers.missing();
+    if !missing.is_empty() {
+        let questions = missing.iter().map(|&(ref name, qtype)| (name.as_str(), qtype)).collect::<Vec<_>>();
+        socket.send_to(&query(&questions)?, group)?;
+        listen(&mut socket, wait, &service_type, &mut answers)?;
+    }
+
+    Ok(answers.services())
+}
+
+fn fully_qualified(service_type: &str) -> String {
+    let service_type = service_type.trim_end_matches('.');
+    if service_type.to_ascii_lowercase().ends_with(".local") {
+        service_type.to_string()
+    } else {
+        format!("{}.{}", service_type, DOMAIN)
+    }
+}
+
+// A one-shot query, asking for unicast replies
+fn query(questions: &[(&str, QueryType)]) -> Result<Vec<u8>> {
+    let mut builder = Builder::new_query(0, false);
+    for &(name, qtype) in questions {
+        if name.split('.').any(|label| label.is_empty() || label.len() >= 63) {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        builder.add_question(name, true, qtype, QueryClass::IN);
+    }
+    builder.build().map_err(|_| EfiErrorKind::BufferTooSmall.into())
+}
+
+fn listen(socket: &mut UdpSocket, wait: Duration, service_type: &str, answers: &mut Answers) -> Result<()> {
+    let deadline = Timer::create(wait, TimerSchedule::Relative, TimerState::Active, EventTpl::Callback)?;
+    let mut buf = vec![0u8; MAX_REPLY_LEN];
+    while !deadline.is_signaled()? {
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                // Anything we can't make sense of is someone else's business
+                if let Ok(packet) = Packet::parse(&buf[..len]) {
+                    if !packet.header.query {
+                        answers.add(&packet, service_type);
+                    }
+                }
+            },
+            Err(ref e) if e.kind() == EfiErrorKind::Timeout => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// What's been heard so far. Records can come in any order and any section, and in separate replies
+#[derive(Debug, Default)]
+struct Answers {
+    instances: Vec<String>,
+    srv: Vec<(String, String, u16)>,
+    txt: Vec<(String, Vec<(String, String)>)>,
+    addrs: Vec<(String, IpAddr)>,
+}
+
+impl Answers {
+    fn add(&mut self, packet: &Packet, service_type: &str) {
+        for record in packet.answers.iter().chain(packet.additional.iter()) {
+            let name = record.name.to_string();
+            match record.data {
+                RData::PTR(rdata::ptr::Record(ref instance)) if name.eq_ignore_ascii_case(service_type) => {
+                    let instance = instance.to_string();
+                    if !contains(&self.instances, &instance) {
+                        self.instances.push(instance);
+                    }
+                },
+                RData::SRV(ref srv) => {
+                    self.srv.retain(|(n, _, _)| !n.eq_ignore_ascii_case(&name));
+                    self.srv.push((name, srv.target.to_string(), srv.port));
+                },
+                RData::TXT(ref txt) => {
+                    let pairs = txt.iter().filter(|s| !s.is_empty()).map(|s| {
+                        let s = String::from_utf8_lossy(s);
+                        match s.find('=') {
+                            Some(i) => (s[..i].to_string(), s[i + 1..].to_string()),
+                            None => (s.to_string(), String::new()),
+                        }
+                    }).collect();
+                    self.txt.retain(|(n, _)| !n.eq_ignore_ascii_case(&name));
+                    self.txt.push((name, pairs));
+                },
+                RData::A(rdata::a::Record(ip)) => self.add_addr(name, IpAddr::V4(ip)),
+                RData::AAAA(rdata::aaaa::Record(ip)) => self.add_addr(name, IpAddr::V6(ip)),
+                _ => (),
+            }
+        }
+    }
+
+    fn add_addr(&mut self, host: String, ip: IpAddr) {
+        if !self.addrs.iter().any(|&(ref h, a)| h.eq_ignore_ascii_case(&host) && a == ip) {
+            self.addrs.push((host, ip));
+        }
+    }
+
+    fn srv(&self, instance: &str) -> Option<&(String, String, u16)> {
+        self.srv.iter().find(|(n, _, _)| n.eq_ignore_ascii_case(instance))
+    }
+
+    fn addrs(&self, host: &str) -> Vec<IpAddr> {
+        self.addrs.iter().filter(|(h, _)| h.eq_ignore_ascii_case(host)).map(|&(_, ip)| ip).collect()
+    }
+
+    // What to ask again for: SRV and TXT records for instances we've no SRV for, addresses for hosts we've none for
+    fn missing(&self) -> Vec<(String, QueryType)> {
+        let mut missing = Vec::new();
+        for instance in &self.instances {
+            match self.srv(instance) {
+                None => {
+                    missing.push((instance.clone(), QueryType::SRV));
+                    missing.push((instance.clone(), QueryType::TXT));
+                },
+                Some((_, host, _)) if self.addrs(host).is_empty() && !missing.iter().any(|(n, _)| n == host) => {
+                    missing.push((host.clone(), QueryType::A));
+                },
+                Some(_) => (),
+            }
+        }
+        missing
+    }
+
+    // The instances we know where to find, in the order they were heard of
+    fn services(&self) -> Vec<Service> {
+        self.instances.iter().filter_map(|instance| {
+            let &(_, ref host, port) = self.srv(instance)?;
+            let txt = self.txt.iter().find(|(n, _)| n.eq_ignore_ascii_case(instance)).map(|(_, t)| t.clone());
+            Some(Service { name: instance.clone(), host: host.clone(), port, addrs: self.addrs(host), txt: txt.unwrap_or_default() })
+        }).collect()
+    }
+}
+
+fn contains(names: &[String], name: &str) -> bool {
+    names.iter().any(|n| n.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, ByteOrder};
+
+    fn name(buf: &mut Vec<u8>, name: &str) {
+        for label in name.split('.') {
+            buf.push(label.len() as u8);
+            buf.extend(label.as_bytes());
+        }
+        buf.push(0);
+    }
+
+    fn record(buf: &mut Vec<u8>, owner: &str, rtype: u16, rdata: &[u8]) {
+        name(buf, owner);
+        let mut fixed = [0u8; 10];
+        BigEndian::write_u16(&mut fixed[0..2], rtype);
+        BigEndian::write_u16(&mut fixed[2..4], 0x8001); // IN, with the cache flush bit
+        BigEndian::write_u32(&mut fixed[4..8], 120);
+        BigEndian::write_u16(&mut fixed[8..10], rdata.len() as u16);
+        buf.extend(&fixed);
+        buf.extend(rdata);
+    }
+
+    // A reply with the given number of answers, the rest of the records being additional
+    fn reply(answers: u16, records: &[(&str, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut buf = vec![0, 0, 0x84, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        BigEndian::write_u16(&mut buf[6..8], answers);
+        BigEndian::write_u16(&mut buf[10..12], records.len() as u16 - answers);
+        for &(owner, rtype, ref rdata) in records {
+            record(&mut buf, owner, rtype, rdata);
+        }
+        buf
+    }
+
+    #[test]
+    fn collects_services_from_replies() {
+        assert_eq!(fully_qualified("_http._tcp"), "_http._tcp.local");
+        assert_eq!(fully_qualified("_http._tcp.local."), "_http._tcp.local");
+
+        let mut ptr = Vec::new();
+        name(&mut ptr, "imaging._http._tcp.local");
+        let mut srv = vec![0, 0, 0, 0, 0x1f, 0x90]; // Port 8080
+        name(&mut srv, "server1.local");
+        let txt = b"\x09path=/img\x05debug".to_vec();
+
+        let mut answers = Answers::default();
+        let first = reply(1, &[
+            ("_http._tcp.local", 12, ptr.clone()),
+            ("imaging._http._tcp.local", 33, srv),
+            ("imaging._http._tcp.local", 16, txt),
+        ]);
+        answers.add(&Packet::parse(&first).unwrap(), "_http._tcp.local");
+        // Somebody else's service type is ignored
+        let mut other = Vec::new();
+        name(&mut other, "printer._ipp._tcp.local");
+        answers.add(&Packet::parse(&reply(1, &[("_ipp._tcp.local", 12, other)])).unwrap(), "_http._tcp.local");
+
+        // Knows where it is but not its address yet
+        assert_eq!(answers.missing(), [("server1.local".to_string(), QueryType::A)]);
+        let second = reply(2, &[("_HTTP._tcp.local", 12, ptr), ("server1.local", 1, vec![192, 168, 7, 10])]);
+        answers.add(&Packet::parse(&second).unwrap(), "_http._tcp.local");
+        assert!(answers.missing().is_empty());
+
+        let services = answers.services();
+        assert_eq!(services.len(), 1);
+        let service = &services[0];
+        assert_eq!((service.name.as_str(), service.host.as_str(), service.port), ("imaging._http._tcp.local", "server1.local", 8080));
+        assert_eq!(service.socket_addrs(), [SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 7, 10)), 8080)]);
+        assert_eq!(service.txt_value("PATH"), Some("/img"));
+        assert_eq!(service.txt_value("debug"), Some(""));
+        assert_eq!(service.txt_value("other"), None);
+
+        assert!(query(&[("_http._tcp.local", QueryType::PTR)]).is_ok());
+        assert!(query(&[("bad..name", QueryType::PTR)]).is_err());
+    }
+}
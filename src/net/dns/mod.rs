@@ -47,10 +47,11 @@ struct DnsServer {
 const DNS_TIMEOUT: Duration = Duration::from_secs(30);
 // TODO: Swallowing/transmorgifying all errors. Fix this large scale shit wherever present
 impl DnsServer {
-    fn query(&self, hostname: &str) -> ::Result<Vec<IpAddr>> {
-        use net::dns::rdata::a::Record;
+    // The addresses of the type asked for, A or AAAA
+    fn query(&self, hostname: &str, qtype: QueryType) -> ::Result<Vec<IpAddr>> {
+        use net::dns::rdata::{a, aaaa};
         let mut builder = Builder::new_query(1, true);
-        builder.add_question(hostname, false, QueryType::A, QueryClass::IN);
+        builder.add_question(hostname, false, qtype, QueryClass::IN);
         let packet = builder.build().map_err(|_| ::EfiErrorKind::DeviceError)?; 
         let mut socket = UdpSocket::bind("0.0.0.0:0")?;
         socket.send_to(&packet, self.addr)?;
@@ -70,7 +71,8 @@ impl DnsServer {
         let addrs = pkt.answers.iter()
                             .filter_map(|a| { 
                                 match a.data {
-                                    RData::A(Record(addr)) => Some(IpAddr::V4(addr)),
+                                    RData::A(a::Record(addr)) => Some(IpAddr::V4(addr)),
+                                    RData::AAAA(aaaa::Record(addr)) => Some(IpAddr::V6(addr)),
                                     _ => None
                                 }
                             }).collect::<Vec<_>>();
@@ -85,7 +87,7 @@ pub (crate) fn lookup_host(hostname: &str) -> ::Result<Vec<IpAddr>> {
     }
 
     for dns_server in dns_servers {
-        let addrs = match dns_server.query(hostname) {
+        let addrs = match dns_server.query(hostname, QueryType::A) {
             Ok(addrs) => addrs,
             Err(_) => continue
         };
@@ -97,6 +99,31 @@ pub (crate) fn lookup_host(hostname: &str) -> ::Result<Vec<IpAddr>> {
     Ok(Vec::new())
 }
 
+// lookup_host() with the AAAA records too, for the happy eyeballs connect, which is the only thing that races the
+// families. Everything else stays on A alone so that a server that drops AAAA queries doesn't hold up IPv4 lookups
+pub (crate) fn lookup_host_dual_stack(hostname: &str) -> ::Result<Vec<IpAddr>> {
+    let dns_servers = get_dns_servers()?;
+    if dns_servers.is_empty() {
+        return Err(::EfiErrorKind::DeviceError.into());
+    }
+
+    for dns_server in dns_servers {
+        let mut addrs = match dns_server.query(hostname, QueryType::A) {
+            Ok(addrs) => addrs,
+            Err(ref e) if e.kind() == ::EfiErrorKind::Timeout => continue, // Not worth waiting for it twice
+            Err(_) => Vec::new(), // Maybe there's only AAAA
+        };
+        if let Ok(v6_addrs) = dns_server.query(hostname, QueryType::AAAA) {
+            addrs.extend(v6_addrs);
+        }
+        if !addrs.is_empty() {
+            return Ok(addrs);
+        }
+    }
+
+    Ok(Vec::new())
+}
+
 fn get_dns_servers() -> ::Result<Vec<DnsServer>> {
     // TODO: Assuming here that PXE has already happened. Should we kick it off here if it hasn't?
     const DNS_PORT: u16 = 53;
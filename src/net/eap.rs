@@ -0,0 +1,249 @@
+// 802.1X settings via EFI_EAP_CONFIGURATION_PROTOCOL.
+// On networks with port authentication the NIC's driver runs EAP itself, and this is where it gets the method,
+// identity and credentials to use. Set them before connecting: the driver reads them when it authenticates. Secrets
+// like passwords are kept in volatile storage by the driver and have to be set again after a reboot.
+//
+// Certificates and keys are handed over as bytes in the form the firmware's TLS library takes them: DER or PEM
+// certificates and PEM private keys.
+
+use ffi::{
+    eap::{
+        EFI_EAP_CONFIGURATION_PROTOCOL,
+        EFI_EAP_CONFIGURATION_PROTOCOL_GUID,
+        EFI_EAP_CONFIG_DATA_TYPE,
+        EFI_EAP_TYPE,
+        EFI_EAP_TYPE_ATTRIBUTE,
+        EFI_EAP_TYPE_IDENTITY,
+        EFI_EAP_TYPE_EAPTLS,
+        EFI_EAP_TYPE_TTLS,
+        EFI_EAP_TYPE_PEAP,
+        EFI_EAP_TYPE_MSCHAPV2,
+    },
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_BUFFER_TOO_SMALL,
+    EFI_HANDLE,
+    UINTN,
+    VOID,
+};
+use boot_services::locate_handles;
+use utils::to_ucs2;
+use {Result, EfiErrorKind, system_table, image_handle, to_res};
+use core::{mem, ptr};
+use alloc::vec::Vec;
+
+/// An EAP authentication method
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EapMethod {
+    Tls,
+    Ttls,
+    Peap,
+    MsChapV2,
+    Other(u8),
+}
+
+impl From<u8> for EapMethod {
+    fn from(eap_type: u8) -> Self {
+        match eap_type {
+            EFI_EAP_TYPE_EAPTLS => EapMethod::Tls,
+            EFI_EAP_TYPE_TTLS => EapMethod::Ttls,
+            EFI_EAP_TYPE_PEAP => EapMethod::Peap,
+            EFI_EAP_TYPE_MSCHAPV2 => EapMethod::MsChapV2,
+            other => EapMethod::Other(other),
+        }
+    }
+}
+
+impl From<EapMethod> for u8 {
+    fn from(method: EapMethod) -> Self {
+        match method {
+            EapMethod::Tls => EFI_EAP_TYPE_EAPTLS,
+            EapMethod::Ttls => EFI_EAP_TYPE_TTLS,
+            EapMethod::Peap => EFI_EAP_TYPE_PEAP,
+            EapMethod::MsChapV2 => EFI_EAP_TYPE_MSCHAPV2,
+            EapMethod::Other(other) => other,
+        }
+    }
+}
+
+/// The EAP settings of one NIC
+pub struct EapConfig {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_EAP_CONFIGURATION_PROTOCOL,
+}
+
+impl EapConfig {
+    /// One for every NIC whose driver can do 802.1X
+    pub fn all() -> Result<Vec<Self>> {
+        locate_handles(&EFI_EAP_CONFIGURATION_PROTOCOL_GUID)?.into_iter().map(Self::for_handle).collect()
+    }
+
+    /// The settings of a particular NIC e.g. a WifiAdapter's handle. Fails with Unsupported if it can't do 802.1X
+    pub fn for_handle(handle: EFI_HANDLE) -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_EAP_CONFIGURATION_PROTOCOL = ptr::null();
+        let status = unsafe { traced!(((*bs).OpenProtocol)(handle, &EFI_EAP_CONFIGURATION_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL)) };
+        if !::ffi::IsSuccess(status) || protocol.is_null() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        Ok(EapConfig { handle, protocol })
+    }
+
+    /// The NIC's handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// The methods the driver can authenticate with
+    pub fn supported_methods(&self) -> Result<Vec<EapMethod>> {
+        let methods = self.get(EFI_EAP_TYPE_ATTRIBUTE, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapSupportedAuthMethod)?;
+        Ok(methods.into_iter().map(EapMethod::from).collect())
+    }
+
+    /// The method the driver will authenticate with
+    pub fn method(&self) -> Result<EapMethod> {
+        let method = self.get(EFI_EAP_TYPE_ATTRIBUTE, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapAuthMethod)?;
+        method.first().map(|&m| m.into()).ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    pub fn set_method(&self, method: EapMethod) -> Result<()> {
+        self.set(EFI_EAP_TYPE_ATTRIBUTE, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapAuthMethod, &[method.into()])
+    }
+
+    /// The identity sent in the clear at the start of authentication, often the user name or an anonymous one
+    /// when the real identity goes inside a TLS tunnel
+    pub fn set_identity(&self, identity: &str) -> Result<()> {
+        self.set(EFI_EAP_TYPE_IDENTITY, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigIdentityString, identity.as_bytes())
+    }
+
+    /// The certificate authority the authentication server's certificate must chain to
+    pub fn set_ca_certificate(&self, certificate: &[u8]) -> Result<()> {
+        self.set(EFI_EAP_TYPE_EAPTLS, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapTlsCACert, certificate)
+    }
+
+    /// The certificate and private key EAP-TLS proves who we are with. `password` decrypts the key if it's
+    /// encrypted
+    pub fn set_client_certificate(&self, certificate: &[u8], private_key: &[u8], password: Option<&str>) -> Result<()> {
+        self.set(EFI_EAP_TYPE_EAPTLS, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapTlsClientCert, certificate)?;
+        self.set(EFI_EAP_TYPE_EAPTLS, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapTlsClientPrivateKeyFile, private_key)?;
+        if let Some(password) = password {
+            let mut password = password.as_bytes().to_vec();
+            password.push(0);
+            self.set(EFI_EAP_TYPE_EAPTLS, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapTlsClientPrivateKeyFilePassword, &password)?;
+        }
+        Ok(())
+    }
+
+    /// The method used inside the tunnel PEAP and TTLS set up, typically MsChapV2
+    pub fn set_inner_method(&self, outer: EapMethod, inner: EapMethod) -> Result<()> {
+        if outer != EapMethod::Peap && outer != EapMethod::Ttls {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        self.set(outer.into(), EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEap2ndAuthMethod, &[inner.into()])
+    }
+
+    pub fn set_mschapv2_password(&self, password: &str) -> Result<()> {
+        let password = to_ucs2(password);
+        let bytes = unsafe { ::core::slice::from_raw_parts(password.as_ptr() as *const u8, password.len() * 2) };
+        self.set(EFI_EAP_TYPE_MSCHAPV2, EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapMSChapV2Password, bytes)
+    }
+
+    fn set(&self, eap_type: EFI_EAP_TYPE, data_type: EFI_EAP_CONFIG_DATA_TYPE, data: &[u8]) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).SetData)(self.protocol, eap_type, data_type, data.as_ptr() as *const VOID, data.len()));
+        }
+        Ok(())
+    }
+
+    fn get(&self, eap_type: EFI_EAP_TYPE, data_type: EFI_EAP_CONFIG_DATA_TYPE) -> Result<Vec<u8>> {
+        let mut size: UINTN = 0;
+        let status = unsafe { traced!(((*self.protocol).GetData)(self.protocol, eap_type, data_type, ptr::null_mut(), &mut size)) };
+        if status != EFI_BUFFER_TOO_SMALL {
+            to_res((), status)?;
+            return Ok(Vec::new());
+        }
+        let mut data = vec![0u8; size];
+        unsafe {
+            ret_on_err!(((*self.protocol).GetData)(self.protocol, eap_type, data_type, data.as_mut_ptr() as *mut VOID, &mut size));
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_STATUS, EFI_SUCCESS, EFI_NOT_FOUND};
+    use testing::mock;
+    use core::cell::RefCell;
+    use alloc::boxed::Box;
+
+    #[repr(C)]
+    struct FakeDriver {
+        protocol: EFI_EAP_CONFIGURATION_PROTOCOL,
+        settings: RefCell<Vec<(EFI_EAP_TYPE, EFI_EAP_CONFIG_DATA_TYPE, Vec<u8>)>>,
+    }
+
+    fn settings<'a>(this: *const EFI_EAP_CONFIGURATION_PROTOCOL) -> &'a RefCell<Vec<(EFI_EAP_TYPE, EFI_EAP_CONFIG_DATA_TYPE, Vec<u8>)>> {
+        unsafe { &(*(this as *const FakeDriver)).settings }
+    }
+
+    extern "efiapi" fn set_data(this: *const EFI_EAP_CONFIGURATION_PROTOCOL, eap_type: EFI_EAP_TYPE, data_type: EFI_EAP_CONFIG_DATA_TYPE, data: *const VOID, size: UINTN) -> EFI_STATUS {
+        let data = unsafe { ::core::slice::from_raw_parts(data as *const u8, size) }.to_vec();
+        let mut settings = settings(this).borrow_mut();
+        settings.retain(|&(t, d, _)| (t, d) != (eap_type, data_type));
+        settings.push((eap_type, data_type, data));
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_data(this: *const EFI_EAP_CONFIGURATION_PROTOCOL, eap_type: EFI_EAP_TYPE, data_type: EFI_EAP_CONFIG_DATA_TYPE, data: *mut VOID, size: *mut UINTN) -> EFI_STATUS {
+        let settings = settings(this).borrow();
+        let value: &[u8] = match settings.iter().find(|&&(t, d, _)| (t, d) == (eap_type, data_type)) {
+            Some((_, _, value)) => value,
+            None if data_type == EFI_EAP_CONFIG_DATA_TYPE::EfiEapConfigEapSupportedAuthMethod => &[EFI_EAP_TYPE_EAPTLS, EFI_EAP_TYPE_PEAP, EFI_EAP_TYPE_MSCHAPV2],
+            None => return EFI_NOT_FOUND,
+        };
+        unsafe {
+            if *size < value.len() {
+                *size = value.len();
+                return EFI_BUFFER_TOO_SMALL;
+            }
+            ptr::copy_nonoverlapping(value.as_ptr(), data as *mut u8, value.len());
+            *size = value.len();
+        }
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn configures_peap() {
+        mock::install();
+        let driver: &'static FakeDriver = Box::leak(Box::new(FakeDriver {
+            protocol: EFI_EAP_CONFIGURATION_PROTOCOL { SetData: set_data, GetData: get_data },
+            settings: RefCell::new(Vec::new()),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_EAP_CONFIGURATION_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, driver as *const _ as *const VOID);
+        }
+
+        let eap = EapConfig::for_handle(handle).unwrap();
+        assert_eq!(eap.supported_methods().unwrap(), [EapMethod::Tls, EapMethod::Peap, EapMethod::MsChapV2]);
+        assert_eq!(eap.method().unwrap_err().kind(), EfiErrorKind::NotFound);
+        eap.set_method(EapMethod::Peap).unwrap();
+        eap.set_identity("anonymous@example.org").unwrap();
+        eap.set_ca_certificate(b"-----BEGIN CERTIFICATE-----").unwrap();
+        eap.set_inner_method(EapMethod::Peap, EapMethod::MsChapV2).unwrap();
+        eap.set_mschapv2_password("pa55").unwrap();
+        assert_eq!(eap.method().unwrap(), EapMethod::Peap);
+        assert_eq!(eap.set_inner_method(EapMethod::Tls, EapMethod::MsChapV2).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+
+        let settings = driver.settings.borrow();
+        assert_eq!(settings.iter().map(|&(t, _, ref v)| (t, v.len())).collect::<Vec<_>>(), [
+            (EFI_EAP_TYPE_ATTRIBUTE, 1),
+            (EFI_EAP_TYPE_IDENTITY, 21),
+            (EFI_EAP_TYPE_EAPTLS, 27),
+            (EFI_EAP_TYPE_PEAP, 1),
+            (EFI_EAP_TYPE_MSCHAPV2, 10), // UCS-2 with a terminator
+        ]);
+    }
+}
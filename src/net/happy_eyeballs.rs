@@ -0,0 +1,128 @@
+// Happy Eyeballs (RFC 8305) for TcpStreamBuilder::happy_eyeballs(). Connections to the addresses are started one at a
+// time, a fixed delay apart or as soon as the last one fails, and the first to be made is the one we keep. The rest
+// of the RFC is about asking DNS for both families at once and starting before both have answered, which
+// lookup_host_dual_stack() doesn't do, so the race starts once all the addresses are in
+
+use ::{
+    Result,
+    EfiError,
+    EfiErrorKind,
+    system_table,
+    events::{Timer, TimerSchedule, TimerState, EventTpl, AsRawEvt},
+};
+use ffi::{EFI_EVENT, UINTN};
+use super::{SocketAddr, TcpStreamBuilder, Inner};
+use alloc::vec::{self, Vec};
+use core::time::Duration;
+
+// The order to try the addresses in: taking turns between the families starting with IPv6 (section 4), each family in
+// the order DNS gave it
+fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.iter().partition(|a| match **a {
+        SocketAddr::V6(_) => true,
+        SocketAddr::V4(_) => false,
+    });
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut ordered = Vec::with_capacity(addrs.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => {
+                ordered.extend(a);
+                ordered.extend(b);
+            }
+        }
+    }
+}
+
+// Races the connections for the addresses, as TcpStreamBuilder::happy_eyeballs() says. The losers are aborted by being
+// dropped
+pub(super) fn connect(addrs: &[SocketAddr], options: &TcpStreamBuilder, attempt_delay: Duration) -> Result<Inner> {
+    let deadline = options.retry.deadline()?;
+    let mut race = Race {
+        waiting: interleave(addrs).into_iter(),
+        attempts: Vec::new(),
+        next_attempt: Timer::create(attempt_delay, TimerSchedule::Relative, TimerState::Inactive, EventTpl::Callback)?,
+        attempt_delay,
+        last_error: EfiErrorKind::NotFound.into(),
+    };
+
+    let bs = system_table().BootServices;
+    loop {
+        if race.attempts.is_empty() && !race.start_next(options, &deadline)? {
+            return Err(race.last_error);
+        }
+
+        // The attempts, then the timer for starting the next one, then the deadline
+        let mut events: Vec<EFI_EVENT> = race.attempts.iter().map(|a| a.connect_event()).collect();
+        unsafe {
+            events.push(race.next_attempt.as_raw());
+            if let Some(ref deadline) = deadline {
+                events.push(deadline.as_raw());
+            }
+        }
+        let mut index: UINTN = 0;
+        unsafe {
+            ret_on_err!(((*bs).WaitForEvent)(events.len(), events.as_ptr(), &mut index));
+        }
+
+        if index < race.attempts.len() {
+            let mut attempt = race.attempts.remove(index);
+            match attempt.finish() {
+                Ok(()) => return Ok(attempt),
+                Err(e) => {
+                    race.last_error = e;
+                    // Don't wait out the delay for one that's already failed
+                    race.start_next(options, &deadline)?;
+                }
+            }
+        } else if index == race.attempts.len() {
+            race.start_next(options, &deadline)?;
+        } else {
+            return Err(EfiErrorKind::Timeout.into());
+        }
+    }
+}
+
+struct Race {
+    waiting: vec::IntoIter<SocketAddr>,
+    attempts: Vec<Inner>,
+    next_attempt: Timer,
+    attempt_delay: Duration,
+    last_error: EfiError,
+}
+
+impl Race {
+    // Starts a connection to the next address that one can be started for, if there's one left, and the delay before
+    // the one after
+    fn start_next(&mut self, options: &TcpStreamBuilder, deadline: &Option<Timer>) -> Result<bool> {
+        for addr in &mut self.waiting {
+            match Inner::start(addr, options, deadline) {
+                Ok(attempt) => {
+                    self.attempts.push(attempt);
+                    self.next_attempt.set(self.attempt_delay, TimerSchedule::Relative)?;
+                    return Ok(true);
+                },
+                Err(e) => self.last_error = e, // e.g. no TCP6 on this NIC, so on to the next family
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::{Ipv4Addr, Ipv6Addr, IpAddr};
+
+    #[test]
+    fn takes_turns_starting_with_ipv6() {
+        let v4 = |d| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, d)), 80);
+        let v6 = |d| SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, d)), 80);
+
+        assert_eq!(interleave(&[v4(1), v4(2), v4(3), v6(1), v6(2)]), [v6(1), v4(1), v6(2), v4(2), v4(3)]);
+        assert_eq!(interleave(&[v6(1), v4(1), v6(2), v6(3)]), [v6(1), v4(1), v6(2), v6(3)]);
+        assert_eq!(interleave(&[v4(1), v4(2)]), [v4(1), v4(2)]);
+        assert_eq!(interleave(&[]), []);
+    }
+}
@@ -0,0 +1,299 @@
+// A minimal HTTP/1.1 client. Enough to GET boot files from a web server: plain http:// only, one request per
+// connection, following redirects. Bodies are streamed, delimited by Content-Length, chunked encoding or the server
+// closing the connection.
+
+use io::{self, BufRead, BufReader, Read, Write};
+use net::{TcpStream, Url};
+use retry::RetryPolicy;
+use {Result, EfiError, EfiErrorKind};
+use alloc::{string::{String, ToString}, vec::Vec};
+
+// Following more redirects than this is assumed to be a loop
+pub const MAX_REDIRECTS: usize = 8;
+
+// Longest status or header line accepted, so a misbehaving server can't make us buffer forever
+const MAX_LINE: usize = 8 * 1024;
+
+enum Body {
+    Length(u64),
+    Chunked { left: u64, first: bool, done: bool },
+    UntilClose,
+}
+
+/// A response whose headers have been read. Reading it gives the body
+pub struct Response<S> {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Body,
+    stream: BufReader<S>,
+}
+
+impl<S: Read> Response<S> {
+    /// Reads a response's status line and headers off `stream`. `head` says whether the request was a HEAD, whose
+    /// response has no body whatever its headers claim
+    pub fn read_from(stream: S, head: bool) -> Result<Self> {
+        let mut stream = BufReader::new(stream);
+        let status_line = read_line(&mut stream)?;
+        let mut parts = status_line.splitn(3, ' ');
+        let (version, status) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        if !version.starts_with("HTTP/1.") || status.len() != 3 {
+            return Err(EfiErrorKind::ProtocolError.into());
+        }
+        let status = status.parse().map_err(|_| EfiError::from(EfiErrorKind::ProtocolError))?;
+        let reason = parts.next().unwrap_or("").to_string();
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(&mut stream)?;
+            if line.is_empty() {
+                break;
+            }
+            let colon = line.find(':').ok_or_else(|| EfiError::from(EfiErrorKind::ProtocolError))?;
+            headers.push((line[..colon].trim().to_string(), line[colon + 1..].trim().to_string()));
+        }
+
+        let mut response = Response { status, reason, headers, body: Body::UntilClose, stream };
+        response.body = if head || status / 100 == 1 || status == 204 || status == 304 {
+            Body::Length(0)
+        } else if response.header("transfer-encoding").map_or(false, |te| te.to_ascii_lowercase().contains("chunked")) {
+            Body::Chunked { left: 0, first: true, done: false }
+        } else if let Some(len) = response.header("content-length") {
+            Body::Length(len.parse().map_err(|_| EfiError::from(EfiErrorKind::ProtocolError))?)
+        } else {
+            Body::UntilClose
+        };
+        Ok(response)
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The first header with the given name, which is matched ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|&&(ref n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, ref v)| v.as_str())
+    }
+
+    /// The connection, with anything read past the headers still in the buffer. For protocols taking over from HTTP
+    /// e.g. after a 101
+    pub(crate) fn into_inner(self) -> BufReader<S> {
+        self.stream
+    }
+
+    /// The length of the body if the server said what it is
+    pub fn content_length(&self) -> Option<u64> {
+        match self.body {
+            Body::Length(_) => self.header("content-length").and_then(|len| len.parse().ok()).or(Some(0)),
+            _ => None,
+        }
+    }
+
+    // Reads the size line at the start of a chunk, and the empty line ending the previous one
+    fn next_chunk(&mut self, first: bool) -> io::Result<u64> {
+        if !first {
+            let line = read_line(&mut self.stream).map_err(|_| invalid_chunk())?;
+            if !line.is_empty() {
+                return Err(invalid_chunk());
+            }
+        }
+        let line = read_line(&mut self.stream).map_err(|_| invalid_chunk())?;
+        let size = line.split(';').next().unwrap_or("").trim(); // Chunk extensions are ignored
+        let size = u64::from_str_radix(size, 16).map_err(|_| invalid_chunk())?;
+        if size == 0 {
+            // The trailer, which is headers we don't need up to an empty line
+            while !read_line(&mut self.stream).map_err(|_| invalid_chunk())?.is_empty() {}
+        }
+        Ok(size)
+    }
+}
+
+impl<S: Read> Read for Response<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.body {
+            Body::Length(left) => {
+                if left == 0 {
+                    return Ok(0);
+                }
+                let len = (buf.len() as u64).min(left) as usize;
+                let n = self.stream.read(&mut buf[..len])?;
+                if n == 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the end of the body"));
+                }
+                self.body = Body::Length(left - n as u64);
+                Ok(n)
+            },
+            Body::Chunked { done: true, .. } => Ok(0),
+            Body::Chunked { left: 0, first, .. } => {
+                let size = self.next_chunk(first)?;
+                self.body = Body::Chunked { left: size, first: false, done: size == 0 };
+                self.read(buf)
+            },
+            Body::Chunked { left, .. } => {
+                let len = (buf.len() as u64).min(left) as usize;
+                let n = self.stream.read(&mut buf[..len])?;
+                if n == 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in the middle of a chunk"));
+                }
+                self.body = Body::Chunked { left: left - n as u64, first: false, done: false };
+                Ok(n)
+            },
+            Body::UntilClose => self.stream.read(buf),
+        }
+    }
+}
+
+/// Sends a request for `url` on an already open connection and reads the response's headers
+pub fn request<S: Read + Write>(mut stream: S, method: &str, url: &Url) -> Result<Response<S>> {
+    let host = if url.host().contains(':') { format!("[{}]", url.host()) } else { url.host().to_string() };
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    };
+    let request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: efi\r\nAccept: */*\r\nConnection: close\r\n\r\n", method, url.request_target(), host);
+    stream.write_all(request.as_bytes()).and_then(|_| stream.flush()).map_err(network_error)?;
+    Response::read_from(stream, method == "HEAD")
+}
+
+/// GETs `url`, following redirects. Responses other than 200 fail: with NotFound for 404 and 410, AccessDenied for 401
+/// and 403, NoResponse for server errors and ProtocolError for anything else
+pub fn get(url: &Url) -> Result<Response<TcpStream>> {
+    get_with(url, &RetryPolicy::never())
+}
+
+/// As get() but connecting and sending each request as often as `retry` says. Its time limit is on each connect
+pub fn get_with(url: &Url, retry: &RetryPolicy) -> Result<Response<TcpStream>> {
+    let mut url = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        if url.scheme() != "http" {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        let port = url.port_or_default().unwrap_or(80);
+        let response = retry.run(|| {
+            let mut builder = TcpStream::builder();
+            builder.retry(retry.once());
+            let stream = match url.ip() {
+                Some(ip) => builder.connect((ip, port))?,
+                None => builder.connect((url.host(), port))?,
+            };
+            request(stream, "GET", &url)
+        })?;
+        match response.status() {
+            200 => return Ok(response),
+            301 | 302 | 303 | 307 | 308 => {
+                let location = response.header("location").ok_or_else(|| EfiError::from(EfiErrorKind::ProtocolError))?;
+                url = url.join(location).map_err(|_| EfiError::from(EfiErrorKind::ProtocolError))?;
+            },
+            status => return Err(status_error(status)),
+        }
+    }
+    Err(EfiErrorKind::ProtocolError.into())
+}
+
+pub(crate) fn status_error(status: u16) -> EfiError {
+    match status {
+        404 | 410 => EfiErrorKind::NotFound.into(),
+        401 | 403 => EfiErrorKind::AccessDenied.into(),
+        500..=599 => EfiErrorKind::NoResponse.into(),
+        _ => EfiErrorKind::ProtocolError.into(),
+    }
+}
+
+pub(crate) fn network_error(e: io::Error) -> EfiError {
+    match e.kind() {
+        io::ErrorKind::TimedOut => EfiErrorKind::Timeout.into(),
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::NotConnected => EfiErrorKind::NoResponse.into(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => EfiErrorKind::ProtocolError.into(),
+        _ => EfiErrorKind::DeviceError.into(),
+    }
+}
+
+fn invalid_chunk() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid chunked encoding")
+}
+
+// A CRLF (or bare LF) terminated line without its terminator
+pub(crate) fn read_line<R: BufRead>(stream: &mut R) -> Result<String> {
+    let mut line = Vec::new();
+    stream.by_ref().take(MAX_LINE as u64).read_until(b'\n', &mut line).map_err(network_error)?;
+    if line.last() != Some(&b'\n') {
+        return Err(EfiErrorKind::ProtocolError.into()); // Too long or the connection closed part way
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| EfiErrorKind::ProtocolError.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::Cursor;
+
+    // A connection that answers with a canned response and keeps what was sent
+    struct Connection {
+        response: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl<'a> Write for &'a mut Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> Read for &'a mut Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.response.read(buf)
+        }
+    }
+
+    fn respond(response: &str) -> (Result<Vec<u8>>, String) {
+        let mut connection = Connection { response: Cursor::new(response.as_bytes().to_vec()), sent: Vec::new() };
+        let url = "http://[fe80::1]:8080/boot/vmlinuz?arch=x64".parse().unwrap();
+        let body = request(&mut connection, "GET", &url).and_then(|mut response| {
+            assert_eq!((response.status(), response.reason()), (200, "OK"));
+            let mut body = Vec::new();
+            response.read_to_end(&mut body).map_err(network_error)?;
+            Ok(body)
+        });
+        (body, String::from_utf8(connection.sent).unwrap())
+    }
+
+    #[test]
+    fn reads_bodies() {
+        let (body, sent) = respond("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello and more");
+        assert_eq!(body.unwrap(), b"hello");
+        assert!(sent.starts_with("GET /boot/vmlinuz?arch=x64 HTTP/1.1\r\nHost: [fe80::1]:8080\r\n"));
+
+        let (body, _) = respond("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n6;ext=1\r\npedia \r\nD\r\nin\r\n\r\nchunks.\r\n0\r\nExpires: never\r\n\r\n");
+        assert_eq!(body.unwrap(), b"Wikipedia in\r\n\r\nchunks.");
+
+        let (body, _) = respond("HTTP/1.0 200 OK\nServer: old\n\nuntil the end");
+        assert_eq!(body.unwrap(), b"until the end");
+    }
+
+    #[test]
+    fn rejects_broken_responses() {
+        assert_eq!(respond("SSH-2.0-OpenSSH\r\n\r\n").0.unwrap_err().kind(), EfiErrorKind::ProtocolError);
+        assert_eq!(respond("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").0.unwrap_err().kind(), EfiErrorKind::ProtocolError);
+        assert_eq!(respond("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").0.unwrap_err().kind(), EfiErrorKind::ProtocolError);
+        assert_eq!((status_error(404).kind(), status_error(503).kind()), (EfiErrorKind::NotFound, EfiErrorKind::NoResponse));
+    }
+}
@@ -0,0 +1,444 @@
+// A tiny HTTP/1.1 server, so a machine sitting in a boot menu can be looked at and told what to do by whatever is
+// provisioning it. Routes map a method and path to a handler, which gets the request with its body already read and
+// returns the whole response:
+//
+//     let chosen = Cell::new(None);
+//     let mut server = Server::new(8080);
+//     server.static_response("/", "text/html", include_bytes!("status.html"))
+//         .get("/entries", |_| Response::json(200, &entry_titles))
+//         .post("/boot/*", |request| match request.rest().parse::<usize>() {
+//             Ok(i) if i < entry_titles.len() => { chosen.set(Some(i)); Response::new(204) },
+//             _ => Response::text(404, "no such entry"),
+//         });
+//     server.serve_until(|| chosen.get().is_some())?;
+//
+// Connections are taken one at a time, each with its own passive open on the port, and closed after one request.
+// Someone connecting while a request is being answered has to try again. Plain http only: there's no TLS to do https
+// with, so anything reachable through it is reachable by anyone on the network.
+
+use io::{self, BufReader, Read, Write};
+use json::{self, FromJson, ToJson};
+use net::{TcpStream, Ipv4Addr, SocketAddrV4};
+use retry::RetryPolicy;
+use super::http::{read_line, network_error};
+use super::url::{percent_decode, form_pairs};
+use {Result, EfiErrorKind};
+use alloc::{boxed::Box, string::{String, ToString}, vec::Vec};
+use core::time::Duration;
+
+// How long serve_until() waits for a connection before asking whether to stop
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Requests with more header lines than this are refused rather than buffered
+const MAX_HEADERS: usize = 100;
+
+/// A request whose headers and body have been read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    rest: String,
+}
+
+impl Request {
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Percent-decoded, without the query
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The part of the path a route's trailing `*` matched. Empty for routes without one
+    pub fn rest(&self) -> &str {
+        &self.rest
+    }
+
+    /// The query's `key=value` pairs, decoded
+    pub fn query_pairs(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    /// The value of the first query pair with the given key
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.query.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The first header with the given name, which is matched ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body parsed as JSON, with anything that isn't UTF-8 read as U+FFFD
+    pub fn json<T: FromJson>(&self) -> json::Result<T> {
+        let text = String::from_utf8_lossy(&self.body);
+        json::from_str(&text)
+    }
+}
+
+/// What a handler answers with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// One without a body
+    pub fn new(status: u16) -> Self {
+        Response { status, headers: Vec::new(), body: Vec::new() }
+    }
+
+    pub fn bytes(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Response::new(status).with_header("Content-Type", content_type).with_body(body)
+    }
+
+    pub fn text(status: u16, body: &str) -> Self {
+        Response::bytes(status, "text/plain; charset=utf-8", body.as_bytes().to_vec())
+    }
+
+    pub fn json<T: ToJson + ?Sized>(status: u16, value: &T) -> Self {
+        Response::bytes(status, "application/json", json::to_string(value).into_bytes())
+    }
+
+    /// Adds a header. Content-Length and Connection are sent anyway and shouldn't be added
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    // The status line, headers and, unless the request was a HEAD, the body
+    fn write_to<W: Write>(&self, mut stream: W, head: bool) -> io::Result<()> {
+        let mut head_lines = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head_lines.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head_lines.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", self.body.len()));
+        stream.write_all(head_lines.as_bytes())?;
+        if !head {
+            stream.write_all(&self.body)?;
+        }
+        stream.flush()
+    }
+}
+
+struct Route<'a> {
+    method: String,
+    pattern: String,
+    handler: Box<dyn FnMut(&Request) -> Response + 'a>,
+}
+
+impl<'a> Route<'a> {
+    // What the `*` matched if the route is for `path`. A pattern without one has to match all of it
+    fn matches<'p>(&self, path: &'p str) -> Option<&'p str> {
+        if self.pattern.ends_with('*') {
+            path.strip_prefix(&self.pattern[..self.pattern.len() - 1])
+        } else if path == self.pattern {
+            Some("")
+        } else {
+            None
+        }
+    }
+}
+
+/// Answers requests on a port with the routes it's been given. Requests for paths with no route get 404, and for
+/// paths with routes for other methods 405. HEAD requests go to the GET route and get its response without the body
+pub struct Server<'a> {
+    port: u16,
+    routes: Vec<Route<'a>>,
+    max_body: usize,
+}
+
+impl<'a> Server<'a> {
+    /// Taking request bodies of up to 64KiB
+    pub fn new(port: u16) -> Self {
+        Server { port, routes: Vec::new(), max_body: 64 * 1024 }
+    }
+
+    /// Requests with bodies bigger than this get 413
+    pub fn max_body(&mut self, len: usize) -> &mut Self {
+        self.max_body = len;
+        self
+    }
+
+    /// Routes requests with `method` for `pattern` to `handler`. A pattern ending in `*` is for every path starting
+    /// with what comes before it, and Request::rest() gives the remainder. The first route added that matches is used
+    pub fn route<F: FnMut(&Request) -> Response + 'a>(&mut self, method: &str, pattern: &str, handler: F) -> &mut Self {
+        self.routes.push(Route { method: method.to_string(), pattern: pattern.to_string(), handler: Box::new(handler) });
+        self
+    }
+
+    pub fn get<F: FnMut(&Request) -> Response + 'a>(&mut self, pattern: &str, handler: F) -> &mut Self {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F: FnMut(&Request) -> Response + 'a>(&mut self, pattern: &str, handler: F) -> &mut Self {
+        self.route("POST", pattern, handler)
+    }
+
+    /// GETs for `path` get `body`
+    pub fn static_response(&mut self, path: &str, content_type: &'a str, body: &'a [u8]) -> &mut Self {
+        self.get(path, move |_| Response::bytes(200, content_type, body.to_vec()))
+    }
+
+    /// Waits for a connection and answers its request. Timeout if nobody connected within `timeout`. Errors
+    /// answering make no difference, since there's nobody to tell
+    pub fn serve_one(&mut self, timeout: Option<Duration>) -> Result<()> {
+        let stream = self.accept(timeout)?;
+        let _ = self.handle(stream);
+        Ok(())
+    }
+
+    /// Answers requests until `done` says to stop, which it's asked before each one and every second while nobody
+    /// is connecting. Only fails if connections can't be accepted at all e.g. for want of a network
+    pub fn serve_until<F: FnMut() -> bool>(&mut self, mut done: F) -> Result<()> {
+        while !done() {
+            match self.serve_one(Some(POLL_INTERVAL)) {
+                Err(ref e) if e.kind() == EfiErrorKind::Timeout => (),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads one request off `stream` and writes the response. For running the server over something other than
+    /// its own connections
+    pub fn handle<S: Read + Write>(&mut self, mut stream: S) -> Result<()> {
+        let (response, head) = match read_request(&mut stream, self.max_body) {
+            Ok(mut request) => {
+                let head = request.method == "HEAD";
+                (self.respond(&mut request), head)
+            },
+            Err(status) => (Response::text(status, reason(status)), false),
+        };
+        response.write_to(&mut stream, head).map_err(network_error)
+    }
+
+    fn accept(&self, timeout: Option<Duration>) -> Result<TcpStream> {
+        let mut builder = TcpStream::builder();
+        builder.passive().station_port(self.port);
+        if let Some(timeout) = timeout {
+            builder.retry(RetryPolicy::never().with_timeout(timeout));
+        }
+        builder.connect(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0)) // From anyone
+    }
+
+    fn respond(&mut self, request: &mut Request) -> Response {
+        let method = if request.method == "HEAD" { "GET" } else { request.method.as_str() }.to_string();
+        let mut allowed = Vec::new();
+        for route in &mut self.routes {
+            let rest = match route.matches(&request.path) {
+                Some(rest) => rest.to_string(),
+                None => continue,
+            };
+            if route.method == method {
+                request.rest = rest;
+                return (route.handler)(request);
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+        if allowed.is_empty() {
+            Response::text(404, reason(404))
+        } else {
+            Response::text(405, reason(405)).with_header("Allow", &allowed.join(", "))
+        }
+    }
+}
+
+// The request, or the status to refuse it with
+fn read_request<S: Read + Write>(stream: &mut S, max_body: usize) -> core::result::Result<Request, u16> {
+    let mut reader = BufReader::new(&mut *stream);
+    let request_line = read_line(&mut reader).map_err(|_| 400u16)?;
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) if !method.is_empty() && target.starts_with('/') => (method, target, version),
+        _ => return Err(400),
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(505);
+    }
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+    let path = percent_decode(path).map_err(|_| 400u16)?;
+    let query = form_pairs(query).map_err(|_| 400u16)?;
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(&mut reader).map_err(|_| 400u16)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(431);
+        }
+        let colon = line.find(':').ok_or(400u16)?;
+        headers.push((line[..colon].trim().to_string(), line[colon + 1..].trim().to_string()));
+    }
+    let mut request = Request { method: method.to_string(), path, query, headers, body: Vec::new(), rest: String::new() };
+
+    if request.header("transfer-encoding").is_some() {
+        return Err(501); // Chunked request bodies aren't worth it for what gets sent here
+    }
+    let len = match request.header("content-length") {
+        Some(len) => len.parse::<usize>().map_err(|_| 400u16)?,
+        None => 0,
+    };
+    if len > max_body {
+        return Err(413);
+    }
+    if len > 0 {
+        // curl waits a second for this before sending bodies of more than 1KiB
+        if request.header("expect").map(|e| e.eq_ignore_ascii_case("100-continue")) == Some(true) {
+            reader.get_mut().write_all(b"HTTP/1.1 100 Continue\r\n\r\n").map_err(|_| 400u16)?;
+        }
+        request.body = vec![0; len];
+        reader.read_exact(&mut request.body).map_err(|_| 400u16)?;
+    }
+    Ok(request)
+}
+
+// The reason phrase for a status, which is also the body of the responses the server makes up itself
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use io::Cursor;
+    use json::Value;
+
+    // A connection sending a canned request and keeping what was sent back
+    struct Connection {
+        request: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Write for &mut Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for &mut Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    fn send(server: &mut Server, request: &str) -> String {
+        let mut connection = Connection { request: Cursor::new(request.as_bytes().to_vec()), sent: Vec::new() };
+        server.handle(&mut connection).unwrap();
+        String::from_utf8(connection.sent).unwrap()
+    }
+
+    #[test]
+    fn routes_requests() {
+        let entries = ["Linux", "Windows"];
+        let chosen = Cell::new(None);
+        let mut server = Server::new(8080);
+        server.static_response("/", "text/html", b"<h1>Boot menu</h1>")
+            .get("/entries", |_| Response::json(200, &entries.iter().map(|e| e.to_string()).collect::<Vec<_>>()))
+            .post("/boot/*", |request| match request.rest().parse::<usize>() {
+                Ok(i) if i < entries.len() => {
+                    chosen.set(Some(i));
+                    Response::new(204)
+                },
+                _ => Response::text(404, "no such entry"),
+            })
+            .post("/echo", |request| match request.json::<Value>() {
+                Ok(value) => Response::json(200, &value["name"]),
+                Err(e) => Response::text(400, &format!("{}", e)),
+            })
+            .get("/files/*", |request| Response::text(200, &format!("{}|{}|{:?}|{:?}", request.path(), request.rest(),
+                request.query_param("name"), request.header("x-thing"))))
+            .max_body(32);
+
+        assert_eq!(send(&mut server, "GET / HTTP/1.1\r\nHost: x\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 18\r\nConnection: close\r\n\r\n<h1>Boot menu</h1>");
+        assert_eq!(send(&mut server, "HEAD / HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 18\r\nConnection: close\r\n\r\n");
+        assert!(send(&mut server, "GET /entries?verbose=1 HTTP/1.0\n\n").ends_with("\r\n\r\n[\"Linux\",\"Windows\"]"));
+
+        let files = send(&mut server, "GET /files/a%20b?x=1&name=two+words HTTP/1.1\r\nX-Thing:  yes \r\n\r\n");
+        assert!(files.ends_with("\r\n\r\n/files/a b|a b|Some(\"two words\")|Some(\"yes\")"));
+
+        assert!(send(&mut server, "POST /boot/5 HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert_eq!(chosen.get(), None);
+        assert!(send(&mut server, "POST /boot/1 HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert_eq!(chosen.get(), Some(1));
+
+        let echo = send(&mut server, "POST /echo HTTP/1.1\r\nContent-Length: 17\r\nExpect: 100-continue\r\n\r\n{\"name\": \"h\u{e9}\"}  ");
+        assert!(echo.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n"));
+        assert!(echo.ends_with("\r\n\r\n\"h\u{e9}\""));
+
+        let wrong_method = send(&mut server, "DELETE /boot/1 HTTP/1.1\r\n\r\n");
+        assert!(wrong_method.starts_with("HTTP/1.1 405 Method Not Allowed\r\nContent-Type: text/plain; charset=utf-8\r\nAllow: POST\r\n"));
+        assert!(send(&mut server, "GET /nowhere HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 "));
+        assert!(send(&mut server, "POST /echo HTTP/1.1\r\nContent-Length: 33\r\n\r\n").starts_with("HTTP/1.1 413 "));
+        assert!(send(&mut server, "GET /%zz HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 400 "));
+        assert!(send(&mut server, "GET / SPDY/3\r\n\r\n").starts_with("HTTP/1.1 505 "));
+        assert!(send(&mut server, "hello\r\n\r\n").starts_with("HTTP/1.1 400 "));
+    }
+}
@@ -1,9 +1,13 @@
-use {Result, boxed::EfiBox, system_table, image_handle};
-use alloc::vec::Vec;
-use core::{ptr, mem, slice};
+use {Result, EfiErrorKind, boxed::EfiBox, system_table, image_handle, component_name};
+use alloc::{string::String, vec::Vec};
+use core::{ptr, mem, slice, time::Duration};
+use events::{Timer, TimerSchedule, TimerState, EventTpl, Wait};
 use ffi::{
     EFI_HANDLE,
+    EFI_GUID,
     VOID,
+    TRUE,
+    EFI_SUCCESS,
     EFI_BUFFER_TOO_SMALL,
     ip4::{
         EFI_IP4_SERVICE_BINDING_PROTOCOL_GUID,
@@ -12,16 +16,29 @@ use ffi::{
         EFI_IP4_IPCONFIG_DATA,
         EFI_IP4_ROUTE_TABLE,
     },
-    boot_services::{EFI_LOCATE_SEARCH_TYPE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL},
+    simple_network::{EFI_SIMPLE_NETWORK_PROTOCOL, EFI_SIMPLE_NETWORK_PROTOCOL_GUID},
+    boot_services::{EFI_LOCATE_SEARCH_TYPE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL, EFI_OPEN_PROTOCOL_GET_PROTOCOL},
 };
 use net::addr::Ipv4Addr;
+use net::adapter_info::{AdapterInfo, MediaState};
 
 pub struct Interface {
+    handle: EFI_HANDLE,
     ipv4_config: EfiBox<EFI_IP4_IPCONFIG_DATA>,
     // TODO: add IPv6 config too
 }
 
 impl Interface {
+    /// The NIC's handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// What the NIC's driver calls it, e.g. "Intel(R) PRO/1000 MT Network Connection"
+    pub fn name(&self) -> Option<String> {
+        component_name::controller_name(self.handle)
+    }
+
     pub fn station_address_ipv4(&self) -> Ipv4Addr {
         self.ipv4_config.StationAddress.into()
     }
@@ -33,6 +50,112 @@ impl Interface {
     pub fn routes_ipv4(&self) -> Ipv4RouteTable {
         Ipv4RouteTable::from_raw_parts(self.ipv4_config.RouteTable, self.ipv4_config.RouteTableSize)
     }
+
+    /// The largest packet the NIC sends, not counting its media header, from the simple network protocol's mode
+    pub fn mtu(&self) -> Result<usize> {
+        let snp = open_protocol::<EFI_SIMPLE_NETWORK_PROTOCOL>(self.handle, &EFI_SIMPLE_NETWORK_PROTOCOL_GUID)?;
+        unsafe {
+            if (*snp).Mode.is_null() {
+                return Err(EfiErrorKind::Unsupported.into());
+            }
+            Ok((*(*snp).Mode).MaxPacketSize as usize)
+        }
+    }
+
+    /// What the driver says about the NIC beyond its addresses, if it says anything
+    pub fn adapter_info(&self) -> Result<AdapterInfo> {
+        AdapterInfo::new(self.handle)
+    }
+
+    /// Whether there's a cable in and a link partner on the other end of it
+    pub fn link_state(&self) -> LinkState {
+        link_state(self.handle)
+    }
+
+    /// For noticing the cable being pulled or put back: checks the link every `interval`
+    pub fn on_link_change(&self, interval: Duration) -> Result<LinkWatcher> {
+        let timer = Timer::create(interval, TimerSchedule::Periodic, TimerState::Active, EventTpl::Callback)?;
+        Ok(LinkWatcher { handle: self.handle, timer, state: self.link_state() })
+    }
+}
+
+/// Whether a NIC has link. Speed and duplex aren't here since no standard protocol reports them
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkState {
+    Up,
+    Down,
+    /// Still being negotiated
+    NotReady,
+    /// The driver can't tell
+    Unknown,
+}
+
+// The adapter information protocol's media state if the driver has it, otherwise the simple network protocol's
+// media present flag if it supports one
+fn link_state(handle: EFI_HANDLE) -> LinkState {
+    match AdapterInfo::new(handle).and_then(|info| info.media_state()) {
+        Ok(MediaState::Present) => return LinkState::Up,
+        Ok(MediaState::NoMedia) => return LinkState::Down,
+        Ok(MediaState::NotReady) => return LinkState::NotReady,
+        Err(_) => (),
+    }
+
+    let snp = match open_protocol::<EFI_SIMPLE_NETWORK_PROTOCOL>(handle, &EFI_SIMPLE_NETWORK_PROTOCOL_GUID) {
+        Ok(snp) => snp,
+        Err(_) => return LinkState::Unknown,
+    };
+    unsafe {
+        if (*snp).Mode.is_null() || (*(*snp).Mode).MediaPresentSupported != TRUE {
+            return LinkState::Unknown;
+        }
+        // Refreshes MediaPresent. Fails if the NIC hasn't been started, in which case we know nothing
+        if ((*snp).GetStatus)(snp, ptr::null_mut(), ptr::null_mut()) != EFI_SUCCESS {
+            return LinkState::Unknown;
+        }
+        if (*(*snp).Mode).MediaPresent == TRUE { LinkState::Up } else { LinkState::Down }
+    }
+}
+
+/// Reports a NIC's link going up or down, from Interface::on_link_change()
+pub struct LinkWatcher {
+    handle: EFI_HANDLE,
+    timer: Timer,
+    state: LinkState,
+}
+
+impl LinkWatcher {
+    /// The link as of the last check
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// The new state if it's changed since the last check. Doesn't block and only checks the link once an interval
+    /// has gone by, so it can be called from a busy loop
+    pub fn poll(&mut self) -> Result<Option<LinkState>> {
+        if !self.timer.is_signaled()? {
+            return Ok(None);
+        }
+        Ok(self.check())
+    }
+
+    /// Blocks until the link changes and returns the new state
+    pub fn wait(&mut self) -> Result<LinkState> {
+        loop {
+            self.timer.wait()?;
+            if let Some(state) = self.check() {
+                return Ok(state);
+            }
+        }
+    }
+
+    fn check(&mut self) -> Option<LinkState> {
+        let state = link_state(self.handle);
+        if state == self.state {
+            return None;
+        }
+        self.state = state;
+        Some(state)
+    }
 }
 
 pub struct Ipv4RouteTable<'a> { 
@@ -117,7 +240,7 @@ pub fn interfaces() -> Result<Vec<Interface>> {
         // TODO: add code to wait for IP protocol to initialize here.
         // Otherwise we get a no mapping error
         let mut data_size = 0;
-        let status = unsafe { ((*config_proto).GetData)(config_proto, &mut data_size, ptr::null_mut()) };
+        let status = unsafe { traced!(((*config_proto).GetData)(config_proto, &mut data_size, ptr::null_mut())) };
 
         if status != EFI_BUFFER_TOO_SMALL {
             return Err(status.into());
@@ -126,8 +249,20 @@ pub fn interfaces() -> Result<Vec<Interface>> {
         let config_data = unsafe { EfiBox::<EFI_IP4_IPCONFIG_DATA>::allocate(data_size)? };
         unsafe { ret_on_err!(((*config_proto).GetData)(config_proto, &mut data_size, config_data.as_raw())); }
 
-        interfaces.push(Interface { ipv4_config: config_data });
+        interfaces.push(Interface { handle: *handle, ipv4_config: config_data });
     }
 
     Ok(interfaces)
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> Result<*const T> {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL));
+    }
+    if protocol.is_null() {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    Ok(protocol)
 }
\ No newline at end of file
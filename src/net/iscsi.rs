@@ -0,0 +1,393 @@
+// iSCSI boot information. The firmware describes the initiator, NICs and targets it used or was configured to use in
+// the iSCSI Boot Firmware Table (iBFT), an ACPI table an installer can read to set up the OS to boot from the same
+// target. The initiator's name lives in EFI_ISCSI_INITIATOR_NAME_PROTOCOL, where it can also be changed.
+//
+// See the iSCSI Boot Firmware Table specification, version 1.03.
+
+use byteorder::{ByteOrder, BigEndian, LittleEndian};
+use ffi::{
+    iscsi::{EFI_ISCSI_INITIATOR_NAME_PROTOCOL, EFI_ISCSI_INITIATOR_NAME_PROTOCOL_GUID},
+    EFI_BUFFER_TOO_SMALL,
+    UINTN,
+    VOID,
+};
+use net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use acpi;
+use {Result, EfiError, EfiErrorKind, system_table, to_res};
+use core::{mem, ptr};
+use alloc::{string::String, vec::Vec};
+
+// Longest name the protocol accepts, not counting the terminating null
+pub const MAX_INITIATOR_NAME_LEN: usize = 223;
+
+const CONTROL_OFFSET: usize = 48;
+const HEADER_SIZE: usize = 6;
+
+const CONTROL_ID: u8 = 1;
+const INITIATOR_ID: u8 = 2;
+const NIC_ID: u8 = 3;
+const TARGET_ID: u8 = 4;
+
+const FLAG_VALID: u8 = 1 << 0;
+const FLAG_BOOT_SELECTED: u8 = 1 << 1;
+
+/// The iSCSI Boot Firmware Table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ibft {
+    pub initiator: Option<Initiator>,
+    pub nics: Vec<Nic>,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Initiator {
+    pub name: String,
+    pub isns_server: Option<IpAddr>,
+    pub slp_server: Option<IpAddr>,
+    pub primary_radius_server: Option<IpAddr>,
+    pub secondary_radius_server: Option<IpAddr>,
+    pub boot_selected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nic {
+    pub index: u8,
+    pub boot_selected: bool,
+    pub ip: Option<IpAddr>,
+    pub prefix_len: u8,
+    /// How the address was assigned, as in the IP_PREFIX_ORIGIN values of the IP Helper API e.g. 3 for DHCP
+    pub origin: u8,
+    pub gateway: Option<IpAddr>,
+    pub primary_dns: Option<IpAddr>,
+    pub secondary_dns: Option<IpAddr>,
+    pub dhcp_server: Option<IpAddr>,
+    /// 0 if the NIC isn't on a VLAN
+    pub vlan: u16,
+    pub mac: [u8; 6],
+    /// Bus, device and function packed as in PCI configuration addresses
+    pub pci_bdf: u16,
+    pub hostname: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub index: u8,
+    pub boot_selected: bool,
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+    pub lun: u64,
+    /// Index of the NIC the target is reached through
+    pub nic_index: u8,
+    pub name: String,
+    pub chap: Chap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chap {
+    None,
+    OneWay { name: String, secret: String },
+    Mutual { name: String, secret: String, reverse_name: String, reverse_secret: String },
+}
+
+impl Ibft {
+    /// The iBFT the firmware installed, if there is one
+    pub fn find() -> Result<Option<Self>> {
+        let table = match acpi::find_table(b"iBFT")? {
+            Some(table) => Some(table),
+            None => acpi::find_table(b"IBFT")?, // The signature older firmware uses
+        };
+        table.map(Self::parse).transpose()
+    }
+
+    /// Parses a whole table, ACPI header included. Structures that aren't marked valid are left out. Fails with
+    /// LoadError if the table is malformed
+    pub fn parse(table: &[u8]) -> Result<Self> {
+        let control = structure(table, CONTROL_OFFSET, CONTROL_ID, 18)?.ok_or_else(malformed)?;
+
+        let initiator = match structure(table, LittleEndian::read_u16(&control[8..]) as usize, INITIATOR_ID, 74)? {
+            Some(s) if s[5] & FLAG_VALID != 0 => Some(Initiator {
+                name: string(table, &s[70..])?,
+                isns_server: ip(&s[6..]),
+                slp_server: ip(&s[22..]),
+                primary_radius_server: ip(&s[38..]),
+                secondary_radius_server: ip(&s[54..]),
+                boot_selected: s[5] & FLAG_BOOT_SELECTED != 0,
+            }),
+            _ => None,
+        };
+
+        let mut nics = Vec::new();
+        let mut targets = Vec::new();
+        // NIC and target offsets come in pairs after the initiator's, two of each unless the control structure is longer
+        let mut at = 10;
+        while at + 4 <= control.len() {
+            if let Some(s) = structure(table, LittleEndian::read_u16(&control[at..]) as usize, NIC_ID, 102)? {
+                if s[5] & FLAG_VALID != 0 {
+                    nics.push(nic(table, s)?);
+                }
+            }
+            if let Some(s) = structure(table, LittleEndian::read_u16(&control[at + 2..]) as usize, TARGET_ID, 54)? {
+                if s[5] & FLAG_VALID != 0 {
+                    targets.push(target(table, s)?);
+                }
+            }
+            at += 4;
+        }
+
+        Ok(Ibft { initiator, nics, targets })
+    }
+
+    /// The target the firmware was set up to boot from, or the first target if none is marked as such
+    pub fn boot_target(&self) -> Option<&Target> {
+        self.targets.iter().find(|t| t.boot_selected).or_else(|| self.targets.first())
+    }
+
+    /// The NIC a target is reached through
+    pub fn nic_for(&self, target: &Target) -> Option<&Nic> {
+        self.nics.iter().find(|n| n.index == target.nic_index)
+    }
+}
+
+fn nic(table: &[u8], s: &[u8]) -> Result<Nic> {
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&s[90..96]);
+    Ok(Nic {
+        index: s[4],
+        boot_selected: s[5] & FLAG_BOOT_SELECTED != 0,
+        ip: ip(&s[6..]),
+        prefix_len: s[22],
+        origin: s[23],
+        gateway: ip(&s[24..]),
+        primary_dns: ip(&s[40..]),
+        secondary_dns: ip(&s[56..]),
+        dhcp_server: ip(&s[72..]),
+        vlan: LittleEndian::read_u16(&s[88..]),
+        mac,
+        pci_bdf: LittleEndian::read_u16(&s[96..]),
+        hostname: string(table, &s[98..])?,
+    })
+}
+
+fn target(table: &[u8], s: &[u8]) -> Result<Target> {
+    let chap = match s[32] {
+        0 => Chap::None,
+        1 => Chap::OneWay { name: string(table, &s[38..])?, secret: string(table, &s[42..])? },
+        2 => Chap::Mutual {
+            name: string(table, &s[38..])?,
+            secret: string(table, &s[42..])?,
+            reverse_name: string(table, &s[46..])?,
+            reverse_secret: string(table, &s[50..])?,
+        },
+        _ => return Err(malformed()),
+    };
+    Ok(Target {
+        index: s[4],
+        boot_selected: s[5] & FLAG_BOOT_SELECTED != 0,
+        ip: ip(&s[6..]),
+        port: LittleEndian::read_u16(&s[22..]),
+        lun: BigEndian::read_u64(&s[24..]), // Kept in the byte order SCSI uses
+        nic_index: s[33],
+        name: string(table, &s[34..])?,
+        chap,
+    })
+}
+
+// The structure at `offset` if it's there, checking its header. Offset 0 means the structure is absent
+fn structure(table: &[u8], offset: usize, id: u8, min_len: usize) -> Result<Option<&[u8]>> {
+    if offset == 0 {
+        return Ok(None);
+    }
+    let header = table.get(offset..offset + HEADER_SIZE).ok_or_else(malformed)?;
+    let len = LittleEndian::read_u16(&header[2..]) as usize;
+    if header[0] != id || len < min_len {
+        return Err(malformed());
+    }
+    table.get(offset..offset + len).map(Some).ok_or_else(malformed)
+}
+
+// A string given by the length and offset at the start of `field`. Strings are stored elsewhere in the table
+fn string(table: &[u8], field: &[u8]) -> Result<String> {
+    let len = LittleEndian::read_u16(field) as usize;
+    let offset = LittleEndian::read_u16(&field[2..]) as usize;
+    if len == 0 || offset == 0 {
+        return Ok(String::new());
+    }
+    let bytes = table.get(offset..offset + len).ok_or_else(malformed)?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+// Addresses are all IPv6, with IPv4 ones mapped into it, and all zeroes when there isn't one
+fn ip(field: &[u8]) -> Option<IpAddr> {
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&field[..16]);
+    if bytes == [0; 16] {
+        None
+    } else if bytes[..10] == [0; 10] && bytes[10..12] == [0xff, 0xff] {
+        Some(IpAddr::V4(Ipv4Addr::new(bytes[12], bytes[13], bytes[14], bytes[15])))
+    } else {
+        Some(IpAddr::V6(Ipv6Addr::from(bytes)))
+    }
+}
+
+fn malformed() -> EfiError {
+    EfiErrorKind::LoadError.into()
+}
+
+/// The name the firmware's iSCSI initiator goes by e.g. `iqn.1991-05.com.microsoft:host`
+pub fn initiator_name() -> Result<String> {
+    let protocol = initiator_name_protocol()?;
+    let mut size: UINTN = 0;
+    let mut buf = Vec::new();
+    unsafe {
+        let status = traced!(((*protocol).Get)(protocol, &mut size, ptr::null_mut()));
+        if status != EFI_BUFFER_TOO_SMALL {
+            to_res((), status)?;
+        }
+        buf.resize(size, 0);
+        ret_on_err!(((*protocol).Get)(protocol, &mut size, buf.as_mut_ptr() as *mut VOID));
+    }
+    buf.truncate(size);
+    if let Some(nul) = buf.iter().position(|&b| b == 0) {
+        buf.truncate(nul);
+    }
+    String::from_utf8(buf).map_err(|_| EfiErrorKind::VolumeCorrupted.into())
+}
+
+/// Changes the initiator's name. The firmware checks it's a valid iqn., eui. or naa. name and fails with
+/// InvalidParameter if it isn't
+pub fn set_initiator_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_INITIATOR_NAME_LEN || name.contains('\0') {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+    let protocol = initiator_name_protocol()?;
+    let mut buf = Vec::with_capacity(name.len() + 1);
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(0);
+    let mut size: UINTN = buf.len();
+    unsafe {
+        ret_on_err!(((*protocol).Set)(protocol, &mut size, buf.as_mut_ptr() as *mut VOID));
+    }
+    Ok(())
+}
+
+fn initiator_name_protocol() -> Result<*const EFI_ISCSI_INITIATOR_NAME_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_ISCSI_INITIATOR_NAME_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_ISCSI_INITIATOR_NAME_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+    }
+
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+
+    Ok(protocol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_HANDLE, EFI_STATUS, EFI_SUCCESS};
+    use testing::mock;
+    use core::cell::RefCell;
+    use alloc::{boxed::Box, string::ToString};
+
+    // Lays out an iBFT the way firmware does: structures then the strings they point at
+    fn build_ibft() -> Vec<u8> {
+        let mut body = vec![0u8; 512 - acpi::SDT_HEADER_SIZE];
+        let mut put = |at: usize, bytes: &[u8]| body[at - acpi::SDT_HEADER_SIZE..][..bytes.len()].copy_from_slice(bytes);
+        let header = |id, len: u16, index, flags| [id, 1, len as u8, (len >> 8) as u8, index, flags];
+        let (initiator, nic, target, strings) = (72, 152, 256, 320);
+        let v4 = |a, b, c, d| [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d];
+
+        put(48, &header(CONTROL_ID, 18, 0, 0));
+        put(56, &[initiator, 0, nic, 0, target as u8, 1, 0, 0, 0, 0]);
+        put(initiator as usize, &header(INITIATOR_ID, 74, 0, FLAG_VALID | FLAG_BOOT_SELECTED));
+        put(initiator as usize + 70, &[26, 0, strings as u8, 1]);
+        put(strings, b"iqn.2020-01.org.example:pc");
+
+        put(nic as usize, &header(NIC_ID, 102, 0, FLAG_VALID | FLAG_BOOT_SELECTED));
+        put(nic as usize + 6, &v4(10, 0, 0, 5));
+        put(nic as usize + 22, &[24, 3]);
+        put(nic as usize + 24, &v4(10, 0, 0, 1));
+        put(nic as usize + 88, &[12, 0, 0x52, 0x54, 0, 0x12, 0x34, 0x56, 0x18, 0]);
+
+        put(target, &header(TARGET_ID, 54, 0, FLAG_VALID | FLAG_BOOT_SELECTED));
+        put(target + 6, &v4(10, 0, 0, 2));
+        put(target + 22, &[0xbc, 0x0c, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0]);
+        put(target + 34, &[26, 0, (strings + 32) as u8, 1]);
+        put(target + 38, &[4, 0, (strings + 64) as u8, 1, 6, 0, (strings + 72) as u8, 1]);
+        put(strings + 32, b"iqn.2020-01.org.example:t1");
+        put(strings + 64, b"user");
+        put(strings + 72, b"secret");
+        acpi::build_table(b"iBFT", 1, &body)
+    }
+
+    #[test]
+    fn parses_ibft() {
+        let ibft = Ibft::parse(&build_ibft()).unwrap();
+        assert_eq!(ibft.initiator.as_ref().unwrap().name, "iqn.2020-01.org.example:pc");
+        assert_eq!(ibft.nics.len(), 1);
+        let nic = &ibft.nics[0];
+        assert_eq!((nic.ip, nic.prefix_len, nic.gateway, nic.primary_dns), (Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5))), 24, Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))), None));
+        assert_eq!((nic.vlan, nic.mac, nic.pci_bdf), (12, [0x52, 0x54, 0, 0x12, 0x34, 0x56], 0x18));
+
+        let target = ibft.boot_target().unwrap();
+        assert_eq!((target.ip, target.port, target.lun), (Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))), 3260, 1 << 48));
+        assert_eq!(target.name, "iqn.2020-01.org.example:t1");
+        assert_eq!(target.chap, Chap::OneWay { name: "user".to_string(), secret: "secret".to_string() });
+        assert_eq!(ibft.nic_for(target), Some(nic));
+
+        let mut broken = build_ibft();
+        broken[152] = TARGET_ID;
+        assert_eq!(Ibft::parse(&broken).unwrap_err().kind(), EfiErrorKind::LoadError);
+        broken[152] = NIC_ID;
+        broken[256 + 37] = 0xff; // The target name's offset is past the end
+        assert_eq!(Ibft::parse(&broken).unwrap_err().kind(), EfiErrorKind::LoadError);
+    }
+
+    // The protocol with the name it hands out after it, as a driver would keep its state
+    #[repr(C)]
+    struct FakeInitiator {
+        protocol: EFI_ISCSI_INITIATOR_NAME_PROTOCOL,
+        name: RefCell<Vec<u8>>,
+    }
+
+    extern "efiapi" fn get(this: *const EFI_ISCSI_INITIATOR_NAME_PROTOCOL, size: *mut UINTN, buffer: *mut VOID) -> EFI_STATUS {
+        let name = unsafe { (*(this as *const FakeInitiator)).name.borrow() };
+        unsafe {
+            if *size < name.len() {
+                *size = name.len();
+                return EFI_BUFFER_TOO_SMALL;
+            }
+            ptr::copy_nonoverlapping(name.as_ptr(), buffer as *mut u8, name.len());
+            *size = name.len();
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn set(this: *const EFI_ISCSI_INITIATOR_NAME_PROTOCOL, size: *mut UINTN, buffer: *mut VOID) -> EFI_STATUS {
+        let name = unsafe { core::slice::from_raw_parts(buffer as *const u8, *size) };
+        unsafe { *(*(this as *const FakeInitiator)).name.borrow_mut() = name.to_vec() };
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn gets_and_sets_initiator_name() {
+        mock::install();
+        let initiator: &'static FakeInitiator = Box::leak(Box::new(FakeInitiator {
+            protocol: EFI_ISCSI_INITIATOR_NAME_PROTOCOL { Get: get, Set: set },
+            name: RefCell::new(b"iqn.2020-01.org.example:pc\0".to_vec()),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        let bs = system_table().BootServices;
+        unsafe {
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_ISCSI_INITIATOR_NAME_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, initiator as *const _ as *const VOID);
+        }
+
+        assert_eq!(initiator_name().unwrap(), "iqn.2020-01.org.example:pc");
+        set_initiator_name("iqn.2020-01.org.example:installer").unwrap();
+        assert_eq!(initiator_name().unwrap(), "iqn.2020-01.org.example:installer");
+        assert_eq!(set_initiator_name("").unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
@@ -2,7 +2,22 @@ pub mod addr;
 pub mod dns;
 pub mod pxebc;
 pub mod ifconfig;
+pub mod url;
+pub mod http;
+pub mod http_server;
+pub mod websocket;
+pub mod iscsi;
+pub mod vlan;
+pub mod wifi;
+pub mod eap;
+pub mod adapter_info;
+pub mod sntp;
+pub mod syslog;
+pub mod discovery;
+pub mod telnet;
 mod parser;
+mod tcp6;
+mod happy_eyeballs;
 
 use ::{
     Result,
@@ -12,8 +27,10 @@ use ::{
     EfiErrorKind,
     to_res,
     io::{self, Read, Write},
-    events::{self, TimerSchedule, TimerState, EventTpl, Wait},
+    events::{self, TimerSchedule, TimerState, EventTpl, Wait, AsRawEvt},
+    retry::RetryPolicy,
     boot_services::locate_handles,
+    protocol_cache,
 };
 use self::pxebc::DhcpConfig;
 use ffi::{
@@ -24,6 +41,7 @@ use ffi::{
     EFI_STATUS,
     EFI_SUCCESS,
     EFI_NOT_READY,
+    EFI_ABORTED,
     EFI_IPv4_ADDRESS,
     EFI_MAC_ADDRESS,
     UINTN,
@@ -44,6 +62,7 @@ use ffi::{
         EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID,
         EFI_TCP4_PROTOCOL,
         EFI_TCP4_CONNECTION_TOKEN,
+        EFI_TCP4_LISTEN_TOKEN,
         EFI_TCP4_IO_TOKEN,
         EFI_TCP4_RECEIVE_DATA,
         EFI_TCP4_TRANSMIT_DATA,
@@ -69,59 +88,340 @@ use ffi::{
 
 use core::{ptr, mem, cmp, ops::Drop, time::Duration};
 pub use self::addr::*;
+pub use self::url::Url;
 
 // TODO: There are no timeouts anywhere (e.g. connect, read, write etc.). Add timeouts at all those places
 pub struct TcpStream {
-    tcp4_stream: Tcp4Stream,
+    inner: Inner,
 }
 
-fn for_ip4_only<A: ToSocketAddrs, F: FnMut(SocketAddrV4) -> Result<S>, S>(addr: A, mut callback: F) -> Result<S> {
-    let socket_addrs = addr.to_socket_addrs().map_err(|_| ::EfiError::from(::EfiErrorKind::DeviceError))?; // Not just doing into() on EfiErrKind because compiler wants type annotations
+// The stream for whichever family the peer's address is in
+enum Inner {
+    V4(Tcp4Stream),
+    V6(tcp6::Tcp6Stream),
+}
 
-    for addr in socket_addrs {
+impl Inner {
+    fn connect(addr: SocketAddr, options: &TcpStreamBuilder) -> Result<Self> {
+        Ok(match addr {
+            SocketAddr::V4(addr) => Inner::V4(Tcp4Stream::connect(addr, options)?),
+            SocketAddr::V6(addr) => Inner::V6(tcp6::Tcp6Stream::connect(addr, options)?),
+        })
+    }
+
+    // As connect() but only as far as sending the SYN, so several connections can be waited for at once. The
+    // connection is made when connect_event() is signaled, which finish() then checks
+    fn start(addr: SocketAddr, options: &TcpStreamBuilder, deadline: &Option<events::Timer>) -> Result<Self> {
+        Ok(match addr {
+            SocketAddr::V4(addr) => Inner::V4(Tcp4Stream::start(addr, options, deadline)?),
+            SocketAddr::V6(addr) => Inner::V6(tcp6::Tcp6Stream::start(addr, options, deadline)?),
+        })
+    }
+
+    fn connect_event(&self) -> EFI_EVENT {
+        match *self {
+            Inner::V4(ref s) => s.connect_event(),
+            Inner::V6(ref s) => s.connect_event(),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match *self {
+            Inner::V4(ref mut s) => s.finish(true),
+            Inner::V6(ref mut s) => s.finish(true),
+        }
+    }
+}
+
+// An address family there are sockets for. Everything that takes addresses goes through for_each_addr() with the
+// family its protocol speaks, so another family (i.e. IPv6) is another implementation rather than another copy of
+// each caller
+trait AddressFamily {
+    type Addr: Copy;
+
+    fn pick(addr: SocketAddr) -> Option<Self::Addr>;
+}
+
+struct Ip4;
+
+// Either family, for protocols which have sockets for both
+struct Ip;
+
+impl AddressFamily for Ip {
+    type Addr = SocketAddr;
+
+    fn pick(addr: SocketAddr) -> Option<SocketAddr> {
+        Some(addr)
+    }
+}
+
+impl AddressFamily for Ip4 {
+    type Addr = SocketAddrV4;
+
+    fn pick(addr: SocketAddr) -> Option<SocketAddrV4> {
         match addr {
-            SocketAddr::V4(addr) => {
-                match callback(addr) {
-                    Ok(s) => return Ok(s),
-                    Err(_) => continue, // TODO: ideally we should be gathering all the errors here to return at the end if no addr works
+            SocketAddr::V4(addr) => Some(addr),
+            SocketAddr::V6(_) => None,
+        }
+    }
+}
+
+// Tries `callback` on each of the addresses in family F until it works. Fails with its last error, Unsupported if all
+// the addresses were in some other family, NotFound if there weren't any and InvalidParameter if `addr` doesn't parse
+fn for_each_addr<F: AddressFamily, A: ToSocketAddrs, C: FnMut(F::Addr) -> Result<S>, S>(addr: A, mut callback: C) -> Result<S> {
+    let socket_addrs = resolve(addr)?;
+
+    let mut last_error = EfiError::from(EfiErrorKind::NotFound);
+    let mut tried = false;
+    for addr in socket_addrs {
+        match F::pick(addr) {
+            Some(addr) => match callback(addr) {
+                Ok(s) => return Ok(s),
+                Err(e) => {
+                    last_error = e;
+                    tried = true;
                 }
             },
-            SocketAddr::V6(_) => {}
+            None if !tried => last_error = EfiErrorKind::Unsupported.into(),
+            None => (),
         }
     }
+    Err(last_error)
+}
 
-    // TODO: If all Ipv4 addresses didn't work and all we got left with was ipv6,
-    // our error must say something to the effect of "Ipv6 not supported yet" 
-    Err(EfiErrorKind::DeviceError.into())
+fn resolve<A: ToSocketAddrs>(addr: A) -> Result<A::Iter> {
+    addr.to_socket_addrs().map_err(resolve_error)
+}
+
+fn resolve_error(e: io::Error) -> EfiError {
+    EfiError::from(match e.kind() {
+        io::ErrorKind::InvalidInput => EfiErrorKind::InvalidParameter,
+        _ => EfiErrorKind::DeviceError, // Couldn't resolve it
+    })
 }
 
 impl TcpStream {
     pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
-        Ok(Self {tcp4_stream: for_ip4_only(addr, |addr| Tcp4Stream::connect(addr))? })
+        Self::builder().connect(addr)
+    }
+
+    /// For connecting with something other than the defaults connect() uses
+    pub fn builder() -> TcpStreamBuilder {
+        Tcp4Stream::builder()
     }
 
     pub fn peer_addr(&self) -> Result<SocketAddr> {
-        self.tcp4_stream.peer_addr().map(|a| SocketAddr::V4(a))
+        match self.inner {
+            Inner::V4(ref s) => s.peer_addr().map(SocketAddr::V4),
+            Inner::V6(ref s) => s.peer_addr().map(SocketAddr::V6),
+        }
     }
 
     pub fn local_addr(&self) -> Result<SocketAddr> {
-        self.tcp4_stream.local_addr().map(|a| SocketAddr::V4(a))
+        match self.inner {
+            Inner::V4(ref s) => s.local_addr().map(SocketAddr::V4),
+            Inner::V6(ref s) => s.local_addr().map(SocketAddr::V6),
+        }
+    }
+
+    /// The largest packet the interface sends, not counting its media header. 1500 on most Ethernet
+    pub fn mtu(&self) -> Result<usize> {
+        match self.inner {
+            Inner::V4(ref s) => s.mtu(),
+            Inner::V6(ref s) => s.mtu(),
+        }
+    }
+
+    /// The most data that fits in one segment. Writes are sent a segment at a time and return how much was sent,
+    /// so write_all() is what sends a buffer bigger than this
+    pub fn max_segment_size(&self) -> Result<usize> {
+        match self.inner {
+            Inner::V4(ref s) => s.max_segment_size(),
+            Inner::V6(ref s) => s.max_segment_size(),
+        }
+    }
+
+    /// How long reads wait for data before failing with TimedOut. None, which is what it starts as, waits for ever
+    pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> Result<()> {
+        match self.inner {
+            Inner::V4(ref mut s) => s.set_read_timeout(dur),
+            Inner::V6(ref mut s) => s.set_read_timeout(dur),
+        }
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        match self.inner {
+            Inner::V4(ref s) => Ok(s.read_timeout()),
+            Inner::V6(ref s) => Ok(s.read_timeout()),
+        }
     }
 }
 
 impl Read for TcpStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.tcp4_stream.read(buf)
+        match self.inner {
+            Inner::V4(ref mut s) => s.read(buf),
+            Inner::V6(ref mut s) => s.read(buf),
+        }
     }
 }
 
 impl Write for TcpStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.tcp4_stream.write(buf)
+        match self.inner {
+            Inner::V4(ref mut s) => s.write(buf),
+            Inner::V6(ref mut s) => s.write(buf),
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.tcp4_stream.flush()
+        match self.inner {
+            Inner::V4(ref mut s) => s.flush(),
+            Inner::V6(ref mut s) => s.flush(),
+        }
+    }
+}
+
+/// How a TCP connection is made. Anything not set is as TcpStream::connect() has it: the address from the cached
+/// DHCP config with its router as the default gateway, a port of the firmware's choosing, a TTL of 255, a ToS of 0,
+/// an active open, a single try with no time limit and the addresses tried one after the other.
+///
+/// The station address and gateway are only for IPv4. Over IPv6 the driver always uses the address it was configured
+/// with (by stateless autoconfiguration or DHCPv6) and the TTL and ToS become the hop limit and traffic class
+#[derive(Debug, Clone)]
+pub struct TcpStreamBuilder {
+    station: Option<(Ipv4Addr, Ipv4Addr)>,
+    gateway: Option<Ipv4Addr>,
+    use_default_address: bool,
+    station_port: u16,
+    time_to_live: u8,
+    type_of_service: u8,
+    active: bool,
+    retry: RetryPolicy,
+    keep_alive: Option<(Duration, Duration, u32)>,
+    attempt_delay: Option<Duration>,
+}
+
+impl TcpStreamBuilder {
+    fn new() -> Self {
+        Self {
+            station: None,
+            gateway: None,
+            use_default_address: false,
+            station_port: 0,
+            time_to_live: 255,
+            type_of_service: 0,
+            active: true,
+            retry: RetryPolicy::never(),
+            keep_alive: None,
+            attempt_delay: None,
+        }
+    }
+
+    /// Our address and its subnet mask, rather than the DHCP config's. There's no default route unless gateway() is
+    /// set too
+    pub fn station_address(&mut self, address: Ipv4Addr, subnet_mask: Ipv4Addr) -> &mut Self {
+        self.station = Some((address, subnet_mask));
+        self
+    }
+
+    /// The router for addresses off the subnet
+    pub fn gateway(&mut self, gateway: Ipv4Addr) -> &mut Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Leaves the address and routes to the IP driver's own configuration rather than the DHCP config's. Some
+    /// firmware, e.g. Hyper-V's, never finishes configuring when asked to do this
+    pub fn use_default_address(&mut self) -> &mut Self {
+        self.use_default_address = true;
+        self
+    }
+
+    /// Our port. Zero lets the firmware pick one, which it can only do for active opens
+    pub fn station_port(&mut self, port: u16) -> &mut Self {
+        self.station_port = port;
+        self
+    }
+
+    pub fn time_to_live(&mut self, ttl: u8) -> &mut Self {
+        self.time_to_live = ttl;
+        self
+    }
+
+    pub fn type_of_service(&mut self, tos: u8) -> &mut Self {
+        self.type_of_service = tos;
+        self
+    }
+
+    /// Waits for the peer to connect to us on station_port() instead of connecting to it. The address connect() is
+    /// given is then who to accept the connection from, with an unspecified address or a zero port standing for any
+    pub fn passive(&mut self) -> &mut Self {
+        self.active = false;
+        self
+    }
+
+    /// How many times to try each address and how long each try gets, covering the wait for the IP driver to be
+    /// configured as well as the handshake. For a passive open the time limit is how long to wait for the peer
+    pub fn retry(&mut self, retry: RetryPolicy) -> &mut Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Probes a connection that's been idle for `idle` every `interval` and resets it after `probes` go unanswered,
+    /// so reads and writes fail with ConnectionReset once the peer or the cable between us has gone. Off by default
+    pub fn keep_alive(&mut self, idle: Duration, interval: Duration, probes: u32) -> &mut Self {
+        self.keep_alive = Some((idle, interval, probes));
+        self
+    }
+
+    /// Races the addresses rather than trying them in turn, as in Happy Eyeballs (RFC 8305): IPv6 and IPv4 addresses
+    /// take turns starting with IPv6, each one starting `attempt_delay` after the last or as soon as the last fails,
+    /// and the first connection made is the one we keep. RFC 8305 suggests 250ms. The retry() time limit is for the
+    /// whole race. Only for active opens. Host names are looked up for AAAA records as well as A, which connect() only
+    /// does with this set
+    pub fn happy_eyeballs(&mut self, attempt_delay: Duration) -> &mut Self {
+        self.attempt_delay = Some(attempt_delay);
+        self
+    }
+
+    /// Connects, or for a passive open accepts the connection, with the first address that works
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<TcpStream> {
+        let inner = match self.attempt_delay {
+            Some(attempt_delay) if self.active => {
+                let addrs = addr.to_dual_stack_socket_addrs().map_err(resolve_error)?;
+                self.retry.run(|| happy_eyeballs::connect(&addrs, self, attempt_delay))?
+            },
+            _ => for_each_addr::<Ip, _, _, _>(addr, |addr| self.retry.run(|| Inner::connect(addr, self)))?,
+        };
+        Ok(TcpStream { inner })
+    }
+
+    // Only needed for keep-alive. Without it the driver's defaults apply, which have keep-alive off
+    fn control_option(&self) -> Option<EFI_TCP4_OPTION> {
+        self.keep_alive.map(|(idle, interval, probes)| EFI_TCP4_OPTION {
+            KeepAliveProbes: probes,
+            KeepAliveTime: cmp::max(idle.as_secs(), 1) as UINT32, // In seconds
+            KeepAliveInterval: cmp::max(interval.as_secs(), 1) as UINT32,
+            ..EFI_TCP4_OPTION::default()
+        })
+    }
+
+    fn config_data(&self, remote: SocketAddrV4, station_address: EFI_IPv4_ADDRESS, subnet_mask: EFI_IPv4_ADDRESS, control_option: Option<&EFI_TCP4_OPTION>) -> EFI_TCP4_CONFIG_DATA {
+        EFI_TCP4_CONFIG_DATA {
+            TypeOfService: self.type_of_service,
+            TimeToLive: self.time_to_live,
+            AccessPoint: EFI_TCP4_ACCESS_POINT {
+                UseDefaultAddress: if self.use_default_address { TRUE } else { FALSE },
+                StationAddress: station_address,
+                SubnetMask: subnet_mask,
+                StationPort: self.station_port,
+                RemoteAddress: (*remote.ip()).into(),
+                RemotePort: remote.port(),
+                ActiveFlag: if self.active { TRUE } else { FALSE },
+            },
+            ControlOption: control_option.map_or(ptr::null(), |o| o as *const EFI_TCP4_OPTION),
+        }
     }
 }
 
@@ -134,15 +434,31 @@ struct Tcp4Stream {
     recv_token: EFI_TCP4_IO_TOKEN,
     send_token: EFI_TCP4_IO_TOKEN,
     close_token: EFI_TCP4_CLOSE_TOKEN,
-    is_connected: bool
+    is_connected: bool,
+    segment_size: usize,
+    read_timer: Timer,
+}
+
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const MAX_IPV4_PACKET_LEN: usize = 65535;
+
+// The IP payload that fits in one packet, given what GetModeData() says of the IP instance and the NIC underneath it
+fn ip4_payload_size(ip_mode: &EFI_IP4_MODE_DATA, snp_mode: &EFI_SIMPLE_NETWORK_MODE) -> usize {
+    match (ip_mode.MaxPacketSize as usize, snp_mode.MaxPacketSize as usize) {
+        (0, mtu) => mtu.saturating_sub(IPV4_HEADER_LEN),
+        (ip, 0) => ip,
+        (ip, mtu) => cmp::min(ip, mtu.saturating_sub(IPV4_HEADER_LEN)),
+    }
 }
 
-extern "win64" fn empty_cb(_event: EFI_EVENT, _context: *const VOID) -> EFI_STATUS {
+extern "efiapi" fn empty_cb(_event: EFI_EVENT, _context: *const VOID) -> EFI_STATUS {
     EFI_SUCCESS
 }
 
 static mut OP_DONE: bool = false;
-extern "win64" fn common_cb(_event: EFI_EVENT, _context: *const VOID) -> EFI_STATUS {
+extern "efiapi" fn common_cb(_event: EFI_EVENT, _context: *const VOID) -> EFI_STATUS {
     unsafe { OP_DONE = true };
     EFI_SUCCESS
 }
@@ -166,35 +482,54 @@ impl Tcp4Stream {
             recv_token: EFI_TCP4_IO_TOKEN::default(),
             send_token: EFI_TCP4_IO_TOKEN::default(),
             close_token: EFI_TCP4_CLOSE_TOKEN::default(),
-            is_connected: false
+            is_connected: false,
+            segment_size: 0,
+            read_timer: Timer::infinite(),
         }
     }
 
-    fn connect(addr: SocketAddrV4) -> Result<Self> {
+    fn builder() -> TcpStreamBuilder {
+        TcpStreamBuilder::new()
+    }
+
+    fn connect(addr: SocketAddrV4, options: &TcpStreamBuilder) -> Result<Self> {
+        let deadline = options.retry.deadline()?;
+        let mut stream = Self::start(addr, options, &deadline)?;
+        unsafe {
+            if options.active {
+                stream.wait_for_evt_until(&stream.connect_token.CompletionToken.Event, &deadline)?;
+            } else {
+                stream.accept(&deadline)?;
+            }
+        }
+        stream.finish(options.active)?;
+        Ok(stream)
+    }
+
+    // Everything up to the handshake: an instance configured for the connection which, for an active open, has sent
+    // the SYN and signals connect_token's event once the handshake's done
+    fn start(addr: SocketAddrV4, options: &TcpStreamBuilder, deadline: &Option<events::Timer>) -> Result<Self> {
         // TODO: this function is too ugly right now. Refactor/clean it up.
-        let ip: EFI_IPv4_ADDRESS = (*addr.ip()).into();
-        
-        let dhcp_config = pxebc::PxeBaseCodeProtocol::get_any()?
-            .ok_or_else(|| ::EfiError::from(::EfiErrorKind::DeviceError))?
-            .cached_dhcp_config()?
-            .ok_or_else(|| ::EfiError::from(::EfiErrorKind::DeviceError))?;
+        // The DHCP config gives us our address and router unless they've been given or left to the IP driver
+        let dhcp_config = if options.station.is_none() && !options.use_default_address {
+            Some(pxebc::PxeBaseCodeProtocol::get_any()?
+                .ok_or_else(|| ::EfiError::from(::EfiErrorKind::DeviceError))?
+                .cached_dhcp_config()?
+                .ok_or_else(|| ::EfiError::from(::EfiErrorKind::DeviceError))?)
+        } else {
+            None
+        };
 
-        let station_ip = if let IpAddr::V4(ip) = dhcp_config.ip() { ip.into() } else { EFI_IPv4_ADDRESS::zero() };
-        let subnet_mask = if let IpAddr::V4(ip) = dhcp_config.subnet_mask() { ip.into() } else { EFI_IPv4_ADDRESS::zero() };
-        let config_data = EFI_TCP4_CONFIG_DATA {
-            TypeOfService: 0,
-            TimeToLive: 255,
-            AccessPoint: EFI_TCP4_ACCESS_POINT {
-                UseDefaultAddress: FALSE,
-                StationAddress: station_ip, //EFI_IPv4_ADDRESS::zero(),
-                SubnetMask: subnet_mask, //EFI_IPv4_ADDRESS::zero(),
-                StationPort: 0,
-                RemoteAddress: ip,
-                RemotePort: addr.port(),
-                ActiveFlag: TRUE,
-            },
-            ControlOption: ptr::null() as *const EFI_TCP4_OPTION 
+        let (station_ip, subnet_mask) = match (options.station, &dhcp_config) {
+            (Some((ip, mask)), _) => (ip.into(), mask.into()),
+            (None, Some(dhcp_config)) => (
+                if let IpAddr::V4(ip) = dhcp_config.ip() { ip.into() } else { EFI_IPv4_ADDRESS::zero() },
+                if let IpAddr::V4(ip) = dhcp_config.subnet_mask() { ip.into() } else { EFI_IPv4_ADDRESS::zero() },
+            ),
+            (None, None) => (EFI_IPv4_ADDRESS::zero(), EFI_IPv4_ADDRESS::zero()),
         };
+        let control_option = options.control_option();
+        let config_data = options.config_data(addr, station_ip, subnet_mask, control_option.as_ref());
 
         let mut stream = Self::new();
         unsafe {
@@ -206,7 +541,7 @@ impl Tcp4Stream {
 
             // TODO: This is broken. We take only the first available protocol. Instead find the right protocol matching the requested local IP (or mac addr) 
             // just like we're doing in UDP below.
-            ret_on_err!(((*stream.bs).LocateProtocol)(&EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID, ptr::null() as *const VOID, mem::transmute(&stream.binding_protocol)));
+            stream.binding_protocol = protocol_cache::locate(&EFI_TCP4_SERVICE_BINDING_PROTOCOL_GUID)?;
 
             ret_on_err!(((*stream.binding_protocol).CreateChild)(stream.binding_protocol, &mut stream.device_handle));
 
@@ -217,15 +552,20 @@ impl Tcp4Stream {
                 ptr::null() as EFI_HANDLE,
                 EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL)); // TODO: BY_HANDLE is used for applications. Drivers should use GET. Will we ever support drivers?
         
-            let status = ((*stream.protocol).Configure)(stream.protocol, &config_data);
+            let status = traced!(((*stream.protocol).Configure)(stream.protocol, &config_data));
 
             if status == EFI_NO_MAPPING { // Wait until the IP configuration process (probably DHCP) has finished
                 let mut ip_mode_data = EFI_IP4_MODE_DATA::new();
                 loop {
-                    // TODO: This becomes an infinite loop on some firmeware such as Hyper-v
+                    // TODO: This becomes an infinite loop on some firmeware such as Hyper-v unless there's a time limit
                     // Figure out why and fix it.
                     ret_on_err!(((*stream.protocol).GetModeData)(stream.protocol, ptr::null_mut(), ptr::null_mut(), &mut ip_mode_data, ptr::null_mut(), ptr::null_mut()));
                     if ip_mode_data.IsConfigured == TRUE { break }
+                    if let Some(ref deadline) = *deadline {
+                        if deadline.is_signaled()? {
+                            return Err(EfiErrorKind::Timeout.into());
+                        }
+                    }
                 }
 
                 ret_on_err!(((*stream.protocol).Configure)(stream.protocol, &config_data));
@@ -237,14 +577,19 @@ impl Tcp4Stream {
 
         // Copy in all routes from the DHCP config
         // TODO: This is faulty. Get the dhcp config specifically of the interface we're binding on
-        let (subnet_addr, subnet_mask, gateway_addr) = form_default_route(&dhcp_config)?;
+        let route = match (options.gateway, &dhcp_config) {
+            (Some(gateway), _) => Some((Ipv4Addr::unspecified().into(), Ipv4Addr::unspecified().into(), gateway.into())),
+            (None, Some(dhcp_config)) => Some(form_default_route(dhcp_config)?),
+            (None, None) => None,
+        };
         unsafe {
-            ret_on_err!(((*stream.protocol).Routes)(stream.protocol, FALSE, &subnet_addr, &subnet_mask, &gateway_addr));
+            if let Some((subnet_addr, subnet_mask, gateway_addr)) = route {
+                ret_on_err!(((*stream.protocol).Routes)(stream.protocol, FALSE, &subnet_addr, &subnet_mask, &gateway_addr));
+            }
 
-            ret_on_err!(((*stream.protocol).Connect)(stream.protocol, &mut stream.connect_token));
-            stream.wait_for_evt(&stream.connect_token.CompletionToken.Event)?;
-            ret_on_err!(stream.connect_token.CompletionToken.Status);
-            stream.is_connected = true;
+            if options.active {
+                ret_on_err!(((*stream.protocol).Connect)(stream.protocol, &mut stream.connect_token));
+            }
         }
 
         // TODO: We should try to close all events that have been created if we're returning early
@@ -252,6 +597,47 @@ impl Tcp4Stream {
         Ok(stream)
     }
 
+    fn connect_event(&self) -> EFI_EVENT {
+        self.connect_token.CompletionToken.Event
+    }
+
+    // Once the handshake's done, or the connection's been accepted
+    fn finish(&mut self, active: bool) -> Result<()> {
+        if active {
+            ret_on_err!(self.connect_token.CompletionToken.Status);
+        }
+        self.is_connected = true;
+        self.segment_size = self.max_segment_size().unwrap_or(0);
+        Ok(())
+    }
+
+    // Waits for a connection on the listening instance then carries on with the new instance the connection is on,
+    // getting rid of the listening one
+    unsafe fn accept(&mut self, deadline: &Option<events::Timer>) -> Result<()> {
+        let mut listen_token = EFI_TCP4_LISTEN_TOKEN::default();
+        ret_on_err!(((*self.bs).CreateEvent)(EVT_NOTIFY_WAIT, TPL_CALLBACK, Some(empty_cb), ptr::null(), &mut listen_token.CompletionToken.Event));
+        let status = traced!(((*self.protocol).Accept)(self.protocol, &listen_token));
+        let res = to_res((), status)
+            .and_then(|_| self.wait_for_evt_until(&listen_token.CompletionToken.Event, deadline))
+            .and_then(|_| to_res((), listen_token.CompletionToken.Status));
+        ((*self.bs).CloseEvent)(listen_token.CompletionToken.Event);
+        res?;
+
+        let protocol = ptr::null::<EFI_TCP4_PROTOCOL>() as *mut EFI_TCP4_PROTOCOL;
+        ret_on_err!(((*self.bs).OpenProtocol)(listen_token.NewChildHandle,
+            &EFI_TCP4_PROTOCOL_GUID,
+            mem::transmute(&protocol),
+            image_handle(),
+            ptr::null() as EFI_HANDLE,
+            EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+
+        ((*self.protocol).Configure)(self.protocol, ptr::null());
+        ((*self.binding_protocol).DestroyChild)(self.binding_protocol, &mut self.device_handle);
+        self.device_handle = listen_token.NewChildHandle;
+        self.protocol = protocol;
+        Ok(())
+    }
+
     fn peer_addr(&self) -> Result<SocketAddrV4> {
         let config_data = self.get_config_data()?;
         Ok(SocketAddrV4::new(config_data.AccessPoint.RemoteAddress.into(), config_data.AccessPoint.RemotePort))
@@ -262,6 +648,33 @@ impl Tcp4Stream {
         Ok(SocketAddrV4::new(config_data.AccessPoint.StationAddress.into(), config_data.AccessPoint.StationPort))
     }
 
+    fn mode_data(&self) -> Result<(EFI_IP4_MODE_DATA, EFI_SIMPLE_NETWORK_MODE)> {
+        let mut ip_mode = EFI_IP4_MODE_DATA::new();
+        let mut snp_mode = EFI_SIMPLE_NETWORK_MODE::default();
+        unsafe {
+            ret_on_err!(((*self.protocol).GetModeData)(self.protocol, ptr::null_mut(), ptr::null_mut(), &mut ip_mode, ptr::null_mut(), &mut snp_mode));
+        }
+        Ok((ip_mode, snp_mode))
+    }
+
+    fn mtu(&self) -> Result<usize> {
+        Ok(self.mode_data()?.1.MaxPacketSize as usize)
+    }
+
+    // Leaving room for the TCP header but not its options, the same as the MSS we advertise
+    fn max_segment_size(&self) -> Result<usize> {
+        let (ip_mode, snp_mode) = self.mode_data()?;
+        Ok(ip4_payload_size(&ip_mode, &snp_mode).saturating_sub(TCP_HEADER_LEN))
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) -> Result<()> {
+        self.read_timer.set_timeout(dur)
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        self.read_timer.timeout()
+    }
+
     fn get_config_data(&self) -> Result<EFI_TCP4_CONFIG_DATA> {
         let mut config_data = EFI_TCP4_CONFIG_DATA::default();
         unsafe {
@@ -277,10 +690,27 @@ impl Tcp4Stream {
 
     unsafe fn wait_for_evt(&self, event: *const EFI_EVENT) -> Result<()> {
         let mut _index: UINTN = 0;
-        let status = ((*self.bs).WaitForEvent)(1, event, &mut _index);
+        let status = traced!(((*self.bs).WaitForEvent)(1, event, &mut _index));
         to_res((), status)
     }
 
+    // As wait_for_evt() but gives up with Timeout when the deadline passes first, aborting whatever the event was
+    // for so the firmware doesn't complete it after we've gone
+    unsafe fn wait_for_evt_until(&self, event: *const EFI_EVENT, deadline: &Option<events::Timer>) -> Result<()> {
+        let deadline = match *deadline {
+            Some(ref deadline) => deadline,
+            None => return self.wait_for_evt(event),
+        };
+        let events = [*event, deadline.as_raw()];
+        let mut index: UINTN = 0;
+        ret_on_err!(((*self.bs).WaitForEvent)(events.len(), events.as_ptr(), &mut index));
+        if index == 1 {
+            ((*self.protocol).Configure)(self.protocol, ptr::null());
+            return Err(EfiErrorKind::Timeout.into());
+        }
+        Ok(())
+    }
+
     fn read_buf(&mut self, buf: &mut [u8]) -> Result<usize> {
         let fragment_data = EFI_TCP4_FRAGMENT_DATA {
             FragmentLength: buf.len() as UINT32,
@@ -299,8 +729,20 @@ impl Tcp4Stream {
         self.recv_token.Packet.RxData =  &recv_data;
         ret_on_err!(unsafe { ((*self.protocol).Receive)(self.protocol, &self.recv_token) });
 
-        // TODO: add a read timeout. Can be done by setting a timer for the length of the timeout
+        self.read_timer.start()?;
         while !op_done() {
+            if self.read_timer.is_expired()? {
+                // Cancelling completes the token with ABORTED, unless data came in first. Either way the driver is
+                // done with the buffer once it's completed
+                unsafe { ((*self.protocol).Cancel)(self.protocol, &self.recv_token.CompletionToken) };
+                while !op_done() {
+                    ret_on_err!(unsafe { ((*self.protocol).Poll)(self.protocol) });
+                }
+                if self.recv_token.CompletionToken.Status == EFI_ABORTED {
+                    return Err(EfiErrorKind::Timeout.into());
+                }
+                break;
+            }
             ret_on_err!(unsafe { ((*self.protocol).Poll)(self.protocol) });
         }
 
@@ -308,6 +750,9 @@ impl Tcp4Stream {
     }
 
     fn write_buf(&mut self, buf: &[u8]) -> Result<usize> {
+        // A segment at a time. Zero means the driver didn't say, so leave it to that
+        let buf = if self.segment_size > 0 && buf.len() > self.segment_size { &buf[..self.segment_size] } else { buf };
+
         let fragment_data = EFI_TCP4_FRAGMENT_DATA {
             FragmentLength: buf.len() as UINT32,
             FragmentBuffer: buf.as_ptr() as *const VOID
@@ -340,56 +785,53 @@ impl Drop for Tcp4Stream {
             ((*self.bs).CloseEvent)(self.send_token.CompletionToken.Event);
             ((*self.bs).CloseEvent)(self.recv_token.CompletionToken.Event);
 
-            self.close_token.AbortOnClose = FALSE;
+            if !self.protocol.is_null() { // Null if connecting failed before there was an instance
+                self.close_token.AbortOnClose = FALSE;
 
-            ((*self.protocol).Close)(self.protocol, &self.close_token);
-            if self.is_connected { // We don't want want to wait if we weren't connected because then we end up waiting forever
-                if let Err(_) = self.wait_for_evt(&self.close_token.CompletionToken.Event) { // Blocking until the connection is closed for certain
-                     return; // Don't do anything further since we failed to close the connection safely.
+                ((*self.protocol).Close)(self.protocol, &self.close_token);
+                if self.is_connected { // We don't want want to wait if we weren't connected because then we end up waiting forever
+                    if let Err(_) = self.wait_for_evt(&self.close_token.CompletionToken.Event) { // Blocking until the connection is closed for certain
+                         return; // Don't do anything further since we failed to close the connection safely.
+                    }
                 }
-            }
 
-            // This Configure call and the comment about the bug is copied verbatim from FastBoot protocol in tianocore:
-            // Possible bug in EDK2 TCP4 driver: closing a connection doesn't remove its
-            // PCB from the list of live connections. Subsequent attempts to Configure()
-            // a TCP instance with the same local port will fail with INVALID_PARAMETER.
-            // Calling Configure with NULL is a workaround for this issue.
-            ((*self.protocol).Configure)(self.protocol, ptr::null());
+                // This Configure call and the comment about the bug is copied verbatim from FastBoot protocol in tianocore:
+                // Possible bug in EDK2 TCP4 driver: closing a connection doesn't remove its
+                // PCB from the list of live connections. Subsequent attempts to Configure()
+                // a TCP instance with the same local port will fail with INVALID_PARAMETER.
+                // Calling Configure with NULL is a workaround for this issue.
+                ((*self.protocol).Configure)(self.protocol, ptr::null());
+            }
 
             ((*self.bs).CloseEvent)(self.close_token.CompletionToken.Event);
-            ((*self.binding_protocol).DestroyChild)(self.binding_protocol, &mut self.device_handle);
+            if !self.device_handle.is_null() {
+                ((*self.binding_protocol).DestroyChild)(self.binding_protocol, &mut self.device_handle);
+            }
         }
     }
 }
 
+// What a read or write's error is to the caller
+fn tcp_io_error(e: EfiError) -> io::Error {
+    match e.kind() {
+        // Handling errors that indicate connection closed specially so the caller can retry
+        EfiErrorKind::ConnectionReset => io::ErrorKind::ConnectionReset.into(),
+        EfiErrorKind::ConnectionFin => io::ErrorKind::ConnectionAborted.into(),
+        EfiErrorKind::AccessDenied => io::ErrorKind::NotConnected.into(), // As per UEFI spec we get access denied error when the connection has been closed
+        EfiErrorKind::Timeout => io::ErrorKind::TimedOut.into(),
+        _ => io::ErrorKind::Other.into(),
+    }
+}
+
 impl Read for Tcp4Stream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.read_buf(buf).map_err(|e| {
-            match e.kind() {
-                // Handling errors that indicate connection closed specially so the caller can retry
-                EfiErrorKind::ConnectionReset => io::ErrorKind::ConnectionReset.into(),
-                EfiErrorKind::ConnectionFin => io::ErrorKind::ConnectionAborted.into(),
-                EfiErrorKind::AccessDenied => io::ErrorKind::NotConnected.into(), // As per UEFI spec we get access denied error when the connection has been closed
-                EfiErrorKind::Timeout => io::ErrorKind::TimedOut.into(),
-                _ => io::ErrorKind::Other.into(),
-            }
-        })
+        self.read_buf(buf).map_err(tcp_io_error)
     }
 }
 
 impl Write for Tcp4Stream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.write_buf(buf).map_err(|e| {
-            match e.kind() {
-                // Handling errors that indicate connection closed specially so the caller can retry
-                EfiErrorKind::ConnectionReset => io::ErrorKind::ConnectionReset.into(),
-                EfiErrorKind::ConnectionFin => io::ErrorKind::ConnectionAborted.into(),
-                EfiErrorKind::AccessDenied => io::ErrorKind::NotConnected.into(), // As per UEFI spec we get access denied error when the connection has been closed
-                EfiErrorKind::Timeout => io::ErrorKind::TimedOut.into(),
-                _ => io::ErrorKind::Other.into(),
-            }
-        })
-
+        self.write_buf(buf).map_err(tcp_io_error)
     }
 
 
@@ -406,13 +848,13 @@ pub struct UdpSocket {
 
 impl UdpSocket {
     pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
-        Ok(Self {udp4_socket: for_ip4_only(addr, |addr| Udp4Socket::bind(addr))? })
+        Ok(Self {udp4_socket: for_each_addr::<Ip4, _, _, _>(addr, Udp4Socket::bind)? })
     }
 
     // TODO: Fix this bullshit around how we're creating a new socket on every connect
     // (we're doing this because UEFI doesn't allow us to change the address of an already created UDP protocol)
     pub fn connect<A: ToSocketAddrs>(&mut self, addr: A) -> Result<()> {
-        for_ip4_only(addr, |addr| {
+        for_each_addr::<Ip4, _, _, _>(addr, |addr| {
             let bound_addr = self.udp4_socket.bound_addr;
             self.udp4_socket = Udp4Socket::bind_and_connect(bound_addr, addr)?;
             Ok(())
@@ -432,28 +874,23 @@ impl UdpSocket {
     // TODO: implement recv_from() as well
 
     // TODO: need to make self non-mut just like in the std lib
+    /// InvalidParameter if `addr` doesn't come to an IPv4 address, otherwise the last send's error
     pub fn send_to<A: ToSocketAddrs>(&mut self, buf: &[u8], addr: A) -> Result<usize> {
-        let mut last_error = EfiError::from(EfiErrorKind::InvalidParameter);
-        let socket_addrs = addr.to_socket_addrs().map_err(|_| ::EfiError::from(::EfiErrorKind::DeviceError))?; // Not just doing into() on EfiErrKind because compiler wants type annotations
-        for addr in socket_addrs {
-            if let SocketAddr::V4(addr) = addr {
-                let session_data = EFI_UDP4_SESSION_DATA{
-                    SourceAddress: Ipv4Addr::unspecified().into(), // Unspecified to use the socket's configured addr
-                    SourcePort: 0, // zero to use the socket's configured port
-                    DestinationAddress: (*addr.ip()).into(),
-                    DestinationPort: addr.port(),
-                };
-                match self.udp4_socket.send_buf(buf, Some(&session_data)) {
-                    Ok(s) => return Ok(s),
-                    Err(e) => {
-                        last_error = e;
-                        continue;
-                    },
-                };
-            }
+        let mut tried = false;
+        let sent = for_each_addr::<Ip4, _, _, _>(addr, |addr| {
+            tried = true;
+            let session_data = EFI_UDP4_SESSION_DATA{
+                SourceAddress: Ipv4Addr::unspecified().into(), // Unspecified to use the socket's configured addr
+                SourcePort: 0, // zero to use the socket's configured port
+                DestinationAddress: (*addr.ip()).into(),
+                DestinationPort: addr.port(),
+            };
+            self.udp4_socket.send_buf(buf, Some(&session_data))
+        });
+        match sent {
+            Err(_) if !tried => Err(EfiErrorKind::InvalidParameter.into()),
+            sent => sent,
         }
-
-        Err(last_error)
     }
 
     pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> Result<()> {
@@ -476,6 +913,21 @@ impl UdpSocket {
         self.udp4_socket.local_addr().map(|a| SocketAddr::V4(a))
     }
 
+    /// The largest packet the interface sends, not counting its media header. 1500 on most Ethernet
+    pub fn mtu(&self) -> Result<usize> {
+        self.udp4_socket.mtu()
+    }
+
+    /// The biggest datagram send() and send_to() take. Datagrams aren't split up since the peer would get several,
+    /// so bigger ones fail with BadBufferSize up front. Anything over max_unfragmented_size() is fragmented by IP
+    pub fn max_datagram_size(&self) -> Result<usize> {
+        self.udp4_socket.max_datagram_size()
+    }
+
+    /// The biggest datagram that goes in a single packet
+    pub fn max_unfragmented_size(&self) -> Result<usize> {
+        self.udp4_socket.max_unfragmented_size()
+    }
 }
 
 struct Timer {
@@ -536,6 +988,7 @@ struct Udp4Socket {
     read_timer: Timer,
     write_timer: Timer,
     bound_addr: SocketAddrV4, // This is the address that was passed to us to bind to. It's different from local_addr() because the OS might choose arbitrary port if 0 is passed in bound_addr
+    datagram_size: usize, // max_datagram_size() as of binding, 0 if it couldn't be had
 }
 
 impl Udp4Socket {
@@ -598,6 +1051,7 @@ impl Udp4Socket {
             read_timer: Timer::infinite(),
             write_timer: Timer::infinite(),
             bound_addr: local_addr,
+            datagram_size: 0,
         };
 
         unsafe {
@@ -629,25 +1083,25 @@ impl Udp4Socket {
         for handle in service_binding_handles {
             unsafe {
                 let binding_protocol = ptr::null::<EFI_SERVICE_BINDING_PROTOCOL>();
-                let open_binding_status = ((*socket.bs).OpenProtocol)(handle, &EFI_UDP4_SERVICE_BINDING_PROTOCOL_GUID, mem::transmute(&binding_protocol), image_handle(), ptr::null() as EFI_HANDLE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL);
+                let open_binding_status = traced!(((*socket.bs).OpenProtocol)(handle, &EFI_UDP4_SERVICE_BINDING_PROTOCOL_GUID, mem::transmute(&binding_protocol), image_handle(), ptr::null() as EFI_HANDLE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
                 if open_binding_status != EFI_SUCCESS {
                     continue;
                 }
 
                 let mut device_handle = ptr::null() as EFI_HANDLE;
-                let create_child_status = ((*binding_protocol).CreateChild)(binding_protocol, &mut device_handle);
+                let create_child_status = traced!(((*binding_protocol).CreateChild)(binding_protocol, &mut device_handle));
                 if create_child_status != EFI_SUCCESS {
                     continue;
                 }
 
                 let protocol = ptr::null::<EFI_UDP4_PROTOCOL>();
-                let open_udp_status = ((*socket.bs).OpenProtocol)(device_handle, &EFI_UDP4_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null() as EFI_HANDLE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL); // TODO: BY_HANDLE is used for applications. Drivers should use GET. Will we ever support drivers?
+                let open_udp_status = traced!(((*socket.bs).OpenProtocol)(device_handle, &EFI_UDP4_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null() as EFI_HANDLE, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL)); // TODO: BY_HANDLE is used for applications. Drivers should use GET. Will we ever support drivers?
                 if open_udp_status != EFI_SUCCESS {
                     continue;
                 }
 
                 let mut snp_mode = EFI_SIMPLE_NETWORK_MODE::default();
-                let get_mode_status = ((*protocol).GetModeData)(protocol, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut snp_mode);
+                let get_mode_status = traced!(((*protocol).GetModeData)(protocol, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut snp_mode));
                 if get_mode_status != EFI_SUCCESS {
                     continue;
                 }
@@ -666,7 +1120,7 @@ impl Udp4Socket {
         }
 
         unsafe {
-            let status = ((*socket.protocol).Configure)(socket.protocol, &config);
+            let status = traced!(((*socket.protocol).Configure)(socket.protocol, &config));
             if status == EFI_NO_MAPPING { // Wait until the IP configuration process (probably DHCP) has finished
                 let mut ip_mode_data = EFI_IP4_MODE_DATA::new();
                 loop {
@@ -688,6 +1142,7 @@ impl Udp4Socket {
         unsafe {
             ret_on_err!(((*socket.protocol).Routes)(socket.protocol, FALSE, &subnet_addr, &subnet_mask, &gateway_addr));
         }
+        socket.datagram_size = socket.max_datagram_size().unwrap_or(0);
 
         // TODO: We should try to close all events that have been created if we're returning early
 
@@ -696,7 +1151,7 @@ impl Udp4Socket {
 
     unsafe fn wait_for_evt(&self, event: *const EFI_EVENT) -> Result<()> {
         let mut _index: UINTN = 0;
-        let status = ((*self.bs).WaitForEvent)(1, event, &mut _index);
+        let status = traced!(((*self.bs).WaitForEvent)(1, event, &mut _index));
         to_res((), status)
     }
 
@@ -706,7 +1161,7 @@ impl Udp4Socket {
 
         self.read_timer.start()?;
         let read_succeeded = loop {
-            let status = unsafe { ((*self.protocol).Poll)(self.protocol) };
+            let status = unsafe { traced!(((*self.protocol).Poll)(self.protocol)) };
             if status != EFI_SUCCESS  && status != EFI_NOT_READY { // EFI_NOT_READY merely means there's not data received on the socket yet. It does not indicate any kind of failure.
                 return Err(status.into());
             }
@@ -737,6 +1192,11 @@ impl Udp4Socket {
     }
 
     fn send_buf(&mut self, buf: &[u8], session_data: Option<&EFI_UDP4_SESSION_DATA>) -> Result<usize> {
+        // Without the limit Transmit says BadBufferSize itself
+        if self.datagram_size > 0 && buf.len() > self.datagram_size {
+            return Err(EfiErrorKind::BadBufferSize.into());
+        }
+
         let fragment_data = EFI_UDP4_FRAGMENT_DATA {
             FragmentLength: buf.len() as UINT32,
             FragmentBuffer: buf.as_ptr() as *const VOID
@@ -778,6 +1238,35 @@ impl Udp4Socket {
         Ok(SocketAddrV4::new(config.StationAddress.into(), config.StationPort))
     }
 
+    fn mode_data(&self) -> Result<(EFI_UDP4_CONFIG_DATA, EFI_IP4_MODE_DATA, EFI_SIMPLE_NETWORK_MODE)> {
+        let mut config_data = EFI_UDP4_CONFIG_DATA::default();
+        let mut ip_mode = EFI_IP4_MODE_DATA::new();
+        let mut snp_mode = EFI_SIMPLE_NETWORK_MODE::default();
+        unsafe {
+            ret_on_err!(((*self.protocol).GetModeData)(self.protocol, &mut config_data, &mut ip_mode, ptr::null_mut(), &mut snp_mode));
+        }
+        Ok((config_data, ip_mode, snp_mode))
+    }
+
+    fn mtu(&self) -> Result<usize> {
+        Ok(self.mode_data()?.2.MaxPacketSize as usize)
+    }
+
+    fn max_unfragmented_size(&self) -> Result<usize> {
+        let (_, ip_mode, snp_mode) = self.mode_data()?;
+        Ok(ip4_payload_size(&ip_mode, &snp_mode).saturating_sub(UDP_HEADER_LEN))
+    }
+
+    // Datagrams IP may not fragment have to fit in a packet. The rest can be as big as an IP packet
+    fn max_datagram_size(&self) -> Result<usize> {
+        let (config_data, ip_mode, snp_mode) = self.mode_data()?;
+        if config_data.DoNotFragment == TRUE {
+            Ok(ip4_payload_size(&ip_mode, &snp_mode).saturating_sub(UDP_HEADER_LEN))
+        } else {
+            Ok(MAX_IPV4_PACKET_LEN - IPV4_HEADER_LEN - UDP_HEADER_LEN)
+        }
+    }
+
     fn get_config_data(&self) -> Result<EFI_UDP4_CONFIG_DATA> {
         let mut config_data = EFI_UDP4_CONFIG_DATA::default();
         unsafe {
@@ -858,3 +1347,29 @@ fn to_mac_addr(hw_addr: &[u8], valid_len: usize) -> EFI_MAC_ADDRESS {
     mac_addr
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_v4<A: ToSocketAddrs>(addr: A) -> Result<SocketAddrV4> {
+        for_each_addr::<Ip4, _, _, _>(addr, Ok)
+    }
+
+    #[test]
+    fn takes_addresses_like_std() {
+        let expected = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 2), 80);
+        assert_eq!(first_v4("10.0.2.2:80").unwrap(), expected);
+        assert_eq!(first_v4(("10.0.2.2", 80)).unwrap(), expected);
+        assert_eq!(first_v4(([10, 0, 2, 2], 80)).unwrap(), expected);
+        assert_eq!(first_v4(SocketAddr::from(([10, 0, 2, 2], 80))).unwrap(), expected);
+        assert_eq!(first_v4("10.0.2.2").unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        assert_eq!(first_v4(([0, 0, 0, 0, 0, 0, 0, 1], 80)).unwrap_err().kind(), EfiErrorKind::Unsupported);
+
+        // Other families are skipped, and when nothing works it's the last failure that's returned
+        let both = [SocketAddr::from(([0u8; 16], 80)), SocketAddr::from(expected)];
+        assert_eq!(first_v4(&both[..]).unwrap(), expected);
+        let failed = for_each_addr::<Ip4, _, _, ()>(&both[..], |_| Err(EfiErrorKind::Timeout.into()));
+        assert_eq!(failed.unwrap_err().kind(), EfiErrorKind::Timeout);
+    }
+}
@@ -36,6 +36,7 @@ use {
     net::{IpAddr, Ipv4Addr},
     NullTerminatedAsciiStr,
     boot_services::locate_handles,
+    retry::RetryPolicy,
 };
 
 use core::{self, mem, ptr, default::Default};
@@ -316,6 +317,12 @@ impl PxeBaseCodeProtocol {
     }
 
     pub fn run_dhcp(&self) -> Result<DhcpConfig> {
+        self.run_dhcp_with(&RetryPolicy::never())
+    }
+
+    /// As run_dhcp() but trying as often as `retry` says. The base code's Dhcp() blocks until its own timeouts run
+    /// out, so the policy's time limit can't cut a try short
+    pub fn run_dhcp_with(&self, retry: &RetryPolicy) -> Result<DhcpConfig> {
         // TODO: see tianocore-edk2\NetworkPkg\UefiPxeBcDxe\PxeBcBoot.c file to know to implement PXE sequence especially the method PxeBcDiscoverBootFile
 
         // TODO: we're using PxeBaseCodeProtocol for now for expediency,
@@ -330,7 +337,7 @@ impl PxeBaseCodeProtocol {
         }
 
         let sort_offers = false; // TODO: may want to expose this out to the caller
-        self.dhcp(sort_offers)?;
+        retry.run(|| self.dhcp(sort_offers))?;
 
         // The above code will result in the config being cached.
         // So return that
@@ -0,0 +1,195 @@
+// SNTP (RFC 4330): asking an NTP server the time. Mostly so the RTC can be put right before anything checks a
+// certificate's validity period against it, which fails when the clock's battery has gone flat or it's never been set:
+//
+//     let sample = sntp::query(("pool.ntp.org", sntp::NTP_PORT), Duration::from_secs(5))?;
+//     if sample.offset_millis.abs() > 5_000 {
+//         sample.set_clock()?;
+//     }
+
+use ::{Result, EfiErrorKind, system_table};
+use services::RuntimeServices;
+use time::{Timestamp, UnixTime};
+use super::{UdpSocket, ToSocketAddrs};
+use byteorder::{BigEndian, ByteOrder};
+use core::{cmp, time::Duration};
+
+pub const NTP_PORT: u16 = 123;
+
+const PACKET_LEN: usize = 48;
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const LEAP_ALARM: u8 = 3; // The server's clock isn't synchronized
+
+// From 1900, where NTP counts from, to 1970
+const UNIX_EPOCH_NTP_SECS: i64 = 2_208_988_800;
+// A 32 bit count of seconds from 1900 wraps in 2036. Those with the top bit clear are taken to be after that (RFC 4330
+// section 3), which works from 1968 to 2104
+const NTP_ERA_SECS: i64 = 1 << 32;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// What a server said the time was, compared with the RTC
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// How far the server's clock is ahead of the RTC. Negative when the RTC is ahead
+    pub offset_millis: i64,
+    /// How long the request and reply spent on the network, not counting the time the server took to answer
+    pub round_trip: Duration,
+    /// How many servers the server is from a reference clock. 1 means it has one of its own, such as a GPS receiver
+    pub stratum: u8,
+}
+
+impl Sample {
+    /// The RTC's time corrected by the offset
+    pub fn now(&self) -> Result<UnixTime> {
+        corrected(UnixTime::now()?, self.offset_millis)
+    }
+
+    /// Sets the RTC to now(), leaving it in the time zone it was in
+    pub fn set_clock(&self) -> Result<()> {
+        let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+        let current = rs.get_time()?;
+        let now = corrected(UnixTime::from_efi_time(&current)?, self.offset_millis)?;
+        rs.set_time(&now.to_efi_time(current.TimeZone, current.Daylight))
+    }
+}
+
+/// Asks the first of the server's addresses for the time. Timeout if it hasn't answered within `timeout` and
+/// ProtocolError if it answered with something other than the time, such as a kiss-o'-death telling us to go away
+pub fn query<A: ToSocketAddrs>(server: A, timeout: Duration) -> Result<Sample> {
+    let mut socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(timeout))?;
+
+    // The RTC only counts seconds on a lot of machines, so the time the reply took is better measured with the
+    // timestamp counter where there is one
+    let sent = unix_nanos(UnixTime::now()?);
+    let sent_at = Timestamp::now().ok();
+    socket.send_to(&request(sent), server)?;
+
+    let mut buf = [0u8; PACKET_LEN];
+    let len = socket.recv(&mut buf)?;
+    let received = match sent_at {
+        Some(sent_at) => sent + sent_at.elapsed()?.as_nanos() as i64,
+        None => cmp::max(sent, unix_nanos(UnixTime::now()?)),
+    };
+
+    let (stratum, server_received, server_sent) = parse_reply(&buf[..len], sent)?;
+    Ok(sample(sent, server_received, server_sent, received, stratum))
+}
+
+// The RTC time plus the offset, which needn't be after 1970 if the offset is nonsense
+fn corrected(time: UnixTime, offset_millis: i64) -> Result<UnixTime> {
+    let time = if offset_millis >= 0 {
+        time.checked_add(Duration::from_millis(offset_millis as u64))
+    } else {
+        time.checked_sub(Duration::from_millis(offset_millis.wrapping_neg() as u64))
+    };
+    time.ok_or_else(|| EfiErrorKind::InvalidParameter.into())
+}
+
+fn unix_nanos(time: UnixTime) -> i64 {
+    let since_epoch = time.since_epoch();
+    since_epoch.as_secs() as i64 * NANOS_PER_SEC + since_epoch.subsec_nanos() as i64
+}
+
+fn to_ntp_timestamp(unix_nanos: i64) -> u64 {
+    let secs = unix_nanos.div_euclid(NANOS_PER_SEC) + UNIX_EPOCH_NTP_SECS;
+    let fraction = (unix_nanos.rem_euclid(NANOS_PER_SEC) << 32) / NANOS_PER_SEC;
+    (secs as u64) << 32 | fraction as u64
+}
+
+fn from_ntp_timestamp(timestamp: u64) -> i64 {
+    let mut secs = (timestamp >> 32) as i64;
+    if secs & 0x8000_0000 == 0 {
+        secs += NTP_ERA_SECS;
+    }
+    let nanos = ((timestamp & 0xffff_ffff) as i64 * NANOS_PER_SEC + (1 << 31)) >> 32; // Rounded
+    (secs - UNIX_EPOCH_NTP_SECS) * NANOS_PER_SEC + nanos
+}
+
+// A client request with our time in it as the transmit timestamp, which the server sends back so its reply can be
+// matched up with the request
+fn request(sent: i64) -> [u8; PACKET_LEN] {
+    let mut packet = [0u8; PACKET_LEN];
+    packet[0] = VERSION << 3 | MODE_CLIENT;
+    BigEndian::write_u64(&mut packet[40..48], to_ntp_timestamp(sent));
+    packet
+}
+
+// The server's stratum and when it received the request and sent the reply, in nanoseconds since 1970
+fn parse_reply(reply: &[u8], sent: i64) -> Result<(u8, i64, i64)> {
+    if reply.len() < PACKET_LEN {
+        return Err(EfiErrorKind::ProtocolError.into());
+    }
+
+    let leap = reply[0] >> 6;
+    let mode = reply[0] & 0x7;
+    let stratum = reply[1];
+    let originate = BigEndian::read_u64(&reply[24..32]);
+    let receive = BigEndian::read_u64(&reply[32..40]);
+    let transmit = BigEndian::read_u64(&reply[40..48]);
+    // Stratum 0 is a kiss-o'-death
+    if mode != MODE_SERVER || stratum == 0 || leap == LEAP_ALARM || originate != to_ntp_timestamp(sent) || transmit == 0 {
+        return Err(EfiErrorKind::ProtocolError.into());
+    }
+
+    Ok((stratum, from_ntp_timestamp(receive), from_ntp_timestamp(transmit)))
+}
+
+// The offset and round trip from the four timestamps, as in RFC 4330 section 5
+fn sample(sent: i64, server_received: i64, server_sent: i64, received: i64, stratum: u8) -> Sample {
+    let offset = ((server_received - sent) + (server_sent - received)) / 2;
+    let round_trip = (received - sent) - (server_sent - server_received);
+    Sample {
+        offset_millis: offset / 1_000_000,
+        round_trip: Duration::from_nanos(cmp::max(round_trip, 0) as u64),
+        stratum,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn works_out_offset_from_reply() {
+        // 2026-10-14 12:00:00 UTC
+        let sent = 1_791_979_200 * NANOS_PER_SEC;
+        assert_eq!(from_ntp_timestamp(to_ntp_timestamp(sent)), sent);
+        assert_eq!(to_ntp_timestamp(0), (UNIX_EPOCH_NTP_SECS as u64) << 32);
+        // 2036-02-07 06:28:16, where the seconds wrap
+        assert_eq!(from_ntp_timestamp(0), (NTP_ERA_SECS - UNIX_EPOCH_NTP_SECS) * NANOS_PER_SEC);
+
+        let request = request(sent);
+        assert_eq!(request[0], 0x23);
+
+        // A server 90 seconds ahead that took 10ms to answer, with 20ms each way on the network
+        let server_received = sent + 90 * NANOS_PER_SEC + 20_000_000;
+        let server_sent = server_received + 10_000_000;
+        let received = sent + 50_000_000;
+        let mut reply = [0u8; PACKET_LEN];
+        reply[0] = VERSION << 3 | MODE_SERVER;
+        reply[1] = 2;
+        reply[24..32].copy_from_slice(&request[40..48]);
+        BigEndian::write_u64(&mut reply[32..40], to_ntp_timestamp(server_received));
+        BigEndian::write_u64(&mut reply[40..48], to_ntp_timestamp(server_sent));
+
+        let (stratum, t2, t3) = parse_reply(&reply, sent).unwrap();
+        let sample = sample(sent, t2, t3, received, stratum);
+        assert_eq!(sample.offset_millis, 90_000);
+        assert_eq!(sample.round_trip, Duration::from_millis(40));
+        assert_eq!(sample.stratum, 2);
+
+        // Not an answer to this request
+        assert!(parse_reply(&reply, sent + 1).is_err());
+        // Kiss-o'-death
+        reply[1] = 0;
+        assert!(parse_reply(&reply, sent).is_err());
+        reply[1] = 2;
+        // Unsynchronized
+        reply[0] |= LEAP_ALARM << 6;
+        assert!(parse_reply(&reply, sent).is_err());
+        assert!(parse_reply(&reply[..PACKET_LEN - 1], sent).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+// Logging to a syslog collector: RFC 5424 messages over UDP (RFC 5426), so machines booting off the network can
+// send what happened before the OS to the same place as everything else rather than to a serial console nobody is
+// watching. Like NvramLog each line written is a message, so it can be written to with `writeln!`:
+//
+//     let mut log = Syslog::new(("10.0.0.1", syslog::SYSLOG_PORT))?;
+//     log.set_app_name("loader");
+//     writeln!(log, "fetching {}", url)?;
+//     log.log(Severity::Error, "no kernel found")?;
+//
+// UDP is fire and forget, so messages can be lost and a collector that isn't there isn't noticed.
+
+use Result;
+use ffi::EFI_UNSPECIFIED_TIMEZONE;
+use io;
+use time::UnixTime;
+use super::{UdpSocket, ToSocketAddrs};
+use alloc::{string::String, vec::Vec};
+use core::{fmt::Write, mem};
+
+pub const SYSLOG_PORT: u16 = 514;
+
+// RFC 5426 has collectors take at least 2048 bytes over IPv6 and 480 over IPv4, but they all take 2048 in practice
+const MAX_MESSAGE_LEN: usize = 2048;
+// What RFC 5424 allows in the header fields
+const MAX_HOSTNAME_LEN: usize = 255;
+const MAX_APP_NAME_LEN: usize = 48;
+
+/// How much a message matters. Lower numbers matter more
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Emergency = 0,
+    Alert = 1,
+    Critical = 2,
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Informational = 6,
+    Debug = 7,
+}
+
+/// What sort of program is logging. Local0 to Local7 are for whatever the site wants them for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Facility {
+    Kernel = 0,
+    User = 1,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// Sends messages to a collector. Anything not set is as new() has it: the user facility, Informational for lines
+/// written with io::Write, our IP address as the host name and no app name
+pub struct Syslog {
+    socket: UdpSocket,
+    facility: Facility,
+    severity: Severity,
+    hostname: String,
+    app_name: String,
+    line: Vec<u8>,
+}
+
+impl Syslog {
+    /// Logging to the first IPv4 address of `collector`
+    pub fn new<A: ToSocketAddrs>(collector: A) -> Result<Self> {
+        let mut socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(collector)?;
+        let hostname = match socket.local_addr() {
+            Ok(addr) if !addr.ip().is_unspecified() => format!("{}", addr.ip()),
+            _ => String::new(),
+        };
+        Ok(Syslog { socket, facility: Facility::User, severity: Severity::Informational, hostname, app_name: String::new(), line: Vec::new() })
+    }
+
+    pub fn set_facility(&mut self, facility: Facility) -> &mut Self {
+        self.facility = facility;
+        self
+    }
+
+    /// The severity of lines written with io::Write
+    pub fn set_severity(&mut self, severity: Severity) -> &mut Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Ours, which the collector files the messages under. Cut short at 255 characters
+    pub fn set_hostname(&mut self, hostname: &str) -> &mut Self {
+        self.hostname = header_field(hostname, MAX_HOSTNAME_LEN);
+        self
+    }
+
+    /// Cut short at 48 characters
+    pub fn set_app_name(&mut self, app_name: &str) -> &mut Self {
+        self.app_name = header_field(app_name, MAX_APP_NAME_LEN);
+        self
+    }
+
+    /// Sends one message, cut short if it doesn't fit in a datagram. It's stamped with the RTC's time unless the RTC
+    /// can't say
+    pub fn log(&mut self, severity: Severity, message: &str) -> Result<()> {
+        let message = format_message(self.facility, severity, UnixTime::now().ok(), &self.hostname, &self.app_name, message);
+        self.socket.send(message.as_bytes())?;
+        Ok(())
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let line = mem::take(&mut self.line);
+        let line = String::from_utf8_lossy(&line);
+        let severity = self.severity;
+        self.log(severity, line.trim_end_matches('\r')).map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to send to the syslog collector"))
+    }
+}
+
+impl io::Write for Syslog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            if b == b'\n' {
+                self.send()?;
+            } else {
+                self.line.push(b);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.line.is_empty() { Ok(()) } else { self.send() }
+    }
+}
+
+impl Drop for Syslog {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+// Header fields are printable ASCII without spaces, cut short at `max_len`
+fn header_field(value: &str, max_len: usize) -> String {
+    value.chars().filter(|c| c.is_ascii_graphic()).take(max_len).collect()
+}
+
+// <PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG, with a dash for anything we don't have
+fn format_message(facility: Facility, severity: Severity, time: Option<UnixTime>, hostname: &str, app_name: &str, message: &str) -> String {
+    let mut formatted = format!("<{}>1 ", facility as u8 * 8 + severity as u8);
+    match time {
+        Some(time) => {
+            let t = time.to_efi_time(EFI_UNSPECIFIED_TIMEZONE as i16, 0);
+            let _ = write!(formatted, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", t.Year, t.Month, t.Day, t.Hour, t.Minute, t.Second,
+                t.Nanosecond / 1_000_000);
+        },
+        None => formatted.push('-'),
+    }
+    for field in [hostname, app_name].iter() {
+        formatted.push(' ');
+        formatted.push_str(if field.is_empty() { "-" } else { field });
+    }
+    formatted.push_str(" - - - "); // No process ID, message ID or structured data
+
+    let mut end = message.len().min(MAX_MESSAGE_LEN.saturating_sub(formatted.len()));
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    formatted.push_str(&message[..end]);
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+
+    #[test]
+    fn formats_rfc5424_messages() {
+        // 2026-10-14 12:34:56.789 UTC
+        let time = UnixTime::from_since_epoch(Duration::new(1_791_981_296, 789_000_000));
+        assert_eq!(format_message(Facility::Local3, Severity::Error, Some(time), "10.0.0.5", "loader", "no kernel"),
+            "<155>1 2026-10-14T12:34:56.789Z 10.0.0.5 loader - - - no kernel");
+        assert_eq!(format_message(Facility::User, Severity::Informational, None, "", "", "hello"),
+            "<14>1 - - - - - - hello");
+
+        assert_eq!(header_field("my host\tname", MAX_HOSTNAME_LEN), "myhostname");
+        assert_eq!(header_field(&"a".repeat(60), MAX_APP_NAME_LEN).len(), MAX_APP_NAME_LEN);
+
+        // Cut to fit a datagram, on a character boundary
+        let long = format_message(Facility::User, Severity::Debug, None, "", "", &"é".repeat(MAX_MESSAGE_LEN));
+        assert!(long.len() <= MAX_MESSAGE_LEN && long.len() >= MAX_MESSAGE_LEN - 1);
+    }
+}
@@ -0,0 +1,382 @@
+// TCP over IPv6. The same as Tcp4Stream apart from the addresses, except that there's nothing to configure beyond
+// them: the IP6 driver has its address and routes from autoconfiguration or DHCPv6 and TCP6 always uses those
+
+use ::{
+    Result,
+    system_table,
+    image_handle,
+    EfiErrorKind,
+    to_res,
+    io::{self, Read, Write},
+    events::{self, Wait, AsRawEvt},
+    protocol_cache,
+};
+use ffi::{
+    TRUE,
+    FALSE,
+    EFI_EVENT,
+    EFI_HANDLE,
+    EFI_IPv6_ADDRESS,
+    UINTN,
+    UINT32,
+    VOID,
+    EFI_SERVICE_BINDING_PROTOCOL,
+    EFI_NO_MAPPING,
+    EFI_ABORTED,
+    boot_services::{
+        EFI_BOOT_SERVICES,
+        EVT_NOTIFY_WAIT,
+        EVT_NOTIFY_SIGNAL,
+        TPL_CALLBACK,
+        TPL_NOTIFY,
+        EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    },
+    tcp6::{
+        EFI_TCP6_PROTOCOL_GUID,
+        EFI_TCP6_SERVICE_BINDING_PROTOCOL_GUID,
+        EFI_TCP6_PROTOCOL,
+        EFI_TCP6_CONNECTION_TOKEN,
+        EFI_TCP6_LISTEN_TOKEN,
+        EFI_TCP6_IO_TOKEN,
+        EFI_TCP6_RECEIVE_DATA,
+        EFI_TCP6_TRANSMIT_DATA,
+        EFI_TCP6_CLOSE_TOKEN,
+        EFI_TCP6_CONFIG_DATA,
+        EFI_TCP6_ACCESS_POINT,
+        EFI_TCP6_OPTION,
+        EFI_TCP6_FRAGMENT_DATA,
+    },
+    simple_network::EFI_SIMPLE_NETWORK_MODE,
+};
+use super::{
+    SocketAddrV6,
+    TcpStreamBuilder,
+    TCP_HEADER_LEN,
+    empty_cb,
+    common_cb,
+    reset_op_done,
+    op_done,
+    tcp_io_error,
+    Timer,
+};
+
+use core::{ptr, mem, ops::Drop, time::Duration};
+
+const IPV6_HEADER_LEN: usize = 40;
+
+// How long to leave the IP6 driver between tries at configuring while it's still getting an address
+const NO_MAPPING_POLL_MICROS: UINTN = 100_000;
+
+pub(super) struct Tcp6Stream {
+    bs: *mut EFI_BOOT_SERVICES,
+    binding_protocol: *const EFI_SERVICE_BINDING_PROTOCOL,
+    device_handle: EFI_HANDLE,
+    protocol: *mut EFI_TCP6_PROTOCOL,
+    connect_token: EFI_TCP6_CONNECTION_TOKEN,
+    recv_token: EFI_TCP6_IO_TOKEN,
+    send_token: EFI_TCP6_IO_TOKEN,
+    close_token: EFI_TCP6_CLOSE_TOKEN,
+    is_connected: bool,
+    segment_size: usize,
+    read_timer: Timer,
+}
+
+fn config_data(options: &TcpStreamBuilder, remote: SocketAddrV6, control_option: Option<&EFI_TCP6_OPTION>) -> EFI_TCP6_CONFIG_DATA {
+    EFI_TCP6_CONFIG_DATA {
+        TrafficClass: options.type_of_service,
+        HopLimit: options.time_to_live,
+        AccessPoint: EFI_TCP6_ACCESS_POINT {
+            StationAddress: EFI_IPv6_ADDRESS::zero(), // Whichever address the driver has that can reach the peer
+            StationPort: options.station_port,
+            RemoteAddress: (*remote.ip()).into(),
+            RemotePort: remote.port(),
+            ActiveFlag: if options.active { TRUE } else { FALSE },
+        },
+        ControlOption: control_option.map_or(ptr::null(), |o| o as *const EFI_TCP6_OPTION),
+    }
+}
+
+impl Tcp6Stream {
+    fn new() -> Self {
+        Self {
+            bs: system_table().BootServices,
+            binding_protocol: ptr::null(),
+            device_handle: ptr::null() as EFI_HANDLE,
+            protocol: ptr::null::<EFI_TCP6_PROTOCOL>() as *mut EFI_TCP6_PROTOCOL,
+            connect_token: EFI_TCP6_CONNECTION_TOKEN::default(),
+            recv_token: EFI_TCP6_IO_TOKEN::default(),
+            send_token: EFI_TCP6_IO_TOKEN::default(),
+            close_token: EFI_TCP6_CLOSE_TOKEN::default(),
+            is_connected: false,
+            segment_size: 0,
+            read_timer: Timer::infinite(),
+        }
+    }
+
+    pub(super) fn connect(addr: SocketAddrV6, options: &TcpStreamBuilder) -> Result<Self> {
+        let deadline = options.retry.deadline()?;
+        let mut stream = Self::start(addr, options, &deadline)?;
+        unsafe {
+            if options.active {
+                stream.wait_for_evt_until(&stream.connect_token.CompletionToken.Event, &deadline)?;
+            } else {
+                stream.accept(&deadline)?;
+            }
+        }
+        stream.finish(options.active)?;
+        Ok(stream)
+    }
+
+    // As Tcp4Stream::start()
+    pub(super) fn start(addr: SocketAddrV6, options: &TcpStreamBuilder, deadline: &Option<events::Timer>) -> Result<Self> {
+        let control_option = options.control_option();
+        let config_data = config_data(options, addr, control_option.as_ref());
+
+        let mut stream = Self::new();
+        unsafe {
+            ret_on_err!(((*stream.bs).CreateEvent)(EVT_NOTIFY_WAIT, TPL_CALLBACK, Some(empty_cb), ptr::null(), &mut stream.connect_token.CompletionToken.Event));
+            ret_on_err!(((*stream.bs).CreateEvent)(EVT_NOTIFY_WAIT, TPL_CALLBACK, Some(empty_cb), ptr::null(), &mut stream.send_token.CompletionToken.Event));
+            ret_on_err!(((*stream.bs).CreateEvent)(EVT_NOTIFY_SIGNAL, TPL_NOTIFY, Some(common_cb), ptr::null(), &mut stream.recv_token.CompletionToken.Event));
+            ret_on_err!(((*stream.bs).CreateEvent)(EVT_NOTIFY_WAIT, TPL_CALLBACK, Some(empty_cb), ptr::null(), &mut stream.close_token.CompletionToken.Event));
+
+            // TODO: Like TCP4 this takes the first NIC there is rather than the one with a route to the peer
+            stream.binding_protocol = protocol_cache::locate(&EFI_TCP6_SERVICE_BINDING_PROTOCOL_GUID)?;
+
+            ret_on_err!(((*stream.binding_protocol).CreateChild)(stream.binding_protocol, &mut stream.device_handle));
+
+            ret_on_err!(((*stream.bs).OpenProtocol)(stream.device_handle,
+                &EFI_TCP6_PROTOCOL_GUID,
+                mem::transmute(&stream.protocol),
+                image_handle(),
+                ptr::null() as EFI_HANDLE,
+                EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+
+            // No mapping until the IP6 driver has an address, which takes a while after startup because of duplicate
+            // address detection. There's no IP6 mode data to watch so just try again
+            loop {
+                let status = traced!(((*stream.protocol).Configure)(stream.protocol, &config_data));
+                if status != EFI_NO_MAPPING {
+                    ret_on_err!(status);
+                    break;
+                }
+                if let Some(ref deadline) = *deadline {
+                    if deadline.is_signaled()? {
+                        return Err(EfiErrorKind::Timeout.into());
+                    }
+                }
+                ((*stream.bs).Stall)(NO_MAPPING_POLL_MICROS);
+            }
+
+            if options.active {
+                ret_on_err!(((*stream.protocol).Connect)(stream.protocol, &mut stream.connect_token));
+            }
+        }
+
+        Ok(stream)
+    }
+
+    pub(super) fn connect_event(&self) -> EFI_EVENT {
+        self.connect_token.CompletionToken.Event
+    }
+
+    pub(super) fn finish(&mut self, active: bool) -> Result<()> {
+        if active {
+            ret_on_err!(self.connect_token.CompletionToken.Status);
+        }
+        self.is_connected = true;
+        self.segment_size = self.max_segment_size().unwrap_or(0);
+        Ok(())
+    }
+
+    // As Tcp4Stream::accept()
+    unsafe fn accept(&mut self, deadline: &Option<events::Timer>) -> Result<()> {
+        let mut listen_token = EFI_TCP6_LISTEN_TOKEN::default();
+        ret_on_err!(((*self.bs).CreateEvent)(EVT_NOTIFY_WAIT, TPL_CALLBACK, Some(empty_cb), ptr::null(), &mut listen_token.CompletionToken.Event));
+        let status = traced!(((*self.protocol).Accept)(self.protocol, &listen_token));
+        let res = to_res((), status)
+            .and_then(|_| self.wait_for_evt_until(&listen_token.CompletionToken.Event, deadline))
+            .and_then(|_| to_res((), listen_token.CompletionToken.Status));
+        ((*self.bs).CloseEvent)(listen_token.CompletionToken.Event);
+        res?;
+
+        let protocol = ptr::null::<EFI_TCP6_PROTOCOL>() as *mut EFI_TCP6_PROTOCOL;
+        ret_on_err!(((*self.bs).OpenProtocol)(listen_token.NewChildHandle,
+            &EFI_TCP6_PROTOCOL_GUID,
+            mem::transmute(&protocol),
+            image_handle(),
+            ptr::null() as EFI_HANDLE,
+            EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+
+        ((*self.protocol).Configure)(self.protocol, ptr::null());
+        ((*self.binding_protocol).DestroyChild)(self.binding_protocol, &mut self.device_handle);
+        self.device_handle = listen_token.NewChildHandle;
+        self.protocol = protocol;
+        Ok(())
+    }
+
+    pub(super) fn peer_addr(&self) -> Result<SocketAddrV6> {
+        let config_data = self.get_config_data()?;
+        Ok(SocketAddrV6::new(config_data.AccessPoint.RemoteAddress.into(), config_data.AccessPoint.RemotePort))
+    }
+
+    pub(super) fn local_addr(&self) -> Result<SocketAddrV6> {
+        let config_data = self.get_config_data()?;
+        Ok(SocketAddrV6::new(config_data.AccessPoint.StationAddress.into(), config_data.AccessPoint.StationPort))
+    }
+
+    pub(super) fn mtu(&self) -> Result<usize> {
+        let mut snp_mode = EFI_SIMPLE_NETWORK_MODE::default();
+        unsafe {
+            ret_on_err!(((*self.protocol).GetModeData)(self.protocol, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut snp_mode));
+        }
+        Ok(snp_mode.MaxPacketSize as usize)
+    }
+
+    // IPv6 doesn't let routers fragment, so unlike IPv4 there's nothing to go on but the link's MTU
+    pub(super) fn max_segment_size(&self) -> Result<usize> {
+        Ok(self.mtu()?.saturating_sub(IPV6_HEADER_LEN + TCP_HEADER_LEN))
+    }
+
+    pub(super) fn set_read_timeout(&mut self, dur: Option<Duration>) -> Result<()> {
+        self.read_timer.set_timeout(dur)
+    }
+
+    pub(super) fn read_timeout(&self) -> Option<Duration> {
+        self.read_timer.timeout()
+    }
+
+    fn get_config_data(&self) -> Result<EFI_TCP6_CONFIG_DATA> {
+        let mut config_data = EFI_TCP6_CONFIG_DATA::default();
+        unsafe {
+            ret_on_err!(((*self.protocol).GetModeData)(self.protocol,
+                ptr::null_mut(),
+                &mut config_data,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut()));
+        }
+        Ok(config_data)
+    }
+
+    unsafe fn wait_for_evt(&self, event: *const EFI_EVENT) -> Result<()> {
+        let mut _index: UINTN = 0;
+        let status = traced!(((*self.bs).WaitForEvent)(1, event, &mut _index));
+        to_res((), status)
+    }
+
+    unsafe fn wait_for_evt_until(&self, event: *const EFI_EVENT, deadline: &Option<events::Timer>) -> Result<()> {
+        let deadline = match *deadline {
+            Some(ref deadline) => deadline,
+            None => return self.wait_for_evt(event),
+        };
+        let events = [*event, deadline.as_raw()];
+        let mut index: UINTN = 0;
+        ret_on_err!(((*self.bs).WaitForEvent)(events.len(), events.as_ptr(), &mut index));
+        if index == 1 {
+            ((*self.protocol).Configure)(self.protocol, ptr::null());
+            return Err(EfiErrorKind::Timeout.into());
+        }
+        Ok(())
+    }
+
+    fn read_buf(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let fragment_data = EFI_TCP6_FRAGMENT_DATA {
+            FragmentLength: buf.len() as UINT32,
+            FragmentBuffer: buf.as_ptr() as *const VOID
+        };
+
+        let recv_data = EFI_TCP6_RECEIVE_DATA {
+            UrgentFlag: FALSE,
+            DataLength: buf.len() as UINT32,
+            FragmentCount: 1,
+            FragmentTable: [fragment_data]
+        };
+
+        reset_op_done();
+        self.recv_token.Packet.RxData = &recv_data;
+        ret_on_err!(unsafe { ((*self.protocol).Receive)(self.protocol, &self.recv_token) });
+
+        self.read_timer.start()?;
+        while !op_done() {
+            if self.read_timer.is_expired()? {
+                // Cancelling completes the token with ABORTED, unless data came in first. Either way the driver is
+                // done with the buffer once it's completed
+                unsafe { ((*self.protocol).Cancel)(self.protocol, &self.recv_token.CompletionToken) };
+                while !op_done() {
+                    ret_on_err!(unsafe { ((*self.protocol).Poll)(self.protocol) });
+                }
+                if self.recv_token.CompletionToken.Status == EFI_ABORTED {
+                    return Err(EfiErrorKind::Timeout.into());
+                }
+                break;
+            }
+            ret_on_err!(unsafe { ((*self.protocol).Poll)(self.protocol) });
+        }
+
+        to_res(recv_data.DataLength as usize, self.recv_token.CompletionToken.Status)
+    }
+
+    fn write_buf(&mut self, buf: &[u8]) -> Result<usize> {
+        let buf = if self.segment_size > 0 && buf.len() > self.segment_size { &buf[..self.segment_size] } else { buf };
+
+        let fragment_data = EFI_TCP6_FRAGMENT_DATA {
+            FragmentLength: buf.len() as UINT32,
+            FragmentBuffer: buf.as_ptr() as *const VOID
+        };
+
+        let send_data = EFI_TCP6_TRANSMIT_DATA {
+            Push: FALSE,
+            Urgent: FALSE,
+            DataLength: buf.len() as UINT32,
+            FragmentCount: 1,
+            FragmentTable: [fragment_data]
+        };
+
+        self.send_token.Packet.TxData = &send_data;
+        ret_on_err!(unsafe { ((*self.protocol).Transmit)(self.protocol, &self.send_token) });
+        unsafe { self.wait_for_evt(&self.send_token.CompletionToken.Event)? };
+        to_res(buf.len(), self.send_token.CompletionToken.Status)
+    }
+}
+
+impl Drop for Tcp6Stream {
+    fn drop(&mut self) {
+        unsafe {
+            ((*self.bs).CloseEvent)(self.connect_token.CompletionToken.Event);
+            ((*self.bs).CloseEvent)(self.send_token.CompletionToken.Event);
+            ((*self.bs).CloseEvent)(self.recv_token.CompletionToken.Event);
+
+            if !self.protocol.is_null() {
+                self.close_token.AbortOnClose = FALSE;
+                ((*self.protocol).Close)(self.protocol, &self.close_token);
+                if self.is_connected && self.wait_for_evt(&self.close_token.CompletionToken.Event).is_err() {
+                    return; // As TCP4, leaving it be when it won't close
+                }
+
+                // The same workaround as for TCP4, since EDK2's TCP6 is the same driver
+                ((*self.protocol).Configure)(self.protocol, ptr::null());
+            }
+
+            ((*self.bs).CloseEvent)(self.close_token.CompletionToken.Event);
+            if !self.device_handle.is_null() {
+                ((*self.binding_protocol).DestroyChild)(self.binding_protocol, &mut self.device_handle);
+            }
+        }
+    }
+}
+
+impl Read for Tcp6Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_buf(buf).map_err(tcp_io_error)
+    }
+}
+
+impl Write for Tcp6Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buf(buf).map_err(tcp_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
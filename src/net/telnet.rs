@@ -0,0 +1,367 @@
+// A remote console over TCP, so a headless server can be driven through the network while it's still in UEFI. Once
+// attached everything written to the console goes to the connection as well, and keys typed at the other end come
+// back through the console's input alongside the local keyboard, so menus and prompts work from either:
+//
+//     telnet::attach(RemoteConsole::accept(23, Mode::Telnet, Some(Duration::from_secs(60)))?);
+//     let choice = boot_menu.show()?; // Answerable from `telnet server 23`
+//
+// Mode::Telnet does just enough negotiation (RFC 854, 857 and 858) to get character-at-a-time input from a telnet
+// client with us doing the echo. Mode::Raw is for netcat and the like, where the terminal is left in whatever mode it
+// was. Arrows, Home/End, PgUp/PgDn, Insert/Delete and F1-F12 come in as the usual VT100/xterm escape sequences.
+// Colours and what's drawn outside the console's text output (graphics, the firmware's own setup screens) aren't
+// mirrored. Anyone who can reach the port gets the console, so it's for provisioning networks only.
+
+use ffi::console::{
+    EFI_INPUT_KEY,
+    SCAN_NULL, SCAN_UP, SCAN_DOWN, SCAN_RIGHT, SCAN_LEFT, SCAN_HOME, SCAN_END, SCAN_INSERT, SCAN_DELETE,
+    SCAN_PAGE_UP, SCAN_PAGE_DOWN, SCAN_F1, SCAN_F2, SCAN_F3, SCAN_F4, SCAN_F5, SCAN_F6, SCAN_F7, SCAN_F8, SCAN_F9,
+    SCAN_F10, SCAN_F11, SCAN_F12, SCAN_ESC,
+};
+use io::{self, Read, Write};
+use net::{TcpStream, Ipv4Addr, SocketAddrV4};
+use retry::RetryPolicy;
+use mux::{self, Sink};
+use Result;
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{mem, time::Duration};
+
+pub const TELNET_PORT: u16 = 23;
+
+/// What the remote console is called among the console's sinks
+pub const SINK_NAME: &str = "telnet";
+
+// How long the console waits on the connection for a key before looking at the local keyboard again
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Telnet commands and the options we deal in
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const OPT_ECHO: u8 = 1;
+const OPT_SUPPRESS_GO_AHEAD: u8 = 3;
+
+const ESC: u8 = 0x1B;
+const BS: u16 = 8;
+const CR: u16 = 13;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Bytes both ways as they are
+    Raw,
+    /// Telnet, with us echoing and the client sending each key as it's typed
+    Telnet,
+}
+
+/// A connection that can be attached as a remote console
+pub struct RemoteConsole {
+    stream: TcpStream,
+    mode: Mode,
+    decoder: Decoder,
+    keys: VecDeque<EFI_INPUT_KEY>,
+}
+
+impl RemoteConsole {
+    /// Uses an already open connection, e.g. one made out to a console server
+    pub fn new(mut stream: TcpStream, mode: Mode) -> Result<Self> {
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        if mode == Mode::Telnet {
+            let negotiation = [IAC, WILL, OPT_ECHO, IAC, WILL, OPT_SUPPRESS_GO_AHEAD, IAC, DO, OPT_SUPPRESS_GO_AHEAD];
+            stream.write_all(&negotiation).map_err(super::http::network_error)?;
+        }
+        Ok(RemoteConsole { stream, mode, decoder: Decoder::new(mode), keys: VecDeque::new() })
+    }
+
+    /// Waits for someone to connect to `port`, for up to `timeout` if there is one
+    pub fn accept(port: u16, mode: Mode, timeout: Option<Duration>) -> Result<Self> {
+        let mut builder = TcpStream::builder();
+        builder.passive().station_port(port);
+        if let Some(timeout) = timeout {
+            builder.retry(RetryPolicy::never().with_timeout(timeout));
+        }
+        let stream = builder.connect(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0))?;
+        RemoteConsole::new(stream, mode)
+    }
+
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+impl Sink for RemoteConsole {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        let encoded = encode_output(text, self.mode);
+        self.stream.write_all(&encoded)
+    }
+
+    fn set_cursor_pos(&mut self, row: u32, col: u32) -> io::Result<()> {
+        write!(self.stream, "\x1b[{};{}H", row + 1, col + 1)
+    }
+
+    fn clear_screen(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"\x1b[2J\x1b[H")
+    }
+
+    // The next key typed at the other end, waiting a moment for one if none is waiting
+    fn poll_key(&mut self) -> io::Result<Option<EFI_INPUT_KEY>> {
+        if self.keys.is_empty() {
+            let mut buf = [0u8; 256];
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => {
+                    self.decoder.feed(&buf[..len], &mut self.keys);
+                    let replies = mem::take(&mut self.decoder.replies);
+                    self.stream.write_all(&replies)?;
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.keys.pop_front())
+    }
+}
+
+/// Makes `console` the remote console, replacing any there was. It's one of the console's sinks, called SINK_NAME,
+/// and taken out again when the connection closes
+pub fn attach(console: RemoteConsole) {
+    mux::add(SINK_NAME, console);
+}
+
+/// Stops mirroring to the remote console and closes the connection. False if there wasn't one
+pub fn detach() -> bool {
+    mux::remove(SINK_NAME).is_some()
+}
+
+pub fn is_attached() -> bool {
+    mux::contains(SINK_NAME)
+}
+
+// What the console writes as it goes to the other end, or to a serial terminal. The console already ends lines with CR LF; a backspace on its
+// own only moves a terminal's cursor, so it's followed by rubbing out the character
+pub(crate) fn encode_output(text: &str, mode: Mode) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for &b in text.as_bytes() {
+        match b {
+            0x08 => encoded.extend(b"\x08 \x08"),
+            IAC if mode == Mode::Telnet => encoded.extend(&[IAC, IAC]), // Can't be in UTF-8 but just in case
+            b => encoded.push(b),
+        }
+    }
+    encoded
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Data,
+    // After a CR, whose LF or NUL is dropped
+    Cr,
+    Iac,
+    // The option after WILL, WONT, DO or DONT
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+    Esc,
+    // The parameters so far of an ESC [ sequence
+    Csi(Vec<u8>),
+    // After ESC O
+    Ss3,
+    // A UTF-8 sequence's code point so far and how many bytes are left of it
+    Utf8(u32, u8),
+}
+
+// Turns what comes in from a terminal into keys, leaving answers to the other end's telnet negotiation in `replies`
+pub(crate) struct Decoder {
+    mode: Mode,
+    state: State,
+    replies: Vec<u8>,
+}
+
+impl Decoder {
+    pub(crate) fn new(mode: Mode) -> Self {
+        Decoder { mode, state: State::Data, replies: Vec::new() }
+    }
+
+    // A lone ESC at the end of what came in is taken to be the Esc key rather than the start of a sequence, since the
+    // rest of a sequence comes in the same segment
+    pub(crate) fn feed(&mut self, data: &[u8], keys: &mut VecDeque<EFI_INPUT_KEY>) {
+        for &b in data {
+            self.byte(b, keys);
+        }
+        if self.state == State::Esc {
+            keys.push_back(scan(SCAN_ESC));
+            self.state = State::Data;
+        }
+    }
+
+    fn byte(&mut self, b: u8, keys: &mut VecDeque<EFI_INPUT_KEY>) {
+        let state = mem::replace(&mut self.state, State::Data);
+        match state {
+            State::Iac => match b {
+                WILL | WONT | DO | DONT => self.state = State::Negotiation(b),
+                SB => self.state = State::Subnegotiation,
+                IAC => keys.push_back(unicode(0xFF)),
+                _ => (), // NOP, go ahead, break and the rest mean nothing here
+            },
+            State::Negotiation(command) => self.negotiate(command, b),
+            State::Subnegotiation => self.state = if b == IAC { State::SubnegotiationIac } else { State::Subnegotiation },
+            State::SubnegotiationIac => self.state = if b == SE { State::Data } else { State::Subnegotiation },
+            State::Cr if b == b'\n' || b == 0 => (),
+            State::Esc => match b {
+                b'[' => self.state = State::Csi(Vec::new()),
+                b'O' => self.state = State::Ss3,
+                _ => {
+                    keys.push_back(scan(SCAN_ESC));
+                    self.byte(b, keys);
+                },
+            },
+            State::Csi(mut params) => match b {
+                b'0'..=b'9' | b';' if params.len() < 16 => {
+                    params.push(b);
+                    self.state = State::Csi(params);
+                },
+                0x40..=0x7E => {
+                    let code = csi_scan_code(&params, b);
+                    if code != SCAN_NULL {
+                        keys.push_back(scan(code));
+                    }
+                },
+                _ => (), // Not one we know, so it's dropped
+            },
+            State::Ss3 => {
+                let code = match b {
+                    b'P' => SCAN_F1,
+                    b'Q' => SCAN_F2,
+                    b'R' => SCAN_F3,
+                    b'S' => SCAN_F4,
+                    _ => csi_scan_code(&[], b),
+                };
+                if code != SCAN_NULL {
+                    keys.push_back(scan(code));
+                }
+            },
+            State::Utf8(code_point, left) if b & 0xC0 == 0x80 => {
+                let code_point = code_point << 6 | (b & 0x3F) as u32;
+                if left > 1 {
+                    self.state = State::Utf8(code_point, left - 1);
+                } else if code_point <= 0xFFFF { // The console is UCS-2
+                    keys.push_back(unicode(code_point as u16));
+                }
+            },
+            // Anything else is the start of something new, including after a CR that had neither LF nor NUL after it
+            // and a UTF-8 sequence cut short
+            _ => self.data(b, keys),
+        }
+    }
+
+    fn data(&mut self, b: u8, keys: &mut VecDeque<EFI_INPUT_KEY>) {
+        match b {
+            IAC if self.mode == Mode::Telnet => self.state = State::Iac,
+            ESC => self.state = State::Esc,
+            b'\r' => {
+                keys.push_back(unicode(CR));
+                self.state = State::Cr;
+            },
+            b'\n' => keys.push_back(unicode(CR)), // What Enter sends from a terminal in raw mode
+            0x7F | 0x08 => keys.push_back(unicode(BS)), // Most terminals send DEL for backspace
+            0xC0..=0xDF => self.state = State::Utf8((b & 0x1F) as u32, 1),
+            0xE0..=0xEF => self.state = State::Utf8((b & 0x0F) as u32, 2),
+            0xF0..=0xF7 => self.state = State::Utf8((b & 0x07) as u32, 3),
+            0x80..=0xFF => (),
+            b => keys.push_back(unicode(b as u16)),
+        }
+    }
+
+    // We offered to echo and suppress go-ahead, and asked the client to suppress go-ahead. Anything else is refused,
+    // and refusals aren't answered, which is what stops negotiation going round in circles
+    fn negotiate(&mut self, command: u8, option: u8) {
+        let ours = option == OPT_ECHO || option == OPT_SUPPRESS_GO_AHEAD;
+        match command {
+            DO if !ours => self.replies.extend(&[IAC, WONT, option]),
+            WILL if option != OPT_SUPPRESS_GO_AHEAD => self.replies.extend(&[IAC, DONT, option]),
+            _ => (),
+        }
+    }
+}
+
+// The key for the final byte of an ESC [ or ESC O sequence, with its parameters. SCAN_NULL for ones we don't know
+fn csi_scan_code(params: &[u8], last: u8) -> u16 {
+    match last {
+        b'A' => SCAN_UP,
+        b'B' => SCAN_DOWN,
+        b'C' => SCAN_RIGHT,
+        b'D' => SCAN_LEFT,
+        b'H' => SCAN_HOME,
+        b'F' => SCAN_END,
+        b'~' => {
+            let number = params.split(|&b| b == b';').next().and_then(|n| core::str::from_utf8(n).ok()).and_then(|n| n.parse().ok());
+            match number {
+                Some(1) | Some(7) => SCAN_HOME,
+                Some(2) => SCAN_INSERT,
+                Some(3) => SCAN_DELETE,
+                Some(4) | Some(8) => SCAN_END,
+                Some(5) => SCAN_PAGE_UP,
+                Some(6) => SCAN_PAGE_DOWN,
+                Some(11) => SCAN_F1,
+                Some(12) => SCAN_F2,
+                Some(13) => SCAN_F3,
+                Some(14) => SCAN_F4,
+                Some(15) => SCAN_F5,
+                Some(17) => SCAN_F6,
+                Some(18) => SCAN_F7,
+                Some(19) => SCAN_F8,
+                Some(20) => SCAN_F9,
+                Some(21) => SCAN_F10,
+                Some(23) => SCAN_F11,
+                Some(24) => SCAN_F12,
+                _ => SCAN_NULL,
+            }
+        },
+        _ => SCAN_NULL,
+    }
+}
+
+fn unicode(c: u16) -> EFI_INPUT_KEY {
+    EFI_INPUT_KEY { ScanCode: SCAN_NULL, UnicodeChar: c }
+}
+
+fn scan(code: u16) -> EFI_INPUT_KEY {
+    EFI_INPUT_KEY { ScanCode: code, UnicodeChar: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: &mut Decoder, data: &[u8]) -> Vec<(u16, u16)> {
+        let mut keys = VecDeque::new();
+        decoder.feed(data, &mut keys);
+        keys.iter().map(|k| (k.ScanCode, k.UnicodeChar)).collect()
+    }
+
+    #[test]
+    fn turns_input_into_keys() {
+        let mut telnet = Decoder::new(Mode::Telnet);
+        // The client's answers to our offers, then asking for something we don't do
+        assert_eq!(decode(&mut telnet, &[IAC, DO, OPT_ECHO, IAC, WILL, OPT_SUPPRESS_GO_AHEAD, IAC, DO, 31, IAC, WILL, 24]), []);
+        assert_eq!(telnet.replies, [IAC, WONT, 31, IAC, DONT, 24]);
+        // Enter is CR NUL or CR LF, and a subnegotiation can come in between anything
+        assert_eq!(decode(&mut telnet, b"a\r\0b\r\n\xff\xfa\x18\x00xterm\xff\xf0\x7f"),
+            [(0, 'a' as u16), (0, CR), (0, 'b' as u16), (0, CR), (0, BS)]);
+        assert_eq!(decode(&mut telnet, b"\x1b[A\x1b[6~\x1bOP\x1b[24~\x1b[1;5C"),
+            [(SCAN_UP, 0), (SCAN_PAGE_DOWN, 0), (SCAN_F1, 0), (SCAN_F12, 0), (SCAN_RIGHT, 0)]);
+        // A sequence split across reads, then Esc on its own
+        assert_eq!(decode(&mut telnet, b"\x1b["), []);
+        assert_eq!(decode(&mut telnet, b"B\x1b"), [(SCAN_DOWN, 0), (SCAN_ESC, 0)]);
+        assert_eq!(decode(&mut telnet, "é€😀".as_bytes()), [(0, 0xE9), (0, 0x20AC)]);
+
+        // IAC is just another byte in raw mode, and Enter can be a bare LF
+        let mut raw = Decoder::new(Mode::Raw);
+        assert_eq!(decode(&mut raw, b"q\n\xff"), [(0, 'q' as u16), (0, CR)]);
+        assert!(raw.replies.is_empty());
+
+        assert_eq!(encode_output("ab\x08\r\n", Mode::Telnet), b"ab\x08 \x08\r\n");
+    }
+}
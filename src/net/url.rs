@@ -0,0 +1,398 @@
+// URLs (RFC 3986) of the kind boot configurations point at: http://server/path, tftp://server/file, file:///EFI/path.
+//
+// The path and query are kept percent-encoded as given, the way they go out on the wire, and decoded on request. The
+// host is kept decoded and without the brackets around an IPv6 literal.
+
+use core::{fmt, str::{self, FromStr}};
+use net::IpAddr;
+use alloc::{string::{String, ToString}, vec::Vec};
+
+/// An error returned when parsing a URL or percent-decoding part of one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlParseError(());
+
+impl fmt::Display for UrlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid URL")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    scheme: String,
+    userinfo: Option<String>,
+    host: Option<String>, // None if there was no authority at all, as in file:/path
+    port: Option<u16>,
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+impl Url {
+    /// Always lower case
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// The `user:password` part before the host, still percent-encoded
+    pub fn userinfo(&self) -> Option<&str> {
+        self.userinfo.as_deref()
+    }
+
+    /// Empty for URLs like file:///path that have an authority without a host
+    pub fn host(&self) -> &str {
+        self.host.as_ref().map_or("", |h| h.as_str())
+    }
+
+    /// The host if it's an IP address rather than a name
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.host().parse().ok()
+    }
+
+    /// The port given in the URL
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// The port given in the URL or the scheme's usual one
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or_else(|| default_port(&self.scheme))
+    }
+
+    /// Percent-encoded, as it's sent in a request
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn decoded_path(&self) -> Result<String, UrlParseError> {
+        percent_decode(&self.path)
+    }
+
+    /// Percent-encoded, without the `?`
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// The query's `key=value` pairs decoded as a form would be, with `+` for spaces
+    pub fn query_pairs(&self) -> Result<Vec<(String, String)>, UrlParseError> {
+        match self.query {
+            Some(ref query) => form_pairs(query),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// The path and query, as they go in an HTTP request line
+    pub fn request_target(&self) -> String {
+        let path = if self.path.is_empty() { "/" } else { &self.path };
+        match self.query {
+            Some(ref query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        }
+    }
+
+    /// Resolves a reference relative to this URL, like a redirect's Location or a link in a document
+    pub fn join(&self, reference: &str) -> Result<Url, UrlParseError> {
+        if has_scheme(reference) {
+            return reference.parse();
+        }
+        let (reference, fragment) = split_off(reference, '#');
+        let (reference, query) = split_off(reference, '?');
+
+        let mut url = self.clone();
+        url.fragment = fragment.map(String::from);
+        if reference.starts_with("//") {
+            let joined: Url = format!("{}:{}", self.scheme, reference).parse()?;
+            return Ok(Url { query: query.map(String::from), fragment: url.fragment, ..joined });
+        }
+
+        if reference.is_empty() {
+            if query.is_some() {
+                url.query = query.map(String::from);
+            }
+            return Ok(url);
+        }
+
+        let path = if reference.starts_with('/') {
+            reference.to_string()
+        } else {
+            // Merge with everything up to the last / of the base path
+            let base = match self.path.rfind('/') {
+                Some(i) => &self.path[..=i],
+                None if self.host.is_some() => "/",
+                None => "",
+            };
+            format!("{}{}", base, reference)
+        };
+        check(&path, is_path_char)?;
+        url.path = remove_dot_segments(&path);
+        url.query = query.map(String::from);
+        Ok(url)
+    }
+}
+
+impl FromStr for Url {
+    type Err = UrlParseError;
+
+    fn from_str(s: &str) -> Result<Url, UrlParseError> {
+        let s = s.trim();
+        if !has_scheme(s) {
+            return Err(UrlParseError(()));
+        }
+        let colon = s.find(':').unwrap();
+        let scheme = s[..colon].to_ascii_lowercase();
+        let rest = &s[colon + 1..];
+
+        let (rest, fragment) = split_off(rest, '#');
+        let (rest, query) = split_off(rest, '?');
+        if let Some(query) = query {
+            check(query, |c| is_path_char(c) || c == b'?')?;
+        }
+        if let Some(fragment) = fragment {
+            check(fragment, |c| is_path_char(c) || c == b'?')?;
+        }
+
+        let (userinfo, host, port, path) = if rest.starts_with("//") {
+            let rest = &rest[2..];
+            let (authority, path) = rest.find('/').map_or((rest, ""), |i| (&rest[..i], &rest[i..]));
+            let (userinfo, hostport) = match authority.rfind('@') {
+                Some(i) => (Some(&authority[..i]), &authority[i + 1..]),
+                None => (None, authority),
+            };
+            if let Some(userinfo) = userinfo {
+                check(userinfo, |c| is_unreserved(c) || is_sub_delim(c) || c == b':' || c == b'%')?;
+            }
+            let (host, port) = parse_host_port(hostport)?;
+            (userinfo.map(String::from), Some(host), port, path)
+        } else {
+            (None, None, None, rest)
+        };
+        check(path, is_path_char)?;
+
+        Ok(Url {
+            scheme,
+            userinfo,
+            host,
+            port,
+            path: path.to_string(),
+            query: query.map(String::from),
+            fragment: fragment.map(String::from),
+        })
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.scheme)?;
+        if let Some(ref host) = self.host {
+            f.write_str("//")?;
+            if let Some(ref userinfo) = self.userinfo {
+                write!(f, "{}@", userinfo)?;
+            }
+            if host.contains(':') {
+                write!(f, "[{}]", host)?;
+            } else {
+                f.write_str(&percent_encode(host, |c| is_unreserved(c) || is_sub_delim(c)))?;
+            }
+            if let Some(port) = self.port {
+                write!(f, ":{}", port)?;
+            }
+        }
+        f.write_str(&self.path)?;
+        if let Some(ref query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(ref fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes %XX escapes. Fails if an escape is malformed or the result isn't UTF-8
+pub fn percent_decode(s: &str) -> Result<String, UrlParseError> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).and_then(|hex| str::from_utf8(hex).ok()).ok_or(UrlParseError(()))?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| UrlParseError(()))?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| UrlParseError(()))
+}
+
+/// Decodes `key=value&key=value` as a form would be, with `+` for spaces
+pub(crate) fn form_pairs(s: &str) -> Result<Vec<(String, String)>, UrlParseError> {
+    s.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        let key = parts.next().unwrap_or("").replace('+', " ");
+        let value = parts.next().unwrap_or("").replace('+', " ");
+        Ok((percent_decode(&key)?, percent_decode(&value)?))
+    }).collect()
+}
+
+/// Escapes everything but unreserved characters, for putting arbitrary text in a path segment or query value
+pub fn percent_encode_component(s: &str) -> String {
+    percent_encode(s, is_unreserved)
+}
+
+fn percent_encode<F: Fn(u8) -> bool>(s: &str, keep: F) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        if keep(b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn parse_host_port(hostport: &str) -> Result<(String, Option<u16>), UrlParseError> {
+    let (host, port) = if hostport.starts_with('[') {
+        let end = hostport.find(']').ok_or(UrlParseError(()))?;
+        let host = &hostport[1..end];
+        host.parse::<::net::Ipv6Addr>().map_err(|_| UrlParseError(()))?;
+        match &hostport[end + 1..] {
+            "" => (host.to_string(), None),
+            port if port.starts_with(':') => (host.to_string(), Some(&port[1..])),
+            _ => return Err(UrlParseError(())),
+        }
+    } else {
+        let (host, port) = match hostport.rfind(':') {
+            Some(i) => (&hostport[..i], Some(&hostport[i + 1..])),
+            None => (hostport, None),
+        };
+        check(host, |c| is_unreserved(c) || is_sub_delim(c) || c == b'%')?;
+        (percent_decode(host)?, port)
+    };
+
+    // An empty port is allowed and means the default
+    let port = match port {
+        Some("") | None => None,
+        Some(port) if port.bytes().all(|c| c.is_ascii_digit()) => Some(port.parse().map_err(|_| UrlParseError(()))?),
+        Some(_) => return Err(UrlParseError(())),
+    };
+    Ok((host, port))
+}
+
+// Removes . and .. segments from an absolute path
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').peekable();
+    let absolute = path.starts_with('/');
+    if absolute {
+        parts.next();
+    }
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." | ".." => {
+                if part == ".." {
+                    segments.pop();
+                }
+                if last {
+                    segments.push(""); // Keep the trailing slash of a/b/..
+                }
+            },
+            part => segments.push(part),
+        }
+    }
+    let joined = segments.join("/");
+    if absolute { format!("/{}", joined) } else { joined }
+}
+
+fn has_scheme(s: &str) -> bool {
+    match s.find(':') {
+        Some(colon) if colon > 0 => {
+            let scheme = s[..colon].as_bytes();
+            scheme[0].is_ascii_alphabetic() && scheme.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'+' || c == b'-' || c == b'.')
+        },
+        _ => false,
+    }
+}
+
+fn split_off(s: &str, separator: char) -> (&str, Option<&str>) {
+    match s.find(separator) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "tftp" => Some(69),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+fn check<F: Fn(u8) -> bool>(s: &str, allowed: F) -> Result<(), UrlParseError> {
+    if s.bytes().all(allowed) { Ok(()) } else { Err(UrlParseError(())) }
+}
+
+fn is_unreserved(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-' || c == b'.' || c == b'_' || c == b'~'
+}
+
+fn is_sub_delim(c: u8) -> bool {
+    b"!$&'()*+,;=".contains(&c)
+}
+
+fn is_path_char(c: u8) -> bool {
+    is_unreserved(c) || is_sub_delim(c) || b":@/%".contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::Ipv4Addr;
+
+    #[test]
+    fn parses_urls() {
+        let url: Url = "HTTP://user:pw@Boot.Example.com:8080/images/linux%2Bkvm/vmlinuz?arch=x86+64&v=%C3%A9#top".parse().unwrap();
+        assert_eq!((url.scheme(), url.userinfo(), url.host(), url.port()), ("http", Some("user:pw"), "Boot.Example.com", Some(8080)));
+        assert_eq!(url.path(), "/images/linux%2Bkvm/vmlinuz");
+        assert_eq!(url.decoded_path().unwrap(), "/images/linux+kvm/vmlinuz");
+        assert_eq!(url.query_pairs().unwrap(), [("arch".into(), "x86 64".into()), ("v".into(), "\u{e9}".into())]);
+        assert_eq!(url.fragment(), Some("top"));
+        assert_eq!(url.to_string(), "http://user:pw@Boot.Example.com:8080/images/linux%2Bkvm/vmlinuz?arch=x86+64&v=%C3%A9#top");
+
+        let url: Url = "tftp://10.0.0.1/pxelinux.0".parse().unwrap();
+        assert_eq!((url.ip(), url.port_or_default()), (Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))), Some(69)));
+        let url: Url = "http://[fe80::1]:/".parse().unwrap();
+        assert_eq!((url.host(), url.port_or_default(), url.request_target().as_str()), ("fe80::1", Some(80), "/"));
+        let url: Url = "file:///EFI/BOOT/BOOTX64.EFI".parse().unwrap();
+        assert_eq!((url.host(), url.path()), ("", "/EFI/BOOT/BOOTX64.EFI"));
+
+        for bad in &["no-scheme/path", "http://host:port/", "http://[::1/", "http://host/a path", "1http://host/"] {
+            assert_eq!(bad.parse::<Url>(), Err(UrlParseError(())), "{}", bad);
+        }
+        assert!(percent_decode("%zz").is_err() && percent_decode("%C3").is_err());
+        assert_eq!(percent_encode_component("a b/\u{e9}"), "a%20b%2F%C3%A9");
+    }
+
+    #[test]
+    fn joins_references() {
+        let base: Url = "http://host/a/b/c?q".parse().unwrap();
+        let join = |reference| base.join(reference).unwrap().to_string();
+        assert_eq!(join("d"), "http://host/a/b/d");
+        assert_eq!(join("../d/./e"), "http://host/a/d/e");
+        assert_eq!(join("/x/../y"), "http://host/y");
+        assert_eq!(join("?r"), "http://host/a/b/c?r");
+        assert_eq!(join(".."), "http://host/a/");
+        assert_eq!(join("//other:81/z"), "http://other:81/z");
+        assert_eq!(join("tftp://t/f"), "tftp://t/f");
+    }
+}
@@ -0,0 +1,179 @@
+// VLANs via EFI_VLAN_CONFIG_PROTOCOL.
+// Each NIC whose driver supports tagging has the protocol on its handle. Adding a VLAN makes the firmware create a
+// child network device for it, which sockets then bind to like any other NIC. Configure VLANs before opening sockets:
+// the firmware tears down and recreates the children when they change. The configuration is kept in a variable so it
+// survives reboots.
+
+use ffi::{
+    vlan::{EFI_VLAN_CONFIG_PROTOCOL, EFI_VLAN_CONFIG_PROTOCOL_GUID, EFI_VLAN_FIND_DATA},
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_HANDLE,
+    EFI_NOT_FOUND,
+};
+use boxed::EfiBox;
+use boot_services::locate_handles;
+use {Result, EfiErrorKind, system_table, image_handle};
+use core::{mem, ptr, slice};
+use alloc::vec::Vec;
+
+/// Highest VLAN id. 4095 is reserved
+pub const MAX_VLAN_ID: u16 = 4094;
+
+/// Highest 802.1Q priority
+pub const MAX_PRIORITY: u8 = 7;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Vlan {
+    pub id: u16,
+    pub priority: u8,
+}
+
+/// The VLAN configuration of one NIC
+pub struct VlanConfig {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_VLAN_CONFIG_PROTOCOL,
+}
+
+impl VlanConfig {
+    /// One for every NIC that supports VLANs
+    pub fn all() -> Result<Vec<Self>> {
+        let bs = system_table().BootServices;
+        let mut configs = Vec::new();
+        for handle in locate_handles(&EFI_VLAN_CONFIG_PROTOCOL_GUID)? {
+            let protocol: *const EFI_VLAN_CONFIG_PROTOCOL = ptr::null();
+            unsafe {
+                ret_on_err!(((*bs).OpenProtocol)(handle, &EFI_VLAN_CONFIG_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+            }
+            if !protocol.is_null() {
+                configs.push(VlanConfig { handle, protocol });
+            }
+        }
+        Ok(configs)
+    }
+
+    /// The NIC's handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// The VLANs set up on the NIC
+    pub fn vlans(&self) -> Result<Vec<Vlan>> {
+        self.find_raw(None)
+    }
+
+    pub fn find(&self, id: u16) -> Result<Option<Vlan>> {
+        Ok(self.find_raw(Some(id))?.into_iter().next())
+    }
+
+    /// Adds a VLAN, or changes its priority if there already is one with this id. Id 0 sets the priority of untagged
+    /// frames
+    pub fn set(&self, id: u16, priority: u8) -> Result<()> {
+        if id > MAX_VLAN_ID || priority > MAX_PRIORITY {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        unsafe {
+            ret_on_err!(((*self.protocol).Set)(self.protocol, id, priority));
+        }
+        Ok(())
+    }
+
+    /// Removes a VLAN. Fails with NotFound if there isn't one with this id
+    pub fn remove(&self, id: u16) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).Remove)(self.protocol, id));
+        }
+        Ok(())
+    }
+
+    fn find_raw(&self, id: Option<u16>) -> Result<Vec<Vlan>> {
+        let id_ptr = id.as_ref().map_or(ptr::null(), |id| id as *const u16);
+        let mut count = 0;
+        let mut entries: *mut EFI_VLAN_FIND_DATA = ptr::null_mut();
+        let status = unsafe { traced!(((*self.protocol).Find)(self.protocol, id_ptr, &mut count, &mut entries)) };
+        if status == EFI_NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        ::to_res((), status)?;
+        if count == 0 || entries.is_null() {
+            return Ok(Vec::new());
+        }
+
+        let entries = unsafe { EfiBox::from_raw(entries) }; // The firmware allocates the entries and we free them
+        let vlans = unsafe { slice::from_raw_parts(entries.as_raw(), count as usize) };
+        Ok(vlans.iter().map(|v| Vlan { id: v.VlanId, priority: v.Priority }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_STATUS, EFI_SUCCESS, UINT8, UINT16, VOID};
+    use testing::mock;
+    use core::cell::RefCell;
+    use alloc::boxed::Box;
+
+    #[repr(C)]
+    struct FakeNic {
+        protocol: EFI_VLAN_CONFIG_PROTOCOL,
+        vlans: RefCell<Vec<EFI_VLAN_FIND_DATA>>,
+    }
+
+    fn vlans<'a>(this: *const EFI_VLAN_CONFIG_PROTOCOL) -> &'a RefCell<Vec<EFI_VLAN_FIND_DATA>> {
+        unsafe { &(*(this as *const FakeNic)).vlans }
+    }
+
+    extern "efiapi" fn set(this: *const EFI_VLAN_CONFIG_PROTOCOL, id: UINT16, priority: UINT8) -> EFI_STATUS {
+        let mut vlans = vlans(this).borrow_mut();
+        vlans.retain(|v| v.VlanId != id);
+        vlans.push(EFI_VLAN_FIND_DATA { VlanId: id, Priority: priority });
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn find(this: *const EFI_VLAN_CONFIG_PROTOCOL, id: *const UINT16, count: *mut UINT16, entries: *mut *mut EFI_VLAN_FIND_DATA) -> EFI_STATUS {
+        let found = vlans(this).borrow().iter().filter(|v| id.is_null() || v.VlanId == unsafe { *id }).cloned().collect::<Vec<_>>();
+        if found.is_empty() {
+            return EFI_NOT_FOUND;
+        }
+        unsafe {
+            let size = found.len() * mem::size_of::<EFI_VLAN_FIND_DATA>();
+            let buf = EfiBox::<EFI_VLAN_FIND_DATA>::allocate(size).unwrap().into_raw();
+            ptr::copy_nonoverlapping(found.as_ptr(), buf, found.len());
+            *entries = buf;
+            *count = found.len() as u16;
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn remove(this: *const EFI_VLAN_CONFIG_PROTOCOL, id: UINT16) -> EFI_STATUS {
+        let mut vlans = vlans(this).borrow_mut();
+        let before = vlans.len();
+        vlans.retain(|v| v.VlanId != id);
+        if vlans.len() == before { EFI_NOT_FOUND } else { EFI_SUCCESS }
+    }
+
+    #[test]
+    fn adds_finds_and_removes_vlans() {
+        mock::install();
+        let nic: &'static FakeNic = Box::leak(Box::new(FakeNic {
+            protocol: EFI_VLAN_CONFIG_PROTOCOL { Set: set, Find: find, Remove: remove },
+            vlans: RefCell::new(Vec::new()),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_VLAN_CONFIG_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, nic as *const _ as *const VOID);
+        }
+
+        let config = VlanConfig::all().unwrap().into_iter().find(|c| c.handle() == handle).unwrap();
+        assert_eq!(config.vlans().unwrap(), []);
+        config.set(100, 0).unwrap();
+        config.set(200, 5).unwrap();
+        config.set(100, 3).unwrap();
+        assert_eq!(config.vlans().unwrap(), [Vlan { id: 200, priority: 5 }, Vlan { id: 100, priority: 3 }]);
+        assert_eq!(config.find(200).unwrap(), Some(Vlan { id: 200, priority: 5 }));
+
+        config.remove(200).unwrap();
+        assert_eq!(config.find(200).unwrap(), None);
+        assert_eq!(config.remove(200).unwrap_err().kind(), EfiErrorKind::NotFound);
+        assert_eq!(config.set(4095, 0).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
@@ -0,0 +1,451 @@
+// A WebSocket client (RFC 6455), for a two-way channel to a controller through infrastructure that only lets HTTP
+// through, e.g. a remote console or a deployment agent taking orders:
+//
+//     let mut socket = WebSocket::connect(&"ws://deploy.example.com/agent".parse()?)?;
+//     socket.send_text("{\"hello\": \"node7\"}")?;
+//     loop {
+//         match socket.read_message()? {
+//             Message::Text(order) => run(&order),
+//             Message::Close(_) => break,
+//             _ => (),
+//         }
+//     }
+//
+// Pings are answered as they're read, so something has to keep reading for the connection to stay up. ws:// only:
+// there's no TLS to do wss:// with. No extensions, so nothing is compressed.
+
+use io::{BufReader, Read, Write};
+use net::{TcpStream, Url};
+use services::BootServices;
+use time::Timestamp;
+use super::http::{Response, network_error, status_error};
+use hash::sha1;
+use utils::to_base64;
+use {Result, EfiError, EfiErrorKind, system_table};
+use alloc::{string::{String, ToString}, vec::Vec};
+use byteorder::{BigEndian, ByteOrder};
+use core::str;
+
+// What the server's Sec-WebSocket-Accept is worked out from, along with our key
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Messages bigger than this are refused rather than buffered, unless set_max_message_len() says otherwise
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+// Control frames have to fit in a frame with a 7 bit length
+const MAX_CONTROL_LEN: usize = 125;
+
+/// Status codes for closing, from RFC 6455 section 7.4.1
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// A ping from the server, which has already been answered
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The server is closing the connection, with its status code and reason if it gave them. Our side of the close
+    /// has already been sent
+    Close(Option<(u16, String)>),
+}
+
+/// A client connection that's been upgraded to a WebSocket
+pub struct WebSocket<S> {
+    stream: BufReader<S>,
+    mask_state: u64,
+    max_message_len: usize,
+    // The opcode and payload so far of a message sent in fragments
+    partial: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket<TcpStream> {
+    /// Connects to a ws:// URL and does the opening handshake
+    pub fn connect(url: &Url) -> Result<Self> {
+        if url.scheme() != "ws" {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        let port = url.port_or_default().unwrap_or(80);
+        let stream = match url.ip() {
+            Some(ip) => TcpStream::connect((ip, port))?,
+            None => TcpStream::connect((url.host(), port))?,
+        };
+        WebSocket::client(stream, url)
+    }
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Does the opening handshake for `url` on an already open connection. Fails with the error http::get() would
+    /// give for the status if the server doesn't switch protocols, and ProtocolError if it switches to something else
+    pub fn client(stream: S, url: &Url) -> Result<Self> {
+        let mut mask_state = seed()?;
+        let mut key = [0u8; 16];
+        BigEndian::write_u64(&mut key[..8], splitmix64(&mut mask_state));
+        BigEndian::write_u64(&mut key[8..], splitmix64(&mut mask_state));
+        handshake(stream, url, &to_base64(&key), mask_state)
+    }
+
+    /// Refuses messages longer than `len` with BufferTooSmall
+    pub fn set_max_message_len(&mut self, len: usize) {
+        self.max_message_len = len;
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<()> {
+        match *message {
+            Message::Text(ref text) => self.send_text(text),
+            Message::Binary(ref data) => self.send_binary(data),
+            Message::Ping(ref payload) => self.ping(payload),
+            Message::Pong(ref payload) => self.control_frame(OP_PONG, payload),
+            Message::Close(Some((code, ref reason))) => self.close(code, reason),
+            Message::Close(None) => {
+                self.control_frame(OP_CLOSE, &[])?;
+                self.close_sent = true;
+                Ok(())
+            },
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        self.write_frame(OP_TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.write_frame(OP_BINARY, data)
+    }
+
+    /// Payloads are at most 125 bytes. The server's pong comes back through read_message()
+    pub fn ping(&mut self, payload: &[u8]) -> Result<()> {
+        self.control_frame(OP_PING, payload)
+    }
+
+    /// Starts closing the connection. The server answers with a close of its own, which read_message() gives once
+    /// whatever the server sent before it has been read. Nothing can be sent after this
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        let mut payload = [0u8; 2].to_vec();
+        BigEndian::write_u16(&mut payload, code);
+        let mut end = reason.len().min(MAX_CONTROL_LEN - 2);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.extend(&reason.as_bytes()[..end]);
+        self.control_frame(OP_CLOSE, &payload)?;
+        self.close_sent = true;
+        Ok(())
+    }
+
+    /// The next message, put back together if it came in fragments. ConnectionFin once the server's close has been
+    /// read, ProtocolError if the server breaks the protocol and BufferTooSmall if a message is too long
+    pub fn read_message(&mut self) -> Result<Message> {
+        if self.close_received {
+            return Err(EfiErrorKind::ConnectionFin.into());
+        }
+        loop {
+            let (fin, opcode, payload) = self.read_frame()?;
+            match opcode {
+                OP_CONTINUATION => {
+                    let (opcode, mut message) = self.partial.take().ok_or_else(|| EfiError::from(EfiErrorKind::ProtocolError))?;
+                    if message.len() + payload.len() > self.max_message_len {
+                        return Err(EfiErrorKind::BufferTooSmall.into());
+                    }
+                    message.extend(payload);
+                    if fin {
+                        return data_message(opcode, message);
+                    }
+                    self.partial = Some((opcode, message));
+                },
+                OP_TEXT | OP_BINARY if self.partial.is_none() => {
+                    if fin {
+                        return data_message(opcode, payload);
+                    }
+                    self.partial = Some((opcode, payload));
+                },
+                OP_CLOSE => {
+                    self.close_received = true;
+                    let close = parse_close(&payload)?;
+                    if !self.close_sent {
+                        // Echoing the code, as section 5.5.1 has it
+                        let echo = if payload.len() >= 2 { &payload[..2] } else { &[] };
+                        self.control_frame(OP_CLOSE, echo)?;
+                        self.close_sent = true;
+                    }
+                    return Ok(Message::Close(close));
+                },
+                OP_PING => {
+                    if !self.close_sent {
+                        self.control_frame(OP_PONG, &payload)?;
+                    }
+                    return Ok(Message::Ping(payload));
+                },
+                OP_PONG => return Ok(Message::Pong(payload)),
+                _ => return Err(EfiErrorKind::ProtocolError.into()),
+            }
+        }
+    }
+
+    fn control_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        if payload.len() > MAX_CONTROL_LEN {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        self.write_frame(opcode, payload)
+    }
+
+    // A whole message in one frame, masked as frames from clients have to be
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        if self.close_sent {
+            return Err(EfiErrorKind::ConnectionFin.into());
+        }
+        let mut mask = [0u8; 4];
+        BigEndian::write_u32(&mut mask, splitmix64(&mut self.mask_state) as u32);
+        let frame = encode_frame(opcode, payload, mask);
+        let stream = self.stream.get_mut();
+        stream.write_all(&frame).and_then(|_| stream.flush()).map_err(network_error)
+    }
+
+    // Whether it's the last of a message, the opcode and the payload
+    fn read_frame(&mut self) -> Result<(bool, u8, Vec<u8>)> {
+        let mut head = [0u8; 2];
+        self.read_exact(&mut head)?;
+        let (fin, opcode) = (head[0] & 0x80 != 0, head[0] & 0x0F);
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                self.read_exact(&mut len)?;
+                BigEndian::read_u16(&len) as u64
+            },
+            127 => {
+                let mut len = [0u8; 8];
+                self.read_exact(&mut len)?;
+                BigEndian::read_u64(&len)
+            },
+            len => len as u64,
+        };
+        // No extensions were agreed, so no reserved bits, and servers mustn't mask
+        if head[0] & 0x70 != 0 || head[1] & 0x80 != 0 || (opcode >= OP_CLOSE && (!fin || len > MAX_CONTROL_LEN as u64)) {
+            return Err(EfiErrorKind::ProtocolError.into());
+        }
+        if len > self.max_message_len as u64 {
+            return Err(EfiErrorKind::BufferTooSmall.into());
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.read_exact(&mut payload)?;
+        Ok((fin, opcode, payload))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.stream.read_exact(buf).map_err(network_error)
+    }
+}
+
+// The handshake with a given key, and where the masks carry on from
+fn handshake<S: Read + Write>(mut stream: S, url: &Url, key: &str, mask_state: u64) -> Result<WebSocket<S>> {
+    let host = if url.host().contains(':') { format!("[{}]", url.host()) } else { url.host().to_string() };
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    };
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: efi\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+        Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n", url.request_target(), host, key);
+    stream.write_all(request.as_bytes()).and_then(|_| stream.flush()).map_err(network_error)?;
+
+    let response = Response::read_from(stream, false)?;
+    if response.status() != 101 {
+        return Err(status_error(response.status()));
+    }
+    let has_token = |name: &str, token: &str| {
+        response.header(name).map(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token))) == Some(true)
+    };
+    if !has_token("upgrade", "websocket") || !has_token("connection", "upgrade") ||
+        response.header("sec-websocket-accept") != Some(&accept_key(key)) ||
+        response.header("sec-websocket-extensions").is_some() {
+        return Err(EfiErrorKind::ProtocolError.into());
+    }
+
+    Ok(WebSocket {
+        stream: response.into_inner(),
+        mask_state,
+        max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+        partial: None,
+        close_sent: false,
+        close_received: false,
+    })
+}
+
+// What the server has to answer our key with
+fn accept_key(key: &str) -> String {
+    let mut input = key.as_bytes().to_vec();
+    input.extend(ACCEPT_GUID.as_bytes());
+    to_base64(&sha1(&input))
+}
+
+fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode); // The whole message
+    match payload.len() {
+        len if len < 126 => frame.push(0x80 | len as u8),
+        len if len <= 0xFFFF => {
+            frame.extend(&[0x80 | 126, 0, 0]);
+            let at = frame.len() - 2;
+            BigEndian::write_u16(&mut frame[at..], len as u16);
+        },
+        len => {
+            frame.extend(&[0x80 | 127, 0, 0, 0, 0, 0, 0, 0, 0]);
+            let at = frame.len() - 8;
+            BigEndian::write_u64(&mut frame[at..], len as u64);
+        },
+    }
+    frame.extend(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, &b)| b ^ mask[i % 4]));
+    frame
+}
+
+fn data_message(opcode: u8, payload: Vec<u8>) -> Result<Message> {
+    if opcode == OP_TEXT {
+        String::from_utf8(payload).map(Message::Text).map_err(|_| EfiErrorKind::ProtocolError.into())
+    } else {
+        Ok(Message::Binary(payload))
+    }
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<(u16, String)>> {
+    match payload.len() {
+        0 => Ok(None),
+        1 => Err(EfiErrorKind::ProtocolError.into()),
+        _ => {
+            let reason = str::from_utf8(&payload[2..]).map_err(|_| EfiError::from(EfiErrorKind::ProtocolError))?;
+            Ok(Some((BigEndian::read_u16(payload), reason.to_string())))
+        },
+    }
+}
+
+// Masks only have to be unpredictable enough that a client can't aim what goes through a proxy (section 10.3), and
+// keys only have to differ between connections, so the firmware's timestamp counter and monotonic count are seed
+// enough
+fn seed() -> Result<u64> {
+    let count = BootServices::new(unsafe { &*system_table().BootServices }).next_monotonic_count()?;
+    let ticks = Timestamp::now().map(|t| t.since_reset().as_nanos() as u64).unwrap_or(0);
+    Ok(count.rotate_left(32) ^ ticks)
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::{self, Cursor};
+
+    // A connection with the server's side canned, keeping what was sent to it
+    struct Connection {
+        received: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Write for &mut Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for &mut Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.received.read(buf)
+        }
+    }
+
+    // An unmasked frame, as servers send them
+    fn server_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = encode_frame(first & 0x0F, payload, [0; 4]);
+        frame[0] = first;
+        frame[1] &= 0x7F;
+        frame.drain(frame.len() - payload.len() - 4..frame.len() - payload.len());
+        frame
+    }
+
+    // Undoes encode_frame() for a short frame
+    fn unmask(frame: &[u8]) -> (u8, Vec<u8>) {
+        assert_eq!(frame[1] & 0x80, 0x80);
+        let mask = &frame[2..6];
+        (frame[0], frame[6..].iter().enumerate().map(|(i, &b)| b ^ mask[i % 4]).collect())
+    }
+
+    #[test]
+    fn talks_websocket() {
+        // The example from RFC 6455 section 1.3
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        assert_eq!(accept_key(key), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(to_base64(b"ab"), "YWI=");
+
+        let mut received = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: WebSocket\r\nConnection: keep-alive, Upgrade\r\n\
+            Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n".to_vec();
+        received.extend(server_frame(0x01, b"Hel")); // A text message in two fragments with a ping between them
+        received.extend(server_frame(0x89, b"?"));
+        received.extend(server_frame(0x80, b"lo"));
+        received.extend(server_frame(0x82, &[7; 300]));
+        received.extend(server_frame(0x88, b"\x03\xe8bye"));
+        let mut connection = Connection { received: Cursor::new(received), sent: Vec::new() };
+        let url = "ws://deploy.example.com:8080/agent?id=7".parse().unwrap();
+
+        let sent_len = {
+            let mut socket = handshake(&mut connection, &url, key, 1).unwrap();
+            assert_eq!(socket.read_message().unwrap(), Message::Ping(b"?".to_vec()));
+            assert_eq!(socket.read_message().unwrap(), Message::Text("Hello".to_string()));
+            assert_eq!(socket.read_message().unwrap(), Message::Binary([7; 300].to_vec()));
+            socket.send_text("hi").unwrap();
+            assert_eq!(socket.read_message().unwrap(), Message::Close(Some((CLOSE_NORMAL, "bye".to_string()))));
+            assert_eq!(socket.read_message().unwrap_err().kind(), EfiErrorKind::ConnectionFin);
+            assert_eq!(socket.send_text("too late").unwrap_err().kind(), EfiErrorKind::ConnectionFin);
+            socket.get_ref().sent.len()
+        };
+
+        let sent = connection.sent;
+        assert_eq!(sent.len(), sent_len);
+        let request_end = sent.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let request = str::from_utf8(&sent[..request_end]).unwrap();
+        assert!(request.starts_with("GET /agent?id=7 HTTP/1.1\r\nHost: deploy.example.com:8080\r\n"));
+        assert!(request.contains("\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n"));
+
+        // The pong, the text, then the close echoing the server's code
+        let frames = &sent[request_end..];
+        assert_eq!(unmask(&frames[..7]), (0x8A, b"?".to_vec()));
+        assert_eq!(unmask(&frames[7..15]), (0x81, b"hi".to_vec()));
+        assert_eq!(unmask(&frames[15..]), (0x88, b"\x03\xe8".to_vec()));
+
+        // Long frames have longer lengths
+        assert_eq!(&encode_frame(OP_BINARY, &[0; 300], [0; 4])[..4], [0x82, 0xFE, 0x01, 0x2C]);
+        assert_eq!(&encode_frame(OP_BINARY, &[0; 70000], [0; 4])[..10], [0x82, 0xFF, 0, 0, 0, 0, 0, 1, 0x11, 0x70]);
+
+        // A server that doesn't know the key
+        let mut connection = Connection {
+            received: Cursor::new(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                Sec-WebSocket-Accept: wrong\r\n\r\n".to_vec()),
+            sent: Vec::new(),
+        };
+        assert_eq!(handshake(&mut connection, &url, key, 1).err().unwrap().kind(), EfiErrorKind::ProtocolError);
+        let mut connection = Connection { received: Cursor::new(b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec()), sent: Vec::new() };
+        assert_eq!(handshake(&mut connection, &url, key, 1).err().unwrap().kind(), EfiErrorKind::NotFound);
+    }
+}
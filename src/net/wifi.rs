@@ -0,0 +1,445 @@
+// Wi-Fi via EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL, with EFI_SUPPLICANT_PROTOCOL doing WPA2 authentication.
+// Once an adapter is connected the firmware's network stack runs over it like it would over Ethernet, so sockets,
+// DHCP and so on work as usual. Scanning and connecting block until the driver is done, which can take seconds.
+
+use ffi::{
+    wifi::{
+        EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL,
+        EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID,
+        EFI_80211_AKM_SUITE_SELECTOR,
+        EFI_80211_CIPHER_SUITE_SELECTOR,
+        EFI_80211_CONNECT_NETWORK_DATA,
+        EFI_80211_CONNECT_NETWORK_TOKEN,
+        EFI_80211_DISCONNECT_NETWORK_TOKEN,
+        EFI_80211_GET_NETWORKS_DATA,
+        EFI_80211_GET_NETWORKS_TOKEN,
+        EFI_80211_NETWORK,
+        EFI_80211_SSID,
+        EFI_80211_SUITE_SELECTOR,
+        EFI_MAX_SSID_LEN,
+        IeeeInfrastructureBSS,
+        ConnectSuccess,
+        ConnectRefused,
+        ConnectFailureTimeout,
+    },
+    supplicant::{EFI_SUPPLICANT_PROTOCOL, EFI_SUPPLICANT_PROTOCOL_GUID, EFI_SUPPLICANT_DATA_TYPE},
+    supplicant::{Ieee80211UnauthenticatedUnassociated, Ieee80211AuthenticatedUnassociated, Ieee80211PendingRSNAuthentication, Ieee80211AuthenticatedAssociated},
+    boot_services::{EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL, TPL_CALLBACK},
+    EFI_EVENT,
+    EFI_HANDLE,
+    UINTN,
+    VOID,
+};
+use boxed::EfiBox;
+use boot_services::locate_handles;
+use {Result, EfiErrorKind, system_table, image_handle, to_res};
+use core::{cmp::Reverse, mem, ptr, slice};
+use alloc::{string::String, vec::Vec};
+
+// How long the driver gets to associate and authenticate before giving up
+const CONNECT_TIMEOUT_SECS: u32 = 20;
+
+// Suites are identified by the IEEE 802.11 OUI 00-0F-AC and a type
+const IEEE_OUI: [u8; 3] = [0x00, 0x0f, 0xac];
+const AKM_8021X: u8 = 1;
+const AKM_PSK: u8 = 2;
+const AKM_8021X_SHA256: u8 = 5;
+const AKM_PSK_SHA256: u8 = 6;
+const AKM_SAE: u8 = 8;
+const CIPHER_CCMP: u8 = 4;
+
+/// How a network authenticates clients, judging by the key management suites it advertises
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Security {
+    Open,
+    /// WPA2 with a pre-shared key i.e. a password
+    Wpa2Personal,
+    /// WPA3 SAE. Networks in transition mode that also allow WPA2 are reported as Wpa2Personal
+    Wpa3Personal,
+    /// 802.1X, which needs EAP credentials
+    Enterprise,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    /// Not necessarily UTF-8
+    pub ssid: Vec<u8>,
+    pub security: Security,
+    /// Signal quality from 0 to 100
+    pub quality: u8,
+}
+
+impl Network {
+    /// The SSID for showing to users
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.ssid).into_owned()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkState {
+    Disconnected,
+    /// Associated but not done with WPA authentication
+    Authenticating,
+    Connected,
+    Unknown(u32),
+}
+
+/// A wireless NIC
+pub struct WifiAdapter {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL,
+}
+
+impl WifiAdapter {
+    pub fn all() -> Result<Vec<Self>> {
+        let mut adapters = Vec::new();
+        for handle in locate_handles(&EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID)? {
+            let protocol = open_protocol::<EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL>(handle, &EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID)?;
+            adapters.push(WifiAdapter { handle, protocol });
+        }
+        Ok(adapters)
+    }
+
+    /// The NIC's handle
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// The networks in range, best signal first
+    pub fn scan(&self) -> Result<Vec<Network>> {
+        let event = Completion::new()?;
+        let mut data = EFI_80211_GET_NETWORKS_DATA { NumOfSSID: 0, SSIDList: [EFI_80211_SSID { SSIdLen: 0, SSId: [0; EFI_MAX_SSID_LEN] }] };
+        let mut token = EFI_80211_GET_NETWORKS_TOKEN { Event: event.0, Status: 0, Data: &mut data, Result: ptr::null_mut() };
+        unsafe {
+            ret_on_err!(((*self.protocol).GetNetworks)(self.protocol, &mut token));
+        }
+        event.wait()?;
+        to_res((), token.Status)?;
+        if token.Result.is_null() {
+            return Ok(Vec::new());
+        }
+
+        // The suite lists the descriptions point at are left alone, as drivers differ on whether they're part of
+        // the result's allocation
+        let result = unsafe { EfiBox::from_raw(token.Result) };
+        let descriptions = unsafe { slice::from_raw_parts(result.NetworkDesc.as_ptr(), result.NumOfNetworkDesc as usize) };
+        let mut networks = descriptions.iter()
+            .filter(|d| d.Network.BSSType == IeeeInfrastructureBSS)
+            .map(|d| Network {
+                ssid: ssid_bytes(&d.Network.SSId).to_vec(),
+                security: unsafe { security(d.Network.AKMSuite) },
+                quality: d.NetworkQuality.min(100),
+            })
+            .collect::<Vec<_>>();
+        networks.sort_by_key(|n| Reverse(n.quality));
+        Ok(networks)
+    }
+
+    /// Connects to a network, with WPA2-PSK if there's a password and without security if not. Fails with
+    /// AccessDenied if the network turns us away e.g. because the password is wrong, and with Timeout if it
+    /// doesn't answer
+    pub fn connect(&self, ssid: &[u8], password: Option<&str>) -> Result<()> {
+        if ssid.is_empty() || ssid.len() > EFI_MAX_SSID_LEN {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let mut target = EFI_80211_SSID { SSIdLen: ssid.len() as u8, SSId: [0; EFI_MAX_SSID_LEN] };
+        target.SSId[..ssid.len()].copy_from_slice(ssid);
+
+        let mut akm = EFI_80211_AKM_SUITE_SELECTOR { AKMSuiteCount: 1, AKMSuiteList: [suite(AKM_PSK)] };
+        let mut cipher = EFI_80211_CIPHER_SUITE_SELECTOR { CipherSuiteCount: 1, CipherSuiteList: [suite(CIPHER_CCMP)] };
+        let mut network = EFI_80211_NETWORK { BSSType: IeeeInfrastructureBSS, SSId: target, AKMSuite: ptr::null_mut(), CipherSuite: ptr::null_mut() };
+
+        if let Some(password) = password {
+            // 8 to 63 printable characters, from which the supplicant derives the key
+            if password.len() < 8 || password.len() > 63 || !password.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+                return Err(EfiErrorKind::InvalidParameter.into());
+            }
+            let supplicant = self.supplicant()?;
+            let mut password = password.as_bytes().to_vec();
+            password.push(0);
+            set_data(supplicant, EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211AKMSuite, &akm.AKMSuiteList[0])?;
+            set_data(supplicant, EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211PairwiseCipherSuite, &cipher.CipherSuiteList[0])?;
+            set_data(supplicant, EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211TargetSSIDName, &target)?;
+            set_data(supplicant, EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211PskPassword, &password[..])?;
+            network.AKMSuite = &mut akm;
+            network.CipherSuite = &mut cipher;
+        }
+
+        let event = Completion::new()?;
+        let mut data = EFI_80211_CONNECT_NETWORK_DATA { Network: &mut network, FailureTimeout: CONNECT_TIMEOUT_SECS };
+        let mut token = EFI_80211_CONNECT_NETWORK_TOKEN { Event: event.0, Status: 0, Data: &mut data, ResultCode: ConnectSuccess };
+        unsafe {
+            ret_on_err!(((*self.protocol).ConnectNetwork)(self.protocol, &mut token));
+        }
+        event.wait()?;
+        to_res((), token.Status)?;
+        let code = token.ResultCode;
+        if code == ConnectSuccess {
+            Ok(())
+        } else if code == ConnectRefused {
+            Err(EfiErrorKind::AccessDenied.into())
+        } else if code == ConnectFailureTimeout {
+            Err(EfiErrorKind::Timeout.into())
+        } else {
+            Err(EfiErrorKind::NoResponse.into())
+        }
+    }
+
+    pub fn disconnect(&self) -> Result<()> {
+        let event = Completion::new()?;
+        let mut token = EFI_80211_DISCONNECT_NETWORK_TOKEN { Event: event.0, Status: 0 };
+        unsafe {
+            ret_on_err!(((*self.protocol).DisconnectNetwork)(self.protocol, &mut token));
+        }
+        event.wait()?;
+        to_res((), token.Status)
+    }
+
+    /// Whether the adapter is connected, as far as the supplicant knows. Fails with Unsupported if there's no
+    /// supplicant for the adapter
+    pub fn link_state(&self) -> Result<LinkState> {
+        let supplicant = self.supplicant()?;
+        let mut state = 0u32;
+        let mut size: UINTN = mem::size_of::<u32>();
+        unsafe {
+            ret_on_err!(((*supplicant).GetData)(supplicant, EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211LinkState, &mut state as *mut u32 as *mut u8, &mut size));
+        }
+        Ok(if state == Ieee80211UnauthenticatedUnassociated || state == Ieee80211AuthenticatedUnassociated {
+            LinkState::Disconnected
+        } else if state == Ieee80211PendingRSNAuthentication {
+            LinkState::Authenticating
+        } else if state == Ieee80211AuthenticatedAssociated {
+            LinkState::Connected
+        } else {
+            LinkState::Unknown(state)
+        })
+    }
+
+    // The supplicant is installed on the NIC's handle alongside the connection protocol
+    fn supplicant(&self) -> Result<*const EFI_SUPPLICANT_PROTOCOL> {
+        open_protocol(self.handle, &EFI_SUPPLICANT_PROTOCOL_GUID)
+            .map_err(|_| EfiErrorKind::Unsupported.into())
+    }
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &::ffi::EFI_GUID) -> Result<*const T> {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+    }
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+    Ok(protocol)
+}
+
+fn set_data<T: ?Sized>(supplicant: *const EFI_SUPPLICANT_PROTOCOL, data_type: EFI_SUPPLICANT_DATA_TYPE, data: &T) -> Result<()> {
+    unsafe {
+        ret_on_err!(((*supplicant).SetData)(supplicant, data_type, data as *const T as *const VOID, mem::size_of_val(data)));
+    }
+    Ok(())
+}
+
+fn suite(suite_type: u8) -> EFI_80211_SUITE_SELECTOR {
+    EFI_80211_SUITE_SELECTOR { Oui: IEEE_OUI, SuiteType: suite_type }
+}
+
+fn ssid_bytes(ssid: &EFI_80211_SSID) -> &[u8] {
+    &ssid.SSId[..(ssid.SSIdLen as usize).min(EFI_MAX_SSID_LEN)]
+}
+
+unsafe fn security(akm: *const EFI_80211_AKM_SUITE_SELECTOR) -> Security {
+    if akm.is_null() || (*akm).AKMSuiteCount == 0 {
+        return Security::Open;
+    }
+    let suites = slice::from_raw_parts((*akm).AKMSuiteList.as_ptr(), (*akm).AKMSuiteCount as usize);
+    let has = |types: &[u8]| suites.iter().any(|s| s.Oui == IEEE_OUI && types.contains(&s.SuiteType));
+    if has(&[AKM_PSK, AKM_PSK_SHA256]) {
+        Security::Wpa2Personal
+    } else if has(&[AKM_SAE]) {
+        Security::Wpa3Personal
+    } else if has(&[AKM_8021X, AKM_8021X_SHA256]) {
+        Security::Enterprise
+    } else {
+        Security::Other
+    }
+}
+
+// An event for the driver to signal when an asynchronous call is done. Closed on drop
+struct Completion(EFI_EVENT);
+
+impl Completion {
+    fn new() -> Result<Self> {
+        let mut event = ptr::null();
+        unsafe {
+            ret_on_err!(((*system_table().BootServices).CreateEvent)(0, TPL_CALLBACK, None, ptr::null(), &mut event));
+        }
+        Ok(Completion(event))
+    }
+
+    fn wait(&self) -> Result<()> {
+        let mut index = 0;
+        unsafe {
+            ret_on_err!(((*system_table().BootServices).WaitForEvent)(1, &self.0, &mut index));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        unsafe { ((*system_table().BootServices).CloseEvent)(self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{
+        wifi::{EFI_80211_GET_NETWORKS_RESULT, EFI_80211_NETWORK_DESCRIPTION},
+        supplicant::{EFI_SUPPLICANT_CRYPT_MODE, EFI_SUPPLICANT_FRAGMENT_DATA},
+        boot_services::EFI_INTERFACE_TYPE,
+        EFI_STATUS,
+        EFI_SUCCESS,
+        EFI_UNSUPPORTED,
+        UINT8,
+        UINT32,
+    };
+    use testing::mock;
+    use core::cell::RefCell;
+    use alloc::boxed::Box;
+
+    // An access point called "home" with password "correct horse" and an open one called "cafe"
+    #[repr(C)]
+    struct FakeAdapter {
+        protocol: EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL,
+        supplicant: EFI_SUPPLICANT_PROTOCOL,
+        password: RefCell<Vec<u8>>,
+        linked: RefCell<bool>,
+    }
+
+    fn adapter<'a>(this: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL) -> &'a FakeAdapter {
+        unsafe { &*(this as *const FakeAdapter) }
+    }
+
+    fn supplicant_adapter<'a>(this: *const EFI_SUPPLICANT_PROTOCOL) -> &'a FakeAdapter {
+        unsafe { &*((this as usize - mem::size_of::<EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL>()) as *const FakeAdapter) }
+    }
+
+    fn signal(event: EFI_EVENT) {
+        unsafe { ((*system_table().BootServices).SignalEvent)(event) };
+    }
+
+    fn description(name: &[u8], akm: *mut EFI_80211_AKM_SUITE_SELECTOR, quality: u8) -> EFI_80211_NETWORK_DESCRIPTION {
+        let mut ssid = EFI_80211_SSID { SSIdLen: name.len() as u8, SSId: [0; EFI_MAX_SSID_LEN] };
+        ssid.SSId[..name.len()].copy_from_slice(name);
+        EFI_80211_NETWORK_DESCRIPTION {
+            Network: EFI_80211_NETWORK { BSSType: IeeeInfrastructureBSS, SSId: ssid, AKMSuite: akm, CipherSuite: ptr::null_mut() },
+            NetworkQuality: quality,
+        }
+    }
+
+    extern "efiapi" fn get_networks(_this: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL, token: *mut EFI_80211_GET_NETWORKS_TOKEN) -> EFI_STATUS {
+        let psk = Box::leak(Box::new(EFI_80211_AKM_SUITE_SELECTOR { AKMSuiteCount: 1, AKMSuiteList: [suite(AKM_PSK)] }));
+        let size = mem::size_of::<EFI_80211_GET_NETWORKS_RESULT>() + mem::size_of::<EFI_80211_NETWORK_DESCRIPTION>();
+        unsafe {
+            let result = EfiBox::<EFI_80211_GET_NETWORKS_RESULT>::allocate(size).unwrap().into_raw();
+            (*result).NumOfNetworkDesc = 2;
+            let descriptions = (*result).NetworkDesc.as_mut_ptr();
+            ptr::write(descriptions, description(b"cafe", ptr::null_mut(), 40));
+            ptr::write(descriptions.add(1), description(b"home", psk, 80));
+            (*token).Result = result;
+            (*token).Status = EFI_SUCCESS;
+            signal((*token).Event);
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn connect_network(this: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL, token: *mut EFI_80211_CONNECT_NETWORK_TOKEN) -> EFI_STATUS {
+        let adapter = adapter(this);
+        unsafe {
+            let network = &*(*(*token).Data).Network;
+            let ok = match ssid_bytes(&network.SSId) {
+                b"home" => !network.AKMSuite.is_null() && *adapter.password.borrow() == b"correct horse\0",
+                b"cafe" => network.AKMSuite.is_null(),
+                _ => false,
+            };
+            *adapter.linked.borrow_mut() = ok;
+            (*token).ResultCode = if ok { ConnectSuccess } else { ConnectRefused };
+            (*token).Status = EFI_SUCCESS;
+            signal((*token).Event);
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn disconnect_network(this: *const EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL, token: *mut EFI_80211_DISCONNECT_NETWORK_TOKEN) -> EFI_STATUS {
+        *adapter(this).linked.borrow_mut() = false;
+        unsafe {
+            (*token).Status = EFI_SUCCESS;
+            signal((*token).Event);
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn build_response_packet(_this: *const EFI_SUPPLICANT_PROTOCOL, _request: *const UINT8, _request_size: UINTN, _buffer: *mut UINT8, _size: *mut UINTN) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn process_packet(_this: *const EFI_SUPPLICANT_PROTOCOL, _fragments: *mut *mut EFI_SUPPLICANT_FRAGMENT_DATA, _count: *mut UINT32, _mode: EFI_SUPPLICANT_CRYPT_MODE) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn fake_set_data(this: *const EFI_SUPPLICANT_PROTOCOL, data_type: EFI_SUPPLICANT_DATA_TYPE, data: *const VOID, size: UINTN) -> EFI_STATUS {
+        if data_type == EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211PskPassword {
+            *supplicant_adapter(this).password.borrow_mut() = unsafe { slice::from_raw_parts(data as *const u8, size) }.to_vec();
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn fake_get_data(this: *const EFI_SUPPLICANT_PROTOCOL, data_type: EFI_SUPPLICANT_DATA_TYPE, data: *mut UINT8, _size: *mut UINTN) -> EFI_STATUS {
+        if data_type != EFI_SUPPLICANT_DATA_TYPE::EfiSupplicant80211LinkState {
+            return EFI_UNSUPPORTED;
+        }
+        let state = if *supplicant_adapter(this).linked.borrow() { Ieee80211AuthenticatedAssociated } else { Ieee80211UnauthenticatedUnassociated };
+        unsafe { *(data as *mut u32) = state };
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn scans_and_connects() {
+        mock::install();
+        let fake: &'static FakeAdapter = Box::leak(Box::new(FakeAdapter {
+            protocol: EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL { GetNetworks: get_networks, ConnectNetwork: connect_network, DisconnectNetwork: disconnect_network },
+            supplicant: EFI_SUPPLICANT_PROTOCOL {
+                BuildResponsePacket: build_response_packet,
+                ProcessPacket: process_packet,
+                SetData: fake_set_data,
+                GetData: fake_get_data,
+            },
+            password: RefCell::new(Vec::new()),
+            linked: RefCell::new(false),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        let bs = system_table().BootServices;
+        unsafe {
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, &fake.protocol as *const _ as *const VOID);
+            ((*bs).InstallProtocolInterface)(&mut handle, &EFI_SUPPLICANT_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, &fake.supplicant as *const _ as *const VOID);
+        }
+        let wifi = WifiAdapter::all().unwrap().into_iter().find(|a| a.handle() == handle).unwrap();
+
+        let networks = wifi.scan().unwrap();
+        assert_eq!(networks.iter().map(|n| (n.name(), n.security, n.quality)).collect::<Vec<_>>(),
+            [("home".into(), Security::Wpa2Personal, 80), ("cafe".into(), Security::Open, 40)]);
+
+        assert_eq!(wifi.connect(b"home", Some("wrong password")).unwrap_err().kind(), EfiErrorKind::AccessDenied);
+        assert_eq!(wifi.connect(b"home", Some("short")).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        assert_eq!(wifi.link_state().unwrap(), LinkState::Disconnected);
+        wifi.connect(b"home", Some("correct horse")).unwrap();
+        assert_eq!(wifi.link_state().unwrap(), LinkState::Connected);
+        wifi.disconnect().unwrap();
+        wifi.connect(b"cafe", None).unwrap();
+    }
+}
@@ -0,0 +1,556 @@
+// TCG Opal self-encrypting drives.
+//
+// Level 0 discovery says what the drive supports and which ComID to talk to it on. Everything else is method calls
+// in sessions with one of its security providers (SPs): the Admin SP, which owns the drive as a whole and whose SID
+// authority takes ownership, and the Locking SP, which once activated has the locking ranges and the shadow MBR.
+// Calls go in a ComPacket/Packet/SubPacket envelope sent with IF-SEND; the response is fetched with IF-RECV, polling
+// until the drive has one.
+//
+// Passwords go to the drive as given. Tools that hash them first (sedutil uses PBKDF2 over the serial number) have
+// to be matched by the caller.
+
+pub mod token;
+pub mod uid;
+
+use self::token::{Token, Value, END_OF_DATA, END_OF_SESSION, CALL};
+use self::uid::Uid;
+use storage_security::{StorageSecurity, PROTOCOL_TCG};
+use firmware::firmware;
+use byteorder::{ByteOrder, BigEndian};
+use {Result, EfiErrorKind};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+pub const FEATURE_TPER: u16 = 0x0001;
+pub const FEATURE_LOCKING: u16 = 0x0002;
+pub const FEATURE_ENTERPRISE: u16 = 0x0100;
+pub const FEATURE_OPAL1: u16 = 0x0200;
+pub const FEATURE_OPAL2: u16 = 0x0203;
+pub const FEATURE_OPALITE: u16 = 0x0301;
+pub const FEATURE_PYRITE1: u16 = 0x0302;
+pub const FEATURE_PYRITE2: u16 = 0x0303;
+pub const FEATURE_RUBY: u16 = 0x0304;
+
+// Locking table columns
+pub const RANGE_START: u64 = 3;
+pub const RANGE_LENGTH: u64 = 4;
+pub const READ_LOCK_ENABLED: u64 = 5;
+pub const WRITE_LOCK_ENABLED: u64 = 6;
+pub const READ_LOCKED: u64 = 7;
+pub const WRITE_LOCKED: u64 = 8;
+// C_PIN and MBRControl columns
+pub const PIN: u64 = 3;
+pub const MBR_ENABLE: u64 = 1;
+pub const MBR_DONE: u64 = 2;
+
+const LEVEL0_DISCOVERY_COMID: u16 = 0x0001;
+const LEVEL0_HEADER_SIZE: usize = 48;
+const COM_PACKET_HEADER_SIZE: usize = 20;
+const PACKET_HEADER_SIZE: usize = 24;
+const SUB_PACKET_HEADER_SIZE: usize = 12;
+const PAYLOAD_OFFSET: usize = COM_PACKET_HEADER_SIZE + PACKET_HEADER_SIZE + SUB_PACKET_HEADER_SIZE;
+const BUFFER_SIZE: usize = 2048; // The smallest MaxComPacketSize a drive may have
+const HOST_SESSION_ID: u32 = 1; // One session at a time, so it doesn't need to be unique
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const CLOSE_SESSION: Uid = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06];
+
+/// Which security subsystem class the drive implements
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ssc {
+    Enterprise,
+    Opal1,
+    Opal2,
+    Opalite,
+    Pyrite1,
+    Pyrite2,
+    Ruby,
+}
+
+/// The locking feature descriptor's flags
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct LockingFeature {
+    pub supported: bool,
+    /// The Locking SP has been activated
+    pub enabled: bool,
+    /// At least one range is locked
+    pub locked: bool,
+    pub media_encryption: bool,
+    pub mbr_enabled: bool,
+    pub mbr_done: bool,
+}
+
+/// What level 0 discovery found
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Discovery {
+    pub ssc: Option<Ssc>,
+    pub base_comid: u16,
+    pub comid_count: u16,
+    pub locking: Option<LockingFeature>,
+    /// The code of every feature descriptor, including ones we don't decode
+    pub features: Vec<u16>,
+}
+
+impl Discovery {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < LEVEL0_HEADER_SIZE {
+            return Err(EfiErrorKind::ProtocolError.into());
+        }
+        let len = (BigEndian::read_u32(data) as usize + 4).min(data.len()); // Not counting the length itself
+        let mut discovery = Discovery::default();
+        let mut features = &data[LEVEL0_HEADER_SIZE..len];
+        while features.len() >= 4 {
+            let code = BigEndian::read_u16(features);
+            let feature = match features.get(4..4 + features[3] as usize) {
+                Some(feature) => feature,
+                None => break,
+            };
+            discovery.features.push(code);
+            let ssc = match code {
+                FEATURE_LOCKING if !feature.is_empty() => {
+                    let flags = feature[0];
+                    discovery.locking = Some(LockingFeature {
+                        supported: flags & 0x01 != 0,
+                        enabled: flags & 0x02 != 0,
+                        locked: flags & 0x04 != 0,
+                        media_encryption: flags & 0x08 != 0,
+                        mbr_enabled: flags & 0x10 != 0,
+                        mbr_done: flags & 0x20 != 0,
+                    });
+                    None
+                }
+                FEATURE_ENTERPRISE => Some(Ssc::Enterprise),
+                FEATURE_OPAL1 => Some(Ssc::Opal1),
+                FEATURE_OPAL2 => Some(Ssc::Opal2),
+                FEATURE_OPALITE => Some(Ssc::Opalite),
+                FEATURE_PYRITE1 => Some(Ssc::Pyrite1),
+                FEATURE_PYRITE2 => Some(Ssc::Pyrite2),
+                FEATURE_RUBY => Some(Ssc::Ruby),
+                _ => None,
+            };
+            // All the SSC descriptors start with the base ComID and how many there are
+            if let (Some(ssc), None, true) = (ssc, discovery.ssc, feature.len() >= 4) {
+                discovery.ssc = Some(ssc);
+                discovery.base_comid = BigEndian::read_u16(feature);
+                discovery.comid_count = BigEndian::read_u16(&feature[2..]);
+            }
+            features = &features[4 + feature.len()..];
+        }
+        Ok(discovery)
+    }
+}
+
+/// A method call, encoded ready to go in a subpacket
+#[derive(Debug, Clone)]
+pub struct MethodCall {
+    invoking: Uid,
+    method: Uid,
+    args: Vec<Value>,
+}
+
+impl MethodCall {
+    pub fn new(invoking: Uid, method: Uid) -> Self {
+        MethodCall { invoking, method, args: Vec::new() }
+    }
+
+    /// Reads columns `first` to `last` of a table row
+    pub fn get(object: Uid, first: u64, last: u64) -> Self {
+        Self::new(object, uid::GET).arg(Value::List(vec![Value::named(3, first.into()), Value::named(4, last.into())]))
+    }
+
+    /// Writes columns of a table row
+    pub fn set(object: Uid, columns: &[(u64, Value)]) -> Self {
+        let values = columns.iter().map(|&(column, ref value)| Value::named(column, value.clone())).collect();
+        Self::new(object, uid::SET).named(1, Value::List(values))
+    }
+
+    pub fn arg<V: Into<Value>>(mut self, value: V) -> Self {
+        self.args.push(value.into());
+        self
+    }
+
+    /// Adds an optional argument, which go after the required ones
+    pub fn named<V: Into<Value>>(mut self, name: u64, value: V) -> Self {
+        self.args.push(Value::named(name, value.into()));
+        self
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![CALL];
+        token::encode_bytes(&self.invoking, &mut out);
+        token::encode_bytes(&self.method, &mut out);
+        Value::List(self.args.clone()).encode(&mut out);
+        out.push(END_OF_DATA);
+        Value::List(vec![0.into(), 0.into(), 0.into()]).encode(&mut out); // The status list we expect back
+        out
+    }
+}
+
+impl From<Uid> for Value {
+    fn from(uid: Uid) -> Self {
+        Value::Bytes(uid.to_vec())
+    }
+}
+
+/// A drive that speaks one of the TCG SSCs
+pub struct Opal {
+    device: StorageSecurity,
+    discovery: Discovery,
+}
+
+impl Opal {
+    /// Every drive that speaks Opal or a relative
+    pub fn all() -> Result<Vec<Self>> {
+        let mut drives = Vec::new();
+        for device in StorageSecurity::all()? {
+            if device.supported_protocols().map(|p| p.contains(&PROTOCOL_TCG)).unwrap_or(false) {
+                if let Ok(drive) = Self::new(device) {
+                    drives.push(drive);
+                }
+            }
+        }
+        Ok(drives)
+    }
+
+    /// Fails with Unsupported if level 0 discovery doesn't find an SSC
+    pub fn new(device: StorageSecurity) -> Result<Self> {
+        let discovery = discover(&device)?;
+        if discovery.ssc.is_none() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        Ok(Opal { device, discovery })
+    }
+
+    pub fn device(&self) -> &StorageSecurity {
+        &self.device
+    }
+
+    /// Discovery as of when we opened the drive
+    pub fn discovery(&self) -> &Discovery {
+        &self.discovery
+    }
+
+    /// Runs discovery again, e.g. to see whether the locking SP is enabled now
+    pub fn locking(&self) -> Result<Option<LockingFeature>> {
+        Ok(discover(&self.device)?.locking)
+    }
+
+    /// Starts building a session with the given SP
+    pub fn session(&self, sp: Uid) -> SessionBuilder<'_> {
+        SessionBuilder { opal: self, sp, write: false, authority: None }
+    }
+
+    /// The manufactured SID password, which is what SID's password is until someone takes ownership
+    pub fn msid(&self) -> Result<Vec<u8>> {
+        let mut session = self.session(uid::ADMIN_SP).start()?;
+        let msid = session.get(uid::C_PIN_MSID, PIN)?;
+        session.end()?;
+        msid.as_bytes().map(|b| b.to_vec()).ok_or_else(|| EfiErrorKind::ProtocolError.into())
+    }
+
+    /// Changes SID's password from the MSID to `password`
+    pub fn take_ownership(&self, password: &[u8]) -> Result<()> {
+        let msid = self.msid()?;
+        let mut session = self.session(uid::ADMIN_SP).write().authority(uid::SID, &msid).start()?;
+        session.set(uid::C_PIN_SID, &[(PIN, password.into())])?;
+        session.end()
+    }
+
+    /// Activates the Locking SP. Its Admin1 gets SID's password
+    pub fn activate_locking(&self, sid_password: &[u8]) -> Result<()> {
+        let mut session = self.session(uid::ADMIN_SP).write().authority(uid::SID, sid_password).start()?;
+        session.call(&MethodCall::new(uid::LOCKING_SP, uid::ACTIVATE))?;
+        session.end()
+    }
+
+    /// Sets where a locking range is and which kinds of lock it honours. The global range (0) has no start or length
+    pub fn configure_range(&self, admin_password: &[u8], range: u8, config: &RangeConfig) -> Result<()> {
+        let mut columns = Vec::new();
+        if range != 0 {
+            columns.push((RANGE_START, config.start.into()));
+            columns.push((RANGE_LENGTH, config.length.into()));
+        }
+        columns.push((READ_LOCK_ENABLED, config.read_lock_enabled.into()));
+        columns.push((WRITE_LOCK_ENABLED, config.write_lock_enabled.into()));
+        self.with_admin(admin_password, |session| session.set(uid::locking_range(range), &columns))
+    }
+
+    /// Locks or unlocks a range for both reading and writing
+    pub fn set_locked(&self, admin_password: &[u8], range: u8, locked: bool) -> Result<()> {
+        let columns = [(READ_LOCKED, locked.into()), (WRITE_LOCKED, locked.into())];
+        self.with_admin(admin_password, |session| session.set(uid::locking_range(range), &columns))
+    }
+
+    /// Marks the shadow MBR done, so the drive shows the real start of the disk until the next power cycle. Part of
+    /// unlocking a drive that boots a pre-boot authentication image from the shadow MBR
+    pub fn set_mbr_done(&self, admin_password: &[u8], done: bool) -> Result<()> {
+        self.with_admin(admin_password, |session| session.set(uid::MBR_CONTROL, &[(MBR_DONE, done.into())]))
+    }
+
+    /// Unlocks a range and, if the shadow MBR is on, hides it
+    pub fn unlock(&self, admin_password: &[u8], range: u8) -> Result<()> {
+        self.set_locked(admin_password, range, false)?;
+        match self.locking()? {
+            Some(ref locking) if locking.mbr_enabled && !locking.mbr_done => self.set_mbr_done(admin_password, true),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reverts the Admin SP to factory state as SID or PSID (the password printed on the drive's label), which
+    /// throws away the media encryption keys and with them everything on the drive. SID's password goes back to the
+    /// MSID. The drive ends the session itself
+    pub fn revert(&self, authority: Uid, password: &[u8]) -> Result<()> {
+        let mut session = self.session(uid::ADMIN_SP).write().authority(authority, password).start()?;
+        session.call(&MethodCall::new(uid::ADMIN_SP, uid::REVERT))?;
+        let _ = session.end();
+        Ok(())
+    }
+
+    fn with_admin<F: FnOnce(&mut Session<'_>) -> Result<()>>(&self, password: &[u8], f: F) -> Result<()> {
+        let mut session = self.session(uid::LOCKING_SP).write().authority(uid::admin(1), password).start()?;
+        f(&mut session)?;
+        session.end()
+    }
+
+    // Sends a subpacket and waits for the response subpacket
+    fn exchange(&self, tsn: u32, hsn: u32, payload: &[u8]) -> Result<Vec<u8>> {
+        let comid = self.discovery.base_comid;
+        self.device.send(PROTOCOL_TCG, comid, &frame(comid, tsn, hsn, payload), TIMEOUT)?;
+        let mut buf = vec![0u8; BUFFER_SIZE];
+        let attempts = (TIMEOUT.as_millis() / POLL_INTERVAL.as_millis()) as usize;
+        for _ in 0..attempts {
+            let len = self.device.receive(PROTOCOL_TCG, comid, &mut buf, TIMEOUT)?;
+            if let Some(payload) = unframe(&buf[..len])? {
+                return Ok(payload.to_vec());
+            }
+            firmware().stall(POLL_INTERVAL)?;
+        }
+        Err(EfiErrorKind::Timeout.into())
+    }
+}
+
+/// Where a locking range is and what it locks
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct RangeConfig {
+    /// In logical blocks
+    pub start: u64,
+    pub length: u64,
+    pub read_lock_enabled: bool,
+    pub write_lock_enabled: bool,
+}
+
+pub struct SessionBuilder<'a> {
+    opal: &'a Opal,
+    sp: Uid,
+    write: bool,
+    authority: Option<(Uid, Vec<u8>)>,
+}
+
+impl<'a> SessionBuilder<'a> {
+    /// Sessions are read only unless asked otherwise
+    pub fn write(mut self) -> Self {
+        self.write = true;
+        self
+    }
+
+    /// Signs the session in as `authority`. Without one it's Anybody, who can read only public things like the MSID
+    pub fn authority(mut self, authority: Uid, password: &[u8]) -> Self {
+        self.authority = Some((authority, password.to_vec()));
+        self
+    }
+
+    pub fn start(self) -> Result<Session<'a>> {
+        let mut call = MethodCall::new(uid::SMUID, uid::START_SESSION).arg(HOST_SESSION_ID as u64).arg(self.sp).arg(self.write);
+        if let Some((authority, password)) = self.authority {
+            call = call.named(0, &password[..]).named(3, authority);
+        }
+        // Session manager calls go outside of any session
+        let response = self.opal.exchange(0, 0, &call.encode())?;
+        let results = parse_response(&response)?;
+        let sync = results.first().and_then(Value::as_list).ok_or(EfiErrorKind::ProtocolError)?;
+        let tsn = sync.get(1).and_then(Value::as_uint).ok_or(EfiErrorKind::ProtocolError)?;
+        Ok(Session { opal: self.opal, tsn: tsn as u32, hsn: HOST_SESSION_ID, open: true })
+    }
+}
+
+/// An open session. Ended when dropped, ignoring errors; end() reports them
+pub struct Session<'a> {
+    opal: &'a Opal,
+    tsn: u32,
+    hsn: u32,
+    open: bool,
+}
+
+impl<'a> Session<'a> {
+    /// Calls a method, returning its results. A non-zero status fails with the closest EFI error
+    pub fn call(&mut self, call: &MethodCall) -> Result<Vec<Value>> {
+        let response = self.opal.exchange(self.tsn, self.hsn, &call.encode())?;
+        let results = parse_response(&response)?;
+        match results.into_iter().next() {
+            Some(Value::List(results)) => Ok(results),
+            _ => Err(EfiErrorKind::ProtocolError.into()),
+        }
+    }
+
+    pub fn get(&mut self, object: Uid, column: u64) -> Result<Value> {
+        let results = self.call(&MethodCall::get(object, column, column))?;
+        results.first().and_then(|row| row.find(column)).cloned().ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    pub fn set(&mut self, object: Uid, columns: &[(u64, Value)]) -> Result<()> {
+        self.call(&MethodCall::set(object, columns)).map(|_| ())
+    }
+
+    /// Adds another authority to the session. Fails with AccessDenied if the password is wrong
+    pub fn authenticate(&mut self, authority: Uid, password: &[u8]) -> Result<()> {
+        let results = self.call(&MethodCall::new(uid::THIS_SP, uid::AUTHENTICATE).arg(authority).named(0, password))?;
+        match results.first().and_then(Value::as_uint) {
+            Some(1) => Ok(()),
+            _ => Err(EfiErrorKind::AccessDenied.into()),
+        }
+    }
+
+    pub fn end(mut self) -> Result<()> {
+        self.open = false;
+        self.opal.exchange(self.tsn, self.hsn, &[END_OF_SESSION]).map(|_| ())
+    }
+}
+
+impl<'a> Drop for Session<'a> {
+    fn drop(&mut self) {
+        if self.open {
+            let _ = self.opal.exchange(self.tsn, self.hsn, &[END_OF_SESSION]);
+        }
+    }
+}
+
+fn discover(device: &StorageSecurity) -> Result<Discovery> {
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    let len = device.receive(PROTOCOL_TCG, LEVEL0_DISCOVERY_COMID, &mut buf, TIMEOUT)?;
+    Discovery::parse(&buf[..len])
+}
+
+// Wraps a payload in a subpacket in a packet in a ComPacket. Each level's length leaves out its own header, and the
+// subpacket is padded to a multiple of 4 bytes without counting the padding
+fn frame(comid: u16, tsn: u32, hsn: u32, payload: &[u8]) -> Vec<u8> {
+    let padded = (payload.len() + 3) & !3;
+    let mut buf = vec![0u8; PAYLOAD_OFFSET + padded];
+    BigEndian::write_u16(&mut buf[4..], comid);
+    BigEndian::write_u32(&mut buf[16..], (PACKET_HEADER_SIZE + SUB_PACKET_HEADER_SIZE + padded) as u32);
+    let packet = &mut buf[COM_PACKET_HEADER_SIZE..];
+    BigEndian::write_u32(packet, tsn);
+    BigEndian::write_u32(&mut packet[4..], hsn);
+    BigEndian::write_u32(&mut packet[20..], (SUB_PACKET_HEADER_SIZE + padded) as u32);
+    BigEndian::write_u32(&mut packet[PACKET_HEADER_SIZE + 8..], payload.len() as u32);
+    buf[PAYLOAD_OFFSET..PAYLOAD_OFFSET + payload.len()].copy_from_slice(payload);
+    buf
+}
+
+// The payload of the first subpacket. None if the drive hasn't got a response ready yet
+fn unframe(buf: &[u8]) -> Result<Option<&[u8]>> {
+    if buf.len() < COM_PACKET_HEADER_SIZE {
+        return Err(EfiErrorKind::ProtocolError.into());
+    }
+    let outstanding = BigEndian::read_u32(&buf[8..]);
+    let length = BigEndian::read_u32(&buf[16..]);
+    if length == 0 {
+        return if outstanding != 0 { Ok(None) } else { Err(EfiErrorKind::NoResponse.into()) };
+    }
+    if buf.len() < PAYLOAD_OFFSET {
+        return Err(EfiErrorKind::ProtocolError.into());
+    }
+    let len = BigEndian::read_u32(&buf[PAYLOAD_OFFSET - 4..]) as usize;
+    buf.get(PAYLOAD_OFFSET..PAYLOAD_OFFSET + len).map(Some).ok_or_else(|| EfiErrorKind::BadBufferSize.into())
+}
+
+// The results of a method call: [results] EndOfData [status 0 0]. Session manager methods come back as calls
+// themselves (SyncSession for StartSession), or as CloseSession if the drive gave up on the session
+fn parse_response(response: &[u8]) -> Result<Vec<Value>> {
+    let tokens = token::decode(response)?;
+    let mut tokens = &tokens[..];
+    if let Some(&Token::Control(END_OF_SESSION)) = tokens.first() {
+        return Ok(Vec::new());
+    }
+    if let Some(&Token::Control(CALL)) = tokens.first() {
+        match tokens.get(2) {
+            Some(Token::Bytes(method)) if method[..] == CLOSE_SESSION[..] => return Err(EfiErrorKind::Aborted.into()),
+            Some(&Token::Bytes(_)) => tokens = &tokens[3..],
+            _ => return Err(EfiErrorKind::ProtocolError.into()),
+        }
+    }
+    let (results, rest) = token::parse_values(tokens)?;
+    let status = match rest.split_first() {
+        Some((&Token::Control(END_OF_DATA), rest)) => token::parse_values(rest)?.0,
+        _ => return Err(EfiErrorKind::ProtocolError.into()),
+    };
+    match status.first().and_then(Value::as_list).and_then(|s| s.first()).and_then(Value::as_uint) {
+        Some(0) => Ok(results),
+        Some(status) => Err(status_error(status).into()),
+        None => Err(EfiErrorKind::ProtocolError.into()),
+    }
+}
+
+// Method status codes from the core spec, as the nearest EFI error
+fn status_error(status: u64) -> EfiErrorKind {
+    match status {
+        0x01 => EfiErrorKind::AccessDenied, // NOT_AUTHORIZED
+        0x03 => EfiErrorKind::NotReady, // SP_BUSY
+        0x05 => EfiErrorKind::NotStarted, // SP_DISABLED
+        0x06 => EfiErrorKind::WriteProtected, // SP_FROZEN
+        0x07 => EfiErrorKind::OutOfResources, // NO_SESSIONS_AVAILABLE
+        0x09 | 0x0a => EfiErrorKind::VolumeFull, // INSUFFICIENT_SPACE, INSUFFICIENT_ROWS
+        0x0c => EfiErrorKind::InvalidParameter,
+        0x12 => EfiErrorKind::SecurityViolation, // AUTHORITY_LOCKED_OUT
+        _ => EfiErrorKind::DeviceError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovers_and_frames() {
+        let mut level0 = vec![0u8; LEVEL0_HEADER_SIZE];
+        level0.extend_from_slice(&[0x00, 0x01, 0x10, 0x0c, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // TPer
+        level0.extend_from_slice(&[0x00, 0x02, 0x10, 0x0c, 0x19, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // Locking
+        level0.extend_from_slice(&[0x02, 0x03, 0x20, 0x10, 0x07, 0xfe, 0x00, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // Opal 2
+        let length = level0.len() as u32 - 4;
+        BigEndian::write_u32(&mut level0, length);
+        level0.resize(512, 0); // Padding past the length isn't features
+        let discovery = Discovery::parse(&level0).unwrap();
+        assert_eq!((discovery.ssc, discovery.base_comid, discovery.comid_count), (Some(Ssc::Opal2), 0x07fe, 1));
+        let locking = discovery.locking.unwrap();
+        assert!(locking.supported && !locking.enabled && !locking.locked && locking.media_encryption && locking.mbr_enabled && !locking.mbr_done);
+        assert_eq!(discovery.features, [FEATURE_TPER, FEATURE_LOCKING, FEATURE_OPAL2]);
+
+        let call = MethodCall::get(uid::C_PIN_MSID, PIN, PIN).encode();
+        let framed = frame(0x07fe, 0, 0, &call);
+        assert_eq!(framed.len() % 4, 0);
+        assert_eq!(unframe(&framed).unwrap(), Some(&call[..]));
+        let mut pending = vec![0u8; 512];
+        pending[11] = 1;
+        assert_eq!(unframe(&pending).unwrap(), None);
+
+        // SyncSession for StartSession then an MSID Get, as a drive sends them
+        let mut sync = vec![CALL];
+        token::encode_bytes(&uid::SMUID, &mut sync);
+        token::encode_bytes(&uid::SYNC_SESSION, &mut sync);
+        Value::List(vec![Value::Uint(1), Value::Uint(0x1234)]).encode(&mut sync);
+        sync.push(END_OF_DATA);
+        Value::List(vec![0.into(), 0.into(), 0.into()]).encode(&mut sync);
+        assert_eq!(parse_response(&sync).unwrap(), [Value::List(vec![Value::Uint(1), Value::Uint(0x1234)])]);
+
+        let mut get = Vec::new();
+        Value::List(vec![Value::List(vec![Value::named(PIN, Value::Bytes(b"MSIDMSID".to_vec()))])]).encode(&mut get);
+        get.push(END_OF_DATA);
+        Value::List(vec![0.into(), 0.into(), 0.into()]).encode(&mut get);
+        let results = parse_response(&get).unwrap();
+        assert_eq!(results[0].as_list().unwrap()[0].find(PIN).and_then(Value::as_bytes), Some(&b"MSIDMSID"[..]));
+
+        let last = get.len() - 4;
+        get[last] = 0x01; // NOT_AUTHORIZED
+        assert_eq!(parse_response(&get).unwrap_err().kind(), EfiErrorKind::AccessDenied);
+    }
+}
@@ -0,0 +1,256 @@
+// The TCG data stream: method calls and their results as a sequence of tokens. Values are atoms (integers or byte
+// strings, with a header saying which and how long), grouped with list and name tokens. See section 3.2.2 of the
+// TCG Storage Architecture Core Specification.
+
+use {Result, EfiErrorKind};
+use alloc::{boxed::Box, vec::Vec};
+
+pub const START_LIST: u8 = 0xf0;
+pub const END_LIST: u8 = 0xf1;
+pub const START_NAME: u8 = 0xf2;
+pub const END_NAME: u8 = 0xf3;
+pub const CALL: u8 = 0xf8;
+pub const END_OF_DATA: u8 = 0xf9;
+pub const END_OF_SESSION: u8 = 0xfa;
+pub const START_TRANSACTION: u8 = 0xfb;
+pub const END_TRANSACTION: u8 = 0xfc;
+pub const EMPTY: u8 = 0xff;
+
+/// A single token as it appears in the stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Uint(u64),
+    Int(i64),
+    Bytes(Vec<u8>),
+    /// One of the control tokens above
+    Control(u8),
+}
+
+/// Tokens grouped into what they mean
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Uint(u64),
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    /// A name (usually a column number) and its value
+    Named(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    pub fn named(name: u64, value: Value) -> Self {
+        Value::Named(Box::new(Value::Uint(name)), Box::new(value))
+    }
+
+    pub fn as_uint(&self) -> Option<u64> {
+        match *self {
+            Value::Uint(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Bytes(ref bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match *self {
+            Value::List(ref list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// The value named `name` in a list of named values, the way Get returns columns
+    pub fn find(&self, name: u64) -> Option<&Value> {
+        self.as_list()?.iter().filter_map(|v| match *v {
+            Value::Named(ref n, ref value) if n.as_uint() == Some(name) => Some(&**value),
+            _ => None,
+        }).next()
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            Value::Uint(n) => encode_uint(n, out),
+            Value::Int(n) => encode_int(n, out),
+            Value::Bytes(ref bytes) => encode_bytes(bytes, out),
+            Value::List(ref list) => {
+                out.push(START_LIST);
+                for value in list {
+                    value.encode(out);
+                }
+                out.push(END_LIST);
+            }
+            Value::Named(ref name, ref value) => {
+                out.push(START_NAME);
+                name.encode(out);
+                value.encode(out);
+                out.push(END_NAME);
+            }
+        }
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Uint(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Uint(b as u64)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Value {
+    fn from(bytes: &'a [u8]) -> Self {
+        Value::Bytes(bytes.to_vec())
+    }
+}
+
+pub fn encode_uint(n: u64, out: &mut Vec<u8>) {
+    if n < 0x40 {
+        out.push(n as u8); // Tiny atom
+        return;
+    }
+    let bytes = n.to_be_bytes();
+    let skip = (n.leading_zeros() / 8) as usize;
+    out.push(0x80 | (8 - skip) as u8); // Short atom
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+pub fn encode_int(n: i64, out: &mut Vec<u8>) {
+    if (-32..32).contains(&n) {
+        out.push(0x40 | (n as u8 & 0x3f));
+        return;
+    }
+    out.push(0x90 | 8);
+    out.extend_from_slice(&n.to_be_bytes());
+}
+
+pub fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    let len = bytes.len();
+    if len < 16 {
+        out.push(0xa0 | len as u8);
+    } else if len < 2048 {
+        out.push(0xd0 | (len >> 8) as u8);
+        out.push(len as u8);
+    } else {
+        out.push(0xe2);
+        out.extend_from_slice(&(len as u32).to_be_bytes()[1..]);
+    }
+    out.extend_from_slice(bytes);
+}
+
+/// Splits a stream into tokens, dropping empty atoms (padding)
+pub fn decode(mut data: &[u8]) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    while let Some(&first) = data.first() {
+        let (header, len, is_bytes, signed) = match first {
+            0x00..=0x3f => { tokens.push(Token::Uint(first as u64)); data = &data[1..]; continue; }
+            0x40..=0x7f => { tokens.push(Token::Int(((first << 2) as i8 >> 2) as i64)); data = &data[1..]; continue; }
+            0x80..=0xbf => (1, (first & 0x0f) as usize, first & 0x20 != 0, first & 0x10 != 0),
+            0xc0..=0xdf => {
+                let second = *data.get(1).ok_or(EfiErrorKind::ProtocolError)?;
+                (2, ((first as usize & 0x07) << 8) | second as usize, first & 0x10 != 0, first & 0x08 != 0)
+            }
+            0xe0..=0xe3 => {
+                let len = data.get(1..4).ok_or(EfiErrorKind::ProtocolError)?;
+                (4, (len[0] as usize) << 16 | (len[1] as usize) << 8 | len[2] as usize, first & 0x02 != 0, first & 0x01 != 0)
+            }
+            EMPTY => { data = &data[1..]; continue; }
+            0xf0..=0xfe => { tokens.push(Token::Control(first)); data = &data[1..]; continue; }
+            _ => return Err(EfiErrorKind::ProtocolError.into()),
+        };
+        let atom = data.get(header..header + len).ok_or(EfiErrorKind::ProtocolError)?;
+        tokens.push(if is_bytes {
+            Token::Bytes(atom.to_vec())
+        } else if len > 8 {
+            return Err(EfiErrorKind::ProtocolError.into());
+        } else if signed {
+            let sign = if !atom.is_empty() && atom[0] & 0x80 != 0 { -1i64 } else { 0 };
+            Token::Int(atom.iter().fold(sign, |n, b| (n << 8) | *b as i64))
+        } else {
+            Token::Uint(atom.iter().fold(0, |n, b| (n << 8) | *b as u64))
+        });
+        data = &data[header + len..];
+    }
+    Ok(tokens)
+}
+
+/// Groups tokens into values. Stops at the first control token that isn't a list or name, returning the values so
+/// far and the rest of the tokens, starting with it
+pub fn parse_values(tokens: &[Token]) -> Result<(Vec<Value>, &[Token])> {
+    let mut values = Vec::new();
+    let mut rest = tokens;
+    loop {
+        match rest.first() {
+            None | Some(&Token::Control(END_LIST)) | Some(&Token::Control(END_NAME)) => return Ok((values, rest)),
+            Some(&Token::Control(c)) if c != START_LIST && c != START_NAME => return Ok((values, rest)),
+            _ => {}
+        }
+        let (value, after) = parse_value(rest)?;
+        values.push(value);
+        rest = after;
+    }
+}
+
+fn parse_value(tokens: &[Token]) -> Result<(Value, &[Token])> {
+    let (first, rest) = tokens.split_first().ok_or(EfiErrorKind::ProtocolError)?;
+    match *first {
+        Token::Uint(n) => Ok((Value::Uint(n), rest)),
+        Token::Int(n) => Ok((Value::Int(n), rest)),
+        Token::Bytes(ref bytes) => Ok((Value::Bytes(bytes.clone()), rest)),
+        Token::Control(START_LIST) => {
+            let (list, rest) = parse_values(rest)?;
+            match rest.split_first() {
+                Some((&Token::Control(END_LIST), rest)) => Ok((Value::List(list), rest)),
+                _ => Err(EfiErrorKind::ProtocolError.into()),
+            }
+        }
+        Token::Control(START_NAME) => {
+            let (name, rest) = parse_value(rest)?;
+            let (value, rest) = parse_value(rest)?;
+            match rest.split_first() {
+                Some((&Token::Control(END_NAME), rest)) => Ok((Value::Named(Box::new(name), Box::new(value)), rest)),
+                _ => Err(EfiErrorKind::ProtocolError.into()),
+            }
+        }
+        Token::Control(_) => Err(EfiErrorKind::ProtocolError.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_atoms() {
+        let value = Value::List(vec![
+            Value::Uint(5),
+            Value::Uint(0x1234),
+            Value::Int(-2),
+            Value::Bytes(vec![0xaa; 20]),
+            Value::named(3, Value::Bytes(b"MSID".to_vec())),
+        ]);
+        let mut data = Vec::new();
+        value.encode(&mut data);
+        assert_eq!(&data[..6], &[START_LIST, 0x05, 0x82, 0x12, 0x34, 0x7e]);
+        assert_eq!(&data[6..8], &[0xd0, 20]);
+        data.push(EMPTY);
+        data.push(END_OF_DATA);
+
+        let tokens = decode(&data).unwrap();
+        let (values, rest) = parse_values(&tokens).unwrap();
+        assert_eq!(values, [value]);
+        assert_eq!(rest, [Token::Control(END_OF_DATA)]);
+        assert_eq!(values[0].find(3).and_then(Value::as_bytes), Some(&b"MSID"[..]));
+
+        assert_eq!(decode(&[0x91, 0xfe]).unwrap(), [Token::Int(-2)]);
+        assert!(decode(&[0xa4, 1, 2]).is_err());
+        assert!(parse_values(&decode(&[START_LIST, 1]).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+// UIDs of the objects and methods the Opal sequences use, from the TCG Storage Architecture Core Specification and
+// the Opal SSC. Locking SP objects that come in numbered rows (ranges, users) have functions instead.
+
+pub type Uid = [u8; 8];
+
+/// The session manager, which handles StartSession outside of any session
+pub const SMUID: Uid = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff];
+/// The SP the session is with, for methods invoked on the SP itself
+pub const THIS_SP: Uid = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+pub const ADMIN_SP: Uid = [0x00, 0x00, 0x02, 0x05, 0x00, 0x00, 0x00, 0x01];
+pub const LOCKING_SP: Uid = [0x00, 0x00, 0x02, 0x05, 0x00, 0x00, 0x00, 0x02];
+
+pub const ANYBODY: Uid = [0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x01];
+pub const SID: Uid = [0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x06];
+pub const PSID: Uid = [0x00, 0x00, 0x00, 0x09, 0x00, 0x01, 0xff, 0x01];
+
+pub const C_PIN_SID: Uid = [0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x01];
+pub const C_PIN_MSID: Uid = [0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x84, 0x02];
+
+pub const LOCKING_GLOBAL_RANGE: Uid = [0x00, 0x00, 0x08, 0x02, 0x00, 0x00, 0x00, 0x01];
+pub const MBR_CONTROL: Uid = [0x00, 0x00, 0x08, 0x03, 0x00, 0x00, 0x00, 0x01];
+
+pub const START_SESSION: Uid = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x02];
+pub const SYNC_SESSION: Uid = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x03];
+pub const PROPERTIES: Uid = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x01];
+pub const GET: Uid = [0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x16];
+pub const SET: Uid = [0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x17];
+pub const AUTHENTICATE: Uid = [0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x1c];
+pub const REVERT: Uid = [0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x02, 0x02];
+pub const ACTIVATE: Uid = [0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x02, 0x03];
+pub const GEN_KEY: Uid = [0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x10];
+
+/// Admin authority `n` of the Locking SP, from 1
+pub fn admin(n: u8) -> Uid {
+    [0x00, 0x00, 0x00, 0x09, 0x00, 0x01, 0x00, n]
+}
+
+/// User authority `n` of the Locking SP, from 1
+pub fn user(n: u8) -> Uid {
+    [0x00, 0x00, 0x00, 0x09, 0x00, 0x03, 0x00, n]
+}
+
+/// The C_PIN row holding admin `n`'s password
+pub fn c_pin_admin(n: u8) -> Uid {
+    [0x00, 0x00, 0x00, 0x0b, 0x00, 0x01, 0x00, n]
+}
+
+pub fn c_pin_user(n: u8) -> Uid {
+    [0x00, 0x00, 0x00, 0x0b, 0x00, 0x03, 0x00, n]
+}
+
+/// Locking range `n`, where 0 is the global range covering whatever the others don't
+pub fn locking_range(n: u8) -> Uid {
+    if n == 0 { LOCKING_GLOBAL_RANGE } else { [0x00, 0x00, 0x08, 0x02, 0x00, 0x03, 0x00, n] }
+}
@@ -0,0 +1,246 @@
+use ffi::{
+    boot_services::{EFI_ALLOCATE_TYPE, EFI_MEMORY_TYPE, EFI_PHYSICAL_ADDRESS},
+    UINTN,
+};
+use core::{mem, slice};
+use {system_table, Result, EfiErrorKind, services::boot_services_exited};
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// The highest address that can be expressed in 32 bits.
+/// Handy for things that must be allocated below 4GiB (e.g. Linux boot params).
+pub const MAX_ADDRESS_32BIT: u64 = 0xFFFF_FFFF;
+
+/// A run of physically contiguous pages allocated via the AllocatePages boot service.
+/// The pages are freed when this object is dropped unless `leak()` is called.
+#[derive(Debug)]
+pub struct Pages {
+    addr: EFI_PHYSICAL_ADDRESS,
+    count: usize,
+}
+
+impl Pages {
+    /// Allocates `count` pages of loader data anywhere in memory
+    pub fn allocate(count: usize) -> Result<Self> {
+        PageAlloc::builder().allocate(count)
+    }
+
+    /// Allocates `count` pages of loader data such that the last byte of the allocation is at or below `max_addr`
+    pub fn allocate_below(max_addr: u64, count: usize) -> Result<Self> {
+        PageAlloc::builder().below(max_addr).allocate(count)
+    }
+
+    /// Allocates `count` pages of loader data starting exactly at `addr` which must be page aligned
+    pub fn allocate_at(addr: u64, count: usize) -> Result<Self> {
+        PageAlloc::builder().at(addr).allocate(count)
+    }
+
+    pub(crate) fn allocate_raw(alloc_type: EFI_ALLOCATE_TYPE, memory_type: EFI_MEMORY_TYPE, addr: u64, count: usize) -> Result<Self> {
+        let bs = system_table().BootServices;
+        let mut addr: EFI_PHYSICAL_ADDRESS = addr;
+        unsafe {
+            ret_on_err!(((*bs).AllocatePages)(alloc_type, memory_type, count as UINTN, &mut addr));
+        }
+
+        Ok(Self { addr, count })
+    }
+
+    /// Number of pages needed to hold `size` bytes
+    pub fn count_for(size: usize) -> usize {
+        (size + PAGE_SIZE - 1) / PAGE_SIZE
+    }
+
+    /// Physical address of the first page
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// Number of pages in this allocation
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Length of the allocation in bytes
+    pub fn len(&self) -> usize {
+        self.count * PAGE_SIZE
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.addr as *const u8
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.addr as *mut u8
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) } // UEFI identity maps memory so physical addr is the same as virtual
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    }
+
+    /// Gives up ownership of the pages without freeing them.
+    /// Used for memory that must stay around after we're gone (e.g. memory handed over to an OS kernel)
+    pub fn leak(self) -> &'static mut [u8] {
+        let buf = unsafe { slice::from_raw_parts_mut(self.addr as *mut u8, self.len()) };
+        mem::forget(self);
+        buf
+    }
+}
+
+impl Drop for Pages {
+    fn drop(&mut self) {
+        if boot_services_exited() {
+            return;
+        }
+
+        let bs = system_table().BootServices;
+        unsafe { ((*bs).FreePages)(self.addr, self.count as UINTN) }; // Can't do anything if this fails
+    }
+}
+
+/// Where in physical memory an allocation may go
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Placement {
+    Anywhere,
+    /// With its last byte at or below this address
+    Below(u64),
+    /// Starting exactly at this page aligned address
+    At(u64),
+}
+
+/// What the memory map will say pages are, which decides what the OS does with them after ExitBootServices
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryType {
+    /// Free for the OS to reuse once it's done with what we left there. The default
+    LoaderData,
+    /// The same but for code
+    LoaderCode,
+    /// Freed by ExitBootServices, for what's only needed until then
+    BootServicesData,
+    /// Kept by the OS and mapped for runtime services, e.g. for a runtime driver's buffers
+    RuntimeServicesData,
+    RuntimeServicesCode,
+    /// Kept until the OS has read the ACPI tables in it
+    AcpiReclaim,
+    /// Kept by the OS for good, across sleep states too. For what firmware or SMM reads after boot
+    AcpiNvs,
+    /// Never touched by the OS, e.g. for a RAM disk it's meant to find
+    Reserved,
+}
+
+impl From<MemoryType> for EFI_MEMORY_TYPE {
+    fn from(memory_type: MemoryType) -> Self {
+        match memory_type {
+            MemoryType::LoaderData => EFI_MEMORY_TYPE::EfiLoaderData,
+            MemoryType::LoaderCode => EFI_MEMORY_TYPE::EfiLoaderCode,
+            MemoryType::BootServicesData => EFI_MEMORY_TYPE::EfiBootServicesData,
+            MemoryType::RuntimeServicesData => EFI_MEMORY_TYPE::EfiRuntimeServicesData,
+            MemoryType::RuntimeServicesCode => EFI_MEMORY_TYPE::EfiRuntimeServicesCode,
+            MemoryType::AcpiReclaim => EFI_MEMORY_TYPE::EfiACPIReclaimMemory,
+            MemoryType::AcpiNvs => EFI_MEMORY_TYPE::EfiACPIMemoryNVS,
+            MemoryType::Reserved => EFI_MEMORY_TYPE::EfiReservedMemoryType,
+        }
+    }
+}
+
+/// How to allocate pages: where they may go and what type they are. For handoff structures that must end up at a
+/// particular place or with a particular type in the memory map
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PageAlloc {
+    pub placement: Placement,
+    pub memory_type: MemoryType,
+}
+
+impl Default for PageAlloc {
+    fn default() -> Self {
+        PageAlloc { placement: Placement::Anywhere, memory_type: MemoryType::LoaderData }
+    }
+}
+
+impl PageAlloc {
+    /// Loader data anywhere in memory, until told otherwise
+    pub fn builder() -> PageAllocBuilder {
+        PageAllocBuilder { alloc: PageAlloc::default() }
+    }
+
+    /// Allocates `count` pages
+    pub fn allocate(&self, count: usize) -> Result<Pages> {
+        let (alloc_type, addr) = match self.placement {
+            Placement::Anywhere => (EFI_ALLOCATE_TYPE::AllocateAnyPages, 0),
+            Placement::Below(max_addr) => (EFI_ALLOCATE_TYPE::AllocateMaxAddress, max_addr),
+            Placement::At(addr) if addr % PAGE_SIZE as u64 != 0 => return Err(EfiErrorKind::InvalidParameter.into()),
+            Placement::At(addr) => (EFI_ALLOCATE_TYPE::AllocateAddress, addr),
+        };
+        Pages::allocate_raw(alloc_type, self.memory_type.into(), addr, count)
+    }
+
+    /// Allocates enough pages for `size` bytes
+    pub fn allocate_bytes(&self, size: usize) -> Result<Pages> {
+        self.allocate(Pages::count_for(size))
+    }
+}
+
+/// Builds a PageAlloc
+#[derive(Debug, Copy, Clone)]
+pub struct PageAllocBuilder {
+    alloc: PageAlloc,
+}
+
+impl PageAllocBuilder {
+    pub fn anywhere(&mut self) -> &mut Self {
+        self.alloc.placement = Placement::Anywhere;
+        self
+    }
+
+    /// With the last byte at or below `max_addr`, e.g. MAX_ADDRESS_32BIT
+    pub fn below(&mut self, max_addr: u64) -> &mut Self {
+        self.alloc.placement = Placement::Below(max_addr);
+        self
+    }
+
+    /// At exactly `addr`, which must be page aligned
+    pub fn at(&mut self, addr: u64) -> &mut Self {
+        self.alloc.placement = Placement::At(addr);
+        self
+    }
+
+    pub fn memory_type(&mut self, memory_type: MemoryType) -> &mut Self {
+        self.alloc.memory_type = memory_type;
+        self
+    }
+
+    pub fn build(&self) -> PageAlloc {
+        self.alloc
+    }
+
+    /// Allocates `count` pages as built so far
+    pub fn allocate(&self, count: usize) -> Result<Pages> {
+        self.alloc.allocate(count)
+    }
+
+    /// Allocates enough pages for `size` bytes as built so far
+    pub fn allocate_bytes(&self, size: usize) -> Result<Pages> {
+        self.alloc.allocate_bytes(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::mock;
+
+    #[test]
+    fn builds_allocations() {
+        mock::install();
+        let alloc = PageAlloc::builder().below(u64::max_value()).memory_type(MemoryType::AcpiNvs).build();
+        assert_eq!(alloc, PageAlloc { placement: Placement::Below(u64::max_value()), memory_type: MemoryType::AcpiNvs });
+        assert_eq!(alloc.allocate_bytes(PAGE_SIZE + 1).unwrap().count(), 2);
+
+        // Nothing fits below the first page
+        assert_eq!(PageAlloc::builder().below(PAGE_SIZE as u64).allocate(1).unwrap_err().kind(), EfiErrorKind::NotFound);
+        assert_eq!(PageAlloc::builder().at(0x1001).allocate(1).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+    }
+}
@@ -0,0 +1,350 @@
+// GUID partition tables (UEFI spec chapter 5).
+//
+// A GPT disk starts with a protective MBR claiming the whole disk, then the primary header at LBA 1 followed by the
+// partition entry array. The last LBA holds a backup header with a backup entry array just before it so that a disk
+// whose start got trashed can still be read.
+
+use fs::BlockDevice;
+use ffi::EFI_GUID;
+use services::{BootServices, RuntimeServices};
+use utils::crc32;
+use {Result, EfiError, EfiErrorKind, system_table};
+use alloc::{string::String, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
+
+pub const EFI_PART_TYPE_UNUSED_GUID: EFI_GUID = EFI_GUID(0x00000000, 0x0000, 0x0000, [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+pub const EFI_PART_TYPE_EFI_SYSTEM_PART_GUID: EFI_GUID = EFI_GUID(0xC12A7328, 0xF81F, 0x11D2, [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B]);
+pub const EFI_PART_TYPE_LEGACY_MBR_GUID: EFI_GUID = EFI_GUID(0x024DEE41, 0x33E7, 0x11D3, [0x9D, 0x69, 0x00, 0x08, 0xC7, 0x81, 0xF3, 0x9F]);
+// Not from the UEFI spec but what every installer wants next to the ESP
+pub const LINUX_FILESYSTEM_DATA_GUID: EFI_GUID = EFI_GUID(0x0FC63DAF, 0x8483, 0x4772, [0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4]);
+// The Boot Loader Specification's extended boot loader partition (XBOOTLDR), for entries that don't fit on the ESP
+pub const LINUX_EXTENDED_BOOT_GUID: EFI_GUID = EFI_GUID(0xBC13C2FF, 0x59E6, 0x4262, [0xA3, 0x52, 0xB2, 0x75, 0xFD, 0x6F, 0x71, 0x72]);
+pub const MICROSOFT_BASIC_DATA_GUID: EFI_GUID = EFI_GUID(0xEBD0A0A2, 0xB9E5, 0x4433, [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7]);
+
+/// Firmware must not ignore this partition's contents
+pub const GPT_ATTRIBUTE_REQUIRED_PARTITION: u64 = 1 << 0;
+/// Firmware must not produce an EFI_BLOCK_IO_PROTOCOL for this partition
+pub const GPT_ATTRIBUTE_NO_BLOCK_IO_PROTOCOL: u64 = 1 << 1;
+/// The partition may be bootable by legacy BIOS
+pub const GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+const EFI_PTAB_HEADER_ID: &[u8; 8] = b"EFI PART";
+const GPT_REVISION: u32 = 0x0001_0000;
+const GPT_HEADER_SIZE: usize = 92;
+const GPT_ENTRY_COUNT: usize = 128;
+const GPT_ENTRY_SIZE: usize = 128;
+const GPT_NAME_CHARS: usize = 36;
+
+const MBR_PARTITION_TABLE: usize = 446;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// Partitions start on 1MiB boundaries like every modern partitioning tool does. That suits 4KiB sector drives,
+/// SSD erase blocks and RAID stripes alike
+pub const PARTITION_ALIGNMENT: u64 = 1024 * 1024;
+
+/// A partition to create
+pub struct NewPartition<'a> {
+    pub name: &'a str,
+    pub type_guid: EFI_GUID,
+    /// Size in bytes, rounded up to whole blocks. None takes the rest of the disk and is only valid for the last one
+    pub size: Option<u64>,
+    pub attributes: u64,
+}
+
+/// A partition as found in a GPT
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    pub type_guid: EFI_GUID,
+    pub unique_guid: EFI_GUID,
+    pub first_lba: u64,
+    pub last_lba: u64, // Inclusive
+    pub attributes: u64,
+    pub name: String,
+}
+
+impl Partition {
+    /// Offset and length of the partition in bytes. Use with fs::Region to get at its contents
+    pub fn byte_range(&self, block_size: usize) -> (u64, u64) {
+        (self.first_lba * block_size as u64, (self.last_lba - self.first_lba + 1) * block_size as u64)
+    }
+}
+
+/// Writes a new GPT, along with a protective MBR, laid out with the given partitions in order. Anything already on
+/// the disk is lost. Unique GUIDs for the disk and partitions are generated with new_guid()
+pub fn create_gpt<B: BlockDevice>(device: &mut B, partitions: &[NewPartition]) -> Result<Vec<Partition>> {
+    let block_size = device.block_size();
+    let block_count = device.block_count();
+    if block_size < GPT_HEADER_SIZE || partitions.len() > GPT_ENTRY_COUNT {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+
+    let entry_blocks = ((GPT_ENTRY_COUNT * GPT_ENTRY_SIZE + block_size - 1) / block_size) as u64;
+    let last_lba = block_count.checked_sub(1).ok_or_else(|| EfiError::from(EfiErrorKind::VolumeFull))?;
+    let first_usable = 2 + entry_blocks;
+    let last_usable = last_lba.checked_sub(1 + entry_blocks).ok_or_else(|| EfiError::from(EfiErrorKind::VolumeFull))?;
+    let alignment = (PARTITION_ALIGNMENT / block_size as u64).max(1);
+
+    // Lay the partitions out one after the other, each starting on an alignment boundary
+    let mut created = Vec::with_capacity(partitions.len());
+    let mut next = first_usable;
+    for (i, partition) in partitions.iter().enumerate() {
+        let first = (next + alignment - 1) / alignment * alignment;
+        let last = match partition.size {
+            Some(0) => return Err(EfiErrorKind::InvalidParameter.into()),
+            Some(size) => first + (size + block_size as u64 - 1) / block_size as u64 - 1,
+            None if i == partitions.len() - 1 => last_usable,
+            None => return Err(EfiErrorKind::InvalidParameter.into()),
+        };
+        if first > last_usable || last > last_usable || last < first {
+            return Err(EfiErrorKind::VolumeFull.into());
+        }
+        if partition.name.encode_utf16().count() > GPT_NAME_CHARS {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+
+        created.push(Partition {
+            type_guid: partition.type_guid,
+            unique_guid: new_guid()?,
+            first_lba: first,
+            last_lba: last,
+            attributes: partition.attributes,
+            name: partition.name.into(),
+        });
+        next = last + 1;
+    }
+
+    let mut entries = vec![0u8; entry_blocks as usize * block_size];
+    for (partition, entry) in created.iter().zip(entries.chunks_mut(GPT_ENTRY_SIZE)) {
+        write_guid(&mut entry[0..16], &partition.type_guid);
+        write_guid(&mut entry[16..32], &partition.unique_guid);
+        LittleEndian::write_u64(&mut entry[32..40], partition.first_lba);
+        LittleEndian::write_u64(&mut entry[40..48], partition.last_lba);
+        LittleEndian::write_u64(&mut entry[48..56], partition.attributes);
+        for (i, c) in partition.name.encode_utf16().enumerate() {
+            LittleEndian::write_u16(&mut entry[56 + i * 2..58 + i * 2], c);
+        }
+    }
+    let entries_crc = crc32(&entries[..GPT_ENTRY_COUNT * GPT_ENTRY_SIZE]);
+
+    let disk_guid = new_guid()?;
+    let header = |my_lba: u64, alternate_lba: u64, entries_lba: u64| {
+        let mut header = vec![0u8; block_size];
+        header[0..8].copy_from_slice(EFI_PTAB_HEADER_ID);
+        LittleEndian::write_u32(&mut header[8..12], GPT_REVISION);
+        LittleEndian::write_u32(&mut header[12..16], GPT_HEADER_SIZE as u32);
+        LittleEndian::write_u64(&mut header[24..32], my_lba);
+        LittleEndian::write_u64(&mut header[32..40], alternate_lba);
+        LittleEndian::write_u64(&mut header[40..48], first_usable);
+        LittleEndian::write_u64(&mut header[48..56], last_usable);
+        write_guid(&mut header[56..72], &disk_guid);
+        LittleEndian::write_u64(&mut header[72..80], entries_lba);
+        LittleEndian::write_u32(&mut header[80..84], GPT_ENTRY_COUNT as u32);
+        LittleEndian::write_u32(&mut header[84..88], GPT_ENTRY_SIZE as u32);
+        LittleEndian::write_u32(&mut header[88..92], entries_crc);
+        let header_crc = crc32(&header[..GPT_HEADER_SIZE]); // Computed with the CRC field itself zeroed
+        LittleEndian::write_u32(&mut header[16..20], header_crc);
+        header
+    };
+
+    // Backup first so there's never a primary header pointing at a backup that isn't there yet
+    let backup_entries_lba = last_lba - entry_blocks;
+    device.write_blocks(backup_entries_lba, &entries)?;
+    device.write_blocks(last_lba, &header(last_lba, 1, backup_entries_lba))?;
+    device.write_blocks(2, &entries)?;
+    device.write_blocks(1, &header(1, last_lba, 2))?;
+    device.write_blocks(0, &protective_mbr(block_size, block_count))?;
+    device.flush()?;
+
+    Ok(created)
+}
+
+// An MBR with a single partition of type 0xEE covering the whole disk, or as much of it as MBR can describe, so
+// that tools that don't know GPT leave the disk alone
+fn protective_mbr(block_size: usize, block_count: u64) -> Vec<u8> {
+    let mut mbr = vec![0u8; block_size];
+    let entry = &mut mbr[MBR_PARTITION_TABLE..MBR_PARTITION_TABLE + 16];
+    entry[1..4].copy_from_slice(&[0x00, 0x02, 0x00]); // CHS of LBA 1
+    entry[4] = MBR_TYPE_GPT_PROTECTIVE;
+    entry[5..8].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+    LittleEndian::write_u32(&mut entry[8..12], 1);
+    LittleEndian::write_u32(&mut entry[12..16], (block_count - 1).min(0xFFFF_FFFF) as u32);
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    mbr
+}
+
+/// The partitions in a disk's GPT. Falls back to the backup GPT if the primary one is damaged
+pub fn read_gpt<B: BlockDevice>(device: &mut B) -> Result<Vec<Partition>> {
+    match read_gpt_at(device, 1) {
+        Ok(partitions) => Ok(partitions),
+        Err(_) => {
+            let last_lba = device.block_count().checked_sub(1).ok_or_else(|| EfiError::from(EfiErrorKind::VolumeCorrupted))?;
+            read_gpt_at(device, last_lba)
+        }
+    }
+}
+
+fn read_gpt_at<B: BlockDevice>(device: &mut B, lba: u64) -> Result<Vec<Partition>> {
+    let block_size = device.block_size();
+    let mut header = vec![0u8; block_size];
+    device.read_blocks(lba, &mut header)?;
+
+    let header_size = LittleEndian::read_u32(&header[12..16]) as usize;
+    if &header[0..8] != EFI_PTAB_HEADER_ID || header_size < GPT_HEADER_SIZE || header_size > block_size || LittleEndian::read_u64(&header[24..32]) != lba {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+    let header_crc = LittleEndian::read_u32(&header[16..20]);
+    LittleEndian::write_u32(&mut header[16..20], 0);
+    if crc32(&header[..header_size]) != header_crc {
+        return Err(EfiErrorKind::CrcError.into());
+    }
+
+    let entries_lba = LittleEndian::read_u64(&header[72..80]);
+    let entry_count = LittleEndian::read_u32(&header[80..84]) as usize;
+    let entry_size = LittleEndian::read_u32(&header[84..88]) as usize;
+    if entry_size < GPT_ENTRY_SIZE || entry_size % 8 != 0 || entry_count.checked_mul(entry_size).map_or(true, |len| len > 16 * 1024 * 1024) {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+
+    let len = entry_count * entry_size;
+    let mut entries = vec![0u8; (len + block_size - 1) / block_size * block_size];
+    device.read_blocks(entries_lba, &mut entries)?;
+    if crc32(&entries[..len]) != LittleEndian::read_u32(&header[88..92]) {
+        return Err(EfiErrorKind::CrcError.into());
+    }
+
+    Ok(entries[..len].chunks(entry_size)
+        .map(|entry| {
+            let name = (0..GPT_NAME_CHARS).map(|i| LittleEndian::read_u16(&entry[56 + i * 2..58 + i * 2])).take_while(|&c| c != 0).collect::<Vec<_>>();
+            Partition {
+                type_guid: read_guid(&entry[0..16]),
+                unique_guid: read_guid(&entry[16..32]),
+                first_lba: LittleEndian::read_u64(&entry[32..40]),
+                last_lba: LittleEndian::read_u64(&entry[40..48]),
+                attributes: LittleEndian::read_u64(&entry[48..56]),
+                name: String::from_utf16_lossy(&name),
+            }
+        })
+        .filter(|p| p.type_guid != EFI_PART_TYPE_UNUSED_GUID)
+        .collect())
+}
+
+// GUIDs are stored the same way EFI_GUID is laid out in memory: the first three fields little endian
+fn write_guid(buf: &mut [u8], guid: &EFI_GUID) {
+    LittleEndian::write_u32(&mut buf[0..4], guid.0);
+    LittleEndian::write_u16(&mut buf[4..6], guid.1);
+    LittleEndian::write_u16(&mut buf[6..8], guid.2);
+    buf[8..16].copy_from_slice(&guid.3);
+}
+
+fn read_guid(buf: &[u8]) -> EFI_GUID {
+    let mut tail = [0u8; 8];
+    tail.copy_from_slice(&buf[8..16]);
+    EFI_GUID(LittleEndian::read_u32(&buf[0..4]), LittleEndian::read_u16(&buf[4..6]), LittleEndian::read_u16(&buf[6..8]), tail)
+}
+
+/// A version 4 style GUID for a new disk or partition
+// TODO: this mixes the time with the monotonic counter, which is unique but not random. Use EFI_RNG_PROTOCOL
+// when the firmware has it
+pub fn new_guid() -> Result<EFI_GUID> {
+    let st = system_table();
+    let count = BootServices::new(unsafe { &*st.BootServices }).next_monotonic_count()?;
+    let time = RuntimeServices::new(unsafe { &*st.RuntimeServices }).get_time()?;
+    let seed = (time.Year as u64) << 40 ^ (time.Month as u64) << 32 ^ (time.Day as u64) << 24 ^ (time.Hour as u64) << 16
+        ^ (time.Minute as u64) << 8 ^ time.Second as u64 ^ (time.Nanosecond as u64) << 20;
+
+    let high = splitmix64(seed ^ count);
+    let low = splitmix64(high ^ count.rotate_left(32));
+
+    let mut bytes = [0u8; 16];
+    LittleEndian::write_u64(&mut bytes[0..8], high);
+    LittleEndian::write_u64(&mut bytes[8..16], low);
+    bytes[7] = (bytes[7] & 0x0F) | 0x40; // Version 4, in the top of the third field
+    bytes[8] = (bytes[8] & 0x3F) | 0x80; // RFC 4122 variant
+    Ok(read_guid(&bytes))
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::{BlockDisk, Region, FileSystem, format_fat32};
+    use testing::mock;
+
+    const MIB: u64 = 1024 * 1024;
+
+    struct MemBlocks(Vec<u8>);
+
+    impl BlockDevice for MemBlocks {
+        fn block_size(&self) -> usize {
+            512
+        }
+
+        fn block_count(&self) -> u64 {
+            self.0.len() as u64 / 512
+        }
+
+        fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+            buf.copy_from_slice(&self.0[lba as usize * 512..][..buf.len()]);
+            Ok(())
+        }
+
+        fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+            self.0[lba as usize * 512..][..buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn created_gpt_reads_back_from_either_header() {
+        mock::install();
+        let mut device = MemBlocks(vec![0u8; 16 * MIB as usize]);
+        let created = create_gpt(&mut device, &[
+            NewPartition { name: "EFI system partition", type_guid: EFI_PART_TYPE_EFI_SYSTEM_PART_GUID, size: Some(4 * MIB + 1), attributes: 0 },
+            NewPartition { name: "root", type_guid: LINUX_FILESYSTEM_DATA_GUID, size: None, attributes: 0 },
+        ]).unwrap();
+
+        assert_eq!((created[0].first_lba, created[0].last_lba), (2048, 2048 + 8192));
+        assert_eq!(created[1].first_lba, 4096 * 3); // The next MiB boundary
+        assert_eq!(created[1].last_lba, device.block_count() - 34);
+        assert_ne!(created[0].unique_guid, created[1].unique_guid);
+        assert_eq!(&device.0[510..512], &[0x55, 0xAA]);
+        assert_eq!(device.0[446 + 4], MBR_TYPE_GPT_PROTECTIVE);
+
+        assert_eq!(read_gpt(&mut device).unwrap(), created);
+
+        // Trash the primary header and its entries
+        for b in device.0[512..34 * 512].iter_mut() {
+            *b = 0;
+        }
+        assert_eq!(read_gpt(&mut device).unwrap(), created);
+    }
+
+    #[test]
+    fn formatted_esp_holds_files() {
+        mock::install();
+        let mut device = MemBlocks(vec![0u8; 48 * MIB as usize]);
+        let esp = create_gpt(&mut device, &[
+            NewPartition { name: "EFI system partition", type_guid: EFI_PART_TYPE_EFI_SYSTEM_PART_GUID, size: None, attributes: 0 },
+        ]).unwrap().remove(0);
+
+        let (start, len) = esp.byte_range(device.block_size());
+        let fs = format_fat32(Region::new(BlockDisk::new(device), start, len).unwrap(), "esp").unwrap();
+        assert!(fs.read_dir("/").unwrap().is_empty());
+        fs.create_dir("EFI").unwrap();
+        fs.create_dir("EFI/BOOT").unwrap();
+        fs.write("EFI/BOOT/BOOTX64.EFI", b"MZ").unwrap();
+
+        // Mount it again from scratch
+        let fs = ::fs::Fat::new(fs.into_inner()).unwrap();
+        assert_eq!(fs.fat_type(), ::fs::fat::FatType::Fat32);
+        assert_eq!(fs.read("/efi/boot/bootx64.efi").unwrap(), b"MZ");
+        assert_eq!(format_fat32(::fs::MemDisk::zeroed(8 * MIB as usize), "").err().map(|e| e.kind()), Some(EfiErrorKind::InvalidParameter));
+    }
+}
@@ -0,0 +1,518 @@
+// PCI functions via EFI_PCI_IO_PROTOCOL, which the PCI bus driver installs on a handle for each function it finds.
+// Only configuration space, the expansion ROM and mapping buffers for bus-master DMA are wrapped; BARs belong to
+// whichever driver manages the device.
+//
+// An expansion ROM (option ROM) is a series of images, each a 0x55AA header pointing to a PCI data structure that
+// says how long the image is, what code it holds (legacy x86, EFI, ...) and whether it's the last one. EFI images
+// have a PE/COFF driver in them, possibly compressed. Firmware runs these, so they're worth hashing and looking at.
+
+use ffi::{
+    pci_io::{EFI_PCI_IO_PROTOCOL, EFI_PCI_IO_PROTOCOL_GUID, EFI_PCI_IO_PROTOCOL_WIDTH, EFI_PCI_IO_PROTOCOL_OPERATION},
+    boot_services::{EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL, EFI_PHYSICAL_ADDRESS},
+    EFI_HANDLE,
+    EFI_GUID,
+    UINTN,
+    VOID,
+};
+use boot_services::locate_handles;
+use dma::DmaBuffer;
+use decompress::tiano::{self, Version};
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind, system_table, image_handle};
+use core::{fmt, mem, ptr, slice, marker::PhantomData};
+use alloc::vec::Vec;
+
+/// Bytes of the configuration space header common to all header types
+pub const HEADER_SIZE: usize = 64;
+
+const COMMAND: u32 = 0x04;
+const COMMAND_MEMORY_SPACE: u16 = 0x02;
+const ROM_BAR: u32 = 0x30;
+const BRIDGE_ROM_BAR: u32 = 0x38;
+const ROM_BAR_ENABLE: u32 = 0x01;
+const ROM_BAR_ADDRESS_MASK: u32 = 0xffff_f800;
+
+const ROM_SIGNATURE: u16 = 0xaa55;
+const PCIR_SIGNATURE: &[u8] = b"PCIR";
+const EFI_ROM_SIGNATURE: u32 = 0x0ef1;
+const ROM_UNIT: usize = 512;
+const LAST_IMAGE: u8 = 0x80;
+// The most the ROM BAR can decode
+const MAX_ROM_SIZE: usize = 16 * 1024 * 1024;
+
+/// Where a function is, as in lspci's domain:bus:device.function
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub segment: u32,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:02x}:{:02x}.{:x}", self.segment, self.bus, self.device, self.function)
+    }
+}
+
+/// The identifying part of a function's configuration space header
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub revision: u8,
+    pub prog_if: u8,
+    pub subclass: u8,
+    pub class: u8,
+    pub header_type: u8, // Without the multi-function bit
+    pub multi_function: bool,
+    pub subsystem_vendor_id: Option<u16>, // Only type 0 (endpoint) headers have them
+    pub subsystem_id: Option<u16>,
+}
+
+impl Header {
+    pub fn parse(config: &[u8]) -> Option<Self> {
+        if config.len() < HEADER_SIZE {
+            return None;
+        }
+        let header_type = config[0x0e];
+        let endpoint = header_type & 0x7f == 0;
+        Some(Header {
+            vendor_id: LittleEndian::read_u16(config),
+            device_id: LittleEndian::read_u16(&config[2..]),
+            revision: config[0x08],
+            prog_if: config[0x09],
+            subclass: config[0x0a],
+            class: config[0x0b],
+            header_type: header_type & 0x7f,
+            multi_function: header_type & 0x80 != 0,
+            subsystem_vendor_id: if endpoint { Some(LittleEndian::read_u16(&config[0x2c..])) } else { None },
+            subsystem_id: if endpoint { Some(LittleEndian::read_u16(&config[0x2e..])) } else { None },
+        })
+    }
+
+    /// What the class code means in a couple of words, e.g. "Ethernet controller"
+    pub fn class_name(&self) -> &'static str {
+        match (self.class, self.subclass) {
+            (0x01, 0x01) => "IDE controller",
+            (0x01, 0x06) => "SATA controller",
+            (0x01, 0x08) => "NVMe controller",
+            (0x01, _) => "Storage controller",
+            (0x02, 0x00) => "Ethernet controller",
+            (0x02, _) => "Network controller",
+            (0x03, _) => "Display controller",
+            (0x04, _) => "Multimedia controller",
+            (0x05, _) => "Memory controller",
+            (0x06, 0x00) => "Host bridge",
+            (0x06, 0x01) => "ISA bridge",
+            (0x06, 0x04) => "PCI bridge",
+            (0x06, _) => "Bridge",
+            (0x07, _) => "Communication controller",
+            (0x08, _) => "System peripheral",
+            (0x0c, 0x03) => "USB controller",
+            (0x0c, 0x05) => "SMBus",
+            (0x0c, _) => "Serial bus controller",
+            (0x0d, _) => "Wireless controller",
+            (0x10, _) => "Encryption controller",
+            _ => "Unclassified device",
+        }
+    }
+}
+
+pub struct PciDevice {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_PCI_IO_PROTOCOL,
+}
+
+/// Which way a bus-master transfer goes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// The device reads the buffer
+    ToDevice,
+    /// The device writes the buffer
+    FromDevice,
+}
+
+/// A buffer mapped for a device to DMA to or from. Unmapped when dropped, which is when what the device wrote is
+/// sure to be in the buffer: the mapping may be a bounce buffer of the IOMMU driver's
+pub struct DmaMapping<'a> {
+    device: &'a PciDevice,
+    mapping: *mut VOID,
+    device_address: u64,
+    len: usize,
+    _buffer: PhantomData<&'a mut DmaBuffer>,
+}
+
+impl<'a> DmaMapping<'a> {
+    /// The address to program the device with, which is not necessarily the buffer's
+    pub fn device_address(&self) -> u64 {
+        self.device_address
+    }
+
+    /// How much of the buffer was mapped. Can be less than all of it, in which case transfer in pieces
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'a> Drop for DmaMapping<'a> {
+    fn drop(&mut self) {
+        let protocol = self.device.protocol;
+        unsafe { ((*protocol).Unmap)(protocol, self.mapping) }; // Can't do anything if this fails
+    }
+}
+
+impl PciDevice {
+    /// Every function the PCI bus driver has found, in bus order
+    pub fn all() -> Result<Vec<Self>> {
+        let mut devices = Vec::new();
+        for handle in locate_handles(&EFI_PCI_IO_PROTOCOL_GUID)? {
+            let protocol = open_protocol::<EFI_PCI_IO_PROTOCOL>(handle, &EFI_PCI_IO_PROTOCOL_GUID);
+            if !protocol.is_null() {
+                devices.push(PciDevice { handle, protocol });
+            }
+        }
+        devices.sort_by_key(|d| d.location().ok());
+        Ok(devices)
+    }
+
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    pub fn location(&self) -> Result<Location> {
+        let (mut segment, mut bus, mut device, mut function): (UINTN, UINTN, UINTN, UINTN) = (0, 0, 0, 0);
+        unsafe {
+            ret_on_err!(((*self.protocol).GetLocation)(self.protocol, &mut segment, &mut bus, &mut device, &mut function));
+        }
+        Ok(Location { segment: segment as u32, bus: bus as u8, device: device as u8, function: function as u8 })
+    }
+
+    /// Reads configuration space starting at `offset`
+    pub fn read_config(&self, offset: u32, buf: &mut [u8]) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).Pci.Read)(self.protocol, EFI_PCI_IO_PROTOCOL_WIDTH::EfiPciIoWidthUint8, offset, buf.len(), buf.as_mut_ptr() as *mut VOID));
+        }
+        Ok(())
+    }
+
+    pub fn header(&self) -> Result<Header> {
+        let mut config = [0u8; HEADER_SIZE];
+        self.read_config(0, &mut config)?;
+        Header::parse(&config).ok_or_else(|| EfiErrorKind::DeviceError.into())
+    }
+
+    /// Writes configuration space starting at `offset`
+    pub fn write_config(&self, offset: u32, buf: &[u8]) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).Pci.Write)(self.protocol, EFI_PCI_IO_PROTOCOL_WIDTH::EfiPciIoWidthUint8, offset, buf.len(), buf.as_ptr() as *mut VOID));
+        }
+        Ok(())
+    }
+
+    /// Maps a buffer for a bus-master transfer. Buffers both sides use at once need the protocol's AllocateBuffer,
+    /// which isn't wrapped
+    pub fn map<'a>(&'a self, buffer: &'a mut DmaBuffer, direction: DmaDirection) -> Result<DmaMapping<'a>> {
+        let operation = match direction {
+            DmaDirection::ToDevice => EFI_PCI_IO_PROTOCOL_OPERATION::EfiPciIoOperationBusMasterRead,
+            DmaDirection::FromDevice => EFI_PCI_IO_PROTOCOL_OPERATION::EfiPciIoOperationBusMasterWrite,
+        };
+        let mut len: UINTN = buffer.len();
+        let mut device_address: EFI_PHYSICAL_ADDRESS = 0;
+        let mut mapping: *mut VOID = ptr::null_mut();
+        unsafe {
+            ret_on_err!(((*self.protocol).Map)(self.protocol, operation, buffer.as_mut_ptr() as *mut VOID, &mut len, &mut device_address, &mut mapping));
+        }
+        Ok(DmaMapping { device: self, mapping, device_address, len, _buffer: PhantomData })
+    }
+
+    /// The copy of the expansion ROM the PCI bus driver made when it found the function, if it found one. Platform
+    /// firmware may have put a ROM of its own here in place of the device's
+    pub fn rom_image(&self) -> Option<&[u8]> {
+        unsafe {
+            let (image, size) = ((*self.protocol).RomImage, (*self.protocol).RomSize as usize);
+            if image.is_null() || size == 0 { None } else { Some(slice::from_raw_parts(image as *const u8, size)) }
+        }
+    }
+
+    // Reads the ROM straight through the ROM BAR, if firmware assigned it an address and memory decoding is on.
+    // The BAR is only enabled for as long as it takes
+    fn read_rom_bar(&self) -> Result<Vec<u8>> {
+        let mut register = [0u8; 4];
+        self.read_config(COMMAND, &mut register[..2])?;
+        if LittleEndian::read_u16(&register) & COMMAND_MEMORY_SPACE == 0 {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        let offset = if self.header()?.header_type == 1 { BRIDGE_ROM_BAR } else { ROM_BAR };
+        self.read_config(offset, &mut register)?;
+        let bar = LittleEndian::read_u32(&register);
+        let address = (bar & ROM_BAR_ADDRESS_MASK) as usize;
+        if address == 0 {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        if bar & ROM_BAR_ENABLE == 0 {
+            LittleEndian::write_u32(&mut register, bar | ROM_BAR_ENABLE);
+            self.write_config(offset, &register)?;
+        }
+        let rom = address as *const u8;
+        let mut data = Vec::new();
+        // Images say how long they are, so read one header at a time rather than sizing the BAR
+        while data.len() + ROM_UNIT <= MAX_ROM_SIZE {
+            let start = data.len();
+            data.extend((0..ROM_UNIT).map(|i| unsafe { ptr::read_volatile(rom.add(start + i)) }));
+            let (length, last) = match image_extent(&data[start..]) {
+                Some(extent) => extent,
+                None => {
+                    data.truncate(start);
+                    break;
+                }
+            };
+            let end = (start + length).min(MAX_ROM_SIZE);
+            data.extend((start + ROM_UNIT..end).map(|i| unsafe { ptr::read_volatile(rom.add(i)) }));
+            if last {
+                break;
+            }
+        }
+        if bar & ROM_BAR_ENABLE == 0 {
+            LittleEndian::write_u32(&mut register, bar);
+            self.write_config(offset, &register)?;
+        }
+        Ok(data)
+    }
+}
+
+/// Copies the function's expansion ROM and splits it into images. Uses the bus driver's copy if there is one and reads
+/// the ROM BAR if not. Fails with NotFound if the function doesn't have a ROM
+pub fn read_expansion_rom(device: &PciDevice) -> Result<ExpansionRom> {
+    let data = match device.rom_image() {
+        Some(image) => image.to_vec(),
+        None => device.read_rom_bar()?,
+    };
+    if data.is_empty() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+    Ok(ExpansionRom::parse(data))
+}
+
+/// What kind of code an image holds
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CodeType {
+    /// A legacy BIOS option ROM
+    PcAt,
+    OpenFirmware,
+    PaRisc,
+    Efi,
+    Other(u8),
+}
+
+/// The EFI specific part of an EFI image's header
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EfiRomHeader {
+    /// EFI_IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER and so on
+    pub subsystem: u16,
+    /// The PE machine type, e.g. 0x8664 for x64
+    pub machine: u16,
+    /// Compressed with the UEFI compression algorithm
+    pub compressed: bool,
+    /// Where the PE image is, from the start of the ROM image
+    pub image_offset: usize,
+}
+
+/// One image from an expansion ROM
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RomImage {
+    /// Where it is in the ROM
+    pub offset: usize,
+    pub length: usize,
+    /// From the PCI data structure, which needn't match the function's
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub code_type: CodeType,
+    pub code_revision: u16,
+    pub efi: Option<EfiRomHeader>,
+}
+
+/// A copy of a function's expansion ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpansionRom {
+    pub data: Vec<u8>,
+    /// The images, up to the last one or the first that doesn't parse
+    pub images: Vec<RomImage>,
+}
+
+impl ExpansionRom {
+    pub fn parse(data: Vec<u8>) -> Self {
+        let mut images = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let image = match parse_image(&data[offset..]) {
+                Some(image) => image,
+                None => break,
+            };
+            let last = image_extent(&data[offset..]).map(|(_, last)| last).unwrap_or(true);
+            images.push(RomImage { offset, ..image });
+            if last {
+                break;
+            }
+            offset += image.length;
+        }
+        ExpansionRom { data, images }
+    }
+
+    /// The bytes of an image, cut short if the ROM is
+    pub fn image_data(&self, image: &RomImage) -> &[u8] {
+        let start = image.offset.min(self.data.len());
+        &self.data[start..(image.offset + image.length).min(self.data.len())]
+    }
+
+    /// The PE/COFF driver in an EFI image, decompressed. Fails with Unsupported for other kinds of image
+    pub fn efi_driver(&self, image: &RomImage) -> Result<Vec<u8>> {
+        let efi = image.efi.ok_or(EfiErrorKind::Unsupported)?;
+        let data = self.image_data(image);
+        let driver = data.get(efi.image_offset..).ok_or(EfiErrorKind::VolumeCorrupted)?;
+        if efi.compressed {
+            tiano::decompress(driver, Version::Efi)
+        } else {
+            Ok(driver.to_vec())
+        }
+    }
+}
+
+// How long the image at the start of `data` is and whether it's the last one
+fn image_extent(data: &[u8]) -> Option<(usize, bool)> {
+    let pcir = pcir(data)?;
+    let length = LittleEndian::read_u16(&pcir[0x10..]) as usize * ROM_UNIT;
+    if length == 0 {
+        return None;
+    }
+    Some((length, pcir[0x15] & LAST_IMAGE != 0))
+}
+
+// The PCI data structure of the image at the start of `data`
+fn pcir(data: &[u8]) -> Option<&[u8]> {
+    if data.len() < 0x1a || LittleEndian::read_u16(data) != ROM_SIGNATURE {
+        return None;
+    }
+    let offset = LittleEndian::read_u16(&data[0x18..]) as usize;
+    let pcir = data.get(offset..offset + 0x18)?;
+    if &pcir[..4] != PCIR_SIGNATURE {
+        return None;
+    }
+    Some(pcir)
+}
+
+fn parse_image(data: &[u8]) -> Option<RomImage> {
+    let pcir = pcir(data)?;
+    let (length, _) = image_extent(data)?;
+    let code_type = match pcir[0x14] {
+        0 => CodeType::PcAt,
+        1 => CodeType::OpenFirmware,
+        2 => CodeType::PaRisc,
+        3 => CodeType::Efi,
+        n => CodeType::Other(n),
+    };
+    let efi = if code_type == CodeType::Efi && LittleEndian::read_u32(&data[0x04..]) == EFI_ROM_SIGNATURE {
+        Some(EfiRomHeader {
+            subsystem: LittleEndian::read_u16(&data[0x08..]),
+            machine: LittleEndian::read_u16(&data[0x0a..]),
+            compressed: LittleEndian::read_u16(&data[0x0c..]) == 1,
+            image_offset: LittleEndian::read_u16(&data[0x16..]) as usize,
+        })
+    } else {
+        None
+    };
+    Some(RomImage {
+        offset: 0,
+        length,
+        vendor_id: LittleEndian::read_u16(&pcir[0x04..]),
+        device_id: LittleEndian::read_u16(&pcir[0x06..]),
+        prog_if: pcir[0x0d],
+        subclass: pcir[0x0e],
+        class: pcir[0x0f],
+        code_type,
+        code_revision: LittleEndian::read_u16(&pcir[0x12..]),
+        efi,
+    })
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> *const T {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        let status = traced!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+        if !::ffi::IsSuccess(status) {
+            return ptr::null();
+        }
+    }
+    protocol
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn parses_header() {
+        // QEMU's e1000
+        let mut config = [0u8; HEADER_SIZE];
+        config[..4].copy_from_slice(&[0x86, 0x80, 0x0e, 0x10]);
+        config[0x08..0x0c].copy_from_slice(&[0x03, 0x00, 0x00, 0x02]);
+        config[0x2c..0x30].copy_from_slice(&[0xf4, 0x1a, 0x00, 0x11]);
+        let header = Header::parse(&config).unwrap();
+        assert_eq!((header.vendor_id, header.device_id, header.revision), (0x8086, 0x100e, 3));
+        assert_eq!(header.class_name(), "Ethernet controller");
+        assert_eq!((header.subsystem_vendor_id, header.subsystem_id), (Some(0x1af4), Some(0x1100)));
+
+        config[0x0e] = 0x81;
+        let bridge = Header::parse(&config).unwrap();
+        assert!(bridge.multi_function && bridge.header_type == 1 && bridge.subsystem_id.is_none());
+        assert!(Header::parse(&config[..16]).is_none());
+        assert_eq!(Location { segment: 0, bus: 0, device: 0x1f, function: 3 }.to_string(), "0000:00:1f.3");
+    }
+
+    // An image of `units` 512 byte blocks with its PCI data structure at 0x1c
+    fn rom_image(code_type: u8, units: u16, last: bool) -> Vec<u8> {
+        let mut image = vec![0u8; units as usize * ROM_UNIT];
+        image[..2].copy_from_slice(&[0x55, 0xaa]);
+        image[0x18] = 0x1c;
+        image[0x1c..0x20].copy_from_slice(PCIR_SIGNATURE);
+        image[0x20..0x24].copy_from_slice(&[0x86, 0x80, 0x0e, 0x10]);
+        image[0x29..0x2c].copy_from_slice(&[0x00, 0x00, 0x02]);
+        LittleEndian::write_u16(&mut image[0x2c..], units);
+        image[0x30] = code_type;
+        image[0x31] = if last { LAST_IMAGE } else { 0 };
+        image
+    }
+
+    #[test]
+    fn splits_expansion_rom() {
+        let mut rom = rom_image(0, 2, false);
+        let mut efi = rom_image(3, 1, true);
+        LittleEndian::write_u32(&mut efi[0x04..], EFI_ROM_SIGNATURE);
+        efi[0x08..0x0e].copy_from_slice(&[0x0b, 0x00, 0x64, 0x86, 0x00, 0x00]);
+        efi[0x16] = 0x40;
+        efi[0x40..0x42].copy_from_slice(b"MZ");
+        rom.extend_from_slice(&efi);
+        rom.extend_from_slice(&[0xff; 512]);
+
+        let rom = ExpansionRom::parse(rom);
+        assert_eq!(rom.images.len(), 2);
+        let (legacy, efi) = (rom.images[0], rom.images[1]);
+        assert_eq!((legacy.offset, legacy.length, legacy.code_type, legacy.class), (0, 1024, CodeType::PcAt, 0x02));
+        assert_eq!((legacy.vendor_id, legacy.device_id, legacy.efi), (0x8086, 0x100e, None));
+        assert_eq!((efi.offset, efi.length, efi.code_type), (1024, 512, CodeType::Efi));
+        assert_eq!(efi.efi, Some(EfiRomHeader { subsystem: 0x0b, machine: 0x8664, compressed: false, image_offset: 0x40 }));
+        assert_eq!(&rom.efi_driver(&efi).unwrap()[..2], b"MZ");
+        assert_eq!(rom.efi_driver(&legacy).unwrap_err().kind(), EfiErrorKind::Unsupported);
+        assert_eq!(image_extent(&rom.data[1024..]), Some((512, true)));
+        assert!(ExpansionRom::parse(vec![0xff; 512]).images.is_empty());
+    }
+}
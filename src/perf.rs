@@ -0,0 +1,212 @@
+// Boot performance: how long firmware took to get to us, and how long we took after that.
+//
+// Firmware that supports it publishes an ACPI Firmware Performance Data Table (FPDT) pointing at a Firmware Basic
+// Boot Performance Table (FBPT), whose basic boot record has nanosecond times since reset for when the firmware
+// finished its reset phase and when it loaded and started the OS loader (us). The exit boot services times are
+// filled in later, so are zero while we're running.
+// mark() adds our own points in time (e.g. "config loaded", "kernel downloaded") and report() puts them all in order.
+
+use acpi::{self, SDT_HEADER_SIZE};
+use byteorder::{ByteOrder, LittleEndian};
+use json::{ToJson, Value};
+use time::Timestamp;
+use Result;
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt, ptr, time::Duration};
+
+const FPDT_RECORD_FBPT_POINTER: u16 = 0x0000;
+const FBPT_RECORD_BASIC_BOOT: u16 = 0x0002;
+const FBPT_HEADER_SIZE: usize = 8;
+
+/// Times from the FBPT's basic boot record, since reset. Zero means the firmware didn't record it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FirmwareBootTimes {
+    pub reset_end: Duration,
+    pub os_loader_load_image_start: Duration,
+    pub os_loader_start_image_start: Duration,
+    pub exit_boot_services_entry: Duration,
+    pub exit_boot_services_exit: Duration,
+}
+
+impl FirmwareBootTimes {
+    /// None if there's no FPDT or it doesn't have a basic boot record
+    pub fn get() -> Result<Option<Self>> {
+        let fpdt = match acpi::find_table(b"FPDT")? {
+            Some(fpdt) => fpdt,
+            None => return Ok(None),
+        };
+        let address = match fbpt_address(fpdt) {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+        // The FBPT isn't an ACPI table as such, it has a signature and length but no checksum
+        let header = unsafe { core::slice::from_raw_parts(address as *const u8, FBPT_HEADER_SIZE) };
+        if &header[..4] != b"FBPT" {
+            return Ok(None);
+        }
+        let length = LittleEndian::read_u32(&header[4..]) as usize;
+        let fbpt = unsafe { core::slice::from_raw_parts(address as *const u8, length.max(FBPT_HEADER_SIZE)) };
+        Ok(parse_fbpt(fbpt))
+    }
+}
+
+// Records are a type, a length and a revision followed by the data
+fn records(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    core::iter::from_fn(move || {
+        if data.len() < 4 {
+            return None;
+        }
+        let kind = LittleEndian::read_u16(data);
+        let length = data[2] as usize;
+        if length < 4 || length > data.len() {
+            return None;
+        }
+        let record = &data[..length];
+        data = &data[length..];
+        Some((kind, record))
+    })
+}
+
+fn fbpt_address(fpdt: &[u8]) -> Option<u64> {
+    let (_, record) = records(fpdt.get(SDT_HEADER_SIZE..)?).find(|&(kind, _)| kind == FPDT_RECORD_FBPT_POINTER)?;
+    record.get(8..16).map(LittleEndian::read_u64)
+}
+
+fn parse_fbpt(fbpt: &[u8]) -> Option<FirmwareBootTimes> {
+    let (_, record) = records(fbpt.get(FBPT_HEADER_SIZE..)?).find(|&(kind, _)| kind == FBPT_RECORD_BASIC_BOOT)?;
+    if record.len() < 48 {
+        return None;
+    }
+    let time = |offset: usize| Duration::from_nanos(LittleEndian::read_u64(&record[offset..]));
+    Some(FirmwareBootTimes {
+        reset_end: time(8),
+        os_loader_load_image_start: time(16),
+        os_loader_start_image_start: time(24),
+        exit_boot_services_entry: time(32),
+        exit_boot_services_exit: time(40),
+    })
+}
+
+static mut MARKERS: *mut Vec<(&'static str, Timestamp)> = ptr::null_mut(); // Boxed
+
+/// Records that `name` happened now. Does nothing if the firmware can't tell the time
+pub fn mark(name: &'static str) {
+    if let Ok(now) = Timestamp::now() {
+        mark_at(name, now);
+    }
+}
+
+pub fn mark_at(name: &'static str, at: Timestamp) {
+    let mut markers = unsafe { MARKERS };
+    if markers.is_null() {
+        markers = Box::into_raw(Box::new(Vec::new()));
+        unsafe { MARKERS = markers };
+    }
+    unsafe { (*markers).push((name, at)) };
+}
+
+/// What mark() has recorded so far, in the order it was called
+pub fn markers() -> Vec<(&'static str, Timestamp)> {
+    let markers = unsafe { MARKERS };
+    if markers.is_null() { Vec::new() } else { unsafe { (*markers).clone() } }
+}
+
+/// The firmware's times and ours, in order
+pub fn report() -> BootTimeReport {
+    let firmware = FirmwareBootTimes::get().ok().and_then(|times| times);
+    BootTimeReport::new(firmware.as_ref(), &markers())
+}
+
+/// A point in the boot and how long it's been since reset
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub name: &'static str,
+    pub since_reset: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootTimeReport {
+    pub events: Vec<Event>,
+}
+
+impl BootTimeReport {
+    pub fn new(firmware: Option<&FirmwareBootTimes>, markers: &[(&'static str, Timestamp)]) -> Self {
+        let mut events = Vec::new();
+        if let Some(firmware) = firmware {
+            for &(name, time) in &[
+                ("firmware reset end", firmware.reset_end),
+                ("loader load", firmware.os_loader_load_image_start),
+                ("loader start", firmware.os_loader_start_image_start),
+            ] {
+                if time != Duration::default() {
+                    events.push(Event { name, since_reset: time });
+                }
+            }
+        }
+        events.extend(markers.iter().map(|&(name, at)| Event { name, since_reset: at.since_reset() }));
+        events.sort_by_key(|e| e.since_reset); // Stable, so markers at the same time keep their order
+        BootTimeReport { events }
+    }
+}
+
+impl fmt::Display for BootTimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>10} {:>10}  event", "ms", "+ms")?;
+        let mut previous = Duration::default();
+        for event in &self.events {
+            let delta = event.since_reset.checked_sub(previous).unwrap_or_default();
+            writeln!(f, "{:>10.3} {:>10.3}  {}", millis(event.since_reset), millis(delta), event.name)?;
+            previous = event.since_reset;
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for BootTimeReport {
+    fn to_json(&self) -> Value {
+        Value::Array(self.events.iter().map(|e| {
+            let mut value = Value::Object(Vec::new());
+            value.set("name", e.name);
+            value.set("since_reset_ns", &(e.since_reset.as_nanos() as u64));
+            value
+        }).collect())
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn combines_firmware_and_markers() {
+        let mut fpdt = acpi::build_table(b"FPDT", 1, &[0, 0, 16, 1, 0, 0, 0, 0, 0x78, 0x56, 0x34, 0x12, 0, 0, 0, 0]);
+        assert_eq!(fbpt_address(&fpdt), Some(0x1234_5678));
+        fpdt.truncate(SDT_HEADER_SIZE + 8);
+        assert_eq!(fbpt_address(&fpdt), None);
+
+        let mut fbpt = vec![0u8; FBPT_HEADER_SIZE + 48];
+        fbpt[..4].copy_from_slice(b"FBPT");
+        let length = fbpt.len() as u32;
+        LittleEndian::write_u32(&mut fbpt[4..], length);
+        fbpt[8..12].copy_from_slice(&[0x02, 0x00, 48, 2]);
+        for (i, ms) in [500u64, 2_000, 2_100].iter().enumerate() {
+            LittleEndian::write_u64(&mut fbpt[16 + i * 8..], ms * 1_000_000);
+        }
+        let firmware = parse_fbpt(&fbpt).unwrap();
+        assert_eq!(firmware.os_loader_start_image_start, Duration::from_millis(2_100));
+        assert_eq!(firmware.exit_boot_services_entry, Duration::default());
+
+        let markers = [("kernel loaded", Timestamp::from_since_reset(Duration::from_millis(3_250))), ("config read", Timestamp::from_since_reset(Duration::from_millis(2_200)))];
+        let report = BootTimeReport::new(Some(&firmware), &markers);
+        let names = report.events.iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names, ["firmware reset end", "loader load", "loader start", "config read", "kernel loaded"]);
+        let text = report.to_string();
+        assert!(text.contains("  3250.000   1050.000  kernel loaded"), "{}", text);
+        assert!(::json::to_string(&report).starts_with(r#"[{"name":"firmware reset end","since_reset_ns":500000000},"#));
+    }
+}
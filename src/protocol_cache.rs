@@ -0,0 +1,170 @@
+// LocateProtocol, remembered.
+//
+// Firmware-wide protocols (service bindings, device path to text, status codes) are looked up with LocateProtocol,
+// which walks the whole protocol database every time. The crate did that on every connect, every device path shown
+// and every status code reported. Now the first lookup of a GUID is remembered, and a protocol notify event marks it
+// stale as soon as another interface of that GUID is installed or one is reinstalled, so the next lookup goes back to
+// firmware.
+//
+// Firmware doesn't say when an interface is uninstalled. That mostly happens when a driver is disconnected or
+// unloaded, e.g. a NIC's after `disconnect` in the shell or a USB device being pulled. Code that does that, or lets it
+// happen, should invalidate() the protocols concerned or clear() the lot, or turn the cache off altogether.
+
+use ffi::{
+    boot_services::{EVT_NOTIFY_SIGNAL, TPL_CALLBACK},
+    EFI_EVENT,
+    EFI_GUID,
+    EFI_STATUS,
+    EFI_SUCCESS,
+    VOID,
+};
+use services::boot_services_exited;
+use utils::TryLock;
+use {Result, EfiErrorKind, system_table};
+use alloc::{boxed::Box, vec::Vec};
+use core::{mem, ptr, sync::atomic::{AtomicBool, Ordering}};
+
+struct Entry {
+    guid: EFI_GUID,
+    interface: usize,
+    event: usize, // The protocol notify event
+    stale: Box<AtomicBool>, // Where the event's callback can get at it
+}
+
+static CACHE: TryLock<Vec<Entry>> = TryLock::new(Vec::new());
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The first interface of the protocol, as LocateProtocol has it, from the cache if it's there.
+/// Fails with NotFound if there's none
+pub fn locate<T>(guid: &EFI_GUID) -> Result<*const T> {
+    let enabled = is_enabled() && !boot_services_exited();
+    if enabled {
+        let hit = CACHE.try_with(|entries| {
+            entries.iter().find(|e| e.guid == *guid && !e.stale.load(Ordering::Acquire)).map(|e| e.interface)
+        });
+        if let Some(Some(interface)) = hit {
+            return Ok(interface as *const T);
+        }
+    }
+
+    let interface = locate_uncached(guid)?;
+    if enabled {
+        remember(guid, interface);
+    }
+    Ok(interface as *const T)
+}
+
+/// Forgets the protocol, e.g. after disconnecting the driver that installed it
+pub fn invalidate(guid: &EFI_GUID) {
+    CACHE.try_with(|entries| {
+        if let Some(entry) = entries.iter().find(|e| e.guid == *guid) {
+            entry.stale.store(true, Ordering::Release);
+        }
+    });
+}
+
+/// Forgets every protocol
+pub fn clear() {
+    let entries = CACHE.try_with(mem::take).unwrap_or_default();
+    if boot_services_exited() {
+        return;
+    }
+    let bs = system_table().BootServices;
+    for entry in entries {
+        unsafe { ((*bs).CloseEvent)(entry.event as EFI_EVENT) }; // Nothing to be done if it fails
+    }
+}
+
+/// Turns the cache on or off. It's on to start with. Turning it off clears it
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+    if !enabled {
+        clear();
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+fn locate_uncached(guid: &EFI_GUID) -> Result<usize> {
+    let bs = system_table().BootServices;
+    let interface: *const VOID = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(guid, ptr::null(), mem::transmute(&interface)));
+    }
+    if interface.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+    Ok(interface as usize)
+}
+
+// Keeps the interface, registering for notifications about the protocol the first time. Left out of the cache if
+// that can't be done, since it would never be invalidated
+fn remember(guid: &EFI_GUID, interface: usize) {
+    CACHE.try_with(|entries| {
+        if let Some(entry) = entries.iter_mut().find(|e| e.guid == *guid) {
+            entry.interface = interface;
+            entry.stale.store(false, Ordering::Release);
+            return;
+        }
+
+        let bs = system_table().BootServices;
+        let stale = Box::new(AtomicBool::new(false));
+        let mut event: EFI_EVENT = ptr::null();
+        let mut registration: *const VOID = ptr::null();
+        unsafe {
+            if ((*bs).CreateEvent)(EVT_NOTIFY_SIGNAL, TPL_CALLBACK, Some(mark_stale), &*stale as *const AtomicBool as *const VOID, &mut event) != EFI_SUCCESS {
+                return;
+            }
+            if ((*bs).RegisterProtocolNotify)(guid, event, &mut registration) != EFI_SUCCESS {
+                ((*bs).CloseEvent)(event);
+                return;
+            }
+        }
+        entries.push(Entry { guid: *guid, interface, event: event as usize, stale });
+    });
+}
+
+extern "efiapi" fn mark_stale(_event: EFI_EVENT, context: *const VOID) -> EFI_STATUS {
+    unsafe { (*(context as *const AtomicBool)).store(true, Ordering::Release) };
+    EFI_SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, EFI_HANDLE};
+    use testing::mock;
+
+    const CACHED_GUID: EFI_GUID = EFI_GUID(0x5c1d7e2a, 0x93b4, 0x4f06, [0x8a, 0x2e, 0x61, 0x0d, 0xc7, 0x35, 0x9b, 0x14]);
+
+    fn install(interface: usize) -> EFI_HANDLE {
+        let mut handle: EFI_HANDLE = ptr::null();
+        let bs = system_table().BootServices;
+        unsafe { ((*bs).InstallProtocolInterface)(&mut handle, &CACHED_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, interface as *const VOID) };
+        handle
+    }
+
+    #[test]
+    fn goes_back_to_firmware_when_told_to() {
+        mock::install();
+        assert_eq!(locate::<VOID>(&CACHED_GUID).unwrap_err().kind(), EfiErrorKind::NotFound);
+        let first = install(0x1000);
+        assert_eq!(locate::<VOID>(&CACHED_GUID).unwrap() as usize, 0x1000);
+
+        // Uninstalling goes unnoticed until we're told
+        let bs = system_table().BootServices;
+        unsafe { ((*bs).UninstallProtocolInterface)(first, &CACHED_GUID, 0x1000 as *const VOID) };
+        assert_eq!(locate::<VOID>(&CACHED_GUID).unwrap() as usize, 0x1000);
+        invalidate(&CACHED_GUID);
+        assert_eq!(locate::<VOID>(&CACHED_GUID).unwrap_err().kind(), EfiErrorKind::NotFound);
+
+        // Installing is noticed by itself
+        let second = install(0x2000);
+        assert_eq!(locate::<VOID>(&CACHED_GUID).unwrap() as usize, 0x2000);
+        unsafe { ((*bs).UninstallProtocolInterface)(second, &CACHED_GUID, 0x2000 as *const VOID) };
+        install(0x3000);
+        assert_eq!(locate::<VOID>(&CACHED_GUID).unwrap() as usize, 0x3000);
+    }
+}
@@ -0,0 +1,265 @@
+// Booting kernels through the PVH entry point, which is how Xen and direct kernel boot in QEMU, Firecracker and the
+// like start vmlinux: an ELF image with a Xen note giving a 32 bit entry point, called in protected mode with paging
+// off and EBX pointing at an hvm_start_info. That has the command line, the modules (Linux takes the first as its
+// initrd), the RSDP and an E820 style memory map.
+//
+// The entry wants paging off, so boot services have to be exited first. The kernel is left runtime services and the
+// tables the firmware installed, which it finds through the RSDP. The entry is x86 code, so this is only built there.
+
+use {Result, EfiErrorKind, system_table};
+use arch::x86;
+use elf::{self, Elf};
+use pages::{Pages, MAX_ADDRESS_32BIT};
+use memory::MemoryMap;
+use ffi::boot_services::{EFI_MEMORY_DESCRIPTOR, EFI_MEMORY_TYPE};
+use acpi;
+use security::measure::{self, Component};
+use byteorder::{ByteOrder, LittleEndian};
+use alloc::{vec::Vec, string::String};
+
+/// The note type of the 32 bit entry point, in a note owned by "Xen"
+pub const XEN_ELFNOTE_PHYS32_ENTRY: u32 = 18;
+
+/// What hvm_start_info starts with
+pub const START_MAGIC: u32 = 0x336e_c578;
+
+// Version 1 is the first with the memory map
+const START_VERSION: u32 = 1;
+const START_INFO_SIZE: usize = 56;
+const MODLIST_ENTRY_SIZE: usize = 32;
+const MEMMAP_ENTRY_SIZE: usize = 24;
+
+// E820 memory types
+pub const E820_RAM: u32 = 1;
+pub const E820_RESERVED: u32 = 2;
+pub const E820_ACPI: u32 = 3;
+pub const E820_NVS: u32 = 4;
+pub const E820_UNUSABLE: u32 = 5;
+pub const E820_PMEM: u32 = 7;
+
+// EfiPersistentMemory, from UEFI 2.5 on
+const EFI_PERSISTENT_MEMORY: u32 = 14;
+
+struct Module {
+    data: Vec<u8>,
+    cmdline: String,
+}
+
+/// A PVH kernel ready to be booted along with its modules and command line
+pub struct Pvh {
+    image: Vec<u8>,
+    entry: u32,
+    cmdline: String,
+    modules: Vec<Module>,
+}
+
+impl Pvh {
+    /// Fails with LoadError if the image isn't ELF and with Unsupported if it has no PVH entry point
+    pub fn new(image: Vec<u8>) -> Result<Self> {
+        let entry = pvh_entry(&Elf::parse(&image)?).ok_or(EfiErrorKind::Unsupported)?;
+        Ok(Self { image, entry, cmdline: String::new(), modules: Vec::new() })
+    }
+
+    /// The 32 bit entry point's physical address
+    pub fn entry(&self) -> u32 {
+        self.entry
+    }
+
+    pub fn cmdline<S: Into<String>>(&mut self, cmdline: S) -> &mut Self {
+        self.cmdline = cmdline.into();
+        self
+    }
+
+    /// Adds a module. Linux takes the first to be its initrd
+    pub fn module<S: Into<String>>(&mut self, data: Vec<u8>, cmdline: S) -> &mut Self {
+        self.modules.push(Module { data, cmdline: cmdline.into() });
+        self
+    }
+
+    /// Boots the kernel, measuring it, its modules and the command lines first if there's a TPM. Only returns if
+    /// something went wrong, which past ExitBootServices leaves the caller little to do but reset
+    pub fn boot(&self) -> Result<()> {
+        if !x86::can_enter_protected_mode() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        self.measure()?;
+        let segments = Elf::parse(&self.image)?.segments()?;
+        let (start, end) = elf::extent(&segments)?;
+        if end > MAX_ADDRESS_32BIT {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let mut kernel = Pages::allocate_at(start, Pages::count_for((end - start) as usize))?;
+        elf::copy_segments(&self.image, &segments, start, kernel.as_mut_slice())?;
+
+        let mut module_pages = Vec::new();
+        for module in &self.modules {
+            let mut pages = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(module.data.len().max(1)))?;
+            pages.as_mut_slice()[..module.data.len()].copy_from_slice(&module.data);
+            module_pages.push(pages);
+        }
+
+        // Room for the memory map to have grown by the time boot services exit. Leaked straight away since it's
+        // written after that, when pages can't be freed
+        let map = MemoryMap::get()?;
+        let strings = self.cmdline.len() + 1 + self.modules.iter().map(|m| m.cmdline.len() + 1).sum::<usize>();
+        let size = START_INFO_SIZE + self.modules.len() * MODLIST_ENTRY_SIZE + strings + (map.len() + 32) * MEMMAP_ENTRY_SIZE + 8;
+        let info = Pages::allocate_below(MAX_ADDRESS_32BIT, Pages::count_for(size))?.leak();
+        let info_addr = info.as_ptr() as u64;
+
+        let modules: Vec<_> = self.modules.iter().zip(&module_pages).map(|(m, pages)| (pages.addr(), m.data.len() as u64, m.cmdline.as_str())).collect();
+        let rsdp = match acpi::rsdp() {
+            Ok(Some(rsdp)) => rsdp.as_ptr() as u64,
+            _ => 0,
+        };
+        let memmap_offset = write_start_info(info, info_addr, &self.cmdline, &modules, rsdp);
+
+        // The kernel owns all of these from here on
+        kernel.leak();
+        for pages in module_pages {
+            pages.leak();
+        }
+
+        let map = ::services::exit_boot_services(system_table())?;
+        let entries = write_memmap(&mut info[memmap_offset..], map.iter())?;
+        LittleEndian::write_u64(&mut info[40..], info_addr + memmap_offset as u64);
+        LittleEndian::write_u32(&mut info[48..], entries);
+        unsafe { x86::enter_protected_mode(self.entry, info_addr as u32) }
+    }
+
+    // The kernel, each module with its command line, and the kernel's command line
+    fn measure(&self) -> Result<()> {
+        measure::measure(Component::Kernel, &self.image, "kernel")?;
+        for module in &self.modules {
+            measure::measure(Component::Initrd, &module.data, "module")?;
+            if !module.cmdline.is_empty() {
+                measure::measure(Component::Cmdline, module.cmdline.as_bytes(), &module.cmdline)?;
+            }
+        }
+        measure::measure(Component::Cmdline, self.cmdline.as_bytes(), &self.cmdline)
+    }
+}
+
+// The entry point note's address. Linux gives it as a pointer sized value, so 8 bytes in a 64 bit kernel
+fn pvh_entry(elf: &Elf) -> Option<u32> {
+    let desc = elf.note(b"Xen", XEN_ELFNOTE_PHYS32_ENTRY)?;
+    match desc.len() {
+        4 => Some(LittleEndian::read_u32(desc)),
+        8 if LittleEndian::read_u64(desc) <= MAX_ADDRESS_32BIT => Some(LittleEndian::read_u32(desc)),
+        _ => None,
+    }
+}
+
+// Writes hvm_start_info at the start of `buf`, which the kernel will see at `addr`, then the module list and the
+// strings. Each module is (address, size, command line). Returns the 8 byte aligned offset the memory map is to go at
+fn write_start_info(buf: &mut [u8], addr: u64, cmdline: &str, modules: &[(u64, u64, &str)], rsdp: u64) -> usize {
+    let modlist = START_INFO_SIZE + 8; // Aligned like the 64 bit fields after it
+    let mut strings = modlist + modules.len() * MODLIST_ENTRY_SIZE;
+    let mut string = |buf: &mut [u8], s: &str| {
+        buf[strings..strings + s.len()].copy_from_slice(s.as_bytes());
+        buf[strings + s.len()] = 0;
+        strings += s.len() + 1;
+        addr + (strings - s.len() - 1) as u64
+    };
+
+    for b in buf[..START_INFO_SIZE].iter_mut() {
+        *b = 0;
+    }
+    LittleEndian::write_u32(buf, START_MAGIC);
+    LittleEndian::write_u32(&mut buf[4..], START_VERSION);
+    LittleEndian::write_u32(&mut buf[12..], modules.len() as u32);
+    LittleEndian::write_u64(&mut buf[16..], if modules.is_empty() { 0 } else { addr + modlist as u64 });
+    let cmdline_addr = string(buf, cmdline);
+    LittleEndian::write_u64(&mut buf[24..], cmdline_addr);
+    LittleEndian::write_u64(&mut buf[32..], rsdp);
+    for (i, &(address, size, cmdline)) in modules.iter().enumerate() {
+        let cmdline_addr = if cmdline.is_empty() { 0 } else { string(buf, cmdline) };
+        let entry = &mut buf[modlist + i * MODLIST_ENTRY_SIZE..][..MODLIST_ENTRY_SIZE];
+        LittleEndian::write_u64(entry, address);
+        LittleEndian::write_u64(&mut entry[8..], size);
+        LittleEndian::write_u64(&mut entry[16..], cmdline_addr);
+        LittleEndian::write_u64(&mut entry[24..], 0);
+    }
+    (strings + 7) & !7
+}
+
+// The firmware's memory map as E820 entries, neighbours of the same type merged, for after ExitBootServices. Returns
+// how many were written. Fails with BufferTooSmall if they don't fit
+fn write_memmap<'m, I: Iterator<Item = &'m EFI_MEMORY_DESCRIPTOR>>(buf: &mut [u8], map: I) -> Result<u32> {
+    let mut count = 0;
+    let mut last: Option<(u64, u64, u32)> = None;
+    for d in map {
+        let (start, size, kind) = (d.PhysicalStart, d.NumberOfPages * 4096, e820_type(d.Type));
+        if let Some((last_start, last_size, last_kind)) = last {
+            if last_start + last_size == start && last_kind == kind {
+                last = Some((last_start, last_size + size, kind));
+                write_memmap_entry(buf, count - 1, last_start, last_size + size, kind)?;
+                continue;
+            }
+        }
+        write_memmap_entry(buf, count, start, size, kind)?;
+        last = Some((start, size, kind));
+        count += 1;
+    }
+    Ok(count as u32)
+}
+
+fn write_memmap_entry(buf: &mut [u8], index: usize, start: u64, size: u64, kind: u32) -> Result<()> {
+    let entry = buf.get_mut(index * MEMMAP_ENTRY_SIZE..(index + 1) * MEMMAP_ENTRY_SIZE).ok_or(EfiErrorKind::BufferTooSmall)?;
+    LittleEndian::write_u64(entry, start);
+    LittleEndian::write_u64(&mut entry[8..], size);
+    LittleEndian::write_u32(&mut entry[16..], kind);
+    LittleEndian::write_u32(&mut entry[20..], 0);
+    Ok(())
+}
+
+// Boot services and our own memory are free once the kernel has control
+fn e820_type(efi_type: u32) -> u32 {
+    use self::EFI_MEMORY_TYPE::*;
+    match efi_type {
+        t if [EfiLoaderCode as u32, EfiLoaderData as u32, EfiBootServicesCode as u32, EfiBootServicesData as u32, EfiConventionalMemory as u32].contains(&t) => E820_RAM,
+        t if t == EfiACPIReclaimMemory as u32 => E820_ACPI,
+        t if t == EfiACPIMemoryNVS as u32 => E820_NVS,
+        t if t == EfiUnusableMemory as u32 => E820_UNUSABLE,
+        EFI_PERSISTENT_MEMORY => E820_PMEM,
+        _ => E820_RESERVED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(efi_type: u32, start: u64, pages: u64) -> EFI_MEMORY_DESCRIPTOR {
+        EFI_MEMORY_DESCRIPTOR { Type: efi_type, PhysicalStart: start, VirtualStart: 0, NumberOfPages: pages, Attribute: 0 }
+    }
+
+    #[test]
+    fn writes_start_info_and_merged_memmap() {
+        let mut buf = [0xffu8; 512];
+        let offset = write_start_info(&mut buf, 0x1000, "console=ttyS0", &[(0x20_0000, 0x1234, "initrd"), (0x30_0000, 16, "")], 0xe_0000);
+        assert_eq!((LittleEndian::read_u32(&buf), LittleEndian::read_u32(&buf[4..]), LittleEndian::read_u32(&buf[12..])), (START_MAGIC, 1, 2));
+        assert_eq!(LittleEndian::read_u64(&buf[16..]), 0x1000 + 64);
+        assert_eq!(LittleEndian::read_u64(&buf[24..]), 0x1000 + 128);
+        assert_eq!(&buf[128..142], b"console=ttyS0\0");
+        assert_eq!(LittleEndian::read_u64(&buf[32..]), 0xe_0000);
+        assert_eq!((LittleEndian::read_u64(&buf[64..]), LittleEndian::read_u64(&buf[72..]), LittleEndian::read_u64(&buf[80..])), (0x20_0000, 0x1234, 0x1000 + 142));
+        assert_eq!(&buf[142..149], b"initrd\0");
+        assert_eq!(LittleEndian::read_u64(&buf[112..]), 0);
+        assert_eq!(offset, 152);
+
+        use self::EFI_MEMORY_TYPE::*;
+        let map = [
+            descriptor(EfiConventionalMemory as u32, 0, 0xa0),
+            descriptor(EfiBootServicesData as u32, 0x10_0000, 0x100),
+            descriptor(EfiLoaderCode as u32, 0x20_0000, 0x10),
+            descriptor(EfiACPIReclaimMemory as u32, 0x21_0000, 1),
+            descriptor(EFI_PERSISTENT_MEMORY, 0x1_0000_0000, 0x1000),
+        ];
+        let mut memmap = [0u8; 4 * MEMMAP_ENTRY_SIZE];
+        assert_eq!(write_memmap(&mut memmap, map.iter()).unwrap(), 4);
+        assert_eq!((LittleEndian::read_u64(&memmap[24..]), LittleEndian::read_u64(&memmap[32..])), (0x10_0000, 0x11_0000));
+        let types: Vec<u32> = memmap.chunks(MEMMAP_ENTRY_SIZE).map(|e| LittleEndian::read_u32(&e[16..])).collect();
+        assert_eq!(types, [E820_RAM, E820_RAM, E820_ACPI, E820_PMEM]);
+        assert_eq!(write_memmap(&mut memmap[..MEMMAP_ENTRY_SIZE], map.iter()).unwrap_err().kind(), EfiErrorKind::BufferTooSmall);
+    }
+}
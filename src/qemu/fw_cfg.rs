@@ -0,0 +1,281 @@
+// QEMU's firmware configuration device (fw_cfg), through which the host hands the guest blobs: the kernel, initrd
+// and command line given with -kernel/-initrd/-append, and named files given with -fw_cfg name=opt/...,file=...
+// Each item has a 16-bit selector. Selecting one resets the data offset, after which reading the data register
+// returns the item byte by byte. Newer QEMUs also have a DMA interface which copies a whole item at once.
+// On x86 the registers are I/O ports; on ARM and RISC-V virt machines they're MMIO, at the address given by the
+// "qemu,fw-cfg-mmio" device tree node. See docs/specs/fw_cfg.rst in the QEMU tree.
+
+use {Result, EfiErrorKind};
+use byteorder::{ByteOrder, BigEndian, LittleEndian};
+use alloc::{vec::Vec, string::String};
+use core::{ptr, str};
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+use arch::x86;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+use arch::mmio;
+
+pub const SIGNATURE: u16 = 0x0000;
+pub const ID: u16 = 0x0001;
+pub const KERNEL_SIZE: u16 = 0x0008;
+pub const INITRD_SIZE: u16 = 0x000b;
+pub const KERNEL_DATA: u16 = 0x0011;
+pub const INITRD_DATA: u16 = 0x0012;
+pub const CMDLINE_SIZE: u16 = 0x0014;
+pub const CMDLINE_DATA: u16 = 0x0015;
+pub const FILE_DIR: u16 = 0x0019;
+
+const ID_DMA: u32 = 1 << 1; // Bit in ID for the DMA interface
+
+const DMA_CTL_ERROR: u32 = 0x01;
+const DMA_CTL_READ: u32 = 0x02;
+const DMA_CTL_SKIP: u32 = 0x04;
+const DMA_CTL_SELECT: u32 = 0x08;
+
+const FILE_NAME_LEN: usize = 56;
+const FILE_ENTRY_LEN: usize = 64;
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const SELECTOR_PORT: u16 = 0x510;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const DATA_PORT: u16 = 0x511;
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+const DMA_PORT: u16 = 0x514;
+
+// Where the virt machines put it, for when there's no device tree to go by
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+const DEFAULT_MMIO_BASE: usize = 0x0902_0000;
+
+/// An entry of the file directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    pub name: String,
+    pub selector: u16,
+    pub size: u32,
+}
+
+// Everything in it is big-endian
+#[repr(C)]
+struct DmaAccess {
+    control: u32,
+    length: u32,
+    address: u64,
+}
+
+pub struct FwCfg {
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    base: usize,
+    dma: bool,
+}
+
+impl FwCfg {
+    /// Returns NotFound if there's no fw_cfg device, e.g. when not running under QEMU
+    pub fn detect() -> Result<Self> {
+        let mut fw_cfg = FwCfg {
+            #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+            base: mmio_base()?,
+            dma: false,
+        };
+        let mut signature = [0u8; 4];
+        fw_cfg.read(SIGNATURE, &mut signature);
+        if &signature != b"QEMU" {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        let mut id = [0u8; 4];
+        fw_cfg.read(ID, &mut id);
+        fw_cfg.dma = LittleEndian::read_u32(&id) & ID_DMA != 0;
+        Ok(fw_cfg)
+    }
+
+    /// Whether transfers go over DMA rather than a byte at a time
+    pub fn has_dma(&self) -> bool {
+        self.dma
+    }
+
+    /// Fills `buf` from the start of the item with the given selector. Past the end of an item reads return zeroes
+    pub fn read(&mut self, selector: u16, buf: &mut [u8]) {
+        self.read_at(selector, 0, buf)
+    }
+
+    fn read_at(&mut self, selector: u16, offset: usize, buf: &mut [u8]) {
+        if self.dma {
+            self.dma_transfer(DMA_CTL_SELECT | DMA_CTL_SKIP | ((selector as u32) << 16), offset as u32, ptr::null_mut());
+            self.dma_transfer(DMA_CTL_READ, buf.len() as u32, buf.as_mut_ptr());
+            return;
+        }
+        self.select(selector);
+        for _ in 0..offset {
+            self.read_byte();
+        }
+        for b in buf.iter_mut() {
+            *b = self.read_byte();
+        }
+    }
+
+    /// The named files, e.g. "etc/boot-menu-wait" or whatever was given with -fw_cfg
+    pub fn files(&mut self) -> Result<Vec<File>> {
+        let mut count = [0u8; 4];
+        self.read(FILE_DIR, &mut count);
+        let count = BigEndian::read_u32(&count) as usize;
+        let mut dir = vec![0u8; 4 + count * FILE_ENTRY_LEN];
+        self.read(FILE_DIR, &mut dir);
+        parse_files(&dir)
+    }
+
+    pub fn find(&mut self, name: &str) -> Result<File> {
+        self.files()?.into_iter().find(|f| f.name == name).ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    pub fn read_file(&mut self, name: &str) -> Result<Vec<u8>> {
+        let file = self.find(name)?;
+        let mut data = vec![0u8; file.size as usize];
+        self.read(file.selector, &mut data);
+        Ok(data)
+    }
+
+    /// The image given with -kernel, if any
+    pub fn kernel(&mut self) -> Option<Vec<u8>> {
+        self.sized_item(KERNEL_SIZE, KERNEL_DATA)
+    }
+
+    /// The initrd given with -initrd, if any
+    pub fn initrd(&mut self) -> Option<Vec<u8>> {
+        self.sized_item(INITRD_SIZE, INITRD_DATA)
+    }
+
+    /// The command line given with -append, if any
+    pub fn cmdline(&mut self) -> Option<String> {
+        let mut cmdline = self.sized_item(CMDLINE_SIZE, CMDLINE_DATA)?;
+        if let Some(nul) = cmdline.iter().position(|b| *b == 0) {
+            cmdline.truncate(nul);
+        }
+        String::from_utf8(cmdline).ok()
+    }
+
+    fn sized_item(&mut self, size_selector: u16, data_selector: u16) -> Option<Vec<u8>> {
+        let mut size = [0u8; 4];
+        self.read(size_selector, &mut size);
+        let size = LittleEndian::read_u32(&size) as usize;
+        if size == 0 {
+            return None;
+        }
+        let mut data = vec![0u8; size];
+        self.read(data_selector, &mut data);
+        Some(data)
+    }
+
+    // Runs a DMA command and waits for the device to finish it. Errors (e.g. a bad selector) leave the buffer untouched,
+    // the same as reading past the end of an item byte by byte would leave zeroes
+    fn dma_transfer(&mut self, control: u32, length: u32, address: *mut u8) {
+        let mut access = DmaAccess { control: control.to_be(), length: length.to_be(), address: (address as u64).to_be() };
+        let access_ptr: *mut DmaAccess = &mut access;
+        self.start_dma(access_ptr as u64);
+        loop {
+            let control = u32::from_be(unsafe { ptr::read_volatile(&(*access_ptr).control) });
+            if control & !DMA_CTL_ERROR == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    fn select(&mut self, selector: u16) {
+        unsafe { x86::outw(SELECTOR_PORT, selector) };
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    fn read_byte(&mut self) -> u8 {
+        unsafe { x86::inb(DATA_PORT) }
+    }
+
+    // The address register is big-endian, and writing its low half starts the transfer
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    fn start_dma(&mut self, address: u64) {
+        let high = ((address >> 32) as u32).swap_bytes();
+        let low = (address as u32).swap_bytes();
+        unsafe {
+            x86::outl(DMA_PORT, high);
+            x86::outl(DMA_PORT + 4, low);
+        }
+    }
+
+    // Data at +0, selector at +8 (big-endian) and the DMA address at +16 (big-endian)
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    fn select(&mut self, selector: u16) {
+        unsafe { mmio::write(self.base + 8, selector.to_be()) };
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    fn read_byte(&mut self) -> u8 {
+        unsafe { mmio::read::<u8>(self.base) }
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    fn start_dma(&mut self, address: u64) {
+        unsafe { mmio::write(self.base + 16, address.to_be()) };
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+fn mmio_base() -> Result<usize> {
+    fn find(node: &::fdt::Node) -> Option<u64> {
+        let compatible = node.property("compatible").map(|p| p.as_str_list()).unwrap_or_default();
+        if compatible.contains(&"qemu,fw-cfg-mmio") {
+            // Two address cells on the virt machines
+            let reg = node.property("reg")?.value();
+            return if reg.len() >= 8 { Some(BigEndian::read_u64(reg)) } else { None };
+        }
+        node.children().iter().filter_map(find).next()
+    }
+    let fdt = match ::fdt::Fdt::from_config_table()? {
+        Some(fdt) => fdt,
+        None => return Ok(DEFAULT_MMIO_BASE),
+    };
+    find(fdt.root()).map(|base| base as usize).ok_or_else(|| EfiErrorKind::NotFound.into())
+}
+
+// The directory is a big-endian count followed by that many 64 byte entries of size, selector, reserved and name
+fn parse_files(dir: &[u8]) -> Result<Vec<File>> {
+    if dir.len() < 4 {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+    let count = BigEndian::read_u32(dir) as usize;
+    let entries = &dir[4..];
+    if entries.len() < count * FILE_ENTRY_LEN {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+    let mut files = Vec::with_capacity(count);
+    for entry in entries.chunks(FILE_ENTRY_LEN).take(count) {
+        let name = &entry[8..8 + FILE_NAME_LEN];
+        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(FILE_NAME_LEN)];
+        files.push(File {
+            name: str::from_utf8(name).map_err(|_| EfiErrorKind::VolumeCorrupted)?.into(),
+            selector: BigEndian::read_u16(&entry[4..]),
+            size: BigEndian::read_u32(entry),
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_file_directory() {
+        let mut dir = vec![0u8; 4 + 2 * FILE_ENTRY_LEN];
+        BigEndian::write_u32(&mut dir, 2);
+        for (i, (name, selector, size)) in [("etc/boot-fail-wait", 0x20u16, 4u32), ("opt/efi/test", 0x2a, 0x1234)].iter().enumerate() {
+            let entry = &mut dir[4 + i * FILE_ENTRY_LEN..][..FILE_ENTRY_LEN];
+            BigEndian::write_u32(entry, *size);
+            BigEndian::write_u16(&mut entry[4..], *selector);
+            entry[8..8 + name.len()].copy_from_slice(name.as_bytes());
+        }
+        let files = parse_files(&dir).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1], File { name: "opt/efi/test".into(), selector: 0x2a, size: 0x1234 });
+        assert_eq!(files[0].name, "etc/boot-fail-wait");
+
+        assert!(parse_files(&dir[..100]).is_err());
+    }
+}
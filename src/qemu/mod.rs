@@ -0,0 +1,46 @@
+// Helpers for running under QEMU (feature "qemu"), e.g. with the runner in runner/ that boots images under OVMF.
+// QEMU throws away the status given to ResetSystem, so the only way for an image to tell the host whether it passed
+// is the isa-debug-exit device: writing a value v to its port makes QEMU exit with status (v << 1) | 1. The runner
+// adds the device at DEBUG_EXIT_PORT. It only exists on x86; elsewhere the runner goes by the test harness' output.
+// fw_cfg reads what the host passed in (e.g. with -fw_cfg), which is also how the runner hands images their arguments.
+
+pub mod fw_cfg;
+
+use ffi::{runtime_services::EFI_RESET_TYPE, EFI_SUCCESS, EFI_ABORTED};
+use services::RuntimeServices;
+use system_table;
+use alloc::string::String;
+
+/// Where the runner puts isa-debug-exit (`-device isa-debug-exit,iobase=0xf4,iosize=0x04`)
+pub const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// What the runner takes to mean success and failure. They have to be odd after QEMU's (v << 1) | 1, which leaves
+/// out 0 and 1 since those are also what QEMU exits with itself
+pub const EXIT_SUCCESS: u32 = 0x10;
+pub const EXIT_FAILURE: u32 = 0x11;
+
+/// The fw_cfg file the runner puts EFI_RUNNER_ARGS in
+pub const ARGS_FILE: &str = "opt/efi-runner/args";
+
+/// What the runner was given in EFI_RUNNER_ARGS. Images it boots are started by the boot manager with no load options,
+/// so this is where their arguments are. None if it wasn't set or we're not under QEMU
+pub fn runner_args() -> Option<String> {
+    let args = fw_cfg::FwCfg::detect().ok()?.read_file(ARGS_FILE).ok()?;
+    String::from_utf8(args).ok()
+}
+
+/// Has QEMU exit with status (code << 1) | 1. Returns if there's no isa-debug-exit device at DEBUG_EXIT_PORT, which
+/// includes not running under QEMU at all (the write goes nowhere) and not being on x86
+pub fn debug_exit(code: u32) {
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    unsafe { ::arch::x86::outl(DEBUG_EXIT_PORT, code) };
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    let _ = code;
+}
+
+/// Ends the run, telling the runner whether it passed. Shuts the machine down if debug_exit() didn't
+pub fn exit(success: bool) -> ! {
+    debug_exit(if success { EXIT_SUCCESS } else { EXIT_FAILURE });
+    let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+    rs.reset(EFI_RESET_TYPE::EfiResetShutdown, if success { EFI_SUCCESS } else { EFI_ABORTED })
+}
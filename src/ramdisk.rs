@@ -0,0 +1,149 @@
+// RAM disks via EFI_RAM_DISK_PROTOCOL.
+// Lets you expose an image held in memory (e.g. an ISO downloaded over HTTP) as a block device. The firmware's
+// partition and file system drivers then pick it up like any other disk so you can boot from it.
+
+use ffi::{
+    ram_disk::{EFI_RAM_DISK_PROTOCOL, EFI_RAM_DISK_PROTOCOL_GUID},
+    device_path::EFI_DEVICE_PATH_PROTOCOL,
+    EFI_GUID,
+};
+pub use ffi::ram_disk::{EFI_VIRTUAL_DISK_GUID, EFI_VIRTUAL_CD_GUID, EFI_PERSISTENT_VIRTUAL_DISK_GUID, EFI_PERSISTENT_VIRTUAL_CD_GUID};
+use device_path::DevicePath;
+use pages::{Pages, PageAlloc, MemoryType};
+use services::boot_services_exited;
+use {Result, EfiErrorKind, system_table};
+use core::{mem::{self, ManuallyDrop}, ptr};
+
+/// What to fill a new RAM disk with
+pub enum RamDiskData<'a> {
+    /// A copy of this buffer
+    Buffer(&'a [u8]),
+    /// This many zeroed bytes
+    Zeroed(usize),
+}
+
+impl<'a> From<&'a [u8]> for RamDiskData<'a> {
+    fn from(buf: &'a [u8]) -> Self {
+        RamDiskData::Buffer(buf)
+    }
+}
+
+impl<'a> From<usize> for RamDiskData<'a> {
+    fn from(size: usize) -> Self {
+        RamDiskData::Zeroed(size)
+    }
+}
+
+/// A registered RAM disk. Unregistered and freed on drop
+pub struct RamDisk {
+    pages: ManuallyDrop<Pages>, // Only freed once the disk is unregistered
+    size: usize,
+    device_path: DevicePath,
+}
+
+/// Creates a RAM disk from a buffer (`&[u8]`) or of a given size (`usize`).
+/// `disk_type` is one of the EFI_*_DISK/CD_GUIDs. Use EFI_VIRTUAL_CD_GUID for ISO images.
+pub fn create<'a, D: Into<RamDiskData<'a>>>(data: D, disk_type: &EFI_GUID) -> Result<RamDisk> {
+    let data = data.into();
+    let size = match data {
+        RamDiskData::Buffer(buf) => buf.len(),
+        RamDiskData::Zeroed(size) => size,
+    };
+
+    if size == 0 {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+
+    // Reserved memory so that an OS booted off the disk can still find it via the NFIT the firmware publishes
+    let mut pages = PageAlloc::builder().memory_type(MemoryType::Reserved).allocate_bytes(size)?;
+    {
+        let buf = pages.as_mut_slice();
+        match data {
+            RamDiskData::Buffer(data) => {
+                buf[..size].copy_from_slice(data);
+                for b in buf[size..].iter_mut() {
+                    *b = 0;
+                }
+            }
+            RamDiskData::Zeroed(_) => {
+                for b in buf.iter_mut() {
+                    *b = 0;
+                }
+            }
+        }
+    }
+
+    let protocol = ram_disk_protocol()?;
+    let mut device_path: *const EFI_DEVICE_PATH_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*protocol).Register)(pages.addr(), size as u64, disk_type, ptr::null(), &mut device_path));
+    }
+
+    Ok(RamDisk { pages: ManuallyDrop::new(pages), size, device_path: DevicePath::from_ptr(device_path)? })
+}
+
+impl RamDisk {
+    /// Device path of the disk. Use it to find the disk's handle or to load an image off it
+    pub fn device_path(&self) -> &DevicePath {
+        &self.device_path
+    }
+
+    /// Physical address of the start of the disk
+    pub fn addr(&self) -> u64 {
+        self.pages.addr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The contents of the disk
+    pub fn as_slice(&self) -> &[u8] {
+        &self.pages.as_slice()[..self.size]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.pages.as_mut_slice()[..self.size]
+    }
+
+    /// Keeps the disk registered for good, e.g. for an OS that is going to boot from it
+    pub fn leak(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for RamDisk {
+    fn drop(&mut self) {
+        if boot_services_exited() {
+            return;
+        }
+
+        // If unregistering fails leak the pages rather than free memory the firmware is still using as a disk
+        let unregistered = match ram_disk_protocol() {
+            Ok(protocol) => ::ffi::IsSuccess(unsafe { ((*protocol).Unregister)(self.device_path.as_ptr()) }),
+            Err(_) => false,
+        };
+
+        if unregistered {
+            unsafe { ManuallyDrop::drop(&mut self.pages) };
+        }
+    }
+}
+
+fn ram_disk_protocol() -> Result<*const EFI_RAM_DISK_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_RAM_DISK_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_RAM_DISK_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+    }
+
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+
+    Ok(protocol)
+}
@@ -0,0 +1,321 @@
+// A/B slots: two copies of the OS, one updated while the other runs, booting the new one a few times on trial and
+// going back to the old one if it never manages to say it's fine.
+//
+// Each slot has a priority (0 means don't boot it), the tries it has left and whether it has ever booted
+// successfully. select() picks the bootable slot with the highest priority; if that slot hasn't booted successfully
+// yet it uses up one of its tries first, so a slot that keeps failing runs out and the other one gets picked. The OS
+// marks the slot successful once it's up.
+//
+// The state is in one non-volatile variable so the OS can read and change it, e.g. through efivarfs:
+// AbSlotState-3f6b2a91-8c2d-4e57-b10e-6a94d237c815 holds a version byte (1), the number of slots (2) and then four
+// bytes per slot: priority, tries left, successful (0 or 1) and a reserved zero. Marking a slot successful is
+// setting its third byte to 1. The slot select() picked is in the volatile AbCurrentSlot, as 0 for A or 1 for B.
+
+use ffi::runtime_services::{EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_RUNTIME_ACCESS};
+use ffi::EFI_GUID;
+use bootcfg::{BootEntry, BootMenu};
+use firmware::{firmware, Firmware};
+use {Result, EfiErrorKind};
+use core::fmt;
+
+pub const VENDOR: EFI_GUID = EFI_GUID(0x3f6b2a91, 0x8c2d, 0x4e57, [0xb1, 0x0e, 0x6a, 0x94, 0xd2, 0x37, 0xc8, 0x15]);
+pub const STATE_VARIABLE: &str = "AbSlotState";
+pub const CURRENT_VARIABLE: &str = "AbCurrentSlot";
+
+/// How many tries a slot gets after set_active()
+pub const DEFAULT_TRIES: u8 = 3;
+const MAX_PRIORITY: u8 = 15;
+
+const VERSION: u8 = 1;
+const SLOTS: usize = 2;
+const STATE_SIZE: usize = 2 + SLOTS * 4;
+const STATE_ATTRIBUTES: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+const CURRENT_ATTRIBUTES: u32 = EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    fn index(self) -> usize {
+        match self {
+            Slot::A => 0,
+            Slot::B => 1,
+        }
+    }
+
+    fn from_index(index: usize) -> Option<Self> {
+        match index {
+            0 => Some(Slot::A),
+            1 => Some(Slot::B),
+            _ => None,
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    /// "a" or "b", as in slot suffixes and entry names
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Slot::A => "a",
+            Slot::B => "b",
+        }
+    }
+
+    /// The menu entry for the slot: the first whose id, without a .conf extension, ends in -a or _a (for A)
+    pub fn entry(self, menu: &BootMenu) -> Option<&BootEntry> {
+        menu.entries.iter().find(|e| {
+            let id = e.id.trim_end_matches(".conf").to_ascii_lowercase();
+            id.ends_with(&format!("-{}", self.suffix())) || id.ends_with(&format!("_{}", self.suffix()))
+        })
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Slot::A => "A",
+            Slot::B => "B",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SlotState {
+    /// Higher boots first. 0 means the slot isn't bootable
+    pub priority: u8,
+    pub tries_remaining: u8,
+    pub successful: bool,
+}
+
+impl SlotState {
+    pub fn is_bootable(&self) -> bool {
+        self.priority != 0 && (self.successful || self.tries_remaining != 0)
+    }
+}
+
+/// Both slots' state
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct State {
+    pub slots: [SlotState; SLOTS],
+}
+
+/// With no state yet, A is active and already known good and B is the fallback, not yet tried
+impl Default for State {
+    fn default() -> Self {
+        State {
+            slots: [
+                SlotState { priority: MAX_PRIORITY, tries_remaining: 0, successful: true },
+                SlotState { priority: MAX_PRIORITY - 1, tries_remaining: DEFAULT_TRIES, successful: false },
+            ],
+        }
+    }
+}
+
+impl State {
+    pub fn slot(&self, slot: Slot) -> &SlotState {
+        &self.slots[slot.index()]
+    }
+
+    pub fn slot_mut(&mut self, slot: Slot) -> &mut SlotState {
+        &mut self.slots[slot.index()]
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < STATE_SIZE || data[0] != VERSION || data[1] as usize != SLOTS {
+            return None;
+        }
+        let slot = |i: usize| {
+            let s = &data[2 + i * 4..];
+            SlotState { priority: s[0], tries_remaining: s[1], successful: s[2] != 0 }
+        };
+        Some(State { slots: [slot(0), slot(1)] })
+    }
+
+    pub fn encode(&self) -> [u8; STATE_SIZE] {
+        let mut data = [0u8; STATE_SIZE];
+        data[0] = VERSION;
+        data[1] = SLOTS as u8;
+        for (i, slot) in self.slots.iter().enumerate() {
+            data[2 + i * 4] = slot.priority;
+            data[3 + i * 4] = slot.tries_remaining;
+            data[4 + i * 4] = slot.successful as u8;
+        }
+        data
+    }
+
+    /// The bootable slot with the highest priority, A winning a tie
+    pub fn best(&self) -> Option<Slot> {
+        let mut best: Option<Slot> = None;
+        for i in 0..SLOTS {
+            let slot = Slot::from_index(i)?;
+            let better = match best {
+                Some(b) => self.slots[i].priority > self.slot(b).priority,
+                None => true,
+            };
+            if self.slots[i].is_bootable() && better {
+                best = Some(slot);
+            }
+        }
+        best
+    }
+}
+
+/// The state as it is now. Missing or unreadable state is the default
+pub fn state() -> Result<State> {
+    read_state(firmware())
+}
+
+/// Picks the slot to boot and uses up one of its tries if it's on trial. None if neither can be booted, which is
+/// when to go to recovery
+pub fn select() -> Result<Option<Slot>> {
+    select_in(firmware())
+}
+
+/// Picks the slot to boot and finds its entry in `menu`. A slot without an entry is marked unbootable and the other
+/// tried instead
+pub fn select_entry(menu: &BootMenu) -> Result<Option<(Slot, &BootEntry)>> {
+    select_entry_in(firmware(), menu)
+}
+
+/// Records that the slot booted fine, so it stops using up tries
+pub fn mark_boot_successful(slot: Slot) -> Result<()> {
+    update(firmware(), |state| {
+        let s = state.slot_mut(slot);
+        s.successful = true;
+        s.tries_remaining = 0;
+    })
+}
+
+/// The slot select() picked this boot, if it ran
+pub fn current() -> Result<Option<Slot>> {
+    match firmware().get_variable(CURRENT_VARIABLE, &VENDOR) {
+        Ok(ref data) if data.len() == 1 => Ok(Slot::from_index(data[0] as usize)),
+        Ok(_) => Ok(None),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Makes the slot the one to boot, on trial with DEFAULT_TRIES tries, e.g. after installing an update to it
+pub fn set_active(slot: Slot) -> Result<()> {
+    update(firmware(), |state| set_active_in(state, slot))
+}
+
+/// Stops the slot from being booted until set_active() is called for it
+pub fn mark_unbootable(slot: Slot) -> Result<()> {
+    update(firmware(), |state| state.slot_mut(slot).priority = 0)
+}
+
+fn set_active_in(state: &mut State, slot: Slot) {
+    *state.slot_mut(slot) = SlotState { priority: MAX_PRIORITY, tries_remaining: DEFAULT_TRIES, successful: false };
+    let other = state.slot_mut(slot.other());
+    if other.priority >= MAX_PRIORITY {
+        other.priority = MAX_PRIORITY - 1;
+    }
+}
+
+fn read_state(fw: &dyn Firmware) -> Result<State> {
+    match fw.get_variable(STATE_VARIABLE, &VENDOR) {
+        Ok(data) => Ok(State::parse(&data).unwrap_or_default()),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(State::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_state(fw: &dyn Firmware, state: &State) -> Result<()> {
+    fw.set_variable(STATE_VARIABLE, &VENDOR, STATE_ATTRIBUTES, &state.encode())
+}
+
+fn update<F: FnOnce(&mut State)>(fw: &dyn Firmware, f: F) -> Result<()> {
+    let mut state = read_state(fw)?;
+    let before = state;
+    f(&mut state);
+    if state != before { write_state(fw, &state) } else { Ok(()) }
+}
+
+fn select_in(fw: &dyn Firmware) -> Result<Option<Slot>> {
+    let mut state = read_state(fw)?;
+    let slot = match state.best() {
+        Some(slot) => slot,
+        None => return Ok(None),
+    };
+    let s = state.slot_mut(slot);
+    if !s.successful {
+        // Written before booting, so a hang or crash still counts the try
+        s.tries_remaining -= 1;
+        write_state(fw, &state)?;
+    }
+    fw.set_variable(CURRENT_VARIABLE, &VENDOR, CURRENT_ATTRIBUTES, &[slot.index() as u8])?;
+    Ok(Some(slot))
+}
+
+fn select_entry_in<'a>(fw: &dyn Firmware, menu: &'a BootMenu) -> Result<Option<(Slot, &'a BootEntry)>> {
+    for _ in 0..SLOTS {
+        let state = read_state(fw)?;
+        let slot = match state.best() {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        match slot.entry(menu) {
+            Some(entry) => return Ok(select_in(fw)?.map(|slot| (slot, entry))),
+            None => update(fw, |state| state.slot_mut(slot).priority = 0)?,
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bootcfg::BootImage;
+    use firmware::FakeFirmware;
+    use alloc::{string::{String, ToString}, vec::Vec};
+
+    fn menu(ids: &[&str]) -> BootMenu {
+        let entries = ids.iter().map(|id| BootEntry {
+            id: id.to_string(),
+            title: id.to_string(),
+            version: None,
+            image: BootImage::Efi(format!("/EFI/{}.efi", id)),
+            options: String::new(),
+        }).collect::<Vec<_>>();
+        BootMenu { entries, default: None, timeout: None }
+    }
+
+    #[test]
+    fn falls_back_when_tries_run_out() {
+        let fw = FakeFirmware::new();
+        assert_eq!(select_in(&fw).unwrap(), Some(Slot::A));
+        assert_eq!(fw.variable(STATE_VARIABLE, &VENDOR), None); // A is known good, nothing to count
+
+        update(&fw, |state| set_active_in(state, Slot::B)).unwrap();
+        for tries_left in (0..DEFAULT_TRIES).rev() {
+            assert_eq!(select_in(&fw).unwrap(), Some(Slot::B));
+            assert_eq!(read_state(&fw).unwrap().slot(Slot::B).tries_remaining, tries_left);
+        }
+        assert_eq!(fw.variable(CURRENT_VARIABLE, &VENDOR), Some((CURRENT_ATTRIBUTES, vec![1])));
+        assert_eq!(select_in(&fw).unwrap(), Some(Slot::A));
+
+        update(&fw, |state| set_active_in(state, Slot::B)).unwrap();
+        assert_eq!(select_in(&fw).unwrap(), Some(Slot::B));
+        update(&fw, |state| state.slot_mut(Slot::B).successful = true).unwrap();
+        assert_eq!(fw.variable(STATE_VARIABLE, &VENDOR).unwrap().1, [1, 2, 14, 0, 1, 0, 15, 2, 1, 0]);
+
+        // B's entry is missing, so B is given up on
+        let menu = menu(&["linux-a.conf", "recovery"]);
+        let (slot, entry) = select_entry_in(&fw, &menu).unwrap().unwrap();
+        assert_eq!((slot, &entry.id[..]), (Slot::A, "linux-a.conf"));
+        assert_eq!(read_state(&fw).unwrap().slot(Slot::B).priority, 0);
+        update(&fw, |state| state.slot_mut(Slot::A).priority = 0).unwrap();
+        assert!(select_entry_in(&fw, &menu).unwrap().is_none());
+        assert_eq!(Slot::B.entry(&self::menu(&["os_B"])).map(|e| &e.id[..]), Some("os_B"));
+    }
+}
@@ -0,0 +1,125 @@
+// Trying again after failures that might not happen next time.
+//
+// Pre-boot networking fails a lot right after link-up: the switch port is still coming out of spanning tree, DHCP
+// hasn't been answered yet, the server's ARP entry isn't in. So connects, HTTP fetches and DHCP take a RetryPolicy
+// saying how many times to try, how long to wait between tries and how long each try gets. The waits and time limits
+// are timer events, so they work the same under real firmware and the mock.
+
+use core::time::Duration;
+use events::{Timer, TimerSchedule, TimerState, EventTpl, Wait};
+use {Result, EfiError, EfiErrorKind};
+
+/// How often and how patiently to try again after a failure that might not happen next time e.g. a timeout
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Tries in all, including the first
+    pub attempts: u32,
+    /// Wait before the first retry
+    pub delay: Duration,
+    /// What the wait is multiplied by after each retry
+    pub backoff: u32,
+    /// How long each try gets before it fails with Timeout. None leaves it to whatever is being tried
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { attempts: 3, delay: Duration::from_secs(1), backoff: 2, timeout: None }
+    }
+}
+
+impl RetryPolicy {
+    /// One attempt only
+    pub fn never() -> Self {
+        RetryPolicy { attempts: 1, delay: Duration::from_secs(0), backoff: 1, timeout: None }
+    }
+
+    /// The same but with a time limit on each try
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        RetryPolicy { timeout: Some(timeout), ..self }
+    }
+
+    /// A single try with the same time limit, for when something further out does the retrying
+    pub fn once(&self) -> Self {
+        RetryPolicy { timeout: self.timeout, ..RetryPolicy::never() }
+    }
+
+    /// The waits before each retry in turn
+    pub fn delays(&self) -> impl Iterator<Item=Duration> {
+        let backoff = self.backoff;
+        let mut delay = self.delay;
+        (1..self.attempts).map(move |_| {
+            let this = delay;
+            delay = delay.checked_mul(backoff).unwrap_or(this);
+            this
+        })
+    }
+
+    /// Calls `attempt` until it succeeds, fails with an error that isn't transient or runs out of tries
+    pub fn run<T, F: FnMut() -> Result<T>>(&self, mut attempt: F) -> Result<T> {
+        let mut delays = self.delays();
+        loop {
+            let e = match attempt() {
+                Err(e) => e,
+                result => return result,
+            };
+            match delays.next() {
+                Some(delay) if is_transient(&e) => sleep(delay)?,
+                _ => return Err(e),
+            }
+        }
+    }
+
+    /// A running timer for one try's time limit, to wait on alongside whatever the try is waiting for
+    pub(crate) fn deadline(&self) -> Result<Option<Timer>> {
+        match self.timeout {
+            Some(timeout) => Ok(Some(Timer::create(timeout, TimerSchedule::Relative, TimerState::Active, EventTpl::Callback)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Errors worth trying again for. Anything else (not found, access denied, bad URL...) will just fail again
+pub fn is_transient(e: &EfiError) -> bool {
+    match e.kind() {
+        EfiErrorKind::Timeout | EfiErrorKind::NoResponse | EfiErrorKind::DeviceError | EfiErrorKind::NotReady |
+        EfiErrorKind::ProtocolError | EfiErrorKind::TftpError | EfiErrorKind::IcmpError | EfiErrorKind::NoMapping |
+        EfiErrorKind::ConnectionRefused | EfiErrorKind::ConnectionReset => true,
+        _ => false,
+    }
+}
+
+fn sleep(delay: Duration) -> Result<()> {
+    if delay == Duration::from_secs(0) {
+        return Ok(());
+    }
+    Timer::create(delay, TimerSchedule::Relative, TimerState::Active, EventTpl::Callback)?.wait()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::mock;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn backs_off_between_tries() {
+        let policy = RetryPolicy { attempts: 4, delay: Duration::from_millis(500), backoff: 3, timeout: None };
+        let delays: Vec<_> = policy.delays().collect();
+        assert_eq!(delays, [Duration::from_millis(500), Duration::from_millis(1500), Duration::from_millis(4500)]);
+        assert_eq!(RetryPolicy::never().delays().count(), 0);
+        assert_eq!(policy.with_timeout(Duration::from_secs(2)).once(), RetryPolicy::never().with_timeout(Duration::from_secs(2)));
+
+        mock::install();
+        let mut tries = 0;
+        let res: Result<()> = policy.run(|| { tries += 1; Err(EfiErrorKind::ConnectionRefused.into()) });
+        assert_eq!((res.unwrap_err().kind(), tries), (EfiErrorKind::ConnectionRefused, 4));
+
+        tries = 0;
+        let res: Result<()> = policy.run(|| { tries += 1; Err(EfiErrorKind::AccessDenied.into()) });
+        assert_eq!((res.unwrap_err().kind(), tries), (EfiErrorKind::AccessDenied, 1));
+
+        tries = 0;
+        assert_eq!(policy.run(|| { tries += 1; if tries < 3 { Err(EfiErrorKind::Timeout.into()) } else { Ok(tries) } }).unwrap(), 3);
+    }
+}
@@ -0,0 +1,196 @@
+// Scroll-back for the console.
+// Firmware consoles don't keep anything that scrolls off the top, so an error followed by a screenful of anything
+// else is lost on machines without a serial port. Once enabled, everything written to the console is also kept here,
+// in a buffer of the last so many lines, to be paged through with view() or saved with dump() before exiting.
+
+use console::console;
+use ffi::console::{SCAN_UP, SCAN_DOWN, SCAN_PAGE_UP, SCAN_PAGE_DOWN, SCAN_HOME, SCAN_END, SCAN_ESC};
+use io::{self, Write};
+use {Result, EfiErrorKind};
+use core::{cmp, ptr};
+use alloc::{boxed::Box, collections::VecDeque, string::String, vec::Vec};
+
+/// The last `capacity` lines of output
+pub struct Scrollback {
+    lines: VecDeque<String>,
+    partial: String, // The line being written, not yet ended by a line feed
+    capacity: usize,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Scrollback { lines: VecDeque::new(), partial: String::new(), capacity: cmp::max(capacity, 1) }
+    }
+
+    /// Adds output. Carriage returns are dropped
+    pub fn push(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    let line = core::mem::take(&mut self.partial);
+                    if self.lines.len() == self.capacity {
+                        self.lines.pop_front();
+                    }
+                    self.lines.push_back(line);
+                }
+                '\r' => {}
+                c => self.partial.push(c),
+            }
+        }
+    }
+
+    /// The lines, oldest first, including one that hasn't been ended yet
+    pub fn lines(&self) -> Vec<&str> {
+        let mut lines = self.lines.iter().map(String::as_str).collect::<Vec<_>>();
+        if !self.partial.is_empty() {
+            lines.push(&self.partial);
+        }
+        lines
+    }
+
+    /// Everything as one string, lines ended by line feeds
+    pub fn contents(&self) -> String {
+        let mut contents = String::new();
+        for line in self.lines.iter() {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        contents.push_str(&self.partial);
+        contents
+    }
+}
+
+static mut SCROLLBACK: *mut Scrollback = ptr::null_mut(); // Boxed
+
+/// Starts keeping the last `lines` lines of console output. Anything kept so far is thrown away
+pub fn enable(lines: usize) {
+    replace(Some(Scrollback::new(lines)));
+}
+
+/// Stops keeping output and returns what was kept
+pub fn disable() -> Option<Scrollback> {
+    replace(None)
+}
+
+pub fn is_enabled() -> bool {
+    unsafe { !SCROLLBACK.is_null() }
+}
+
+/// Everything kept so far. None if scroll-back isn't enabled
+pub fn contents() -> Option<String> {
+    with(|scrollback| scrollback.contents())
+}
+
+/// Saves everything kept so far to a file on the volume we were loaded from
+pub fn dump(path: &str) -> Result<()> {
+    match contents() {
+        Some(contents) => ::firmware::firmware().write_file(path, contents.as_bytes()),
+        None => Err(EfiErrorKind::NotStarted.into()),
+    }
+}
+
+/// Writes everything kept so far somewhere else, e.g. to a serial port
+pub fn dump_to<W: Write>(out: &mut W) -> io::Result<()> {
+    match contents() {
+        Some(contents) => out.write_all(contents.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+/// Shows the scroll-back full screen. PgUp/PgDn, the arrows, Home and End scroll; Esc or q goes back, leaving the
+/// last screenful of output on the screen
+pub fn view() -> Result<()> {
+    // Taken out while viewing so that what's drawn doesn't end up in it
+    let scrollback = match replace(None) {
+        Some(scrollback) => scrollback,
+        None => return Err(EfiErrorKind::NotStarted.into()),
+    };
+    let result = show(&scrollback);
+    replace(Some(scrollback));
+    result
+}
+
+// Used by the console on everything written to it
+pub(crate) fn record(text: &str) {
+    with(|scrollback| scrollback.push(text));
+}
+
+fn show(scrollback: &Scrollback) -> Result<()> {
+    let mut console = console();
+    let (cols, rows) = console.size()?;
+    let lines = scrollback.lines();
+    let page = cmp::max(rows, 2) - 1; // The bottom row is for the status line
+    let last_top = lines.len().saturating_sub(page);
+    let mut top = last_top;
+
+    loop {
+        draw(&mut console, &lines[top..cmp::min(top + page, lines.len())], cols)?;
+        let status = format!("-- {}-{} of {}. PgUp/PgDn to scroll, Esc to go back --", top + 1, cmp::min(top + page, lines.len()), lines.len());
+        write!(console, "{}", truncate(&status, cols)).map_err(|_| EfiErrorKind::DeviceError)?;
+
+        let key = console.read_key()?;
+        match key.ScanCode {
+            SCAN_PAGE_UP => top = top.saturating_sub(page),
+            SCAN_PAGE_DOWN => top = cmp::min(top + page, last_top),
+            SCAN_UP => top = top.saturating_sub(1),
+            SCAN_DOWN => top = cmp::min(top + 1, last_top),
+            SCAN_HOME => top = 0,
+            SCAN_END => top = last_top,
+            SCAN_ESC => break,
+            _ if key.UnicodeChar == 'q' as u16 => break,
+            _ => {}
+        }
+    }
+
+    draw(&mut console, &lines[last_top..], cols)
+}
+
+fn draw(console: &mut ::console::Console, lines: &[&str], cols: usize) -> Result<()> {
+    console.clear_screen()?;
+    for line in lines {
+        writeln!(console, "{}", truncate(line, cols)).map_err(|_| EfiErrorKind::DeviceError)?;
+    }
+    Ok(())
+}
+
+// Cut to one less than the width so that the console doesn't wrap onto the next row
+fn truncate(line: &str, cols: usize) -> &str {
+    match line.char_indices().nth(cols.saturating_sub(1)) {
+        Some((end, _)) => &line[..end],
+        None => line,
+    }
+}
+
+fn replace(scrollback: Option<Scrollback>) -> Option<Scrollback> {
+    let new = scrollback.map_or(ptr::null_mut(), |s| Box::into_raw(Box::new(s)));
+    unsafe {
+        let old = SCROLLBACK;
+        SCROLLBACK = new;
+        if old.is_null() { None } else { Some(*Box::from_raw(old)) }
+    }
+}
+
+fn with<R, F: FnOnce(&mut Scrollback) -> R>(f: F) -> Option<R> {
+    let scrollback = unsafe { SCROLLBACK };
+    if scrollback.is_null() {
+        None
+    } else {
+        Some(f(unsafe { &mut *scrollback }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_lines() {
+        let mut scrollback = Scrollback::new(3);
+        scrollback.push("one\r\ntwo\nthr");
+        scrollback.push("ee\nfour\nfi");
+        assert_eq!(scrollback.lines(), ["two", "three", "four", "fi"]);
+        assert_eq!(scrollback.contents(), "two\nthree\nfour\nfi");
+        assert_eq!(truncate("abcdef", 4), "abc");
+        assert_eq!(truncate("ab", 4), "ab");
+    }
+}
@@ -0,0 +1,241 @@
+// Authenticode, the signatures on PE images that Secure Boot checks. The signature is PKCS #7 signed data in the
+// image's certificate table, signing a digest of the image without the checksum, the certificate table and the
+// table's entry in the data directories, since those change when it's signed.
+//
+// Checking here is how firmware does it against db: an image is allowed if its digest is trusted outright or a
+// signature on it chains to a trusted certificate. Nothing about dbx, which is for the caller to look at.
+
+use {Result, EfiErrorKind};
+use ffi::image_authentication::{WIN_CERTIFICATE, WIN_CERT_REVISION_2_0, WIN_CERT_TYPE_PKCS_SIGNED_DATA};
+use hash::{Sha256, SHA256_LEN};
+use super::der::{self, Reader};
+use super::pkcs7::SignedData;
+use super::trust::TrustStore;
+use alloc::vec::Vec;
+use core::{mem, ops::Range};
+use byteorder::{ByteOrder, LittleEndian};
+
+const PE_OFFSET: usize = 0x3c;
+// The PE signature and COFF file header
+const COFF_SIZE: usize = 24;
+const SECTION_SIZE: usize = 40;
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+// Where things are in the optional header
+const SIZE_OF_HEADERS: usize = 60;
+const CHECKSUM: usize = 64;
+const PE32_DIRECTORIES: usize = 92; // Starting with their count
+const PE32_PLUS_DIRECTORIES: usize = 108;
+const CERTIFICATE_DIRECTORY: usize = 4;
+
+/// The image's Authenticode SHA-256 digest, which is what db and dbx list images by. LoadError if it isn't a PE image
+pub fn image_sha256(image: &[u8]) -> Result<[u8; SHA256_LEN]> {
+    let layout = Layout::parse(image)?;
+    let mut hasher = Sha256::new();
+    hasher.update(&image[..layout.checksum]);
+    hasher.update(&image[layout.checksum + 4..layout.certificate_directory]);
+    hasher.update(&image[layout.certificate_directory + 8..layout.headers_end]);
+
+    // Sections in the order they're in the file, then whatever's after them apart from the certificates
+    let mut hashed = layout.headers_end;
+    let mut sections = layout.sections;
+    sections.sort_by_key(|&(offset, _)| offset);
+    for (offset, size) in sections {
+        let section = offset.checked_add(size).and_then(|end| image.get(offset..end)).ok_or(EfiErrorKind::LoadError)?;
+        hasher.update(section);
+        hashed += size;
+    }
+    let end = if layout.certificates.is_empty() { image.len() } else { layout.certificates.start };
+    if end > hashed {
+        hasher.update(&image[hashed..end]);
+    }
+    Ok(hasher.finish())
+}
+
+/// The Authenticode signatures in the image's certificate table. Entries that aren't PKCS #7 are skipped. LoadError if
+/// it isn't a PE image and InvalidParameter if the table or a signature is malformed
+pub fn signatures(image: &[u8]) -> Result<Vec<SignedData>> {
+    const HEADER: usize = mem::size_of::<WIN_CERTIFICATE>();
+    let layout = Layout::parse(image)?;
+    let mut table = &image[layout.certificates];
+    let mut signatures = Vec::new();
+    while table.len() >= HEADER {
+        let len = LittleEndian::read_u32(table) as usize;
+        let revision = LittleEndian::read_u16(&table[4..]);
+        let certificate_type = LittleEndian::read_u16(&table[6..]);
+        if len < HEADER || len > table.len() {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        if revision == WIN_CERT_REVISION_2_0 && certificate_type == WIN_CERT_TYPE_PKCS_SIGNED_DATA {
+            signatures.push(SignedData::from_der(&table[HEADER..len])?);
+        }
+        // Each entry starts on an 8 byte boundary
+        table = table.get((len + 7) & !7..).unwrap_or(&[]);
+    }
+    Ok(signatures)
+}
+
+/// Checks that the image's digest is trusted or a signature on it chains to a trusted certificate. SecurityViolation
+/// if neither, LoadError if it isn't a PE image
+pub fn verify(image: &[u8], trust: &TrustStore) -> Result<()> {
+    let digest = image_sha256(image)?;
+    if trust.contains_sha256(&digest) {
+        return Ok(());
+    }
+    let mut result = Err(EfiErrorKind::SecurityViolation.into());
+    for signature in signatures(image)? {
+        result = verify_signature(&signature, &digest, trust);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+// The signed SpcIndirectDataContent has to have the image's digest in it
+fn verify_signature(signature: &SignedData, digest: &[u8; SHA256_LEN], trust: &TrustStore) -> Result<()> {
+    if signature.content_type() != der::OID_SPC_INDIRECT_DATA {
+        return Err(EfiErrorKind::SecurityViolation.into());
+    }
+    let mut content = Reader::new(signature.content().ok_or(EfiErrorKind::SecurityViolation)?);
+    content.read_tag(der::SEQUENCE)?; // What was signed, which for images says nothing we need
+    let mut digest_info = content.read_tag(der::SEQUENCE)?.reader();
+    if digest_info.read_algorithm()? != der::OID_SHA256 {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    if digest_info.read_tag(der::OCTET_STRING)?.value != &digest[..] {
+        return Err(EfiErrorKind::SecurityViolation.into());
+    }
+    signature.verify(trust).map(|_| ())
+}
+
+// Offsets into the image of what's left out of its digest, checked to be in bounds
+struct Layout {
+    checksum: usize,
+    certificate_directory: usize,
+    headers_end: usize,
+    sections: Vec<(usize, usize)>, // Offset and size in the file
+    certificates: Range<usize>,
+}
+
+impl Layout {
+    fn parse(image: &[u8]) -> Result<Self> {
+        let field = |offset: usize, len: usize| image.get(offset..offset + len).ok_or(EfiErrorKind::LoadError);
+        if field(0, 2)? != b"MZ" {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let pe = LittleEndian::read_u32(field(PE_OFFSET, 4)?) as usize;
+        if field(pe, 4)? != b"PE\0\0" {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let count = LittleEndian::read_u16(field(pe + 6, 2)?) as usize;
+        let optional_header = pe + COFF_SIZE;
+        let optional_header_size = LittleEndian::read_u16(field(pe + 20, 2)?) as usize;
+        let directories = match LittleEndian::read_u16(field(optional_header, 2)?) {
+            PE32_MAGIC => optional_header + PE32_DIRECTORIES,
+            PE32_PLUS_MAGIC => optional_header + PE32_PLUS_DIRECTORIES,
+            _ => return Err(EfiErrorKind::LoadError.into()),
+        };
+        if (LittleEndian::read_u32(field(directories, 4)?) as usize) <= CERTIFICATE_DIRECTORY {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+        let certificate_directory = directories + 4 + CERTIFICATE_DIRECTORY * 8;
+        let directory = field(certificate_directory, 8)?;
+        let start = LittleEndian::read_u32(directory) as usize;
+        let certificates = start..start + LittleEndian::read_u32(&directory[4..]) as usize;
+        let headers_end = LittleEndian::read_u32(field(optional_header + SIZE_OF_HEADERS, 4)?) as usize;
+        if headers_end > image.len() || headers_end < certificate_directory + 8 || certificates.end > image.len() {
+            return Err(EfiErrorKind::LoadError.into());
+        }
+
+        let table = field(optional_header + optional_header_size, count * SECTION_SIZE)?;
+        let sections = table.chunks(SECTION_SIZE)
+            .map(|s| (LittleEndian::read_u32(&s[20..]) as usize, LittleEndian::read_u32(&s[16..]) as usize))
+            .filter(|&(_, size)| size > 0)
+            .collect();
+        Ok(Layout { checksum: optional_header + CHECKSUM, certificate_directory, headers_end, sections, certificates })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use super::super::testdata::{ROOT_PEM, AUTHENTICODE_SIGNATURE};
+    use hash::{from_hex, to_hex};
+
+    // A PE32+ image with two sections, the second first in the file
+    pub(crate) fn image() -> Vec<u8> {
+        let pe = 0x80;
+        let optional_header = pe + COFF_SIZE;
+        let table = optional_header + 240;
+        let mut data = vec![0u8; 0x600];
+        data[..2].copy_from_slice(b"MZ");
+        LittleEndian::write_u32(&mut data[PE_OFFSET..], pe as u32);
+        data[pe..pe + 4].copy_from_slice(b"PE\0\0");
+        LittleEndian::write_u16(&mut data[pe + 4..], 0x8664);
+        LittleEndian::write_u16(&mut data[pe + 6..], 2);
+        LittleEndian::write_u16(&mut data[pe + 20..], 240);
+        LittleEndian::write_u16(&mut data[optional_header..], PE32_PLUS_MAGIC);
+        LittleEndian::write_u32(&mut data[optional_header + SIZE_OF_HEADERS..], 0x200);
+        LittleEndian::write_u32(&mut data[optional_header + CHECKSUM..], 0x1234_5678);
+        LittleEndian::write_u32(&mut data[optional_header + PE32_PLUS_DIRECTORIES..], 16);
+        for (i, &(name, offset)) in [(".text", 0x400u32), (".data", 0x200)].iter().enumerate() {
+            let header = table + i * SECTION_SIZE;
+            data[header..header + name.len()].copy_from_slice(name.as_bytes());
+            LittleEndian::write_u32(&mut data[header + 16..], 0x200);
+            LittleEndian::write_u32(&mut data[header + 20..], offset);
+        }
+        for b in &mut data[0x200..0x400] {
+            *b = 0xDD;
+        }
+        for b in &mut data[0x400..] {
+            *b = 0xCC;
+        }
+        data
+    }
+
+    pub(crate) fn sign(mut image: Vec<u8>, signature: &[u8]) -> Vec<u8> {
+        let start = image.len();
+        let len = 8 + signature.len();
+        image.extend(&(len as u32).to_le_bytes());
+        image.extend(&WIN_CERT_REVISION_2_0.to_le_bytes());
+        image.extend(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+        image.extend(signature);
+        image.resize(start + ((len + 7) & !7), 0);
+        let directory = 0x98 + PE32_PLUS_DIRECTORIES + 4 + CERTIFICATE_DIRECTORY * 8;
+        LittleEndian::write_u32(&mut image[directory..], start as u32);
+        let size = image.len() - start;
+        LittleEndian::write_u32(&mut image[directory + 4..], size as u32);
+        image
+    }
+
+    #[test]
+    fn verifies_signed_images() {
+        let unsigned = image();
+        let digest = image_sha256(&unsigned).unwrap();
+        assert_eq!(to_hex(&digest), "2e510fb350e74751b34e89c95a4ea9094f4a65908224fda479de07a459cd1d3d");
+        let signed = sign(unsigned.clone(), &from_hex(AUTHENTICODE_SIGNATURE).unwrap());
+        assert_eq!(image_sha256(&signed).unwrap(), digest);
+        assert_eq!(signatures(&signed).unwrap().len(), 1);
+        assert!(signatures(&unsigned).unwrap().is_empty());
+
+        let trust = TrustStore::from_pem(ROOT_PEM).unwrap();
+        verify(&signed, &trust).unwrap();
+        assert_eq!(verify(&unsigned, &trust).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert_eq!(verify(&signed, &TrustStore::new()).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        let mut allowed = TrustStore::new();
+        allowed.add_sha256(digest);
+        verify(&unsigned, &allowed).unwrap();
+
+        let mut tampered = signed.clone();
+        tampered[0x450] ^= 1;
+        assert_eq!(verify(&tampered, &trust).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        // The checksum isn't covered, so setting it doesn't break the signature
+        let mut checksummed = signed;
+        checksummed[0x98 + CHECKSUM] = 0x42;
+        verify(&checksummed, &trust).unwrap();
+
+        assert_eq!(image_sha256(&unsigned[..0x100]).unwrap_err().kind(), EfiErrorKind::LoadError);
+        assert_eq!(image_sha256(b"not an image").unwrap_err().kind(), EfiErrorKind::LoadError);
+    }
+}
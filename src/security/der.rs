@@ -0,0 +1,155 @@
+// Just enough DER (X.690) to pick certificates and signatures apart. Only single byte tags and definite lengths,
+// since that's all DER allows for what we read. Anything malformed is InvalidParameter.
+
+use {Result, EfiErrorKind};
+use time::UnixTime;
+use ffi::{EFI_TIME, EFI_UNSPECIFIED_TIMEZONE};
+
+pub(crate) const BOOLEAN: u8 = 0x01;
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const OID: u8 = 0x06;
+pub(crate) const UTC_TIME: u8 = 0x17;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+
+/// A constructed context specific tag, `[n]` in ASN.1
+pub(crate) const fn context(n: u8) -> u8 {
+    0xA0 | n
+}
+
+// Object identifiers as they're encoded, without the tag and length
+pub(crate) const OID_RSA_ENCRYPTION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+pub(crate) const OID_SHA256_WITH_RSA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B];
+pub(crate) const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+pub(crate) const OID_SIGNED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+pub(crate) const OID_CONTENT_TYPE: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x03];
+pub(crate) const OID_MESSAGE_DIGEST: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x04];
+pub(crate) const OID_SPC_INDIRECT_DATA: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x04];
+pub(crate) const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+pub(crate) const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1D, 0x13];
+pub(crate) const OID_KEY_USAGE: &[u8] = &[0x55, 0x1D, 0x0F];
+
+/// One element: its tag, what's inside, and the whole of it including the tag and length
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Element<'a> {
+    pub tag: u8,
+    pub value: &'a [u8],
+    pub der: &'a [u8],
+}
+
+impl<'a> Element<'a> {
+    /// The elements inside a constructed one
+    pub fn reader(&self) -> Reader<'a> {
+        Reader::new(self.value)
+    }
+}
+
+/// Elements one after another
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().cloned()
+    }
+
+    pub fn read(&mut self) -> Result<Element<'a>> {
+        let data = self.data;
+        if data.len() < 2 || data[0] & 0x1F == 0x1F {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let (len, header) = match data[1] {
+            len if len < 0x80 => (len as usize, 2),
+            0x81..=0x84 => {
+                let count = (data[1] & 0x7F) as usize;
+                let bytes = data.get(2..2 + count).ok_or(EfiErrorKind::InvalidParameter)?;
+                (bytes.iter().fold(0usize, |len, &b| len << 8 | b as usize), 2 + count)
+            },
+            _ => return Err(EfiErrorKind::InvalidParameter.into()), // Indefinite, or longer than we'd ever read
+        };
+        let end = header.checked_add(len).filter(|&end| end <= data.len()).ok_or(EfiErrorKind::InvalidParameter)?;
+        self.data = &data[end..];
+        Ok(Element { tag: data[0], value: &data[header..end], der: &data[..end] })
+    }
+
+    /// The next element, which has to have tag `tag`
+    pub fn read_tag(&mut self, tag: u8) -> Result<Element<'a>> {
+        let element = self.read()?;
+        if element.tag != tag {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(element)
+    }
+
+    /// The next element if it has tag `tag`
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<Element<'a>>> {
+        if self.peek_tag() == Some(tag) { self.read().map(Some) } else { Ok(None) }
+    }
+
+    /// The OID of an AlgorithmIdentifier, skipping the parameters
+    pub fn read_algorithm(&mut self) -> Result<&'a [u8]> {
+        Ok(self.read_tag(SEQUENCE)?.reader().read_tag(OID)?.value)
+    }
+}
+
+/// An INTEGER's value without the leading zero that keeps positive numbers positive
+pub(crate) fn unsigned(integer: &[u8]) -> &[u8] {
+    match integer {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        _ => integer,
+    }
+}
+
+/// A UTCTime or GeneralizedTime, which in certificates is always UTC to the second. Times before 1970 are taken to be
+/// 1970
+pub(crate) fn time(element: &Element) -> Result<UnixTime> {
+    let text = element.value;
+    let (year, rest) = match element.tag {
+        UTC_TIME if text.len() == 13 => {
+            let year = digits(&text[..2])?;
+            (if year < 50 { 2000 + year } else { 1900 + year }, &text[2..])
+        },
+        GENERALIZED_TIME if text.len() == 15 => (digits(&text[..4])?, &text[4..]),
+        _ => return Err(EfiErrorKind::InvalidParameter.into()),
+    };
+    if rest[10] != b'Z' {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+    if year < 1970 {
+        return Ok(UnixTime::from_since_epoch(Default::default()));
+    }
+    let time = EFI_TIME {
+        Year: year as u16,
+        Month: digits(&rest[0..2])? as u8,
+        Day: digits(&rest[2..4])? as u8,
+        Hour: digits(&rest[4..6])? as u8,
+        Minute: digits(&rest[6..8])? as u8,
+        Second: digits(&rest[8..10])? as u8,
+        Pad1: 0,
+        Nanosecond: 0,
+        TimeZone: EFI_UNSPECIFIED_TIMEZONE as i16,
+        Daylight: 0,
+        Pad2: 0,
+    };
+    UnixTime::from_efi_time(&time)
+}
+
+fn digits(text: &[u8]) -> Result<u32> {
+    text.iter().try_fold(0, |n, &c| match c {
+        b'0'..=b'9' => Ok(n * 10 + (c - b'0') as u32),
+        _ => Err(EfiErrorKind::InvalidParameter.into()),
+    })
+}
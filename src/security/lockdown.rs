@@ -0,0 +1,238 @@
+// Lockdown: refusing to start images that have been revoked. Firmware checks dbx when it verifies an image, but it
+// doesn't verify images loaded from memory when Secure Boot is off, and a boot manager that checks signatures itself
+// against its own CA never asks firmware at all. Turning lockdown on makes the crate check every image it loads,
+// and every kernel with an EFI stub it boots, against a DenyList first:
+//
+//     let mut deny = DenyList::from_dbx()?;
+//     deny.add_sha256(known_bad_kernel);
+//     lockdown::enable(Lockdown::new(deny, Mode::Enforce));
+//
+// An image is revoked if its Authenticode digest is denied, if a signature on it chains to a denied certificate, or
+// if it carries a certificate whose TBS digest is denied, which is everything dbx can say about an image. Signatures
+// that can't be checked, such as SHA-1 ones, don't count either way.
+
+use {Result, EfiErrorKind};
+use ffi::image_authentication::{EFI_CERT_X509_GUID, EFI_CERT_SHA256_GUID, EFI_CERT_X509_SHA256_GUID,
+    EFI_IMAGE_SECURITY_DATABASE1, EFI_IMAGE_SECURITY_DATABASE_GUID};
+use firmware::firmware;
+use hash::{self, SHA256_LEN};
+use utils::TryLock;
+use super::authenticode;
+use super::trust::{self, TrustStore};
+use super::x509::Certificate;
+use alloc::vec::Vec;
+
+/// Images, signers and certificates that mustn't be started or believed
+#[derive(Debug, Clone, Default)]
+pub struct DenyList {
+    // Denied certificates and image digests, checked against the same way db's are trusted
+    revoked: TrustStore,
+    tbs_sha256: Vec<[u8; SHA256_LEN]>,
+}
+
+impl DenyList {
+    /// An empty list, which denies nothing
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Whatever's in the Secure Boot dbx. NotFound if there's no dbx
+    pub fn from_dbx() -> Result<Self> {
+        Self::from_signature_lists(&firmware().get_variable(EFI_IMAGE_SECURITY_DATABASE1, &EFI_IMAGE_SECURITY_DATABASE_GUID)?)
+    }
+
+    /// From EFI_SIGNATURE_LISTs, the format of dbx and its updates. X.509 certificates, SHA-256 image digests and
+    /// SHA-256 TBS digests are kept and other types skipped. InvalidParameter if the lists are malformed
+    pub fn from_signature_lists(data: &[u8]) -> Result<Self> {
+        let mut deny = DenyList::new();
+        for (signature_type, signature) in trust::signature_lists(data)? {
+            if signature_type == EFI_CERT_X509_GUID {
+                if let Ok(cert) = Certificate::from_der(signature) {
+                    deny.add_certificate(cert);
+                }
+            } else if signature_type == EFI_CERT_SHA256_GUID && signature.len() == SHA256_LEN {
+                deny.add_sha256(digest(signature));
+            } else if signature_type == EFI_CERT_X509_SHA256_GUID && signature.len() >= SHA256_LEN {
+                // The digest is followed by when the certificate was revoked, which we don't go by
+                deny.add_tbs_sha256(digest(&signature[..SHA256_LEN]));
+            }
+        }
+        Ok(deny)
+    }
+
+    /// Denies images signed by `cert` or anything it issued
+    pub fn add_certificate(&mut self, cert: Certificate) {
+        self.revoked.add(cert);
+    }
+
+    /// Denies the image with this Authenticode digest
+    pub fn add_sha256(&mut self, digest: [u8; SHA256_LEN]) {
+        self.revoked.add_sha256(digest);
+    }
+
+    /// Denies the certificate whose TBSCertificate has this digest
+    pub fn add_tbs_sha256(&mut self, digest: [u8; SHA256_LEN]) {
+        if !self.tbs_sha256.contains(&digest) {
+            self.tbs_sha256.push(digest);
+        }
+    }
+
+    /// Everything in `other` as well
+    pub fn extend(&mut self, other: DenyList) {
+        for cert in other.revoked.certificates() {
+            self.add_certificate(cert.clone());
+        }
+        for digest in other.revoked.sha256_digests() {
+            self.add_sha256(*digest);
+        }
+        for digest in other.tbs_sha256 {
+            self.add_tbs_sha256(digest);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revoked.is_empty() && self.tbs_sha256.is_empty()
+    }
+
+    /// Whether the certificate itself is denied, by its DER or its TBS digest
+    pub fn denies_certificate(&self, cert: &Certificate) -> bool {
+        self.revoked.certificates().contains(cert) || self.tbs_sha256.contains(&hash::sha256(cert.tbs_der()))
+    }
+
+    /// Checks that the image isn't revoked. SecurityViolation if it is, LoadError if it isn't a PE image and
+    /// InvalidParameter if its signatures are malformed
+    pub fn check(&self, image: &[u8]) -> Result<()> {
+        match authenticode::verify(image, &self.revoked) {
+            Ok(()) => return Err(EfiErrorKind::SecurityViolation.into()),
+            Err(e) if e.kind() == EfiErrorKind::SecurityViolation || e.kind() == EfiErrorKind::Unsupported => (),
+            Err(e) => return Err(e),
+        }
+        for signature in authenticode::signatures(image)? {
+            if signature.certificates().iter().any(|cert| self.denies_certificate(cert)) {
+                return Err(EfiErrorKind::SecurityViolation.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What to do about a revoked image
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Refuse to load it
+    Enforce,
+    /// Say so on the console and load it anyway, for trying out a deny list before relying on it
+    Warn,
+}
+
+/// A deny list and what to do about what it denies
+#[derive(Debug, Clone)]
+pub struct Lockdown {
+    deny: DenyList,
+    mode: Mode,
+}
+
+impl Lockdown {
+    pub fn new(deny: DenyList, mode: Mode) -> Self {
+        Lockdown { deny, mode }
+    }
+
+    pub fn deny_list(&self) -> &DenyList {
+        &self.deny
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Checks the image, naming it `description` if it has to warn. In Mode::Warn this only fails if the image
+    /// can't be checked at all
+    pub fn check(&self, image: &[u8], description: &str) -> Result<()> {
+        match self.deny.check(image) {
+            Err(e) if e.kind() == EfiErrorKind::SecurityViolation && self.mode == Mode::Warn => {
+                println!("warning: {} is revoked, starting it anyway", description);
+                Ok(())
+            },
+            result => result,
+        }
+    }
+}
+
+static LOCKDOWN: TryLock<Option<Lockdown>> = TryLock::new(None);
+
+/// Checks what the crate loads from now on
+pub fn enable(lockdown: Lockdown) {
+    LOCKDOWN.try_with(|l| *l = Some(lockdown));
+}
+
+pub fn disable() {
+    LOCKDOWN.try_with(|l| *l = None);
+}
+
+pub fn is_enabled() -> bool {
+    LOCKDOWN.try_with(|l| l.is_some()).unwrap_or(true)
+}
+
+/// Checks `image` if lockdown is on. Fails closed if the lock's held, since it can't be told whether it's on
+pub(crate) fn check(image: &[u8], description: &str) -> Result<()> {
+    match LOCKDOWN.try_with(|l| l.as_ref().map(|lockdown| lockdown.check(image, description))) {
+        Some(Some(result)) => result,
+        Some(None) => Ok(()),
+        None => Err(EfiErrorKind::SecurityViolation.into()),
+    }
+}
+
+fn digest(data: &[u8]) -> [u8; SHA256_LEN] {
+    let mut digest = [0; SHA256_LEN];
+    digest.copy_from_slice(data);
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::authenticode::tests::{image, sign};
+    use super::super::testdata::{ROOT_PEM, SIGNER_PEM, AUTHENTICODE_SIGNATURE};
+    use super::super::trust::tests::signature_list;
+    use hash::from_hex;
+
+    #[test]
+    fn denies_revoked_images() {
+        let root = Certificate::from_pem(ROOT_PEM).unwrap().remove(0);
+        let signer = Certificate::from_pem(SIGNER_PEM).unwrap().remove(0);
+        let unsigned = image();
+        let signed = sign(unsigned.clone(), &from_hex(AUTHENTICODE_SIGNATURE).unwrap());
+
+        let empty = DenyList::new();
+        assert!(empty.is_empty());
+        empty.check(&signed).unwrap();
+        empty.check(&unsigned).unwrap();
+        assert_eq!(empty.check(b"not an image").unwrap_err().kind(), EfiErrorKind::LoadError);
+
+        // By the image's digest, whether or not it's signed
+        let mut dbx = signature_list(&EFI_CERT_SHA256_GUID, &[&authenticode::image_sha256(&unsigned).unwrap()]);
+        let by_digest = DenyList::from_signature_lists(&dbx).unwrap();
+        assert_eq!(by_digest.check(&signed).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert_eq!(by_digest.check(&unsigned).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+
+        // By the CA the signer chains to, which the signature doesn't carry
+        let mut by_ca = DenyList::new();
+        by_ca.add_certificate(root.clone());
+        assert_eq!(by_ca.check(&signed).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        by_ca.check(&unsigned).unwrap();
+
+        // By the signer's TBS digest, followed by the time of revocation
+        let mut revocation = hash::sha256(signer.tbs_der()).to_vec();
+        revocation.extend(&[0; 16]);
+        dbx.extend(signature_list(&EFI_CERT_X509_SHA256_GUID, &[&revocation]));
+        let mut by_tbs = DenyList::from_signature_lists(&dbx[dbx.len() - 28 - 16 - 48..]).unwrap();
+        assert!(by_tbs.denies_certificate(&signer) && !by_tbs.denies_certificate(&root));
+        assert_eq!(by_tbs.check(&signed).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        by_tbs.check(&unsigned).unwrap();
+
+        by_tbs.extend(by_digest);
+        assert_eq!(by_tbs.check(&unsigned).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+
+        assert_eq!(Lockdown::new(by_ca, Mode::Enforce).check(&signed, "image").unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+    }
+}
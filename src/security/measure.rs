@@ -0,0 +1,311 @@
+// Measured boot: hashing what's loaded into the TPM's PCRs and the event log before it runs, so the TPM can later
+// attest to, or only unseal secrets for, exactly what booted. Firmware measures itself and the images it loads
+// from devices; this carries on for what the crate loads from memory, i.e. everything image, linux, multiboot2 and pvh
+// are handed as bytes.
+//
+// That happens on its own whenever there's a TPM. The Policy picks which PCR and event type each kind of component
+// goes to, or leaves a kind out. It defaults to what GRUB does, so PCR policies written for GRUB carry over:
+//
+//     // systemd-stub's PCR for the command line
+//     measure::set_policy(Policy::new().with(Component::Cmdline, Some(Measurement::new(12, tcg2::EV_IPL))));
+//
+// Measuring fails closed: if the TPM is there but measuring doesn't work, nothing is loaded. A component that booted
+// without being measured would leave the PCRs looking like something else booted.
+
+use {Result, EfiErrorKind, system_table, image_handle};
+use ffi::{
+    tcg2::{EFI_TCG2_PROTOCOL, EFI_TCG2_PROTOCOL_GUID, EFI_TCG2_BOOT_SERVICE_CAPABILITY, EFI_TCG2_EVENT,
+        EFI_TCG2_EVENT_HEADER, EFI_TCG2_EVENT_HEADER_VERSION, PE_COFF_IMAGE, EV_IPL},
+    boot_services::EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    EFI_HANDLE,
+    EFI_PHYSICAL_ADDRESS,
+};
+use utils::TryLock;
+use alloc::vec::Vec;
+use core::{mem, ptr, slice};
+
+/// Where GRUB measures what it loads
+pub const GRUB_CODE_PCR: u32 = 9;
+/// Where GRUB measures command lines
+pub const GRUB_CMDLINE_PCR: u32 = 8;
+
+/// A kind of thing that gets measured
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Component {
+    /// A UEFI image loaded from memory, e.g. a chain loaded UKI or a kernel's EFI stub loaded by hand
+    Image,
+    /// A kernel booted by linux, multiboot2 or pvh
+    Kernel,
+    /// An initrd, or a multiboot2 or PVH module
+    Initrd,
+    /// A kernel's command line, or a module's
+    Cmdline,
+}
+
+/// Which PCR to extend and the type of the event logged for it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Measurement {
+    pub pcr: u32,
+    pub event_type: u32,
+}
+
+impl Measurement {
+    pub const fn new(pcr: u32, event_type: u32) -> Self {
+        Measurement { pcr, event_type }
+    }
+}
+
+/// What to measure where
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Policy {
+    image: Option<Measurement>,
+    kernel: Option<Measurement>,
+    initrd: Option<Measurement>,
+    cmdline: Option<Measurement>,
+}
+
+impl Policy {
+    /// Everything as GRUB does it: code and data into PCR 9 and command lines into PCR 8, all as EV_IPL
+    pub const fn new() -> Self {
+        let code = Some(Measurement::new(GRUB_CODE_PCR, EV_IPL));
+        Policy { image: code, kernel: code, initrd: code, cmdline: Some(Measurement::new(GRUB_CMDLINE_PCR, EV_IPL)) }
+    }
+
+    /// Measures nothing
+    pub const fn disabled() -> Self {
+        Policy { image: None, kernel: None, initrd: None, cmdline: None }
+    }
+
+    /// Measures the component as given, or not at all for None
+    pub fn with(mut self, component: Component, measurement: Option<Measurement>) -> Self {
+        *self.slot(component) = measurement;
+        self
+    }
+
+    pub fn get(&self, component: Component) -> Option<Measurement> {
+        match component {
+            Component::Image => self.image,
+            Component::Kernel => self.kernel,
+            Component::Initrd => self.initrd,
+            Component::Cmdline => self.cmdline,
+        }
+    }
+
+    /// Measures `data` into the TPM if the policy says to, logging `description` as the event. Returns whether it
+    /// did. Images are hashed as they are rather than by their Authenticode digest, the same as GRUB
+    pub fn measure(&self, tpm: &Tpm, component: Component, data: &[u8], description: &[u8]) -> Result<bool> {
+        match self.get(component) {
+            Some(measurement) => tpm.hash_log_extend(measurement, data, description).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn slot(&mut self, component: Component) -> &mut Option<Measurement> {
+        match component {
+            Component::Image => &mut self.image,
+            Component::Kernel => &mut self.kernel,
+            Component::Initrd => &mut self.initrd,
+            Component::Cmdline => &mut self.cmdline,
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::new()
+    }
+}
+
+static POLICY: TryLock<Policy> = TryLock::new(Policy::new());
+
+/// The policy for what the crate loads from now on
+pub fn set_policy(policy: Policy) {
+    POLICY.try_with(|p| *p = policy);
+}
+
+pub fn policy() -> Policy {
+    POLICY.try_with(|p| *p).unwrap_or_default() // Measuring by default rather than not is the safe side
+}
+
+/// Measures `data` according to the current policy if there's a TPM
+pub(crate) fn measure(component: Component, data: &[u8], description: &str) -> Result<()> {
+    let policy = policy();
+    if policy.get(component).is_none() {
+        return Ok(());
+    }
+    match Tpm::locate() {
+        Ok(tpm) => policy.measure(&tpm, component, data, description.as_bytes()).map(|_| ()),
+        Err(_) => Ok(()),
+    }
+}
+
+/// A TPM 2.0, through EFI_TCG2_PROTOCOL
+pub struct Tpm {
+    protocol: *const EFI_TCG2_PROTOCOL,
+}
+
+impl Tpm {
+    /// The TPM. NotFound if there isn't one, including when firmware has the protocol but says there's no TPM
+    pub fn locate() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_TCG2_PROTOCOL = ptr::null();
+        let status = unsafe { traced!(((*bs).LocateProtocol)(&EFI_TCG2_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol))) };
+        if !::ffi::IsSuccess(status) || protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Self::present(protocol)
+    }
+
+    /// The TPM behind the protocol on `handle`
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_TCG2_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).OpenProtocol)(handle, &EFI_TCG2_PROTOCOL_GUID, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_GET_PROTOCOL));
+        }
+        if protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Self::present(protocol)
+    }
+
+    fn present(protocol: *const EFI_TCG2_PROTOCOL) -> Result<Self> {
+        let tpm = Tpm { protocol };
+        if tpm.capability()?.TPMPresentFlag == 0 {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Ok(tpm)
+    }
+
+    /// What firmware says about the TPM: its hash algorithms, active PCR banks, manufacturer and so on
+    pub fn capability(&self) -> Result<EFI_TCG2_BOOT_SERVICE_CAPABILITY> {
+        let mut capability = EFI_TCG2_BOOT_SERVICE_CAPABILITY { Size: mem::size_of::<EFI_TCG2_BOOT_SERVICE_CAPABILITY>() as u8, ..Default::default() };
+        unsafe {
+            ret_on_err!(((*self.protocol).GetCapability)(self.protocol, &mut capability));
+        }
+        Ok(capability)
+    }
+
+    /// Hashes `data` into the PCR with every active bank and logs `event` with it
+    pub fn hash_log_extend(&self, measurement: Measurement, data: &[u8], event: &[u8]) -> Result<()> {
+        self.extend(0, measurement, data, event)
+    }
+
+    /// Measures a PE image the way firmware does on LoadImage, by its Authenticode digest
+    pub fn hash_log_extend_pe_image(&self, measurement: Measurement, image: &[u8], event: &[u8]) -> Result<()> {
+        self.extend(PE_COFF_IMAGE, measurement, image, event)
+    }
+
+    fn extend(&self, flags: u64, measurement: Measurement, data: &[u8], event: &[u8]) -> Result<()> {
+        const HEADER: usize = mem::size_of::<EFI_TCG2_EVENT>();
+        let size = HEADER + event.len();
+        let header = EFI_TCG2_EVENT {
+            Size: size as u32,
+            Header: EFI_TCG2_EVENT_HEADER {
+                HeaderSize: mem::size_of::<EFI_TCG2_EVENT_HEADER>() as u32,
+                HeaderVersion: EFI_TCG2_EVENT_HEADER_VERSION,
+                PCRIndex: measurement.pcr,
+                EventType: measurement.event_type,
+            },
+        };
+        let mut buf = Vec::with_capacity(size);
+        buf.extend_from_slice(unsafe { slice::from_raw_parts(&header as *const _ as *const u8, HEADER) });
+        buf.extend_from_slice(event);
+        unsafe {
+            ret_on_err!(((*self.protocol).HashLogExtendEvent)(self.protocol, flags, data.as_ptr() as usize as EFI_PHYSICAL_ADDRESS, data.len() as u64, buf.as_ptr() as *const EFI_TCG2_EVENT));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EFI_INTERFACE_TYPE, tcg2::*, EFI_STATUS, EFI_SUCCESS, EFI_UNSUPPORTED, BOOLEAN, UINT8, VOID};
+    use hash::{sha256, SHA256_LEN};
+    use testing::mock;
+    use alloc::boxed::Box;
+    use core::cell::RefCell;
+
+    #[repr(C)]
+    struct FakeTpm {
+        protocol: EFI_TCG2_PROTOCOL,
+        events: RefCell<Vec<(u32, u32, [u8; SHA256_LEN], Vec<u8>)>>,
+    }
+
+    fn fake(this: *const EFI_TCG2_PROTOCOL) -> &'static FakeTpm {
+        unsafe { &*(this as *const FakeTpm) }
+    }
+
+    extern "efiapi" fn get_capability(_this: *const EFI_TCG2_PROTOCOL, capability: *mut EFI_TCG2_BOOT_SERVICE_CAPABILITY) -> EFI_STATUS {
+        unsafe {
+            (*capability).TPMPresentFlag = 1;
+            (*capability).ActivePcrBanks = EFI_TCG2_BOOT_HASH_ALG_SHA256;
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn hash_log_extend_event(this: *const EFI_TCG2_PROTOCOL, _flags: u64, data: EFI_PHYSICAL_ADDRESS, len: u64, event: *const EFI_TCG2_EVENT) -> EFI_STATUS {
+        unsafe {
+            let header = *event;
+            let data = slice::from_raw_parts(data as usize as *const u8, len as usize);
+            let event = slice::from_raw_parts((event as *const u8).add(mem::size_of::<EFI_TCG2_EVENT>()), header.Size as usize - mem::size_of::<EFI_TCG2_EVENT>());
+            fake(this).events.borrow_mut().push((header.Header.PCRIndex, header.Header.EventType, sha256(data), event.to_vec()));
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_event_log(_this: *const EFI_TCG2_PROTOCOL, _format: u32, _location: *mut EFI_PHYSICAL_ADDRESS, _last: *mut EFI_PHYSICAL_ADDRESS, _truncated: *mut BOOLEAN) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn submit_command(_this: *const EFI_TCG2_PROTOCOL, _in_size: u32, _input: *const UINT8, _out_size: u32, _output: *mut UINT8) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_active_pcr_banks(_this: *const EFI_TCG2_PROTOCOL, _banks: *mut u32) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn set_active_pcr_banks(_this: *const EFI_TCG2_PROTOCOL, _banks: u32) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_result_of_set_active_pcr_banks(_this: *const EFI_TCG2_PROTOCOL, _present: *mut u32, _response: *mut u32) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    #[test]
+    fn measures_by_policy() {
+        mock::install();
+        let fake: &'static FakeTpm = Box::leak(Box::new(FakeTpm {
+            protocol: EFI_TCG2_PROTOCOL {
+                GetCapability: get_capability,
+                GetEventLog: get_event_log,
+                HashLogExtendEvent: hash_log_extend_event,
+                SubmitCommand: submit_command,
+                GetActivePcrBanks: get_active_pcr_banks,
+                SetActivePcrBanks: set_active_pcr_banks,
+                GetResultOfSetActivePcrBanks: get_result_of_set_active_pcr_banks,
+            },
+            events: RefCell::new(Vec::new()),
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_TCG2_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, &fake.protocol as *const _ as *const VOID);
+        }
+        let tpm = Tpm::new(handle).unwrap();
+        assert_eq!({ tpm.capability().unwrap().ActivePcrBanks }, EFI_TCG2_BOOT_HASH_ALG_SHA256);
+
+        let policy = Policy::new().with(Component::Cmdline, Some(Measurement::new(12, EV_EVENT_TAG))).with(Component::Initrd, None);
+        assert!(policy.measure(&tpm, Component::Kernel, b"kernel", b"vmlinuz").unwrap());
+        assert!(!policy.measure(&tpm, Component::Initrd, b"initrd", b"initrd.img").unwrap());
+        assert!(policy.measure(&tpm, Component::Cmdline, b"root=/dev/sda2", b"root=/dev/sda2").unwrap());
+        assert!(!Policy::disabled().measure(&tpm, Component::Image, b"image", b"").unwrap());
+        assert_eq!(*fake.events.borrow(), [
+            (GRUB_CODE_PCR, EV_IPL, sha256(b"kernel"), b"vmlinuz".to_vec()),
+            (12, EV_EVENT_TAG, sha256(b"root=/dev/sda2"), b"root=/dev/sda2".to_vec()),
+        ]);
+        assert_eq!(Policy::default().get(Component::Cmdline), Some(Measurement::new(GRUB_CMDLINE_PCR, EV_IPL)));
+    }
+}
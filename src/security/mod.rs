@@ -0,0 +1,27 @@
+// Checking that what's about to be used is what it's meant to be: signatures over downloads and images, whoever's
+// firmware verification does or doesn't do.
+//
+// x509 and pkcs7 parse certificates and signed data, and verify_pkcs7() checks signed data with firmware's help if
+// there's any to be had. TrustStore says which certificates to believe, from db or a PEM bundle, and authenticode puts
+// them together to check PE images the way Secure Boot does. measure records what's loaded in the TPM instead, for
+// measured boot, and lockdown refuses what dbx or the caller has revoked.
+// mok reads and asks for changes to shim's Machine Owner Keys.
+
+pub mod rsa;
+pub(crate) mod der;
+pub mod x509;
+pub mod pkcs7;
+pub mod trust;
+pub mod authenticode;
+pub mod measure;
+pub mod lockdown;
+pub mod mok;
+
+pub use self::rsa::RsaPublicKey;
+pub use self::x509::Certificate;
+pub use self::pkcs7::{verify_pkcs7, SignedData};
+pub use self::trust::TrustStore;
+pub use self::lockdown::DenyList;
+
+#[cfg(test)]
+pub(crate) mod testdata;
@@ -0,0 +1,243 @@
+// Machine Owner Keys: the keys shim trusts and denies on top of db and dbx, which the machine's owner manages
+// through MokManager instead of firmware setup. When we're started by shim, MokList and MokListX say what else the
+// owner trusts and has revoked, and ShimLock checks an image against all of it the way shim itself would.
+//
+// Nothing here changes the lists directly: only MokManager does, when shim starts it. Asking for a change leaves a
+// request and a password in variables; on the next boot shim sees them and starts MokManager, which shows the keys
+// and asks for the password before doing anything:
+//
+//     mok::request(Request::Enroll, &mok::signature_lists(&[cert], &[]), "hunter2")?;
+//     os_indications::reboot();
+
+use {Result, EfiErrorKind, system_table};
+use ffi::{
+    shim::*,
+    image_authentication::{EFI_CERT_X509_GUID, EFI_CERT_SHA256_GUID},
+    runtime_services::{EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_RUNTIME_ACCESS},
+    VOID,
+};
+use firmware::{firmware, Firmware};
+use hash::{Sha256, SHA256_LEN};
+use super::lockdown::DenyList;
+use super::trust::{push_signature_list, TrustStore};
+use super::x509::Certificate;
+use alloc::vec::Vec;
+use core::{mem, ptr};
+
+const ATTRIBUTES: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+
+/// The keys the owner has enrolled, and shim's built in vendor certificate. NotFound if there's no MokList,
+/// i.e. we weren't started by shim or the owner hasn't enrolled anything
+pub fn mok_list() -> Result<TrustStore> {
+    TrustStore::from_signature_lists(&read_list(firmware(), MOK_LIST_RT, MOK_LIST)?)
+}
+
+/// The keys and images the owner has revoked. NotFound if there's no MokListX
+pub fn mok_list_x() -> Result<DenyList> {
+    DenyList::from_signature_lists(&read_list(firmware(), MOK_LIST_X_RT, MOK_LIST_X)?)
+}
+
+/// Whether the owner has turned shim's verification off, in which case it starts anything
+pub fn validation_disabled() -> Result<bool> {
+    validation_disabled_in(firmware())
+}
+
+/// A change to the lists for MokManager to make
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Enroll keys into MokList
+    Enroll,
+    /// Delete keys from MokList
+    Delete,
+    /// Enroll keys into MokListX, revoking them
+    EnrollDeny,
+    /// Delete keys from MokListX
+    DeleteDeny,
+}
+
+impl Request {
+    // The variables holding the keys and the password hash
+    fn variables(self) -> (&'static str, &'static str) {
+        match self {
+            Request::Enroll => (MOK_NEW, MOK_AUTH),
+            Request::Delete => (MOK_DEL, MOK_DEL_AUTH),
+            Request::EnrollDeny => (MOK_X_NEW, MOK_X_AUTH),
+            Request::DeleteDeny => (MOK_X_DEL, MOK_X_DEL_AUTH),
+        }
+    }
+}
+
+/// Asks MokManager to make the change the next time shim starts, once the owner types `password`. `keys` are
+/// EFI_SIGNATURE_LISTs, see signature_lists(). Replaces a request of the same kind that's still pending.
+/// InvalidParameter if there are no keys or the password is empty or longer than MokManager takes
+pub fn request(request: Request, keys: &[u8], password: &str) -> Result<()> {
+    request_in(firmware(), request, keys, password)
+}
+
+/// Takes back a request that MokManager hasn't acted on yet
+pub fn cancel(request: Request) -> Result<()> {
+    cancel_in(firmware(), request)
+}
+
+/// The keys of a request that's waiting for MokManager, if there is one
+pub fn pending(request: Request) -> Result<Option<Vec<u8>>> {
+    pending_in(firmware(), request)
+}
+
+/// EFI_SIGNATURE_LISTs for certificates and image digests, the way mokutil makes them: a list per certificate,
+/// since they differ in size, and one for all the digests, each owned by shim
+pub fn signature_lists(certs: &[Certificate], sha256: &[[u8; SHA256_LEN]]) -> Vec<u8> {
+    let mut lists = Vec::new();
+    for cert in certs {
+        push_signature_list(&mut lists, &EFI_CERT_X509_GUID, &SHIM_LOCK_GUID, &[cert.der()]);
+    }
+    if !sha256.is_empty() {
+        let digests: Vec<&[u8]> = sha256.iter().map(|digest| &digest[..]).collect();
+        push_signature_list(&mut lists, &EFI_CERT_SHA256_GUID, &SHIM_LOCK_GUID, &digests);
+    }
+    lists
+}
+
+/// Shim's protocol for checking images, which it installs for the loaders it starts
+pub struct ShimLock {
+    protocol: *const SHIM_LOCK,
+}
+
+impl ShimLock {
+    /// NotFound if we weren't started by shim
+    pub fn locate() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const SHIM_LOCK = ptr::null();
+        let status = unsafe { traced!(((*bs).LocateProtocol)(&SHIM_LOCK_GUID, ptr::null(), mem::transmute(&protocol))) };
+        if !::ffi::IsSuccess(status) || protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Ok(ShimLock { protocol })
+    }
+
+    /// Checks the image against db, dbx, MokList, MokListX and shim's vendor certificate. SecurityViolation if shim
+    /// wouldn't start it
+    pub fn verify(&self, image: &[u8]) -> Result<()> {
+        if image.len() > u32::max_value() as usize {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        unsafe {
+            ret_on_err!(((*self.protocol).Verify)(image.as_ptr() as *const VOID, image.len() as u32));
+        }
+        Ok(())
+    }
+}
+
+// The RT copy and any parts it was split into, or the boot services only list if there's no copy
+fn read_list(fw: &dyn Firmware, rt: &str, list: &str) -> Result<Vec<u8>> {
+    let mut data = match fw.get_variable(rt, &SHIM_LOCK_GUID) {
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => return fw.get_variable(list, &SHIM_LOCK_GUID),
+        result => result?,
+    };
+    for part in 1.. {
+        match fw.get_variable(&format!("{}{}", rt, part), &SHIM_LOCK_GUID) {
+            Ok(more) => data.extend(more),
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(data)
+}
+
+fn validation_disabled_in(fw: &dyn Firmware) -> Result<bool> {
+    match fw.get_variable(MOK_SB_STATE_RT, &SHIM_LOCK_GUID) {
+        Ok(state) => Ok(state.first() == Some(&1)),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn request_in(fw: &dyn Firmware, request: Request, keys: &[u8], password: &str) -> Result<()> {
+    let password: Vec<u16> = password.encode_utf16().collect();
+    if keys.is_empty() || password.is_empty() || password.len() > SHIM_PASSWORD_MAX {
+        return Err(EfiErrorKind::InvalidParameter.into());
+    }
+    let (keys_name, auth_name) = request.variables();
+    fw.set_variable(keys_name, &SHIM_LOCK_GUID, ATTRIBUTES, keys)?;
+    fw.set_variable(auth_name, &SHIM_LOCK_GUID, ATTRIBUTES, &auth(keys, &password))
+}
+
+fn cancel_in(fw: &dyn Firmware, request: Request) -> Result<()> {
+    let (keys_name, auth_name) = request.variables();
+    for name in &[keys_name, auth_name] {
+        match fw.set_variable(name, &SHIM_LOCK_GUID, ATTRIBUTES, &[]) {
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => (),
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+fn pending_in(fw: &dyn Firmware, request: Request) -> Result<Option<Vec<u8>>> {
+    match fw.get_variable(request.variables().0, &SHIM_LOCK_GUID) {
+        Ok(keys) => Ok(Some(keys)),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// What MokManager compares the password it's typed against
+fn auth(keys: &[u8], password: &[u16]) -> [u8; SHA256_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(keys);
+    for c in password {
+        hasher.update(&c.to_le_bytes());
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testdata::{ROOT_PEM, SIGNER_PEM};
+    use super::super::trust;
+    use ffi::image_authentication::EFI_SIGNATURE_LIST;
+    use firmware::FakeFirmware;
+    use hash::to_hex;
+
+    #[test]
+    fn reads_lists_and_requests_changes() {
+        let root = Certificate::from_pem(ROOT_PEM).unwrap().remove(0);
+        let signer = Certificate::from_pem(SIGNER_PEM).unwrap().remove(0);
+        let lists = signature_lists(&[root.clone(), signer.clone()], &[[1; 32], [2; 32]]);
+        let signatures = trust::signature_lists(&lists).unwrap();
+        assert_eq!(signatures.len(), 4);
+        assert_eq!(signatures[1], (EFI_CERT_X509_GUID, signer.der()));
+        assert_eq!(signatures[3], (EFI_CERT_SHA256_GUID, &[2; 32][..]));
+
+        // Split across MokListRT and MokListRT1, past the first list
+        let fw = FakeFirmware::new();
+        assert_eq!(read_list(&fw, MOK_LIST_RT, MOK_LIST).unwrap_err().kind(), EfiErrorKind::NotFound);
+        let split = mem::size_of::<EFI_SIGNATURE_LIST>() + 16 + root.der().len();
+        fw.set_variable(MOK_LIST_RT, &SHIM_LOCK_GUID, EFI_VARIABLE_BOOTSERVICE_ACCESS, &lists[..split]).unwrap();
+        fw.set_variable("MokListRT1", &SHIM_LOCK_GUID, EFI_VARIABLE_BOOTSERVICE_ACCESS, &lists[split..]).unwrap();
+        let trusted = TrustStore::from_signature_lists(&read_list(&fw, MOK_LIST_RT, MOK_LIST).unwrap()).unwrap();
+        assert_eq!(trusted.certificates(), [root, signer]);
+        assert_eq!(trusted.sha256_digests(), [[1; 32], [2; 32]]);
+
+        assert!(!validation_disabled_in(&fw).unwrap());
+        fw.set_variable(MOK_SB_STATE_RT, &SHIM_LOCK_GUID, EFI_VARIABLE_BOOTSERVICE_ACCESS, &[1]).unwrap();
+        assert!(validation_disabled_in(&fw).unwrap());
+
+        let keys = signature_lists(&[], &[[3; 32]]);
+        request_in(&fw, Request::EnrollDeny, &keys, "pass").unwrap();
+        assert_eq!(pending_in(&fw, Request::EnrollDeny).unwrap(), Some(keys.clone()));
+        assert_eq!(pending_in(&fw, Request::Enroll).unwrap(), None);
+        let (_, auth) = fw.variable(MOK_X_AUTH, &SHIM_LOCK_GUID).unwrap();
+        let mut hashed = keys.clone();
+        hashed.extend(&[b'p', 0, b'a', 0, b's', 0, b's', 0]);
+        assert_eq!(to_hex(&auth), to_hex(&::hash::sha256(&hashed)));
+        assert_eq!(request_in(&fw, Request::Enroll, &keys, "").unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        assert_eq!(request_in(&fw, Request::Enroll, &[], "pass").unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+
+        cancel_in(&fw, Request::EnrollDeny).unwrap();
+        assert_eq!(pending_in(&fw, Request::EnrollDeny).unwrap(), None);
+        assert_eq!(fw.variable(MOK_X_AUTH, &SHIM_LOCK_GUID), None);
+        cancel_in(&fw, Request::EnrollDeny).unwrap();
+    }
+}
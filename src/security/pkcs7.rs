@@ -0,0 +1,359 @@
+// PKCS #7 / CMS signed data (RFC 5652), the envelope Authenticode signatures and most detached signatures come in.
+// Signers are found among the certificates it carries by issuer and serial number, and have to chain to a TrustStore.
+// SHA-256 and RSA only, with or without signed attributes.
+//
+// Firmware may have EFI_PKCS7_VERIFY_PROTOCOL, with a crypto library of its own that handles more than that.
+// verify_pkcs7() uses it where it's there and falls back to SignedData where it isn't, or where it can't check what
+// it's given, so callers get an answer either way:
+//
+//     let trust = TrustStore::from_db()?;
+//     pkcs7::verify_pkcs7(&signature, Some(&initrd), &trust)?;
+
+use {Result, EfiErrorKind, system_table};
+use ffi::{
+    image_authentication::{EFI_SIGNATURE_LIST, EFI_CERT_X509_GUID},
+    pkcs7_verify::{EFI_PKCS7_VERIFY_PROTOCOL, EFI_PKCS7_VERIFY_PROTOCOL_GUID},
+    EFI_GUID,
+    VOID,
+};
+use hash;
+use super::der::{self, Reader};
+use super::trust::{push_signature_list, TrustStore};
+use super::x509::Certificate;
+use alloc::vec::Vec;
+use core::{mem, ptr};
+
+// Who the certificates we hand firmware belong to, which it doesn't look at
+const OWNER: EFI_GUID = EFI_GUID(0, 0, 0, [0; 8]);
+
+/// A parsed SignedData, which keeps what it needs of its DER
+#[derive(Debug, Clone)]
+pub struct SignedData {
+    content_type: Vec<u8>,
+    content: Option<Vec<u8>>,
+    certificates: Vec<Certificate>,
+    signers: Vec<SignerInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct SignerInfo {
+    issuer: Vec<u8>,
+    serial: Vec<u8>,
+    digest_algorithm: Vec<u8>,
+    signed_attributes: Option<Vec<u8>>, // Their DER, tagged [0] as they are in the SignerInfo
+    message_digest: Option<Vec<u8>>,
+    content_type: Option<Vec<u8>>,
+    signature_algorithm: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl SignedData {
+    /// From the DER of a ContentInfo holding signed data. InvalidParameter if that's not what it is
+    pub fn from_der(data: &[u8]) -> Result<Self> {
+        let mut info = Reader::new(data).read_tag(der::SEQUENCE)?.reader();
+        if info.read_tag(der::OID)?.value != der::OID_SIGNED_DATA {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let mut signed = info.read_tag(der::context(0))?.reader().read_tag(der::SEQUENCE)?.reader();
+        signed.read_tag(der::INTEGER)?; // The version
+        signed.read_tag(der::SET)?; // The digest algorithms, which the signers say again
+
+        let mut encapsulated = signed.read_tag(der::SEQUENCE)?.reader();
+        let content_type = encapsulated.read_tag(der::OID)?.value.to_vec();
+        // What's signed is the value inside, without its tag and length, whatever type it is
+        let content = match encapsulated.read_optional(der::context(0))? {
+            Some(explicit) => Some(explicit.reader().read()?.value.to_vec()),
+            None => None,
+        };
+
+        let mut certificates = Vec::new();
+        if let Some(certs) = signed.read_optional(der::context(0))? {
+            let mut certs = certs.reader();
+            while !certs.is_empty() {
+                let cert = certs.read()?;
+                if cert.tag == der::SEQUENCE {
+                    certificates.push(Certificate::from_der(cert.der)?);
+                } // Otherwise an attribute certificate or some such, which we've no use for
+            }
+        }
+        signed.read_optional(der::context(1))?; // Revocation lists
+
+        let mut signers = Vec::new();
+        let mut infos = signed.read_tag(der::SET)?.reader();
+        while !infos.is_empty() {
+            signers.push(SignerInfo::parse(infos.read_tag(der::SEQUENCE)?.reader())?);
+        }
+
+        Ok(SignedData { content_type, content, certificates, signers })
+    }
+
+    /// The OID of what's signed, encoded
+    pub fn content_type(&self) -> &[u8] {
+        &self.content_type
+    }
+
+    /// What's signed, unless the signature is detached. For Authenticode this is the SpcIndirectDataContent
+    /// without its tag and length, which is how it's digested
+    pub fn content(&self) -> Option<&[u8]> {
+        self.content.as_ref().map(Vec::as_slice)
+    }
+
+    /// The certificates it carries, the signers' and any intermediates
+    pub fn certificates(&self) -> &[Certificate] {
+        &self.certificates
+    }
+
+    /// Checks the content it carries. InvalidParameter if it's detached
+    pub fn verify<'a>(&'a self, trust: &TrustStore) -> Result<&'a Certificate> {
+        let content = self.content().ok_or(EfiErrorKind::InvalidParameter)?;
+        self.verify_detached(content, trust)
+    }
+
+    /// Checks that a signer signed `content` and chains to `trust`, returning the signer's certificate. If there's
+    /// more than one signer any will do. SecurityViolation if none did, or Unsupported if none could be checked
+    pub fn verify_detached<'a>(&'a self, content: &[u8], trust: &TrustStore) -> Result<&'a Certificate> {
+        let mut result = Err(EfiErrorKind::SecurityViolation.into());
+        for signer in &self.signers {
+            result = self.verify_signer(signer, content, trust);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn verify_signer<'a>(&'a self, signer: &SignerInfo, content: &[u8], trust: &TrustStore) -> Result<&'a Certificate> {
+        let cert = self.certificates.iter()
+            .find(|cert| cert.issuer() == &signer.issuer[..] && cert.serial() == &signer.serial[..])
+            .ok_or(EfiErrorKind::SecurityViolation)?;
+        let key = cert.public_key().ok_or(EfiErrorKind::Unsupported)?;
+        if signer.digest_algorithm != der::OID_SHA256
+            || (signer.signature_algorithm != der::OID_RSA_ENCRYPTION && signer.signature_algorithm != der::OID_SHA256_WITH_RSA) {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+
+        let digest = hash::sha256(content);
+        let signed_digest = match signer.signed_attributes {
+            Some(ref attributes) => {
+                if signer.message_digest.as_ref().map(Vec::as_slice) != Some(&digest[..])
+                    || signer.content_type.as_ref() != Some(&self.content_type) {
+                    return Err(EfiErrorKind::SecurityViolation.into());
+                }
+                // They're signed as a SET, not with the [0] they have in the SignerInfo
+                let mut hasher = hash::Sha256::new();
+                hasher.update(&[der::SET]);
+                hasher.update(&attributes[1..]);
+                hasher.finish()
+            },
+            None => digest,
+        };
+        key.verify_sha256(&signed_digest, &signer.signature)?;
+        trust.verify_chain(cert, &self.certificates)?;
+        Ok(cert)
+    }
+}
+
+/// Checks that `signature`, DER PKCS #7 signed data, is by someone `trust` trusts, over `detached` if it's given or
+/// over the content it carries if not. Gives the content it carries, or None if it's detached. SecurityViolation if
+/// it isn't signed or isn't trusted, as SignedData::verify_detached()
+pub fn verify_pkcs7(signature: &[u8], detached: Option<&[u8]>, trust: &TrustStore) -> Result<Option<Vec<u8>>> {
+    verify_with(Pkcs7Verify::locate().ok().as_ref(), signature, detached, trust)
+}
+
+// Firmware's answer if it has one, since it can check more, and ours if it doesn't
+fn verify_with(firmware: Option<&Pkcs7Verify>, signature: &[u8], detached: Option<&[u8]>, trust: &TrustStore) -> Result<Option<Vec<u8>>> {
+    if let Some(firmware) = firmware {
+        match firmware.verify(signature, detached, trust) {
+            Err(ref e) if e.kind() == EfiErrorKind::Unsupported => {},
+            result => return result,
+        }
+    }
+    let signed = SignedData::from_der(signature)?;
+    match detached {
+        Some(content) => signed.verify_detached(content, trust).map(|_| None),
+        None => signed.verify(trust).map(|_| signed.content().map(<[u8]>::to_vec)),
+    }
+}
+
+/// Firmware's EFI_PKCS7_VERIFY_PROTOCOL
+pub struct Pkcs7Verify {
+    protocol: *const EFI_PKCS7_VERIFY_PROTOCOL,
+}
+
+impl Pkcs7Verify {
+    /// NotFound if firmware doesn't have it
+    pub fn locate() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EFI_PKCS7_VERIFY_PROTOCOL = ptr::null();
+        let status = unsafe { traced!(((*bs).LocateProtocol)(&EFI_PKCS7_VERIFY_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol))) };
+        if !::ffi::IsSuccess(status) || protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Ok(Pkcs7Verify { protocol })
+    }
+
+    /// The same as verify_pkcs7(), by firmware alone. Only the store's certificates are passed on, not its digests,
+    /// which are for images
+    pub fn verify(&self, signature: &[u8], detached: Option<&[u8]>, trust: &TrustStore) -> Result<Option<Vec<u8>>> {
+        // A list per certificate, since they differ in size and firmware only reads one list at each pointer
+        let lists: Vec<Vec<u8>> = trust.certificates().iter().map(|cert| {
+            let mut list = Vec::new();
+            push_signature_list(&mut list, &EFI_CERT_X509_GUID, &OWNER, &[cert.der()]);
+            list
+        }).collect();
+        if lists.is_empty() {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        let mut allowed: Vec<*const EFI_SIGNATURE_LIST> = lists.iter().map(|list| list.as_ptr() as *const EFI_SIGNATURE_LIST).collect();
+        allowed.push(ptr::null());
+
+        // What it carries can't be longer than the signature carrying it
+        let mut content = match detached {
+            Some(_) => Vec::new(),
+            None => vec![0u8; signature.len()],
+        };
+        let mut content_size = content.len();
+        let (in_data, in_size, out) = match detached {
+            Some(data) => (data.as_ptr() as *const VOID, data.len(), ptr::null_mut()),
+            None => (ptr::null(), 0, content.as_mut_ptr() as *mut VOID),
+        };
+        unsafe {
+            ret_on_err!(((*self.protocol).VerifyBuffer)(self.protocol, signature.as_ptr() as *const VOID, signature.len(), in_data, in_size, allowed.as_ptr(), ptr::null(), ptr::null(), out, &mut content_size));
+        }
+        Ok(match detached {
+            Some(_) => None,
+            None => {
+                content.truncate(content_size);
+                Some(content)
+            },
+        })
+    }
+}
+
+impl SignerInfo {
+    fn parse(mut info: Reader) -> Result<Self> {
+        info.read_tag(der::INTEGER)?; // The version
+        // Signers named by subject key identifier instead never match a certificate, so never verify
+        let (issuer, serial) = match info.read()? {
+            id if id.tag == der::SEQUENCE => {
+                let mut id = id.reader();
+                let issuer = id.read_tag(der::SEQUENCE)?.der.to_vec();
+                (issuer, der::unsigned(id.read_tag(der::INTEGER)?.value).to_vec())
+            },
+            _ => (Vec::new(), Vec::new()),
+        };
+        let digest_algorithm = info.read_algorithm()?.to_vec();
+
+        let (mut signed_attributes, mut message_digest, mut content_type) = (None, None, None);
+        if let Some(attributes) = info.read_optional(der::context(0))? {
+            signed_attributes = Some(attributes.der.to_vec());
+            let mut attributes = attributes.reader();
+            while !attributes.is_empty() {
+                let mut attribute = attributes.read_tag(der::SEQUENCE)?.reader();
+                let id = attribute.read_tag(der::OID)?.value;
+                let mut values = attribute.read_tag(der::SET)?.reader();
+                if id == der::OID_MESSAGE_DIGEST {
+                    message_digest = Some(values.read_tag(der::OCTET_STRING)?.value.to_vec());
+                } else if id == der::OID_CONTENT_TYPE {
+                    content_type = Some(values.read_tag(der::OID)?.value.to_vec());
+                }
+            }
+        }
+
+        let signature_algorithm = info.read_algorithm()?.to_vec();
+        let signature = info.read_tag(der::OCTET_STRING)?.value.to_vec();
+        Ok(SignerInfo { issuer, serial, digest_algorithm, signed_attributes, message_digest, content_type, signature_algorithm, signature })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testdata::{ROOT_PEM, DETACHED_SIGNATURE};
+    use hash::from_hex;
+    use ffi::{EFI_STATUS, EFI_SUCCESS, EFI_UNSUPPORTED, EFI_SECURITY_VIOLATION, UINTN};
+    use alloc::boxed::Box;
+    use core::{cell::Cell, slice};
+
+    // 1.2.840.113549.1.7.1, plain data
+    const DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x01];
+
+    #[test]
+    fn verifies_signed_data() {
+        let signed = SignedData::from_der(&from_hex(DETACHED_SIGNATURE).unwrap()).unwrap();
+        assert_eq!(signed.content_type(), DATA);
+        assert_eq!(signed.content(), None);
+        assert_eq!(signed.certificates().len(), 1);
+
+        let trust = TrustStore::from_pem(ROOT_PEM).unwrap();
+        let signer = signed.verify_detached(b"kernel image", &trust).unwrap();
+        assert_eq!(signer.serial(), [0x12, 0x34]);
+        assert_eq!(signed.verify_detached(b"kernel imagf", &trust).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert_eq!(signed.verify(&trust).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        assert_eq!(signed.verify_detached(b"kernel image", &TrustStore::new()).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+
+        // Trusting the signer itself is enough
+        let mut trust = TrustStore::new();
+        trust.add(signed.certificates()[0].clone());
+        signed.verify_detached(b"kernel image", &trust).unwrap();
+
+        assert!(SignedData::from_der(&from_hex(DETACHED_SIGNATURE).unwrap()[..500]).is_err());
+    }
+
+    #[repr(C)]
+    struct FakeVerify {
+        protocol: EFI_PKCS7_VERIFY_PROTOCOL,
+        status: Cell<EFI_STATUS>,
+        trusted: Cell<usize>,
+    }
+
+    extern "efiapi" fn verify_buffer(this: *const EFI_PKCS7_VERIFY_PROTOCOL, _signed: *const VOID, _signed_size: UINTN, in_data: *const VOID, _in_size: UINTN,
+                                     allowed: *const *const EFI_SIGNATURE_LIST, _revoked: *const *const EFI_SIGNATURE_LIST, _timestamps: *const *const EFI_SIGNATURE_LIST,
+                                     content: *mut VOID, content_size: *mut UINTN) -> EFI_STATUS {
+        unsafe {
+            let fake = &*(this as *const FakeVerify);
+            let mut trusted = 0;
+            while !(*allowed.add(trusted)).is_null() {
+                assert_eq!((**allowed.add(trusted)).SignatureType, EFI_CERT_X509_GUID);
+                trusted += 1;
+            }
+            fake.trusted.set(trusted);
+            if in_data.is_null() && fake.status.get() == EFI_SUCCESS {
+                slice::from_raw_parts_mut(content as *mut u8, *content_size)[..7].copy_from_slice(b"carried");
+                *content_size = 7;
+            }
+            fake.status.get()
+        }
+    }
+
+    extern "efiapi" fn verify_signature(_this: *const EFI_PKCS7_VERIFY_PROTOCOL, _signature: *const VOID, _signature_size: UINTN, _hash: *const VOID, _hash_size: UINTN,
+                                        _allowed: *const *const EFI_SIGNATURE_LIST, _revoked: *const *const EFI_SIGNATURE_LIST, _timestamps: *const *const EFI_SIGNATURE_LIST) -> EFI_STATUS {
+        EFI_UNSUPPORTED
+    }
+
+    #[test]
+    fn verifies_with_firmware_or_without() {
+        let fake: &'static FakeVerify = Box::leak(Box::new(FakeVerify {
+            protocol: EFI_PKCS7_VERIFY_PROTOCOL { VerifyBuffer: verify_buffer, VerifySignature: verify_signature },
+            status: Cell::new(EFI_SUCCESS),
+            trusted: Cell::new(0),
+        }));
+        let firmware = Pkcs7Verify { protocol: &fake.protocol };
+        let signature = from_hex(DETACHED_SIGNATURE).unwrap();
+        let trust = TrustStore::from_pem(ROOT_PEM).unwrap();
+
+        assert_eq!(verify_with(Some(&firmware), &signature, Some(b"kernel image"), &trust).unwrap(), None);
+        assert_eq!(fake.trusted.get(), 1);
+        assert_eq!(verify_with(Some(&firmware), &signature, None, &trust).unwrap().unwrap(), b"carried");
+
+        // What firmware rejects stays rejected, but what it can't check we check ourselves
+        fake.status.set(EFI_SECURITY_VIOLATION);
+        assert_eq!(verify_with(Some(&firmware), &signature, Some(b"kernel image"), &trust).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        fake.status.set(EFI_UNSUPPORTED);
+        assert_eq!(verify_with(Some(&firmware), &signature, Some(b"kernel image"), &trust).unwrap(), None);
+        assert_eq!(verify_with(Some(&firmware), &signature, Some(b"kernel imagf"), &trust).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert_eq!(verify_with(None, &signature, Some(b"kernel image"), &trust).unwrap(), None);
+        assert_eq!(verify_with(None, &signature, None, &trust).unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        assert_eq!(firmware.verify(&signature, Some(b"kernel image"), &TrustStore::new()).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+    }
+}
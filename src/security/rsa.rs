@@ -0,0 +1,258 @@
+// RSA signature checking, PKCS #1 v1.5 as in RFC 8017, which is what Authenticode, the Secure Boot databases and
+// most signed payloads use. Only the public half: there's nothing here for signing or decrypting, so no secrets to
+// keep and nothing that needs to take constant time.
+//
+// Numbers are little endian vectors of 32 bit limbs, multiplied Montgomery style so nothing needs dividing.
+
+use {Result, EfiErrorKind};
+use hash::SHA256_LEN;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+// The DER of a DigestInfo for SHA-256 up to the digest itself
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20,
+];
+
+// Anything shorter is too easy to factor to be worth checking against
+const MIN_MODULUS_BITS: usize = 1024;
+
+/// An RSA public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RsaPublicKey {
+    modulus: Vec<u32>,
+    exponent: Vec<u32>,
+    len: usize, // Of the modulus in bytes
+}
+
+impl RsaPublicKey {
+    /// From the modulus and public exponent as big endian numbers, the way certificates and PKCS #1 have them.
+    /// InvalidParameter for moduli under 1024 bits and for even moduli or exponents, or an exponent of 1
+    pub fn new(modulus: &[u8], exponent: &[u8]) -> Result<Self> {
+        let modulus = from_be_bytes(modulus);
+        let exponent = from_be_bytes(exponent);
+        if bit_len(&modulus) < MIN_MODULUS_BITS || modulus[0] & 1 == 0 || bit_len(&exponent) < 2 || exponent[0] & 1 == 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let len = byte_len(&modulus);
+        Ok(RsaPublicKey { modulus, exponent, len })
+    }
+
+    /// The modulus's length in bytes, which is also how long signatures are
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    pub fn modulus(&self) -> Vec<u8> {
+        to_be_bytes(&self.modulus, self.len)
+    }
+
+    pub fn exponent(&self) -> Vec<u8> {
+        to_be_bytes(&self.exponent, byte_len(&self.exponent))
+    }
+
+    /// Checks that `signature` is this key's PKCS #1 v1.5 signature over a SHA-256 digest. SecurityViolation if not
+    pub fn verify_sha256(&self, digest: &[u8; SHA256_LEN], signature: &[u8]) -> Result<()> {
+        let mut digest_info = SHA256_DIGEST_INFO.to_vec();
+        digest_info.extend(digest);
+        self.verify_digest_info(&digest_info, signature)
+    }
+
+    /// Checks that `signature` is this key's PKCS #1 v1.5 signature over `digest_info`, the DER of a DigestInfo
+    /// naming the hash algorithm as well as holding the digest
+    pub fn verify_digest_info(&self, digest_info: &[u8], signature: &[u8]) -> Result<()> {
+        let message = self.public_op(signature)?;
+        // 00 01, at least eight FFs, 00, then the DigestInfo
+        let padding_len = self.len.checked_sub(digest_info.len() + 3).filter(|&len| len >= 8)
+            .ok_or(EfiErrorKind::SecurityViolation)?;
+        let mut expected = Vec::with_capacity(self.len);
+        expected.extend(&[0x00, 0x01]);
+        expected.resize(2 + padding_len, 0xFF);
+        expected.push(0x00);
+        expected.extend(digest_info);
+        if message == expected { Ok(()) } else { Err(EfiErrorKind::SecurityViolation.into()) }
+    }
+
+    // signature ^ exponent mod modulus, as big endian bytes as long as the modulus
+    fn public_op(&self, signature: &[u8]) -> Result<Vec<u8>> {
+        if signature.len() != self.len {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        let mut s = from_be_bytes(signature);
+        s.resize(self.modulus.len(), 0);
+        if compare(&s, &self.modulus) != Ordering::Less {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        let m = Montgomery::new(&self.modulus);
+        Ok(to_be_bytes(&m.pow(&s, &self.exponent), self.len))
+    }
+}
+
+struct Montgomery<'a> {
+    n: &'a [u32],
+    n0_inv: u32, // -1/n mod 2^32
+    r2: Vec<u32>, // R^2 mod n, R being 2^(32 * limbs)
+}
+
+impl<'a> Montgomery<'a> {
+    fn new(n: &'a [u32]) -> Self {
+        // Newton's method, each step doubling the bits that are right
+        let mut inv = 1u32;
+        for _ in 0..5 {
+            inv = inv.wrapping_mul(2u32.wrapping_sub(n[0].wrapping_mul(inv)));
+        }
+
+        // Doubling 1 until it's R^2, reducing as it goes
+        let mut r2 = vec![0u32; n.len()];
+        r2[0] = 1;
+        for _ in 0..64 * n.len() {
+            let carry = shift_left_1(&mut r2);
+            if carry || compare(&r2, n) != Ordering::Less {
+                sub(&mut r2, n);
+            }
+        }
+        Montgomery { n, n0_inv: inv.wrapping_neg(), r2 }
+    }
+
+    // a * b / R mod n
+    fn mul(&self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        let k = self.n.len();
+        let mut t = vec![0u32; k + 2];
+        for &a in a {
+            let mut carry = 0u64;
+            for (t, &b) in t.iter_mut().zip(b) {
+                let sum = *t as u64 + a as u64 * b as u64 + carry;
+                *t = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[k] as u64 + carry;
+            t[k] = sum as u32;
+            t[k + 1] = (sum >> 32) as u32;
+
+            // Adding a multiple of n that clears the bottom limb, then dropping it
+            let m = t[0].wrapping_mul(self.n0_inv) as u64;
+            let mut carry = (t[0] as u64 + m * self.n[0] as u64) >> 32;
+            for j in 1..k {
+                let sum = t[j] as u64 + m * self.n[j] as u64 + carry;
+                t[j - 1] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[k] as u64 + carry;
+            t[k - 1] = sum as u32;
+            t[k] = t[k + 1] + (sum >> 32) as u32;
+            t[k + 1] = 0;
+        }
+        let over = t[k] != 0;
+        t.truncate(k);
+        if over || compare(&t, self.n) != Ordering::Less {
+            sub(&mut t, self.n);
+        }
+        t
+    }
+
+    fn pow(&self, base: &[u32], exponent: &[u32]) -> Vec<u32> {
+        let mut one = vec![0u32; self.n.len()];
+        one[0] = 1;
+        let base = self.mul(base, &self.r2);
+        let mut acc = self.mul(&one, &self.r2);
+        for i in (0..bit_len(exponent)).rev() {
+            acc = self.mul(&acc, &acc);
+            if exponent[i / 32] >> (i % 32) & 1 != 0 {
+                acc = self.mul(&acc, &base);
+            }
+        }
+        self.mul(&acc, &one)
+    }
+}
+
+fn from_be_bytes(bytes: &[u8]) -> Vec<u32> {
+    let mut limbs = bytes.rchunks(4).map(|chunk| chunk.iter().fold(0u32, |limb, &b| limb << 8 | b as u32)).collect::<Vec<_>>();
+    while limbs.len() > 1 && limbs[limbs.len() - 1] == 0 {
+        limbs.pop();
+    }
+    if limbs.is_empty() {
+        limbs.push(0);
+    }
+    limbs
+}
+
+fn to_be_bytes(limbs: &[u32], len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    for (i, byte) in bytes.iter_mut().rev().enumerate() {
+        if let Some(limb) = limbs.get(i / 4) {
+            *byte = (limb >> (8 * (i % 4))) as u8;
+        }
+    }
+    bytes
+}
+
+fn bit_len(limbs: &[u32]) -> usize {
+    match limbs.iter().rposition(|&limb| limb != 0) {
+        Some(i) => i * 32 + 32 - limbs[i].leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+fn byte_len(limbs: &[u32]) -> usize {
+    match limbs.iter().rposition(|&limb| limb != 0) {
+        Some(i) => i * 4 + 4 - limbs[i].leading_zeros() as usize / 8,
+        None => 0,
+    }
+}
+
+// Of numbers with the same number of limbs
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+// a -= b, wrapping
+fn sub(a: &mut [u32], b: &[u32]) {
+    let mut borrow = 0u64;
+    for (a, &b) in a.iter_mut().zip(b) {
+        let diff = (*a as u64).wrapping_sub(b as u64 + borrow);
+        *a = diff as u32;
+        borrow = (diff >> 63) & 1;
+    }
+}
+
+// Returns the bit shifted out of the top
+fn shift_left_1(a: &mut [u32]) -> bool {
+    let mut carry = 0;
+    for limb in a.iter_mut() {
+        let next = *limb >> 31;
+        *limb = *limb << 1 | carry;
+        carry = next;
+    }
+    carry != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::{sha256, from_hex};
+
+    // From `openssl genrsa 1024` and `openssl dgst -sha256 -sign`
+    const MODULUS: &str = "c7e0bbed966ece387785f46f7ae598fc29b909c31100d82051378fd662e2651a618dcc414b214255a315470080eaabdf37bcc5cbdfc2d9d59fac184203464f895ddd26c2932f6216d81e6a88534e1b142d7b11c05cc18d3da9b731ee4030e84294b854496f78955bb11e26fb407a292a3adcbc1c81571f0d64588d12dcdd43ad";
+    const SIGNATURE: &str = "774d6849c30d8508d172d881e87c5bbcebaf2b69820369dc9a503890d530e8ad7b12c8bb4f2c7be8e1c61faa6781dcbcd8b86a533556e0203809d7321eabe549d5e979e6020ce8c6b67227aed5951318ba5c4f482bacc7ace2bac7a20da75f9dbb0764859ee82d99bbb8095d4101eb0370126ae1b9b8826197cc030bf4344dd4";
+
+    #[test]
+    fn verifies_pkcs1_signatures() {
+        let key = RsaPublicKey::new(&from_hex(MODULUS).unwrap(), &[0x01, 0x00, 0x01]).unwrap();
+        assert_eq!(key.size(), 128);
+        assert_eq!(key.modulus(), from_hex(MODULUS).unwrap());
+        assert_eq!(key.exponent(), [0x01, 0x00, 0x01]);
+
+        let signature = from_hex(SIGNATURE).unwrap();
+        key.verify_sha256(&sha256(b"signed by the build server"), &signature).unwrap();
+        assert_eq!(key.verify_sha256(&sha256(b"signed by someone else"), &signature).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        let mut tampered = signature.clone();
+        tampered[100] ^= 1;
+        assert!(key.verify_sha256(&sha256(b"signed by the build server"), &tampered).is_err());
+        assert!(key.verify_sha256(&sha256(b"signed by the build server"), &signature[1..]).is_err());
+        assert!(key.verify_sha256(&sha256(b"signed by the build server"), &from_hex(MODULUS).unwrap()).is_err());
+
+        assert!(RsaPublicKey::new(&[0xFF; 64], &[3]).is_err()); // Too short
+        assert!(RsaPublicKey::new(&[0xFE; 128], &[3]).is_err()); // Even
+        assert!(RsaPublicKey::new(&from_hex(MODULUS).unwrap(), &[1]).is_err());
+    }
+}
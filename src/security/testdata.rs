@@ -0,0 +1,148 @@
+// Certificates and signatures for the tests, made with openssl: a self signed CA and a code signing certificate it
+// issued, both 1024 bit RSA and valid from 2026 to 2126
+
+pub const ROOT_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICODCCAaGgAwIBAgIUQExIuCmg4o7b9iSthxMZAUeiXsMwDQYJKoZIhvcNAQEL
+BQAwJTEVMBMGA1UEAwwMVGVzdCBSb290IENBMQwwCgYDVQQKDANlZmkwIBcNMjYx
+MDE0MDg1NjQyWhgPMjEyNjA5MjAwODU2NDJaMCUxFTATBgNVBAMMDFRlc3QgUm9v
+dCBDQTEMMAoGA1UECgwDZWZpMIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDS
+CyF5iPv4grY3hwvsKodaynypM93NENOqFAB2WHpWeq5NXEe0hp9C6b2LE2HtwuNh
+YP1oJxoXqhaMeIxNu6ZfyoGx/I1Pmflp5K5HbQ5owOGm+QrHikcThRgWlJu0LJJj
+d6y/Yv5CASYPlH+eB9ldTV8RZ1DKV/Ue48I2LGTQVQIDAQABo2MwYTAdBgNVHQ4E
+FgQUn0+hGoBcKSl68Hz1l3C14tsX+10wHwYDVR0jBBgwFoAUn0+hGoBcKSl68Hz1
+l3C14tsX+10wDwYDVR0TAQH/BAUwAwEB/zAOBgNVHQ8BAf8EBAMCAgQwDQYJKoZI
+hvcNAQELBQADgYEAQulmPuEcEJdzHVNmDHgS+qAFVA0O4PXNilPf7zDtAkA6T0i7
+CcG9U5YlXbmA5+ks4coX2v97XJMozpC97HdVp07Afrysb7hAcs9QnRC4a8L7FJAX
+rC5N5vnvh9elU+ADe9N/BJnIAs3u5ZDV4X8ctqr9pqv6rVJpSsWXmg3dTtI=
+-----END CERTIFICATE-----
+";
+
+pub const SIGNER_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICMTCCAZqgAwIBAgICEjQwDQYJKoZIhvcNAQELBQAwJTEVMBMGA1UEAwwMVGVz
+dCBSb290IENBMQwwCgYDVQQKDANlZmkwIBcNMjYxMDE0MDg1NjQyWhgPMjEyNjA5
+MjAwODU2NDJaMCQxFDASBgNVBAMMC1Rlc3QgU2lnbmVyMQwwCgYDVQQKDANlZmkw
+gZ8wDQYJKoZIhvcNAQEBBQADgY0AMIGJAoGBAMtpkTgSkYMVUBxS6CiXr5dCKii1
+RgYX7mAjlAU04dlg4ww7e0v/IzBpffxodvF9snIC1LtEeNwpOWqa6JiNl5Y3G/pv
+EOUdQa7fyJ6BYFa9CRTTAgYuiyhfWAWknY54tdBZPIj81a2AnZlpXDVmZKjGh5di
+aIewT/7l7dX2FUaJAgMBAAGjbzBtMAkGA1UdEwQCMAAwCwYDVR0PBAQDAgeAMBMG
+A1UdJQQMMAoGCCsGAQUFBwMDMB0GA1UdDgQWBBSgNz4Rf2ZVf5uxQCUldEMAAyBJ
+qTAfBgNVHSMEGDAWgBSfT6EagFwpKXrwfPWXcLXi2xf7XTANBgkqhkiG9w0BAQsF
+AAOBgQBAZcsQtH4RoMHuc0wyf3Rp6dRB7we4oWNCSu7YW/XNcMdkci/BbZs1Yrgf
+wtFFnk1CddzZGmcB+p9SQDUIVp7n6BFNRY73kU326fTo6RSK8YubChs86Gs5NrlC
+O8efPEobHfTdIxX+LvBvsWYNInYfJOUQV5T+XJSzlDZIXPnsSg==
+-----END CERTIFICATE-----
+";
+
+// `openssl cms -sign -binary -outform DER` by the signer over "kernel image"
+pub const DETACHED_SIGNATURE: &str = concat!(
+    "308203ad06092a864886f70d010702a082039e3082039a020101310d300b0609608648016503040201300b06092a864886f70d010701a082",
+    "0235308202313082019aa00302010202021234300d06092a864886f70d01010b050030253115301306035504030c0c5465737420526f6f74",
+    "204341310c300a060355040a0c036566693020170d3236313031343038353634325a180f32313236303932303038353634325a3024311430",
+    "1206035504030c0b54657374205369676e6572310c300a060355040a0c0365666930819f300d06092a864886f70d010101050003818d0030",
+    "818902818100cb69913812918315501c52e82897af97422a28b5460617ee6023940534e1d960e30c3b7b4bff2330697dfc6876f17db27202",
+    "d4bb4478dc29396a9ae8988d9796371bfa6f10e51d41aedfc89e816056bd0914d302062e8b285f5805a49d8e78b5d0593c88fcd5ad809d99",
+    "695c356664a8c68797626887b04ffee5edd5f61546890203010001a36f306d30090603551d1304023000300b0603551d0f04040302078030",
+    "130603551d25040c300a06082b06010505070303301d0603551d0e04160414a0373e117f66557f9bb1402525744300032049a9301f060355",
+    "1d230418301680149f4fa11a805c29297af07cf59770b5e2db17fb5d300d06092a864886f70d01010b0500038181004065cb10b47e11a0c1",
+    "ee734c327f7469e9d441ef07b8a163424aeed85bf5cd70c764722fc16d9b3562b81fc2d1459e4d4275dcd91a6701fa9f52403508569ee7e8",
+    "114d458ef7914df6e9f4e8e9148af18b9b0a1b3ce86b3936b9423bc79f3c4a1b1df4dd2315fe2ef06fb1660d22761f24e5105794fe5c94b3",
+    "9436485cf9ec4a3182013e3082013a020101302b30253115301306035504030c0c5465737420526f6f74204341310c300a060355040a0c03",
+    "65666902021234300b0609608648016503040201a069301806092a864886f70d010903310b06092a864886f70d010701301c06092a864886",
+    "f70d010905310f170d3236313031343038353634325a302f06092a864886f70d01090431220420a8438c585bb5070930b9d66b141a05ef02",
+    "bb7a326620ae09fc44f2d1f4e2a9a7300d06092a864886f70d0101010500048180812948e50bea9fbfc755895229f0917648395763088701",
+    "fc55e49454351a3ba1d86d22b71bcb0b12491327ba470edde4e69b7961898dd88b508d17ad3f0bcde3df9d721bd3e018e128db433eb578b8",
+    "9452d7175858b17c0cb269ad12324efd087494dd67fa702c3b7a9825ecc9aed4377711fb63a6e1a06f59a5f7f6e1f4e23d",
+);
+
+// The signer's Authenticode signature over authenticode::tests::image()
+pub const AUTHENTICODE_SIGNATURE: &str = concat!(
+    "308203e506092a864886f70d010702a08203d6308203d2020101310f300d06096086480165030402010500305c060a2b0601040182370201",
+    "04a04e304c3017060a2b06010401823702010f3009030100a004a20280003031300d0609608648016503040201050004202e510fb350e747",
+    "51b34e89c95a4ea9094f4a65908224fda479de07a459cd1d3da0820235308202313082019aa00302010202021234300d06092a864886f70d",
+    "01010b050030253115301306035504030c0c5465737420526f6f74204341310c300a060355040a0c036566693020170d3236313031343038",
+    "353634325a180f32313236303932303038353634325a30243114301206035504030c0b54657374205369676e6572310c300a060355040a0c",
+    "0365666930819f300d06092a864886f70d010101050003818d0030818902818100cb69913812918315501c52e82897af97422a28b5460617",
+    "ee6023940534e1d960e30c3b7b4bff2330697dfc6876f17db27202d4bb4478dc29396a9ae8988d9796371bfa6f10e51d41aedfc89e816056",
+    "bd0914d302062e8b285f5805a49d8e78b5d0593c88fcd5ad809d99695c356664a8c68797626887b04ffee5edd5f61546890203010001a36f",
+    "306d30090603551d1304023000300b0603551d0f04040302078030130603551d25040c300a06082b06010505070303301d0603551d0e0416",
+    "0414a0373e117f66557f9bb1402525744300032049a9301f0603551d230418301680149f4fa11a805c29297af07cf59770b5e2db17fb5d30",
+    "0d06092a864886f70d01010b0500038181004065cb10b47e11a0c1ee734c327f7469e9d441ef07b8a163424aeed85bf5cd70c764722fc16d",
+    "9b3562b81fc2d1459e4d4275dcd91a6701fa9f52403508569ee7e8114d458ef7914df6e9f4e8e9148af18b9b0a1b3ce86b3936b9423bc79f",
+    "3c4a1b1df4dd2315fe2ef06fb1660d22761f24e5105794fe5c94b39436485cf9ec4a318201233082011f020101302b302531153013060355",
+    "04030c0c5465737420526f6f74204341310c300a060355040a0c0365666902021234300d06096086480165030402010500a04c301906092a",
+    "864886f70d010903310c060a2b060104018237020104302f06092a864886f70d01090431220420c20439c52ba775fff660c1f4b8fe52ac7e",
+    "2bb3b5a360072254169940f54f00ed300d06092a864886f70d0101010500048180873e24b6531bc38ee513357dcd47061b0bce04dffba602",
+    "d25ff6279abc80dc9430ee0e60d9564e3c3a9b5cce2bace0cb5511794f3eaa99a55c37c74c3b426374c39ba2c93881e060b2013dd51dac7d",
+    "5cb113af2e9fd035ed8800d69df3d33cdef84cbc1d795b87c04c41555202db75acf7d17edb78ba168b133eefa8a044f484",
+);
+
+// Another CA's, for chains that mustn't verify: an intermediate CA whose key usage doesn't include signing
+// certificates with a leaf it issued, and a leaf with a critical extension nobody knows (1.3.6.1.4.1.55555.1)
+pub const ANCHOR_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICAjCCAWugAwIBAgIBITANBgkqhkiG9w0BAQsFADAkMRQwEgYDVQQDDAtUZXN0
+IEFuY2hvcjEMMAoGA1UECgwDZWZpMCAXDTI2MTAxNDEwNDc1M1oYDzIxMjYwOTIw
+MTA0NzUzWjAkMRQwEgYDVQQDDAtUZXN0IEFuY2hvcjEMMAoGA1UECgwDZWZpMIGf
+MA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDGUSY8FygXMeBr+oKlvOqbD07WYYAP
+bFS5EStq2+uKod1ER1PHOu8eabVmxubGwxF3a61XQOi6Uyb8W4zLvM+iH+ZyYFDI
+pFJRc92LbUhBOltbslBYx+Wy6uMfofYK59vFuEBzvQ4yFQkV3OfwPzPAmXsaEt2x
+E3Mi8nHLlRBsWQIDAQABo0IwQDAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQE
+AwICBDAdBgNVHQ4EFgQUE3/MXWso2zx/bhWM3KhNAJ2I+rMwDQYJKoZIhvcNAQEL
+BQADgYEAVaUdbYx/cdiIV66Bej8W7JT0RV2fnpMRGWS/SNAuQENn24FgbtVR48KV
+wOtkj84Ii4LkvlxEwxAzv+FUVHAe0O4wzOpavYsba/i3kx0/8jNIZQNj0Oo2c8mg
+2tdAsKkmcSy6E+afK8K3O2eJ4O3am2GwHGhhqOMAYPvIf8jtGpE=
+-----END CERTIFICATE-----
+";
+
+pub const NO_CERT_SIGN_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICKTCCAZKgAwIBAgIBIjANBgkqhkiG9w0BAQsFADAkMRQwEgYDVQQDDAtUZXN0
+IEFuY2hvcjEMMAoGA1UECgwDZWZpMCAXDTI2MTAxNDEwNDc1M1oYDzIxMjYwOTIw
+MTA0NzUzWjAqMRowGAYDVQQDDBFUZXN0IEludGVybWVkaWF0ZTEMMAoGA1UECgwD
+ZWZpMIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQCixbo5yZIFLyXobtM6v6Ix
+tSXyGKZJuJs/ouNB3p4DwwHC7cJX2R0ovpCYl+V6KSBegt6Ub6M2VMgbbiVfvSwJ
+x6esqtrzP9JjrbGkwxt3jtC/OugDoqW3JEbAphOvBjupBptoDxKXYls/Womx+6ui
+x/afIF13+EvPqrX5RjaBkQIDAQABo2MwYTAPBgNVHRMBAf8EBTADAQH/MA4GA1Ud
+DwEB/wQEAwIHgDAdBgNVHQ4EFgQUZNb3iwT41sTLwdHnC37kCZhR78owHwYDVR0j
+BBgwFoAUE3/MXWso2zx/bhWM3KhNAJ2I+rMwDQYJKoZIhvcNAQELBQADgYEAW9tQ
+YFE7w19zMR4yAxjaPyXqMVI0U5eQ9LqfrKxPhu31Aldvk1w20oXQTdBSJVrN1m8V
+44SM3qWyPessWglIaBJgag27o9yD/AneMpZD+Z4fciq74vvW9o9/Pus4u7Z9SpWy
+XtwHdUX9ROJGL2H1nXYu1i72j4taK0t2hWsGQW8=
+-----END CERTIFICATE-----
+";
+
+pub const NO_CERT_SIGN_LEAF_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICHjCCAYegAwIBAgIBIzANBgkqhkiG9w0BAQsFADAqMRowGAYDVQQDDBFUZXN0
+IEludGVybWVkaWF0ZTEMMAoGA1UECgwDZWZpMCAXDTI2MTAxNDEwNDc1M1oYDzIx
+MjYwOTIwMTA0NzUzWjAiMRIwEAYDVQQDDAlUZXN0IExlYWYxDDAKBgNVBAoMA2Vm
+aTCBnzANBgkqhkiG9w0BAQEFAAOBjQAwgYkCgYEAt23mjW8jIjhx4OgvV3KFknnn
+EttXPnAbyAIIY+z74XuWN9c6fm2vBwzNTTf7FazYqQ4H+nxxwxdhChFvvAR75X1F
+cEePBQNG40BK4TLbBOj9GCd+euVTrmGKRCHNqvraovrWXJGTVjH3/BRszFIz7QPE
+h1eQvSTImsIttUr64S8CAwEAAaNaMFgwCQYDVR0TBAIwADALBgNVHQ8EBAMCB4Aw
+HQYDVR0OBBYEFMdMebqibkXVm6RQ60ffasQt6zWLMB8GA1UdIwQYMBaAFGTW94sE
++NbEy8HR5wt+5AmYUe/KMA0GCSqGSIb3DQEBCwUAA4GBAKEnhswQpkvCPl7zWJkA
+1Vf/KqLWdkraUcvXvzLBHBg94YxARcJF4Q6fHjiRGFdcVGoiM3OPqLX8s7pl4wxW
+4Ognj9+7ajbdvHYmYDOz+KU8aqPNiUS58uqQFFgWVLdcz8jVXowVRzYVX2Id0nxo
+nP0Yfx6sB8HUQ1vluib6cLyl
+-----END CERTIFICATE-----
+";
+
+pub const CRITICAL_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICMDCCAZmgAwIBAgIBJDANBgkqhkiG9w0BAQsFADAkMRQwEgYDVQQDDAtUZXN0
+IEFuY2hvcjEMMAoGA1UECgwDZWZpMCAXDTI2MTAxNDEwNDc1M1oYDzIxMjYwOTIw
+MTA0NzUzWjAmMRYwFAYDVQQDDA1UZXN0IENyaXRpY2FsMQwwCgYDVQQKDANlZmkw
+gZ8wDQYJKoZIhvcNAQEBBQADgY0AMIGJAoGBANofZhYuN5avD8BrQ7SX/cqiLhLG
+qgwY3ULkUZDwDmpCH6RkAA++MGhnc1xUY0MYRgDNDzEROWHrtPhCwDISGP0LS5X3
+QZaCj7XsuKM1Ctw6gPlEAuvt1sY7A3hEBHXWm3H/yAmTaeK9QJJ5XHpzyITZLsL0
+fMxN4kBchgkGhQobAgMBAAGjbjBsMAkGA1UdEwQCMAAwCwYDVR0PBAQDAgeAMBIG
+CSsGAQQBg7IDAQEB/wQCBQAwHQYDVR0OBBYEFCqCb+cGK/y4saebTAGjyIfZFRuC
+MB8GA1UdIwQYMBaAFBN/zF1rKNs8f24VjNyoTQCdiPqzMA0GCSqGSIb3DQEBCwUA
+A4GBAHmKf6jP2MC/+5vpfjXbJBkhI4QduxdWcoS2x7Vjz1ZG22jPjLiNDSFQ+hPx
+Fq+d0HccfVuKwUNaYvAAU3zjxDLBQEbv4D6u5A2YIWusIwDRNnFdY0FgzsopV46G
+nPH4bCW6RIZJSDlQVJdqkDlPCCcBiXnw+8zb7E4EkJrhQnkG
+-----END CERTIFICATE-----
+";
@@ -0,0 +1,211 @@
+// What's trusted to sign: certificates, and SHA-256 digests of images allowed whoever signed them, which is what the
+// Secure Boot db holds. A TrustStore can be read from db so we trust exactly what firmware would, or built from a
+// PEM bundle shipped with the loader for payloads firmware never sees.
+//
+// Chains are checked by signatures and names only. Validity dates aren't, the same as firmware, since the clock can't
+// be trusted before boot and signed images outlive their certificates anyway.
+
+use {Result, EfiErrorKind};
+use ffi::EFI_GUID;
+use ffi::image_authentication::{EFI_SIGNATURE_LIST, EFI_SIGNATURE_DATA, EFI_CERT_X509_GUID, EFI_CERT_SHA256_GUID,
+    EFI_IMAGE_SECURITY_DATABASE, EFI_IMAGE_SECURITY_DATABASE_GUID};
+use firmware::firmware;
+use hash::SHA256_LEN;
+use super::x509::Certificate;
+use alloc::vec::Vec;
+use core::mem;
+use byteorder::{LittleEndian, ByteOrder};
+
+// Longest chain from a signer to something trusted, so a loop of certificates can't keep us going
+const MAX_DEPTH: usize = 8;
+
+/// Certificates and digests to check signatures and images against
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    certs: Vec<Certificate>,
+    sha256: Vec<[u8; SHA256_LEN]>,
+}
+
+impl TrustStore {
+    /// An empty store, which trusts nothing
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Whatever's in the Secure Boot db
+    pub fn from_db() -> Result<Self> {
+        Self::from_signature_lists(&firmware().get_variable(EFI_IMAGE_SECURITY_DATABASE, &EFI_IMAGE_SECURITY_DATABASE_GUID)?)
+    }
+
+    /// From EFI_SIGNATURE_LISTs, the format of db and of the files that update it. X.509 certificates and SHA-256
+    /// digests are kept and other types skipped, as are certificates that don't parse, so one odd entry doesn't cost
+    /// the rest. InvalidParameter if the lists themselves are malformed
+    pub fn from_signature_lists(data: &[u8]) -> Result<Self> {
+        let mut store = TrustStore::new();
+        for (signature_type, signature) in signature_lists(data)? {
+            if signature_type == EFI_CERT_X509_GUID {
+                if let Ok(cert) = Certificate::from_der(signature) {
+                    store.add(cert);
+                }
+            } else if signature_type == EFI_CERT_SHA256_GUID && signature.len() == SHA256_LEN {
+                let mut digest = [0; SHA256_LEN];
+                digest.copy_from_slice(signature);
+                store.add_sha256(digest);
+            }
+        }
+        Ok(store)
+    }
+
+    /// Every certificate in a PEM bundle
+    pub fn from_pem(text: &str) -> Result<Self> {
+        Ok(TrustStore { certs: Certificate::from_pem(text)?, sha256: Vec::new() })
+    }
+
+    pub fn add(&mut self, cert: Certificate) {
+        if !self.certs.contains(&cert) {
+            self.certs.push(cert);
+        }
+    }
+
+    /// Trusts an image with this Authenticode digest, signed or not
+    pub fn add_sha256(&mut self, digest: [u8; SHA256_LEN]) {
+        if !self.contains_sha256(&digest) {
+            self.sha256.push(digest);
+        }
+    }
+
+    pub fn certificates(&self) -> &[Certificate] {
+        &self.certs
+    }
+
+    pub fn sha256_digests(&self) -> &[[u8; SHA256_LEN]] {
+        &self.sha256
+    }
+
+    pub fn contains_sha256(&self, digest: &[u8; SHA256_LEN]) -> bool {
+        self.sha256.contains(digest)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty() && self.sha256.is_empty()
+    }
+
+    /// Checks that `cert` is trusted, or signed by something trusted through CAs among `intermediates`.
+    /// SecurityViolation if it isn't. Nothing on the way can have a critical extension we don't understand, and the
+    /// CAs' key usage has to allow signing certificates; what's trusted is taken as it is, as firmware takes db.
+    /// Short of full path validation (RFC 5280 section 6): name constraints, policies and extended key usage aren't
+    /// checked
+    pub fn verify_chain(&self, cert: &Certificate, intermediates: &[Certificate]) -> Result<()> {
+        let mut current = cert;
+        for _ in 0..MAX_DEPTH {
+            if self.certs.contains(current) {
+                return Ok(());
+            } else if current.has_unknown_critical_extension() {
+                break;
+            } else if self.certs.iter().any(|trusted| current.verify_signed_by(trusted).is_ok()) {
+                return Ok(());
+            }
+            current = match intermediates.iter()
+                .find(|issuer| issuer.may_sign_certificates() && *issuer != current && current.verify_signed_by(issuer).is_ok()) {
+                Some(issuer) => issuer,
+                None => break,
+            };
+        }
+        Err(EfiErrorKind::SecurityViolation.into())
+    }
+}
+
+/// Each signature in a run of EFI_SIGNATURE_LISTs with its list's type, after the owner GUID. InvalidParameter if a
+/// list's sizes don't add up
+pub(crate) fn signature_lists(mut data: &[u8]) -> Result<Vec<(EFI_GUID, &[u8])>> {
+    const LIST_HEADER: usize = mem::size_of::<EFI_SIGNATURE_LIST>();
+    const OWNER: usize = mem::size_of::<EFI_SIGNATURE_DATA>();
+    let mut signatures = Vec::new();
+    while !data.is_empty() {
+        if data.len() < LIST_HEADER {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let mut guid = [0; 16];
+        guid.copy_from_slice(&data[..16]);
+        let signature_type = EFI_GUID::from_le_bytes(&guid);
+        let list_size = LittleEndian::read_u32(&data[16..]) as usize;
+        let header_size = LittleEndian::read_u32(&data[20..]) as usize;
+        let signature_size = LittleEndian::read_u32(&data[24..]) as usize;
+        let start = LIST_HEADER.checked_add(header_size).ok_or(EfiErrorKind::InvalidParameter)?;
+        if list_size > data.len() || list_size < start || signature_size <= OWNER || (list_size - start) % signature_size != 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        for signature in data[start..list_size].chunks(signature_size) {
+            signatures.push((signature_type, &signature[OWNER..]));
+        }
+        data = &data[list_size..];
+    }
+    Ok(signatures)
+}
+
+// Appends one EFI_SIGNATURE_LIST, of signatures that have to be all the same size
+pub(crate) fn push_signature_list(lists: &mut Vec<u8>, signature_type: &EFI_GUID, owner: &EFI_GUID, signatures: &[&[u8]]) {
+    let signature_size = mem::size_of::<EFI_GUID>() + signatures[0].len();
+    let list_size = mem::size_of::<EFI_SIGNATURE_LIST>() + signature_size * signatures.len();
+    lists.extend(&signature_type.to_le_bytes());
+    for n in &[list_size, 0, signature_size] {
+        lists.extend(&(*n as u32).to_le_bytes());
+    }
+    for signature in signatures {
+        lists.extend(&owner.to_le_bytes());
+        lists.extend(*signature);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use super::super::testdata::{ROOT_PEM, SIGNER_PEM, ANCHOR_PEM, NO_CERT_SIGN_PEM, NO_CERT_SIGN_LEAF_PEM, CRITICAL_PEM};
+
+    pub(crate) fn signature_list(signature_type: &EFI_GUID, signatures: &[&[u8]]) -> Vec<u8> {
+        let size = 16 + signatures[0].len();
+        let mut list = signature_type.to_le_bytes().to_vec();
+        for n in &[28 + size * signatures.len(), 0, size] {
+            list.extend(&(*n as u32).to_le_bytes());
+        }
+        for signature in signatures {
+            list.extend(&[0xAA; 16]);
+            list.extend(*signature);
+        }
+        list
+    }
+
+    #[test]
+    fn verifies_chains() {
+        let root = Certificate::from_pem(ROOT_PEM).unwrap().remove(0);
+        let signer = Certificate::from_pem(SIGNER_PEM).unwrap().remove(0);
+
+        let mut db = signature_list(&EFI_CERT_SHA256_GUID, &[&[1; 32], &[2; 32]]);
+        db.extend(signature_list(&EFI_CERT_X509_GUID, &[root.der()]));
+        db.extend(signature_list(&EFI_CERT_X509_GUID, &[b"not a certificate"]));
+        let trust = TrustStore::from_signature_lists(&db).unwrap();
+        assert_eq!(trust.certificates(), [root.clone()]);
+        assert!(trust.contains_sha256(&[2; 32]) && !trust.contains_sha256(&[3; 32]));
+        assert!(TrustStore::from_signature_lists(&db[..db.len() - 1]).is_err());
+
+        trust.verify_chain(&signer, &[]).unwrap();
+        trust.verify_chain(&root, &[]).unwrap();
+        // A signer's certificate isn't a CA so can't vouch for anything else
+        let mut only_signer = TrustStore::new();
+        only_signer.add(signer.clone());
+        assert_eq!(only_signer.verify_chain(&root, &[signer.clone()]).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert!(TrustStore::new().verify_chain(&signer, &[root.clone()]).is_err());
+
+        // Nor can a CA whose key usage is only for signatures, and critical extensions have to be understood
+        let pem = |text| Certificate::from_pem(text).unwrap().remove(0);
+        let (anchor, no_cert_sign, leaf, critical) = (pem(ANCHOR_PEM), pem(NO_CERT_SIGN_PEM), pem(NO_CERT_SIGN_LEAF_PEM), pem(CRITICAL_PEM));
+        let mut trust = TrustStore::new();
+        trust.add(anchor.clone());
+        trust.verify_chain(&no_cert_sign, &[]).unwrap();
+        leaf.verify_signed_by(&no_cert_sign).unwrap();
+        assert_eq!(trust.verify_chain(&leaf, &[no_cert_sign]).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        critical.verify_signed_by(&anchor).unwrap();
+        assert!(critical.has_unknown_critical_extension());
+        assert_eq!(trust.verify_chain(&critical, &[]).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+    }
+}
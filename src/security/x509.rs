@@ -0,0 +1,293 @@
+// X.509 certificates (RFC 5280), as far as checking signatures goes: who it's for, who signed it, the key and
+// whether it's a CA, which is basic constraints and key usage. Other extensions are skipped, since Authenticode and
+// the Secure Boot databases don't rely on them either, but a critical one is noted so trust can refuse the
+// certificate as RFC 5280 says to.
+//
+// Only RSA keys and SHA-256 signatures are understood. Certificates with anything else still parse, they just can't
+// be used to check or be checked.
+
+use {Result, EfiErrorKind};
+use hash::{self, SHA256_LEN};
+use time::UnixTime;
+use utils::from_base64;
+use super::der::{self, Reader};
+use super::rsa::RsaPublicKey;
+use alloc::{string::String, vec::Vec};
+use core::{fmt, ops::Range};
+
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+// In the first byte of key usage's bits
+const KEY_CERT_SIGN: u8 = 0x04;
+
+/// A parsed certificate, which keeps its DER
+#[derive(Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+    tbs: Range<usize>,
+    serial: Range<usize>,
+    issuer: Range<usize>,
+    subject: Range<usize>,
+    not_before: UnixTime,
+    not_after: UnixTime,
+    key: Option<RsaPublicKey>,
+    signature_algorithm: Range<usize>,
+    signature: Range<usize>,
+    is_ca: bool,
+    key_usage: Option<u8>,
+    unknown_critical_extension: bool,
+}
+
+impl Certificate {
+    /// InvalidParameter if it isn't a certificate. Anything after it is ignored, since signature databases pad
+    pub fn from_der(data: &[u8]) -> Result<Self> {
+        let cert = Reader::new(data).read_tag(der::SEQUENCE)?;
+        let data = cert.der;
+        let range = |part: &[u8]| {
+            let start = part.as_ptr() as usize - data.as_ptr() as usize;
+            start..start + part.len()
+        };
+
+        let mut outer = cert.reader();
+        let tbs = outer.read_tag(der::SEQUENCE)?;
+        let signature_algorithm = outer.read_algorithm()?;
+        let signature = outer.read_tag(der::BIT_STRING)?.value;
+        let signature = signature.get(1..).ok_or(EfiErrorKind::InvalidParameter)?; // After the count of unused bits
+
+        let mut fields = tbs.reader();
+        fields.read_optional(der::context(0))?; // The version
+        let serial = fields.read_tag(der::INTEGER)?.value;
+        fields.read_algorithm()?; // The same as signature_algorithm
+        let issuer = fields.read_tag(der::SEQUENCE)?.der;
+        let mut validity = fields.read_tag(der::SEQUENCE)?.reader();
+        let not_before = der::time(&validity.read()?)?;
+        let not_after = der::time(&validity.read()?)?;
+        let subject = fields.read_tag(der::SEQUENCE)?.der;
+        let key = parse_public_key(fields.read_tag(der::SEQUENCE)?.reader())?;
+
+        let mut extensions = Extensions::default();
+        while let Some(tag) = fields.peek_tag() {
+            let field = fields.read()?;
+            if tag == der::context(3) {
+                extensions = parse_extensions(field.reader().read_tag(der::SEQUENCE)?.reader())?;
+            }
+        }
+
+        Ok(Certificate {
+            der: data.to_vec(),
+            tbs: range(tbs.der),
+            serial: range(der::unsigned(serial)),
+            issuer: range(issuer),
+            subject: range(subject),
+            not_before,
+            not_after,
+            key,
+            signature_algorithm: range(signature_algorithm),
+            signature: range(signature),
+            is_ca: extensions.is_ca,
+            key_usage: extensions.key_usage,
+            unknown_critical_extension: extensions.unknown_critical,
+        })
+    }
+
+    /// Every certificate in PEM text, e.g. a CA bundle. Text outside the BEGIN and END lines is skipped
+    pub fn from_pem(text: &str) -> Result<Vec<Self>> {
+        let mut certs = Vec::new();
+        let mut rest = text;
+        while let Some(begin) = rest.find(PEM_BEGIN) {
+            let body = &rest[begin + PEM_BEGIN.len()..];
+            let end = body.find(PEM_END).ok_or(EfiErrorKind::InvalidParameter)?;
+            let der = from_base64(&body[..end]).ok_or(EfiErrorKind::InvalidParameter)?;
+            certs.push(Certificate::from_der(&der)?);
+            rest = &body[end + PEM_END.len()..];
+        }
+        Ok(certs)
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The signed part, which is what db and dbx hash certificates by
+    pub fn tbs_der(&self) -> &[u8] {
+        &self.der[self.tbs.clone()]
+    }
+
+    /// Big endian, without leading zeros
+    pub fn serial(&self) -> &[u8] {
+        &self.der[self.serial.clone()]
+    }
+
+    /// The DER of the issuer's name
+    pub fn issuer(&self) -> &[u8] {
+        &self.der[self.issuer.clone()]
+    }
+
+    /// The DER of the subject's name
+    pub fn subject(&self) -> &[u8] {
+        &self.der[self.subject.clone()]
+    }
+
+    pub fn subject_common_name(&self) -> Option<String> {
+        common_name(self.subject())
+    }
+
+    pub fn issuer_common_name(&self) -> Option<String> {
+        common_name(self.issuer())
+    }
+
+    pub fn not_before(&self) -> UnixTime {
+        self.not_before
+    }
+
+    pub fn not_after(&self) -> UnixTime {
+        self.not_after
+    }
+
+    pub fn is_valid_at(&self, time: UnixTime) -> bool {
+        self.not_before <= time && time <= self.not_after
+    }
+
+    /// The subject's key. None if it isn't RSA
+    pub fn public_key(&self) -> Option<&RsaPublicKey> {
+        self.key.as_ref()
+    }
+
+    /// Whether basic constraints say it's a CA
+    pub fn is_ca(&self) -> bool {
+        self.is_ca
+    }
+
+    /// Whether it's a CA and its key usage, if it has one, includes signing certificates
+    pub fn may_sign_certificates(&self) -> bool {
+        self.is_ca && match self.key_usage {
+            Some(usage) => usage & KEY_CERT_SIGN != 0,
+            None => true,
+        }
+    }
+
+    /// Whether it has a critical extension we don't understand, which means it mustn't be relied on
+    pub fn has_unknown_critical_extension(&self) -> bool {
+        self.unknown_critical_extension
+    }
+
+    pub fn sha256(&self) -> [u8; SHA256_LEN] {
+        hash::sha256(&self.der)
+    }
+
+    /// Checks that `issuer` signed this. SecurityViolation if it didn't or it isn't this certificate's issuer, and
+    /// Unsupported for signature algorithms or keys we can't check
+    pub fn verify_signed_by(&self, issuer: &Certificate) -> Result<()> {
+        if self.issuer() != issuer.subject() {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        let key = issuer.public_key().ok_or(EfiErrorKind::Unsupported)?;
+        if &self.der[self.signature_algorithm.clone()] != der::OID_SHA256_WITH_RSA {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        key.verify_sha256(&hash::sha256(self.tbs_der()), &self.der[self.signature.clone()])
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Certificate")
+            .field("subject", &self.subject_common_name())
+            .field("issuer", &self.issuer_common_name())
+            .field("serial", &hash::to_hex(self.serial()))
+            .finish()
+    }
+}
+
+// SubjectPublicKeyInfo. None if it isn't RSA
+fn parse_public_key(mut info: Reader) -> Result<Option<RsaPublicKey>> {
+    let algorithm = info.read_algorithm()?;
+    if algorithm != der::OID_RSA_ENCRYPTION {
+        return Ok(None);
+    }
+    let bits = info.read_tag(der::BIT_STRING)?.value;
+    let mut key = Reader::new(bits.get(1..).ok_or(EfiErrorKind::InvalidParameter)?).read_tag(der::SEQUENCE)?.reader();
+    let modulus = key.read_tag(der::INTEGER)?.value;
+    let exponent = key.read_tag(der::INTEGER)?.value;
+    RsaPublicKey::new(der::unsigned(modulus), der::unsigned(exponent)).map(Some)
+}
+
+#[derive(Default)]
+struct Extensions {
+    is_ca: bool,
+    key_usage: Option<u8>,
+    unknown_critical: bool,
+}
+
+// Basic constraints' cA, the first byte of key usage, and whether any of the others is critical
+fn parse_extensions(mut extensions: Reader) -> Result<Extensions> {
+    let mut parsed = Extensions::default();
+    while !extensions.is_empty() {
+        let mut extension = extensions.read_tag(der::SEQUENCE)?.reader();
+        let id = extension.read_tag(der::OID)?.value;
+        let critical = extension.read_optional(der::BOOLEAN)?.map(|critical| critical.value != [0]) == Some(true);
+        let value = extension.read_tag(der::OCTET_STRING)?.value;
+        if id == der::OID_BASIC_CONSTRAINTS {
+            let mut constraints = Reader::new(value).read_tag(der::SEQUENCE)?.reader();
+            parsed.is_ca = constraints.read_optional(der::BOOLEAN)?.map(|ca| ca.value != [0]) == Some(true);
+        } else if id == der::OID_KEY_USAGE {
+            let bits = Reader::new(value).read_tag(der::BIT_STRING)?.value;
+            parsed.key_usage = Some(bits.get(1).cloned().unwrap_or(0)); // After the count of unused bits
+        } else if critical {
+            parsed.unknown_critical = true;
+        }
+    }
+    Ok(parsed)
+}
+
+// The first common name in a Name, which is a sequence of sets of attributes
+fn common_name(name: &[u8]) -> Option<String> {
+    let mut rdns = Reader::new(name).read_tag(der::SEQUENCE).ok()?.reader();
+    while !rdns.is_empty() {
+        let mut attributes = rdns.read_tag(der::SET).ok()?.reader();
+        while !attributes.is_empty() {
+            let mut attribute = attributes.read_tag(der::SEQUENCE).ok()?.reader();
+            if attribute.read_tag(der::OID).ok()?.value == der::OID_COMMON_NAME {
+                return Some(String::from_utf8_lossy(attribute.read().ok()?.value).into_owned());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testdata::{ROOT_PEM, SIGNER_PEM};
+    use core::time::Duration;
+
+    #[test]
+    fn parses_certificates() {
+        let root = Certificate::from_pem(ROOT_PEM).unwrap().remove(0);
+        let signers = Certificate::from_pem(&format!("Signer\n{}\n", SIGNER_PEM)).unwrap();
+        assert_eq!(signers.len(), 1);
+        let signer = &signers[0];
+
+        assert_eq!(root.subject_common_name().as_ref().map(String::as_str), Some("Test Root CA"));
+        assert_eq!(signer.subject_common_name().as_ref().map(String::as_str), Some("Test Signer"));
+        assert_eq!(signer.issuer(), root.subject());
+        assert_eq!(signer.serial(), [0x12, 0x34]);
+        assert!(root.is_ca() && !signer.is_ca());
+        assert!(root.may_sign_certificates() && !signer.may_sign_certificates());
+        assert!(!root.has_unknown_critical_extension()); // Its basic constraints and key usage are critical
+        assert_eq!(signer.public_key().unwrap().size(), 128);
+        assert!(signer.is_valid_at(UnixTime::from_since_epoch(Duration::from_secs(1_800_000_000))));
+        assert!(!signer.is_valid_at(UnixTime::from_since_epoch(Duration::from_secs(1_700_000_000))));
+
+        signer.verify_signed_by(&root).unwrap();
+        root.verify_signed_by(&root).unwrap();
+        assert_eq!(root.verify_signed_by(signer).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        let mut tampered = signer.der().to_vec();
+        let serial_at = tampered.windows(2).position(|w| w == [0x12, 0x34]).unwrap();
+        tampered[serial_at] = 0x13;
+        assert_eq!(Certificate::from_der(&tampered).unwrap().verify_signed_by(&root).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+
+        assert!(Certificate::from_der(&root.der()[..100]).is_err());
+        assert!(Certificate::from_pem("-----BEGIN CERTIFICATE-----\nnot base64!\n-----END CERTIFICATE-----").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// Serial ports via EFI_SERIAL_IO_PROTOCOL.
+// Firmware usually has the console on one of these as well, in which case writing to it directly interleaves with
+// console output, and reading from it steals console input. That's what you want for talking to a debugger or a
+// machine at the other end of a null modem cable, but not for printing (use the console for that).
+
+use ffi::{
+    serial_io::{EFI_SERIAL_IO_PROTOCOL, EFI_SERIAL_IO_PROTOCOL_GUID, EFI_PARITY_TYPE, EFI_STOP_BITS_TYPE},
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_HANDLE,
+    EFI_GUID,
+    EFI_TIMEOUT,
+    UINTN,
+    VOID,
+};
+use boot_services::locate_handles;
+use fs::to_io_error;
+use io::{self, Read, Write};
+use {Result, EfiErrorKind, system_table, image_handle};
+use core::{mem, ptr};
+use alloc::vec::Vec;
+
+pub struct SerialPort {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_SERIAL_IO_PROTOCOL,
+}
+
+impl SerialPort {
+    pub fn all() -> Result<Vec<Self>> {
+        let mut ports = Vec::new();
+        for handle in locate_handles(&EFI_SERIAL_IO_PROTOCOL_GUID)? {
+            let protocol = open_protocol::<EFI_SERIAL_IO_PROTOCOL>(handle, &EFI_SERIAL_IO_PROTOCOL_GUID);
+            if !protocol.is_null() {
+                ports.push(SerialPort { handle, protocol });
+            }
+        }
+        Ok(ports)
+    }
+
+    /// The first port, which is COM1 on most PCs
+    pub fn first() -> Result<Self> {
+        Self::all()?.into_iter().next().ok_or_else(|| EfiErrorKind::NotFound.into())
+    }
+
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    pub fn baud_rate(&self) -> u64 {
+        unsafe { (*(*self.protocol).Mode).BaudRate }
+    }
+
+    /// How long reads wait for data to arrive, in microseconds
+    pub fn timeout(&self) -> u32 {
+        unsafe { (*(*self.protocol).Mode).Timeout }
+    }
+
+    /// Sets the line to `baud_rate` 8N1, keeping the timeout
+    pub fn set_baud_rate(&mut self, baud_rate: u64) -> Result<()> {
+        let timeout = self.timeout();
+        self.set_attributes(baud_rate, timeout)
+    }
+
+    /// Sets how long reads wait for data to arrive, in microseconds
+    pub fn set_timeout(&mut self, timeout: u32) -> Result<()> {
+        let baud_rate = self.baud_rate();
+        self.set_attributes(baud_rate, timeout)
+    }
+
+    fn set_attributes(&mut self, baud_rate: u64, timeout: u32) -> Result<()> {
+        unsafe {
+            ret_on_err!(((*self.protocol).SetAttributes)(self.protocol, baud_rate, 0, timeout, EFI_PARITY_TYPE::NoParity, 8, EFI_STOP_BITS_TYPE::OneStopBit));
+        }
+        Ok(())
+    }
+}
+
+/// Reads return Ok(0) if nothing arrived within the timeout rather than meaning the end of the stream
+impl Read for SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut size: UINTN = buf.len();
+        let status = unsafe { traced!(((*self.protocol).Read)(self.protocol, &mut size, buf.as_mut_ptr() as *mut VOID)) };
+        if status == EFI_TIMEOUT {
+            return Ok(size);
+        }
+        ::to_res(size, status).map_err(to_io_error)
+    }
+}
+
+impl Write for SerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut size: UINTN = buf.len();
+        let status = unsafe { traced!(((*self.protocol).Write)(self.protocol, &mut size, buf.as_ptr() as *const VOID)) };
+        if status == EFI_TIMEOUT && size > 0 {
+            return Ok(size);
+        }
+        ::to_res(size, status).map_err(to_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> *const T {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        let status = traced!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+        if !::ffi::IsSuccess(status) {
+            return ptr::null();
+        }
+    }
+    protocol
+}
@@ -0,0 +1,211 @@
+// Typed access to boot and runtime services.
+//
+// SystemTable<Boot> is what you get at entry. It hands out BootServices and RuntimeServices borrowed from it.
+// exit_boot_services() consumes it and returns a SystemTable<Runtime> which only hands out RuntimeServices.
+// So holding on to boot services past ExitBootServices is a compile error rather than a crash.
+
+use ffi::{
+    boot_services::EFI_BOOT_SERVICES,
+    runtime_services::{EFI_RUNTIME_SERVICES, EFI_RESET_TYPE},
+    EFI_GUID,
+    EFI_STATUS,
+    EFI_SUCCESS,
+    EFI_BUFFER_TOO_SMALL,
+    EFI_NOT_FOUND,
+    EFI_SYSTEM_TABLE,
+    EFI_TIME,
+    CHAR16,
+    UINTN,
+    UINT32,
+    UINT64,
+    VOID,
+};
+use memory::MemoryMap;
+use handles::{self, HandleInfo};
+use ucs2;
+use Result;
+use alloc::{string::String, vec::Vec};
+use core::{mem, ptr, sync::atomic::{AtomicBool, Ordering}, time::Duration};
+
+static BOOT_SERVICES_EXITED: AtomicBool = AtomicBool::new(false);
+
+/// Whether ExitBootServices has been called successfully. Boot services must not be touched if so
+pub fn boot_services_exited() -> bool {
+    BOOT_SERVICES_EXITED.load(Ordering::SeqCst)
+}
+
+/// Marker for the system table view before ExitBootServices
+pub struct Boot;
+
+/// Marker for the system table view after ExitBootServices
+pub struct Runtime;
+
+pub trait SystemTableView {}
+impl SystemTableView for Boot {}
+impl SystemTableView for Runtime {}
+
+/// Boot services. Only obtainable from a SystemTable<Boot> and can't outlive it
+pub struct BootServices<'a> {
+    inner: &'a EFI_BOOT_SERVICES,
+}
+
+impl<'a> BootServices<'a> {
+    pub(crate) fn new(inner: &'a EFI_BOOT_SERVICES) -> Self {
+        Self { inner }
+    }
+
+    /// The raw boot services table for calling services this crate doesn't wrap
+    pub unsafe fn raw(&self) -> *mut EFI_BOOT_SERVICES {
+        self.inner as *const EFI_BOOT_SERVICES as *mut EFI_BOOT_SERVICES
+    }
+
+    pub fn memory_map(&self) -> Result<MemoryMap> {
+        MemoryMap::get()
+    }
+
+    /// Every handle in the handle database with the protocols on it
+    pub fn handles(&self) -> Result<Vec<HandleInfo>> {
+        handles::handles()
+    }
+
+    /// Busy waits for at least the given duration
+    pub fn stall(&self, dur: Duration) -> Result<()> {
+        let micros = (dur.as_secs() * 1000_000 + dur.subsec_micros() as u64) as UINTN; // TODO: this cast can be lossy. fix it
+        ret_on_err!((self.inner.Stall)(micros));
+        Ok(())
+    }
+
+    pub fn next_monotonic_count(&self) -> Result<u64> {
+        let mut count: UINT64 = 0;
+        ret_on_err!((self.inner.GetNextMonotonicCount)(&mut count));
+        Ok(count)
+    }
+}
+
+/// What QueryVariableInfo says about variable storage, in bytes. The maximum size of a variable covers its name and
+/// header as well as its data, so it's more than can actually be written
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VariableStorage {
+    pub maximum: u64,
+    pub remaining: u64,
+    pub max_variable_size: u64,
+}
+
+/// Runtime services. Available both before and after ExitBootServices
+pub struct RuntimeServices<'a> {
+    inner: &'a EFI_RUNTIME_SERVICES,
+}
+
+impl<'a> RuntimeServices<'a> {
+    pub(crate) fn new(inner: &'a EFI_RUNTIME_SERVICES) -> Self {
+        Self { inner }
+    }
+
+    /// The raw runtime services table for calling services this crate doesn't wrap
+    pub unsafe fn raw(&self) -> *const EFI_RUNTIME_SERVICES {
+        self.inner
+    }
+
+    pub fn get_time(&self) -> Result<EFI_TIME> {
+        let mut time: EFI_TIME = unsafe { ::core::mem::zeroed() };
+        ret_on_err!((self.inner.GetTime)(&mut time, ptr::null_mut()));
+        Ok(time)
+    }
+
+    /// Sets the real-time clock, along with the time zone and daylight saving flags in `time`
+    pub fn set_time(&self, time: &EFI_TIME) -> Result<()> {
+        ret_on_err!((self.inner.SetTime)(time));
+        Ok(())
+    }
+
+    /// Reads a variable. Returns its data and attributes
+    pub fn get_variable(&self, name: &str, vendor: &EFI_GUID) -> Result<(Vec<u8>, u32)> {
+        let name = ucs2::cached(name);
+        let mut attributes: UINT32 = 0;
+        let mut size: UINTN = 0;
+        let status = (self.inner.GetVariable)(name.as_ptr(), vendor, &mut attributes, &mut size, ptr::null_mut());
+        if status == EFI_SUCCESS {
+            return Ok((Vec::new(), attributes));
+        } else if status != EFI_BUFFER_TOO_SMALL {
+            ret_on_err!(status);
+        }
+
+        let mut data = vec![0u8; size];
+        ret_on_err!((self.inner.GetVariable)(name.as_ptr(), vendor, &mut attributes, &mut size, data.as_mut_ptr() as *mut VOID));
+        data.truncate(size);
+        Ok((data, attributes))
+    }
+
+    /// Creates, updates or, if data is empty, deletes a variable
+    pub fn set_variable(&self, name: &str, vendor: &EFI_GUID, attributes: u32, data: &[u8]) -> Result<()> {
+        let name = ucs2::cached(name);
+        ret_on_err!((self.inner.SetVariable)(name.as_ptr(), vendor, attributes, data.len(), data.as_ptr() as *const VOID));
+        Ok(())
+    }
+
+    /// How much room there is for variables with these attributes. Unsupported before UEFI 2.0, which added it
+    pub fn query_variable_info(&self, attributes: u32) -> Result<VariableStorage> {
+        if self.inner.Hdr.Revision < (2 << 16) {
+            return Err(::EfiErrorKind::Unsupported.into());
+        }
+        let mut storage = VariableStorage { maximum: 0, remaining: 0, max_variable_size: 0 };
+        ret_on_err!((self.inner.QueryVariableInfo)(attributes, &mut storage.maximum, &mut storage.remaining, &mut storage.max_variable_size));
+        Ok(storage)
+    }
+
+    /// The name and vendor of every variable, in the order firmware lists them
+    pub fn variable_names(&self) -> Result<Vec<(String, EFI_GUID)>> {
+        let mut names = Vec::new();
+        let mut name: Vec<CHAR16> = vec![0; 64]; // Starting from an empty name begins the enumeration
+        let mut vendor = EFI_GUID(0, 0, 0, [0; 8]);
+        loop {
+            let mut size: UINTN = name.len() * mem::size_of::<CHAR16>();
+            let status = (self.inner.GetNextVariableName)(&mut size, name.as_mut_ptr(), &mut vendor);
+            if status == EFI_BUFFER_TOO_SMALL {
+                // Has to keep the current name for the retry
+                name.resize(size / mem::size_of::<CHAR16>() + 1, 0);
+                continue;
+            } else if status == EFI_NOT_FOUND {
+                return Ok(names);
+            }
+            ret_on_err!(status);
+            let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+            names.push((String::from_utf16_lossy(&name[..len]), vendor));
+        }
+    }
+
+    /// Increments the high 32 bits of the platform's monotonic count, which persist across resets, and gives them.
+    /// The low 32 bits, which boot services count up, start again from zero
+    pub fn next_high_monotonic_count(&self) -> Result<u32> {
+        let mut high: UINT32 = 0;
+        ret_on_err!((self.inner.GetNextHighMonotonicCount)(&mut high));
+        Ok(high)
+    }
+
+    /// Resets or shuts down the platform. The status is reported to whoever is watching e.g. a hypervisor
+    pub fn reset(&self, reset_type: EFI_RESET_TYPE, status: EFI_STATUS) -> ! {
+        (self.inner.ResetSystem)(reset_type, status, 0, ptr::null());
+        loop {} // ResetSystem never returns
+    }
+}
+
+// Performs the ExitBootServices dance.
+// If the map key went stale between getting the map and the call (e.g. a timer event allocated memory) we must get
+// the map again and retry. The spec prohibits allocating memory in between so the map is refreshed in place.
+pub(crate) fn exit_boot_services(table_ptr: *const EFI_SYSTEM_TABLE) -> Result<MemoryMap> {
+    const MAX_ATTEMPTS: usize = 4;
+
+    let mut map = MemoryMap::get()?;
+    let bs = unsafe { (*table_ptr).BootServices };
+    let mut status = 0;
+    for _ in 0..MAX_ATTEMPTS {
+        status = unsafe { ((*bs).ExitBootServices)(::image_handle(), map.key()) };
+        if status == ::ffi::EFI_SUCCESS {
+            BOOT_SERVICES_EXITED.store(true, Ordering::SeqCst);
+            return Ok(map);
+        }
+        map.refresh()?;
+    }
+
+    Err(status.into())
+}
@@ -0,0 +1,174 @@
+// SMBIOS tables, which describe the machine as its vendor sees it: who made it, the firmware version, the serial
+// number, what's in each memory slot.
+//
+// Firmware puts an entry point in the configuration table, the 64-bit SMBIOS 3 one and/or the older 32-bit one,
+// which says where the structure table is. Each structure is a type, a length and a handle followed by formatted
+// fields and then a set of null-terminated strings, which fields refer to by 1-based index, ending in an empty one.
+// See DSP0134 for what's in each type.
+
+use byteorder::{ByteOrder, LittleEndian};
+use ffi::EFI_GUID;
+use {system_table, Result, EfiErrorKind};
+use core::{slice, str};
+
+pub const SMBIOS_TABLE_GUID: EFI_GUID = EFI_GUID(0xEB9D2D31, 0x2D88, 0x11D3, [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
+pub const SMBIOS3_TABLE_GUID: EFI_GUID = EFI_GUID(0xF2FD1544, 0x9794, 0x4A2C, [0x99, 0x2E, 0xE5, 0xBB, 0xCF, 0x20, 0xE3, 0x94]);
+
+pub const TYPE_BIOS: u8 = 0;
+pub const TYPE_SYSTEM: u8 = 1;
+pub const TYPE_BASEBOARD: u8 = 2;
+pub const TYPE_CHASSIS: u8 = 3;
+pub const TYPE_PROCESSOR: u8 = 4;
+pub const TYPE_MEMORY_DEVICE: u8 = 17;
+pub const TYPE_END: u8 = 127;
+
+const HEADER_SIZE: usize = 4;
+
+/// The structure table
+#[derive(Debug, Copy, Clone)]
+pub struct Smbios {
+    pub major: u8,
+    pub minor: u8,
+    table: &'static [u8],
+}
+
+impl Smbios {
+    /// Finds the structure table from the configuration table, preferring the SMBIOS 3 entry point
+    pub fn get() -> Result<Self> {
+        let st = system_table();
+        let tables = unsafe { slice::from_raw_parts(st.ConfigurationTable, st.NumberOfTableEntries) };
+        let find = |guid: EFI_GUID| tables.iter().find(|t| t.VendorGuid == guid).map(|t| t.VendorTable as *const u8);
+
+        if let Some(entry) = find(SMBIOS3_TABLE_GUID) {
+            let entry = unsafe { slice::from_raw_parts(entry, 24) };
+            if &entry[..5] == b"_SM3_" {
+                let size = LittleEndian::read_u32(&entry[12..]) as usize; // A maximum, the end structure says where it really ends
+                let address = LittleEndian::read_u64(&entry[16..]) as usize;
+                let table = unsafe { slice::from_raw_parts(address as *const u8, size) };
+                return Ok(Smbios { major: entry[7], minor: entry[8], table });
+            }
+        }
+        if let Some(entry) = find(SMBIOS_TABLE_GUID) {
+            let entry = unsafe { slice::from_raw_parts(entry, 31) };
+            if &entry[..4] == b"_SM_" && &entry[16..21] == b"_DMI_" {
+                let size = LittleEndian::read_u16(&entry[22..]) as usize;
+                let address = LittleEndian::read_u32(&entry[24..]) as usize;
+                let table = unsafe { slice::from_raw_parts(address as *const u8, size) };
+                return Ok(Smbios { major: entry[6], minor: entry[7], table });
+            }
+        }
+        Err(EfiErrorKind::NotFound.into())
+    }
+
+    pub fn structures(&self) -> Structures<'static> {
+        Structures::new(self.table)
+    }
+
+    /// The first structure of the given type
+    pub fn find(&self, kind: u8) -> Option<Structure<'static>> {
+        self.structures().find(|s| s.kind == kind)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Structure<'a> {
+    pub kind: u8,
+    pub handle: u16,
+    formatted: &'a [u8], // Including the header, so that offsets are the ones in the spec
+    strings: &'a [u8],
+}
+
+impl<'a> Structure<'a> {
+    pub fn formatted(&self) -> &'a [u8] {
+        self.formatted
+    }
+
+    /// The byte at `offset`, None if the structure is too short to have it (e.g. from an older version of the spec)
+    pub fn byte(&self, offset: usize) -> Option<u8> {
+        self.formatted.get(offset).cloned()
+    }
+
+    pub fn word(&self, offset: usize) -> Option<u16> {
+        self.formatted.get(offset..offset + 2).map(LittleEndian::read_u16)
+    }
+
+    pub fn dword(&self, offset: usize) -> Option<u32> {
+        self.formatted.get(offset..offset + 4).map(LittleEndian::read_u32)
+    }
+
+    /// The string the byte at `offset` refers to. None if there's no such string, which is what index 0 means
+    pub fn string(&self, offset: usize) -> Option<&'a str> {
+        let index = self.byte(offset)? as usize;
+        if index == 0 {
+            return None;
+        }
+        let s = self.strings.split(|b| *b == 0).nth(index - 1)?;
+        str::from_utf8(s).ok().map(str::trim)
+    }
+}
+
+pub struct Structures<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> Structures<'a> {
+    pub fn new(table: &'a [u8]) -> Self {
+        Structures { table }
+    }
+}
+
+impl<'a> Iterator for Structures<'a> {
+    type Item = Structure<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let table = self.table;
+        if table.len() < HEADER_SIZE {
+            return None;
+        }
+        let length = table[1] as usize;
+        if length < HEADER_SIZE || length > table.len() {
+            self.table = &[];
+            return None;
+        }
+        // The strings end at the first double null, which is there even if the structure has no strings
+        let rest = &table[length..];
+        let end = match rest.windows(2).position(|w| w == [0, 0]) {
+            Some(end) => end,
+            None => {
+                self.table = &[];
+                return None;
+            }
+        };
+        let structure = Structure { kind: table[0], handle: LittleEndian::read_u16(&table[2..]), formatted: &table[..length], strings: &rest[..end] };
+        self.table = if structure.kind == TYPE_END { &[] } else { &rest[end + 2..] };
+        Some(structure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn reads_structures() {
+        let mut table = Vec::new();
+        // Type 1 (system) with manufacturer and product, version left out
+        table.extend_from_slice(&[TYPE_SYSTEM, 8, 0x01, 0x00, 1, 2, 0, 0]);
+        table.extend_from_slice(b"QEMU\0Standard PC (Q35 + ICH9, 2009)\0\0");
+        // Type 17 without strings
+        table.extend_from_slice(&[TYPE_MEMORY_DEVICE, 6, 0x02, 0x00, 0x00, 0x04, 0, 0]);
+        table.extend_from_slice(&[TYPE_END, 4, 0xff, 0xff, 0, 0]);
+        table.extend_from_slice(&[0xaa; 8]); // Slack after the end structure isn't read
+
+        let structures = Structures::new(&table).collect::<Vec<_>>();
+        assert_eq!(structures.len(), 3);
+        assert_eq!((structures[0].kind, structures[0].handle), (TYPE_SYSTEM, 1));
+        assert_eq!(structures[0].string(4), Some("QEMU"));
+        assert_eq!(structures[0].string(5), Some("Standard PC (Q35 + ICH9, 2009)"));
+        assert_eq!(structures[0].string(6), None);
+        assert_eq!(structures[1].word(4), Some(0x400));
+        assert_eq!(structures[1].word(6), None);
+        assert_eq!(structures[2].kind, TYPE_END);
+    }
+}
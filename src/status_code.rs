@@ -0,0 +1,395 @@
+// Status codes via EFI_STATUS_CODE_PROTOCOL.
+// These are what the firmware itself uses to report progress and errors during POST. Whatever listeners the platform
+// has get them: port 80 POST-code displays, the BMC's event log, the firmware's serial debug output and so on. So an
+// application can report where it's got to and why it failed somewhere that's visible even without a screen.
+// Everything we report is from the software class, subclass EFI application (or OS loader, see set_source())
+
+use ffi::{
+    status_code::{
+        EFI_STATUS_CODE_PROTOCOL,
+        EFI_STATUS_CODE_RUNTIME_PROTOCOL_GUID,
+        EFI_STATUS_CODE_TYPE,
+        EFI_STATUS_CODE_VALUE,
+        EFI_STATUS_CODE_DATA,
+        EFI_STATUS_CODE_STRING_DATA,
+        EFI_STATUS_CODE_DATA_TYPE_STRING_GUID,
+        EfiStringAscii,
+        EFI_PROGRESS_CODE,
+        EFI_ERROR_CODE,
+        EFI_DEBUG_CODE,
+        EFI_ERROR_MINOR,
+        EFI_ERROR_MAJOR,
+        EFI_ERROR_UNRECOVERED,
+        EFI_ERROR_UNCONTAINED,
+        EFI_STATUS_CODE_OPERATION_MASK,
+        EFI_SOFTWARE_EFI_APPLICATION,
+        EFI_SOFTWARE_EFI_OS_LOADER,
+        EFI_SW_PC_INIT,
+        EFI_SW_PC_LOAD,
+        EFI_SW_PC_INIT_BEGIN,
+        EFI_SW_PC_INIT_END,
+        EFI_SW_PC_AUTHENTICATE_BEGIN,
+        EFI_SW_PC_AUTHENTICATE_END,
+        EFI_SW_PC_INPUT_WAIT,
+        EFI_SW_PC_USER_SETUP,
+        EFI_SW_EC_NON_SPECIFIC,
+        EFI_SW_EC_LOAD_ERROR,
+        EFI_SW_EC_INVALID_PARAMETER,
+        EFI_SW_EC_UNSUPPORTED,
+        EFI_SW_EC_INVALID_BUFFER,
+        EFI_SW_EC_OUT_OF_RESOURCES,
+        EFI_SW_EC_ABORTED,
+        EFI_SW_EC_ILLEGAL_SOFTWARE_STATE,
+        EFI_SW_EC_ILLEGAL_HARDWARE_STATE,
+        EFI_SW_EC_START_ERROR,
+        EFI_SW_EC_BAD_DATE_TIME,
+        EFI_SW_EC_CFG_INVALID,
+        EFI_SW_EC_PWD_INVALID,
+        EFI_SW_EC_EVENT_LOG_FULL,
+        EFI_DC_UNSPECIFIED,
+    },
+};
+use {Result, EfiError, EfiErrorKind, protocol_cache};
+use io;
+use core::{mem, ptr};
+use alloc::{string::{String, ToString}, vec::Vec};
+
+/// How bad an error is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// We carried on
+    Minor,
+    /// We couldn't do what we were asked to
+    Major,
+    /// We can't carry on at all
+    Unrecovered,
+    /// We can't carry on and may have left things broken
+    Uncontained,
+}
+
+impl Severity {
+    fn code_type(self) -> EFI_STATUS_CODE_TYPE {
+        match self {
+            Severity::Minor => EFI_ERROR_MINOR,
+            Severity::Major => EFI_ERROR_MAJOR,
+            Severity::Unrecovered => EFI_ERROR_UNRECOVERED,
+            Severity::Uncontained => EFI_ERROR_UNCONTAINED,
+        }
+    }
+}
+
+/// The progress codes common to all software
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Progress {
+    Init,
+    Load,
+    InitBegin,
+    InitEnd,
+    AuthenticateBegin,
+    AuthenticateEnd,
+    InputWait,
+    UserSetup,
+}
+
+impl Progress {
+    fn operation(self) -> EFI_STATUS_CODE_VALUE {
+        match self {
+            Progress::Init => EFI_SW_PC_INIT,
+            Progress::Load => EFI_SW_PC_LOAD,
+            Progress::InitBegin => EFI_SW_PC_INIT_BEGIN,
+            Progress::InitEnd => EFI_SW_PC_INIT_END,
+            Progress::AuthenticateBegin => EFI_SW_PC_AUTHENTICATE_BEGIN,
+            Progress::AuthenticateEnd => EFI_SW_PC_AUTHENTICATE_END,
+            Progress::InputWait => EFI_SW_PC_INPUT_WAIT,
+            Progress::UserSetup => EFI_SW_PC_USER_SETUP,
+        }
+    }
+}
+
+/// The error codes common to all software
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    NonSpecific,
+    LoadError,
+    InvalidParameter,
+    Unsupported,
+    InvalidBuffer,
+    OutOfResources,
+    Aborted,
+    IllegalSoftwareState,
+    IllegalHardwareState,
+    StartError,
+    BadDateTime,
+    ConfigInvalid,
+    PasswordInvalid,
+    EventLogFull,
+}
+
+impl ErrorCode {
+    fn operation(self) -> EFI_STATUS_CODE_VALUE {
+        match self {
+            ErrorCode::NonSpecific => EFI_SW_EC_NON_SPECIFIC,
+            ErrorCode::LoadError => EFI_SW_EC_LOAD_ERROR,
+            ErrorCode::InvalidParameter => EFI_SW_EC_INVALID_PARAMETER,
+            ErrorCode::Unsupported => EFI_SW_EC_UNSUPPORTED,
+            ErrorCode::InvalidBuffer => EFI_SW_EC_INVALID_BUFFER,
+            ErrorCode::OutOfResources => EFI_SW_EC_OUT_OF_RESOURCES,
+            ErrorCode::Aborted => EFI_SW_EC_ABORTED,
+            ErrorCode::IllegalSoftwareState => EFI_SW_EC_ILLEGAL_SOFTWARE_STATE,
+            ErrorCode::IllegalHardwareState => EFI_SW_EC_ILLEGAL_HARDWARE_STATE,
+            ErrorCode::StartError => EFI_SW_EC_START_ERROR,
+            ErrorCode::BadDateTime => EFI_SW_EC_BAD_DATE_TIME,
+            ErrorCode::ConfigInvalid => EFI_SW_EC_CFG_INVALID,
+            ErrorCode::PasswordInvalid => EFI_SW_EC_PWD_INVALID,
+            ErrorCode::EventLogFull => EFI_SW_EC_EVENT_LOG_FULL,
+        }
+    }
+}
+
+impl From<EfiErrorKind> for ErrorCode {
+    fn from(kind: EfiErrorKind) -> Self {
+        match kind {
+            EfiErrorKind::LoadError => ErrorCode::LoadError,
+            EfiErrorKind::InvalidParameter => ErrorCode::InvalidParameter,
+            EfiErrorKind::Unsupported => ErrorCode::Unsupported,
+            EfiErrorKind::BadBufferSize | EfiErrorKind::BufferTooSmall => ErrorCode::InvalidBuffer,
+            EfiErrorKind::OutOfResources | EfiErrorKind::VolumeFull => ErrorCode::OutOfResources,
+            EfiErrorKind::Aborted => ErrorCode::Aborted,
+            EfiErrorKind::NotStarted | EfiErrorKind::AlreadyStarted => ErrorCode::IllegalSoftwareState,
+            EfiErrorKind::DeviceError => ErrorCode::IllegalHardwareState,
+            _ => ErrorCode::NonSpecific,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StatusCode {
+    Progress(Progress),
+    Error(Severity, ErrorCode),
+    /// Carries a message, see debug()
+    Debug,
+    /// Anything else, e.g. the subclass- and OEM-specific operations (EFI_SUBCLASS_SPECIFIC and EFI_OEM_SPECIFIC in
+    /// the operation). Only the operation bits of `operation` are used
+    Custom { code_type: EFI_STATUS_CODE_TYPE, operation: EFI_STATUS_CODE_VALUE },
+}
+
+impl StatusCode {
+    pub fn code_type(&self) -> EFI_STATUS_CODE_TYPE {
+        match *self {
+            StatusCode::Progress(_) => EFI_PROGRESS_CODE,
+            StatusCode::Error(severity, _) => EFI_ERROR_CODE | severity.code_type(),
+            StatusCode::Debug => EFI_DEBUG_CODE,
+            StatusCode::Custom { code_type, .. } => code_type,
+        }
+    }
+
+    /// Class, subclass and operation
+    pub fn value(&self) -> EFI_STATUS_CODE_VALUE {
+        let operation = match *self {
+            StatusCode::Progress(progress) => progress.operation(),
+            StatusCode::Error(_, error) => error.operation(),
+            StatusCode::Debug => EFI_DC_UNSPECIFIED,
+            StatusCode::Custom { operation, .. } => operation & EFI_STATUS_CODE_OPERATION_MASK,
+        };
+        source().subclass() | operation
+    }
+}
+
+/// A major error with the closest error code to the error's kind
+impl<'a> From<&'a EfiError> for StatusCode {
+    fn from(error: &'a EfiError) -> Self {
+        StatusCode::Error(Severity::Major, error.kind().into())
+    }
+}
+
+/// What we report ourselves as
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    Application,
+    OsLoader,
+}
+
+impl Source {
+    fn subclass(self) -> EFI_STATUS_CODE_VALUE {
+        match self {
+            Source::Application => EFI_SOFTWARE_EFI_APPLICATION,
+            Source::OsLoader => EFI_SOFTWARE_EFI_OS_LOADER,
+        }
+    }
+}
+
+static mut SOURCE: Source = Source::Application;
+
+/// Report as an OS loader rather than an application. Codes from loaders are told apart by some platforms, e.g. to
+/// know that POST has finished
+pub fn set_source(source: Source) {
+    unsafe { SOURCE = source };
+}
+
+pub fn source() -> Source {
+    unsafe { SOURCE }
+}
+
+/// Whether the platform takes status codes at all. If not, reporting fails with NotFound
+pub fn is_available() -> bool {
+    status_code_protocol().is_ok()
+}
+
+pub fn report(code: StatusCode) -> Result<()> {
+    report_data(code, ptr::null())
+}
+
+/// Reports the code along with a message. Listeners that show text, such as the firmware's debug output, show it
+pub fn report_with_message(code: StatusCode, message: &str) -> Result<()> {
+    let mut ascii = message.bytes().map(|b| if b.is_ascii() && b != 0 { b } else { b'?' }).collect::<Vec<_>>();
+    ascii.push(0);
+    let header_size = mem::size_of::<EFI_STATUS_CODE_DATA>();
+    let data = EFI_STATUS_CODE_STRING_DATA {
+        DataHeader: EFI_STATUS_CODE_DATA {
+            HeaderSize: header_size as u16,
+            Size: (mem::size_of::<EFI_STATUS_CODE_STRING_DATA>() - header_size) as u16,
+            Type: EFI_STATUS_CODE_DATA_TYPE_STRING_GUID,
+        },
+        StringType: EfiStringAscii,
+        String: ascii.as_ptr() as *const i8,
+    };
+    report_data(code, &data.DataHeader)
+}
+
+/// Reports the error as a major error with its description as the message
+pub fn report_error(error: &EfiError) -> Result<()> {
+    report_with_message(error.into(), &error.to_string())
+}
+
+/// Sends a debug message
+pub fn debug(message: &str) -> Result<()> {
+    report_with_message(StatusCode::Debug, message)
+}
+
+/// Sends each line written to it as a debug message, so that status codes can be written to like any other log
+/// (e.g. with `writeln!`) or put behind something that is. Lines are held until they end so that a message isn't
+/// split across codes
+pub struct StatusCodeWriter {
+    line: Vec<u8>,
+}
+
+impl StatusCodeWriter {
+    pub fn new() -> Self {
+        StatusCodeWriter { line: Vec::new() }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let line = mem::take(&mut self.line);
+        let line = String::from_utf8_lossy(&line);
+        debug(line.trim_end_matches('\r')).map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to report status code"))
+    }
+}
+
+impl Default for StatusCodeWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl io::Write for StatusCodeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            if b == b'\n' {
+                self.send()?;
+            } else {
+                self.line.push(b);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.line.is_empty() { Ok(()) } else { self.send() }
+    }
+}
+
+impl Drop for StatusCodeWriter {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+fn report_data(code: StatusCode, data: *const EFI_STATUS_CODE_DATA) -> Result<()> {
+    let protocol = status_code_protocol()?;
+    unsafe {
+        ret_on_err!(((*protocol).ReportStatusCode)(code.code_type(), code.value(), 0, ptr::null(), data));
+    }
+    Ok(())
+}
+
+fn status_code_protocol() -> Result<*const EFI_STATUS_CODE_PROTOCOL> {
+    protocol_cache::locate(&EFI_STATUS_CODE_RUNTIME_PROTOCOL_GUID)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{
+        boot_services::EFI_INTERFACE_TYPE,
+        EFI_GUID,
+        EFI_HANDLE,
+        EFI_STATUS,
+        EFI_SUCCESS,
+        UINT32,
+        VOID,
+    };
+    use testing::mock;
+    use system_table;
+    use io::Write;
+    use alloc::boxed::Box;
+
+    // ReportStatusCode doesn't get a This pointer, so what it's sent has to go somewhere global
+    static mut REPORTS: *mut Vec<(EFI_STATUS_CODE_TYPE, EFI_STATUS_CODE_VALUE, Option<String>)> = ptr::null_mut();
+
+    extern "efiapi" fn report_status_code(code_type: EFI_STATUS_CODE_TYPE, value: EFI_STATUS_CODE_VALUE, _instance: UINT32, _caller: *const EFI_GUID, data: *const EFI_STATUS_CODE_DATA) -> EFI_STATUS {
+        unsafe {
+            let message = if data.is_null() {
+                None
+            } else {
+                assert_eq!((*data).Type, EFI_STATUS_CODE_DATA_TYPE_STRING_GUID);
+                let data = &*(data as *const EFI_STATUS_CODE_STRING_DATA);
+                let mut message = String::new();
+                let mut c = data.String as *const u8;
+                while *c != 0 {
+                    message.push(*c as char);
+                    c = c.add(1);
+                }
+                Some(message)
+            };
+            (*REPORTS).push((code_type, value, message));
+        }
+        EFI_SUCCESS
+    }
+
+    #[test]
+    fn reports_codes() {
+        mock::install();
+        unsafe { REPORTS = Box::into_raw(Box::new(Vec::new())) };
+        let fake = Box::leak(Box::new(EFI_STATUS_CODE_PROTOCOL { ReportStatusCode: report_status_code }));
+        let mut handle: EFI_HANDLE = ptr::null_mut();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_STATUS_CODE_RUNTIME_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, fake as *const _ as *const VOID);
+        }
+
+        report(StatusCode::Progress(Progress::InitBegin)).unwrap();
+        report_error(&EfiErrorKind::OutOfResources.into()).unwrap();
+        {
+            let mut writer = StatusCodeWriter::new();
+            write!(writer, "loaded {} bytes\r\nbooting", 42).unwrap();
+        }
+
+        let reports = unsafe { &*REPORTS };
+        assert_eq!(reports[0], (EFI_PROGRESS_CODE, 0x03100002, None));
+        assert_eq!(reports[1].0, EFI_ERROR_CODE | EFI_ERROR_MAJOR);
+        assert_eq!(reports[1].1, 0x03100005);
+        assert!(reports[1].2.as_ref().unwrap().ends_with("A resource has run out"));
+        assert_eq!(reports[2], (EFI_DEBUG_CODE, 0x03100000, Some(String::from("loaded 42 bytes"))));
+        assert_eq!(reports[3], (EFI_DEBUG_CODE, 0x03100000, Some(String::from("booting"))));
+        assert_eq!(reports.len(), 4);
+    }
+}
@@ -0,0 +1,121 @@
+// Security protocol commands to storage devices: ATA TRUSTED SEND/RECEIVE, SCSI SECURITY PROTOCOL IN/OUT and the
+// NVMe Security Send/Receive admin commands, all behind EFI_STORAGE_SECURITY_COMMAND_PROTOCOL. Self-encrypting
+// drives speak TCG Opal over them, see the opal module.
+
+use ffi::{
+    storage_security::{EFI_STORAGE_SECURITY_COMMAND_PROTOCOL, EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID},
+    boot_services::EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+    EFI_HANDLE,
+    EFI_GUID,
+    UINTN,
+    VOID,
+};
+use boot_services::locate_handles;
+use fs::BlockIo;
+use byteorder::{ByteOrder, BigEndian};
+use {Result, EfiErrorKind, system_table, image_handle};
+use alloc::vec::Vec;
+use core::{mem, ptr, time::Duration};
+
+/// Security protocol 0 lists the supported protocols (and gets certificates)
+pub const PROTOCOL_INFORMATION: u8 = 0x00;
+/// TCG, for Opal and the other SSCs. The protocol specific data is a ComID
+pub const PROTOCOL_TCG: u8 = 0x01;
+pub const PROTOCOL_TCG_COMID_MANAGEMENT: u8 = 0x02;
+/// The ATA security feature set (SECURITY ERASE and friends) tunnelled through SCSI
+pub const PROTOCOL_ATA_SECURITY: u8 = 0xef;
+
+// SCSI and ATA want transfers in whole 512 byte blocks
+const TRANSFER_UNIT: usize = 512;
+
+pub struct StorageSecurity {
+    handle: EFI_HANDLE,
+    protocol: *const EFI_STORAGE_SECURITY_COMMAND_PROTOCOL,
+    media_id: u32,
+}
+
+impl StorageSecurity {
+    /// Every device that accepts security protocol commands. Firmware puts the protocol on the whole disk, not on
+    /// partitions
+    pub fn all() -> Result<Vec<Self>> {
+        let mut devices = Vec::new();
+        for handle in locate_handles(&EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID)? {
+            if let Ok(device) = Self::new(handle) {
+                devices.push(device);
+            }
+        }
+        Ok(devices)
+    }
+
+    pub fn new(handle: EFI_HANDLE) -> Result<Self> {
+        let protocol = open_protocol::<EFI_STORAGE_SECURITY_COMMAND_PROTOCOL>(handle, &EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID);
+        if protocol.is_null() {
+            return Err(EfiErrorKind::Unsupported.into());
+        }
+        // Commands are for whatever medium is in there now. No block IO means no medium, which is fine for the
+        // commands that don't touch it, and firmware ignores the id then
+        let media_id = BlockIo::new(handle).map(|b| b.media_id()).unwrap_or(0);
+        Ok(StorageSecurity { handle, protocol, media_id })
+    }
+
+    pub fn handle(&self) -> EFI_HANDLE {
+        self.handle
+    }
+
+    /// Sends a payload for the given security protocol. A zero timeout waits for as long as it takes
+    pub fn send(&self, protocol: u8, specific: u16, payload: &[u8], timeout: Duration) -> Result<()> {
+        let mut padded;
+        let mut payload = payload;
+        let partial = payload.len() % TRANSFER_UNIT;
+        if partial != 0 {
+            padded = payload.to_vec();
+            padded.resize(payload.len() - partial + TRANSFER_UNIT, 0);
+            payload = &padded;
+        }
+        unsafe {
+            ret_on_err!(((*self.protocol).SendData)(self.protocol, self.media_id, to_100ns(timeout), protocol, specific.to_be(), payload.len(), payload.as_ptr() as *const VOID));
+        }
+        Ok(())
+    }
+
+    /// Receives into `buf`, which should be a multiple of 512 bytes. Returns how much the device sent
+    pub fn receive(&self, protocol: u8, specific: u16, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let mut transferred: UINTN = 0;
+        unsafe {
+            ret_on_err!(((*self.protocol).ReceiveData)(self.protocol, self.media_id, to_100ns(timeout), protocol, specific.to_be(), buf.len(), buf.as_mut_ptr() as *mut VOID, &mut transferred));
+        }
+        Ok(transferred.min(buf.len()))
+    }
+
+    /// The security protocols the device supports, from protocol 0's list
+    pub fn supported_protocols(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; TRANSFER_UNIT];
+        let len = self.receive(PROTOCOL_INFORMATION, 0, &mut buf, Duration::from_secs(1))?;
+        Ok(parse_protocol_list(&buf[..len]))
+    }
+}
+
+// Six reserved bytes, a big-endian count and then a byte per protocol
+fn parse_protocol_list(list: &[u8]) -> Vec<u8> {
+    if list.len() < 8 {
+        return Vec::new();
+    }
+    let count = BigEndian::read_u16(&list[6..]) as usize;
+    list[8..].iter().take(count).cloned().collect()
+}
+
+fn to_100ns(timeout: Duration) -> u64 {
+    (timeout.as_nanos() / 100) as u64
+}
+
+fn open_protocol<T>(handle: EFI_HANDLE, guid: &EFI_GUID) -> *const T {
+    let bs = system_table().BootServices;
+    let protocol: *const T = ptr::null();
+    unsafe {
+        let status = traced!(((*bs).OpenProtocol)(handle, guid, mem::transmute(&protocol), image_handle(), ptr::null(), EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL));
+        if !::ffi::IsSuccess(status) {
+            return ptr::null();
+        }
+    }
+    protocol
+}
@@ -0,0 +1,434 @@
+// What the machine is, for diagnostics and support reports.
+// Everything here is read-only and best effort: whatever the firmware or processor can't tell us is left out rather
+// than failing the whole report. SystemReport::collect() gathers it all in one go; it prints as text and turns into
+// JSON for tools to pick apart.
+
+use ffi::boot_services::EFI_MEMORY_TYPE;
+use firmware::{self, Revision};
+use json::{ToJson, Value};
+use memory::MemoryMap;
+use pci::{self, PciDevice};
+use pages::PAGE_SIZE;
+use smbios::{self, Smbios, Structure};
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::fmt::{self, Display, Formatter};
+
+/// Everything we know about the machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemReport {
+    pub firmware: FirmwareInfo,
+    pub smbios: Option<SmbiosInfo>,
+    pub cpu: Option<CpuInfo>,
+    pub memory: Option<MemoryInfo>,
+    pub pci: Vec<PciFunction>,
+}
+
+impl SystemReport {
+    pub fn collect() -> Self {
+        SystemReport {
+            firmware: FirmwareInfo::get(),
+            smbios: Smbios::get().ok().map(|smbios| SmbiosInfo::from_table(&smbios)),
+            cpu: CpuInfo::get(),
+            memory: MemoryInfo::get(),
+            pci: PciFunction::all(),
+        }
+    }
+}
+
+impl Display for SystemReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.firmware)?;
+        if let Some(ref smbios) = self.smbios {
+            writeln!(f, "{}", smbios)?;
+        }
+        if let Some(ref cpu) = self.cpu {
+            writeln!(f, "{}", cpu)?;
+        }
+        if let Some(ref memory) = self.memory {
+            writeln!(f, "{}", memory)?;
+        }
+        if !self.pci.is_empty() {
+            writeln!(f, "PCI:")?;
+            for function in &self.pci {
+                writeln!(f, "  {}", function)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for SystemReport {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("firmware", &self.firmware);
+        value.set("smbios", &self.smbios);
+        value.set("cpu", &self.cpu);
+        value.set("memory", &self.memory);
+        value.set("pci", &self.pci);
+        value
+    }
+}
+
+/// What the system table says about the firmware
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareInfo {
+    pub vendor: String,
+    pub revision: u32, // Vendor defined
+    pub uefi_major: u16,
+    pub uefi_minor: u16,
+}
+
+impl FirmwareInfo {
+    pub fn get() -> Self {
+        let uefi = firmware::revision();
+        FirmwareInfo { vendor: firmware::vendor(), revision: firmware::vendor_revision(), uefi_major: uefi.major, uefi_minor: uefi.minor }
+    }
+}
+
+impl Display for FirmwareInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Firmware: {} revision {:#x}, UEFI {}", self.vendor, self.revision, Revision::new(self.uefi_major, self.uefi_minor))
+    }
+}
+
+impl ToJson for FirmwareInfo {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("vendor", &self.vendor);
+        value.set("revision", &self.revision);
+        value.set("uefi_major", &self.uefi_major);
+        value.set("uefi_minor", &self.uefi_minor);
+        value
+    }
+}
+
+/// The interesting parts of the SMBIOS tables
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SmbiosInfo {
+    pub version: String,
+    pub bios_vendor: Option<String>,
+    pub bios_version: Option<String>,
+    pub bios_date: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    pub board: Option<String>,
+    pub memory_devices: Vec<MemoryDevice>,
+}
+
+impl SmbiosInfo {
+    pub fn from_table(smbios: &Smbios) -> Self {
+        Self::from_structures(format!("{}.{}", smbios.major, smbios.minor), smbios.structures())
+    }
+
+    pub fn from_structures<'a, I: Iterator<Item = Structure<'a>>>(version: String, structures: I) -> Self {
+        let mut info = SmbiosInfo { version, ..Default::default() };
+        let string = |s: &Structure, offset| s.string(offset).filter(|s| !s.is_empty()).map(String::from);
+        for s in structures {
+            match s.kind {
+                smbios::TYPE_BIOS => {
+                    info.bios_vendor = string(&s, 0x04);
+                    info.bios_version = string(&s, 0x05);
+                    info.bios_date = string(&s, 0x08);
+                }
+                smbios::TYPE_SYSTEM => {
+                    info.manufacturer = string(&s, 0x04);
+                    info.product = string(&s, 0x05);
+                    info.serial_number = string(&s, 0x07);
+                }
+                smbios::TYPE_BASEBOARD => info.board = string(&s, 0x05),
+                smbios::TYPE_MEMORY_DEVICE => {
+                    if let Some(device) = MemoryDevice::from_structure(&s) {
+                        info.memory_devices.push(device);
+                    }
+                }
+                _ => {}
+            }
+        }
+        info
+    }
+}
+
+impl Display for SmbiosInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let or_unknown = |s: &Option<String>| s.clone().unwrap_or_else(|| "unknown".into());
+        writeln!(f, "System: {} {} (serial {})", or_unknown(&self.manufacturer), or_unknown(&self.product), or_unknown(&self.serial_number))?;
+        if let Some(ref board) = self.board {
+            writeln!(f, "  Board: {}", board)?;
+        }
+        write!(f, "  BIOS: {} {} {} (SMBIOS {})", or_unknown(&self.bios_vendor), or_unknown(&self.bios_version), or_unknown(&self.bios_date), self.version)?;
+        for device in &self.memory_devices {
+            write!(f, "\n  {}", device)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for SmbiosInfo {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("version", &self.version);
+        value.set("bios_vendor", &self.bios_vendor);
+        value.set("bios_version", &self.bios_version);
+        value.set("bios_date", &self.bios_date);
+        value.set("manufacturer", &self.manufacturer);
+        value.set("product", &self.product);
+        value.set("serial_number", &self.serial_number);
+        value.set("board", &self.board);
+        value.set("memory_devices", &self.memory_devices);
+        value
+    }
+}
+
+/// A populated memory slot (SMBIOS type 17)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDevice {
+    pub locator: Option<String>,
+    pub size_mb: u64,
+    pub speed_mts: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub part_number: Option<String>,
+}
+
+impl MemoryDevice {
+    // None for empty slots
+    fn from_structure(s: &Structure) -> Option<Self> {
+        let size = s.word(0x0c)?;
+        let size_mb = match size {
+            0 | 0xffff => return None, // Empty or unknown
+            0x7fff => s.dword(0x1c)? as u64 & 0x7fff_ffff, // Too big for the word, in the extended size instead
+            size if size & 0x8000 != 0 => (size & 0x7fff) as u64 / 1024, // In KB
+            size => size as u64,
+        };
+        let string = |offset| s.string(offset).filter(|s| !s.is_empty()).map(String::from);
+        Some(MemoryDevice {
+            locator: string(0x10),
+            size_mb,
+            speed_mts: s.word(0x15).filter(|speed| *speed != 0),
+            manufacturer: string(0x17),
+            part_number: string(0x1a),
+        })
+    }
+}
+
+impl Display for MemoryDevice {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Memory: {} MB", self.size_mb)?;
+        if let Some(ref locator) = self.locator {
+            write!(f, " in {}", locator)?;
+        }
+        if let Some(speed) = self.speed_mts {
+            write!(f, " at {} MT/s", speed)?;
+        }
+        if let Some(ref manufacturer) = self.manufacturer {
+            write!(f, ", {}", manufacturer)?;
+        }
+        if let Some(ref part_number) = self.part_number {
+            write!(f, " {}", part_number)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for MemoryDevice {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("locator", &self.locator);
+        value.set("size_mb", &self.size_mb);
+        value.set("speed_mts", &self.speed_mts);
+        value.set("manufacturer", &self.manufacturer);
+        value.set("part_number", &self.part_number);
+        value
+    }
+}
+
+/// Totals from the UEFI memory map
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryInfo {
+    pub total: u64, // Bytes of RAM, i.e. everything but MMIO
+    pub usable: u64, // What the OS gets once boot services are gone
+    pub regions: usize,
+}
+
+impl MemoryInfo {
+    pub fn get() -> Option<Self> {
+        use self::EFI_MEMORY_TYPE::*;
+        let map = MemoryMap::get().ok()?;
+        let mut info = MemoryInfo { total: 0, usable: 0, regions: map.len() };
+        for d in &map {
+            let bytes = d.NumberOfPages * PAGE_SIZE as u64;
+            let usable = [EfiConventionalMemory, EfiLoaderCode, EfiLoaderData, EfiBootServicesCode, EfiBootServicesData];
+            if usable.iter().any(|t| *t as u32 == d.Type) {
+                info.usable += bytes;
+            }
+            if d.Type != EfiMemoryMappedIO as u32 && d.Type != EfiMemoryMappedIOPortSpace as u32 {
+                info.total += bytes;
+            }
+        }
+        Some(info)
+    }
+}
+
+impl Display for MemoryInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Memory map: {} MB, {} MB usable, {} regions", self.total >> 20, self.usable >> 20, self.regions)
+    }
+}
+
+impl ToJson for MemoryInfo {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("total", &self.total);
+        value.set("usable", &self.usable);
+        value.set("regions", &(self.regions as u64));
+        value
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PciFunction {
+    pub location: pci::Location,
+    pub header: pci::Header,
+}
+
+impl PciFunction {
+    pub fn all() -> Vec<Self> {
+        let devices = PciDevice::all().unwrap_or_default();
+        devices.iter().filter_map(|d| Some(PciFunction { location: d.location().ok()?, header: d.header().ok()? })).collect()
+    }
+}
+
+impl Display for PciFunction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let h = &self.header;
+        write!(f, "{} {} [{:02x}{:02x}]: {:04x}:{:04x} (rev {:02x})", self.location, h.class_name(), h.class, h.subclass, h.vendor_id, h.device_id, h.revision)
+    }
+}
+
+impl ToJson for PciFunction {
+    fn to_json(&self) -> Value {
+        let h = &self.header;
+        let mut value = Value::Object(Vec::new());
+        value.set("location", &self.location.to_string());
+        value.set("vendor_id", &h.vendor_id);
+        value.set("device_id", &h.device_id);
+        value.set("revision", &h.revision);
+        value.set("class", &h.class);
+        value.set("subclass", &h.subclass);
+        value.set("prog_if", &h.prog_if);
+        value.set("subsystem_vendor_id", &h.subsystem_vendor_id);
+        value.set("subsystem_id", &h.subsystem_id);
+        value
+    }
+}
+
+/// The processor we're running on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuInfo {
+    pub vendor: String,
+    pub brand: Option<String>,
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    pub microcode: Option<u32>,
+    pub features: Vec<&'static str>,
+    pub threads_per_core: u32,
+    pub logical_per_package: u32,
+}
+
+impl CpuInfo {
+    /// None on architectures we don't know how to identify yet
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    pub fn get() -> Option<Self> {
+        use arch::x86;
+        let signature = x86::Signature::get();
+        let topology = x86::Topology::get();
+        Some(CpuInfo {
+            vendor: String::from_utf8_lossy(&x86::vendor()).into_owned(),
+            brand: x86::brand().map(|brand| trim(&brand)),
+            family: signature.family,
+            model: signature.model,
+            stepping: signature.stepping,
+            microcode: x86::microcode_revision(),
+            features: x86::features().map(|f| f.name).collect(),
+            threads_per_core: topology.threads_per_core,
+            logical_per_package: topology.logical_per_package,
+        })
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    pub fn get() -> Option<Self> {
+        None
+    }
+}
+
+impl Display for CpuInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "CPU: {}", self.brand.as_ref().unwrap_or(&self.vendor))?;
+        write!(f, "  {} family {:#x} model {:#x} stepping {:#x}", self.vendor, self.family, self.model, self.stepping)?;
+        if let Some(microcode) = self.microcode {
+            write!(f, ", microcode {:#x}", microcode)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "  {} logical processors per package, {} per core", self.logical_per_package, self.threads_per_core)?;
+        write!(f, "  Features: {}", self.features.join(" "))
+    }
+}
+
+impl ToJson for CpuInfo {
+    fn to_json(&self) -> Value {
+        let mut value = Value::Object(Vec::new());
+        value.set("vendor", &self.vendor);
+        value.set("brand", &self.brand);
+        value.set("family", &self.family);
+        value.set("model", &self.model);
+        value.set("stepping", &self.stepping);
+        value.set("microcode", &self.microcode);
+        value.set("features", &self.features);
+        value.set("threads_per_core", &self.threads_per_core);
+        value.set("logical_per_package", &self.logical_per_package);
+        value
+    }
+}
+
+// Brand strings are null padded, and some processors pad the front with spaces too
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn trim(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smbios::Structures;
+
+    #[test]
+    fn summarizes_smbios() {
+        let mut table = Vec::new();
+        table.extend_from_slice(&[smbios::TYPE_BIOS, 0x12, 0x00, 0x00, 1, 2, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        table.extend_from_slice(b"EFI Development Kit II / OVMF\x000.0.0\x0002/06/2015\0\0");
+        table.extend_from_slice(&[smbios::TYPE_SYSTEM, 0x08, 0x01, 0x00, 1, 2, 0, 3]);
+        table.extend_from_slice(b"QEMU\0Standard PC\0 \0\0");
+        // A 16 GB DIMM and an empty slot
+        let mut dimm = [0u8; 0x1c];
+        dimm[..4].copy_from_slice(&[smbios::TYPE_MEMORY_DEVICE, 0x1c, 0x02, 0x00]);
+        dimm[0x0c..0x0e].copy_from_slice(&0x4000u16.to_le_bytes());
+        dimm[0x10] = 1;
+        dimm[0x15..0x17].copy_from_slice(&2666u16.to_le_bytes());
+        table.extend_from_slice(&dimm);
+        table.extend_from_slice(b"DIMM 0\0\0");
+        dimm[0x0c..0x0e].copy_from_slice(&[0, 0]);
+        table.extend_from_slice(&dimm);
+        table.extend_from_slice(b"DIMM 1\0\0");
+
+        let info = SmbiosInfo::from_structures("3.0".into(), Structures::new(&table));
+        assert_eq!(info.bios_vendor.as_deref(), Some("EFI Development Kit II / OVMF"));
+        assert_eq!(info.bios_date.as_deref(), Some("02/06/2015"));
+        assert_eq!(info.product.as_deref(), Some("Standard PC"));
+        assert_eq!(info.serial_number, None); // Blank
+        assert_eq!(info.memory_devices, vec![MemoryDevice { locator: Some("DIMM 0".into()), size_mb: 16384, speed_mts: Some(2666), manufacturer: None, part_number: None }]);
+        assert_eq!(info.memory_devices[0].to_string(), "Memory: 16384 MB in DIMM 0 at 2666 MT/s");
+        assert_eq!(::json::to_string(&info.memory_devices[0]), r#"{"locator":"DIMM 0","size_mb":16384,"speed_mts":2666,"manufacturer":null,"part_number":null}"#);
+    }
+}
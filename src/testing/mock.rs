@@ -0,0 +1,1063 @@
+// Fake boot and runtime services for host side unit tests.
+//
+// mock::install() builds a system table whose services are implemented in Rust on top of host memory and points
+// the crate's globals (system_table(), image_handle()) at it. Code under test then calls into "firmware" exactly as
+// it would on a real machine.
+//
+// What is faked:
+// - Pages and pool. Backed by the global allocator, so this only works when the "allocator" feature is off (or
+//   under cfg(test) where the crate leaves the allocator to std).
+// - The memory map. A single region of conventional memory. The map key changes on every allocation and free
+//   so ExitBootServices with a stale key fails like it would on real firmware.
+// - The handle database i.e. installing, opening and locating protocols, plus configuration tables.
+// - Variables. Kept in memory and forgotten at the end of the process regardless of attributes.
+// - Events and timers. Time is simulated: it only moves forward in Stall() or when WaitForEvent() would otherwise
+//   block forever waiting for a timer.
+//
+// Consoles aren't faked. ConIn and ConOut are null so don't print from code under test.
+//
+// The state is global and shared by all tests in the process, which run in parallel. So tests shouldn't assume
+// e.g. an empty handle database; install your own protocols on fresh handles and look them up by handle.
+
+use ffi::{
+    boot_services::{
+        EFI_BOOT_SERVICES, EFI_BOOT_SERVICES_SIGNATURE, EFI_BOOT_SERVICES_REVISION,
+        EFI_ALLOCATE_TYPE, EFI_MEMORY_TYPE, EFI_MEMORY_DESCRIPTOR, EFI_MEMORY_WB,
+        EFI_TPL, EFI_TIMER_DELAY, EFI_INTERFACE_TYPE, EFI_LOCATE_SEARCH_TYPE, EFI_EVENT_NOTIFY,
+        EVT_TIMER, EVT_NOTIFY_SIGNAL, EVT_NOTIFY_WAIT,
+    },
+    runtime_services::{EFI_RUNTIME_SERVICES, EFI_RUNTIME_SERVICES_SIGNATURE, EFI_RUNTIME_SERVICES_REVISION, EFI_RESET_TYPE, EFI_VARIABLE_APPEND_WRITE},
+    EFI_SYSTEM_TABLE, EFI_SYSTEM_TABLE_SIGNATURE, EFI_SYSTEM_TABLE_REVISION,
+    EFI_CONFIGURATION_TABLE, EFI_TABLE_HEADER, EFI_TIME, EFI_TIME_CAPABILITIES,
+    device_path::EFI_DEVICE_PATH_PROTOCOL, EFI_PHYSICAL_ADDRESS,
+    EFI_GUID, EFI_HANDLE, EFI_EVENT, EFI_STATUS, UINTN, UINT32, UINT64, CHAR16, BOOLEAN, VOID,
+    EFI_SUCCESS, EFI_INVALID_PARAMETER, EFI_UNSUPPORTED, EFI_BUFFER_TOO_SMALL, EFI_NOT_READY,
+    EFI_OUT_OF_RESOURCES, EFI_NOT_FOUND,
+};
+use pages::PAGE_SIZE;
+use utils::as_slice;
+use init_env;
+use alloc::{alloc::{alloc_zeroed, dealloc, Layout}, boxed::Box, vec::Vec};
+use core::{cell::UnsafeCell, mem, ptr, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+/// Physical address the fake memory map claims conventional memory starts at
+pub const MEMORY_START: u64 = 0x100000;
+/// Size of the fake conventional memory region
+pub const MEMORY_SIZE: u64 = 256 * 1024 * 1024;
+
+const POOL_HEADER_SIZE: usize = 16; // Pool allocations remember their size here. Also keeps them 16 byte aligned
+
+// A spin lock. All we need since nobody holds it for long and there is no std to borrow a Mutex from
+struct Lock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for Lock<T> {}
+
+impl<T> Lock<T> {
+    const fn new(value: T) -> Self {
+        Self { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::sync::atomic::spin_loop_hint();
+        }
+        let result = f(unsafe { &mut *self.value.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+struct Protocol {
+    handle: usize,
+    guid: EFI_GUID,
+    interface: usize,
+}
+
+struct Event {
+    event_type: UINT32,
+    notify: Option<EFI_EVENT_NOTIFY>,
+    context: *const VOID,
+    signaled: bool,
+    deadline: Option<u64>,
+    period: u64,
+}
+
+struct Variable {
+    name: Vec<CHAR16>, // Null-terminated
+    vendor: EFI_GUID,
+    attributes: UINT32,
+    data: Vec<u8>,
+}
+
+struct State {
+    system_table: usize,
+    variables: Vec<Variable>,
+    protocols: Vec<Protocol>,
+    protocol_notifies: Vec<(EFI_GUID, usize)>, // Events to signal when an interface of the protocol is installed
+    config_tables: Vec<EFI_CONFIGURATION_TABLE>,
+    events: Vec<usize>,
+    next_handle: usize,
+    map_key: usize,
+    monotonic_count: u64,
+    now: u64, // Simulated time in 100ns units, which is what timers are specified in
+}
+
+static STATE: Lock<State> = Lock::new(State {
+    system_table: 0,
+    variables: Vec::new(),
+    protocols: Vec::new(),
+    protocol_notifies: Vec::new(),
+    config_tables: Vec::new(),
+    events: Vec::new(),
+    next_handle: 1,
+    map_key: 1,
+    monotonic_count: 0,
+    now: 0,
+});
+
+static INSTALLED: AtomicUsize = AtomicUsize::new(0);
+
+/// Installs the fake firmware and points the crate at it. Safe to call from every test; only the first call does anything
+pub fn install() {
+    if INSTALLED.compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        while INSTALLED.load(Ordering::SeqCst) != 2 {
+            core::sync::atomic::spin_loop_hint();
+        }
+        return;
+    }
+
+    let boot_services = Box::into_raw(Box::new(boot_services()));
+    let runtime_services = Box::into_raw(Box::new(runtime_services()));
+    let system_table = Box::into_raw(Box::new(EFI_SYSTEM_TABLE {
+        Hdr: header(EFI_SYSTEM_TABLE_SIGNATURE, EFI_SYSTEM_TABLE_REVISION as UINT32, mem::size_of::<EFI_SYSTEM_TABLE>()),
+        FirmwareVendor: ptr::null(),
+        FirmwareRevision: 0,
+        ConsoleInHandle: ptr::null(),
+        ConIn: ptr::null(),
+        ConsoleOutHandle: ptr::null(),
+        ConOut: ptr::null(),
+        ConsoleErrorHandle: ptr::null(),
+        StdErr: ptr::null(),
+        RuntimeServices: runtime_services,
+        BootServices: boot_services,
+        NumberOfTableEntries: 0,
+        ConfigurationTable: ptr::null(),
+    }));
+
+    let image_handle = STATE.with(|state| {
+        state.system_table = system_table as usize;
+        new_handle(state)
+    });
+
+    init_env(image_handle, system_table);
+    INSTALLED.store(2, Ordering::SeqCst);
+}
+
+/// A handle that nothing is installed on yet
+pub fn create_handle() -> EFI_HANDLE {
+    STATE.with(new_handle)
+}
+
+/// Current simulated time in 100ns units
+pub fn now() -> u64 {
+    STATE.with(|state| state.now)
+}
+
+/// Moves simulated time forward, firing any timers that expire
+pub fn advance(ticks: u64) {
+    let target = STATE.with(|state| state.now + ticks);
+    advance_to(target);
+}
+
+fn new_handle(state: &mut State) -> EFI_HANDLE {
+    let handle = state.next_handle;
+    state.next_handle += 1;
+    handle as EFI_HANDLE
+}
+
+fn header(signature: UINT64, revision: UINT32, size: usize) -> EFI_TABLE_HEADER {
+    EFI_TABLE_HEADER { Signature: signature, Revision: revision, HeaderSize: size as UINT32, CRC32: 0, Reserved: 0 }
+}
+
+fn boot_services() -> EFI_BOOT_SERVICES {
+    EFI_BOOT_SERVICES {
+        Hdr: header(EFI_BOOT_SERVICES_SIGNATURE, EFI_BOOT_SERVICES_REVISION as UINT32, mem::size_of::<EFI_BOOT_SERVICES>()),
+        RaiseTPL: ptr::null(),
+        RestoreTPL: ptr::null(),
+        AllocatePages: allocate_pages,
+        FreePages: free_pages,
+        GetMemoryMap: get_memory_map,
+        AllocatePool: allocate_pool,
+        FreePool: free_pool,
+        CreateEvent: create_event,
+        SetTimer: set_timer,
+        WaitForEvent: wait_for_event,
+        SignalEvent: signal_event,
+        CloseEvent: close_event,
+        CheckEvent: check_event,
+        InstallProtocolInterface: install_protocol_interface,
+        ReinstallProtocolInterface: ptr::null(),
+        UninstallProtocolInterface: uninstall_protocol_interface,
+        HandleProtocol: ptr::null(),
+        Reserve: ptr::null(),
+        RegisterProtocolNotify: register_protocol_notify,
+        LocateHandle: ptr::null(),
+        LocateDevicePath: locate_device_path,
+        InstallConfigurationTable: install_configuration_table,
+        LoadImage: load_image,
+        StartImage: start_image,
+        Exit: ptr::null(),
+        UnloadImage: ptr::null(),
+        ExitBootServices: exit_boot_services,
+        GetNextMonotonicCount: get_next_monotonic_count,
+        Stall: stall,
+        SetWatchdogTimer: ptr::null(),
+        ConnectController: connect_controller,
+        DisconnectController: disconnect_controller,
+        OpenProtocol: open_protocol,
+        CloseProtocol: close_protocol,
+        OpenProtocolInformation: ptr::null(),
+        ProtocolsPerHandle: protocols_per_handle,
+        LocateHandleBuffer: locate_handle_buffer,
+        LocateProtocol: locate_protocol,
+        InstallMultipleProtocolInterfaces: ptr::null(),
+        UninstallMultipleProtocolInterfaces: ptr::null(),
+        CalculateCrc32: ptr::null(),
+        CopyMem: ptr::null(),
+        SetMem: ptr::null(),
+        CreateEventEx: ptr::null(),
+    }
+}
+
+fn runtime_services() -> EFI_RUNTIME_SERVICES {
+    EFI_RUNTIME_SERVICES {
+        Hdr: header(EFI_RUNTIME_SERVICES_SIGNATURE, EFI_RUNTIME_SERVICES_REVISION as UINT32, mem::size_of::<EFI_RUNTIME_SERVICES>()),
+        GetTime: get_time,
+        SetTime: set_time,
+        GetWakeupTime: ptr::null(),
+        SetWakeupTime: ptr::null(),
+        SetVirtualAddressMap: ptr::null(),
+        ConvertPointer: ptr::null(),
+        GetVariable: get_variable,
+        GetNextVariableName: get_next_variable_name,
+        SetVariable: set_variable,
+        GetNextHighMonotonicCount: get_next_high_monotonic_count,
+        ResetSystem: reset_system,
+        UpdateCapsule: ptr::null(),
+        QueryCapsuleCapabilities: ptr::null(),
+        QueryVariableInfo: query_variable_info,
+    }
+}
+
+// Memory
+
+extern "efiapi" fn allocate_pages(alloc_type: EFI_ALLOCATE_TYPE, _memory_type: EFI_MEMORY_TYPE, pages: UINTN, memory: *mut EFI_PHYSICAL_ADDRESS) -> EFI_STATUS {
+    if memory.is_null() || pages == 0 {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    // Host memory can't be placed at an address of our choosing
+    if let EFI_ALLOCATE_TYPE::AllocateAddress = alloc_type {
+        return EFI_NOT_FOUND;
+    }
+
+    let layout = match Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE) {
+        Ok(layout) => layout,
+        Err(_) => return EFI_OUT_OF_RESOURCES,
+    };
+    let addr = unsafe { alloc_zeroed(layout) };
+    if addr.is_null() {
+        return EFI_OUT_OF_RESOURCES;
+    }
+
+    // For AllocateMaxAddress *memory holds the highest address the caller can accept
+    if let EFI_ALLOCATE_TYPE::AllocateMaxAddress = alloc_type {
+        if addr as u64 + (pages * PAGE_SIZE) as u64 - 1 > unsafe { *memory } {
+            unsafe { dealloc(addr, layout) };
+            return EFI_NOT_FOUND;
+        }
+    }
+
+    unsafe { *memory = addr as EFI_PHYSICAL_ADDRESS };
+    STATE.with(|state| state.map_key += 1);
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn free_pages(memory: EFI_PHYSICAL_ADDRESS, pages: UINTN) -> EFI_STATUS {
+    if memory == 0 || pages == 0 {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    unsafe { dealloc(memory as usize as *mut u8, Layout::from_size_align_unchecked(pages * PAGE_SIZE, PAGE_SIZE)) };
+    STATE.with(|state| state.map_key += 1);
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn allocate_pool(_pool_type: EFI_MEMORY_TYPE, size: UINTN, buffer: *mut *const VOID) -> EFI_STATUS {
+    if buffer.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    match pool_alloc(size) {
+        Some(ptr) => {
+            unsafe { *buffer = ptr as *const VOID };
+            EFI_SUCCESS
+        }
+        None => EFI_OUT_OF_RESOURCES,
+    }
+}
+
+extern "efiapi" fn free_pool(buffer: *const VOID) -> EFI_STATUS {
+    if buffer.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    unsafe {
+        let base = (buffer as *mut u8).sub(POOL_HEADER_SIZE);
+        let size = *(base as *const usize);
+        dealloc(base, Layout::from_size_align_unchecked(size + POOL_HEADER_SIZE, POOL_HEADER_SIZE));
+    }
+    STATE.with(|state| state.map_key += 1);
+    EFI_SUCCESS
+}
+
+fn pool_alloc(size: usize) -> Option<*mut u8> {
+    let layout = Layout::from_size_align(size + POOL_HEADER_SIZE, POOL_HEADER_SIZE).ok()?;
+    unsafe {
+        let base = alloc_zeroed(layout);
+        if base.is_null() {
+            return None;
+        }
+        *(base as *mut usize) = size;
+        STATE.with(|state| state.map_key += 1);
+        Some(base.add(POOL_HEADER_SIZE))
+    }
+}
+
+extern "efiapi" fn get_memory_map(memory_map_size: *mut UINTN, memory_map: *mut EFI_MEMORY_DESCRIPTOR, map_key: *mut UINTN, descriptor_size: *mut UINTN, descriptor_version: *mut UINT32) -> EFI_STATUS {
+    if memory_map_size.is_null() || map_key.is_null() || descriptor_size.is_null() || descriptor_version.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let size = mem::size_of::<EFI_MEMORY_DESCRIPTOR>();
+    unsafe {
+        *descriptor_size = size;
+        *descriptor_version = 1;
+
+        if *memory_map_size < size || memory_map.is_null() {
+            *memory_map_size = size;
+            return EFI_BUFFER_TOO_SMALL;
+        }
+
+        *memory_map = EFI_MEMORY_DESCRIPTOR {
+            Type: EFI_MEMORY_TYPE::EfiConventionalMemory as UINT32,
+            PhysicalStart: MEMORY_START,
+            VirtualStart: 0,
+            NumberOfPages: MEMORY_SIZE / PAGE_SIZE as u64,
+            Attribute: EFI_MEMORY_WB,
+        };
+        *memory_map_size = size;
+        *map_key = STATE.with(|state| state.map_key);
+    }
+
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn exit_boot_services(_image_handle: EFI_HANDLE, map_key: UINTN) -> EFI_STATUS {
+    if STATE.with(|state| state.map_key) == map_key { EFI_SUCCESS } else { EFI_INVALID_PARAMETER }
+}
+
+// Events and time
+
+extern "efiapi" fn create_event(event_type: UINT32, _notify_tpl: EFI_TPL, notify: Option<EFI_EVENT_NOTIFY>, context: *const VOID, event: *mut EFI_EVENT) -> EFI_STATUS {
+    if event.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let new = Box::into_raw(Box::new(Event { event_type, notify, context, signaled: false, deadline: None, period: 0 }));
+    STATE.with(|state| state.events.push(new as usize));
+    unsafe { *event = new as EFI_EVENT };
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn close_event(event: EFI_EVENT) -> EFI_STATUS {
+    let found = STATE.with(|state| {
+        let index = state.events.iter().position(|e| *e == event as usize)?;
+        Some(state.events.remove(index))
+    });
+
+    match found {
+        Some(event) => {
+            unsafe { drop(Box::from_raw(event as *mut Event)) };
+            EFI_SUCCESS
+        }
+        None => EFI_INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn signal_event(event: EFI_EVENT) -> EFI_STATUS {
+    let notify = STATE.with(|state| {
+        let event = lookup_event(state, event)?;
+        Some(signal(event))
+    });
+
+    match notify {
+        Some(notify) => {
+            run_notify(notify);
+            EFI_SUCCESS
+        }
+        None => EFI_INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn check_event(event: EFI_EVENT) -> EFI_STATUS {
+    match poll(event) {
+        Some(true) => EFI_SUCCESS,
+        Some(false) => EFI_NOT_READY,
+        None => EFI_INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn wait_for_event(number_of_events: UINTN, events: *const EFI_EVENT, index: *mut UINTN) -> EFI_STATUS {
+    if number_of_events == 0 || events.is_null() || index.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let events = unsafe { ::core::slice::from_raw_parts(events, number_of_events) };
+    loop {
+        for (i, event) in events.iter().enumerate() {
+            match poll(*event) {
+                Some(true) => {
+                    unsafe { *index = i };
+                    return EFI_SUCCESS;
+                }
+                Some(false) => {}
+                None => {
+                    unsafe { *index = i };
+                    return EFI_INVALID_PARAMETER;
+                }
+            }
+        }
+
+        // Nothing is signaled so skip ahead to when the next timer goes off.
+        // If there are no timers nothing can ever signal these events. Real firmware would hang here
+        match STATE.with(|state| next_deadline(state)) {
+            Some(deadline) => advance_to(deadline),
+            None => return EFI_NOT_READY,
+        }
+    }
+}
+
+extern "efiapi" fn set_timer(event: EFI_EVENT, timer_type: EFI_TIMER_DELAY, trigger_time: UINT64) -> EFI_STATUS {
+    let ok = STATE.with(|state| {
+        let now = state.now;
+        match lookup_event(state, event) {
+            Some(event) if event.event_type & EVT_TIMER != 0 => {
+                match timer_type {
+                    EFI_TIMER_DELAY::TimerCancel => event.deadline = None,
+                    EFI_TIMER_DELAY::TimerRelative => {
+                        event.deadline = Some(now + trigger_time);
+                        event.period = 0;
+                    }
+                    EFI_TIMER_DELAY::TimerPeriodic => {
+                        let period = if trigger_time == 0 { 1 } else { trigger_time }; // Zero means fire on every tick
+                        event.deadline = Some(now + period);
+                        event.period = period;
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    });
+
+    if ok { EFI_SUCCESS } else { EFI_INVALID_PARAMETER }
+}
+
+extern "efiapi" fn stall(microseconds: UINTN) -> EFI_STATUS {
+    advance(microseconds as u64 * 10);
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn get_next_monotonic_count(count: *mut UINT64) -> EFI_STATUS {
+    if count.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    unsafe { *count = STATE.with(|state| { state.monotonic_count += 1; state.monotonic_count }) };
+    EFI_SUCCESS
+}
+
+// Bumps the high half of the count and starts the low half again from zero, like a reboot does
+extern "efiapi" fn get_next_high_monotonic_count(high: *mut UINT32) -> EFI_STATUS {
+    if high.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    unsafe {
+        *high = STATE.with(|state| {
+            let next = (state.monotonic_count >> 32) as UINT32 + 1;
+            state.monotonic_count = (next as u64) << 32;
+            next
+        })
+    };
+    EFI_SUCCESS
+}
+
+type Notify = Option<(EFI_EVENT_NOTIFY, EFI_EVENT, *const VOID)>;
+
+fn lookup_event<'a>(state: &'a mut State, event: EFI_EVENT) -> Option<&'a mut Event> {
+    if state.events.contains(&(event as usize)) {
+        Some(unsafe { &mut *(event as *mut Event) })
+    } else {
+        None
+    }
+}
+
+// Marks the event signaled. Returns the notification function to queue, if any
+// Firmware clears a notify signal event as it queues the notification, so it can be signaled again afterwards
+fn signal(event: &mut Event) -> Notify {
+    let was_signaled = event.signaled;
+    match event.notify {
+        Some(notify) if !was_signaled && event.event_type & EVT_NOTIFY_SIGNAL != 0 => Some((notify, event as *mut Event as EFI_EVENT, event.context)),
+        _ => {
+            event.signaled = true;
+            None
+        }
+    }
+}
+
+// Notification functions run outside the lock since they are free to call back into boot services
+fn run_notify(notify: Notify) {
+    if let Some((notify, event, context)) = notify {
+        notify(event, context);
+    }
+}
+
+// Checks an event and clears it if it was signaled, as CheckEvent and WaitForEvent do.
+// Wait type events get their notification function run first to give them a chance to signal themselves
+fn poll(event: EFI_EVENT) -> Option<bool> {
+    let notify = STATE.with(|state| {
+        let event = lookup_event(state, event)?;
+        if event.event_type & EVT_NOTIFY_SIGNAL != 0 {
+            return None; // Not allowed to wait on these
+        }
+        match event.notify {
+            Some(notify) if !event.signaled && event.event_type & EVT_NOTIFY_WAIT != 0 => Some(Some((notify, event as *mut Event as EFI_EVENT, event.context))),
+            _ => Some(None),
+        }
+    })?;
+    run_notify(notify);
+
+    STATE.with(|state| {
+        let event = lookup_event(state, event)?;
+        let signaled = event.signaled;
+        event.signaled = false;
+        Some(signaled)
+    })
+}
+
+fn next_deadline(state: &State) -> Option<u64> {
+    state.events.iter().filter_map(|e| unsafe { (*(*e as *const Event)).deadline }).min()
+}
+
+fn advance_to(target: u64) {
+    let notifies = STATE.with(|state| {
+        if target > state.now {
+            state.now = target;
+        }
+
+        let now = state.now;
+        let mut notifies = Vec::new();
+        for e in state.events.iter() {
+            let event = unsafe { &mut *(*e as *mut Event) };
+            if let Some(deadline) = event.deadline {
+                if deadline <= now {
+                    // Periodic timers that missed several periods only fire once, like on real firmware
+                    event.deadline = if event.period == 0 { None } else { Some(deadline + ((now - deadline) / event.period + 1) * event.period) };
+                    notifies.push(signal(event));
+                }
+            }
+        }
+        notifies
+    });
+
+    for notify in notifies {
+        run_notify(notify);
+    }
+}
+
+// The handle database
+
+extern "efiapi" fn install_protocol_interface(handle: *mut EFI_HANDLE, protocol: *const EFI_GUID, _interface_type: EFI_INTERFACE_TYPE, interface: *const VOID) -> EFI_STATUS {
+    if handle.is_null() || protocol.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let notifies = STATE.with(|state| unsafe {
+        if (*handle).is_null() {
+            *handle = new_handle(state);
+        }
+
+        let guid = *protocol;
+        if state.protocols.iter().any(|p| p.handle == *handle as usize && p.guid == guid) {
+            return None;
+        }
+
+        state.protocols.push(Protocol { handle: *handle as usize, guid, interface: interface as usize });
+        Some(state.protocol_notifies.iter().filter(|n| n.0 == guid).map(|n| n.1).collect::<Vec<_>>())
+    });
+
+    match notifies {
+        Some(notifies) => {
+            for event in notifies {
+                signal_event(event as EFI_EVENT);
+            }
+            EFI_SUCCESS
+        }
+        None => EFI_INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn register_protocol_notify(protocol: *const EFI_GUID, event: EFI_EVENT, registration: *mut *const VOID) -> EFI_STATUS {
+    if protocol.is_null() || event.is_null() || registration.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    STATE.with(|state| {
+        let events = &state.events;
+        state.protocol_notifies.retain(|n| events.contains(&n.1)); // Forget closed events
+        state.protocol_notifies.push((unsafe { *protocol }, event as usize));
+    });
+    unsafe { *registration = event as *const VOID };
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn uninstall_protocol_interface(handle: EFI_HANDLE, protocol: *const EFI_GUID, interface: *const VOID) -> EFI_STATUS {
+    if protocol.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    STATE.with(|state| {
+        let guid = unsafe { *protocol };
+        match state.protocols.iter().position(|p| p.handle == handle as usize && p.guid == guid && p.interface == interface as usize) {
+            Some(index) => {
+                state.protocols.remove(index);
+                EFI_SUCCESS
+            }
+            None => EFI_NOT_FOUND,
+        }
+    })
+}
+
+extern "efiapi" fn open_protocol(handle: EFI_HANDLE, protocol: *const EFI_GUID, interface: *mut *const VOID, _agent_handle: EFI_HANDLE, _controller_handle: EFI_HANDLE, _attributes: UINT32) -> EFI_STATUS {
+    if protocol.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    match find_interface(Some(handle), unsafe { &*protocol }) {
+        Some(found) => {
+            // Interface is allowed to be null for EFI_OPEN_PROTOCOL_TEST_PROTOCOL
+            if !interface.is_null() {
+                unsafe { *interface = found };
+            }
+            EFI_SUCCESS
+        }
+        None => EFI_UNSUPPORTED,
+    }
+}
+
+extern "efiapi" fn close_protocol(handle: EFI_HANDLE, protocol: *const EFI_GUID, _agent_handle: EFI_HANDLE, _controller_handle: EFI_HANDLE) -> EFI_STATUS {
+    if protocol.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    match find_interface(Some(handle), unsafe { &*protocol }) {
+        Some(_) => EFI_SUCCESS,
+        None => EFI_NOT_FOUND,
+    }
+}
+
+extern "efiapi" fn locate_protocol(protocol: *const EFI_GUID, _registration: *const VOID, interface: *mut *const VOID) -> EFI_STATUS {
+    if protocol.is_null() || interface.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    match find_interface(None, unsafe { &*protocol }) {
+        Some(found) => {
+            unsafe { *interface = found };
+            EFI_SUCCESS
+        }
+        None => EFI_NOT_FOUND,
+    }
+}
+
+extern "efiapi" fn locate_handle_buffer(search_type: EFI_LOCATE_SEARCH_TYPE, protocol: *const EFI_GUID, _search_key: *const VOID, no_handles: *mut UINTN, buffer: *mut *const EFI_HANDLE) -> EFI_STATUS {
+    if no_handles.is_null() || buffer.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let mut handles = STATE.with(|state| -> Option<Vec<usize>> {
+        let handles = match search_type {
+            EFI_LOCATE_SEARCH_TYPE::AllHandles => state.protocols.iter().map(|p| p.handle).collect(),
+            EFI_LOCATE_SEARCH_TYPE::ByProtocol if !protocol.is_null() => {
+                let guid = unsafe { *protocol };
+                state.protocols.iter().filter(|p| p.guid == guid).map(|p| p.handle).collect()
+            }
+            _ => return None,
+        };
+        Some(handles)
+    });
+
+    let handles = match handles.as_mut() {
+        Some(handles) => handles,
+        None => return EFI_INVALID_PARAMETER,
+    };
+    handles.sort();
+    handles.dedup();
+    if handles.is_empty() {
+        return EFI_NOT_FOUND;
+    }
+
+    // The caller frees the buffer with FreePool so it has to come from the pool
+    let array = match pool_alloc(handles.len() * mem::size_of::<EFI_HANDLE>()) {
+        Some(array) => array as *mut EFI_HANDLE,
+        None => return EFI_OUT_OF_RESOURCES,
+    };
+    unsafe {
+        for (i, handle) in handles.iter().enumerate() {
+            *array.add(i) = *handle as EFI_HANDLE;
+        }
+        *no_handles = handles.len();
+        *buffer = array;
+    }
+
+    EFI_SUCCESS
+}
+
+extern "efiapi" fn protocols_per_handle(handle: EFI_HANDLE, protocol_buffer: *mut *const *const EFI_GUID, protocol_buffer_count: *mut UINTN) -> EFI_STATUS {
+    if protocol_buffer.is_null() || protocol_buffer_count.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let guids: Vec<EFI_GUID> = STATE.with(|state| state.protocols.iter().filter(|p| p.handle == handle as usize).map(|p| p.guid).collect());
+    if guids.is_empty() {
+        return EFI_INVALID_PARAMETER; // Not a handle
+    }
+
+    // The caller frees the array with FreePool but not the GUIDs, so they go in the same allocation after it
+    let pointers_size = guids.len() * mem::size_of::<*const EFI_GUID>();
+    let array = match pool_alloc(pointers_size + guids.len() * mem::size_of::<EFI_GUID>()) {
+        Some(array) => array,
+        None => return EFI_OUT_OF_RESOURCES,
+    };
+    unsafe {
+        let pointers = array as *mut *const EFI_GUID;
+        let copies = array.add(pointers_size) as *mut EFI_GUID;
+        for (i, guid) in guids.iter().enumerate() {
+            *copies.add(i) = *guid;
+            *pointers.add(i) = copies.add(i);
+        }
+        *protocol_buffer_count = guids.len();
+        *protocol_buffer = pointers;
+    }
+
+    EFI_SUCCESS
+}
+
+// There are no drivers, so nothing ever gets connected but there's never anything to disconnect either
+extern "efiapi" fn connect_controller(controller_handle: EFI_HANDLE, _driver_image_handle: *const EFI_HANDLE, _remaining_device_path: *const EFI_DEVICE_PATH_PROTOCOL, _recursive: BOOLEAN) -> EFI_STATUS {
+    if !STATE.with(|state| state.protocols.iter().any(|p| p.handle == controller_handle as usize)) {
+        return EFI_INVALID_PARAMETER;
+    }
+    EFI_NOT_FOUND
+}
+
+extern "efiapi" fn disconnect_controller(controller_handle: EFI_HANDLE, _driver_image_handle: EFI_HANDLE, _child_handle: EFI_HANDLE) -> EFI_STATUS {
+    if !STATE.with(|state| state.protocols.iter().any(|p| p.handle == controller_handle as usize)) {
+        return EFI_INVALID_PARAMETER;
+    }
+    EFI_SUCCESS
+}
+
+// Installed protocols don't come with device paths here, so there's never a match
+extern "efiapi" fn locate_device_path(protocol: *const EFI_GUID, device_path: *mut *const EFI_DEVICE_PATH_PROTOCOL, device: *mut EFI_HANDLE) -> EFI_STATUS {
+    if protocol.is_null() || device_path.is_null() || device.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+    EFI_NOT_FOUND
+}
+
+fn find_interface(handle: Option<EFI_HANDLE>, guid: &EFI_GUID) -> Option<*const VOID> {
+    STATE.with(|state| {
+        state.protocols.iter()
+            .find(|p| p.guid == *guid && handle.map_or(true, |h| p.handle == h as usize))
+            .map(|p| p.interface as *const VOID)
+    })
+}
+
+extern "efiapi" fn install_configuration_table(guid: *const EFI_GUID, table: *const VOID) -> EFI_STATUS {
+    if guid.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    STATE.with(|state| {
+        let guid = unsafe { *guid };
+        let existing = state.config_tables.iter().position(|t| t.VendorGuid == guid);
+        match (existing, table.is_null()) {
+            (Some(index), true) => { state.config_tables.remove(index); }
+            (Some(index), false) => state.config_tables[index].VendorTable = table as *const (),
+            (None, true) => return EFI_NOT_FOUND,
+            (None, false) => state.config_tables.push(EFI_CONFIGURATION_TABLE { VendorGuid: guid, VendorTable: table as *const () }),
+        }
+
+        let system_table = state.system_table as *mut EFI_SYSTEM_TABLE;
+        unsafe {
+            (*system_table).NumberOfTableEntries = state.config_tables.len();
+            (*system_table).ConfigurationTable = state.config_tables.as_ptr();
+        }
+        EFI_SUCCESS
+    })
+}
+
+// Images. There is no loader behind the mock
+
+extern "efiapi" fn load_image(_boot_policy: BOOLEAN, _parent_image_handle: EFI_HANDLE, _device_path: *const EFI_DEVICE_PATH_PROTOCOL, _source_buffer: *const VOID, _source_size: UINTN, _image_handle: *mut EFI_HANDLE) -> EFI_STATUS {
+    EFI_UNSUPPORTED
+}
+
+extern "efiapi" fn start_image(_image_handle: EFI_HANDLE, _exit_data_size: *mut UINTN, _exit_data: *mut *const CHAR16) -> EFI_STATUS {
+    EFI_UNSUPPORTED
+}
+
+// Runtime services
+
+extern "efiapi" fn get_time(time: *mut EFI_TIME, _capabilities: *mut EFI_TIME_CAPABILITIES) -> EFI_STATUS {
+    if time.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    // The simulated clock starts at midnight on 1 Jan 2000
+    let seconds = now() / 10_000_000;
+    unsafe {
+        *time = EFI_TIME {
+            Year: 2000,
+            Month: 1,
+            Day: 1 + (seconds / 86400) as u8 % 28,
+            Hour: (seconds / 3600 % 24) as u8,
+            Minute: (seconds / 60 % 60) as u8,
+            Second: (seconds % 60) as u8,
+            Pad1: 0,
+            Nanosecond: (now() % 10_000_000 * 100) as u32,
+            TimeZone: 2047, // EFI_UNSPECIFIED_TIMEZONE
+            Daylight: 0,
+            Pad2: 0,
+        };
+    }
+
+    EFI_SUCCESS
+}
+
+// The simulated clock runs from boot, so there's nothing to set
+extern "efiapi" fn set_time(_time: *const EFI_TIME) -> EFI_STATUS {
+    EFI_UNSUPPORTED
+}
+
+extern "efiapi" fn get_variable(variable_name: *const CHAR16, vendor_guid: *const EFI_GUID, attributes: *mut UINT32, data_size: *mut UINTN, data: *mut VOID) -> EFI_STATUS {
+    if variable_name.is_null() || vendor_guid.is_null() || data_size.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let name = unsafe { ucs2_with_terminator(variable_name) };
+    STATE.with(|state| unsafe {
+        let variable = match state.variables.iter().find(|v| v.name == name && v.vendor == *vendor_guid) {
+            Some(variable) => variable,
+            None => return EFI_NOT_FOUND,
+        };
+
+        if !attributes.is_null() {
+            *attributes = variable.attributes;
+        }
+
+        let len = variable.data.len();
+        if *data_size < len || data.is_null() {
+            *data_size = len;
+            return EFI_BUFFER_TOO_SMALL;
+        }
+
+        ptr::copy_nonoverlapping(variable.data.as_ptr(), data as *mut u8, len);
+        *data_size = len;
+        EFI_SUCCESS
+    })
+}
+
+extern "efiapi" fn get_next_variable_name(variable_name_size: *mut UINTN, variable_name: *mut CHAR16, vendor_guid: *mut EFI_GUID) -> EFI_STATUS {
+    if variable_name_size.is_null() || variable_name.is_null() || vendor_guid.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let name = unsafe { ucs2_with_terminator(variable_name) };
+    STATE.with(|state| unsafe {
+        // An empty name starts the enumeration. Otherwise carry on after the variable named
+        let next = if name.len() == 1 {
+            0
+        } else {
+            match state.variables.iter().position(|v| v.name == name && v.vendor == *vendor_guid) {
+                Some(index) => index + 1,
+                None => return EFI_INVALID_PARAMETER,
+            }
+        };
+
+        let variable = match state.variables.get(next) {
+            Some(variable) => variable,
+            None => return EFI_NOT_FOUND,
+        };
+
+        let size = variable.name.len() * mem::size_of::<CHAR16>();
+        if *variable_name_size < size {
+            *variable_name_size = size;
+            return EFI_BUFFER_TOO_SMALL;
+        }
+
+        ptr::copy_nonoverlapping(variable.name.as_ptr(), variable_name, variable.name.len());
+        *vendor_guid = variable.vendor;
+        *variable_name_size = size;
+        EFI_SUCCESS
+    })
+}
+
+extern "efiapi" fn set_variable(variable_name: *const CHAR16, vendor_guid: *const EFI_GUID, attributes: UINT32, data_size: UINTN, data: *const VOID) -> EFI_STATUS {
+    if variable_name.is_null() || vendor_guid.is_null() || (data_size != 0 && data.is_null()) {
+        return EFI_INVALID_PARAMETER;
+    }
+
+    let name = unsafe { ucs2_with_terminator(variable_name) };
+    let vendor = unsafe { *vendor_guid };
+    let new_data = unsafe { ::core::slice::from_raw_parts(data as *const u8, data_size) };
+    STATE.with(|state| {
+        let existing = state.variables.iter().position(|v| v.name == name && v.vendor == vendor);
+        if attributes & EFI_VARIABLE_APPEND_WRITE != 0 {
+            return match existing {
+                Some(index) => {
+                    state.variables[index].data.extend_from_slice(new_data);
+                    EFI_SUCCESS
+                }
+                None if data_size == 0 => EFI_SUCCESS,
+                None => {
+                    state.variables.push(Variable { name, vendor, attributes: attributes & !EFI_VARIABLE_APPEND_WRITE, data: new_data.to_vec() });
+                    EFI_SUCCESS
+                }
+            };
+        }
+
+        if variable_size(&name, new_data) > MAX_VARIABLE_SIZE {
+            return EFI_OUT_OF_RESOURCES;
+        }
+
+        // Zero attributes or no data means delete
+        match existing {
+            Some(index) if data_size == 0 || attributes == 0 => { state.variables.remove(index); }
+            Some(index) => {
+                state.variables[index].attributes = attributes;
+                state.variables[index].data = new_data.to_vec();
+            }
+            None if data_size == 0 || attributes == 0 => return EFI_NOT_FOUND,
+            None => state.variables.push(Variable { name, vendor, attributes, data: new_data.to_vec() }),
+        }
+        EFI_SUCCESS
+    })
+}
+
+// Storage is only nominal, shared as it is by every test, but a variable bigger than MAX_VARIABLE_SIZE is refused
+const VARIABLE_STORAGE_SIZE: u64 = 1024 * 1024;
+const MAX_VARIABLE_SIZE: u64 = 32 * 1024;
+
+extern "efiapi" fn query_variable_info(attributes: UINT32, maximum: *mut UINT64, remaining: *mut UINT64, max_variable_size: *mut UINT64) -> EFI_STATUS {
+    if attributes == 0 || maximum.is_null() || remaining.is_null() || max_variable_size.is_null() {
+        return EFI_INVALID_PARAMETER;
+    }
+    let used: u64 = STATE.with(|state| state.variables.iter().map(|v| variable_size(&v.name, &v.data)).sum());
+    unsafe {
+        *maximum = VARIABLE_STORAGE_SIZE;
+        *remaining = VARIABLE_STORAGE_SIZE.saturating_sub(used);
+        *max_variable_size = MAX_VARIABLE_SIZE;
+    }
+    EFI_SUCCESS
+}
+
+fn variable_size(name: &[CHAR16], data: &[u8]) -> u64 {
+    (name.len() * mem::size_of::<CHAR16>() + data.len()) as u64
+}
+
+unsafe fn ucs2_with_terminator(s: *const CHAR16) -> Vec<CHAR16> {
+    let mut name = as_slice(s).to_vec();
+    name.push(0);
+    name
+}
+
+extern "efiapi" fn reset_system(reset_type: EFI_RESET_TYPE, reset_status: EFI_STATUS, _data_size: UINTN, _reset_data: *const VOID) {
+    panic!("ResetSystem({:?}, 0x{:X}) called under the mock firmware", reset_type, reset_status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pages::Pages;
+    use memory::MemoryMap;
+
+    const TEST_GUID: EFI_GUID = EFI_GUID(0x2b0e2d1a, 0x7f43, 0x4c8e, [0x9d, 0x52, 0x1e, 0x6a, 0x33, 0x0b, 0xc4, 0x17]);
+
+    #[test]
+    fn pages_round_trip() {
+        install();
+        let mut pages = Pages::allocate(2).unwrap();
+        assert_eq!(pages.len(), 2 * PAGE_SIZE);
+        assert_eq!(pages.addr() % PAGE_SIZE as u64, 0);
+        pages.as_mut_slice()[PAGE_SIZE] = 0xaa;
+        assert_eq!(pages.as_slice()[PAGE_SIZE], 0xaa);
+    }
+
+    #[test]
+    fn stale_map_key_is_rejected() {
+        install();
+        let map = MemoryMap::get().unwrap();
+        assert_eq!(map.len(), 1);
+        let _pages = Pages::allocate(1).unwrap();
+        assert_eq!(exit_boot_services(ptr::null(), map.key()), EFI_INVALID_PARAMETER);
+    }
+
+    #[test]
+    fn protocols_are_found_on_their_handle() {
+        install();
+        let interface = 0x1234usize as *const VOID;
+        let mut handle = create_handle();
+        assert_eq!(install_protocol_interface(&mut handle, &TEST_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, interface), EFI_SUCCESS);
+
+        let mut found = ptr::null();
+        assert_eq!(open_protocol(handle, &TEST_GUID, &mut found, ptr::null(), ptr::null(), 0), EFI_SUCCESS);
+        assert_eq!(found, interface);
+        assert_eq!(open_protocol(create_handle(), &TEST_GUID, &mut found, ptr::null(), ptr::null(), 0), EFI_UNSUPPORTED);
+
+        assert_eq!(uninstall_protocol_interface(handle, &TEST_GUID, interface), EFI_SUCCESS);
+        assert_eq!(open_protocol(handle, &TEST_GUID, &mut found, ptr::null(), ptr::null(), 0), EFI_UNSUPPORTED);
+    }
+
+    #[test]
+    fn waiting_on_a_timer_advances_time() {
+        install();
+        let mut event = ptr::null();
+        assert_eq!(create_event(EVT_TIMER, 0, None, ptr::null(), &mut event), EFI_SUCCESS);
+        let start = now();
+        assert_eq!(set_timer(event, EFI_TIMER_DELAY::TimerRelative, 1000), EFI_SUCCESS);
+        assert_eq!(check_event(event), EFI_NOT_READY);
+
+        let mut index = 99;
+        assert_eq!(wait_for_event(1, &event, &mut index), EFI_SUCCESS);
+        assert_eq!(index, 0);
+        assert!(now() >= start + 1000);
+        assert_eq!(close_event(event), EFI_SUCCESS);
+    }
+}
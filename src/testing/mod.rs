@@ -0,0 +1,108 @@
+// Test harness. There are two halves to it:
+//
+// - The freestanding half (feature "testing") runs tests inside real firmware e.g. QEMU/OVMF. Tests are marked with
+//   #[efi::test] and collected by rustc's custom test frameworks. The test binary sets it up like so:
+//
+//       #![feature(custom_test_frameworks)]
+//       #![test_runner(efi::testing::runner)]
+//       #![reexport_test_harness_main = "test_main"]
+//
+//   and calls test_main() from efi_main after init_env(). Its #[panic_handler] should call
+//   efi::testing::panic_handler(). Results go to the console, which OVMF mirrors to serial, and the run ends by
+//   shutting the machine down with a status saying whether everything passed.
+//
+// - The std-hosted half (the mock module) is a fake set of boot services backed by host memory. It lets ordinary
+//   #[test]s on the host exercise code that calls into firmware (pages, protocols, events, the memory map etc.)
+
+pub mod mock;
+
+use ffi::{runtime_services::EFI_RESET_TYPE, EFI_STATUS, EFI_SUCCESS, EFI_ABORTED};
+use services::RuntimeServices;
+use backtrace::Backtrace;
+use system_table;
+use core::{fmt::Debug, panic::PanicInfo};
+
+/// A test collected by #[efi::test]. You don't normally construct these yourself
+pub struct Test {
+    pub name: &'static str,
+    pub ignore: bool,
+    pub run: fn() -> bool,
+}
+
+/// What tests are allowed to return
+pub trait TestResult {
+    /// Whether the test passed. Reports the reason if it didn't
+    fn passed(self) -> bool;
+}
+
+impl TestResult for () {
+    fn passed(self) -> bool {
+        true
+    }
+}
+
+impl<T, E: Debug> TestResult for Result<T, E> {
+    fn passed(self) -> bool {
+        match self {
+            Ok(_) => true,
+            Err(e) => {
+                println!("error: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+// Name of the test currently running so that the panic handler can tell which one it was
+static mut CURRENT_TEST: Option<&'static str> = None;
+
+/// The test runner. Install it with #![test_runner(efi::testing::runner)]
+pub fn runner(tests: &[&Test]) -> ! {
+    println!("\nrunning {} tests", tests.len());
+
+    let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+    for test in tests {
+        if test.ignore {
+            println!("test {} ... ignored", test.name);
+            ignored += 1;
+            continue;
+        }
+
+        print!("test {} ... ", test.name);
+        unsafe { CURRENT_TEST = Some(test.name) };
+        if (test.run)() {
+            println!("ok");
+            passed += 1;
+        } else {
+            println!("FAILED");
+            failed += 1;
+        }
+        unsafe { CURRENT_TEST = None };
+    }
+
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    println!("\ntest result: {}. {} passed; {} failed; {} ignored\n", result, passed, failed, ignored);
+
+    exit(if failed == 0 { EFI_SUCCESS } else { EFI_ABORTED })
+}
+
+/// Call this from the test binary's #[panic_handler].
+/// There is no unwinding in firmware so a panicking test ends the whole run as a failure
+pub fn panic_handler(info: &PanicInfo) -> ! {
+    match unsafe { CURRENT_TEST } {
+        Some(name) => println!("FAILED\n\ntest {} panicked: {}\n", name, info),
+        None => println!("\npanicked outside of a test: {}\n", info),
+    }
+    println!("{}", Backtrace::capture());
+
+    exit(EFI_ABORTED)
+}
+
+/// Ends the test run by shutting down the machine with the given status. QEMU doesn't pass that on to the host, so
+/// with the qemu feature the result goes out through its isa-debug-exit device first (see the qemu module)
+pub fn exit(status: EFI_STATUS) -> ! {
+    #[cfg(feature = "qemu")]
+    ::qemu::debug_exit(if status == EFI_SUCCESS { ::qemu::EXIT_SUCCESS } else { ::qemu::EXIT_FAILURE });
+    let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+    rs.reset(EFI_RESET_TYPE::EfiResetShutdown, status)
+}
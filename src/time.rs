@@ -1,10 +1,240 @@
-use ffi::UINTN;
-use core::time::Duration;
-use {system_table, Result};
+use ffi::{
+    timestamp::{EFI_TIMESTAMP_PROTOCOL, EFI_TIMESTAMP_PROTOCOL_GUID, EFI_TIMESTAMP_PROPERTIES},
+    EFI_TIME,
+    EFI_UNSPECIFIED_TIMEZONE,
+    UINTN,
+};
+use services::RuntimeServices;
+use core::{mem, ptr, time::Duration};
+use {system_table, Result, EfiErrorKind};
 
 pub fn sleep(dur: Duration) -> Result<()> {
     let bs = system_table().BootServices;
     let micros = (dur.as_secs() * 1000_000 + dur.subsec_micros() as u64) as UINTN; // TODO: this cast can be lossy. fix it
     unsafe { ret_on_err!(((*bs).Stall)(micros)); }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// A reading of the platform's free running counter via EFI_TIMESTAMP_PROTOCOL.
+/// On most platforms that's the same counter firmware uses for its performance records (the TSC on x86, the generic
+/// timer on ARM), which starts at reset, so since_reset() lines up with the times in the FPDT
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(Duration);
+
+static mut TIMESTAMP: *const EFI_TIMESTAMP_PROTOCOL = ptr::null();
+static mut PROPERTIES: EFI_TIMESTAMP_PROPERTIES = EFI_TIMESTAMP_PROPERTIES { Frequency: 0, EndValue: 0 };
+
+impl Timestamp {
+    /// NotFound if the firmware has no timestamp protocol
+    pub fn now() -> Result<Self> {
+        let (protocol, properties) = timestamp_protocol()?;
+        let ticks = unsafe { ((*protocol).GetTimestamp)() };
+        Ok(Timestamp(ticks_to_duration(ticks, properties.Frequency)))
+    }
+
+    pub fn from_since_reset(since_reset: Duration) -> Self {
+        Timestamp(since_reset)
+    }
+
+    pub fn since_reset(&self) -> Duration {
+        self.0
+    }
+
+    /// Zero if `earlier` is actually later (e.g. after the counter wrapped)
+    pub fn duration_since(&self, earlier: Timestamp) -> Duration {
+        self.0.checked_sub(earlier.0).unwrap_or_default()
+    }
+
+    pub fn elapsed(&self) -> Result<Duration> {
+        Ok(Self::now()?.duration_since(*self))
+    }
+}
+
+fn ticks_to_duration(ticks: u64, frequency: u64) -> Duration {
+    let secs = ticks / frequency;
+    let nanos = (ticks % frequency) as u128 * 1_000_000_000 / frequency as u128;
+    Duration::new(secs, nanos as u32)
+}
+
+// Located once, along with the frequency which doesn't change
+fn timestamp_protocol() -> Result<(*const EFI_TIMESTAMP_PROTOCOL, EFI_TIMESTAMP_PROPERTIES)> {
+    let protocol = unsafe { TIMESTAMP };
+    if !protocol.is_null() {
+        return Ok((protocol, unsafe { PROPERTIES }));
+    }
+
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_TIMESTAMP_PROTOCOL = ptr::null();
+    let mut properties = EFI_TIMESTAMP_PROPERTIES::default();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_TIMESTAMP_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+        if protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        ret_on_err!(((*protocol).GetProperties)(&mut properties));
+    }
+    if properties.Frequency == 0 {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+
+    unsafe {
+        TIMESTAMP = protocol;
+        PROPERTIES = properties;
+    }
+    Ok((protocol, properties))
+}
+
+/// Wall clock time as the time since 1970-01-01 00:00:00 UTC, which is what NTP servers, certificates and the like are
+/// compared in. Times before 1970 can't be represented
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnixTime(Duration);
+
+const SECS_PER_DAY: i64 = 86400;
+
+impl UnixTime {
+    /// The real-time clock's time
+    pub fn now() -> Result<Self> {
+        let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+        Self::from_efi_time(&rs.get_time()?)
+    }
+
+    pub fn from_since_epoch(since_epoch: Duration) -> Self {
+        UnixTime(since_epoch)
+    }
+
+    pub fn since_epoch(&self) -> Duration {
+        self.0
+    }
+
+    /// InvalidParameter if any field is out of range or it's before 1970. A time zone is minutes ahead of UTC, as in
+    /// UEFI 2.7 on (earlier specs had the sign the other way round), and an unspecified one is taken to be UTC
+    pub fn from_efi_time(time: &EFI_TIME) -> Result<Self> {
+        if time.Month < 1 || time.Month > 12 || time.Day < 1 || time.Day > days_in_month(time.Year, time.Month) ||
+            time.Hour > 23 || time.Minute > 59 || time.Second > 59 || time.Nanosecond > 999_999_999 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+
+        let mut secs = days_from_civil(time.Year as i64, time.Month, time.Day) * SECS_PER_DAY +
+            time.Hour as i64 * 3600 + time.Minute as i64 * 60 + time.Second as i64;
+        if time.TimeZone as UINTN != EFI_UNSPECIFIED_TIMEZONE {
+            secs -= time.TimeZone as i64 * 60;
+        }
+        if secs < 0 {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        Ok(UnixTime(Duration::new(secs as u64, time.Nanosecond)))
+    }
+
+    /// In the time zone `time_zone` minutes ahead of UTC, or in UTC when that's EFI_UNSPECIFIED_TIMEZONE. `daylight`
+    /// is only carried over since the time zone already says where the clock is
+    pub fn to_efi_time(&self, time_zone: i16, daylight: u8) -> EFI_TIME {
+        let mut secs = self.0.as_secs() as i64;
+        if time_zone as UINTN != EFI_UNSPECIFIED_TIMEZONE {
+            secs += time_zone as i64 * 60;
+        }
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let secs_of_day = secs.rem_euclid(SECS_PER_DAY);
+        EFI_TIME {
+            Year: year as u16,
+            Month: month,
+            Day: day,
+            Hour: (secs_of_day / 3600) as u8,
+            Minute: (secs_of_day / 60 % 60) as u8,
+            Second: (secs_of_day % 60) as u8,
+            Pad1: 0,
+            Nanosecond: self.0.subsec_nanos(),
+            TimeZone: time_zone,
+            Daylight: daylight,
+            Pad2: 0,
+        }
+    }
+
+    pub fn checked_add(&self, dur: Duration) -> Option<Self> {
+        self.0.checked_add(dur).map(UnixTime)
+    }
+
+    pub fn checked_sub(&self, dur: Duration) -> Option<Self> {
+        self.0.checked_sub(dur).map(UnixTime)
+    }
+
+    /// Zero if `earlier` is actually later
+    pub fn duration_since(&self, earlier: UnixTime) -> Duration {
+        self.0.checked_sub(earlier.0).unwrap_or_default()
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, and back. These are Howard Hinnant's algorithms
+// (http://howardhinnant.github.io/date_algorithms.html), which count in 400 year eras starting on 1 March so that
+// leap days come at the end of the year
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (if month <= 2 { year_of_era + era * 400 + 1 } else { year_of_era + era * 400 }, month as u8, day as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn efi_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8, time_zone: i16) -> EFI_TIME {
+        EFI_TIME { Year: year, Month: month, Day: day, Hour: hour, Minute: minute, Second: second, TimeZone: time_zone, ..EFI_TIME::zero() }
+    }
+
+    #[test]
+    fn converts_efi_time_to_and_from_unix_time() {
+        let utc = EFI_UNSPECIFIED_TIMEZONE as i16;
+        let cases = [
+            (efi_time(1970, 1, 1, 0, 0, 0, utc), 0),
+            (efi_time(2000, 2, 29, 12, 0, 0, utc), 951_825_600),
+            (efi_time(2026, 10, 14, 23, 59, 59, utc), 1_792_022_399),
+            (efi_time(2038, 1, 19, 3, 14, 8, utc), 1 << 31),
+            (efi_time(2100, 3, 1, 0, 0, 0, utc), 4_107_542_400),
+        ];
+        for &(ref time, secs) in cases.iter() {
+            let unix = UnixTime::from_efi_time(time).unwrap();
+            assert_eq!(unix.since_epoch(), Duration::from_secs(secs));
+            let back = unix.to_efi_time(utc, 0);
+            assert_eq!((back.Year, back.Month, back.Day, back.Hour, back.Minute, back.Second),
+                (time.Year, time.Month, time.Day, time.Hour, time.Minute, time.Second));
+        }
+
+        // An hour ahead of UTC is an hour earlier in Unix time, and back again in the same zone
+        let cet = UnixTime::from_efi_time(&efi_time(2026, 1, 1, 1, 0, 0, 60)).unwrap();
+        assert_eq!(cet, UnixTime::from_efi_time(&efi_time(2026, 1, 1, 0, 0, 0, utc)).unwrap());
+        assert_eq!(cet.to_efi_time(60, 0).Hour, 1);
+        assert_eq!(cet.to_efi_time(-300, 0).Day, 31);
+
+        assert!(UnixTime::from_efi_time(&efi_time(2025, 2, 29, 0, 0, 0, utc)).is_err());
+        assert!(UnixTime::from_efi_time(&efi_time(1969, 12, 31, 23, 59, 59, utc)).is_err());
+        assert!(UnixTime::from_efi_time(&efi_time(2026, 13, 1, 0, 0, 0, utc)).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+// Tracing of the calls the crate makes into firmware (feature "trace-ffi").
+//
+// When something fails deep inside, all that comes back is the status, e.g. DeviceError, and not which of the dozen
+// boot services and protocol calls behind the operation returned it. With tracing on, every call the crate checks the
+// status of is recorded: the function, the handle or event it was called on if the first argument is one, the status,
+// how long it took (if the firmware has a timestamp protocol) and where in the crate it was made. The last so many
+// calls are kept in a ring buffer to look at or dump after the fact, and each can also be passed to a logger as it
+// happens, e.g. one writing to the serial port or an NvramLog.
+//
+// Calls made by the tracing itself, and by the logger, aren't traced. Neither is anything once boot services are gone.
+
+use ffi::{EFI_HANDLE, EFI_STATUS, EFI_SUCCESS, IsError};
+use services::boot_services_exited;
+use time::Timestamp;
+use utils::TryLock;
+use io::{self, Write};
+use EfiErrorKind;
+use alloc::vec::Vec;
+use core::{fmt, time::Duration, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+const DEFAULT_CAPACITY: usize = 256;
+
+/// One call into firmware
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// The function pointer called, as written, e.g. `(*bs).OpenProtocol`
+    pub callee: &'static str,
+    pub handle: Option<usize>,
+    pub status: EFI_STATUS,
+    /// None if the firmware can't tell the time
+    pub duration: Option<Duration>,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+impl Call {
+    /// Just the function, e.g. `OpenProtocol`
+    pub fn function(&self) -> &'static str {
+        match self.callee.rfind('.') {
+            Some(i) => &self.callee[i + 1..],
+            None => self.callee,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        IsError(self.status)
+    }
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.function())?;
+        if let Some(handle) = self.handle {
+            write!(f, "({:#x})", handle)?;
+        }
+        if self.status == EFI_SUCCESS {
+            write!(f, " -> Success")?;
+        } else if self.is_error() {
+            write!(f, " -> {:?}", EfiErrorKind::from(self.status))?;
+        } else {
+            write!(f, " -> warning {:#x}", self.status)?;
+        }
+        if let Some(duration) = self.duration {
+            write!(f, " in {}us", duration.as_micros())?;
+        }
+        write!(f, " at {}:{}", self.file, self.line)
+    }
+}
+
+// A Vec rather than a VecDeque since it has to be made in a static. Once it's full the oldest call is at `head` and
+// each new one replaces it
+struct Ring {
+    calls: Vec<Call>,
+    head: usize,
+    capacity: usize,
+}
+
+impl Ring {
+    fn oldest_first(&self) -> Vec<Call> {
+        self.calls[self.head..].iter().chain(self.calls[..self.head].iter()).cloned().collect()
+    }
+
+    fn push(&mut self, call: Call) {
+        if self.calls.len() < self.capacity {
+            self.calls.push(call);
+        } else {
+            self.calls[self.head] = call;
+            self.head = (self.head + 1) % self.calls.len();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.calls.clear();
+        self.head = 0;
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        let mut calls = self.oldest_first();
+        let excess = calls.len().saturating_sub(capacity);
+        calls.drain(..excess);
+        self.calls = calls;
+        self.head = 0;
+        self.capacity = capacity;
+    }
+}
+
+static RING: TryLock<Ring> = TryLock::new(Ring { calls: Vec::new(), head: 0, capacity: DEFAULT_CAPACITY });
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static BUSY: AtomicBool = AtomicBool::new(false); // Set while tracing, so calls it makes itself aren't traced
+static NO_CLOCK: AtomicBool = AtomicBool::new(false);
+static LOGGER: AtomicUsize = AtomicUsize::new(0); // fn(&Call), or 0
+
+/// The calls kept so far, oldest first
+pub fn calls() -> Vec<Call> {
+    RING.try_with(|ring| ring.oldest_first()).unwrap_or_default()
+}
+
+/// Just the ones that failed
+pub fn errors() -> Vec<Call> {
+    calls().into_iter().filter(Call::is_error).collect()
+}
+
+pub fn clear() {
+    RING.try_with(|ring| ring.clear());
+}
+
+/// How many calls to keep. 256 to start with
+pub fn set_capacity(capacity: usize) {
+    RING.try_with(|ring| ring.set_capacity(capacity.max(1)));
+}
+
+/// Pauses or resumes tracing. It's on to start with
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Has each call passed to `logger` as well as kept, or stops that with None
+pub fn set_logger(logger: Option<fn(&Call)>) {
+    LOGGER.store(logger.map_or(0, |logger| logger as usize), Ordering::Release);
+}
+
+/// Writes the calls kept, oldest first, one per line
+pub fn dump<W: Write>(w: &mut W) -> io::Result<()> {
+    for call in calls() {
+        writeln!(w, "{}", call)?;
+    }
+    Ok(())
+}
+
+// What the trace macros expand to
+
+#[doc(hidden)]
+pub fn start() -> Option<Timestamp> {
+    if !should_trace() || NO_CLOCK.load(Ordering::Relaxed) || BUSY.swap(true, Ordering::Acquire) {
+        return None;
+    }
+    let now = Timestamp::now().ok();
+    if now.is_none() {
+        NO_CLOCK.store(true, Ordering::Relaxed);
+    }
+    BUSY.store(false, Ordering::Release);
+    now
+}
+
+#[doc(hidden)]
+pub fn record(callee: &'static str, handle: Option<usize>, status: EFI_STATUS, start: Option<Timestamp>, file: &'static str, line: u32) {
+    if !should_trace() || BUSY.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let duration = match start {
+        Some(start) => start.elapsed().ok(),
+        None => None,
+    };
+    let call = Call { callee, handle, status, duration, file, line };
+    RING.try_with(|ring| ring.push(call));
+    let logger = LOGGER.load(Ordering::Acquire);
+    if logger != 0 {
+        let logger: fn(&Call) = unsafe { core::mem::transmute(logger) };
+        logger(&call);
+    }
+    BUSY.store(false, Ordering::Release);
+}
+
+fn should_trace() -> bool {
+    let system_table = unsafe { ::SYSTEM_TABLE };
+    is_enabled() && system_table.is_some() && !boot_services_exited()
+}
+
+// Picks out the first argument if it's a handle, by autoref specialisation: Arg<EFI_HANDLE> has handle() itself,
+// anything else only gets the fallback on &Arg
+#[doc(hidden)]
+pub struct Arg<'a, T: 'a>(pub &'a T);
+
+#[doc(hidden)]
+pub trait IsHandle {
+    fn handle(&self) -> Option<usize>;
+}
+
+impl<'a> IsHandle for Arg<'a, EFI_HANDLE> {
+    fn handle(&self) -> Option<usize> {
+        Some(*self.0 as usize)
+    }
+}
+
+#[doc(hidden)]
+pub trait NotHandle {
+    fn handle(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<'a, 'b, T> NotHandle for &'b Arg<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{boot_services::EVT_NOTIFY_SIGNAL, EFI_EVENT};
+    use alloc::string::String;
+    use core::ptr;
+    use testing::mock;
+    use {Result, system_table};
+
+    fn signal(event: EFI_EVENT) -> Result<()> {
+        let bs = system_table().BootServices;
+        unsafe { ret_on_err!(((*bs).SignalEvent)(event)); }
+        Ok(())
+    }
+
+    #[test]
+    fn records_the_failing_call() {
+        mock::install();
+        set_capacity(4096); // Other tests are making calls too
+        let bs = system_table().BootServices;
+        let mut event: EFI_EVENT = ptr::null();
+        let status = unsafe { traced!(((*bs).CreateEvent)(EVT_NOTIFY_SIGNAL, 0, None, ptr::null(), &mut event)) };
+        assert_eq!(status, EFI_SUCCESS);
+
+        // A call is left out if another thread's is being recorded at the time, which firmware doesn't have to worry about
+        let failed = (0..100).filter_map(|_| {
+            assert!(signal(0x5eed as EFI_EVENT).is_err());
+            calls().into_iter().rev().find(|c| c.handle == Some(0x5eed))
+        }).next().unwrap();
+        assert_eq!(failed.function(), "SignalEvent");
+        assert!(failed.is_error() && failed.file.ends_with("trace.rs"));
+        let created = calls().into_iter().find(|c| c.function() == "CreateEvent" && c.file.ends_with("trace.rs"));
+        if let Some(created) = created {
+            assert_eq!((created.status, created.handle), (EFI_SUCCESS, None));
+        }
+
+        let mut out = Vec::new();
+        dump(&mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("SignalEvent(0x5eed) -> InvalidParameter"));
+    }
+
+    #[test]
+    fn keeps_the_latest() {
+        let call = |line| Call { callee: "Stall", handle: None, status: EFI_SUCCESS, duration: None, file: "trace.rs", line };
+        let lines = |ring: &Ring| ring.oldest_first().iter().map(|c| c.line).collect::<Vec<_>>();
+        let mut ring = Ring { calls: Vec::new(), head: 0, capacity: 3 };
+        for line in 1..6 {
+            ring.push(call(line));
+        }
+        assert_eq!(lines(&ring), [3, 4, 5]);
+        ring.set_capacity(2);
+        assert_eq!(lines(&ring), [4, 5]);
+        ring.push(call(6));
+        assert_eq!(lines(&ring), [5, 6]);
+        ring.clear();
+        assert!(lines(&ring).is_empty());
+    }
+}
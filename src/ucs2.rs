@@ -0,0 +1,78 @@
+// Converting names to the null-terminated UCS-2 firmware wants, once rather than on every call.
+//
+// Variable services and file opens take UCS-2 names, so every get_variable("BootOrder") or open("EFI\\BOOT") used
+// to encode the name into a fresh allocation and free it again. Code that reads the same few variables in a loop or
+// opens file after file in one directory pays that each time. The crate's own calls go through a small cache of
+// recently converted names, and intern() keeps a name converted for good.
+//
+// An event callback that interrupts a lookup finds the cache locked and converts without it.
+
+use ffi::CHAR16;
+use utils::{to_ucs2, TryLock};
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+// How many recently converted names to keep, and the longest worth keeping. Paths much longer than this are one-offs
+const RECENT: usize = 32;
+const MAX_CACHED_LEN: usize = 128;
+
+struct Cache {
+    interned: Vec<(String, Arc<[CHAR16]>)>,
+    recent: Vec<(String, Arc<[CHAR16]>)>, // Most recently used first
+}
+
+static CACHE: TryLock<Cache> = TryLock::new(Cache { interned: Vec::new(), recent: Vec::new() });
+
+/// `s` as null-terminated UCS-2, converted the first time and kept for the rest of the run. For names used over and
+/// over, e.g. a vendor's variable names. Never freed, so not for names that come from outside
+pub fn intern(s: &str) -> &'static [CHAR16] {
+    let name = CACHE.try_with(|cache| {
+        if let Some(&(_, ref name)) = cache.interned.iter().find(|&&(ref k, _)| k == s) {
+            return name.clone();
+        }
+        let name: Arc<[CHAR16]> = to_ucs2(s).into();
+        cache.interned.push((s.into(), name.clone()));
+        name
+    });
+    // The cache's copy is never dropped but this one keeps it alive regardless
+    let name = name.unwrap_or_else(|| to_ucs2(s).into());
+    unsafe { &*Arc::into_raw(name) }
+}
+
+/// `s` as null-terminated UCS-2, from the cache if it's been converted lately or interned
+pub(crate) fn cached(s: &str) -> Arc<[CHAR16]> {
+    if s.len() > MAX_CACHED_LEN {
+        return to_ucs2(s).into();
+    }
+    let found = CACHE.try_with(|cache| {
+        if let Some(&(_, ref name)) = cache.interned.iter().find(|&&(ref k, _)| k == s) {
+            return name.clone();
+        }
+        let name = match cache.recent.iter().position(|&(ref k, _)| k == s) {
+            Some(i) => cache.recent.remove(i).1,
+            None => to_ucs2(s).into(),
+        };
+        cache.recent.truncate(RECENT - 1);
+        cache.recent.insert(0, (s.into(), name.clone()));
+        name
+    });
+    found.unwrap_or_else(|| to_ucs2(s).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_once() {
+        let name = intern("InternedTestName");
+        assert_eq!(name.last(), Some(&0));
+        assert_eq!(String::from_utf16_lossy(&name[..name.len() - 1]), "InternedTestName");
+        assert_eq!(intern("InternedTestName").as_ptr(), name.as_ptr());
+        assert_eq!(cached("InternedTestName").as_ptr(), name.as_ptr());
+
+        // Other tests are using the cache too, so whether it's the same copy is up to them
+        assert_eq!(&*cached("RecentTestName"), &*to_ucs2("RecentTestName"));
+        let long: String = core::iter::repeat('x').take(MAX_CACHED_LEN + 1).collect();
+        assert!(!Arc::ptr_eq(&cached(&long), &cached(&long)));
+    }
+}
@@ -0,0 +1,463 @@
+// Firmware user profiles via EFI_USER_MANAGER_PROTOCOL.
+// Only there on platforms with pre-boot authentication. Each profile is a list of information records: a name, an
+// identifier, which credentials may log the user in (the identity policy) and so on. Private records can only be read
+// by the user they belong to, so most of a profile other than the current one may be hidden from us.
+
+use ffi::{
+    user_manager::{
+        EFI_USER_MANAGER_PROTOCOL,
+        EFI_USER_MANAGER_PROTOCOL_GUID,
+        EFI_USER_PROFILE_HANDLE,
+        EFI_USER_INFO_HANDLE,
+        EFI_USER_INFO,
+        EFI_USER_INFO_IDENTITY_POLICY,
+        EFI_USER_INFO_NAME_RECORD,
+        EFI_USER_INFO_IDENTIFIER_RECORD,
+        EFI_USER_INFO_IDENTITY_POLICY_RECORD,
+        EFI_USER_INFO_IDENTITY_FALSE,
+        EFI_USER_INFO_IDENTITY_TRUE,
+        EFI_USER_INFO_IDENTITY_CREDENTIAL_TYPE,
+        EFI_USER_INFO_IDENTITY_CREDENTIAL_PROVIDER,
+        EFI_USER_INFO_IDENTITY_NOT,
+        EFI_USER_INFO_IDENTITY_AND,
+        EFI_USER_INFO_IDENTITY_OR,
+        EFI_USER_INFO_EXCLUSIVE,
+        EFI_USER_INFO_PRIVATE,
+        EFI_USER_INFO_PROTECTED,
+    },
+    EFI_BUFFER_TOO_SMALL,
+    EFI_NOT_FOUND,
+    EFI_GUID,
+};
+pub use ffi::user_manager::EFI_USER_INFO_ATTRIBS;
+use {Result, EfiErrorKind, system_table, to_res};
+use core::{mem, ptr};
+use alloc::{string::String, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Who may see a record
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Visibility {
+    /// Anyone
+    Public,
+    /// Only the user the profile belongs to
+    Private,
+    /// Anyone may read it but only the user the profile belongs to may change it
+    Protected,
+}
+
+/// One information record of a profile
+#[derive(Debug, Clone)]
+pub struct UserInfo {
+    /// The credential provider the record came from. All zeroes for records that don't belong to one
+    pub credential: EFI_GUID,
+    /// One of the EFI_USER_INFO_*_RECORD types
+    pub info_type: u8,
+    pub attributes: EFI_USER_INFO_ATTRIBS,
+    /// The record's contents, without the header
+    pub data: Vec<u8>,
+}
+
+impl UserInfo {
+    pub fn visibility(&self) -> Visibility {
+        // PROTECTED has both the PUBLIC and PRIVATE bits set
+        match self.attributes & EFI_USER_INFO_PROTECTED {
+            EFI_USER_INFO_PROTECTED => Visibility::Protected,
+            EFI_USER_INFO_PRIVATE => Visibility::Private,
+            _ => Visibility::Public,
+        }
+    }
+
+    /// Whether there can be only one record of this type in a profile
+    pub fn is_exclusive(&self) -> bool {
+        self.attributes & EFI_USER_INFO_EXCLUSIVE != 0
+    }
+}
+
+/// One term of an identity policy. Policies are written in postfix: `Not`, `And` and `Or` apply to the terms before them
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PolicyTerm {
+    False,
+    True,
+    /// Any credential provider of this class, e.g. any smart card reader
+    CredentialType(EFI_GUID),
+    /// This particular credential provider
+    CredentialProvider(EFI_GUID),
+    Not,
+    And,
+    Or,
+    /// A term type from a newer spec
+    Other(u32),
+}
+
+/// A user profile
+pub struct UserProfile {
+    protocol: *const EFI_USER_MANAGER_PROTOCOL,
+    handle: EFI_USER_PROFILE_HANDLE,
+}
+
+impl PartialEq for UserProfile {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle
+    }
+}
+
+impl UserProfile {
+    /// The user's name, if the profile has one we may read
+    pub fn name(&self) -> Result<Option<String>> {
+        Ok(self.find(EFI_USER_INFO_NAME_RECORD)?.map(|info| {
+            let chars = info.data.chunks_exact(2).map(LittleEndian::read_u16).take_while(|&c| c != 0).collect::<Vec<_>>();
+            String::from_utf16_lossy(&chars)
+        }))
+    }
+
+    /// The identifier that's unique to this user across all machines
+    pub fn identifier(&self) -> Result<Option<[u8; 16]>> {
+        match self.find(EFI_USER_INFO_IDENTIFIER_RECORD)? {
+            Some(ref info) if info.data.len() == 16 => {
+                let mut id = [0; 16];
+                id.copy_from_slice(&info.data);
+                Ok(Some(id))
+            }
+            Some(_) => Err(EfiErrorKind::VolumeCorrupted.into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Which credentials the user has to present to log in. Empty if the profile has no identity policy
+    pub fn identity_policy(&self) -> Result<Vec<PolicyTerm>> {
+        match self.find(EFI_USER_INFO_IDENTITY_POLICY_RECORD)? {
+            Some(info) => parse_policy(&info.data),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Every record of the profile. Records we may not read are left out
+    pub fn records(&self) -> Result<Vec<UserInfo>> {
+        let mut records = Vec::new();
+        let mut info: EFI_USER_INFO_HANDLE = ptr::null();
+        loop {
+            let status = unsafe { traced!(((*self.protocol).GetNextInfo)(self.protocol, self.handle, &mut info)) };
+            if status == EFI_NOT_FOUND {
+                return Ok(records);
+            }
+            to_res((), status)?;
+
+            match self.info(info) {
+                Ok(record) => records.push(record),
+                Err(ref e) if e.kind() == EfiErrorKind::AccessDenied => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn find(&self, info_type: u8) -> Result<Option<UserInfo>> {
+        Ok(self.records()?.into_iter().find(|info| info.info_type == info_type))
+    }
+
+    fn info(&self, info: EFI_USER_INFO_HANDLE) -> Result<UserInfo> {
+        let mut size = 0;
+        let status = unsafe { traced!(((*self.protocol).GetInfo)(self.protocol, self.handle, info, ptr::null_mut(), &mut size)) };
+        if status != EFI_BUFFER_TOO_SMALL {
+            to_res((), status)?;
+        }
+
+        // A Vec<u32> so that the header is aligned
+        let mut buf = vec![0u32; (size + 3) / 4];
+        unsafe {
+            ret_on_err!(((*self.protocol).GetInfo)(self.protocol, self.handle, info, buf.as_mut_ptr() as *mut EFI_USER_INFO, &mut size));
+        }
+        parse_info(unsafe { core::slice::from_raw_parts(buf.as_ptr() as *const u8, size) })
+    }
+}
+
+/// The user who's logged in
+pub fn current() -> Result<UserProfile> {
+    let protocol = user_manager()?;
+    let mut handle: EFI_USER_PROFILE_HANDLE = ptr::null();
+    unsafe {
+        ret_on_err!(((*protocol).Current)(protocol, &mut handle));
+    }
+    Ok(UserProfile { protocol, handle })
+}
+
+/// Every user known to the firmware
+pub fn profiles() -> Result<Vec<UserProfile>> {
+    let protocol = user_manager()?;
+    let mut profiles = Vec::new();
+    let mut handle: EFI_USER_PROFILE_HANDLE = ptr::null();
+    loop {
+        let status = unsafe { traced!(((*protocol).GetNext)(protocol, &mut handle)) };
+        if status == EFI_NOT_FOUND {
+            return Ok(profiles);
+        }
+        to_res((), status)?;
+        profiles.push(UserProfile { protocol, handle });
+    }
+}
+
+/// Has the firmware ask the user to log in (again) and returns who did. Fails with AccessDenied if nobody could be
+/// identified
+pub fn identify() -> Result<UserProfile> {
+    let protocol = user_manager()?;
+    let mut handle: EFI_USER_PROFILE_HANDLE = ptr::null();
+    unsafe {
+        ret_on_err!(((*protocol).Identify)(protocol, &mut handle));
+    }
+    Ok(UserProfile { protocol, handle })
+}
+
+fn user_manager() -> Result<*const EFI_USER_MANAGER_PROTOCOL> {
+    let bs = system_table().BootServices;
+    let protocol: *const EFI_USER_MANAGER_PROTOCOL = ptr::null();
+    unsafe {
+        ret_on_err!(((*bs).LocateProtocol)(&EFI_USER_MANAGER_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+    }
+
+    if protocol.is_null() {
+        return Err(EfiErrorKind::NotFound.into());
+    }
+
+    Ok(protocol)
+}
+
+fn parse_info(buf: &[u8]) -> Result<UserInfo> {
+    let header_size = mem::size_of::<EFI_USER_INFO>();
+    if buf.len() < header_size {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+
+    let header = unsafe { ptr::read_unaligned(buf.as_ptr() as *const EFI_USER_INFO) };
+    let size = header.InfoSize as usize;
+    if size < header_size || size > buf.len() {
+        return Err(EfiErrorKind::VolumeCorrupted.into());
+    }
+
+    Ok(UserInfo {
+        credential: header.Credential,
+        info_type: header.InfoType,
+        attributes: header.InfoAttribs,
+        data: buf[header_size..size].to_vec(),
+    })
+}
+
+fn parse_policy(mut data: &[u8]) -> Result<Vec<PolicyTerm>> {
+    let header_size = mem::size_of::<EFI_USER_INFO_IDENTITY_POLICY>();
+    let mut terms = Vec::new();
+    while !data.is_empty() {
+        if data.len() < header_size {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        let term_type = LittleEndian::read_u32(&data[0..4]);
+        let len = LittleEndian::read_u32(&data[4..8]) as usize;
+        if len < header_size || len > data.len() {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        let body = &data[header_size..len];
+
+        let guid = || -> Result<EFI_GUID> {
+            if body.len() < 16 {
+                return Err(EfiErrorKind::VolumeCorrupted.into());
+            }
+            let mut tail = [0; 8];
+            tail.copy_from_slice(&body[8..16]);
+            Ok(EFI_GUID(LittleEndian::read_u32(&body[0..4]), LittleEndian::read_u16(&body[4..6]), LittleEndian::read_u16(&body[6..8]), tail))
+        };
+
+        terms.push(if term_type == EFI_USER_INFO_IDENTITY_FALSE {
+            PolicyTerm::False
+        } else if term_type == EFI_USER_INFO_IDENTITY_TRUE {
+            PolicyTerm::True
+        } else if term_type == EFI_USER_INFO_IDENTITY_CREDENTIAL_TYPE {
+            PolicyTerm::CredentialType(guid()?)
+        } else if term_type == EFI_USER_INFO_IDENTITY_CREDENTIAL_PROVIDER {
+            PolicyTerm::CredentialProvider(guid()?)
+        } else if term_type == EFI_USER_INFO_IDENTITY_NOT {
+            PolicyTerm::Not
+        } else if term_type == EFI_USER_INFO_IDENTITY_AND {
+            PolicyTerm::And
+        } else if term_type == EFI_USER_INFO_IDENTITY_OR {
+            PolicyTerm::Or
+        } else {
+            PolicyTerm::Other(term_type)
+        });
+        data = &data[len..];
+    }
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::{
+        boot_services::EFI_INTERFACE_TYPE,
+        user_manager::EFI_USER_INFO_PUBLIC,
+        EFI_ACCESS_DENIED,
+        EFI_HANDLE,
+        EFI_STATUS,
+        EFI_SUCCESS,
+        UINTN,
+        VOID,
+    };
+    use testing::mock;
+    use utils::to_ucs2;
+    use alloc::boxed::Box;
+
+    const SMART_CARD: EFI_GUID = EFI_GUID(0x5f03ba33, 0x8b8d, 0x4a0b, [0x99, 0xf3, 0x9b, 0x2f, 0x2e, 0x6d, 0x4f, 0x1a]);
+    const PASSWORD: EFI_GUID = EFI_GUID(0xd6a4e8b0, 0x2a35, 0x4e02, [0x8c, 0x0e, 0xb8, 0x8a, 0x3c, 0x47, 0x57, 0x86]);
+
+    struct Record {
+        info_type: u8,
+        attributes: EFI_USER_INFO_ATTRIBS,
+        data: Vec<u8>,
+    }
+
+    #[repr(C)]
+    struct FakeManager {
+        protocol: EFI_USER_MANAGER_PROTOCOL,
+        users: Vec<Vec<Record>>, // Handles are index + 1
+        current: usize,
+    }
+
+    fn manager<'a>(this: *const EFI_USER_MANAGER_PROTOCOL) -> &'a FakeManager {
+        unsafe { &*(this as *const FakeManager) }
+    }
+
+    extern "efiapi" fn get_next(this: *const EFI_USER_MANAGER_PROTOCOL, user: *mut EFI_USER_PROFILE_HANDLE) -> EFI_STATUS {
+        unsafe {
+            let next = *user as usize + 1;
+            if next > manager(this).users.len() {
+                return EFI_NOT_FOUND;
+            }
+            *user = next as EFI_USER_PROFILE_HANDLE;
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn current(this: *const EFI_USER_MANAGER_PROTOCOL, user: *mut EFI_USER_PROFILE_HANDLE) -> EFI_STATUS {
+        unsafe { *user = (manager(this).current + 1) as EFI_USER_PROFILE_HANDLE };
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn identify(_this: *const EFI_USER_MANAGER_PROTOCOL, _user: *mut EFI_USER_PROFILE_HANDLE) -> EFI_STATUS {
+        EFI_ACCESS_DENIED
+    }
+
+    extern "efiapi" fn get_info(this: *const EFI_USER_MANAGER_PROTOCOL, user: EFI_USER_PROFILE_HANDLE, info: EFI_USER_INFO_HANDLE, buf: *mut EFI_USER_INFO, size: *mut UINTN) -> EFI_STATUS {
+        let manager = manager(this);
+        let record = &manager.users[user as usize - 1][info as usize - 1];
+        if record.attributes & EFI_USER_INFO_PROTECTED == EFI_USER_INFO_PRIVATE && user as usize - 1 != manager.current {
+            return EFI_ACCESS_DENIED;
+        }
+
+        let needed = mem::size_of::<EFI_USER_INFO>() + record.data.len();
+        unsafe {
+            if *size < needed {
+                *size = needed;
+                return EFI_BUFFER_TOO_SMALL;
+            }
+            *buf = EFI_USER_INFO { Credential: EFI_GUID(0, 0, 0, [0; 8]), InfoType: record.info_type, Reserved1: 0, InfoAttribs: record.attributes, InfoSize: needed as u32 };
+            ptr::copy_nonoverlapping(record.data.as_ptr(), (buf as *mut u8).add(mem::size_of::<EFI_USER_INFO>()), record.data.len());
+            *size = needed;
+        }
+        EFI_SUCCESS
+    }
+
+    extern "efiapi" fn get_next_info(this: *const EFI_USER_MANAGER_PROTOCOL, user: EFI_USER_PROFILE_HANDLE, info: *mut EFI_USER_INFO_HANDLE) -> EFI_STATUS {
+        unsafe {
+            let next = *info as usize + 1;
+            if next > manager(this).users[user as usize - 1].len() {
+                return EFI_NOT_FOUND;
+            }
+            *info = next as EFI_USER_INFO_HANDLE;
+        }
+        EFI_SUCCESS
+    }
+
+    fn name(name: &str) -> Record {
+        let data = to_ucs2(name).iter().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        Record { info_type: EFI_USER_INFO_NAME_RECORD, attributes: EFI_USER_INFO_PUBLIC, data }
+    }
+
+    fn policy_term(term_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut term = vec![0; 8];
+        LittleEndian::write_u32(&mut term[0..4], term_type);
+        LittleEndian::write_u32(&mut term[4..8], 8 + data.len() as u32);
+        term.extend_from_slice(data);
+        term
+    }
+
+    fn guid_bytes(guid: &EFI_GUID) -> Vec<u8> {
+        let mut bytes = vec![0; 8];
+        LittleEndian::write_u32(&mut bytes[0..4], guid.0);
+        LittleEndian::write_u16(&mut bytes[4..6], guid.1);
+        LittleEndian::write_u16(&mut bytes[6..8], guid.2);
+        bytes.extend_from_slice(&guid.3);
+        bytes
+    }
+
+    #[test]
+    fn reads_profiles_and_identity_policy() {
+        mock::install();
+        // Smart card and password, or else password alone
+        let mut policy = policy_term(EFI_USER_INFO_IDENTITY_CREDENTIAL_TYPE, &guid_bytes(&SMART_CARD));
+        policy.extend(policy_term(EFI_USER_INFO_IDENTITY_CREDENTIAL_PROVIDER, &guid_bytes(&PASSWORD)));
+        policy.extend(policy_term(EFI_USER_INFO_IDENTITY_AND, &[]));
+        policy.extend(policy_term(EFI_USER_INFO_IDENTITY_CREDENTIAL_PROVIDER, &guid_bytes(&PASSWORD)));
+        policy.extend(policy_term(EFI_USER_INFO_IDENTITY_OR, &[]));
+
+        let fake: &'static FakeManager = Box::leak(Box::new(FakeManager {
+            protocol: EFI_USER_MANAGER_PROTOCOL {
+                Create: ptr::null(),
+                Delete: ptr::null(),
+                GetNext: get_next,
+                Current: current,
+                Identify: identify,
+                Find: ptr::null(),
+                Notify: ptr::null(),
+                GetInfo: get_info,
+                SetInfo: ptr::null(),
+                DeleteInfo: ptr::null(),
+                GetNextInfo: get_next_info,
+            },
+            users: vec![
+                vec![name("admin"), Record { info_type: EFI_USER_INFO_IDENTIFIER_RECORD, attributes: EFI_USER_INFO_PRIVATE, data: vec![7; 16] }],
+                vec![name("guest"), Record { info_type: EFI_USER_INFO_IDENTITY_POLICY_RECORD, attributes: EFI_USER_INFO_PROTECTED, data: policy }],
+            ],
+            current: 1,
+        }));
+        let mut handle: EFI_HANDLE = ptr::null();
+        unsafe {
+            ((*system_table().BootServices).InstallProtocolInterface)(&mut handle, &EFI_USER_MANAGER_PROTOCOL_GUID, EFI_INTERFACE_TYPE::EFI_NATIVE_INTERFACE, fake as *const _ as *const VOID);
+        }
+
+        let profiles = profiles().unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name().unwrap().as_deref(), Some("admin"));
+        assert_eq!(profiles[0].identifier().unwrap(), None); // Private to admin
+        assert_eq!(profiles[0].identity_policy().unwrap(), []);
+
+        let user = super::current().unwrap();
+        assert!(user == profiles[1]);
+        assert_eq!(user.name().unwrap().as_deref(), Some("guest"));
+        assert_eq!(user.identity_policy().unwrap(), [
+            PolicyTerm::CredentialType(SMART_CARD),
+            PolicyTerm::CredentialProvider(PASSWORD),
+            PolicyTerm::And,
+            PolicyTerm::CredentialProvider(PASSWORD),
+            PolicyTerm::Or,
+        ]);
+        assert_eq!(user.records().unwrap()[1].visibility(), Visibility::Protected);
+        assert_eq!(super::identify().err().unwrap().kind(), EfiErrorKind::AccessDenied);
+    }
+
+    #[test]
+    fn rejects_truncated_policy() {
+        let mut policy = policy_term(EFI_USER_INFO_IDENTITY_TRUE, &[]);
+        policy.extend(policy_term(EFI_USER_INFO_IDENTITY_CREDENTIAL_TYPE, &[1, 2, 3]));
+        assert_eq!(parse_policy(&policy).unwrap_err().kind(), EfiErrorKind::VolumeCorrupted);
+        policy.truncate(12);
+        assert_eq!(parse_policy(&policy).unwrap_err().kind(), EfiErrorKind::VolumeCorrupted);
+        assert_eq!(parse_policy(&policy[..8]).unwrap(), [PolicyTerm::True]);
+    }
+}
@@ -1,8 +1,8 @@
 // TODO: Write a proc macro called derive(TupleWrapper) which automaticlly impls Wrapper trait for any tuple struct wrapping types
 use ffi::CHAR16;
-use core::{self, mem, slice, fmt};
+use core::{self, mem, slice, fmt, cell::UnsafeCell, sync::atomic::{AtomicBool, Ordering}};
 use {EfiError, EfiErrorKind};
-use alloc::str;
+use alloc::{str, string::String, vec::Vec};
 
 pub trait Wrapper {
     type Inner;
@@ -33,14 +33,42 @@ macro_rules! impl_wrapper {
 }
 
 macro_rules! ret_on_err {
-    ($e:expr) => {
-        let status: ::ffi::EFI_STATUS = $e;
+    ($($e:tt)*) => {
+        let status: ::ffi::EFI_STATUS = traced!($($e)*);
         if !$crate::ffi::IsSuccess(status) {
             return Err($crate::EfiError::from(status));
         }
     }
 }
 
+// A call into firmware, recorded by the trace module when the trace-ffi feature is on. For calls whose status is
+// checked by hand rather than with ret_on_err
+#[cfg(not(feature = "trace-ffi"))]
+macro_rules! traced {
+    ($($e:tt)*) => { $($e)* };
+}
+
+#[cfg(feature = "trace-ffi")]
+macro_rules! traced {
+    (($($callee:tt)*)($first:expr $(, $rest:expr)* $(,)*)) => {{
+        #[allow(unused_imports)] use $crate::trace::{IsHandle, NotHandle};
+        let first = $first;
+        let handle = (&$crate::trace::Arg(&first)).handle();
+        let start = $crate::trace::start();
+        let status = ($($callee)*)(first $(, $rest)*);
+        $crate::trace::record(stringify!($($callee)*), handle, status, start, file!(), line!());
+        status
+    }};
+    (($($callee:tt)*)()) => {{
+        let start = $crate::trace::start();
+        let status = ($($callee)*)();
+        $crate::trace::record(stringify!($($callee)*), None, status, start, file!(), line!());
+        status
+    }};
+    (unsafe { $($e:tt)* }) => { unsafe { traced!($($e)*) } };
+    ($($e:tt)*) => { $($e)* }; // Not a call through a function pointer
+}
+
 pub unsafe fn as_slice<'a>(s: *const CHAR16) -> &'a [CHAR16] {
     let mut len = 0;
     let mut temp = s;
@@ -52,6 +80,120 @@ pub unsafe fn as_slice<'a>(s: *const CHAR16) -> &'a [CHAR16] {
     slice::from_raw_parts(s, len)
 }
 
+// Converts to the null-terminated UCS-2 that UEFI expects. Code points outside the BMP become surrogate pairs which
+// firmware will show as garbage but there's nothing better we can do with them
+pub fn to_ucs2(s: &str) -> Vec<CHAR16> {
+    let mut buf = s.encode_utf16().collect::<Vec<_>>();
+    buf.push(0);
+    buf
+}
+
+// A lock around crate-wide state that event callbacks might want too. Firmware is single threaded but a callback can
+// run in the middle of anything, including whatever holds the lock, and wouldn't get it back before returning. So this
+// only spins for a little while, in case it's really another CPU, and then gives up
+pub(crate) struct TryLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for TryLock<T> {}
+
+impl<T> TryLock<T> {
+    const SPINS: usize = 1000;
+
+    pub(crate) const fn new(value: T) -> Self {
+        TryLock { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    // None if it's held
+    pub(crate) fn try_with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        let mut tries = 0;
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            tries += 1;
+            if tries == Self::SPINS {
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.value.get() });
+        self.locked.store(false, Ordering::Release);
+        Some(result)
+    }
+}
+
+// CRC-32 as used by GPT, gzip and zip (IEEE 802.3, reflected, polynomial 0xEDB88320)
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Continues a CRC-32 with more data. Start from 0
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &b| CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Base64 as in RFC 4648, with padding
+pub fn to_base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len() / 3 * 4 + 4);
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes base64 as in RFC 4648, skipping whitespace so PEM's line breaks can be left in. None if there's anything
+/// else that isn't base64 or padding is in the wrong place
+pub fn from_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let (mut n, mut bits, mut padding) = (0u32, 0, 0);
+    for c in encoded.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return None;
+        }
+        let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+        n = (n << 6 | value) & 0x3FFF; // Never more than 14 bits waiting
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((n >> bits) as u8);
+        }
+    }
+    if padding > 2 || bits >= 6 {
+        return None;
+    }
+    Some(decoded)
+}
+
 #[derive(Debug)]
 pub struct NullTerminatedAsciiStr<'a> {
     buffer: &'a [u8]
@@ -0,0 +1,196 @@
+// Saving every variable to a file and putting them back, for when a bad boot configuration leaves a machine that
+// won't boot.
+//
+// The file is a cpio archive with a file per variable laid out like Linux's efivarfs: named Name-vendor-guid and
+// holding the attributes (32 bits, little endian) followed by the data. So it can be unpacked and looked at, or
+// restored, from Linux too.
+//
+// Volatile variables are left out since firmware makes them again on every boot, and so are authenticated ones
+// (Secure Boot keys and the like) since they can't be written back without the signed update that put them there.
+
+use ffi::{
+    runtime_services::{
+        EFI_VARIABLE_NON_VOLATILE,
+        EFI_VARIABLE_APPEND_WRITE,
+        EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS,
+        EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS,
+        EFI_VARIABLE_ENHANCED_AUTHENTICATED_ACCESS,
+    },
+    EFI_GUID,
+};
+use archive::{CpioReader, CpioWriter};
+use fs::{FileSystem, from_io_error};
+use io::Read;
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+
+/// Where save_to() and restore_from() put the backup on the volume unless told otherwise
+pub const DEFAULT_PATH: &str = "EFI/VariableBackup/variables.cpio";
+
+const AUTHENTICATED: u32 = EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS | EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS | EFI_VARIABLE_ENHANCED_AUTHENTICATED_ACCESS;
+// The name, a dash and a GUID
+const GUID_LEN: usize = 36;
+
+/// A variable as it was when backed up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub vendor: EFI_GUID,
+    pub attributes: u32,
+    pub data: Vec<u8>,
+}
+
+impl Variable {
+    /// Whether backups leave it out however they're asked: it's volatile or authenticated
+    pub fn always_skipped(&self) -> bool {
+        self.attributes & EFI_VARIABLE_NON_VOLATILE == 0 || self.attributes & AUTHENTICATED != 0
+    }
+}
+
+/// A set of variables
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Backup {
+    pub variables: Vec<Variable>,
+}
+
+impl Backup {
+    /// Reads every variable except the volatile and authenticated ones and those `skip` picks
+    pub fn collect<F: Fn(&str, &EFI_GUID) -> bool>(skip: F) -> Result<Self> {
+        let rs = super::runtime_services();
+        let mut variables = Vec::new();
+        for (name, vendor) in super::names()? {
+            if skip(&name, &vendor) {
+                continue;
+            }
+            let (data, attributes) = match rs.get_variable(&name, &vendor) {
+                Ok(variable) => variable,
+                Err(ref e) if e.kind() == EfiErrorKind::NotFound => continue, // Gone since we listed them
+                Err(e) => return Err(e),
+            };
+            let variable = Variable { name, vendor, attributes: attributes & !EFI_VARIABLE_APPEND_WRITE, data };
+            if !variable.always_skipped() {
+                variables.push(variable);
+            }
+        }
+        Ok(Backup { variables })
+    }
+
+    pub fn to_archive(&self) -> Result<Vec<u8>> {
+        let mut writer = CpioWriter::new(Vec::new());
+        for variable in &self.variables {
+            let mut contents = Vec::with_capacity(4 + variable.data.len());
+            contents.extend_from_slice(&variable.attributes.to_le_bytes());
+            contents.extend_from_slice(&variable.data);
+            let name = format!("{}-{}", variable.name, variable.vendor);
+            writer.add_file(&name, 0o644, &contents).map_err(from_io_error)?;
+        }
+        writer.finish().map_err(from_io_error)
+    }
+
+    /// Fails with VolumeCorrupted if an entry isn't a variable
+    pub fn from_archive(data: &[u8]) -> Result<Self> {
+        let mut reader = CpioReader::new(data);
+        let mut variables = Vec::new();
+        while let Some(entry) = reader.next_entry().map_err(from_io_error)? {
+            if !entry.is_file() {
+                continue;
+            }
+            let mut contents = Vec::with_capacity(entry.len as usize);
+            reader.read_to_end(&mut contents).map_err(from_io_error)?;
+            variables.push(parse_entry(&entry.name, &contents).ok_or(EfiErrorKind::VolumeCorrupted)?);
+        }
+        Ok(Backup { variables })
+    }
+
+    /// Writes the variables back, carrying on past those that won't go. Returns those with why. Variables that
+    /// exist now but aren't in the backup are left alone
+    pub fn restore(&self) -> Vec<(String, EFI_GUID, EfiErrorKind)> {
+        let mut failed = Vec::new();
+        for variable in &self.variables {
+            if variable.always_skipped() {
+                continue;
+            }
+            if let Err(e) = super::reset(&variable.name, &variable.vendor, variable.attributes, &variable.data) {
+                failed.push((variable.name.clone(), variable.vendor, e.kind()));
+            }
+        }
+        failed
+    }
+}
+
+// Name-8be4df61-93ca-11d2-aa0d-00e098032b8c with the attributes first in the contents
+fn parse_entry(file_name: &str, contents: &[u8]) -> Option<Variable> {
+    let split = file_name.len().checked_sub(GUID_LEN + 1)?;
+    if split == 0 || !file_name.is_char_boundary(split) || &file_name[split..split + 1] != "-" || contents.len() < 4 {
+        return None;
+    }
+    Some(Variable {
+        name: file_name[..split].into(),
+        vendor: EFI_GUID::parse(&file_name[split + 1..])?,
+        attributes: LittleEndian::read_u32(contents),
+        data: contents[4..].to_vec(),
+    })
+}
+
+/// Backs up the variables to `path` on `volume`, usually the EFI system partition, making the directories it's in.
+/// Returns how many were saved
+pub fn save_to<F: Fn(&str, &EFI_GUID) -> bool>(volume: &dyn FileSystem, path: &str, skip: F) -> Result<usize> {
+    let backup = Backup::collect(skip)?;
+    let mut dir = String::new();
+    let parents = &path[..path.rfind('/').unwrap_or(0)];
+    for component in parents.split('/').filter(|c| !c.is_empty()) {
+        if !dir.is_empty() {
+            dir.push('/');
+        }
+        dir.push_str(component);
+        if !volume.exists(&dir) {
+            volume.create_dir(&dir)?;
+        }
+    }
+    volume.write(path, &backup.to_archive()?)?;
+    Ok(backup.variables.len())
+}
+
+/// Restores the variables saved at `path` on `volume`, returning those that couldn't be written
+pub fn restore_from(volume: &dyn FileSystem, path: &str) -> Result<Vec<(String, EFI_GUID, EfiErrorKind)>> {
+    Ok(Backup::from_archive(&volume.read(path)?)?.restore())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::runtime_services::{EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_RUNTIME_ACCESS};
+    use services::RuntimeServices;
+    use testing::mock;
+    use system_table;
+    use alloc::string::ToString;
+
+    const VENDOR: EFI_GUID = EFI_GUID(0x5d8c6b1e, 0x4a2b, 0x4c1d, [0x8e, 0x0f, 0x10, 0x20, 0x30, 0x40, 0x50, 0x60]);
+    const NV_BS_RT: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+
+    #[test]
+    fn backs_up_and_restores() {
+        mock::install();
+        let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+        rs.set_variable("BackupBoot0000", &VENDOR, NV_BS_RT, &[1, 2, 3]).unwrap();
+        rs.set_variable("BackupVolatile", &VENDOR, EFI_VARIABLE_BOOTSERVICE_ACCESS, &[4]).unwrap();
+        rs.set_variable("BackupSigned", &VENDOR, NV_BS_RT | EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS, &[5]).unwrap();
+        rs.set_variable("BackupSkipped", &VENDOR, NV_BS_RT, &[6]).unwrap();
+
+        let backup = Backup::collect(|name, vendor| *vendor != VENDOR || name == "BackupSkipped").unwrap();
+        assert_eq!(backup.variables, [Variable { name: "BackupBoot0000".into(), vendor: VENDOR, attributes: NV_BS_RT, data: vec![1, 2, 3] }]);
+        let archive = backup.to_archive().unwrap();
+        assert_eq!(Backup::from_archive(&archive).unwrap(), backup);
+
+        rs.set_variable("BackupBoot0000", &VENDOR, EFI_VARIABLE_BOOTSERVICE_ACCESS, &[9]).unwrap();
+        assert!(Backup::from_archive(&archive).unwrap().restore().is_empty());
+        assert_eq!(rs.get_variable("BackupBoot0000", &VENDOR).unwrap(), (vec![1, 2, 3], NV_BS_RT));
+
+        let entry = parse_entry("Boot0001-8be4df61-93ca-11d2-aa0d-00e098032b8c", &[7, 0, 0, 0, 0xaa]).unwrap();
+        assert_eq!((&entry.name[..], entry.attributes, &entry.data[..]), ("Boot0001", 7, &[0xaa][..]));
+        assert_eq!(entry.vendor.to_string(), "8be4df61-93ca-11d2-aa0d-00e098032b8c");
+        assert!(parse_entry("Boot0001", &[7, 0, 0, 0]).is_none());
+        assert!(parse_entry("-8be4df61-93ca-11d2-aa0d-00e098032b8c", &[7, 0, 0, 0]).is_none());
+    }
+}
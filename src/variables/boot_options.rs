@@ -0,0 +1,527 @@
+// The boot manager's variables: Boot#### load options, BootOrder, BootNext and BootCurrent.
+//
+// Editing several of them is not atomic, and a machine whose BootOrder points at options that aren't there (or at
+// ones whose devices aren't) might not boot at all. Transaction stages changes, checks that every option's device
+// path leads somewhere and that the order only names options that will exist, saves what it's about to change in a
+// backup variable and only then writes. New options go first, then BootOrder, then deletions, so there's never an
+// order naming a missing option. If a write fails part way the backup is put back, and rollback() puts it back later
+// too.
+
+use ffi::{
+    runtime_services::{EFI_GLOBAL_VARIABLE, EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_RUNTIME_ACCESS},
+    device_path::{EFI_DEVICE_PATH_PROTOCOL, EFI_DEVICE_PATH_PROTOCOL_GUID, MEDIA_DEVICE_PATH, MEDIA_HARDDRIVE_DP, MEDIA_FILEPATH_DP, MESSAGING_DEVICE_PATH, END_DEVICE_PATH_TYPE},
+    media::EFI_BLOCK_IO_PROTOCOL_GUID,
+    EFI_GUID,
+};
+use boot_services::{device_path, locate_device_path, locate_handles};
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+use core::slice;
+
+pub const LOAD_OPTION_ACTIVE: u32 = 0x0000_0001;
+pub const LOAD_OPTION_FORCE_RECONNECT: u32 = 0x0000_0002;
+pub const LOAD_OPTION_HIDDEN: u32 = 0x0000_0008;
+pub const LOAD_OPTION_CATEGORY_APP: u32 = 0x0000_0100;
+
+const ATTRIBUTES: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+const BOOT_ORDER: &str = "BootOrder";
+const BOOT_NEXT: &str = "BootNext";
+const BOOT_CURRENT: &str = "BootCurrent";
+
+/// Where Transaction keeps the variables it's about to change
+pub const BACKUP_VENDOR: EFI_GUID = EFI_GUID(0x7d3a9c2e, 0x5b41, 0x4f0e, [0x9a, 0x6d, 0x3c, 0x81, 0x27, 0xe4, 0x5f, 0xb0]);
+pub const BACKUP_NAME: &str = "BootOptionsBackup";
+// Stands for BootOrder in the backup
+const BOOT_ORDER_RECORD: u32 = 0x1_0000;
+
+/// An EFI_LOAD_OPTION
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOption {
+    pub attributes: u32,
+    pub description: String,
+    /// The device path list, as bytes. The first path is what gets booted
+    pub file_path: Vec<u8>,
+    /// Passed to the image as its load options
+    pub optional_data: Vec<u8>,
+}
+
+impl LoadOption {
+    /// An active option booting `file_path`
+    pub fn new(description: &str, file_path: Vec<u8>) -> Self {
+        LoadOption { attributes: LOAD_OPTION_ACTIVE, description: description.into(), file_path, optional_data: Vec::new() }
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 6 {
+            return None;
+        }
+        let attributes = LittleEndian::read_u32(data);
+        let path_len = LittleEndian::read_u16(&data[4..]) as usize;
+        let mut description = Vec::new();
+        let mut pos = 6;
+        loop {
+            let c = LittleEndian::read_u16(data.get(pos..pos + 2)?);
+            pos += 2;
+            if c == 0 {
+                break;
+            }
+            description.push(c);
+        }
+        let file_path = data.get(pos..pos + path_len)?.to_vec();
+        Some(LoadOption {
+            attributes,
+            description: String::from_utf16_lossy(&description),
+            file_path,
+            optional_data: data[pos + path_len..].to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(6 + self.description.len() * 2 + 2 + self.file_path.len() + self.optional_data.len());
+        data.extend_from_slice(&self.attributes.to_le_bytes());
+        data.extend_from_slice(&(self.file_path.len() as u16).to_le_bytes());
+        for c in self.description.encode_utf16().chain(Some(0)) {
+            data.extend_from_slice(&c.to_le_bytes());
+        }
+        data.extend_from_slice(&self.file_path);
+        data.extend_from_slice(&self.optional_data);
+        data
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.attributes & LOAD_OPTION_ACTIVE != 0
+    }
+
+    /// The file the option boots: the file path nodes of its first device path put together. None if it has none,
+    /// e.g. for a whole disk or a network boot
+    pub fn file_name(&self) -> Option<String> {
+        let mut path = first_path(&self.file_path)?;
+        let mut name = String::new();
+        while path[0] != END_DEVICE_PATH_TYPE {
+            let node = first_node(path);
+            if node[0] == MEDIA_DEVICE_PATH && node[1] == MEDIA_FILEPATH_DP {
+                let chars = node[4..].chunks_exact(2).map(LittleEndian::read_u16).take_while(|&c| c != 0).collect::<Vec<_>>();
+                let part = String::from_utf16_lossy(&chars);
+                if !name.is_empty() && !name.ends_with('\\') && !part.starts_with('\\') {
+                    name.push('\\');
+                }
+                name.push_str(&part);
+            }
+            path = &path[node.len()..];
+        }
+        if name.is_empty() { None } else { Some(name) }
+    }
+}
+
+fn boot_name(number: u16) -> String {
+    format!("Boot{:04X}", number)
+}
+
+fn read_u16s(data: &[u8]) -> Vec<u16> {
+    data.chunks(2).filter(|c| c.len() == 2).map(LittleEndian::read_u16).collect()
+}
+
+fn write_u16s(numbers: &[u16]) -> Vec<u8> {
+    numbers.iter().flat_map(|n| n.to_le_bytes().to_vec()).collect()
+}
+
+// None if the variable doesn't exist
+fn read(name: &str) -> Result<Option<(Vec<u8>, u32)>> {
+    match super::runtime_services().get_variable(name, &EFI_GLOBAL_VARIABLE) {
+        Ok(variable) => Ok(Some(variable)),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The order the boot manager tries options in. Empty if there's no BootOrder
+pub fn boot_order() -> Result<Vec<u16>> {
+    Ok(read(BOOT_ORDER)?.map(|(data, _)| read_u16s(&data)).unwrap_or_default())
+}
+
+pub fn set_boot_order(order: &[u16]) -> Result<()> {
+    super::reset(BOOT_ORDER, &EFI_GLOBAL_VARIABLE, ATTRIBUTES, &write_u16s(order))
+}
+
+/// Boot####, None if there isn't one. Fails with VolumeCorrupted if it doesn't parse
+pub fn load_option(number: u16) -> Result<Option<LoadOption>> {
+    match read(&boot_name(number))? {
+        Some((data, _)) => LoadOption::parse(&data).map(Some).ok_or_else(|| EfiErrorKind::VolumeCorrupted.into()),
+        None => Ok(None),
+    }
+}
+
+pub fn set_load_option(number: u16, option: &LoadOption) -> Result<()> {
+    super::reset(&boot_name(number), &EFI_GLOBAL_VARIABLE, ATTRIBUTES, &option.encode())
+}
+
+/// Every Boot#### there is, in number order, whether or not BootOrder has it. Ones that don't parse are left out
+pub fn boot_options() -> Result<Vec<(u16, LoadOption)>> {
+    let mut options = Vec::new();
+    for (name, vendor) in super::names()? {
+        if vendor != EFI_GLOBAL_VARIABLE {
+            continue;
+        }
+        if let Some(number) = parse_boot_name(&name) {
+            if let Some(option) = load_option(number).ok().and_then(|o| o) {
+                options.push((number, option));
+            }
+        }
+    }
+    options.sort_by_key(|&(number, _)| number);
+    Ok(options)
+}
+
+fn parse_boot_name(name: &str) -> Option<u16> {
+    let hex = name.strip_prefix("Boot")?;
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c)) {
+        return None;
+    }
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// The option we were booted from
+pub fn boot_current() -> Result<Option<u16>> {
+    Ok(read(BOOT_CURRENT)?.and_then(|(data, _)| read_u16s(&data).first().cloned()))
+}
+
+/// Boots `number` next time only, ahead of BootOrder
+pub fn set_boot_next(number: u16) -> Result<()> {
+    super::reset(BOOT_NEXT, &EFI_GLOBAL_VARIABLE, ATTRIBUTES, &number.to_le_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Set(u16, LoadOption),
+    Delete(u16),
+}
+
+/// Changes to the boot options that are written together or not at all
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Transaction {
+    changes: Vec<Change>,
+    order: Option<Vec<u16>>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes Boot#### with `option`, replacing what's there
+    pub fn set(&mut self, number: u16, option: LoadOption) -> &mut Self {
+        self.changes.push(Change::Set(number, option));
+        self
+    }
+
+    /// Adds an option under the lowest number that isn't taken now or by this transaction, and returns it. It isn't
+    /// put in the order
+    pub fn add(&mut self, option: LoadOption) -> Result<u16> {
+        let mut taken = boot_options()?.into_iter().map(|(number, _)| number).collect::<Vec<_>>();
+        taken.extend(self.changes.iter().map(|change| match *change {
+            Change::Set(number, _) | Change::Delete(number) => number,
+        }));
+        let number = (0..=u16::MAX).find(|n| !taken.contains(n)).ok_or(EfiErrorKind::OutOfResources)?;
+        self.set(number, option);
+        Ok(number)
+    }
+
+    /// Deletes Boot####, and takes it out of the order unless the order is set explicitly
+    pub fn delete(&mut self, number: u16) -> &mut Self {
+        self.changes.push(Change::Delete(number));
+        self
+    }
+
+    pub fn set_order(&mut self, order: Vec<u16>) -> &mut Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// The options that will exist after the transaction
+    fn final_numbers(&self, existing: &[u16]) -> Vec<u16> {
+        let mut numbers = existing.to_vec();
+        for change in &self.changes {
+            match *change {
+                Change::Set(number, _) => if !numbers.contains(&number) { numbers.push(number) },
+                Change::Delete(number) => numbers.retain(|&n| n != number),
+            }
+        }
+        numbers
+    }
+
+    // The order to write, if it changes
+    fn final_order(&self, current: &[u16], numbers: &[u16]) -> Option<Vec<u16>> {
+        match self.order {
+            Some(ref order) => Some(order.clone()),
+            None if current.iter().any(|n| !numbers.contains(n)) => Some(current.iter().cloned().filter(|n| numbers.contains(n)).collect()),
+            None => None,
+        }
+    }
+
+    /// Checks the device paths of the options being set and that the order only names options that will exist.
+    /// Fails with NotFound if a device path doesn't lead to a device or the order names a missing option, and
+    /// InvalidParameter if an order repeats an option or a device path is malformed
+    pub fn validate(&self) -> Result<()> {
+        let existing = boot_options()?.into_iter().map(|(number, _)| number).collect::<Vec<_>>();
+        let numbers = self.final_numbers(&existing);
+        if let Some(ref order) = self.order {
+            for (i, number) in order.iter().enumerate() {
+                if order[..i].contains(number) {
+                    return Err(EfiErrorKind::InvalidParameter.into());
+                }
+                if !numbers.contains(number) {
+                    return Err(EfiErrorKind::NotFound.into());
+                }
+            }
+        }
+        for change in &self.changes {
+            if let Change::Set(_, ref option) = *change {
+                let path = first_path(&option.file_path).ok_or(EfiErrorKind::InvalidParameter)?;
+                if !resolves(path)? {
+                    return Err(EfiErrorKind::NotFound.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates, saves the backup and writes the changes, putting everything back if a write fails
+    pub fn commit(self) -> Result<()> {
+        self.validate()?;
+        self.apply()
+    }
+
+    fn apply(&self) -> Result<()> {
+        let existing = boot_options()?.into_iter().map(|(number, _)| number).collect::<Vec<_>>();
+        let current_order = boot_order()?;
+        let numbers = self.final_numbers(&existing);
+        let order = self.final_order(&current_order, &numbers);
+
+        let mut touched = Vec::new();
+        for change in &self.changes {
+            let number = match *change {
+                Change::Set(number, _) | Change::Delete(number) => number as u32,
+            };
+            if !touched.contains(&number) {
+                touched.push(number);
+            }
+        }
+        if order.is_some() {
+            touched.push(BOOT_ORDER_RECORD);
+        }
+        save_backup(&touched)?;
+
+        let result = self.write(order.as_ref().map(|o| &o[..]));
+        if result.is_err() {
+            let _ = rollback();
+        }
+        result
+    }
+
+    fn write(&self, order: Option<&[u16]>) -> Result<()> {
+        // The last change to an option is the one that counts
+        let last = |number: u16| self.changes.iter().rev().find(|c| match **c {
+            Change::Set(n, _) | Change::Delete(n) => n == number,
+        });
+        for change in &self.changes {
+            if let Change::Set(number, ref option) = *change {
+                if last(number) == Some(change) {
+                    set_load_option(number, option)?;
+                }
+            }
+        }
+        if let Some(order) = order {
+            set_boot_order(order)?;
+        }
+        for change in &self.changes {
+            if let Change::Delete(number) = *change {
+                if last(number) == Some(change) {
+                    super::delete(&boot_name(number), &EFI_GLOBAL_VARIABLE)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The backup is a record per variable: which one (a Boot#### number or BOOT_ORDER_RECORD), its attributes (0 if it
+// didn't exist) and its data, with the lengths before the data
+fn save_backup(records: &[u32]) -> Result<()> {
+    let mut backup = Vec::new();
+    for &record in records {
+        let name = if record == BOOT_ORDER_RECORD { BOOT_ORDER.into() } else { boot_name(record as u16) };
+        let (data, attributes) = read(&name)?.unwrap_or_default();
+        backup.extend_from_slice(&record.to_le_bytes());
+        backup.extend_from_slice(&attributes.to_le_bytes());
+        backup.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        backup.extend_from_slice(&data);
+    }
+    super::reset(BACKUP_NAME, &BACKUP_VENDOR, EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS, &backup)
+}
+
+/// Puts back the variables the last committed transaction changed. Returns false if there's no backup
+pub fn rollback() -> Result<bool> {
+    let backup = match super::runtime_services().get_variable(BACKUP_NAME, &BACKUP_VENDOR) {
+        Ok((data, _)) => data,
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let mut rest = &backup[..];
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err(EfiErrorKind::VolumeCorrupted.into());
+        }
+        let record = LittleEndian::read_u32(rest);
+        let attributes = LittleEndian::read_u32(&rest[4..]);
+        let len = LittleEndian::read_u32(&rest[8..]) as usize;
+        let data = rest.get(12..12 + len).ok_or(EfiErrorKind::VolumeCorrupted)?;
+        let name = if record == BOOT_ORDER_RECORD { BOOT_ORDER.into() } else { boot_name(record as u16) };
+        if attributes == 0 {
+            super::delete(&name, &EFI_GLOBAL_VARIABLE)?;
+        } else {
+            super::reset(&name, &EFI_GLOBAL_VARIABLE, attributes, data)?;
+        }
+        rest = &rest[12 + len..];
+    }
+    Ok(true)
+}
+
+// Device path nodes: type, subtype and a length that includes the four byte header
+
+// The first path of a list, with its end node, if it's well formed
+fn first_path(list: &[u8]) -> Option<&[u8]> {
+    let mut pos = 0;
+    loop {
+        let node = list.get(pos..pos + 4)?;
+        let len = LittleEndian::read_u16(&node[2..]) as usize;
+        if len < 4 || pos + len > list.len() {
+            return None;
+        }
+        pos += len;
+        if node[0] == END_DEVICE_PATH_TYPE {
+            return Some(&list[..pos]);
+        }
+    }
+}
+
+fn first_node(path: &[u8]) -> &[u8] {
+    &path[..LittleEndian::read_u16(&path[2..]) as usize]
+}
+
+// Whether a path leads to a device that's there. Full paths have to match a device up to the media part (partition,
+// file); short ones starting at a partition have to match a partition on some disk. Short forms starting with a
+// messaging node (USB WWID, URI) are found by the boot manager and are taken on trust
+fn resolves(path: &[u8]) -> Result<bool> {
+    let first = first_node(path);
+    match first[0] {
+        END_DEVICE_PATH_TYPE => Ok(false),
+        MESSAGING_DEVICE_PATH => Ok(true),
+        MEDIA_DEVICE_PATH if first[1] == MEDIA_HARDDRIVE_DP => {
+            for handle in locate_handles(&EFI_BLOCK_IO_PROTOCOL_GUID)? {
+                if let Ok(device) = device_path(handle) {
+                    if let Some(nodes) = unsafe { path_bytes(device) } {
+                        if contains_node(nodes, first) {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+            Ok(false)
+        }
+        _ => match locate_device_path(&EFI_DEVICE_PATH_PROTOCOL_GUID, path.as_ptr() as *const EFI_DEVICE_PATH_PROTOCOL) {
+            Ok((_, rest)) => {
+                let kind = unsafe { (*rest).Type };
+                Ok(kind == MEDIA_DEVICE_PATH || kind == END_DEVICE_PATH_TYPE)
+            }
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+// A device path firmware gave us, up to and including its end node
+unsafe fn path_bytes<'a>(path: *const EFI_DEVICE_PATH_PROTOCOL) -> Option<&'a [u8]> {
+    let start = path as *const u8;
+    let mut len = 0;
+    loop {
+        let node = slice::from_raw_parts(start.add(len), 4);
+        let node_len = LittleEndian::read_u16(&node[2..]) as usize;
+        if node_len < 4 {
+            return None;
+        }
+        len += node_len;
+        if node[0] == END_DEVICE_PATH_TYPE {
+            return Some(slice::from_raw_parts(start, len));
+        }
+    }
+}
+
+fn contains_node(mut path: &[u8], node: &[u8]) -> bool {
+    while path.len() >= 4 {
+        let first = first_node(path);
+        if first.is_empty() || first.len() > path.len() {
+            return false;
+        }
+        if first == node {
+            return true;
+        }
+        path = &path[first.len()..];
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::mock;
+
+    // A short form HD() path to \EFI\BOOT\BOOTX64.EFI on partition 1
+    fn hd_path() -> Vec<u8> {
+        let mut path = vec![MEDIA_DEVICE_PATH, MEDIA_HARDDRIVE_DP, 42, 0, 1, 0, 0, 0];
+        path.resize(42, 0x11);
+        path.extend_from_slice(&[0x04, 0x04, 12, 0, b'a', 0, b'b', 0, 0, 0, 0, 0]);
+        path.extend_from_slice(&[END_DEVICE_PATH_TYPE, 0xff, 4, 0]);
+        path
+    }
+
+    #[test]
+    fn stages_commits_and_rolls_back() {
+        let mut option = LoadOption::new("Linux", hd_path());
+        option.optional_data = b"quiet".to_vec();
+        let encoded = option.encode();
+        assert_eq!(&encoded[..8], &[1, 0, 0, 0, 58, 0, b'L', 0]);
+        assert_eq!(LoadOption::parse(&encoded), Some(option.clone()));
+        assert_eq!(first_path(&option.file_path).map(|p| p.len()), Some(58));
+        assert!(first_path(&option.file_path[..50]).is_none());
+        assert!(contains_node(&hd_path(), &[0x04, 0x04, 12, 0, b'a', 0, b'b', 0, 0, 0, 0, 0]));
+        assert_eq!(option.file_name().as_deref(), Some("ab"));
+        assert_eq!(LoadOption::new("Disk", vec![END_DEVICE_PATH_TYPE, 0xff, 4, 0]).file_name(), None);
+        assert_eq!(parse_boot_name("Boot00A1"), Some(0xa1));
+        assert_eq!(parse_boot_name("Boot00a1"), None);
+
+        mock::install();
+        set_load_option(0x10, &LoadOption::new("Old", hd_path())).unwrap();
+        set_boot_order(&[0x10]).unwrap();
+
+        let mut transaction = Transaction::new();
+        let number = transaction.add(option.clone()).unwrap();
+        assert_eq!(number, 0);
+        transaction.delete(0x10);
+        // The mock has no disks, so nothing resolves
+        assert_eq!(transaction.validate().unwrap_err().kind(), EfiErrorKind::NotFound);
+        transaction.set_order(vec![0, 0]);
+        assert_eq!(transaction.validate().unwrap_err().kind(), EfiErrorKind::InvalidParameter);
+        transaction.set_order(vec![0]);
+
+        transaction.apply().unwrap();
+        assert_eq!(boot_order().unwrap(), [0]);
+        assert_eq!(load_option(0).unwrap(), Some(option));
+        assert_eq!(load_option(0x10).unwrap(), None);
+
+        assert!(rollback().unwrap());
+        assert_eq!(boot_order().unwrap(), [0x10]);
+        assert_eq!(load_option(0).unwrap(), None);
+        assert_eq!(load_option(0x10).unwrap().unwrap().description, "Old");
+    }
+}
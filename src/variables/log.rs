@@ -0,0 +1,274 @@
+// A log kept in variables, for when there's nowhere else to keep one that lasts: no writable file system, no serial
+// port, or a crash that takes both down with it. What was logged can be read back on the next boot, or from the OS
+// (the variables are runtime accessible, so Linux shows them under efivarfs).
+//
+// The log is a ring of a few variables (segments) named LogNNNN under our own vendor GUID. Each message is a record
+// appended to the current segment: its sequence number and length, a CRC of the lot, then the text. Once a segment
+// is full the next one is emptied and written from the start, so the log never takes more than slots * segment size
+// of NVRAM. A record torn by a reset part way through a write fails its CRC and it and anything after it in that
+// segment are dropped on reading.
+//
+// Every message is a write to flash, so this is for the messages that matter after a crash, not for tracing.
+
+use ffi::{
+    runtime_services::{EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_RUNTIME_ACCESS, EFI_VARIABLE_APPEND_WRITE},
+    EFI_GUID,
+};
+use io;
+use utils::crc32_update;
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::{string::String, vec::Vec};
+use core::{cmp, mem};
+
+/// The vendor of the log's variables
+pub const VENDOR: EFI_GUID = EFI_GUID(0x7d3f0b52, 0x6c1e, 0x4a8f, [0x95, 0x2d, 0x0e, 0x4b, 0x71, 0xc3, 0xa6, 0x18]);
+
+/// How many segments open() uses
+pub const DEFAULT_SLOTS: usize = 4;
+
+/// How big open() lets each segment get. Small enough for firmware with tight limits on variable size
+pub const DEFAULT_SEGMENT_SIZE: usize = 4096;
+
+const PREFIX: &str = "Log";
+const ATTRIBUTES: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+// Sequence number, length, CRC
+const HEADER_LEN: usize = 10;
+
+/// A message read back from the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Counts up across boots from the first message ever logged
+    pub sequence: u32,
+    pub text: String,
+}
+
+/// Appends messages to the log. Carries on where the previous boot's log left off
+pub struct NvramLog {
+    slots: usize,
+    segment_size: usize,
+    slot: usize,
+    used: usize,
+    sequence: u32,
+    line: Vec<u8>,
+}
+
+impl NvramLog {
+    /// With DEFAULT_SLOTS segments of DEFAULT_SEGMENT_SIZE
+    pub fn open() -> Result<Self> {
+        Self::with_capacity(DEFAULT_SLOTS, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// With `slots` segments of up to `segment_size` bytes each. Segments past `slots` from a bigger log earlier
+    /// stay until clear()
+    pub fn with_capacity(slots: usize, segment_size: usize) -> Result<Self> {
+        if slots == 0 || segment_size <= HEADER_LEN {
+            return Err(EfiErrorKind::InvalidParameter.into());
+        }
+        let mut log = NvramLog { slots, segment_size, slot: 0, used: 0, sequence: 0, line: Vec::new() };
+        // Carry on in the segment with the latest record
+        let mut newest = None;
+        for slot in 0..slots {
+            let data = read_segment(slot)?;
+            if let Some(last) = parse(&data).last() {
+                match newest {
+                    Some((sequence, _, _)) if sequence >= last.sequence => {},
+                    _ => newest = Some((last.sequence, slot, data.len())),
+                }
+            }
+        }
+        if let Some((sequence, slot, used)) = newest {
+            log.sequence = sequence.wrapping_add(1);
+            log.slot = slot;
+            log.used = used;
+        }
+        Ok(log)
+    }
+
+    /// Logs one message, cutting it short if it doesn't fit in a segment
+    pub fn log(&mut self, message: &str) -> Result<()> {
+        let text = &message.as_bytes()[..cmp::min(message.len(), self.segment_size - HEADER_LEN)];
+        let record = encode(self.sequence, text);
+        if self.used + record.len() > self.segment_size {
+            self.slot = (self.slot + 1) % self.slots;
+            self.used = 0;
+            super::delete(&slot_name(self.slot), &VENDOR)?;
+        }
+        append_segment(self.slot, &record)?;
+        self.used += record.len();
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(())
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let line = mem::take(&mut self.line);
+        let line = String::from_utf8_lossy(&line);
+        self.log(line.trim_end_matches('\r')).map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to write log variable"))
+    }
+}
+
+/// Each line is a message, so the log can be written to with `writeln!` like any other
+impl io::Write for NvramLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            if b == b'\n' {
+                self.send()?;
+            } else {
+                self.line.push(b);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.line.is_empty() { Ok(()) } else { self.send() }
+    }
+}
+
+impl Drop for NvramLog {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+/// Every intact record in the log, oldest first
+pub fn records() -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for name in segment_names()? {
+        let data = match super::runtime_services().get_variable(&name, &VENDOR) {
+            Ok((data, _)) => data,
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        records.extend(parse(&data));
+    }
+    records.sort_by_key(|r| r.sequence);
+    Ok(records)
+}
+
+/// The log as text, a line per message
+pub fn contents() -> Result<String> {
+    let mut text = String::new();
+    for record in records()? {
+        text.push_str(&record.text);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Writes the log out as contents() has it, e.g. to a file on a USB stick
+pub fn export_to<W: io::Write>(out: &mut W) -> Result<()> {
+    out.write_all(contents()?.as_bytes()).map_err(|_| EfiErrorKind::DeviceError.into())
+}
+
+/// Deletes every segment
+pub fn clear() -> Result<()> {
+    for name in segment_names()? {
+        super::delete(&name, &VENDOR)?;
+    }
+    Ok(())
+}
+
+fn slot_name(slot: usize) -> String {
+    format!("{}{:04X}", PREFIX, slot)
+}
+
+fn segment_names() -> Result<Vec<String>> {
+    Ok(super::names()?.into_iter()
+        .filter(|(name, vendor)| *vendor == VENDOR && name.starts_with(PREFIX))
+        .map(|(name, _)| name)
+        .collect())
+}
+
+fn read_segment(slot: usize) -> Result<Vec<u8>> {
+    match super::runtime_services().get_variable(&slot_name(slot), &VENDOR) {
+        Ok((data, _)) => Ok(data),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// Appending writes only the new record, where rewriting the segment would wear the flash for all of it. Some
+// firmware only lets authenticated variables be appended to, so fall back to that
+fn append_segment(slot: usize, record: &[u8]) -> Result<()> {
+    let rs = super::runtime_services();
+    let name = slot_name(slot);
+    match rs.set_variable(&name, &VENDOR, ATTRIBUTES | EFI_VARIABLE_APPEND_WRITE, record) {
+        Err(ref e) if e.kind() == EfiErrorKind::InvalidParameter || e.kind() == EfiErrorKind::Unsupported => {
+            let mut data = read_segment(slot)?;
+            data.extend_from_slice(record);
+            rs.set_variable(&name, &VENDOR, ATTRIBUTES, &data)
+        },
+        res => res,
+    }
+}
+
+fn encode(sequence: u32, text: &[u8]) -> Vec<u8> {
+    let mut record = vec![0; HEADER_LEN];
+    LittleEndian::write_u32(&mut record[0..4], sequence);
+    LittleEndian::write_u16(&mut record[4..6], text.len() as u16);
+    let crc = crc32_update(crc32_update(0, &record[0..6]), text);
+    LittleEndian::write_u32(&mut record[6..10], crc);
+    record.extend_from_slice(text);
+    record
+}
+
+// The records in a segment up to the first one that's torn or corrupt
+fn parse(mut data: &[u8]) -> Vec<Record> {
+    let mut records = Vec::new();
+    while data.len() >= HEADER_LEN {
+        let len = LittleEndian::read_u16(&data[4..6]) as usize;
+        if data.len() < HEADER_LEN + len {
+            break;
+        }
+        let text = &data[HEADER_LEN..HEADER_LEN + len];
+        if crc32_update(crc32_update(0, &data[0..6]), text) != LittleEndian::read_u32(&data[6..10]) {
+            break;
+        }
+        records.push(Record { sequence: LittleEndian::read_u32(&data[0..4]), text: String::from_utf8_lossy(text).into_owned() });
+        data = &data[HEADER_LEN + len..];
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::Write;
+    use testing::mock;
+
+    #[test]
+    fn rotates_and_survives_reopening() {
+        mock::install();
+        clear().unwrap();
+        {
+            // Room for two 10 byte messages per segment
+            let mut log = NvramLog::with_capacity(3, 2 * (HEADER_LEN + 10)).unwrap();
+            for i in 0..5 {
+                writeln!(log, "message {:02}", i).unwrap();
+            }
+            write!(log, "unfinished").unwrap();
+        }
+        assert_eq!(records().unwrap().iter().map(|r| r.sequence).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+
+        // Wraps round to the first segment, dropping the oldest two
+        let mut log = NvramLog::with_capacity(3, 2 * (HEADER_LEN + 10)).unwrap();
+        log.log("message 06").unwrap();
+        log.log("message 07").unwrap();
+        let text = contents().unwrap();
+        assert_eq!(text, "message 02\nmessage 03\nmessage 04\nunfinished\nmessage 06\nmessage 07\n");
+
+        // A torn write loses that record and what follows it in the segment, nothing else
+        let (mut data, _) = super::super::runtime_services().get_variable(&slot_name(0), &VENDOR).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        super::super::runtime_services().set_variable(&slot_name(0), &VENDOR, ATTRIBUTES, &data).unwrap();
+        assert_eq!(records().unwrap().last().unwrap().text, "message 06");
+
+        let mut exported = Vec::new();
+        export_to(&mut exported).unwrap();
+        assert!(exported.starts_with(b"message 02\n"));
+        clear().unwrap();
+        assert_eq!(records().unwrap(), []);
+    }
+}
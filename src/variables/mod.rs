@@ -0,0 +1,122 @@
+// Variables beyond reading and writing one at a time: listing them, and deleting or rewriting them in ways firmware
+// will accept.
+//
+// SetVariable is picky. A variable's attributes can't be changed by writing it again, only by deleting it first, and
+// deleting takes the attributes it already has (or none at all, depending on the implementation). Authenticated
+// variables can only be deleted with a signed empty update, which is beyond what we can do here, and variables
+// locked by policy can't be touched at all.
+
+pub mod policy;
+pub mod os_indications;
+pub mod backup;
+pub mod boot_options;
+pub mod log;
+pub mod rollback;
+
+use ffi::{
+    runtime_services::{EFI_VARIABLE_APPEND_WRITE, EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS, EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS},
+    EFI_GUID,
+};
+use services::RuntimeServices;
+use {Result, EfiErrorKind, system_table};
+use alloc::{string::String, vec::Vec};
+
+/// The name and vendor of every variable
+pub fn names() -> Result<Vec<(String, EFI_GUID)>> {
+    runtime_services().variable_names()
+}
+
+/// The attributes of the variable, None if it doesn't exist
+pub fn attributes(name: &str, vendor: &EFI_GUID) -> Result<Option<u32>> {
+    match runtime_services().get_variable(name, vendor) {
+        Ok((_, attributes)) => Ok(Some(attributes)),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Deletes the variable. Returns whether there was one to delete.
+/// Fails with WriteProtected if a variable policy has locked it and SecurityViolation if it's authenticated
+pub fn delete(name: &str, vendor: &EFI_GUID) -> Result<bool> {
+    let attributes = match attributes(name, vendor)? {
+        Some(attributes) => attributes & !EFI_VARIABLE_APPEND_WRITE,
+        None => return Ok(false),
+    };
+    if attributes & (EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS | EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS) != 0 {
+        return Err(EfiErrorKind::SecurityViolation.into());
+    }
+    // Best effort: the policy check is only there to give a better error, firmware has the last word either way
+    if policy::is_locked(name, vendor).unwrap_or(false) {
+        return Err(EfiErrorKind::WriteProtected.into());
+    }
+
+    let rs = runtime_services();
+    match rs.set_variable(name, vendor, attributes, &[]) {
+        Ok(()) => Ok(true),
+        // Some implementations want no attributes at all for a delete
+        Err(ref e) if e.kind() == EfiErrorKind::InvalidParameter => rs.set_variable(name, vendor, 0, &[]).map(|_| true),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the variable with exactly these attributes and data, deleting it first if it exists with other attributes
+pub fn reset(name: &str, vendor: &EFI_GUID, attributes: u32, data: &[u8]) -> Result<()> {
+    let attributes = attributes & !EFI_VARIABLE_APPEND_WRITE;
+    if let Some(existing) = self::attributes(name, vendor)? {
+        if existing & !EFI_VARIABLE_APPEND_WRITE != attributes {
+            delete(name, vendor)?;
+        }
+    }
+    if data.is_empty() {
+        return Ok(()); // Writing nothing would be a delete, which is where we already are
+    }
+    runtime_services().set_variable(name, vendor, attributes, data)
+}
+
+/// Deletes every variable `filter` picks, carrying on past ones that can't be deleted. Returns those with why
+pub fn delete_all<F: Fn(&str, &EFI_GUID) -> bool>(filter: F) -> Result<Vec<(String, EFI_GUID, EfiErrorKind)>> {
+    let mut failed = Vec::new();
+    for (name, vendor) in names()? {
+        if filter(&name, &vendor) {
+            if let Err(e) = delete(&name, &vendor) {
+                failed.push((name, vendor, e.kind()));
+            }
+        }
+    }
+    Ok(failed)
+}
+
+fn runtime_services() -> RuntimeServices<'static> {
+    RuntimeServices::new(unsafe { &*system_table().RuntimeServices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::runtime_services::{EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_RUNTIME_ACCESS};
+    use testing::mock;
+
+    const VENDOR: EFI_GUID = EFI_GUID(0x3c2b6a5e, 0x1f3d, 0x4d66, [0x9c, 0x81, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    const NV_BS_RT: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+
+    #[test]
+    fn deletes_and_resets_variables() {
+        mock::install();
+        let rs = runtime_services();
+        rs.set_variable("Boot0001", &VENDOR, NV_BS_RT, &[1, 2]).unwrap();
+        rs.set_variable("Boot0002", &VENDOR, NV_BS_RT, &[3]).unwrap();
+        rs.set_variable("Signed", &VENDOR, NV_BS_RT | EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS, &[4]).unwrap();
+        rs.set_variable("Keep", &VENDOR, EFI_VARIABLE_BOOTSERVICE_ACCESS, &[5]).unwrap();
+
+        reset("Keep", &VENDOR, NV_BS_RT, &[6]).unwrap();
+        assert_eq!(rs.get_variable("Keep", &VENDOR).unwrap(), (vec![6], NV_BS_RT));
+
+        assert!(delete("Boot0001", &VENDOR).unwrap());
+        assert!(!delete("Boot0001", &VENDOR).unwrap());
+        let failed = delete_all(|name, vendor| *vendor == VENDOR && name != "Keep").unwrap();
+        assert_eq!(failed, [("Signed".into(), VENDOR, EfiErrorKind::SecurityViolation)]);
+        let left = names().unwrap().into_iter().filter(|&(_, vendor)| vendor == VENDOR).map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(left, ["Signed", "Keep"]);
+    }
+}
@@ -0,0 +1,214 @@
+// Asking firmware to do something on the next boot through the OsIndications variable: stop in its setup UI,
+// process capsules left on the EFI system partition, or start OS or platform recovery.
+//
+// OsIndicationsSupported says which bits firmware understands. We set bits in OsIndications and reset; firmware acts
+// on them early in the next boot and clears them. Setting a bit without resetting just leaves it for whenever the
+// next boot happens to be.
+
+use ffi::runtime_services::*;
+use firmware::{firmware, Firmware};
+use fs::{FileSystem, SimpleFs};
+use services::RuntimeServices;
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind, system_table};
+use core::{fmt, ops::BitOr};
+
+const OS_INDICATIONS: &str = "OsIndications";
+const OS_INDICATIONS_SUPPORTED: &str = "OsIndicationsSupported";
+const ATTRIBUTES: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+
+/// Where firmware looks for capsules to deliver when FILE_CAPSULE_DELIVERY is set
+pub const CAPSULE_DIR: &str = "EFI/UpdateCapsule";
+
+/// A set of OsIndications bits
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Indications(pub u64);
+
+impl Indications {
+    pub const BOOT_TO_FW_UI: Self = Indications(EFI_OS_INDICATIONS_BOOT_TO_FW_UI);
+    pub const TIMESTAMP_REVOCATION: Self = Indications(EFI_OS_INDICATIONS_TIMESTAMP_REVOCATION);
+    pub const FILE_CAPSULE_DELIVERY: Self = Indications(EFI_OS_INDICATIONS_FILE_CAPSULE_DELIVERY_SUPPORTED);
+    pub const FMP_CAPSULE: Self = Indications(EFI_OS_INDICATIONS_FMP_CAPSULE_SUPPORTED);
+    pub const CAPSULE_RESULT_VAR: Self = Indications(EFI_OS_INDICATIONS_CAPSULE_RESULT_VAR_SUPPORTED);
+    pub const START_OS_RECOVERY: Self = Indications(EFI_OS_INDICATIONS_START_OS_RECOVERY);
+    pub const START_PLATFORM_RECOVERY: Self = Indications(EFI_OS_INDICATIONS_START_PLATFORM_RECOVERY);
+    pub const JSON_CONFIG_DATA_REFRESH: Self = Indications(EFI_OS_INDICATIONS_JSON_CONFIG_DATA_REFRESH);
+
+    const NAMES: [(Indications, &'static str); 8] = [
+        (Self::BOOT_TO_FW_UI, "boot-to-fw-ui"),
+        (Self::TIMESTAMP_REVOCATION, "timestamp-revocation"),
+        (Self::FILE_CAPSULE_DELIVERY, "file-capsule-delivery"),
+        (Self::FMP_CAPSULE, "fmp-capsule"),
+        (Self::CAPSULE_RESULT_VAR, "capsule-result-var"),
+        (Self::START_OS_RECOVERY, "start-os-recovery"),
+        (Self::START_PLATFORM_RECOVERY, "start-platform-recovery"),
+        (Self::JSON_CONFIG_DATA_REFRESH, "json-config-data-refresh"),
+    ];
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for Indications {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Indications(self.0 | other.0)
+    }
+}
+
+/// The names of the set bits separated by commas, with unknown ones in hex
+impl fmt::Display for Indications {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rest = *self;
+        let mut first = true;
+        for &(bit, name) in Self::NAMES.iter() {
+            if rest.contains(bit) {
+                write!(f, "{}{}", if first { "" } else { "," }, name)?;
+                rest.remove(bit);
+                first = false;
+            }
+        }
+        if !rest.is_empty() {
+            write!(f, "{}{:#x}", if first { "" } else { "," }, rest.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// What firmware understands. Empty if it doesn't have OsIndicationsSupported at all
+pub fn supported() -> Result<Indications> {
+    read(firmware(), OS_INDICATIONS_SUPPORTED)
+}
+
+/// What's been asked for on the next boot
+pub fn pending() -> Result<Indications> {
+    read(firmware(), OS_INDICATIONS)
+}
+
+/// Asks for the given indications on the next boot, on top of any already asked for.
+/// Fails with Unsupported if firmware doesn't support all of them
+pub fn request(indications: Indications) -> Result<()> {
+    request_in(firmware(), indications)
+}
+
+/// Takes back indications asked for, leaving the rest
+pub fn clear(indications: Indications) -> Result<()> {
+    clear_in(firmware(), indications)
+}
+
+/// Resets into the firmware's setup UI. Only returns if firmware doesn't support that
+pub fn reboot_to_firmware_ui() -> Result<()> {
+    request(Indications::BOOT_TO_FW_UI)?;
+    reboot()
+}
+
+/// Resets into OS recovery, where firmware goes through the OsRecovery#### entries. Only returns on failure
+pub fn reboot_to_os_recovery() -> Result<()> {
+    request(Indications::START_OS_RECOVERY)?;
+    reboot()
+}
+
+/// Puts a capsule in EFI/UpdateCapsule on the boot volume, which should be the EFI system partition, and asks for it
+/// to be delivered on the next boot. `name` is the file name; firmware processes them in alphabetical order
+pub fn stage_capsule(name: &str, capsule: &[u8]) -> Result<()> {
+    if !supported()?.contains(Indications::FILE_CAPSULE_DELIVERY) {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    let volume = SimpleFs::boot_volume()?;
+    if !volume.exists("EFI") {
+        volume.create_dir("EFI")?;
+    }
+    if !volume.exists(CAPSULE_DIR) {
+        volume.create_dir(CAPSULE_DIR)?;
+    }
+    volume.write(&format!("{}/{}", CAPSULE_DIR, name), capsule)?;
+    request(Indications::FILE_CAPSULE_DELIVERY)
+}
+
+/// A cold reset, which is what firmware expects after OsIndications has been set
+pub fn reboot() -> ! {
+    let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+    rs.reset(EFI_RESET_TYPE::EfiResetCold, ::ffi::EFI_SUCCESS)
+}
+
+fn read(fw: &dyn Firmware, name: &str) -> Result<Indications> {
+    match fw.get_variable(name, &EFI_GLOBAL_VARIABLE) {
+        Ok(ref data) if data.len() == 8 => Ok(Indications(LittleEndian::read_u64(data))),
+        Ok(_) => Err(EfiErrorKind::VolumeCorrupted.into()),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(Indications::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write(fw: &dyn Firmware, indications: Indications) -> Result<()> {
+    if indications.is_empty() {
+        // Nothing asked for is the same as no variable
+        return match fw.set_variable(OS_INDICATIONS, &EFI_GLOBAL_VARIABLE, ATTRIBUTES, &[]) {
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(()),
+            result => result,
+        };
+    }
+    let mut data = [0u8; 8];
+    LittleEndian::write_u64(&mut data, indications.0);
+    fw.set_variable(OS_INDICATIONS, &EFI_GLOBAL_VARIABLE, ATTRIBUTES, &data)
+}
+
+fn request_in(fw: &dyn Firmware, indications: Indications) -> Result<()> {
+    if !read(fw, OS_INDICATIONS_SUPPORTED)?.contains(indications) {
+        return Err(EfiErrorKind::Unsupported.into());
+    }
+    let mut pending = read(fw, OS_INDICATIONS)?;
+    pending.insert(indications);
+    write(fw, pending)
+}
+
+fn clear_in(fw: &dyn Firmware, indications: Indications) -> Result<()> {
+    let mut pending = read(fw, OS_INDICATIONS)?;
+    if pending.0 & indications.0 != 0 {
+        pending.remove(indications);
+        write(fw, pending)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use firmware::FakeFirmware;
+    use alloc::string::ToString;
+
+    #[test]
+    fn sets_and_clears_bits() {
+        let fw = FakeFirmware::new();
+        assert_eq!(request_in(&fw, Indications::BOOT_TO_FW_UI).unwrap_err().kind(), EfiErrorKind::Unsupported);
+
+        let supported = Indications::BOOT_TO_FW_UI | Indications::FILE_CAPSULE_DELIVERY;
+        fw.set_variable(OS_INDICATIONS_SUPPORTED, &EFI_GLOBAL_VARIABLE, EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS, &supported.0.to_le_bytes()).unwrap();
+        request_in(&fw, Indications::BOOT_TO_FW_UI).unwrap();
+        request_in(&fw, Indications::FILE_CAPSULE_DELIVERY).unwrap();
+        assert!(request_in(&fw, Indications::START_OS_RECOVERY).is_err());
+        assert_eq!(fw.variable(OS_INDICATIONS, &EFI_GLOBAL_VARIABLE), Some((ATTRIBUTES, vec![5, 0, 0, 0, 0, 0, 0, 0])));
+        assert_eq!(read(&fw, OS_INDICATIONS).unwrap().to_string(), "boot-to-fw-ui,file-capsule-delivery");
+
+        clear_in(&fw, Indications::BOOT_TO_FW_UI).unwrap();
+        assert_eq!(read(&fw, OS_INDICATIONS).unwrap(), Indications::FILE_CAPSULE_DELIVERY);
+        clear_in(&fw, supported).unwrap();
+        assert_eq!(fw.variable(OS_INDICATIONS, &EFI_GLOBAL_VARIABLE), None);
+        clear_in(&fw, supported).unwrap();
+        assert_eq!(Indications(0x101).to_string(), "boot-to-fw-ui,0x100");
+    }
+}
@@ -0,0 +1,269 @@
+// EDK2's variable policy: rules registered by platform drivers saying which attributes and sizes a variable may
+// have and when it becomes read-only. Firmware enforces them in SetVariable, so a write that breaks one fails with
+// EFI_WRITE_PROTECTED or EFI_INVALID_PARAMETER and nothing more to go on. Dumping the policies lets tools explain
+// why, or not try in the first place.
+//
+// Only EDK2 derived firmware has the protocol. Elsewhere locate() fails with NotFound and is_locked() says no.
+
+use ffi::{
+    variable_policy::*,
+    EFI_GUID,
+    EFI_BUFFER_TOO_SMALL,
+    BOOLEAN,
+    UINT32,
+};
+use services::RuntimeServices;
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind, system_table};
+use alloc::{string::String, vec::Vec};
+use core::{mem, ptr};
+
+const ENTRY_SIZE: usize = 44; // VARIABLE_POLICY_ENTRY without what follows it
+const LOCK_ON_VAR_STATE_SIZE: usize = 18;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// Never locked, only the attribute and size rules apply
+    None,
+    /// Read-only from the moment the policy was registered
+    Now,
+    /// Read-only once it exists
+    OnCreate,
+    /// Read-only once `name` in `namespace` holds the single byte `value`
+    OnVariableState { namespace: EFI_GUID, name: String, value: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub namespace: EFI_GUID,
+    /// None for a policy covering every variable in the namespace. '#' stands for any hex digit, e.g. "Boot####"
+    pub name: Option<String>,
+    pub min_size: u32,
+    pub max_size: u32,
+    pub attributes_must_have: u32,
+    pub attributes_cant_have: u32,
+    pub lock: LockPolicy,
+}
+
+impl Policy {
+    /// Parses what DumpVariablePolicy returns
+    pub fn parse_all(mut dump: &[u8]) -> Result<Vec<Policy>> {
+        let mut policies = Vec::new();
+        while !dump.is_empty() {
+            let (policy, size) = Self::parse(dump).ok_or(EfiErrorKind::VolumeCorrupted)?;
+            policies.push(policy);
+            dump = &dump[size..];
+        }
+        Ok(policies)
+    }
+
+    // The policy at the start of `entry` and how many bytes it took up
+    fn parse(entry: &[u8]) -> Option<(Policy, usize)> {
+        if entry.len() < ENTRY_SIZE || LittleEndian::read_u32(entry) != VARIABLE_POLICY_ENTRY_REVISION {
+            return None;
+        }
+        let size = LittleEndian::read_u16(&entry[4..]) as usize;
+        let name_offset = LittleEndian::read_u16(&entry[6..]) as usize;
+        if size < ENTRY_SIZE || size > entry.len() || name_offset < ENTRY_SIZE || name_offset > size {
+            return None;
+        }
+        let entry = &entry[..size];
+        let lock = match entry[40] {
+            VARIABLE_POLICY_TYPE_NO_LOCK => LockPolicy::None,
+            VARIABLE_POLICY_TYPE_LOCK_NOW => LockPolicy::Now,
+            VARIABLE_POLICY_TYPE_LOCK_ON_CREATE => LockPolicy::OnCreate,
+            VARIABLE_POLICY_TYPE_LOCK_ON_VAR_STATE => {
+                let state = entry.get(ENTRY_SIZE..name_offset)?;
+                if state.len() < LOCK_ON_VAR_STATE_SIZE {
+                    return None;
+                }
+                LockPolicy::OnVariableState { namespace: read_guid(state), name: read_name(&state[LOCK_ON_VAR_STATE_SIZE..])?, value: state[16] }
+            }
+            _ => return None,
+        };
+        let name = if name_offset == size { None } else { Some(read_name(&entry[name_offset..])?) };
+        let policy = Policy {
+            namespace: read_guid(&entry[8..]),
+            name,
+            min_size: LittleEndian::read_u32(&entry[24..]),
+            max_size: LittleEndian::read_u32(&entry[28..]),
+            attributes_must_have: LittleEndian::read_u32(&entry[32..]),
+            attributes_cant_have: LittleEndian::read_u32(&entry[36..]),
+            lock,
+        };
+        Some((policy, size))
+    }
+
+    /// How well the policy matches the variable, the way firmware decides which one applies: 0 for its exact name,
+    /// then the number of wildcards, with namespace-wide policies last. None if it doesn't match at all
+    pub fn priority(&self, name: &str, vendor: &EFI_GUID) -> Option<u8> {
+        if self.namespace != *vendor {
+            return None;
+        }
+        let pattern = match self.name {
+            Some(ref pattern) => pattern,
+            None => return Some(u8::MAX),
+        };
+        if pattern.chars().count() != name.chars().count() {
+            return None;
+        }
+        let mut wildcards = 0u8;
+        for (p, c) in pattern.chars().zip(name.chars()) {
+            if p == '#' && c.is_ascii_hexdigit() {
+                wildcards = wildcards.saturating_add(1);
+            } else if p != c {
+                return None;
+            }
+        }
+        Some(wildcards.min(u8::MAX - 1))
+    }
+
+    /// Whether a variable with these attributes and this much data passes the attribute and size rules
+    pub fn allows(&self, attributes: u32, size: usize) -> bool {
+        attributes & self.attributes_must_have == self.attributes_must_have
+            && attributes & self.attributes_cant_have == 0
+            && size >= self.min_size as usize
+            && size <= self.max_size as usize
+    }
+}
+
+/// The policy firmware would apply to the variable, if any
+pub fn find<'a>(policies: &'a [Policy], name: &str, vendor: &EFI_GUID) -> Option<&'a Policy> {
+    policies.iter().filter_map(|p| p.priority(name, vendor).map(|priority| (priority, p))).min_by_key(|&(priority, _)| priority).map(|(_, p)| p)
+}
+
+pub struct VariablePolicy {
+    protocol: *const EDKII_VARIABLE_POLICY_PROTOCOL,
+}
+
+impl VariablePolicy {
+    pub fn locate() -> Result<Self> {
+        let bs = system_table().BootServices;
+        let protocol: *const EDKII_VARIABLE_POLICY_PROTOCOL = ptr::null();
+        unsafe {
+            ret_on_err!(((*bs).LocateProtocol)(&EDKII_VARIABLE_POLICY_PROTOCOL_GUID, ptr::null(), mem::transmute(&protocol)));
+        }
+        if protocol.is_null() {
+            return Err(EfiErrorKind::NotFound.into());
+        }
+        Ok(VariablePolicy { protocol })
+    }
+
+    /// Whether firmware is enforcing the policies. Only ever false on debug builds that allow turning it off
+    pub fn is_enabled(&self) -> Result<bool> {
+        let mut state: BOOLEAN = 0;
+        unsafe {
+            ret_on_err!(((*self.protocol).IsVariablePolicyEnabled)(&mut state));
+        }
+        Ok(state != 0)
+    }
+
+    pub fn policies(&self) -> Result<Vec<Policy>> {
+        let mut size: UINT32 = 0;
+        let status = unsafe { traced!(((*self.protocol).DumpVariablePolicy)(ptr::null_mut(), &mut size)) };
+        if status != EFI_BUFFER_TOO_SMALL {
+            ret_on_err!(status);
+            return Ok(Vec::new()); // Success with nothing to copy means there are none
+        }
+        let mut dump = vec![0u8; size as usize];
+        unsafe {
+            ret_on_err!(((*self.protocol).DumpVariablePolicy)(dump.as_mut_ptr(), &mut size));
+        }
+        dump.truncate(size as usize);
+        Policy::parse_all(&dump)
+    }
+
+    /// Whether firmware would refuse any write to the variable because of its lock policy
+    pub fn is_locked(&self, name: &str, vendor: &EFI_GUID) -> Result<bool> {
+        if !self.is_enabled()? {
+            return Ok(false);
+        }
+        let policies = self.policies()?;
+        let policy = match find(&policies, name, vendor) {
+            Some(policy) => policy,
+            None => return Ok(false),
+        };
+        let rs = RuntimeServices::new(unsafe { &*system_table().RuntimeServices });
+        Ok(match policy.lock {
+            LockPolicy::None => false,
+            LockPolicy::Now => true,
+            LockPolicy::OnCreate => rs.get_variable(name, vendor).is_ok(),
+            LockPolicy::OnVariableState { ref namespace, name: ref state_name, value } => {
+                match rs.get_variable(state_name, namespace) {
+                    Ok((data, _)) => data == [value],
+                    Err(_) => false,
+                }
+            }
+        })
+    }
+}
+
+/// Whether firmware would refuse writes to the variable. False if there's no variable policy protocol
+pub fn is_locked(name: &str, vendor: &EFI_GUID) -> Result<bool> {
+    match VariablePolicy::locate() {
+        Ok(policy) => policy.is_locked(name, vendor),
+        Err(ref e) if e.kind() == EfiErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_guid(buf: &[u8]) -> EFI_GUID {
+    let mut tail = [0u8; 8];
+    tail.copy_from_slice(&buf[8..16]);
+    EFI_GUID(LittleEndian::read_u32(&buf[0..4]), LittleEndian::read_u16(&buf[4..6]), LittleEndian::read_u16(&buf[6..8]), tail)
+}
+
+// A null-terminated UCS-2 name
+fn read_name(buf: &[u8]) -> Option<String> {
+    let chars = buf.chunks_exact(2).map(LittleEndian::read_u16).take_while(|c| *c != 0).collect::<Vec<_>>();
+    if chars.len() * 2 >= buf.len() {
+        return None; // No terminator
+    }
+    String::from_utf16(&chars).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(namespace: &EFI_GUID, name: Option<&str>, lock: u8, must_have: u32) -> Vec<u8> {
+        let mut entry = vec![0u8; ENTRY_SIZE];
+        LittleEndian::write_u32(&mut entry, VARIABLE_POLICY_ENTRY_REVISION);
+        LittleEndian::write_u32(&mut entry[8..], namespace.0);
+        LittleEndian::write_u16(&mut entry[12..], namespace.1);
+        LittleEndian::write_u16(&mut entry[14..], namespace.2);
+        entry[16..24].copy_from_slice(&namespace.3);
+        LittleEndian::write_u32(&mut entry[28..], VARIABLE_POLICY_NO_MAX_SIZE);
+        LittleEndian::write_u32(&mut entry[32..], must_have);
+        entry[40] = lock;
+        let name_offset = entry.len() as u16;
+        if let Some(name) = name {
+            for c in name.encode_utf16().chain(Some(0)) {
+                entry.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        let size = entry.len() as u16;
+        LittleEndian::write_u16(&mut entry[4..], size);
+        LittleEndian::write_u16(&mut entry[6..], name_offset);
+        entry
+    }
+
+    #[test]
+    fn picks_the_most_specific_policy() {
+        let global = EFI_GUID(0x8BE4DF61, 0x93CA, 0x11d2, [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C]);
+        let mut dump = entry(&global, None, VARIABLE_POLICY_TYPE_NO_LOCK, 0);
+        dump.extend(entry(&global, Some("Boot####"), VARIABLE_POLICY_TYPE_NO_LOCK, 0x7));
+        dump.extend(entry(&global, Some("BootOrder"), VARIABLE_POLICY_TYPE_LOCK_NOW, 0));
+        let policies = Policy::parse_all(&dump).unwrap();
+        assert_eq!(policies.len(), 3);
+
+        assert_eq!(find(&policies, "BootOrder", &global).unwrap().lock, LockPolicy::Now);
+        let boot_entry = find(&policies, "Boot000A", &global).unwrap();
+        assert_eq!(boot_entry.name.as_deref(), Some("Boot####"));
+        assert!(boot_entry.allows(0x7, 100) && !boot_entry.allows(0x3, 100));
+        assert_eq!(find(&policies, "Boot00XY", &global).unwrap().name, None);
+        assert!(find(&policies, "BootOrder", &EFI_GUID(0, 0, 0, [0; 8])).is_none());
+
+        assert!(Policy::parse_all(&dump[..dump.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+// Rollback protection: counters that only go up, holding the lowest version of something that's still allowed to
+// boot, so an attacker can't swap in an older, signed but vulnerable kernel or slot. An A/B update raises the counter
+// once the new version has booted successfully; from then on the old one fails check():
+//
+//     let index = RollbackIndex::new("Kernel");
+//     index.check(kernel_version)?;
+//     // ... and once the OS has said it's fine
+//     index.advance(kernel_version)?;
+//
+// Each counter is a non-volatile variable with boot services access only, Rollback<name> under our own vendor GUID.
+// Firmware stops taking writes to boot services variables at ExitBootServices, so the OS can't lower it, and one
+// with runtime access can only have been made after that, so it's treated as tampered with rather than believed.
+// So is one whose data doesn't check out. The counter is a version byte (1), its value as 64 bits and a CRC32 of its
+// name and the lot, all little endian.
+//
+// What that can't stop is the variable being deleted, by clearing NVRAM in firmware setup for instance, which sets
+// it back to zero. Platforms where that matters should keep the counter in the TPM instead.
+
+use ffi::{
+    runtime_services::{EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS},
+    EFI_GUID,
+};
+use utils::crc32_update;
+use byteorder::{ByteOrder, LittleEndian};
+use {Result, EfiErrorKind};
+use alloc::string::String;
+
+/// The vendor of the counters' variables
+pub const VENDOR: EFI_GUID = EFI_GUID(0x9c2e4f17, 0x05ab, 0x4d3c, [0x8e, 0x61, 0x2f, 0xd4, 0x3a, 0x70, 0xb9, 0x5c]);
+
+const PREFIX: &str = "Rollback";
+const ATTRIBUTES: u32 = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS;
+const VERSION: u8 = 1;
+const SIZE: usize = 13;
+
+/// A rollback index: a counter kept in a variable, which only ever goes up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackIndex {
+    name: String,
+}
+
+impl RollbackIndex {
+    /// The counter called `name`, e.g. "Kernel" or one per slot, kept in the variable Rollback<name>
+    pub fn new(name: &str) -> Self {
+        RollbackIndex { name: format!("{}{}", PREFIX, name) }
+    }
+
+    /// The name of its variable
+    pub fn variable(&self) -> &str {
+        &self.name
+    }
+
+    /// Its value, 0 if it's never been advanced. SecurityViolation if the variable has been tampered with
+    pub fn get(&self) -> Result<u64> {
+        let (data, attributes) = match super::runtime_services().get_variable(&self.name, &VENDOR) {
+            Err(ref e) if e.kind() == EfiErrorKind::NotFound => return Ok(0),
+            result => result?,
+        };
+        if attributes != ATTRIBUTES {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        decode(&self.name, &data).ok_or_else(|| EfiErrorKind::SecurityViolation.into())
+    }
+
+    /// Whether `version` is allowed to boot: Ok if it's at least the counter, SecurityViolation if it's older
+    pub fn check(&self, version: u64) -> Result<()> {
+        if version < self.get()? {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        }
+        Ok(())
+    }
+
+    /// Raises the counter to `version`, doing nothing if it's there already. SecurityViolation if that would lower
+    /// it. Only works before ExitBootServices
+    pub fn advance(&self, version: u64) -> Result<()> {
+        let current = self.get()?;
+        if version < current {
+            return Err(EfiErrorKind::SecurityViolation.into());
+        } else if version == current {
+            return Ok(());
+        }
+        super::runtime_services().set_variable(&self.name, &VENDOR, ATTRIBUTES, &encode(&self.name, version))
+    }
+}
+
+fn crc(name: &str, data: &[u8]) -> u32 {
+    crc32_update(crc32_update(0, name.as_bytes()), data)
+}
+
+fn encode(name: &str, value: u64) -> [u8; SIZE] {
+    let mut data = [0u8; SIZE];
+    data[0] = VERSION;
+    LittleEndian::write_u64(&mut data[1..], value);
+    let crc = crc(name, &data[..9]);
+    LittleEndian::write_u32(&mut data[9..], crc);
+    data
+}
+
+fn decode(name: &str, data: &[u8]) -> Option<u64> {
+    if data.len() != SIZE || data[0] != VERSION || LittleEndian::read_u32(&data[9..]) != crc(name, &data[..9]) {
+        return None;
+    }
+    Some(LittleEndian::read_u64(&data[1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::runtime_services;
+    use ffi::runtime_services::EFI_VARIABLE_RUNTIME_ACCESS;
+    use testing::mock;
+
+    #[test]
+    fn only_goes_up() {
+        mock::install();
+        let index = RollbackIndex::new("Kernel");
+        assert_eq!(index.variable(), "RollbackKernel");
+        assert_eq!(index.get().unwrap(), 0);
+        index.check(0).unwrap();
+
+        index.advance(3).unwrap();
+        assert_eq!(index.get().unwrap(), 3);
+        index.check(3).unwrap();
+        index.check(4).unwrap();
+        assert_eq!(index.check(2).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        assert_eq!(index.advance(2).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        index.advance(3).unwrap();
+        assert_eq!(RollbackIndex::new("Initrd").get().unwrap(), 0);
+
+        // Made from the OS, or copied from another counter, or corrupted
+        let rs = runtime_services();
+        rs.set_variable("RollbackKernel", &VENDOR, ATTRIBUTES | EFI_VARIABLE_RUNTIME_ACCESS, &encode("RollbackKernel", 1)).unwrap();
+        assert_eq!(index.check(5).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        rs.set_variable("RollbackKernel", &VENDOR, ATTRIBUTES, &encode("RollbackInitrd", 1)).unwrap();
+        assert_eq!(index.get().unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+        let mut data = encode("RollbackKernel", 1);
+        data[1] ^= 0x10;
+        rs.set_variable("RollbackKernel", &VENDOR, ATTRIBUTES, &data).unwrap();
+        assert_eq!(index.advance(7).unwrap_err().kind(), EfiErrorKind::SecurityViolation);
+
+        // The platform's own counter, whose high half goes up once a call and across resets
+        let high = rs.next_high_monotonic_count().unwrap();
+        assert_eq!(rs.next_high_monotonic_count().unwrap(), high + 1);
+    }
+}